@@ -0,0 +1,44 @@
+//! Audible alerts in the browser.
+
+use std::cell::RefCell;
+
+use wasm_bindgen::JsValue;
+use web_sys::{AudioContext, OscillatorType};
+
+const BEEP_FREQUENCY_HZ: f32 = 880.0;
+const BEEP_DURATION_SEC: f64 = 0.3;
+const BEEP_GAIN: f32 = 0.2;
+
+thread_local! {
+    // Browsers limit the number of audio contexts, so a single one is reused.
+    static AUDIO_CONTEXT: RefCell<Option<AudioContext>> = const { RefCell::new(None) };
+}
+
+/// Play a short beep to get the attention of the user.
+///
+/// Browsers only play audio after the user has interacted with the page, so
+/// this may be silent until the user clicks somewhere on the page.
+pub fn beep() {
+    // Failing to beep is not worth bothering the user with.
+    let _ = AUDIO_CONTEXT.with(|cell| -> Result<(), JsValue> {
+        let mut opt_ctx = cell.borrow_mut();
+        if opt_ctx.is_none() {
+            *opt_ctx = Some(AudioContext::new()?);
+        }
+        let ctx = opt_ctx.as_ref().unwrap();
+        let _ = ctx.resume()?;
+
+        let oscillator = ctx.create_oscillator()?;
+        oscillator.set_type(OscillatorType::Square);
+        oscillator.frequency().set_value(BEEP_FREQUENCY_HZ);
+        let gain = ctx.create_gain()?;
+        gain.gain().set_value(BEEP_GAIN);
+        oscillator.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&ctx.destination())?;
+
+        let now = ctx.current_time();
+        oscillator.start_with_when(now)?;
+        oscillator.stop_with_when(now + BEEP_DURATION_SEC)?;
+        Ok(())
+    });
+}
@@ -1 +1,2 @@
+pub mod audio_alert;
 pub mod components;
@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use eyre::{self as anyhow, Context};
+
+/// Estimate the homography from the image of a top-down camera to the floor
+/// for 2D tracking in Braid.
+///
+/// The `[mainbrain.tracking_2d.homography]` table for the Braid configuration
+/// file is printed.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Cli {
+    /// Image of the camera.
+    ///
+    /// This gives the image size and is used to detect April Tags.
+    pub image_filename: PathBuf,
+
+    /// CSV file with landmarks, with columns `image_x`, `image_y`, `x` and
+    /// `y`.
+    #[arg(long, required_unless_present = "apriltags_coords")]
+    pub landmarks: Option<PathBuf>,
+
+    /// CSV file with the floor coordinates of April Tags in the image, with
+    /// columns `id`, `x`, `y` and `z`.
+    #[arg(long, conflicts_with = "landmarks")]
+    pub apriltags_coords: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let cli = Cli::parse();
+
+    let image = image::open(&cli.image_filename)
+        .with_context(|| format!("opening {}", cli.image_filename.display()))?;
+
+    let homography = if let Some(landmarks) = cli.landmarks.as_ref() {
+        flytrax_apriltags_calibration::floor_homography_from_landmarks(&image, landmarks)?
+    } else {
+        // Required by clap if there are no landmarks.
+        let apriltags_coords = cli.apriltags_coords.as_ref().unwrap();
+        flytrax_apriltags_calibration::floor_homography_from_apriltags(&image, apriltags_coords)?
+    };
+
+    eprintln!(
+        "Homography from {} points: {:.2} pixel mean reprojection distance",
+        homography.num_points, homography.mean_reproj_dist
+    );
+    print!("{}", homography.to_braid_config_toml());
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use std::path::Path;
+
+use eyre::{self as anyhow, Context};
+use nalgebra::{Matrix3, Point2};
+use serde::Deserialize;
+
+use ads_webasm::components::{parse_csv, MaybeCsvData};
+use braid_april_cal::Fiducial3DCoords;
+
+/// A landmark on the floor, with its position in the image (e.g. clicked in
+/// an image viewer) and on the floor.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FloorLandmark {
+    pub image_x: f64,
+    pub image_y: f64,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A homography from the image of a camera to the floor.
+#[derive(Debug, Clone)]
+pub struct FloorHomography {
+    pub width: u32,
+    pub height: u32,
+    pub image_to_plane: Matrix3<f64>,
+    /// The number of points used for the estimate.
+    pub num_points: usize,
+    /// The mean distance, in pixels, between the image positions and the
+    /// floor positions mapped into the image.
+    pub mean_reproj_dist: f64,
+}
+
+impl FloorHomography {
+    /// Estimate the homography from corresponding points in the image and on
+    /// the floor.
+    pub fn estimate(
+        width: u32,
+        height: u32,
+        image_pts: &[Point2<f64>],
+        floor_pts: &[Point2<f64>],
+    ) -> anyhow::Result<Self> {
+        let image_to_plane = strand_cam_pseudo_cal::estimate_homography(image_pts, floor_pts)?;
+        let plane_to_image = image_to_plane
+            .try_inverse()
+            .ok_or_else(|| anyhow::anyhow!("homography is singular"))?;
+        let mean_reproj_dist = image_pts
+            .iter()
+            .zip(floor_pts.iter())
+            .map(|(image_pt, floor_pt)| {
+                let projected = strand_cam_pseudo_cal::apply_homography(&plane_to_image, floor_pt);
+                nalgebra::distance(&projected, image_pt)
+            })
+            .sum::<f64>()
+            / image_pts.len() as f64;
+        Ok(Self {
+            width,
+            height,
+            image_to_plane,
+            num_points: image_pts.len(),
+            mean_reproj_dist,
+        })
+    }
+
+    /// Format as the `[mainbrain.tracking_2d.homography]` table of the Braid
+    /// configuration file.
+    pub fn to_braid_config_toml(&self) -> String {
+        let h = &self.image_to_plane;
+        let rows: Vec<String> = (0..3)
+            .map(|i| format!("    [{:?}, {:?}, {:?}],\n", h[(i, 0)], h[(i, 1)], h[(i, 2)]))
+            .collect();
+        format!(
+            "[mainbrain.tracking_2d.homography]\nwidth = {}\nheight = {}\nimage_to_plane = [\n{}]\n",
+            self.width,
+            self.height,
+            rows.concat()
+        )
+    }
+}
+
+fn read_csv<T>(fname: &Path) -> anyhow::Result<Vec<T>>
+where
+    for<'de> T: Deserialize<'de> + Clone,
+{
+    let buf = std::fs::read(fname).with_context(|| format!("reading {}", fname.display()))?;
+    match parse_csv::<T>(format!("{}", fname.display()), &buf) {
+        MaybeCsvData::Valid(data) => Ok(data.rows().to_vec()),
+        MaybeCsvData::ParseFail(e) => {
+            anyhow::bail!("failed parsing file {}: {}", fname.display(), e);
+        }
+        MaybeCsvData::Empty => {
+            anyhow::bail!("empty file {}", fname.display());
+        }
+    }
+}
+
+/// Estimate the homography from the image to the floor from landmarks in a CSV
+/// file with columns `image_x`, `image_y`, `x` and `y`.
+pub fn floor_homography_from_landmarks(
+    image: &image::DynamicImage,
+    landmarks_csv: &Path,
+) -> anyhow::Result<FloorHomography> {
+    let landmarks: Vec<FloorLandmark> = read_csv(landmarks_csv)?;
+    let image_pts: Vec<_> = landmarks
+        .iter()
+        .map(|l| Point2::new(l.image_x, l.image_y))
+        .collect();
+    let floor_pts: Vec<_> = landmarks.iter().map(|l| Point2::new(l.x, l.y)).collect();
+    FloorHomography::estimate(image.width(), image.height(), &image_pts, &floor_pts)
+}
+
+/// Estimate the homography from the image to the floor from April Tags
+/// detected in `image`, with their floor coordinates in a CSV file with
+/// columns `id`, `x`, `y` and `z`.
+///
+/// All tags must be on the floor, i.e. have `z` of zero.
+pub fn floor_homography_from_apriltags(
+    image: &image::DynamicImage,
+    apriltags_coords_csv: &Path,
+) -> anyhow::Result<FloorHomography> {
+    let fiducial_coords: Vec<Fiducial3DCoords> = read_csv(apriltags_coords_csv)?;
+    if let Some(f) = fiducial_coords.iter().find(|f| f.z != 0.0) {
+        anyhow::bail!(
+            "April Tag {} in {} is not on the floor (z = {}).",
+            f.id,
+            apriltags_coords_csv.display(),
+            f.z
+        );
+    }
+
+    let detections = crate::detect_apriltags(image)?;
+    let (image_pts, floor_pts): (Vec<_>, Vec<_>) = detections
+        .iter()
+        .filter_map(|d| {
+            fiducial_coords
+                .iter()
+                .find(|f| i64::from(f.id) == i64::from(d.id))
+                .map(|f| (Point2::new(d.x, d.y), Point2::new(f.x, f.y)))
+        })
+        .unzip();
+    tracing::info!(
+        "{} of {} detected April Tags have floor coordinates.",
+        image_pts.len(),
+        detections.len()
+    );
+    FloorHomography::estimate(image.width(), image.height(), &image_pts, &floor_pts)
+}
+
+#[test]
+fn test_to_braid_config_toml() {
+    let h = FloorHomography {
+        width: 640,
+        height: 480,
+        image_to_plane: Matrix3::new(0.001, 0.0, -0.32, 0.0, -0.001, 0.24, 0.0, 0.0, 1.0),
+        num_points: 4,
+        mean_reproj_dist: 0.0,
+    };
+    assert_eq!(
+        h.to_braid_config_toml(),
+        "[mainbrain.tracking_2d.homography]
+width = 640
+height = 480
+image_to_plane = [
+    [0.001, 0.0, -0.32],
+    [0.0, -0.001, 0.24],
+    [0.0, 0.0, 1.0],
+]
+"
+    );
+}
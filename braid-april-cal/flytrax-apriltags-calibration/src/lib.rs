@@ -9,9 +9,15 @@ use opencv_ros_camera::NamedIntrinsicParameters;
 use ads_apriltag as apriltag;
 use ads_webasm::components::{parse_csv, MaybeCsvData};
 
+mod floor_homography;
 mod img_write;
 mod tiny_skia_frame;
 
+pub use floor_homography::{
+    floor_homography_from_apriltags, floor_homography_from_landmarks, FloorHomography,
+    FloorLandmark,
+};
+
 struct AprilTagCoords2D {
     id: i32,
     x: f64,
@@ -21,6 +27,23 @@ struct AprilTagCoords2D {
 fn read_apriltags<P: AsRef<std::path::Path>>(
     fname: P,
 ) -> anyhow::Result<(Vec<AprilTagCoords2D>, Vec<u8>)> {
+    let jpeg_buf =
+        std::fs::read(&fname).with_context(|| format!("reading {}", fname.as_ref().display()))?;
+    let image = image::load_from_memory_with_format(&jpeg_buf, image::ImageFormat::Jpeg)
+        .with_context(|| format!("parsing {}", fname.as_ref().display()))?;
+
+    let res = detect_apriltags(&image)?;
+
+    tracing::info!(
+        "In image file {}, got {} detection(s).",
+        fname.as_ref().display(),
+        res.len()
+    );
+
+    Ok((res, jpeg_buf))
+}
+
+fn detect_apriltags(image: &image::DynamicImage) -> anyhow::Result<Vec<AprilTagCoords2D>> {
     let mut td = apriltag::Detector::new();
     let tf = apriltag::Family::new_tag_36h11();
     td.add_family(tf);
@@ -32,23 +55,12 @@ fn read_apriltags<P: AsRef<std::path::Path>>(
     raw_td.refine_edges = 1;
     raw_td.decode_sharpening = 0.25;
 
-    let jpeg_buf =
-        std::fs::read(&fname).with_context(|| format!("reading {}", fname.as_ref().display()))?;
-    let image = image::load_from_memory_with_format(&jpeg_buf, image::ImageFormat::Jpeg)
-        .with_context(|| format!("parsing {}", fname.as_ref().display()))?;
-
-    let rgb = convert_image::image_to_rgb8(image)?;
+    let rgb = convert_image::image_to_rgb8(image.clone())?;
 
     let dest = convert_image::convert_ref::<_, Mono8>(&rgb)?;
     let im = apriltag::ImageU8Borrowed::view(&dest);
     let detections = td.detect(apriltag::ImageU8::inner(&im));
 
-    tracing::info!(
-        "In image file {}, got {} detection(s).",
-        fname.as_ref().display(),
-        detections.len()
-    );
-
     let res = detections
         .as_slice()
         .iter()
@@ -66,7 +78,7 @@ fn read_apriltags<P: AsRef<std::path::Path>>(
         })
         .collect();
 
-    Ok((res, jpeg_buf))
+    Ok(res)
 }
 
 #[derive(Debug, Clone)]
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 
-use flydra_types::{BraidCameraConfig, FakeSyncConfig, TriggerType, TriggerboxConfig};
+use flydra_types::{
+    BraidCameraConfig, FakeSyncConfig, TrackingVolume, TriggerType, TriggerboxConfig,
+};
 
 /// The Braid configuration error type.
 #[derive(thiserror::Error, Debug)]
@@ -92,9 +94,17 @@ pub struct MainbrainConfig {
     /// Defaults to [DEFAULT_OUTPUT_BASE_DIRNAME].
     #[serde(default = "default_output_base_dirname")]
     pub output_base_dirname: std::path::PathBuf,
-    /// Parameters for Kalman filter and data association
-    #[serde(default = "flydra_types::default_tracking_params_full_3d")]
-    pub tracking_params: flydra_types::TrackingParams,
+    /// Parameters for Kalman filter and data association.
+    ///
+    /// If not set, [flydra_types::default_tracking_params_full_3d] is used, or,
+    /// with [MainbrainConfig::tracking_2d],
+    /// [flydra_types::default_tracking_params_flat_3d]. Use
+    /// [MainbrainConfig::tracking_params] to get the parameters in use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracking_params: Option<flydra_types::TrackingParams>,
+    /// Track objects in 2D with a single camera.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracking_2d: Option<Tracking2dConfig>,
     // Raising the mainbrain thread priority is currently disabled.
     // /// Parameters to potentially raise the mainbrain thread priority.
     // sched_policy_priority: Option<(i32, i32)>,
@@ -139,6 +149,16 @@ pub struct MainbrainConfig {
     pub save_empty_data2d: bool,
     /// Secret to use for signing HTTP cookies (base64 encoded)
     pub secret_base64: Option<String>,
+    /// Tokens granting read-only access to the web UI.
+    ///
+    /// A browser opening the web UI with one of these tokens (e.g.
+    /// `http://host:port/?token=...`) can see the state but cannot change
+    /// anything, e.g. start or stop recordings. This applies also to the
+    /// cameras viewed via Braid. The token printed at startup grants full
+    /// access. Only used if the HTTP server is not listening only on the
+    /// loopback interface, in which case no token is required.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub read_only_tokens: Vec<String>,
     /// For debugging: filename to store captured packet data.
     pub packet_capture_dump_fname: Option<std::path::PathBuf>,
     /// Threshold duration before logging error (msec).
@@ -153,6 +173,50 @@ pub struct MainbrainConfig {
     /// sending data to disk.
     #[serde(default = "default_write_buffer_size_num_messages")]
     pub write_buffer_size_num_messages: usize,
+    /// Number of frames, after synchronization, used to estimate per-camera
+    /// trigger offsets.
+    ///
+    /// If set, the latency between the trigger pulse and frame arrival is
+    /// measured for each camera once all cameras are synchronized. Cameras
+    /// whose latency differs from the others by a whole number of frame
+    /// periods have their synchronized frame numbers corrected accordingly.
+    /// Only used with the triggerbox. Defaults to `None` (no estimation).
+    #[serde(default)]
+    pub trigger_offset_estimation_num_frames: Option<usize>,
+    /// Warn when less than this many megabytes are free in
+    /// `output_base_dirname`.
+    #[serde(default = "default_disk_space_warning_mb")]
+    pub disk_space_warning_mb: u64,
+    /// Stop recordings when less than this many megabytes are free in
+    /// `output_base_dirname`.
+    ///
+    /// The `.braidz` file and the MP4 files of all cameras are finalized.
+    #[serde(default = "default_disk_space_stop_mb")]
+    pub disk_space_stop_mb: u64,
+    /// Closed-loop stimulus rules evaluated on live tracking data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed_loop: Option<ClosedLoopConfig>,
+    /// Devices with digital (TTL) outputs to mark events.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub output_devices: Vec<OutputDeviceConfig>,
+    /// Markers emitted at regular frame numbers and when saving starts and
+    /// stops, to align the data with other acquisition systems.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_markers: Option<SyncMarkerConfig>,
+    /// Where to send alerts about runtime problems, such as a camera which
+    /// stops sending data.
+    ///
+    /// For example:
+    ///
+    /// ```toml
+    /// [mainbrain.notifications]
+    /// slack_webhook_urls = ["https://hooks.slack.com/services/..."]
+    /// ```
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notifications: Option<alert_notifier::NotificationConfig>,
+    /// Serve the web UI additionally over HTTPS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub https: Option<HttpsConfig>,
 }
 
 impl std::default::Default for MainbrainConfig {
@@ -160,7 +224,8 @@ impl std::default::Default for MainbrainConfig {
         Self {
             cal_fname: None,
             output_base_dirname: default_output_base_dirname(),
-            tracking_params: flydra_types::default_tracking_params_full_3d(),
+            tracking_params: None,
+            tracking_2d: None,
             // Raising the mainbrain thread priority is currently disabled.
             // sched_policy_priority: None,
             lowlatency_camdata_udp_addr: None,
@@ -169,18 +234,356 @@ impl std::default::Default for MainbrainConfig {
             model_server_addr: default_model_server_addr(),
             save_empty_data2d: true,
             secret_base64: None,
+            read_only_tokens: Vec::new(),
             packet_capture_dump_fname: None,
             acquisition_duration_allowed_imprecision_msec:
                 flydra_types::DEFAULT_ACQUISITION_DURATION_ALLOWED_IMPRECISION_MSEC,
             write_buffer_size_num_messages: default_write_buffer_size_num_messages(),
+            trigger_offset_estimation_num_frames: None,
+            disk_space_warning_mb: default_disk_space_warning_mb(),
+            disk_space_stop_mb: default_disk_space_stop_mb(),
+            closed_loop: None,
+            output_devices: Vec::new(),
+            sync_markers: None,
+            notifications: None,
+            https: None,
         }
     }
 }
 
+impl MainbrainConfig {
+    /// The parameters for Kalman filter and data association.
+    ///
+    /// These are [MainbrainConfig::tracking_params] if set, or else the
+    /// default parameters for 3D or, with [MainbrainConfig::tracking_2d], 2D
+    /// tracking.
+    pub fn tracking_params(&self) -> flydra_types::TrackingParams {
+        match (&self.tracking_params, &self.tracking_2d) {
+            (Some(tracking_params), _) => tracking_params.clone(),
+            (None, None) => flydra_types::default_tracking_params_full_3d(),
+            (None, Some(_)) => flydra_types::default_tracking_params_flat_3d(),
+        }
+    }
+}
+
+/// 2D tracking configuration, part of [MainbrainConfig].
+///
+/// With a single camera, objects are tracked on the z=0 plane of the
+/// calibration given in [MainbrainConfig::cal_fname]. Without a calibration
+/// file, either `homography` or `image_circle` defines the calibration. The
+/// tracking parameters must not contain `hypothesis_test_params`.
+///
+/// For example, to track in the plane of an arena with a diameter of 10 cm
+/// which appears as a circle with a radius of 400 pixels:
+///
+/// ```toml
+/// [mainbrain.tracking_2d.image_circle]
+/// width = 1280
+/// height = 1024
+/// center_x = 640
+/// center_y = 512
+/// radius = 400
+/// physical_diameter_meters = 0.1
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tracking2dConfig {
+    /// Calibration of the camera from a homography between the image and the
+    /// plane, used if [MainbrainConfig::cal_fname] is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homography: Option<HomographyCalibration>,
+    /// Calibration of the camera from a circle in the image, used if
+    /// [MainbrainConfig::cal_fname] is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_circle: Option<ImageCircleCalibration>,
+}
+
+/// A calibration of a camera by a homography from the image to the z=0 plane,
+/// part of [Tracking2dConfig].
+///
+/// This is estimated from landmarks or April Tags on the plane by
+/// `floor-homography-calibration`, which prints it in this format, e.g.:
+///
+/// ```toml
+/// [mainbrain.tracking_2d.homography]
+/// width = 1280
+/// height = 1024
+/// image_to_plane = [
+///     [0.00051, 0.00008, -0.3679],
+///     [-0.00002, -0.00055, 0.2769],
+///     [0.0, 0.0, 1.0],
+/// ]
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HomographyCalibration {
+    /// Width of the camera image in pixels.
+    pub width: u32,
+    /// Height of the camera image in pixels.
+    pub height: u32,
+    /// The rows of the matrix mapping homogeneous pixel coordinates to
+    /// homogeneous coordinates on the plane, in meters.
+    pub image_to_plane: [[f64; 3]; 3],
+}
+
+/// A calibration of a camera looking perpendicularly onto the z=0 plane, part
+/// of [Tracking2dConfig].
+///
+/// The origin of the world coordinates is at the center of the circle.
+/// Coordinates are in meters. To track in pixel coordinates instead, set
+/// `physical_diameter_meters` to `2 * radius`, in which case distances in the
+/// tracking parameters are also in pixels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageCircleCalibration {
+    /// Width of the camera image in pixels.
+    pub width: u32,
+    /// Height of the camera image in pixels.
+    pub height: u32,
+    /// Horizontal position of the center of the circle in pixels.
+    pub center_x: i16,
+    /// Vertical position of the center of the circle in pixels.
+    pub center_y: i16,
+    /// Radius of the circle in pixels.
+    pub radius: u16,
+    /// Diameter of the circle in meters.
+    pub physical_diameter_meters: f32,
+}
+
+/// HTTPS server configuration, part of [MainbrainConfig].
+///
+/// The web UI is served over HTTPS at `addr` in addition to the HTTP server at
+/// [MainbrainConfig::http_api_server_addr], which the cameras use. If neither
+/// `cert_file` nor `key_file` is given, a self-signed certificate is generated
+/// at startup, which browsers will ask to accept.
+///
+/// For example:
+///
+/// ```toml
+/// [mainbrain.https]
+/// addr = "0.0.0.0:8443"
+/// cert_file = "cert.pem"
+/// key_file = "key.pem"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpsConfig {
+    /// Address of the HTTPS server, in the same format as
+    /// [MainbrainConfig::http_api_server_addr].
+    pub addr: String,
+    /// PEM file with the certificate chain.
+    ///
+    /// Can contain shell variables such as `~`, `$A`, or `${B}`.
+    pub cert_file: Option<std::path::PathBuf>,
+    /// PEM file with the private key.
+    ///
+    /// Can contain shell variables such as `~`, `$A`, or `${B}`.
+    pub key_file: Option<std::path::PathBuf>,
+}
+
+/// Closed-loop stimulus configuration, part of [MainbrainConfig].
+///
+/// Each rule is evaluated whenever the Kalman estimate of a tracked object is
+/// updated. The actions of a rule are performed immediately when its condition
+/// starts or stops being met by any object. These events are also saved to the
+/// `textlog` table of the `.braidz` file when saving.
+///
+/// For example, to switch on LED box channel 1 while an object is in a
+/// cylinder and moving:
+///
+/// ```toml
+/// [mainbrain.closed_loop]
+/// led_box_device = "/dev/ttyACM0"
+///
+/// [[mainbrain.closed_loop.rules]]
+/// name = "center"
+/// region = { type = "Cylinder", center_x = 0.0, center_y = 0.0, radius = 0.05, z_min = 0.0, z_max = 0.3 }
+/// min_speed = 0.05
+/// on_enter = [{ type = "LedBox", channel = 1, on = true }]
+/// on_exit = [{ type = "LedBox", channel = 1, on = false }]
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClosedLoopConfig {
+    /// Path of the serial device of the LED box used by
+    /// [ClosedLoopAction::LedBox], e.g. `/dev/ttyACM0`.
+    pub led_box_device: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<ClosedLoopRule>,
+}
+
+/// A condition on tracked objects and the actions performed when it changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClosedLoopRule {
+    /// Name of the rule, used in log messages.
+    pub name: String,
+    /// The region in which an object must be. Defaults to everywhere.
+    #[serde(default)]
+    pub region: TrackingVolume,
+    /// Minimum speed of an object, in meters per second.
+    pub min_speed: Option<f64>,
+    /// Maximum speed of an object, in meters per second.
+    pub max_speed: Option<f64>,
+    /// Actions performed when the first object meets the condition.
+    #[serde(default)]
+    pub on_enter: Vec<ClosedLoopAction>,
+    /// Actions performed when no object meets the condition anymore.
+    #[serde(default)]
+    pub on_exit: Vec<ClosedLoopAction>,
+}
+
+/// An action performed by a [ClosedLoopRule].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum ClosedLoopAction {
+    /// Set a channel of the LED box in [ClosedLoopConfig::led_box_device].
+    LedBox {
+        /// Channel number (1-4).
+        channel: u8,
+        /// Whether the channel is on.
+        on: bool,
+        /// PWM intensity of the channel. Defaults to the maximum intensity.
+        intensity: Option<u16>,
+    },
+    /// Send `message` as a UDP packet to `addr`, e.g. to a device raising a
+    /// TTL line.
+    Udp {
+        addr: std::net::SocketAddr,
+        message: String,
+    },
+    /// Set a line of an output device in [MainbrainConfig::output_devices].
+    Ttl {
+        /// Name of the output device.
+        device: String,
+        /// Line number.
+        line: u8,
+        /// Whether the line is set high.
+        high: bool,
+        /// If set, the line returns to the opposite level after this many
+        /// milliseconds.
+        pulse_msec: Option<u64>,
+    },
+}
+
+/// A device with digital (TTL) output lines, part of [MainbrainConfig].
+///
+/// Output devices mark events, such as the start of saving data, in the
+/// recordings of other equipment, e.g. for neural recordings.
+///
+/// For example, to keep line 2 of an Arduino high while saving data:
+///
+/// ```toml
+/// [[mainbrain.output_devices]]
+/// name = "arduino"
+/// device = { type = "ArduinoSerial", path = "/dev/ttyACM1" }
+/// recording_line = 2
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputDeviceConfig {
+    /// Name of the device, used in [ClosedLoopAction::Ttl].
+    pub name: String,
+    pub device: OutputDeviceKind,
+    /// Line which is high while the `.braidz` file is being saved.
+    pub recording_line: Option<u8>,
+}
+
+/// Markers for aligning the data with other acquisition systems, part of
+/// [MainbrainConfig].
+///
+/// Each marker is saved in the `textlog` table of the `.braidz` file with the
+/// synchronized frame number at which it was emitted.
+///
+/// For example, to pulse line 3 of an output device every 1000 frames and
+/// when saving starts and stops:
+///
+/// ```toml
+/// [mainbrain.sync_markers]
+/// every_n_frames = 1000
+/// on_recording_start_stop = true
+/// actions = [{ type = "Ttl", device = "arduino", line = 3 }]
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyncMarkerConfig {
+    /// Emit a marker at each synchronized frame number which is a multiple of
+    /// this.
+    pub every_n_frames: Option<u64>,
+    /// Emit a marker when saving of the `.braidz` file starts and stops.
+    #[serde(default)]
+    pub on_recording_start_stop: bool,
+    /// What is done for each marker.
+    pub actions: Vec<SyncMarkerAction>,
+}
+
+/// The default value of `pulse_msec` of [SyncMarkerAction::Ttl].
+pub const DEFAULT_SYNC_MARKER_PULSE_MSEC: u64 = 10;
+
+const fn default_sync_marker_pulse_msec() -> u64 {
+    DEFAULT_SYNC_MARKER_PULSE_MSEC
+}
+
+/// An action performed for each marker of [SyncMarkerConfig].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum SyncMarkerAction {
+    /// Pulse a line of an output device in [MainbrainConfig::output_devices]
+    /// high.
+    Ttl {
+        /// Name of the output device.
+        device: String,
+        /// Line number.
+        line: u8,
+        /// Duration of the pulse. Defaults to
+        /// [DEFAULT_SYNC_MARKER_PULSE_MSEC].
+        #[serde(default = "default_sync_marker_pulse_msec")]
+        pulse_msec: u64,
+    },
+    /// Send `message` as a UDP packet to `addr`.
+    ///
+    /// In `message`, `{frame}` is replaced with the synchronized frame number
+    /// and `{event}` with the kind of marker: `frame`, `start` or `stop`.
+    Udp {
+        addr: std::net::SocketAddr,
+        message: String,
+    },
+}
+
+/// The default value for the baud rate of [OutputDeviceKind::ArduinoSerial].
+pub const DEFAULT_ARDUINO_BAUD_RATE: u32 = 115_200;
+
+const fn default_arduino_baud_rate() -> u32 {
+    DEFAULT_ARDUINO_BAUD_RATE
+}
+
+/// The kind of an output device and how to connect to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum OutputDeviceKind {
+    /// An Arduino running the `braid-ttl-output` sketch, connected by serial
+    /// port. The line number is the Arduino digital pin number.
+    ArduinoSerial {
+        /// Path of the serial device, e.g. `/dev/ttyACM1`.
+        path: String,
+        /// Defaults to [DEFAULT_ARDUINO_BAUD_RATE].
+        #[serde(default = "default_arduino_baud_rate")]
+        baud_rate: u32,
+    },
+}
+
 pub const fn default_write_buffer_size_num_messages() -> usize {
     10000
 }
 
+const fn default_disk_space_warning_mb() -> u64 {
+    disk_space_monitor::DEFAULT_WARNING_THRESHOLD_MB
+}
+
+const fn default_disk_space_stop_mb() -> u64 {
+    disk_space_monitor::DEFAULT_STOP_THRESHOLD_MB
+}
+
 /// The Braid configuration format used in [the Braid configuration `TOML`
 /// file](https://strawlab.github.io/strand-braid/braid_configuration_and_launching.html).
 ///
@@ -238,6 +641,16 @@ impl BraidConfig {
         // fixup self.mainbrain.output_base_dirname
         fixup_relative_path(&mut self.mainbrain.output_base_dirname, &dirname)?;
 
+        // fixup self.mainbrain.https
+        if let Some(https) = self.mainbrain.https.as_mut() {
+            for path in [&mut https.cert_file, &mut https.key_file]
+                .into_iter()
+                .flatten()
+            {
+                fixup_relative_path(path, &dirname)?;
+            }
+        }
+
         // fixup self.cameras.camera_settings_filename
         for camera_config in self.cameras.iter_mut() {
             if let Some(ref mut camera_settings_filename) =
@@ -299,3 +712,56 @@ pub fn parse_config_file<P: AsRef<std::path::Path>>(fname: P) -> Result<BraidCon
     cfg.fixup_relative_paths(fname.as_ref())?;
     Ok(cfg)
 }
+
+#[test]
+fn test_parse_morphology() {
+    use flydra_feature_detector_types::MorphologyCfg;
+
+    let config = |morphology: &str| {
+        format!(
+            r#"
+[[cameras]]
+name = "cam1"
+
+[cameras.point_detection_config]
+do_update_background_model = true
+polarity = "DetectAbsDiff"
+alpha = 0.01
+n_sigma = 7.0
+bright_non_gaussian_cutoff = 255
+bright_non_gaussian_replacement = 5
+bg_update_interval = 200
+diff_threshold = 30
+use_cmp = true
+max_num_points = 1
+feature_window_size = 30
+clear_fraction = 0.3
+despeckle_threshold = 5
+valid_region = "Everything"
+{morphology}
+"#
+        )
+    };
+
+    let cfg: BraidConfig = toml::from_str(&config("morphology = { Open = 3 }")).unwrap();
+    assert_eq!(
+        cfg.cameras[0].point_detection_config.morphology,
+        MorphologyCfg::Open(3)
+    );
+    let cfg: BraidConfig = toml::from_str(&config("morphology = { Close = 5 }")).unwrap();
+    assert_eq!(
+        cfg.cameras[0].point_detection_config.morphology,
+        MorphologyCfg::Close(5)
+    );
+    let cfg: BraidConfig = toml::from_str(&config("")).unwrap();
+    assert_eq!(
+        cfg.cameras[0].point_detection_config.morphology,
+        MorphologyCfg::None
+    );
+
+    // The structuring element must have an odd size of at least 3.
+    for invalid in ["{ Open = 4 }", "{ Close = 1 }", "{ Open = 0 }"] {
+        let result = toml::from_str::<BraidConfig>(&config(&format!("morphology = {invalid}")));
+        assert!(result.is_err(), "{invalid}");
+    }
+}
@@ -0,0 +1,129 @@
+//! Run the tracker again on the 2D detections of a previous recording.
+//!
+//! This is run as `braid retrack`. The input is either a `.braidz` file (or
+//! `.braid` directory) saved by Braid or a CSV file with 2D detections saved by
+//! Strand Camera next to its `.ufmf` file. A new `.braidz` file is written
+//! using the current tracking code with, optionally, new tracking parameters
+//! and a new calibration.
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use eyre::{self as anyhow, WrapErr};
+use tracing_futures::Instrument;
+
+/// Name of the program which tracks 2D detections saved by Strand Camera.
+const FLYTRAX_CSV_TO_BRAIDZ: &str = "flytrax-csv-to-braidz";
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Cli {
+    /// Input `.braidz` file or `.braid` directory saved by Braid, or CSV file
+    /// with 2D detections saved by Strand Camera.
+    input: PathBuf,
+    /// Output file (must end with .braidz)
+    #[arg(short = 'o', long)]
+    output: PathBuf,
+    /// Tracking parameters TOML file. If not given, the tracking parameters
+    /// of the input are used.
+    #[arg(long)]
+    tracking_params: Option<PathBuf>,
+    /// New calibration. If not given, the calibration of the input is used.
+    /// Required for CSV input.
+    #[arg(long)]
+    calibration: Option<PathBuf>,
+    /// Set frames per second
+    #[arg(long)]
+    fps: Option<f64>,
+    /// Set start frame to start tracking
+    #[arg(long)]
+    start_frame: Option<u64>,
+    /// Set stop frame to stop tracking
+    #[arg(long)]
+    stop_frame: Option<u64>,
+    /// Disable display of progress indicator
+    #[arg(long)]
+    no_progress: bool,
+}
+
+/// Return whether `path` is a CSV file (possibly gzipped) saved by Strand
+/// Camera.
+fn is_strand_cam_csv(path: &std::path::Path) -> bool {
+    let name = path.to_string_lossy().to_lowercase();
+    name.ends_with(".csv") || name.ends_with(".csv.gz")
+}
+
+/// Track the 2D detections in a CSV file saved by Strand Camera using the
+/// `flytrax-csv-to-braidz` program.
+fn retrack_strand_cam_csv(cli: Cli) -> anyhow::Result<()> {
+    let Some(calibration) = cli.calibration else {
+        anyhow::bail!("--calibration is required to track 2D detections from a CSV file");
+    };
+    if cli.fps.is_some() {
+        anyhow::bail!("--fps cannot be set for CSV input");
+    }
+
+    let mut exec = std::process::Command::new(FLYTRAX_CSV_TO_BRAIDZ);
+    exec.arg("--csv")
+        .arg(&cli.input)
+        .arg("--output")
+        .arg(&cli.output)
+        .arg("--cal")
+        .arg(&calibration);
+    if let Some(tracking_params) = &cli.tracking_params {
+        exec.arg("--tracking-params").arg(tracking_params);
+    }
+    if let Some(start_frame) = cli.start_frame {
+        exec.arg("--start-frame").arg(start_frame.to_string());
+    }
+    if let Some(stop_frame) = cli.stop_frame {
+        exec.arg("--stop-frame").arg(stop_frame.to_string());
+    }
+    if cli.no_progress {
+        exec.arg("--no-progress");
+    }
+    tracing::debug!("exec: {:?}", exec);
+    let status = exec
+        .status()
+        .with_context(|| format!("running '{FLYTRAX_CSV_TO_BRAIDZ}'"))?;
+    if !status.success() {
+        anyhow::bail!("'{FLYTRAX_CSV_TO_BRAIDZ}' failed ({status})");
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "braid_offline=info,flydra2=info,warn");
+    }
+
+    let _tracing_guard = env_tracing_logger::init();
+
+    let cli = Cli::parse();
+
+    if is_strand_cam_csv(&cli.input) {
+        return retrack_strand_cam_csv(cli);
+    }
+
+    let opt = braid_offline::Cli {
+        data_src: cli.input,
+        output: cli.output,
+        fps: cli.fps,
+        start_frame: cli.start_frame,
+        stop_frame: cli.stop_frame,
+        tracking_params: cli.tracking_params,
+        new_calibration: cli.calibration,
+        no_progress: cli.no_progress,
+    };
+
+    let future = async { braid_offline::braid_offline_retrack(opt).await };
+    let instrumented = future.instrument(tracing::info_span!("braid-retrack"));
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .worker_threads(2)
+        .thread_name("braid-retrack")
+        .build()?;
+
+    rt.block_on(instrumented)
+}
@@ -58,6 +58,10 @@ fn no_data_row(obj_id: u32, frame: SyncFno) -> KalmanEstimatesRow {
         P33: nan,
         P44: nan,
         P55: nan,
+        num_cams: 0,
+        max_ray_angle: nan,
+        innovation_pixels: nan,
+        pos_cov_trace: nan,
     }
 }
 
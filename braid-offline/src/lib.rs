@@ -83,6 +83,7 @@ fn to_point_info(row: &Data2dDistortedRow, idx: u8) -> NumberedRawUdpPoint {
             cur_val: row.cur_val,
             mean_val: row.mean_val,
             sumsqf_val: row.sumsqf_val,
+            mean_intensity: row.mean_intensity,
         },
     }
 }
@@ -182,6 +183,8 @@ pub struct KalmanizeOptions {
     pub start_frame: Option<u64>,
     pub stop_frame: Option<u64>,
     pub model_server_addr: Option<String>,
+    /// Stream the tracking to rerun.
+    pub rerun: Option<flydra2::RerunTarget>,
 }
 
 /// Perform offline tracking on the data
@@ -350,6 +353,9 @@ where
         Some(recon.clone()),
         metadata_builder.clone(),
     )?;
+    if let Some(rerun) = &opt2.rerun {
+        coord_processor.set_rerun_logger(flydra2::RerunLogger::new(rerun)?)?;
+    }
 
     let images_dirname = data_src.path_starter().join(IMAGES_DIRNAME);
     let mut found_image_paths: Vec<_> = match images_dirname.list_paths() {
@@ -779,7 +785,7 @@ pub async fn braid_offline_retrack(opt: Cli) -> anyhow::Result<()> {
                 .context(format!("loading tracking parameters {}", fname.display()))?;
             let tracking_params: flydra_types::TrackingParams = toml::from_str(&buf)?;
             let is_multicam = cam_info.camid2camn.keys().len() > 1;
-            if is_multicam == tracking_params.hypothesis_test_params.is_none() {
+            if is_multicam == tracking_params.is_2d() {
                 anyhow::bail!(
                     "In tracking parameters file \"{}\" for multicamera data, \
                     `hypothesis_test_params` must be set. For single camera data, \
@@ -812,6 +818,7 @@ pub async fn braid_offline_retrack(opt: Cli) -> anyhow::Result<()> {
     let opts = KalmanizeOptions {
         start_frame: opt.start_frame,
         stop_frame: opt.stop_frame,
+        rerun: opt.rerun,
         ..Default::default()
     };
 
@@ -881,4 +888,10 @@ pub struct Cli {
     /// Disable display of progress indicator
     #[arg(long)]
     pub no_progress: bool,
+    /// Stream camera calibrations, 2D detections and 3D tracking to rerun.
+    ///
+    /// This is either the address of a rerun viewer (which listens by default
+    /// on 127.0.0.1:9876) or the name of an `.rrd` file to save.
+    #[arg(long, env = "RERUN_VIEWER_ADDR")]
+    pub rerun: Option<flydra2::RerunTarget>,
 }
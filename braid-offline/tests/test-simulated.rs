@@ -0,0 +1,153 @@
+//! Tracking of simulated recordings with known ground truth.
+
+use std::path::Path;
+
+use eyre::{self as anyhow};
+
+use braid_sim::{GroundTruthRow, ObjectConfig, SimConfig, TrajectoryConfig};
+use flydra_types::{KalmanEstimatesRow, TrackTerminationReason, TrackTerminationRow};
+
+/// Read a CSV table saved as `<name>.gz` in the `.braidz` file `braidz`.
+fn read_braidz_table<T: serde::de::DeserializeOwned>(
+    braidz: &Path,
+    name: &str,
+) -> anyhow::Result<Vec<T>> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(braidz)?)?;
+    let file = archive.by_name(&format!("{name}.gz"))?;
+    let decoder = libflate::gzip::Decoder::new(file)?;
+    let rows = csv::Reader::from_reader(decoder)
+        .into_deserialize()
+        .collect::<Result<Vec<T>, _>>()?;
+    Ok(rows)
+}
+
+/// Simulate `config` and track it with `tracking_params`, returning the path
+/// of the tracked `.braidz` file and the ground truth.
+async fn simulate_and_track(
+    config: &SimConfig,
+    tracking_params: flydra_types::TrackingParams,
+    dir: &Path,
+) -> anyhow::Result<(std::path::PathBuf, Vec<GroundTruthRow>)> {
+    let sim_braidz = dir.join("sim.braidz");
+    let sim = braid_sim::simulate(config, None)?;
+    sim.save(&sim_braidz)?;
+
+    let data_src =
+        braidz_parser::incremental_parser::IncrementalParser::open_braidz_file(&sim_braidz)?;
+    let data_src = data_src.parse_basics()?;
+    let tracked_braidz = dir.join("tracked.braidz");
+    braid_offline::kalmanize(
+        data_src,
+        &tracked_braidz,
+        None,
+        tracking_params,
+        braid_offline::KalmanizeOptions::default(),
+        false,
+        &format!("{}:{}", file!(), line!()),
+        true,
+        None,
+    )
+    .await?;
+    Ok((tracked_braidz, sim.ground_truth))
+}
+
+#[tokio::test]
+async fn test_tracking_accuracy() -> anyhow::Result<()> {
+    // Two well separated objects, observed with noise, missed detections and
+    // outliers.
+    let config = SimConfig {
+        seed: 2,
+        fps: 100.0,
+        num_frames: 200,
+        start_time: 1704067200.0,
+        cameras: Default::default(),
+        detection: braid_sim::DetectionConfig {
+            pixel_noise_std: 0.5,
+            miss_probability: 0.05,
+            outliers_per_camera_frame: 0.2,
+        },
+        objects: vec![
+            ObjectConfig {
+                start_frame: 0,
+                num_frames: None,
+                trajectory: TrajectoryConfig::Circle {
+                    center: [0.0, 0.0, 0.2],
+                    radius_meters: 0.1,
+                    period_sec: 2.0,
+                },
+            },
+            ObjectConfig {
+                start_frame: 20,
+                num_frames: Some(150),
+                trajectory: TrajectoryConfig::Linear {
+                    start: [-0.3, 0.3, 0.4],
+                    velocity: [0.2, 0.0, 0.0],
+                },
+            },
+        ],
+    };
+    let tracking_params = flydra_types::default_tracking_params_full_3d();
+
+    let tmpdir = tempfile::tempdir()?; // cleanup on drop
+    let (tracked, ground_truth) =
+        simulate_and_track(&config, tracking_params, tmpdir.path()).await?;
+
+    let estimates: Vec<KalmanEstimatesRow> =
+        read_braidz_table(&tracked, flydra_types::KALMAN_ESTIMATES_CSV_FNAME)?;
+    let summary = braid_sim::evaluate(&ground_truth, &estimates, 0.05);
+    // A few frames pass before a new object is tracked.
+    assert!(summary.recall > 0.9, "{summary:?}");
+    assert!(summary.rms_error_meters < 0.005, "{summary:?}");
+    assert!(summary.max_error_meters < 0.02, "{summary:?}");
+    // Outliers do not give rise to tracked objects.
+    assert!(
+        summary.num_unmatched_estimates * 20 < summary.num_estimates,
+        "{summary:?}"
+    );
+    // No trajectory is fragmented.
+    assert_eq!(summary.mean_obj_ids_per_object, 1.0, "{summary:?}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_coast_limit() -> anyhow::Result<()> {
+    const MAX_COAST_FRAMES: u32 = 5;
+    // A single object observed without noise in frames 0-49.
+    let config = SimConfig {
+        seed: 1,
+        fps: 100.0,
+        num_frames: 100,
+        start_time: 1704067200.0,
+        cameras: Default::default(),
+        detection: braid_sim::DetectionConfig {
+            pixel_noise_std: 0.0,
+            ..Default::default()
+        },
+        objects: vec![ObjectConfig {
+            start_frame: 0,
+            num_frames: Some(50),
+            trajectory: TrajectoryConfig::Linear {
+                start: [0.0, 0.0, 0.2],
+                velocity: [0.1, 0.0, 0.0],
+            },
+        }],
+    };
+    let mut tracking_params = flydra_types::default_tracking_params_full_3d();
+    tracking_params.max_coast_frames = Some(MAX_COAST_FRAMES);
+    // Large enough that the object is not lost before reaching the coast
+    // limit.
+    tracking_params.max_position_std_meters = 1.0;
+
+    let tmpdir = tempfile::tempdir()?; // cleanup on drop
+    let (tracked, _) = simulate_and_track(&config, tracking_params, tmpdir.path()).await?;
+
+    let terminations: Vec<TrackTerminationRow> =
+        read_braidz_table(&tracked, flydra_types::TRACK_TERMINATION_CSV_FNAME)?;
+    assert_eq!(terminations.len(), 1, "{terminations:?}");
+    let termination = &terminations[0];
+    assert_eq!(termination.reason, TrackTerminationReason::CoastLimit);
+    // The object coasts in frames 50-54 and is terminated in frame 55.
+    assert_eq!(termination.frame.0, 49 + u64::from(MAX_COAST_FRAMES) + 1);
+    assert_eq!(termination.merged_into, None);
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use eyre::{self as anyhow, Result};
+use eyre::{self as anyhow, Result, WrapErr};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
@@ -90,6 +90,16 @@ pub struct ProcessingConfig {
     pub tracking_parameters_source: TrackingParametersSource,
 }
 
+impl Validate for ProcessingConfig {
+    fn validate<P: AsRef<Path>>(self, basedir: Option<P>) -> Result<Valid<Self>> {
+        let feature_detection_method = self.feature_detection_method.validate(basedir)?.0;
+        Ok(Valid(Self {
+            feature_detection_method,
+            ..self
+        }))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(deny_unknown_fields, tag = "type")]
 #[derive(Default)]
@@ -99,8 +109,47 @@ pub enum FeatureDetectionMethod {
     CopyExisting,
     // #[serde(rename = "bright-point")]
     // BrightPoint(BrightPointOptions),
-    // #[serde(rename = "flydra")]
-    // Flydra,
+    /// Detect features in the video frames with the flydra feature detector,
+    /// as done live by Strand Camera.
+    #[serde(rename = "flydra")]
+    Flydra(FlydraFeatureDetectionOptions),
+}
+
+impl Validate for FeatureDetectionMethod {
+    fn validate<P: AsRef<Path>>(self, basedir: Option<P>) -> Result<Valid<Self>> {
+        match self {
+            FeatureDetectionMethod::CopyExisting => Ok(Valid(self)),
+            FeatureDetectionMethod::Flydra(opts) => {
+                let detection_cfg = base_join(opts.detection_cfg, basedir)?;
+                Ok(Valid(FeatureDetectionMethod::Flydra(
+                    FlydraFeatureDetectionOptions { detection_cfg },
+                )))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct FlydraFeatureDetectionOptions {
+    /// YAML file with the feature detection parameters, in the format used by
+    /// Strand Camera. If not given, the default parameters are used.
+    pub detection_cfg: Option<String>,
+}
+
+impl FlydraFeatureDetectionOptions {
+    /// Load the feature detection parameters.
+    pub fn load(&self) -> Result<flydra_feature_detector_types::ImPtDetectCfg> {
+        match &self.detection_cfg {
+            Some(fname) => {
+                let buf = std::fs::read_to_string(fname)
+                    .with_context(|| format!("reading feature detection config \"{fname}\""))?;
+                serde_yaml::from_str(&buf)
+                    .with_context(|| format!("parsing feature detection config \"{fname}\""))
+            }
+            None => Ok(flydra_pt_detect_cfg::default_absdiff()),
+        }
+    }
 }
 
 
@@ -319,6 +368,14 @@ impl Validate for BraidRetrackVideoConfig {
             anyhow::bail!("No input videos or braidz file. At least one source is required.")
         }
 
+        if matches!(
+            self.processing_config.feature_detection_method,
+            FeatureDetectionMethod::Flydra(_)
+        ) && self.input_video.is_empty()
+        {
+            anyhow::bail!("Feature detection method \"flydra\" requires input videos.")
+        }
+
         // Validate `input_braidz`.
         let input_braidz = base_join(self.input_braidz, basedir.as_ref())?;
 
@@ -342,10 +399,14 @@ impl Validate for BraidRetrackVideoConfig {
             .map(|iv| iv.0)
             .collect();
 
+        // Validate `processing_config`.
+        let processing_config = self.processing_config.validate(basedir.as_ref())?.0;
+
         Ok(Valid(Self {
             input_braidz,
             output,
             input_video,
+            processing_config,
             ..self
         }))
     }
@@ -432,3 +493,41 @@ fn test_default_config_is_valid_and_serializable() -> Result<()> {
     toml::to_string_pretty(&cfg.valid())?;
     Ok(())
 }
+
+#[test]
+fn test_flydra_feature_detection_config() -> Result<()> {
+    let buf = r#"
+        output = []
+        input_video = [{ filename = "movie20240302_144852.000002145_Basler-40454395.mp4" }]
+
+        [processing_config]
+        camera_calibration_source = { type = "none" }
+        tracking_parameters_source = { type = "default" }
+
+        [processing_config.feature_detection_method]
+        type = "flydra"
+        detection_cfg = "detect.yaml"
+    "#;
+    let cfg: BraidRetrackVideoConfig = toml::from_str(buf)?;
+    let cfg = cfg.validate(Some("/data"))?;
+    assert_eq!(
+        cfg.valid().processing_config.feature_detection_method,
+        FeatureDetectionMethod::Flydra(FlydraFeatureDetectionOptions {
+            detection_cfg: Some("/data/detect.yaml".into()),
+        })
+    );
+
+    // Feature detection needs images.
+    let buf = r#"
+        output = []
+        input_braidz = "20240302_144852.braidz"
+
+        [processing_config]
+        feature_detection_method = { type = "flydra" }
+        camera_calibration_source = { type = "copy" }
+        tracking_parameters_source = { type = "copy" }
+    "#;
+    let cfg: BraidRetrackVideoConfig = toml::from_str(buf)?;
+    assert!(cfg.validate(Some("/data")).is_err());
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! Detection of features in the video frames.
+
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use basic_frame::DynamicFrame;
+use chrono::{DateTime, FixedOffset, Utc};
+use eyre::Result;
+use machine_vision_formats::{pixel_format::Mono8, PixFmt};
+
+use flydra_feature_detector::{FlydraFeatureDetector, UfmfState};
+use flydra_feature_detector_types::ImPtDetectCfg;
+use flydra_types::{FlydraRawUdpPoint, RawCamName};
+
+/// Runs the flydra feature detector on the frames of each camera.
+///
+/// As in Strand Camera, each camera has its own detector with its own model
+/// of the background, so the frames of a camera must be given in order.
+pub(crate) struct FlydraDetectors {
+    cfg: ImPtDetectCfg,
+    detectors: BTreeMap<RawCamName, FlydraFeatureDetector>,
+}
+
+impl FlydraDetectors {
+    pub(crate) fn new(cfg: ImPtDetectCfg) -> Self {
+        Self {
+            cfg,
+            detectors: BTreeMap::new(),
+        }
+    }
+
+    /// Detect the features in `frame` of camera `raw_name`.
+    pub(crate) fn detect(
+        &mut self,
+        raw_name: &RawCamName,
+        fno: usize,
+        timestamp: DateTime<FixedOffset>,
+        frame: &DynamicFrame,
+    ) -> Result<Vec<FlydraRawUdpPoint>> {
+        let converted;
+        let frame = if frame.pixel_format() == PixFmt::Mono8 {
+            frame
+        } else {
+            converted = DynamicFrame::from(frame.clone().into_pixel_format::<Mono8>()?);
+            &converted
+        };
+
+        let detector = match self.detectors.entry(raw_name.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(FlydraFeatureDetector::new(
+                raw_name,
+                frame.width(),
+                frame.height(),
+                self.cfg.clone(),
+                None,
+                None,
+            )?),
+        };
+
+        let (packet, _ufmf_state) = detector.process_new_frame(
+            frame,
+            fno,
+            timestamp.with_timezone(&Utc),
+            UfmfState::Stopped,
+            None,
+            None,
+            0,
+            None,
+        )?;
+        Ok(packet.points)
+    }
+}
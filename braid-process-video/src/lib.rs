@@ -10,7 +10,7 @@ use ordered_float::NotNan;
 
 use machine_vision_formats::{owned::OImage, pixel_format::Mono8, ImageData};
 
-use flydra_types::{Data2dDistortedRow, KalmanEstimatesRow, RawCamName};
+use flydra_types::{Data2dDistortedRow, FlydraRawUdpPoint, KalmanEstimatesRow, RawCamName};
 
 mod peek2;
 use peek2::Peek2;
@@ -34,6 +34,9 @@ pub use auto_config_generator::auto_config;
 
 mod tiny_skia_frame;
 
+mod feature_detection;
+use feature_detection::FlydraDetectors;
+
 mod output_types;
 use output_types::*;
 
@@ -316,6 +319,7 @@ impl PerCamRender {
             p: self,
             png_buf: None,
             points: vec![],
+            features: vec![],
             reprojected_points: vec![],
             pts_chrono,
         }
@@ -326,6 +330,9 @@ pub(crate) struct PerCamRenderFrame<'a> {
     pub(crate) p: &'a PerCamRender,
     pub(crate) png_buf: Option<Vec<u8>>,
     pub(crate) points: Vec<(NotNan<f64>, NotNan<f64>)>,
+    /// The 2D features, including the information beyond the position saved in
+    /// braidz output.
+    pub(crate) features: Vec<FlydraRawUdpPoint>,
     pub(crate) reprojected_points: Vec<(NotNan<f64>, NotNan<f64>)>,
     pub(crate) pts_chrono: DateTime<FixedOffset>,
 }
@@ -352,7 +359,23 @@ impl PerCamRenderFrame<'_> {
     }
 
     pub(crate) fn append_2d_point(&mut self, x: NotNan<f64>, y: NotNan<f64>) -> Result<()> {
+        self.append_feature(FlydraRawUdpPoint {
+            x0_abs: *x,
+            y0_abs: *y,
+            area: f64::NAN,
+            maybe_slope_eccentricty: None,
+            cur_val: 0,
+            mean_val: f64::NAN,
+            sumsqf_val: f64::NAN,
+            mean_intensity: f64::NAN,
+        })
+    }
+
+    pub(crate) fn append_feature(&mut self, pt: FlydraRawUdpPoint) -> Result<()> {
+        let x = NotNan::new(pt.x0_abs)?;
+        let y = NotNan::new(pt.y0_abs)?;
         self.points.push((x, y));
+        self.features.push(pt);
         Ok(())
     }
 }
@@ -497,6 +520,12 @@ pub async fn run_config(cfg: &Valid<BraidRetrackVideoConfig>) -> Result<Vec<std:
         .as_ref()
         .map(|archive| archive.expected_fps as f32);
 
+    let detection_cfg = match &cfg.processing_config.feature_detection_method {
+        FeatureDetectionMethod::CopyExisting => None,
+        FeatureDetectionMethod::Flydra(opts) => Some(opts.load()?),
+    };
+    let mut detectors = detection_cfg.clone().map(FlydraDetectors::new);
+
     let frame_sources: Vec<_> = cfg
         .input_video
         .iter()
@@ -786,6 +815,7 @@ pub async fn run_config(cfg: &Valid<BraidRetrackVideoConfig>) -> Result<Vec<std:
                         all_expected_cameras.clone(),
                         expected_framerate,
                         braidz_calibration.clone(),
+                        detection_cfg.clone(),
                     )
                     .await?;
 
@@ -835,8 +865,14 @@ pub async fn run_config(cfg: &Valid<BraidRetrackVideoConfig>) -> Result<Vec<std:
         }
 
         // --- Collect input data for this timepoint. -----
-        let all_cam_render_data =
-            gather_frame_data(&synced_data, &sources, &mut output_storage, cfg)?;
+        let all_cam_render_data = gather_frame_data(
+            out_fno,
+            &synced_data,
+            &sources,
+            &mut output_storage,
+            detectors.as_mut(),
+            cfg,
+        )?;
 
         // --- Done collecting input data for this timepoint. -----
         for output in output_storage.iter_mut() {
@@ -862,9 +898,11 @@ pub async fn run_config(cfg: &Valid<BraidRetrackVideoConfig>) -> Result<Vec<std:
 }
 
 fn gather_frame_data<'a>(
+    out_fno: usize,
     synced_data: &SyncedPictures,
     sources: &'a [CameraSource],
     output_storage: &mut [OutputStorage],
+    mut detectors: Option<&mut FlydraDetectors>,
     cfg: &BraidRetrackVideoConfig,
 ) -> Result<Vec<PerCamRenderFrame<'a>>> {
     let synced_pics: &[OutTimepointPerCamera] = &synced_data.camera_pictures;
@@ -880,6 +918,18 @@ fn gather_frame_data<'a>(
         // Did we get an image from the MP4 file?
         if let Some(pic) = &per_cam.image {
             cam_render_data.set_original_image(pic)?;
+
+            if let Some(detectors) = detectors.as_mut() {
+                let points = detectors.detect(
+                    &source.per_cam_render.raw_name,
+                    out_fno,
+                    per_cam.timestamp,
+                    pic,
+                )?;
+                for pt in points {
+                    cam_render_data.append_feature(pt)?;
+                }
+            }
         }
         let mut wrote_debug = false;
 
@@ -914,6 +964,9 @@ fn gather_frame_data<'a>(
                         cam_render_data.append_2d_point(x, y)?;
                     }
                 }
+                FeatureDetectionMethod::Flydra(_) => {
+                    // The features detected above replace the saved ones.
+                }
             }
         }
 
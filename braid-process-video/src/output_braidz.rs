@@ -24,6 +24,7 @@ impl BraidStorage {
         all_expected_cameras: BTreeSet<RawCamName>,
         expected_framerate: Option<f32>,
         braidz_calibration: Option<braidz_types::CalibrationInfo>,
+        detection_cfg: Option<flydra_feature_detector_types::ImPtDetectCfg>,
     ) -> Result<Self> {
         let output_braidz_path = std::path::PathBuf::from(&b.filename);
         let output_dirname =
@@ -79,7 +80,11 @@ impl BraidStorage {
                     PerCamSaveData {
                         current_image_png,
                         cam_settings_data: None,
-                        feature_detect_settings: None,
+                        feature_detect_settings: detection_cfg.clone().map(|cfg| {
+                            flydra_types::UpdateFeatureDetectSettings {
+                                current_feature_detect_settings: cfg,
+                            }
+                        }),
                     },
                 )
             })
@@ -174,23 +179,12 @@ impl BraidStorage {
             );
 
             let points: Vec<_> = cam_render_data
-                .points
+                .features
                 .iter()
                 .enumerate()
-                .map(|(idx, xy)| {
-                    let pt = flydra_types::FlydraRawUdpPoint {
-                        x0_abs: *xy.0,
-                        y0_abs: *xy.1,
-                        area: f64::NAN,
-                        maybe_slope_eccentricty: None,
-                        cur_val: 0,
-                        mean_val: f64::NAN,
-                        sumsqf_val: f64::NAN,
-                    };
-                    flydra2::NumberedRawUdpPoint {
-                        idx: idx.try_into().unwrap(),
-                        pt,
-                    }
+                .map(|(idx, pt)| flydra2::NumberedRawUdpPoint {
+                    idx: idx.try_into().unwrap(),
+                    pt: pt.clone(),
                 })
                 .collect();
 
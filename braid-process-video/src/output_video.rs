@@ -59,12 +59,14 @@ impl<'lib> VideoStorage<'lib> {
                     codec,
                     max_framerate: Default::default(),
                     h264_metadata: None,
+                    color_encoding: Default::default(),
                 }
             }
             crate::config::VideoCodecConfig::LessAvc => Mp4RecordingConfig {
                 codec: Mp4Codec::H264LessAvc,
                 max_framerate: Default::default(),
                 h264_metadata: None,
+                color_encoding: Default::default(),
             },
         };
 
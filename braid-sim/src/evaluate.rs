@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use flydra_types::KalmanEstimatesRow;
+
+use crate::GroundTruthRow;
+
+/// Comparison of tracking results with the ground truth of a simulation.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EvaluationSummary {
+    pub num_truth_samples: usize,
+    pub num_estimates: usize,
+    /// The fraction of ground truth samples matched by an estimate.
+    pub recall: f64,
+    /// The number of estimates not matched to a ground truth sample.
+    pub num_unmatched_estimates: usize,
+    /// The root mean square distance, in meters, between the matched
+    /// estimates and ground truth samples.
+    pub rms_error_meters: f64,
+    /// The largest distance, in meters, between a matched estimate and its
+    /// ground truth sample.
+    pub max_error_meters: f64,
+    /// The mean, over the ground truth objects with matches, of the number of
+    /// distinct object IDs matched to them. This is 1 if no trajectory is
+    /// fragmented.
+    pub mean_obj_ids_per_object: f64,
+}
+
+/// Compare the Kalman estimates of tracking with the ground truth.
+///
+/// In each frame, estimates are matched to ground truth samples within
+/// `max_distance_meters`, closest pairs first, with each used at most once.
+pub fn evaluate(
+    ground_truth: &[GroundTruthRow],
+    estimates: &[KalmanEstimatesRow],
+    max_distance_meters: f64,
+) -> EvaluationSummary {
+    let mut truth_by_frame: BTreeMap<u64, Vec<&GroundTruthRow>> = BTreeMap::new();
+    for row in ground_truth.iter() {
+        truth_by_frame.entry(row.frame).or_default().push(row);
+    }
+    let mut estimates_by_frame: BTreeMap<u64, Vec<&KalmanEstimatesRow>> = BTreeMap::new();
+    for row in estimates.iter() {
+        estimates_by_frame.entry(row.frame.0).or_default().push(row);
+    }
+
+    let mut errors = Vec::new();
+    let mut obj_ids: BTreeMap<u32, BTreeSet<u32>> = BTreeMap::new();
+    for (frame, truth) in truth_by_frame.iter() {
+        let Some(frame_estimates) = estimates_by_frame.get(frame) else {
+            continue;
+        };
+        let mut pairs = Vec::new();
+        for (i, t) in truth.iter().enumerate() {
+            for (j, e) in frame_estimates.iter().enumerate() {
+                let dist = ((t.x - e.x).powi(2) + (t.y - e.y).powi(2) + (t.z - e.z).powi(2)).sqrt();
+                if dist <= max_distance_meters {
+                    pairs.push((dist, i, j));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut used_truth = vec![false; truth.len()];
+        let mut used_estimates = vec![false; frame_estimates.len()];
+        for (dist, i, j) in pairs {
+            if used_truth[i] || used_estimates[j] {
+                continue;
+            }
+            used_truth[i] = true;
+            used_estimates[j] = true;
+            errors.push(dist);
+            obj_ids
+                .entry(truth[i].obj_id)
+                .or_default()
+                .insert(frame_estimates[j].obj_id);
+        }
+    }
+
+    let num_matched = errors.len();
+    let rms_error_meters = if num_matched > 0 {
+        (errors.iter().map(|e| e * e).sum::<f64>() / num_matched as f64).sqrt()
+    } else {
+        f64::NAN
+    };
+    let mean_obj_ids_per_object = if obj_ids.is_empty() {
+        f64::NAN
+    } else {
+        obj_ids.values().map(|ids| ids.len()).sum::<usize>() as f64 / obj_ids.len() as f64
+    };
+    EvaluationSummary {
+        num_truth_samples: ground_truth.len(),
+        num_estimates: estimates.len(),
+        recall: num_matched as f64 / ground_truth.len() as f64,
+        num_unmatched_estimates: estimates.len() - num_matched,
+        rms_error_meters,
+        max_error_meters: errors.iter().copied().fold(f64::NAN, f64::max),
+        mean_obj_ids_per_object,
+    }
+}
+
+#[test]
+fn test_evaluate() {
+    use flydra_types::SyncFno;
+
+    let truth = |obj_id, frame, x| GroundTruthRow {
+        obj_id,
+        frame,
+        timestamp: frame as f64 * 0.01,
+        x,
+        y: 0.0,
+        z: 0.0,
+        xvel: 0.0,
+        yvel: 0.0,
+        zvel: 0.0,
+    };
+    let estimate = |obj_id, frame, x| KalmanEstimatesRow {
+        obj_id,
+        frame: SyncFno(frame),
+        timestamp: None,
+        x,
+        y: 0.0,
+        z: 0.0,
+        xvel: 0.0,
+        yvel: 0.0,
+        zvel: 0.0,
+        P00: 0.0,
+        P01: 0.0,
+        P02: 0.0,
+        P11: 0.0,
+        P12: 0.0,
+        P22: 0.0,
+        P33: 0.0,
+        P44: 0.0,
+        P55: 0.0,
+        num_cams: 2,
+        max_ray_angle: 1.0,
+        innovation_pixels: 0.0,
+        pos_cov_trace: 0.0,
+    };
+    let ground_truth = vec![
+        truth(0, 0, 0.0),
+        truth(0, 1, 0.1),
+        truth(0, 2, 0.2),
+        truth(1, 0, 1.0),
+    ];
+    let estimates = vec![
+        estimate(7, 0, 0.01),
+        estimate(7, 1, 0.1),
+        // A new object ID for the same object.
+        estimate(8, 2, 0.23),
+        // Too far from the ground truth.
+        estimate(9, 0, 1.5),
+    ];
+    let summary = evaluate(&ground_truth, &estimates, 0.05);
+    assert_eq!(summary.num_truth_samples, 4);
+    assert_eq!(summary.num_unmatched_estimates, 1);
+    assert!((summary.recall - 0.75).abs() < 1e-12);
+    assert!((summary.max_error_meters - 0.03).abs() < 1e-12);
+    assert!((summary.mean_obj_ids_per_object - 2.0).abs() < 1e-12);
+}
@@ -0,0 +1,511 @@
+//! Simulation of Braid recordings for testing tracking and calibration.
+//!
+//! A simulation has cameras, either on a ring looking at a common point or
+//! from a calibration file, and objects moving along configured trajectories.
+//! The objects are projected to each camera to create the 2D detections which
+//! Braid would save, with pixel noise, missed detections and outliers. The
+//! result is saved as a `.braidz` file without tracking results, which can be
+//! tracked with `braid-offline`, together with CSV files of the ground truth
+//! trajectories and of the source of every detection. [evaluate] compares the
+//! tracking results with the ground truth.
+//!
+//! Simulations are reproducible: the same configuration, including its
+//! `seed`, always gives the same output.
+
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+use nalgebra::{Point3, Unit, Vector3};
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal, Poisson};
+use serde::{Deserialize, Serialize};
+
+use flydra_mvg::FlydraMultiCameraSystem;
+use flydra_types::{CamInfoRow, CamNum, Data2dDistortedRow, FlydraFloatTimestampLocal, TextlogRow};
+use mvg::PointWorldFrame;
+
+mod evaluate;
+mod trajectory;
+
+pub use evaluate::{evaluate, EvaluationSummary};
+pub use trajectory::TrajectoryConfig;
+
+/// The filename suffix of the ground truth CSV file.
+pub const GROUND_TRUTH_SUFFIX: &str = ".ground_truth.csv";
+/// The filename suffix of the CSV file with the source of each detection.
+pub const DETECTION_SOURCES_SUFFIX: &str = ".detection_sources.csv";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("MVG error: {0}")]
+    Mvg(#[from] mvg::MvgError),
+    #[error("{0}")]
+    FlydraMvg(#[from] flydra_mvg::FlydraMvgError),
+    #[error("{0}")]
+    BraidzWriter(#[from] braidz_writer::Error),
+    #[error("invalid configuration: {0}")]
+    InvalidConfig(String),
+}
+
+/// Cameras equally spaced on a horizontal circle, all looking at `look_at`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct CameraRingConfig {
+    pub num_cameras: usize,
+    pub radius_meters: f64,
+    pub height_meters: f64,
+    pub look_at: [f64; 3],
+    pub width: usize,
+    pub height: usize,
+    pub focal_length_px: f64,
+}
+
+impl Default for CameraRingConfig {
+    fn default() -> Self {
+        Self {
+            num_cameras: 4,
+            radius_meters: 1.0,
+            height_meters: 1.0,
+            look_at: [0.0, 0.0, 0.0],
+            width: 1280,
+            height: 1024,
+            focal_length_px: 1000.0,
+        }
+    }
+}
+
+impl CameraRingConfig {
+    /// The camera system, with cameras named `sim-cam-1`, `sim-cam-2`, etc.
+    pub fn build(&self) -> Result<FlydraMultiCameraSystem<f64>, Error> {
+        if self.num_cameras == 0 {
+            return Err(Error::InvalidConfig("no cameras".into()));
+        }
+        let look_at = Vector3::from(self.look_at);
+        let up = Unit::new_normalize(Vector3::z());
+        let mut cams = BTreeMap::new();
+        for i in 0..self.num_cameras {
+            let angle = std::f64::consts::TAU * i as f64 / self.num_cameras as f64;
+            let (s, c) = angle.sin_cos();
+            let camcenter = Vector3::new(
+                look_at.x + self.radius_meters * c,
+                look_at.y + self.radius_meters * s,
+                self.height_meters,
+            );
+            let extrinsics = cam_geom::ExtrinsicParameters::from_view(&camcenter, &look_at, &up);
+            let intrinsics = opencv_ros_camera::RosOpenCvIntrinsics::from_params(
+                self.focal_length_px,
+                0.0,
+                self.focal_length_px,
+                self.width as f64 / 2.0,
+                self.height as f64 / 2.0,
+            );
+            let cam = mvg::Camera::new(self.width, self.height, extrinsics, intrinsics)?;
+            cams.insert(format!("sim-cam-{}", i + 1), cam);
+        }
+        Ok(FlydraMultiCameraSystem::new(cams, None))
+    }
+}
+
+/// The imperfections of the 2D detections.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct DetectionConfig {
+    /// The standard deviation of the normally distributed noise added to each
+    /// pixel coordinate.
+    pub pixel_noise_std: f64,
+    /// The probability that an object visible to a camera is not detected.
+    pub miss_probability: f64,
+    /// The mean number of outliers, uniformly distributed over the image, per
+    /// camera and frame.
+    pub outliers_per_camera_frame: f64,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            pixel_noise_std: 0.5,
+            miss_probability: 0.0,
+            outliers_per_camera_frame: 0.0,
+        }
+    }
+}
+
+/// A simulated object.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObjectConfig {
+    /// The first frame in which the object exists.
+    #[serde(default)]
+    pub start_frame: u64,
+    /// The number of frames in which the object exists. Defaults to the end
+    /// of the simulation.
+    pub num_frames: Option<u64>,
+    pub trajectory: TrajectoryConfig,
+}
+
+/// The configuration of a simulation, usually loaded from a TOML file.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimConfig {
+    /// The seed of the random number generator.
+    #[serde(default)]
+    pub seed: u64,
+    pub fps: f64,
+    pub num_frames: u64,
+    /// The trigger timestamp of frame 0, in seconds since the Unix epoch.
+    #[serde(default = "default_start_time")]
+    pub start_time: f64,
+    /// The cameras. Ignored if a calibration is given to [simulate].
+    #[serde(default)]
+    pub cameras: CameraRingConfig,
+    #[serde(default)]
+    pub detection: DetectionConfig,
+    pub objects: Vec<ObjectConfig>,
+}
+
+fn default_start_time() -> f64 {
+    // 2024-01-01T00:00:00Z
+    1704067200.0
+}
+
+/// The true state of an object in a frame.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct GroundTruthRow {
+    /// The index of the object in [SimConfig::objects].
+    pub obj_id: u32,
+    pub frame: u64,
+    pub timestamp: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub xvel: f64,
+    pub yvel: f64,
+    pub zvel: f64,
+}
+
+/// The source of a row of the `data2d_distorted` table.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DetectionSourceRow {
+    pub camn: CamNum,
+    pub frame: i64,
+    pub frame_pt_idx: u8,
+    /// The object which was detected, or `None` for outliers.
+    pub obj_id: Option<u32>,
+}
+
+/// The result of a simulation.
+pub struct SimOutput {
+    pub system: FlydraMultiCameraSystem<f64>,
+    pub fps: f64,
+    pub start_time: f64,
+    pub cam_info: Vec<CamInfoRow>,
+    pub ground_truth: Vec<GroundTruthRow>,
+    /// The 2D detections. Cameras without detections in a frame have a row
+    /// with NaN coordinates, as Braid saves with `save_empty_data2d`.
+    pub data2d: Vec<Data2dDistortedRow>,
+    /// The source of each detection of `data2d` which is not empty.
+    pub detection_sources: Vec<DetectionSourceRow>,
+}
+
+/// Whether `pt` is in front of the camera.
+fn is_in_front(cam: &flydra_mvg::MultiCamera<f64>, pt: &Point3<f64>) -> bool {
+    let extrinsics = cam.extrinsics();
+    let cam_frame = extrinsics.rotation() * (pt - extrinsics.camcenter());
+    cam_frame.z > 0.0
+}
+
+/// Run a simulation.
+///
+/// If `calibration` is given, its cameras are used instead of
+/// [SimConfig::cameras].
+pub fn simulate(
+    config: &SimConfig,
+    calibration: Option<FlydraMultiCameraSystem<f64>>,
+) -> Result<SimOutput, Error> {
+    if config.fps.is_nan() || config.fps <= 0.0 {
+        return Err(Error::InvalidConfig("fps must be positive".into()));
+    }
+    let det = &config.detection;
+    let noise = Normal::new(0.0, det.pixel_noise_std)
+        .map_err(|e| Error::InvalidConfig(format!("invalid pixel noise: {e}")))?;
+    if !(0.0..=1.0).contains(&det.miss_probability) {
+        return Err(Error::InvalidConfig(
+            "miss probability must be between 0 and 1".into(),
+        ));
+    }
+    let outliers = if det.outliers_per_camera_frame > 0.0 {
+        Some(
+            Poisson::new(det.outliers_per_camera_frame)
+                .map_err(|e| Error::InvalidConfig(format!("invalid outlier rate: {e}")))?,
+        )
+    } else {
+        None
+    };
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(config.seed);
+    let system = match calibration {
+        Some(system) => system,
+        None => config.cameras.build()?,
+    };
+    let dt = 1.0 / config.fps;
+    let timestamp = |frame: u64| config.start_time + frame as f64 * dt;
+
+    let mut ground_truth = Vec::new();
+    for (obj_id, obj) in config.objects.iter().enumerate() {
+        let remaining = config.num_frames.saturating_sub(obj.start_frame);
+        let n = obj.num_frames.unwrap_or(remaining).min(remaining);
+        let states = obj.trajectory.states(n as usize, dt, &mut rng)?;
+        for (i, (p, v)) in states.into_iter().enumerate() {
+            let frame = obj.start_frame + i as u64;
+            ground_truth.push(GroundTruthRow {
+                obj_id: obj_id as u32,
+                frame,
+                timestamp: timestamp(frame),
+                x: p.x,
+                y: p.y,
+                z: p.z,
+                xvel: v.x,
+                yvel: v.y,
+                zvel: v.z,
+            });
+        }
+    }
+    ground_truth.sort_by_key(|row| (row.frame, row.obj_id));
+    let mut truth_by_frame: BTreeMap<u64, Vec<&GroundTruthRow>> = BTreeMap::new();
+    for row in ground_truth.iter() {
+        truth_by_frame.entry(row.frame).or_default().push(row);
+    }
+
+    let cams: Vec<_> = system.cameras().collect();
+    let cam_info: Vec<CamInfoRow> = cams
+        .iter()
+        .enumerate()
+        .map(|(camn, cam)| CamInfoRow {
+            camn: CamNum(camn as u8),
+            cam_id: cam.name().to_string(),
+        })
+        .collect();
+
+    let mut data2d = Vec::new();
+    let mut detection_sources = Vec::new();
+    let no_truth = Vec::new();
+    for frame in 0..config.num_frames {
+        let trigger_timestamp = timestamp(frame);
+        let truth = truth_by_frame.get(&frame).unwrap_or(&no_truth);
+        for (cam, info) in cams.iter().zip(cam_info.iter()) {
+            let (w, h) = (cam.width() as f64, cam.height() as f64);
+            let mut points: Vec<(f64, f64, Option<u32>)> = Vec::new();
+            for row in truth.iter() {
+                let pt = Point3::new(row.x, row.y, row.z);
+                if !is_in_front(cam, &pt) {
+                    continue;
+                }
+                let px = cam.project_3d_to_distorted_pixel(&PointWorldFrame { coords: pt });
+                if rng.gen::<f64>() < det.miss_probability {
+                    continue;
+                }
+                let x = px.coords.x + noise.sample(&mut rng);
+                let y = px.coords.y + noise.sample(&mut rng);
+                if (0.0..w).contains(&x) && (0.0..h).contains(&y) {
+                    points.push((x, y, Some(row.obj_id)));
+                }
+            }
+            if let Some(outliers) = &outliers {
+                let n: f64 = outliers.sample(&mut rng);
+                for _ in 0..n as usize {
+                    points.push((rng.gen_range(0.0..w), rng.gen_range(0.0..h), None));
+                }
+            }
+
+            let row = |frame_pt_idx: u8, x: f64, y: f64| Data2dDistortedRow {
+                camn: info.camn,
+                frame: frame as i64,
+                timestamp: Some(FlydraFloatTimestampLocal::from_f64(trigger_timestamp)),
+                cam_received_timestamp: FlydraFloatTimestampLocal::from_f64(
+                    trigger_timestamp + 0.002,
+                ),
+                device_timestamp: None,
+                block_id: Some(frame),
+                x,
+                y,
+                area: if x.is_nan() { f64::NAN } else { 10.0 },
+                slope: f64::NAN,
+                eccentricity: f64::NAN,
+                frame_pt_idx,
+                cur_val: 255,
+                mean_val: f64::NAN,
+                sumsqf_val: f64::NAN,
+                mean_intensity: f64::NAN,
+            };
+            if points.is_empty() {
+                data2d.push(row(0, f64::NAN, f64::NAN));
+            }
+            // Braid saves at most 256 detections per camera and frame.
+            for (idx, (x, y, obj_id)) in points.into_iter().take(256).enumerate() {
+                let frame_pt_idx = idx as u8;
+                data2d.push(row(frame_pt_idx, x, y));
+                detection_sources.push(DetectionSourceRow {
+                    camn: info.camn,
+                    frame: frame as i64,
+                    frame_pt_idx,
+                    obj_id,
+                });
+            }
+        }
+    }
+
+    Ok(SimOutput {
+        system,
+        fps: config.fps,
+        start_time: config.start_time,
+        cam_info,
+        ground_truth,
+        data2d,
+        detection_sources,
+    })
+}
+
+fn write_csv<T: Serialize>(path: &Path, rows: &[T]) -> Result<(), Error> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    for row in rows.iter() {
+        wtr.serialize(row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+impl SimOutput {
+    /// Save the simulated recording to `output_braidz` and the ground truth
+    /// and detection sources to CSV files next to it, with the suffixes
+    /// [GROUND_TRUTH_SUFFIX] and [DETECTION_SOURCES_SUFFIX].
+    pub fn save<P: AsRef<Path>>(&self, output_braidz: P) -> Result<(), Error> {
+        let output_braidz = output_braidz.as_ref();
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path().join("sim.braid");
+        std::fs::create_dir(&dir)?;
+
+        let fd = std::fs::File::create(dir.join(flydra_types::CALIBRATION_XML_FNAME))?;
+        self.system.to_flydra_xml(fd)?;
+
+        write_csv(&dir.join(flydra_types::CAM_INFO_CSV_FNAME), &self.cam_info)?;
+        write_csv(
+            &dir.join(flydra_types::DATA2D_DISTORTED_CSV_FNAME),
+            &self.data2d,
+        )?;
+
+        let recording_time = datetime_conversion::f64_to_datetime(self.start_time);
+        let metadata = braidz_types::BraidMetadata {
+            schema: flydra_types::BRAID_SCHEMA, // BraidMetadataSchemaTag
+            git_revision: "unknown".to_string(),
+            original_recording_time: Some(recording_time.with_timezone(&chrono::Local)),
+            save_empty_data2d: true,
+            saving_program_name: env!("CARGO_PKG_NAME").to_string(),
+            tracking_2d: false,
+        };
+        let mut fd = std::fs::File::create(dir.join(flydra_types::BRAID_METADATA_YML_FNAME))?;
+        fd.write_all(serde_yaml::to_string(&metadata)?.as_bytes())?;
+
+        // The frame rate and tracking parameters are read from the textlog.
+        let tracking_params = serde_json::json!({
+            "tracking_params": flydra_types::default_tracking_params_full_3d(),
+            "git_revision": "unknown",
+        });
+        let textlog = [
+            format!(
+                "MainBrain running at {} fps, (flydra_version 2.0.0, \
+                git_revision unknown, time_tzname0 UTC)",
+                self.fps
+            ),
+            serde_json::to_string(&tracking_params)?,
+        ]
+        .map(|message| TextlogRow {
+            mainbrain_timestamp: self.start_time,
+            cam_id: "mainbrain".to_string(),
+            host_timestamp: self.start_time,
+            message,
+        });
+        write_csv(&dir.join(flydra_types::TEXTLOG_CSV_FNAME), &textlog)?;
+
+        braidz_writer::dir_to_braidz(&dir, output_braidz)?;
+
+        let with_suffix = |suffix: &str| {
+            let mut path = output_braidz.as_os_str().to_owned();
+            path.push(suffix);
+            std::path::PathBuf::from(path)
+        };
+        write_csv(&with_suffix(GROUND_TRUTH_SUFFIX), &self.ground_truth)?;
+        write_csv(
+            &with_suffix(DETECTION_SOURCES_SUFFIX),
+            &self.detection_sources,
+        )?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_example_config() {
+    let config: SimConfig = toml::from_str(include_str!("../example-sim.toml")).unwrap();
+    assert_eq!(config.objects.len(), 3);
+    let output = simulate(&config, None).unwrap();
+    assert_eq!(output.cam_info.len(), config.cameras.num_cameras);
+}
+
+#[test]
+fn test_simulate_triangulates() {
+    let config = SimConfig {
+        seed: 1,
+        fps: 100.0,
+        num_frames: 10,
+        start_time: default_start_time(),
+        cameras: CameraRingConfig::default(),
+        detection: DetectionConfig {
+            pixel_noise_std: 0.0,
+            ..Default::default()
+        },
+        objects: vec![ObjectConfig {
+            start_frame: 2,
+            num_frames: None,
+            trajectory: TrajectoryConfig::Linear {
+                start: [0.1, -0.05, 0.2],
+                velocity: [0.5, 0.0, 0.0],
+            },
+        }],
+    };
+    let output = simulate(&config, None).unwrap();
+    assert_eq!(output.ground_truth.len(), 8);
+    // A row per camera and frame, as there is one object and no outliers.
+    assert_eq!(output.data2d.len(), 4 * 10);
+    assert_eq!(output.detection_sources.len(), 4 * 8);
+
+    let truth = &output.ground_truth[3];
+    let camn2name: BTreeMap<_, _> = output
+        .cam_info
+        .iter()
+        .map(|row| (row.camn, row.cam_id.clone()))
+        .collect();
+    let points: Vec<_> = output
+        .data2d
+        .iter()
+        .filter(|row| row.frame == truth.frame as i64 && !row.x.is_nan())
+        .map(|row| {
+            (
+                camn2name[&row.camn].clone(),
+                mvg::DistortedPixel {
+                    coords: nalgebra::Point2::new(row.x, row.y),
+                },
+            )
+        })
+        .collect();
+    assert_eq!(points.len(), 4);
+    let found = output.system.find3d_distorted(&points).unwrap();
+    let coords = found.point().coords;
+    let expected = Vector3::new(truth.x, truth.y, truth.z);
+    assert!((coords.coords - expected).norm() < 1e-6, "{coords:?}");
+}
@@ -0,0 +1,98 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use eyre::{self as anyhow, WrapErr};
+
+use braid_sim::{GroundTruthRow, SimConfig};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Simulate a recording and save it as a `.braidz` file, with the ground
+    /// truth and detection sources in CSV files next to it.
+    Generate {
+        /// The simulation configuration TOML file.
+        #[arg(long)]
+        config: PathBuf,
+        /// A calibration XML file with the cameras to use instead of those of
+        /// the configuration.
+        #[arg(long)]
+        calibration: Option<PathBuf>,
+        /// The output `.braidz` file.
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Compare the tracking results of a simulated recording with its ground
+    /// truth.
+    Evaluate {
+        /// The ground truth CSV file saved by `generate`.
+        #[arg(long)]
+        ground_truth: PathBuf,
+        /// The `.braidz` file with tracking results, e.g. from
+        /// `braid-offline-retrack`.
+        #[arg(long)]
+        tracked: PathBuf,
+        /// The largest distance, in meters, of an estimate matched to a ground
+        /// truth position.
+        #[arg(long, default_value_t = 0.05)]
+        max_distance: f64,
+    },
+}
+
+fn main() -> anyhow::Result<()> {
+    let _tracing_guard = env_tracing_logger::init();
+    let cli = Cli::parse();
+    match cli.command {
+        Commands::Generate {
+            config,
+            calibration,
+            output,
+        } => {
+            let buf = std::fs::read_to_string(&config)
+                .with_context(|| format!("while reading config file {}", config.display()))?;
+            let config: SimConfig = toml::from_str(&buf)
+                .with_context(|| format!("while parsing config file {}", config.display()))?;
+            let calibration = calibration
+                .map(|path| {
+                    flydra_mvg::FlydraMultiCameraSystem::from_path(&path).with_context(|| {
+                        format!("while reading calibration file {}", path.display())
+                    })
+                })
+                .transpose()?;
+            let sim = braid_sim::simulate(&config, calibration)?;
+            sim.save(&output)?;
+            tracing::info!(
+                "Saved {} detections of {} ground truth positions to {}",
+                sim.detection_sources.len(),
+                sim.ground_truth.len(),
+                output.display()
+            );
+        }
+        Commands::Evaluate {
+            ground_truth,
+            tracked,
+            max_distance,
+        } => {
+            let truth = csv::Reader::from_path(&ground_truth)
+                .and_then(|mut rdr| {
+                    rdr.deserialize()
+                        .collect::<Result<Vec<GroundTruthRow>, _>>()
+                })
+                .with_context(|| format!("while reading {}", ground_truth.display()))?;
+            let archive = braidz_parser::braidz_parse_path(&tracked)
+                .with_context(|| format!("while parsing {}", tracked.display()))?;
+            let estimates = archive
+                .kalman_estimates_table
+                .ok_or_else(|| anyhow::anyhow!("no tracking results in {}", tracked.display()))?;
+            let summary = braid_sim::evaluate(&truth, &estimates, max_distance);
+            print!("{}", serde_yaml::to_string(&summary)?);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use nalgebra::Vector3;
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// The motion of a simulated object.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, tag = "type", rename_all = "snake_case")]
+pub enum TrajectoryConfig {
+    /// Constant velocity.
+    Linear { start: [f64; 3], velocity: [f64; 3] },
+    /// A horizontal circle, counterclockwise seen from above, starting at
+    /// the point with the largest x.
+    Circle {
+        center: [f64; 3],
+        radius_meters: f64,
+        period_sec: f64,
+    },
+    /// Velocity changed each frame by normally distributed accelerations.
+    RandomWalk {
+        start: [f64; 3],
+        #[serde(default)]
+        initial_velocity: [f64; 3],
+        accel_std_meters_per_sec2: f64,
+    },
+}
+
+impl TrajectoryConfig {
+    /// The position and velocity of `n` frames at intervals of `dt`.
+    pub(crate) fn states<G: Rng>(
+        &self,
+        n: usize,
+        dt: f64,
+        rng: &mut G,
+    ) -> Result<Vec<(Vector3<f64>, Vector3<f64>)>, Error> {
+        let states = match self {
+            Self::Linear { start, velocity } => {
+                let (start, velocity) = (Vector3::from(*start), Vector3::from(*velocity));
+                (0..n)
+                    .map(|i| (start + velocity * (i as f64 * dt), velocity))
+                    .collect()
+            }
+            Self::Circle {
+                center,
+                radius_meters,
+                period_sec,
+            } => {
+                let center = Vector3::from(*center);
+                let omega = std::f64::consts::TAU / period_sec;
+                (0..n)
+                    .map(|i| {
+                        let (s, c) = (omega * i as f64 * dt).sin_cos();
+                        let position = center + Vector3::new(c, s, 0.0) * *radius_meters;
+                        let velocity = Vector3::new(-s, c, 0.0) * (radius_meters * omega);
+                        (position, velocity)
+                    })
+                    .collect()
+            }
+            Self::RandomWalk {
+                start,
+                initial_velocity,
+                accel_std_meters_per_sec2,
+            } => {
+                let accel = Normal::new(0.0, *accel_std_meters_per_sec2).map_err(|e| {
+                    Error::InvalidConfig(format!("invalid acceleration standard deviation: {e}"))
+                })?;
+                let mut position = Vector3::from(*start);
+                let mut velocity = Vector3::from(*initial_velocity);
+                let mut states = Vec::with_capacity(n);
+                for _ in 0..n {
+                    states.push((position, velocity));
+                    let a = Vector3::from_fn(|_, _| accel.sample(rng));
+                    position += velocity * dt + a * (0.5 * dt * dt);
+                    velocity += a * dt;
+                }
+                states
+            }
+        };
+        Ok(states)
+    }
+}
+
+#[test]
+fn test_circle() {
+    use rand::SeedableRng;
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let trajectory = TrajectoryConfig::Circle {
+        center: [0.0, 0.0, 0.5],
+        radius_meters: 0.2,
+        period_sec: 1.0,
+    };
+    let states = trajectory.states(101, 0.01, &mut rng).unwrap();
+    let (p0, v0) = states[0];
+    assert!((p0 - Vector3::new(0.2, 0.0, 0.5)).norm() < 1e-12);
+    assert!((v0.norm() - 0.2 * std::f64::consts::TAU).abs() < 1e-12);
+    // A full period later, the object is back at the start.
+    assert!((states[100].0 - p0).norm() < 1e-9);
+    assert!((states[25].0 - Vector3::new(0.0, 0.2, 0.5)).norm() < 1e-9);
+}
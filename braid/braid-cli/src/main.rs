@@ -0,0 +1,64 @@
+use clap::{Parser, Subcommand};
+use eyre::Result;
+use std::sync::{Arc, RwLock};
+
+use flydra_types::{BraidHttpApiCallback, BuiServerAddrInfo};
+
+/// Control a running instance of Braid.
+#[derive(Debug, Parser)]
+#[command(author, version)]
+struct Cli {
+    /// Braid HTTP URL address, including the token (e.g.
+    /// 'http://host:port/?token=abc')
+    #[arg(long, env = "BRAID_URL")]
+    braid_url: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Record MP4 files on all cameras
+    Record {
+        #[command(subcommand)]
+        action: RecordAction,
+    },
+    /// Shut down Braid and all cameras after finalizing all recordings
+    Quit,
+}
+
+#[derive(Debug, Subcommand)]
+enum RecordAction {
+    /// Start recording on all cameras with the same synchronized frame
+    Start {
+        /// Prefix of the MP4 filenames
+        #[arg(long)]
+        prefix: Option<String>,
+    },
+    /// Stop recording on all cameras
+    Stop,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let _tracing_guard = env_tracing_logger::init();
+    let cli = Cli::parse();
+
+    let msg = match cli.command {
+        Command::Record { action } => match action {
+            RecordAction::Start { prefix } => {
+                BraidHttpApiCallback::StartSynchronizedMp4Recording(prefix)
+            }
+            RecordAction::Stop => BraidHttpApiCallback::DoRecordMp4Files(false),
+        },
+        Command::Quit => BraidHttpApiCallback::DoQuit,
+    };
+
+    let mainbrain_bui_loc = BuiServerAddrInfo::parse_url_with_token(&cli.braid_url)?;
+    let jar = Arc::new(RwLock::new(cookie_store::CookieStore::new(None)));
+    let mut mainbrain_session =
+        braid_http_session::create_mainbrain_session(mainbrain_bui_loc, jar).await?;
+    mainbrain_session.post_callback_message(msg).await?;
+    Ok(())
+}
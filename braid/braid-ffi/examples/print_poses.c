@@ -0,0 +1,49 @@
+/* Print the poses streamed by Braid and their projections into each camera.
+ *
+ * Build, after `cargo build --release -p braid-ffi`, with:
+ *
+ *   cc -Iinclude examples/print_poses.c -L../../target/release -lbraid_ffi -o print_poses
+ */
+
+#include <stdio.h>
+
+#include "braid_ffi.h"
+
+int main(int argc, char **argv) {
+    const char *url = argc > 1 ? argv[1] : "http://127.0.0.1:8397/";
+    const char *cam_name = argc > 2 ? argv[2] : NULL;
+    braid_pose_stream *stream = NULL;
+    braid_calibration *calibration = NULL;
+    braid_pose_event event;
+
+    if (braid_ffi_abi_version() != BRAID_FFI_ABI_VERSION) {
+        fprintf(stderr, "braid_ffi library ABI version %u, expected %u\n",
+                braid_ffi_abi_version(), BRAID_FFI_ABI_VERSION);
+        return 1;
+    }
+    if (braid_pose_stream_connect(url, &stream) != BRAID_OK) {
+        fprintf(stderr, "error: %s\n", braid_last_error_message());
+        return 1;
+    }
+    while (braid_pose_stream_next(stream, &event) == BRAID_OK) {
+        if (event.kind == BRAID_POSE_CALIBRATION) {
+            braid_calibration_free(calibration);
+            calibration = NULL;
+            braid_pose_stream_calibration(stream, &calibration);
+        } else if (event.kind == BRAID_POSE_UPDATE) {
+            double xyz[3] = {event.x, event.y, event.z};
+            double xy[2];
+            printf("frame %llu obj_id %u: %f %f %f", (unsigned long long)event.frame,
+                   event.obj_id, event.x, event.y, event.z);
+            if (calibration != NULL && cam_name != NULL &&
+                braid_calibration_project(calibration, cam_name, xyz, 1, xy) == BRAID_OK) {
+                printf(", pixel %f %f in %s", xy[0], xy[1], cam_name);
+            }
+            printf("\n");
+        }
+    }
+    fprintf(stderr, "error: %s\n", braid_last_error_message());
+    braid_calibration_free(calibration);
+    braid_pose_stream_free(stream);
+    return 0;
+}
@@ -0,0 +1,115 @@
+/*
+ * C interface of braid-ffi: the live pose stream of Braid and Braid
+ * calibrations.
+ *
+ * BRAID_FFI_ABI_VERSION is increased whenever a declaration in this file
+ * changes. Check it against braid_ffi_abi_version() at startup.
+ *
+ * Functions returning braid_status set a message, available from
+ * braid_last_error_message() in the same thread, on error.
+ */
+
+#ifndef BRAID_FFI_H
+#define BRAID_FFI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define BRAID_FFI_ABI_VERSION 1
+
+typedef enum braid_status {
+    BRAID_OK = 0,
+    BRAID_ERR_NULL_POINTER = 1,
+    BRAID_ERR_INVALID_ARGUMENT = 2,
+    BRAID_ERR_IO = 3,
+    BRAID_ERR_PROTOCOL = 4,
+    BRAID_ERR_UNSUPPORTED_VERSION = 5,
+    BRAID_ERR_END_OF_STREAM = 6,
+    BRAID_ERR_UNKNOWN_CAMERA = 7,
+    BRAID_ERR_CALIBRATION = 8,
+} braid_status;
+
+typedef enum braid_pose_event_kind {
+    /* A new object, with its first estimate. */
+    BRAID_POSE_BIRTH = 0,
+    /* A new estimate of an object. */
+    BRAID_POSE_UPDATE = 1,
+    /* An object is no longer tracked. Only obj_id is set. */
+    BRAID_POSE_DEATH = 2,
+    /* All estimates of the frame have been sent. */
+    BRAID_POSE_END_OF_FRAME = 3,
+    /* A calibration was received, see braid_pose_stream_calibration(). */
+    BRAID_POSE_CALIBRATION = 4,
+} braid_pose_event_kind;
+
+typedef struct braid_pose_event {
+    braid_pose_event_kind kind;
+    uint32_t obj_id;
+    /* The synchronized frame number. */
+    uint64_t frame;
+    /* Time of the trigger pulse of the frame, in seconds since the Unix
+     * epoch, or NaN if unknown. */
+    double trigger_timestamp;
+    /* Time from the trigger pulse until the event was sent, in seconds, or
+     * NaN if unknown. */
+    double latency;
+    /* Position and velocity, NaN unless kind is BIRTH or UPDATE. */
+    double x;
+    double y;
+    double z;
+    double xvel;
+    double yvel;
+    double zvel;
+} braid_pose_event;
+
+typedef struct BraidPoseStream braid_pose_stream;
+typedef struct BraidCalibration braid_calibration;
+
+uint32_t braid_ffi_abi_version(void);
+
+/* The message of the last error in the calling thread, valid until the next
+ * call of a function of this library in the same thread. */
+const char *braid_last_error_message(void);
+
+/* Connect to the pose stream of the Braid model server at url, such as
+ * "http://127.0.0.1:8397/". */
+braid_status braid_pose_stream_connect(const char *url, braid_pose_stream **out);
+
+/* Wait for the next event of the pose stream. */
+braid_status braid_pose_stream_next(braid_pose_stream *stream, braid_pose_event *out);
+
+/* Get the calibration most recently received on the pose stream. It must be
+ * freed with braid_calibration_free(). */
+braid_status braid_pose_stream_calibration(const braid_pose_stream *stream,
+                                           braid_calibration **out);
+
+/* Close the connection and free the stream. */
+void braid_pose_stream_free(braid_pose_stream *stream);
+
+/* Load a calibration from a Braid XML or pymvg JSON file. It must be freed
+ * with braid_calibration_free(). */
+braid_status braid_calibration_load(const char *path, braid_calibration **out);
+
+/* Project n 3D points, x, y, z triplets in xyz, to pixels of camera
+ * cam_name, x, y pairs written to out_xy. */
+braid_status braid_calibration_project(const braid_calibration *calibration,
+                                       const char *cam_name, const double *xyz,
+                                       size_t n, double *out_xy);
+
+/* Undistort n pixels of camera cam_name, x, y pairs in xy, writing the
+ * undistorted x, y pairs to out_xy. */
+braid_status braid_calibration_undistort(const braid_calibration *calibration,
+                                         const char *cam_name, const double *xy,
+                                         size_t n, double *out_xy);
+
+void braid_calibration_free(braid_calibration *calibration);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BRAID_FFI_H */
@@ -0,0 +1,164 @@
+//! Projection and undistortion with a Braid calibration.
+//!
+//! Refraction at a water surface at z=0 is taken into account if the
+//! calibration has one.
+
+use std::ffi::c_char;
+
+use nalgebra::{Point2, Point3};
+
+use flydra_mvg::{FlydraMultiCameraSystem, MultiCamera};
+use mvg::{DistortedPixel, PointWorldFrame};
+
+use crate::{str_arg, to_status, Error, Status};
+
+/// A multi-camera calibration.
+pub struct BraidCalibration {
+    system: FlydraMultiCameraSystem<f64>,
+}
+
+impl BraidCalibration {
+    pub(crate) fn from_flydra_xml(xml: &str) -> Result<Self, Error> {
+        let system = FlydraMultiCameraSystem::from_flydra_xml(xml.as_bytes())?;
+        Ok(Self { system })
+    }
+
+    fn cam(&self, cam_name: &str) -> Result<MultiCamera<f64>, Error> {
+        self.system
+            .cam_by_name(cam_name)
+            .ok_or_else(|| Error::UnknownCamera(cam_name.to_string()))
+    }
+
+    fn project(&self, cam_name: &str, xyz: &[f64], out_xy: &mut [f64]) -> Result<(), Error> {
+        let cam = self.cam(cam_name)?;
+        for (pt, out) in xyz.chunks_exact(3).zip(out_xy.chunks_exact_mut(2)) {
+            let pt3d = PointWorldFrame {
+                coords: Point3::new(pt[0], pt[1], pt[2]),
+            };
+            let px = cam.project_3d_to_distorted_pixel(&pt3d);
+            out.copy_from_slice(&[px.coords.x, px.coords.y]);
+        }
+        Ok(())
+    }
+
+    fn undistort(&self, cam_name: &str, xy: &[f64], out_xy: &mut [f64]) -> Result<(), Error> {
+        let cam = self.cam(cam_name)?;
+        for (px, out) in xy.chunks_exact(2).zip(out_xy.chunks_exact_mut(2)) {
+            let distorted = DistortedPixel {
+                coords: Point2::new(px[0], px[1]),
+            };
+            let undistorted = cam.undistort(&distorted);
+            out.copy_from_slice(&[undistorted.coords.x, undistorted.coords.y]);
+        }
+        Ok(())
+    }
+}
+
+/// Get the input and output slices of `n` points.
+///
+/// # Safety
+///
+/// If `n` is not zero, `input` must be valid for reads of `n * dim` values and
+/// `out` for writes of `n * 2` values.
+unsafe fn point_slices<'a>(
+    input: *const f64,
+    dim: usize,
+    n: usize,
+    out: *mut f64,
+) -> Result<(&'a [f64], &'a mut [f64]), Error> {
+    if n == 0 {
+        return Ok((&[], &mut []));
+    }
+    if input.is_null() || out.is_null() {
+        return Err(Error::NullPointer);
+    }
+    Ok((
+        std::slice::from_raw_parts(input, n * dim),
+        std::slice::from_raw_parts_mut(out, n * 2),
+    ))
+}
+
+/// Load a calibration from a Braid XML or pymvg JSON file.
+///
+/// The calibration must be freed with [braid_calibration_free].
+///
+/// # Safety
+///
+/// `path` must be a null-terminated string and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn braid_calibration_load(
+    path: *const c_char,
+    out: *mut *mut BraidCalibration,
+) -> Status {
+    to_status(|| {
+        if out.is_null() {
+            return Err(Error::NullPointer);
+        }
+        let system = FlydraMultiCameraSystem::from_path(str_arg(path)?)?;
+        *out = Box::into_raw(Box::new(BraidCalibration { system }));
+        Ok(())
+    })
+}
+
+/// Project `n` 3D points, given as x, y, z triplets in `xyz`, to pixels of the
+/// camera `cam_name`, written as x, y pairs to `out_xy`.
+///
+/// # Safety
+///
+/// `calibration` must be valid, `cam_name` must be a null-terminated string,
+/// `xyz` must be valid for reads of `3 * n` values and `out_xy` for writes of
+/// `2 * n` values.
+#[no_mangle]
+pub unsafe extern "C" fn braid_calibration_project(
+    calibration: *const BraidCalibration,
+    cam_name: *const c_char,
+    xyz: *const f64,
+    n: usize,
+    out_xy: *mut f64,
+) -> Status {
+    to_status(|| {
+        if calibration.is_null() {
+            return Err(Error::NullPointer);
+        }
+        let (xyz, out_xy) = point_slices(xyz, 3, n, out_xy)?;
+        (*calibration).project(str_arg(cam_name)?, xyz, out_xy)
+    })
+}
+
+/// Undistort `n` pixels of the camera `cam_name`, given as x, y pairs in
+/// `xy`, writing the undistorted x, y pairs to `out_xy`.
+///
+/// # Safety
+///
+/// `calibration` must be valid, `cam_name` must be a null-terminated string,
+/// `xy` must be valid for reads of `2 * n` values and `out_xy` for writes of
+/// `2 * n` values.
+#[no_mangle]
+pub unsafe extern "C" fn braid_calibration_undistort(
+    calibration: *const BraidCalibration,
+    cam_name: *const c_char,
+    xy: *const f64,
+    n: usize,
+    out_xy: *mut f64,
+) -> Status {
+    to_status(|| {
+        if calibration.is_null() {
+            return Err(Error::NullPointer);
+        }
+        let (xy, out_xy) = point_slices(xy, 2, n, out_xy)?;
+        (*calibration).undistort(str_arg(cam_name)?, xy, out_xy)
+    })
+}
+
+/// Free a calibration.
+///
+/// # Safety
+///
+/// `calibration` must be null or have been returned by this library and not
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn braid_calibration_free(calibration: *mut BraidCalibration) {
+    if !calibration.is_null() {
+        drop(Box::from_raw(calibration));
+    }
+}
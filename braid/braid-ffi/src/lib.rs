@@ -0,0 +1,137 @@
+//! C library to receive the live pose stream of Braid and to use Braid
+//! calibrations.
+//!
+//! The C interface is declared in `include/braid_ffi.h`. Functions return a
+//! [Status]. On error, a description is available from
+//! [braid_last_error_message] in the same thread.
+//!
+//! The ABI is versioned by [ABI_VERSION], which must equal
+//! `BRAID_FFI_ABI_VERSION` in the header. It is increased whenever a function
+//! signature, a struct or an enum of the header changes. Programs should check
+//! it against [braid_ffi_abi_version] at startup.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+};
+
+mod calibration;
+mod pose_stream;
+
+pub use calibration::{
+    braid_calibration_free, braid_calibration_load, braid_calibration_project,
+    braid_calibration_undistort, BraidCalibration,
+};
+pub use pose_stream::{
+    braid_pose_stream_calibration, braid_pose_stream_connect, braid_pose_stream_free,
+    braid_pose_stream_next, BraidPoseStream, PoseEvent, PoseEventKind,
+};
+
+/// The version of the ABI of this library.
+pub const ABI_VERSION: u32 = 1;
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum Error {
+    #[error("null pointer argument")]
+    NullPointer,
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Protocol(String),
+    #[error("unsupported Braid pose API version {0}")]
+    UnsupportedVersion(u16),
+    #[error("end of stream")]
+    EndOfStream,
+    #[error("unknown camera \"{0}\"")]
+    UnknownCamera(String),
+    #[error("{0}")]
+    Calibration(#[from] flydra_mvg::FlydraMvgError),
+    #[error("no calibration has been received")]
+    NoCalibration,
+}
+
+/// The result of a function of the library.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidArgument = 2,
+    Io = 3,
+    Protocol = 4,
+    UnsupportedVersion = 5,
+    EndOfStream = 6,
+    UnknownCamera = 7,
+    Calibration = 8,
+}
+
+impl From<&Error> for Status {
+    fn from(e: &Error) -> Self {
+        match e {
+            Error::NullPointer => Status::NullPointer,
+            Error::InvalidArgument(_) => Status::InvalidArgument,
+            Error::Io(_) => Status::Io,
+            Error::Protocol(_) => Status::Protocol,
+            Error::UnsupportedVersion(_) => Status::UnsupportedVersion,
+            Error::EndOfStream => Status::EndOfStream,
+            Error::UnknownCamera(_) => Status::UnknownCamera,
+            Error::Calibration(_) | Error::NoCalibration => Status::Calibration,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// Run `f`, converting its result to a [Status] and saving the error message
+/// of this thread.
+pub(crate) fn to_status<F: FnOnce() -> Result<(), Error>>(f: F) -> Status {
+    match f() {
+        Ok(()) => Status::Ok,
+        Err(e) => {
+            // Error messages do not contain null bytes, except from invalid
+            // input, which is replaced.
+            let msg = CString::new(e.to_string().replace('\0', "?")).unwrap();
+            LAST_ERROR.with(|last| *last.borrow_mut() = msg);
+            Status::from(&e)
+        }
+    }
+}
+
+/// Convert a C string argument.
+///
+/// # Safety
+///
+/// `s` must be null or point to a null-terminated string.
+pub(crate) unsafe fn str_arg<'a>(s: *const c_char) -> Result<&'a str, Error> {
+    if s.is_null() {
+        return Err(Error::NullPointer);
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|e| Error::InvalidArgument(format!("string is not UTF-8: {e}")))
+}
+
+/// The version of the ABI of the library.
+#[no_mangle]
+pub extern "C" fn braid_ffi_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+/// The message of the last error in the calling thread.
+///
+/// The string is valid until the next call of a function of the library in
+/// the same thread.
+#[no_mangle]
+pub extern "C" fn braid_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+#[test]
+fn test_header_abi_version() {
+    let header = include_str!("../include/braid_ffi.h");
+    assert!(header.contains(&format!("#define BRAID_FFI_ABI_VERSION {ABI_VERSION}\n")));
+}
@@ -0,0 +1,316 @@
+//! Reception of the live pose stream of Braid.
+//!
+//! Braid sends the stream as HTTP server-sent events at the `events` path of
+//! its model server. Each event is a JSON message of the Braid pose API.
+
+use std::{
+    ffi::c_char,
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+};
+
+use flydra2::{SendType, ToListener, BRAID_POSE_API_VERSION};
+
+use crate::{calibration::BraidCalibration, str_arg, to_status, Error, Status};
+
+const DATA_PREFIX: &str = "data:";
+
+/// The kind of a [PoseEvent].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoseEventKind {
+    /// A new object, with its first estimate.
+    Birth = 0,
+    /// A new estimate of an object.
+    Update = 1,
+    /// An object is no longer tracked. Only `obj_id` is set.
+    Death = 2,
+    /// All estimates of the frame have been sent.
+    EndOfFrame = 3,
+    /// A calibration was received, which is available from
+    /// [braid_pose_stream_calibration].
+    Calibration = 4,
+}
+
+/// An event of the pose stream.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoseEvent {
+    pub kind: PoseEventKind,
+    pub obj_id: u32,
+    /// The synchronized frame number.
+    pub frame: u64,
+    /// The time of the trigger pulse of the frame, in seconds since the Unix
+    /// epoch, or NaN if unknown.
+    pub trigger_timestamp: f64,
+    /// The time from the trigger pulse until the event was sent, in seconds,
+    /// or NaN if unknown.
+    pub latency: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub xvel: f64,
+    pub yvel: f64,
+    pub zvel: f64,
+}
+
+/// Parse the data of an event, returning the event and any calibration.
+fn parse_event(data: &str) -> Result<(PoseEvent, Option<String>), Error> {
+    let msg: ToListener =
+        serde_json::from_str(data).map_err(|e| Error::Protocol(format!("invalid message: {e}")))?;
+    if msg.v != BRAID_POSE_API_VERSION {
+        return Err(Error::UnsupportedVersion(msg.v));
+    }
+    let mut event = PoseEvent {
+        kind: PoseEventKind::EndOfFrame,
+        obj_id: 0,
+        frame: msg.synced_frame.0,
+        trigger_timestamp: msg
+            .trigger_timestamp
+            .map(|ts| ts.as_f64())
+            .unwrap_or(f64::NAN),
+        latency: msg.latency,
+        x: f64::NAN,
+        y: f64::NAN,
+        z: f64::NAN,
+        xvel: f64::NAN,
+        yvel: f64::NAN,
+        zvel: f64::NAN,
+    };
+    let mut calibration_xml = None;
+    let estimate = match msg.msg {
+        SendType::Birth(estimate) => {
+            event.kind = PoseEventKind::Birth;
+            Some(estimate)
+        }
+        SendType::Update(estimate) => {
+            event.kind = PoseEventKind::Update;
+            Some(estimate)
+        }
+        SendType::Death(obj_id) => {
+            event.kind = PoseEventKind::Death;
+            event.obj_id = obj_id;
+            None
+        }
+        SendType::EndOfFrame(frame) => {
+            event.frame = frame.0;
+            None
+        }
+        SendType::CalibrationFlydraXml(xml) => {
+            event.kind = PoseEventKind::Calibration;
+            calibration_xml = Some(xml);
+            None
+        }
+    };
+    if let Some(e) = estimate {
+        event.obj_id = e.obj_id;
+        (event.x, event.y, event.z) = (e.x, e.y, e.z);
+        (event.xvel, event.yvel, event.zvel) = (e.xvel, e.yvel, e.zvel);
+    }
+    Ok((event, calibration_xml))
+}
+
+/// A connection to the pose stream of Braid.
+pub struct BraidPoseStream {
+    reader: BufReader<TcpStream>,
+    calibration_xml: Option<String>,
+}
+
+impl BraidPoseStream {
+    fn connect(url: &str) -> Result<Self, Error> {
+        let url = url::Url::parse(url)
+            .map_err(|e| Error::InvalidArgument(format!("invalid URL \"{url}\": {e}")))?;
+        if url.scheme() != "http" {
+            return Err(Error::InvalidArgument(format!(
+                "URL scheme \"{}\" is not http",
+                url.scheme()
+            )));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| Error::InvalidArgument(format!("URL \"{url}\" has no host")))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        let events_path = format!("{}/events", url.path().trim_end_matches('/'));
+
+        let mut stream = TcpStream::connect((host, port))?;
+        stream.set_nodelay(true)?;
+        // With HTTP/1.0, the body is not chunked and ends when the connection
+        // is closed.
+        write!(
+            stream,
+            "GET {events_path} HTTP/1.0\r\nHost: {host}:{port}\r\nAccept: text/event-stream\r\n\r\n"
+        )?;
+        let mut reader = BufReader::new(stream);
+        let status_line = read_line(&mut reader)?;
+        if status_line.split(' ').nth(1) != Some("200") {
+            return Err(Error::Protocol(format!(
+                "unexpected response \"{status_line}\""
+            )));
+        }
+        // Skip the headers.
+        while !read_line(&mut reader)?.is_empty() {}
+        Ok(Self {
+            reader,
+            calibration_xml: None,
+        })
+    }
+
+    /// Read the data of the next event.
+    fn next_data(&mut self) -> Result<String, Error> {
+        let mut data: Option<String> = None;
+        loop {
+            let line = read_line(&mut self.reader)?;
+            if line.is_empty() {
+                if let Some(data) = data.take() {
+                    return Ok(data);
+                }
+            } else if let Some(value) = line.strip_prefix(DATA_PREFIX) {
+                let value = value.strip_prefix(' ').unwrap_or(value);
+                match data.as_mut() {
+                    Some(data) => {
+                        data.push('\n');
+                        data.push_str(value);
+                    }
+                    None => data = Some(value.to_string()),
+                }
+            }
+            // Other fields, such as the event name, and comments are ignored.
+        }
+    }
+
+    fn next_event(&mut self) -> Result<PoseEvent, Error> {
+        let data = self.next_data()?;
+        let (event, calibration_xml) = parse_event(&data)?;
+        if calibration_xml.is_some() {
+            self.calibration_xml = calibration_xml;
+        }
+        Ok(event)
+    }
+}
+
+/// Read a line, without the line ending.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, Error> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(Error::EndOfStream);
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Connect to the pose stream of the Braid model server at `url`, such as
+/// `http://127.0.0.1:8397/`.
+///
+/// # Safety
+///
+/// `url` must be a null-terminated string and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn braid_pose_stream_connect(
+    url: *const c_char,
+    out: *mut *mut BraidPoseStream,
+) -> Status {
+    to_status(|| {
+        if out.is_null() {
+            return Err(Error::NullPointer);
+        }
+        let stream = BraidPoseStream::connect(str_arg(url)?)?;
+        *out = Box::into_raw(Box::new(stream));
+        Ok(())
+    })
+}
+
+/// Wait for the next event of the pose stream.
+///
+/// # Safety
+///
+/// `stream` must have been returned by [braid_pose_stream_connect] and not
+/// freed, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn braid_pose_stream_next(
+    stream: *mut BraidPoseStream,
+    out: *mut PoseEvent,
+) -> Status {
+    to_status(|| {
+        if stream.is_null() || out.is_null() {
+            return Err(Error::NullPointer);
+        }
+        *out = (*stream).next_event()?;
+        Ok(())
+    })
+}
+
+/// Get the calibration most recently received on the pose stream.
+///
+/// The calibration must be freed with `braid_calibration_free`.
+///
+/// # Safety
+///
+/// `stream` must have been returned by [braid_pose_stream_connect] and not
+/// freed, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn braid_pose_stream_calibration(
+    stream: *const BraidPoseStream,
+    out: *mut *mut BraidCalibration,
+) -> Status {
+    to_status(|| {
+        if stream.is_null() || out.is_null() {
+            return Err(Error::NullPointer);
+        }
+        let xml = (*stream)
+            .calibration_xml
+            .as_ref()
+            .ok_or(Error::NoCalibration)?;
+        let calibration = BraidCalibration::from_flydra_xml(xml)?;
+        *out = Box::into_raw(Box::new(calibration));
+        Ok(())
+    })
+}
+
+/// Close the connection and free the stream.
+///
+/// # Safety
+///
+/// `stream` must be null or have been returned by [braid_pose_stream_connect]
+/// and not freed.
+#[no_mangle]
+pub unsafe extern "C" fn braid_pose_stream_free(stream: *mut BraidPoseStream) {
+    if !stream.is_null() {
+        drop(Box::from_raw(stream));
+    }
+}
+
+#[test]
+fn test_parse_event() {
+    let (event, xml) = parse_event(
+        r#"{"v":3,"msg":{"Update":{"obj_id":7,"frame":100,"x":1.0,"y":2.0,"z":3.0,
+        "xvel":0.5,"yvel":0.0,"zvel":0.0,"P00":1.0,"P01":0.0,"P02":0.0,"P11":1.0,
+        "P12":0.0,"P22":1.0,"P33":1.0,"P44":1.0,"P55":1.0}},"latency":0.01,
+        "synced_frame":100,"trigger_timestamp":1700000000.5}"#,
+    )
+    .unwrap();
+    assert_eq!(xml, None);
+    assert_eq!(event.kind, PoseEventKind::Update);
+    assert_eq!((event.obj_id, event.frame), (7, 100));
+    assert_eq!((event.x, event.xvel), (1.0, 0.5));
+    assert_eq!(event.trigger_timestamp, 1700000000.5);
+
+    let (event, _) = parse_event(
+        r#"{"v":3,"msg":{"Death":7},"latency":null,"synced_frame":101,"trigger_timestamp":null}"#,
+    )
+    .unwrap();
+    assert_eq!((event.kind, event.obj_id), (PoseEventKind::Death, 7));
+    assert!(event.latency.is_nan() && event.x.is_nan());
+
+    let (event, xml) = parse_event(
+        r#"{"v":3,"msg":{"CalibrationFlydraXml":"<xml/>"},"latency":null,"synced_frame":0,"trigger_timestamp":null}"#,
+    )
+    .unwrap();
+    assert_eq!(event.kind, PoseEventKind::Calibration);
+    assert_eq!(xml.as_deref(), Some("<xml/>"));
+
+    assert!(matches!(
+        parse_event(
+            r#"{"v":2,"msg":{"Death":7},"latency":null,"synced_frame":0,"trigger_timestamp":null}"#
+        ),
+        Err(Error::UnsupportedVersion(2))
+    ));
+}
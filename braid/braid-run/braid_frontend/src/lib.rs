@@ -6,19 +6,21 @@ use std::{
 use serde::{Deserialize, Serialize};
 
 use gloo_events::EventListener;
+use gloo_timers::callback::Interval;
 use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue, UnwrapThrowExt};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{EventSource, MessageEvent};
 
 use flydra_types::{
-    BraidHttpApiCallback, BraidHttpApiSharedState, BuiServerInfo, CamInfo, TriggerType,
+    Alert, BraidHttpApiCallback, BraidHttpApiSharedState, BuiServerInfo, CamInfo, CameraGroup,
+    CameraParams, DiscoveredCamera, PerCam, RawCamName, SetGroupCameraParams, TriggerType,
 };
-use rust_cam_bui_types::RecordingPath;
+use rust_cam_bui_types::{DiskSpace, ExperimentMetadata, RecordingPath};
 
-use yew::{html, Component, Context, Event, Html};
+use yew::{html, Component, Context, Event, Html, InputEvent, TargetCast};
 use yew_tincture::components::{Button, CheckboxLabel, TypedInput, TypedInputStorage};
 
-use ads_webasm::components::{RecordingPathWidget, ReloadButton};
+use ads_webasm::components::{RecordingPathWidget, ReloadButton, Toggle};
 
 // -----------------------------------------------------------------------------
 
@@ -41,6 +43,13 @@ impl std::fmt::Display for MyError {
 
 // Model
 
+/// The URL fragment of the page showing all cameras.
+const MOSAIC_HASH: &str = "#mosaic";
+/// Width of the camera images on the mosaic page.
+const MOSAIC_IMAGE_WIDTH: u32 = 320;
+/// Interval at which the camera images on the mosaic page are reloaded.
+const MOSAIC_REFRESH_MSEC: u32 = 2000;
+
 struct Model {
     shared: Option<Box<BraidHttpApiSharedState>>,
     es: EventSource,
@@ -49,9 +58,56 @@ struct Model {
     recording_path: Option<RecordingPath>,
     fake_mp4_recording_path: Option<RecordingPath>,
     post_trigger_buffer_size_local: TypedInputStorage<usize>,
+    experimenter_local: TypedInputStorage<String>,
+    animal_id_local: TypedInputStorage<String>,
+    condition_local: TypedInputStorage<String>,
+    /// Experiment metadata being edited, not yet sent to Braid.
+    experiment_metadata: ExperimentMetadata,
+    /// Do not play a sound for new alerts.
+    alerts_muted: bool,
+    log_filter_local: TypedInputStorage<String>,
+    /// The cameras for which camera parameters are set.
+    camera_params_target: CameraParamsTarget,
+    /// Camera parameters being edited, not yet sent to Braid.
+    camera_params: CameraParams,
+    exposure_time_local: TypedInputStorage<f64>,
+    gain_local: TypedInputStorage<f64>,
+    /// Incremented to reload the camera images on the mosaic page.
+    mosaic_generation: u32,
+    _mosaic_interval: Interval,
     _listeners: Vec<EventListener>,
 }
 
+/// The cameras for which camera parameters are set in the UI.
+#[derive(Debug, Clone, PartialEq)]
+enum CameraParamsTarget {
+    All,
+    Group(String),
+    /// Override the parameters of a single camera.
+    Camera(String),
+}
+
+impl CameraParamsTarget {
+    /// Encode as value of an `<option>` element.
+    fn to_value(&self) -> String {
+        match self {
+            Self::All => "all".to_string(),
+            Self::Group(name) => format!("group:{name}"),
+            Self::Camera(name) => format!("cam:{name}"),
+        }
+    }
+
+    fn from_value(value: &str) -> Self {
+        if let Some(name) = value.strip_prefix("group:") {
+            Self::Group(name.to_string())
+        } else if let Some(name) = value.strip_prefix("cam:") {
+            Self::Camera(name.to_string())
+        } else {
+            Self::All
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------
 
 enum Msg {
@@ -62,6 +118,21 @@ enum Msg {
     SendMessageFetchState(FetchState),
     SetPostTriggerBufferSize(usize),
     PostTriggerMp4Recording,
+    SetExperimenter(String),
+    SetAnimalId(String),
+    SetCondition(String),
+    SetNotes(String),
+    SendExperimentMetadata,
+    SetAlertsMuted(bool),
+    ClearAlerts,
+    SetLogFilter(String),
+    SetCameraParamsTarget(String),
+    SetExposureTime(f64),
+    SetGain(f64),
+    SendCameraParams,
+    RemoveCameraParamOverride(String),
+    RefreshMosaic,
+    DoQuit,
     RenderView,
 }
 
@@ -129,6 +200,23 @@ impl Component for Model {
             link.send_message(Msg::RenderView);
         }));
 
+        // Switch between the main page and the mosaic page.
+        let link = ctx.link().clone();
+        _listeners.push(EventListener::new(
+            &gloo_utils::window(),
+            "hashchange",
+            move |_event: &Event| {
+                link.send_message(Msg::RenderView);
+            },
+        ));
+
+        let _mosaic_interval = {
+            let link = ctx.link().clone();
+            Interval::new(MOSAIC_REFRESH_MSEC, move || {
+                link.send_message(Msg::RefreshMosaic)
+            })
+        };
+
         Self {
             shared: None,
             es,
@@ -137,6 +225,18 @@ impl Component for Model {
             recording_path: None,
             fake_mp4_recording_path: None,
             post_trigger_buffer_size_local: TypedInputStorage::empty(),
+            experimenter_local: TypedInputStorage::empty(),
+            animal_id_local: TypedInputStorage::empty(),
+            condition_local: TypedInputStorage::empty(),
+            experiment_metadata: ExperimentMetadata::default(),
+            alerts_muted: false,
+            log_filter_local: TypedInputStorage::empty(),
+            camera_params_target: CameraParamsTarget::All,
+            camera_params: CameraParams::default(),
+            exposure_time_local: TypedInputStorage::empty(),
+            gain_local: TypedInputStorage::empty(),
+            mosaic_generation: 0,
+            _mosaic_interval,
             _listeners,
         }
     }
@@ -144,6 +244,12 @@ impl Component for Model {
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
             Msg::RenderView => {}
+            Msg::RefreshMosaic => {
+                if !is_mosaic_page() {
+                    return false;
+                }
+                self.mosaic_generation = self.mosaic_generation.wrapping_add(1);
+            }
             Msg::SendMessageFetchState(_fetch_state) => {
                 return false;
             }
@@ -158,6 +264,30 @@ impl Component for Model {
 
                 self.post_trigger_buffer_size_local
                     .set_if_not_focused(data_result.post_trigger_buffer_size);
+                self.log_filter_local
+                    .set_if_not_focused(data_result.log_filter.clone());
+
+                let metadata = &data_result.experiment_metadata;
+                let previous = self.shared.as_ref().map(|s| &s.experiment_metadata);
+                if previous != Some(metadata) {
+                    // Show the values saved by Braid.
+                    self.experimenter_local
+                        .set_if_not_focused(metadata.experimenter.clone());
+                    self.animal_id_local
+                        .set_if_not_focused(metadata.animal_id.clone());
+                    self.condition_local
+                        .set_if_not_focused(metadata.condition.clone());
+                    self.experiment_metadata = metadata.clone();
+                }
+
+                if let Some(previous) = self.shared.as_ref() {
+                    let newest = |alerts: &[Alert]| alerts.last().map(|a| a.time);
+                    if newest(&data_result.recent_alerts) > newest(&previous.recent_alerts)
+                        && !self.alerts_muted
+                    {
+                        ads_webasm::audio_alert::beep();
+                    }
+                }
 
                 self.shared = Some(data_result);
 
@@ -197,11 +327,102 @@ impl Component for Model {
             Msg::PostTriggerMp4Recording => {
                 return self.send_to_all_cams(ctx, BraidHttpApiCallback::PostTriggerMp4Recording);
             }
+            Msg::SetExperimenter(val) => {
+                self.experiment_metadata.experimenter = val;
+                return false;
+            }
+            Msg::SetAnimalId(val) => {
+                self.experiment_metadata.animal_id = val;
+                return false;
+            }
+            Msg::SetCondition(val) => {
+                self.experiment_metadata.condition = val;
+                return false;
+            }
+            Msg::SetNotes(val) => {
+                self.experiment_metadata.notes = val;
+                return false;
+            }
+            Msg::SendExperimentMetadata => {
+                let metadata = self.experiment_metadata.clone();
+                return self
+                    .send_to_all_cams(ctx, BraidHttpApiCallback::SetExperimentMetadata(metadata));
+            }
+            Msg::SetAlertsMuted(val) => {
+                self.alerts_muted = val;
+            }
+            Msg::ClearAlerts => {
+                return self.send_to_all_cams(ctx, BraidHttpApiCallback::ClearAlerts);
+            }
+            Msg::SetLogFilter(val) => {
+                return self.send_to_all_cams(ctx, BraidHttpApiCallback::SetLogFilter(val));
+            }
+            Msg::SetCameraParamsTarget(value) => {
+                self.camera_params_target = CameraParamsTarget::from_value(&value);
+            }
+            Msg::SetExposureTime(val) => {
+                self.camera_params.exposure_time_usec = Some(val);
+                return false;
+            }
+            Msg::SetGain(val) => {
+                self.camera_params.gain = Some(val);
+                return false;
+            }
+            Msg::SendCameraParams => {
+                let params = self.camera_params.clone();
+                let msg = match &self.camera_params_target {
+                    CameraParamsTarget::All => {
+                        BraidHttpApiCallback::SetGroupCameraParams(SetGroupCameraParams {
+                            group: None,
+                            params,
+                        })
+                    }
+                    CameraParamsTarget::Group(name) => {
+                        BraidHttpApiCallback::SetGroupCameraParams(SetGroupCameraParams {
+                            group: Some(name.clone()),
+                            params,
+                        })
+                    }
+                    CameraParamsTarget::Camera(name) => {
+                        BraidHttpApiCallback::SetCameraParamOverride(PerCam {
+                            raw_cam_name: RawCamName::new(name.clone()),
+                            inner: params,
+                        })
+                    }
+                };
+                return self.send_to_all_cams(ctx, msg);
+            }
+            Msg::RemoveCameraParamOverride(name) => {
+                let msg = BraidHttpApiCallback::SetCameraParamOverride(PerCam {
+                    raw_cam_name: RawCamName::new(name),
+                    inner: CameraParams::default(),
+                });
+                return self.send_to_all_cams(ctx, msg);
+            }
+            Msg::DoQuit => {
+                let confirmed = gloo_utils::window()
+                    .confirm_with_message("Quit Braid and all cameras?")
+                    .unwrap_or(false);
+                if confirmed {
+                    return self.send_to_all_cams(ctx, BraidHttpApiCallback::DoQuit);
+                }
+                return false;
+            }
         }
         true
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
+        if is_mosaic_page() {
+            return html! {
+                <div id="page-container">
+                    <div id="content-wrap">
+                        {self.disconnected_dialog()}
+                        {self.view_mosaic()}
+                    </div>
+                </div>
+            };
+        }
         html! {
             <div id="page-container">
                 <div id="content-wrap">
@@ -264,6 +485,180 @@ impl Model {
         }
     }
 
+    fn view_experiment_metadata(&self, ctx: &Context<Self>) -> Html {
+        let on_notes = ctx.link().callback(|e: InputEvent| {
+            let textarea: web_sys::HtmlTextAreaElement = e.target_unchecked_into();
+            Msg::SetNotes(textarea.value())
+        });
+        html! {
+            <div class="wrap-collapsible">
+                <CheckboxLabel label="Experiment Metadata" initially_checked=true />
+                <div>
+                    <p>{"Describe the experiment. This is saved in the .braidz file and in the
+                    .mp4 files recorded by all cameras."}</p>
+                </div>
+                <div class="experiment-metadata">
+                    <label>{"experimenter "}
+                        <TypedInput<String>
+                            storage={self.experimenter_local.clone()}
+                            on_send_valid={ctx.link().callback(Msg::SetExperimenter)}
+                            />
+                    </label>
+                    <label>{"animal ID "}
+                        <TypedInput<String>
+                            storage={self.animal_id_local.clone()}
+                            on_send_valid={ctx.link().callback(Msg::SetAnimalId)}
+                            />
+                    </label>
+                    <label>{"condition "}
+                        <TypedInput<String>
+                            storage={self.condition_local.clone()}
+                            on_send_valid={ctx.link().callback(Msg::SetCondition)}
+                            />
+                    </label>
+                    <label>{"notes "}
+                        <textarea rows="4" value={self.experiment_metadata.notes.clone()} oninput={on_notes} />
+                    </label>
+                    <Button title={"Set Experiment Metadata"} onsignal={ctx.link().callback(|_| Msg::SendExperimentMetadata)}/>
+                </div>
+            </div>
+        }
+    }
+
+    fn view_log_filter(&self, ctx: &Context<Self>) -> Html {
+        html! {
+            <div class="wrap-collapsible">
+                <CheckboxLabel label="Logging" initially_checked=false />
+                <div>
+                    <p>{"Which messages are logged by Braid and all cameras, e.g. "}
+                    <code>{"info,flydra2=debug"}</code>{". The syntax is that of the RUST_LOG
+                    environment variable."}</p>
+                </div>
+                <div>
+                    <label>{"log filter "}
+                        <TypedInput<String>
+                            storage={self.log_filter_local.clone()}
+                            on_send_valid={ctx.link().callback(Msg::SetLogFilter)}
+                            />
+                    </label>
+                </div>
+            </div>
+        }
+    }
+
+    fn view_camera_params(&self, ctx: &Context<Self>, shared: &BraidHttpApiSharedState) -> Html {
+        let onchange = ctx.link().callback(|e: Event| {
+            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+            Msg::SetCameraParamsTarget(select.value())
+        });
+        let option = |target: CameraParamsTarget, label: String| {
+            let selected = target == self.camera_params_target;
+            html! {
+                <option value={target.to_value()} {selected}>{label}</option>
+            }
+        };
+        // Cameras which are expected but not connected yet are in groups.
+        let mut cam_names: Vec<&str> = shared
+            .connected_cameras
+            .iter()
+            .map(|cam| cam.name.as_str())
+            .chain(
+                shared
+                    .camera_groups
+                    .iter()
+                    .flat_map(|g| g.cam_names.iter().map(|n| n.as_str())),
+            )
+            .collect();
+        cam_names.sort();
+        cam_names.dedup();
+        let button_title = match self.camera_params_target {
+            CameraParamsTarget::Camera(_) => "Override Camera Parameters",
+            _ => "Set Camera Parameters",
+        };
+        html! {
+            <div class="wrap-collapsible">
+                <CheckboxLabel label="Camera Parameters" initially_checked=false />
+                <div>
+                    <p>{"Set the exposure time and gain of all cameras or of a group of
+                    cameras at once. Values set for a single camera override those set for
+                    its groups. Only the values entered are set."}</p>
+                </div>
+                <div>
+                    <label>{"cameras "}
+                        <select {onchange}>
+                            {option(CameraParamsTarget::All, "all cameras".to_string())}
+                            { for shared.camera_groups.iter().map(|g| {
+                                option(CameraParamsTarget::Group(g.name.clone()), format!("group \"{}\"", g.name))
+                            })}
+                            { for cam_names.iter().map(|name| {
+                                option(CameraParamsTarget::Camera(name.to_string()), format!("camera \"{name}\" only"))
+                            })}
+                        </select>
+                    </label>
+                    <label>{"exposure time (µsec) "}
+                        <TypedInput<f64>
+                            storage={self.exposure_time_local.clone()}
+                            on_send_valid={ctx.link().callback(Msg::SetExposureTime)}
+                            />
+                    </label>
+                    <label>{"gain "}
+                        <TypedInput<f64>
+                            storage={self.gain_local.clone()}
+                            on_send_valid={ctx.link().callback(Msg::SetGain)}
+                            />
+                    </label>
+                    <Button title={button_title} onsignal={ctx.link().callback(|_| Msg::SendCameraParams)}/>
+                </div>
+                {view_camera_groups(&shared.camera_groups)}
+                {self.view_camera_param_overrides(ctx, &shared.camera_param_overrides)}
+            </div>
+        }
+    }
+
+    fn view_camera_param_overrides(
+        &self,
+        ctx: &Context<Self>,
+        overrides: &[PerCam<CameraParams>],
+    ) -> Html {
+        if overrides.is_empty() {
+            return html! {};
+        }
+        let all_rendered: Vec<Html> = overrides
+            .iter()
+            .map(|o| {
+                let name = o.raw_cam_name.as_str().to_string();
+                html! {
+                    <li>
+                        {format!("\"{name}\": {}", format_camera_params(&o.inner))}
+                        {" "}
+                        <Button title={"Remove Override"} onsignal={ctx.link().callback(move |_| Msg::RemoveCameraParamOverride(name.clone()))}/>
+                    </li>
+                }
+            })
+            .collect();
+        html! {
+            <div>
+                {"Overridden cameras:"}
+                <ul>
+                    {all_rendered}
+                </ul>
+            </div>
+        }
+    }
+
+    fn view_quit(&self, ctx: &Context<Self>, is_shutting_down: bool) -> Html {
+        if is_shutting_down {
+            return html! {
+                <div>{"Shutting down. Finalizing recordings..."}</div>
+            };
+        }
+        html! {
+            <div>
+                <Button title={"Quit Braid"} onsignal={ctx.link().callback(|_| Msg::DoQuit)}/>
+            </div>
+        }
+    }
+
     fn view_shared(&self, ctx: &Context<Self>) -> Html {
         if let Some(ref value) = self.shared {
             let clock_model_ready = if value.needs_clock_model {
@@ -298,26 +693,37 @@ impl Model {
                     <div>{"Recording disabled until cameras are synchronized and clock model is established."}</div>
                 }
             };
-            let fake_sync_warning = if let TriggerType::FakeSync(_) = value.trigger_type {
-                html! {
+            let fake_sync_warning = match value.trigger_type {
+                TriggerType::FakeSync(_) => html! {
                     <div>
                         {"⚠ Emulating synchronization because no trigger box in use. Data will not be perfectly synchronized. ⚠"}
                     </div>
-                }
-            } else {
-                html! {
+                },
+                TriggerType::SoftwareSync(_) => html! {
+                    <div>
+                        {"⚠ Frames are grouped by arrival time because no trigger box in use. Data will only be approximately synchronized. ⚠"}
+                    </div>
+                },
+                _ => html! {
                     <></>
-                }
+                },
             };
             html! {
                 <div>
                     {fake_sync_warning}
+                    {self.view_alerts(ctx, &value.recent_alerts)}
                     <div>
                         {record_widget}
+                        {self.view_experiment_metadata(ctx)}
+                        {view_disk_space(&value.disk_space)}
                         {view_clock_model(&value)}
                         {view_calibration(&value.calibration_filename)}
                         {view_cam_list(&value.connected_cameras)}
+                        {view_discovered_cameras(&value.discovered_cameras)}
                         {view_model_server_link(&value.model_server_addr)}
+                        {self.view_camera_params(ctx, value)}
+                        {self.view_log_filter(ctx)}
+                        {self.view_quit(ctx, value.is_shutting_down)}
                     </div>
                 </div>
             }
@@ -330,6 +736,73 @@ impl Model {
         }
     }
 
+    fn view_mosaic(&self) -> Html {
+        let Some(shared) = self.shared.as_ref() else {
+            return html! {};
+        };
+        let tiles = shared.connected_cameras.iter().map(|cci| {
+            let src = format!(
+                "{}/{}?max_width={MOSAIC_IMAGE_WIDTH}&generation={}",
+                flydra_types::braid_http::CAMERA_THUMBNAIL_PATH,
+                flydra_types::braid_http::encode_cam_name(&cci.name),
+                self.mosaic_generation,
+            );
+            let recording = if shared.mp4_recording_cameras.contains(&cci.name) {
+                html! { <span class="mosaic-recording">{" ● REC"}</span> }
+            } else {
+                html! {}
+            };
+            let unsynchronized = if cci.state.is_synchronized() {
+                ""
+            } else {
+                " (unsynchronized)"
+            };
+            html! {
+                <div class="mosaic-tile">
+                    <a href={cam_url(cci)}>
+                        <img src={src} alt={cci.name.as_str().to_string()} width={MOSAIC_IMAGE_WIDTH.to_string()}/>
+                    </a>
+                    <div class="mosaic-label">
+                        {cci.name.as_str()}{unsynchronized}{recording}
+                    </div>
+                </div>
+            }
+        });
+        html! {
+            <div>
+                <div><a href="#">{"Back to Braid"}</a></div>
+                <div class="mosaic">
+                    {for tiles}
+                </div>
+            </div>
+        }
+    }
+
+    fn view_alerts(&self, ctx: &Context<Self>, alerts: &[Alert]) -> Html {
+        if alerts.is_empty() {
+            return html! {};
+        }
+        html! {
+            <div class="alerts">
+                <h2>{"⚠ Alerts"}</h2>
+                <ul>
+                    {for alerts.iter().rev().map(|alert| {
+                        let time = alert.time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S");
+                        html! {
+                            <li>{format!("{time} {}: {}", alert.source, alert.message)}</li>
+                        }
+                    })}
+                </ul>
+                <Button title={"Dismiss Alerts"} onsignal={ctx.link().callback(|_| Msg::ClearAlerts)}/>
+                <Toggle
+                    label="Mute alert sound"
+                    value={self.alerts_muted}
+                    ontoggle={ctx.link().callback(Msg::SetAlertsMuted)}
+                />
+            </div>
+        }
+    }
+
     fn disconnected_dialog(&self) -> Html {
         // 0: connecting, 1: open, 2: closed
         if self.es.ready_state() == 1 {
@@ -374,6 +847,36 @@ fn view_clock_model(shared: &BraidHttpApiSharedState) -> Html {
     }
 }
 
+fn view_disk_space(disk_space: &Option<DiskSpace>) -> Html {
+    if let Some(ref space) = disk_space {
+        let msg = format!(
+            "Free disk space: {:.1} of {:.1} GB at \"{}\".",
+            space.available_bytes as f64 / 1e9,
+            space.total_bytes as f64 / 1e9,
+            space.path
+        );
+        if space.is_low {
+            html! {
+                <div>
+                    <p>
+                        {"⚠ "}{msg}{" Recordings will be stopped before the disk is full. ⚠"}
+                    </p>
+                </div>
+            }
+        } else {
+            html! {
+                <div>
+                    <p>
+                        {msg}
+                    </p>
+                </div>
+            }
+        }
+    } else {
+        html! {}
+    }
+}
+
 fn view_calibration(calibration_filename: &Option<String>) -> Html {
     if let Some(ref fname) = calibration_filename {
         html! {
@@ -394,6 +897,25 @@ fn view_calibration(calibration_filename: &Option<String>) -> Html {
     }
 }
 
+/// Whether the page showing all cameras is shown rather than the main page.
+fn is_mosaic_page() -> bool {
+    gloo_utils::window().location().hash().as_deref() == Ok(MOSAIC_HASH)
+}
+
+/// The URL of the Strand Camera page of a camera.
+fn cam_url(cci: &CamInfo) -> String {
+    match cci.strand_cam_http_server_info {
+        BuiServerInfo::NoServer => "/does-not-exist".to_string(),
+        BuiServerInfo::Server(_) => {
+            format!(
+                "/{}/{}/",
+                flydra_types::braid_http::CAM_PROXY_PATH,
+                flydra_types::braid_http::encode_cam_name(&cci.name)
+            )
+        }
+    }
+}
+
 fn view_cam_list(cams: &[CamInfo]) -> Html {
     let n_cams_msg = if cams.len() == 1 {
         "1 camera:".to_string()
@@ -403,16 +925,7 @@ fn view_cam_list(cams: &[CamInfo]) -> Html {
     let all_rendered: Vec<Html> = cams
         .iter()
         .map(|cci| {
-            let cam_url = match cci.strand_cam_http_server_info {
-                BuiServerInfo::NoServer => "/does-not-exist".to_string(),
-                BuiServerInfo::Server(_) => {
-                    format!(
-                        "/{}/{}/",
-                        flydra_types::braid_http::CAM_PROXY_PATH,
-                        flydra_types::braid_http::encode_cam_name(&cci.name)
-                    )
-                }
-            };
+            let cam_url = cam_url(cci);
             let state = format!("{:?}", cci.state);
             let stats = format!("{:?}", cci.recent_stats);
             html! {
@@ -430,6 +943,7 @@ fn view_cam_list(cams: &[CamInfo]) -> Html {
         <div>
             <div>
                 {n_cams_msg}
+                {" "}<a href={MOSAIC_HASH}>{"(show all cameras)"}</a>
                 <ul>
                     {all_rendered}
                 </ul>
@@ -438,6 +952,84 @@ fn view_cam_list(cams: &[CamInfo]) -> Html {
     }
 }
 
+fn view_discovered_cameras(cams: &[DiscoveredCamera]) -> Html {
+    if cams.is_empty() {
+        return html! {};
+    }
+    let all_rendered: Vec<Html> = cams
+        .iter()
+        .map(|cam| {
+            let status = match (cam.in_braid, &cam.config_name) {
+                (true, _) => "(in Braid)".to_string(),
+                (false, Some(config_name)) => {
+                    format!("(without Braid, configured as \"{config_name}\")")
+                }
+                (false, None) => "(without Braid, not configured)".to_string(),
+            };
+            html! {
+                <li>
+                    {cam.name.as_str()}
+                    {" "}
+                    {status}
+                    {for cam.urls.iter().map(|url| html! {
+                        <>{" "}<a href={url.clone()}>{url.as_str()}</a></>
+                    })}
+                </li>
+            }
+        })
+        .collect();
+    html! {
+        <div>
+            {"Cameras on the network:"}
+            <ul>
+                {all_rendered}
+            </ul>
+        </div>
+    }
+}
+
+fn view_camera_groups(groups: &[CameraGroup]) -> Html {
+    if groups.is_empty() {
+        return html! {};
+    }
+    let all_rendered: Vec<Html> = groups
+        .iter()
+        .map(|g| {
+            let cam_names: Vec<&str> = g.cam_names.iter().map(|n| n.as_str()).collect();
+            html! {
+                <li>
+                    {format!(
+                        "\"{}\" ({}): {}",
+                        g.name,
+                        cam_names.join(", "),
+                        format_camera_params(&g.params)
+                    )}
+                </li>
+            }
+        })
+        .collect();
+    html! {
+        <div>
+            {"Groups:"}
+            <ul>
+                {all_rendered}
+            </ul>
+        </div>
+    }
+}
+
+fn format_camera_params(params: &CameraParams) -> String {
+    let exposure = match params.exposure_time_usec {
+        Some(v) => format!("exposure time {v} µsec"),
+        None => "exposure time not set".to_string(),
+    };
+    let gain = match params.gain {
+        Some(v) => format!("gain {v}"),
+        None => "gain not set".to_string(),
+    };
+    format!("{exposure}, {gain}")
+}
+
 fn view_model_server_link(opt_addr: &Option<std::net::SocketAddr>) -> Html {
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
@@ -451,11 +1043,16 @@ fn view_model_server_link(opt_addr: &Option<std::net::SocketAddr>) -> Html {
             addr.ip()
         };
         let url = format!("http://{}:{}/", ip, addr.port());
+        let view3d_url = format!("{url}view3d.html");
         html! {
             <div>
                 <a href={url}>
                     {"Model server"}
                 </a>
+                {" "}
+                <a href={view3d_url}>
+                    {"(live 3D view)"}
+                </a>
             </div>
         }
     } else {
@@ -0,0 +1,68 @@
+//! Alerts about runtime problems in Braid and the connected cameras.
+//!
+//! Alerts are shown in the browser UI, which plays a sound when a new alert
+//! arrives, and are sent to the services given in the
+//! `[mainbrain.notifications]` configuration section.
+
+use tracing::error;
+
+use alert_notifier::{NotificationConfig, Notifier};
+use flydra_types::{Alert, MAX_RECENT_ALERTS};
+
+use eyre::{Result, WrapErr};
+
+use crate::mainbrain::SharedStore;
+
+/// Source name of alerts raised by Braid itself.
+pub(crate) const MAINBRAIN_SOURCE: &str = "mainbrain";
+
+#[derive(Clone)]
+pub(crate) struct Alerts {
+    shared_store: SharedStore,
+    notifier: Option<Notifier>,
+}
+
+impl Alerts {
+    pub(crate) fn new(shared_store: SharedStore, cfg: Option<NotificationConfig>) -> Result<Self> {
+        let notifier = cfg
+            .map(Notifier::new)
+            .transpose()
+            .with_context(|| "notifications configuration")?;
+        Ok(Self {
+            shared_store,
+            notifier,
+        })
+    }
+
+    /// Raise a new alert of `kind` from `source`.
+    pub(crate) fn raise(&self, source: &str, kind: &str, message: String) {
+        self.raise_alert(Alert {
+            time: chrono::Utc::now(),
+            source: source.to_string(),
+            kind: kind.to_string(),
+            message,
+        });
+    }
+
+    /// Raise an alert, e.g. one received from a camera.
+    pub(crate) fn raise_alert(&self, alert: Alert) {
+        error!("Alert from \"{}\": {}", alert.source, alert.message);
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(&alert.source, &alert.kind, &alert.message);
+        }
+        let mut tracker = self.shared_store.write().unwrap();
+        tracker.modify(|shared| {
+            shared.recent_alerts.push(alert);
+            let n_alerts = shared.recent_alerts.len();
+            if n_alerts > MAX_RECENT_ALERTS {
+                shared.recent_alerts.drain(..n_alerts - MAX_RECENT_ALERTS);
+            }
+        });
+    }
+
+    /// Remove all alerts from the UI.
+    pub(crate) fn clear(&self) {
+        let mut tracker = self.shared_store.write().unwrap();
+        tracker.modify(|shared| shared.recent_alerts.clear());
+    }
+}
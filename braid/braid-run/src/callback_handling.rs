@@ -1,14 +1,22 @@
 use axum::response::IntoResponse;
-use tracing::debug;
+use tracing::{debug, error, warn};
 
-use event_stream_types::TolerantJson;
-use flydra_types::{BraidHttpApiCallback, PerCamSaveData};
+use event_stream_types::{AccessRole, TolerantJson};
+use flydra_types::{
+    BraidHttpApiCallback, CameraParams, PerCamSaveData, RawCamName, TextlogRow,
+    UpdateFeatureDetectSettings,
+};
 use http::StatusCode;
 use rust_cam_bui_types::RecordingPath;
 
 use crate::mainbrain::*;
 
-fn start_saving_mp4s_all_cams(app_state: &BraidAppState, start_saving: bool) {
+/// Time from receiving the request until synchronized MP4 recording starts.
+///
+/// This must be long enough to send the request to all cameras.
+const SYNCHRONIZED_MP4_START_DELAY: chrono::TimeDelta = chrono::TimeDelta::seconds(2);
+
+pub(crate) fn start_saving_mp4s_all_cams(app_state: &BraidAppState, start_saving: bool) {
     let mut tracker = app_state.shared_store.write().unwrap();
     tracker.modify(|store| {
         if start_saving {
@@ -19,13 +27,145 @@ fn start_saving_mp4s_all_cams(app_state: &BraidAppState, start_saving: bool) {
     });
 }
 
+/// Bring a camera which connected again, e.g. after its process restarted, into
+/// the state of the other cameras.
+///
+/// The camera is synchronized again as its frames arrive. Here, the settings
+/// which Braid pushed to the camera before are pushed again and the gap in the
+/// data is noted in the text log of the braidz file.
+async fn resync_reconnected_camera(
+    app_state: BraidAppState,
+    raw_cam_name: RawCamName,
+    feature_detect_settings: Option<UpdateFeatureDetectSettings>,
+) {
+    let cam_name = raw_cam_name.as_str();
+    warn!("Camera \"{cam_name}\" connected again. Restoring its configuration.");
+
+    // The session belongs to the previous instance of the camera.
+    let handler = &app_state.strand_cam_http_session_handler;
+    handler.forget_session(&raw_cam_name);
+
+    // The per-camera trigger offset is replaced by the synchronization upon
+    // reconnection.
+    if let Some(estimator) = &app_state.trigger_offset_estimator {
+        estimator.write().unwrap().forget(&raw_cam_name);
+    }
+
+    let clock_model = app_state.time_model_arc.read().unwrap().clone();
+    if let Err(e) = handler
+        .send_triggerbox_clock_model(&raw_cam_name, clock_model)
+        .await
+    {
+        error!("Error sending clock model to camera \"{cam_name}\": {e}");
+    }
+
+    if let Some(settings) = feature_detect_settings {
+        if let Err(e) = handler
+            .send_obj_detection_config(&raw_cam_name, &settings.current_feature_detect_settings)
+            .await
+        {
+            error!("Error sending object detection config to camera \"{cam_name}\": {e}");
+        }
+    }
+
+    let params = app_state
+        .camera_params
+        .read()
+        .unwrap()
+        .effective(&raw_cam_name);
+    if !params.is_empty() {
+        if let Err(e) = handler.send_camera_params(&raw_cam_name, &params).await {
+            error!("Error sending camera parameters to camera \"{cam_name}\": {e}");
+        }
+    }
+
+    let is_recording_mp4 = app_state
+        .shared_store
+        .read()
+        .unwrap()
+        .as_ref()
+        .fake_mp4_recording_path
+        .is_some();
+    if is_recording_mp4 {
+        if let Err(e) = handler.toggle_saving_mp4_files(&raw_cam_name, true).await {
+            error!("Error restarting MP4 recording of camera \"{cam_name}\": {e}");
+        }
+    }
+
+    if let Some(braidz_write_tx) = app_state.braidz_write_tx_weak.upgrade() {
+        let now = datetime_conversion::datetime_to_f64(&chrono::Utc::now());
+        let row = TextlogRow {
+            mainbrain_timestamp: now,
+            cam_id: cam_name.to_string(),
+            host_timestamp: now,
+            message: format!(
+                "camera \"{cam_name}\" reconnected, data missing since its last frame"
+            ),
+        };
+        // Ignore error on shutdown.
+        let _ = braidz_write_tx
+            .send(flydra2::SaveToDiskMsg::Textlog(row))
+            .await;
+    }
+}
+
+/// Send the parameters set for all cameras or a group to a camera which
+/// connected after they were set.
+async fn send_camera_params_to_new_camera(app_state: BraidAppState, raw_cam_name: RawCamName) {
+    let params = app_state
+        .camera_params
+        .read()
+        .unwrap()
+        .effective(&raw_cam_name);
+    if params.is_empty() {
+        return;
+    }
+    if let Err(e) = app_state
+        .strand_cam_http_session_handler
+        .send_camera_params(&raw_cam_name, &params)
+        .await
+    {
+        error!(
+            "Error sending camera parameters to camera \"{}\": {e}",
+            raw_cam_name.as_str()
+        );
+    }
+}
+
+/// Send camera parameters to those of the cameras which are connected.
+async fn send_camera_params_connected(
+    app_state: &BraidAppState,
+    to_send: Vec<(RawCamName, CameraParams)>,
+) -> Result<(), (StatusCode, &'static str)> {
+    let connected = app_state.cam_manager.all_raw_cam_names();
+    let to_send = to_send
+        .into_iter()
+        .filter(|(cam_name, _)| connected.contains(cam_name))
+        .collect();
+    let handler = &app_state.strand_cam_http_session_handler;
+    let failed =
+        crate::camera_params::send_each("parameters", to_send, |cam_name, params| async move {
+            handler.send_camera_params(&cam_name, &params).await
+        })
+        .await;
+    if !failed.is_empty() {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "send_camera_params failed",
+        ));
+    }
+    Ok(())
+}
+
 pub(crate) async fn callback_handler(
     axum::extract::State(app_state): axum::extract::State<crate::mainbrain::BraidAppState>,
     session_key: axum_token_auth::SessionKey,
+    axum::Extension(role): axum::Extension<AccessRole>,
     TolerantJson(payload): TolerantJson<BraidHttpApiCallback>,
 ) -> impl IntoResponse {
     session_key.is_present();
     let fut = async {
+        role.require_control()?;
         use BraidHttpApiCallback::*;
         match payload {
             NewCamera(cam_info) => {
@@ -35,7 +175,7 @@ pub(crate) async fn callback_handler(
                 let camera_periodic_signal_period_usec =
                     cam_info.camera_periodic_signal_period_usec;
                 let mut cam_manager3 = app_state.cam_manager.clone();
-                cam_manager3
+                let registration = cam_manager3
                     .register_new_camera(
                         &cam_info.raw_cam_name,
                         &http_camserver_info,
@@ -43,9 +183,9 @@ pub(crate) async fn callback_handler(
                     )
                     .map_err(|msg| (StatusCode::BAD_REQUEST, msg))?;
 
-                let mut current_cam_data = app_state.per_cam_data_arc.write().unwrap();
-                if current_cam_data
-                    .insert(
+                let previous = {
+                    let mut current_cam_data = app_state.per_cam_data_arc.write().unwrap();
+                    current_cam_data.insert(
                         cam_info.raw_cam_name.clone(),
                         PerCamSaveData {
                             cam_settings_data: Some(cam_settings_data),
@@ -53,9 +193,21 @@ pub(crate) async fn callback_handler(
                             current_image_png: cam_info.current_image_png,
                         },
                     )
-                    .is_some()
-                {
-                    panic!("camera {} already known", cam_info.raw_cam_name.as_str());
+                };
+
+                if registration == flydra2::Registration::Reconnected {
+                    let feature_detect_settings =
+                        previous.and_then(|previous| previous.feature_detect_settings);
+                    tokio::spawn(resync_reconnected_camera(
+                        app_state.clone(),
+                        cam_info.raw_cam_name.clone(),
+                        feature_detect_settings,
+                    ));
+                } else {
+                    tokio::spawn(send_camera_params_to_new_camera(
+                        app_state.clone(),
+                        cam_info.raw_cam_name.clone(),
+                    ));
                 }
             }
             UpdateCurrentImage(image_info) => {
@@ -84,6 +236,31 @@ pub(crate) async fn callback_handler(
                     .unwrap()
                     .feature_detect_settings = Some(feature_detect_settings.inner);
             }
+            UpdateMp4Recording(mp4_recording) => {
+                debug!(
+                    "got UpdateMp4Recording for camera \"{}\"",
+                    mp4_recording.raw_cam_name.as_str()
+                );
+                {
+                    let mut recording = app_state.mp4_recording_cams.write().unwrap();
+                    if mp4_recording.inner.is_some() {
+                        recording.insert(mp4_recording.raw_cam_name.clone());
+                    } else {
+                        recording.remove(&mp4_recording.raw_cam_name);
+                    }
+                    let mut tracker = app_state.shared_store.write().unwrap();
+                    tracker.modify(|shared| {
+                        shared.mp4_recording_cameras = recording.iter().cloned().collect();
+                    });
+                }
+                if let Some(braidz_write_tx) = app_state.braidz_write_tx_weak.upgrade() {
+                    // `braidz_write_tx` will be dropped after this scope.
+                    braidz_write_tx
+                        .send(flydra2::SaveToDiskMsg::Mp4Recording(mp4_recording))
+                        .await
+                        .unwrap();
+                }
+            }
             DoRecordCsvTables(value) => {
                 debug!("got DoRecordCsvTables({})", value);
                 toggle_saving_csv_tables(
@@ -93,6 +270,7 @@ pub(crate) async fn callback_handler(
                     app_state.braidz_write_tx_weak.clone(),
                     app_state.per_cam_data_arc.clone(),
                     app_state.shared_store.clone(),
+                    &app_state.sync_markers,
                 )
                 .await;
             }
@@ -112,6 +290,43 @@ pub(crate) async fn callback_handler(
 
                 start_saving_mp4s_all_cams(&app_state, start_saving);
             }
+            StartSynchronizedMp4Recording(filename_prefix) => {
+                debug!("got StartSynchronizedMp4Recording({filename_prefix:?})");
+
+                if filename_prefix
+                    .as_deref()
+                    .is_some_and(|prefix| prefix.is_empty() || prefix.contains(['/', '\\']))
+                {
+                    return Err((StatusCode::BAD_REQUEST, "invalid filename prefix"));
+                }
+
+                let start_time = chrono::Utc::now() + SYNCHRONIZED_MP4_START_DELAY;
+                let start_synced_frame = {
+                    let time_model = app_state.time_model_arc.read().unwrap();
+                    time_model.as_ref().and_then(|model| {
+                        let start = flydra_types::FlydraFloatTimestampLocal::from_dt(&start_time);
+                        estimate_synced_frame(model, &start, 0.0)
+                    })
+                };
+                let start = ci2_remote_control::SynchronizedMp4Start {
+                    filename_prefix,
+                    start_time,
+                    start_synced_frame: start_synced_frame.map(|f| f.0),
+                };
+
+                app_state
+                    .strand_cam_http_session_handler
+                    .start_synchronized_mp4_all(&start)
+                    .await
+                    .map_err(|_e| {
+                        (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            "start_synchronized_mp4_all failed",
+                        )
+                    })?;
+
+                start_saving_mp4s_all_cams(&app_state, true);
+            }
             SetExperimentUuid(value) => {
                 debug!("got SetExperimentUuid({})", value);
                 if let Some(braidz_write_tx) = app_state.braidz_write_tx_weak.upgrade() {
@@ -122,6 +337,36 @@ pub(crate) async fn callback_handler(
                         .unwrap();
                 }
             }
+            SetExperimentMetadata(metadata) => {
+                debug!("got SetExperimentMetadata({:?})", metadata);
+                if let Some(braidz_write_tx) = app_state.braidz_write_tx_weak.upgrade() {
+                    // `braidz_write_tx` will be dropped after this scope.
+                    braidz_write_tx
+                        .send(flydra2::SaveToDiskMsg::SetExperimentMetadata(
+                            metadata.clone(),
+                        ))
+                        .await
+                        .unwrap();
+                }
+
+                app_state
+                    .strand_cam_http_session_handler
+                    .send_experiment_metadata_all(&metadata)
+                    .await
+                    .map_err(|_e| {
+                        (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            "send_experiment_metadata_all failed",
+                        )
+                    })?;
+
+                {
+                    let mut tracker = app_state.shared_store.write().unwrap();
+                    tracker.modify(|store| {
+                        store.experiment_metadata = metadata;
+                    });
+                }
+            }
             SetPostTriggerBufferSize(val) => {
                 debug!("got SetPostTriggerBufferSize({val})");
 
@@ -168,6 +413,112 @@ pub(crate) async fn callback_handler(
                     debug!("Already saving, not initiating again.");
                 }
             }
+            ReportAlert(alert) => {
+                app_state.alerts.raise_alert(alert);
+            }
+            ClearAlerts => {
+                app_state.alerts.clear();
+            }
+            SetLogFilter(directives) => {
+                debug!("got SetLogFilter({directives:?})");
+                if let Err(e) = app_state.log_handle.set_filter(&directives) {
+                    tracing::error!("invalid log filter \"{directives}\": {e}");
+                    return Err((StatusCode::BAD_REQUEST, "invalid log filter"));
+                }
+
+                app_state
+                    .strand_cam_http_session_handler
+                    .send_log_filter_all(&directives)
+                    .await
+                    .map_err(|_e| {
+                        (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            "send_log_filter_all failed",
+                        )
+                    })?;
+
+                {
+                    let mut tracker = app_state.shared_store.write().unwrap();
+                    tracker.modify(|store| {
+                        store.log_filter = app_state.log_handle.filter();
+                    });
+                }
+            }
+            DoQuit => {
+                debug!("got DoQuit");
+                // If the queue is full, shutdown was already requested.
+                let _ = app_state.shtdwn_q_tx.try_send(());
+            }
+            SetGroupCameraParams(request) => {
+                debug!("got SetGroupCameraParams({request:?})");
+                let cam_names = match &request.group {
+                    None => {
+                        // Include the cameras not yet connected.
+                        let mut cam_names: std::collections::BTreeSet<RawCamName> =
+                            app_state.camera_configs.keys().cloned().collect();
+                        cam_names.extend(app_state.cam_manager.all_raw_cam_names());
+                        cam_names.into_iter().collect()
+                    }
+                    Some(group) => {
+                        let tracker = app_state.shared_store.read().unwrap();
+                        tracker
+                            .as_ref()
+                            .camera_groups
+                            .iter()
+                            .find(|g| &g.name == group)
+                            .map(|g| g.cam_names.clone())
+                            .ok_or((StatusCode::BAD_REQUEST, "unknown camera group"))?
+                    }
+                };
+
+                let to_send = app_state
+                    .camera_params
+                    .write()
+                    .unwrap()
+                    .set_for_group(&cam_names, &request.params);
+
+                {
+                    let mut tracker = app_state.shared_store.write().unwrap();
+                    tracker.modify(|store| {
+                        for group in store.camera_groups.iter_mut() {
+                            if request.group.is_none()
+                                || request.group.as_ref() == Some(&group.name)
+                            {
+                                group.params = request.params.or(&group.params);
+                            }
+                        }
+                    });
+                }
+
+                send_camera_params_connected(&app_state, to_send).await?;
+            }
+            SetCameraParamOverride(per_cam) => {
+                debug!("got SetCameraParamOverride({per_cam:?})");
+                let raw_cam_name = per_cam.raw_cam_name;
+                if !app_state.camera_configs.contains_key(&raw_cam_name)
+                    && !app_state
+                        .cam_manager
+                        .all_raw_cam_names()
+                        .contains(&raw_cam_name)
+                {
+                    return Err((StatusCode::BAD_REQUEST, "unknown camera"));
+                }
+
+                let (params, overrides) = {
+                    let mut camera_params = app_state.camera_params.write().unwrap();
+                    let params = camera_params.set_override(&raw_cam_name, per_cam.inner);
+                    (params, camera_params.overrides())
+                };
+
+                {
+                    let mut tracker = app_state.shared_store.write().unwrap();
+                    tracker.modify(|store| {
+                        store.camera_param_overrides = overrides;
+                    });
+                }
+
+                send_camera_params_connected(&app_state, vec![(raw_cam_name, params)]).await?;
+            }
         }
         Ok::<_, (StatusCode, &'static str)>(())
     };
@@ -0,0 +1,242 @@
+//! Exposure time and gain of several cameras at once.
+//!
+//! Cameras are assigned to named groups with the `groups` field of their
+//! configuration. The parameters can be set for all cameras or for the cameras
+//! of a group, in which case they are sent to each camera like a change in its
+//! own web UI. Parameters set for a single camera override those set for its
+//! groups until the override is removed. Cameras which connect later receive
+//! the parameters set before.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use flydra_types::{BraidCameraConfig, CameraGroup, CameraParams, PerCam, RawCamName};
+
+/// The parameter groups defined in the camera configurations, sorted by name.
+pub(crate) fn camera_groups(
+    camera_configs: &BTreeMap<RawCamName, BraidCameraConfig>,
+) -> Vec<CameraGroup> {
+    let mut groups: BTreeMap<&str, BTreeSet<RawCamName>> = BTreeMap::new();
+    for (cam_name, config) in camera_configs.iter() {
+        for group in config.groups.iter() {
+            groups
+                .entry(group.as_str())
+                .or_default()
+                .insert(cam_name.clone());
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(name, cam_names)| CameraGroup {
+            name: name.to_string(),
+            cam_names: cam_names.into_iter().collect(),
+            params: CameraParams::default(),
+        })
+        .collect()
+}
+
+/// Send a value to each camera with `send`, `what` naming the value in logs.
+///
+/// A failure to send to one camera does not prevent sending to the others.
+/// Returns the cameras to which sending failed.
+pub(crate) async fn send_each<T, F, Fut, E>(
+    what: &str,
+    to_send: Vec<(RawCamName, T)>,
+    mut send: F,
+) -> Vec<RawCamName>
+where
+    F: FnMut(RawCamName, T) -> Fut,
+    Fut: std::future::Future<Output = Result<(), E>>,
+    E: std::fmt::Display,
+{
+    let mut failed = Vec::new();
+    for (cam_name, value) in to_send {
+        if let Err(e) = send(cam_name.clone(), value).await {
+            tracing::error!(
+                "Failed sending {what} to camera \"{}\": {e}",
+                cam_name.as_str()
+            );
+            failed.push(cam_name);
+        }
+    }
+    failed
+}
+
+/// The camera parameters set by Braid.
+#[derive(Debug, Default)]
+pub(crate) struct CameraParamsState {
+    /// The parameters set for all cameras or for a group, by camera.
+    from_groups: BTreeMap<RawCamName, CameraParams>,
+    /// The parameters set for single cameras.
+    overrides: BTreeMap<RawCamName, CameraParams>,
+}
+
+impl CameraParamsState {
+    /// Set `params` for the cameras `cam_names`.
+    ///
+    /// Returns the parameters to send to each camera, which excludes
+    /// overridden parameters.
+    pub(crate) fn set_for_group(
+        &mut self,
+        cam_names: &[RawCamName],
+        params: &CameraParams,
+    ) -> Vec<(RawCamName, CameraParams)> {
+        let mut to_send = Vec::new();
+        for cam_name in cam_names.iter() {
+            let current = self.from_groups.entry(cam_name.clone()).or_default();
+            *current = params.or(current);
+
+            let overrides = self.overrides.get(cam_name).cloned().unwrap_or_default();
+            let params = CameraParams {
+                exposure_time_usec: params
+                    .exposure_time_usec
+                    .filter(|_| overrides.exposure_time_usec.is_none()),
+                gain: params.gain.filter(|_| overrides.gain.is_none()),
+            };
+            if !params.is_empty() {
+                to_send.push((cam_name.clone(), params));
+            }
+        }
+        to_send
+    }
+
+    /// Replace the overrides of the camera `cam_name` with `params`.
+    ///
+    /// Returns the parameters to send to the camera. For a parameter no longer
+    /// overridden, this is the value set for its groups, if any.
+    pub(crate) fn set_override(
+        &mut self,
+        cam_name: &RawCamName,
+        params: CameraParams,
+    ) -> CameraParams {
+        if params.is_empty() {
+            self.overrides.remove(cam_name);
+        } else {
+            self.overrides.insert(cam_name.clone(), params);
+        }
+        self.effective(cam_name)
+    }
+
+    /// The parameters of the camera `cam_name`.
+    pub(crate) fn effective(&self, cam_name: &RawCamName) -> CameraParams {
+        let from_groups = self.from_groups.get(cam_name).cloned().unwrap_or_default();
+        match self.overrides.get(cam_name) {
+            Some(overrides) => overrides.or(&from_groups),
+            None => from_groups,
+        }
+    }
+
+    /// The overrides of all cameras, sorted by camera name.
+    pub(crate) fn overrides(&self) -> Vec<PerCam<CameraParams>> {
+        self.overrides
+            .iter()
+            .map(|(raw_cam_name, params)| PerCam {
+                raw_cam_name: raw_cam_name.clone(),
+                inner: params.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+fn test_cam_names(names: &[&str]) -> Vec<RawCamName> {
+    names
+        .iter()
+        .map(|name| RawCamName::new(name.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_camera_groups() {
+    let camera_configs: BTreeMap<_, _> = [("cam1", vec!["a", "b"]), ("cam2", vec!["a"])]
+        .into_iter()
+        .map(|(name, groups)| {
+            let mut config = BraidCameraConfig::default_absdiff_config(name.to_string());
+            config.groups = groups.into_iter().map(String::from).collect();
+            (RawCamName::new(name.to_string()), config)
+        })
+        .collect();
+    let groups = camera_groups(&camera_configs);
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].name, "a");
+    assert_eq!(groups[0].cam_names, test_cam_names(&["cam1", "cam2"]));
+    assert_eq!(groups[1].name, "b");
+    assert_eq!(groups[1].cam_names, test_cam_names(&["cam1"]));
+}
+
+#[test]
+fn test_set_for_group() {
+    let cams = test_cam_names(&["cam1", "cam2"]);
+    let mut state = CameraParamsState::default();
+    let exposure = CameraParams {
+        exposure_time_usec: Some(1000.0),
+        gain: None,
+    };
+    let gain = CameraParams {
+        exposure_time_usec: None,
+        gain: Some(5.0),
+    };
+
+    assert_eq!(
+        state.set_for_group(&cams, &exposure),
+        vec![
+            (cams[0].clone(), exposure.clone()),
+            (cams[1].clone(), exposure.clone())
+        ]
+    );
+    // Parameters set for groups accumulate.
+    state.set_for_group(&cams[..1], &gain);
+    assert_eq!(state.effective(&cams[0]), exposure.or(&gain));
+    assert_eq!(state.effective(&cams[1]), exposure);
+
+    // An overridden parameter is not sent when set for a group.
+    let exposure_override = CameraParams {
+        exposure_time_usec: Some(2000.0),
+        gain: None,
+    };
+    assert_eq!(
+        state.set_override(&cams[0], exposure_override.clone()),
+        exposure_override.or(&gain)
+    );
+    assert_eq!(
+        state.set_for_group(&cams, &exposure),
+        vec![(cams[1].clone(), exposure.clone())]
+    );
+    assert_eq!(
+        state.set_for_group(&cams, &exposure.or(&gain)),
+        vec![
+            (cams[0].clone(), gain.clone()),
+            (cams[1].clone(), exposure.or(&gain))
+        ]
+    );
+    assert_eq!(state.overrides().len(), 1);
+
+    // Removing the override restores the value set for the groups.
+    assert_eq!(
+        state.set_override(&cams[0], CameraParams::default()),
+        exposure.or(&gain)
+    );
+    assert!(state.overrides().is_empty());
+}
+
+#[tokio::test]
+async fn test_send_each_partial_failure() {
+    let cams = test_cam_names(&["cam1", "cam2", "cam3"]);
+    let to_send = cams
+        .iter()
+        .map(|cam_name| (cam_name.clone(), CameraParams::default()))
+        .collect();
+    let mut attempted = Vec::new();
+    let failed = send_each("parameters", to_send, |cam_name, _params: CameraParams| {
+        let result = if cam_name.as_str() == "cam2" {
+            Err("camera not responding")
+        } else {
+            Ok(())
+        };
+        attempted.push(cam_name);
+        async move { result }
+    })
+    .await;
+    // The camera after the failed one is still sent its parameters.
+    assert_eq!(attempted, cams);
+    assert_eq!(failed, test_cam_names(&["cam2"]));
+}
@@ -0,0 +1,359 @@
+//! Per-camera processing of the incoming 2D data on a pool of worker tasks.
+//!
+//! The datagrams received from each camera are decoded and processed (frame
+//! drop detection, synchronization and timestamps) in a task for that camera.
+//! The tasks run in parallel on the worker threads of the runtime. Their
+//! results are merged into a single stream for the coordinate processor, in
+//! which only the synchronized data association and tracking are done
+//! serially.
+//!
+//! The queues between the stages are bounded. If the queue of a camera is
+//! full, the datagrams of that camera are dropped and later counted as lost in
+//! transport. When the merged queue is full, the camera tasks wait, so that
+//! their queues fill up.
+//!
+//! Each camera task owns its state, a [CameraTask], and records the latencies
+//! of its stages locally, adding them to the shared [StageLatencies] only every
+//! [LATENCY_FLUSH_INTERVAL].
+
+use std::{collections::HashMap, future::Future, net::SocketAddr, time::Instant};
+
+use bytes::BytesMut;
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_util::codec::Decoder;
+use tracing::{error, warn};
+
+use flydra2::{LocalStageLatencies, StageLatencies, StreamItem, STAGE_CAMERA, STAGE_CAMERA_QUEUE};
+use flydra_types::{CborPacketCodec, FlydraRawUdpPacket};
+
+/// Number of datagrams queued for each camera.
+const CAMERA_QUEUE_LEN: usize = 64;
+
+/// Number of processed packets of all cameras queued for the coordinate
+/// processor.
+const MERGED_QUEUE_LEN: usize = 256;
+
+/// Minimum interval between warnings about dropped datagrams of a camera.
+const DROP_WARNING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Interval at which each camera task adds its latencies to the shared ones.
+const LATENCY_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Process the `datagrams` of each camera in its own task with
+/// `process_packet` and return the merged results.
+///
+/// Cameras are distinguished by the address from which they send. An error
+/// receiving datagrams ends the returned stream with [StreamItem::EOF].
+pub(crate) fn spawn_camera_pipeline<S, F, Fut>(
+    datagrams: S,
+    process_packet: F,
+    stage_latencies: StageLatencies,
+) -> impl Stream<Item = StreamItem>
+where
+    S: Stream<Item = std::io::Result<(BytesMut, SocketAddr)>> + Send + 'static,
+    F: Fn(FlydraRawUdpPacket) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Option<StreamItem>> + Send + 'static,
+{
+    let (merged_tx, merged_rx) = mpsc::channel(MERGED_QUEUE_LEN);
+    tokio::spawn(dispatch(
+        datagrams,
+        process_packet,
+        stage_latencies,
+        merged_tx,
+    ));
+    tokio_stream::wrappers::ReceiverStream::new(merged_rx)
+}
+
+struct Worker {
+    tx: mpsc::Sender<(BytesMut, Instant)>,
+    n_dropped: usize,
+    last_drop_warning: Option<Instant>,
+}
+
+async fn dispatch<S, F, Fut>(
+    datagrams: S,
+    process_packet: F,
+    stage_latencies: StageLatencies,
+    merged_tx: mpsc::Sender<StreamItem>,
+) where
+    S: Stream<Item = std::io::Result<(BytesMut, SocketAddr)>> + Send + 'static,
+    F: Fn(FlydraRawUdpPacket) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Option<StreamItem>> + Send + 'static,
+{
+    let mut workers: HashMap<SocketAddr, Worker> = HashMap::new();
+    let mut datagrams = std::pin::pin!(datagrams);
+    while let Some(r) = datagrams.next().await {
+        let (buf, addr) = match r {
+            Ok(r) => r,
+            Err(e) => {
+                error!("{}", e);
+                let _ = merged_tx.send(StreamItem::EOF).await;
+                return;
+            }
+        };
+        let worker = workers.entry(addr).or_insert_with(|| Worker {
+            tx: spawn_worker(
+                addr,
+                process_packet.clone(),
+                stage_latencies.clone(),
+                merged_tx.clone(),
+            ),
+            n_dropped: 0,
+            last_drop_warning: None,
+        });
+        match worker.tx.try_send((buf, Instant::now())) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                worker.n_dropped += 1;
+                let do_warn = worker
+                    .last_drop_warning
+                    .map(|t| t.elapsed() >= DROP_WARNING_INTERVAL)
+                    .unwrap_or(true);
+                if do_warn {
+                    warn!(
+                        "Queue for data from {addr} is full. Dropped {} datagram(s).",
+                        worker.n_dropped
+                    );
+                    worker.n_dropped = 0;
+                    worker.last_drop_warning = Some(Instant::now());
+                }
+            }
+            Err(TrySendError::Closed(_)) => {
+                // The coordinate processor is no longer running.
+                return;
+            }
+        }
+    }
+}
+
+fn spawn_worker<F, Fut>(
+    addr: SocketAddr,
+    process_packet: F,
+    stage_latencies: StageLatencies,
+    merged_tx: mpsc::Sender<StreamItem>,
+) -> mpsc::Sender<(BytesMut, Instant)>
+where
+    F: Fn(FlydraRawUdpPacket) -> Fut + Send + 'static,
+    Fut: Future<Output = Option<StreamItem>> + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::channel::<(BytesMut, Instant)>(CAMERA_QUEUE_LEN);
+    let mut task = CameraTask::new(addr, process_packet);
+    tokio::spawn(async move {
+        while let Some((buf, received)) = rx.recv().await {
+            if !task.process_datagram(buf, received, &merged_tx).await {
+                // The coordinate processor is no longer running.
+                return;
+            }
+            task.maybe_flush_latencies(&stage_latencies);
+        }
+        task.latencies.flush(&stage_latencies);
+    });
+    tx
+}
+
+/// The state of the task processing the data of a camera.
+struct CameraTask<F> {
+    addr: SocketAddr,
+    codec: CborPacketCodec,
+    process_packet: F,
+    latencies: LocalStageLatencies,
+    last_flush: Instant,
+}
+
+impl<F, Fut> CameraTask<F>
+where
+    F: Fn(FlydraRawUdpPacket) -> Fut,
+    Fut: Future<Output = Option<StreamItem>>,
+{
+    fn new(addr: SocketAddr, process_packet: F) -> Self {
+        Self {
+            addr,
+            codec: CborPacketCodec::default(),
+            process_packet,
+            latencies: LocalStageLatencies::default(),
+            last_flush: Instant::now(),
+        }
+    }
+
+    /// Process the packets in `buf`, received at `received`, and send the
+    /// results to `merged_tx`.
+    ///
+    /// Returns `false` if `merged_tx` is closed.
+    async fn process_datagram(
+        &mut self,
+        mut buf: BytesMut,
+        received: Instant,
+        merged_tx: &mpsc::Sender<StreamItem>,
+    ) -> bool {
+        let start = Instant::now();
+        self.latencies.record(STAGE_CAMERA_QUEUE, start - received);
+        // A datagram may contain more than one packet.
+        loop {
+            let packet_start = Instant::now();
+            let packet = match self.codec.decode(&mut buf) {
+                Ok(Some(packet)) => packet,
+                Ok(None) => return true,
+                Err(e) => {
+                    error!("Could not decode datagram from {}: {e}", self.addr);
+                    return true;
+                }
+            };
+            let item = (self.process_packet)(packet).await;
+            self.latencies.record(STAGE_CAMERA, packet_start.elapsed());
+            if let Some(item) = item {
+                if merged_tx.send(item).await.is_err() {
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// Add the recorded latencies to `shared` if they were last added more
+    /// than [LATENCY_FLUSH_INTERVAL] ago.
+    fn maybe_flush_latencies(&mut self, shared: &StageLatencies) {
+        if self.last_flush.elapsed() >= LATENCY_FLUSH_INTERVAL {
+            self.latencies.flush(shared);
+            self.last_flush = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_packet(cam_name: &str, framenumber: i32) -> FlydraRawUdpPacket {
+    FlydraRawUdpPacket {
+        cam_name: cam_name.to_string(),
+        timestamp: None,
+        cam_received_time: flydra_types::FlydraFloatTimestampLocal::from_f64(0.0),
+        device_timestamp: None,
+        block_id: None,
+        framenumber,
+        n_frames_skipped: 0,
+        done_camnode_processing: 0.0,
+        preprocess_stamp: 0.0,
+        image_processing_steps: flydra_types::ImageProcessingSteps::empty(),
+        points: vec![],
+    }
+}
+
+/// Encode `packets` into a single datagram.
+#[cfg(test)]
+fn test_datagram(packets: Vec<FlydraRawUdpPacket>) -> BytesMut {
+    use tokio_util::codec::Encoder;
+    let mut buf = BytesMut::new();
+    let mut codec = CborPacketCodec::default();
+    for packet in packets {
+        codec.encode(packet, &mut buf).unwrap();
+    }
+    buf
+}
+
+/// Process a packet into an item with its camera name and frame number, or
+/// drop it if the frame number is negative.
+#[cfg(test)]
+async fn test_process_packet(packet: FlydraRawUdpPacket) -> Option<StreamItem> {
+    let frame = u64::try_from(packet.framenumber).ok()?;
+    let frame_data = flydra2::FrameData::new(
+        flydra_types::RawCamName::new(packet.cam_name),
+        flydra_types::CamNum(0),
+        flydra_types::SyncFno(frame),
+        None,
+        packet.cam_received_time,
+        None,
+        None,
+    );
+    Some(StreamItem::Packet(flydra2::FrameDataAndPoints {
+        frame_data,
+        points: vec![],
+    }))
+}
+
+/// The camera name and frame number of `item`, or `None` for the end of the
+/// stream.
+#[cfg(test)]
+fn test_item_frame(item: &StreamItem) -> Option<(String, u64)> {
+    match item {
+        StreamItem::Packet(fdp) => Some((
+            fdp.frame_data.cam_name.as_str().to_string(),
+            fdp.frame_data.synced_frame.0,
+        )),
+        StreamItem::EOF => None,
+    }
+}
+
+#[tokio::test]
+async fn test_camera_task() {
+    let addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+    let stage_latencies = StageLatencies::new();
+    let (merged_tx, mut merged_rx) = mpsc::channel(10);
+    let mut task = CameraTask::new(addr, test_process_packet);
+
+    // All packets of a datagram are processed in order.
+    let buf = test_datagram(vec![
+        test_packet("cam1", 1),
+        test_packet("cam1", -1),
+        test_packet("cam1", 2),
+    ]);
+    assert!(task.process_datagram(buf, Instant::now(), &merged_tx).await);
+    let mut frames = vec![];
+    while let Ok(item) = merged_rx.try_recv() {
+        frames.push(test_item_frame(&item).unwrap());
+    }
+    assert_eq!(frames, vec![("cam1".into(), 1), ("cam1".into(), 2)]);
+
+    // An invalid datagram is skipped.
+    let buf = BytesMut::from(&b"not cbor"[..]);
+    assert!(task.process_datagram(buf, Instant::now(), &merged_tx).await);
+    assert!(merged_rx.try_recv().is_err());
+
+    // Latencies are only shared when flushed.
+    assert!(stage_latencies.summary_and_reset().is_empty());
+    task.latencies.flush(&stage_latencies);
+    let summary = stage_latencies.summary_and_reset();
+    let count = |stage| summary.iter().find(|s| s.stage == stage).unwrap().count;
+    assert_eq!(count(STAGE_CAMERA_QUEUE), 2);
+    assert_eq!(count(STAGE_CAMERA), 3);
+
+    // Processing stops when the coordinate processor is gone.
+    drop(merged_rx);
+    let buf = test_datagram(vec![test_packet("cam1", 3)]);
+    assert!(!task.process_datagram(buf, Instant::now(), &merged_tx).await);
+}
+
+#[tokio::test]
+async fn test_camera_pipeline() {
+    let addr1: SocketAddr = "127.0.0.1:1001".parse().unwrap();
+    let addr2: SocketAddr = "127.0.0.1:1002".parse().unwrap();
+    let datagrams = futures::stream::iter(vec![
+        Ok((
+            test_datagram(vec![test_packet("cam1", 1), test_packet("cam1", 2)]),
+            addr1,
+        )),
+        Ok((test_datagram(vec![test_packet("cam2", 1)]), addr2)),
+        Ok((test_datagram(vec![test_packet("cam1", 3)]), addr1)),
+        Err(std::io::Error::other("socket closed")),
+    ]);
+    let stage_latencies = StageLatencies::new();
+    let stream = spawn_camera_pipeline(datagrams, test_process_packet, stage_latencies.clone());
+
+    // The stream ends when all camera tasks are done.
+    let items: Vec<StreamItem> = stream.collect().await;
+    let frames: Vec<_> = items.iter().filter_map(test_item_frame).collect();
+    assert_eq!(items.len() - frames.len(), 1, "one EOF");
+    let of_cam = |name: &str| -> Vec<u64> {
+        frames
+            .iter()
+            .filter(|(cam, _)| cam == name)
+            .map(|(_, frame)| *frame)
+            .collect()
+    };
+    // The order of the data of each camera is kept.
+    assert_eq!(of_cam("cam1"), vec![1, 2, 3]);
+    assert_eq!(of_cam("cam2"), vec![1]);
+
+    // The camera tasks added their latencies when finishing.
+    let summary = stage_latencies.summary_and_reset();
+    let count = |stage| summary.iter().find(|s| s.stage == stage).unwrap().count;
+    assert_eq!(count(STAGE_CAMERA_QUEUE), 3);
+    assert_eq!(count(STAGE_CAMERA), 4);
+}
@@ -0,0 +1,118 @@
+//! Small JPEG images of the cameras for the mosaic page of the browser UI.
+//!
+//! Each camera periodically sends its most recent image to Braid as PNG (see
+//! `send_current_image_interval_msec` in the camera configuration). These are
+//! downscaled and encoded as JPEG on request, so that a preview of all cameras
+//! needs only little bandwidth and no connection to each camera.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
+};
+use http::{header, StatusCode};
+use image::{codecs::jpeg::JpegEncoder, DynamicImage};
+use serde::Deserialize;
+
+use flydra_types::RawCamName;
+
+use crate::mainbrain::BraidAppState;
+
+/// The width of the thumbnails if not given in the request.
+const DEFAULT_MAX_WIDTH: u32 = 320;
+/// The largest width of the thumbnails which may be requested.
+const LARGEST_MAX_WIDTH: u32 = 1280;
+const JPEG_QUALITY: u8 = 70;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ThumbnailQuery {
+    max_width: Option<u32>,
+}
+
+/// Decode a PNG image and encode it as JPEG, downscaled to be at most
+/// `max_width` pixels wide.
+fn thumbnail_jpeg(png: &[u8], max_width: u32) -> image::ImageResult<Vec<u8>> {
+    let image = image::load_from_memory_with_format(png, image::ImageFormat::Png)?;
+    let image = if image.width() > max_width {
+        image.thumbnail(max_width, u32::MAX)
+    } else {
+        image
+    };
+    // JPEG supports only 8 bit luma and RGB.
+    let image = match image {
+        DynamicImage::ImageLuma8(_) | DynamicImage::ImageRgb8(_) => image,
+        DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA8(_) => {
+            DynamicImage::ImageLuma8(image.to_luma8())
+        }
+        _ => DynamicImage::ImageRgb8(image.to_rgb8()),
+    };
+    let mut jpeg = Vec::new();
+    image.write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))?;
+    Ok(jpeg)
+}
+
+pub(crate) async fn camera_thumbnail_handler(
+    State(app_state): State<BraidAppState>,
+    session_key: axum_token_auth::SessionKey,
+    Path(raw_cam_name): Path<String>,
+    Query(query): Query<ThumbnailQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    session_key.is_present();
+    let cam_name = RawCamName::new(raw_cam_name);
+    let png = app_state
+        .per_cam_data_arc
+        .read()
+        .unwrap()
+        .get(&cam_name)
+        .map(|data| data.current_image_png.data.clone())
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Camera \"{}\" not found.", cam_name.as_str()),
+            )
+        })?;
+    if png.is_empty() {
+        // The camera has not sent an image yet.
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+    let max_width = query
+        .max_width
+        .unwrap_or(DEFAULT_MAX_WIDTH)
+        .clamp(1, LARGEST_MAX_WIDTH);
+
+    // Decoding and encoding take a few milliseconds for large images.
+    let jpeg = tokio::task::spawn_blocking(move || thumbnail_jpeg(&png, max_width))
+        .await
+        .unwrap()
+        .map_err(|e| {
+            let err_msg = format!(
+                "Creating thumbnail of camera \"{}\": {e}",
+                cam_name.as_str()
+            );
+            tracing::error!(err_msg);
+            (StatusCode::INTERNAL_SERVER_ERROR, err_msg)
+        })?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/jpeg"),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        jpeg,
+    )
+        .into_response())
+}
+
+#[test]
+fn test_thumbnail_jpeg() {
+    let image = image::GrayImage::from_fn(640, 480, |x, y| image::Luma([((x + y) % 256) as u8]));
+    let mut png = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+
+    let jpeg = thumbnail_jpeg(png.get_ref(), 320).unwrap();
+    let thumbnail = image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (320, 240));
+
+    // Smaller images are not upscaled.
+    let jpeg = thumbnail_jpeg(png.get_ref(), 1000).unwrap();
+    let thumbnail = image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg).unwrap();
+    assert_eq!(thumbnail.width(), 640);
+}
@@ -0,0 +1,470 @@
+//! Closed-loop stimulus control from live tracking data.
+//!
+//! The rules in [ClosedLoopConfig] are evaluated on each update of a Kalman
+//! estimate, so actions are performed in the same frame in which an object
+//! starts or stops meeting a condition.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use futures::{SinkExt, StreamExt};
+use json_lines::codec::JsonLinesCodec;
+use nalgebra::Point3;
+use tokio_serial::SerialPortBuilderExt;
+use tokio_util::codec::Decoder;
+use tracing::{debug, error, info};
+
+use braid_config_data::{ClosedLoopAction, ClosedLoopConfig, ClosedLoopRule};
+use flydra2::{SendKalmanEstimatesRow, SendType, TimeDataPassthrough, TrackingVolumeShape};
+use flydra_types::TextlogRow;
+use led_box_comms::{DeviceState, OnState, ToDevice};
+
+use eyre::{self, Result, WrapErr};
+
+use crate::output_devices::OutputDevices;
+
+struct RuleState {
+    cfg: ClosedLoopRule,
+    region: TrackingVolumeShape,
+    /// Objects currently meeting the condition.
+    active: BTreeSet<u32>,
+}
+
+impl RuleState {
+    fn condition_met(&self, row: &SendKalmanEstimatesRow) -> bool {
+        let speed = (row.xvel * row.xvel + row.yvel * row.yvel + row.zvel * row.zvel).sqrt();
+        let fast_enough = match self.cfg.min_speed {
+            Some(min_speed) => speed >= min_speed,
+            None => true,
+        };
+        let slow_enough = match self.cfg.max_speed {
+            Some(max_speed) => speed <= max_speed,
+            None => true,
+        };
+        fast_enough && slow_enough && self.region.contains(&Point3::new(row.x, row.y, row.z))
+    }
+
+    /// Update the objects meeting the condition with `msg` from the tracker.
+    ///
+    /// The rule stays active as long as any object meets the condition, so a
+    /// transition happens only for the first object to enter and the last
+    /// object to exit.
+    fn update(&mut self, msg: &SendType) -> Option<(Transition, u32)> {
+        let was_active = !self.active.is_empty();
+        let obj_id = match msg {
+            SendType::Birth(row) | SendType::Update(row) => {
+                if self.condition_met(row) {
+                    self.active.insert(row.obj_id);
+                } else {
+                    self.active.remove(&row.obj_id);
+                }
+                row.obj_id
+            }
+            SendType::Death(obj_id) => {
+                self.active.remove(obj_id);
+                *obj_id
+            }
+            SendType::EndOfFrame(_) | SendType::CalibrationFlydraXml(_) => return None,
+        };
+        transition(was_active, !self.active.is_empty()).map(|t| (t, obj_id))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Transition {
+    Enter,
+    Exit,
+}
+
+pub(crate) struct ClosedLoop {
+    rules: Vec<RuleState>,
+    led_box: Option<(tokio::sync::mpsc::Sender<ToDevice>, DeviceState)>,
+    udp_socket: Option<std::net::UdpSocket>,
+    output_devices: Arc<OutputDevices>,
+    braidz_write_tx_weak: tokio::sync::mpsc::WeakSender<flydra2::SaveToDiskMsg>,
+}
+
+impl ClosedLoop {
+    pub(crate) async fn new(
+        cfg: ClosedLoopConfig,
+        braidz_write_tx_weak: tokio::sync::mpsc::WeakSender<flydra2::SaveToDiskMsg>,
+        output_devices: Arc<OutputDevices>,
+    ) -> Result<Self> {
+        let rules = cfg
+            .rules
+            .into_iter()
+            .map(|rule| {
+                let region = TrackingVolumeShape::new(&rule.region)
+                    .with_context(|| format!("closed loop rule \"{}\"", rule.name))?;
+                Ok(RuleState {
+                    cfg: rule,
+                    region,
+                    active: BTreeSet::new(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let actions = || {
+            rules
+                .iter()
+                .flat_map(|rule| rule.cfg.on_enter.iter().chain(rule.cfg.on_exit.iter()))
+        };
+
+        // Check the configuration before opening any device.
+        for action in actions() {
+            match action {
+                ClosedLoopAction::LedBox { channel, .. } => {
+                    if !(1..=4).contains(channel) {
+                        eyre::bail!("closed loop LED box action uses unknown channel {channel}");
+                    }
+                }
+                ClosedLoopAction::Ttl { device, .. } => {
+                    if !output_devices.contains(device) {
+                        eyre::bail!(
+                            "closed loop TTL action uses unknown output device \"{device}\""
+                        );
+                    }
+                }
+                ClosedLoopAction::Udp { .. } => {}
+            }
+        }
+
+        let led_box = if actions().any(|a| matches!(a, ClosedLoopAction::LedBox { .. })) {
+            let Some(device) = cfg.led_box_device.as_ref() else {
+                eyre::bail!("closed loop LED box action used but no `led_box_device` configured");
+            };
+            Some((open_led_box(device).await?, DeviceState::default()))
+        } else {
+            None
+        };
+
+        let udp_socket = if actions().any(|a| matches!(a, ClosedLoopAction::Udp { .. })) {
+            let any_ipv6 = actions()
+                .any(|a| matches!(a, ClosedLoopAction::Udp { addr, .. } if addr.is_ipv6()));
+            let bind_addr = if any_ipv6 { "[::]:0" } else { "0.0.0.0:0" };
+            Some(std::net::UdpSocket::bind(bind_addr)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            rules,
+            led_box,
+            udp_socket,
+            output_devices,
+            braidz_write_tx_weak,
+        })
+    }
+
+    /// Evaluate the rules for each message from the tracker until the tracker
+    /// stops.
+    pub(crate) async fn run(
+        mut self,
+        mut rx: tokio::sync::mpsc::Receiver<(SendType, TimeDataPassthrough)>,
+    ) {
+        while let Some((msg, tdpt)) = rx.recv().await {
+            let transitions: Vec<_> = self
+                .rules
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(idx, rule)| rule.update(&msg).map(|(t, obj_id)| (idx, t, obj_id)))
+                .collect();
+            for (idx, t, obj_id) in transitions {
+                if let Err(e) = self.on_transition(idx, t, obj_id, &tdpt).await {
+                    error!("closed loop rule \"{}\": {e:?}", self.rules[idx].cfg.name);
+                }
+            }
+        }
+        debug!("closed loop done");
+    }
+
+    async fn on_transition(
+        &mut self,
+        rule_idx: usize,
+        transition: Transition,
+        obj_id: u32,
+        tdpt: &TimeDataPassthrough,
+    ) -> Result<()> {
+        let rule = &self.rules[rule_idx].cfg;
+        let actions = match transition {
+            Transition::Enter => rule.on_enter.clone(),
+            Transition::Exit => rule.on_exit.clone(),
+        };
+        let message = format!(
+            "closed loop rule \"{}\": {transition:?} (obj_id {obj_id}, frame {})",
+            rule.name,
+            tdpt.synced_frame()
+        );
+        info!("{message}");
+
+        for action in actions.iter() {
+            match action {
+                ClosedLoopAction::LedBox {
+                    channel,
+                    on,
+                    intensity,
+                } => {
+                    // Checked in `new()`.
+                    let (led_box_tx, state) = self.led_box.as_mut().unwrap();
+                    let chan = match channel {
+                        1 => &mut state.ch1,
+                        2 => &mut state.ch2,
+                        3 => &mut state.ch3,
+                        4 => &mut state.ch4,
+                        _ => eyre::bail!("unknown LED box channel {channel}"),
+                    };
+                    chan.on_state = if *on {
+                        OnState::ConstantOn
+                    } else {
+                        OnState::Off
+                    };
+                    chan.intensity = intensity.unwrap_or(led_box_comms::MAX_INTENSITY);
+                    led_box_tx.send(ToDevice::DeviceState(*state)).await?;
+                }
+                ClosedLoopAction::Udp { addr, message } => {
+                    // Checked in `new()`.
+                    let socket = self.udp_socket.as_ref().unwrap();
+                    socket.send_to(message.as_bytes(), addr)?;
+                }
+                ClosedLoopAction::Ttl {
+                    device,
+                    line,
+                    high,
+                    pulse_msec,
+                } => {
+                    let pulse = pulse_msec.map(std::time::Duration::from_millis);
+                    self.output_devices.set_line(device, *line, *high, pulse)?;
+                }
+            }
+        }
+
+        if let Some(braidz_write_tx) = self.braidz_write_tx_weak.upgrade() {
+            let now = datetime_conversion::datetime_to_f64(&chrono::Utc::now());
+            let host_timestamp = tdpt
+                .trigger_timestamp()
+                .map(|ts| ts.as_f64())
+                .unwrap_or(now);
+            let row = TextlogRow {
+                mainbrain_timestamp: now,
+                cam_id: "mainbrain".to_string(),
+                host_timestamp,
+                message,
+            };
+            // Ignore error on shutdown.
+            let _ = braidz_write_tx
+                .send(flydra2::SaveToDiskMsg::Textlog(row))
+                .await;
+        }
+        Ok(())
+    }
+}
+
+fn transition(was_active: bool, is_active: bool) -> Option<Transition> {
+    match (was_active, is_active) {
+        (false, true) => Some(Transition::Enter),
+        (true, false) => Some(Transition::Exit),
+        _ => None,
+    }
+}
+
+/// Open the LED box and return a channel to send messages to it.
+async fn open_led_box(device: &str) -> Result<tokio::sync::mpsc::Sender<ToDevice>> {
+    info!("opening LED box \"{device}\" for closed loop control");
+    #[allow(unused_mut)]
+    let mut port = tokio_serial::new(device, led_box_comms::BAUD_RATE)
+        .open_native_async()
+        .with_context(|| format!("opening LED box \"{device}\""))?;
+    #[cfg(unix)]
+    port.set_exclusive(false)?;
+
+    let (mut writer, mut reader) = JsonLinesCodec::default().framed(port).split();
+    writer.send(ToDevice::VersionRequest).await?;
+
+    let (led_box_tx, mut led_box_rx) = tokio::sync::mpsc::channel(10);
+    tokio::spawn(async move {
+        while let Some(msg) = led_box_rx.recv().await {
+            if let Err(e) = writer.send(msg).await {
+                error!("Failed sending to LED box: {e}");
+            }
+        }
+    });
+
+    // Responses must be read so that the device does not block.
+    tokio::spawn(async move {
+        while let Some(msg) = reader.next().await {
+            match msg {
+                Ok(led_box_comms::FromDevice::VersionResponse(found)) => {
+                    if found != led_box_comms::COMM_VERSION {
+                        error!(
+                            "LED box has comm version {found}, but version {} is needed.",
+                            led_box_comms::COMM_VERSION
+                        );
+                    }
+                }
+                Ok(msg) => {
+                    debug!("LED box message: {msg:?}");
+                }
+                Err(e) => {
+                    error!("LED box error: {e}");
+                    break;
+                }
+            }
+        }
+    });
+    Ok(led_box_tx)
+}
+
+#[cfg(test)]
+fn test_rule(min_speed: Option<f64>, max_speed: Option<f64>) -> RuleState {
+    let rule = ClosedLoopRule {
+        name: "test".to_string(),
+        region: flydra_types::TrackingVolume::Box {
+            min: [0.0, 0.0, 0.0],
+            max: [1.0, 1.0, 1.0],
+        },
+        min_speed,
+        max_speed,
+        on_enter: vec![],
+        on_exit: vec![],
+    };
+    RuleState {
+        region: TrackingVolumeShape::new(&rule.region).unwrap(),
+        cfg: rule,
+        active: BTreeSet::new(),
+    }
+}
+
+#[cfg(test)]
+fn test_update(obj_id: u32, x: f64, xvel: f64) -> SendType {
+    SendType::Update(SendKalmanEstimatesRow {
+        obj_id,
+        frame: flydra_types::SyncFno(0),
+        x,
+        y: 0.5,
+        z: 0.5,
+        xvel,
+        yvel: 0.0,
+        zvel: 0.0,
+        P00: 0.0,
+        P01: 0.0,
+        P02: 0.0,
+        P11: 0.0,
+        P12: 0.0,
+        P22: 0.0,
+        P33: 0.0,
+        P44: 0.0,
+        P55: 0.0,
+    })
+}
+
+#[test]
+fn test_rule_enter_exit() {
+    let mut rule = test_rule(None, None);
+    assert_eq!(rule.update(&test_update(1, -0.5, 0.0)), None);
+    assert_eq!(
+        rule.update(&test_update(1, 0.5, 0.0)),
+        Some((Transition::Enter, 1))
+    );
+    // No transition while the object stays inside.
+    assert_eq!(rule.update(&test_update(1, 0.6, 0.0)), None);
+    assert_eq!(
+        rule.update(&test_update(1, 1.5, 0.0)),
+        Some((Transition::Exit, 1))
+    );
+    assert_eq!(rule.update(&test_update(1, 2.0, 0.0)), None);
+
+    // The death of an object inside the region is an exit.
+    assert_eq!(
+        rule.update(&test_update(2, 0.5, 0.0)),
+        Some((Transition::Enter, 2))
+    );
+    assert_eq!(
+        rule.update(&SendType::Death(2)),
+        Some((Transition::Exit, 2))
+    );
+    assert_eq!(rule.update(&SendType::Death(1)), None);
+    assert_eq!(
+        rule.update(&SendType::EndOfFrame(flydra_types::SyncFno(0))),
+        None
+    );
+}
+
+#[test]
+fn test_rule_multiple_objects() {
+    let mut rule = test_rule(None, None);
+    assert_eq!(
+        rule.update(&test_update(1, 0.5, 0.0)),
+        Some((Transition::Enter, 1))
+    );
+    // A second object entering or leaving does not cause a transition while
+    // the first is still inside.
+    assert_eq!(rule.update(&test_update(2, 0.5, 0.0)), None);
+    assert_eq!(rule.update(&test_update(1, 1.5, 0.0)), None);
+    assert_eq!(rule.update(&test_update(1, 0.5, 0.0)), None);
+    assert_eq!(rule.update(&SendType::Death(2)), None);
+    // Only the last object leaving is an exit.
+    assert_eq!(
+        rule.update(&test_update(1, 1.5, 0.0)),
+        Some((Transition::Exit, 1))
+    );
+}
+
+#[test]
+fn test_rule_speed() {
+    let mut rule = test_rule(Some(0.1), Some(1.0));
+    assert_eq!(rule.update(&test_update(1, 0.5, 0.05)), None);
+    assert_eq!(
+        rule.update(&test_update(1, 0.5, 0.1)),
+        Some((Transition::Enter, 1))
+    );
+    assert_eq!(rule.update(&test_update(1, 0.5, -1.0)), None);
+    assert_eq!(
+        rule.update(&test_update(1, 0.5, 1.5)),
+        Some((Transition::Exit, 1))
+    );
+    // Fast enough, but outside the region.
+    assert_eq!(rule.update(&test_update(1, 1.5, 0.5)), None);
+}
+
+#[tokio::test]
+async fn test_invalid_config() {
+    let (braidz_write_tx, _braidz_write_rx) = tokio::sync::mpsc::channel(1);
+    let output_devices = Arc::new(OutputDevices::new(&[]).unwrap());
+    let config = |action| ClosedLoopConfig {
+        led_box_device: Some("/nonexistent".to_string()),
+        rules: vec![ClosedLoopRule {
+            on_enter: vec![action],
+            ..test_rule(None, None).cfg
+        }],
+    };
+
+    let bad_channel = ClosedLoopAction::LedBox {
+        channel: 5,
+        on: true,
+        intensity: None,
+    };
+    let err = ClosedLoop::new(
+        config(bad_channel),
+        braidz_write_tx.downgrade(),
+        output_devices.clone(),
+    )
+    .await
+    .err()
+    .unwrap();
+    assert!(err.to_string().contains("channel 5"), "{err}");
+
+    let bad_device = ClosedLoopAction::Ttl {
+        device: "missing".to_string(),
+        line: 1,
+        high: true,
+        pulse_msec: None,
+    };
+    let err = ClosedLoop::new(
+        config(bad_device),
+        braidz_write_tx.downgrade(),
+        output_devices,
+    )
+    .await
+    .err()
+    .unwrap();
+    assert!(err.to_string().contains("missing"), "{err}");
+}
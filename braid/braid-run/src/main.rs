@@ -4,13 +4,23 @@ use tracing::debug;
 
 use braid::braid_start;
 use braid_config_data::parse_config_file;
+use bui_backend_session_types::AccessToken;
+use env_tracing_logger::LogConfig;
 use flydra_types::{
     BraidCameraConfig, BuiServerAddrInfo, RawCamName, StartCameraBackend, TriggerType,
 };
 
+mod alerts;
 mod callback_handling;
+mod camera_params;
+mod camera_pipeline;
+mod camera_thumbnail;
+mod closed_loop;
 mod mainbrain;
 mod multicam_http_session_handler;
+mod output_devices;
+mod shutdown;
+mod sync_markers;
 
 #[derive(Debug, Parser)]
 #[command(author, version, about)]
@@ -20,29 +30,107 @@ struct BraidRunCliArgs {
     /// Flag if logging to console should be disabled.
     #[arg(short, long)]
     disable_console: bool,
+    /// Write the log files of Braid and the cameras it launches as JSON.
+    #[arg(long)]
+    log_json: bool,
+    /// Stream camera calibrations, 2D detections and 3D tracking to rerun.
+    ///
+    /// This is either the address of a rerun viewer (which listens by default
+    /// on 127.0.0.1:9876) or the name of an `.rrd` file to save.
+    #[arg(long, env = "RERUN_VIEWER_ADDR")]
+    rerun: Option<flydra2::RerunTarget>,
 }
 
+/// Parts of Braid with their own log file in addition to the main log file.
+const LOG_COMPONENTS: &[env_tracing_logger::LogComponent] = &[
+    env_tracing_logger::LogComponent {
+        name: "cameras",
+        targets: &[
+            "braid_run::multicam_http_session_handler",
+            "flydra2::connected_camera_manager",
+            "flydra2::software_sync",
+        ],
+    },
+    env_tracing_logger::LogComponent {
+        name: "tracking",
+        targets: &["flydra2"],
+    },
+    env_tracing_logger::LogComponent {
+        name: "triggerbox",
+        targets: &["braid_triggerbox"],
+    },
+];
+
 fn compute_strand_cam_args(
     camera: &BraidCameraConfig,
     mainbrain_internal_addr: &BuiServerAddrInfo,
+    log_json: bool,
 ) -> Result<Vec<String>> {
     let urls = mainbrain_internal_addr.build_urls()?;
     let url = urls
         .first()
         .ok_or_else(|| eyre::eyre!("need at least one URL"))?;
     let url_string = format!("{url}");
-    Ok(vec![
+    let mut args = vec![
         "--camera-name".into(),
         camera.name.clone(),
         "--braid-url".into(),
         url_string,
-    ])
+    ];
+    if log_json {
+        args.push("--log-json".into());
+    }
+    Ok(args)
+}
+
+/// A Strand Cam process which ran at least this long started successfully.
+const STRAND_CAM_MIN_RUNTIME_FOR_RESTART: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Delay before the first restart of a crashed Strand Cam process.
+const STRAND_CAM_RESTART_DELAY_MIN: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Maximum delay before restarting a crashed Strand Cam process.
+const STRAND_CAM_RESTART_DELAY_MAX: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// The delays before restarting a crashed Strand Cam process.
+///
+/// A process which never started successfully, e.g. because it is
+/// misconfigured, is not restarted. Afterwards, e.g. when the camera was
+/// unplugged, the delay doubles with each restart which fails, up to
+/// [STRAND_CAM_RESTART_DELAY_MAX].
+struct RestartBackoff {
+    has_started: bool,
+    next_delay: std::time::Duration,
+}
+
+impl RestartBackoff {
+    fn new() -> Self {
+        Self {
+            has_started: false,
+            next_delay: STRAND_CAM_RESTART_DELAY_MIN,
+        }
+    }
+
+    /// The delay before restarting a process which crashed after `runtime`,
+    /// or `None` if it is not restarted.
+    fn on_crash(&mut self, runtime: std::time::Duration) -> Option<std::time::Duration> {
+        if runtime >= STRAND_CAM_MIN_RUNTIME_FOR_RESTART {
+            self.has_started = true;
+            self.next_delay = STRAND_CAM_RESTART_DELAY_MIN;
+        } else if !self.has_started {
+            return None;
+        }
+        let delay = self.next_delay;
+        self.next_delay = (delay * 2).min(STRAND_CAM_RESTART_DELAY_MAX);
+        Some(delay)
+    }
 }
 
 fn launch_strand_cam(
     strand_cam_set: &mut tokio::task::JoinSet<()>,
     camera: &BraidCameraConfig,
     mainbrain_internal_addr: &BuiServerAddrInfo,
+    log_json: bool,
 ) -> Result<()> {
     // On initial startup strand cam queries for
     // [flydra_types::RemoteCameraInfoResponse] and thus we do not need to
@@ -66,7 +154,7 @@ fn launch_strand_cam(
     let cam_name = camera.name.clone();
 
     let mut exec = std::process::Command::new(&exe);
-    let args = compute_strand_cam_args(camera, mainbrain_internal_addr)?;
+    let args = compute_strand_cam_args(camera, mainbrain_internal_addr, log_json)?;
     exec.args(&args);
     debug!("exec: {:?}", exec);
     let mut obj = exec.spawn().context(format!(
@@ -74,16 +162,39 @@ fn launch_strand_cam(
         exe.display()
     ))?;
 
-    let _abort_handle = strand_cam_set.spawn_blocking(move || {
+    let mut backoff = RestartBackoff::new();
+    let _abort_handle = strand_cam_set.spawn_blocking(move || loop {
+        let started = std::time::Instant::now();
         let exit_code = obj.wait().unwrap();
-        if !exit_code.success() {
-            tracing::error!(
-                "Strand Cam executable for {cam_name} exited with exit code {:?}",
-                exit_code.code()
-            );
-        } else {
+        if exit_code.success() {
             debug!("Strand Cam executable done.");
+            return;
         }
+        tracing::error!(
+            "Strand Cam executable for {cam_name} exited with exit code {:?}",
+            exit_code.code()
+        );
+        // Restart a camera which crashed. Braid resynchronizes it when it
+        // connects again. A camera which was killed by a signal (e.g. Ctrl-C)
+        // is not restarted.
+        if exit_code.code().is_none() {
+            return;
+        }
+        let Some(delay) = backoff.on_crash(started.elapsed()) else {
+            return;
+        };
+        tracing::warn!(
+            "Restarting Strand Cam executable for {cam_name} in {:.0} seconds.",
+            delay.as_secs_f64()
+        );
+        std::thread::sleep(delay);
+        obj = match exec.spawn() {
+            Ok(obj) => obj,
+            Err(e) => {
+                tracing::error!("Restarting Strand Cam executable for {cam_name} failed: {e}");
+                return;
+            }
+        };
     });
     Ok(())
 }
@@ -108,8 +219,13 @@ async fn main() -> Result<()> {
     let log_file_name = std::path::PathBuf::from(shellexpand::full(&log_file_name)?.to_string());
     // TODO: delete log files older than, e.g. one week.
 
-    let _guard = env_tracing_logger::initiate_logging(Some(&log_file_name), args.disable_console)
-        .map_err(|e| eyre::eyre!("error initiating logging: {e}"))?;
+    let log_handle = env_tracing_logger::initiate_logging_with_config(LogConfig {
+        path: Some(log_file_name),
+        disable_console: args.disable_console,
+        json: args.log_json,
+        components: LOG_COMPONENTS,
+    })
+    .map_err(|e| eyre::eyre!("error initiating logging: {e}"))?;
 
     let version = format!("{} (git {})", env!("CARGO_PKG_VERSION"), env!("GIT_HASH"));
     tracing::info!("{} {}", "run", version);
@@ -132,6 +248,10 @@ async fn main() -> Result<()> {
             false,
             flydra_types::StartSoftwareFrameRateLimit::Enable(cfg.framerate),
         ),
+        TriggerType::SoftwareSync(cfg) => (
+            false,
+            flydra_types::StartSoftwareFrameRateLimit::Enable(cfg.framerate),
+        ),
         TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp => {
             (false, flydra_types::StartSoftwareFrameRateLimit::NoChange)
         }
@@ -147,18 +267,47 @@ async fn main() -> Result<()> {
 
     let address_string: String = cfg.mainbrain.http_api_server_addr.clone();
     let (listener, mainbrain_server_info) = flydra_types::start_listener(&address_string).await?;
+    let (https_listener, mainbrain_server_info) = match cfg.mainbrain.https.as_ref() {
+        Some(https) => {
+            let (https_listener, https_server_info) =
+                flydra_types::start_listener(&https.addr).await?;
+            // Both servers share the token. It is required if either server is
+            // not listening only on the loopback interface.
+            let token = match mainbrain_server_info.token() {
+                AccessToken::NoToken => https_server_info.token().clone(),
+                token => token.clone(),
+            };
+            let https_listener = mainbrain::HttpsListener {
+                listener: https_listener,
+                tls: https_server::TlsConfig {
+                    cert_file: https.cert_file.clone(),
+                    key_file: https.key_file.clone(),
+                },
+            };
+            (
+                Some(https_listener),
+                BuiServerAddrInfo::new(*mainbrain_server_info.addr(), token),
+            )
+        }
+        None => (None, mainbrain_server_info),
+    };
     let mainbrain_internal_addr = mainbrain_server_info.clone();
 
     let cfg_cameras = cfg.cameras;
     let mut strand_cam_set = tokio::task::JoinSet::new();
     for camera in cfg_cameras.into_iter() {
         if camera.start_backend != StartCameraBackend::Remote {
-            launch_strand_cam(&mut strand_cam_set, &camera, &mainbrain_internal_addr)?;
+            launch_strand_cam(
+                &mut strand_cam_set,
+                &camera,
+                &mainbrain_internal_addr,
+                args.log_json,
+            )?;
         } else {
             tracing::info!(
                 "Not starting remote camera \"{}\". Use args: {}",
                 camera.name,
-                compute_strand_cam_args(&camera, &mainbrain_internal_addr)
+                compute_strand_cam_args(&camera, &mainbrain_internal_addr, args.log_json)
                     .unwrap()
                     .join(" ")
             );
@@ -186,8 +335,11 @@ async fn main() -> Result<()> {
         software_limit_framerate.clone(),
         "braid",
         listener,
+        https_listener,
         mainbrain_server_info,
         strand_cam_set,
+        log_handle,
+        args.rerun,
     )
     .await?;
 
@@ -195,3 +347,28 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_restart_backoff() {
+    use std::time::Duration;
+
+    let short = Duration::from_secs(1);
+    let long = STRAND_CAM_MIN_RUNTIME_FOR_RESTART;
+
+    // A process which fails at startup is not restarted.
+    assert_eq!(RestartBackoff::new().on_crash(short), None);
+
+    let mut backoff = RestartBackoff::new();
+    assert_eq!(backoff.on_crash(long), Some(STRAND_CAM_RESTART_DELAY_MIN));
+    // Restarts which fail are delayed ever longer, up to the maximum.
+    let mut delays = vec![];
+    for _ in 0..10 {
+        delays.push(backoff.on_crash(short).unwrap());
+    }
+    assert_eq!(delays[0], STRAND_CAM_RESTART_DELAY_MIN * 2);
+    assert_eq!(delays[1], STRAND_CAM_RESTART_DELAY_MIN * 4);
+    assert!(delays.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(delays[9], STRAND_CAM_RESTART_DELAY_MAX);
+    // After a successful restart, the delay is reset.
+    assert_eq!(backoff.on_crash(long), Some(STRAND_CAM_RESTART_DELAY_MIN));
+}
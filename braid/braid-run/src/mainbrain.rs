@@ -22,19 +22,25 @@ use tower_http::trace::TraceLayer;
 use tracing::{debug, error, info};
 
 use bui_backend_session_types::AccessToken;
-use event_stream_types::{AcceptsEventStream, EventBroadcaster};
+use disk_space_monitor::DiskSpaceLevel;
+use event_stream_types::{AcceptsEventStream, AccessRole, EventBroadcaster};
 use flydra2::{CoordProcessor, CoordProcessorConfig, FrameDataAndPoints, StreamItem};
 use flydra_types::{
-    braid_http::{CAM_PROXY_PATH, REMOTE_CAMERA_INFO_PATH},
-    BraidHttpApiSharedState, BuiServerAddrInfo, CamInfo, CborPacketCodec, FakeSyncConfig,
-    FlydraFloatTimestampLocal, HostClock, PerCamSaveData, RawCamName, SyncFno, TriggerType,
-    Triggerbox, BRAID_EVENTS_URL_PATH, BRAID_EVENT_NAME, TRIGGERBOX_SYNC_SECONDS,
+    braid_http::{CAMERA_THUMBNAIL_PATH, CAM_PROXY_PATH, REMOTE_CAMERA_INFO_PATH},
+    BraidHttpApiSharedState, BuiServerAddrInfo, CamInfo, FakeSyncConfig, FlydraFloatTimestampLocal,
+    HostClock, PerCamSaveData, RawCamName, StartCameraBackend, SyncFno, TriggerType, Triggerbox,
+    BRAID_EVENTS_URL_PATH, BRAID_EVENT_NAME, TRIGGERBOX_SYNC_SECONDS,
 };
 use rust_cam_bui_types::{ClockModel, RecordingPath};
 
 use eyre::{self, Result, WrapErr};
 
-use crate::multicam_http_session_handler::{MaybeSession, StrandCamHttpSessionHandler};
+use crate::{
+    alerts::{self, Alerts},
+    multicam_http_session_handler::{MaybeSession, StrandCamHttpSessionHandler},
+    output_devices::OutputDevices,
+    sync_markers::SyncMarkers,
+};
 
 #[cfg(feature = "bundle_files")]
 static ASSETS_DIR: include_dir::Dir<'static> =
@@ -51,7 +57,13 @@ pub(crate) const APP_INFO: AppInfo = AppInfo {
 const COOKIE_SECRET_KEY: &str = "cookie-secret-base64";
 pub(crate) const STRAND_CAM_COOKIE_KEY: &str = "strand-cam-cookie";
 
-type SharedStore = Arc<RwLock<ChangeTracker<BraidHttpApiSharedState>>>;
+/// Time without data from a camera after which an alert is raised.
+const CAMERA_STALL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Interval at which the latency of each stage of processing is reported.
+const STAGE_LATENCY_REPORT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+pub(crate) type SharedStore = Arc<RwLock<ChangeTracker<BraidHttpApiSharedState>>>;
 
 #[derive(thiserror::Error, Debug)]
 pub(crate) enum MainbrainError {
@@ -72,6 +84,11 @@ pub(crate) enum MainbrainError {
     },
     #[error("unknown camera \"{cam_name}\"")]
     UnknownCamera { cam_name: RawCamName },
+    #[error("failed sending {what} to {} camera(s)", cam_names.len())]
+    SendFailed {
+        what: &'static str,
+        cam_names: Vec<RawCamName>,
+    },
 }
 
 pub(crate) type MainbrainResult<T> = std::result::Result<T, MainbrainError>;
@@ -86,12 +103,20 @@ pub(crate) struct BraidAppState {
     event_broadcaster: EventBroadcaster<usize>,
     pub(crate) per_cam_data_arc: Arc<RwLock<BTreeMap<RawCamName, PerCamSaveData>>>,
     pub(crate) expected_framerate_arc: Arc<RwLock<Option<f32>>>,
-    camera_configs: BTreeMap<RawCamName, flydra_types::BraidCameraConfig>,
+    pub(crate) camera_configs: BTreeMap<RawCamName, flydra_types::BraidCameraConfig>,
     next_connection_id: Arc<RwLock<usize>>,
     pub(crate) strand_cam_http_session_handler: StrandCamHttpSessionHandler,
     pub(crate) cam_manager: flydra2::ConnectedCamerasManager,
+    pub(crate) time_model_arc: Arc<RwLock<Option<ClockModel>>>,
+    pub(crate) trigger_offset_estimator: Option<Arc<RwLock<flydra2::TriggerOffsetEstimator>>>,
     pub(crate) output_base_dirname: PathBuf,
+    pub(crate) mp4_recording_cams: crate::shutdown::Mp4RecordingCams,
+    pub(crate) camera_params: Arc<RwLock<crate::camera_params::CameraParamsState>>,
+    pub(crate) shtdwn_q_tx: tokio::sync::mpsc::Sender<()>,
     pub(crate) braidz_write_tx_weak: tokio::sync::mpsc::WeakSender<flydra2::SaveToDiskMsg>,
+    pub(crate) sync_markers: Arc<SyncMarkers>,
+    pub(crate) alerts: Alerts,
+    pub(crate) log_handle: env_tracing_logger::LogHandle,
 }
 
 async fn events_handler(
@@ -187,15 +212,59 @@ async fn remote_camera_info_handler(
     }
 }
 
+/// Largest body of a request to a camera which is checked for being allowed
+/// with read-only access.
+const MAX_READ_ONLY_BODY_SIZE: usize = 100_000;
+
+/// With read-only access, only allow requests to a camera which do not change
+/// anything.
+async fn check_read_only_cam_request(
+    cam_path: &str,
+    req: axum::extract::Request,
+) -> std::result::Result<axum::extract::Request, (StatusCode, String)> {
+    if matches!(*req.method(), http::Method::GET | http::Method::HEAD) {
+        return Ok(req);
+    }
+    let forbidden = || (StatusCode::FORBIDDEN, "Access is read-only".to_string());
+    if cam_path != "callback" {
+        return Err(forbidden());
+    }
+    // Callbacks concerning only the connection of the browser are allowed.
+    let (parts, body) = req.into_parts();
+    let body = axum::body::to_bytes(body, MAX_READ_ONLY_BODY_SIZE)
+        .await
+        .map_err(|_| forbidden())?;
+    match serde_json::from_slice::<strand_cam_storetype::CallbackType>(&body) {
+        Ok(callback) if callback.is_read_only() => Ok(axum::extract::Request::from_parts(
+            parts,
+            axum::body::Body::from(body),
+        )),
+        _ => Err(forbidden()),
+    }
+}
+
 async fn cam_proxy_handler_inner(
     app_state: BraidAppState,
     session_key: axum_token_auth::SessionKey,
+    role: AccessRole,
     raw_cam_name: String,
     cam_path: String,
     req: axum::extract::Request,
 ) -> impl axum::response::IntoResponse {
     session_key.is_present();
     tracing::debug!("raw_cam_name: {raw_cam_name}, cam_path: \"{cam_path}\", req: {req:?}");
+    if cam_path == strand_cam_storetype::STRAND_CAM_WS_URL_PATH {
+        // WebSockets are not proxied. The browser falls back to the event
+        // stream.
+        return Err((
+            StatusCode::NOT_FOUND,
+            "WebSockets are not proxied by Braid".to_string(),
+        ));
+    }
+    let req = match role {
+        AccessRole::Control => req,
+        AccessRole::ReadOnly => check_read_only_cam_request(&cam_path, req).await?,
+    };
     let accepts: Vec<HeaderValue> = req
         .headers()
         .get_all(http::header::ACCEPT)
@@ -246,26 +315,36 @@ async fn cam_proxy_handler_inner(
 async fn cam_proxy_handler_root(
     State(app_state): State<BraidAppState>,
     session_key: axum_token_auth::SessionKey,
+    axum::Extension(role): axum::Extension<AccessRole>,
     Path(raw_cam_name): Path<String>,
     req: axum::extract::Request,
 ) -> impl axum::response::IntoResponse {
     session_key.is_present();
-    cam_proxy_handler_inner(app_state, session_key, raw_cam_name, "".into(), req).await
+    cam_proxy_handler_inner(app_state, session_key, role, raw_cam_name, "".into(), req).await
 }
 
 async fn cam_proxy_handler(
     State(app_state): State<BraidAppState>,
     session_key: axum_token_auth::SessionKey,
+    axum::Extension(role): axum::Extension<AccessRole>,
     Path((raw_cam_name, cam_path)): Path<(String, String)>,
     req: axum::extract::Request,
 ) -> impl axum::response::IntoResponse {
     session_key.is_present();
-    cam_proxy_handler_inner(app_state, session_key, raw_cam_name, cam_path, req).await
+    cam_proxy_handler_inner(app_state, session_key, role, raw_cam_name, cam_path, req).await
+}
+
+/// A listener of the HTTPS server, which serves the same as the HTTP server.
+pub(crate) struct HttpsListener {
+    pub(crate) listener: tokio::net::TcpListener,
+    pub(crate) tls: https_server::TlsConfig,
 }
 
 async fn launch_braid_http_backend(
     secret_base64: Option<String>,
+    read_only_tokens: Vec<String>,
     listener: tokio::net::TcpListener,
+    https_listener: Option<HttpsListener>,
     mainbrain_server_info: BuiServerAddrInfo,
     app_state: BraidAppState,
 ) -> Result<impl futures::Future<Output = Result<()>>> {
@@ -296,6 +375,13 @@ async fn launch_braid_http_backend(
         AccessToken::NoToken => None,
     };
 
+    let access_control = event_stream_types::AccessControl::new(
+        mainbrain_server_info.token(),
+        read_only_tokens,
+        persistent_secret.clone(),
+        "braid-bui-role",
+    );
+
     let cfg = axum_token_auth::AuthConfig {
         token_config,
         persistent_secret,
@@ -318,6 +404,7 @@ async fn launch_braid_http_backend(
     assert_eq!(BRAID_EVENTS_URL_PATH, "braid-events");
     assert_eq!(REMOTE_CAMERA_INFO_PATH, "remote-camera-info");
     assert_eq!(CAM_PROXY_PATH, "cam-proxy");
+    assert_eq!(CAMERA_THUMBNAIL_PATH, "camera-thumbnail");
 
     // Create axum router.
     let router = axum::Router::new()
@@ -326,6 +413,10 @@ async fn launch_braid_http_backend(
             "/remote-camera-info/{encoded_cam_name}",
             get(remote_camera_info_handler),
         )
+        .route(
+            "/camera-thumbnail/{encoded_cam_name}",
+            get(crate::camera_thumbnail::camera_thumbnail_handler),
+        )
         // .route("/cam-proxy/:encoded_cam_name", get(slash_redirect_handler))
         .route(
             "/cam-proxy/{encoded_cam_name}/",
@@ -344,6 +435,12 @@ async fn launch_braid_http_backend(
         .layer(
             tower::ServiceBuilder::new()
                 .layer(TraceLayer::new_for_http())
+                // Determine read-only or control access before the auth layer
+                // checks the token.
+                .layer(axum::middleware::from_fn_with_state(
+                    access_control,
+                    event_stream_types::access_control,
+                ))
                 // Auth layer will produce an error if the request cannot be
                 // authorized so we must handle that.
                 .layer(axum::error_handling::HandleErrorLayer::new(
@@ -353,13 +450,44 @@ async fn launch_braid_http_backend(
         )
         .with_state(app_state);
 
+    let mut urls = mainbrain_server_info.build_urls()?;
+
+    let https_serve_future = match https_listener {
+        Some(HttpsListener { listener, tls }) => {
+            let https_server_info = BuiServerAddrInfo::new(
+                listener.local_addr()?,
+                mainbrain_server_info.token().clone(),
+            );
+            let https_urls: Vec<_> = https_server_info
+                .build_urls()?
+                .iter()
+                .map(https_server::https_uri)
+                .collect();
+            let tls = tls.server_config(https_server::hostnames(&https_urls))?;
+            info!(
+                "Braid HTTPS server listening at {}",
+                https_server_info.addr()
+            );
+            urls.extend(https_urls);
+            Some(https_server::serve(listener, router.clone(), tls))
+        }
+        None => None,
+    };
+
     // create future for our app
     let http_serve_future = {
         use futures::TryFutureExt;
         use std::future::IntoFuture;
-        axum::serve(listener, router)
+        let http_serve_future = axum::serve(listener, router)
             .into_future()
-            .map_err(eyre::Report::from)
+            .map_err(eyre::Report::from);
+        let https_serve_future = async move {
+            if let Some(https_serve_future) = https_serve_future {
+                https_serve_future.await?;
+            }
+            Ok::<_, eyre::Report>(())
+        };
+        futures::future::try_join(http_serve_future, https_serve_future).map_ok(|_| ())
     };
 
     // Display where we are listening.
@@ -368,7 +496,6 @@ async fn launch_braid_http_backend(
         mainbrain_server_info.addr()
     );
 
-    let urls = mainbrain_server_info.build_urls()?;
     for url in urls.iter() {
         info!("Predicted URL: {url}");
         if !flydra_types::is_loopback(url) {
@@ -392,6 +519,24 @@ fn compute_trigger_timestamp(
     }
 }
 
+/// Estimate the synchronized frame number of a frame from its arrival time.
+///
+/// This is the inverse of [compute_trigger_timestamp], where `latency_sec` is
+/// the expected duration from the trigger to the arrival of the frame.
+pub(crate) fn estimate_synced_frame(
+    model: &ClockModel,
+    cam_received_time: &FlydraFloatTimestampLocal<HostClock>,
+    latency_sec: f64,
+) -> Option<SyncFno> {
+    let trigger_time = cam_received_time.as_f64() - latency_sec;
+    let synced_frame = ((trigger_time - model.offset) / model.gain).round();
+    if synced_frame.is_finite() && synced_frame >= 0.0 {
+        Some(SyncFno(synced_frame as u64))
+    } else {
+        None
+    }
+}
+
 struct SendConnectedCamToBuiBackend {
     shared_store: SharedStore,
 }
@@ -503,12 +648,16 @@ pub(crate) async fn do_run_forever(
     software_limit_framerate: flydra_types::StartSoftwareFrameRateLimit,
     saving_program_name: &str,
     listener: tokio::net::TcpListener,
+    https_listener: Option<HttpsListener>,
     mainbrain_server_info: BuiServerAddrInfo,
     mut strand_cam_set: tokio::task::JoinSet<()>,
+    log_handle: env_tracing_logger::LogHandle,
+    rerun: Option<flydra2::RerunTarget>,
 ) -> Result<()> {
     let cal_fname: Option<std::path::PathBuf> = mainbrain_config.cal_fname.clone();
+    let read_only_tokens = mainbrain_config.read_only_tokens.clone();
     let output_base_dirname: std::path::PathBuf = mainbrain_config.output_base_dirname.clone();
-    let tracking_params: flydra_types::TrackingParams = mainbrain_config.tracking_params.clone();
+    let tracking_params: flydra_types::TrackingParams = mainbrain_config.tracking_params();
 
     let lowlatency_camdata_udp_port = &mainbrain_config.lowlatency_camdata_udp_port;
     let mut ensure_camdata_ip = None;
@@ -529,7 +678,46 @@ pub(crate) async fn do_run_forever(
     // Create `stream_cancel::Valve` for shutting everything down. Note this is
     // `Clone`, so we can (and should) shut down everything with it.
     let (quit_trigger, valve) = stream_cancel::Valve::new();
-    let (_shtdwn_q_tx, mut shtdwn_q_rx) = tokio::sync::mpsc::channel::<()>(5);
+    let (shtdwn_q_tx, mut shtdwn_q_rx) = tokio::sync::mpsc::channel::<()>(5);
+
+    {
+        // Shut down nicely upon the first signal and immediately upon the
+        // second.
+        let shtdwn_q_tx = shtdwn_q_tx.clone();
+        tokio::spawn(async move {
+            let name = crate::shutdown::signal().await;
+            info!("Got {name}, shutting down. Repeat to quit immediately.");
+            let _ = shtdwn_q_tx.send(()).await;
+            let name = crate::shutdown::signal().await;
+            tracing::warn!(
+                "Got {name} again, quitting immediately. Data being recorded can be \
+                recovered with `braid recover`."
+            );
+            std::process::exit(1);
+        });
+    }
+
+    let tracking_2d = mainbrain_config.tracking_2d.as_ref();
+    if tracking_2d.is_some() && !tracking_params.is_2d() {
+        eyre::bail!(
+            "With `tracking_2d`, the tracking parameters must not contain \
+            `hypothesis_test_params`."
+        );
+    }
+    if tracking_2d.is_none() && tracking_params.is_2d() {
+        // Such configurations were valid before `tracking_2d` was added.
+        tracing::warn!(
+            "The tracking parameters do not contain `hypothesis_test_params`, \
+            so objects are tracked without triangulation. Set `tracking_2d` to \
+            configure 2D tracking."
+        );
+    }
+    if tracking_2d.is_some() && all_expected_cameras.len() != 1 {
+        eyre::bail!(
+            "`tracking_2d` requires exactly one camera, but {} are configured.",
+            all_expected_cameras.len()
+        );
+    }
 
     let recon = if let Some(ref cal_fname) = cal_fname {
         info!("using calibration: {}", cal_fname.display());
@@ -538,9 +726,43 @@ pub(crate) async fn do_run_forever(
                 format!("loading calibration in file \"{}\"", cal_fname.display())
             })?,
         )
+    } else if let Some(homography) = tracking_2d.and_then(|t| t.homography.as_ref()) {
+        info!("using calibration from homography: {homography:?}");
+        // Checked above that there is exactly one camera.
+        let cam_name = all_expected_cameras.iter().next().unwrap().clone();
+        let h = &homography.image_to_plane;
+        let cal_data = strand_cam_pseudo_cal::HomographyCalibrationData {
+            cam_name,
+            width: homography.width,
+            height: homography.height,
+            image_to_plane: nalgebra::Matrix3::from_row_slice(&h.concat()),
+        };
+        Some(cal_data.to_camera_system()?)
+    } else if let Some(image_circle) = tracking_2d.and_then(|t| t.image_circle.as_ref()) {
+        info!("using calibration from image circle: {image_circle:?}");
+        // Checked above that there is exactly one camera.
+        let cam_name = all_expected_cameras.iter().next().unwrap().clone();
+        let cal_data = strand_cam_pseudo_cal::PseudoCameraCalibrationData {
+            cam_name,
+            width: image_circle.width,
+            height: image_circle.height,
+            physical_diameter_meters: image_circle.physical_diameter_meters,
+            image_circle: http_video_streaming_types::CircleParams {
+                center_x: image_circle.center_x,
+                center_y: image_circle.center_y,
+                radius: image_circle.radius,
+            },
+        };
+        Some(cal_data.to_camera_system()?)
     } else {
         None
     };
+    if tracking_2d.is_some() && recon.is_none() {
+        eyre::bail!(
+            "`tracking_2d` requires `cal_fname`, `tracking_2d.homography` or \
+            `tracking_2d.image_circle`."
+        );
+    }
 
     let signal_all_cams_present = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
     let signal_all_cams_synced = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
@@ -551,6 +773,25 @@ pub(crate) async fn do_run_forever(
         None
     };
 
+    let trigger_offset_estimator = match (
+        mainbrain_config.trigger_offset_estimation_num_frames,
+        &trigger_cfg,
+    ) {
+        (Some(num_frames), TriggerType::TriggerboxV1(_)) => {
+            Some(Arc::new(RwLock::new(flydra2::TriggerOffsetEstimator::new(
+                all_expected_cameras.iter().cloned().collect(),
+                num_frames,
+            ))))
+        }
+        (Some(_), _) => {
+            tracing::warn!(
+                "Trigger offset estimation is only available with the triggerbox. Ignoring."
+            );
+            None
+        }
+        (None, _) => None,
+    };
+
     let mut cam_manager = flydra2::ConnectedCamerasManager::new(
         &recon,
         all_expected_cameras,
@@ -594,59 +835,22 @@ pub(crate) async fn do_run_forever(
         flydra2::BraidMetadataBuilder::saving_program_name(saving_program_name),
     )?;
 
-    // Here is what we do on quit:
-    // 1) Stop saving data, convert .braid dir to .braidz, close files.
-    // 2) Fire a DoQuit message to all cameras and wait for them to quit.
-    // 3) Only then close all our network ports and streams nicely.
-    let mut quit_trigger_container = Some(quit_trigger);
-    let mut strand_cam_http_session_handler2 = strand_cam_http_session_handler.clone();
-    let braidz_write_tx_weak = coord_processor.braidz_write_tx.downgrade();
-    tokio::spawn(async move {
-        while let Some(()) = shtdwn_q_rx.recv().await {
-            debug!("got shutdown command {}:{}", file!(), line!());
-
-            if let Some(braidz_write_tx) = braidz_write_tx_weak.upgrade() {
-                // `braidz_write_tx` will be dropped after this scope.
-
-                // Stop saving Braid data.
-
-                // Do not need to wait for completion because we are going to
-                // exit nicely by manually ending all threads and letting all
-                // drop handlers run (without aborting) and thus the program
-                // will finish writing without an explicit wait. (Of course,
-                // this fails during an actual abort).
-
-                braidz_write_tx
-                    .send(flydra2::SaveToDiskMsg::StopSavingCsv)
-                    .await
-                    .unwrap_or(()); // ignore error on shutdown
-            }
-
-            strand_cam_http_session_handler2.send_quit_all().await;
-
-            // When we get here, we have successfully sent DoQuit to all cams.
-            // We can now quit everything in the mainbrain.
-            if let Some(quit_trigger) = quit_trigger_container.take() {
-                quit_trigger.cancel();
-                break; // no point to listen for more
-            }
-        }
-        debug!("shutdown handler finished {}:{}", file!(), line!());
-    });
-
     let (triggerbox_cmd, triggerbox_rx) = match &trigger_cfg {
         TriggerType::TriggerboxV1(_) => {
             let (tx, rx) = tokio::sync::mpsc::channel(20);
             (Some(tx), Some(rx))
         }
-        TriggerType::FakeSync(_) | TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp => {
-            (None, None)
-        }
+        TriggerType::FakeSync(_)
+        | TriggerType::PtpSync(_)
+        | TriggerType::DeviceTimestamp
+        | TriggerType::SoftwareSync(_) => (None, None),
     };
 
     let needs_clock_model = match &trigger_cfg {
         TriggerType::TriggerboxV1(_) | TriggerType::FakeSync(_) => true,
-        TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp => false,
+        TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp | TriggerType::SoftwareSync(_) => {
+            false
+        }
     };
 
     let sync_pulse_pause_started: Option<std::time::Instant> = None;
@@ -666,6 +870,15 @@ pub(crate) async fn do_run_forever(
         flydra_app_name,
         all_expected_cameras_are_synced: false,
         needs_clock_model,
+        disk_space: None,
+        experiment_metadata: Default::default(),
+        recent_alerts: Vec::new(),
+        log_filter: log_handle.filter(),
+        discovered_cameras: Vec::new(),
+        is_shutting_down: false,
+        camera_groups: crate::camera_params::camera_groups(&camera_configs),
+        camera_param_overrides: Vec::new(),
+        mp4_recording_cameras: Vec::new(),
     };
     let shared_store = ChangeTracker::new(shared);
     let mut shared_store_changes_rx = shared_store.get_changes(1);
@@ -720,6 +933,15 @@ pub(crate) async fn do_run_forever(
 
     let time_model_arc = Arc::new(RwLock::new(None));
 
+    let output_devices = Arc::new(OutputDevices::new(&mainbrain_config.output_devices)?);
+    let sync_markers = Arc::new(SyncMarkers::new(
+        mainbrain_config.sync_markers.clone(),
+        output_devices.clone(),
+        braidz_write_tx_weak.clone(),
+    )?);
+
+    let alerts = Alerts::new(shared_store.clone(), mainbrain_config.notifications.clone())?;
+
     // Create our app state.
     let app_state = BraidAppState {
         shared_store: shared_store.clone(),
@@ -733,10 +955,174 @@ pub(crate) async fn do_run_forever(
         expected_framerate_arc: expected_framerate_arc.clone(),
         braidz_write_tx_weak,
         cam_manager: cam_manager.clone(),
+        time_model_arc: time_model_arc.clone(),
+        trigger_offset_estimator: trigger_offset_estimator.clone(),
         output_base_dirname,
+        mp4_recording_cams: Default::default(),
+        camera_params: Default::default(),
+        shtdwn_q_tx,
         strand_cam_http_session_handler: strand_cam_http_session_handler.clone(),
+        sync_markers: sync_markers.clone(),
+        alerts: alerts.clone(),
+        log_handle,
     };
 
+    // Here is what we do on quit:
+    // 1) Stop saving data: stop MP4 recording on all cameras and wait for
+    //    the files to be finalized, then convert .braid dir to .braidz.
+    // 2) Fire a DoQuit message to all cameras.
+    // 3) Only then close all our network ports and streams nicely.
+    {
+        let app_state = app_state.clone();
+        tokio::spawn(async move {
+            if shtdwn_q_rx.recv().await.is_some() {
+                debug!("got shutdown command {}:{}", file!(), line!());
+                crate::shutdown::finalize_recordings(
+                    &app_state.shared_store,
+                    &app_state.strand_cam_http_session_handler,
+                    &app_state.mp4_recording_cams,
+                    &app_state.braidz_write_tx_weak,
+                )
+                .await;
+
+                let mut strand_cam_http_session_handler =
+                    app_state.strand_cam_http_session_handler.clone();
+                strand_cam_http_session_handler.send_quit_all().await;
+
+                // When we get here, we have successfully sent DoQuit to all
+                // cams. We can now quit everything in the mainbrain.
+                quit_trigger.cancel();
+            }
+            debug!("shutdown handler finished {}:{}", file!(), line!());
+        });
+    }
+
+    {
+        // Periodically check the free space in the output directory and stop
+        // recordings before the disk is full.
+        let disk_space_monitor = disk_space_monitor::DiskSpaceMonitor::new(
+            &app_state.output_base_dirname,
+            mainbrain_config.disk_space_warning_mb,
+            mainbrain_config.disk_space_stop_mb,
+        );
+        let app_state = app_state.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(disk_space_monitor::CHECK_INTERVAL);
+            let mut prev_level = DiskSpaceLevel::Ok;
+            loop {
+                interval.tick().await;
+                let (space, level) = match disk_space_monitor.check() {
+                    Ok(x) => x,
+                    Err(e) => {
+                        error!(
+                            "checking disk space of \"{}\": {e}",
+                            disk_space_monitor.path().display()
+                        );
+                        continue;
+                    }
+                };
+                if level != prev_level && level != DiskSpaceLevel::Ok {
+                    app_state.alerts.raise(
+                        alerts::MAINBRAIN_SOURCE,
+                        "low-disk-space",
+                        format!(
+                            "Low disk space: {} MB available at \"{}\".",
+                            space.available_bytes / 1_000_000,
+                            space.path
+                        ),
+                    );
+                }
+                prev_level = level;
+                let (is_saving_csv, is_saving_mp4) = {
+                    let mut tracker = app_state.shared_store.write().unwrap();
+                    tracker.modify(|shared| shared.disk_space = Some(space));
+                    let shared: &BraidHttpApiSharedState = tracker.as_ref();
+                    (
+                        shared.csv_tables_dirname.is_some(),
+                        shared.fake_mp4_recording_path.is_some(),
+                    )
+                };
+                if level != DiskSpaceLevel::Full {
+                    continue;
+                }
+                if is_saving_csv {
+                    error!("Disk almost full. Stopping saving .braidz file.");
+                    toggle_saving_csv_tables(
+                        false,
+                        app_state.expected_framerate_arc.clone(),
+                        app_state.output_base_dirname.clone(),
+                        app_state.braidz_write_tx_weak.clone(),
+                        app_state.per_cam_data_arc.clone(),
+                        app_state.shared_store.clone(),
+                        &app_state.sync_markers,
+                    )
+                    .await;
+                }
+                if is_saving_mp4 {
+                    error!("Disk almost full. Stopping saving MP4 files.");
+                    if let Err(e) = app_state
+                        .strand_cam_http_session_handler
+                        .toggle_saving_mp4_files_all(false)
+                        .await
+                    {
+                        error!("Error stopping MP4 files: {e}");
+                    }
+                    crate::callback_handling::start_saving_mp4s_all_cams(&app_state, false);
+                }
+            }
+        });
+    }
+
+    {
+        // List the Strand Camera instances advertised on the local network.
+        let app_state = app_state.clone();
+        tokio::spawn(async move {
+            let browser = match mdns_discovery::Browser::new() {
+                Ok(browser) => browser,
+                Err(e) => {
+                    tracing::warn!("Cannot discover cameras by mDNS: {e}");
+                    return;
+                }
+            };
+            let mut found = BTreeMap::new();
+            while let Some(event) = browser.next().await {
+                match event {
+                    mdns_discovery::Event::Found { id, camera } => {
+                        debug!("Discovered camera by mDNS: {camera:?}");
+                        let config =
+                            find_advertised_camera_config(&app_state.camera_configs, &camera.name);
+                        if let Some(config) = config {
+                            if config.start_backend == StartCameraBackend::Remote
+                                && !camera.in_braid
+                            {
+                                info!(
+                                    "Camera \"{}\" is running without Braid at {}. Restart it \
+                                    with the arguments printed at startup to connect it to Braid.",
+                                    config.name,
+                                    camera.urls.join(", ")
+                                );
+                            }
+                        }
+                        let discovered = flydra_types::DiscoveredCamera {
+                            name: camera.name,
+                            urls: camera.urls,
+                            in_braid: camera.in_braid,
+                            config_name: config.map(|config| config.name.clone()),
+                        };
+                        found.insert(id, discovered);
+                    }
+                    mdns_discovery::Event::Lost { id } => {
+                        found.remove(&id);
+                    }
+                }
+                let mut discovered_cameras: Vec<_> = found.values().cloned().collect();
+                discovered_cameras.sort_by(|a, b| a.name.cmp(&b.name));
+                let mut tracker = app_state.shared_store.write().unwrap();
+                tracker.modify(|shared| shared.discovered_cameras = discovered_cameras);
+            }
+        });
+    }
+
     // This future will send state updates to all connected event listeners.
     let event_broadcaster = app_state.event_broadcaster.clone();
     let event_broadcast_fut = async move {
@@ -746,9 +1132,15 @@ pub(crate) async fn do_run_forever(
         }
     };
 
-    let http_serve_future =
-        launch_braid_http_backend(secret_base64, listener, mainbrain_server_info, app_state)
-            .await?;
+    let http_serve_future = launch_braid_http_backend(
+        secret_base64,
+        read_only_tokens,
+        listener,
+        https_listener,
+        mainbrain_server_info,
+        app_state,
+    )
+    .await?;
 
     let signal_triggerbox_connected = Arc::new(AtomicBool::new(false));
 
@@ -844,7 +1236,9 @@ pub(crate) async fn do_run_forever(
                         };
                     });
                 }
-                TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp => {
+                TriggerType::PtpSync(_)
+                | TriggerType::DeviceTimestamp
+                | TriggerType::SoftwareSync(_) => {
                     // no central clock model
                     panic!("No need for clock model.");
                 }
@@ -947,20 +1341,64 @@ pub(crate) async fn do_run_forever(
         TriggerType::DeviceTimestamp => {
             signal_triggerbox_connected.store(true, Ordering::SeqCst);
         }
+        TriggerType::SoftwareSync(swcfg) => {
+            info!(
+                "No triggerbox configuration. Using software synchronization with tolerance {} msec.",
+                swcfg.tolerance_sec() * 1000.0
+            );
+            signal_triggerbox_connected.store(true, Ordering::SeqCst);
+
+            let mut expected_framerate = expected_framerate_arc.write().unwrap();
+            *expected_framerate = Some(swcfg.framerate as f32);
+        }
     };
 
     let expected_framerate_arc9 = expected_framerate_arc.clone();
 
     let live_stats_collector = LiveStatsCollector::new(tracker.clone());
+
+    {
+        // Raise an alert when a camera stops sending data.
+        let live_stats_collector = live_stats_collector.clone();
+        let cam_manager = cam_manager.clone();
+        let alerts = alerts.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+            let mut stalled = std::collections::BTreeSet::new();
+            loop {
+                interval.tick().await;
+                // Cameras which quit are no longer known to the manager.
+                let known = cam_manager.all_raw_cam_names();
+                for (name, elapsed) in live_stats_collector.time_since_last_frame() {
+                    if elapsed < CAMERA_STALL_TIMEOUT {
+                        if stalled.remove(&name) {
+                            info!("Camera \"{}\" is sending data again.", name.as_str());
+                        }
+                    } else if known.contains(&name) && stalled.insert(name.clone()) {
+                        alerts.raise(
+                            name.as_str(),
+                            "camera-stalled",
+                            format!(
+                                "Camera \"{}\" has not sent data for {} seconds.",
+                                name.as_str(),
+                                elapsed.as_secs()
+                            ),
+                        );
+                    }
+                }
+            }
+        });
+    }
     let tracker2 = tracker.clone();
 
-    // decode UDP frames
+    // Receive UDP datagrams. These are decoded in `camera_pipeline`.
     let raw_cam_data_stream =
-        tokio_util::udp::UdpFramed::new(camdata_socket, CborPacketCodec::default());
+        tokio_util::udp::UdpFramed::new(camdata_socket, tokio_util::codec::BytesCodec::new());
 
     // Initiate camera synchronization on startup
     let sync_pulse_pause_started_arc2 = sync_pulse_pause_started_arc.clone();
     let time_model_arc2 = time_model_arc.clone();
+    let trigger_offset_estimator2 = trigger_offset_estimator.clone();
     let cam_manager2 = cam_manager.clone();
     let valve2 = valve.clone();
     let triggerbox_cmd2 = triggerbox_cmd.clone();
@@ -983,6 +1421,7 @@ pub(crate) async fn do_run_forever(
                     sync_pulse_pause_started_arc2.clone(),
                     cam_manager2.clone(),
                     time_model_arc2.clone(),
+                    trigger_offset_estimator2.clone(),
                 )
                 .await
                 .unwrap();
@@ -1015,8 +1454,10 @@ pub(crate) async fn do_run_forever(
     let strand_cam_http_session_handler2 = strand_cam_http_session_handler.clone();
     let cam_manager2 = cam_manager.clone();
     let live_stats_collector2 = live_stats_collector.clone();
+    let braidz_write_tx_weak2 = coord_processor.braidz_write_tx.downgrade();
+    let sync_markers2 = sync_markers.clone();
 
-    let packet_filter = move |r| {
+    let packet_filter = move |packet: flydra_types::FlydraRawUdpPacket| {
         let live_stats_collector2 = live_stats_collector2.clone();
         let trigger_cfg = trigger_cfg.clone();
         let strand_cam_http_session_handler2 = strand_cam_http_session_handler2.clone();
@@ -1028,28 +1469,51 @@ pub(crate) async fn do_run_forever(
         let mut raw_packet_logger =
             RawPacketLogger::new(mainbrain_config.packet_capture_dump_fname.as_deref()).unwrap();
         let time_model_arc = time_model_arc.clone();
+        let trigger_offset_estimator = trigger_offset_estimator.clone();
+        let braidz_write_tx_weak = braidz_write_tx_weak2.clone();
+        let sync_markers = sync_markers2.clone();
         async move {
             // vvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvv
             // Start of closure for on each incoming packet.
 
-            // We run this closure for each incoming packet.
-
-            // Let's be sure about the type of our input.
-            let r: std::result::Result<
-                (flydra_types::FlydraRawUdpPacket, std::net::SocketAddr),
-                std::io::Error,
-            > = r;
-
-            let (packet, _addr) = match r {
-                Ok(r) => r,
-                Err(e) => {
-                    error!("{}", e);
-                    return Some(StreamItem::EOF);
-                }
-            };
+            // We run this closure for each incoming packet. This runs in the
+            // task of the camera which sent the packet (see
+            // `camera_pipeline`), so the closures for different cameras run
+            // in parallel.
 
             let raw_cam_name = RawCamName::new(packet.cam_name.clone());
-            live_stats_collector2.register_new_frame_data(&raw_cam_name, packet.points.len());
+            let n_dropped_transport =
+                live_stats_collector2.register_new_frame_data(&raw_cam_name, &packet);
+            let n_dropped_camera = u64::from(packet.n_frames_skipped);
+            if n_dropped_camera != 0 || n_dropped_transport != 0 {
+                tracing::warn!(
+                    cam = raw_cam_name.as_str(),
+                    frame = packet.framenumber,
+                    "Camera \"{}\": {} frame(s) dropped by camera, {} frame(s) lost in \
+                    transport prior to frame {}.",
+                    raw_cam_name.as_str(),
+                    n_dropped_camera,
+                    n_dropped_transport,
+                    packet.framenumber,
+                );
+                if let Some(braidz_write_tx) = braidz_write_tx_weak.upgrade() {
+                    // `braidz_write_tx` will be dropped after this scope.
+                    let row = flydra_types::FrameDropRow {
+                        cam_id: raw_cam_name.as_str().to_string(),
+                        cam_received_time: packet.cam_received_time.clone(),
+                        framenumber: packet.framenumber,
+                        block_id: packet.block_id,
+                        n_dropped_camera,
+                        n_dropped_transport,
+                    };
+                    if let Err(e) = braidz_write_tx
+                        .send(flydra2::SaveToDiskMsg::FrameDrop(row))
+                        .await
+                    {
+                        error!("Error saving frame drop: {e}");
+                    }
+                }
+            }
 
             // Create closure which is called only if there is a new frame offset
             // (which occurs upon synchronization).
@@ -1070,12 +1534,39 @@ pub(crate) async fn do_run_forever(
                 tokio::spawn(fut_no_err);
             };
 
-            let synced_frame = cam_manager2.got_new_frame_live(
-                &packet,
-                &sync_pulse_pause_started_arc,
-                send_new_frame_offset,
-                &trigger_cfg,
-            );
+            // With the triggerbox, a camera which connected again is
+            // synchronized using the arrival time of its frames.
+            let estimated_synced_frame = match &trigger_cfg {
+                TriggerType::TriggerboxV1(_) | TriggerType::FakeSync(_) => {
+                    let time_model = time_model_arc.read().unwrap();
+                    time_model.as_ref().and_then(|model| {
+                        let latency_sec = trigger_offset_estimator
+                            .as_ref()
+                            .and_then(|estimator| estimator.read().unwrap().median_latency_sec())
+                            .unwrap_or(model.gain / 2.0);
+                        estimate_synced_frame(model, &packet.cam_received_time, latency_sec)
+                    })
+                }
+                _ => None,
+            };
+
+            let synced_frame = {
+                // Include the camera name and frame number in events about
+                // this packet.
+                let _span = tracing::info_span!(
+                    "packet",
+                    cam = raw_cam_name.as_str(),
+                    frame = packet.framenumber
+                )
+                .entered();
+                cam_manager2.got_new_frame_live(
+                    &packet,
+                    &sync_pulse_pause_started_arc,
+                    estimated_synced_frame,
+                    send_new_frame_offset,
+                    &trigger_cfg,
+                )
+            };
 
             let cam_num = cam_manager.cam_num(&raw_cam_name);
 
@@ -1104,10 +1595,25 @@ pub(crate) async fn do_run_forever(
             };
 
             let (synced_frame, trigger_timestamp) = match synced_frame {
-                Some(synced_frame) => {
+                Some(mut synced_frame) => {
                     let trigger_timestamp = match &trigger_cfg {
                         TriggerType::TriggerboxV1(_) | TriggerType::FakeSync(_) => {
                             let time_model = time_model_arc.read().unwrap();
+                            if let (Some(estimator), Some(model)) =
+                                (&trigger_offset_estimator, time_model.as_ref())
+                            {
+                                let mut estimator = estimator.write().unwrap();
+                                if let Some(ts) =
+                                    compute_trigger_timestamp(&time_model, synced_frame)
+                                {
+                                    let latency_sec =
+                                        packet.cam_received_time.as_f64() - ts.as_f64();
+                                    estimator.push(&raw_cam_name, latency_sec, model.gain);
+                                }
+                                // Drop frames which would precede the first
+                                // synchronized frame after correction.
+                                synced_frame = estimator.apply(&raw_cam_name, synced_frame)?;
+                            }
                             compute_trigger_timestamp(&time_model, synced_frame)
                         }
                         TriggerType::PtpSync(_) => {
@@ -1126,6 +1632,9 @@ pub(crate) async fn do_run_forever(
                         TriggerType::DeviceTimestamp => {
                             todo!();
                         }
+                        TriggerType::SoftwareSync(swcfg) => {
+                            Some(cam_manager.software_sync_timestamp(synced_frame, swcfg))
+                        }
                     };
                     (synced_frame, trigger_timestamp)
                 }
@@ -1135,6 +1644,8 @@ pub(crate) async fn do_run_forever(
                 }
             };
 
+            sync_markers.on_synced_frame(synced_frame, trigger_timestamp.as_ref());
+
             let frame_data = flydra2::FrameData::new(
                 raw_cam_name,
                 cam_num,
@@ -1163,7 +1674,16 @@ pub(crate) async fn do_run_forever(
         }
     };
 
-    let flydra2_stream = raw_cam_data_stream.filter_map(packet_filter);
+    let stage_latencies = flydra2::StageLatencies::new();
+    coord_processor.set_stage_latencies(stage_latencies.clone());
+    if let Some(rerun) = &rerun {
+        coord_processor.set_rerun_logger(flydra2::RerunLogger::new(rerun)?)?;
+    }
+    let flydra2_stream = crate::camera_pipeline::spawn_camera_pipeline(
+        raw_cam_data_stream,
+        packet_filter,
+        stage_latencies.clone(),
+    );
 
     let (data_tx, data_rx) = tokio::sync::mpsc::channel(50);
 
@@ -1178,7 +1698,47 @@ pub(crate) async fn do_run_forever(
     let expected_framerate: Option<f32> = *expected_framerate_arc9.read().unwrap();
     info!("expected_framerate: {:?}", expected_framerate);
 
+    {
+        // Periodically report the latency of each stage of processing the
+        // incoming data.
+        let valve2 = valve.clone();
+        tokio::spawn(async move {
+            let interval_stream = tokio_stream::wrappers::IntervalStream::new(
+                tokio::time::interval(STAGE_LATENCY_REPORT_INTERVAL),
+            );
+            let mut interval_stream = valve2.wrap(interval_stream);
+            // The first tick completes immediately.
+            interval_stream.next().await;
+            while let Some(_now) = interval_stream.next().await {
+                for summary in stage_latencies.summary_and_reset() {
+                    debug!("Stage latency {summary}");
+                    let slow = expected_framerate
+                        .map(|fps| summary.p99_usec as f64 > 1e6 / fps as f64)
+                        .unwrap_or(false);
+                    if summary.stage == flydra2::STAGE_TRACK && slow {
+                        tracing::warn!(
+                            "Tracking is slower than the frame rate ({summary}). \
+                            Data will be delayed or dropped."
+                        );
+                    }
+                }
+            }
+        });
+    }
+
     coord_processor.add_listener(data_tx);
+
+    if let Some(closed_loop_cfg) = mainbrain_config.closed_loop.clone() {
+        let closed_loop = crate::closed_loop::ClosedLoop::new(
+            closed_loop_cfg,
+            coord_processor.braidz_write_tx.downgrade(),
+            output_devices,
+        )
+        .await?;
+        let (closed_loop_tx, closed_loop_rx) = tokio::sync::mpsc::channel(50);
+        tokio::spawn(closed_loop.run(closed_loop_rx));
+        coord_processor.add_listener(closed_loop_tx);
+    }
     let coord_proc_fut = coord_processor.consume_stream(flydra2_stream, expected_framerate);
 
     // We "block" (in an async way) here for the entire runtime of the program.
@@ -1216,31 +1776,46 @@ struct LiveStatsCollector {
 #[derive(Debug)]
 struct LiveStatsAccum {
     start: std::time::Instant,
+    last_frame: std::time::Instant,
     n_frames: usize,
     n_points: usize,
+    n_dropped: usize,
+    total_dropped: usize,
+    /// Gaps in the host frame numbers, i.e. frames lost in transport.
+    framenumber_gaps: flydra_types::FrameGapTracker,
 }
 
 impl LiveStatsAccum {
     fn new() -> Self {
         Self {
             start: std::time::Instant::now(),
+            last_frame: std::time::Instant::now(),
             n_frames: 0,
             n_points: 0,
+            n_dropped: 0,
+            total_dropped: 0,
+            framenumber_gaps: Default::default(),
         }
     }
-    fn update(&mut self, n_points: usize) {
+    fn update(&mut self, n_points: usize, n_dropped: usize) {
+        self.last_frame = std::time::Instant::now();
         self.n_frames += 1;
         self.n_points += n_points;
+        self.n_dropped += n_dropped;
+        self.total_dropped += n_dropped;
     }
     fn get_results_and_reset(&mut self) -> flydra_types::RecentStats {
         let recent = flydra_types::RecentStats {
             total_frames_collected: 0,
             frames_collected: self.n_frames,
             points_detected: self.n_points,
+            frames_dropped: self.n_dropped,
+            total_frames_dropped: self.total_dropped,
         };
         self.start = std::time::Instant::now();
         self.n_frames = 0;
         self.n_points = 0;
+        self.n_dropped = 0;
         recent
     }
 }
@@ -1251,20 +1826,35 @@ impl LiveStatsCollector {
         Self { shared, collected }
     }
 
-    fn register_new_frame_data(&self, name: &RawCamName, n_points: usize) {
-        let to_send = {
+    /// Update statistics with a newly received packet.
+    ///
+    /// Returns the number of frames which went missing between the camera
+    /// host and here immediately prior to this packet.
+    fn register_new_frame_data(
+        &self,
+        name: &RawCamName,
+        packet: &flydra_types::FlydraRawUdpPacket,
+    ) -> u64 {
+        let (n_dropped_transport, to_send) = {
             // scope for lock on self.collected
             let mut collected = self.collected.write().unwrap();
             let entry = collected
                 .entry(name.clone())
                 .or_insert_with(LiveStatsAccum::new);
-            entry.update(n_points);
+            let n_dropped_transport = if packet.framenumber >= 0 {
+                entry.framenumber_gaps.update(packet.framenumber as u64)
+            } else {
+                0
+            };
+            let n_dropped = n_dropped_transport + u64::from(packet.n_frames_skipped);
+            entry.update(packet.points.len(), n_dropped as usize);
 
-            if entry.start.elapsed() > std::time::Duration::from_secs(1) {
+            let to_send = if entry.start.elapsed() > std::time::Duration::from_secs(1) {
                 Some((name.clone(), entry.get_results_and_reset()))
             } else {
                 None
-            }
+            };
+            (n_dropped_transport, to_send)
         };
         if let Some((name, recent_stats)) = to_send {
             // scope for shared scope
@@ -1281,6 +1871,16 @@ impl LiveStatsCollector {
                 }
             });
         }
+        n_dropped_transport
+    }
+
+    /// Time since the last packet for each camera which sent data.
+    fn time_since_last_frame(&self) -> Vec<(RawCamName, std::time::Duration)> {
+        let collected = self.collected.read().unwrap();
+        collected
+            .iter()
+            .map(|(name, accum)| (name.clone(), accum.last_frame.elapsed()))
+            .collect()
     }
 }
 
@@ -1291,6 +1891,7 @@ pub(crate) async fn toggle_saving_csv_tables(
     braidz_write_tx_weak: tokio::sync::mpsc::WeakSender<flydra2::SaveToDiskMsg>,
     per_cam_data_arc: Arc<RwLock<BTreeMap<RawCamName, PerCamSaveData>>>,
     shared_data: SharedStore,
+    sync_markers: &SyncMarkers,
 ) {
     if start_saving {
         let expected_framerate: Option<f32> = *expected_framerate_arc.read().unwrap();
@@ -1323,6 +1924,7 @@ pub(crate) async fn toggle_saving_csv_tables(
         } else {
             error!("data writing thread lost. Not saving data as requested");
         }
+        sync_markers.set_recording(true);
 
         {
             let mut tracker = shared_data.write().unwrap();
@@ -1331,6 +1933,7 @@ pub(crate) async fn toggle_saving_csv_tables(
             });
         }
     } else {
+        sync_markers.set_recording(false);
         if let Some(braidz_write_tx) = braidz_write_tx_weak.upgrade() {
             // `braidz_write_tx` will be dropped after this scope.
             braidz_write_tx
@@ -1357,6 +1960,7 @@ async fn synchronize_cameras(
     sync_pulse_pause_started_arc: Arc<RwLock<Option<std::time::Instant>>>,
     mut cam_manager: flydra2::ConnectedCamerasManager,
     time_model_arc: Arc<RwLock<Option<rust_cam_bui_types::ClockModel>>>,
+    trigger_offset_estimator: Option<Arc<RwLock<flydra2::TriggerOffsetEstimator>>>,
 ) -> Result<()> {
     info!("preparing to synchronize cameras");
 
@@ -1374,6 +1978,10 @@ async fn synchronize_cameras(
         *guard = None;
     }
 
+    if let Some(estimator) = &trigger_offset_estimator {
+        estimator.write().unwrap().reset();
+    }
+
     if let Some(tx) = triggerbox_cmd {
         begin_cam_sync_triggerbox_in_process(tx).await?;
     }
@@ -1399,8 +2007,42 @@ async fn begin_cam_sync_triggerbox_in_process(
     Ok(())
 }
 
+/// Find the configuration of the camera advertised by mDNS as `advertised_name`.
+///
+/// As DNS names are case-insensitive, the name in the configuration matches
+/// regardless of case if no camera has exactly this name.
+fn find_advertised_camera_config<'a>(
+    camera_configs: &'a BTreeMap<RawCamName, flydra_types::BraidCameraConfig>,
+    advertised_name: &str,
+) -> Option<&'a flydra_types::BraidCameraConfig> {
+    camera_configs
+        .get(&RawCamName::new(advertised_name.to_string()))
+        .or_else(|| {
+            camera_configs
+                .values()
+                .find(|config| config.name.eq_ignore_ascii_case(advertised_name))
+        })
+}
+
 fn to_event_frame(state: &BraidHttpApiSharedState) -> String {
     let buf = serde_json::to_string(&state).unwrap();
     let frame_string = format!("event: {BRAID_EVENT_NAME}\ndata: {buf}\n\n");
     frame_string
 }
+
+#[test]
+fn test_find_advertised_camera_config() {
+    let camera_configs: BTreeMap<_, _> = ["Basler-22005677", "basler-40022057", "Basler-40022057"]
+        .into_iter()
+        .map(|name| {
+            let config = flydra_types::BraidCameraConfig::default_absdiff_config(name.to_string());
+            (RawCamName::new(name.to_string()), config)
+        })
+        .collect();
+    let find = |name| find_advertised_camera_config(&camera_configs, name).map(|c| c.name.as_str());
+    assert_eq!(find("Basler-22005677"), Some("Basler-22005677"));
+    assert_eq!(find("BASLER-22005677"), Some("Basler-22005677"));
+    assert_eq!(find("Basler-40022057"), Some("Basler-40022057"));
+    assert_eq!(find("basler-40022057"), Some("basler-40022057"));
+    assert_eq!(find("Basler-1"), None);
+}
@@ -107,6 +107,12 @@ impl StrandCamHttpSessionHandler {
         }
     }
 
+    /// Forget the session to a camera, e.g. because the camera restarted and
+    /// the session is no longer valid. A new session is opened when needed.
+    pub(crate) fn forget_session(&self, cam_name: &RawCamName) {
+        self.name_to_session.write().unwrap().remove(cam_name);
+    }
+
     async fn post(
         &self,
         cam_name: &RawCamName,
@@ -248,6 +254,66 @@ impl StrandCamHttpSessionHandler {
         Ok(())
     }
 
+    /// Start MP4 recording on all cameras at `start.start_time`.
+    ///
+    /// If any camera cannot be reached, recording is stopped on all cameras
+    /// so that no partial set of files is recorded.
+    pub(crate) async fn start_synchronized_mp4_all(
+        &self,
+        start: &ci2_remote_control::SynchronizedMp4Start,
+    ) -> MainbrainResult<()> {
+        let cam_names = self.cam_manager.all_raw_cam_names();
+        for cam_name in cam_names.iter() {
+            if let Err(e) = self.start_synchronized_mp4(cam_name, start.clone()).await {
+                error!(
+                    "Starting synchronized MP4 recording failed for camera \"{}\": {e}",
+                    cam_name.as_str()
+                );
+                for cam_name in cam_names.iter() {
+                    if let Err(e) = self.toggle_saving_mp4_files(cam_name, false).await {
+                        warn!(
+                            "Stopping MP4 recording failed for camera \"{}\": {e}",
+                            cam_name.as_str()
+                        );
+                    }
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn start_synchronized_mp4(
+        &self,
+        cam_name: &RawCamName,
+        start: ci2_remote_control::SynchronizedMp4Start,
+    ) -> MainbrainResult<()> {
+        debug!(
+            "for cam {}, sending synchronized mp4 start {:?}",
+            cam_name.as_str(),
+            start
+        );
+        let cam_name = cam_name.clone();
+
+        let args = ci2_remote_control::CamArg::StartSynchronizedMp4(start);
+        self.post(&cam_name, args).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn send_obj_detection_config(
+        &self,
+        cam_name: &RawCamName,
+        cfg: &flydra_feature_detector_types::ImPtDetectCfg,
+    ) -> MainbrainResult<()> {
+        debug!(
+            "for cam {}, sending object detection config",
+            cam_name.as_str()
+        );
+        let cfg_yaml = serde_yaml::to_string(cfg).unwrap();
+        let args = ci2_remote_control::CamArg::SetObjDetectionConfig(cfg_yaml);
+        self.post(cam_name, args).await
+    }
+
     pub(crate) async fn send_clock_model_to_all(
         &self,
         clock_model: Option<rust_cam_bui_types::ClockModel>,
@@ -304,6 +370,104 @@ impl StrandCamHttpSessionHandler {
         Ok(())
     }
 
+    pub(crate) async fn send_experiment_metadata_all(
+        &self,
+        metadata: &rust_cam_bui_types::ExperimentMetadata,
+    ) -> MainbrainResult<()> {
+        let what = "experiment metadata";
+        let to_send = self
+            .cam_manager
+            .all_raw_cam_names()
+            .into_iter()
+            .map(|cam_name| (cam_name, metadata.clone()))
+            .collect();
+        let failed =
+            crate::camera_params::send_each(what, to_send, |cam_name, metadata| async move {
+                self.send_experiment_metadata(&cam_name, metadata).await
+            })
+            .await;
+        if !failed.is_empty() {
+            return Err(MainbrainError::SendFailed {
+                what,
+                cam_names: failed,
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn send_experiment_metadata(
+        &self,
+        cam_name: &RawCamName,
+        metadata: rust_cam_bui_types::ExperimentMetadata,
+    ) -> MainbrainResult<()> {
+        debug!(
+            "for cam {}, sending experiment metadata {:?}",
+            cam_name.as_str(),
+            metadata
+        );
+        let cam_name = cam_name.clone();
+
+        let args = ci2_remote_control::CamArg::SetExperimentMetadata(metadata);
+        self.post(&cam_name, args).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn send_log_filter_all(&self, directives: &str) -> MainbrainResult<()> {
+        let what = "log filter";
+        let to_send = self
+            .cam_manager
+            .all_raw_cam_names()
+            .into_iter()
+            .map(|cam_name| (cam_name, directives.to_string()))
+            .collect();
+        let failed =
+            crate::camera_params::send_each(what, to_send, |cam_name, directives| async move {
+                self.send_log_filter(&cam_name, directives).await
+            })
+            .await;
+        if !failed.is_empty() {
+            return Err(MainbrainError::SendFailed {
+                what,
+                cam_names: failed,
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn send_log_filter(
+        &self,
+        cam_name: &RawCamName,
+        directives: String,
+    ) -> MainbrainResult<()> {
+        debug!(
+            "for cam {}, sending log filter \"{}\"",
+            cam_name.as_str(),
+            directives
+        );
+        let cam_name = cam_name.clone();
+
+        let args = ci2_remote_control::CamArg::SetLogFilter(directives);
+        self.post(&cam_name, args).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn send_camera_params(
+        &self,
+        cam_name: &RawCamName,
+        params: &flydra_types::CameraParams,
+    ) -> MainbrainResult<()> {
+        debug!("for cam {}, sending {:?}", cam_name.as_str(), params);
+        if let Some(exposure_time_usec) = params.exposure_time_usec {
+            let args = ci2_remote_control::CamArg::SetExposureTime(exposure_time_usec);
+            self.post(cam_name, args).await?;
+        }
+        if let Some(gain) = params.gain {
+            let args = ci2_remote_control::CamArg::SetGain(gain);
+            self.post(cam_name, args).await?;
+        }
+        Ok(())
+    }
+
     pub(crate) async fn initiate_post_trigger_mp4_all(&self) -> MainbrainResult<()> {
         let cam_names = self.cam_manager.all_raw_cam_names();
         for cam_name in cam_names.iter() {
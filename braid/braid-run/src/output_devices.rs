@@ -0,0 +1,146 @@
+//! Devices with digital (TTL) outputs to mark events.
+//!
+//! Each kind of device implements [OutputDevice]. The lines are set when
+//! saving of the `.braidz` file starts and stops and by closed-loop actions,
+//! so that these events can be aligned with recordings made by other
+//! equipment.
+
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use tokio::io::AsyncWriteExt;
+use tokio_serial::SerialPortBuilderExt;
+use tracing::{error, info};
+
+use braid_config_data::{OutputDeviceConfig, OutputDeviceKind};
+
+use eyre::{self, Result, WrapErr};
+
+/// A device with digital output lines.
+pub(crate) trait OutputDevice: Send + Sync {
+    /// Set line `line` high or low.
+    ///
+    /// This must not block, as it is called from async code.
+    fn set_line(&self, line: u8, high: bool) -> Result<()>;
+}
+
+const ARDUINO_BOOT_DURATION: Duration = Duration::from_secs(2);
+
+/// An Arduino running the `braid-ttl-output` sketch.
+///
+/// The commands are lines of text: `H<n>` sets pin `n` high and `L<n>` sets it
+/// low.
+struct ArduinoSerial {
+    tx: tokio::sync::mpsc::Sender<String>,
+}
+
+impl ArduinoSerial {
+    fn open(path: &str, baud_rate: u32) -> Result<Self> {
+        info!("opening Arduino output device \"{path}\"");
+        #[allow(unused_mut)]
+        let mut port = tokio_serial::new(path, baud_rate)
+            .open_native_async()
+            .with_context(|| format!("opening Arduino output device \"{path}\""))?;
+        #[cfg(unix)]
+        port.set_exclusive(false)?;
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(100);
+        let path = path.to_string();
+        tokio::spawn(async move {
+            // Opening the port resets the Arduino. Commands sent while it
+            // boots would be lost, so they are queued until then.
+            tokio::time::sleep(ARDUINO_BOOT_DURATION).await;
+            while let Some(cmd) = rx.recv().await {
+                if let Err(e) = port.write_all(cmd.as_bytes()).await {
+                    error!("Failed sending to Arduino output device \"{path}\": {e}");
+                }
+            }
+        });
+        Ok(Self { tx })
+    }
+}
+
+impl OutputDevice for ArduinoSerial {
+    fn set_line(&self, line: u8, high: bool) -> Result<()> {
+        let level = if high { 'H' } else { 'L' };
+        self.tx
+            .try_send(format!("{level}{line}\n"))
+            .map_err(|e| eyre::eyre!("Arduino output device: {e}"))
+    }
+}
+
+struct Device {
+    device: Arc<dyn OutputDevice>,
+    recording_line: Option<u8>,
+}
+
+/// All output devices configured in the mainbrain config.
+pub(crate) struct OutputDevices {
+    devices: BTreeMap<String, Device>,
+}
+
+impl OutputDevices {
+    pub(crate) fn new(cfgs: &[OutputDeviceConfig]) -> Result<Self> {
+        let mut devices = BTreeMap::new();
+        for cfg in cfgs.iter() {
+            let device: Arc<dyn OutputDevice> = match &cfg.device {
+                OutputDeviceKind::ArduinoSerial { path, baud_rate } => {
+                    Arc::new(ArduinoSerial::open(path, *baud_rate)?)
+                }
+            };
+            let dev = Device {
+                device,
+                recording_line: cfg.recording_line,
+            };
+            if devices.insert(cfg.name.clone(), dev).is_some() {
+                eyre::bail!("output device name \"{}\" used more than once", cfg.name);
+            }
+        }
+        let result = Self { devices };
+        // Start in a known state.
+        result.set_recording(false);
+        Ok(result)
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.devices.contains_key(name)
+    }
+
+    /// Set a line of the device named `name`.
+    ///
+    /// If `pulse` is given, the line is set to the opposite level after this
+    /// duration.
+    pub(crate) fn set_line(
+        &self,
+        name: &str,
+        line: u8,
+        high: bool,
+        pulse: Option<Duration>,
+    ) -> Result<()> {
+        let Some(dev) = self.devices.get(name) else {
+            eyre::bail!("unknown output device \"{name}\"");
+        };
+        dev.device.set_line(line, high)?;
+        if let Some(pulse) = pulse {
+            let device = dev.device.clone();
+            let name = name.to_string();
+            tokio::spawn(async move {
+                tokio::time::sleep(pulse).await;
+                if let Err(e) = device.set_line(line, !high) {
+                    error!("output device \"{name}\": {e}");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Set the recording line of each device which has one.
+    pub(crate) fn set_recording(&self, recording: bool) {
+        for (name, dev) in self.devices.iter() {
+            if let Some(line) = dev.recording_line {
+                if let Err(e) = dev.device.set_line(line, recording) {
+                    error!("output device \"{name}\": {e}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Coordinated shutdown of Braid.
+//!
+//! Braid shuts down upon SIGINT (Ctrl-C), SIGTERM or the quit button of the
+//! web UI. First, MP4 recording is stopped on all cameras and Braid waits, with
+//! a timeout, until the cameras report their files finalized. Then the
+//! `.braidz` file is finalized. Only then are the cameras told to quit. A
+//! second signal quits immediately, in which case the data being recorded can
+//! be recovered with `braid recover`.
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use tracing::{error, info, warn};
+
+use flydra_types::RawCamName;
+
+use crate::{mainbrain::SharedStore, multicam_http_session_handler::StrandCamHttpSessionHandler};
+
+/// Maximum duration to wait for the cameras to finalize their MP4 files.
+const MP4_FINALIZE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Interval at which the cameras still recording MP4 files are checked.
+const MP4_FINALIZE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The cameras currently recording MP4 files, as reported by the cameras.
+pub(crate) type Mp4RecordingCams = Arc<RwLock<BTreeSet<RawCamName>>>;
+
+/// Wait for SIGINT (Ctrl-C) or, on unix, SIGTERM. Returns the signal name.
+pub(crate) async fn signal() -> &'static str {
+    if flydra_types::quit_signal().await {
+        "SIGINT"
+    } else {
+        "SIGTERM"
+    }
+}
+
+/// Stop and finalize all recordings.
+///
+/// Returns once the `.braidz` file, if any, is complete.
+pub(crate) async fn finalize_recordings(
+    shared_store: &SharedStore,
+    strand_cam_http_session_handler: &StrandCamHttpSessionHandler,
+    mp4_recording_cams: &Mp4RecordingCams,
+    braidz_write_tx_weak: &tokio::sync::mpsc::WeakSender<flydra2::SaveToDiskMsg>,
+) {
+    {
+        let mut tracker = shared_store.write().unwrap();
+        tracker.modify(|shared| shared.is_shutting_down = true);
+    }
+
+    if !mp4_recording_cams.read().unwrap().is_empty() {
+        info!("Stopping MP4 recording.");
+        if let Err(e) = strand_cam_http_session_handler
+            .toggle_saving_mp4_files_all(false)
+            .await
+        {
+            error!("Error stopping MP4 recording: {e}");
+        }
+        let all_finalized = async {
+            while !mp4_recording_cams.read().unwrap().is_empty() {
+                tokio::time::sleep(MP4_FINALIZE_POLL_INTERVAL).await;
+            }
+        };
+        if tokio::time::timeout(MP4_FINALIZE_TIMEOUT, all_finalized)
+            .await
+            .is_err()
+        {
+            let cam_names = mp4_recording_cams
+                .read()
+                .unwrap()
+                .iter()
+                .map(|x| format!("\"{}\"", x.as_str()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            warn!("Timeout waiting for MP4 files to be finalized by cameras: {cam_names}.");
+        }
+    }
+
+    if let Some(braidz_write_tx) = braidz_write_tx_weak.upgrade() {
+        let is_saving = shared_store
+            .read()
+            .unwrap()
+            .as_ref()
+            .csv_tables_dirname
+            .is_some();
+        if is_saving {
+            info!("Finalizing .braidz file.");
+        }
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let sent = braidz_write_tx
+            .send(flydra2::SaveToDiskMsg::StopSavingCsv)
+            .await
+            .is_ok()
+            && braidz_write_tx
+                .send(flydra2::SaveToDiskMsg::Barrier(tx))
+                .await
+                .is_ok();
+        // The writer replies once the `.braidz` file is complete.
+        if sent && rx.await.is_ok() && is_saving {
+            info!("Done finalizing .braidz file.");
+        }
+    }
+
+    {
+        let mut tracker = shared_store.write().unwrap();
+        tracker.modify(|shared| {
+            shared.csv_tables_dirname = None;
+            shared.fake_mp4_recording_path = None;
+        });
+    }
+}
@@ -0,0 +1,324 @@
+//! Markers for aligning the data with other acquisition systems.
+//!
+//! Markers are emitted, as configured in [SyncMarkerConfig], at regular
+//! synchronized frame numbers and when saving of the `.braidz` file starts and
+//! stops. Each marker is saved in the `textlog` table of the `.braidz` file, so
+//! that it can be found offline in the data of both systems.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tracing::{error, info};
+
+use braid_config_data::{SyncMarkerAction, SyncMarkerConfig};
+use flydra_types::{FlydraFloatTimestampLocal, SyncFno, TextlogRow, Triggerbox};
+
+use eyre::{self, Result};
+
+use crate::output_devices::OutputDevices;
+
+/// How many frames older than the most recent frame a packet may be before the
+/// frame numbers are taken to have restarted after synchronization.
+///
+/// Packets of different cameras arrive slightly out of order, so an older
+/// frame alone does not indicate a restart.
+const MAX_FRAME_REORDER: u64 = 100;
+
+/// The kind of a marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MarkerEvent {
+    Frame,
+    Start,
+    Stop,
+}
+
+impl MarkerEvent {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Frame => "frame",
+            Self::Start => "start",
+            Self::Stop => "stop",
+        }
+    }
+}
+
+#[derive(Default)]
+struct MarkerState {
+    /// The most recent synchronized frame and its trigger timestamp.
+    last_frame: Option<(SyncFno, Option<FlydraFloatTimestampLocal<Triggerbox>>)>,
+    /// The most recent frame number divided by `every_n_frames`.
+    last_period: Option<u64>,
+}
+
+/// Whether a marker is due for `frame`, given the period of the previous
+/// frame. Returns the new period.
+///
+/// A marker is due at the first frame of each period of `every_n_frames`
+/// frames. No marker is emitted for the first frame seen, which need not be
+/// the first of its period.
+fn marker_due(frame: u64, every_n_frames: u64, last_period: Option<u64>) -> (bool, u64) {
+    let period = frame / every_n_frames;
+    let due = matches!(last_period, Some(last) if period > last);
+    (due, period)
+}
+
+/// Replace `{frame}` and `{event}` in a UDP message.
+fn format_message(message: &str, frame: SyncFno, event: MarkerEvent) -> String {
+    message
+        .replace("{frame}", &frame.0.to_string())
+        .replace("{event}", event.as_str())
+}
+
+/// The output devices together with the markers emitted on them.
+pub(crate) struct SyncMarkers {
+    output_devices: Arc<OutputDevices>,
+    cfg: Option<SyncMarkerConfig>,
+    udp_socket: Option<std::net::UdpSocket>,
+    braidz_write_tx_weak: tokio::sync::mpsc::WeakSender<flydra2::SaveToDiskMsg>,
+    state: Mutex<MarkerState>,
+}
+
+impl SyncMarkers {
+    pub(crate) fn new(
+        cfg: Option<SyncMarkerConfig>,
+        output_devices: Arc<OutputDevices>,
+        braidz_write_tx_weak: tokio::sync::mpsc::WeakSender<flydra2::SaveToDiskMsg>,
+    ) -> Result<Self> {
+        let mut udp_socket = None;
+        if let Some(cfg) = cfg.as_ref() {
+            if cfg.every_n_frames == Some(0) {
+                eyre::bail!("sync markers: `every_n_frames` must be larger than 0");
+            }
+            for action in cfg.actions.iter() {
+                match action {
+                    SyncMarkerAction::Ttl { device, .. } => {
+                        if !output_devices.contains(device) {
+                            eyre::bail!("sync markers: unknown output device \"{device}\"");
+                        }
+                    }
+                    SyncMarkerAction::Udp { addr, .. } => {
+                        if udp_socket.is_none() {
+                            let bind_addr = if addr.is_ipv6() {
+                                "[::]:0"
+                            } else {
+                                "0.0.0.0:0"
+                            };
+                            udp_socket = Some(std::net::UdpSocket::bind(bind_addr)?);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            output_devices,
+            cfg,
+            udp_socket,
+            braidz_write_tx_weak,
+            state: Mutex::new(MarkerState::default()),
+        })
+    }
+
+    /// Called for each packet of each camera with its synchronized frame
+    /// number, emitting a marker if one is due.
+    pub(crate) fn on_synced_frame(
+        &self,
+        frame: SyncFno,
+        trigger_timestamp: Option<&FlydraFloatTimestampLocal<Triggerbox>>,
+    ) {
+        let Some(cfg) = self.cfg.as_ref() else {
+            return;
+        };
+        let due = {
+            let mut state = self.state.lock().unwrap();
+            let restarted = state
+                .last_frame
+                .as_ref()
+                .map(|(last, _)| frame.0.saturating_add(MAX_FRAME_REORDER) < last.0)
+                .unwrap_or(false);
+            if restarted {
+                info!("sync markers: frame numbers restarted at {frame}");
+                *state = MarkerState::default();
+            }
+            let is_newer = state
+                .last_frame
+                .as_ref()
+                .map(|(last, _)| frame.0 > last.0)
+                .unwrap_or(true);
+            if is_newer {
+                state.last_frame = Some((frame, trigger_timestamp.cloned()));
+            }
+            match cfg.every_n_frames {
+                Some(every_n_frames) if is_newer => {
+                    let (due, period) = marker_due(frame.0, every_n_frames, state.last_period);
+                    state.last_period = Some(period);
+                    due
+                }
+                _ => false,
+            }
+        };
+        if due {
+            self.emit(MarkerEvent::Frame, frame, trigger_timestamp);
+        }
+    }
+
+    /// Set the recording line of the output devices and, if configured, emit a
+    /// marker for the start or stop of saving.
+    ///
+    /// When starting, call this after saving started, and when stopping,
+    /// before saving stops, so that the marker is saved.
+    pub(crate) fn set_recording(&self, recording: bool) {
+        self.output_devices.set_recording(recording);
+        if !self
+            .cfg
+            .as_ref()
+            .map(|cfg| cfg.on_recording_start_stop)
+            .unwrap_or(false)
+        {
+            return;
+        }
+        let last_frame = self.state.lock().unwrap().last_frame.clone();
+        let Some((frame, trigger_timestamp)) = last_frame else {
+            error!("sync markers: no synchronized frame, not emitting recording marker");
+            return;
+        };
+        let event = if recording {
+            MarkerEvent::Start
+        } else {
+            MarkerEvent::Stop
+        };
+        self.emit(event, frame, trigger_timestamp.as_ref());
+    }
+
+    /// Perform the configured actions for a marker and save it.
+    ///
+    /// This does not block, as it is called for the incoming packets.
+    fn emit(
+        &self,
+        event: MarkerEvent,
+        frame: SyncFno,
+        trigger_timestamp: Option<&FlydraFloatTimestampLocal<Triggerbox>>,
+    ) {
+        // Checked by the caller.
+        let cfg = self.cfg.as_ref().unwrap();
+        for action in cfg.actions.iter() {
+            let result = match action {
+                SyncMarkerAction::Ttl {
+                    device,
+                    line,
+                    pulse_msec,
+                } => self.output_devices.set_line(
+                    device,
+                    *line,
+                    true,
+                    Some(Duration::from_millis(*pulse_msec)),
+                ),
+                SyncMarkerAction::Udp { addr, message } => {
+                    // Checked in `new()`.
+                    let socket = self.udp_socket.as_ref().unwrap();
+                    socket
+                        .send_to(format_message(message, frame, event).as_bytes(), addr)
+                        .map(|_| ())
+                        .map_err(Into::into)
+                }
+            };
+            if let Err(e) = result {
+                error!("sync markers: {e:?}");
+            }
+        }
+
+        let message = format!("sync marker: {} (frame {frame})", event.as_str());
+        if event == MarkerEvent::Frame {
+            tracing::debug!("{message}");
+        } else {
+            info!("{message}");
+        }
+        if let Some(braidz_write_tx) = self.braidz_write_tx_weak.upgrade() {
+            let now = datetime_conversion::datetime_to_f64(&chrono::Utc::now());
+            let host_timestamp = trigger_timestamp.map(|ts| ts.as_f64()).unwrap_or(now);
+            let row = TextlogRow {
+                mainbrain_timestamp: now,
+                cam_id: "mainbrain".to_string(),
+                host_timestamp,
+                message,
+            };
+            match braidz_write_tx.try_send(flydra2::SaveToDiskMsg::Textlog(row)) {
+                Ok(()) => {}
+                Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => {
+                    error!("sync markers: writer busy, marker not saved");
+                }
+                // Ignore error on shutdown.
+                Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => {}
+            }
+        }
+    }
+}
+
+#[test]
+fn test_marker_due() {
+    // The first frame seen only sets the period.
+    assert_eq!(marker_due(1234, 1000, None), (false, 1));
+    assert_eq!(marker_due(1999, 1000, Some(1)), (false, 1));
+    assert_eq!(marker_due(2000, 1000, Some(1)), (true, 2));
+    // If frame 3000 was dropped by all cameras, the marker is at 3001.
+    assert_eq!(marker_due(3001, 1000, Some(2)), (true, 3));
+    // Frame numbers restart after synchronization.
+    assert_eq!(marker_due(5, 1000, Some(3)), (false, 0));
+    assert_eq!(marker_due(1000, 1000, Some(0)), (true, 1));
+
+    assert_eq!(
+        format_message("{event} {frame}", SyncFno(42), MarkerEvent::Start),
+        "start 42"
+    );
+}
+
+#[test]
+fn test_markers_across_resync() -> Result<()> {
+    let cfg = SyncMarkerConfig {
+        every_n_frames: Some(10),
+        on_recording_start_stop: true,
+        actions: vec![],
+    };
+    let (braidz_write_tx, mut braidz_write_rx) = tokio::sync::mpsc::channel(100);
+    let markers = SyncMarkers::new(
+        Some(cfg),
+        Arc::new(OutputDevices::new(&[])?),
+        braidz_write_tx.downgrade(),
+    )?;
+    let mut saved = || {
+        let mut messages = vec![];
+        while let Ok(flydra2::SaveToDiskMsg::Textlog(row)) = braidz_write_rx.try_recv() {
+            messages.push(row.message);
+        }
+        messages
+    };
+
+    for frame in 1000..1025 {
+        // Two cameras, one of which lags behind by a frame.
+        markers.on_synced_frame(SyncFno(frame), None);
+        markers.on_synced_frame(SyncFno(frame - 1), None);
+    }
+    assert_eq!(
+        saved(),
+        vec![
+            "sync marker: frame (frame 1010)",
+            "sync marker: frame (frame 1020)"
+        ]
+    );
+
+    // Synchronization restarts the frame numbers.
+    for frame in 0..25 {
+        markers.on_synced_frame(SyncFno(frame), None);
+    }
+    assert_eq!(
+        saved(),
+        vec![
+            "sync marker: frame (frame 10)",
+            "sync marker: frame (frame 20)"
+        ]
+    );
+    markers.set_recording(true);
+    assert_eq!(saved(), vec!["sync marker: start (frame 24)"]);
+    Ok(())
+}
@@ -0,0 +1,49 @@
+// Reference firmware for Braid output devices of type "ArduinoSerial".
+//
+// Braid sends lines of text over the serial port: "H<n>" sets digital pin <n>
+// high and "L<n>" sets it low. Pins are configured as outputs when first used.
+// The baud rate must match `baud_rate` in the Braid configuration (default
+// 115200).
+
+const unsigned long BAUD_RATE = 115200;
+const int MAX_LINE_LEN = 8;
+
+char line_buf[MAX_LINE_LEN + 1];
+int line_len = 0;
+
+void setup() {
+  Serial.begin(BAUD_RATE);
+}
+
+void handle_line() {
+  if (line_len < 2) {
+    return;
+  }
+  line_buf[line_len] = '\0';
+  int pin = atoi(line_buf + 1);
+  if (pin < 0 || pin >= NUM_DIGITAL_PINS) {
+    return;
+  }
+  switch (line_buf[0]) {
+  case 'H':
+    pinMode(pin, OUTPUT);
+    digitalWrite(pin, HIGH);
+    break;
+  case 'L':
+    pinMode(pin, OUTPUT);
+    digitalWrite(pin, LOW);
+    break;
+  }
+}
+
+void loop() {
+  while (Serial.available() > 0) {
+    char c = Serial.read();
+    if (c == '\n' || c == '\r') {
+      handle_line();
+      line_len = 0;
+    } else if (line_len < MAX_LINE_LEN) {
+      line_buf[line_len++] = c;
+    }
+  }
+}
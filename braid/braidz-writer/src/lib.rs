@@ -1,7 +1,15 @@
 use std::{io::Write, path::Path};
 
+mod repair;
 mod zip_dir;
 
+pub use repair::{repair_dir, RepairedFile};
+
+/// The text at the start of every braidz file, before the ZIP archive.
+const BRAIDZ_HEADER: &str = "BRAIDZ file. This is a standard ZIP file with a \
+                        specific schema. You can view the contents of this \
+                        file at https://braidz.strawlab.org/\n";
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("IO error: {source}")]
@@ -16,17 +24,38 @@ pub enum Error {
     },
 }
 
+/// The options of the files in the ZIP archive.
+fn file_options() -> zip::write::SimpleFileOptions {
+    // Since most of our files are already compressed as .gz files,
+    // we do not bother attempting to compress again. This would
+    // cost significant computation but wouldn't save much space.
+    // (The compressed files should all end with .gz so we could
+    // theoretically compress the uncompressed files by a simple
+    // file name filter. However, the README.md file should ideally
+    // remain uncompressed and as the first file so that inspecting
+    // the braidz file will show this.)
+    zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(true)
+        .unix_permissions(0o755)
+}
+
 // zip the output_dirname directory
+//
+// The zip file is first written with a `.part` suffix and renamed when
+// complete, so that an interrupted conversion does not leave an incomplete
+// file with the final name.
 pub fn dir_to_braidz<P1: AsRef<Path>, P2: AsRef<Path>>(
     output_dirname: P1,
     output_zipfile: P2,
 ) -> Result<(), Error> {
-    let mut file = std::fs::File::create(&output_zipfile)?;
+    let mut part_zipfile = output_zipfile.as_ref().as_os_str().to_owned();
+    part_zipfile.push(".part");
+    let part_zipfile = std::path::PathBuf::from(part_zipfile);
 
-    let header = "BRAIDZ file. This is a standard ZIP file with a \
-                        specific schema. You can view the contents of this \
-                        file at https://braidz.strawlab.org/\n";
-    file.write_all(header.as_bytes())?;
+    let mut file = std::fs::File::create(&part_zipfile)?;
+
+    file.write_all(BRAIDZ_HEADER.as_bytes())?;
 
     let walkdir = walkdir::WalkDir::new(&output_dirname);
 
@@ -48,20 +77,80 @@ pub fn dir_to_braidz<P1: AsRef<Path>, P2: AsRef<Path>>(
     }
 
     let mut zipw = zip::ZipWriter::new(file);
-    // Since most of our files are already compressed as .gz files,
-    // we do not bother attempting to compress again. This would
-    // cost significant computation but wouldn't save much space.
-    // (The compressed files should all end with .gz so we could
-    // theoretically compress the uncompressed files by a simple
-    // file name filter. However, the README.md file should ideally
-    // remain uncompressed and as the first file so that inspecting
-    // the braidz file will show this.)
-    let options = zip::write::SimpleFileOptions::default()
-        .compression_method(zip::CompressionMethod::Stored)
-        .large_file(true)
-        .unix_permissions(0o755);
+    zip_dir::zip_dir(
+        &mut files.into_iter(),
+        &output_dirname,
+        &mut zipw,
+        file_options(),
+    )?;
+    zipw.finish()?.sync_all()?;
+    std::fs::rename(&part_zipfile, output_zipfile)?;
+    Ok(())
+}
+
+/// Add the file `name` with `contents` to the braidz file or unzipped braid
+/// directory at `path`, replacing any existing file of that name.
+///
+/// A braidz file is rewritten with a `.part` suffix and renamed when
+/// complete, so the original is kept if writing fails.
+pub fn add_file_to_braidz<P: AsRef<Path>>(
+    path: P,
+    name: &str,
+    contents: &[u8],
+) -> Result<(), Error> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        std::fs::write(path.join(name), contents)?;
+        return Ok(());
+    }
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+
+    let mut part_zipfile = path.as_os_str().to_owned();
+    part_zipfile.push(".part");
+    let part_zipfile = std::path::PathBuf::from(part_zipfile);
+
+    let mut file = std::fs::File::create(&part_zipfile)?;
+    file.write_all(BRAIDZ_HEADER.as_bytes())?;
+    let mut zipw = zip::ZipWriter::new(file);
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i)?;
+        if entry.name() != name {
+            zipw.raw_copy_file(entry)?;
+        }
+    }
+    zipw.start_file(name, file_options())?;
+    zipw.write_all(contents)?;
+    zipw.finish()?.sync_all()?;
+    std::fs::rename(&part_zipfile, path)?;
+    Ok(())
+}
+
+#[test]
+fn test_add_file_to_braidz() -> anyhow::Result<()> {
+    use std::io::Read;
+
+    let dir = tempfile::tempdir()?;
+    let braid_dir = dir.path().join("test.braid");
+    std::fs::create_dir(&braid_dir)?;
+    std::fs::write(braid_dir.join(flydra_types::README_MD_FNAME), "readme")?;
+    std::fs::write(braid_dir.join("a.csv"), "a\n1\n")?;
+    let braidz = dir.path().join("test.braidz");
+    dir_to_braidz(&braid_dir, &braidz)?;
+
+    add_file_to_braidz(&braidz, "b.csv", b"b\n1\n")?;
+    add_file_to_braidz(&braidz, "b.csv", b"b\n2\n")?;
+
+    assert!(std::fs::read(&braidz)?.starts_with(BRAIDZ_HEADER.as_bytes()));
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&braidz)?)?;
+    let mut names: Vec<_> = archive.file_names().collect();
+    names.sort();
+    assert_eq!(names, vec![flydra_types::README_MD_FNAME, "a.csv", "b.csv"]);
+    let mut contents = String::new();
+    archive.by_name("b.csv")?.read_to_string(&mut contents)?;
+    assert_eq!(contents, "b\n2\n");
 
-    zip_dir::zip_dir(&mut files.into_iter(), &output_dirname, &mut zipw, options)?;
-    zipw.finish()?;
+    add_file_to_braidz(&braid_dir, "b.csv", b"b\n1\n")?;
+    assert_eq!(std::fs::read(braid_dir.join("b.csv"))?, b"b\n1\n");
     Ok(())
 }
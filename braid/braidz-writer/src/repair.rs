@@ -0,0 +1,153 @@
+//! Repair the data directory of a recording which was not finalized.
+//!
+//! While recording, Braid writes to a `.braid` directory which is converted to
+//! a `.braidz` file when recording stops. If Braid does not shut down cleanly,
+//! the directory remains and its CSV files may end with an incomplete row or,
+//! when compressed, with an incomplete gzip stream. [repair_dir] rewrites such
+//! files so that they contain all complete rows and can be read again.
+
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::Error;
+
+/// A file changed by [repair_dir].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairedFile {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The number of (uncompressed) bytes kept.
+    pub kept_bytes: u64,
+    /// The number of (uncompressed) bytes of incomplete rows dropped.
+    pub dropped_bytes: u64,
+}
+
+/// Repair the CSV files in `dir`.
+///
+/// Returns the files which needed repair. Files which are complete are not
+/// changed.
+pub fn repair_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<RepairedFile>, Error> {
+    let mut repaired = Vec::new();
+    for entry in walkdir::WalkDir::new(dir.as_ref())
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy();
+        let result = if name.ends_with(".csv.gz") {
+            repair_csv_gz(path)?
+        } else if name.ends_with(".csv") {
+            repair_csv(path)?
+        } else {
+            None
+        };
+        repaired.extend(result);
+    }
+    Ok(repaired)
+}
+
+/// The length of `buf` up to and including its last newline.
+fn complete_rows_len(buf: &[u8]) -> usize {
+    buf.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1)
+}
+
+fn repair_csv(path: &Path) -> Result<Option<RepairedFile>, Error> {
+    let buf = std::fs::read(path)?;
+    let kept = complete_rows_len(&buf);
+    if kept == buf.len() {
+        return Ok(None);
+    }
+    let fd = std::fs::OpenOptions::new().write(true).open(path)?;
+    fd.set_len(kept as u64)?;
+    Ok(Some(RepairedFile {
+        path: path.to_path_buf(),
+        kept_bytes: kept as u64,
+        dropped_bytes: (buf.len() - kept) as u64,
+    }))
+}
+
+fn repair_csv_gz(path: &Path) -> Result<Option<RepairedFile>, Error> {
+    // Decompress as much as possible. A truncated stream ends with an error.
+    let mut buf = Vec::new();
+    let complete = match libflate::gzip::Decoder::new(std::fs::File::open(path)?) {
+        Ok(mut decoder) => {
+            let mut chunk = vec![0u8; 64 * 1024];
+            loop {
+                match decoder.read(&mut chunk) {
+                    Ok(0) => break true,
+                    Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                    Err(_) => break false,
+                }
+            }
+        }
+        // Not even the gzip header was written.
+        Err(_) => false,
+    };
+    if complete {
+        return Ok(None);
+    }
+
+    let kept = complete_rows_len(&buf);
+
+    // Write the repaired file next to the original and then replace it, so
+    // that the original is kept if writing fails.
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".repaired");
+    let tmp_path = PathBuf::from(tmp_path);
+    {
+        let fd = std::fs::File::create(&tmp_path)?;
+        let mut encoder = libflate::gzip::Encoder::new(fd)?;
+        encoder.write_all(&buf[..kept])?;
+        encoder.finish().into_result()?;
+    }
+    std::fs::rename(&tmp_path, path)?;
+
+    Ok(Some(RepairedFile {
+        path: path.to_path_buf(),
+        kept_bytes: kept as u64,
+        dropped_bytes: (buf.len() - kept) as u64,
+    }))
+}
+
+#[test]
+fn test_repair_dir() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    let rows = "a,b\n1,2\n3,4\n".repeat(1000);
+    let gz_path = dir.path().join("data.csv.gz");
+    {
+        let mut encoder = libflate::gzip::Encoder::new(std::fs::File::create(&gz_path)?)?;
+        encoder.write_all(rows.as_bytes())?;
+        encoder.finish().into_result()?;
+    }
+    let csv_path = dir.path().join("textlog.csv");
+    std::fs::write(&csv_path, "a,b\n1,2\n")?;
+
+    // Complete files are not changed.
+    assert_eq!(repair_dir(dir.path())?, vec![]);
+
+    // Truncate both files as if writing was interrupted.
+    std::fs::write(&csv_path, "a,b\n1,2\n3,")?;
+    let gz_len = std::fs::metadata(&gz_path)?.len();
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(&gz_path)?
+        .set_len(gz_len - 10)?;
+
+    let repaired = repair_dir(dir.path())?;
+    assert_eq!(repaired.len(), 2);
+
+    assert_eq!(std::fs::read_to_string(&csv_path)?, "a,b\n1,2\n");
+
+    let mut decoded = String::new();
+    libflate::gzip::Decoder::new(std::fs::File::open(&gz_path)?)?.read_to_string(&mut decoded)?;
+    assert!(decoded.ends_with('\n'));
+    assert!(rows.starts_with(&decoded));
+
+    // Repairing again changes nothing.
+    assert_eq!(repair_dir(dir.path())?, vec![]);
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use braid::braid_start;
+use clap::Parser;
+use eyre::{Result, WrapErr};
+use std::path::PathBuf;
+
+/// create the .braidz file from the data of a recording which was not
+/// finalized, e.g. because Braid did not shut down cleanly
+#[derive(Debug, Parser)]
+#[command(author, version)]
+struct BraidRecoverCliArgs {
+    /// The `.braid` directory of the recording (or the `.braidz` file to
+    /// recreate)
+    path: PathBuf,
+    /// Remove the `.braid` directory after creating the `.braidz` file
+    #[arg(long)]
+    remove_dir: bool,
+}
+
+fn main() -> Result<()> {
+    braid_start("recover").with_context(|| "launching recover command".to_string())?;
+
+    env_tracing_logger::init();
+
+    let version = format!("{} (git {})", env!("CARGO_PKG_VERSION"), env!("GIT_HASH"));
+    tracing::info!("{} {}", env!("CARGO_PKG_NAME"), version);
+
+    let args = BraidRecoverCliArgs::parse();
+    tracing::debug!("{:?}", args);
+
+    let braid_dir = if args.path.extension().is_some_and(|ext| ext == "braidz") {
+        args.path.with_extension("braid")
+    } else {
+        args.path.clone()
+    };
+    if !braid_dir.is_dir() {
+        eyre::bail!(
+            "Directory \"{}\" not found. Only recordings whose .braid directory remains can be \
+            recovered.",
+            braid_dir.display()
+        );
+    }
+    let braidz_file = braid_dir.with_extension("braidz");
+
+    let repaired = braidz_writer::repair_dir(&braid_dir)
+        .with_context(|| format!("repairing \"{}\"", braid_dir.display()))?;
+    for file in repaired.iter() {
+        tracing::info!(
+            "Repaired \"{}\": kept {} bytes, dropped {} bytes of incomplete rows.",
+            file.path.display(),
+            file.kept_bytes,
+            file.dropped_bytes
+        );
+    }
+
+    if braidz_file.exists() {
+        tracing::warn!(
+            "Replacing \"{}\", which is presumably incomplete.",
+            braidz_file.display()
+        );
+    }
+    braidz_writer::dir_to_braidz(&braid_dir, &braidz_file)
+        .with_context(|| format!("creating \"{}\"", braidz_file.display()))?;
+    tracing::info!("Created \"{}\".", braidz_file.display());
+
+    if args.remove_dir {
+        std::fs::remove_dir_all(&braid_dir)
+            .with_context(|| format!("removing \"{}\"", braid_dir.display()))?;
+    }
+    Ok(())
+}
@@ -4,9 +4,10 @@ use eyre::{WrapErr, Result};
 #[derive(Debug, Parser)]
 #[command(author, version, about)]
 struct BraidLauncherCliArgs {
-    /// Command to execute (e.g. run, show-config, default-config, help)
+    /// Command to execute (e.g. run, retrack, show-config, default-config, help)
     command: String,
     /// Options specific to the command
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     options: Vec<String>,
 }
 
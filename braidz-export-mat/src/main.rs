@@ -0,0 +1,224 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use clap::Parser;
+use eyre::{self as anyhow, WrapErr};
+
+use braidz_types::KalmanEstimatesRow;
+
+mod mat73;
+use mat73::{MatFile, Value};
+
+/// The columns of the `covariance` field of each object.
+const COVARIANCE_COLUMNS: &str = "P00 P01 P02 P11 P12 P22 P33 P44 P55";
+
+/// Export a .braidz file to a MATLAB v7.3 .mat file.
+///
+/// The file contains the variables:
+///
+/// `kalman_estimates`, a struct array with an element per object with the
+/// fields `obj_id`, `frame`, `t` (seconds since the Unix epoch, NaN if
+/// unknown), `x`, `y`, `z`, `vx`, `vy`, `vz` and `covariance`. These are column
+/// vectors with a row per frame, except `obj_id` and `covariance`, which has
+/// the columns listed in `info.covariance_columns`.
+///
+/// `calibration`, if the file has one, a struct with the fields `water` (the
+/// refractive index at z<0, or empty) and `cameras`, a struct array with the
+/// fields `name`, `width`, `height`, `K` (intrinsic matrix), `distortion`
+/// (OpenCV order: k1, k2, p1, p2, k3), `R` (rotation from world to camera
+/// coordinates), `camera_center` and `P` (linear projection matrix).
+///
+/// `info`, a struct with the fields `braidz_filename`, `expected_fps` and
+/// `covariance_columns`.
+#[derive(Debug, Parser)]
+#[command(author, version, verbatim_doc_comment)]
+struct Opt {
+    /// Output .mat filename. Defaults to "<INPUT>.mat"
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Input .braidz filename
+    input: PathBuf,
+}
+
+fn column(rows: &[&KalmanEstimatesRow], f: impl Fn(&KalmanEstimatesRow) -> f64) -> Value {
+    Value::column(rows.iter().map(|r| f(r)).collect())
+}
+
+fn kalman_estimates_value(rows: &[KalmanEstimatesRow]) -> Value {
+    let mut by_obj_id: BTreeMap<u32, Vec<&KalmanEstimatesRow>> = BTreeMap::new();
+    for row in rows.iter() {
+        by_obj_id.entry(row.obj_id).or_default().push(row);
+    }
+    let fields = [
+        "obj_id",
+        "frame",
+        "t",
+        "x",
+        "y",
+        "z",
+        "vx",
+        "vy",
+        "vz",
+        "covariance",
+    ];
+    let elements = by_obj_id
+        .into_iter()
+        .map(|(obj_id, rows)| {
+            let covariance: [fn(&KalmanEstimatesRow) -> f64; 9] = [
+                |r| r.P00,
+                |r| r.P01,
+                |r| r.P02,
+                |r| r.P11,
+                |r| r.P12,
+                |r| r.P22,
+                |r| r.P33,
+                |r| r.P44,
+                |r| r.P55,
+            ];
+            // Column-major, so each column is contiguous.
+            let covariance_data = covariance
+                .iter()
+                .flat_map(|f| rows.iter().map(|r| f(r)))
+                .collect();
+            vec![
+                Value::scalar(obj_id.into()),
+                column(&rows, |r| r.frame.0 as f64),
+                column(&rows, |r| {
+                    r.timestamp.as_ref().map(|t| t.as_f64()).unwrap_or(f64::NAN)
+                }),
+                column(&rows, |r| r.x),
+                column(&rows, |r| r.y),
+                column(&rows, |r| r.z),
+                column(&rows, |r| r.xvel),
+                column(&rows, |r| r.yvel),
+                column(&rows, |r| r.zvel),
+                Value::Double {
+                    rows: rows.len(),
+                    cols: covariance.len(),
+                    data: covariance_data,
+                },
+            ]
+        })
+        .collect();
+    Value::StructArray {
+        fields: fields.iter().map(|f| f.to_string()).collect(),
+        elements,
+    }
+}
+
+/// A matrix value of an nalgebra matrix, which is column-major like MATLAB.
+fn matrix_value<R: nalgebra::Dim, C: nalgebra::Dim, S>(m: &nalgebra::Matrix<f64, R, C, S>) -> Value
+where
+    S: nalgebra::RawStorage<f64, R, C>,
+{
+    Value::Double {
+        rows: m.nrows(),
+        cols: m.ncols(),
+        data: m.iter().copied().collect(),
+    }
+}
+
+fn calibration_value(cal: &braidz_types::CalibrationInfo) -> Value {
+    let fields = [
+        "name",
+        "width",
+        "height",
+        "K",
+        "distortion",
+        "R",
+        "camera_center",
+        "P",
+    ];
+    let elements = cal
+        .cameras
+        .cams()
+        .iter()
+        .map(|(name, cam)| {
+            let intrinsics = cam.intrinsics();
+            let d = &intrinsics.distortion;
+            let distortion = [
+                d.radial1(),
+                d.radial2(),
+                d.tangential1(),
+                d.tangential2(),
+                d.radial3(),
+            ];
+            let rotation = cam.extrinsics().rotation();
+            vec![
+                Value::Char(name.clone()),
+                Value::scalar(cam.width() as f64),
+                Value::scalar(cam.height() as f64),
+                matrix_value(&intrinsics.k),
+                Value::Double {
+                    rows: 1,
+                    cols: distortion.len(),
+                    data: distortion.to_vec(),
+                },
+                matrix_value(rotation.matrix()),
+                matrix_value(cam.extrinsics().camcenter()),
+                matrix_value(cam.linear_part_as_pmat()),
+            ]
+        })
+        .collect();
+    Value::Struct(vec![
+        (
+            "water".into(),
+            cal.water.map(Value::scalar).unwrap_or(Value::Empty),
+        ),
+        (
+            "cameras".into(),
+            Value::StructArray {
+                fields: fields.iter().map(|f| f.to_string()).collect(),
+                elements,
+            },
+        ),
+    ])
+}
+
+fn main() -> anyhow::Result<()> {
+    env_tracing_logger::init();
+    let opt = Opt::parse();
+
+    let archive = braidz_parser::braidz_parse_path(&opt.input)
+        .with_context(|| format!("Parsing file {}", opt.input.display()))?;
+
+    let output = opt.output.unwrap_or_else(|| {
+        let mut output = opt.input.as_os_str().to_owned();
+        output.push(".mat");
+        output.into()
+    });
+
+    let mut mat = MatFile::create(&output)
+        .with_context(|| format!("Creating output file {}", output.display()))?;
+
+    let kalman_estimates = archive
+        .kalman_estimates_table
+        .as_deref()
+        .unwrap_or_default();
+    mat.write(
+        "kalman_estimates",
+        &kalman_estimates_value(kalman_estimates),
+    )?;
+
+    match &archive.calibration_info {
+        Some(cal) => mat.write("calibration", &calibration_value(cal))?,
+        None => tracing::warn!("No calibration in {}", opt.input.display()),
+    }
+
+    let info = Value::Struct(vec![
+        (
+            "braidz_filename".into(),
+            Value::Char(opt.input.display().to_string()),
+        ),
+        ("expected_fps".into(), Value::scalar(archive.expected_fps)),
+        (
+            "covariance_columns".into(),
+            Value::Char(COVARIANCE_COLUMNS.into()),
+        ),
+    ]);
+    mat.write("info", &info)?;
+
+    mat.finish(&output)?;
+    tracing::info!("Saved {}", output.display());
+    Ok(())
+}
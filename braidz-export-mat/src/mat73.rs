@@ -0,0 +1,340 @@
+//! Writing of MATLAB v7.3 .mat files.
+//!
+//! A v7.3 file is an HDF5 file with a 512 byte user block holding the MATLAB
+//! header. Each variable is an HDF5 dataset or group with a `MATLAB_class`
+//! attribute. Arrays are stored column-major, so the HDF5 shape is the
+//! reverse of the MATLAB size. Scalar structs are groups with a dataset or
+//! group per field. In struct arrays, each field is a dataset of object
+//! references to the values of the elements, which are stored in the `#refs#`
+//! group.
+
+use std::{io::Write, path::Path};
+
+use eyre::Result;
+use hdf5::{
+    references::ObjectReference1,
+    types::{FixedAscii, VarLenArray},
+    Group, Location,
+};
+
+const USERBLOCK_SIZE: u64 = 512;
+const HEADER_TEXT_SIZE: usize = 116;
+const REFS_GROUP: &str = "#refs#";
+
+/// A value of a MATLAB variable.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    /// A `rows` by `cols` double matrix, with the data in column-major order.
+    Double {
+        rows: usize,
+        cols: usize,
+        data: Vec<f64>,
+    },
+    /// The empty double matrix `[]`.
+    Empty,
+    Char(String),
+    /// A scalar struct.
+    Struct(Vec<(String, Value)>),
+    /// A 1 by N struct array. All elements have the fields `fields`, in order.
+    StructArray {
+        fields: Vec<String>,
+        elements: Vec<Vec<Value>>,
+    },
+}
+
+impl Value {
+    pub(crate) fn scalar(value: f64) -> Self {
+        Self::Double {
+            rows: 1,
+            cols: 1,
+            data: vec![value],
+        }
+    }
+
+    pub(crate) fn column(data: Vec<f64>) -> Self {
+        Self::Double {
+            rows: data.len(),
+            cols: 1,
+            data,
+        }
+    }
+}
+
+/// A .mat file being written.
+pub(crate) struct MatFile {
+    file: hdf5::File,
+    refs: Option<Group>,
+    n_refs: usize,
+}
+
+impl MatFile {
+    pub(crate) fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = hdf5::File::with_options()
+            .with_fcpl(|fcpl| fcpl.userblock(USERBLOCK_SIZE))
+            .create(path)?;
+        Ok(Self {
+            file,
+            refs: None,
+            n_refs: 0,
+        })
+    }
+
+    /// Write the variable `name`.
+    pub(crate) fn write(&mut self, name: &str, value: &Value) -> Result<()> {
+        let root = self.file.as_group()?;
+        self.write_value(&root, name, value)
+    }
+
+    /// Close the file and write the MATLAB header.
+    pub(crate) fn finish<P: AsRef<Path>>(self, path: P) -> Result<()> {
+        self.file.close()?;
+        let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+        file.write_all(&header(chrono::Local::now()))?;
+        Ok(())
+    }
+
+    fn write_value(&mut self, loc: &Group, name: &str, value: &Value) -> Result<()> {
+        match value {
+            Value::Double { rows, cols, data } => {
+                assert_eq!(rows * cols, data.len());
+                let ds = loc
+                    .new_dataset::<f64>()
+                    .shape((*cols, *rows))
+                    .create(name)?;
+                ds.write_raw(data)?;
+                set_class(&ds, "double")?;
+            }
+            Value::Empty => {
+                write_empty(loc, name, "double")?;
+            }
+            Value::Char(s) => {
+                let data: Vec<u16> = s.encode_utf16().collect();
+                if data.is_empty() {
+                    write_empty(loc, name, "char")?;
+                } else {
+                    let ds = loc
+                        .new_dataset::<u16>()
+                        .shape((data.len(), 1))
+                        .create(name)?;
+                    ds.write_raw(&data)?;
+                    set_class(&ds, "char")?;
+                    ds.new_attr::<i32>()
+                        .shape(())
+                        .create("MATLAB_int_decode")?
+                        .write_scalar(&2)?;
+                }
+            }
+            Value::Struct(fields) => {
+                let group = loc.create_group(name)?;
+                set_class(&group, "struct")?;
+                set_fields(&group, fields.iter().map(|(name, _)| name.as_str()))?;
+                for (field_name, field_value) in fields.iter() {
+                    self.write_value(&group, field_name, field_value)?;
+                }
+            }
+            Value::StructArray { fields, elements } => {
+                if elements.is_empty() {
+                    write_empty(loc, name, "struct")?;
+                    return Ok(());
+                }
+                let group = loc.create_group(name)?;
+                set_class(&group, "struct")?;
+                set_fields(&group, fields.iter().map(String::as_str))?;
+                for (i, field_name) in fields.iter().enumerate() {
+                    let mut refs = Vec::with_capacity(elements.len());
+                    for element in elements.iter() {
+                        refs.push(self.write_ref(&element[i])?);
+                    }
+                    let ds = group
+                        .new_dataset::<ObjectReference1>()
+                        .shape((refs.len(), 1))
+                        .create(field_name.as_str())?;
+                    ds.write_raw(&refs)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write `value` into the `#refs#` group and return a reference to it.
+    fn write_ref(&mut self, value: &Value) -> Result<ObjectReference1> {
+        let refs = match self.refs.take() {
+            Some(refs) => refs,
+            None => self.file.create_group(REFS_GROUP)?,
+        };
+        let name = format!("r{}", self.n_refs);
+        self.n_refs += 1;
+        let result = self.write_value(&refs, &name, value);
+        let reference = result.and_then(|()| Ok(refs.reference::<ObjectReference1>(&name)?));
+        self.refs = Some(refs);
+        reference
+    }
+}
+
+fn set_class(loc: &Location, class: &str) -> Result<()> {
+    set_ascii_attr(loc, "MATLAB_class", class)
+}
+
+/// Set an attribute to a fixed-length ASCII string.
+fn set_ascii_attr(loc: &Location, name: &str, value: &str) -> Result<()> {
+    macro_rules! write_fixed {
+        ($($n:literal),*) => {
+            match value.len() {
+                $($n => {
+                    loc.new_attr::<FixedAscii<$n>>()
+                        .shape(())
+                        .create(name)?
+                        .write_scalar(&FixedAscii::<$n>::from_ascii(value)?)?;
+                })*
+                _ => eyre::bail!("unsupported attribute length {}", value.len()),
+            }
+        };
+    }
+    // The lengths of the MATLAB classes written.
+    write_fixed!(4, 6);
+    Ok(())
+}
+
+/// Set the `MATLAB_fields` attribute, which lists the fields of a struct.
+fn set_fields<'a>(loc: &Location, fields: impl Iterator<Item = &'a str>) -> Result<()> {
+    let fields = fields
+        .map(|field| {
+            let chars = field
+                .chars()
+                .map(|c| FixedAscii::<1>::from_ascii(&c.to_string()))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            Ok(VarLenArray::from_slice(&chars))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    loc.new_attr::<VarLenArray<FixedAscii<1>>>()
+        .shape(fields.len())
+        .create("MATLAB_fields")?
+        .write_raw(&fields)?;
+    Ok(())
+}
+
+/// Write an empty array of `class`, which MATLAB stores as its size.
+fn write_empty(loc: &Group, name: &str, class: &str) -> Result<()> {
+    let ds = loc.new_dataset::<u64>().shape(2).create(name)?;
+    ds.write_raw(&[0u64, 0])?;
+    set_class(&ds, class)?;
+    ds.new_attr::<u8>()
+        .shape(())
+        .create("MATLAB_empty")?
+        .write_scalar(&1)?;
+    Ok(())
+}
+
+/// The first 128 bytes of the user block.
+fn header<TZ: chrono::TimeZone>(created: chrono::DateTime<TZ>) -> [u8; 128]
+where
+    TZ::Offset: std::fmt::Display,
+{
+    let text = format!(
+        "MATLAB 7.3 MAT-file, Platform: {}, Created on: {} HDF5 schema 1.00 .",
+        std::env::consts::OS,
+        created.format("%a %b %e %H:%M:%S %Y"),
+    );
+    let mut buf = [b' '; 128];
+    let n = text.len().min(HEADER_TEXT_SIZE);
+    buf[..n].copy_from_slice(&text.as_bytes()[..n]);
+    // Subsystem data offset (none), version 0x0200 and endian indicator.
+    buf[116..124].fill(0);
+    buf[124..126].copy_from_slice(&0x0200u16.to_le_bytes());
+    buf[126..128].copy_from_slice(b"IM");
+    buf
+}
+
+#[test]
+fn test_header() {
+    use chrono::TimeZone;
+    let created = chrono::Utc.with_ymd_and_hms(2024, 3, 5, 14, 2, 3).unwrap();
+    let buf = header(created);
+    let text = std::str::from_utf8(&buf[..HEADER_TEXT_SIZE]).unwrap();
+    assert!(text.starts_with("MATLAB 7.3 MAT-file, Platform: "));
+    assert!(text.contains("Created on: Tue Mar  5 14:02:03 2024 HDF5 schema 1.00 ."));
+    assert_eq!(&buf[124..], &[0x00, 0x02, b'I', b'M']);
+}
+
+#[cfg(test)]
+fn read_class(loc: &Location) -> Result<String> {
+    let attr = loc.attr("MATLAB_class")?;
+    Ok(match attr.dtype()?.size() {
+        4 => attr.read_scalar::<FixedAscii<4>>()?.to_string(),
+        6 => attr.read_scalar::<FixedAscii<6>>()?.to_string(),
+        n => eyre::bail!("unexpected attribute length {n}"),
+    })
+}
+
+#[test]
+fn test_roundtrip() -> Result<()> {
+    use hdf5::{references::ReferencedObject, Dataset};
+
+    let tmpdir = tempfile::tempdir()?;
+    let path = tmpdir.path().join("test.mat");
+    let mut mat = MatFile::create(&path)?;
+    mat.write(
+        "objs",
+        &Value::StructArray {
+            fields: vec!["obj_id".into(), "x".into()],
+            elements: vec![
+                vec![Value::scalar(1.0), Value::column(vec![0.1, 0.2, 0.3])],
+                vec![Value::scalar(7.0), Value::column(vec![-1.0])],
+            ],
+        },
+    )?;
+    mat.write(
+        "info",
+        &Value::Struct(vec![
+            (
+                "m".into(),
+                Value::Double {
+                    rows: 2,
+                    cols: 3,
+                    data: vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0],
+                },
+            ),
+            ("name".into(), Value::Char("cam1".into())),
+            ("water".into(), Value::Empty),
+        ]),
+    )?;
+    mat.finish(&path)?;
+
+    let buf = std::fs::read(&path)?;
+    assert!(buf.starts_with(b"MATLAB 7.3 MAT-file"));
+    assert_eq!(&buf[124..128], &[0x00, 0x02, b'I', b'M']);
+
+    let file = hdf5::File::open(&path)?;
+    // Matrices are stored column-major with the shape reversed.
+    let m = file.dataset("info/m")?;
+    assert_eq!(m.shape(), vec![3, 2]);
+    assert_eq!(m.read_raw::<f64>()?, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    assert_eq!(read_class(&m)?, "double");
+
+    let name = file.dataset("info/name")?;
+    let name: Vec<u16> = name.read_raw()?;
+    assert_eq!(String::from_utf16(&name)?, "cam1");
+
+    let water = file.dataset("info/water")?;
+    assert_eq!(water.read_raw::<u64>()?, vec![0, 0]);
+    assert_eq!(water.attr("MATLAB_empty")?.read_scalar::<u8>()?, 1);
+    assert_eq!(read_class(&file.group("info")?)?, "struct");
+
+    // The elements of struct arrays are referenced from the `#refs#` group.
+    let deref = |r: &ObjectReference1| -> Result<Dataset> {
+        match file.dereference(r)? {
+            ReferencedObject::Dataset(ds) => Ok(ds),
+            _ => eyre::bail!("expected a dataset"),
+        }
+    };
+    let obj_ids = file
+        .dataset("objs/obj_id")?
+        .read_raw::<ObjectReference1>()?;
+    let xs = file.dataset("objs/x")?.read_raw::<ObjectReference1>()?;
+    assert_eq!(obj_ids.len(), 2);
+    assert_eq!(deref(&obj_ids[0])?.read_raw::<f64>()?, vec![1.0]);
+    assert_eq!(deref(&obj_ids[1])?.read_raw::<f64>()?, vec![7.0]);
+    assert_eq!(deref(&xs[0])?.read_raw::<f64>()?, vec![0.1, 0.2, 0.3]);
+    assert_eq!(deref(&xs[1])?.read_raw::<f64>()?, vec![-1.0]);
+    Ok(())
+}
@@ -4,21 +4,22 @@ use clap::{Parser, ValueEnum};
 use eyre::{self as anyhow, WrapErr};
 use frame_source::{ImageData, Timestamp};
 use mp4_writer::Mp4Writer;
-use mvg::rerun_io::{cam_geom_to_rr_pinhole_archetype as to_pinhole, AsRerunTransform3D};
+use mvg::{
+    rerun_io::{cam_geom_to_rr_pinhole_archetype as to_pinhole, AsRerunTransform3D},
+    undistortion::UndistortionMap,
+};
 use rayon::prelude::*;
 use re_types::{
-    archetypes::{EncodedImage, Pinhole, Points2D, Points3D},
+    archetypes::{EncodedImage, LineStrips3D, Pinhole, Points2D, Points3D},
     components::PinholeProjection,
     datatypes::Mat3x3,
 };
-use std::{collections::BTreeMap, path::PathBuf};
-
-#[cfg(feature = "undistort-images")]
-use crate::undistortion::UndistortionCache;
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
 
-#[cfg(feature = "undistort-images")]
 mod undistortion;
 
+mod tracking_volume;
+
 const SECONDS_TIMELINE: &str = "wall_clock";
 const FRAMES_TIMELINE: &str = "frame";
 const DETECT_NAME: &str = "detect";
@@ -49,6 +50,18 @@ struct Opt {
     #[arg(short, long)]
     export_linearized_mp4s: bool,
 
+    /// Show the original images of cameras with lens distortion.
+    ///
+    /// Rerun cannot model lens distortion, so by default the images and 2D
+    /// points of such cameras are undistorted (linearized). With this option,
+    /// the original images and detected points are shown in the `raw` view of
+    /// the camera, and the undistorted detections are shown in the `lin` view
+    /// together with the reprojections of the tracked objects, which is
+    /// consistent with the 3D view. This is faster as images are not
+    /// undistorted.
+    #[arg(long, conflicts_with = "export_linearized_mp4s")]
+    keep_distorted_images: bool,
+
     /// If exporting MP4 files, which MP4 encoder should be be used?
     #[arg(long, default_value = "less-avc")]
     encoder: Encoder,
@@ -58,14 +71,6 @@ struct Opt {
     version: bool,
 }
 
-#[cfg(feature = "undistort-images")]
-const CAN_UNDISTORT_IMAGES: bool = true;
-#[cfg(not(feature = "undistort-images"))]
-const CAN_UNDISTORT_IMAGES: bool = false;
-
-#[cfg(not(feature = "undistort-images"))]
-struct UndistortionCache {}
-
 #[derive(Clone, Debug)]
 struct CachedCamData {
     /// The rerun entity path for image data
@@ -78,8 +83,9 @@ struct CachedCamData {
     log_undistorted_2d_points: Option<String>,
     /// The camera calibration, if present.
     calibration: Option<mvg::Camera<f64>>,
-    /// non-linear intrinsics, if present
-    nl_intrinsics: Option<opencv_ros_camera::RosOpenCvIntrinsics<f64>>,
+    /// Undistortion of points and images, if the camera has non-linear
+    /// intrinsics.
+    undistortion: Option<Arc<UndistortionMap<f64>>>,
     /// The camera number
     camn: CamNum,
     /// The camera name (also called "cam_id").
@@ -94,7 +100,6 @@ struct OfflineBraidzRerunLogger {
     by_camname: BTreeMap<String, CachedCamData>,
     frametimes: BTreeMap<CamNum, Vec<(i64, f64)>>,
     inter_frame_interval_f64: f64,
-    have_image_data: bool,
     did_show_2499_warning: bool,
     /// Caches the frame number of the last data drawn for a given entity path.
     ///
@@ -104,6 +109,9 @@ struct OfflineBraidzRerunLogger {
     last_data2d: BTreeMap<String, i64>,
     last_frame: Option<i64>,
     last_timestamp: Option<f64>,
+    /// Whether images of cameras with distortion are shown without
+    /// undistortion.
+    keep_distorted_images: bool,
 }
 
 impl OfflineBraidzRerunLogger {
@@ -111,7 +119,7 @@ impl OfflineBraidzRerunLogger {
         rec: re_sdk::RecordingStream,
         camid2camn: BTreeMap<String, CamNum>,
         inter_frame_interval_f64: f64,
-        have_image_data: bool,
+        keep_distorted_images: bool,
     ) -> Self {
         Self {
             rec,
@@ -120,11 +128,11 @@ impl OfflineBraidzRerunLogger {
             by_camname: Default::default(),
             frametimes: Default::default(),
             inter_frame_interval_f64,
-            have_image_data,
             did_show_2499_warning: false,
             last_data2d: Default::default(),
             last_frame: None,
             last_timestamp: None,
+            keep_distorted_images,
         }
     }
 
@@ -147,7 +155,7 @@ impl OfflineBraidzRerunLogger {
                 log_raw_2d_points: Some(raw_path),
                 log_undistorted_2d_points: None,
                 calibration: None,
-                nl_intrinsics: None,
+                undistortion: None,
                 camn: *camn,
                 cam_name: cam_name.clone(),
             };
@@ -182,14 +190,14 @@ impl OfflineBraidzRerunLogger {
                     log_raw_2d_points: Some(raw_path),
                     log_undistorted_2d_points: None,
                     calibration: Some(cam.clone()),
-                    nl_intrinsics: None,
+                    undistortion: None,
                     camn: *camn,
                     cam_name: cam_name.to_string(),
                 }
             }
             Err(mvg::MvgError::RerunUnsupportedIntrinsics) => {
                 let lin_path = format!("{base_path}/lin"); // undistorted = linear
-                if !self.did_show_2499_warning {
+                if !self.did_show_2499_warning && !self.keep_distorted_images {
                     tracing::warn!(
                         "You have one or more cameras with distortion. While \
                         https://github.com/rerun-io/rerun/issues/2499 is not \
@@ -199,43 +207,40 @@ impl OfflineBraidzRerunLogger {
                     self.did_show_2499_warning = true;
                 }
                 let lin_cam = cam.linearize_to_cam_geom();
-                self.rec.log_static(
-                    lin_path.clone(),
-                    &to_pinhole(&lin_cam, cam.width(), cam.height()),
-                )?;
-
-                let use_intrinsics = Some(cam.intrinsics().clone());
-
-                let mut image_ent_path = lin_path.clone();
-                let mut image_is_undistorted = true;
-
-                let log_raw_2d_points = if self.have_image_data && !CAN_UNDISTORT_IMAGES {
-                    // If we cannot undistort the images, also show the original
-                    // image detection coordinates.
-                    tracing::warn!(
-                        "Cannot undistort images for {cam_name}. Logged images will contain \
-                    distortion, but not logging distorted camera models. There will be some \
-                    inconsistencies in the logged data."
-                    );
-                    image_ent_path = raw_path.clone();
-                    image_is_undistorted = false;
-                    Some(raw_path)
+                let lin_pinhole = to_pinhole(&lin_cam, cam.width(), cam.height());
+                self.rec.log_static(lin_path.clone(), &lin_pinhole)?;
+
+                let undistortion = Some(Arc::new(UndistortionMap::new(cam)?));
+
+                if self.keep_distorted_images {
+                    // The linear pinhole is only approximately correct for the
+                    // raw images, but shows them in the 3D view.
+                    self.rec.log_static(raw_path.clone(), &lin_pinhole)?;
+
+                    // Images and points are shown as original in the raw view
+                    // and points are undistorted in the linear view.
+                    CachedCamData {
+                        image_ent_path: raw_path.clone(),
+                        image_is_undistorted: false,
+                        calibration: Some(cam.clone()),
+                        log_raw_2d_points: Some(raw_path),
+                        log_undistorted_2d_points: Some(lin_path),
+                        undistortion,
+                        camn: *camn,
+                        cam_name: cam_name.to_string(),
+                    }
                 } else {
-                    None
-                };
-
-                // Always log the linear (a.k.a. undistorted) points.
-                let log_undistorted_2d_points = Some(lin_path);
-
-                CachedCamData {
-                    image_ent_path,
-                    image_is_undistorted,
-                    calibration: Some(cam.clone()),
-                    log_raw_2d_points,
-                    log_undistorted_2d_points,
-                    nl_intrinsics: use_intrinsics,
-                    camn: *camn,
-                    cam_name: cam_name.to_string(),
+                    // Images and points are undistorted (linearized).
+                    CachedCamData {
+                        image_ent_path: lin_path.clone(),
+                        image_is_undistorted: true,
+                        calibration: Some(cam.clone()),
+                        log_raw_2d_points: None,
+                        log_undistorted_2d_points: Some(lin_path),
+                        undistortion,
+                        camn: *camn,
+                        cam_name: cam_name.to_string(),
+                    }
                 }
             }
             Err(e) => {
@@ -262,24 +267,8 @@ impl OfflineBraidzRerunLogger {
         let camname = camname.unwrap();
         let cam_data = self.by_camname.get(&camname).unwrap();
 
-        let undist_cache = if let Some(intrinsics) = &cam_data.nl_intrinsics {
-            let calibration = cam_data.calibration.as_ref().unwrap();
-            #[cfg(not(feature = "undistort-images"))]
-            {
-                let _ = intrinsics; // silence unused warning.
-                let _ = calibration; // silence unused warning.
-                tracing::error!(
-                    "Support to undistortion images was not compiled. \
-                Images will be distorted but geometry will be linear."
-                );
-                None
-            }
-            #[cfg(feature = "undistort-images")]
-            Some(UndistortionCache::new(
-                intrinsics,
-                calibration.width(),
-                calibration.height(),
-            )?)
+        let undistortion = if cam_data.image_is_undistorted {
+            cam_data.undistortion.as_deref()
         } else {
             None
         };
@@ -322,7 +311,7 @@ impl OfflineBraidzRerunLogger {
                 self.rec.disable_timeline(FRAMES_TIMELINE);
             }
             self.rec.set_time_seconds(SECONDS_TIMELINE, stamp_f64);
-            let (image, decoded) = to_rr_image(frame.into_image(), undist_cache.as_ref())?;
+            let (image, decoded) = to_rr_image(frame.into_image(), undistortion)?;
             self.rec.log(cam_data.image_ent_path.clone(), &image)?;
             if let Some(my_mp4_writer) = my_mp4_writer.as_mut() {
                 my_mp4_writer.write_dynamic(&decoded, stamp_chrono)?;
@@ -376,16 +365,17 @@ impl OfflineBraidzRerunLogger {
             }
         };
 
-        if let (Some(nl_intrinsics), Some(path_base)) = (
-            &cam_data.nl_intrinsics,
+        if let (Some(undistortion), Some(path_base)) = (
+            &cam_data.undistortion,
             cam_data.log_undistorted_2d_points.as_ref(),
         ) {
             let ent_path = format!("{path_base}/{DETECT_NAME}");
             if !row.x.is_nan() {
-                let pt2d = cam_geom::Pixels::new(nalgebra::Vector2::new(row.x, row.y).transpose());
-                let linearized = nl_intrinsics.undistort(&pt2d);
-                let x = linearized.data[0];
-                let y = linearized.data[1];
+                let linearized = undistortion.undistort(&mvg::DistortedPixel {
+                    coords: nalgebra::Point2::new(row.x, row.y),
+                });
+                let x = linearized.coords.x;
+                let y = linearized.coords.y;
                 self.rec
                     .log(ent_path.clone(), &Points2D::new([(x as f32, y as f32)]))?;
                 self.last_data2d.insert(ent_path, row.frame);
@@ -405,6 +395,15 @@ impl OfflineBraidzRerunLogger {
         Ok(())
     }
 
+    fn add_tracking_volume(&self, volume: &flydra_types::TrackingVolume) -> anyhow::Result<()> {
+        let strips = tracking_volume::outline(volume)?;
+        if !strips.is_empty() {
+            self.rec
+                .log_static("world/tracking_volume", &LineStrips3D::new(strips))?;
+        }
+        Ok(())
+    }
+
     fn add_empty3d(&self) -> anyhow::Result<()> {
         // fake 3d data so rerun viewer 0.14 setups up blueprint nicely for us.
         if let (Some(frame), Some(timestamp)) = (&self.last_frame, &self.last_timestamp) {
@@ -447,15 +446,17 @@ impl OfflineBraidzRerunLogger {
                         coords: nalgebra::Point3::new(row.x, row.y, row.z),
                     };
                     if let Some(cam_cal) = cam_cal {
-                        let arch = if cam_data.image_is_undistorted {
-                            let pt2d = cam_cal.project_3d_to_pixel(&pt3d).coords;
-                            Points2D::new([(pt2d[0] as f32, pt2d[1] as f32)])
-                        } else {
+                        // Reproject alongside the detected points.
+                        if let Some(path_base) = &cam_data.log_raw_2d_points {
                             let pt2d = cam_cal.project_3d_to_distorted_pixel(&pt3d).coords;
-                            Points2D::new([(pt2d[0] as f32, pt2d[1] as f32)])
-                        };
-                        let ent_path = &cam_data.image_ent_path;
-                        self.rec.log(format!("{ent_path}/reproj"), &arch)?;
+                            let arch = Points2D::new([(pt2d[0] as f32, pt2d[1] as f32)]);
+                            self.rec.log(format!("{path_base}/reproj"), &arch)?;
+                        }
+                        if let Some(path_base) = &cam_data.log_undistorted_2d_points {
+                            let pt2d = cam_cal.project_3d_to_pixel(&pt3d).coords;
+                            let arch = Points2D::new([(pt2d[0] as f32, pt2d[1] as f32)]);
+                            self.rec.log(format!("{path_base}/reproj"), &arch)?;
+                        }
                     }
                 }
             }
@@ -483,23 +484,15 @@ impl OfflineBraidzRerunLogger {
 
 fn to_rr_image(
     im: ImageData,
-    undist_cache: Option<&UndistortionCache>,
+    undist_map: Option<&UndistortionMap<f64>>,
 ) -> anyhow::Result<(EncodedImage, DynamicFrame)> {
     let decoded = match im {
         ImageData::Decoded(decoded) => decoded,
         _ => anyhow::bail!("image not decoded"),
     };
 
-    let decoded: DynamicFrame = if let Some(undist_cache) = undist_cache {
-        #[cfg(feature = "undistort-images")]
-        {
-            undistortion::undistort_image(decoded, undist_cache)?
-        }
-        #[cfg(not(feature = "undistort-images"))]
-        {
-            let _ = undist_cache; // silence unused variable warning.
-            unreachable!();
-        }
+    let decoded: DynamicFrame = if let Some(undist_map) = undist_map {
+        undistortion::undistort_image(decoded, undist_map)?
     } else {
         decoded
     };
@@ -584,7 +577,7 @@ fn main() -> anyhow::Result<()> {
         rec,
         archive.cam_info.camid2camn.clone(),
         inter_frame_interval_f64,
-        !mp4_inputs.is_empty(),
+        opt.keep_distorted_images,
     );
 
     // Process camera calibrations
@@ -599,6 +592,11 @@ fn main() -> anyhow::Result<()> {
         rrd_logger.add_camera_info(&archive.cam_info)?;
     }
 
+    // Show the volume in which objects were tracked
+    if let Some(kest_info) = &archive.kalman_estimates_info {
+        rrd_logger.add_tracking_volume(&kest_info.tracking_parameters.tracking_volume)?;
+    }
+
     // Process 2D point detections
     for row in archive.iter_data2d_distorted()? {
         let row = row?;
@@ -660,6 +658,7 @@ fn main() -> anyhow::Result<()> {
                 codec,
                 max_framerate: Default::default(),
                 h264_metadata: None,
+                color_encoding: Default::default(),
             };
 
             let my_mp4_writer = mp4_writer::Mp4Writer::new(out_fd, cfg, None).unwrap();
@@ -0,0 +1,74 @@
+use eyre::{self as anyhow};
+use flydra_types::TrackingVolume;
+use nalgebra::Point3;
+
+/// Number of line segments used to draw the circles of a cylinder.
+const CIRCLE_SEGMENTS: usize = 64;
+
+/// Line strips outlining the tracking volume.
+///
+/// Returns no strips for an unbounded volume.
+pub(crate) fn outline(volume: &TrackingVolume) -> anyhow::Result<Vec<Vec<[f32; 3]>>> {
+    let strips = match volume {
+        TrackingVolume::Unbounded => vec![],
+        TrackingVolume::Box { min, max } => {
+            let corner = |x: usize, y: usize| -> [[f32; 3]; 2] {
+                let xv = [min[0], max[0]][x] as f32;
+                let yv = [min[1], max[1]][y] as f32;
+                [[xv, yv, min[2] as f32], [xv, yv, max[2] as f32]]
+            };
+            let corners = [corner(0, 0), corner(1, 0), corner(1, 1), corner(0, 1)];
+            let mut strips: Vec<Vec<[f32; 3]>> = (0..2)
+                .map(|z| (0..5).map(|i| corners[i % 4][z]).collect())
+                .collect();
+            strips.extend(corners.iter().map(|c| c.to_vec()));
+            strips
+        }
+        TrackingVolume::Cylinder {
+            center_x,
+            center_y,
+            radius,
+            z_min,
+            z_max,
+        } => {
+            let point = |i: usize, z: f64| {
+                let theta = i as f64 / CIRCLE_SEGMENTS as f64 * std::f64::consts::TAU;
+                [
+                    (center_x + radius * theta.cos()) as f32,
+                    (center_y + radius * theta.sin()) as f32,
+                    z as f32,
+                ]
+            };
+            let mut strips: Vec<Vec<[f32; 3]>> = [z_min, z_max]
+                .iter()
+                .map(|z| (0..=CIRCLE_SEGMENTS).map(|i| point(i, **z)).collect())
+                .collect();
+            strips.extend((0..4).map(|i| {
+                vec![
+                    point(i * CIRCLE_SEGMENTS / 4, *z_min),
+                    point(i * CIRCLE_SEGMENTS / 4, *z_max),
+                ]
+            }));
+            strips
+        }
+        TrackingVolume::ConvexHull { vertices } => {
+            let points: Vec<Point3<f64>> = vertices.iter().map(|v| Point3::from(*v)).collect();
+            let hull = parry3d_f64::shape::ConvexPolyhedron::from_convex_hull(&points)
+                .ok_or_else(|| anyhow::anyhow!("invalid convex hull for tracking volume"))?;
+            let hull_points = hull.points();
+            hull.edges()
+                .iter()
+                .map(|edge| {
+                    edge.vertices
+                        .iter()
+                        .map(|i| {
+                            let p = &hull_points[*i as usize];
+                            [p.x as f32, p.y as f32, p.z as f32]
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+    };
+    Ok(strips)
+}
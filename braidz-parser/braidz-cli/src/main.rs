@@ -1,29 +1,215 @@
 use anyhow::Context;
-use clap::Parser;
-use std::path::PathBuf;
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+
+use braidz_parser::Severity;
+use braidz_smooth::{SmoothingParams, StitchParams, Trajectory};
+
+mod stats;
+mod tune;
+
+/// Exit code of `validate` if there are warnings, but no errors.
+const EXIT_WARNINGS: i32 = 1;
+/// Exit code of `validate` if there are errors.
+const EXIT_ERRORS: i32 = 2;
+/// Exit code of `validate` if the archive cannot be opened.
+const EXIT_UNREADABLE: i32 = 3;
 
 #[derive(Debug, Parser)]
-#[command(author, version)]
+#[command(
+    author,
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Opt {
     /// Input braidz filename
-    input: PathBuf,
+    #[arg(required = true)]
+    input: Option<PathBuf>,
 
     /// print all data in the `data2d_distorted` table
     #[arg(short, long)]
     data2d_distorted: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Check the internal consistency of a braidz file.
+    ///
+    /// The exit code is 0 if no problems are found, 1 if there are only
+    /// warnings, 2 if there are errors and 3 if the file cannot be opened.
+    Validate {
+        /// Input braidz filename
+        input: PathBuf,
+
+        /// print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Post-process the trajectories of a braidz file and save them as CSV.
+    ///
+    /// Trajectories split into several object IDs are stitched, the Kalman
+    /// estimates are smoothed with a Rauch-Tung-Striebel smoother, filling
+    /// short gaps, and, optionally, resampled to a uniform rate. The output
+    /// has the columns obj_id, frame, timestamp, x, y, z, xvel, yvel and zvel.
+    /// Stitched trajectories have the object ID of their first part.
+    Smooth(SmoothOpt),
+    /// Detect behavioral events in the trajectories of a braidz file.
+    ///
+    /// Takeoffs, landings, saccades and entries into and exits from a volume
+    /// of interest are detected with the thresholds of a TOML configuration
+    /// file. The events are saved as the `behavior_events.csv.gz` table of the
+    /// braidz file and, optionally, as a CSV file.
+    Events(EventsOpt),
+    /// Compute occupancy heatmaps and per-object statistics of a braidz file.
+    ///
+    /// The output directory contains `objects.csv`, with the duration, path
+    /// length and mean speed of each object, `voxels.csv`, with the number of
+    /// samples in each non-empty voxel, the heatmaps `heatmap_xy.png`,
+    /// `heatmap_xz.png` and `heatmap_yz.png` of the projections of the voxel
+    /// counts and, if regions are given, `regions.csv`, with the time each
+    /// object spent in each region.
+    Stats(StatsOpt),
+    /// Estimate the noise parameters of the Kalman filter from a braidz file
+    /// with good tracking.
+    ///
+    /// The motion noise (`motion_noise_scale`) and the observation noise of
+    /// the cameras (`ekf_observation_covariance_pixels`) are estimated by
+    /// autocovariance least squares from the detections associated with each
+    /// object. The tracking parameters of the file, with these values
+    /// replaced, are printed as a TOML block for the Braid configuration file.
+    Tune(TuneOpt),
+}
+
+#[derive(Debug, clap::Args)]
+struct TuneOpt {
+    /// Input braidz filename
+    input: PathBuf,
+
+    /// Shortest run of consecutive frames of an object to use
+    #[arg(long, default_value_t = 10)]
+    min_run_frames: usize,
+}
+
+#[derive(Debug, clap::Args)]
+struct StatsOpt {
+    /// Input braidz filename
+    input: PathBuf,
+
+    /// Output directory. Defaults to "<INPUT>.stats"
+    #[arg(short, long)]
+    output_dir: Option<PathBuf>,
+
+    /// Edge length of the heatmap bins and voxels, in meters
+    #[arg(long, default_value_t = 0.01)]
+    bin_size: f64,
+
+    /// TOML file with regions, as `[[region]]` tables with `name`, `x`, `y`
+    /// and `z`, where each coordinate is given as `[min, max]` in meters
+    #[arg(long)]
+    regions: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct EventsOpt {
+    /// Input braidz filename
+    input: PathBuf,
+
+    /// Configuration TOML filename. The defaults are used if not given
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Also save the events to this CSV filename
+    #[arg(long)]
+    csv: Option<PathBuf>,
+
+    /// Do not add the events to the braidz file
+    #[arg(long)]
+    no_write_archive: bool,
+
+    /// Smooth the trajectories before detecting events
+    #[arg(long)]
+    smooth: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct SmoothOpt {
+    /// Input braidz filename
+    input: PathBuf,
+
+    /// Output CSV filename. Defaults to "<INPUT>.smoothed.csv"
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Do not smooth, only fill gaps by linear interpolation
+    #[arg(long)]
+    no_smooth: bool,
+
+    /// Do not stitch trajectories
+    #[arg(long)]
+    no_stitch: bool,
+
+    /// Resample to this rate, in samples per second
+    #[arg(long)]
+    rate: Option<f64>,
+
+    /// Longest run of missing frames to fill and to stitch across
+    #[arg(long, default_value_t = 10)]
+    max_gap_frames: u64,
+
+    /// Largest distance, in meters, between the predicted end of a trajectory
+    /// and the start of the trajectory stitched to it
+    #[arg(long, default_value_t = 0.05)]
+    stitch_max_distance: f64,
+
+    /// Scale of the state noise covariance of the motion model
+    #[arg(long, default_value_t = 0.1)]
+    motion_noise_scale: f64,
+
+    /// Standard deviation of the saved positions, in meters
+    #[arg(long, default_value_t = 0.01)]
+    observation_std: f64,
+}
+
+/// A row of the output of `smooth`.
+#[derive(serde::Serialize)]
+struct SmoothedRow {
+    obj_id: u32,
+    frame: f64,
+    timestamp: f64,
+    x: f64,
+    y: f64,
+    z: f64,
+    xvel: f64,
+    yvel: f64,
+    zvel: f64,
 }
 
 fn main() -> anyhow::Result<()> {
     env_tracing_logger::init();
     let opt = Opt::parse();
-    let attr = std::fs::metadata(&opt.input)
-        .with_context(|| format!("Getting file metadata for {}", opt.input.display()))?;
+    match opt.command {
+        Some(Command::Validate { input, json }) => {
+            std::process::exit(validate(&input, json)?);
+        }
+        Some(Command::Smooth(smooth_opt)) => return smooth(&smooth_opt),
+        Some(Command::Events(events_opt)) => return events(&events_opt),
+        Some(Command::Stats(stats_opt)) => return stats(&stats_opt),
+        Some(Command::Tune(tune_opt)) => return tune(&tune_opt),
+        None => {}
+    }
+    let input = opt.input.unwrap();
+    let attr = std::fs::metadata(&input)
+        .with_context(|| format!("Getting file metadata for {}", input.display()))?;
 
-    let mut archive = braidz_parser::braidz_parse_path(&opt.input)
-        .with_context(|| format!("Parsing file {}", opt.input.display()))?;
+    let mut archive = braidz_parser::braidz_parse_path(&input)
+        .with_context(|| format!("Parsing file {}", input.display()))?;
 
     let summary =
-        braidz_parser::summarize_braidz(&archive, opt.input.display().to_string(), attr.len());
+        braidz_parser::summarize_braidz(&archive, input.display().to_string(), attr.len());
 
     let yaml_buf = serde_yaml::to_string(&summary)?;
     println!("{}", yaml_buf);
@@ -37,3 +223,259 @@ fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Validate the archive at `input` and print the report, returning the exit
+/// code.
+fn validate(input: &Path, json: bool) -> anyhow::Result<i32> {
+    let report = braidz_parser::braidz_parse_path(input)
+        .with_context(|| format!("Parsing file {}", input.display()))
+        .and_then(|mut archive| Ok(braidz_parser::validate_braidz(&mut archive)?));
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{} cannot be read: {e:?}", input.display());
+            return Ok(EXIT_UNREADABLE);
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for issue in report.issues.iter() {
+            println!("{issue}");
+        }
+        println!(
+            "{}: {} data2d_distorted rows, {} frame gaps explained by frame drops, \
+            {} error(s), {} warning(s)",
+            input.display(),
+            report.num_data2d_rows,
+            report.num_explained_frame_gaps,
+            report.count(Severity::Error),
+            report.count(Severity::Warning),
+        );
+    }
+
+    Ok(match report.max_severity() {
+        None => 0,
+        Some(Severity::Warning) => EXIT_WARNINGS,
+        Some(Severity::Error) => EXIT_ERRORS,
+    })
+}
+
+/// Post-process the trajectories as described in [Command::Smooth].
+fn smooth(opt: &SmoothOpt) -> anyhow::Result<()> {
+    let archive = braidz_parser::braidz_parse_path(&opt.input)
+        .with_context(|| format!("Parsing file {}", opt.input.display()))?;
+    let fps = archive.expected_fps;
+    if fps.is_nan() || fps <= 0.0 {
+        anyhow::bail!("{} has no frame rate", opt.input.display());
+    }
+    if let Some(rate) = opt.rate {
+        if rate.is_nan() || rate <= 0.0 {
+            anyhow::bail!("rate must be positive");
+        }
+    }
+    let rows = archive
+        .kalman_estimates_table
+        .as_deref()
+        .unwrap_or_default();
+
+    let mut trajectories = braidz_smooth::trajectories_from_rows(rows);
+    if !opt.no_stitch {
+        let params = StitchParams {
+            max_gap_frames: opt.max_gap_frames,
+            max_distance_meters: opt.stitch_max_distance,
+        };
+        trajectories = braidz_smooth::stitch(trajectories, fps, &params);
+    }
+    let params = SmoothingParams {
+        motion_noise_scale: opt.motion_noise_scale,
+        observation_std_meters: opt.observation_std,
+        max_gap_frames: opt.max_gap_frames,
+        ..Default::default()
+    };
+    let trajectories: Vec<Trajectory> = trajectories
+        .iter()
+        .map(|t| {
+            let t = if opt.no_smooth {
+                braidz_smooth::interpolate_gaps(t, opt.max_gap_frames)
+            } else {
+                braidz_smooth::smooth(t, fps, &params)
+            };
+            match opt.rate {
+                Some(rate) => braidz_smooth::resample(&t, fps, rate),
+                None => t,
+            }
+        })
+        .collect();
+
+    let output = opt.output.clone().unwrap_or_else(|| {
+        let mut output = opt.input.as_os_str().to_owned();
+        output.push(".smoothed.csv");
+        output.into()
+    });
+    let mut wtr = csv::Writer::from_path(&output)
+        .with_context(|| format!("Creating output file {}", output.display()))?;
+    let mut n_stitched = 0;
+    for trajectory in trajectories.iter() {
+        n_stitched += trajectory.stitched_obj_ids.len();
+        for s in trajectory.samples.iter() {
+            wtr.serialize(SmoothedRow {
+                obj_id: trajectory.obj_id,
+                frame: s.frame,
+                timestamp: s.timestamp,
+                x: s.position.x,
+                y: s.position.y,
+                z: s.position.z,
+                xvel: s.velocity.x,
+                yvel: s.velocity.y,
+                zvel: s.velocity.z,
+            })?;
+        }
+    }
+    wtr.flush()?;
+    println!(
+        "Saved {} trajectories ({} object IDs stitched into others) to {}",
+        trajectories.len(),
+        n_stitched,
+        output.display()
+    );
+    Ok(())
+}
+
+/// Detect behavioral events as described in [Command::Events].
+fn events(opt: &EventsOpt) -> anyhow::Result<()> {
+    let config: braidz_events::EventConfig = match &opt.config {
+        Some(path) => {
+            let buf = std::fs::read_to_string(path)
+                .with_context(|| format!("Reading config file {}", path.display()))?;
+            toml::from_str(&buf)
+                .with_context(|| format!("Parsing config file {}", path.display()))?
+        }
+        None => Default::default(),
+    };
+
+    let events = {
+        let archive = braidz_parser::braidz_parse_path(&opt.input)
+            .with_context(|| format!("Parsing file {}", opt.input.display()))?;
+        let fps = archive.expected_fps;
+        if fps.is_nan() || fps <= 0.0 {
+            anyhow::bail!("{} has no frame rate", opt.input.display());
+        }
+        let rows = archive
+            .kalman_estimates_table
+            .as_deref()
+            .unwrap_or_default();
+        let mut trajectories = braidz_smooth::trajectories_from_rows(rows);
+        if opt.smooth {
+            let params = SmoothingParams::default();
+            trajectories = trajectories
+                .iter()
+                .map(|t| braidz_smooth::smooth(t, fps, &params))
+                .collect();
+        }
+        braidz_events::detect_all_events(&trajectories, fps, &config)
+    };
+
+    if let Some(csv_path) = &opt.csv {
+        let fd = std::fs::File::create(csv_path)
+            .with_context(|| format!("Creating output file {}", csv_path.display()))?;
+        braidz_events::write_events_csv(&events, fd)?;
+    }
+    if !opt.no_write_archive {
+        let name = format!("{}.gz", braidz_events::BEHAVIOR_EVENTS_CSV_FNAME);
+        braidz_writer::add_file_to_braidz(
+            &opt.input,
+            &name,
+            &braidz_events::events_csv_gz(&events)?,
+        )
+        .with_context(|| format!("Adding {name} to {}", opt.input.display()))?;
+    }
+    println!(
+        "Detected {} events in {}",
+        events.len(),
+        opt.input.display()
+    );
+    Ok(())
+}
+
+/// Compute the statistics described in [Command::Stats].
+fn stats(opt: &StatsOpt) -> anyhow::Result<()> {
+    if opt.bin_size.is_nan() || opt.bin_size <= 0.0 {
+        anyhow::bail!("bin size must be positive");
+    }
+    let regions: stats::RegionsConfig = match &opt.regions {
+        Some(path) => {
+            let buf = std::fs::read_to_string(path)
+                .with_context(|| format!("Reading regions file {}", path.display()))?;
+            toml::from_str(&buf)
+                .with_context(|| format!("Parsing regions file {}", path.display()))?
+        }
+        None => Default::default(),
+    };
+
+    let archive = braidz_parser::braidz_parse_path(&opt.input)
+        .with_context(|| format!("Parsing file {}", opt.input.display()))?;
+    let fps = archive.expected_fps;
+    if fps.is_nan() || fps <= 0.0 {
+        anyhow::bail!("{} has no frame rate", opt.input.display());
+    }
+    let rows = archive
+        .kalman_estimates_table
+        .as_deref()
+        .unwrap_or_default();
+    let trajectories = braidz_smooth::trajectories_from_rows(rows);
+
+    let output_dir = opt.output_dir.clone().unwrap_or_else(|| {
+        let mut output_dir = opt.input.as_os_str().to_owned();
+        output_dir.push(".stats");
+        output_dir.into()
+    });
+    for path in stats::write_stats(&trajectories, fps, opt.bin_size, &regions, &output_dir)? {
+        println!("Saved {}", path.display());
+    }
+    Ok(())
+}
+
+/// Estimate the tracking parameters as described in [Command::Tune].
+fn tune(opt: &TuneOpt) -> anyhow::Result<()> {
+    if opt.min_run_frames < 5 {
+        anyhow::bail!("min-run-frames must be at least 5");
+    }
+    let mut archive = braidz_parser::braidz_parse_path(&opt.input)
+        .with_context(|| format!("Parsing file {}", opt.input.display()))?;
+    let fps = archive.expected_fps;
+    if fps.is_nan() || fps <= 0.0 {
+        anyhow::bail!("{} has no frame rate", opt.input.display());
+    }
+    let params = archive
+        .kalman_estimates_info
+        .as_ref()
+        .map(|info| info.tracking_parameters.clone())
+        .unwrap_or_else(flydra_types::default_tracking_params_full_3d);
+
+    let estimate = tune::estimate_noise(&mut archive, opt.min_run_frames)
+        .with_context(|| format!("Estimating noise of {}", opt.input.display()))?;
+
+    // The summary goes to stderr so that stdout is only the TOML block.
+    for cam in estimate.cameras.iter() {
+        eprintln!(
+            "{}: observation covariance {:.3} pixels² from {} samples",
+            cam.cam_name, cam.covariance_pixels, cam.num_samples
+        );
+    }
+    match (estimate.motion_noise_scale, estimate.position_std_meters) {
+        (Some(q), Some(std)) => eprintln!(
+            "3D: motion noise scale {q:.4} m²/s³, position std {:.2} mm from {} samples",
+            std * 1000.0,
+            estimate.num_samples_3d
+        ),
+        _ => eprintln!("3D: not enough triangulated positions, keeping motion_noise_scale"),
+    }
+    if estimate.cameras.is_empty() {
+        eprintln!("2D: not enough detections, keeping ekf_observation_covariance_pixels");
+    }
+
+    print!("{}", tune::tracking_params_toml(&estimate.apply(&params))?);
+    Ok(())
+}
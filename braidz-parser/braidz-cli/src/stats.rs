@@ -0,0 +1,347 @@
+//! Occupancy heatmaps, per-object statistics and time in regions.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use braidz_smooth::{Sample, Trajectory};
+
+/// A user-defined axis-aligned region, with the `[min, max]` of each
+/// coordinate in meters.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Region {
+    name: String,
+    x: [f64; 2],
+    y: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Region {
+    fn contains(&self, p: &[f64; 3]) -> bool {
+        [(p[0], self.x), (p[1], self.y), (p[2], self.z)]
+            .iter()
+            .all(|(v, [min, max])| min <= v && v <= max)
+    }
+}
+
+/// The contents of the regions TOML file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RegionsConfig {
+    #[serde(default)]
+    region: Vec<Region>,
+}
+
+/// A row of `objects.csv`.
+#[derive(Debug, Serialize)]
+struct ObjectStats {
+    obj_id: u32,
+    first_frame: u64,
+    last_frame: u64,
+    num_frames: usize,
+    duration_sec: f64,
+    path_length_meters: f64,
+    mean_speed_meters_per_sec: f64,
+}
+
+impl ObjectStats {
+    fn new(trajectory: &Trajectory, fps: f64) -> Self {
+        let first_frame = trajectory.samples[0].frame as u64;
+        let last_frame = trajectory.samples.last().unwrap().frame as u64;
+        let positions: Vec<_> = trajectory.samples.iter().filter_map(position).collect();
+        let path_length_meters = positions
+            .windows(2)
+            .map(|pair| {
+                let d: f64 = (0..3).map(|i| (pair[1][i] - pair[0][i]).powi(2)).sum();
+                d.sqrt()
+            })
+            .sum();
+        let duration_sec = (last_frame - first_frame) as f64 / fps;
+        let mean_speed_meters_per_sec = if duration_sec > 0.0 {
+            path_length_meters / duration_sec
+        } else {
+            f64::NAN
+        };
+        Self {
+            obj_id: trajectory.obj_id,
+            first_frame,
+            last_frame,
+            num_frames: trajectory.samples.len(),
+            duration_sec,
+            path_length_meters,
+            mean_speed_meters_per_sec,
+        }
+    }
+}
+
+/// A row of `regions.csv`.
+#[derive(Debug, Serialize)]
+struct RegionStats<'a> {
+    obj_id: u32,
+    region: &'a str,
+    num_frames: usize,
+    time_sec: f64,
+}
+
+/// A row of `voxels.csv`.
+#[derive(Debug, Serialize)]
+struct VoxelRow {
+    x: f64,
+    y: f64,
+    z: f64,
+    count: u64,
+}
+
+/// The position of a sample, if finite.
+fn position(sample: &Sample) -> Option<[f64; 3]> {
+    let p = [sample.position.x, sample.position.y, sample.position.z];
+    p.iter().all(|v| v.is_finite()).then_some(p)
+}
+
+/// Maximum width or height of a heatmap image, in pixels.
+const MAX_HEATMAP_SIZE: usize = 10_000;
+
+/// Counts of positions in cubic voxels of `bin_size` meters.
+///
+/// Only the non-empty voxels are stored, so that a few distant positions do
+/// not require memory for the whole bounding box.
+struct Occupancy {
+    bin_size: f64,
+    /// The voxel index of the lowest x, y and z.
+    origin: [i64; 3],
+    shape: [usize; 3],
+    /// The non-zero counts, indexed by x, then y, then z relative to `origin`.
+    counts: BTreeMap<[usize; 3], u64>,
+}
+
+impl Occupancy {
+    fn new(positions: &[[f64; 3]], bin_size: f64) -> Self {
+        let index = |v: f64| (v / bin_size).floor() as i64;
+        let mut min = [i64::MAX; 3];
+        let mut max = [i64::MIN; 3];
+        for p in positions.iter() {
+            let idx = p.map(index);
+            min = [0, 1, 2].map(|i| min[i].min(idx[i]));
+            max = [0, 1, 2].map(|i| max[i].max(idx[i]));
+        }
+        let (min, shape) = if positions.is_empty() {
+            ([0; 3], [0; 3])
+        } else {
+            let shape = [0, 1, 2].map(|i| max[i].abs_diff(min[i]).saturating_add(1) as usize);
+            (min, shape)
+        };
+        let mut counts = BTreeMap::new();
+        for p in positions.iter() {
+            let idx = [0, 1, 2].map(|i| index(p[i]).abs_diff(min[i]) as usize);
+            *counts.entry(idx).or_default() += 1;
+        }
+        Self {
+            bin_size,
+            origin: min,
+            shape,
+            counts,
+        }
+    }
+
+    /// The center of a voxel, in meters.
+    fn center(&self, axis: usize, idx: usize) -> f64 {
+        ((self.origin[axis] + idx as i64) as f64 + 0.5) * self.bin_size
+    }
+
+    /// The non-empty voxels.
+    fn voxels(&self) -> Vec<VoxelRow> {
+        self.counts
+            .iter()
+            .map(|(&[ix, iy, iz], &count)| VoxelRow {
+                x: self.center(0, ix),
+                y: self.center(1, iy),
+                z: self.center(2, iz),
+                count,
+            })
+            .collect()
+    }
+
+    /// The counts summed along the axis other than `axes`, as rows of the
+    /// second axis (from high to low values) and columns of the first.
+    ///
+    /// Returns an error if the image would be larger than
+    /// [MAX_HEATMAP_SIZE] in either dimension.
+    fn projection(&self, axes: [usize; 2]) -> anyhow::Result<(usize, usize, Vec<u64>)> {
+        let (w, h) = (self.shape[axes[0]], self.shape[axes[1]]);
+        if w > MAX_HEATMAP_SIZE || h > MAX_HEATMAP_SIZE {
+            anyhow::bail!(
+                "Heatmap of {w}x{h} pixels exceeds the maximum of {MAX_HEATMAP_SIZE} \
+                pixels per side. Use a larger bin size."
+            );
+        }
+        let mut result = vec![0; w * h];
+        for (idx, count) in self.counts.iter() {
+            let (col, row) = (idx[axes[0]], h - 1 - idx[axes[1]]);
+            result[row * w + col] += count;
+        }
+        Ok((w, h, result))
+    }
+}
+
+/// Render counts as a grayscale image, with a logarithmic scale from black
+/// (zero) to white (the maximum).
+fn render_heatmap(w: usize, h: usize, counts: &[u64]) -> image::GrayImage {
+    let max = counts.iter().copied().max().unwrap_or(0);
+    let scale = ((max + 1) as f64).ln();
+    let pixels = counts
+        .iter()
+        .map(|&c| {
+            if max == 0 {
+                0
+            } else {
+                (255.0 * ((c + 1) as f64).ln() / scale).round() as u8
+            }
+        })
+        .collect();
+    image::GrayImage::from_raw(w as u32, h as u32, pixels).unwrap()
+}
+
+fn write_csv<T: Serialize>(path: &Path, rows: impl IntoIterator<Item = T>) -> anyhow::Result<()> {
+    let mut wtr = csv::Writer::from_path(path)
+        .with_context(|| format!("Creating output file {}", path.display()))?;
+    for row in rows {
+        wtr.serialize(row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Compute the statistics of `trajectories` recorded at `fps` and save them
+/// in `output_dir`.
+pub(crate) fn write_stats(
+    trajectories: &[Trajectory],
+    fps: f64,
+    bin_size: f64,
+    regions: &RegionsConfig,
+    output_dir: &Path,
+) -> anyhow::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(output_dir)
+        .with_context(|| format!("Creating directory {}", output_dir.display()))?;
+    let mut saved = Vec::new();
+
+    let path = output_dir.join("objects.csv");
+    write_csv(
+        &path,
+        trajectories
+            .iter()
+            .filter(|t| !t.samples.is_empty())
+            .map(|t| ObjectStats::new(t, fps)),
+    )?;
+    saved.push(path);
+
+    if !regions.region.is_empty() {
+        let mut rows = Vec::new();
+        for trajectory in trajectories.iter() {
+            let mut num_frames: BTreeMap<&str, usize> = BTreeMap::new();
+            for p in trajectory.samples.iter().filter_map(position) {
+                for region in regions.region.iter().filter(|r| r.contains(&p)) {
+                    *num_frames.entry(region.name.as_str()).or_default() += 1;
+                }
+            }
+            for region in regions.region.iter() {
+                let n = num_frames.get(region.name.as_str()).copied().unwrap_or(0);
+                rows.push(RegionStats {
+                    obj_id: trajectory.obj_id,
+                    region: &region.name,
+                    num_frames: n,
+                    time_sec: n as f64 / fps,
+                });
+            }
+        }
+        let path = output_dir.join("regions.csv");
+        write_csv(&path, rows)?;
+        saved.push(path);
+    }
+
+    let positions: Vec<_> = trajectories
+        .iter()
+        .flat_map(|t| t.samples.iter().filter_map(position))
+        .collect();
+    let occupancy = Occupancy::new(&positions, bin_size);
+    let path = output_dir.join("voxels.csv");
+    write_csv(&path, occupancy.voxels())?;
+    saved.push(path);
+
+    for (name, axes) in [("xy", [0, 1]), ("xz", [0, 2]), ("yz", [1, 2])] {
+        let (w, h, counts) = occupancy
+            .projection(axes)
+            .with_context(|| format!("Computing heatmap_{name}.png"))?;
+        if w == 0 || h == 0 {
+            continue;
+        }
+        let path = output_dir.join(format!("heatmap_{name}.png"));
+        render_heatmap(w, h, &counts)
+            .save(&path)
+            .with_context(|| format!("Saving {}", path.display()))?;
+        saved.push(path);
+    }
+    Ok(saved)
+}
+
+#[test]
+fn test_occupancy() {
+    let positions = [
+        [0.005, 0.005, 0.005],
+        [0.006, 0.004, 0.001],
+        [0.025, -0.005, 0.005],
+    ];
+    let occupancy = Occupancy::new(&positions, 0.01);
+    assert_eq!(occupancy.origin, [0, -1, 0]);
+    assert_eq!(occupancy.shape, [3, 2, 1]);
+    let voxels = occupancy.voxels();
+    assert_eq!(voxels.len(), 2);
+    assert_eq!(voxels[0].count, 2);
+    assert!((voxels[0].x - 0.005).abs() < 1e-12);
+
+    // The xy projection has y increasing upward.
+    let (w, h, counts) = occupancy.projection([0, 1]).unwrap();
+    assert_eq!((w, h), (3, 2));
+    assert_eq!(counts, vec![2, 0, 0, 0, 0, 1]);
+
+    let image = render_heatmap(w, h, &counts);
+    assert_eq!(image.get_pixel(0, 0).0, [255]);
+    assert_eq!(image.get_pixel(1, 0).0, [0]);
+}
+
+#[test]
+fn test_occupancy_sparse() {
+    // Two positions far apart do not need memory for the voxels between them.
+    let positions = [[0.0, 0.0, 0.0], [10000.0, 10000.0, 10000.0]];
+    let occupancy = Occupancy::new(&positions, 0.5);
+    assert_eq!(occupancy.shape, [20_001; 3]);
+    let voxels = occupancy.voxels();
+    assert_eq!(voxels.len(), 2);
+    assert_eq!(voxels[1].z, 10000.25);
+    assert!(occupancy.projection([0, 1]).is_err());
+
+    let occupancy = Occupancy::new(&[], 0.01);
+    assert!(occupancy.voxels().is_empty());
+    assert_eq!(occupancy.projection([0, 2]).unwrap(), (0, 0, vec![]));
+}
+
+#[test]
+fn test_regions_config() {
+    let config: RegionsConfig = toml::from_str(
+        r#"
+        [[region]]
+        name = "feeder"
+        x = [0.0, 0.1]
+        y = [0.0, 0.1]
+        z = [0.0, 0.05]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.region.len(), 1);
+    assert!(config.region[0].contains(&[0.05, 0.05, 0.0]));
+    assert!(!config.region[0].contains(&[0.05, 0.05, 0.1]));
+}
@@ -0,0 +1,380 @@
+//! Estimation of the noise parameters of the Kalman filter from tracked data.
+//!
+//! The noise is estimated by autocovariance least squares. For a constant
+//! velocity model whose acceleration is white noise with spectral density `q`
+//! (`motion_noise_scale`), observed with white noise of variance `r`, the
+//! second differences of the observations at interval `dt` have the
+//! autocovariances
+//!
+//! * lag 0: 2/3 q dt³ + 6 r
+//! * lag 1: 1/6 q dt³ − 4 r
+//! * lag 2: r
+//!
+//! and zero for longer lags. `q` and `r` are found by least squares from the
+//! autocovariances of the data.
+//!
+//! The observations are the detections associated with each object in the
+//! `data_association` table. The 2D detections of each camera give `r` in
+//! pixels, which is `ekf_observation_covariance_pixels`. The 3D positions
+//! triangulated from the detections give `q`. The Kalman estimates themselves
+//! are not used, as they are already filtered with the original parameters.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek},
+};
+
+use anyhow::Context;
+use mvg::DistortedPixel;
+use nalgebra::Point2;
+
+use braidz_parser::BraidzArchive;
+use flydra_types::{CamNum, TrackingParams};
+
+/// Sums of the lagged products of second differences, for lags 0, 1 and 2.
+#[derive(Debug, Default, Clone)]
+struct LagSums {
+    sums: [f64; 3],
+    counts: [usize; 3],
+}
+
+impl LagSums {
+    /// Add the observations of one coordinate in consecutive frames.
+    fn add_run(&mut self, values: &[f64]) {
+        let d: Vec<f64> = values
+            .windows(3)
+            .map(|w| w[2] - 2.0 * w[1] + w[0])
+            .collect();
+        let lags = self.sums.iter_mut().zip(self.counts.iter_mut());
+        for (lag, (sum, count)) in lags.enumerate() {
+            for (a, b) in d.iter().zip(d.iter().skip(lag)) {
+                *sum += a * b;
+                *count += 1;
+            }
+        }
+    }
+
+    fn autocovariances(&self) -> Option<[f64; 3]> {
+        if self.counts[2] == 0 {
+            return None;
+        }
+        Some([0, 1, 2].map(|lag| self.sums[lag] / self.counts[lag] as f64))
+    }
+
+    fn num_samples(&self) -> usize {
+        self.counts[0]
+    }
+}
+
+/// Coefficients of `q dt³` and `r` in the autocovariances at lags 0, 1 and 2.
+const ALS_COEFFS: [[f64; 2]; 3] = [[2.0 / 3.0, 6.0], [1.0 / 6.0, -4.0], [0.0, 1.0]];
+
+/// Solve for the non-negative `(q dt³, r)` best fitting the autocovariances
+/// `c`.
+fn solve_als(c: [f64; 3]) -> (f64, f64) {
+    let dot = |a: [f64; 3], b: [f64; 3]| a.iter().zip(b.iter()).map(|(x, y)| x * y).sum::<f64>();
+    let (col_a, col_r) = (ALS_COEFFS.map(|row| row[0]), ALS_COEFFS.map(|row| row[1]));
+
+    // Normal equations.
+    let (aa, ar, rr) = (dot(col_a, col_a), dot(col_a, col_r), dot(col_r, col_r));
+    let (ac, rc) = (dot(col_a, c), dot(col_r, c));
+    let det = aa * rr - ar * ar;
+    let a = (rr * ac - ar * rc) / det;
+    let r = (aa * rc - ar * ac) / det;
+    // If negative, solve with that unknown being zero.
+    if a < 0.0 {
+        (0.0, (rc / rr).max(0.0))
+    } else if r < 0.0 {
+        ((ac / aa).max(0.0), 0.0)
+    } else {
+        (a, r)
+    }
+}
+
+/// Split samples, sorted by frame, into runs of consecutive frames with at
+/// least `min_len` samples.
+fn consecutive_runs<T>(samples: &[(i64, T)], min_len: usize) -> Vec<&[(i64, T)]> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for i in 1..=samples.len() {
+        if i == samples.len() || samples[i].0 != samples[i - 1].0 + 1 {
+            if i - start >= min_len {
+                runs.push(&samples[start..i]);
+            }
+            start = i;
+        }
+    }
+    runs
+}
+
+/// The observation noise of one camera.
+#[derive(Debug)]
+pub(crate) struct CameraNoise {
+    pub(crate) cam_name: String,
+    pub(crate) num_samples: usize,
+    /// The variance of the detections, in pixels².
+    pub(crate) covariance_pixels: f64,
+}
+
+/// The estimated noise parameters.
+#[derive(Debug)]
+pub(crate) struct NoiseEstimate {
+    /// The spectral density of the acceleration, in m²/s³, which is
+    /// [TrackingParams::motion_noise_scale].
+    pub(crate) motion_noise_scale: Option<f64>,
+    /// The standard deviation of the triangulated positions, in meters.
+    pub(crate) position_std_meters: Option<f64>,
+    pub(crate) num_samples_3d: usize,
+    pub(crate) cameras: Vec<CameraNoise>,
+}
+
+impl NoiseEstimate {
+    /// The median of the observation noise of all cameras, which is
+    /// [TrackingParams::ekf_observation_covariance_pixels]. Cameras with a
+    /// non-finite noise are ignored.
+    pub(crate) fn observation_covariance_pixels(&self) -> Option<f64> {
+        let values: Vec<f64> = self.cameras.iter().map(|c| c.covariance_pixels).collect();
+        mvg::median(&values)
+    }
+
+    /// `params` with the estimated noise parameters.
+    pub(crate) fn apply(&self, params: &TrackingParams) -> TrackingParams {
+        let mut params = params.clone();
+        if let Some(q) = self.motion_noise_scale {
+            params.motion_noise_scale = q;
+        }
+        if let Some(r) = self.observation_covariance_pixels() {
+            params.ekf_observation_covariance_pixels = r;
+        }
+        params
+    }
+}
+
+/// Estimate the noise from the detections of the tracked objects in
+/// `archive`, using runs of at least `min_run_frames` consecutive frames.
+pub(crate) fn estimate_noise<R: Read + Seek>(
+    archive: &mut BraidzArchive<R>,
+    min_run_frames: usize,
+) -> anyhow::Result<NoiseEstimate> {
+    let fps = archive.expected_fps;
+    let Some(cal) = archive.calibration_info.as_ref() else {
+        anyhow::bail!("no calibration");
+    };
+    let system = flydra_mvg::FlydraMultiCameraSystem::from_system(cal.cameras.clone(), cal.water);
+    let camn2camid = archive.cam_info.camn2camid.clone();
+
+    let mut obj_ids = BTreeMap::new();
+    for row in archive
+        .iter_data_association()
+        .context("Reading data_association table")?
+    {
+        let row = row?;
+        obj_ids.insert((row.frame.0 as i64, row.cam_num, row.pt_idx), row.obj_id);
+    }
+
+    // The detections of each object by frame.
+    let mut detections: BTreeMap<u32, BTreeMap<i64, Vec<(CamNum, Point2<f64>)>>> = BTreeMap::new();
+    for row in archive
+        .iter_data2d_distorted()
+        .context("Reading data2d_distorted table")?
+    {
+        let row = row?;
+        if row.x.is_nan() {
+            continue;
+        }
+        if let Some(obj_id) = obj_ids.get(&(row.frame, row.camn, row.frame_pt_idx)) {
+            detections
+                .entry(*obj_id)
+                .or_default()
+                .entry(row.frame)
+                .or_default()
+                .push((row.camn, Point2::new(row.x, row.y)));
+        }
+    }
+
+    let mut sums_3d = LagSums::default();
+    let mut sums_2d: BTreeMap<CamNum, LagSums> = BTreeMap::new();
+    for frames in detections.values() {
+        let mut positions = Vec::new();
+        let mut pixels: BTreeMap<CamNum, Vec<(i64, Point2<f64>)>> = BTreeMap::new();
+        for (frame, obs) in frames.iter() {
+            // An object seen twice by a camera in the same frame is ambiguous.
+            let mut camns: Vec<CamNum> = obs.iter().map(|(camn, _)| *camn).collect();
+            camns.sort();
+            camns.dedup();
+            if camns.len() != obs.len() {
+                continue;
+            }
+            for (camn, pt) in obs.iter() {
+                pixels.entry(*camn).or_default().push((*frame, *pt));
+            }
+            let points: Vec<(String, DistortedPixel<f64>)> = obs
+                .iter()
+                .filter_map(|(camn, pt)| {
+                    let cam_name = camn2camid.get(camn)?.clone();
+                    Some((cam_name, DistortedPixel { coords: *pt }))
+                })
+                .collect();
+            if points.len() < 2 {
+                continue;
+            }
+            if let Ok(pt) = system.find3d_distorted(&points) {
+                positions.push((*frame, pt.point().coords));
+            }
+        }
+
+        for run in consecutive_runs(&positions, min_run_frames) {
+            for axis in 0..3 {
+                let values: Vec<f64> = run.iter().map(|(_, p)| p[axis]).collect();
+                sums_3d.add_run(&values);
+            }
+        }
+        for (camn, samples) in pixels.iter() {
+            let sums = sums_2d.entry(*camn).or_default();
+            for run in consecutive_runs(samples, min_run_frames) {
+                for axis in 0..2 {
+                    let values: Vec<f64> = run.iter().map(|(_, p)| p[axis]).collect();
+                    sums.add_run(&values);
+                }
+            }
+        }
+    }
+
+    let (motion_noise_scale, position_std_meters) = match sums_3d.autocovariances() {
+        Some(c) => {
+            let (q_dt3, r) = solve_als(c);
+            (Some(q_dt3 * fps.powi(3)), Some(r.sqrt()))
+        }
+        None => (None, None),
+    };
+    let cameras = sums_2d
+        .iter()
+        .filter_map(|(camn, sums)| {
+            let c = sums.autocovariances()?;
+            let (_, r) = solve_als(c);
+            Some(CameraNoise {
+                cam_name: camn2camid
+                    .get(camn)
+                    .cloned()
+                    .unwrap_or_else(|| format!("camn {}", camn.0)),
+                num_samples: sums.num_samples(),
+                covariance_pixels: r,
+            })
+        })
+        .collect();
+    Ok(NoiseEstimate {
+        motion_noise_scale,
+        position_std_meters,
+        num_samples_3d: sums_3d.num_samples(),
+        cameras,
+    })
+}
+
+/// Format `params` as the tracking parameter table of a Braid configuration
+/// file.
+pub(crate) fn tracking_params_toml(params: &TrackingParams) -> anyhow::Result<String> {
+    // Converting to a `toml::Value` first emits the values before the tables.
+    let mut value = toml::Value::try_from(params)?;
+    // Print the `f32` as written, rather than as its `f64` value.
+    if let Ok(max_std) = params.max_position_std_meters.to_string().parse::<f64>() {
+        value["max_position_std_meters"] = toml::Value::Float(max_std);
+    }
+    let mut mainbrain = toml::value::Table::new();
+    mainbrain.insert("tracking_params".into(), value);
+    let mut config = toml::value::Table::new();
+    config.insert("mainbrain".into(), toml::Value::Table(mainbrain));
+    Ok(toml::to_string(&toml::Value::Table(config))?)
+}
+
+#[test]
+fn test_als() {
+    // Simulate a constant velocity model with white noise acceleration and
+    // white observation noise using a simple deterministic generator.
+    let mut seed: u64 = 12345;
+    let mut normal = move || {
+        // Sum of uniform variables, approximately normal with unit variance.
+        (0..12)
+            .map(|_| {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (seed >> 11) as f64 / (1u64 << 53) as f64
+            })
+            .sum::<f64>()
+            - 6.0
+    };
+    let (q, r, dt): (f64, f64, f64) = (2.0, 1e-6, 0.01);
+    let (mut x, mut v) = (0.0, 0.0);
+    let mut obs = Vec::new();
+    for _ in 0..200_000 {
+        // Exact discretization of the continuous model.
+        let (w1, w2) = (normal(), normal());
+        let dv = (q * dt).sqrt() * w1;
+        let dx = v * dt + (q * dt.powi(3) / 12.0).sqrt() * w2 + dv * dt / 2.0;
+        x += dx;
+        v += dv;
+        obs.push(x + r.sqrt() * normal());
+    }
+    let mut sums = LagSums::default();
+    sums.add_run(&obs);
+    let (q_dt3, r_est) = solve_als(sums.autocovariances().unwrap());
+    let q_est = q_dt3 / dt.powi(3);
+    assert!((q_est - q).abs() / q < 0.1, "{q_est}");
+    assert!((r_est - r).abs() / r < 0.1, "{r_est}");
+
+    // Negative estimates are clamped.
+    assert_eq!(solve_als([5.0, -4.5, 1.0]).0, 0.0);
+    let (a, r) = solve_als([1.0, 0.25, -0.1]);
+    assert!(a > 0.0);
+    assert_eq!(r, 0.0);
+}
+
+#[test]
+fn test_consecutive_runs() {
+    let samples: Vec<(i64, ())> = [1, 2, 3, 5, 6, 10, 11, 12, 13]
+        .iter()
+        .map(|f| (*f, ()))
+        .collect();
+    let runs: Vec<_> = consecutive_runs(&samples, 3)
+        .iter()
+        .map(|r| r.len())
+        .collect();
+    assert_eq!(runs, vec![3, 4]);
+}
+
+#[test]
+fn test_tracking_params_toml() {
+    let params = flydra_types::default_tracking_params_full_3d();
+    let buf = tracking_params_toml(&params).unwrap();
+    assert!(buf.contains("max_position_std_meters = 0.01212"), "{buf}");
+    let value: toml::Value = toml::from_str(&buf).unwrap();
+    let parsed: TrackingParams = value["mainbrain"]["tracking_params"]
+        .clone()
+        .try_into()
+        .unwrap();
+    assert_eq!(parsed.motion_noise_scale, params.motion_noise_scale);
+    assert!(parsed.hypothesis_test_params.is_some());
+}
+
+#[test]
+fn test_observation_covariance_pixels() {
+    let estimate = |values: &[f64]| NoiseEstimate {
+        motion_noise_scale: None,
+        position_std_meters: None,
+        num_samples_3d: 0,
+        cameras: values
+            .iter()
+            .map(|v| CameraNoise {
+                cam_name: "cam".to_string(),
+                num_samples: 10,
+                covariance_pixels: *v,
+            })
+            .collect(),
+    };
+    assert_eq!(estimate(&[]).observation_covariance_pixels(), None);
+    assert_eq!(estimate(&[f64::NAN]).observation_covariance_pixels(), None);
+    assert_eq!(
+        estimate(&[3.0, f64::NAN, 1.0, f64::INFINITY]).observation_covariance_pixels(),
+        Some(2.0)
+    );
+}
@@ -0,0 +1,457 @@
+// Copyright 2024 Andrew D. Straw.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Detection of behavioral events in the trajectories of a braidz file.
+//!
+//! The events are:
+//!
+//! - takeoff and landing, from the height and speed of the animal,
+//! - saccades, rapid turns in the horizontal plane, from the angular velocity
+//!   of the heading,
+//! - entry into and exit from a volume of interest.
+//!
+//! The thresholds are set with an [EventConfig], which is usually loaded from
+//! a TOML file such as `example-events.toml`. Detection works best on smoothed
+//! trajectories (see [braidz_smooth::smooth]) at integer frame numbers.
+
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+
+use braidz_smooth::{Sample, Trajectory};
+
+/// The name of the events table added to a braidz file.
+///
+/// This is not saved during recording, so it is not part of the braidz schema
+/// (see `flydra_types::BRAID_SCHEMA`).
+pub const BEHAVIOR_EVENTS_CSV_FNAME: &str = "behavior_events.csv";
+
+/// The thresholds of takeoff and landing detection.
+///
+/// The animal is on the ground when it is at most `ground_z_meters` high and
+/// slower than `min_flight_speed_meters_per_sec`, and flying when it is
+/// higher and at least that fast. Otherwise, the previous state is kept. A
+/// change of state is only an event if the new state is kept for
+/// `min_duration_frames` frames.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct TakeoffLandingConfig {
+    pub enabled: bool,
+    pub ground_z_meters: f64,
+    pub min_flight_speed_meters_per_sec: f64,
+    pub min_duration_frames: u64,
+}
+
+impl Default for TakeoffLandingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ground_z_meters: 0.01,
+            min_flight_speed_meters_per_sec: 0.1,
+            min_duration_frames: 5,
+        }
+    }
+}
+
+/// The thresholds of saccade detection.
+///
+/// A saccade is a run of consecutive samples in which the angular velocity of
+/// the heading in the horizontal plane exceeds
+/// `min_angular_velocity_deg_per_sec`. The heading is only defined when the
+/// horizontal speed is at least `min_horizontal_speed_meters_per_sec`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct SaccadeConfig {
+    pub enabled: bool,
+    pub min_angular_velocity_deg_per_sec: f64,
+    pub min_horizontal_speed_meters_per_sec: f64,
+}
+
+impl Default for SaccadeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_angular_velocity_deg_per_sec: 300.0,
+            min_horizontal_speed_meters_per_sec: 0.05,
+        }
+    }
+}
+
+/// An axis-aligned volume of interest, with the `[min, max]` of each
+/// coordinate in meters.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VolumeConfig {
+    pub x: [f64; 2],
+    pub y: [f64; 2],
+    pub z: [f64; 2],
+}
+
+impl VolumeConfig {
+    fn contains(&self, sample: &Sample) -> bool {
+        let p = &sample.position;
+        [(p.x, self.x), (p.y, self.y), (p.z, self.z)]
+            .iter()
+            .all(|(v, [min, max])| min <= v && v <= max)
+    }
+}
+
+/// The configuration of event detection.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct EventConfig {
+    pub takeoff_landing: TakeoffLandingConfig,
+    pub saccade: SaccadeConfig,
+    /// The volume of interest. If not set, no entry or exit events are
+    /// detected.
+    pub volume: Option<VolumeConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Takeoff,
+    Landing,
+    Saccade,
+    VolumeEntry,
+    VolumeExit,
+}
+
+/// A behavioral event, as saved in the `behavior_events.csv.gz` table.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BehaviorEvent {
+    pub obj_id: u32,
+    pub frame: u64,
+    /// The trigger timestamp in seconds since the Unix epoch, or NaN if
+    /// unknown.
+    pub timestamp: f64,
+    pub event: EventKind,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    /// The speed, in meters per second, for takeoff and landing, and the
+    /// peak angular velocity, in degrees per second, for saccades. Positive
+    /// angular velocities are counterclockwise seen from above. NaN for
+    /// volume entry and exit.
+    pub value: f64,
+}
+
+impl BehaviorEvent {
+    fn new(obj_id: u32, sample: &Sample, event: EventKind, value: f64) -> Self {
+        Self {
+            obj_id,
+            frame: sample.frame.round() as u64,
+            timestamp: sample.timestamp,
+            event,
+            x: sample.position.x,
+            y: sample.position.y,
+            z: sample.position.z,
+            value,
+        }
+    }
+}
+
+/// Detect the events of a trajectory recorded at `fps`.
+///
+/// The events are sorted by frame.
+pub fn detect_events(
+    trajectory: &Trajectory,
+    fps: f64,
+    config: &EventConfig,
+) -> Vec<BehaviorEvent> {
+    let mut events = Vec::new();
+    if config.takeoff_landing.enabled {
+        detect_takeoff_landing(trajectory, &config.takeoff_landing, &mut events);
+    }
+    if config.saccade.enabled {
+        detect_saccades(trajectory, fps, &config.saccade, &mut events);
+    }
+    if let Some(volume) = &config.volume {
+        detect_volume_crossings(trajectory, volume, &mut events);
+    }
+    events.sort_by_key(|e| e.frame);
+    events
+}
+
+/// Detect the events of all trajectories, sorted by frame and object ID.
+pub fn detect_all_events(
+    trajectories: &[Trajectory],
+    fps: f64,
+    config: &EventConfig,
+) -> Vec<BehaviorEvent> {
+    let mut events: Vec<_> = trajectories
+        .iter()
+        .flat_map(|t| detect_events(t, fps, config))
+        .collect();
+    events.sort_by_key(|e| (e.frame, e.obj_id));
+    events
+}
+
+fn speed(sample: &Sample) -> f64 {
+    sample.velocity.norm()
+}
+
+fn detect_takeoff_landing(
+    trajectory: &Trajectory,
+    config: &TakeoffLandingConfig,
+    events: &mut Vec<BehaviorEvent>,
+) {
+    // `true` if flying.
+    let classify = |sample: &Sample| {
+        let (z, speed) = (sample.position.z, speed(sample));
+        if z <= config.ground_z_meters && speed < config.min_flight_speed_meters_per_sec {
+            Some(false)
+        } else if z > config.ground_z_meters && speed >= config.min_flight_speed_meters_per_sec {
+            Some(true)
+        } else {
+            // Intermediate or NaN.
+            None
+        }
+    };
+
+    let mut current: Option<bool> = None;
+    // The new state, the index of its first sample and its number of
+    // samples.
+    let mut candidate: Option<(bool, usize, u64)> = None;
+    for (i, sample) in trajectory.samples.iter().enumerate() {
+        let Some(state) = classify(sample) else {
+            continue;
+        };
+        if current.is_none() || current == Some(state) {
+            current = Some(state);
+            candidate = None;
+            continue;
+        }
+        let (state, start, n) = match candidate {
+            Some((s, start, n)) if s == state => (s, start, n + 1),
+            _ => (state, i, 1),
+        };
+        if n >= config.min_duration_frames {
+            let first = &trajectory.samples[start];
+            let kind = if state {
+                EventKind::Takeoff
+            } else {
+                EventKind::Landing
+            };
+            events.push(BehaviorEvent::new(
+                trajectory.obj_id,
+                first,
+                kind,
+                speed(first),
+            ));
+            current = Some(state);
+            candidate = None;
+        } else {
+            candidate = Some((state, start, n));
+        }
+    }
+}
+
+fn detect_saccades(
+    trajectory: &Trajectory,
+    fps: f64,
+    config: &SaccadeConfig,
+    events: &mut Vec<BehaviorEvent>,
+) {
+    let heading = |sample: &Sample| {
+        let v = &sample.velocity;
+        if v.x.hypot(v.y) >= config.min_horizontal_speed_meters_per_sec {
+            Some(v.y.atan2(v.x))
+        } else {
+            None
+        }
+    };
+
+    // The peak angular velocity of the current saccade and its sample.
+    let mut peak: Option<(f64, &Sample)> = None;
+    for pair in trajectory.samples.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let angular_velocity = match (heading(a), heading(b)) {
+            (Some(ha), Some(hb)) => {
+                let dt = (b.frame - a.frame) / fps;
+                let mut dh = hb - ha;
+                if dh > std::f64::consts::PI {
+                    dh -= std::f64::consts::TAU;
+                } else if dh < -std::f64::consts::PI {
+                    dh += std::f64::consts::TAU;
+                }
+                Some((dh / dt).to_degrees())
+            }
+            _ => None,
+        };
+        match angular_velocity {
+            Some(w) if w.abs() >= config.min_angular_velocity_deg_per_sec => {
+                if peak.map(|(p, _)| w.abs() > p.abs()).unwrap_or(true) {
+                    peak = Some((w, b));
+                }
+            }
+            _ => {
+                if let Some((w, sample)) = peak.take() {
+                    events.push(BehaviorEvent::new(
+                        trajectory.obj_id,
+                        sample,
+                        EventKind::Saccade,
+                        w,
+                    ));
+                }
+            }
+        }
+    }
+    if let Some((w, sample)) = peak {
+        events.push(BehaviorEvent::new(
+            trajectory.obj_id,
+            sample,
+            EventKind::Saccade,
+            w,
+        ));
+    }
+}
+
+fn detect_volume_crossings(
+    trajectory: &Trajectory,
+    volume: &VolumeConfig,
+    events: &mut Vec<BehaviorEvent>,
+) {
+    let mut inside: Option<bool> = None;
+    for sample in trajectory.samples.iter() {
+        if !sample.position.iter().all(|v| v.is_finite()) {
+            continue;
+        }
+        let now_inside = volume.contains(sample);
+        if let Some(was_inside) = inside {
+            if now_inside != was_inside {
+                let kind = if now_inside {
+                    EventKind::VolumeEntry
+                } else {
+                    EventKind::VolumeExit
+                };
+                events.push(BehaviorEvent::new(
+                    trajectory.obj_id,
+                    sample,
+                    kind,
+                    f64::NAN,
+                ));
+            }
+        }
+        inside = Some(now_inside);
+    }
+}
+
+/// Write events as CSV.
+pub fn write_events_csv<W: Write>(events: &[BehaviorEvent], wtr: W) -> csv::Result<()> {
+    let mut wtr = csv::Writer::from_writer(wtr);
+    for event in events.iter() {
+        wtr.serialize(event)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// The gzipped CSV of events, as saved in a braidz file.
+pub fn events_csv_gz(events: &[BehaviorEvent]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = libflate::gzip::Encoder::new(Vec::new())?;
+    write_events_csv(events, &mut encoder)?;
+    encoder.finish().into_result()
+}
+
+#[cfg(test)]
+fn test_trajectory(states: &[([f64; 3], [f64; 3])]) -> Trajectory {
+    use nalgebra::Vector3;
+    Trajectory {
+        obj_id: 1,
+        stitched_obj_ids: vec![],
+        samples: states
+            .iter()
+            .enumerate()
+            .map(|(i, (p, v))| Sample {
+                frame: i as f64,
+                timestamp: f64::NAN,
+                position: Vector3::from(*p),
+                velocity: Vector3::from(*v),
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn test_takeoff_landing() {
+    let ground = ([0.0, 0.0, 0.0], [0.0, 0.0, 0.0]);
+    let flying = ([0.0, 0.0, 0.1], [0.5, 0.0, 0.0]);
+    let mut states = vec![ground; 10];
+    // A short hop is ignored.
+    states.extend([flying; 2]);
+    states.extend([ground; 5]);
+    states.extend([flying; 10]);
+    states.extend([ground; 10]);
+    let trajectory = test_trajectory(&states);
+    let config = EventConfig::default();
+    let events = detect_events(&trajectory, 100.0, &config);
+    let summary: Vec<_> = events.iter().map(|e| (e.event, e.frame)).collect();
+    assert_eq!(
+        summary,
+        vec![(EventKind::Takeoff, 17), (EventKind::Landing, 27)]
+    );
+    assert_eq!(events[0].value, 0.5);
+}
+
+#[test]
+fn test_saccade() {
+    // Flying at 0.5 m/s at 100 fps, turning by 90 degrees over 3 frames.
+    let mut states = Vec::new();
+    for i in 0..20 {
+        let angle: f64 = match i {
+            0..=9 => 0.0,
+            10 => 20.0,
+            11 => 60.0,
+            _ => 90.0,
+        };
+        let (s, c) = angle.to_radians().sin_cos();
+        states.push(([0.0, 0.0, 0.1], [0.5 * c, 0.5 * s, 0.0]));
+    }
+    let trajectory = test_trajectory(&states);
+    let events = detect_events(&trajectory, 100.0, &EventConfig::default());
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event, EventKind::Saccade);
+    assert_eq!(events[0].frame, 11);
+    assert!((events[0].value - 4000.0).abs() < 1e-6);
+}
+
+#[test]
+fn test_volume_and_config() {
+    let config: EventConfig = toml::from_str(
+        r#"
+        [takeoff_landing]
+        enabled = false
+
+        [volume]
+        x = [-1.0, 1.0]
+        y = [-1.0, 1.0]
+        z = [0.0, 1.0]
+        "#,
+    )
+    .unwrap();
+    assert!(config.saccade.enabled);
+    let v = [0.0, 0.0, 0.0];
+    let trajectory = test_trajectory(&[
+        ([2.0, 0.0, 0.5], v),
+        ([0.5, 0.0, 0.5], v),
+        ([f64::NAN, 0.0, 0.5], v),
+        ([0.5, 0.0, 0.5], v),
+        ([0.5, 0.0, 1.5], v),
+    ]);
+    let events = detect_events(&trajectory, 100.0, &config);
+    let summary: Vec<_> = events.iter().map(|e| (e.event, e.frame)).collect();
+    assert_eq!(
+        summary,
+        vec![(EventKind::VolumeEntry, 1), (EventKind::VolumeExit, 4)]
+    );
+
+    let mut buf = Vec::new();
+    write_events_csv(&events, &mut buf).unwrap();
+    let csv = String::from_utf8(buf).unwrap();
+    assert!(csv.starts_with("obj_id,frame,timestamp,event,x,y,z,value\n1,1,NaN,volume_entry,"));
+}
@@ -0,0 +1,161 @@
+// Copyright 2024 Andrew D. Straw.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Post-processing of the trajectories saved in a braidz file.
+//!
+//! The Kalman estimates saved by Braid are causal: each estimate uses only the
+//! observations up to its frame. This crate provides offline processing of the
+//! saved estimates:
+//!
+//! - [smooth]: Rauch-Tung-Striebel smoothing, which uses the observations
+//!   before and after each frame.
+//! - [interpolate_gaps]: linear interpolation of short runs of missing frames.
+//! - [resample]: linear interpolation at a uniform rate.
+//! - [stitch]: joining of trajectories which were split into several object
+//!   IDs although they clearly belong to the same animal.
+//!
+//! Start with [trajectories_from_rows]. Smoothing, gap interpolation and
+//! stitching require samples at integer frame numbers, so resampling should
+//! be done last.
+
+use std::collections::BTreeMap;
+
+use nalgebra::Vector3;
+
+use flydra_types::KalmanEstimatesRow;
+
+mod resample;
+mod smooth;
+mod stitch;
+
+pub use resample::{interpolate_gaps, resample};
+pub use smooth::{smooth, SmoothingParams};
+pub use stitch::{stitch, StitchParams};
+
+/// A state estimate of a trajectory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    /// The synchronized frame number. This is not an integer after
+    /// resampling.
+    pub frame: f64,
+    /// The trigger timestamp in seconds since the Unix epoch, or NaN if
+    /// unknown.
+    pub timestamp: f64,
+    pub position: Vector3<f64>,
+    pub velocity: Vector3<f64>,
+}
+
+impl Sample {
+    /// Linear interpolation between `self` (at `alpha` = 0) and `other` (at
+    /// `alpha` = 1).
+    fn lerp(&self, other: &Sample, alpha: f64) -> Sample {
+        let lerp = |a: f64, b: f64| a + (b - a) * alpha;
+        Sample {
+            frame: lerp(self.frame, other.frame),
+            timestamp: lerp(self.timestamp, other.timestamp),
+            position: self.position.lerp(&other.position, alpha),
+            velocity: self.velocity.lerp(&other.velocity, alpha),
+        }
+    }
+}
+
+impl From<&KalmanEstimatesRow> for Sample {
+    fn from(row: &KalmanEstimatesRow) -> Self {
+        Sample {
+            frame: row.frame.0 as f64,
+            timestamp: row
+                .timestamp
+                .as_ref()
+                .map(|t| t.as_f64())
+                .unwrap_or(f64::NAN),
+            position: Vector3::new(row.x, row.y, row.z),
+            velocity: Vector3::new(row.xvel, row.yvel, row.zvel),
+        }
+    }
+}
+
+/// The trajectory of an object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trajectory {
+    pub obj_id: u32,
+    /// The object IDs of the trajectories which were joined to this one by
+    /// [stitch], in order.
+    pub stitched_obj_ids: Vec<u32>,
+    /// The samples, sorted by frame.
+    pub samples: Vec<Sample>,
+}
+
+impl Trajectory {
+    fn first(&self) -> &Sample {
+        &self.samples[0]
+    }
+
+    fn last(&self) -> &Sample {
+        self.samples.last().unwrap()
+    }
+}
+
+/// Group the rows of the `kalman_estimates` table into a trajectory per
+/// object, sorted by object ID.
+pub fn trajectories_from_rows<'a, I>(rows: I) -> Vec<Trajectory>
+where
+    I: IntoIterator<Item = &'a KalmanEstimatesRow>,
+{
+    let mut by_obj_id: BTreeMap<u32, Vec<Sample>> = BTreeMap::new();
+    for row in rows {
+        by_obj_id.entry(row.obj_id).or_default().push(row.into());
+    }
+    by_obj_id
+        .into_iter()
+        .map(|(obj_id, mut samples)| {
+            samples.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+            Trajectory {
+                obj_id,
+                stitched_obj_ids: Vec::new(),
+                samples,
+            }
+        })
+        .collect()
+}
+
+/// Split `samples` where more than `max_gap_frames` frames are missing.
+fn split_at_gaps(samples: &[Sample], max_gap_frames: u64) -> Vec<&[Sample]> {
+    let max_step = (max_gap_frames + 1) as f64;
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for i in 1..samples.len() {
+        if samples[i].frame - samples[i - 1].frame > max_step {
+            segments.push(&samples[start..i]);
+            start = i;
+        }
+    }
+    if start < samples.len() {
+        segments.push(&samples[start..]);
+    }
+    segments
+}
+
+#[cfg(test)]
+fn sample(frame: u64, x: f64, xvel: f64) -> Sample {
+    Sample {
+        frame: frame as f64,
+        timestamp: 1000.0 + frame as f64 * 0.01,
+        position: Vector3::new(x, 0.0, 0.0),
+        velocity: Vector3::new(xvel, 0.0, 0.0),
+    }
+}
+
+#[test]
+fn test_split_at_gaps() {
+    let samples: Vec<_> = [0, 1, 3, 4, 10]
+        .into_iter()
+        .map(|f| sample(f, 0.0, 0.0))
+        .collect();
+    let lens: Vec<_> = split_at_gaps(&samples, 1).iter().map(|s| s.len()).collect();
+    assert_eq!(lens, vec![4, 1]);
+    assert_eq!(split_at_gaps(&samples, 0).len(), 3);
+}
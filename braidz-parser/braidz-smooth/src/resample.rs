@@ -0,0 +1,131 @@
+use crate::{split_at_gaps, Sample, Trajectory};
+
+/// Fill runs of up to `max_gap_frames` missing frames by linear interpolation.
+///
+/// Longer gaps are kept. Nothing is interpolated between samples of the same
+/// frame or out of order.
+pub fn interpolate_gaps(trajectory: &Trajectory, max_gap_frames: u64) -> Trajectory {
+    let mut samples: Vec<Sample> = Vec::with_capacity(trajectory.samples.len());
+    for segment in split_at_gaps(&trajectory.samples, max_gap_frames) {
+        for pair in segment.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            samples.push(*a);
+            let Some(n_missing) = ((b.frame - a.frame).round() as u64).checked_sub(1) else {
+                continue;
+            };
+            for k in 1..=n_missing {
+                samples.push(a.lerp(b, k as f64 / (n_missing + 1) as f64));
+            }
+        }
+        samples.push(*segment.last().unwrap());
+    }
+    Trajectory {
+        obj_id: trajectory.obj_id,
+        stitched_obj_ids: trajectory.stitched_obj_ids.clone(),
+        samples,
+    }
+}
+
+/// Resample a trajectory recorded at `fps` to `rate` samples per second by
+/// linear interpolation.
+///
+/// The samples are at the frames `f0 + k * fps / rate`, where `f0` is the
+/// first frame of the trajectory. No samples are made within gaps of missing
+/// frames, so call [interpolate_gaps] or [crate::smooth] first to resample
+/// across short gaps.
+///
+/// Panics if `rate` is not positive.
+pub fn resample(trajectory: &Trajectory, fps: f64, rate: f64) -> Trajectory {
+    assert!(rate > 0.0, "rate must be positive");
+    let step = fps / rate;
+    let mut samples = Vec::new();
+    if let Some(first) = trajectory.samples.first() {
+        let f0 = first.frame;
+        let mut i = 0;
+        for k in 0.. {
+            let frame = f0 + k as f64 * step;
+            // Find the samples at or around `frame`.
+            while i + 1 < trajectory.samples.len() && trajectory.samples[i + 1].frame <= frame {
+                i += 1;
+            }
+            let a = &trajectory.samples[i];
+            if a.frame == frame {
+                samples.push(Sample { frame, ..*a });
+                continue;
+            }
+            let Some(b) = trajectory.samples.get(i + 1) else {
+                break;
+            };
+            // Consecutive frames differ by one.
+            if b.frame - a.frame <= 1.0 + 1e-9 {
+                samples.push(Sample {
+                    frame,
+                    ..a.lerp(b, (frame - a.frame) / (b.frame - a.frame))
+                });
+            }
+        }
+    }
+    Trajectory {
+        obj_id: trajectory.obj_id,
+        stitched_obj_ids: trajectory.stitched_obj_ids.clone(),
+        samples,
+    }
+}
+
+#[test]
+fn test_interpolate_gaps() {
+    use crate::sample;
+
+    let trajectory = Trajectory {
+        obj_id: 1,
+        stitched_obj_ids: vec![],
+        samples: vec![
+            sample(0, 0.0, 1.0),
+            sample(3, 3.0, 1.0),
+            sample(10, 10.0, 1.0),
+        ],
+    };
+    let filled = interpolate_gaps(&trajectory, 2);
+    let frames: Vec<_> = filled.samples.iter().map(|s| s.frame).collect();
+    assert_eq!(frames, vec![0.0, 1.0, 2.0, 3.0, 10.0]);
+    assert!((filled.samples[2].position.x - 2.0).abs() < 1e-12);
+    assert!((filled.samples[2].timestamp - 1000.02).abs() < 1e-9);
+
+    // Repeated and out of order frames are kept without interpolation.
+    let trajectory = Trajectory {
+        obj_id: 1,
+        stitched_obj_ids: vec![],
+        samples: vec![
+            sample(5, 5.0, 1.0),
+            sample(5, 5.0, 1.0),
+            sample(4, 4.0, 1.0),
+        ],
+    };
+    let filled = interpolate_gaps(&trajectory, 2);
+    let frames: Vec<_> = filled.samples.iter().map(|s| s.frame).collect();
+    assert_eq!(frames, vec![5.0, 5.0, 4.0]);
+}
+
+#[test]
+fn test_resample() {
+    use crate::sample;
+
+    // Frames 0 to 4 and 8 to 10 at 100 fps.
+    let samples = (0..=10)
+        .filter(|f| !(5..8).contains(f))
+        .map(|f| sample(f, f as f64, 1.0))
+        .collect();
+    let trajectory = Trajectory {
+        obj_id: 1,
+        stitched_obj_ids: vec![],
+        samples,
+    };
+
+    let resampled = resample(&trajectory, 100.0, 40.0);
+    let frames: Vec<_> = resampled.samples.iter().map(|s| s.frame).collect();
+    assert_eq!(frames, vec![0.0, 2.5, 10.0]);
+    assert!((resampled.samples[1].position.x - 2.5).abs() < 1e-12);
+
+    let upsampled = resample(&trajectory, 100.0, 200.0);
+    assert_eq!(upsampled.samples.len(), 9 + 5);
+}
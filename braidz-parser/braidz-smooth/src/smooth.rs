@@ -0,0 +1,221 @@
+use nalgebra::{Matrix3, Matrix3x6, Matrix6, Vector3, Vector6};
+
+use tracking::motion_model_3d::ConstantVelocity3DModel;
+use tracking::motion_model_3d_fixed_dt::MotionModel3D;
+
+use crate::{split_at_gaps, Sample, Trajectory};
+
+/// Parameters of [smooth].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmoothingParams {
+    /// Scale of the state noise covariance matrix **Q** of the constant
+    /// velocity motion model, as in the tracking parameters of Braid.
+    pub motion_noise_scale: f64,
+    /// The standard deviation of the saved positions around the true
+    /// positions, in meters.
+    pub observation_std_meters: f64,
+    /// The standard deviation of the velocity of the first sample, in meters
+    /// per second.
+    pub initial_vel_std_meters_per_sec: f64,
+    /// Runs of up to this many missing frames are filled by the smoother.
+    /// Longer gaps split the trajectory into segments which are smoothed
+    /// independently.
+    pub max_gap_frames: u64,
+}
+
+impl Default for SmoothingParams {
+    fn default() -> Self {
+        Self {
+            motion_noise_scale: 0.1,
+            observation_std_meters: 0.01,
+            initial_vel_std_meters_per_sec: 1.0,
+            max_gap_frames: 10,
+        }
+    }
+}
+
+/// Smooth a trajectory with a Rauch-Tung-Striebel smoother.
+///
+/// The saved positions are the observations of a Kalman filter with the
+/// constant velocity motion model used by Braid, run forward in time at
+/// `fps`. The filtered estimates are then refined backward in time. Missing
+/// frames in gaps of up to `params.max_gap_frames` frames are filled with the
+/// smoothed estimates. Their timestamps are linearly interpolated.
+///
+/// Samples with non-finite positions are treated as missing.
+pub fn smooth(trajectory: &Trajectory, fps: f64, params: &SmoothingParams) -> Trajectory {
+    let samples = split_at_gaps(&trajectory.samples, params.max_gap_frames)
+        .into_iter()
+        .flat_map(|segment| smooth_segment(segment, fps, params))
+        .collect();
+    Trajectory {
+        obj_id: trajectory.obj_id,
+        stitched_obj_ids: trajectory.stitched_obj_ids.clone(),
+        samples,
+    }
+}
+
+/// Smooth samples at integer frames without long gaps.
+fn smooth_segment(segment: &[Sample], fps: f64, params: &SmoothingParams) -> Vec<Sample> {
+    let first = &segment[0];
+    let frame0 = first.frame.round() as i64;
+    let n_frames = (segment.last().unwrap().frame.round() as i64 - frame0 + 1) as usize;
+
+    // The observations and the known timestamps, indexed from frame0.
+    let mut observations: Vec<Option<Vector3<f64>>> = vec![None; n_frames];
+    let mut timestamps = vec![f64::NAN; n_frames];
+    for sample in segment {
+        let i = (sample.frame.round() as i64 - frame0) as usize;
+        if sample.position.iter().all(|v| v.is_finite()) {
+            observations[i] = Some(sample.position);
+        }
+        timestamps[i] = sample.timestamp;
+    }
+    interpolate_timestamps(&mut timestamps);
+
+    let motion_model =
+        ConstantVelocity3DModel::new(params.motion_noise_scale).calc_for_dt(1.0 / fps);
+    let f = motion_model.transition_model;
+    let ft = motion_model.transition_model_transpose;
+    let q = motion_model.transition_noise_covariance;
+    let h = Matrix3x6::new(
+        1.0, 0.0, 0.0, 0.0, 0.0, 0.0, //
+        0.0, 1.0, 0.0, 0.0, 0.0, 0.0, //
+        0.0, 0.0, 1.0, 0.0, 0.0, 0.0,
+    );
+    let r = Matrix3::identity() * params.observation_std_meters.powi(2);
+
+    // The initial state is the first finite saved estimate.
+    let initial = segment
+        .iter()
+        .find(|s| s.position.iter().all(|v| v.is_finite()));
+    let Some(initial) = initial else {
+        return Vec::new();
+    };
+    let velocity = if initial.velocity.iter().all(|v| v.is_finite()) {
+        initial.velocity
+    } else {
+        Vector3::zeros()
+    };
+    let mut x = Vector6::new(
+        initial.position.x,
+        initial.position.y,
+        initial.position.z,
+        velocity.x,
+        velocity.y,
+        velocity.z,
+    );
+    let pos_var = params.observation_std_meters.powi(2);
+    let vel_var = params.initial_vel_std_meters_per_sec.powi(2);
+    let mut p = Matrix6::from_diagonal(&Vector6::new(
+        pos_var, pos_var, pos_var, vel_var, vel_var, vel_var,
+    ));
+
+    // Forward pass, keeping the prior and posterior of each frame.
+    let mut priors = Vec::with_capacity(n_frames);
+    let mut posteriors = Vec::with_capacity(n_frames);
+    for (i, observation) in observations.iter().enumerate() {
+        if i > 0 {
+            x = f * x;
+            p = f * p * ft + q;
+        }
+        priors.push((x, p));
+        if let Some(z) = observation {
+            let s = h * p * h.transpose() + r;
+            // `s` is positive definite as `r` is.
+            let s_inv = s.try_inverse().unwrap();
+            let k = p * h.transpose() * s_inv;
+            x += k * (z - h * x);
+            p = (Matrix6::identity() - k * h) * p;
+        }
+        posteriors.push((x, p));
+    }
+
+    // Backward pass.
+    let mut smoothed = vec![Vector6::zeros(); n_frames];
+    smoothed[n_frames - 1] = posteriors[n_frames - 1].0;
+    for i in (0..n_frames - 1).rev() {
+        let (x_post, p_post) = &posteriors[i];
+        let (x_prior_next, p_prior_next) = &priors[i + 1];
+        let Some(p_prior_next_inv) = p_prior_next.try_inverse() else {
+            smoothed[i] = *x_post;
+            continue;
+        };
+        let c = p_post * ft * p_prior_next_inv;
+        smoothed[i] = x_post + c * (smoothed[i + 1] - x_prior_next);
+    }
+
+    smoothed
+        .iter()
+        .zip(timestamps)
+        .enumerate()
+        .map(|(i, (state, timestamp))| Sample {
+            frame: (frame0 + i as i64) as f64,
+            timestamp,
+            position: state.fixed_rows::<3>(0).into_owned(),
+            velocity: state.fixed_rows::<3>(3).into_owned(),
+        })
+        .collect()
+}
+
+/// Replace NaN timestamps by linear interpolation between the known ones.
+/// Timestamps before the first or after the last known one stay NaN.
+fn interpolate_timestamps(timestamps: &mut [f64]) {
+    let known: Vec<usize> = (0..timestamps.len())
+        .filter(|&i| !timestamps[i].is_nan())
+        .collect();
+    for pair in known.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        let t0 = timestamps[start];
+        let step = (timestamps[end] - t0) / (end - start) as f64;
+        for (k, t) in timestamps[start + 1..end].iter_mut().enumerate() {
+            *t = t0 + step * (k + 1) as f64;
+        }
+    }
+}
+
+#[test]
+fn test_smooth() {
+    use crate::sample;
+
+    // An object moving at 1 m/s with alternating noise, observed at 100 fps
+    // with frame 5 missing.
+    let samples = (0..20)
+        .filter(|&frame| frame != 5)
+        .map(|frame| {
+            let noise = if frame % 2 == 0 { 0.005 } else { -0.005 };
+            sample(frame, frame as f64 * 0.01 + noise, 1.0)
+        })
+        .collect();
+    let trajectory = Trajectory {
+        obj_id: 3,
+        stitched_obj_ids: vec![],
+        samples,
+    };
+    let smoothed = smooth(&trajectory, 100.0, &SmoothingParams::default());
+    assert_eq!(smoothed.obj_id, 3);
+    assert_eq!(smoothed.samples.len(), 20);
+    let filled = &smoothed.samples[5];
+    assert_eq!(filled.frame, 5.0);
+    assert!((filled.timestamp - 1000.05).abs() < 1e-9);
+    for s in smoothed.samples[2..18].iter() {
+        let expected = s.frame * 0.01;
+        assert!((s.position.x - expected).abs() < 0.005, "{s:?}");
+        assert!((s.velocity.x - 1.0).abs() < 0.3, "{s:?}");
+    }
+
+    // A long gap splits the trajectory.
+    let params = SmoothingParams {
+        max_gap_frames: 0,
+        ..Default::default()
+    };
+    assert_eq!(smooth(&trajectory, 100.0, &params).samples.len(), 19);
+}
+
+#[test]
+fn test_interpolate_timestamps() {
+    let mut timestamps = [f64::NAN, 1.0, f64::NAN, f64::NAN, 4.0, f64::NAN];
+    interpolate_timestamps(&mut timestamps);
+    assert!(timestamps[0].is_nan() && timestamps[5].is_nan());
+    assert_eq!(&timestamps[1..5], &[1.0, 2.0, 3.0, 4.0]);
+}
@@ -0,0 +1,136 @@
+use crate::Trajectory;
+
+/// Parameters of [stitch].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StitchParams {
+    /// The largest number of frames missing between the end of a trajectory
+    /// and the start of the next one.
+    pub max_gap_frames: u64,
+    /// The largest distance, in meters, between the start of the next
+    /// trajectory and the position predicted from the end of the previous
+    /// one.
+    pub max_distance_meters: f64,
+}
+
+impl Default for StitchParams {
+    fn default() -> Self {
+        Self {
+            max_gap_frames: 10,
+            max_distance_meters: 0.05,
+        }
+    }
+}
+
+/// Join trajectories which clearly belong to the same animal.
+///
+/// Trajectory `b` is a candidate continuation of trajectory `a` if it starts
+/// after `a` ends, with at most `params.max_gap_frames` frames missing, and
+/// if its first position is within `params.max_distance_meters` of the
+/// position extrapolated from the last position and velocity of `a`. Only
+/// unambiguous pairs are joined: `a` must have `b` as its only candidate
+/// continuation and `b` must be a candidate continuation of `a` only.
+///
+/// The joined trajectory has the object ID of the first trajectory and the
+/// IDs of the others in [Trajectory::stitched_obj_ids]. Gaps between joined
+/// trajectories are kept. Trajectories without samples are dropped.
+pub fn stitch(trajectories: Vec<Trajectory>, fps: f64, params: &StitchParams) -> Vec<Trajectory> {
+    let trajectories: Vec<Trajectory> = trajectories
+        .into_iter()
+        .filter(|t| !t.samples.is_empty())
+        .collect();
+    let n = trajectories.len();
+    let max_step = (params.max_gap_frames + 1) as f64;
+
+    let mut successors: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut n_predecessors = vec![0; n];
+    for (i, a) in trajectories.iter().enumerate() {
+        let end = a.last();
+        for (j, b) in trajectories.iter().enumerate() {
+            let start = b.first();
+            let step = start.frame - end.frame;
+            if i == j || step <= 0.0 || step > max_step {
+                continue;
+            }
+            let velocity = if end.velocity.iter().all(|v| v.is_finite()) {
+                end.velocity
+            } else {
+                nalgebra::Vector3::zeros()
+            };
+            let predicted = end.position + velocity * (step / fps);
+            // NaN positions are never close.
+            if (start.position - predicted).norm() <= params.max_distance_meters {
+                successors[i].push(j);
+                n_predecessors[j] += 1;
+            }
+        }
+    }
+
+    let next: Vec<Option<usize>> = successors
+        .iter()
+        .map(|s| match s.as_slice() {
+            [j] if n_predecessors[*j] == 1 => Some(*j),
+            _ => None,
+        })
+        .collect();
+    let mut is_continuation = vec![false; n];
+    for j in next.iter().flatten() {
+        is_continuation[*j] = true;
+    }
+
+    let mut trajectories: Vec<Option<Trajectory>> = trajectories.into_iter().map(Some).collect();
+    let mut result = Vec::new();
+    for i in 0..n {
+        if is_continuation[i] {
+            continue;
+        }
+        let mut joined = trajectories[i].take().unwrap();
+        let mut current = i;
+        while let Some(j) = next[current] {
+            let continuation = trajectories[j].take().unwrap();
+            joined.stitched_obj_ids.push(continuation.obj_id);
+            joined
+                .stitched_obj_ids
+                .extend(continuation.stitched_obj_ids);
+            joined.samples.extend(continuation.samples);
+            current = j;
+        }
+        result.push(joined);
+    }
+    result
+}
+
+#[test]
+fn test_stitch() {
+    use crate::sample;
+
+    let trajectory = |obj_id, frames: std::ops::Range<u64>, x0: f64| Trajectory {
+        obj_id,
+        stitched_obj_ids: vec![],
+        samples: frames
+            .map(|f| sample(f, x0 + f as f64 * 0.01, 1.0))
+            .collect(),
+    };
+    // At 100 fps, moving at 1 m/s. Object 2 continues object 1 after 3
+    // missing frames and object 3 continues object 2 after 2 missing frames.
+    // Object 4 is far away.
+    let trajectories = vec![
+        trajectory(1, 0..10, 0.0),
+        trajectory(2, 13..20, 0.0),
+        trajectory(3, 22..30, 0.0),
+        trajectory(4, 12..30, 5.0),
+    ];
+    let params = StitchParams::default();
+    let stitched = stitch(trajectories.clone(), 100.0, &params);
+    assert_eq!(stitched.len(), 2);
+    assert_eq!(stitched[0].obj_id, 1);
+    assert_eq!(stitched[0].stitched_obj_ids, vec![2, 3]);
+    assert_eq!(stitched[0].samples.len(), 25);
+    assert_eq!(stitched[1].obj_id, 4);
+
+    // Ambiguous: two trajectories could continue object 1.
+    let mut ambiguous = trajectories[..2].to_vec();
+    ambiguous.push(trajectory(5, 12..20, 0.0));
+    let stitched = stitch(ambiguous, 100.0, &params);
+    assert_eq!(stitched.len(), 3);
+    assert!(stitched.iter().all(|t| t.stitched_obj_ids.is_empty()));
+}
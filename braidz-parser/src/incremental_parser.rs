@@ -181,6 +181,7 @@ impl<R: Read + Seek> IncrementalParser<R, ArchiveOpened> {
                                     saving_program_name: "flydra".to_string(),
                                     schema: flydra_types::BRAID_SCHEMA,
                                     save_empty_data2d: false,
+                                    tracking_2d: false,
                                 });
                             }
 
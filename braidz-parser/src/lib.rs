@@ -7,7 +7,10 @@ use std::{
 use hdrhistogram::serialization::interval_log;
 use ordered_float::NotNan;
 
-use flydra_types::{FlydraFloatTimestampLocal, HostClock, TextlogRow, TrackingParams, Triggerbox};
+use flydra_types::{
+    DataAssocRow, FlydraFloatTimestampLocal, HostClock, ObjectQualityRow, TextlogRow,
+    TrackingParams, Triggerbox,
+};
 
 use braidz_types::{
     BraidMetadata, BraidzSummary, CalibrationInfo, CamInfo, CamInfoRow, CamNum, Data2dDistortedRow,
@@ -19,6 +22,10 @@ use groupby::{AscendingGroupIter, BufferedSortIter, GroupedRows};
 use csv_eof::EarlyEofOk;
 
 pub mod incremental_parser;
+mod mp4_alignment;
+pub use mp4_alignment::Mp4AlignmentIndex;
+mod validate;
+pub use validate::{validate_braidz, Issue, Severity, ValidationReport};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -349,6 +356,67 @@ impl<'a, R: Read + Seek> BraidzArchive<R> {
         Ok(rdr2.into_deserialize().early_eof_ok())
     }
 
+    /// Iterate over the rows of the `data_association` table.
+    ///
+    /// Each row relates a detection in the `data2d_distorted` table, given by
+    /// its frame, camera and point index, to the object it was used to track.
+    pub fn iter_data_association(
+        &'a mut self,
+    ) -> Result<impl Iterator<Item = Result<DataAssocRow, csv::Error>> + 'a, Error> {
+        let data_fname = self
+            .archive
+            .path_starter()
+            .join(flydra_types::DATA_ASSOCIATE_CSV_FNAME);
+        let rdr = open_maybe_gzipped(data_fname)?;
+        let rdr2 = csv::Reader::from_reader(rdr);
+        Ok(rdr2.into_deserialize().early_eof_ok())
+    }
+
+    /// Load the index relating frames to the MP4 files recorded by the cameras.
+    ///
+    /// The index is empty if no MP4 files were recorded or if the archive was
+    /// saved before the `mp4_alignment` table was introduced.
+    pub fn mp4_alignment_index(&mut self) -> Result<Mp4AlignmentIndex, Error> {
+        let data_fname = self
+            .archive
+            .path_starter()
+            .join(flydra_types::MP4_ALIGNMENT_CSV_FNAME);
+        let rdr = match open_maybe_gzipped(data_fname) {
+            Ok(rdr) => rdr,
+            Err(Error::ZipOrDir {
+                source: zip_or_dir::Error::FileNotFound,
+            }) => return Ok(Mp4AlignmentIndex::default()),
+            Err(e) => return Err(e),
+        };
+        let rows = csv::Reader::from_reader(rdr)
+            .into_deserialize()
+            .early_eof_ok()
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Mp4AlignmentIndex::new(rows))
+    }
+
+    /// Load the quality of the tracking of each object.
+    ///
+    /// This is empty if the archive was saved before the `object_quality`
+    /// table was introduced.
+    pub fn object_quality(&mut self) -> Result<Vec<ObjectQualityRow>, Error> {
+        let data_fname = self
+            .archive
+            .path_starter()
+            .join(flydra_types::OBJECT_QUALITY_CSV_FNAME);
+        let rdr = match open_maybe_gzipped(data_fname) {
+            Ok(rdr) => rdr,
+            Err(Error::ZipOrDir {
+                source: zip_or_dir::Error::FileNotFound,
+            }) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        Ok(csv::Reader::from_reader(rdr)
+            .into_deserialize()
+            .early_eof_ok()
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
     /// Iterate over synchronized frames in `data2d_distorted` table.
     ///
     /// This sorts the data by looking ahead up to `bufsize` rows. Furthermore,
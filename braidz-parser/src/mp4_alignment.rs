@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+
+use braidz_types::CamNum;
+use flydra_types::Mp4AlignmentRow;
+
+/// Relates synchronized frame numbers to the MP4 files recorded by the cameras.
+///
+/// This is loaded from the `mp4_alignment` table of a braidz archive with
+/// [crate::BraidzArchive::mp4_alignment_index].
+#[derive(Debug, Default, Clone)]
+pub struct Mp4AlignmentIndex {
+    rows: Vec<Mp4AlignmentRow>,
+    by_frame: BTreeMap<(CamNum, i64), usize>,
+    by_pts: BTreeMap<String, BTreeMap<u64, usize>>,
+}
+
+impl Mp4AlignmentIndex {
+    pub fn new(rows: Vec<Mp4AlignmentRow>) -> Self {
+        let mut by_frame = BTreeMap::new();
+        let mut by_pts: BTreeMap<String, BTreeMap<u64, usize>> = BTreeMap::new();
+        for (i, row) in rows.iter().enumerate() {
+            by_frame.insert((row.camn, row.frame), i);
+            by_pts
+                .entry(row.mp4_filename.clone())
+                .or_default()
+                .insert(row.mp4_pts_usec, i);
+        }
+        Self {
+            rows,
+            by_frame,
+            by_pts,
+        }
+    }
+
+    /// All rows of the index, in the order saved.
+    pub fn rows(&self) -> &[Mp4AlignmentRow] {
+        &self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Names of the MP4 files in the index.
+    pub fn mp4_filenames(&self) -> impl Iterator<Item = &str> {
+        self.by_pts.keys().map(String::as_str)
+    }
+
+    /// Find the MP4 file and presentation timestamp of `frame` from camera
+    /// `camn`.
+    pub fn mp4_sample(&self, camn: CamNum, frame: i64) -> Option<&Mp4AlignmentRow> {
+        self.by_frame.get(&(camn, frame)).map(|&i| &self.rows[i])
+    }
+
+    /// Find the frame shown at presentation timestamp `pts_usec` in the MP4
+    /// file `mp4_filename`.
+    ///
+    /// The row with the nearest presentation timestamp is returned.
+    pub fn frame_at(&self, mp4_filename: &str, pts_usec: u64) -> Option<&Mp4AlignmentRow> {
+        let samples = self.by_pts.get(mp4_filename)?;
+        let before = samples.range(..=pts_usec).next_back();
+        let after = samples.range(pts_usec..).next();
+        let nearest = match (before, after) {
+            (Some(b), Some(a)) => {
+                if pts_usec - b.0 <= a.0 - pts_usec {
+                    b
+                } else {
+                    a
+                }
+            }
+            (Some(x), None) | (None, Some(x)) => x,
+            (None, None) => return None,
+        };
+        Some(&self.rows[*nearest.1])
+    }
+}
+
+#[test]
+fn test_mp4_alignment_index() {
+    let row = |camn: u8, frame: i64, mp4_filename: &str, mp4_pts_usec: u64| Mp4AlignmentRow {
+        camn: CamNum(camn),
+        frame,
+        timestamp: flydra_types::FlydraFloatTimestampLocal::from_f64(
+            1_700_000_000.0 + frame as f64 / 100.0,
+        ),
+        mp4_filename: mp4_filename.to_string(),
+        mp4_pts_usec,
+    };
+    let index = Mp4AlignmentIndex::new(vec![
+        row(0, 10, "cam0.mp4", 0),
+        row(1, 10, "cam1.mp4", 0),
+        row(0, 11, "cam0.mp4", 10_000),
+        row(1, 11, "cam1.mp4", 10_000),
+        row(0, 12, "cam0.mp4", 20_000),
+    ]);
+
+    assert_eq!(
+        index.mp4_filenames().collect::<Vec<_>>(),
+        ["cam0.mp4", "cam1.mp4"]
+    );
+
+    let sample = index.mp4_sample(CamNum(1), 11).unwrap();
+    assert_eq!(sample.mp4_filename, "cam1.mp4");
+    assert_eq!(sample.mp4_pts_usec, 10_000);
+    assert!(index.mp4_sample(CamNum(1), 12).is_none());
+
+    assert_eq!(index.frame_at("cam0.mp4", 0).unwrap().frame, 10);
+    assert_eq!(index.frame_at("cam0.mp4", 14_000).unwrap().frame, 11);
+    assert_eq!(index.frame_at("cam0.mp4", 16_000).unwrap().frame, 12);
+    assert_eq!(index.frame_at("cam0.mp4", 1_000_000).unwrap().frame, 12);
+    assert!(index.frame_at("cam2.mp4", 0).is_none());
+}
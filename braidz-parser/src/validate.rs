@@ -0,0 +1,404 @@
+//! Checks of the internal consistency of a braidz archive.
+//!
+//! The tables are read completely, so that corrupt compressed data is found.
+//! The rows of `data2d_distorted` are then checked per camera: the cameras
+//! must be listed in `cam_info` (and in the calibration, if there is one), the
+//! timestamps must increase with the frame number and gaps between frame
+//! numbers must be explained by the `frame_drops` table.
+//!
+//! Frames without detections are only saved with `save_empty_data2d`, in
+//! which case they have a row with NaN coordinates. If there is no such row,
+//! gaps between frame numbers are expected and not checked.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use flydra_types::FrameDropRow;
+
+use crate::*;
+
+/// The tables which are read completely to check that they can be read.
+const TABLES: &[&str] = &[
+    flydra_types::DATA2D_DISTORTED_CSV_FNAME,
+    flydra_types::KALMAN_ESTIMATES_CSV_FNAME,
+    flydra_types::DATA_ASSOCIATE_CSV_FNAME,
+    flydra_types::CAM_INFO_CSV_FNAME,
+    flydra_types::TRIGGER_CLOCK_INFO_CSV_FNAME,
+    flydra_types::EXPERIMENT_INFO_CSV_FNAME,
+    flydra_types::TEXTLOG_CSV_FNAME,
+    flydra_types::FRAME_DROPS_CSV_FNAME,
+    flydra_types::TRACK_CONFIRMATION_CSV_FNAME,
+    flydra_types::MP4_ALIGNMENT_CSV_FNAME,
+    flydra_types::OBJECT_QUALITY_CSV_FNAME,
+    flydra_types::TRACK_TERMINATION_CSV_FNAME,
+];
+
+/// Timestamps of frames matching within this many seconds are equal.
+const TIMESTAMP_TOLERANCE: f64 = 1e-6;
+
+/// How serious an [Issue] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Severity {
+    /// The data is usable, but something is unexpected.
+    Warning,
+    /// The data is inconsistent or cannot be read.
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in an archive.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    /// The table or file with the problem.
+    pub table: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.table, self.message)
+    }
+}
+
+/// The result of [validate_braidz].
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+    /// The number of rows of the `data2d_distorted` table.
+    pub num_data2d_rows: u64,
+    /// The number of gaps in the frames of a camera which are explained by
+    /// the `frame_drops` table.
+    pub num_explained_frame_gaps: u64,
+}
+
+impl ValidationReport {
+    /// The severity of the most serious issue, or `None` if there is none.
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.issues.iter().map(|issue| issue.severity).max()
+    }
+
+    /// The number of issues with `severity`.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == severity)
+            .count()
+    }
+
+    fn push(&mut self, severity: Severity, table: &str, message: String) {
+        self.issues.push(Issue {
+            severity,
+            table: table.to_string(),
+            message,
+        });
+    }
+}
+
+/// The timestamps of a frame of a camera.
+#[derive(Debug, Clone, PartialEq)]
+struct FrameStamps {
+    frame: i64,
+    block_id: Option<u64>,
+    trigger: Option<f64>,
+    host: f64,
+}
+
+/// Check the internal consistency of `archive`.
+pub fn validate_braidz<R: Read + Seek>(
+    archive: &mut BraidzArchive<R>,
+) -> Result<ValidationReport, Error> {
+    let mut report = ValidationReport::default();
+
+    for table in TABLES.iter() {
+        let path_like = archive.archive.path_starter().join(table);
+        let result = open_maybe_gzipped(path_like)
+            .and_then(|mut rdr| Ok(std::io::copy(&mut rdr, &mut std::io::sink())?));
+        match result {
+            Ok(_) => {}
+            Err(Error::ZipOrDir {
+                source: zip_or_dir::Error::FileNotFound,
+            }) => {}
+            Err(e) => report.push(Severity::Error, table, format!("cannot be read: {e}")),
+        }
+    }
+
+    let frame_drops = read_frame_drops(archive, &mut report);
+
+    // Collect the timestamps of each frame of each camera.
+    let table = flydra_types::DATA2D_DISTORTED_CSV_FNAME;
+    let mut per_cam: BTreeMap<CamNum, Vec<FrameStamps>> = BTreeMap::new();
+    let mut unknown_cams = BTreeSet::new();
+    let camn2camid = archive.cam_info.camn2camid.clone();
+    let mut num_data2d_rows = 0;
+    let mut has_empty_rows = false;
+    for row in archive.iter_data2d_distorted()? {
+        let row = match row {
+            Ok(row) => row,
+            Err(e) => {
+                report.push(
+                    Severity::Error,
+                    table,
+                    format!("row {} cannot be read: {e}", num_data2d_rows + 1),
+                );
+                break;
+            }
+        };
+        num_data2d_rows += 1;
+        has_empty_rows |= row.x.is_nan();
+        if !camn2camid.contains_key(&row.camn) {
+            unknown_cams.insert(row.camn);
+            continue;
+        }
+        per_cam.entry(row.camn).or_default().push(FrameStamps {
+            frame: row.frame,
+            block_id: row.block_id,
+            trigger: row.timestamp.as_ref().map(|t| t.as_f64()),
+            host: row.cam_received_timestamp.as_f64(),
+        });
+    }
+    report.num_data2d_rows = num_data2d_rows;
+    for camn in unknown_cams.iter() {
+        report.push(
+            Severity::Error,
+            table,
+            format!("camera number {} is not in cam_info", camn.0),
+        );
+    }
+
+    for (camn, mut stamps) in per_cam.into_iter() {
+        let cam_id = &camn2camid[&camn];
+        // Several detections in a frame share the timestamps.
+        stamps.sort_by_key(|s| s.frame);
+        stamps.dedup_by_key(|s| s.frame);
+        let drops = frame_drops
+            .as_ref()
+            .and_then(|drops| drops.get(cam_id))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        check_camera_frames(cam_id, &stamps, drops, has_empty_rows, &mut report);
+    }
+
+    if let Some(calibration_info) = archive.calibration_info.as_ref() {
+        let calibrated = calibration_info.cameras.cams_by_name();
+        for cam_id in archive.cam_info.camid2camn.keys() {
+            if !calibrated.contains_key(cam_id) {
+                report.push(
+                    Severity::Error,
+                    flydra_types::CALIBRATION_XML_FNAME,
+                    format!("camera \"{cam_id}\" of cam_info is not calibrated"),
+                );
+            }
+        }
+        for cam_id in calibrated.keys() {
+            if !archive.cam_info.camid2camn.contains_key(cam_id) {
+                report.push(
+                    Severity::Warning,
+                    flydra_types::CALIBRATION_XML_FNAME,
+                    format!("calibrated camera \"{cam_id}\" is not in cam_info"),
+                );
+            }
+        }
+    }
+
+    if let Some(rows) = archive.kalman_estimates_table.as_ref() {
+        check_kalman_estimates(rows, &mut report);
+    }
+
+    Ok(report)
+}
+
+/// Read the frame drops of each camera, or `None` if the archive has no
+/// `frame_drops` table.
+fn read_frame_drops<R: Read + Seek>(
+    archive: &mut BraidzArchive<R>,
+    report: &mut ValidationReport,
+) -> Option<BTreeMap<String, Vec<FrameDropRow>>> {
+    let table = flydra_types::FRAME_DROPS_CSV_FNAME;
+    let path_like = archive.archive.path_starter().join(table);
+    // Errors opening the table were reported when checking it can be read.
+    let rdr = open_maybe_gzipped(path_like).ok()?;
+    let mut result: BTreeMap<String, Vec<FrameDropRow>> = BTreeMap::new();
+    for row in csv::Reader::from_reader(rdr)
+        .into_deserialize::<FrameDropRow>()
+        .early_eof_ok()
+    {
+        match row {
+            Ok(row) => result.entry(row.cam_id.clone()).or_default().push(row),
+            Err(e) => {
+                report.push(Severity::Error, table, format!("cannot be parsed: {e}"));
+                break;
+            }
+        }
+    }
+    Some(result)
+}
+
+/// Check the timestamps and frame gaps of the frames `stamps` of a camera,
+/// sorted by frame number.
+///
+/// Gaps are only checked if `check_gaps` is true, as frames without
+/// detections are missing otherwise.
+fn check_camera_frames(
+    cam_id: &str,
+    stamps: &[FrameStamps],
+    drops: &[FrameDropRow],
+    check_gaps: bool,
+    report: &mut ValidationReport,
+) {
+    let table = flydra_types::DATA2D_DISTORTED_CSV_FNAME;
+    let mut trigger_decreasing = Vec::new();
+    let mut host_decreasing = Vec::new();
+    let mut unexplained_gaps = Vec::new();
+    for pair in stamps.windows(2) {
+        let (prev, next) = (&pair[0], &pair[1]);
+        if let (Some(prev_trigger), Some(next_trigger)) = (prev.trigger, next.trigger) {
+            if next_trigger <= prev_trigger {
+                trigger_decreasing.push(next.frame);
+            }
+        }
+        if next.host < prev.host {
+            host_decreasing.push(next.frame);
+        }
+        if check_gaps && next.frame > prev.frame + 1 {
+            // Braid records the first frame received after a gap.
+            let explained = drops.iter().any(|drop| {
+                (drop.block_id.is_some() && drop.block_id == next.block_id)
+                    || (drop.cam_received_time.as_f64() - next.host).abs() < TIMESTAMP_TOLERANCE
+            });
+            if explained {
+                report.num_explained_frame_gaps += 1;
+            } else {
+                unexplained_gaps.push((prev.frame, next.frame));
+            }
+        }
+    }
+
+    if let Some(frame) = trigger_decreasing.first() {
+        report.push(
+            Severity::Error,
+            table,
+            format!(
+                "camera \"{cam_id}\": trigger timestamp does not increase at {} frame(s), \
+                first at frame {frame}",
+                trigger_decreasing.len()
+            ),
+        );
+    }
+    if let Some(frame) = host_decreasing.first() {
+        report.push(
+            Severity::Error,
+            table,
+            format!(
+                "camera \"{cam_id}\": host timestamp decreases at {} frame(s), first at \
+                frame {frame}",
+                host_decreasing.len()
+            ),
+        );
+    }
+    if let Some((prev, next)) = unexplained_gaps.first() {
+        report.push(
+            Severity::Warning,
+            table,
+            format!(
+                "camera \"{cam_id}\": {} gap(s) in frame numbers not recorded as frame \
+                drops, first from frame {prev} to {next}",
+                unexplained_gaps.len()
+            ),
+        );
+    }
+}
+
+/// Check that the frames and timestamps of each object increase.
+fn check_kalman_estimates(rows: &[KalmanEstimatesRow], report: &mut ValidationReport) {
+    let mut last: BTreeMap<u32, &KalmanEstimatesRow> = BTreeMap::new();
+    let mut bad_objects = BTreeSet::new();
+    for row in rows.iter() {
+        if let Some(prev) = last.insert(row.obj_id, row) {
+            let frame_ok = row.frame.0 > prev.frame.0;
+            let timestamp_ok = match (&prev.timestamp, &row.timestamp) {
+                (Some(prev), Some(next)) => next.as_f64() > prev.as_f64(),
+                _ => true,
+            };
+            if !frame_ok || !timestamp_ok {
+                bad_objects.insert(row.obj_id);
+            }
+        }
+    }
+    if let Some(obj_id) = bad_objects.first() {
+        report.push(
+            Severity::Error,
+            flydra_types::KALMAN_ESTIMATES_CSV_FNAME,
+            format!(
+                "frame or timestamp does not increase for {} object(s), first for obj_id {obj_id}",
+                bad_objects.len()
+            ),
+        );
+    }
+}
+
+#[test]
+fn test_check_camera_frames() {
+    let stamps = |frame: i64, block_id: u64, t: f64| FrameStamps {
+        frame,
+        block_id: Some(block_id),
+        trigger: Some(t),
+        host: t + 0.01,
+    };
+    let drop = |block_id: Option<u64>, host: f64| FrameDropRow {
+        cam_id: "cam".to_string(),
+        cam_received_time: FlydraFloatTimestampLocal::from_f64(host),
+        framenumber: 0,
+        block_id,
+        n_dropped_camera: 1,
+        n_dropped_transport: 0,
+    };
+
+    // Consistent frames, with a gap explained by block_id and one explained by
+    // the timestamp.
+    let frames = [
+        stamps(10, 100, 1.0),
+        stamps(11, 101, 1.1),
+        stamps(13, 103, 1.3),
+        stamps(15, 105, 1.5),
+    ];
+    let mut report = ValidationReport::default();
+    check_camera_frames(
+        "cam",
+        &frames,
+        &[drop(Some(103), 0.0), drop(None, 1.51)],
+        true,
+        &mut report,
+    );
+    assert_eq!(report.issues, vec![]);
+    assert_eq!(report.num_explained_frame_gaps, 2);
+
+    // An unexplained gap and a decreasing timestamp.
+    let frames = [
+        stamps(10, 100, 1.0),
+        stamps(11, 101, 0.9),
+        stamps(20, 110, 2.0),
+    ];
+    let mut report = ValidationReport::default();
+    check_camera_frames("cam", &frames, &[], true, &mut report);
+    assert_eq!(report.count(Severity::Error), 2);
+    assert_eq!(report.count(Severity::Warning), 1);
+    assert_eq!(report.max_severity(), Some(Severity::Error));
+    assert!(report.issues[2].message.contains("from frame 11 to 20"));
+
+    // Without empty rows, frames without detections are missing.
+    let frames = [stamps(10, 100, 1.0), stamps(20, 110, 2.0)];
+    let mut report = ValidationReport::default();
+    check_camera_frames("cam", &frames, &[], false, &mut report);
+    assert_eq!(report.issues, vec![]);
+    assert_eq!(report.max_severity(), None);
+}
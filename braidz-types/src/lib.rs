@@ -19,6 +19,12 @@ pub struct BraidMetadata {
     /// when loading old files is "".
     #[serde(default = "default_saving_program_name")]
     pub saving_program_name: String,
+    /// Whether objects were tracked in 2D with a single camera.
+    ///
+    /// In this case, the z coordinate of all tracked positions is zero. This
+    /// is new in schema 10. When loading old files, it is false.
+    #[serde(default)]
+    pub tracking_2d: bool,
 }
 
 fn default_saving_program_name() -> String {
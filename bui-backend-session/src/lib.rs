@@ -155,13 +155,20 @@ impl HttpSession {
         tracing::trace!("building request");
         let url = url::Url::parse(req.uri().to_string().as_ref()).unwrap();
         {
+            // All cookies must be sent in a single header.
             let jar = self.jar.read().unwrap();
-            for (cookie_name, cookie_value) in jar.get_request_values(&url) {
-                let cookie = cookie_store::RawCookie::new(cookie_name, cookie_value);
-                tracing::trace!("adding cookie {}", cookie);
+            let cookies: Vec<String> = jar
+                .get_request_values(&url)
+                .map(|(cookie_name, cookie_value)| {
+                    let cookie = cookie_store::RawCookie::new(cookie_name, cookie_value);
+                    tracing::trace!("adding cookie {}", cookie);
+                    cookie.to_string()
+                })
+                .collect();
+            if !cookies.is_empty() {
                 req.headers_mut().insert(
                     COOKIE,
-                    hyper::header::HeaderValue::from_str(&cookie.to_string()).unwrap(),
+                    hyper::header::HeaderValue::from_str(&cookies.join("; ")).unwrap(),
                 );
             }
         }
@@ -415,6 +415,11 @@ where
         let mut c = self.camera.lock().unwrap();
         c.next_frame()
     }
+
+    fn frame_pool_stats(&self) -> Option<ci2::FramePoolStats> {
+        let c = self.camera.lock().unwrap();
+        c.frame_pool_stats()
+    }
 }
 
 impl<M, C, G> ci2::CameraModule for ThreadedAsyncCameraModule<M, C, G>
@@ -3,9 +3,9 @@ extern crate machine_vision_formats as formats;
 use anyhow::Context;
 use std::sync::{Arc, Mutex};
 
-use basic_frame::DynamicFrame;
 use ci2::{
-    AcquisitionMode, AutoMode, DynamicFrameWithInfo, HostTimingInfo, TriggerMode, TriggerSelector,
+    AcquisitionMode, AutoMode, DynamicFrameWithInfo, FramePool, FramePoolStats, HostTimingInfo,
+    TriggerMode, TriggerSelector,
 };
 use pylon_cxx::HasProperties;
 
@@ -23,6 +23,13 @@ pub type Result<M> = std::result::Result<M, Error>;
 
 const BAD_FNO: usize = usize::MAX;
 
+/// Maximum number of frame buffers kept for reuse.
+///
+/// Frames still held by consumers (e.g. queued for processing or in the
+/// post-trigger buffer) cannot be reused. When more frames than this are held,
+/// additional frames are allocated outside the pool.
+const FRAME_POOL_CAPACITY: usize = 128;
+
 mod feature_cache;
 use feature_cache::*;
 
@@ -163,6 +170,7 @@ pub struct WrappedCamera<'a> {
     grab_result: Arc<Mutex<pylon_cxx::GrabResult>>,
     is_sfnc2: bool,
     pfs_cache: Arc<Mutex<PfsCache>>,
+    frame_pool: Arc<Mutex<FramePool>>,
 }
 
 fn _test_camera_is_send() {
@@ -308,6 +316,7 @@ impl<'a> WrappedCamera<'a> {
                     grab_result,
                     is_sfnc2,
                     pfs_cache,
+                    frame_pool: Arc::new(Mutex::new(FramePool::new(FRAME_POOL_CAPACITY))),
                 });
             }
         }
@@ -968,7 +977,6 @@ impl<'a> ci2::Camera for WrappedCamera<'a> {
             let width = gr.width().map_pylon_err()?;
             let height = gr.height().map_pylon_err()?;
             let stride = gr.stride().map_pylon_err()?.try_into()?;
-            let image_data = buffer.to_vec();
             let device_timestamp = gr.time_stamp().map_pylon_err()?;
 
             let backend_data = if !(device_timestamp == 0 && block_id == u64::MAX) {
@@ -983,7 +991,15 @@ impl<'a> ci2::Camera for WrappedCamera<'a> {
             };
 
             let host_timing = HostTimingInfo { fno, datetime: now };
-            let image = DynamicFrame::new(width, height, stride, image_data, pixel_format);
+            // Copy the data out of the driver buffer, which is returned to the
+            // driver with the next grab, into a buffer from the pool.
+            let image = self.frame_pool.lock().unwrap().frame_from_slice(
+                width,
+                height,
+                stride,
+                buffer,
+                pixel_format,
+            );
 
             Ok(DynamicFrameWithInfo {
                 image,
@@ -1002,6 +1018,10 @@ impl<'a> ci2::Camera for WrappedCamera<'a> {
             )))
         }
     }
+
+    fn frame_pool_stats(&self) -> Option<FramePoolStats> {
+        Some(self.frame_pool.lock().unwrap().stats())
+    }
 }
 
 pub fn convert_pixel_format(pixel_format: formats::PixFmt) -> ci2::Result<&'static str> {
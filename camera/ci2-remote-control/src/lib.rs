@@ -181,6 +181,36 @@ impl Default for NvidiaH264Options {
     }
 }
 
+/// Matrix coefficients used to convert between RGB and YUV in MP4 files.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Mp4ColorMatrix {
+    /// ITU-R BT.601, as used for standard definition video.
+    #[default]
+    Bt601,
+    /// ITU-R BT.709, as used for high definition video.
+    Bt709,
+}
+
+/// Range of the Y, U and V values in MP4 files.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Mp4ColorRange {
+    /// All values 0-255 are used.
+    #[default]
+    Full,
+    /// Y uses 16-235 and U and V use 16-240.
+    Limited,
+}
+
+/// How RGB values are encoded as YUV in MP4 files.
+///
+/// The default, BT.601 with full range, matches the conversion of
+/// `convert-image`.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Mp4ColorEncoding {
+    pub matrix: Mp4ColorMatrix,
+    pub range: Mp4ColorRange,
+}
+
 /// Configuration for MP4 recording
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Mp4RecordingConfig {
@@ -188,6 +218,11 @@ pub struct Mp4RecordingConfig {
     /// Limits the recording to a maximum frame rate.
     pub max_framerate: RecordingFrameRate,
     pub h264_metadata: Option<H264Metadata>,
+    /// The color encoding of the frames, which is also signaled in the file.
+    ///
+    /// The NVENC encoder always uses the default.
+    #[serde(default)]
+    pub color_encoding: Mp4ColorEncoding,
 }
 
 /// Configuration for an ffmpeg-based recording
@@ -250,6 +285,10 @@ pub struct H264Metadata {
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub gamma: Option<f32>,
+
+    /// Description of the experiment entered by the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experiment: Option<rust_cam_bui_types::ExperimentMetadata>,
 }
 
 impl H264Metadata {
@@ -260,6 +299,7 @@ impl H264Metadata {
             creation_time,
             camera_name: None,
             gamma: None,
+            experiment: None,
         }
     }
 }
@@ -321,6 +361,37 @@ impl std::fmt::Display for TagFamily {
     }
 }
 
+// Checkerboard calibration
+
+/// The type of board used for checkerboard calibration.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
+pub enum CheckerboardPattern {
+    /// A plain chessboard which must be fully visible to be detected.
+    #[default]
+    Chessboard,
+    /// A ChArUco board with 36h11 AprilTag markers, which may be partially
+    /// visible.
+    Charuco36h11,
+}
+
+impl EnumIter for CheckerboardPattern {
+    fn variants() -> Vec<Self> {
+        use CheckerboardPattern::*;
+        vec![Chessboard, Charuco36h11]
+    }
+}
+
+impl std::fmt::Display for CheckerboardPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use CheckerboardPattern::*;
+        let s = match self {
+            Chessboard => "chessboard",
+            Charuco36h11 => "ChArUco (36h11)",
+        };
+        write!(f, "{s}")
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub enum BitrateSelection {
     Bitrate500,
@@ -365,6 +436,222 @@ impl enum_iter::EnumIter for BitrateSelection {
     }
 }
 
+/// Maximum width of the frames sent to the live view in the browser.
+///
+/// Wider frames are downscaled by an integer factor.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub enum PreviewMaxWidth {
+    Px320,
+    Px640,
+    Px1280,
+    #[default]
+    FullResolution,
+}
+
+impl PreviewMaxWidth {
+    /// The maximum width in pixels, or None for full resolution.
+    pub fn max_width(&self) -> Option<u32> {
+        use PreviewMaxWidth::*;
+        match self {
+            Px320 => Some(320),
+            Px640 => Some(640),
+            Px1280 => Some(1280),
+            FullResolution => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PreviewMaxWidth {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.max_width() {
+            Some(w) => write!(f, "{w} px"),
+            None => write!(f, "Full"),
+        }
+    }
+}
+
+impl enum_iter::EnumIter for PreviewMaxWidth {
+    fn variants() -> Vec<Self> {
+        vec![
+            PreviewMaxWidth::Px320,
+            PreviewMaxWidth::Px640,
+            PreviewMaxWidth::Px1280,
+            PreviewMaxWidth::FullResolution,
+        ]
+    }
+}
+
+/// JPEG quality of the frames sent to the live view in the browser.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub enum PreviewJpegQuality {
+    Low,
+    Medium,
+    #[default]
+    High,
+    Best,
+}
+
+impl PreviewJpegQuality {
+    /// The JPEG quality, from 1 to 100.
+    pub fn quality(&self) -> u8 {
+        use PreviewJpegQuality::*;
+        match self {
+            Low => 30,
+            Medium => 60,
+            High => 80,
+            Best => 95,
+        }
+    }
+}
+
+impl std::fmt::Display for PreviewJpegQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use PreviewJpegQuality::*;
+        let name = match self {
+            Low => "Low",
+            Medium => "Medium",
+            High => "High",
+            Best => "Best",
+        };
+        write!(f, "{name} ({})", self.quality())
+    }
+}
+
+impl enum_iter::EnumIter for PreviewJpegQuality {
+    fn variants() -> Vec<Self> {
+        vec![
+            PreviewJpegQuality::Low,
+            PreviewJpegQuality::Medium,
+            PreviewJpegQuality::High,
+            PreviewJpegQuality::Best,
+        ]
+    }
+}
+
+/// Corner of recorded MP4 frames into which the text overlay is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum OverlayCorner {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl std::fmt::Display for OverlayCorner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use OverlayCorner::*;
+        let name = match self {
+            TopLeft => "Top left",
+            TopRight => "Top right",
+            BottomLeft => "Bottom left",
+            BottomRight => "Bottom right",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl enum_iter::EnumIter for OverlayCorner {
+    fn variants() -> Vec<Self> {
+        vec![
+            OverlayCorner::TopLeft,
+            OverlayCorner::TopRight,
+            OverlayCorner::BottomLeft,
+            OverlayCorner::BottomRight,
+        ]
+    }
+}
+
+/// Size of the text overlay in recorded MP4 frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum OverlaySize {
+    Small,
+    #[default]
+    Medium,
+    Large,
+}
+
+impl OverlaySize {
+    /// The height of the text in pixels.
+    pub fn text_height(&self) -> u16 {
+        use OverlaySize::*;
+        match self {
+            Small => 16,
+            Medium => 24,
+            Large => 40,
+        }
+    }
+}
+
+impl std::fmt::Display for OverlaySize {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use OverlaySize::*;
+        let name = match self {
+            Small => "Small",
+            Medium => "Medium",
+            Large => "Large",
+        };
+        write!(f, "{name} ({} px)", self.text_height())
+    }
+}
+
+impl enum_iter::EnumIter for OverlaySize {
+    fn variants() -> Vec<Self> {
+        vec![OverlaySize::Small, OverlaySize::Medium, OverlaySize::Large]
+    }
+}
+
+/// Text burned into each frame of recorded MP4 files before encoding.
+///
+/// The text consists of the camera name, the timestamp of the frame with
+/// microsecond precision and the frame number.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Mp4OverlayConfig {
+    /// Whether the text is drawn.
+    pub enabled: bool,
+    pub corner: OverlayCorner,
+    pub size: OverlaySize,
+}
+
+/// Wire format of the points sent by the ImOps detector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ImOpsFormat {
+    /// A CBOR encoded centroid of all pixels above the threshold. This is the
+    /// original format, which carries a single point.
+    #[default]
+    CborCentroid,
+    /// Little-endian binary encoding of the brightest points.
+    CompactBinary,
+    /// JSON encoding of the brightest points.
+    Json,
+    /// An Open Sound Control message with the brightest points.
+    Osc,
+}
+
+impl std::fmt::Display for ImOpsFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use ImOpsFormat::*;
+        let name = match self {
+            CborCentroid => "CBOR centroid",
+            CompactBinary => "Compact binary",
+            Json => "JSON",
+            Osc => "OSC",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl enum_iter::EnumIter for ImOpsFormat {
+    fn variants() -> Vec<Self> {
+        vec![
+            ImOpsFormat::CborCentroid,
+            ImOpsFormat::CompactBinary,
+            ImOpsFormat::Json,
+            ImOpsFormat::Osc,
+        ]
+    }
+}
+
 type FfmpegCodecArgList = Option<Vec<(String, String)>>;
 
 /// Codec-specific arguments for ffmpeg
@@ -481,6 +768,24 @@ impl enum_iter::EnumIter for CodecSelection {
     }
 }
 
+/// Parameters to start MP4 recording simultaneously on several cameras.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SynchronizedMp4Start {
+    /// Prepended, followed by an underscore, to the MP4 filename.
+    pub filename_prefix: Option<String>,
+    /// Recording starts with the first frame whose timestamp is at or after
+    /// this time, unless `start_synced_frame` applies.
+    pub start_time: chrono::DateTime<chrono::Utc>,
+    /// Recording starts with the first frame whose synchronized frame number
+    /// is at or after this one.
+    ///
+    /// This is the same frame on all synchronized cameras, regardless of
+    /// their clocks. Cameras which do not know their frame offset yet use
+    /// `start_time` instead.
+    #[serde(default)]
+    pub start_synced_frame: Option<u64>,
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum CamArg {
     /// Ignore future frame processing errors for this duration of seconds from current time.
@@ -494,13 +799,47 @@ pub enum CamArg {
     SetFrameRateLimit(f64),
     SetGain(f64),
     SetGainAuto(ci2_types::AutoMode),
+    /// Set the auto-exposure computed by Strand Camera from the image, as YAML.
+    SetSoftAutoExposureConfig(String),
     SetRecordingFps(RecordingFrameRate),
     SetMp4Bitrate(BitrateSelection),
+    /// Set the maximum width of the frames sent to the live view.
+    SetPreviewMaxWidth(PreviewMaxWidth),
+    /// Set the JPEG quality of the frames sent to the live view.
+    SetPreviewJpegQuality(PreviewJpegQuality),
+    /// Show only this region of the camera image in the live view, or the
+    /// entire image if `None`.
+    SetPreviewCrop(Option<http_video_streaming_types::PreviewCrop>),
+    /// Save the rotation and mirroring of the camera images, which is applied
+    /// after Strand Camera is restarted.
+    SetImageTransform(rust_cam_bui_types::ImageTransform),
     SetMp4Codec(CodecSelection),
     SetMp4CudaDevice(String),
     SetMp4MaxFramerate(RecordingFrameRate),
+    /// Continue MP4 recording in a new file after this many minutes.
+    ///
+    /// If `None`, the duration of a file is unlimited.
+    SetMp4RolloverMinutes(Option<f64>),
+    /// Continue MP4 recording in a new file after this many gigabytes.
+    ///
+    /// If `None`, the size of a file is unlimited.
+    SetMp4RolloverGb(Option<f64>),
+    /// Set the text burned into the frames of new MP4 recordings.
+    SetMp4Overlay(Mp4OverlayConfig),
+    /// Draw the detected points into the frames of MP4 recordings, for
+    /// debugging tracking.
+    SetMp4DrawDetections(bool),
     SetIsRecordingMp4(bool),
+    /// Start MP4 recording at a time shared by several cameras.
+    StartSynchronizedMp4(SynchronizedMp4Start),
     SetIsRecordingFmf(bool),
+    /// Losslessly compress each frame of new FMF recordings with zstd.
+    SetFmfZstdCompression(bool),
+    /// Stop writing frames to the ongoing MP4, FMF and CSV recordings without
+    /// closing the files.
+    PauseRecording,
+    /// Continue writing frames to the paused recordings.
+    ResumeRecording,
     /// used only with image-tracker crate
     SetIsRecordingUfmf(bool),
     /// used only with image-tracker crate
@@ -513,11 +852,17 @@ pub enum CamArg {
     CamArgSetLedProgramConfig(String),
     SetFrameOffset(u64),
     SetTriggerboxClockModel(Option<ClockModel>),
+    /// Re-read the PTP clock status from the camera.
+    UpdatePtpStatus,
+    /// Re-read the statistics of the frame buffer pool from the camera.
+    UpdateFramePoolStats,
     SetFormatStr(String),
     ToggleCheckerboardDetection(bool),
     ToggleCheckerboardDebug(bool),
     SetCheckerboardWidth(u32),
     SetCheckerboardHeight(u32),
+    SetCheckerboardPattern(CheckerboardPattern),
+    SetCharucoMarkerRatio(f64),
     ClearCheckerboards,
     PerformCheckerboardCalibration,
     DoQuit,
@@ -532,4 +877,16 @@ pub enum CamArg {
     SetImOpsCenterX(u32),
     SetImOpsCenterY(u32),
     SetImOpsThreshold(u8),
+    /// Set the maximum number of points sent by the ImOps detector.
+    SetImOpsMaxPoints(u8),
+    SetImOpsFormat(ImOpsFormat),
+    /// Save the current settings to the named profile.
+    SaveProfile(String),
+    /// Apply the settings saved in the named profile.
+    LoadProfile(String),
+    /// Set the description of the experiment stored in recorded MP4 files.
+    SetExperimentMetadata(rust_cam_bui_types::ExperimentMetadata),
+    /// Set which events are logged, in the syntax of the `RUST_LOG`
+    /// environment variable.
+    SetLogFilter(String),
 }
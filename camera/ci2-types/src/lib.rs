@@ -45,3 +45,17 @@ pub enum AcquisitionMode {
     SingleFrame,
     MultiFrame,
 }
+
+/// Statistics of the pool of frame buffers used for acquisition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct FramePoolStats {
+    /// Number of frames whose buffer was reused from the pool.
+    pub num_reused: u64,
+    /// Number of buffers allocated for the pool.
+    pub num_allocated: u64,
+    /// Number of frames allocated outside the pool because all buffers of the
+    /// pool were still in use.
+    pub num_exhausted: u64,
+    /// Number of buffers of the pool currently in use.
+    pub num_in_use: u64,
+}
@@ -110,7 +110,7 @@ fn callback_rust(
                 );
 
                 Ok(InvalidHostFramenumber(DynamicFrameWithInfo {
-                    image,
+                    image: Arc::new(image),
                     host_timing: HostTimingInfo {
                         fno: 0, // will be fixed later
                         datetime: now,
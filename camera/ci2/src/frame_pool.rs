@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use basic_frame::{match_all_dynamic_fmts, DynamicFrame};
+use machine_vision_formats as formats;
+
+use crate::FramePoolStats;
+
+/// A pool of reference-counted frame buffers.
+///
+/// Acquired frames are shared by reference count between the consumers of the
+/// frames (e.g. feature detection, the preview encoder and video writers)
+/// rather than being copied. When all consumers have dropped a frame, its
+/// buffer is reused for a later frame rather than being reallocated.
+pub struct FramePool {
+    capacity: usize,
+    frames: Vec<Arc<DynamicFrame>>,
+    stats: FramePoolStats,
+}
+
+impl FramePool {
+    /// Create a pool which holds at most `capacity` buffers.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            frames: Vec::with_capacity(capacity),
+            stats: FramePoolStats::default(),
+        }
+    }
+
+    /// Return a frame with a copy of `image_data`.
+    ///
+    /// If all buffers of the pool are still in use and the pool is at capacity,
+    /// a frame is allocated outside the pool.
+    pub fn frame_from_slice(
+        &mut self,
+        width: u32,
+        height: u32,
+        stride: u32,
+        image_data: &[u8],
+        pixel_format: formats::PixFmt,
+    ) -> Arc<DynamicFrame> {
+        // A buffer is free if the pool holds the only reference to it.
+        for pooled in self.frames.iter_mut() {
+            if let Some(frame) = Arc::get_mut(pooled) {
+                let mut buf =
+                    match_all_dynamic_fmts!(frame, x, { std::mem::take(&mut x.image_data) });
+                buf.clear();
+                buf.extend_from_slice(image_data);
+                *frame = DynamicFrame::new(width, height, stride, buf, pixel_format);
+                self.stats.num_reused += 1;
+                return pooled.clone();
+            }
+        }
+
+        let frame = Arc::new(DynamicFrame::new(
+            width,
+            height,
+            stride,
+            image_data.to_vec(),
+            pixel_format,
+        ));
+        if self.frames.len() < self.capacity {
+            self.frames.push(frame.clone());
+            self.stats.num_allocated += 1;
+        } else {
+            self.stats.num_exhausted += 1;
+        }
+        frame
+    }
+
+    /// Statistics of the pool since it was created.
+    pub fn stats(&self) -> FramePoolStats {
+        let num_in_use = self
+            .frames
+            .iter()
+            .filter(|frame| Arc::strong_count(frame) > 1)
+            .count();
+        FramePoolStats {
+            num_in_use: num_in_use.try_into().unwrap(),
+            ..self.stats
+        }
+    }
+}
+
+#[test]
+fn test_frame_pool() {
+    let mut pool = FramePool::new(2);
+    let pixfmt = formats::PixFmt::Mono8;
+
+    let a = pool.frame_from_slice(2, 1, 2, &[1, 2], pixfmt);
+    let b = pool.frame_from_slice(2, 1, 2, &[3, 4], pixfmt);
+    // Both buffers are in use, so this frame is not from the pool.
+    let c = pool.frame_from_slice(2, 1, 2, &[5, 6], pixfmt);
+    assert_eq!(c.image_data_without_format(), &[5, 6]);
+    assert_eq!(
+        pool.stats(),
+        FramePoolStats {
+            num_reused: 0,
+            num_allocated: 2,
+            num_exhausted: 1,
+            num_in_use: 2,
+        }
+    );
+
+    // The buffer of `a` is reused once it is dropped.
+    let a_ptr = a.image_data_without_format().as_ptr();
+    drop(a);
+    let d = pool.frame_from_slice(2, 1, 2, &[7, 8], pixfmt);
+    assert_eq!(d.image_data_without_format(), &[7, 8]);
+    assert_eq!(d.image_data_without_format().as_ptr(), a_ptr);
+    assert_eq!(b.image_data_without_format(), &[3, 4]);
+    assert_eq!(pool.stats().num_reused, 1);
+    assert_eq!(pool.stats().num_in_use, 2);
+}
@@ -1,7 +1,12 @@
+use std::sync::Arc;
+
 use basic_frame::DynamicFrame;
-pub use ci2_types::{AcquisitionMode, AutoMode, TriggerMode, TriggerSelector};
+pub use ci2_types::{AcquisitionMode, AutoMode, FramePoolStats, TriggerMode, TriggerSelector};
 use machine_vision_formats as formats;
 
+mod frame_pool;
+pub use frame_pool::FramePool;
+
 // TODO add binning support
 
 // ---------------------------
@@ -81,7 +86,10 @@ pub trait CameraModule: Send {
 #[derive(Clone)]
 pub struct DynamicFrameWithInfo {
     /// The image frame acquired from the camera.
-    pub image: DynamicFrame,
+    ///
+    /// The frame is shared by reference count, so cloning is cheap and does
+    /// not copy the image data.
+    pub image: Arc<DynamicFrame>,
     /// Frame timing information acquired by the host.
     pub host_timing: HostTimingInfo,
     /// Backend-specific information about the frame.
@@ -268,4 +276,11 @@ pub trait Camera: CameraInfo + Send {
     // data do not have to be made.
     // TODO: specify timeout
     fn next_frame(&mut self) -> Result<DynamicFrameWithInfo>;
+
+    /// Statistics of the [FramePool] used for acquisition.
+    ///
+    /// This is `None` if the backend does not use a frame pool.
+    fn frame_pool_stats(&self) -> Option<FramePoolStats> {
+        None
+    }
 }
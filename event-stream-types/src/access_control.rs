@@ -0,0 +1,343 @@
+//! Read-only and control access to a web UI.
+//!
+//! The session layer (`axum_token_auth`) grants a session to browsers which
+//! present the pre-shared token. Additionally, read-only tokens can be
+//! configured. A browser presenting a read-only token is granted a session with
+//! the [AccessRole::ReadOnly] role, with which the state and the live view can
+//! be seen but nothing can be changed. The role of a session is kept in a
+//! signed cookie next to the session cookie.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use bui_backend_session_types::AccessToken;
+use http::{
+    header::{COOKIE, SET_COOKIE},
+    HeaderValue, StatusCode,
+};
+
+/// The access granted to a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessRole {
+    /// Can view the state and the live view but not change anything.
+    ReadOnly,
+    /// Full access.
+    Control,
+}
+
+impl AccessRole {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AccessRole::ReadOnly => "read-only",
+            AccessRole::Control => "control",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "read-only" => Some(AccessRole::ReadOnly),
+            "control" => Some(AccessRole::Control),
+            _ => None,
+        }
+    }
+
+    /// Reject the request unless the role is [AccessRole::Control].
+    pub fn require_control(&self) -> Result<(), (StatusCode, &'static str)> {
+        match self {
+            AccessRole::Control => Ok(()),
+            AccessRole::ReadOnly => Err((StatusCode::FORBIDDEN, "Access is read-only")),
+        }
+    }
+}
+
+struct Inner {
+    control_token: String,
+    read_only_tokens: Vec<String>,
+    key: cookie::Key,
+    cookie_name: String,
+}
+
+/// Configuration for [access_control].
+///
+/// Clones share the configuration.
+#[derive(Clone)]
+pub struct AccessControl {
+    inner: Option<Arc<Inner>>,
+}
+
+impl AccessControl {
+    /// Create the configuration.
+    ///
+    /// `token` is the token accepted by the session layer, which grants
+    /// control. If there is no such token or no `read_only_tokens`, all
+    /// sessions have the [AccessRole::Control] role. `key` signs the cookie
+    /// named `cookie_name` holding the role.
+    pub fn new(
+        token: &AccessToken,
+        read_only_tokens: Vec<String>,
+        key: cookie::Key,
+        cookie_name: &str,
+    ) -> Self {
+        let inner = match token {
+            AccessToken::PreSharedToken(control_token) if !read_only_tokens.is_empty() => {
+                Some(Arc::new(Inner {
+                    control_token: control_token.clone(),
+                    read_only_tokens,
+                    key,
+                    cookie_name: cookie_name.to_string(),
+                }))
+            }
+            AccessToken::PreSharedToken(_) => None,
+            AccessToken::NoToken => {
+                if !read_only_tokens.is_empty() {
+                    tracing::warn!(
+                        "Ignoring read-only tokens because the HTTP server does not \
+                        require a token."
+                    );
+                }
+                None
+            }
+        };
+        Self { inner }
+    }
+}
+
+/// Middleware determining the [AccessRole] of each request.
+///
+/// The role is inserted into the request extensions, from where handlers can
+/// get it with `axum::Extension<AccessRole>`. This must run before the session
+/// layer, which is not aware of the read-only tokens.
+pub async fn access_control(
+    State(access_control): State<AccessControl>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let Some(inner) = access_control.inner else {
+        req.extensions_mut().insert(AccessRole::Control);
+        return next.run(req).await;
+    };
+
+    let (role, is_new) = match query_token(req.uri()) {
+        Some(token) if token == inner.control_token => (AccessRole::Control, true),
+        Some(token) if inner.read_only_tokens.contains(&token) => {
+            // Present the control token to the session layer, which knows only
+            // that. The role cookie limits the session.
+            let uri = replace_query_token(req.uri(), &inner.control_token);
+            *req.uri_mut() = uri;
+            (AccessRole::ReadOnly, true)
+        }
+        _ => (
+            role_from_cookies(&inner, req.headers()).unwrap_or(AccessRole::ReadOnly),
+            false,
+        ),
+    };
+
+    req.extensions_mut().insert(role);
+    let mut response = next.run(req).await;
+
+    if is_new {
+        let mut jar = cookie::CookieJar::new();
+        let mut cookie = cookie::Cookie::new(inner.cookie_name.clone(), role.as_str());
+        cookie.set_path("/");
+        cookie.set_http_only(true);
+        cookie.set_same_site(cookie::SameSite::Lax);
+        cookie.make_permanent();
+        jar.signed_mut(&inner.key).add(cookie);
+        for cookie in jar.delta() {
+            if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+                response.headers_mut().append(SET_COOKIE, value);
+            }
+        }
+    }
+    response
+}
+
+fn query_token(uri: &http::Uri) -> Option<String> {
+    let query = uri.query()?;
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(name, _)| name == "token")
+        .map(|(_, value)| value.into_owned())
+}
+
+fn replace_query_token(uri: &http::Uri, token: &str) -> http::Uri {
+    let query = uri.query().unwrap_or_default();
+    let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+    for (name, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        if name == "token" {
+            serializer.append_pair(&name, token);
+        } else {
+            serializer.append_pair(&name, &value);
+        }
+    }
+    let path_and_query = format!("{}?{}", uri.path(), serializer.finish());
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().expect("valid path and query"));
+    http::Uri::from_parts(parts).expect("valid uri")
+}
+
+fn role_from_cookies(inner: &Inner, headers: &http::HeaderMap) -> Option<AccessRole> {
+    let mut jar = cookie::CookieJar::new();
+    for value in headers.get_all(COOKIE).iter() {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for cookie in cookie::Cookie::split_parse(value).flatten() {
+            if cookie.name() == inner.cookie_name {
+                jar.add_original(cookie.into_owned());
+            }
+        }
+    }
+    let cookie = jar.signed(&inner.key).get(&inner.cookie_name)?;
+    AccessRole::from_str(cookie.value())
+}
+
+#[test]
+fn test_replace_query_token() {
+    let uri: http::Uri = "/cam-proxy/x/?a=1&token=abc".parse().unwrap();
+    assert_eq!(query_token(&uri).as_deref(), Some("abc"));
+    let uri = replace_query_token(&uri, "xyz");
+    assert_eq!(uri.path(), "/cam-proxy/x/");
+    assert_eq!(uri.query(), Some("a=1&token=xyz"));
+}
+
+/// A router with `/`, which responds with the role and the query, and
+/// `/control`, which requires [AccessRole::Control].
+#[cfg(test)]
+fn test_router(access_control: AccessControl) -> axum::Router {
+    use axum::{routing::get, Extension};
+    axum::Router::new()
+        .route(
+            "/",
+            get(
+                |Extension(role): Extension<AccessRole>, uri: http::Uri| async move {
+                    format!("{} {}", role.as_str(), uri.query().unwrap_or_default())
+                },
+            ),
+        )
+        .route(
+            "/control",
+            get(|Extension(role): Extension<AccessRole>| async move {
+                role.require_control().map(|()| "ok")
+            }),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            access_control,
+            self::access_control,
+        ))
+}
+
+/// Request `uri`, sending `cookie` if given. Returns the status, the body and
+/// the cookie set in the response, if any.
+#[cfg(test)]
+async fn test_request(
+    router: &axum::Router,
+    uri: &str,
+    cookie: Option<&str>,
+) -> (StatusCode, String, Option<String>) {
+    use tower::ServiceExt;
+    let mut req = http::Request::builder().uri(uri);
+    if let Some(cookie) = cookie {
+        req = req.header(COOKIE, cookie);
+    }
+    let req = req.body(axum::body::Body::empty()).unwrap();
+    let response = router.clone().oneshot(req).await.unwrap();
+    let status = response.status();
+    let set_cookie = response.headers().get(SET_COOKIE).map(|value| {
+        value
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string()
+    });
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (
+        status,
+        String::from_utf8(body.to_vec()).unwrap(),
+        set_cookie,
+    )
+}
+
+#[tokio::test]
+async fn test_access_control() {
+    let token = AccessToken::PreSharedToken("secret".to_string());
+    let key = cookie::Key::from(&[7u8; 64][..]);
+    let router = test_router(AccessControl::new(
+        &token,
+        vec!["viewer".to_string()],
+        key,
+        "role",
+    ));
+
+    // The control token grants control and sets the role cookie.
+    let (status, body, control_cookie) = test_request(&router, "/?token=secret", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "control token=secret");
+    let control_cookie = control_cookie.unwrap();
+
+    // A read-only token is replaced by the control token for the session
+    // layer, but the role is read-only.
+    let (status, body, read_only_cookie) = test_request(&router, "/?a=1&token=viewer", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "read-only a=1&token=secret");
+    let read_only_cookie = read_only_cookie.unwrap();
+
+    // Later requests get the role from the cookie.
+    let (_, body, set_cookie) = test_request(&router, "/", Some(&control_cookie)).await;
+    assert_eq!(body, "control ");
+    assert_eq!(set_cookie, None);
+    let (status, body, _) = test_request(&router, "/control", Some(&control_cookie)).await;
+    assert_eq!((status, body.as_str()), (StatusCode::OK, "ok"));
+    let (_, body, _) = test_request(&router, "/", Some(&read_only_cookie)).await;
+    assert_eq!(body, "read-only ");
+    let (status, _, _) = test_request(&router, "/control", Some(&read_only_cookie)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Without a valid token or signed cookie, access is read-only.
+    for cookie in [None, Some("role=control")] {
+        let (status, body, set_cookie) = test_request(&router, "/?token=wrong", cookie).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "read-only token=wrong");
+        assert_eq!(set_cookie, None);
+        let (status, _, _) = test_request(&router, "/control", cookie).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    // A cookie signed with another key is not accepted.
+    let other = test_router(AccessControl::new(
+        &token,
+        vec!["viewer".to_string()],
+        cookie::Key::from(&[8u8; 64][..]),
+        "role",
+    ));
+    let (status, _, _) = test_request(&other, "/control", Some(&control_cookie)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_access_control_disabled() {
+    // Without read-only tokens or without a token, everybody has control.
+    for (token, read_only_tokens) in [
+        (AccessToken::PreSharedToken("secret".to_string()), vec![]),
+        (AccessToken::NoToken, vec!["viewer".to_string()]),
+    ] {
+        let router = test_router(AccessControl::new(
+            &token,
+            read_only_tokens,
+            cookie::Key::from(&[7u8; 64][..]),
+            "role",
+        ));
+        for uri in ["/control", "/control?token=viewer"] {
+            let (status, body, set_cookie) = test_request(&router, uri, None).await;
+            assert_eq!((status, body.as_str()), (StatusCode::OK, "ok"));
+            assert_eq!(set_cookie, None);
+        }
+    }
+}
@@ -12,6 +12,9 @@ use std::{
 use tokio::sync::mpsc::Sender;
 use tokio_stream::wrappers::ReceiverStream;
 
+mod access_control;
+pub use access_control::{access_control, AccessControl, AccessRole};
+
 pub type EventChunkSender = Sender<Result<Frame<Bytes>, Infallible>>;
 type EventReceiver = ReceiverStream<Result<Frame<Bytes>, Infallible>>;
 
@@ -128,6 +131,66 @@ impl axum::response::IntoResponse for EventsBody {
     }
 }
 
+// websocket ---------------------------
+
+/// Serve a WebSocket connection as an alternative to an event stream.
+///
+/// Each event sent to `events` is sent as one text message, in the same format
+/// as in the event stream (`event: <name>\ndata: <data>\n\n`). Text messages
+/// from the client are decoded as JSON and passed to `on_message`, much like
+/// the bodies of POST requests to a callback handler. This returns when the
+/// connection is closed by either side.
+pub async fn serve_websocket<T, F, Fut>(
+    mut socket: axum::extract::ws::WebSocket,
+    mut events: EventsBody,
+    mut on_message: F,
+) where
+    T: serde::de::DeserializeOwned,
+    F: FnMut(T) -> Fut,
+    Fut: std::future::Future<Output = ()>,
+{
+    use axum::extract::ws::Message;
+    loop {
+        tokio::select! {
+            event = events.events.next() => {
+                let Some(Ok(frame)) = event else {
+                    // All senders were dropped.
+                    break;
+                };
+                let Ok(data) = frame.into_data() else {
+                    continue;
+                };
+                let text = String::from_utf8_lossy(&data).into_owned();
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    tracing::debug!("websocket send error");
+                    break;
+                }
+            }
+            msg = socket.recv() => {
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        match serde_json::from_str(text.as_str()) {
+                            Ok(payload) => on_message(payload).await,
+                            Err(e) => tracing::error!("could not decode websocket message: {e}"),
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => {
+                        break;
+                    }
+                    Some(Ok(_)) => {
+                        // Ping and pong are handled by axum. Binary messages are
+                        // not used.
+                    }
+                    Some(Err(e)) => {
+                        tracing::debug!("websocket receive error: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
 // -----
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -0,0 +1,60 @@
+use futures::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message;
+
+use event_stream_types::{serve_websocket, EventBroadcaster};
+
+#[derive(Debug, PartialEq, serde::Deserialize)]
+struct Callback {
+    value: u32,
+}
+
+#[tokio::test]
+async fn test_websocket() {
+    let broadcaster: EventBroadcaster<u32> = Default::default();
+    let (callback_tx, mut callback_rx) = tokio::sync::mpsc::channel(10);
+    let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+    let done_tx = std::sync::Arc::new(std::sync::Mutex::new(Some(done_tx)));
+
+    let router = {
+        let broadcaster = broadcaster.clone();
+        axum::Router::new().route(
+            "/ws",
+            axum::routing::get(move |ws: axum::extract::ws::WebSocketUpgrade| async move {
+                let (_tx, body) = broadcaster.new_connection(1);
+                ws.on_upgrade(move |socket| async move {
+                    serve_websocket(socket, body, |payload: Callback| {
+                        let callback_tx = callback_tx.clone();
+                        async move { callback_tx.send(payload).await.unwrap() }
+                    })
+                    .await;
+                    let done_tx = done_tx.lock().unwrap().take().unwrap();
+                    done_tx.send(()).unwrap();
+                })
+            }),
+        )
+    };
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    // Handshake
+    let (mut ws, response) = tokio_tungstenite::connect_async(format!("ws://{addr}/ws"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), http::StatusCode::SWITCHING_PROTOCOLS);
+
+    // Events are sent as text messages, unchanged.
+    let event = "event: test\ndata: {\"a\":1}\n\n".to_string();
+    broadcaster.broadcast_frame(event.clone()).await;
+    let msg = ws.next().await.unwrap().unwrap();
+    assert_eq!(msg.to_text().unwrap(), event);
+
+    // Text messages are decoded as callbacks. Invalid ones are skipped.
+    ws.send(Message::text("not json")).await.unwrap();
+    ws.send(Message::text("{\"value\":42}")).await.unwrap();
+    assert_eq!(callback_rx.recv().await.unwrap(), Callback { value: 42 });
+
+    // Closing the connection ends `serve_websocket`.
+    ws.close(None).await.unwrap();
+    done_rx.await.unwrap();
+}
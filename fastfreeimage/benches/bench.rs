@@ -5,7 +5,7 @@ extern crate itertools;
 #[macro_use]
 extern crate criterion;
 
-use criterion::Criterion;
+use criterion::{BenchmarkId, Criterion, Throughput};
 use itertools::multizip;
 
 use fastfreeimage::{ipp_ctypes, ripp, Chan1, CompareOp, FastImage, FastImageData};
@@ -50,6 +50,31 @@ fn bench_abs_diff_8u_c1r(c: &mut Criterion) {
     });
 }
 
+/// Sizes of common camera sensors. 1440 is not a multiple of the SIMD vector
+/// width, so the remainder of each row is handled separately.
+const SENSOR_SIZES: [(usize, usize); 3] = [(640, 480), (1440, 1080), (2048, 2048)];
+
+fn bench_abs_diff_8u_c1r_sizes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("abs_diff_8u_c1r_sizes");
+    for (w, h) in SENSOR_SIZES {
+        let im10 =
+            FastImageData::<Chan1, u8>::new(w as ipp_ctypes::c_int, h as ipp_ctypes::c_int, 10)
+                .unwrap();
+        let im9 =
+            FastImageData::<Chan1, u8>::new(w as ipp_ctypes::c_int, h as ipp_ctypes::c_int, 9)
+                .unwrap();
+        let mut im_dest =
+            FastImageData::<Chan1, u8>::new(w as ipp_ctypes::c_int, h as ipp_ctypes::c_int, 0)
+                .unwrap();
+        let size = *im_dest.size();
+        group.throughput(Throughput::Bytes((w * h) as u64));
+        group.bench_function(BenchmarkId::from_parameter(format!("{w}x{h}")), |b| {
+            b.iter(|| ripp::abs_diff_8u_c1r(&im10, &im9, &mut im_dest, &size).unwrap())
+        });
+    }
+    group.finish();
+}
+
 fn bench_max_indx_8u_c1r(c: &mut Criterion) {
     let im10 = FastImageData::<Chan1, u8>::new(W as ipp_ctypes::c_int, H as ipp_ctypes::c_int, 10)
         .unwrap();
@@ -103,7 +128,7 @@ fn bench_abs_diff_naive_v6(c: &mut Criterion) {
 criterion_group! {
     name = benches;
     config = Criterion::default();
-    targets = bench_set_8u_c1mr, bench_threshold_val_8u_c1ir, bench_max_indx_8u_c1r, bench_abs_diff_8u_c1r, bench_abs_diff_naive_v2, bench_abs_diff_naive_v6
+    targets = bench_set_8u_c1mr, bench_threshold_val_8u_c1ir, bench_max_indx_8u_c1r, bench_abs_diff_8u_c1r, bench_abs_diff_8u_c1r_sizes, bench_abs_diff_naive_v2, bench_abs_diff_naive_v6
 }
 
 criterion_main!(benches);
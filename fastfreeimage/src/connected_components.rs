@@ -0,0 +1,182 @@
+//! Connected components labeling of binary images.
+//!
+//! This is a two pass algorithm operating on runs of foreground pixels. In the
+//! first pass, runs overlapping runs of the previous row are merged with a
+//! union-find structure. In the second pass, the statistics of each component
+//! are collected from its runs.
+
+use crate::{ipp_ctypes, Chan1, FastImage, FastImageRegion, FastImageSize, Point, Result};
+
+/// Which neighboring pixels are connected.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Connectivity {
+    /// Pixels sharing an edge.
+    Four,
+    /// Pixels sharing an edge or a corner.
+    Eight,
+}
+
+/// A connected component of foreground pixels.
+#[derive(Debug, Clone)]
+pub struct Component {
+    /// The number of pixels.
+    pub area: usize,
+    /// The smallest region containing all pixels.
+    pub bbox: FastImageRegion,
+    /// The mean x and y coordinates of the pixels.
+    pub centroid: (f64, f64),
+    /// The pixels, if requested.
+    pub pixels: Option<Vec<Point>>,
+}
+
+/// A horizontal run of foreground pixels from `start` to `end` (exclusive).
+struct Run {
+    y: ipp_ctypes::c_int,
+    start: ipp_ctypes::c_int,
+    end: ipp_ctypes::c_int,
+    label: usize,
+}
+
+/// Statistics of a component collected from its runs.
+struct Accum {
+    sum_x: f64,
+    sum_y: f64,
+    left: ipp_ctypes::c_int,
+    bottom: ipp_ctypes::c_int,
+    right: ipp_ctypes::c_int,
+    top: ipp_ctypes::c_int,
+}
+
+fn find(parent: &mut [usize], mut label: usize) -> usize {
+    while parent[label] != label {
+        // Path halving
+        parent[label] = parent[parent[label]];
+        label = parent[label];
+    }
+    label
+}
+
+fn union(parent: &mut [usize], a: usize, b: usize) {
+    let a = find(parent, a);
+    let b = find(parent, b);
+    // The smaller label, which was seen first, remains the root.
+    if a < b {
+        parent[b] = a;
+    } else {
+        parent[a] = b;
+    }
+}
+
+/// Find the connected components of the non-zero pixels of `src`.
+///
+/// The components are returned in the order of their first pixel when
+/// scanning row by row. If `keep_pixels` is true, the pixels of each component
+/// are returned in the same order.
+pub fn connected_components_8u_c1r<S>(
+    src: &S,
+    size: &FastImageSize,
+    connectivity: Connectivity,
+    keep_pixels: bool,
+) -> Result<Vec<Component>>
+where
+    S: FastImage<D = u8, C = Chan1>,
+{
+    // With 8-connectivity, runs touching diagonally are connected.
+    let reach = match connectivity {
+        Connectivity::Four => 0,
+        Connectivity::Eight => 1,
+    };
+
+    // First pass: find runs and merge overlapping runs of successive rows.
+    let mut runs: Vec<Run> = Vec::new();
+    let mut parent: Vec<usize> = Vec::new();
+    let mut prev_row_start = 0;
+    for (y, row) in src.valid_row_iter(size)?.enumerate() {
+        let y = y as ipp_ctypes::c_int;
+        let row_start = runs.len();
+        let mut x = 0;
+        while x < row.len() {
+            if row[x] == 0 {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < row.len() && row[x] != 0 {
+                x += 1;
+            }
+            let label = parent.len();
+            parent.push(label);
+            runs.push(Run {
+                y,
+                start: start as ipp_ctypes::c_int,
+                end: x as ipp_ctypes::c_int,
+                label,
+            });
+        }
+
+        // Both rows have their runs sorted by x, so overlaps are found by
+        // advancing through them together.
+        let (prev, cur) = runs[prev_row_start..].split_at(row_start - prev_row_start);
+        let mut j = 0;
+        for run in cur {
+            while j < prev.len() && prev[j].end + reach <= run.start {
+                j += 1;
+            }
+            for other in prev[j..]
+                .iter()
+                .take_while(|other| other.start < run.end + reach)
+            {
+                union(&mut parent, run.label, other.label);
+            }
+        }
+        prev_row_start = row_start;
+    }
+
+    // Second pass: collect the statistics of each component.
+    let mut component_idx: Vec<Option<usize>> = vec![None; parent.len()];
+    let mut components: Vec<Component> = Vec::new();
+    let mut accum: Vec<Accum> = Vec::new();
+    for run in runs.iter() {
+        let root = find(&mut parent, run.label);
+        let idx = *component_idx[root].get_or_insert_with(|| {
+            components.push(Component {
+                area: 0,
+                bbox: FastImageRegion::new(Point::new(0, 0), FastImageSize::new(0, 0)),
+                centroid: (0.0, 0.0),
+                pixels: keep_pixels.then(Vec::new),
+            });
+            accum.push(Accum {
+                sum_x: 0.0,
+                sum_y: 0.0,
+                left: run.start,
+                bottom: run.y,
+                right: run.end,
+                top: run.y + 1,
+            });
+            components.len() - 1
+        });
+        let comp = &mut components[idx];
+        let len = run.end - run.start;
+        comp.area += len as usize;
+        if let Some(pixels) = comp.pixels.as_mut() {
+            pixels.extend((run.start..run.end).map(|x| Point::new(x, run.y)));
+        }
+        let acc = &mut accum[idx];
+        // Sum of the arithmetic series run.start, ..., run.end - 1.
+        acc.sum_x += f64::from(run.start + run.end - 1) * f64::from(len) / 2.0;
+        acc.sum_y += f64::from(run.y) * f64::from(len);
+        acc.left = acc.left.min(run.start);
+        acc.bottom = acc.bottom.min(run.y);
+        acc.right = acc.right.max(run.end);
+        acc.top = acc.top.max(run.y + 1);
+    }
+    for (comp, acc) in components.iter_mut().zip(accum) {
+        let area = comp.area as f64;
+        comp.centroid = (acc.sum_x / area, acc.sum_y / area);
+        comp.bbox = FastImageRegion::new(
+            Point::new(acc.left, acc.bottom),
+            FastImageSize::new(acc.right - acc.left, acc.top - acc.bottom),
+        );
+    }
+    Ok(components)
+}
@@ -0,0 +1,150 @@
+//! Integral images and box filters computed from them.
+//!
+//! As in IPP, the integral image of an image of size `(w, h)` has size
+//! `(w + 1, h + 1)`. The value at row `y` and column `x` is the sum of all
+//! pixels above and left of it, so the first row and column are zero. The sum
+//! over any rectangle is then found from its four corners, independent of the
+//! size of the rectangle.
+
+use crate::{Chan1, Error, FastImage, FastImageSize, MutableFastImage, Result};
+
+fn integral<S, D, T>(src: &S, dest: &mut D, size: &FastImageSize) -> Result<()>
+where
+    S: FastImage<D = u8, C = Chan1>,
+    D: MutableFastImage<D = T, C = Chan1>,
+    T: Copy + PartialEq + Default + std::ops::Add<Output = T> + From<u8>,
+{
+    let dest_size = FastImageSize::new(size.width() + 1, size.height() + 1);
+    let mut dest_rows = dest.valid_row_iter_mut(&dest_size)?;
+    // The height of `dest_size` is at least one.
+    let mut prev = dest_rows.next().unwrap();
+    prev.fill(T::default());
+    for (src_row, dest_row) in src.valid_row_iter(size)?.zip(dest_rows) {
+        dest_row[0] = T::default();
+        let mut row_sum = T::default();
+        for (dest_el, src_el) in dest_row[1..].iter_mut().zip(src_row.iter()) {
+            row_sum = row_sum + T::from(*src_el);
+            *dest_el = row_sum;
+        }
+        // Add the row above. This loop has no dependency between elements and
+        // is vectorized by the compiler.
+        for (dest_el, prev_el) in dest_row.iter_mut().zip(prev.iter()) {
+            *dest_el = *dest_el + *prev_el;
+        }
+        prev = dest_row;
+    }
+    Ok(())
+}
+
+/// Compute the integral image of `src` into `dest` with `u32` values.
+///
+/// `dest` must be at least one pixel wider and higher than `size`. The sums
+/// overflow for images with more than 2^24 pixels.
+pub fn integral_8u32u_c1r<S, D>(src: &S, dest: &mut D, size: &FastImageSize) -> Result<()>
+where
+    S: FastImage<D = u8, C = Chan1>,
+    D: MutableFastImage<D = u32, C = Chan1>,
+{
+    integral(src, dest, size)
+}
+
+/// Compute the integral image of `src` into `dest` with `f32` values.
+///
+/// `dest` must be at least one pixel wider and higher than `size`. Sums above
+/// 2^24, which can occur in images with more than 65793 pixels, are not exact
+/// in `f32`, and neither are box means computed from them. Use
+/// [integral_8u32u_c1r] for exact sums.
+pub fn integral_8u32f_c1r<S, D>(src: &S, dest: &mut D, size: &FastImageSize) -> Result<()>
+where
+    S: FastImage<D = u8, C = Chan1>,
+    D: MutableFastImage<D = f32, C = Chan1>,
+{
+    integral(src, dest, size)
+}
+
+/// Compute a row of the mean of each `(2 * radius + 1)` square window.
+///
+/// `top` and `bottom` are the rows of the integral image at the top and bottom
+/// of the windows, which are `n_rows` apart, and must be at least one element
+/// longer than `out`. This is shared with the IPP implementation in
+/// `fastimage`.
+pub fn box_mean_row<T>(top: &[T], bottom: &[T], n_rows: usize, radius: usize, out: &mut [f32])
+where
+    T: Copy + Into<f64>,
+{
+    let width = out.len();
+    for (x, dest_el) in out.iter_mut().enumerate() {
+        // The window is clipped at the image borders.
+        let x0 = x.saturating_sub(radius);
+        let x1 = (x + radius + 1).min(width);
+        let sum = bottom[x1].into() - bottom[x0].into() - top[x1].into() + top[x0].into();
+        *dest_el = (sum / (n_rows * (x1 - x0)) as f64) as f32;
+    }
+}
+
+fn box_mean<S, D, T>(integral: &S, dest: &mut D, size: &FastImageSize, radius: u32) -> Result<()>
+where
+    S: FastImage<D = T, C = Chan1>,
+    D: MutableFastImage<D = f32, C = Chan1>,
+    T: Copy + PartialEq + Into<f64>,
+{
+    if integral.width() <= size.width() || integral.height() <= size.height() {
+        return Err(Error::SizeError);
+    }
+    let width = size.width() as usize;
+    let height = size.height() as usize;
+    let stride = integral.stride() as usize / std::mem::size_of::<T>();
+    let data = integral.image_slice();
+    let integral_row = |y: usize| &data[y * stride..y * stride + width + 1];
+    let radius = radius as usize;
+    for (y, dest_row) in dest.valid_row_iter_mut(size)?.enumerate() {
+        // The window is clipped at the image borders.
+        let y0 = y.saturating_sub(radius);
+        let y1 = (y + radius + 1).min(height);
+        box_mean_row(
+            integral_row(y0),
+            integral_row(y1),
+            y1 - y0,
+            radius,
+            dest_row,
+        );
+    }
+    Ok(())
+}
+
+/// Compute the mean of each `(2 * radius + 1)` square window of an image from
+/// its `u32` integral image.
+///
+/// `size` is the size of the image, so `integral` must be at least one pixel
+/// wider and higher. At the borders, the mean is over the part of the window
+/// within the image. This is useful as the local threshold for adaptive
+/// thresholding.
+pub fn box_mean_32u32f_c1r<S, D>(
+    integral: &S,
+    dest: &mut D,
+    size: &FastImageSize,
+    radius: u32,
+) -> Result<()>
+where
+    S: FastImage<D = u32, C = Chan1>,
+    D: MutableFastImage<D = f32, C = Chan1>,
+{
+    box_mean(integral, dest, size, radius)
+}
+
+/// Compute the mean of each `(2 * radius + 1)` square window of an image from
+/// its `f32` integral image.
+///
+/// See [box_mean_32u32f_c1r], which is exact for larger images.
+pub fn box_mean_32f_c1r<S, D>(
+    integral: &S,
+    dest: &mut D,
+    size: &FastImageSize,
+    radius: u32,
+) -> Result<()>
+where
+    S: FastImage<D = f32, C = Chan1>,
+    D: MutableFastImage<D = f32, C = Chan1>,
+{
+    box_mean(integral, dest, size, radius)
+}
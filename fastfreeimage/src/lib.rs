@@ -15,6 +15,17 @@ pub const COMPILED_WITH_SIMD_SUPPORT: bool = true;
 #[cfg(not(feature = "portsimd"))]
 pub const COMPILED_WITH_SIMD_SUPPORT: bool = false;
 
+mod connected_components;
+pub use connected_components::{connected_components_8u_c1r, Component, Connectivity};
+
+pub mod integral_image;
+pub use integral_image::{
+    box_mean_32f_c1r, box_mean_32u32f_c1r, integral_8u32f_c1r, integral_8u32u_c1r,
+};
+
+pub mod morphology;
+pub use morphology::{close_8u_c1ir, dilate_8u_c1r, erode_8u_c1r, open_8u_c1ir, MorphologyBuffer};
+
 // ---------------------------
 // errors
 
@@ -1531,16 +1542,34 @@ pub mod ripp {
         Ok(())
     }
 
+    /// Compute the spatial and central moments up to 2nd order.
+    ///
+    /// Within each row, the sums are exact integers. These are accumulated
+    /// over rows as `f64`.
     pub fn moments_8u_c1r<S>(src: &S, size: &FastImageSize, result: &mut MomentState) -> Result<()>
     where
         S: FastImage<D = u8, C = Chan1>,
     {
-        let roi = FastImageRegion::new(Point::new(0, 0), *size);
-
-        let im_view1 = FastImageView::view_region(src, &roi);
-        let im_view: &dyn FastImage<C = Chan1, D = u8> = &im_view1?;
-
-        result.results = Some(imops::calculate_moments(&im_view));
+        let mut m = RawMoments::default();
+        for (y, row) in src.valid_row_iter(size)?.enumerate() {
+            let (mut s0, mut s1, mut s2) = (0u64, 0u64, 0u64);
+            for (x, val) in row.iter().enumerate() {
+                let val = u64::from(*val);
+                let x = x as u64;
+                s0 += val;
+                s1 += x * val;
+                s2 += x * x * val;
+            }
+            let (s0, s1, s2) = (s0 as f64, s1 as f64, s2 as f64);
+            let y = y as f64;
+            m.m00 += s0;
+            m.m10 += s1;
+            m.m01 += y * s0;
+            m.m20 += s2;
+            m.m11 += y * s1;
+            m.m02 += y * y * s0;
+        }
+        result.results = Some(m);
         Ok(())
     }
 
@@ -1615,20 +1644,34 @@ pub enum CompareOp {
     Greater,
 }
 
+/// Spatial moments up to 2nd order, with the origin at the ROI origin.
+///
+/// `mXY` is the sum of `x^X * y^Y * value` over all pixels.
+#[derive(Clone, Copy, Debug, Default)]
+struct RawMoments {
+    m00: f64,
+    m10: f64,
+    m01: f64,
+    m20: f64,
+    m11: f64,
+    m02: f64,
+}
+
+/// Image moments up to 2nd order, as computed by [ripp::moments_8u_c1r].
+///
+/// Like in IPP, `m_ord` is the order in x (the column) and `n_ord` the order
+/// in y (the row).
 pub struct MomentState {
-    results: Option<imops::Moments>,
+    results: Option<RawMoments>,
 }
 
 impl MomentState {
     pub fn new(_hint_algorithm: AlgorithmHint) -> Result<MomentState> {
         Ok(MomentState { results: None })
     }
-    // fn as_mut_ptr(&mut self) -> *mut ipp_sys::MomentState64f {
-    //     self.data.as_mut_ptr() as *mut ipp_sys::MomentState64f
-    // }
-    // fn as_ptr(&self) -> *const ipp_sys::MomentState64f {
-    //     self.data.as_ptr() as *const ipp_sys::MomentState64f
-    // }
+
+    /// Return the spatial moment with the origin `roi_offset` pixels left of
+    /// and above the ROI origin.
     pub fn spatial(
         &self,
         m_ord: ipp_ctypes::c_int,
@@ -1636,23 +1679,28 @@ impl MomentState {
         n_channel: ipp_ctypes::c_int,
         roi_offset: &Point,
     ) -> Result<f64> {
-        if roi_offset != &Point::new(0, 0) {
-            return Err(Error::NotImplemented);
-        }
         if n_channel != 0 {
             return Err(Error::NotImplemented);
         }
-        if let Some(results) = self.results.as_ref() {
-            match (m_ord, n_ord) {
-                (0, 0) => Ok(results.m00.into()),
-                (0, 1) => Ok(results.m01.into()),
-                (1, 0) => Ok(results.m10.into()),
-                _ => Err(Error::MomentStateNotInitialized),
-            }
-        } else {
-            Err(Error::MomentStateNotInitialized)
+        let m = self
+            .results
+            .as_ref()
+            .ok_or(Error::MomentStateNotInitialized)?;
+        let ox = f64::from(roi_offset.x());
+        let oy = f64::from(roi_offset.y());
+        // Expand the sum of `(x+ox)^m_ord * (y+oy)^n_ord * value`.
+        match (m_ord, n_ord) {
+            (0, 0) => Ok(m.m00),
+            (1, 0) => Ok(m.m10 + ox * m.m00),
+            (0, 1) => Ok(m.m01 + oy * m.m00),
+            (2, 0) => Ok(m.m20 + 2.0 * ox * m.m10 + ox * ox * m.m00),
+            (1, 1) => Ok(m.m11 + ox * m.m01 + oy * m.m10 + ox * oy * m.m00),
+            (0, 2) => Ok(m.m02 + 2.0 * oy * m.m01 + oy * oy * m.m00),
+            _ => Err(Error::NotImplemented),
         }
     }
+
+    /// Return the central moment, i.e. with the origin at the centroid.
     pub fn central(
         &self,
         m_ord: ipp_ctypes::c_int,
@@ -1662,15 +1710,17 @@ impl MomentState {
         if n_channel != 0 {
             return Err(Error::NotImplemented);
         }
-        if let Some(results) = self.results.as_ref() {
-            match (m_ord, n_ord) {
-                (1, 1) => Ok(results.u11.into()),
-                (0, 2) => Ok(results.u02.into()),
-                (2, 0) => Ok(results.u20.into()),
-                _ => Err(Error::MomentStateNotInitialized),
-            }
-        } else {
-            Err(Error::MomentStateNotInitialized)
+        let m = self
+            .results
+            .as_ref()
+            .ok_or(Error::MomentStateNotInitialized)?;
+        match (m_ord, n_ord) {
+            (0, 0) => Ok(m.m00),
+            (1, 0) | (0, 1) => Ok(0.0),
+            (2, 0) => Ok(m.m20 - m.m10 * m.m10 / m.m00),
+            (1, 1) => Ok(m.m11 - m.m10 * m.m01 / m.m00),
+            (0, 2) => Ok(m.m02 - m.m01 * m.m01 / m.m00),
+            _ => Err(Error::NotImplemented),
         }
     }
 }
@@ -0,0 +1,267 @@
+//! Grayscale morphology with square structuring elements.
+//!
+//! Erosion sets each pixel to the minimum, and dilation to the maximum, of the
+//! `(2 * radius + 1)` square window around it. For binary masks (0 and 255),
+//! this is the usual binary erosion and dilation. Pixels outside the image are
+//! ignored.
+//!
+//! A square window is separable: the minimum over the rows of the window is
+//! computed first, followed by the minimum along the resulting row. The
+//! buffers for this are kept in a [MorphologyBuffer]. The row
+//! kernels in this module operate on slices and are public so that they can be
+//! used with other image types.
+
+use crate::{Chan1, Error, FastImage, FastImageData, FastImageSize, MutableFastImage, Result};
+
+#[cfg(feature = "portsimd")]
+use std::simd::{cmp::SimdOrd, u8x32};
+
+/// Set each element of `out` to the minimum of itself and `row` at its
+/// position.
+///
+/// `row` must have the length of `out`.
+pub fn min_assign(out: &mut [u8], row: &[u8]) {
+    #[cfg(feature = "portsimd")]
+    {
+        let mut out_chunks = out.chunks_exact_mut(32);
+        let mut row_chunks = row.chunks_exact(32);
+        for (o, r) in (&mut out_chunks).zip(&mut row_chunks) {
+            u8x32::from_slice(o)
+                .simd_min(u8x32::from_slice(r))
+                .copy_to_slice(o);
+        }
+        for (o, r) in out_chunks
+            .into_remainder()
+            .iter_mut()
+            .zip(row_chunks.remainder())
+        {
+            *o = (*o).min(*r);
+        }
+    }
+    #[cfg(not(feature = "portsimd"))]
+    for (o, r) in out.iter_mut().zip(row.iter()) {
+        *o = (*o).min(*r);
+    }
+}
+
+/// Set each element of `out` to the maximum of itself and `row` at its
+/// position.
+///
+/// `row` must have the length of `out`.
+pub fn max_assign(out: &mut [u8], row: &[u8]) {
+    #[cfg(feature = "portsimd")]
+    {
+        let mut out_chunks = out.chunks_exact_mut(32);
+        let mut row_chunks = row.chunks_exact(32);
+        for (o, r) in (&mut out_chunks).zip(&mut row_chunks) {
+            u8x32::from_slice(o)
+                .simd_max(u8x32::from_slice(r))
+                .copy_to_slice(o);
+        }
+        for (o, r) in out_chunks
+            .into_remainder()
+            .iter_mut()
+            .zip(row_chunks.remainder())
+        {
+            *o = (*o).max(*r);
+        }
+    }
+    #[cfg(not(feature = "portsimd"))]
+    for (o, r) in out.iter_mut().zip(row.iter()) {
+        *o = (*o).max(*r);
+    }
+}
+
+/// Set each element of `out` to the minimum of `row` within `radius` elements
+/// of its position.
+///
+/// `row` must have the length of `out`.
+pub fn min_window(row: &[u8], radius: usize, out: &mut [u8]) {
+    out.copy_from_slice(row);
+    let n = row.len();
+    // Each shifted comparison is a loop over contiguous memory which the
+    // compiler vectorizes.
+    for d in 1..=radius.min(n.saturating_sub(1)) {
+        for (o, r) in out[..n - d].iter_mut().zip(&row[d..]) {
+            *o = (*o).min(*r);
+        }
+        for (o, r) in out[d..].iter_mut().zip(&row[..n - d]) {
+            *o = (*o).min(*r);
+        }
+    }
+}
+
+/// Set each element of `out` to the maximum of `row` within `radius` elements
+/// of its position.
+///
+/// `row` must have the length of `out`.
+pub fn max_window(row: &[u8], radius: usize, out: &mut [u8]) {
+    out.copy_from_slice(row);
+    let n = row.len();
+    for d in 1..=radius.min(n.saturating_sub(1)) {
+        for (o, r) in out[..n - d].iter_mut().zip(&row[d..]) {
+            *o = (*o).max(*r);
+        }
+        for (o, r) in out[d..].iter_mut().zip(&row[..n - d]) {
+            *o = (*o).max(*r);
+        }
+    }
+}
+
+/// Scratch space for morphological operations.
+///
+/// This is reused between calls to avoid allocating for each image. It grows
+/// as needed for the images it is used with.
+#[derive(Default)]
+pub struct MorphologyBuffer {
+    image: Option<FastImageData<Chan1, u8>>,
+    row: Vec<u8>,
+}
+
+impl MorphologyBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get an intermediate image of at least `size` and the row buffer.
+    fn parts(
+        &mut self,
+        size: &FastImageSize,
+    ) -> Result<(&mut FastImageData<Chan1, u8>, &mut Vec<u8>)> {
+        let fits = self
+            .image
+            .as_ref()
+            .is_some_and(|im| im.width() >= size.width() && im.height() >= size.height());
+        if !fits {
+            let (w, h) = match self.image.as_ref() {
+                Some(im) => (im.width().max(size.width()), im.height().max(size.height())),
+                None => (size.width(), size.height()),
+            };
+            self.image = Some(FastImageData::new(w, h, 0)?);
+        }
+        Ok((self.image.as_mut().unwrap(), &mut self.row))
+    }
+}
+
+/// Apply the separable filter: `rows_op` combines the rows of the window and
+/// `window_op` then combines the elements along the resulting row.
+fn filter<S, D>(
+    src: &S,
+    dest: &mut D,
+    size: &FastImageSize,
+    radius: u32,
+    row: &mut Vec<u8>,
+    rows_op: fn(&mut [u8], &[u8]),
+    window_op: fn(&[u8], usize, &mut [u8]),
+) -> Result<()>
+where
+    S: FastImage<D = u8, C = Chan1>,
+    D: MutableFastImage<D = u8, C = Chan1>,
+{
+    if size.width() > src.width() || size.height() > src.height() {
+        return Err(Error::SizeError);
+    }
+    let width = size.width() as usize;
+    let height = size.height() as usize;
+    let stride = src.stride() as usize;
+    let src_data = src.image_slice();
+    let src_row = |y: usize| &src_data[y * stride..y * stride + width];
+    let radius = radius as usize;
+    row.resize(width, 0);
+    for (y, dest_row) in dest.valid_row_iter_mut(size)?.enumerate() {
+        let y0 = y.saturating_sub(radius);
+        let y1 = (y + radius + 1).min(height);
+        row.copy_from_slice(src_row(y0));
+        for y in y0 + 1..y1 {
+            rows_op(row, src_row(y));
+        }
+        window_op(row, radius, dest_row);
+    }
+    Ok(())
+}
+
+/// Erode `src` into `dest` with a `(2 * radius + 1)` square structuring
+/// element.
+pub fn erode_8u_c1r<S, D>(
+    src: &S,
+    dest: &mut D,
+    size: &FastImageSize,
+    radius: u32,
+    buf: &mut MorphologyBuffer,
+) -> Result<()>
+where
+    S: FastImage<D = u8, C = Chan1>,
+    D: MutableFastImage<D = u8, C = Chan1>,
+{
+    filter(
+        src,
+        dest,
+        size,
+        radius,
+        &mut buf.row,
+        min_assign,
+        min_window,
+    )
+}
+
+/// Dilate `src` into `dest` with a `(2 * radius + 1)` square structuring
+/// element.
+pub fn dilate_8u_c1r<S, D>(
+    src: &S,
+    dest: &mut D,
+    size: &FastImageSize,
+    radius: u32,
+    buf: &mut MorphologyBuffer,
+) -> Result<()>
+where
+    S: FastImage<D = u8, C = Chan1>,
+    D: MutableFastImage<D = u8, C = Chan1>,
+{
+    filter(
+        src,
+        dest,
+        size,
+        radius,
+        &mut buf.row,
+        max_assign,
+        max_window,
+    )
+}
+
+/// Open `src_dest` in place, i.e. erode and then dilate it, with a
+/// `(2 * radius + 1)` square structuring element.
+///
+/// This removes bright structures smaller than the structuring element, such
+/// as noisy pixels of a mask.
+pub fn open_8u_c1ir<SD>(
+    src_dest: &mut SD,
+    size: &FastImageSize,
+    radius: u32,
+    buf: &mut MorphologyBuffer,
+) -> Result<()>
+where
+    SD: MutableFastImage<D = u8, C = Chan1>,
+{
+    let (tmp, row) = buf.parts(size)?;
+    filter(&*src_dest, tmp, size, radius, row, min_assign, min_window)?;
+    filter(&*tmp, src_dest, size, radius, row, max_assign, max_window)
+}
+
+/// Close `src_dest` in place, i.e. dilate and then erode it, with a
+/// `(2 * radius + 1)` square structuring element.
+///
+/// This fills dark gaps smaller than the structuring element, such as holes
+/// in a mask.
+pub fn close_8u_c1ir<SD>(
+    src_dest: &mut SD,
+    size: &FastImageSize,
+    radius: u32,
+    buf: &mut MorphologyBuffer,
+) -> Result<()>
+where
+    SD: MutableFastImage<D = u8, C = Chan1>,
+{
+    let (tmp, row) = buf.parts(size)?;
+    filter(&*src_dest, tmp, size, radius, row, max_assign, max_window)?;
+    filter(&*tmp, src_dest, size, radius, row, min_assign, min_window)
+}
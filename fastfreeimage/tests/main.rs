@@ -537,20 +537,14 @@ fn test_get_orientation() -> Result<()> {
         approx::assert_relative_eq!(mu01, 20.0);
     }
 
-    // {
-    //     let mu00 = moments
-    //         .spatial(0, 0, 0, &fastfreeimage::Point::new(5, 10))
-    //         ?;
-    //     approx::assert_relative_eq!(mu00, 4.0);
-    //     let mu10 = moments
-    //         .spatial(1, 0, 0, &fastfreeimage::Point::new(5, 10))
-    //         ?;
-    //     approx::assert_relative_eq!(mu10, 34.0);
-    //     let mu01 = moments
-    //         .spatial(0, 1, 0, &fastfreeimage::Point::new(5, 10))
-    //         ?;
-    //     approx::assert_relative_eq!(mu01, 60.0);
-    // }
+    {
+        let mu00 = moments.spatial(0, 0, 0, &fastfreeimage::Point::new(5, 10))?;
+        approx::assert_relative_eq!(mu00, 4.0);
+        let mu10 = moments.spatial(1, 0, 0, &fastfreeimage::Point::new(5, 10))?;
+        approx::assert_relative_eq!(mu10, 34.0);
+        let mu01 = moments.spatial(0, 1, 0, &fastfreeimage::Point::new(5, 10))?;
+        approx::assert_relative_eq!(mu01, 60.0);
+    }
 
     let uu11 = moments.central(1, 1, 0)?;
     approx::assert_relative_eq!(uu11, 1.0);
@@ -572,6 +566,260 @@ fn test_get_orientation() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_moments_2nd_order() -> Result<()> {
+    // A large, bright image, for which f32 accumulation would be inexact.
+    let w: usize = 1280;
+    let h: usize = 1024;
+    let mut im = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 0)?;
+    for row in 0..h {
+        for col in 0..w {
+            im.pixel_slice_mut(row, col)[0] = ((row * 7 + col * 3) % 256) as u8;
+        }
+    }
+
+    let mut moments = MomentState::new(fastfreeimage::AlgorithmHint::Fast)?;
+    ripp::moments_8u_c1r(&im, im.size(), &mut moments)?;
+
+    // Compute the expected values directly.
+    let offset = fastfreeimage::Point::new(3, 2);
+    let (ox, oy) = (3.0, 2.0);
+    let mut expected_spatial = [[0.0f64; 3]; 3];
+    let mut sum = 0.0;
+    let mut sum_x = 0.0;
+    let mut sum_y = 0.0;
+    for row in 0..h {
+        for col in 0..w {
+            let val = f64::from(im.pixel_slice(row, col)[0]);
+            let x = col as f64 + ox;
+            let y = row as f64 + oy;
+            for (m_ord, els) in expected_spatial.iter_mut().enumerate() {
+                for (n_ord, el) in els.iter_mut().enumerate() {
+                    *el += x.powi(m_ord as i32) * y.powi(n_ord as i32) * val;
+                }
+            }
+            sum += val;
+            sum_x += x * val;
+            sum_y += y * val;
+        }
+    }
+    let (cx, cy) = (sum_x / sum, sum_y / sum);
+    let mut expected_central = [[0.0f64; 3]; 3];
+    for row in 0..h {
+        for col in 0..w {
+            let val = f64::from(im.pixel_slice(row, col)[0]);
+            let dx = col as f64 + ox - cx;
+            let dy = row as f64 + oy - cy;
+            for (m_ord, els) in expected_central.iter_mut().enumerate() {
+                for (n_ord, el) in els.iter_mut().enumerate() {
+                    *el += dx.powi(m_ord as i32) * dy.powi(n_ord as i32) * val;
+                }
+            }
+        }
+    }
+
+    for (m_ord, n_ord) in [(0, 0), (1, 0), (0, 1), (2, 0), (1, 1), (0, 2)] {
+        let spatial = moments.spatial(m_ord, n_ord, 0, &offset)?;
+        approx::assert_relative_eq!(
+            spatial,
+            expected_spatial[m_ord as usize][n_ord as usize],
+            max_relative = 1e-12
+        );
+        let central = moments.central(m_ord, n_ord, 0)?;
+        approx::assert_relative_eq!(
+            central,
+            expected_central[m_ord as usize][n_ord as usize],
+            epsilon = 1.0,
+            max_relative = 1e-9
+        );
+    }
+
+    assert!(moments.spatial(3, 0, 0, &offset).is_err());
+    assert!(moments.central(1, 2, 0).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_connected_components() -> Result<()> {
+    use fastfreeimage::{connected_components_8u_c1r, Connectivity, Point};
+
+    // Two blobs touching only diagonally, a "U" shape which is joined in its
+    // last row and a single pixel.
+    let rows = [
+        "##......", //
+        "##......", //
+        "..#..#.#", //
+        ".....#.#", //
+        ".....###", //
+        "#.......", //
+    ];
+    let w = rows[0].len();
+    let h = rows.len();
+    let mut im = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 0)?;
+    for (row, chars) in rows.iter().enumerate() {
+        for (col, c) in chars.chars().enumerate() {
+            if c == '#' {
+                im.pixel_slice_mut(row, col)[0] = 255;
+            }
+        }
+    }
+
+    let comps = connected_components_8u_c1r(&im, im.size(), Connectivity::Four, true)?;
+    assert_eq!(comps.len(), 4);
+    assert_eq!(comps[0].area, 4);
+    approx::assert_relative_eq!(comps[0].centroid.0, 0.5);
+    approx::assert_relative_eq!(comps[0].centroid.1, 0.5);
+    assert_eq!(comps[1].area, 1);
+    assert_eq!(comps[1].pixels, Some(vec![Point::new(2, 2)]));
+    let u = &comps[2];
+    assert_eq!(u.area, 7);
+    assert_eq!((u.bbox.left(), u.bbox.bottom()), (5, 2));
+    assert_eq!((u.bbox.width(), u.bbox.height()), (3, 3));
+    approx::assert_relative_eq!(u.centroid.0, 42.0 / 7.0);
+    approx::assert_relative_eq!(u.centroid.1, 22.0 / 7.0);
+    assert_eq!(u.pixels.as_ref().unwrap().len(), 7);
+    assert_eq!(comps[3].bbox.bottom(), 5);
+
+    let comps = connected_components_8u_c1r(&im, im.size(), Connectivity::Eight, false)?;
+    assert_eq!(comps.len(), 3);
+    assert_eq!(comps[0].area, 5);
+    assert!(comps[0].pixels.is_none());
+    assert_eq!(comps[1].area, 7);
+    assert_eq!(comps[2].area, 1);
+
+    let empty = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 0)?;
+    let comps = connected_components_8u_c1r(&empty, empty.size(), Connectivity::Eight, false)?;
+    assert!(comps.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_integral_and_box_mean() -> Result<()> {
+    let w: usize = 37;
+    let h: usize = 23;
+    let mut im = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 0)?;
+    for row in 0..h {
+        for col in 0..w {
+            im.pixel_slice_mut(row, col)[0] = ((row * 31 + col * 17) % 256) as u8;
+        }
+    }
+
+    let mut integral_u = FastImageData::<Chan1, u32>::new(w as i32 + 1, h as i32 + 1, 0)?;
+    fastfreeimage::integral_8u32u_c1r(&im, &mut integral_u, im.size())?;
+    let mut integral_f = FastImageData::<Chan1, f32>::new(w as i32 + 1, h as i32 + 1, 0.0)?;
+    fastfreeimage::integral_8u32f_c1r(&im, &mut integral_f, im.size())?;
+
+    // The integral image is the sum above and left of each pixel.
+    for row in 0..=h {
+        for col in 0..=w {
+            let mut expected = 0;
+            for r in 0..row {
+                for c in 0..col {
+                    expected += u32::from(im.pixel_slice(r, c)[0]);
+                }
+            }
+            assert_eq!(integral_u.pixel_slice(row, col)[0], expected);
+            assert_eq!(integral_f.pixel_slice(row, col)[0], expected as f32);
+        }
+    }
+
+    // The box mean is the mean of the window clipped to the image.
+    let radius = 3;
+    let mut mean_u = FastImageData::<Chan1, f32>::new(w as i32, h as i32, 0.0)?;
+    fastfreeimage::box_mean_32u32f_c1r(&integral_u, &mut mean_u, im.size(), radius)?;
+    let mut mean_f = FastImageData::<Chan1, f32>::new(w as i32, h as i32, 0.0)?;
+    fastfreeimage::box_mean_32f_c1r(&integral_f, &mut mean_f, im.size(), radius)?;
+    let radius = radius as usize;
+    for row in 0..h {
+        for col in 0..w {
+            let mut sum = 0.0;
+            let mut count = 0.0;
+            for r in row.saturating_sub(radius)..(row + radius + 1).min(h) {
+                for c in col.saturating_sub(radius)..(col + radius + 1).min(w) {
+                    sum += f64::from(im.pixel_slice(r, c)[0]);
+                    count += 1.0;
+                }
+            }
+            let expected = (sum / count) as f32;
+            approx::assert_relative_eq!(mean_u.pixel_slice(row, col)[0], expected);
+            approx::assert_relative_eq!(mean_f.pixel_slice(row, col)[0], expected);
+        }
+    }
+
+    // The integral image must be larger than the image.
+    let mut too_small = FastImageData::<Chan1, u32>::new(w as i32, h as i32, 0)?;
+    assert!(fastfreeimage::integral_8u32u_c1r(&im, &mut too_small, im.size()).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_morphology() -> Result<()> {
+    let w: usize = 45;
+    let h: usize = 17;
+    let mut im = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 0)?;
+    for row in 0..h {
+        for col in 0..w {
+            im.pixel_slice_mut(row, col)[0] = ((row * 71 + col * 37) % 251) as u8;
+        }
+    }
+
+    // Compare with the minimum and maximum of each window clipped to the
+    // image. The width is larger than the SIMD vector length.
+    let mut buf = fastfreeimage::MorphologyBuffer::new();
+    for radius in [0, 1, 2] {
+        let mut eroded = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 0)?;
+        fastfreeimage::erode_8u_c1r(&im, &mut eroded, im.size(), radius, &mut buf)?;
+        let mut dilated = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 0)?;
+        fastfreeimage::dilate_8u_c1r(&im, &mut dilated, im.size(), radius, &mut buf)?;
+        let radius = radius as usize;
+        for row in 0..h {
+            for col in 0..w {
+                let window = (row.saturating_sub(radius)..(row + radius + 1).min(h))
+                    .flat_map(|r| {
+                        (col.saturating_sub(radius)..(col + radius + 1).min(w)).map(move |c| (r, c))
+                    })
+                    .map(|(r, c)| im.pixel_slice(r, c)[0]);
+                let expected_min = window.clone().min().unwrap();
+                let expected_max = window.max().unwrap();
+                assert_eq!(eroded.pixel_slice(row, col)[0], expected_min);
+                assert_eq!(dilated.pixel_slice(row, col)[0], expected_max);
+            }
+        }
+    }
+
+    // Opening removes a single pixel but keeps a 3x3 square.
+    let mut mask = FastImageData::<Chan1, u8>::new(10, 10, 0)?;
+    mask.pixel_slice_mut(1, 1)[0] = 255;
+    for row in 5..8 {
+        for col in 5..8 {
+            mask.pixel_slice_mut(row, col)[0] = 255;
+        }
+    }
+    let size = *mask.size();
+    fastfreeimage::open_8u_c1ir(&mut mask, &size, 1, &mut buf)?;
+    assert_eq!(mask.pixel_slice(1, 1)[0], 0);
+    for row in 0..10 {
+        for col in 0..10 {
+            let expected = if (5..8).contains(&row) && (5..8).contains(&col) {
+                255
+            } else {
+                0
+            };
+            assert_eq!(mask.pixel_slice(row, col)[0], expected);
+        }
+    }
+
+    // Closing fills a hole in the square, with a 3x3 and a 5x5 structuring
+    // element.
+    for radius in [1, 2] {
+        mask.pixel_slice_mut(6, 6)[0] = 0;
+        fastfreeimage::close_8u_c1ir(&mut mask, &size, radius, &mut buf)?;
+        assert_eq!(mask.pixel_slice(6, 6)[0], 255);
+        assert_eq!(mask.pixel_slice(1, 1)[0], 0);
+    }
+    Ok(())
+}
+
 macro_rules! gen_test_alloc {
     ($ty:ty, $pixel_val:expr, $single_val:expr, $name:ident) => {
         #[test]
@@ -0,0 +1,126 @@
+//! Integral images and box filters computed from them.
+//!
+//! The integral images are computed by IPP and have the layout described in
+//! `fastfreeimage`, which also computes the box means with the same row
+//! kernel.
+
+use fastfreeimage::integral_image::box_mean_row;
+
+use crate::{
+    ipp_status_string, Chan1, Error, FastImage, FastImageSize, MutableFastImage, Result, NO_IPP_ERR,
+};
+
+fn check_integral_size<S: FastImage>(integral: &S, size: &FastImageSize) -> Result<()> {
+    if integral.width() <= size.width() || integral.height() <= size.height() {
+        return Err(Error::SizeError);
+    }
+    Ok(())
+}
+
+/// Compute the integral image of `src` into `dest` with `u32` values.
+///
+/// `dest` must be at least one pixel wider and higher than `size`. The sums
+/// overflow for images with more than 2^24 pixels.
+pub fn integral_8u32u_c1r<S, D>(src: &S, dest: &mut D, size: &FastImageSize) -> Result<()>
+where
+    S: FastImage<D = u8, C = Chan1>,
+    D: MutableFastImage<D = u32, C = Chan1>,
+{
+    check_integral_size(dest, size)?;
+    // IPP has only a signed version. The sums are the same below 2^31, and
+    // wrap around to the same bits above.
+    itry!(ipp_sys::ippiIntegral_8u32s_C1R(
+        src.raw_ptr(),
+        src.stride(),
+        dest.raw_mut_ptr() as *mut ipp_sys::Ipp32s,
+        dest.stride(),
+        size.inner,
+        0
+    ));
+    Ok(())
+}
+
+/// Compute the integral image of `src` into `dest` with `f32` values.
+///
+/// `dest` must be at least one pixel wider and higher than `size`. Sums above
+/// 2^24, which can occur in images with more than 65793 pixels, are not exact
+/// in `f32`, and neither are box means computed from them. Use
+/// [integral_8u32u_c1r] for exact sums.
+pub fn integral_8u32f_c1r<S, D>(src: &S, dest: &mut D, size: &FastImageSize) -> Result<()>
+where
+    S: FastImage<D = u8, C = Chan1>,
+    D: MutableFastImage<D = f32, C = Chan1>,
+{
+    check_integral_size(dest, size)?;
+    itry!(ipp_sys::ippiIntegral_8u32f_C1R(
+        src.raw_ptr(),
+        src.stride(),
+        dest.raw_mut_ptr(),
+        dest.stride(),
+        size.inner,
+        0.0
+    ));
+    Ok(())
+}
+
+fn box_mean<S, D, T>(integral: &S, dest: &mut D, size: &FastImageSize, radius: u32) -> Result<()>
+where
+    S: FastImage<D = T, C = Chan1>,
+    D: MutableFastImage<D = f32, C = Chan1>,
+    T: Copy + PartialEq + Into<f64>,
+{
+    check_integral_size(integral, size)?;
+    let width = size.width() as usize;
+    let height = size.height() as usize;
+    let integral_row = |y: usize| &integral.row_slice(y)[..width + 1];
+    let radius = radius as usize;
+    for (y, dest_row) in dest.valid_row_iter_mut(size)?.enumerate() {
+        // The window is clipped at the image borders.
+        let y0 = y.saturating_sub(radius);
+        let y1 = (y + radius + 1).min(height);
+        box_mean_row(
+            integral_row(y0),
+            integral_row(y1),
+            y1 - y0,
+            radius,
+            dest_row,
+        );
+    }
+    Ok(())
+}
+
+/// Compute the mean of each `(2 * radius + 1)` square window of an image from
+/// its `u32` integral image.
+///
+/// `size` is the size of the image, so `integral` must be at least one pixel
+/// wider and higher. At the borders, the mean is over the part of the window
+/// within the image.
+pub fn box_mean_32u32f_c1r<S, D>(
+    integral: &S,
+    dest: &mut D,
+    size: &FastImageSize,
+    radius: u32,
+) -> Result<()>
+where
+    S: FastImage<D = u32, C = Chan1>,
+    D: MutableFastImage<D = f32, C = Chan1>,
+{
+    box_mean(integral, dest, size, radius)
+}
+
+/// Compute the mean of each `(2 * radius + 1)` square window of an image from
+/// its `f32` integral image.
+///
+/// See [box_mean_32u32f_c1r], which is exact for larger images.
+pub fn box_mean_32f_c1r<S, D>(
+    integral: &S,
+    dest: &mut D,
+    size: &FastImageSize,
+    radius: u32,
+) -> Result<()>
+where
+    S: FastImage<D = f32, C = Chan1>,
+    D: MutableFastImage<D = f32, C = Chan1>,
+{
+    box_mean(integral, dest, size, radius)
+}
@@ -172,6 +172,14 @@ macro_rules! itry {
     };
 }
 
+mod integral_image;
+pub use integral_image::{
+    box_mean_32f_c1r, box_mean_32u32f_c1r, integral_8u32f_c1r, integral_8u32u_c1r,
+};
+
+mod morphology;
+pub use morphology::{close_8u_c1ir, dilate_8u_c1r, erode_8u_c1r, open_8u_c1ir, MorphologyBuffer};
+
 pub enum Chan1 {}
 impl PartialEq for Chan1 {
     fn eq(&self, _: &Chan1) -> bool {
@@ -315,6 +323,17 @@ impl FastImageData<Chan1, u8> {
     }
 }
 
+impl FastImageData<Chan1, u32> {
+    pub fn new(
+        width_pixels: ipp_ctypes::c_int,
+        height_pixels: ipp_ctypes::c_int,
+        value: u32,
+    ) -> Result<Self> {
+        let data = Self::empty(value, width_pixels, height_pixels)?;
+        Ok(data)
+    }
+}
+
 impl FastImageData<Chan1, f32> {
     pub fn new(
         width_pixels: ipp_ctypes::c_int,
@@ -0,0 +1,188 @@
+//! Grayscale morphology with square structuring elements.
+//!
+//! Erosion sets each pixel to the minimum, and dilation to the maximum, of the
+//! `(2 * radius + 1)` square window around it, as computed by the IPP minimum
+//! and maximum filters. Pixels outside the image are ignored, like in
+//! `fastfreeimage`.
+
+use crate::{
+    ipp_ctypes, ipp_status_string, Chan1, Error, FastImage, FastImageData, FastImageSize,
+    MutableFastImage, Result, NO_IPP_ERR,
+};
+
+/// Scratch space for morphological operations.
+///
+/// This is reused between calls to avoid allocating for each image. It grows
+/// as needed for the images it is used with.
+#[derive(Default)]
+pub struct MorphologyBuffer {
+    image: Option<FastImageData<Chan1, u8>>,
+    ipp: Vec<u8>,
+}
+
+impl MorphologyBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get an intermediate image of at least `size` and the IPP buffer.
+    fn parts(
+        &mut self,
+        size: &FastImageSize,
+    ) -> Result<(&mut FastImageData<Chan1, u8>, &mut Vec<u8>)> {
+        let fits = self
+            .image
+            .as_ref()
+            .is_some_and(|im| im.width() >= size.width() && im.height() >= size.height());
+        if !fits {
+            let (w, h) = match self.image.as_ref() {
+                Some(im) => (im.width().max(size.width()), im.height().max(size.height())),
+                None => (size.width(), size.height()),
+            };
+            self.image = Some(FastImageData::new(w, h, 0)?);
+        }
+        Ok((self.image.as_mut().unwrap(), &mut self.ipp))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Min,
+    Max,
+}
+
+fn filter<S, D>(
+    src: &S,
+    dest: &mut D,
+    size: &FastImageSize,
+    radius: u32,
+    ipp_buf: &mut Vec<u8>,
+    op: Op,
+) -> Result<()>
+where
+    S: FastImage<D = u8, C = Chan1>,
+    D: MutableFastImage<D = u8, C = Chan1>,
+{
+    if size.width() > src.width()
+        || size.height() > src.height()
+        || size.width() > dest.width()
+        || size.height() > dest.height()
+    {
+        return Err(Error::SizeError);
+    }
+    let side = 2 * radius as ipp_ctypes::c_int + 1;
+    let mask_size = FastImageSize::new(side, side);
+    let mut buf_size = 0;
+    let data_type = ipp_sys::IppDataType::ipp8u;
+    match op {
+        Op::Min => itry!(ipp_sys::ippiFilterMinBorderGetBufferSize(
+            size.inner,
+            mask_size.inner,
+            data_type,
+            1,
+            &mut buf_size
+        )),
+        Op::Max => itry!(ipp_sys::ippiFilterMaxBorderGetBufferSize(
+            size.inner,
+            mask_size.inner,
+            data_type,
+            1,
+            &mut buf_size
+        )),
+    }
+    if ipp_buf.len() < buf_size as usize {
+        ipp_buf.resize(buf_size as usize, 0);
+    }
+    // Replicating the border pixels does not change the minimum or maximum,
+    // so this is the same as ignoring pixels outside the image.
+    let border = ipp_sys::_IppiBorderType::ippBorderRepl;
+    match op {
+        Op::Min => itry!(ipp_sys::ippiFilterMinBorder_8u_C1R(
+            src.raw_ptr(),
+            src.stride(),
+            dest.raw_mut_ptr(),
+            dest.stride(),
+            size.inner,
+            mask_size.inner,
+            border,
+            0,
+            ipp_buf.as_mut_ptr()
+        )),
+        Op::Max => itry!(ipp_sys::ippiFilterMaxBorder_8u_C1R(
+            src.raw_ptr(),
+            src.stride(),
+            dest.raw_mut_ptr(),
+            dest.stride(),
+            size.inner,
+            mask_size.inner,
+            border,
+            0,
+            ipp_buf.as_mut_ptr()
+        )),
+    }
+    Ok(())
+}
+
+/// Erode `src` into `dest` with a `(2 * radius + 1)` square structuring
+/// element.
+pub fn erode_8u_c1r<S, D>(
+    src: &S,
+    dest: &mut D,
+    size: &FastImageSize,
+    radius: u32,
+    buf: &mut MorphologyBuffer,
+) -> Result<()>
+where
+    S: FastImage<D = u8, C = Chan1>,
+    D: MutableFastImage<D = u8, C = Chan1>,
+{
+    filter(src, dest, size, radius, &mut buf.ipp, Op::Min)
+}
+
+/// Dilate `src` into `dest` with a `(2 * radius + 1)` square structuring
+/// element.
+pub fn dilate_8u_c1r<S, D>(
+    src: &S,
+    dest: &mut D,
+    size: &FastImageSize,
+    radius: u32,
+    buf: &mut MorphologyBuffer,
+) -> Result<()>
+where
+    S: FastImage<D = u8, C = Chan1>,
+    D: MutableFastImage<D = u8, C = Chan1>,
+{
+    filter(src, dest, size, radius, &mut buf.ipp, Op::Max)
+}
+
+/// Open `src_dest` in place, i.e. erode and then dilate it, with a
+/// `(2 * radius + 1)` square structuring element.
+pub fn open_8u_c1ir<SD>(
+    src_dest: &mut SD,
+    size: &FastImageSize,
+    radius: u32,
+    buf: &mut MorphologyBuffer,
+) -> Result<()>
+where
+    SD: MutableFastImage<D = u8, C = Chan1>,
+{
+    let (tmp, ipp_buf) = buf.parts(size)?;
+    filter(&*src_dest, tmp, size, radius, ipp_buf, Op::Min)?;
+    filter(&*tmp, src_dest, size, radius, ipp_buf, Op::Max)
+}
+
+/// Close `src_dest` in place, i.e. dilate and then erode it, with a
+/// `(2 * radius + 1)` square structuring element.
+pub fn close_8u_c1ir<SD>(
+    src_dest: &mut SD,
+    size: &FastImageSize,
+    radius: u32,
+    buf: &mut MorphologyBuffer,
+) -> Result<()>
+where
+    SD: MutableFastImage<D = u8, C = Chan1>,
+{
+    let (tmp, ipp_buf) = buf.parts(size)?;
+    filter(&*src_dest, tmp, size, radius, ipp_buf, Op::Max)?;
+    filter(&*tmp, src_dest, size, radius, ipp_buf, Op::Min)
+}
@@ -489,6 +489,167 @@ fn test_get_orientation() {
     approx::assert_relative_eq!(expected_slope, slope, epsilon = 1e-4);
 }
 
+#[test]
+fn test_moments_match_fastfreeimage() {
+    use fastfreeimage::{FastImage as _, MutableFastImage as _};
+    ripp::init().unwrap();
+
+    let w: usize = 640;
+    let h: usize = 480;
+    let mut im = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 0).unwrap();
+    let mut im_free =
+        fastfreeimage::FastImageData::<fastfreeimage::Chan1, u8>::new(w as i32, h as i32, 0)
+            .unwrap();
+    for row in 0..h {
+        for col in 0..w {
+            let val = ((row * 7 + col * 3) % 256) as u8;
+            im.pixel_slice_mut(row, col)[0] = val;
+            im_free.pixel_slice_mut(row, col)[0] = val;
+        }
+    }
+
+    let mut moments = MomentState::new(fastimage::AlgorithmHint::Fast).unwrap();
+    ripp::moments_8u_c1r(&im, im.size(), &mut moments).unwrap();
+    let mut moments_free =
+        fastfreeimage::MomentState::new(fastfreeimage::AlgorithmHint::Fast).unwrap();
+    fastfreeimage::ripp::moments_8u_c1r(&im_free, im_free.size(), &mut moments_free).unwrap();
+
+    for (m_ord, n_ord) in [(0, 0), (1, 0), (0, 1), (2, 0), (1, 1), (0, 2)] {
+        let spatial = moments
+            .spatial(m_ord, n_ord, 0, &fastimage::Point::new(5, 10))
+            .unwrap();
+        let spatial_free = moments_free
+            .spatial(m_ord, n_ord, 0, &fastfreeimage::Point::new(5, 10))
+            .unwrap();
+        approx::assert_relative_eq!(spatial, spatial_free, max_relative = 1e-9);
+
+        let central = moments.central(m_ord, n_ord, 0).unwrap();
+        let central_free = moments_free.central(m_ord, n_ord, 0).unwrap();
+        approx::assert_relative_eq!(central, central_free, epsilon = 1.0, max_relative = 1e-9);
+    }
+}
+
+#[test]
+fn test_integral_and_box_mean_match_fastfreeimage() {
+    use fastfreeimage::{FastImage as _, MutableFastImage as _};
+    ripp::init().unwrap();
+
+    let w: usize = 37;
+    let h: usize = 23;
+    let mut im = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 0).unwrap();
+    let mut im_free =
+        fastfreeimage::FastImageData::<fastfreeimage::Chan1, u8>::new(w as i32, h as i32, 0)
+            .unwrap();
+    for row in 0..h {
+        for col in 0..w {
+            let val = ((row * 31 + col * 17) % 256) as u8;
+            im.pixel_slice_mut(row, col)[0] = val;
+            im_free.pixel_slice_mut(row, col)[0] = val;
+        }
+    }
+
+    let (iw, ih) = (w as i32 + 1, h as i32 + 1);
+    let mut integral_u = FastImageData::<Chan1, u32>::new(iw, ih, 0).unwrap();
+    fastimage::integral_8u32u_c1r(&im, &mut integral_u, im.size()).unwrap();
+    let mut integral_f = FastImageData::<Chan1, f32>::new(iw, ih, 0.0).unwrap();
+    fastimage::integral_8u32f_c1r(&im, &mut integral_f, im.size()).unwrap();
+    let mut integral_u_free =
+        fastfreeimage::FastImageData::<fastfreeimage::Chan1, u32>::new(iw, ih, 0).unwrap();
+    fastfreeimage::integral_8u32u_c1r(&im_free, &mut integral_u_free, im_free.size()).unwrap();
+    for row in 0..=h {
+        for col in 0..=w {
+            let expected = integral_u_free.pixel_slice(row, col)[0];
+            assert_eq!(integral_u.pixel_slice(row, col)[0], expected);
+            assert_eq!(integral_f.pixel_slice(row, col)[0], expected as f32);
+        }
+    }
+
+    let radius = 3;
+    let mut mean_u = FastImageData::<Chan1, f32>::new(w as i32, h as i32, 0.0).unwrap();
+    fastimage::box_mean_32u32f_c1r(&integral_u, &mut mean_u, im.size(), radius).unwrap();
+    let mut mean_f = FastImageData::<Chan1, f32>::new(w as i32, h as i32, 0.0).unwrap();
+    fastimage::box_mean_32f_c1r(&integral_f, &mut mean_f, im.size(), radius).unwrap();
+    let mut mean_free =
+        fastfreeimage::FastImageData::<fastfreeimage::Chan1, f32>::new(w as i32, h as i32, 0.0)
+            .unwrap();
+    fastfreeimage::box_mean_32u32f_c1r(&integral_u_free, &mut mean_free, im_free.size(), radius)
+        .unwrap();
+    for row in 0..h {
+        for col in 0..w {
+            let expected = mean_free.pixel_slice(row, col)[0];
+            assert_eq!(mean_u.pixel_slice(row, col)[0], expected);
+            approx::assert_relative_eq!(mean_f.pixel_slice(row, col)[0], expected);
+        }
+    }
+}
+
+#[test]
+fn test_morphology_matches_fastfreeimage() {
+    use fastfreeimage::{FastImage as _, MutableFastImage as _};
+    ripp::init().unwrap();
+
+    // A view into a larger image, so that pixels outside the region must be
+    // ignored.
+    let w: usize = 45;
+    let h: usize = 17;
+    let mut im = FastImageData::<Chan1, u8>::new(w as i32 + 4, h as i32 + 4, 255).unwrap();
+    let mut im_free =
+        fastfreeimage::FastImageData::<fastfreeimage::Chan1, u8>::new(w as i32, h as i32, 0)
+            .unwrap();
+    for row in 0..h {
+        for col in 0..w {
+            let val = ((row * 71 + col * 37) % 251) as u8;
+            im.pixel_slice_mut(row + 2, col + 2)[0] = val;
+            im_free.pixel_slice_mut(row, col)[0] = val;
+        }
+    }
+    let roi = fastimage::FastImageRegion::new(
+        fastimage::Point::new(2, 2),
+        fastimage::FastImageSize::new(w as i32, h as i32),
+    );
+    let view = FastImageView::view_region(&im, &roi).unwrap();
+    let size = *view.size();
+
+    let mut buf = fastimage::MorphologyBuffer::new();
+    let mut buf_free = fastfreeimage::MorphologyBuffer::new();
+    for radius in [0, 1, 2] {
+        let mut eroded = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 0).unwrap();
+        fastimage::erode_8u_c1r(&view, &mut eroded, &size, radius, &mut buf).unwrap();
+        let mut eroded_free =
+            fastfreeimage::FastImageData::<fastfreeimage::Chan1, u8>::new(w as i32, h as i32, 0)
+                .unwrap();
+        fastfreeimage::erode_8u_c1r(
+            &im_free,
+            &mut eroded_free,
+            im_free.size(),
+            radius,
+            &mut buf_free,
+        )
+        .unwrap();
+
+        let mut closed = FastImageData::<Chan1, u8>::copy_from_8u_c1(&view).unwrap();
+        fastimage::close_8u_c1ir(&mut closed, &size, radius, &mut buf).unwrap();
+        let mut closed_free =
+            fastfreeimage::FastImageData::<fastfreeimage::Chan1, u8>::copy_from_8u_c1(&im_free)
+                .unwrap();
+        let size_free = *im_free.size();
+        fastfreeimage::close_8u_c1ir(&mut closed_free, &size_free, radius, &mut buf_free).unwrap();
+
+        for row in 0..h {
+            for col in 0..w {
+                assert_eq!(
+                    eroded.pixel_slice(row, col)[0],
+                    eroded_free.pixel_slice(row, col)[0]
+                );
+                assert_eq!(
+                    closed.pixel_slice(row, col)[0],
+                    closed_free.pixel_slice(row, col)[0]
+                );
+            }
+        }
+    }
+}
+
 macro_rules! gen_test_alloc {
     ($ty:ty, $pixel_val:expr, $single_val:expr, $name:ident) => {
         #[test]
@@ -0,0 +1,104 @@
+//! Benchmarks of the per-frame cost of feature detection.
+//!
+//! Run with the image processing backend selected, e.g. `cargo bench
+//! --features do_not_use_ipp`. With the `gpu` feature, the difference from the
+//! background is also benchmarked on the GPU. (Without a GPU, this falls back
+//! to the CPU.)
+
+use chrono::DateTime;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use basic_frame::DynamicFrame;
+use flydra_feature_detector::{FlydraFeatureDetector, UfmfState};
+
+/// Sizes of common camera sensors.
+const SENSOR_SIZES: [(u32, u32); 3] = [(640, 480), (1440, 1080), (2048, 2048)];
+
+/// Frames with a uniform background and `num_points` bright squares, moving
+/// by one pixel each frame.
+fn frames(w: u32, h: u32, num_points: usize, num_frames: usize) -> Vec<DynamicFrame> {
+    (0..num_frames)
+        .map(|fno| {
+            let mut buf = vec![20; (w * h) as usize];
+            for i in 0..num_points {
+                let x0 = (50 + 100 * i + fno) % (w as usize - 4);
+                let y0 = (50 + 60 * i) % (h as usize - 4);
+                for row in y0..y0 + 4 {
+                    for col in x0..x0 + 4 {
+                        buf[row * w as usize + col] = 220;
+                    }
+                }
+            }
+            DynamicFrame::new(w, h, w, buf, machine_vision_formats::PixFmt::Mono8)
+        })
+        .collect()
+}
+
+fn bench_process_new_frame(c: &mut Criterion) {
+    bench_group(c, "process_new_frame", false);
+    #[cfg(feature = "gpu")]
+    bench_group(c, "process_new_frame_gpu", true);
+}
+
+fn bench_group(c: &mut Criterion, name: &str, use_gpu: bool) {
+    let timestamp = DateTime::from_timestamp(1431648000, 0).unwrap();
+    let mut group = c.benchmark_group(name);
+    for (w, h) in SENSOR_SIZES {
+        let mut cfg = flydra_pt_detect_cfg::default_absdiff();
+        cfg.max_num_points = 10;
+        cfg.use_gpu = use_gpu;
+        let mut ft = FlydraFeatureDetector::new(
+            &flydra_types::RawCamName::new("bench".to_string()),
+            w,
+            h,
+            cfg,
+            None,
+            None,
+        )
+        .unwrap();
+
+        // The first frames have no points and set the background.
+        let background = frames(w, h, 0, 1).remove(0);
+        let mut fno = 0;
+        for _ in 0..10 {
+            ft.process_new_frame(
+                &background,
+                fno,
+                timestamp,
+                UfmfState::Stopped,
+                None,
+                None,
+                0,
+                None,
+            )
+            .unwrap();
+            fno += 1;
+        }
+
+        let frames = frames(w, h, 5, 100);
+        group.throughput(Throughput::Elements(1));
+        group.bench_function(BenchmarkId::from_parameter(format!("{w}x{h}")), |b| {
+            b.iter(|| {
+                let frame = &frames[fno % frames.len()];
+                let (packet, _) = ft
+                    .process_new_frame(
+                        frame,
+                        fno,
+                        timestamp,
+                        UfmfState::Stopped,
+                        None,
+                        None,
+                        0,
+                        None,
+                    )
+                    .unwrap();
+                fno += 1;
+                packet
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_process_new_frame);
+criterion_main!(benches);
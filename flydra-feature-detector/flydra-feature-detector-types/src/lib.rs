@@ -16,6 +16,159 @@ pub enum ContrastPolarity {
     DetectAbsDiff,
 }
 
+/// Which model of the background is used to detect features.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub enum BackgroundModelCfg {
+    /// A single running mean and variance per pixel.
+    ///
+    /// This is the traditional flydra background model, which is parameterized
+    /// by `alpha`, `n_sigma`, `use_cmp` and the `bright_non_gaussian_*` fields
+    /// of [ImPtDetectCfg].
+    #[default]
+    MeanVariance,
+    /// An adaptive mixture of Gaussians per pixel.
+    ///
+    /// This handles slowly changing illumination and multimodal backgrounds
+    /// and can suppress shadows. `alpha` is used as the learning rate of each
+    /// update (see `bg_update_interval`), while `use_cmp`, `n_sigma` and the
+    /// `bright_non_gaussian_*` fields of [ImPtDetectCfg] have no effect.
+    MixtureOfGaussians(MixtureOfGaussiansCfg),
+}
+
+/// Which method is used to detect features.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub enum DetectorCfg {
+    /// Background subtraction, parameterized by the other fields of
+    /// [ImPtDetectCfg].
+    #[default]
+    BackgroundSubtraction,
+    /// A neural network in ONNX format.
+    ///
+    /// This is for animals which cannot be segmented from the background. Of
+    /// the other fields of [ImPtDetectCfg], only `max_num_points`,
+    /// `feature_window_size` and `valid_region` have an effect.
+    Onnx(OnnxDetectorCfg),
+}
+
+/// Morphological filtering of the difference from the background.
+///
+/// The filtering is applied before features are detected. The value of each
+/// variant is the side length, in pixels, of the square structuring element,
+/// typically 3 or 5. Larger structures remove or fill larger areas. The side
+/// length must be odd and at least 3.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedMorphologyCfg")]
+pub enum MorphologyCfg {
+    /// No filtering.
+    #[default]
+    None,
+    /// Opening (erosion followed by dilation) to remove specks of noise which
+    /// are smaller than the structuring element.
+    Open(u8),
+    /// Closing (dilation followed by erosion) to fill holes and join parts of
+    /// features which are closer than the structuring element.
+    Close(u8),
+}
+
+impl MorphologyCfg {
+    /// The radius of the structuring element, i.e. the number of pixels on
+    /// each side of its center. This is zero if there is no filtering.
+    pub fn radius(&self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Open(size) | Self::Close(size) => u32::from(size / 2),
+        }
+    }
+}
+
+/// [MorphologyCfg] before the size of the structuring element is checked.
+#[derive(Deserialize)]
+enum UncheckedMorphologyCfg {
+    None,
+    Open(u8),
+    Close(u8),
+}
+
+impl TryFrom<UncheckedMorphologyCfg> for MorphologyCfg {
+    type Error = String;
+    fn try_from(cfg: UncheckedMorphologyCfg) -> Result<Self, Self::Error> {
+        let check = |size: u8| {
+            if size < 3 || size % 2 == 0 {
+                Err(format!(
+                    "structuring element size must be odd and at least 3, not {size}"
+                ))
+            } else {
+                Ok(size)
+            }
+        };
+        Ok(match cfg {
+            UncheckedMorphologyCfg::None => Self::None,
+            UncheckedMorphologyCfg::Open(size) => Self::Open(check(size)?),
+            UncheckedMorphologyCfg::Close(size) => Self::Close(check(size)?),
+        })
+    }
+}
+
+/// Parameters of the ONNX neural network detector.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OnnxDetectorCfg {
+    /// Path of the ONNX model file.
+    pub model_path: String,
+    /// Minimum confidence of a detection.
+    ///
+    /// Valid range is 0.0 - 1.0.
+    pub confidence_threshold: f32,
+}
+
+/// Parameters of the per-pixel mixture of Gaussians background model.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MixtureOfGaussiansCfg {
+    /// Number of Gaussian components per pixel.
+    ///
+    /// Valid range is 1 - 5.
+    pub n_components: u8,
+    /// Number of standard deviations within which a pixel value matches a
+    /// component.
+    pub match_n_sigma: f32,
+    /// Fraction of the total weight of the most probable components which are
+    /// considered background.
+    ///
+    /// Valid range is 0.0 - 1.0.
+    pub background_ratio: f32,
+    /// Variance of a newly created component.
+    pub initial_variance: f32,
+    /// Minimum variance of a component.
+    ///
+    /// This prevents oversensitivity in regions without noise.
+    pub min_variance: f32,
+    /// Switch whether to suppress shadows.
+    ///
+    /// A pixel which is not background but whose intensity is darker than the
+    /// background by a factor in the range `shadow_min_ratio` - 1.0 is
+    /// considered a shadow and is not detected.
+    pub shadow_suppression: bool,
+    /// Minimum ratio of pixel intensity to background intensity for a shadow.
+    ///
+    /// Valid range is 0.0 - 1.0.
+    pub shadow_min_ratio: f32,
+}
+
+impl Default for MixtureOfGaussiansCfg {
+    fn default() -> Self {
+        Self {
+            n_components: 3,
+            match_n_sigma: 2.5,
+            background_ratio: 0.9,
+            initial_variance: 225.0,
+            min_variance: 16.0,
+            shadow_suppression: true,
+            shadow_min_ratio: 0.5,
+        }
+    }
+}
+
 /// Configuration parameters for feature detection.
 ///
 /// These parameters are used in the 2D feature detection step. As such, they
@@ -74,4 +227,27 @@ pub struct ImPtDetectCfg {
     /// The shape of the reason over which detected points are checked.
     #[serde(with = "serde_yaml::with::singleton_map")]
     pub valid_region: Shape,
+    /// Regions of the image in which no points are detected.
+    ///
+    /// This is used to suppress persistent false detections, for example from
+    /// reflections or arena hardware, within `valid_region`.
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    pub occlusion_masks: Vec<Shape>,
+    /// Morphological filtering of the difference from the background.
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub morphology: MorphologyCfg,
+    /// The model of the background.
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub background_model: BackgroundModelCfg,
+    /// Switch whether to compute the difference from the background on the
+    /// GPU.
+    ///
+    /// This has an effect only when compiled with the `gpu` feature and when
+    /// the [BackgroundModelCfg::MeanVariance] background model is used. If no
+    /// GPU is available, the difference is computed on the CPU.
+    #[serde(default)]
+    pub use_gpu: bool,
+    /// The method used to detect features.
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub detector: DetectorCfg,
 }
@@ -24,6 +24,11 @@ fn my_default(polarity: ContrastPolarity, valid_region: Shape) -> ImPtDetectCfg
         clear_fraction: 0.3,
         despeckle_threshold: 5,
         valid_region,
+        occlusion_masks: vec![],
+        morphology: Default::default(),
+        background_model: Default::default(),
+        use_gpu: false,
+        detector: Default::default(),
     }
 }
 
@@ -25,6 +25,8 @@ type FromWorker = (
 pub(crate) struct BackgroundModel {
     pub(crate) mean_background: FastImageData<Chan1, f32>,
     pub(crate) mean_im: FastImageData<Chan1, u8>,
+    /// Incremented whenever `mean_im` is replaced.
+    pub(crate) mean_im_generation: u64,
     pub(crate) mean_squared_im: FastImageData<Chan1, f32>,
     pub(crate) cmp_im: FastImageData<Chan1, u8>,
     pub(crate) current_roi: FastImageRegion,
@@ -142,6 +144,7 @@ impl BackgroundModel {
             mean_background: running_mean,
             mean_squared_im,
             mean_im,
+            mean_im_generation: 0,
             cmp_im,
             current_roi,
             tx_to_worker,
@@ -178,6 +181,7 @@ impl BackgroundModel {
                 self.mean_background = running_mean;
                 self.mean_squared_im = mean_squared_im;
                 self.mean_im = mean_im;
+                self.mean_im_generation += 1;
                 self.cmp_im = cmp_im;
                 self.current_roi = roi;
                 self.complete_stamp = ts;
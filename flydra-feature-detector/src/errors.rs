@@ -10,6 +10,14 @@ pub enum Error {
     ImageSizeChanged,
     #[error("BackgroundProcessingThreadDisconnected")]
     BackgroundProcessingThreadDisconnected,
+    #[error("no background model has been acquired")]
+    NoBackgroundModel,
+    #[error("invalid background model file: {0}")]
+    InvalidBackgroundFile(String),
+    #[error("GPU error: {0}")]
+    GpuError(String),
+    #[error("ONNX error: {0}")]
+    OnnxError(String),
 
     #[error("CastError({})", _0)]
     CastError(#[from] cast::Error),
@@ -21,6 +29,8 @@ pub enum Error {
     },
     #[error("FastImageError({0})")]
     FastImageError(#[from] fastim_mod::Error),
+    #[error("YAML error: {0}")]
+    SerdeYamlError(#[from] serde_yaml::Error),
     #[error("IoError: {source}")]
     IoError {
         #[from]
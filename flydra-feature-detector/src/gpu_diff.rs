@@ -0,0 +1,573 @@
+//! Computation of the difference from the background on the GPU.
+//!
+//! With many high resolution cameras at high frame rates, computing the
+//! difference of each frame from the mean background image can saturate the
+//! CPU. This module does this computation in a wgpu compute shader instead.
+//! Four 8-bit pixels are packed into each 32-bit word and processed by one
+//! shader invocation.
+//!
+//! The mean background image stays on the GPU and is only uploaded when it
+//! changes. The shader also marks each row containing a pixel at or above the
+//! difference threshold. Only the rows near such a row are read back, and the
+//! remaining rows are set to zero. Within the rows read back, the result is
+//! identical to the CPU implementation, so the features found are the same.
+//!
+//! The search for blobs in the difference image remains on the CPU because it
+//! proceeds one blob at a time.
+//!
+//! If no GPU is available or a GPU error occurs, the caller falls back to the
+//! CPU implementation.
+
+use std::borrow::Cow;
+
+use flydra_feature_detector_types::ContrastPolarity;
+use tracing::{info, warn};
+
+use crate::{errors::Error, fastim_mod, Result};
+use fastim_mod::{Chan1, FastImage, FastImageSize, MutableFastImage};
+
+const WORKGROUP_SIZE: u32 = 64;
+const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65535;
+
+const SHADER: &str = r#"
+struct Params {
+    n_words: u32,
+    polarity: u32,
+    words_per_row: u32,
+    threshold: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> raw_im: array<u32>;
+@group(0) @binding(2) var<storage, read> mean_im: array<u32>;
+@group(0) @binding(3) var<storage, read_write> absdiff_im: array<u32>;
+@group(0) @binding(4) var<storage, read_write> hot_rows: array<atomic<u32>>;
+
+fn diff(raw: u32, mean: u32) -> u32 {
+    switch params.polarity {
+        case 0u: {
+            // DetectLight
+            return select(0u, raw - mean, raw > mean);
+        }
+        case 1u: {
+            // DetectDark
+            return select(0u, mean - raw, mean > raw);
+        }
+        default: {
+            // DetectAbsDiff
+            return max(raw, mean) - min(raw, mean);
+        }
+    }
+}
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) n_groups: vec3<u32>,
+) {
+    let i = id.y * n_groups.x * 64u + id.x;
+    if (i >= params.n_words) {
+        return;
+    }
+    let raw = raw_im[i];
+    let mean = mean_im[i];
+    var result = 0u;
+    var max_d = 0u;
+    for (var shift = 0u; shift < 32u; shift += 8u) {
+        let d = diff((raw >> shift) & 0xffu, (mean >> shift) & 0xffu);
+        result |= d << shift;
+        max_d = max(max_d, d);
+    }
+    absdiff_im[i] = result;
+    if (max_d >= params.threshold) {
+        atomicOr(&hot_rows[i / params.words_per_row], 1u);
+    }
+}
+"#;
+
+/// Whether the GPU has been set up.
+#[derive(Default)]
+pub(crate) enum GpuState {
+    #[default]
+    Uninitialized,
+    Ready(Box<GpuDiff>),
+    /// No GPU is available or it failed. The CPU is used.
+    Unavailable,
+}
+
+/// The mean background image and when it last changed.
+pub(crate) struct Background<'a, S> {
+    pub(crate) mean_im: &'a S,
+    /// Incremented whenever `mean_im` changes.
+    pub(crate) generation: u64,
+}
+
+/// Which parts of the difference image are needed.
+pub(crate) struct Threshold {
+    /// Rows without a pixel at or above this value may not contain features.
+    pub(crate) value: u8,
+    /// Number of rows around such a row which are still needed.
+    pub(crate) margin: usize,
+}
+
+impl GpuState {
+    /// Compute the difference from the background on the GPU, if possible.
+    ///
+    /// Returns `false` if no GPU is available, in which case `absdiff_im` is
+    /// unchanged and the CPU must be used.
+    pub(crate) fn compute<S1, S2, D>(
+        &mut self,
+        raw_im: &S1,
+        background: &Background<S2>,
+        polarity: &ContrastPolarity,
+        threshold: &Threshold,
+        absdiff_im: &mut D,
+        size: &FastImageSize,
+    ) -> bool
+    where
+        S1: FastImage<D = u8, C = Chan1>,
+        S2: FastImage<D = u8, C = Chan1>,
+        D: MutableFastImage<D = u8, C = Chan1>,
+    {
+        let (width, height) = (size.width() as usize, size.height() as usize);
+        if let GpuState::Ready(gpu) = self {
+            if gpu.width != width || gpu.height != height {
+                *self = GpuState::Uninitialized;
+            }
+        }
+        if let GpuState::Uninitialized = self {
+            *self = match GpuDiff::new(width, height) {
+                Ok(gpu) => {
+                    info!("computing difference from background on GPU");
+                    GpuState::Ready(Box::new(gpu))
+                }
+                Err(e) => {
+                    warn!("GPU not available, using CPU: {e}");
+                    GpuState::Unavailable
+                }
+            };
+        }
+        let GpuState::Ready(gpu) = self else {
+            return false;
+        };
+        match gpu.compute(raw_im, background, polarity, threshold, absdiff_im, size) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("GPU failed, using CPU: {e}");
+                *self = GpuState::Unavailable;
+                false
+            }
+        }
+    }
+}
+
+pub(crate) struct GpuDiff {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    params: wgpu::Buffer,
+    raw_im: wgpu::Buffer,
+    mean_im: wgpu::Buffer,
+    absdiff_im: wgpu::Buffer,
+    hot_rows: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    hot_rows_readback: wgpu::Buffer,
+    width: usize,
+    height: usize,
+    /// Number of bytes per row in the packed images. A multiple of four.
+    row_bytes: usize,
+    /// Staging memory on the host for the packed images.
+    host_buf: Vec<u8>,
+    /// Generation of the mean background image on the GPU.
+    mean_generation: Option<u64>,
+}
+
+impl GpuDiff {
+    pub(crate) fn new(width: usize, height: usize) -> Result<Self> {
+        pollster::block_on(Self::new_async(width, height))
+    }
+
+    async fn new_async(width: usize, height: usize) -> Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter: false,
+                compatible_surface: None,
+            })
+            .await
+            .ok_or_else(|| Error::GpuError("no GPU adapter found".into()))?;
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("flydra-feature-detector"),
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::downlevel_defaults(),
+                    memory_hints: wgpu::MemoryHints::Performance,
+                },
+                None,
+            )
+            .await
+            .map_err(|e| Error::GpuError(e.to_string()))?;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("absdiff"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(SHADER)),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("absdiff"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let row_bytes = width.div_ceil(4) * 4;
+        let n_bytes = (row_bytes * height).max(4) as u64;
+        let storage = |label, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: n_bytes,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let input_usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+        let raw_im = storage("raw_im", input_usage);
+        let mean_im = storage("mean_im", input_usage);
+        let absdiff_im = storage(
+            "absdiff_im",
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+        let readback = storage(
+            "readback",
+            wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        );
+        let hot_rows_bytes = (height.max(1) * 4) as u64;
+        let hot_rows = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("hot_rows"),
+            size: hot_rows_bytes,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let hot_rows_readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("hot_rows_readback"),
+            size: hot_rows_bytes,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("params"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("absdiff"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: raw_im.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: mean_im.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: absdiff_im.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: hot_rows.as_entire_binding(),
+                },
+            ],
+        });
+
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+            bind_group,
+            params,
+            raw_im,
+            mean_im,
+            absdiff_im,
+            hot_rows,
+            readback,
+            hot_rows_readback,
+            width,
+            height,
+            row_bytes,
+            host_buf: vec![0; n_bytes as usize],
+            mean_generation: None,
+        })
+    }
+
+    pub(crate) fn compute<S1, S2, D>(
+        &mut self,
+        raw_im: &S1,
+        background: &Background<S2>,
+        polarity: &ContrastPolarity,
+        threshold: &Threshold,
+        absdiff_im: &mut D,
+        size: &FastImageSize,
+    ) -> Result<()>
+    where
+        S1: FastImage<D = u8, C = Chan1>,
+        S2: FastImage<D = u8, C = Chan1>,
+        D: MutableFastImage<D = u8, C = Chan1>,
+    {
+        let n_words = cast::u32(self.host_buf.len() / 4)?;
+        let words_per_row = cast::u32(self.row_bytes / 4)?.max(1);
+        let polarity: u32 = match polarity {
+            ContrastPolarity::DetectLight => 0,
+            ContrastPolarity::DetectDark => 1,
+            ContrastPolarity::DetectAbsDiff => 2,
+        };
+        let mut params = [0u8; 16];
+        params[0..4].copy_from_slice(&n_words.to_le_bytes());
+        params[4..8].copy_from_slice(&polarity.to_le_bytes());
+        params[8..12].copy_from_slice(&words_per_row.to_le_bytes());
+        params[12..16].copy_from_slice(&u32::from(threshold.value).to_le_bytes());
+        self.queue.write_buffer(&self.params, 0, &params);
+
+        if self.mean_generation != Some(background.generation) {
+            pack(background.mean_im, size, self.row_bytes, &mut self.host_buf)?;
+            self.queue.write_buffer(&self.mean_im, 0, &self.host_buf);
+            self.mean_generation = Some(background.generation);
+        }
+        pack(raw_im, size, self.row_bytes, &mut self.host_buf)?;
+        self.queue.write_buffer(&self.raw_im, 0, &self.host_buf);
+
+        let n_groups = n_words.div_ceil(WORKGROUP_SIZE);
+        let groups_x = n_groups.clamp(1, MAX_WORKGROUPS_PER_DIMENSION);
+        let groups_y = n_groups.div_ceil(groups_x).max(1);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("absdiff"),
+            });
+        encoder.clear_buffer(&self.hot_rows, 0, None);
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("absdiff"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.dispatch_workgroups(groups_x, groups_y, 1);
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.hot_rows,
+            0,
+            &self.hot_rows_readback,
+            0,
+            self.hot_rows.size(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        // Find the rows which are needed.
+        let hot_rows = {
+            let slice = self.hot_rows_readback.slice(..);
+            self.map(&slice)?;
+            let flags = slice.get_mapped_range();
+            let mut hot = flags
+                .chunks_exact(4)
+                .take(self.height)
+                .enumerate()
+                .filter(|(_, flag)| flag.iter().any(|b| *b != 0))
+                .map(|(row, _)| row);
+            let first = hot.next();
+            let last = hot.last().or(first);
+            first.zip(last)
+        };
+        self.hot_rows_readback.unmap();
+        let rows = match hot_rows {
+            Some((first, last)) => {
+                first.saturating_sub(threshold.margin)
+                    ..(last + threshold.margin + 1).min(self.height)
+            }
+            None => 0..0,
+        };
+
+        if !rows.is_empty() {
+            let offset = (rows.start * self.row_bytes) as u64;
+            let n_bytes = (rows.len() * self.row_bytes) as u64;
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("absdiff readback"),
+                });
+            encoder.copy_buffer_to_buffer(&self.absdiff_im, offset, &self.readback, 0, n_bytes);
+            self.queue.submit(Some(encoder.finish()));
+            let slice = self.readback.slice(..n_bytes);
+            self.map(&slice)?;
+            {
+                let packed = slice.get_mapped_range();
+                for (dest, src) in absdiff_im
+                    .valid_row_iter_mut(size)?
+                    .skip(rows.start)
+                    .zip(packed.chunks_exact(self.row_bytes))
+                {
+                    dest.copy_from_slice(&src[..dest.len()]);
+                }
+            }
+            self.readback.unmap();
+        }
+        for (row, dest) in absdiff_im.valid_row_iter_mut(size)?.enumerate() {
+            if !rows.contains(&row) {
+                dest.fill(0);
+            }
+        }
+        Ok(())
+    }
+
+    /// Map `slice` for reading, waiting until the GPU is done.
+    fn map(&self, slice: &wgpu::BufferSlice) -> Result<()> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        rx.recv()
+            .map_err(|e| Error::GpuError(e.to_string()))?
+            .map_err(|e| Error::GpuError(e.to_string()))
+    }
+}
+
+/// Copy the rows of `im` into `packed`, which has `row_bytes` per row.
+fn pack<S>(im: &S, size: &FastImageSize, row_bytes: usize, packed: &mut [u8]) -> Result<()>
+where
+    S: FastImage<D = u8, C = Chan1>,
+{
+    for (src, dest) in im
+        .valid_row_iter(size)?
+        .zip(packed.chunks_exact_mut(row_bytes))
+    {
+        dest[..src.len()].copy_from_slice(src);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_gpu_diff() -> anyhow::Result<()> {
+    use fastim_mod::FastImageData;
+
+    let (w, h) = (7, 9);
+    let gpu = match GpuDiff::new(w, h) {
+        Ok(gpu) => gpu,
+        Err(e) => {
+            // Without a GPU, there is nothing to test.
+            eprintln!("skipping GPU test: {e}");
+            return Ok(());
+        }
+    };
+    let mut gpu = GpuState::Ready(Box::new(gpu));
+
+    let mut raw_im = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 100)?;
+    raw_im.pixel_slice_mut(1, 2)[0] = 250;
+    raw_im.pixel_slice_mut(2, 6)[0] = 10;
+    let mean_im = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 90)?;
+    let size = *raw_im.size();
+    let mut absdiff_im = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 0)?;
+    let background = Background {
+        mean_im: &mean_im,
+        generation: 0,
+    };
+
+    // With a threshold of zero, every row is read back.
+    let all_rows = Threshold {
+        value: 0,
+        margin: 0,
+    };
+    for (polarity, bright, dark) in [
+        (ContrastPolarity::DetectLight, 160, 0),
+        (ContrastPolarity::DetectDark, 0, 80),
+        (ContrastPolarity::DetectAbsDiff, 160, 80),
+    ] {
+        assert!(gpu.compute(
+            &raw_im,
+            &background,
+            &polarity,
+            &all_rows,
+            &mut absdiff_im,
+            &size
+        ));
+        let background = match polarity {
+            ContrastPolarity::DetectDark => 0,
+            _ => 10,
+        };
+        assert_eq!(absdiff_im.pixel_slice(0, 0)[0], background);
+        assert_eq!(absdiff_im.pixel_slice(8, 0)[0], background);
+        assert_eq!(absdiff_im.pixel_slice(1, 2)[0], bright);
+        assert_eq!(absdiff_im.pixel_slice(2, 6)[0], dark);
+    }
+
+    // Only rows within the margin of a pixel above threshold are read back.
+    let threshold = Threshold {
+        value: 50,
+        margin: 1,
+    };
+    let polarity = ContrastPolarity::DetectLight;
+    assert!(gpu.compute(
+        &raw_im,
+        &background,
+        &polarity,
+        &threshold,
+        &mut absdiff_im,
+        &size
+    ));
+    for row in 0..h {
+        let expected = match row {
+            1 => [10, 10, 160, 10, 10, 10, 10],
+            0 | 2 => [10; 7],
+            _ => [0; 7],
+        };
+        let actual: Vec<u8> = (0..w)
+            .map(|col| absdiff_im.pixel_slice(row, col)[0])
+            .collect();
+        assert_eq!(actual, expected, "row {row}");
+    }
+
+    // The background is only uploaded when its generation changes.
+    let new_mean_im = FastImageData::<Chan1, u8>::new(w as i32, h as i32, 0)?;
+    let stale = Background {
+        mean_im: &new_mean_im,
+        generation: 0,
+    };
+    assert!(gpu.compute(
+        &raw_im,
+        &stale,
+        &polarity,
+        &all_rows,
+        &mut absdiff_im,
+        &size
+    ));
+    assert_eq!(absdiff_im.pixel_slice(0, 0)[0], 10);
+    let updated = Background {
+        mean_im: &new_mean_im,
+        generation: 1,
+    };
+    assert!(gpu.compute(
+        &raw_im,
+        &updated,
+        &polarity,
+        &all_rows,
+        &mut absdiff_im,
+        &size
+    ));
+    assert_eq!(absdiff_im.pixel_slice(0, 0)[0], 100);
+    Ok(())
+}
@@ -23,7 +23,8 @@ use std::fs::File;
 
 use fastim_mod::{
     ipp_ctypes, ripp, AlgorithmHint, Chan1, CompareOp, FastImage, FastImageData, FastImageRegion,
-    FastImageSize, FastImageView, MomentState, MutableFastImage, MutableFastImageView,
+    FastImageSize, FastImageView, MomentState, MorphologyBuffer, MutableFastImage,
+    MutableFastImageView,
 };
 
 use formats::{pixel_format::Mono32f, Stride};
@@ -35,7 +36,10 @@ use flydra_types::{
 };
 use ufmf::UFMFWriter;
 
-pub use flydra_feature_detector_types::{ContrastPolarity, ImPtDetectCfg};
+pub use flydra_feature_detector_types::{
+    BackgroundModelCfg, ContrastPolarity, DetectorCfg, ImPtDetectCfg, MixtureOfGaussiansCfg,
+    MorphologyCfg, OnnxDetectorCfg,
+};
 use http_video_streaming_types::Shape;
 
 mod borrow_fastimage;
@@ -44,6 +48,18 @@ use crate::borrow_fastimage::borrow_fi;
 mod background_model;
 use crate::background_model::BackgroundModel;
 
+mod mixture_background;
+use crate::mixture_background::MixtureBackground;
+
+#[cfg(feature = "gpu")]
+mod gpu_diff;
+
+#[cfg(feature = "onnx")]
+mod onnx_detector;
+
+mod saved_background;
+use crate::saved_background::{SavedBackground, SavedBackgroundHeader};
+
 mod errors;
 pub use crate::errors::*;
 
@@ -78,6 +94,28 @@ fn compute_slope(moments: &MomentState) -> Result<(f64, f64)> {
     Ok((slope, eccentricity))
 }
 
+/// Mean value of `im` over the pixels which are nonzero in `mask`.
+fn masked_mean<S1, S2>(im: &S1, mask: &S2, size: &FastImageSize) -> Result<f64>
+where
+    S1: FastImage<D = u8, C = Chan1>,
+    S2: FastImage<D = u8, C = Chan1>,
+{
+    let mut sum = 0u64;
+    let mut count = 0u64;
+    for (im_row, mask_row) in im.valid_row_iter(size)?.zip(mask.valid_row_iter(size)?) {
+        for (val, mask_val) in im_row.iter().zip(mask_row.iter()) {
+            if *mask_val != 0 {
+                sum += *val as u64;
+                count += 1;
+            }
+        }
+    }
+    if count == 0 {
+        return Ok(f64::NAN);
+    }
+    Ok(sum as f64 / count as f64)
+}
+
 #[allow(dead_code)]
 #[derive(Serialize)]
 enum ImageTrackerState {
@@ -115,9 +153,16 @@ impl PointInfo {
 
 struct TrackingState {
     background: BackgroundModel,
+    /// Used if [BackgroundModelCfg::MixtureOfGaussians] is configured.
+    mixture: Option<MixtureBackground>,
+    /// Used if `use_gpu` is configured.
+    #[cfg(feature = "gpu")]
+    gpu: gpu_diff::GpuState,
     moments: MomentState,
     absdiff_im: FastImageData<Chan1, u8>,
     cmpdiff_im: FastImageData<Chan1, u8>,
+    /// Scratch space for [MorphologyCfg] filtering.
+    morphology_buffer: MorphologyBuffer,
     frames_since_background_update: u32,
 }
 
@@ -148,18 +193,28 @@ impl TrackingState {
         Ok(Self {
             moments: MomentState::new(AlgorithmHint::Fast)?,
             background,
+            mixture: None,
+            #[cfg(feature = "gpu")]
+            gpu: Default::default(),
             absdiff_im: FastImageData::<Chan1, u8>::new(w, h, 0)?,
             cmpdiff_im: FastImageData::<Chan1, u8>::new(w, h, 0)?,
+            morphology_buffer: MorphologyBuffer::new(),
             frames_since_background_update: 0,
         })
     }
 
+    /// Find the features in a frame.
+    ///
+    /// If `update_background` is true and the mixture of Gaussians background
+    /// model is used, the model is updated with this frame. (The mean and
+    /// variance background model is updated separately.)
     fn do_work<S1, S2>(
         &mut self,
         // corrected_framenumber: usize,
         raw_im_full: &S1,
         cfg: &ImPtDetectCfg,
         maybe_mask_image: Option<&S2>,
+        update_background: bool,
     ) -> Result<Vec<PointInfo>>
     where
         S1: FastImage<D = u8, C = Chan1>,
@@ -177,35 +232,125 @@ impl TrackingState {
         let mut absdiff_im_roi_view =
             MutableFastImageView::view_region(&mut self.absdiff_im, &self.background.current_roi)?;
 
-        // find difference from mean
-        match cfg.polarity {
-            ContrastPolarity::DetectLight => {
-                // absdiff_im = raw_im_small - mean_im
-                ripp::sub_8u_c1rsfs(
-                    &mean_im_roi_view,
+        let use_cmp = match &cfg.background_model {
+            BackgroundModelCfg::MeanVariance => {
+                self.mixture = None;
+                cfg.use_cmp
+            }
+            BackgroundModelCfg::MixtureOfGaussians(mog_cfg) => {
+                let size = self.background.current_roi.size();
+                let (w, h) = (size.width() as usize, size.height() as usize);
+                if !self
+                    .mixture
+                    .as_ref()
+                    .map(|m| m.is_compatible(mog_cfg, w, h))
+                    .unwrap_or(false)
+                {
+                    // Start from the current mean and variance.
+                    self.mixture = Some(MixtureBackground::new(
+                        mog_cfg,
+                        &self.background.mean_background,
+                        &self.background.mean_squared_im,
+                    )?);
+                }
+                // The variance is part of the mixture model.
+                false
+            }
+        };
+
+        // find difference from background
+        if let Some(mixture) = self.mixture.as_mut() {
+            let learning_rate = if update_background {
+                Some(cfg.alpha)
+            } else {
+                None
+            };
+            mixture.apply(
+                &raw_im_small,
+                &cfg.polarity,
+                learning_rate,
+                &mut absdiff_im_roi_view,
+            )?;
+        } else {
+            #[cfg(feature = "gpu")]
+            let done_on_gpu = cfg.use_gpu && {
+                let background = gpu_diff::Background {
+                    mean_im: &mean_im_roi_view,
+                    generation: self.background.mean_im_generation,
+                };
+                // Features are found only at pixels reaching `diff_threshold`
+                // and use the pixels within `feature_window_size` of them.
+                // Morphology can move these by up to three times its radius.
+                let threshold = gpu_diff::Threshold {
+                    value: cfg.diff_threshold,
+                    margin: usize::from(cfg.feature_window_size)
+                        + 3 * cfg.morphology.radius() as usize,
+                };
+                self.gpu.compute(
                     &raw_im_small,
+                    &background,
+                    &cfg.polarity,
+                    &threshold,
                     &mut absdiff_im_roi_view,
                     self.background.current_roi.size(),
-                    0,
-                )?;
+                )
+            };
+            #[cfg(not(feature = "gpu"))]
+            let done_on_gpu = false;
+
+            if !done_on_gpu {
+                match cfg.polarity {
+                    ContrastPolarity::DetectLight => {
+                        // absdiff_im = raw_im_small - mean_im
+                        ripp::sub_8u_c1rsfs(
+                            &mean_im_roi_view,
+                            &raw_im_small,
+                            &mut absdiff_im_roi_view,
+                            self.background.current_roi.size(),
+                            0,
+                        )?;
+                    }
+                    ContrastPolarity::DetectDark => {
+                        // absdiff_im = mean_im - raw_im_small
+                        ripp::sub_8u_c1rsfs(
+                            &raw_im_small,
+                            &mean_im_roi_view,
+                            &mut absdiff_im_roi_view,
+                            self.background.current_roi.size(),
+                            0,
+                        )?;
+                    }
+                    ContrastPolarity::DetectAbsDiff => {
+                        // absdiff_im = |mean_im - raw_im_small|
+                        ripp::abs_diff_8u_c1r(
+                            &raw_im_small,
+                            &mean_im_roi_view,
+                            &mut absdiff_im_roi_view,
+                            self.background.current_roi.size(),
+                        )?;
+                    }
+                }
             }
-            ContrastPolarity::DetectDark => {
-                // absdiff_im = mean_im - raw_im_small
-                ripp::sub_8u_c1rsfs(
-                    &raw_im_small,
-                    &mean_im_roi_view,
+        }
+
+        // remove noise or fill holes in the difference image before masking, so
+        // that closing does not fill masked pixels
+        match cfg.morphology {
+            MorphologyCfg::None => {}
+            MorphologyCfg::Open(_) => {
+                fastim_mod::open_8u_c1ir(
                     &mut absdiff_im_roi_view,
                     self.background.current_roi.size(),
-                    0,
+                    cfg.morphology.radius(),
+                    &mut self.morphology_buffer,
                 )?;
             }
-            ContrastPolarity::DetectAbsDiff => {
-                // absdiff_im = |mean_im - raw_im_small|
-                ripp::abs_diff_8u_c1r(
-                    &raw_im_small,
-                    &mean_im_roi_view,
+            MorphologyCfg::Close(_) => {
+                fastim_mod::close_8u_c1ir(
                     &mut absdiff_im_roi_view,
                     self.background.current_roi.size(),
+                    cfg.morphology.radius(),
+                    &mut self.morphology_buffer,
                 )?;
             }
         }
@@ -220,7 +365,7 @@ impl TrackingState {
             )?;
         }
 
-        if cfg.use_cmp {
+        if use_cmp {
             // clip the minimum comparison value to diff_threshold
             ripp::threshold_val_8u_c1ir(
                 &mut self.background.cmp_im,
@@ -242,7 +387,7 @@ impl TrackingState {
 
             let (max_abs_diff, max_loc) = {
                 // find max pixel
-                if cfg.use_cmp {
+                if use_cmp {
                     // cmpdiff_im = absdiff_im - cmp_im (saturates 8u)
                     ripp::sub_8u_c1rsfs(
                         &self.background.cmp_im,
@@ -266,7 +411,7 @@ impl TrackingState {
                 }
             };
 
-            if cfg.use_cmp {
+            if use_cmp {
                 if max_std_diff == 0 {
                     break; // no valid point found
                 }
@@ -346,6 +491,9 @@ impl TrackingState {
                             .mean_squared_im
                             .pixel_slice(max_loc.y() as usize, max_loc.x() as usize)[0]
                             as f64;
+                        let raw_im_roi2_view = FastImageView::view_region(&raw_im_small, &roi2)?;
+                        let mean_intensity =
+                            masked_mean(&raw_im_roi2_view, &absdiff_im_roi2_view, &roi2_sz)?;
 
                         all_points_found.push(PointInfo {
                             inner: flydra_types::FlydraRawUdpPoint {
@@ -356,6 +504,7 @@ impl TrackingState {
                                 cur_val,
                                 mean_val,
                                 sumsqf_val,
+                                mean_intensity,
                             },
                             index_x,
                             index_y,
@@ -377,6 +526,7 @@ enum BackgroundAcquisitionState {
     Initialization,
     StartupMode(StartupState),
     ClearToValue(f32),
+    FromSaved(SavedBackground),
     NormalUpdates(TrackingState),
     TemporaryHold,
 }
@@ -419,6 +569,9 @@ pub struct FlydraFeatureDetector {
     background_update_state: BackgroundAcquisitionState, // command from UI "take a new bg image"
     acquisition_histogram: AcquisitionHistogram,
     acquisition_duration_allowed_imprecision_msec: Option<f64>,
+    /// Used if [DetectorCfg::Onnx] is configured.
+    #[cfg(feature = "onnx")]
+    onnx: onnx_detector::OnnxState,
 
     transmit_feature_detect_settings_tx:
         Option<mpsc::Sender<flydra_feature_detector_types::ImPtDetectCfg>>,
@@ -562,6 +715,8 @@ impl FlydraFeatureDetector {
             background_update_state: BackgroundAcquisitionState::Initialization,
             acquisition_histogram,
             acquisition_duration_allowed_imprecision_msec,
+            #[cfg(feature = "onnx")]
+            onnx: Default::default(),
             transmit_feature_detect_settings_tx,
         };
 
@@ -586,7 +741,20 @@ impl FlydraFeatureDetector {
             sender.try_send(self.cfg.clone()).unwrap();
         }
 
-        self.mask_image = Some(compute_mask_image(&self.roi_sz, &self.cfg.valid_region)?);
+        let mut mask_image = compute_mask_image(&self.roi_sz, &self.cfg.valid_region)?;
+        apply_occlusion_masks(&mut mask_image, &self.cfg.occlusion_masks)?;
+        self.mask_image = Some(mask_image);
+
+        #[cfg(feature = "onnx")]
+        self.onnx.configure(
+            &self.cfg.detector,
+            self.roi_sz.width() as usize,
+            self.roi_sz.height() as usize,
+        );
+        #[cfg(not(feature = "onnx"))]
+        if let DetectorCfg::Onnx(_) = self.cfg.detector {
+            warn!("ONNX detector configured but not compiled in, using background subtraction");
+        }
         Ok(())
     }
 
@@ -604,6 +772,80 @@ impl FlydraFeatureDetector {
         Ok(())
     }
 
+    /// Save the current background model to `path`.
+    ///
+    /// The model can be loaded again with [Self::load_background_model].
+    /// Returns an error if the initial background model has not yet been
+    /// acquired.
+    pub fn save_background_model<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
+        let state = match &self.background_update_state {
+            BackgroundAcquisitionState::NormalUpdates(state) => state,
+            _ => return Err(Error::NoBackgroundModel),
+        };
+        let saved = SavedBackground {
+            header: self.saved_background_header(),
+            running_mean: FastImageData::copy_from_32f_c1(&state.background.mean_background)?,
+            mean_squared_im: FastImageData::copy_from_32f_c1(&state.background.mean_squared_im)?,
+        };
+        let mut fd = std::io::BufWriter::new(File::create(path.as_ref())?);
+        saved.write_to(&mut fd)?;
+        debug!("saved background model to {}", path.as_ref().display());
+        Ok(())
+    }
+
+    /// Return a copy of the mean image of the background model.
+    ///
+    /// Returns `None` if the background model has not yet been acquired.
+    pub fn mean_background(&self) -> Result<Option<basic_frame::BasicFrame<Mono32f>>> {
+        let state = match &self.background_update_state {
+            BackgroundAcquisitionState::NormalUpdates(state) => state,
+            _ => return Ok(None),
+        };
+        let mean: BorrowedFrame<Mono32f> = borrow_fi(&state.background.mean_background)?;
+        Ok(Some(basic_frame::BasicFrame::copy_from(&mean)))
+    }
+
+    /// Load a background model saved with [Self::save_background_model].
+    ///
+    /// The loaded model is used starting with the next frame. Returns `false`,
+    /// leaving the current model unchanged, if the saved model was acquired
+    /// with a different camera, image size or valid region.
+    pub fn load_background_model<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<bool> {
+        let fd = File::open(path.as_ref())?;
+        let len = fd.metadata()?.len();
+        let saved = SavedBackground::read_from(&mut std::io::BufReader::new(fd), len)?;
+        let expected = self.saved_background_header();
+        let header = &saved.header;
+        if header.cam_name != expected.cam_name
+            || header.width != expected.width
+            || header.height != expected.height
+            || header.valid_region != expected.valid_region
+        {
+            info!(
+                "Not using background model in {} because camera or region differs.",
+                path.as_ref().display()
+            );
+            return Ok(false);
+        }
+        debug!(
+            "loaded background model from {} (saved at {})",
+            path.as_ref().display(),
+            header.saved_at
+        );
+        self.background_update_state = BackgroundAcquisitionState::FromSaved(saved);
+        Ok(true)
+    }
+
+    fn saved_background_header(&self) -> SavedBackgroundHeader {
+        SavedBackgroundHeader {
+            cam_name: self.raw_cam_name.as_str().to_string(),
+            width: self.roi_sz.width() as u32,
+            height: self.roi_sz.height() as u32,
+            valid_region: self.cfg.valid_region.clone(),
+            saved_at: Utc::now(),
+        }
+    }
+
     /// Detect features of interest and update background model.
     ///
     /// The detected features are returned as a [FlydraRawUdpPacket] in the
@@ -611,6 +853,10 @@ impl FlydraFeatureDetector {
     ///
     /// A ufmf file can be updated by setting the `ufmf_state` argument to a
     /// value other than [UfmfState::Stopped].
+    ///
+    /// `n_frames_skipped` is the number of frames from the camera which were
+    /// missing immediately prior to this one and is passed on in the packet.
+    #[allow(clippy::too_many_arguments)]
     #[tracing::instrument(level = "debug", skip_all)]
     pub fn process_new_frame(
         &mut self,
@@ -620,6 +866,7 @@ impl FlydraFeatureDetector {
         ufmf_state: UfmfState,
         device_timestamp: Option<u64>,
         block_id: Option<u64>,
+        n_frames_skipped: u32,
         braid_ts: Option<FlydraFloatTimestampLocal<flydra_types::Triggerbox>>,
     ) -> Result<(FlydraRawUdpPacket, UfmfState)> {
         let pixel_format = frame.pixel_format();
@@ -698,7 +945,7 @@ impl FlydraFeatureDetector {
             device_timestamp,
             block_id,
             framenumber: fno as i32,
-            n_frames_skipped: 0, // FIXME TODO XXX FIX THIS, should be n_frames_skipped
+            n_frames_skipped,
             done_camnode_processing: 0.0,
             preprocess_stamp,
             image_processing_steps: ImageProcessingSteps::empty(),
@@ -709,6 +956,25 @@ impl FlydraFeatureDetector {
             BackgroundAcquisitionState::TemporaryHold => {
                 panic!("unreachable");
             }
+            #[cfg(feature = "onnx")]
+            state if self.onnx.is_enabled() => {
+                // The background model is not used, so its state is kept.
+                let points =
+                    self.onnx
+                        .detect_points(&raw_im_full, self.mask_image.as_ref(), &self.cfg)?;
+                if let UfmfState::Saving(ref mut ufmf_writer) = new_ufmf_state {
+                    let radius = self.cfg.feature_window_size;
+                    let point_data: Vec<_> = points
+                        .iter()
+                        .map(|p| p.to_ufmf_region(radius * 2))
+                        .collect();
+                    ufmf_writer.add_frame(frame, timestamp_utc, &point_data)?;
+                }
+                packet.image_processing_steps |= ImageProcessingSteps::ONNXDETECT;
+                packet.points = points.into_iter().map(|pt| pt.inner).collect();
+                packet.done_camnode_processing = to_f64(Utc::now());
+                (packet, state)
+            }
             BackgroundAcquisitionState::Initialization => {
                 let running_mean = FastImageData::<Chan1, f32>::copy_from_8u32f_c1(&raw_im_full)?;
 
@@ -792,6 +1058,18 @@ impl FlydraFeatureDetector {
                 packet.image_processing_steps |= ImageProcessingSteps::BGCLEARED;
                 (packet, BackgroundAcquisitionState::NormalUpdates(state))
             }
+            BackgroundAcquisitionState::FromSaved(saved) => {
+                let state = TrackingState::new(
+                    &raw_im_full,
+                    saved.running_mean,
+                    saved.mean_squared_im,
+                    &self.cfg,
+                    pixel_format,
+                    timestamp_utc,
+                )?;
+                packet.image_processing_steps |= ImageProcessingSteps::BGLOADED;
+                (packet, BackgroundAcquisitionState::NormalUpdates(state))
+            }
             BackgroundAcquisitionState::NormalUpdates(mut state) => {
                 let got_new_bg_data = state.background.poll_complete_updates()?;
 
@@ -812,6 +1090,7 @@ impl FlydraFeatureDetector {
                         &raw_im_full,
                         &self.cfg,
                         Some(mask_image),
+                        saved_bg_image.is_some(),
                     )?
                 } else {
                     state.do_work::<_, FastImageData<Chan1, u8>>(
@@ -819,6 +1098,7 @@ impl FlydraFeatureDetector {
                         &raw_im_full,
                         &self.cfg,
                         None,
+                        saved_bg_image.is_some(),
                     )?
                 };
 
@@ -945,6 +1225,26 @@ pub fn compute_mask_image(
     Ok(mask_image)
 }
 
+/// Additionally mask the pixels inside each of `occlusion_masks`.
+pub fn apply_occlusion_masks(
+    mask_image: &mut FastImageData<Chan1, u8>,
+    occlusion_masks: &[Shape],
+) -> Result<()> {
+    let roi_sz = *mask_image.size();
+    for shape in occlusion_masks.iter() {
+        // Pixels outside `shape` are nonzero in this image.
+        let outside = compute_mask_image(&roi_sz, shape)?;
+        for row in 0..roi_sz.height().try_into().unwrap() {
+            for col in 0..roi_sz.width().try_into().unwrap() {
+                if outside.pixel_slice(row, col)[0] == 0 {
+                    mask_image.pixel_slice_mut(row, col)[0] = 255;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 #[test]
 fn test_mask_polygon() -> anyhow::Result<()> {
     let roi_sz = FastImageSize::new(12, 8);
@@ -1026,3 +1326,32 @@ fn test_mask_multiple_circles() -> anyhow::Result<()> {
     assert_eq!(mask, expected);
     Ok(())
 }
+
+#[test]
+fn test_occlusion_masks() -> anyhow::Result<()> {
+    let roi_sz = FastImageSize::new(8, 3);
+    let mut mask = compute_mask_image(&roi_sz, &Shape::Everything)?;
+    let occlusion_masks = vec![
+        Shape::Circle(http_video_streaming_types::CircleParams {
+            center_x: 2,
+            center_y: 1,
+            radius: 1,
+        }),
+        Shape::Polygon(http_video_streaming_types::PolygonParams {
+            points: vec![(5.0, 0.0), (7.0, 0.0), (7.0, 2.0), (5.0, 2.0)],
+        }),
+    ];
+    apply_occlusion_masks(&mut mask, &occlusion_masks)?;
+    let expected = {
+        let mut full = FastImageData::<_, u8>::new(8, 3, 0)?;
+        full.pixel_slice_mut(1, 2)[0] = 255;
+        for row in 0..3 {
+            for col in 5..8 {
+                full.pixel_slice_mut(row, col)[0] = 255;
+            }
+        }
+        full
+    };
+    assert_eq!(mask, expected);
+    Ok(())
+}
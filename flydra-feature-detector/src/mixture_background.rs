@@ -0,0 +1,289 @@
+//! Adaptive per-pixel mixture of Gaussians background model.
+//!
+//! Each pixel is modeled by several Gaussian components, each with a weight,
+//! mean and variance, following Stauffer and Grimson (1999). The components of
+//! a pixel are kept sorted by decreasing weight. The most probable components
+//! which together account for `background_ratio` of the total weight model the
+//! background. A pixel value within `match_n_sigma` standard deviations of a
+//! background component is background. Otherwise, its difference from the mean
+//! of the most probable component is the foreground value used for detection.
+//!
+//! Because gray values carry no chromaticity, shadows are identified by their
+//! intensity alone: a foreground pixel darker than the background by a bounded
+//! factor is taken to be a shadow and suppressed.
+//!
+//! The weights, means and variances are stored as one plane per component so
+//! that, for each row, the distances of all pixels to a component are computed
+//! in a tight loop over contiguous memory which the compiler can vectorize.
+//! Only the classification and update of individual pixels is scalar.
+
+use flydra_feature_detector_types::{ContrastPolarity, MixtureOfGaussiansCfg};
+
+use crate::{fastim_mod, Result};
+use fastim_mod::{Chan1, FastImage, FastImageData, MutableFastImage};
+
+const MAX_COMPONENTS: usize = 5;
+
+pub(crate) struct MixtureBackground {
+    cfg: MixtureOfGaussiansCfg,
+    width: usize,
+    height: usize,
+    n_components: usize,
+    /// Component weights. Index `k * width * height + pixel_index`.
+    weight: Vec<f32>,
+    /// Component means. Same indexing as `weight`.
+    mean: Vec<f32>,
+    /// Component variances. Same indexing as `weight`.
+    variance: Vec<f32>,
+    /// Pixel values of the current row.
+    row_values: Vec<f32>,
+    /// Squared distances of the current row to each component. Index
+    /// `k * width + column`.
+    row_dist2: Vec<f32>,
+}
+
+impl MixtureBackground {
+    /// Create a new model from a running mean and mean squared image.
+    ///
+    /// Initially, each pixel has a single component with the given mean and
+    /// variance.
+    pub(crate) fn new(
+        cfg: &MixtureOfGaussiansCfg,
+        running_mean: &FastImageData<Chan1, f32>,
+        mean_squared_im: &FastImageData<Chan1, f32>,
+    ) -> Result<Self> {
+        let size = *running_mean.size();
+        let width = size.width() as usize;
+        let height = size.height() as usize;
+        let n_pixels = width * height;
+        let n_components = (cfg.n_components as usize).clamp(1, MAX_COMPONENTS);
+
+        let mut weight = vec![0.0; n_components * n_pixels];
+        let mut mean = vec![0.0; n_components * n_pixels];
+        let mut variance = vec![cfg.initial_variance; n_components * n_pixels];
+        weight[..n_pixels].fill(1.0);
+        let rows = running_mean
+            .valid_row_iter(&size)?
+            .zip(mean_squared_im.valid_row_iter(&size)?);
+        for (row, (mean_row, sumsq_row)) in rows.enumerate() {
+            let start = row * width;
+            let dest_mean = &mut mean[start..start + width];
+            let dest_var = &mut variance[start..start + width];
+            for (((m, v), src_m), src_sumsq) in dest_mean
+                .iter_mut()
+                .zip(dest_var.iter_mut())
+                .zip(mean_row.iter())
+                .zip(sumsq_row.iter())
+            {
+                *m = *src_m;
+                *v = (src_sumsq - src_m * src_m).max(cfg.min_variance);
+            }
+        }
+
+        Ok(Self {
+            cfg: cfg.clone(),
+            width,
+            height,
+            n_components,
+            weight,
+            mean,
+            variance,
+            row_values: vec![0.0; width],
+            row_dist2: vec![0.0; n_components * width],
+        })
+    }
+
+    /// Whether this model was created with the given parameters and size.
+    pub(crate) fn is_compatible(
+        &self,
+        cfg: &MixtureOfGaussiansCfg,
+        width: usize,
+        height: usize,
+    ) -> bool {
+        &self.cfg == cfg && self.width == width && self.height == height
+    }
+
+    /// Compute the foreground image and optionally update the model.
+    ///
+    /// Each pixel of `foreground` is set to the difference, with the given
+    /// polarity, between the pixel value in `im` and the background or to
+    /// zero for background and shadow pixels. If `learning_rate` is given, the
+    /// model is updated with `im`.
+    pub(crate) fn apply<S, D>(
+        &mut self,
+        im: &S,
+        polarity: &ContrastPolarity,
+        learning_rate: Option<f32>,
+        foreground: &mut D,
+    ) -> Result<()>
+    where
+        S: FastImage<C = Chan1, D = u8>,
+        D: MutableFastImage<C = Chan1, D = u8>,
+    {
+        let size = *im.size();
+        let rows = im
+            .valid_row_iter(&size)?
+            .zip(foreground.valid_row_iter_mut(&size)?);
+        for (row, (src, dest)) in rows.enumerate() {
+            self.apply_row(row, src, polarity, learning_rate, dest);
+        }
+        Ok(())
+    }
+
+    fn apply_row(
+        &mut self,
+        row: usize,
+        src: &[u8],
+        polarity: &ContrastPolarity,
+        learning_rate: Option<f32>,
+        dest: &mut [u8],
+    ) {
+        let width = self.width;
+        let n_pixels = self.width * self.height;
+        let start = row * width;
+
+        // Vectorizable part: distances to all components.
+        for (v, s) in self.row_values.iter_mut().zip(src.iter()) {
+            *v = *s as f32;
+        }
+        for k in 0..self.n_components {
+            let plane = k * n_pixels + start;
+            let means = &self.mean[plane..plane + width];
+            let dist2 = &mut self.row_dist2[k * width..(k + 1) * width];
+            for ((d2, m), v) in dist2
+                .iter_mut()
+                .zip(means.iter())
+                .zip(self.row_values.iter())
+            {
+                let d = v - m;
+                *d2 = d * d;
+            }
+        }
+
+        // Scalar part: classification and update of each pixel.
+        let match_n_sigma2 = self.cfg.match_n_sigma * self.cfg.match_n_sigma;
+        for (col, dest) in dest.iter_mut().enumerate() {
+            let i = start + col;
+            let value = self.row_values[col];
+
+            let mut matched = None;
+            let mut is_background = false;
+            let mut cum_weight = 0.0;
+            for k in 0..self.n_components {
+                let idx = k * n_pixels + i;
+                if self.weight[idx] <= 0.0 {
+                    // Unused components are at the end.
+                    break;
+                }
+                let dist2 = self.row_dist2[k * width + col];
+                if dist2 < match_n_sigma2 * self.variance[idx] {
+                    matched = Some(k);
+                    is_background = cum_weight < self.cfg.background_ratio;
+                    break;
+                }
+                cum_weight += self.weight[idx];
+            }
+
+            let bg_mean = self.mean[i];
+            let is_shadow = self.cfg.shadow_suppression
+                && value < bg_mean
+                && value >= self.cfg.shadow_min_ratio * bg_mean;
+            *dest = if is_background || is_shadow {
+                0
+            } else {
+                let diff = value - bg_mean;
+                let fg = match polarity {
+                    ContrastPolarity::DetectLight => diff,
+                    ContrastPolarity::DetectDark => -diff,
+                    ContrastPolarity::DetectAbsDiff => diff.abs(),
+                };
+                fg.round().clamp(0.0, 255.0) as u8
+            };
+
+            if let Some(alpha) = learning_rate {
+                self.update_pixel(i, value, matched, alpha);
+            }
+        }
+    }
+
+    fn update_pixel(&mut self, i: usize, value: f32, matched: Option<usize>, alpha: f32) {
+        let n_pixels = self.width * self.height;
+        let idx = |k: usize| k * n_pixels + i;
+
+        for k in 0..self.n_components {
+            self.weight[idx(k)] *= 1.0 - alpha;
+        }
+        let updated = match matched {
+            Some(k) => {
+                let j = idx(k);
+                self.weight[j] += alpha;
+                let rho = (alpha / self.weight[j]).min(1.0);
+                let d = value - self.mean[j];
+                self.mean[j] += rho * d;
+                self.variance[j] += rho * (d * d - self.variance[j]);
+                self.variance[j] = self.variance[j].max(self.cfg.min_variance);
+                k
+            }
+            None => {
+                // Replace the least probable component.
+                let k = self.n_components - 1;
+                let j = idx(k);
+                self.weight[j] = alpha;
+                self.mean[j] = value;
+                self.variance[j] = self.cfg.initial_variance;
+                k
+            }
+        };
+
+        let total: f32 = (0..self.n_components).map(|k| self.weight[idx(k)]).sum();
+        if total > 0.0 {
+            for k in 0..self.n_components {
+                self.weight[idx(k)] /= total;
+            }
+        }
+
+        // Restore the ordering by decreasing weight.
+        let mut k = updated;
+        while k > 0 && self.weight[idx(k)] > self.weight[idx(k - 1)] {
+            let (a, b) = (idx(k), idx(k - 1));
+            self.weight.swap(a, b);
+            self.mean.swap(a, b);
+            self.variance.swap(a, b);
+            k -= 1;
+        }
+    }
+}
+
+#[test]
+fn test_mixture_background() -> anyhow::Result<()> {
+    let cfg = MixtureOfGaussiansCfg::default();
+    let running_mean = FastImageData::<Chan1, f32>::new(4, 2, 100.0)?;
+    let mean_squared_im = FastImageData::<Chan1, f32>::new(4, 2, 100.0 * 100.0)?;
+    let mut model = MixtureBackground::new(&cfg, &running_mean, &mean_squared_im)?;
+    let mut fg = FastImageData::<Chan1, u8>::new(4, 2, 0)?;
+
+    let mut im = FastImageData::<Chan1, u8>::new(4, 2, 101)?;
+    // bright object
+    im.pixel_slice_mut(0, 1)[0] = 200;
+    // shadow
+    im.pixel_slice_mut(1, 2)[0] = 70;
+    // dark object
+    im.pixel_slice_mut(1, 3)[0] = 20;
+
+    model.apply(&im, &ContrastPolarity::DetectAbsDiff, None, &mut fg)?;
+    assert_eq!(fg.pixel_slice(0, 0)[0], 0);
+    assert_eq!(fg.pixel_slice(0, 1)[0], 100);
+    assert_eq!(fg.pixel_slice(1, 2)[0], 0);
+    assert_eq!(fg.pixel_slice(1, 3)[0], 80);
+
+    model.apply(&im, &ContrastPolarity::DetectDark, None, &mut fg)?;
+    assert_eq!(fg.pixel_slice(0, 1)[0], 0);
+    assert_eq!(fg.pixel_slice(1, 3)[0], 80);
+
+    // After the bright object remains for long enough, it becomes background.
+    for _ in 0..200 {
+        model.apply(&im, &ContrastPolarity::DetectAbsDiff, Some(0.05), &mut fg)?;
+    }
+    assert_eq!(fg.pixel_slice(0, 1)[0], 0);
+    Ok(())
+}
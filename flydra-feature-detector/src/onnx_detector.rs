@@ -0,0 +1,288 @@
+//! Feature detection with a neural network in ONNX format.
+//!
+//! For animals which cannot be segmented from the background, a neural
+//! network such as a small YOLO or keypoint model can be used instead of
+//! background subtraction. The model is run on the CPU with tract.
+//!
+//! The model must have a single input of shape `[1, C, H, W]`, where C is 1 or
+//! 3. It receives the grayscale image, scaled to the range 0.0 - 1.0 and
+//! resized to H×W. If H and W are not fixed by the model, the image is used at
+//! its original size.
+//!
+//! The first output must have shape `[1, N, K]`, with one row for each of N
+//! candidate detections, in pixel coordinates of the model input. With K = 3,
+//! each row is `(x, y, confidence)`, as produced by keypoint models. With K of
+//! at least 5, each row is `(center_x, center_y, width, height, confidence,
+//! ...)`, as produced by YOLO models.
+//!
+//! Of overlapping candidates, only the most confident is kept.
+
+use tracing::{error, info};
+use tract_onnx::prelude::*;
+use tract_onnx::tract_hir::infer::Factoid;
+
+use flydra_feature_detector_types::{DetectorCfg, ImPtDetectCfg, OnnxDetectorCfg};
+
+use crate::{errors::Error, fastim_mod, PointInfo, Result};
+use fastim_mod::{ipp_ctypes, Chan1, FastImage};
+
+type Model = TypedRunnableModel<TypedModel>;
+
+fn onnx_err<E: std::fmt::Display>(e: E) -> Error {
+    Error::OnnxError(e.to_string())
+}
+
+/// A candidate detection, in pixel coordinates of the full image.
+#[derive(Debug, Clone, PartialEq)]
+struct Detection {
+    x: f32,
+    y: f32,
+    /// Width and height, if the model estimates them.
+    size: Option<(f32, f32)>,
+    confidence: f32,
+}
+
+impl Detection {
+    /// Whether `other` is too close to this detection to be a distinct one.
+    fn overlaps(&self, other: &Detection, radius: f32) -> bool {
+        let (half_w, half_h) = match self.size {
+            Some((w, h)) => (w / 2.0, h / 2.0),
+            None => (radius, radius),
+        };
+        (self.x - other.x).abs() < half_w && (self.y - other.y).abs() < half_h
+    }
+}
+
+/// Keep the most confident of overlapping detections.
+///
+/// Detections without a size overlap if they are closer than `radius` in
+/// both x and y.
+fn suppress_overlapping(mut candidates: Vec<Detection>, radius: f32) -> Vec<Detection> {
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    let mut kept: Vec<Detection> = Vec::new();
+    for candidate in candidates {
+        if !kept.iter().any(|k| k.overlaps(&candidate, radius)) {
+            kept.push(candidate);
+        }
+    }
+    kept
+}
+
+pub(crate) struct OnnxDetector {
+    model: Model,
+    channels: usize,
+    height: usize,
+    width: usize,
+    confidence_threshold: f32,
+}
+
+impl OnnxDetector {
+    fn load(cfg: &OnnxDetectorCfg, frame_width: usize, frame_height: usize) -> Result<Self> {
+        let mut model = tract_onnx::onnx()
+            .model_for_path(&cfg.model_path)
+            .map_err(onnx_err)?;
+        let fact = model.input_fact(0).map_err(onnx_err)?;
+        let fixed_dim = |i: usize| -> Option<usize> {
+            let dim = fact.shape.dims().nth(i)?.concretize()?.as_i64()?;
+            usize::try_from(dim).ok()
+        };
+        let channels = fixed_dim(1).unwrap_or(1);
+        if channels != 1 && channels != 3 {
+            return Err(Error::OnnxError(format!(
+                "model input has {channels} channels, expected 1 or 3"
+            )));
+        }
+        let height = fixed_dim(2).unwrap_or(frame_height);
+        let width = fixed_dim(3).unwrap_or(frame_width);
+        model
+            .set_input_fact(0, f32::fact([1, channels, height, width]).into())
+            .map_err(onnx_err)?;
+        let model = model
+            .into_optimized()
+            .map_err(onnx_err)?
+            .into_runnable()
+            .map_err(onnx_err)?;
+        Ok(Self {
+            model,
+            channels,
+            height,
+            width,
+            confidence_threshold: cfg.confidence_threshold,
+        })
+    }
+
+    /// Run the model on `im` and return the detections, most confident first.
+    fn detect<S>(&self, im: &S, radius: f32) -> Result<Vec<Detection>>
+    where
+        S: FastImage<D = u8, C = Chan1>,
+    {
+        let size = *im.size();
+        let (frame_width, frame_height) = (size.width() as usize, size.height() as usize);
+        let rows: Vec<&[u8]> = im.valid_row_iter(&size)?.collect();
+        let input = tract_ndarray::Array4::from_shape_fn(
+            (1, self.channels, self.height, self.width),
+            |(_, _, y, x)| {
+                let src_y = y * frame_height / self.height;
+                let src_x = x * frame_width / self.width;
+                rows[src_y][src_x] as f32 / 255.0
+            },
+        );
+        let outputs = self
+            .model
+            .run(tvec!(Tensor::from(input).into()))
+            .map_err(onnx_err)?;
+        let output = outputs[0].to_array_view::<f32>().map_err(onnx_err)?;
+        let shape = output.shape();
+        if shape.len() != 3 || shape[0] != 1 || !(shape[2] == 3 || shape[2] >= 5) {
+            return Err(Error::OnnxError(format!(
+                "model output has shape {shape:?}, expected [1, N, 3] or [1, N, K>=5]"
+            )));
+        }
+
+        let scale_x = frame_width as f32 / self.width as f32;
+        let scale_y = frame_height as f32 / self.height as f32;
+        let candidates = output
+            .index_axis(tract_ndarray::Axis(0), 0)
+            .outer_iter()
+            .filter_map(|row| {
+                let (size, confidence) = if row.len() == 3 {
+                    (None, row[2])
+                } else {
+                    (Some((row[2] * scale_x, row[3] * scale_y)), row[4])
+                };
+                if confidence < self.confidence_threshold {
+                    return None;
+                }
+                Some(Detection {
+                    x: row[0] * scale_x,
+                    y: row[1] * scale_y,
+                    size,
+                    confidence,
+                })
+            })
+            .collect();
+        Ok(suppress_overlapping(candidates, radius))
+    }
+}
+
+/// The ONNX detector, if configured.
+#[derive(Default)]
+pub(crate) struct OnnxState {
+    /// The configuration with which `detector` was loaded.
+    cfg: Option<OnnxDetectorCfg>,
+    /// The loaded model. `None` if loading failed.
+    detector: Option<OnnxDetector>,
+}
+
+impl OnnxState {
+    /// Load the model if the configuration changed.
+    ///
+    /// Errors are logged and result in no detections.
+    pub(crate) fn configure(&mut self, cfg: &DetectorCfg, frame_width: usize, frame_height: usize) {
+        let onnx_cfg = match cfg {
+            DetectorCfg::BackgroundSubtraction => {
+                *self = Default::default();
+                return;
+            }
+            DetectorCfg::Onnx(onnx_cfg) => onnx_cfg,
+        };
+        if self.cfg.as_ref() == Some(onnx_cfg) {
+            return;
+        }
+        self.detector = match OnnxDetector::load(onnx_cfg, frame_width, frame_height) {
+            Ok(detector) => {
+                info!("loaded ONNX model {}", onnx_cfg.model_path);
+                Some(detector)
+            }
+            Err(e) => {
+                error!("could not load ONNX model {}: {e}", onnx_cfg.model_path);
+                None
+            }
+        };
+        self.cfg = Some(onnx_cfg.clone());
+    }
+
+    /// Whether the ONNX detector is used instead of background subtraction.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.cfg.is_some()
+    }
+
+    /// Detect features in `raw_im`.
+    ///
+    /// Detections at pixels which are nonzero in `mask_image` are ignored.
+    pub(crate) fn detect_points<S1, S2>(
+        &self,
+        raw_im: &S1,
+        mask_image: Option<&S2>,
+        cfg: &ImPtDetectCfg,
+    ) -> Result<Vec<PointInfo>>
+    where
+        S1: FastImage<D = u8, C = Chan1>,
+        S2: FastImage<D = u8, C = Chan1>,
+    {
+        let Some(detector) = &self.detector else {
+            return Ok(Vec::new());
+        };
+        let detections = detector.detect(raw_im, cfg.feature_window_size as f32)?;
+        let size = raw_im.size();
+        let mut points = Vec::new();
+        for det in detections {
+            if points.len() >= cfg.max_num_points as usize {
+                break;
+            }
+            let index_x = (det.x.round() as ipp_ctypes::c_int).clamp(0, size.width() - 1);
+            let index_y = (det.y.round() as ipp_ctypes::c_int).clamp(0, size.height() - 1);
+            if let Some(mask_image) = mask_image {
+                if mask_image.pixel_slice(index_y as usize, index_x as usize)[0] != 0 {
+                    continue;
+                }
+            }
+            let cur_val = raw_im.pixel_slice(index_y as usize, index_x as usize)[0];
+            let area = det.size.map(|(w, h)| (w * h) as f64).unwrap_or(f64::NAN);
+            points.push(PointInfo {
+                inner: flydra_types::FlydraRawUdpPoint {
+                    x0_abs: det.x as f64,
+                    y0_abs: det.y as f64,
+                    area,
+                    maybe_slope_eccentricty: None,
+                    cur_val,
+                    mean_val: f64::NAN,
+                    sumsqf_val: f64::NAN,
+                    mean_intensity: f64::NAN,
+                },
+                index_x,
+                index_y,
+                max_value: cur_val,
+            });
+        }
+        Ok(points)
+    }
+}
+
+#[test]
+fn test_suppress_overlapping() {
+    let det = |x, y, size, confidence| Detection {
+        x,
+        y,
+        size,
+        confidence,
+    };
+    let candidates = vec![
+        det(10.0, 10.0, Some((8.0, 8.0)), 0.6),
+        det(12.0, 11.0, Some((8.0, 8.0)), 0.9),
+        det(30.0, 10.0, Some((8.0, 8.0)), 0.5),
+        det(50.0, 50.0, None, 0.7),
+        det(52.0, 49.0, None, 0.8),
+        det(60.0, 50.0, None, 0.4),
+    ];
+    let kept = suppress_overlapping(candidates, 5.0);
+    assert_eq!(
+        kept,
+        vec![
+            det(12.0, 11.0, Some((8.0, 8.0)), 0.9),
+            det(52.0, 49.0, None, 0.8),
+            det(30.0, 10.0, Some((8.0, 8.0)), 0.5),
+            det(60.0, 50.0, None, 0.4),
+        ]
+    );
+}
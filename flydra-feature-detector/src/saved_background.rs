@@ -0,0 +1,156 @@
+//! Saving and loading of the background model.
+//!
+//! The background model is built up over many frames, during which detection
+//! is unreliable. To skip this warm-up period after a restart, the model can
+//! be saved to disk and loaded again later. A saved model is only used if it
+//! was acquired with the same camera, image size and valid region.
+//!
+//! The file consists of a magic number, a format version, a YAML header and
+//! then the mean and mean squared images as little-endian `f32` values in row
+//! major order.
+
+use std::io::{Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use http_video_streaming_types::Shape;
+
+use crate::{errors::Error, fastim_mod, Result};
+use fastim_mod::{Chan1, FastImage, FastImageData, MutableFastImage};
+
+const MAGIC: &[u8; 8] = b"FLYDRABG";
+const VERSION: u32 = 1;
+/// The size of the magic number, version and header length.
+const PREAMBLE_LEN: u64 = MAGIC.len() as u64 + 4 + 4;
+
+/// The conditions under which a background model was acquired.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SavedBackgroundHeader {
+    pub(crate) cam_name: String,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) valid_region: Shape,
+    pub(crate) saved_at: DateTime<Utc>,
+}
+
+pub(crate) struct SavedBackground {
+    pub(crate) header: SavedBackgroundHeader,
+    pub(crate) running_mean: FastImageData<Chan1, f32>,
+    pub(crate) mean_squared_im: FastImageData<Chan1, f32>,
+}
+
+impl SavedBackground {
+    pub(crate) fn write_to<W: Write>(&self, wtr: &mut W) -> Result<()> {
+        let header = serde_yaml::to_string(&self.header)?;
+        wtr.write_all(MAGIC)?;
+        wtr.write_u32::<LittleEndian>(VERSION)?;
+        wtr.write_u32::<LittleEndian>(cast::u32(header.len())?)?;
+        wtr.write_all(header.as_bytes())?;
+        for im in [&self.running_mean, &self.mean_squared_im] {
+            for row in im.valid_row_iter(im.size())? {
+                for val in row.iter() {
+                    wtr.write_f32::<LittleEndian>(*val)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a background model of `len` bytes, e.g. the size of the file.
+    ///
+    /// The sizes in the file are checked against `len` before allocating, so
+    /// that a corrupt file results in an error.
+    pub(crate) fn read_from<R: Read>(rdr: &mut R, len: u64) -> Result<Self> {
+        let mut magic = [0u8; 8];
+        rdr.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::InvalidBackgroundFile(
+                "not a background model".into(),
+            ));
+        }
+        let version = rdr.read_u32::<LittleEndian>()?;
+        if version != VERSION {
+            return Err(Error::InvalidBackgroundFile(format!(
+                "unsupported version {version}"
+            )));
+        }
+        let header_len = rdr.read_u32::<LittleEndian>()?;
+        let remaining = len
+            .checked_sub(PREAMBLE_LEN + u64::from(header_len))
+            .ok_or_else(|| Error::InvalidBackgroundFile("truncated header".into()))?;
+        let mut header = vec![0u8; header_len as usize];
+        rdr.read_exact(&mut header)?;
+        let header: SavedBackgroundHeader = serde_yaml::from_slice(&header)?;
+        // Two images of `f32` values.
+        let image_data_len = u64::from(header.width) * u64::from(header.height) * 4 * 2;
+        if image_data_len != remaining {
+            return Err(Error::InvalidBackgroundFile(format!(
+                "expected {image_data_len} bytes of image data for {}x{} pixels, found \
+                {remaining}",
+                header.width, header.height
+            )));
+        }
+
+        let w = cast::i32(header.width)?;
+        let h = cast::i32(header.height)?;
+        let mut running_mean = FastImageData::<Chan1, f32>::new(w, h, 0.0)?;
+        let mut mean_squared_im = FastImageData::<Chan1, f32>::new(w, h, 0.0)?;
+        for im in [&mut running_mean, &mut mean_squared_im] {
+            let size = *im.size();
+            for row in im.valid_row_iter_mut(&size)? {
+                rdr.read_f32_into::<LittleEndian>(row)?;
+            }
+        }
+        Ok(Self {
+            header,
+            running_mean,
+            mean_squared_im,
+        })
+    }
+}
+
+#[test]
+fn test_saved_background_roundtrip() -> anyhow::Result<()> {
+    let mut running_mean = FastImageData::<Chan1, f32>::new(5, 3, 10.0)?;
+    running_mean.pixel_slice_mut(1, 2)[0] = 42.5;
+    let mut mean_squared_im = FastImageData::<Chan1, f32>::new(5, 3, 100.0)?;
+    mean_squared_im.pixel_slice_mut(2, 4)[0] = 1806.25;
+    let orig = SavedBackground {
+        header: SavedBackgroundHeader {
+            cam_name: "cam1".into(),
+            width: 5,
+            height: 3,
+            valid_region: Shape::Everything,
+            saved_at: Utc::now(),
+        },
+        running_mean,
+        mean_squared_im,
+    };
+
+    let mut buf = Vec::new();
+    orig.write_to(&mut buf)?;
+    let len = buf.len() as u64;
+    let loaded = SavedBackground::read_from(&mut buf.as_slice(), len)?;
+    assert_eq!(loaded.header, orig.header);
+    assert_eq!(loaded.running_mean, orig.running_mean);
+    assert_eq!(loaded.mean_squared_im, orig.mean_squared_im);
+
+    assert!(SavedBackground::read_from(&mut &buf[1..], len - 1).is_err());
+
+    // Truncated or corrupt files are rejected before allocating.
+    let truncated = &buf[..buf.len() - 4];
+    assert!(SavedBackground::read_from(&mut &truncated[..], len - 4).is_err());
+    let mut huge_header = buf.clone();
+    huge_header[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(SavedBackground::read_from(&mut huge_header.as_slice(), len).is_err());
+    let mut huge_image = orig;
+    huge_image.header.width = 100_000;
+    huge_image.header.height = 100_000;
+    let mut buf = Vec::new();
+    huge_image.write_to(&mut buf)?;
+    let len = buf.len() as u64;
+    assert!(SavedBackground::read_from(&mut buf.as_slice(), len).is_err());
+    Ok(())
+}
@@ -35,7 +35,7 @@ async fn track_fmf() -> anyhow::Result<()> {
         let ufmf_state = UfmfState::Stopped;
 
         let maybe_found =
-            ft.process_new_frame(&frame, fno, timestamp, ufmf_state, None, None, None)?;
+            ft.process_new_frame(&frame, fno, timestamp, ufmf_state, None, None, 0, None)?;
         count += 1;
         n_pts += maybe_found.0.points.len();
     }
@@ -32,7 +32,8 @@ async fn track_small() -> anyhow::Result<()> {
     let ufmf_state = UfmfState::Stopped;
     let fno = 0;
     let timestamp = DateTime::from_timestamp(1431648000, 0).unwrap();
-    let maybe_found = ft.process_new_frame(&frame, fno, timestamp, ufmf_state, None, None, None)?;
+    let maybe_found =
+        ft.process_new_frame(&frame, fno, timestamp, ufmf_state, None, None, 0, None)?;
     println!("maybe_found: {:?}", maybe_found);
     assert_eq!(maybe_found.0.points.len(), 0);
     Ok(())
@@ -72,7 +73,7 @@ async fn track_moving_stride() -> anyhow::Result<()> {
         let ufmf_state = UfmfState::Stopped;
         let timestamp = DateTime::from_timestamp(1431648000, 0).unwrap();
         let found_points = ft
-            .process_new_frame(&frame, fno, timestamp, ufmf_state, None, None, None)?
+            .process_new_frame(&frame, fno, timestamp, ufmf_state, None, None, 0, None)?
             .0
             .points
             .into_iter()
@@ -82,3 +83,206 @@ async fn track_moving_stride() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+#[tokio::test]
+async fn track_mixture_of_gaussians_with_shadow() -> anyhow::Result<()> {
+    // Test the mixture of Gaussians background model with a moving bright
+    // point and a static shadow appearing after the background was acquired.
+    const W: u32 = 32;
+    const H: u32 = 16;
+
+    init();
+
+    let mut cfg = flydra_pt_detect_cfg::default_absdiff();
+    cfg.background_model =
+        flydra_feature_detector::BackgroundModelCfg::MixtureOfGaussians(Default::default());
+    cfg.max_num_points = 2;
+    cfg.feature_window_size = 2;
+
+    let mut ft = FlydraFeatureDetector::new(
+        &flydra_types::RawCamName::new("mog".to_string()),
+        W,
+        H,
+        cfg,
+        None,
+        None,
+    )?;
+
+    for fno in 0..60 {
+        let mut buf = vec![100; (W * H) as usize];
+
+        // The point appears after the initial background was acquired.
+        let x_pos = fno % W as usize;
+        let y_pos = 4;
+        if fno >= 20 {
+            buf[y_pos * W as usize + x_pos] = 255;
+        }
+
+        if fno >= 30 {
+            for row in 12..H as usize {
+                for col in 0..4 {
+                    buf[row * W as usize + col] = 60;
+                }
+            }
+        }
+
+        let pixel_format = machine_vision_formats::PixFmt::Mono8;
+        let frame = basic_frame::DynamicFrame::new(W, H, W, buf, pixel_format);
+        let ufmf_state = UfmfState::Stopped;
+        let timestamp = DateTime::from_timestamp(1431648000, 0).unwrap();
+        let found_points = ft
+            .process_new_frame(&frame, fno, timestamp, ufmf_state, None, None, 0, None)?
+            .0
+            .points
+            .into_iter()
+            .map(|pt| (pt.x0_abs, pt.y0_abs))
+            .collect::<Vec<_>>();
+        if fno >= 25 {
+            // Only the bright point, not the shadow, is detected.
+            assert_eq!(found_points.len(), 1, "frame {fno}: {found_points:?}");
+            let (x, y) = found_points[0];
+            assert!((x - x_pos as f64).abs() < 0.5);
+            assert!((y - y_pos as f64).abs() < 0.5);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn track_multiple_blobs_with_shape() -> anyhow::Result<()> {
+    // Test that several blobs are found, each with its own shape statistics.
+    const W: u32 = 32;
+    const H: u32 = 16;
+
+    init();
+
+    let mut cfg = flydra_pt_detect_cfg::default_absdiff();
+    cfg.max_num_points = 5;
+    cfg.feature_window_size = 8;
+
+    let mut ft = FlydraFeatureDetector::new(
+        &flydra_types::RawCamName::new("blobs".to_string()),
+        W,
+        H,
+        cfg,
+        None,
+        None,
+    )?;
+
+    for fno in 0..30 {
+        let mut buf = vec![0; (W * H) as usize];
+
+        if fno >= 25 {
+            // A bright elongated diagonal blob.
+            for i in 0..5 {
+                buf[(4 + i) * W as usize + 4 + i] = 200;
+                buf[(4 + i) * W as usize + 5 + i] = 200;
+            }
+            // A dimmer square blob.
+            for row in 10..12 {
+                for col in 22..24 {
+                    buf[row * W as usize + col] = 100;
+                }
+            }
+        }
+
+        let pixel_format = machine_vision_formats::PixFmt::Mono8;
+        let frame = basic_frame::DynamicFrame::new(W, H, W, buf, pixel_format);
+        let ufmf_state = UfmfState::Stopped;
+        let timestamp = DateTime::from_timestamp(1431648000, 0).unwrap();
+        let points = ft
+            .process_new_frame(&frame, fno, timestamp, ufmf_state, None, None, 0, None)?
+            .0
+            .points;
+        if fno < 25 {
+            assert_eq!(points.len(), 0, "frame {fno}: {points:?}");
+            continue;
+        }
+        assert_eq!(points.len(), 2, "frame {fno}: {points:?}");
+
+        let diagonal = &points[0];
+        assert!((diagonal.mean_intensity - 200.0).abs() < 1e-6);
+        let orientation = diagonal.orientation().unwrap();
+        assert!(
+            (orientation.abs() - std::f64::consts::FRAC_PI_4).abs() < 0.1,
+            "{orientation}"
+        );
+        let (_slope, eccentricity) = diagonal.maybe_slope_eccentricty.unwrap();
+        assert!(eccentricity > 2.0, "{eccentricity}");
+
+        let square = &points[1];
+        assert!((square.x0_abs - 22.5).abs() < 1e-6);
+        assert!((square.y0_abs - 10.5).abs() < 1e-6);
+        assert!((square.mean_intensity - 100.0).abs() < 1e-6);
+        assert!(diagonal.area > square.area);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn track_with_morphology() -> anyhow::Result<()> {
+    // Test that opening removes a single noisy pixel but keeps a blob.
+    const W: u32 = 32;
+    const H: u32 = 16;
+
+    init();
+
+    for (morphology, expected_num_points) in [
+        (flydra_feature_detector::MorphologyCfg::None, 2),
+        (flydra_feature_detector::MorphologyCfg::Open(3), 1),
+    ] {
+        let mut cfg = flydra_pt_detect_cfg::default_absdiff();
+        cfg.max_num_points = 5;
+        cfg.feature_window_size = 4;
+        cfg.morphology = morphology.clone();
+
+        let mut ft = FlydraFeatureDetector::new(
+            &flydra_types::RawCamName::new("morphology".to_string()),
+            W,
+            H,
+            cfg,
+            None,
+            None,
+        )?;
+
+        for fno in 0..30 {
+            let mut buf = vec![0; (W * H) as usize];
+
+            if fno >= 25 {
+                // A single bright pixel.
+                buf[4 * W as usize + 4] = 200;
+                // A bright square blob.
+                for row in 6..10 {
+                    for col in 20..24 {
+                        buf[row * W as usize + col] = 200;
+                    }
+                }
+            }
+
+            let pixel_format = machine_vision_formats::PixFmt::Mono8;
+            let frame = basic_frame::DynamicFrame::new(W, H, W, buf, pixel_format);
+            let ufmf_state = UfmfState::Stopped;
+            let timestamp = DateTime::from_timestamp(1431648000, 0).unwrap();
+            let points = ft
+                .process_new_frame(&frame, fno, timestamp, ufmf_state, None, None, 0, None)?
+                .0
+                .points;
+            if fno < 25 {
+                assert_eq!(points.len(), 0, "frame {fno}: {points:?}");
+                continue;
+            }
+            assert_eq!(
+                points.len(),
+                expected_num_points,
+                "{morphology:?}, frame {fno}: {points:?}"
+            );
+            let square = points
+                .iter()
+                .find(|pt| pt.x0_abs > 10.0)
+                .expect("square is detected");
+            assert!((square.x0_abs - 21.5).abs() < 1e-6);
+            assert!((square.y0_abs - 7.5).abs() < 1e-6);
+        }
+    }
+    Ok(())
+}
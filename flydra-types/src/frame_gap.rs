@@ -0,0 +1,49 @@
+// Copyright 2020-2023 Andrew D. Straw.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+/// Detects gaps in a sequence of frame numbers.
+///
+/// Each stage of the acquisition pipeline which receives numbered frames can
+/// use this to count the frames which went missing before reaching it. A frame
+/// number which does not increase (e.g. because the camera was restarted)
+/// restarts the sequence without counting any missing frames.
+#[derive(Debug, Default, Clone)]
+pub struct FrameGapTracker {
+    prev: Option<u64>,
+    total_dropped: u64,
+}
+
+impl FrameGapTracker {
+    /// Register a frame number and return the number of frames missing since
+    /// the previously registered frame.
+    pub fn update(&mut self, fno: u64) -> u64 {
+        let n_dropped = match self.prev {
+            Some(prev) if fno > prev => fno - prev - 1,
+            _ => 0,
+        };
+        self.prev = Some(fno);
+        self.total_dropped += n_dropped;
+        n_dropped
+    }
+
+    /// The number of missing frames since creation.
+    pub fn total_dropped(&self) -> u64 {
+        self.total_dropped
+    }
+}
+
+#[test]
+fn test_frame_gap_tracker() {
+    let mut gaps = FrameGapTracker::default();
+    assert_eq!(gaps.update(10), 0);
+    assert_eq!(gaps.update(11), 0);
+    assert_eq!(gaps.update(14), 2);
+    // restart of the sequence
+    assert_eq!(gaps.update(0), 0);
+    assert_eq!(gaps.update(2), 1);
+    assert_eq!(gaps.total_dropped(), 3);
+}
@@ -11,7 +11,9 @@ extern crate bitflags;
 extern crate static_assertions;
 
 use ordered_float::NotNan;
-use rust_cam_bui_types::{ClockModel, RecordingPath};
+use rust_cam_bui_types::{ClockModel, DiskSpace, RecordingPath};
+
+pub use rust_cam_bui_types::{ExperimentMetadata, ImageTransform};
 use std::net::SocketAddr;
 
 use serde::{Deserialize, Deserializer, Serialize};
@@ -25,7 +27,7 @@ pub const DEFAULT_MODEL_SERVER_ADDR: &str = "0.0.0.0:8397";
 //
 // Any changes to these names, including additions and removes, should update
 // BraidMetadataSchemaTag.
-pub const BRAID_SCHEMA: u16 = 3; // BraidMetadataSchemaTag
+pub const BRAID_SCHEMA: u16 = 11; // BraidMetadataSchemaTag
 
 // CSV files. (These may also exist as .csv.gz)
 pub const KALMAN_ESTIMATES_CSV_FNAME: &str = "kalman_estimates.csv";
@@ -35,10 +37,16 @@ pub const CAM_INFO_CSV_FNAME: &str = "cam_info.csv";
 pub const TRIGGER_CLOCK_INFO_CSV_FNAME: &str = "trigger_clock_info.csv";
 pub const EXPERIMENT_INFO_CSV_FNAME: &str = "experiment_info.csv";
 pub const TEXTLOG_CSV_FNAME: &str = "textlog.csv";
+pub const FRAME_DROPS_CSV_FNAME: &str = "frame_drops.csv";
+pub const TRACK_CONFIRMATION_CSV_FNAME: &str = "track_confirmation.csv";
+pub const MP4_ALIGNMENT_CSV_FNAME: &str = "mp4_alignment.csv";
+pub const OBJECT_QUALITY_CSV_FNAME: &str = "object_quality.csv";
+pub const TRACK_TERMINATION_CSV_FNAME: &str = "track_termination.csv";
 
 // Other files
 pub const CALIBRATION_XML_FNAME: &str = "calibration.xml";
 pub const BRAID_METADATA_YML_FNAME: &str = "braid_metadata.yml";
+pub const EXPERIMENT_METADATA_YML_FNAME: &str = "experiment_metadata.yml";
 pub const README_MD_FNAME: &str = "README.md";
 pub const IMAGES_DIRNAME: &str = "images";
 pub const CAM_SETTINGS_DIRNAME: &str = "cam_settings";
@@ -115,6 +123,35 @@ pub struct KalmanEstimatesRow {
     pub P33: f64,
     pub P44: f64,
     pub P55: f64,
+    /// The number of cameras with observations used for this estimate.
+    ///
+    /// This is zero in frames without observations. This is new in schema 9.
+    /// When loading old files, it is zero.
+    #[serde(default)]
+    pub num_cams: u8,
+    /// The largest angle, in radians, between the rays from the cameras with
+    /// observations to the estimated position.
+    ///
+    /// Small angles indicate poorly constrained estimates along the rays. This
+    /// is NaN with observations from fewer than two cameras. This is new in
+    /// schema 9. When loading old files, it is NaN.
+    #[serde(default = "default_nan", deserialize_with = "invalid_nan")]
+    pub max_ray_angle: f64,
+    /// The mean distance, in undistorted pixels, between the observations and
+    /// the observations predicted before updating the estimate with them (the
+    /// magnitude of the innovation).
+    ///
+    /// Large values indicate observations which do not fit the motion model,
+    /// e.g. from wrong data association. This is NaN in frames without
+    /// observations. This is new in schema 10. When loading old files, it is
+    /// NaN.
+    #[serde(default = "default_nan", deserialize_with = "invalid_nan")]
+    pub innovation_pixels: f64,
+    /// The trace of the position covariance, `P00 + P11 + P22`, in meters².
+    ///
+    /// This is new in schema 10. When loading old files, it is NaN.
+    #[serde(default = "default_nan", deserialize_with = "invalid_nan")]
+    pub pos_cov_trace: f64,
 }
 impl WithKey<SyncFno> for KalmanEstimatesRow {
     fn key(&self) -> SyncFno {
@@ -145,6 +182,24 @@ pub struct FlydraRawUdpPoint {
     pub cur_val: u8,
     pub mean_val: f64,
     pub sumsqf_val: f64,
+    /// The mean intensity of the image over the pixels of the detection.
+    ///
+    /// This is NaN if unknown, e.g. when received from an older camera.
+    #[serde(default = "default_nan")]
+    pub mean_intensity: f64,
+}
+
+impl FlydraRawUdpPoint {
+    /// The orientation, modulo 𝜋, of the major axis of the detection, in
+    /// radians.
+    pub fn orientation(&self) -> Option<f64> {
+        self.maybe_slope_eccentricty
+            .map(|(slope, _ecc)| slope.atan())
+    }
+}
+
+fn default_nan() -> f64 {
+    f64::NAN
 }
 
 /// The original camera name from the driver.
@@ -170,6 +225,8 @@ pub mod braid_http {
     // URL paths on Braid HTTP server.
     pub const REMOTE_CAMERA_INFO_PATH: &str = "remote-camera-info";
     pub const CAM_PROXY_PATH: &str = "cam-proxy";
+    /// Path of a small JPEG of the most recent image of a camera.
+    pub const CAMERA_THUMBNAIL_PATH: &str = "camera-thumbnail";
 
     /// Encode camera name, potentially with slashes or spaces, to be a single
     /// URL path component.
@@ -315,6 +372,20 @@ pub struct BraidCameraConfig {
     /// The interval at which the current image should be sent, in milliseconds.
     #[serde(default = "default_send_current_image_interval_msec")]
     pub send_current_image_interval_msec: u64,
+    /// Names of the parameter groups of this camera.
+    ///
+    /// The exposure time and gain of all cameras of a group can be set at once
+    /// with [BraidHttpApiCallback::SetGroupCameraParams].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    /// Rotation and mirroring of the camera images, e.g. `"rotate180"` for a
+    /// camera mounted upside down.
+    ///
+    /// Strand Camera applies this to every frame, so detections, recordings
+    /// and the calibration are all in the coordinates of the transformed
+    /// image. Changing it invalidates an existing calibration.
+    #[serde(default, skip_serializing_if = "ImageTransform::is_identity")]
+    pub image_transform: ImageTransform,
 
     /// Deprecated, useless old config option (not removed for backwards compatibility)
     #[serde(
@@ -374,6 +445,8 @@ impl BraidCameraConfig {
                 DEFAULT_ACQUISITION_DURATION_ALLOWED_IMPRECISION_MSEC,
             http_server_addr: None,
             send_current_image_interval_msec: default_send_current_image_interval_msec(),
+            groups: Vec::new(),
+            image_transform: ImageTransform::None,
         }
     }
 }
@@ -437,6 +510,18 @@ pub struct UpdateCamSettings {
     pub current_cam_settings_extension: String,
 }
 
+/// An MP4 file being recorded by a camera.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Mp4RecordingInfo {
+    /// The filename of the MP4 file.
+    pub filename: String,
+    /// Timestamp of the first frame in the file.
+    ///
+    /// This frame has presentation timestamp (PTS) zero. The timestamp of each
+    /// frame is also stored in the MP4 file as its precision timestamp.
+    pub start: chrono::DateTime<chrono::Utc>,
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct UpdateFeatureDetectSettings {
     /// The current feature detection settings.
@@ -475,6 +560,93 @@ pub struct BraidHttpApiSharedState {
     pub model_server_addr: Option<SocketAddr>,
     pub flydra_app_name: String,
     pub all_expected_cameras_are_synced: bool,
+    /// Space on the volume to which `.braidz` files are saved.
+    pub disk_space: Option<DiskSpace>,
+    /// Description of the experiment entered by the user.
+    pub experiment_metadata: ExperimentMetadata,
+    /// Most recent alerts, oldest first. At most [MAX_RECENT_ALERTS] are kept.
+    pub recent_alerts: Vec<Alert>,
+    /// Which events are logged, in the syntax of the `RUST_LOG` environment
+    /// variable.
+    pub log_filter: String,
+    /// Strand Camera instances found on the local network by mDNS, by name.
+    pub discovered_cameras: Vec<DiscoveredCamera>,
+    /// Braid is shutting down, waiting for recordings to be finalized.
+    pub is_shutting_down: bool,
+    /// The parameter groups of the cameras, sorted by name.
+    pub camera_groups: Vec<CameraGroup>,
+    /// Camera parameters set for single cameras, which take precedence over
+    /// those set for their groups.
+    pub camera_param_overrides: Vec<PerCam<CameraParams>>,
+    /// Cameras which are currently recording MP4 files, sorted by name.
+    pub mp4_recording_cameras: Vec<RawCamName>,
+}
+
+/// A named group of cameras whose parameters can be set at once.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CameraGroup {
+    pub name: String,
+    pub cam_names: Vec<RawCamName>,
+    /// The parameters most recently set for the group.
+    pub params: CameraParams,
+}
+
+/// Camera parameters set by Braid. `None` leaves a parameter unchanged.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
+pub struct CameraParams {
+    /// Exposure time in microseconds.
+    pub exposure_time_usec: Option<f64>,
+    /// Gain, in the units of the camera (typically dB).
+    pub gain: Option<f64>,
+}
+
+impl CameraParams {
+    /// The parameters of `self`, with those missing taken from `other`.
+    pub fn or(&self, other: &CameraParams) -> CameraParams {
+        CameraParams {
+            exposure_time_usec: self.exposure_time_usec.or(other.exposure_time_usec),
+            gain: self.gain.or(other.gain),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exposure_time_usec.is_none() && self.gain.is_none()
+    }
+}
+
+/// Set the parameters of several cameras.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SetGroupCameraParams {
+    /// Name of the group or `None` for all cameras.
+    pub group: Option<String>,
+    pub params: CameraParams,
+}
+
+/// A Strand Camera instance found on the local network by mDNS.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DiscoveredCamera {
+    pub name: String,
+    /// URLs of the web UI, without token.
+    pub urls: Vec<String>,
+    /// Whether the camera is running as part of Braid (this or another).
+    pub in_braid: bool,
+    /// Name of the camera in the Braid configuration, if it is configured.
+    pub config_name: Option<String>,
+}
+
+/// Maximum number of alerts kept in [BraidHttpApiSharedState::recent_alerts].
+pub const MAX_RECENT_ALERTS: usize = 20;
+
+/// A runtime problem which needs the attention of the user.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub time: chrono::DateTime<chrono::Utc>,
+    /// Name of the camera or program with the problem.
+    pub source: String,
+    /// Type of problem, e.g. `"camera-stalled"`.
+    pub kind: String,
+    /// Description of the problem.
+    pub message: String,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
@@ -482,6 +654,9 @@ pub struct RecentStats {
     pub total_frames_collected: usize,
     pub frames_collected: usize,
     pub points_detected: usize,
+    /// Frames dropped anywhere between the camera and Braid.
+    pub frames_dropped: usize,
+    pub total_frames_dropped: usize,
 }
 
 /// Generic HTTP API server information
@@ -658,6 +833,27 @@ pub async fn start_listener(
     Ok((listener, http_camserver_info))
 }
 
+#[cfg(feature = "start-listener")]
+/// Wait for SIGINT (Ctrl-C) or, on unix, SIGTERM. Returns `true` for SIGINT.
+pub async fn quit_signal() -> bool {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate()).expect("installing SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => true,
+            _ = sigterm.recv() => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("installing Ctrl-C handler");
+        true
+    }
+}
+
 // -----
 
 #[cfg(feature = "build-urls")]
@@ -702,6 +898,123 @@ pub struct TextlogRow {
     pub message: String,
 }
 
+/// Frames which went missing between a camera and Braid.
+///
+/// A row is saved for each gap in the frames received from a camera.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FrameDropRow {
+    // changes to this struct should update BraidMetadataSchemaTag
+    pub cam_id: String,
+    /// Timestamp of the first frame received after the gap.
+    #[serde(with = "crate::timestamp_f64")]
+    pub cam_received_time: FlydraFloatTimestampLocal<HostClock>,
+    /// Host frame number of the first frame received after the gap.
+    pub framenumber: i32,
+    /// Camera frame number of the first frame received after the gap.
+    pub block_id: Option<u64>,
+    /// Frames which the camera acquired but which never reached the host.
+    pub n_dropped_camera: u64,
+    /// Frames which the host processed but which never reached Braid.
+    pub n_dropped_transport: u64,
+}
+
+/// Whether a tracked object was confirmed.
+///
+/// A row is saved when an object is confirmed, and thus becomes visible, and
+/// when an object is removed before it was confirmed.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TrackConfirmationRow {
+    // changes to this struct should update BraidMetadataSchemaTag
+    pub obj_id: u32,
+    /// Frame in which the object was started.
+    pub start_frame: SyncFno,
+    /// Frame in which the object was confirmed or removed.
+    pub frame: SyncFno,
+    pub confirmed: bool,
+}
+
+/// Why the tracking of an object ended.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackTerminationReason {
+    /// The position uncertainty exceeded `max_position_std_meters`.
+    Lost,
+    /// The object was not observed for more than `max_coast_frames` frames.
+    CoastLimit,
+    /// The object left the tracking volume.
+    OutOfVolume,
+    /// The object came within `merge_distance_meters` of an older object.
+    Merged,
+}
+
+/// Why and when the tracking of an object ended.
+///
+/// A row is saved when a visible object is removed. Objects which are still
+/// tracked when saving stops have no row.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TrackTerminationRow {
+    // changes to this struct should update BraidMetadataSchemaTag
+    pub obj_id: u32,
+    /// The last frame in which the object was tracked.
+    pub frame: SyncFno,
+    pub reason: TrackTerminationReason,
+    /// If `reason` is `merged`, the object into which this one was merged.
+    pub merged_into: Option<u32>,
+}
+
+/// The overall quality of the tracking of an object.
+///
+/// A row is saved for each object in the `kalman_estimates` table when saving
+/// stops.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ObjectQualityRow {
+    // changes to this struct should update BraidMetadataSchemaTag
+    pub obj_id: u32,
+    /// The number of rows of the object in the `kalman_estimates` table.
+    pub num_frames: u64,
+    /// The number of frames with observations from at least one camera.
+    pub num_frames_observed: u64,
+    /// The number of frames with observations from at least two cameras.
+    pub num_frames_multi_cam: u64,
+    /// The mean number of cameras with observations in frames with
+    /// observations.
+    pub mean_num_cams: f64,
+    /// The mean of [KalmanEstimatesRow::innovation_pixels] in frames with
+    /// observations.
+    pub mean_innovation_pixels: f64,
+    /// The largest [KalmanEstimatesRow::pos_cov_trace].
+    pub max_pos_cov_trace: f64,
+    /// The confidence in the object, from 0 to 1.
+    ///
+    /// This is the fraction of frames with observations from at least two
+    /// cameras, in which the position is triangulated rather than predicted
+    /// by the motion model or constrained along a single ray.
+    pub confidence: f64,
+}
+
+/// The location of a camera frame in an MP4 file recorded by that camera.
+///
+/// A row is saved for each frame received from a camera while it records an
+/// MP4 file. Frames not written to the MP4 file, for example while recording
+/// is paused or due to the maximum MP4 framerate, also have a row.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Mp4AlignmentRow {
+    // changes to this struct should update BraidMetadataSchemaTag
+    /// The number of the camera.
+    pub camn: CamNum,
+    /// The synchronized frame number.
+    pub frame: i64,
+    /// Timestamp of the frame, as stored in the precision timestamp of the MP4
+    /// file.
+    #[serde(with = "crate::timestamp_f64")]
+    pub timestamp: FlydraFloatTimestampLocal<Triggerbox>,
+    /// The filename of the MP4 file.
+    pub mp4_filename: String,
+    /// Presentation timestamp (PTS) of the frame in the MP4 file, in
+    /// microseconds.
+    pub mp4_pts_usec: u64,
+}
+
 /// Tracking parameters
 ///
 /// The terminology used is as defined at [the Wikipedia page on the Kalman
@@ -758,7 +1071,23 @@ pub struct TrackingParams {
     pub accept_observation_min_likelihood: f64,
     /// This is used to compute the maximum allowable covariance before an
     /// object is "killed" and no longer tracked.
+    ///
+    /// As the covariance grows in every frame without observations, this also
+    /// limits how long an object is tracked without observations.
     pub max_position_std_meters: f32,
+    /// The maximum number of consecutive frames without observations before
+    /// an object is killed.
+    ///
+    /// If `None`, objects are only killed when their covariance exceeds
+    /// `max_position_std_meters`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub max_coast_frames: Option<u32>,
+    /// Objects closer than this distance are merged.
+    ///
+    /// Of two objects whose estimated positions are closer than this, the
+    /// younger one is killed. If `None`, objects are never merged.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub merge_distance_meters: Option<f64>,
     /// These are the hypothesis testing parameters used to "birth" a new new
     /// object and start tracking it.
     ///
@@ -767,13 +1096,76 @@ pub struct TrackingParams {
     pub hypothesis_test_params: Option<HypothesisTestParams>,
     /// This is the minimum number of observations before object becomes
     /// visible.
+    ///
+    /// Objects are only sent to the network and saved once visible.
     #[serde(default = "default_num_observations_to_visibility")]
     pub num_observations_to_visibility: u8,
+    /// Criteria an observation must meet to count towards
+    /// `num_observations_to_visibility`.
+    ///
+    /// If this is set, an object must be observed in
+    /// `num_observations_to_visibility` consecutive frames meeting these
+    /// criteria before it is confirmed and becomes visible. This suppresses
+    /// "ghost" objects arising from accidental intersections of rays from few
+    /// cameras. If `None`, any observation counts and frames need not be
+    /// consecutive.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub track_confirmation: Option<TrackConfirmationParams>,
     /// Parameters defining mini arena configuration.
     ///
     /// This is MiniArenaConfig::NoMiniArena if no mini arena is in use.
     #[serde(skip_serializing_if = "MiniArenaConfig::is_none", default)]
     pub mini_arena_config: MiniArenaConfig,
+    /// The volume in which objects are tracked.
+    ///
+    /// New objects are not started outside this volume. This is
+    /// TrackingVolume::Unbounded if objects may be tracked anywhere.
+    #[serde(skip_serializing_if = "TrackingVolume::is_unbounded", default)]
+    pub tracking_volume: TrackingVolume,
+}
+
+impl TrackingParams {
+    /// Whether these are parameters for 2D tracking with a single camera.
+    ///
+    /// In 2D tracking, objects are tracked on the z=0 plane, and thus there
+    /// are no `hypothesis_test_params`.
+    pub fn is_2d(&self) -> bool {
+        self.hypothesis_test_params.is_none()
+    }
+}
+
+/// The volume in which objects are tracked.
+///
+/// Coordinates are in meters in the world coordinate frame.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(tag = "type")]
+pub enum TrackingVolume {
+    /// Objects may be tracked anywhere.
+    #[default]
+    Unbounded,
+    /// An axis-aligned box.
+    Box {
+        /// The corner with the smallest x, y and z coordinates.
+        min: [f64; 3],
+        /// The corner with the largest x, y and z coordinates.
+        max: [f64; 3],
+    },
+    /// A cylinder with its axis parallel to the Z axis.
+    Cylinder {
+        center_x: f64,
+        center_y: f64,
+        radius: f64,
+        z_min: f64,
+        z_max: f64,
+    },
+    /// The convex hull of the given vertices.
+    ConvexHull { vertices: Vec<[f64; 3]> },
+}
+
+impl TrackingVolume {
+    fn is_unbounded(&self) -> bool {
+        self == &Self::Unbounded
+    }
 }
 
 pub struct MiniArenaLocator {
@@ -797,6 +1189,16 @@ impl MiniArenaLocator {
     }
 }
 
+/// Criteria for an observation to count towards confirming an object.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TrackConfirmationParams {
+    /// Minimum number of cameras contributing to the observation.
+    pub min_num_cameras: u8,
+    /// Maximum mean reprojection distance of the observation, in pixels.
+    pub max_mean_reproj_dist_pixels: f64,
+}
+
 /// Configuration defining potential mini arenas.
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 #[serde(tag = "type")]
@@ -961,9 +1363,13 @@ pub fn default_tracking_params_full_3d() -> TrackingParams {
         accept_observation_min_likelihood: 1e-8,
         ekf_observation_covariance_pixels: 1.0,
         max_position_std_meters: 0.01212,
+        max_coast_frames: None,
+        merge_distance_meters: None,
         hypothesis_test_params: Some(make_hypothesis_test_full3d_default()),
         num_observations_to_visibility: default_num_observations_to_visibility(),
+        track_confirmation: None,
         mini_arena_config: MiniArenaConfig::NoMiniArena,
+        tracking_volume: TrackingVolume::Unbounded,
     }
 }
 
@@ -975,9 +1381,13 @@ pub fn default_tracking_params_flat_3d() -> TrackingParams {
         accept_observation_min_likelihood: 0.00001,
         ekf_observation_covariance_pixels: 1.0,
         max_position_std_meters: 0.003,
+        max_coast_frames: None,
+        merge_distance_meters: None,
         hypothesis_test_params: None,
         num_observations_to_visibility: 10,
+        track_confirmation: None,
         mini_arena_config: MiniArenaConfig::NoMiniArena,
+        tracking_volume: TrackingVolume::Unbounded,
     }
 }
 
@@ -1021,17 +1431,44 @@ pub enum BraidHttpApiCallback {
     /// Called from strand-cam to update the current feature detection settings
     /// (e.g. threshold different)
     UpdateFeatureDetectSettings(PerCam<UpdateFeatureDetectSettings>),
+    /// Called from strand-cam when it starts writing an MP4 file (`Some`) or
+    /// stops MP4 recording (`None`)
+    UpdateMp4Recording(PerCam<Option<Mp4RecordingInfo>>),
     /// Start or stop recording data (.braid directory with csv tables for later
     /// .braidz file)
     DoRecordCsvTables(bool),
     /// Start or stop recording MKV videos for all cameras
     DoRecordMp4Files(bool),
+    /// Start MP4 recording on all cameras with the same synchronized frame
+    ///
+    /// The filenames start with the given prefix, if any. Recording is stopped
+    /// with `DoRecordMp4Files(false)`.
+    StartSynchronizedMp4Recording(Option<String>),
     /// set uuid in the experiment_info table
     SetExperimentUuid(String),
+    /// Set the description of the experiment, which is saved in the braidz
+    /// file and in MP4 files recorded by all cameras
+    SetExperimentMetadata(ExperimentMetadata),
     /// Set the number of frames to buffer in each camera
     SetPostTriggerBufferSize(usize),
     /// Initiate MKV recording using post trigger
     PostTriggerMp4Recording,
+    /// Called from strand-cam when a problem occurs, e.g. frame processing
+    /// falls behind
+    ReportAlert(Alert),
+    /// Remove all alerts from [BraidHttpApiSharedState::recent_alerts]
+    ClearAlerts,
+    /// Set which events are logged by Braid and all cameras, in the syntax of
+    /// the `RUST_LOG` environment variable (e.g. `info,flydra2=debug`)
+    SetLogFilter(String),
+    /// Shut down Braid and all cameras after finalizing all recordings
+    DoQuit,
+    /// Set exposure time and/or gain of all cameras or of the cameras of a
+    /// group, except where overridden for a single camera
+    SetGroupCameraParams(SetGroupCameraParams),
+    /// Set exposure time and/or gain of a single camera, overriding the values
+    /// set for its groups. Parameters which are `None` are no longer overridden.
+    SetCameraParamOverride(PerCam<CameraParams>),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
@@ -1058,6 +1495,7 @@ pub struct FlydraRawUdpPacket {
     /// frame number from the camera
     pub block_id: Option<u64>,
     pub framenumber: i32,
+    /// number of frames from the camera missing immediately prior to this one
     pub n_frames_skipped: u32,
     /// this will always be 0.0 for flydra1 custom serialized packets
     pub done_camnode_processing: f64,
@@ -1074,6 +1512,9 @@ pub use synced_frame::SyncFno;
 mod cam_num;
 pub use cam_num::CamNum;
 
+mod frame_gap;
+pub use frame_gap::FrameGapTracker;
+
 mod timestamp;
 pub use crate::timestamp::{
     triggerbox_time, FlydraFloatTimestampLocal, HostClock, Source, Triggerbox,
@@ -1127,6 +1568,8 @@ bitflags! {
         const BGCLEARED = 0b00000100;
         const BGUPDATE  = 0b00001000;
         const BGNORMAL  = 0b00010000;
+        const BGLOADED  = 0b00100000;
+        const ONNXDETECT = 0b01000000;
     }
 }
 
@@ -1166,6 +1609,24 @@ pub struct PtpSyncConfig {
     ///
     /// If this is set, it is transmitted to the cameras.
     pub periodic_signal_period_usec: Option<f64>,
+    /// The maximum offset of a camera clock from the PTP master clock for the
+    /// camera to be considered locked.
+    ///
+    /// Cameras wait until they are locked before acquiring frames. Defaults to
+    /// [DEFAULT_PTP_LOCK_THRESHOLD_NSEC] if not set.
+    #[serde(default)]
+    pub lock_threshold_nsec: Option<u64>,
+}
+
+/// Default value of [PtpSyncConfig::lock_threshold_nsec] (1 millisecond).
+pub const DEFAULT_PTP_LOCK_THRESHOLD_NSEC: u64 = 1_000_000;
+
+impl PtpSyncConfig {
+    /// The threshold for considering a camera clock locked to the master.
+    pub fn lock_threshold_nsec(&self) -> u64 {
+        self.lock_threshold_nsec
+            .unwrap_or(DEFAULT_PTP_LOCK_THRESHOLD_NSEC)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -1180,6 +1641,35 @@ impl Default for FakeSyncConfig {
     }
 }
 
+/// Configuration for synchronizing free-running cameras by their timestamps.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SoftwareSyncConfig {
+    /// The frame rate of the cameras and of the synchronized frames.
+    pub framerate: f64,
+    /// The maximum difference between the arrival time of a frame and the
+    /// nominal time of the synchronized frame to which it is assigned.
+    ///
+    /// Frames arriving further than this from any nominal time are dropped.
+    /// Defaults to a quarter of the frame period.
+    #[serde(default)]
+    pub tolerance_msec: Option<f64>,
+}
+
+impl SoftwareSyncConfig {
+    /// The frame period in seconds.
+    pub fn period_sec(&self) -> f64 {
+        1.0 / self.framerate
+    }
+
+    /// The tolerance in seconds.
+    pub fn tolerance_sec(&self) -> f64 {
+        self.tolerance_msec
+            .map(|x| x / 1000.0)
+            .unwrap_or_else(|| self.period_sec() / 4.0)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 #[serde(tag = "trigger_type")]
@@ -1192,6 +1682,10 @@ pub enum TriggerType {
     DeviceTimestamp,
     /// Cameras are not synchronized, but we pretend they are.
     FakeSync(FakeSyncConfig),
+    /// Cameras are free-running and their frames are grouped by arrival time.
+    ///
+    /// This allows approximate 3D tracking without synchronization hardware.
+    SoftwareSync(SoftwareSyncConfig),
 }
 
 impl Default for TriggerType {
@@ -1262,6 +1756,11 @@ pub struct Data2dDistortedRow {
     pub mean_val: f64,
     #[serde(deserialize_with = "invalid_nan")]
     pub sumsqf_val: f64,
+    /// The mean intensity of the image over the pixels of the detection.
+    ///
+    /// This is new in schema 5. When loading old files, it is NaN.
+    #[serde(default = "default_nan", deserialize_with = "invalid_nan")]
+    pub mean_intensity: f64,
 }
 
 /// Lower precision version of [Data2dDistortedRow] for saving to disk.
@@ -1309,6 +1808,8 @@ pub struct Data2dDistortedRowF32 {
     pub cur_val: u8,
     pub mean_val: f32,
     pub sumsqf_val: f32,
+    /// The mean intensity of the image over the pixels of the detection.
+    pub mean_intensity: f32,
 }
 
 impl From<Data2dDistortedRow> for Data2dDistortedRowF32 {
@@ -1329,6 +1830,7 @@ impl From<Data2dDistortedRow> for Data2dDistortedRowF32 {
             cur_val: orig.cur_val,
             mean_val: orig.mean_val as f32,
             sumsqf_val: orig.sumsqf_val as f32,
+            mean_intensity: orig.mean_intensity as f32,
         }
     }
 }
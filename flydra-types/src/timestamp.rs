@@ -113,7 +113,11 @@ pub fn triggerbox_time(
     let frame: u64 = frame.try_into().unwrap();
     if let Some(frame_offset) = frame_offset {
         if let Some(cm) = clock_model {
-            let ts: f64 = ((frame - frame_offset) as f64) * cm.gain + cm.offset;
+            // The offset of a camera which restarted after the other cameras
+            // were synchronized is larger than its frame numbers and wraps
+            // around.
+            let synced_frame = frame.wrapping_sub(frame_offset) as i64;
+            let ts: f64 = (synced_frame as f64) * cm.gain + cm.offset;
             let ts = FlydraFloatTimestampLocal::<Triggerbox>::from_f64(ts);
             return Some(ts);
         }
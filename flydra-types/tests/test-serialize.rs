@@ -46,6 +46,7 @@ fn make_test_point() -> FlydraRawUdpPoint {
         cur_val: 13,
         mean_val: 12345.0,
         sumsqf_val: 55.5,
+        mean_intensity: 42.0,
     }
 }
 
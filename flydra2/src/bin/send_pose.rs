@@ -44,6 +44,10 @@ async fn main() -> Result<()> {
         P33: 0.0,
         P44: 0.0,
         P55: 0.0,
+        num_cams: 0,
+        max_ray_angle: f64::NAN,
+        innovation_pixels: f64::NAN,
+        pos_cov_trace: 0.0,
     };
     let tdpt = TimeDataPassthrough::new(SyncFno(0), &start);
     data_tx
@@ -3,12 +3,14 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc, Mutex, RwLock,
 };
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
+use crate::software_sync::{Assignment, SoftwareSync};
 use crate::{safe_u8, CamInfoRow, MyFloat};
 use flydra_types::{
-    BuiServerInfo, CamInfo, CamNum, ConnectedCameraSyncState, PtpStamp, PtpSyncConfig, RawCamName,
-    RecentStats, SyncFno, TriggerType, TRIGGERBOX_SYNC_SECONDS,
+    BuiServerInfo, CamInfo, CamNum, ConnectedCameraSyncState, FlydraFloatTimestampLocal, PtpStamp,
+    PtpSyncConfig, RawCamName, RecentStats, SoftwareSyncConfig, SyncFno, TriggerType, Triggerbox,
+    TRIGGERBOX_SYNC_SECONDS,
 };
 
 pub(crate) trait HasCameraList {
@@ -45,6 +47,9 @@ pub struct ConnectedCameraInfo {
     http_camserver_info: BuiServerInfo,
     frames_during_sync: u64,
     _camera_periodic_signal_period_usec: Option<f64>,
+    /// The camera connected again after it had been synchronized. It is
+    /// synchronized individually, without a sync pulse pause.
+    reconnected: bool,
 }
 
 impl ConnectedCameraInfo {
@@ -67,6 +72,16 @@ struct ConnectedCamerasManagerInner {
     first_frame_arrived: BTreeSet<RawCamName>,
 }
 
+/// The result of [ConnectedCamerasManager::register_new_camera].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registration {
+    /// The camera connected for the first time.
+    New,
+    /// The camera connected again, e.g. after its process was restarted. It
+    /// keeps its camera number and is synchronized again.
+    Reconnected,
+}
+
 pub trait ConnectedCamCallback: Send {
     fn on_cam_changed(&self, _: Vec<CamInfo>);
 }
@@ -85,6 +100,7 @@ pub struct ConnectedCamerasManager {
     signal_all_cams_synced: Arc<AtomicBool>,
     launch_time_ptp: PtpStamp,
     periodic_signal_period_usec: Option<f64>,
+    software_sync: Arc<Mutex<Option<SoftwareSync>>>,
 }
 
 impl HasCameraList for ConnectedCamerasManager {
@@ -151,6 +167,7 @@ impl ConnectedCamerasManager {
             recon: recon.clone(),
             launch_time_ptp,
             periodic_signal_period_usec,
+            software_sync: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -289,6 +306,7 @@ impl ConnectedCamerasManager {
                     http_camserver_info: http_camserver_info.clone(),
                     frames_during_sync: 0,
                     _camera_periodic_signal_period_usec: camera_periodic_signal_period_usec,
+                    reconnected: false,
                 },
             );
         }
@@ -302,6 +320,11 @@ impl ConnectedCamerasManager {
 
     /// This is called to register a camera when it connects to the mainbrain.
     ///
+    /// A camera which is already registered is registered again, e.g. because
+    /// its process was restarted. It must then be synchronized again, which is
+    /// done without interrupting the other cameras. With the triggerbox, this
+    /// requires `estimated_synced_frame` in [Self::got_new_frame_live].
+    ///
     /// See `new_single_cam` for the case when only a single camera will be
     /// added.
     pub fn register_new_camera(
@@ -309,7 +332,7 @@ impl ConnectedCamerasManager {
         raw_cam_name: &RawCamName,
         http_camserver_info: &BuiServerInfo,
         camera_periodic_signal_period_usec: Option<f64>,
-    ) -> Result<(), &'static str> {
+    ) -> Result<Registration, &'static str> {
         if camera_periodic_signal_period_usec != self.periodic_signal_period_usec {
             return Err(
                 "camera_periodic_signal_period_usec differs from periodic_signal_period_usec.",
@@ -320,11 +343,15 @@ impl ConnectedCamerasManager {
             // This scope is for the write lock on self.inner. Keep it minimal.
             let mut inner = self.inner.write().unwrap();
 
-            if inner.ccis.contains_key(&raw_cam_name) {
-                tracing::error!(
-                    "Camera \"{raw_cam_name}\" has already connected but is attempting to connect again."
-                );
-                return Err("camera already connected");
+            if let Some(cci) = inner.ccis.get_mut(&raw_cam_name) {
+                warn!("Camera \"{raw_cam_name}\" connected again. Synchronizing it again.");
+                cci.reconnected |= cci.sync_state.is_synchronized();
+                cci.sync_state = ConnectedCameraSyncState::Unsynchronized;
+                cci.http_camserver_info = http_camserver_info.clone();
+                cci.frames_during_sync = 0;
+                drop(inner);
+                self.notify_cam_changed_listeners();
+                return Ok(Registration::Reconnected);
             }
 
             let cam_num = if let Some(pre_existing) = inner.not_yet_connected.remove(&raw_cam_name)
@@ -356,6 +383,7 @@ impl ConnectedCamerasManager {
                     http_camserver_info: http_camserver_info.clone(),
                     frames_during_sync: 0,
                     _camera_periodic_signal_period_usec: camera_periodic_signal_period_usec,
+                    reconnected: false,
                 },
             );
             cam_num
@@ -367,16 +395,21 @@ impl ConnectedCamerasManager {
             cam_num
         );
         self.notify_cam_changed_listeners();
-        Ok(())
+        Ok(Registration::New)
     }
 
     /// Register that a new frame was received
     ///
+    /// With the triggerbox (or fake sync), `estimated_synced_frame` is the
+    /// synchronized frame number estimated from the arrival time of the frame,
+    /// if possible. It is used to synchronize a camera which connected again.
+    ///
     /// Returns synced frame number
     pub fn got_new_frame_live<F>(
         &self,
         packet: &flydra_types::FlydraRawUdpPacket,
         sync_pulse_pause_started_arc: &Arc<RwLock<Option<std::time::Instant>>>,
+        estimated_synced_frame: Option<SyncFno>,
         send_new_frame_offset: F,
         trigger_cfg: &TriggerType,
     ) -> Option<SyncFno>
@@ -387,12 +420,17 @@ impl ConnectedCamerasManager {
             TriggerType::TriggerboxV1(_) => self.got_new_frame_live_triggerbox(
                 packet,
                 sync_pulse_pause_started_arc,
+                estimated_synced_frame,
                 TRIGGERBOX_SYNC_SECONDS,
             ),
-            TriggerType::FakeSync(_) => {
-                self.got_new_frame_live_triggerbox(packet, sync_pulse_pause_started_arc, 0)
-            }
+            TriggerType::FakeSync(_) => self.got_new_frame_live_triggerbox(
+                packet,
+                sync_pulse_pause_started_arc,
+                estimated_synced_frame,
+                0,
+            ),
             TriggerType::PtpSync(ptpcfg) => self.got_new_frame_live_ptp(packet, ptpcfg)?,
+            TriggerType::SoftwareSync(swcfg) => self.got_new_frame_live_software(packet, swcfg)?,
             TriggerType::DeviceTimestamp => {
                 todo!();
             }
@@ -405,6 +443,7 @@ impl ConnectedCamerasManager {
         &self,
         packet: &flydra_types::FlydraRawUdpPacket,
         sync_pulse_pause_started_arc: &Arc<RwLock<Option<std::time::Instant>>>,
+        estimated_synced_frame: Option<SyncFno>,
         sync_time_min_sec: u64,
     ) -> SyncData {
         assert!(packet.framenumber >= 0);
@@ -425,6 +464,16 @@ impl ConnectedCamerasManager {
                 // We know this camera already.
                 use crate::ConnectedCameraSyncState::*;
                 match cci.sync_state {
+                    Unsynchronized if cci.reconnected => {
+                        // The other cameras are running synchronized, so do
+                        // not interrupt them with a sync pulse pause. The
+                        // camera restarted counting frames, so its offset
+                        // wraps around.
+                        if let Some(SyncFno(estimated)) = estimated_synced_frame {
+                            new_frame0 = Some(cam_frame.wrapping_sub(estimated));
+                            synced_frame = Some(estimated);
+                        }
+                    }
                     Unsynchronized => {
                         do_check_if_all_cameras_present = true;
                         let sync_pulse_pause_started = sync_pulse_pause_started_arc.read().unwrap();
@@ -447,9 +496,11 @@ impl ConnectedCamerasManager {
                         }
                     }
                     Synchronized(frame0) => {
-                        if cam_frame >= frame0 {
+                        // The offset of a camera which connected again may have
+                        // wrapped around, see above.
+                        let corrected_frame_number = cam_frame.wrapping_sub(frame0);
+                        if (corrected_frame_number as i64) >= 0 {
                             // The camera is already synchronized, return synced frame number
-                            let corrected_frame_number = cam_frame - frame0;
 
                             // if corrected_frame_number > crate::TRIGGERBOX_FIRST_PULSE {
                             if corrected_frame_number == u64::MAX {
@@ -588,6 +639,64 @@ impl ConnectedCamerasManager {
         }
     }
 
+    /// Register that a new frame was received if we are using software sync.
+    fn got_new_frame_live_software(
+        &self,
+        packet: &flydra_types::FlydraRawUdpPacket,
+        swcfg: &SoftwareSyncConfig,
+    ) -> Option<SyncData> {
+        let raw_cam_name = RawCamName::new(packet.cam_name.clone());
+
+        let inner = self.inner.read().unwrap();
+        let Some(cci) = inner.ccis.get(&raw_cam_name) else {
+            // Camera starting up (or shutting down). Ignore this frame.
+            return None;
+        };
+
+        let elapsed_sec = packet.cam_received_time.as_f64() - self.launch_time_sec();
+        let assignment = {
+            let mut software_sync = self.software_sync.lock().unwrap();
+            let software_sync = software_sync.get_or_insert_with(|| {
+                SoftwareSync::new(swcfg.period_sec(), swcfg.tolerance_sec())
+            });
+            software_sync.assign(&raw_cam_name, elapsed_sec)
+        };
+        let synced_frame = match assignment {
+            Assignment::Frame(fno) => Some(fno),
+            Assignment::Dropped => None,
+        };
+
+        let mut do_check_if_all_cameras_present = false;
+        let mut new_frame0 = None;
+        if !cci.sync_state.is_synchronized() {
+            new_frame0 = Some(0);
+            do_check_if_all_cameras_present = true;
+        }
+
+        Some(SyncData {
+            new_frame0,
+            raw_cam_name,
+            do_check_if_all_cameras_present,
+            synced_frame,
+        })
+    }
+
+    /// The launch time in seconds since the UNIX epoch.
+    fn launch_time_sec(&self) -> f64 {
+        self.launch_time_ptp.get() as f64 * 1e-9
+    }
+
+    /// The nominal time of a synchronized frame when using software sync.
+    pub fn software_sync_timestamp(
+        &self,
+        synced_frame: SyncFno,
+        swcfg: &SoftwareSyncConfig,
+    ) -> FlydraFloatTimestampLocal<Triggerbox> {
+        FlydraFloatTimestampLocal::from_f64(
+            self.launch_time_sec() + synced_frame.0 as f64 * swcfg.period_sec(),
+        )
+    }
+
     fn finish_got_new_frame_live<F>(
         &self,
         sync_data: SyncData,
@@ -611,6 +720,7 @@ impl ConnectedCamerasManager {
                 match inner.ccis.get_mut(&raw_cam_name) {
                     Some(cci) => {
                         cci.sync_state = ConnectedCameraSyncState::Synchronized(frame0);
+                        cci.reconnected = false;
                     }
                     None => {
                         panic!("reached impossible code.");
@@ -765,3 +875,102 @@ fn test_camera_list() {
     let c2 = CameraList::new(&[4, 3, 2, 5]);
     assert!(c1 != c2);
 }
+
+#[test]
+fn test_reconnect() {
+    use flydra_types::{FlydraRawUdpPacket, HostClock, ImageProcessingSteps};
+
+    let raw_cam_name = RawCamName::new("cam1".to_string());
+    let mut manager = ConnectedCamerasManager::new(
+        &None,
+        std::iter::once(raw_cam_name.clone()).collect(),
+        Default::default(),
+        Default::default(),
+        None,
+    );
+    let trigger_cfg = TriggerType::FakeSync(Default::default());
+    let packet = |framenumber| FlydraRawUdpPacket {
+        cam_name: raw_cam_name.as_str().to_string(),
+        timestamp: None,
+        cam_received_time: FlydraFloatTimestampLocal::<HostClock>::from_f64(0.0),
+        device_timestamp: None,
+        block_id: None,
+        framenumber,
+        n_frames_skipped: 0,
+        done_camnode_processing: 0.0,
+        preprocess_stamp: 0.0,
+        image_processing_steps: ImageProcessingSteps::empty(),
+        points: vec![],
+    };
+    let mut frame_offsets = vec![];
+
+    assert_eq!(
+        manager.register_new_camera(&raw_cam_name, &BuiServerInfo::NoServer, None),
+        Ok(Registration::New)
+    );
+    let cam_num = manager.cam_num(&raw_cam_name);
+    assert!(cam_num.is_some());
+
+    // The camera is synchronized during the sync pulse pause.
+    let pause_started = std::time::Instant::now() - std::time::Duration::from_millis(100);
+    let sync_pulse_pause_started = Arc::new(RwLock::new(Some(pause_started)));
+    let synced_frame = manager.got_new_frame_live(
+        &packet(10),
+        &sync_pulse_pause_started,
+        None,
+        |frame0| frame_offsets.push(frame0),
+        &trigger_cfg,
+    );
+    assert_eq!(synced_frame, Some(SyncFno(crate::TRIGGERBOX_FIRST_PULSE)));
+    assert_eq!(frame_offsets, vec![10 - crate::TRIGGERBOX_FIRST_PULSE]);
+    *sync_pulse_pause_started.write().unwrap() = None;
+    let synced_frame = manager.got_new_frame_live(
+        &packet(11),
+        &sync_pulse_pause_started,
+        None,
+        |frame0| frame_offsets.push(frame0),
+        &trigger_cfg,
+    );
+    assert_eq!(
+        synced_frame,
+        Some(SyncFno(crate::TRIGGERBOX_FIRST_PULSE + 1))
+    );
+
+    // The camera restarts and counts frames from zero again. It keeps its
+    // camera number.
+    assert_eq!(
+        manager.register_new_camera(&raw_cam_name, &BuiServerInfo::NoServer, None),
+        Ok(Registration::Reconnected)
+    );
+    assert_eq!(manager.cam_num(&raw_cam_name), cam_num);
+
+    // Without an estimate of the synchronized frame number, the camera cannot
+    // be synchronized.
+    let synced_frame = manager.got_new_frame_live(
+        &packet(0),
+        &sync_pulse_pause_started,
+        None,
+        |frame0| frame_offsets.push(frame0),
+        &trigger_cfg,
+    );
+    assert_eq!(synced_frame, None);
+
+    // It is synchronized with the estimate, without a sync pulse pause.
+    let synced_frame = manager.got_new_frame_live(
+        &packet(1),
+        &sync_pulse_pause_started,
+        Some(SyncFno(1000)),
+        |frame0| frame_offsets.push(frame0),
+        &trigger_cfg,
+    );
+    assert_eq!(synced_frame, Some(SyncFno(1000)));
+    assert_eq!(frame_offsets.len(), 2);
+    let synced_frame = manager.got_new_frame_live(
+        &packet(2),
+        &sync_pulse_pause_started,
+        None,
+        |frame0| frame_offsets.push(frame0),
+        &trigger_cfg,
+    );
+    assert_eq!(synced_frame, Some(SyncFno(1001)));
+}
@@ -52,8 +52,15 @@ pub enum Error {
         #[from]
         source: tokio::sync::mpsc::error::SendError<crate::SaveToDiskMsg>,
     },
+    #[error("{source}")]
+    Rerun {
+        #[from]
+        source: re_sdk::RecordingStreamError,
+    },
     #[error("invalid hypothesis testing parameters")]
     InvalidHypothesisTestingParameters,
+    #[error("invalid tracking volume: {0}")]
+    InvalidTrackingVolume(String),
     #[error("insufficient data to calculate FPS")]
     InsufficientDataToCalculateFps,
     #[error(transparent)]
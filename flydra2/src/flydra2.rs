@@ -31,17 +31,23 @@ pub use braidz_types::BraidMetadata;
 
 use flydra_types::{
     CamInfoRow, CamNum, ConnectedCameraSyncState, DataAssocRow, FlydraFloatTimestampLocal,
-    HostClock, KalmanEstimatesRow, RawCamName, SyncFno, TextlogRow, TrackingParams,
-    TriggerClockInfoRow, Triggerbox, RECONSTRUCT_LATENCY_HLOG_FNAME, REPROJECTION_DIST_HLOG_FNAME,
+    FrameDropRow, HostClock, KalmanEstimatesRow, Mp4AlignmentRow, RawCamName, SyncFno, TextlogRow,
+    TrackConfirmationRow, TrackTerminationRow, TrackingParams, TriggerClockInfoRow, Triggerbox,
+    RECONSTRUCT_LATENCY_HLOG_FNAME, REPROJECTION_DIST_HLOG_FNAME,
 };
 pub use flydra_types::{Data2dDistortedRow, Data2dDistortedRowF32};
 
 mod connected_camera_manager;
-pub use connected_camera_manager::{ConnectedCamCallback, ConnectedCamerasManager};
+pub use connected_camera_manager::{ConnectedCamCallback, ConnectedCamerasManager, Registration};
 
 mod write_data;
 pub use write_data::BraidMetadataBuilder;
 
+mod software_sync;
+
+mod trigger_offset;
+pub use trigger_offset::{TriggerOffset, TriggerOffsetEstimator};
+
 mod bundled_data;
 mod contiguous_stream;
 mod frame_bundler;
@@ -51,11 +57,24 @@ mod new_object_test_3d;
 
 mod flat_2d;
 mod tracking_core;
+mod tracking_volume;
+pub use tracking_volume::TrackingVolumeShape;
 
 mod mini_arenas;
 
+mod stage_latency;
+pub use stage_latency::{
+    LocalStageLatencies, StageLatencies, StageLatencySummary, STAGE_CAMERA, STAGE_CAMERA_QUEUE,
+    STAGE_TRACK, STAGE_UNDISTORT,
+};
+
 mod model_server;
-pub use crate::model_server::{new_model_server, SendKalmanEstimatesRow, SendType};
+pub use crate::model_server::{
+    new_model_server, SendKalmanEstimatesRow, SendType, ToListener, BRAID_POSE_API_VERSION,
+};
+
+mod rerun_logger;
+pub use crate::rerun_logger::{RerunLogger, RerunTarget};
 
 use crate::contiguous_stream::make_contiguous;
 use crate::frame_bundler::bundle_frames;
@@ -379,6 +398,7 @@ fn convert_to_save(frame_data: &FrameData, input: &NumberedRawUdpPoint) -> Data2
         cur_val: input.pt.cur_val,
         mean_val: input.pt.mean_val as f32,
         sumsqf_val: input.pt.sumsqf_val as f32,
+        mean_intensity: input.pt.mean_intensity as f32,
     }
 }
 
@@ -399,6 +419,7 @@ fn convert_empty_to_save(frame_data: &FrameData) -> Data2dDistortedRowF32 {
         cur_val: 0,
         mean_val: f32::NAN,
         sumsqf_val: f32::NAN,
+        mean_intensity: f32::NAN,
     }
 }
 
@@ -473,7 +494,15 @@ pub enum SaveToDiskMsg {
     StopSavingCsv,
     Textlog(TextlogRow),
     TriggerClockInfo(TriggerClockInfoRow),
+    FrameDrop(FrameDropRow),
+    TrackConfirmation(TrackConfirmationRow),
+    TrackTermination(TrackTerminationRow),
     SetExperimentUuid(String),
+    SetExperimentMetadata(flydra_types::ExperimentMetadata),
+    Mp4Recording(flydra_types::PerCam<Option<flydra_types::Mp4RecordingInfo>>),
+    /// Reply once all previous messages have been handled, e.g. to wait until
+    /// the `.braidz` file is complete after `StopSavingCsv`.
+    Barrier(tokio::sync::oneshot::Sender<()>),
 }
 
 /// Acts like a `csv::Writer` but buffers and orders by frame.
@@ -712,13 +741,15 @@ pub struct CoordProcessor {
     /// Channel to send messages to the writing thread.
     pub braidz_write_tx: SingletonSender<SaveToDiskMsg>,
     pub writer_join_handle: tokio::task::JoinHandle<Result<()>>,
-    model_servers: Vec<tokio::sync::mpsc::Sender<(SendType, TimeDataPassthrough)>>,
+    model_servers: Vec<Listener>,
     tracking_params: Arc<TrackingParams>,
     /// Images of the "mini arenas" in use.
     ///
     /// One per camera when we have calibrations to do tracking. Empty
     /// otherwise.
-    mini_arena_images: std::collections::BTreeMap<String, MiniArenaImage>,
+    mini_arena_images: Arc<std::collections::BTreeMap<String, MiniArenaImage>>,
+    /// The volume in which objects are tracked.
+    tracking_volume: Arc<tracking_volume::TrackingVolumeShape>,
     /// A vector of model collections, one per "mini arena".
     ///
     /// This is behind `Option<>` for reasons I do not remember.
@@ -726,6 +757,52 @@ pub struct CoordProcessor {
         Vec<crate::tracking_core::ModelCollection<crate::tracking_core::CollectionFrameDone>>,
     >,
     next_obj_id: Arc<Mutex<u32>>,
+    stage_latencies: Option<StageLatencies>,
+    rerun_logger: Option<RerunLogger>,
+}
+
+/// Maximum number of frames being undistorted while the previous frames are
+/// tracked.
+const UNDISTORT_PIPELINE_DEPTH: usize = 4;
+
+/// Minimum interval between warnings about messages dropped for a listener.
+const DROP_WARNING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// A receiver of the Kalman estimates added with
+/// [CoordProcessor::add_listener].
+#[derive(Debug)]
+struct Listener {
+    tx: tokio::sync::mpsc::Sender<(SendType, TimeDataPassthrough)>,
+    /// Number of messages dropped since the last warning.
+    n_dropped: usize,
+    last_drop_warning: Option<std::time::Instant>,
+}
+
+impl Listener {
+    /// Send `msg` without waiting, so that a slow listener does not stall
+    /// tracking. If the listener's queue is full, the message is dropped.
+    fn send(&mut self, msg: (SendType, TimeDataPassthrough)) {
+        match self.tx.try_send(msg) {
+            Ok(()) => {}
+            Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => {
+                self.n_dropped += 1;
+                let do_warn = self
+                    .last_drop_warning
+                    .map(|t| t.elapsed() >= DROP_WARNING_INTERVAL)
+                    .unwrap_or(true);
+                if do_warn {
+                    tracing::warn!(
+                        "Queue of listener to Kalman estimates is full. Dropped {} message(s).",
+                        self.n_dropped
+                    );
+                    self.n_dropped = 0;
+                    self.last_drop_warning = Some(std::time::Instant::now());
+                }
+            }
+            // The listener is no longer running.
+            Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => {}
+        }
+    }
 }
 
 impl CoordProcessor {
@@ -756,6 +833,10 @@ impl CoordProcessor {
             mini_arena_debug_image_dir.as_deref(),
         )?;
 
+        let tracking_volume = Arc::new(tracking_volume::TrackingVolumeShape::new(
+            &tracking_params.tracking_volume,
+        )?);
+
         let tracking_params: Arc<TrackingParams> = Arc::from(tracking_params);
         let tracking_params2 = tracking_params.clone();
         let cam_manager2 = cam_manager.clone();
@@ -783,8 +864,11 @@ impl CoordProcessor {
             tracking_params,
             model_servers: vec![],
             model_collections: None,
-            mini_arena_images,
+            mini_arena_images: Arc::new(mini_arena_images),
+            tracking_volume,
             next_obj_id: Arc::new(Mutex::new(0)),
+            stage_latencies: None,
+            rerun_logger: None,
         })
     }
 
@@ -805,6 +889,7 @@ impl CoordProcessor {
                     fps,
                     self.cam_manager.clone(),
                     mini_arena_idx,
+                    self.tracking_volume.clone(),
                 )
             })
             .collect()
@@ -814,7 +899,25 @@ impl CoordProcessor {
         &mut self,
         model_server: tokio::sync::mpsc::Sender<(SendType, TimeDataPassthrough)>,
     ) {
-        self.model_servers.push(model_server);
+        self.model_servers.push(Listener {
+            tx: model_server,
+            n_dropped: 0,
+            last_drop_warning: None,
+        });
+    }
+
+    /// Record the latency of undistortion and tracking in `stage_latencies`.
+    pub fn set_stage_latencies(&mut self, stage_latencies: StageLatencies) {
+        self.stage_latencies = Some(stage_latencies);
+    }
+
+    /// Stream the calibration, 2D detections and Kalman estimates to rerun.
+    pub fn set_rerun_logger(&mut self, mut rerun_logger: RerunLogger) -> Result<()> {
+        if let Some(recon) = &self.recon {
+            rerun_logger.log_calibration(recon)?;
+        }
+        self.rerun_logger = Some(rerun_logger);
+        Ok(())
     }
 
     /// Consume the CoordProcessor and the input stream.
@@ -857,6 +960,12 @@ impl CoordProcessor {
                         panic!("Impossible frame number with frame data {:?}", fdp);
                     }
 
+                    if let Some(rerun_logger) = &self.rerun_logger {
+                        if let Err(e) = rerun_logger.log_data2d(fdp) {
+                            tracing::warn!("Failed logging to rerun: {e}");
+                        }
+                    }
+
                     self.braidz_write_tx
                         .send(SaveToDiskMsg::Data2dDistorted(fdp.clone()))
                         .await
@@ -889,12 +998,13 @@ impl CoordProcessor {
             let flydra_xml_str = std::str::from_utf8(&flydra_xml_new).unwrap();
 
             for ms in self.model_servers.iter() {
-                ms.send((
-                    SendType::CalibrationFlydraXml(flydra_xml_str.to_string()),
-                    dummy_time.clone(),
-                ))
-                .await
-                .expect("send calibration");
+                ms.tx
+                    .send((
+                        SendType::CalibrationFlydraXml(flydra_xml_str.to_string()),
+                        dummy_time.clone(),
+                    ))
+                    .await
+                    .expect("send calibration");
             }
         }
 
@@ -908,33 +1018,64 @@ impl CoordProcessor {
             bundle_frames(stream1, ccm.clone()).instrument(tracing::info_span!("bundle_frames"));
 
         // Ensure that there are no skipped frames.
-        let mut contiguous_stream =
+        let contiguous_stream =
             make_contiguous(bundled).instrument(tracing::info_span!("contiguous"));
 
+        // Undistort incoming points and assign to mini arenas. This is done
+        // for several frames in parallel on the blocking thread pool while
+        // the previous frames are tracked below. The order of the frames is
+        // kept.
+        let recon = self.recon.clone().map(Arc::new);
+        let mini_arena_images = self.mini_arena_images.clone();
+        let tracking_params = self.tracking_params.clone();
+        let stage_latencies = self.stage_latencies.clone();
+        let mut undistorted_stream = contiguous_stream
+            .map(move |bundle| {
+                let recon = recon.clone();
+                let mini_arena_images = mini_arena_images.clone();
+                let tracking_params = tracking_params.clone();
+                let stage_latencies = stage_latencies.clone();
+                async move {
+                    let frame = bundle.frame();
+                    let Some(recon) = recon else {
+                        return (frame, None);
+                    };
+                    tokio::task::spawn_blocking(move || {
+                        let start = std::time::Instant::now();
+                        let undistorted = bundle.undistort_and_split_to_mini_arenas(
+                            &recon,
+                            &mini_arena_images,
+                            &tracking_params.mini_arena_config,
+                        );
+                        if let Some(stage_latencies) = &stage_latencies {
+                            stage_latencies.record(STAGE_UNDISTORT, start.elapsed());
+                        }
+                        (frame, Some(undistorted))
+                    })
+                    .await
+                    .unwrap()
+                }
+            })
+            .buffered(UNDISTORT_PIPELINE_DEPTH);
+
         let mut mini_arena_assignment_debug = std::env::var_os("DEBUG_MINI_ARENAS")
             .map(|fname| mini_arenas::MiniArenaAssignmentDebug::new(fname).unwrap());
 
         // In this inner loop, we handle each incoming datum. We spend the vast majority
         // of the runtime in this loop.
-        while let Some(bundle) = contiguous_stream.next().await {
+        while let Some((frame, undistorted)) = undistorted_stream.next().await {
             assert!(
-                bundle.frame() >= prev_frame,
+                frame >= prev_frame,
                 "Frame number decreasing? The previously received frame was {}, but now have {}",
                 prev_frame,
-                bundle.frame()
+                frame
             );
-            prev_frame = bundle.frame();
-
-            // Undistort incoming points and assign to mini arenas.
-            let undistorted = if let Some(recon) = &self.recon {
-                bundle.undistort_and_split_to_mini_arenas(
-                    recon,
-                    &self.mini_arena_images,
-                    &self.tracking_params.mini_arena_config,
-                )
-            } else {
+            prev_frame = frame;
+
+            let Some(undistorted) = undistorted else {
                 continue;
             };
+            let track_start = std::time::Instant::now();
 
             if let Some(dbg) = mini_arena_assignment_debug.as_mut() {
                 // This uses blocking IO. It should be rewritten to use async IO.
@@ -993,15 +1134,25 @@ impl CoordProcessor {
                     for msg in save_msgs.into_iter() {
                         self.braidz_write_tx.send(msg).await.unwrap();
                     }
-                    for ms in self.model_servers.iter() {
+                    for ms in self.model_servers.iter_mut() {
                         for msg in send_msgs.iter() {
-                            ms.send(msg.clone()).await.unwrap();
+                            ms.send(msg.clone());
+                        }
+                    }
+                    if let Some(rerun_logger) = &self.rerun_logger {
+                        for msg in send_msgs.iter() {
+                            if let Err(e) = rerun_logger.log_send_msg(msg) {
+                                tracing::warn!("Failed logging to rerun: {e}");
+                            }
                         }
                     }
                 }
 
                 self.model_collections = Some(model_collections);
             }
+            if let Some(stage_latencies) = &self.stage_latencies {
+                stage_latencies.record(STAGE_TRACK, track_start.elapsed());
+            }
         }
         debug!("consume_stream future done");
 
@@ -1048,6 +1199,7 @@ fn test_csv_nan() {
         cur_val: 5,
         mean_val: 6.0,
         sumsqf_val: 7.0,
+        mean_intensity: 8.0,
     };
 
     let mut csv_buf = Vec::<u8>::new();
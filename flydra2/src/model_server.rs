@@ -13,6 +13,11 @@ use flydra_types::{FlydraFloatTimestampLocal, SyncFno, Triggerbox};
 
 const EVENTS_PATH: &str = "/events";
 
+/// The version of the Braid pose API, the value `v` of [ToListener].
+///
+/// Bump when the definition of [ToListener] or [SendType] changes. ZP4q
+pub const BRAID_POSE_API_VERSION: u16 = 3;
+
 #[cfg(feature = "bundle_files")]
 static ASSETS_DIR: include_dir::Dir<'static> =
     include_dir::include_dir!("$CARGO_MANIFEST_DIR/static");
@@ -129,17 +134,29 @@ pub enum SendType {
     CalibrationFlydraXml(String),
 }
 
+/// A message of the Braid pose API, sent as the data of an event.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ToListener {
     // IMPORTANT NOTE: if you change this type, be sure to change the version
     // value `v`. Search for the string ZP4q and `Braid pose API`.
-    /// version
-    v: u16,
-    msg: SendType,
-    latency: f64,
-    synced_frame: SyncFno,
+    /// version, see [BRAID_POSE_API_VERSION]
+    pub v: u16,
+    pub msg: SendType,
+    /// Seconds from the trigger pulse until sending, NaN (sent as null) if
+    /// unknown.
+    #[serde(deserialize_with = "nan_from_null")]
+    pub latency: f64,
+    pub synced_frame: SyncFno,
     #[serde(with = "flydra_types::timestamp_opt_f64")]
-    trigger_timestamp: Option<FlydraFloatTimestampLocal<Triggerbox>>,
+    pub trigger_timestamp: Option<FlydraFloatTimestampLocal<Triggerbox>>,
+}
+
+/// JSON has no NaN, so serde_json serializes it as null.
+fn nan_from_null<'de, D>(deserializer: D) -> std::result::Result<f64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::NAN))
 }
 
 pub async fn new_model_server(
@@ -184,26 +201,6 @@ pub async fn new_model_server(
     let new_data_processor_future = async move {
         let app_state = app_state2;
 
-        const ENV_KEY: &str = "RERUN_VIEWER_ADDR";
-        let rec = std::env::var_os(ENV_KEY).map(|addr_str| {
-            let socket_addr = std::net::ToSocketAddrs::to_socket_addrs(addr_str.to_str().unwrap())
-                .unwrap()
-                .next()
-                .unwrap();
-            tracing::info!("Streaming data to rerun at {socket_addr}");
-            re_sdk::RecordingStreamBuilder::new("braid")
-                .connect_tcp_opts(socket_addr, None)
-                .unwrap()
-        });
-
-        if rec.is_none() {
-            tracing::info!(
-                "No Rerun viewer address specified with environment variable \
-            \"{ENV_KEY}\", not logging data to Rerun. (Hint: the Rerun Viewer \
-                listens by default on port 9876.)"
-            );
-        }
-
         // Wait for the next update time to arrive ...
         loop {
             let opt_new_data = data_rx.recv().await;
@@ -215,24 +212,6 @@ pub async fn new_model_server(
                         *current_calibration = Some((calib.clone(), tdpt.clone()));
                     }
                     send_msg(data, &app_state).await?;
-
-                    if let Some(rec) = &rec {
-                        match data {
-                            (SendType::CalibrationFlydraXml(_calib), _tdpt) => {}
-                            (SendType::Birth(row), _tdpt) | (SendType::Update(row), _tdpt) => {
-                                let obj_id = format!("{}", row.obj_id);
-                                let position = re_types::datatypes::Vec3D::new(
-                                    row.x as f32,
-                                    row.y as f32,
-                                    row.z as f32,
-                                );
-                                rec.log(obj_id, &re_types::archetypes::Points3D::new([position]))
-                                    .unwrap();
-                            }
-                            (SendType::Death(_x), _tdpt) => {}
-                            (SendType::EndOfFrame(_x), _tdpt) => {}
-                        }
-                    }
                 }
                 None => {
                     // All senders done. No new data will be coming, so quit.
@@ -265,7 +244,7 @@ fn get_body(data: &(SendType, TimeDataPassthrough)) -> String {
     // Send updates after each observation for lowest-possible latency.
     let data = ToListener {
         // Braid pose API
-        v: 3, // <- Bump when ToListener or SendType definition changes ZP4q
+        v: BRAID_POSE_API_VERSION,
         msg: msg.clone(),
         latency,
         synced_frame: tdpt.synced_frame(),
@@ -0,0 +1,211 @@
+//! Streaming of live tracking to a [rerun](https://rerun.io) viewer.
+//!
+//! The entity paths are those used by `braidz-export-rrd`, so that live data
+//! and exported `.braidz` files are shown the same way.
+
+use std::collections::BTreeMap;
+
+use re_types::archetypes::{Points2D, Points3D};
+
+use flydra_types::RawCamName;
+use mvg::rerun_io::{cam_geom_to_rr_pinhole_archetype as to_pinhole, AsRerunTransform3D};
+
+use crate::{FrameDataAndPoints, MyFloat, SendType, TimeDataPassthrough};
+
+const SECONDS_TIMELINE: &str = "wall_clock";
+const FRAMES_TIMELINE: &str = "frame";
+const DETECT_NAME: &str = "detect";
+const CAMERA_BASE_PATH: &str = "world/camera";
+
+/// Where live tracking data is streamed to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RerunTarget {
+    /// Address of a running rerun viewer, e.g. `127.0.0.1:9876`.
+    Connect(std::net::SocketAddr),
+    /// Name of an `.rrd` file to save.
+    Save(std::path::PathBuf),
+}
+
+impl std::str::FromStr for RerunTarget {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.ends_with(".rrd") {
+            return Ok(Self::Save(s.into()));
+        }
+        std::net::ToSocketAddrs::to_socket_addrs(s)
+            .map_err(|e| format!("\"{s}\" is neither an .rrd filename nor an address: {e}"))?
+            .next()
+            .map(Self::Connect)
+            .ok_or_else(|| format!("no address found for \"{s}\""))
+    }
+}
+
+/// How the 2D detections of a camera are logged.
+#[derive(Debug)]
+struct CamLog {
+    /// The entity path of the detections.
+    ent_path: String,
+    /// The camera calibration if the detections are undistorted.
+    undistort_with: Option<flydra_mvg::MultiCamera<MyFloat>>,
+}
+
+/// Logs camera calibrations, 2D detections and Kalman estimates to rerun.
+#[derive(Debug)]
+pub struct RerunLogger {
+    rec: re_sdk::RecordingStream,
+    by_cam_name: BTreeMap<RawCamName, CamLog>,
+}
+
+impl RerunLogger {
+    pub fn new(target: &RerunTarget) -> crate::Result<Self> {
+        let builder = re_sdk::RecordingStreamBuilder::new("braid");
+        let rec = match target {
+            RerunTarget::Connect(addr) => {
+                tracing::info!("Streaming data to rerun at {addr}");
+                builder.connect_tcp_opts(*addr, None)?
+            }
+            RerunTarget::Save(path) => {
+                tracing::info!("Saving rerun data to {}", path.display());
+                builder.save(path)?
+            }
+        };
+        Ok(Self {
+            rec,
+            by_cam_name: Default::default(),
+        })
+    }
+
+    /// Log the pose and intrinsics of each camera.
+    ///
+    /// Rerun cannot model lens distortion, so detections of cameras with
+    /// distortion are undistorted and shown with the linearized camera.
+    pub(crate) fn log_calibration(
+        &mut self,
+        recon: &flydra_mvg::FlydraMultiCameraSystem<MyFloat>,
+    ) -> crate::Result<()> {
+        for (cam_name, cam) in recon.system().cams_by_name().iter() {
+            let base_path = format!("{CAMERA_BASE_PATH}/{cam_name}");
+            self.rec.log_static(
+                base_path.as_str(),
+                &cam.extrinsics().as_rerun_transform3d().into(),
+            )?;
+            let cam_log = match cam.rr_pinhole_archetype() {
+                Ok(pinhole) => {
+                    let raw_path = format!("{base_path}/raw");
+                    self.rec.log_static(raw_path.as_str(), &pinhole)?;
+                    CamLog {
+                        ent_path: raw_path,
+                        undistort_with: None,
+                    }
+                }
+                Err(mvg::MvgError::RerunUnsupportedIntrinsics) => {
+                    let lin_path = format!("{base_path}/lin"); // undistorted = linear
+                    let lin_cam = cam.linearize_to_cam_geom();
+                    self.rec.log_static(
+                        lin_path.as_str(),
+                        &to_pinhole(&lin_cam, cam.width(), cam.height()),
+                    )?;
+                    CamLog {
+                        ent_path: lin_path,
+                        undistort_with: recon.cam_by_name(cam_name),
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            };
+            self.by_cam_name
+                .insert(RawCamName::new(cam_name.clone()), cam_log);
+        }
+        Ok(())
+    }
+
+    /// Log the 2D detections of one camera in one frame.
+    pub(crate) fn log_data2d(&self, fdp: &FrameDataAndPoints) -> crate::Result<()> {
+        let frame_data = &fdp.frame_data;
+        let timestamp = match &frame_data.trigger_timestamp {
+            Some(trigger_timestamp) => trigger_timestamp.as_f64(),
+            None => frame_data.cam_received_timestamp.as_f64(),
+        };
+        self.rec.set_time_sequence(
+            FRAMES_TIMELINE,
+            i64::try_from(frame_data.synced_frame.0).unwrap(),
+        );
+        self.rec.set_time_seconds(SECONDS_TIMELINE, timestamp);
+
+        let cam_log = self.by_cam_name.get(&frame_data.cam_name);
+        let points = fdp.points.iter().map(|p| {
+            let (x, y) = (p.pt.x0_abs, p.pt.y0_abs);
+            match cam_log.and_then(|c| c.undistort_with.as_ref()) {
+                Some(cam) => {
+                    let undistorted = cam.undistort(&mvg::DistortedPixel {
+                        coords: nalgebra::Point2::new(x, y),
+                    });
+                    (undistorted.coords.x as f32, undistorted.coords.y as f32)
+                }
+                None => (x as f32, y as f32),
+            }
+        });
+        let ent_path = match cam_log {
+            Some(cam_log) => format!("{}/{DETECT_NAME}", cam_log.ent_path),
+            // Without calibration, the camera is not placed in the world.
+            None => format!(
+                "{CAMERA_BASE_PATH}/{}/raw/{DETECT_NAME}",
+                frame_data.cam_name.as_str()
+            ),
+        };
+        // An empty list of points clears the detections of the previous frame.
+        self.rec.log(ent_path, &Points2D::new(points))?;
+        Ok(())
+    }
+
+    /// Log the Kalman estimates of a tracked object.
+    pub fn log_send_msg(&self, msg: &(SendType, TimeDataPassthrough)) -> crate::Result<()> {
+        let (msg, tdpt) = msg;
+        if let Some(timestamp) = tdpt.trigger_timestamp() {
+            self.rec
+                .set_time_seconds(SECONDS_TIMELINE, timestamp.as_f64());
+        }
+        self.rec.set_time_sequence(
+            FRAMES_TIMELINE,
+            i64::try_from(tdpt.synced_frame().0).unwrap(),
+        );
+        match msg {
+            SendType::Birth(row) | SendType::Update(row) => {
+                self.rec.log(
+                    format!("world/obj_id/{}", row.obj_id),
+                    &Points3D::new([(row.x as f32, row.y as f32, row.z as f32)]),
+                )?;
+            }
+            SendType::Death(obj_id) => {
+                // Indicate there are no more data for this obj_id.
+                let empty_position: [(f32, f32, f32); 0] = [];
+                self.rec.log(
+                    format!("world/obj_id/{obj_id}"),
+                    &Points3D::new(empty_position),
+                )?;
+            }
+            SendType::EndOfFrame(_) | SendType::CalibrationFlydraXml(_) => {}
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_rerun_target_from_str() {
+    use std::str::FromStr;
+
+    assert_eq!(
+        RerunTarget::from_str("127.0.0.1:9876"),
+        Ok(RerunTarget::Connect(([127, 0, 0, 1], 9876).into()))
+    );
+    assert_eq!(
+        RerunTarget::from_str("tracking.rrd"),
+        Ok(RerunTarget::Save("tracking.rrd".into()))
+    );
+    assert_eq!(
+        RerunTarget::from_str("/data/127.0.0.1:9876.rrd"),
+        Ok(RerunTarget::Save("/data/127.0.0.1:9876.rrd".into()))
+    );
+    // Without a port, this is not an address.
+    assert!(RerunTarget::from_str("127.0.0.1").is_err());
+    assert!(RerunTarget::from_str("tracking.txt").is_err());
+}
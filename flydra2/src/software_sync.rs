@@ -0,0 +1,107 @@
+//! Grouping of frames from free-running cameras by arrival time.
+//!
+//! Without a hardware trigger, frames from different cameras are not exposed
+//! at the same instant. Here, time is divided into nominal frame times spaced
+//! by the frame period starting at a common origin, and each frame is assigned
+//! to the nominal frame time closest to its arrival time. Frames further than
+//! a tolerance from any nominal time are dropped. Because free-running cameras
+//! do not run at exactly the nominal frame rate, the phase of each camera
+//! relative to the nominal frame times drifts, which is tracked so that a
+//! warning can be emitted.
+
+use std::collections::BTreeMap;
+
+use flydra_types::RawCamName;
+
+/// Weight of each new sample in the running average of the phase.
+const PHASE_SMOOTHING: f64 = 0.05;
+
+/// Minimum duration between drift warnings for a camera.
+const WARN_INTERVAL_SEC: f64 = 10.0;
+
+#[derive(Debug, Default)]
+struct CamPhase {
+    /// Running average of the difference between arrival time and nominal
+    /// frame time, in seconds.
+    mean_phase_sec: Option<f64>,
+    num_dropped: u64,
+    last_warning_sec: Option<f64>,
+}
+
+/// The outcome of assigning a single frame.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Assignment {
+    /// The frame was assigned to this synchronized frame number.
+    Frame(u64),
+    /// The frame was dropped because it is outside the tolerance.
+    Dropped,
+}
+
+/// Assigns frames of free-running cameras to synchronized frame numbers.
+#[derive(Debug)]
+pub(crate) struct SoftwareSync {
+    period_sec: f64,
+    tolerance_sec: f64,
+    cams: BTreeMap<RawCamName, CamPhase>,
+}
+
+impl SoftwareSync {
+    pub(crate) fn new(period_sec: f64, tolerance_sec: f64) -> Self {
+        Self {
+            period_sec,
+            tolerance_sec,
+            cams: BTreeMap::new(),
+        }
+    }
+
+    /// Assign a frame which arrived `elapsed_sec` after the time origin.
+    pub(crate) fn assign(&mut self, raw_cam_name: &RawCamName, elapsed_sec: f64) -> Assignment {
+        let n_periods = elapsed_sec / self.period_sec;
+        let fno = n_periods.round();
+        let phase_sec = (n_periods - fno) * self.period_sec;
+
+        let cam = self.cams.entry(raw_cam_name.clone()).or_default();
+        let mean_phase_sec = match cam.mean_phase_sec {
+            Some(prev) => prev + PHASE_SMOOTHING * (phase_sec - prev),
+            None => phase_sec,
+        };
+        cam.mean_phase_sec = Some(mean_phase_sec);
+
+        if mean_phase_sec.abs() > self.tolerance_sec / 2.0 {
+            let do_warn = cam
+                .last_warning_sec
+                .map(|t| elapsed_sec - t > WARN_INTERVAL_SEC)
+                .unwrap_or(true);
+            if do_warn {
+                cam.last_warning_sec = Some(elapsed_sec);
+                tracing::warn!(
+                    "Camera \"{}\" drifted {:.2} msec from nominal frame times ({} frames \
+                    dropped so far). Synchronization is approximate.",
+                    raw_cam_name.as_str(),
+                    mean_phase_sec * 1000.0,
+                    cam.num_dropped,
+                );
+            }
+        }
+
+        if phase_sec.abs() > self.tolerance_sec || fno < 0.0 {
+            cam.num_dropped += 1;
+            Assignment::Dropped
+        } else {
+            Assignment::Frame(fno as u64)
+        }
+    }
+}
+
+#[test]
+fn test_software_sync() {
+    let cam = RawCamName::new("cam".to_string());
+    let mut sync = SoftwareSync::new(0.01, 0.0025);
+    assert_eq!(sync.assign(&cam, 0.0), Assignment::Frame(0));
+    assert_eq!(sync.assign(&cam, 0.0101), Assignment::Frame(1));
+    assert_eq!(sync.assign(&cam, 0.0199), Assignment::Frame(2));
+    // Too far from the nominal time of frames 2 and 3.
+    assert_eq!(sync.assign(&cam, 0.025), Assignment::Dropped);
+    assert_eq!(sync.assign(&cam, 0.0302), Assignment::Frame(3));
+    assert_eq!(sync.cams[&cam].num_dropped, 1);
+}
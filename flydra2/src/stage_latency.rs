@@ -0,0 +1,201 @@
+//! Latency of the stages in which incoming data is processed.
+//!
+//! The incoming 2D data passes through several stages (e.g. waiting in the
+//! queue of a camera, per-camera processing, undistortion and tracking). To
+//! find which stage limits the throughput with many cameras, the duration of
+//! each stage is recorded in a histogram which can be summarized and reset
+//! periodically.
+//!
+//! Tasks which record often, such as the task of each camera, record into
+//! their own [LocalStageLatencies] and add these to the shared histograms only
+//! occasionally, so that they do not contend for the lock.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use hdrhistogram::Histogram;
+
+/// Time spent waiting in the queue of a camera before per-camera processing.
+pub const STAGE_CAMERA_QUEUE: &str = "camera-queue";
+/// Per-camera processing: decoding, synchronization and timestamps.
+pub const STAGE_CAMERA: &str = "camera";
+/// Undistortion of all points in a frame.
+pub const STAGE_UNDISTORT: &str = "undistort";
+/// Data association, Kalman update, births and deaths for a frame.
+pub const STAGE_TRACK: &str = "track";
+
+/// Largest recorded latency, in microseconds. Larger values are saturated.
+const MAX_LATENCY_USEC: u64 = 60_000_000;
+
+/// Histograms of the latency of each stage. Clones share the histograms.
+#[derive(Clone, Default)]
+pub struct StageLatencies {
+    inner: Arc<Mutex<BTreeMap<&'static str, Histogram<u64>>>>,
+}
+
+impl std::fmt::Debug for StageLatencies {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StageLatencies").finish_non_exhaustive()
+    }
+}
+
+impl StageLatencies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `stage` took `duration`.
+    pub fn record(&self, stage: &'static str, duration: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        record(&mut inner, stage, duration);
+    }
+
+    /// Summarize the latencies recorded since the last call and reset.
+    pub fn summary_and_reset(&self) -> Vec<StageLatencySummary> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .iter_mut()
+            .filter(|(_, hist)| !hist.is_empty())
+            .map(|(stage, hist)| {
+                let summary = StageLatencySummary {
+                    stage,
+                    count: hist.len(),
+                    p50_usec: hist.value_at_quantile(0.5),
+                    p99_usec: hist.value_at_quantile(0.99),
+                    max_usec: hist.max(),
+                };
+                hist.reset();
+                summary
+            })
+            .collect()
+    }
+}
+
+/// Latencies recorded by a single task, without locking.
+#[derive(Default)]
+pub struct LocalStageLatencies {
+    hists: BTreeMap<&'static str, Histogram<u64>>,
+}
+
+impl std::fmt::Debug for LocalStageLatencies {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalStageLatencies")
+            .finish_non_exhaustive()
+    }
+}
+
+impl LocalStageLatencies {
+    /// Record that `stage` took `duration`.
+    pub fn record(&mut self, stage: &'static str, duration: Duration) {
+        record(&mut self.hists, stage, duration);
+    }
+
+    /// Add the latencies recorded since the last call to `shared`.
+    pub fn flush(&mut self, shared: &StageLatencies) {
+        if self.hists.values().all(|hist| hist.is_empty()) {
+            return;
+        }
+        let mut inner = shared.inner.lock().unwrap();
+        for (stage, hist) in self.hists.iter_mut() {
+            inner
+                .entry(*stage)
+                .or_insert_with(new_histogram)
+                .add(&*hist)
+                .expect("histograms with equal bounds");
+            hist.reset();
+        }
+    }
+}
+
+fn new_histogram() -> Histogram<u64> {
+    Histogram::new_with_bounds(1, MAX_LATENCY_USEC, 2).expect("valid histogram bounds")
+}
+
+fn record(
+    hists: &mut BTreeMap<&'static str, Histogram<u64>>,
+    stage: &'static str,
+    duration: Duration,
+) {
+    let usec = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+    let hist = hists.entry(stage).or_insert_with(new_histogram);
+    hist.saturating_record(usec.max(1));
+}
+
+/// Summary of the latency of a stage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageLatencySummary {
+    pub stage: &'static str,
+    /// Number of recorded durations.
+    pub count: u64,
+    pub p50_usec: u64,
+    pub p99_usec: u64,
+    pub max_usec: u64,
+}
+
+impl std::fmt::Display for StageLatencySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: n={} p50={:.1}ms p99={:.1}ms max={:.1}ms",
+            self.stage,
+            self.count,
+            self.p50_usec as f64 / 1000.0,
+            self.p99_usec as f64 / 1000.0,
+            self.max_usec as f64 / 1000.0,
+        )
+    }
+}
+
+#[test]
+fn test_stage_latencies() {
+    let latencies = StageLatencies::new();
+    let clone = latencies.clone();
+    for ms in 1..=100 {
+        clone.record(STAGE_TRACK, Duration::from_millis(ms));
+    }
+    latencies.record(STAGE_UNDISTORT, Duration::from_micros(10));
+
+    let summary = latencies.summary_and_reset();
+    assert_eq!(summary.len(), 2);
+    let track = summary.iter().find(|s| s.stage == STAGE_TRACK).unwrap();
+    assert_eq!(track.count, 100);
+    // Values are recorded with 2 significant digits.
+    assert!((49_000..=51_000).contains(&track.p50_usec));
+    assert!((98_000..=100_999).contains(&track.max_usec));
+
+    // All histograms were reset.
+    assert!(latencies.summary_and_reset().is_empty());
+}
+
+#[test]
+fn test_local_stage_latencies() {
+    let latencies = StageLatencies::new();
+    latencies.record(STAGE_CAMERA, Duration::from_millis(1));
+
+    let mut local = LocalStageLatencies::default();
+    for _ in 0..10 {
+        local.record(STAGE_CAMERA, Duration::from_millis(2));
+        local.record(STAGE_CAMERA_QUEUE, Duration::from_millis(3));
+    }
+    // Nothing is shared before flushing.
+    assert_eq!(latencies.summary_and_reset().len(), 1);
+
+    latencies.record(STAGE_CAMERA, Duration::from_millis(1));
+    local.flush(&latencies);
+    let summary = latencies.summary_and_reset();
+    assert_eq!(summary.len(), 2);
+    let camera = summary.iter().find(|s| s.stage == STAGE_CAMERA).unwrap();
+    assert_eq!(camera.count, 11);
+    let queue = summary
+        .iter()
+        .find(|s| s.stage == STAGE_CAMERA_QUEUE)
+        .unwrap();
+    assert_eq!(queue.count, 10);
+
+    // The local histograms were reset.
+    local.flush(&latencies);
+    assert!(latencies.summary_and_reset().is_empty());
+}
@@ -18,7 +18,8 @@ use adskalman::{StateAndCovariance, TransitionModelLinearNoControl};
 
 use flydra_types::{
     CamNum, DataAssocRow, FlydraFloatTimestampLocal, FlydraRawUdpPoint, KalmanEstimatesRow,
-    RawCamName, SyncFno, TrackingParams, Triggerbox,
+    RawCamName, SyncFno, TrackConfirmationParams, TrackConfirmationRow, TrackTerminationReason,
+    TrackTerminationRow, TrackingParams, Triggerbox,
 };
 
 use crate::bundled_data::{MiniArenaPointPerCam, PerMiniArenaAllCamsOneFrameUndistorted};
@@ -27,8 +28,10 @@ use crate::{
     model_server::{SendKalmanEstimatesRow, SendType},
     new_object_test_2d::NewObjectTestFlat3D,
     new_object_test_3d::NewObjectTestFull3D,
-    to_world_point, CameraObservationModel, ConnectedCamerasManager, HypothesisTestResult,
-    KalmanEstimateRecord, MyFloat, SaveToDiskMsg, TimeDataPassthrough,
+    to_world_point,
+    tracking_volume::TrackingVolumeShape,
+    CameraObservationModel, ConnectedCamerasManager, HypothesisTestResult, KalmanEstimateRecord,
+    MyFloat, SaveToDiskMsg, TimeDataPassthrough,
 };
 
 // -----------------------------------------------------------------------------
@@ -81,6 +84,9 @@ struct DataAssocInfo {
     cam_num: CamNum,
     /// Reprojection distance. Calculated on undistorted pixel coords.
     reproj_dist: MyFloat,
+    /// Distance between the observation and the observation predicted before
+    /// the update. Calculated on undistorted pixel coords.
+    innovation: MyFloat,
 }
 
 /// have posterior distribution for this object on this frame
@@ -95,6 +101,11 @@ impl ModelFramePosteriors {
     fn covariance_size(&self) -> MyFloat {
         covariance_size(self.posterior.estimate.covariance())
     }
+
+    fn position(&self) -> Point3<MyFloat> {
+        let state = self.posterior.estimate.state();
+        Point3::new(state[0], state[1], state[2])
+    }
 }
 
 fn covariance_size<R: RealField + Copy>(mat: &OMatrix<R, U6, U6>) -> R {
@@ -150,7 +161,7 @@ struct LMInner {
     /// The unique object id for this model
     obj_id: u32,
     /// Initial start frame number
-    _start_frame: SyncFno,
+    start_frame: SyncFno,
 }
 
 impl LivingModel<ModelFrameStarted> {
@@ -286,7 +297,13 @@ impl LivingModel<ModelFrameStarted> {
 }
 
 #[inline]
-fn get_kalman_estimates_row(obj_id: u32, posterior: &StampedEstimate) -> KalmanEstimatesRow {
+fn get_kalman_estimates_row(
+    obj_id: u32,
+    posterior: &StampedEstimate,
+    num_cams: u8,
+    max_ray_angle: f64,
+    innovation_pixels: f64,
+) -> KalmanEstimatesRow {
     let state = posterior.estimate.state();
     let p = posterior.estimate.covariance();
     let timestamp = posterior.trigger_timestamp();
@@ -310,13 +327,20 @@ fn get_kalman_estimates_row(obj_id: u32, posterior: &StampedEstimate) -> KalmanE
         P33: p[(3, 3)],
         P44: p[(4, 4)],
         P55: p[(5, 5)],
+        num_cams,
+        max_ray_angle,
+        innovation_pixels,
+        pos_cov_trace: p[(0, 0)] + p[(1, 1)] + p[(2, 2)],
     }
 }
 
 impl LivingModel<ModelFramePosteriors> {
     fn finish_frame(
         mut self,
+        recon: &flydra_mvg::FlydraMultiCameraSystem<MyFloat>,
+        cam_manager: &ConnectedCamerasManager,
         num_observations_to_visibility: u8,
+        track_confirmation: Option<&TrackConfirmationParams>,
     ) -> (
         LivingModel<ModelFrameDone>,
         Vec<(SendType, TimeDataPassthrough)>,
@@ -338,6 +362,18 @@ impl LivingModel<ModelFramePosteriors> {
             .collect();
         let cum_reproj = mvg::vec_sum(&r);
         let n_pts = r.len();
+        let mean_reproj_dist = cum_reproj / n_pts as f64;
+        let mean_innovation = if n_pts == 0 {
+            f64::NAN
+        } else {
+            let innovations: Vec<f64> = self
+                .state
+                .data_assoc_this_timestamp
+                .iter()
+                .map(|x| x.innovation)
+                .collect();
+            mvg::vec_sum(&innovations) / n_pts as f64
+        };
         let mean_reproj_dist_100x = if n_pts == 0 {
             None
         } else {
@@ -348,6 +384,40 @@ impl LivingModel<ModelFramePosteriors> {
             Some(mean_reproj_dist_100x)
         };
 
+        let cam_nums: std::collections::BTreeSet<CamNum> = self
+            .state
+            .data_assoc_this_timestamp
+            .iter()
+            .map(|x| x.cam_num)
+            .collect();
+        let num_cameras = cam_nums.len();
+
+        // Largest angle between the rays from the observing cameras to the
+        // estimated position.
+        let max_ray_angle = if num_cameras >= 2 {
+            let cam_names: Vec<RawCamName> = cam_nums
+                .iter()
+                .filter_map(|cam_num| cam_manager.get_raw_cam_name(*cam_num))
+                .collect();
+            let point = to_world_point(self.state.posterior.estimate.state());
+            recon
+                .system()
+                .max_ray_angle(cam_names.iter().map(|name| name.as_str()), &point)
+                .unwrap_or(f64::NAN)
+        } else {
+            f64::NAN
+        };
+
+        // Whether this frame counts towards confirming the object.
+        let is_supported = match track_confirmation {
+            None => n_pts > 0,
+            Some(confirmation) => {
+                num_cameras > 0
+                    && num_cameras >= confirmation.min_num_cameras as usize
+                    && mean_reproj_dist <= confirmation.max_mean_reproj_dist_pixels
+            }
+        };
+
         let data_assoc_rows: Vec<_> = self
             .state
             .data_assoc_this_timestamp
@@ -360,16 +430,18 @@ impl LivingModel<ModelFramePosteriors> {
             })
             .collect();
 
-        let record = get_kalman_estimates_row(self.lmi.obj_id, &self.state.posterior);
+        let record = get_kalman_estimates_row(
+            self.lmi.obj_id,
+            &self.state.posterior,
+            num_cameras.try_into().unwrap_or(u8::MAX),
+            max_ray_angle,
+            mean_innovation,
+        );
         let send_kalman_estimate_row: SendKalmanEstimatesRow = record.clone().into();
 
-        // Save kalman estimates and data association data to disk iff there
-        // were one or more observations.
-        if !data_assoc_rows.is_empty() {
-            // We had an observation.
-
-            let mut do_become_visible = false;
-            if let Some(n_obs) = &new_gestation_age {
+        let mut do_become_visible = false;
+        if let Some(n_obs) = &new_gestation_age {
+            if is_supported {
                 // Update our gestation age with another observation.
                 let new_num_observations = n_obs + 1;
                 new_gestation_age = if new_num_observations > num_observations_to_visibility {
@@ -381,14 +453,30 @@ impl LivingModel<ModelFramePosteriors> {
                     // We do not yet have enough observations to become visible.
                     Some(n_obs + 1)
                 };
+            } else if track_confirmation.is_some() {
+                // Confirmation requires consecutive supported frames, so
+                // start counting again.
+                new_gestation_age = Some(0);
             }
+        }
 
-            if do_become_visible {
-                result_messages.push((
-                    SendType::Birth(send_kalman_estimate_row.clone()),
-                    self.state.posterior.tdpt.clone(),
-                ));
-            }
+        if do_become_visible {
+            result_messages.push((
+                SendType::Birth(send_kalman_estimate_row.clone()),
+                self.state.posterior.tdpt.clone(),
+            ));
+            result_save_msgs.push(SaveToDiskMsg::TrackConfirmation(TrackConfirmationRow {
+                obj_id,
+                start_frame: self.lmi.start_frame,
+                frame,
+                confirmed: true,
+            }));
+        }
+
+        // Save kalman estimates and data association data to disk iff there
+        // were one or more observations.
+        if !data_assoc_rows.is_empty() {
+            // We had an observation.
 
             // Handle backlog of frames with no observations.
 
@@ -416,7 +504,8 @@ impl LivingModel<ModelFramePosteriors> {
 
                     // println!("saving row with no observations {} {}", self.lmi.obj_id, fno);
                     // println!("   start idx end {} {} {}", start_idx, idx, end_idx);
-                    let no_obs_record = get_kalman_estimates_row(self.lmi.obj_id, posterior);
+                    let no_obs_record =
+                        get_kalman_estimates_row(self.lmi.obj_id, posterior, 0, f64::NAN, f64::NAN);
                     let msg = SaveToDiskMsg::KalmanEstimate(KalmanEstimateRecord {
                         record: no_obs_record,
                         data_assoc_rows: vec![],
@@ -524,6 +613,7 @@ pub(crate) fn initialize_model_collection(
     fps: f32,
     cam_manager: ConnectedCamerasManager,
     mini_arena_idx: MiniArenaIndex,
+    tracking_volume: Arc<TrackingVolumeShape>,
 ) -> ModelCollection<CollectionFrameDone> {
     let motion_noise_scale = params.motion_noise_scale;
     let dt = 1.0 / fps as f64;
@@ -555,6 +645,7 @@ pub(crate) fn initialize_model_collection(
             new_obj,
             motion_model,
             cam_manager,
+            tracking_volume,
         },
     }
 }
@@ -573,6 +664,7 @@ pub(crate) struct MCInner {
     new_obj: Box<dyn HypothesisTest + Send + Sync>,
     motion_model: MotionModel3DFixedDt<MyFloat>,
     cam_manager: ConnectedCamerasManager,
+    tracking_volume: Arc<TrackingVolumeShape>,
 }
 
 impl ModelCollection<CollectionFrameDone> {
@@ -806,6 +898,12 @@ impl ModelCollection<CollectionFrameWithObservationLikes> {
 
                             let estimate = &next_model.state.posterior;
 
+                            let predicted_undistorted =
+                                obs_model.predict_observation(estimate.estimate.state());
+                            let innovation = ((predicted_undistorted.x - undist_pt.x).powi(2)
+                                + (predicted_undistorted.y - undist_pt.y).powi(2))
+                            .sqrt();
+
                             let form = adskalman::CovarianceUpdateMethod::JosephForm;
                             let posterior = obs_model
                                 .update(&estimate.estimate, &observation_undistorted, form)
@@ -834,6 +932,7 @@ impl ModelCollection<CollectionFrameWithObservationLikes> {
                                 pt_idx: undist_pt.idx,
                                 cam_num,
                                 reproj_dist,
+                                innovation,
                             };
 
                             // trace!(
@@ -930,7 +1029,8 @@ impl ModelCollection<CollectionFramePosteriors> {
         let mut to_kill = Vec::with_capacity(orig_models.len());
         let mut to_live = Vec::with_capacity(orig_models.len() + 1);
 
-        let max_variance = self.mcinner.params.max_position_std_meters.powi(2) as f64; // square so that it is in variance units
+        let params = &self.mcinner.params;
+        let max_variance = params.max_position_std_meters.powi(2) as f64; // square so that it is in variance units
 
         for model in orig_models.into_iter() {
             let covar_size = model.state.covariance_size();
@@ -941,10 +1041,63 @@ impl ModelCollection<CollectionFramePosteriors> {
             //     covar_size,
             //     max_variance
             // );
-            if covar_size <= max_variance {
-                to_live.push(model);
+            let coast_frames = if model.state.data_assoc_this_timestamp.is_empty() {
+                model.posteriors.len() - model.last_observation_offset
             } else {
-                to_kill.push(model);
+                0
+            };
+            if covar_size > max_variance {
+                to_kill.push((model, TrackTerminationReason::Lost, None));
+            } else if params
+                .max_coast_frames
+                .map(|max| coast_frames > max as usize)
+                .unwrap_or(false)
+            {
+                trace!(
+                    "obj_id {} not observed for {coast_frames} frames",
+                    model.lmi.obj_id
+                );
+                to_kill.push((model, TrackTerminationReason::CoastLimit, None));
+            } else if !self
+                .mcinner
+                .tracking_volume
+                .contains(&model.state.position())
+            {
+                trace!("obj_id {} left the tracking volume", model.lmi.obj_id);
+                to_kill.push((model, TrackTerminationReason::OutOfVolume, None));
+            } else {
+                to_live.push(model);
+            }
+        }
+
+        if let Some(merge_distance) = params.merge_distance_meters {
+            // Only visible objects are merged, so that an object is not merged
+            // into one which may turn out to be a ghost.
+            let candidates: Vec<_> = to_live
+                .iter()
+                .filter(|model| model.gestation_age.is_none())
+                .map(|model| {
+                    (
+                        model.lmi.obj_id,
+                        model.lmi.start_frame,
+                        model.state.position(),
+                    )
+                })
+                .collect();
+            let merged = find_merged(&candidates, merge_distance);
+            if !merged.is_empty() {
+                let models = std::mem::take(&mut to_live);
+                for model in models.into_iter() {
+                    if let Some(merged_into) = merged.get(&model.lmi.obj_id) {
+                        trace!(
+                            "obj_id {} merged into obj_id {merged_into}",
+                            model.lmi.obj_id
+                        );
+                        to_kill.push((model, TrackTerminationReason::Merged, Some(*merged_into)));
+                    } else {
+                        to_live.push(model);
+                    }
+                }
             }
         }
 
@@ -988,7 +1141,22 @@ impl ModelCollection<CollectionFramePosteriors> {
             //     );
             // }
 
-            if let Some(new_obj) = self.mcinner.new_obj.hypothesis_test(&good_points) {
+            let new_obj = self
+                .mcinner
+                .new_obj
+                .hypothesis_test(&good_points)
+                .filter(|new_obj| {
+                    let inside = self.mcinner.tracking_volume.contains(&new_obj.coords);
+                    if !inside {
+                        trace!(
+                            "new object at {:?} is outside the tracking volume",
+                            new_obj.coords
+                        );
+                    }
+                    inside
+                });
+
+            if let Some(new_obj) = new_obj {
                 let HypothesisTestResult {
                     coords,
                     cams_and_reproj_dist,
@@ -1005,6 +1173,9 @@ impl ModelCollection<CollectionFramePosteriors> {
                             pt_idx,
                             cam_num,
                             reproj_dist: ci.reproj_dist,
+                            // A new object has no prediction, so use the
+                            // residual of its initial estimate.
+                            innovation: ci.reproj_dist,
                         }
                     })
                     .collect();
@@ -1026,7 +1197,7 @@ impl ModelCollection<CollectionFramePosteriors> {
                     last_observation_offset: 0,
                     lmi: LMInner {
                         obj_id,
-                        _start_frame: tdpt.frame,
+                        start_frame: tdpt.frame,
                     },
                 };
 
@@ -1036,24 +1207,43 @@ impl ModelCollection<CollectionFramePosteriors> {
             }
         }
 
+        let mut save_messages = Vec::new();
+
         if !to_kill.is_empty() {
-            for model in &to_kill {
+            for (model, reason, merged_into) in &to_kill {
                 if model.gestation_age.is_none() {
                     result_messages.push((
                         SendType::Death(model.lmi.obj_id),
                         model.state.posterior.tdpt.clone(),
                     ));
+                    save_messages.push(SaveToDiskMsg::TrackTermination(TrackTerminationRow {
+                        obj_id: model.lmi.obj_id,
+                        frame: model.state.posterior.frame(),
+                        reason: *reason,
+                        merged_into: *merged_into,
+                    }));
+                } else {
+                    save_messages.push(SaveToDiskMsg::TrackConfirmation(TrackConfirmationRow {
+                        obj_id: model.lmi.obj_id,
+                        start_frame: model.lmi.start_frame,
+                        frame: model.state.posterior.frame(),
+                        confirmed: false,
+                    }));
                 }
             }
         }
 
         let num_observations_to_visibility = self.mcinner.params.num_observations_to_visibility;
+        let track_confirmation = self.mcinner.params.track_confirmation.as_ref();
 
         let mut models = vec![];
-        let mut save_messages = Vec::new();
         for x in to_live.into_iter() {
-            let (this_models, this_result_messages, this_sav_msgs) =
-                x.finish_frame(num_observations_to_visibility);
+            let (this_models, this_result_messages, this_sav_msgs) = x.finish_frame(
+                &self.mcinner.recon,
+                &self.mcinner.cam_manager,
+                num_observations_to_visibility,
+                track_confirmation,
+            );
             save_messages.extend(this_sav_msgs);
             result_messages.extend(this_result_messages);
             models.push(this_models);
@@ -1070,6 +1260,39 @@ impl ModelCollection<CollectionFramePosteriors> {
     }
 }
 
+/// Find objects to merge into older objects.
+///
+/// `models` contains the object ID, start frame and position of each object.
+/// An object closer than `merge_distance` to an older object which is not
+/// itself merged is merged into the closest such object. Returns the object
+/// into which each merged object is merged.
+fn find_merged(
+    models: &[(u32, SyncFno, Point3<MyFloat>)],
+    merge_distance: f64,
+) -> BTreeMap<u32, u32> {
+    let mut by_age: Vec<_> = models.iter().collect();
+    by_age.sort_by_key(|(obj_id, start_frame, _)| (start_frame.0, *obj_id));
+
+    let mut kept: Vec<&(u32, SyncFno, Point3<MyFloat>)> = Vec::with_capacity(by_age.len());
+    let mut merged = BTreeMap::new();
+    for model in by_age.into_iter() {
+        let (obj_id, _, position) = model;
+        let closest = kept
+            .iter()
+            .map(|(other_id, _, other_position)| {
+                (*other_id, nalgebra::distance(position, other_position))
+            })
+            .filter(|(_, dist)| *dist < merge_distance)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        if let Some((other_id, _)) = closest {
+            merged.insert(*obj_id, other_id);
+        } else {
+            kept.push(model);
+        }
+    }
+    merged
+}
+
 fn filter_points_and_take_first(
     // fdp_vec: &[FrameDataAndPoints],
     fdp_vec: &UnusedDataPerArena,
@@ -1113,3 +1336,21 @@ fn convert_pt(input: &flydra_types::FlydraRawUdpPoint) -> mvg::DistortedPixel<My
         coords: nalgebra::geometry::Point2::new(input.x0_abs, input.y0_abs),
     }
 }
+
+#[test]
+fn test_find_merged() {
+    let models = [
+        (3, SyncFno(20), Point3::new(0.0, 0.0, 0.0)),
+        (1, SyncFno(10), Point3::new(0.005, 0.0, 0.0)),
+        (2, SyncFno(10), Point3::new(0.1, 0.0, 0.0)),
+        (4, SyncFno(30), Point3::new(0.104, 0.0, 0.0)),
+        (5, SyncFno(30), Point3::new(0.5, 0.0, 0.0)),
+    ];
+    let merged = find_merged(&models, 0.01);
+    // The younger object is merged into the older one.
+    assert_eq!(merged.get(&3), Some(&1));
+    assert_eq!(merged.get(&4), Some(&2));
+    assert_eq!(merged.len(), 2);
+
+    assert!(find_merged(&models, 0.001).is_empty());
+}
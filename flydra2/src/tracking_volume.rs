@@ -0,0 +1,139 @@
+use nalgebra::Point3;
+use parry3d_f64::{
+    query::PointQuery,
+    shape::{ConvexPolyhedron, Shape},
+};
+
+use flydra_types::{MyFloat, TrackingVolume};
+
+use crate::{Error, Result};
+
+/// The volume in which objects are tracked, ready for containment tests.
+#[derive(Clone)]
+pub enum TrackingVolumeShape {
+    Unbounded,
+    Box {
+        min: Point3<MyFloat>,
+        max: Point3<MyFloat>,
+    },
+    Cylinder {
+        center_x: MyFloat,
+        center_y: MyFloat,
+        radius: MyFloat,
+        z_min: MyFloat,
+        z_max: MyFloat,
+    },
+    ConvexHull(ConvexPolyhedron),
+}
+
+impl TrackingVolumeShape {
+    pub fn new(cfg: &TrackingVolume) -> Result<Self> {
+        let invalid = |msg: &str| Error::InvalidTrackingVolume(msg.to_string());
+        Ok(match cfg {
+            TrackingVolume::Unbounded => Self::Unbounded,
+            TrackingVolume::Box { min, max } => {
+                if (0..3).any(|i| min[i] > max[i]) {
+                    return Err(invalid("box minimum exceeds maximum"));
+                }
+                Self::Box {
+                    min: Point3::from(*min),
+                    max: Point3::from(*max),
+                }
+            }
+            TrackingVolume::Cylinder {
+                center_x,
+                center_y,
+                radius,
+                z_min,
+                z_max,
+            } => {
+                if *radius <= 0.0 || z_min > z_max {
+                    return Err(invalid("cylinder has no volume"));
+                }
+                Self::Cylinder {
+                    center_x: *center_x,
+                    center_y: *center_y,
+                    radius: *radius,
+                    z_min: *z_min,
+                    z_max: *z_max,
+                }
+            }
+            TrackingVolume::ConvexHull { vertices } => {
+                let points: Vec<Point3<MyFloat>> =
+                    vertices.iter().map(|v| Point3::from(*v)).collect();
+                let hull = ConvexPolyhedron::from_convex_hull(&points)
+                    .filter(|hull| hull.mass_properties(1.0).mass() > 0.0)
+                    .ok_or_else(|| invalid("convex hull vertices do not enclose a volume"))?;
+                Self::ConvexHull(hull)
+            }
+        })
+    }
+
+    /// Whether `pt` is inside the volume, including its boundary.
+    pub fn contains(&self, pt: &Point3<MyFloat>) -> bool {
+        match self {
+            Self::Unbounded => true,
+            Self::Box { min, max } => (0..3).all(|i| min[i] <= pt[i] && pt[i] <= max[i]),
+            Self::Cylinder {
+                center_x,
+                center_y,
+                radius,
+                z_min,
+                z_max,
+            } => {
+                let dx = pt.x - center_x;
+                let dy = pt.y - center_y;
+                dx * dx + dy * dy <= radius * radius && *z_min <= pt.z && pt.z <= *z_max
+            }
+            Self::ConvexHull(hull) => hull.contains_local_point(pt),
+        }
+    }
+}
+
+#[test]
+fn test_tracking_volume_contains() {
+    let inside = Point3::new(0.1, 0.1, 0.1);
+    let outside = Point3::new(0.1, 0.1, 1.5);
+
+    let shape = TrackingVolumeShape::new(&TrackingVolume::Unbounded).unwrap();
+    assert!(shape.contains(&inside));
+    assert!(shape.contains(&outside));
+
+    let shape = TrackingVolumeShape::new(&TrackingVolume::Box {
+        min: [-1.0, -1.0, 0.0],
+        max: [1.0, 1.0, 1.0],
+    })
+    .unwrap();
+    assert!(shape.contains(&inside));
+    assert!(!shape.contains(&outside));
+
+    let shape = TrackingVolumeShape::new(&TrackingVolume::Cylinder {
+        center_x: 0.0,
+        center_y: 0.0,
+        radius: 0.2,
+        z_min: 0.0,
+        z_max: 1.0,
+    })
+    .unwrap();
+    assert!(shape.contains(&inside));
+    assert!(!shape.contains(&outside));
+    assert!(!shape.contains(&Point3::new(0.15, 0.15, 0.1)));
+
+    let shape = TrackingVolumeShape::new(&TrackingVolume::ConvexHull {
+        vertices: vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ],
+    })
+    .unwrap();
+    assert!(shape.contains(&inside));
+    assert!(!shape.contains(&outside));
+    assert!(!shape.contains(&Point3::new(0.5, 0.5, 0.5)));
+
+    assert!(TrackingVolumeShape::new(&TrackingVolume::ConvexHull {
+        vertices: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+    })
+    .is_err());
+}
@@ -0,0 +1,199 @@
+//! Estimation of per-camera trigger offsets.
+//!
+//! When cameras are synchronized to the triggerbox, each camera's frame number
+//! is mapped to a synchronized frame number during the synchronization pause.
+//! If a camera delivers its frames with a latency differing from the other
+//! cameras by a large fraction of the frame period, this mapping can be off by
+//! one (or more) frames. Here, the latency between the computed trigger time
+//! and the host arrival time is collected for each camera after
+//! synchronization. Cameras whose median latency differs from the median across
+//! all cameras by approximately a whole number of frame periods are assigned a
+//! corresponding frame offset which is then applied to their synchronized frame
+//! numbers.
+
+use std::collections::BTreeMap;
+
+use tracing::{info, warn};
+
+use flydra_types::{RawCamName, SyncFno};
+
+/// The estimated offset of a single camera.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerOffset {
+    /// Number of frames to add to the synchronized frame number.
+    pub frames: i64,
+    /// Median latency from trigger to host arrival (in seconds), after
+    /// correction by `frames`.
+    pub median_latency_sec: f64,
+}
+
+/// Collects latencies after synchronization and estimates per-camera offsets.
+#[derive(Debug)]
+pub struct TriggerOffsetEstimator {
+    expected_cameras: Vec<RawCamName>,
+    num_frames: usize,
+    latencies: BTreeMap<RawCamName, Vec<f64>>,
+    offsets: Option<BTreeMap<RawCamName, TriggerOffset>>,
+}
+
+impl TriggerOffsetEstimator {
+    /// Create a new estimator using `num_frames` frames from each camera.
+    pub fn new(expected_cameras: Vec<RawCamName>, num_frames: usize) -> Self {
+        Self {
+            expected_cameras,
+            num_frames,
+            latencies: BTreeMap::new(),
+            offsets: None,
+        }
+    }
+
+    /// Discard all collected data, e.g. upon resynchronization.
+    pub fn reset(&mut self) {
+        self.latencies.clear();
+        self.offsets = None;
+    }
+
+    /// Register the latency of a single frame.
+    ///
+    /// `frame_period_sec` is the duration between trigger pulses. Once enough
+    /// frames from all expected cameras have been registered, the offsets are
+    /// estimated.
+    pub fn push(&mut self, raw_cam_name: &RawCamName, latency_sec: f64, frame_period_sec: f64) {
+        if self.offsets.is_some() {
+            return;
+        }
+        let samples = self.latencies.entry(raw_cam_name.clone()).or_default();
+        if samples.len() < self.num_frames {
+            samples.push(latency_sec);
+        }
+
+        let all_done = self.expected_cameras.iter().all(|name| {
+            self.latencies
+                .get(name)
+                .map(|s| s.len() >= self.num_frames)
+                .unwrap_or(false)
+        });
+        if all_done {
+            let offsets = estimate_offsets(&self.latencies, frame_period_sec);
+            for (name, offset) in offsets.iter() {
+                if offset.frames != 0 {
+                    warn!(
+                        "Camera \"{}\" is offset by {} frame(s) from the trigger. Correcting.",
+                        name.as_str(),
+                        offset.frames
+                    );
+                }
+                info!(
+                    "Camera \"{}\": trigger offset {} frame(s), median latency {:.2} msec.",
+                    name.as_str(),
+                    offset.frames,
+                    offset.median_latency_sec * 1000.0
+                );
+            }
+            self.offsets = Some(offsets);
+        }
+    }
+
+    /// The estimated offsets, if estimation is complete.
+    pub fn offsets(&self) -> Option<&BTreeMap<RawCamName, TriggerOffset>> {
+        self.offsets.as_ref()
+    }
+
+    /// The median of the per-camera median latencies, if estimation is
+    /// complete.
+    pub fn median_latency_sec(&self) -> Option<f64> {
+        let offsets = self.offsets.as_ref()?;
+        let latencies: Vec<f64> = offsets.values().map(|o| o.median_latency_sec).collect();
+        mvg::median(&latencies)
+    }
+
+    /// Remove the offset of a camera, e.g. because it connected again and was
+    /// synchronized using [Self::median_latency_sec].
+    pub fn forget(&mut self, raw_cam_name: &RawCamName) {
+        if let Some(offset) = self.offsets.as_mut().and_then(|o| o.get_mut(raw_cam_name)) {
+            offset.frames = 0;
+        }
+    }
+
+    /// Apply the estimated offset of a camera to a synchronized frame number.
+    ///
+    /// Returns `None` if the corrected frame number would be negative. If no
+    /// offsets have been estimated yet, the frame is returned unchanged.
+    pub fn apply(&self, raw_cam_name: &RawCamName, synced_frame: SyncFno) -> Option<SyncFno> {
+        let frames = self
+            .offsets
+            .as_ref()
+            .and_then(|o| o.get(raw_cam_name))
+            .map(|o| o.frames)
+            .unwrap_or(0);
+        let corrected = synced_frame.0 as i64 + frames;
+        if corrected < 0 {
+            None
+        } else {
+            Some(SyncFno(corrected as u64))
+        }
+    }
+}
+
+/// Estimate the offset of each camera from its latencies.
+fn estimate_offsets(
+    latencies: &BTreeMap<RawCamName, Vec<f64>>,
+    frame_period_sec: f64,
+) -> BTreeMap<RawCamName, TriggerOffset> {
+    // Cameras without finite latencies have no median and are left out.
+    let medians: BTreeMap<&RawCamName, f64> = latencies
+        .iter()
+        .filter_map(|(k, v)| mvg::median(v).map(|m| (k, m)))
+        .collect();
+    let Some(reference) = mvg::median(&medians.values().copied().collect::<Vec<_>>()) else {
+        return BTreeMap::new();
+    };
+
+    medians
+        .into_iter()
+        .map(|(name, m)| {
+            // A latency which is too large by one period means the trigger
+            // time was computed for a frame which is too early, so the
+            // synchronized frame number must be increased.
+            let frames = ((m - reference) / frame_period_sec).round() as i64;
+            let median_latency_sec = m - frames as f64 * frame_period_sec;
+            (
+                name.clone(),
+                TriggerOffset {
+                    frames,
+                    median_latency_sec,
+                },
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_trigger_offset() {
+    let period = 0.01;
+    let cams: Vec<RawCamName> = ["a", "b", "c"]
+        .iter()
+        .map(|n| RawCamName::new(n.to_string()))
+        .collect();
+    let mut est = TriggerOffsetEstimator::new(cams.clone(), 5);
+    for i in 0..5 {
+        let jitter = i as f64 * 0.0001;
+        est.push(&cams[0], 0.003 + jitter, period);
+        // Camera "b" was synchronized one frame too early.
+        est.push(&cams[1], 0.004 + period + jitter, period);
+        assert!(est.offsets().is_none());
+        est.push(&cams[2], 0.0035 + jitter, period);
+    }
+    let offsets = est.offsets().unwrap();
+    assert_eq!(offsets[&cams[0]].frames, 0);
+    assert_eq!(offsets[&cams[1]].frames, 1);
+    assert_eq!(offsets[&cams[2]].frames, 0);
+    assert!((offsets[&cams[1]].median_latency_sec - 0.0042).abs() < 1e-9);
+
+    assert_eq!(est.apply(&cams[1], SyncFno(10)), Some(SyncFno(11)));
+    assert_eq!(est.apply(&cams[0], SyncFno(10)), Some(SyncFno(10)));
+    assert!((est.median_latency_sec().unwrap() - 0.0037).abs() < 1e-9);
+
+    est.forget(&cams[1]);
+    assert_eq!(est.apply(&cams[1], SyncFno(10)), Some(SyncFno(10)));
+}
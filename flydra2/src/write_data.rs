@@ -4,9 +4,51 @@ use tracing::info;
 use std::io::Write;
 
 use flydra_types::{
-    BRAID_SCHEMA, CAM_SETTINGS_DIRNAME, FEATURE_DETECT_SETTINGS_DIRNAME, IMAGES_DIRNAME,
+    ObjectQualityRow, BRAID_SCHEMA, CAM_SETTINGS_DIRNAME, FEATURE_DETECT_SETTINGS_DIRNAME,
+    IMAGES_DIRNAME,
 };
 
+/// Accumulates the [ObjectQualityRow] of an object from its rows in the
+/// `kalman_estimates` table.
+#[derive(Debug, Default)]
+struct ObjectQuality {
+    num_frames: u64,
+    num_frames_observed: u64,
+    num_frames_multi_cam: u64,
+    sum_num_cams: u64,
+    sum_innovation_pixels: f64,
+    max_pos_cov_trace: f64,
+}
+
+impl ObjectQuality {
+    fn push(&mut self, row: &KalmanEstimatesRow) {
+        self.num_frames += 1;
+        if row.num_cams > 0 {
+            self.num_frames_observed += 1;
+            self.sum_num_cams += u64::from(row.num_cams);
+            self.sum_innovation_pixels += row.innovation_pixels;
+        }
+        if row.num_cams >= 2 {
+            self.num_frames_multi_cam += 1;
+        }
+        self.max_pos_cov_trace = self.max_pos_cov_trace.max(row.pos_cov_trace);
+    }
+
+    fn row(&self, obj_id: u32) -> ObjectQualityRow {
+        let num_observed = self.num_frames_observed as f64;
+        ObjectQualityRow {
+            obj_id,
+            num_frames: self.num_frames,
+            num_frames_observed: self.num_frames_observed,
+            num_frames_multi_cam: self.num_frames_multi_cam,
+            mean_num_cams: self.sum_num_cams as f64 / num_observed,
+            mean_innovation_pixels: self.sum_innovation_pixels / num_observed,
+            max_pos_cov_trace: self.max_pos_cov_trace,
+            confidence: self.num_frames_multi_cam as f64 / self.num_frames as f64,
+        }
+    }
+}
+
 struct WritingState {
     output_dirname: std::path::PathBuf,
     /// The readme file in the output directory.
@@ -26,7 +68,13 @@ struct WritingState {
     data_2d_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
     textlog_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
     trigger_clock_info_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
+    frame_drops_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
+    track_confirmation_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
+    track_termination_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
+    mp4_alignment_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
     experiment_info_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
+    /// The quality of each object, saved when closing.
+    object_quality: BTreeMap<u32, ObjectQuality>,
     writer_stats: Option<(usize, usize)>,
     file_start_time: std::time::SystemTime,
 
@@ -111,6 +159,7 @@ impl WritingState {
                         original_recording_time: local,
                         save_empty_data2d,
                         saving_program_name: parts.saving_program_name,
+                        tracking_2d: tracking_params.is_2d(),
                     }
                 }
                 BraidMetadataBuilder::Existing(metadata) => metadata,
@@ -277,6 +326,42 @@ impl WritingState {
             csv::Writer::from_writer(fd)
         };
 
+        let frame_drops_wtr = {
+            let mut csv_path = output_dirname.clone();
+            csv_path.push(format!("{}.gz", flydra_types::FRAME_DROPS_CSV_FNAME));
+            let fd = std::fs::File::create(&csv_path)?;
+            let fd: Box<dyn std::io::Write + Send> =
+                Box::new(AutoFinishUnchecked::new(Encoder::new(fd)?));
+            csv::Writer::from_writer(fd)
+        };
+
+        let track_confirmation_wtr = {
+            let mut csv_path = output_dirname.clone();
+            csv_path.push(format!("{}.gz", flydra_types::TRACK_CONFIRMATION_CSV_FNAME));
+            let fd = std::fs::File::create(&csv_path)?;
+            let fd: Box<dyn std::io::Write + Send> =
+                Box::new(AutoFinishUnchecked::new(Encoder::new(fd)?));
+            csv::Writer::from_writer(fd)
+        };
+
+        let track_termination_wtr = {
+            let mut csv_path = output_dirname.clone();
+            csv_path.push(format!("{}.gz", flydra_types::TRACK_TERMINATION_CSV_FNAME));
+            let fd = std::fs::File::create(&csv_path)?;
+            let fd: Box<dyn std::io::Write + Send> =
+                Box::new(AutoFinishUnchecked::new(Encoder::new(fd)?));
+            csv::Writer::from_writer(fd)
+        };
+
+        let mp4_alignment_wtr = {
+            let mut csv_path = output_dirname.clone();
+            csv_path.push(format!("{}.gz", flydra_types::MP4_ALIGNMENT_CSV_FNAME));
+            let fd = std::fs::File::create(&csv_path)?;
+            let fd: Box<dyn std::io::Write + Send> =
+                Box::new(AutoFinishUnchecked::new(Encoder::new(fd)?));
+            csv::Writer::from_writer(fd)
+        };
+
         let experiment_info_wtr = {
             // We do not stream this to .gz because we want to maximize chances
             // that it is completely flushed to disk even in event of a panic.
@@ -332,7 +417,12 @@ impl WritingState {
             data_2d_wtr,
             textlog_wtr,
             trigger_clock_info_wtr,
+            frame_drops_wtr,
+            track_confirmation_wtr,
+            track_termination_wtr,
+            mp4_alignment_wtr,
             experiment_info_wtr,
+            object_quality: BTreeMap::new(),
             writer_stats,
             file_start_time,
             reconstruction_latency_usec,
@@ -349,6 +439,30 @@ impl WritingState {
         Ok(data2d_distorted.len())
     }
 
+    /// Write the description of the experiment, replacing any earlier one.
+    fn write_experiment_metadata(&self, metadata: &flydra_types::ExperimentMetadata) -> Result<()> {
+        let path = self
+            .output_dirname
+            .join(flydra_types::EXPERIMENT_METADATA_YML_FNAME);
+        let buf = serde_yaml::to_string(metadata).unwrap();
+        std::fs::write(path, buf)?;
+        Ok(())
+    }
+
+    fn write_object_quality(&self) -> Result<()> {
+        let mut csv_path = self.output_dirname.clone();
+        csv_path.push(format!("{}.gz", flydra_types::OBJECT_QUALITY_CSV_FNAME));
+        let fd = std::fs::File::create(&csv_path)?;
+        let fd: Box<dyn std::io::Write + Send> =
+            Box::new(AutoFinishUnchecked::new(Encoder::new(fd)?));
+        let mut wtr = csv::Writer::from_writer(fd);
+        for (obj_id, quality) in self.object_quality.iter() {
+            wtr.serialize(quality.row(*obj_id))?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
     fn flush_all(&mut self) -> Result<()> {
         if let Some(ref mut kew) = self.kalman_estimates_wtr {
             kew.flush()?;
@@ -359,6 +473,10 @@ impl WritingState {
         self.data_2d_wtr.flush()?;
         self.textlog_wtr.flush()?;
         self.trigger_clock_info_wtr.flush()?;
+        self.frame_drops_wtr.flush()?;
+        self.track_confirmation_wtr.flush()?;
+        self.track_termination_wtr.flush()?;
+        self.mp4_alignment_wtr.flush()?;
         self.experiment_info_wtr.flush()?;
         self.last_flush = std::time::Instant::now();
         Ok(())
@@ -387,9 +505,17 @@ impl Drop for WritingState {
             self.data_2d_wtr = dummy_csv();
             self.textlog_wtr = dummy_csv();
             self.trigger_clock_info_wtr = dummy_csv();
+            self.frame_drops_wtr = dummy_csv();
+            self.track_confirmation_wtr = dummy_csv();
+            self.track_termination_wtr = dummy_csv();
+            self.mp4_alignment_wtr = dummy_csv();
             self.experiment_info_wtr = dummy_csv();
         }
 
+        if let Err(e) = self.write_object_quality() {
+            tracing::error!("saving object quality: {e}");
+        }
+
         // Move out original output name so that a subsequent call to `drop()`
         // doesn't accidentally overwrite our real data.
         let output_dirname = std::mem::take(&mut self.output_dirname);
@@ -474,6 +600,29 @@ impl Drop for WritingState {
     }
 }
 
+/// Locate the frame in `frame_data` within the MP4 file described by `info`.
+///
+/// Returns `None` if the frame has no trigger timestamp or precedes the MP4
+/// file.
+fn mp4_alignment_row(
+    frame_data: &FrameData,
+    info: &flydra_types::Mp4RecordingInfo,
+) -> Option<Mp4AlignmentRow> {
+    let timestamp = frame_data.trigger_timestamp.clone()?;
+    let pts_secs = timestamp.as_f64() - datetime_conversion::datetime_to_f64(&info.start);
+    // Allow for rounding of the timestamps.
+    if pts_secs < -0.5e-6 {
+        return None;
+    }
+    Some(Mp4AlignmentRow {
+        camn: frame_data.cam_num,
+        frame: frame_data.synced_frame.0 as i64,
+        timestamp,
+        mp4_filename: info.filename.clone(),
+        mp4_pts_usec: (pts_secs.max(0.0) * 1e6).round() as u64,
+    })
+}
+
 /// Listen to a Receiver for messages and save the data to disk.
 ///
 /// This function only exits upon error or when the Sender counterpart to the
@@ -493,6 +642,10 @@ pub(crate) fn writer_task_main(
     use std::time::Duration;
 
     let mut writing_state: Option<WritingState> = None;
+    // Kept so that it is also saved in recordings started after it was set.
+    let mut experiment_metadata: Option<flydra_types::ExperimentMetadata> = None;
+    // The MP4 file currently being recorded by each camera.
+    let mut mp4_recordings: BTreeMap<RawCamName, flydra_types::Mp4RecordingInfo> = BTreeMap::new();
 
     const FLUSH_INTERVAL: u64 = 1;
     let flush_interval = Duration::from_secs(FLUSH_INTERVAL);
@@ -516,6 +669,10 @@ pub(crate) fn writer_task_main(
                 // Now actually send the data to the writers.
                 if let Some(ref mut ws) = writing_state {
                     if let Some(ref mut kew) = ws.kalman_estimates_wtr {
+                        ws.object_quality
+                            .entry(record.obj_id)
+                            .or_default()
+                            .push(&record);
                         kew.serialize(record)?;
                         if let Some(count) = ws.writer_stats.as_mut() {
                             count.1 += 1
@@ -597,6 +754,12 @@ pub(crate) fn writer_task_main(
             }
             Data2dDistorted(fdp) => {
                 if let Some(ref mut ws) = writing_state {
+                    if let Some(row) = mp4_recordings
+                        .get(&fdp.frame_data.cam_name)
+                        .and_then(|info| mp4_alignment_row(&fdp.frame_data, info))
+                    {
+                        ws.mp4_alignment_wtr.serialize(&row)?;
+                    }
                     let rows = ws.save_data_2d_distorted(fdp)?;
                     if let Some(count) = ws.writer_stats.as_mut() {
                         count.0 += rows;
@@ -605,14 +768,18 @@ pub(crate) fn writer_task_main(
                 // simply drop data if no file opened
             }
             StartSavingCsv(cfg) => {
-                writing_state = Some(WritingState::new(
+                let ws = WritingState::new(
                     cfg,
                     cam_manager.sample(),
                     &recon,
                     tracking_params.clone(),
                     save_empty_data2d,
                     metadata_builder.clone(),
-                )?);
+                )?;
+                if let Some(metadata) = &experiment_metadata {
+                    ws.write_experiment_metadata(metadata)?;
+                }
+                writing_state = Some(ws);
             }
             StopSavingCsv => {
                 // This will drop `writing_state`, and thus the writers, and
@@ -625,6 +792,24 @@ pub(crate) fn writer_task_main(
                     ws.experiment_info_wtr.serialize(&entry)?;
                 }
             }
+            SetExperimentMetadata(metadata) => {
+                if let Some(ref ws) = writing_state {
+                    ws.write_experiment_metadata(&metadata)?;
+                }
+                experiment_metadata = Some(metadata);
+            }
+            Barrier(tx) => {
+                // The receiver may have given up waiting.
+                let _ = tx.send(());
+            }
+            Mp4Recording(per_cam) => match per_cam.inner {
+                Some(info) => {
+                    mp4_recordings.insert(per_cam.raw_cam_name, info);
+                }
+                None => {
+                    mp4_recordings.remove(&per_cam.raw_cam_name);
+                }
+            },
             Textlog(entry) => {
                 if let Some(ref mut ws) = writing_state {
                     ws.textlog_wtr.serialize(&entry)?;
@@ -637,6 +822,24 @@ pub(crate) fn writer_task_main(
                 }
                 // simply drop data if no file opened
             }
+            FrameDrop(entry) => {
+                if let Some(ref mut ws) = writing_state {
+                    ws.frame_drops_wtr.serialize(&entry)?;
+                }
+                // simply drop data if no file opened
+            }
+            TrackConfirmation(entry) => {
+                if let Some(ref mut ws) = writing_state {
+                    ws.track_confirmation_wtr.serialize(&entry)?;
+                }
+                // simply drop data if no file opened
+            }
+            TrackTermination(entry) => {
+                if let Some(ref mut ws) = writing_state {
+                    ws.track_termination_wtr.serialize(&entry)?;
+                }
+                // simply drop data if no file opened
+            }
         }
 
         if let Some(ref mut ws) = writing_state {
@@ -710,6 +913,47 @@ mod test {
         std::fs::remove_dir_all(root).unwrap();
     }
 
+    #[test]
+    fn test_object_quality() {
+        let row = |num_cams, innovation_pixels| KalmanEstimatesRow {
+            obj_id: 1,
+            frame: SyncFno(0),
+            timestamp: None,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            xvel: 0.0,
+            yvel: 0.0,
+            zvel: 0.0,
+            P00: 1.0,
+            P01: 0.0,
+            P02: 0.0,
+            P11: 1.0,
+            P12: 0.0,
+            P22: 1.0,
+            P33: 0.0,
+            P44: 0.0,
+            P55: 0.0,
+            num_cams,
+            max_ray_angle: f64::NAN,
+            innovation_pixels,
+            pos_cov_trace: f64::from(num_cams) + 1.0,
+        };
+        let mut quality = ObjectQuality::default();
+        quality.push(&row(3, 1.0));
+        quality.push(&row(0, f64::NAN));
+        quality.push(&row(1, 2.0));
+        quality.push(&row(2, 3.0));
+        let row = quality.row(1);
+        assert_eq!(row.num_frames, 4);
+        assert_eq!(row.num_frames_observed, 3);
+        assert_eq!(row.num_frames_multi_cam, 2);
+        assert_eq!(row.mean_num_cams, 2.0);
+        assert_eq!(row.mean_innovation_pixels, 2.0);
+        assert_eq!(row.max_pos_cov_trace, 4.0);
+        assert_eq!(row.confidence, 0.5);
+    }
+
     /// Ensure that .braidz files can exceed 4GB.
     #[ignore]
     #[test]
@@ -0,0 +1,439 @@
+// Live 3D view of the cameras and the objects tracked by Braid.
+//
+// The calibration (sent as flydra XML when connecting) is used to draw the
+// camera frusta. Tracked objects are drawn with a trail of their recent
+// positions and labeled with their object ID.
+
+var TRAIL_LENGTH = 200;
+
+var state = {
+    cameras: [],
+    // Map of obj_id to {trail: [[x, y, z], ...], label: element}
+    objects: new Map(),
+    // Size of the scene, used for the frustum depth and the ground grid.
+    scale: 1.0,
+    azimuth: -Math.PI / 4,
+    elevation: Math.PI / 6,
+    distance: 2.0,
+    target: [0.0, 0.0, 0.0],
+};
+
+// Vector and matrix helpers. Matrices are 4x4 in column-major order as used by
+// WebGL.
+
+function sub(a, b) {
+    return [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+}
+
+function dot(a, b) {
+    return a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+}
+
+function cross(a, b) {
+    return [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+}
+
+function normalize(a) {
+    var n = Math.sqrt(dot(a, a));
+    return [a[0] / n, a[1] / n, a[2] / n];
+}
+
+function perspective(fovy, aspect, near, far) {
+    var f = 1.0 / Math.tan(fovy / 2);
+    return [
+        f / aspect, 0, 0, 0,
+        0, f, 0, 0,
+        0, 0, (far + near) / (near - far), -1,
+        0, 0, (2 * far * near) / (near - far), 0,
+    ];
+}
+
+function look_at(eye, target, up) {
+    var z = normalize(sub(eye, target));
+    var x = normalize(cross(up, z));
+    var y = cross(z, x);
+    return [
+        x[0], y[0], z[0], 0,
+        x[1], y[1], z[1], 0,
+        x[2], y[2], z[2], 0,
+        -dot(x, eye), -dot(y, eye), -dot(z, eye), 1,
+    ];
+}
+
+function mat_mul(a, b) {
+    var out = new Array(16);
+    for (var c = 0; c < 4; c++) {
+        for (var r = 0; r < 4; r++) {
+            var sum = 0;
+            for (var k = 0; k < 4; k++) {
+                sum += a[k * 4 + r] * b[c * 4 + k];
+            }
+            out[c * 4 + r] = sum;
+        }
+    }
+    return out;
+}
+
+function inverse3(m) {
+    var det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) -
+        m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0]) +
+        m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    var inv = [
+        [
+            (m[1][1] * m[2][2] - m[1][2] * m[2][1]) / det,
+            (m[0][2] * m[2][1] - m[0][1] * m[2][2]) / det,
+            (m[0][1] * m[1][2] - m[0][2] * m[1][1]) / det,
+        ],
+        [
+            (m[1][2] * m[2][0] - m[1][0] * m[2][2]) / det,
+            (m[0][0] * m[2][2] - m[0][2] * m[2][0]) / det,
+            (m[0][2] * m[1][0] - m[0][0] * m[1][2]) / det,
+        ],
+        [
+            (m[1][0] * m[2][1] - m[1][1] * m[2][0]) / det,
+            (m[0][1] * m[2][0] - m[0][0] * m[2][1]) / det,
+            (m[0][0] * m[1][1] - m[0][1] * m[1][0]) / det,
+        ],
+    ];
+    return { inv: inv, det: det };
+}
+
+function mat3_vec(m, v) {
+    return [dot(m[0], v), dot(m[1], v), dot(m[2], v)];
+}
+
+// A distinct color for each object ID.
+function obj_color(obj_id) {
+    var hue = (obj_id * 0.618033988749895) % 1.0;
+    var i = Math.floor(hue * 6);
+    var f = hue * 6 - i;
+    var q = 1 - f;
+    return [[1, f, 0], [q, 1, 0], [0, 1, f], [0, q, 1], [f, 0, 1], [1, 0, q]][i % 6];
+}
+
+// Calibration --------------------------------------------------------------
+
+// Compute the camera center and the directions to the image corners from the
+// 3x4 camera matrix P = [M | p4].
+function camera_geometry(cam_id, pmat, width, height) {
+    var m = [pmat[0].slice(0, 3), pmat[1].slice(0, 3), pmat[2].slice(0, 3)];
+    var p4 = [pmat[0][3], pmat[1][3], pmat[2][3]];
+    var r = inverse3(m);
+    var c = mat3_vec(r.inv, p4);
+    var center = [-c[0], -c[1], -c[2]];
+    // Scale the corner directions so that a unit step is a unit distance
+    // along the optical axis, in front of the camera.
+    var axis_scale = Math.sign(r.det) * Math.sqrt(dot(m[2], m[2]));
+    var corners = [[0, 0], [width, 0], [width, height], [0, height]].map(function (uv) {
+        var d = mat3_vec(r.inv, [uv[0], uv[1], 1.0]);
+        return [d[0] * axis_scale, d[1] * axis_scale, d[2] * axis_scale];
+    });
+    return { cam_id: cam_id, center: center, corners: corners };
+}
+
+function parse_calibration(xml_str) {
+    var doc = new DOMParser().parseFromString(xml_str, "application/xml");
+    var cams = [];
+    var elements = doc.getElementsByTagName("single_camera_calibration");
+    for (var i = 0; i < elements.length; i++) {
+        var el = elements[i];
+        var cam_id = el.getElementsByTagName("cam_id")[0].textContent.trim();
+        var pmat = el.getElementsByTagName("calibration_matrix")[0].textContent
+            .trim()
+            .split(";")
+            .map(function (row) { return row.trim().split(/\s+/).map(Number); });
+        var res = el.getElementsByTagName("resolution")[0].textContent.trim().split(/\s+/).map(Number);
+        cams.push(camera_geometry(cam_id, pmat, res[0], res[1]));
+    }
+    return cams;
+}
+
+function set_calibration(xml_str) {
+    state.cameras = parse_calibration(xml_str);
+    document.getElementById("num_cameras").textContent = state.cameras.length;
+
+    // Size the scene by the spread of the camera centers.
+    var max_dist = 0.0;
+    state.cameras.forEach(function (cam) {
+        max_dist = Math.max(max_dist, Math.sqrt(dot(cam.center, cam.center)));
+    });
+    if (max_dist > 0.0) {
+        state.scale = max_dist;
+        state.distance = 2.5 * max_dist;
+    }
+}
+
+// Tracked objects ------------------------------------------------------------
+
+function update_object(row) {
+    var obj = state.objects.get(row.obj_id);
+    if (typeof obj == "undefined") {
+        var label = document.createElement("div");
+        label.className = "label";
+        label.textContent = row.obj_id;
+        var color = obj_color(row.obj_id);
+        label.style.color = "rgb(" + color.map(function (c) { return Math.round(255 * c); }).join(",") + ")";
+        document.getElementById("container").appendChild(label);
+        obj = { trail: [], label: label };
+        state.objects.set(row.obj_id, obj);
+    }
+    obj.trail.push([row.x, row.y, row.z]);
+    if (obj.trail.length > TRAIL_LENGTH) {
+        obj.trail.shift();
+    }
+}
+
+function remove_object(obj_id) {
+    var obj = state.objects.get(obj_id);
+    if (typeof obj != "undefined") {
+        obj.label.remove();
+        state.objects.delete(obj_id);
+    }
+}
+
+function handle_message(to_listener) {
+    var msg = to_listener.msg;
+    if (typeof msg.Birth != "undefined") {
+        update_object(msg.Birth);
+    } else if (typeof msg.Update != "undefined") {
+        update_object(msg.Update);
+    } else if (typeof msg.Death != "undefined") {
+        remove_object(msg.Death);
+    } else if (typeof msg.CalibrationFlydraXml != "undefined") {
+        set_calibration(msg.CalibrationFlydraXml);
+    }
+    document.getElementById("num_objects").textContent = state.objects.size;
+}
+
+// Rendering -------------------------------------------------------------------
+
+var VERTEX_SHADER = `
+attribute vec3 position;
+attribute vec3 color;
+uniform mat4 mvp;
+varying vec3 v_color;
+void main() {
+    gl_Position = mvp * vec4(position, 1.0);
+    gl_PointSize = 8.0;
+    v_color = color;
+}`;
+
+var FRAGMENT_SHADER = `
+precision mediump float;
+varying vec3 v_color;
+void main() {
+    gl_FragColor = vec4(v_color, 1.0);
+}`;
+
+function compile_program(gl) {
+    function compile(type, source) {
+        var shader = gl.createShader(type);
+        gl.shaderSource(shader, source);
+        gl.compileShader(shader);
+        if (!gl.getShaderParameter(shader, gl.COMPILE_STATUS)) {
+            throw new Error(gl.getShaderInfoLog(shader));
+        }
+        return shader;
+    }
+    var program = gl.createProgram();
+    gl.attachShader(program, compile(gl.VERTEX_SHADER, VERTEX_SHADER));
+    gl.attachShader(program, compile(gl.FRAGMENT_SHADER, FRAGMENT_SHADER));
+    gl.linkProgram(program);
+    if (!gl.getProgramParameter(program, gl.LINK_STATUS)) {
+        throw new Error(gl.getProgramInfoLog(program));
+    }
+    return program;
+}
+
+// Accumulates vertices and colors to be drawn with a single draw call.
+function Geometry() {
+    this.positions = [];
+    this.colors = [];
+}
+
+Geometry.prototype.vertex = function (p, color) {
+    this.positions.push(p[0], p[1], p[2]);
+    this.colors.push(color[0], color[1], color[2]);
+};
+
+Geometry.prototype.line = function (a, b, color) {
+    this.vertex(a, color);
+    this.vertex(b, color);
+};
+
+function build_lines() {
+    var geom = new Geometry();
+
+    // Ground grid at z = 0 and coordinate axes at the origin.
+    var grid_color = [0.3, 0.3, 0.3];
+    var n = 10;
+    var step = state.scale / n;
+    for (var i = -n; i <= n; i++) {
+        geom.line([i * step, -n * step, 0], [i * step, n * step, 0], grid_color);
+        geom.line([-n * step, i * step, 0], [n * step, i * step, 0], grid_color);
+    }
+    var axis_len = 0.2 * state.scale;
+    geom.line([0, 0, 0], [axis_len, 0, 0], [1, 0, 0]);
+    geom.line([0, 0, 0], [0, axis_len, 0], [0, 1, 0]);
+    geom.line([0, 0, 0], [0, 0, axis_len], [0, 0, 1]);
+
+    // Camera frusta.
+    var depth = 0.15 * state.scale;
+    var cam_color = [0.8, 0.8, 0.8];
+    state.cameras.forEach(function (cam) {
+        var c = cam.center;
+        var corners = cam.corners.map(function (d) {
+            return [c[0] + depth * d[0], c[1] + depth * d[1], c[2] + depth * d[2]];
+        });
+        for (var i = 0; i < 4; i++) {
+            geom.line(c, corners[i], cam_color);
+            geom.line(corners[i], corners[(i + 1) % 4], cam_color);
+        }
+    });
+
+    // Trails of the tracked objects.
+    state.objects.forEach(function (obj, obj_id) {
+        var color = obj_color(obj_id);
+        for (var i = 1; i < obj.trail.length; i++) {
+            geom.line(obj.trail[i - 1], obj.trail[i], color);
+        }
+    });
+    return geom;
+}
+
+function build_points() {
+    var geom = new Geometry();
+    state.objects.forEach(function (obj, obj_id) {
+        geom.vertex(obj.trail[obj.trail.length - 1], obj_color(obj_id));
+    });
+    return geom;
+}
+
+function Renderer(canvas) {
+    var gl = canvas.getContext("webgl");
+    if (!gl) {
+        throw new Error("WebGL not supported in this browser");
+    }
+    this.gl = gl;
+    this.canvas = canvas;
+    this.program = compile_program(gl);
+    this.position_loc = gl.getAttribLocation(this.program, "position");
+    this.color_loc = gl.getAttribLocation(this.program, "color");
+    this.mvp_loc = gl.getUniformLocation(this.program, "mvp");
+    this.position_buf = gl.createBuffer();
+    this.color_buf = gl.createBuffer();
+}
+
+Renderer.prototype.draw_geometry = function (geom, mode) {
+    var gl = this.gl;
+    gl.bindBuffer(gl.ARRAY_BUFFER, this.position_buf);
+    gl.bufferData(gl.ARRAY_BUFFER, new Float32Array(geom.positions), gl.DYNAMIC_DRAW);
+    gl.enableVertexAttribArray(this.position_loc);
+    gl.vertexAttribPointer(this.position_loc, 3, gl.FLOAT, false, 0, 0);
+    gl.bindBuffer(gl.ARRAY_BUFFER, this.color_buf);
+    gl.bufferData(gl.ARRAY_BUFFER, new Float32Array(geom.colors), gl.DYNAMIC_DRAW);
+    gl.enableVertexAttribArray(this.color_loc);
+    gl.vertexAttribPointer(this.color_loc, 3, gl.FLOAT, false, 0, 0);
+    gl.drawArrays(mode, 0, geom.positions.length / 3);
+};
+
+Renderer.prototype.render = function () {
+    var gl = this.gl;
+    var canvas = this.canvas;
+    var width = canvas.clientWidth * window.devicePixelRatio;
+    var height = canvas.clientHeight * window.devicePixelRatio;
+    if (canvas.width != width || canvas.height != height) {
+        canvas.width = width;
+        canvas.height = height;
+    }
+    gl.viewport(0, 0, width, height);
+    gl.clearColor(0.125, 0.125, 0.125, 1.0);
+    gl.clear(gl.COLOR_BUFFER_BIT | gl.DEPTH_BUFFER_BIT);
+    gl.enable(gl.DEPTH_TEST);
+
+    var cos_el = Math.cos(state.elevation);
+    var eye = [
+        state.target[0] + state.distance * cos_el * Math.cos(state.azimuth),
+        state.target[1] + state.distance * cos_el * Math.sin(state.azimuth),
+        state.target[2] + state.distance * Math.sin(state.elevation),
+    ];
+    var proj = perspective(Math.PI / 4, width / height, 0.01 * state.distance, 100 * state.distance);
+    var mvp = mat_mul(proj, look_at(eye, state.target, [0, 0, 1]));
+
+    gl.useProgram(this.program);
+    gl.uniformMatrix4fv(this.mvp_loc, false, new Float32Array(mvp));
+    this.draw_geometry(build_lines(), gl.LINES);
+    this.draw_geometry(build_points(), gl.POINTS);
+
+    // Place the object ID labels next to the current positions.
+    state.objects.forEach(function (obj) {
+        var p = obj.trail[obj.trail.length - 1];
+        var clip = [0, 1, 3].map(function (r) {
+            return mvp[r] * p[0] + mvp[4 + r] * p[1] + mvp[8 + r] * p[2] + mvp[12 + r];
+        });
+        if (clip[2] <= 0) {
+            obj.label.style.display = "none";
+            return;
+        }
+        obj.label.style.display = "block";
+        obj.label.style.left = ((clip[0] / clip[2] + 1) / 2 * canvas.clientWidth + 6) + "px";
+        obj.label.style.top = ((1 - clip[1] / clip[2]) / 2 * canvas.clientHeight - 6) + "px";
+    });
+};
+
+function setup_mouse(canvas) {
+    var last = null;
+    canvas.addEventListener("mousedown", function (e) {
+        last = [e.clientX, e.clientY];
+    });
+    window.addEventListener("mouseup", function () {
+        last = null;
+    });
+    window.addEventListener("mousemove", function (e) {
+        if (last === null) {
+            return;
+        }
+        state.azimuth -= 0.01 * (e.clientX - last[0]);
+        state.elevation += 0.01 * (e.clientY - last[1]);
+        var max_el = Math.PI / 2 - 0.01;
+        state.elevation = Math.max(-max_el, Math.min(max_el, state.elevation));
+        last = [e.clientX, e.clientY];
+    });
+    canvas.addEventListener("wheel", function (e) {
+        e.preventDefault();
+        state.distance *= Math.exp(0.001 * e.deltaY);
+    });
+}
+
+// Connection -----------------------------------------------------------------
+
+function update_conn_state(ready_state) {
+    var names = ["connecting", "open", "closed"];
+    document.getElementById("conn_state").textContent = names[ready_state];
+}
+
+function start() {
+    var canvas = document.getElementById("canvas");
+    var renderer = new Renderer(canvas);
+    setup_mouse(canvas);
+
+    var source = new EventSource("events");
+    update_conn_state(source.readyState);
+    source.addEventListener("braid", function (e) {
+        handle_message(JSON.parse(e.data));
+    }, false);
+    source.addEventListener("open", function () {
+        update_conn_state(source.readyState);
+    }, false);
+    source.addEventListener("error", function () {
+        update_conn_state(source.readyState);
+    }, false);
+
+    function frame() {
+        renderer.render();
+        window.requestAnimationFrame(frame);
+    }
+    window.requestAnimationFrame(frame);
+}
+
+start();
@@ -268,6 +268,7 @@ where
             original_recording_time: Some(cfg.created_at),
             save_empty_data2d: false, // We do filtering below, but is this correct?
             saving_program_name: env!("CARGO_PKG_NAME").to_string(),
+            tracking_2d: true,
         };
         let metadata_buf = serde_yaml::to_string(&metadata)?;
 
@@ -467,6 +468,7 @@ fn convert_row(
         mean_val: f64::NAN,
         slope,
         sumsqf_val: f64::NAN,
+        mean_intensity: f64::NAN,
         timestamp: None, //flydra_types::FlydraFloatTimestampLocal::from_dt(&dt),
         x: strand_cam_row.x_px,
         y: strand_cam_row.y_px,
@@ -0,0 +1,336 @@
+//! Discovery of the intrinsic calibration of each camera in a directory of
+//! YAML files.
+//!
+//! The YAML files are typically saved by `strand-cam-offline-checkerboards`
+//! or the ROS camera calibrator and are named after the camera, but the names
+//! do not always match the camera names in Braid exactly. A file is matched to
+//! a camera if, ignoring case and treating `-`, `.` and spaces like `_`, its
+//! name without extension (or the `camera_name` saved in it) is the camera name
+//! or starts with the camera name followed by `_`, as in
+//! `Basler_40022057_intrinsics.yaml`. A file whose name is exactly the camera
+//! name is preferred over such files with a suffix.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use eyre::{Context, Result};
+use opencv_ros_camera::RosCameraInfo;
+
+/// The result of looking for the intrinsic calibration of a camera.
+pub(crate) enum CamIntrinsics {
+    /// A calibration was found and its image size matches the camera.
+    Matched {
+        path: PathBuf,
+        intrinsics: RosCameraInfo<f64>,
+    },
+    /// A calibration was found but its image size differs from the camera.
+    Mismatched {
+        path: PathBuf,
+        yaml_size: (usize, usize),
+    },
+    /// The matching file could not be read.
+    Invalid { path: PathBuf, error: String },
+    /// More than one file matches the camera equally well.
+    Ambiguous { paths: Vec<PathBuf> },
+    /// No file matches the camera.
+    Unmatched,
+}
+
+/// The intrinsic calibrations found for all cameras.
+pub(crate) struct DiscoveryReport {
+    dir: PathBuf,
+    /// For each camera, its name, its image size and the calibration found.
+    cams: Vec<(String, (usize, usize), CamIntrinsics)>,
+    /// YAML files which were not matched to any camera.
+    unused: Vec<PathBuf>,
+}
+
+impl DiscoveryReport {
+    /// Return whether the intrinsics of every camera were found.
+    pub(crate) fn all_matched(&self) -> bool {
+        self.cams
+            .iter()
+            .all(|(_, _, found)| matches!(found, CamIntrinsics::Matched { .. }))
+    }
+
+    /// The intrinsics of each camera, in the order in which the cameras were
+    /// given, if all were found.
+    pub(crate) fn into_intrinsics(self) -> Option<Vec<RosCameraInfo<f64>>> {
+        self.cams
+            .into_iter()
+            .map(|(_, _, found)| match found {
+                CamIntrinsics::Matched { intrinsics, .. } => Some(intrinsics),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for DiscoveryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Intrinsic calibrations in {}:", self.dir.display())?;
+        for (name, (w, h), found) in self.cams.iter() {
+            match found {
+                CamIntrinsics::Matched { path, .. } => {
+                    writeln!(f, " {name}: matched {}", path.display())?;
+                }
+                CamIntrinsics::Mismatched {
+                    path,
+                    yaml_size: (yw, yh),
+                } => {
+                    writeln!(
+                        f,
+                        " {name}: MISMATCHED {} is for {yw}x{yh} images but camera images are {w}x{h}",
+                        path.display()
+                    )?;
+                }
+                CamIntrinsics::Invalid { path, error } => {
+                    writeln!(f, " {name}: INVALID {}: {error}", path.display())?;
+                }
+                CamIntrinsics::Ambiguous { paths } => {
+                    let paths: Vec<String> =
+                        paths.iter().map(|p| p.display().to_string()).collect();
+                    writeln!(f, " {name}: AMBIGUOUS, matches {}", paths.join(", "))?;
+                }
+                CamIntrinsics::Unmatched => {
+                    writeln!(f, " {name}: UNMATCHED, no YAML file found")?;
+                }
+            }
+        }
+        for path in self.unused.iter() {
+            writeln!(f, " (unused: {})", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Normalize a name for comparison.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '-' | '.' | ' ' => '_',
+            c => c.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+/// How well `candidate` matches camera name `cam` (both normalized). Lower is
+/// better.
+fn match_quality(cam: &str, candidate: &str) -> Option<u8> {
+    if candidate == cam {
+        Some(0)
+    } else if candidate
+        .strip_prefix(cam)
+        .is_some_and(|rest| rest.starts_with('_'))
+    {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+fn read_intrinsics(path: &Path) -> std::result::Result<RosCameraInfo<f64>, String> {
+    let buf = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_yaml::from_str(&buf).map_err(|e| e.to_string())
+}
+
+struct YamlFile {
+    path: PathBuf,
+    /// Normalized names by which the file can be matched.
+    names: Vec<String>,
+}
+
+impl YamlFile {
+    fn new(path: PathBuf) -> Self {
+        let mut names = vec![];
+        if let Some(stem) = path.file_stem() {
+            names.push(normalize(&stem.to_string_lossy()));
+        }
+        if let Ok(intrinsics) = read_intrinsics(&path) {
+            names.push(normalize(&intrinsics.camera_name));
+        }
+        Self { path, names }
+    }
+}
+
+/// Find the intrinsic calibration of each camera in `dir`.
+///
+/// `cams` are the names and image sizes (width, height) of the cameras.
+pub(crate) fn discover_intrinsics(
+    dir: &Path,
+    cams: &[(String, (usize, usize))],
+) -> Result<DiscoveryReport> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("while reading {}", dir.display()))?;
+    let mut files = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        let is_yaml = path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"))
+            .unwrap_or(false);
+        if is_yaml && path.is_file() {
+            files.push(YamlFile::new(path));
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut used = vec![false; files.len()];
+    let mut result = vec![];
+    for (name, size) in cams.iter() {
+        let cam = normalize(name);
+        let qualities: Vec<Option<u8>> = files
+            .iter()
+            .map(|file| {
+                file.names
+                    .iter()
+                    .filter_map(|candidate| match_quality(&cam, candidate))
+                    .min()
+            })
+            .collect();
+        let best_idx: Vec<usize> = match qualities.iter().flatten().min() {
+            Some(best) => (0..files.len())
+                .filter(|i| qualities[*i] == Some(*best))
+                .collect(),
+            None => vec![],
+        };
+
+        let found = match best_idx.as_slice() {
+            [] => CamIntrinsics::Unmatched,
+            [idx] => {
+                used[*idx] = true;
+                let path = files[*idx].path.clone();
+                match read_intrinsics(&path) {
+                    Ok(intrinsics) => {
+                        let yaml_size = (intrinsics.image_width, intrinsics.image_height);
+                        if yaml_size == *size {
+                            CamIntrinsics::Matched { path, intrinsics }
+                        } else {
+                            CamIntrinsics::Mismatched { path, yaml_size }
+                        }
+                    }
+                    Err(error) => CamIntrinsics::Invalid { path, error },
+                }
+            }
+            _ => {
+                for idx in best_idx.iter() {
+                    used[*idx] = true;
+                }
+                CamIntrinsics::Ambiguous {
+                    paths: best_idx.iter().map(|i| files[*i].path.clone()).collect(),
+                }
+            }
+        };
+        result.push((name.clone(), *size, found));
+    }
+
+    let unused = files
+        .into_iter()
+        .zip(used)
+        .filter(|(_, used)| !used)
+        .map(|(file, _)| file.path)
+        .collect();
+
+    Ok(DiscoveryReport {
+        dir: dir.to_path_buf(),
+        cams: result,
+        unused,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CAMERA_YAML: &str = "image_width: 640
+image_height: 480
+camera_name: NAME
+camera_matrix:
+  rows: 3
+  cols: 3
+  data: [500, 0, 320, 0, 500, 240, 0, 0, 1]
+distortion_model: plumb_bob
+distortion_coefficients:
+  rows: 1
+  cols: 5
+  data: [0, 0, 0, 0, 0]
+rectification_matrix:
+  rows: 3
+  cols: 3
+  data: [1, 0, 0, 0, 1, 0, 0, 0, 1]
+projection_matrix:
+  rows: 3
+  cols: 4
+  data: [500, 0, 320, 0, 0, 500, 240, 0, 0, 0, 1, 0]
+";
+
+    fn write_yaml(dir: &Path, fname: &str, camera_name: &str) {
+        std::fs::write(dir.join(fname), CAMERA_YAML.replace("NAME", camera_name)).unwrap();
+    }
+
+    #[test]
+    fn test_discover_intrinsics() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let dir = tmpdir.path();
+        write_yaml(dir, "basler_1.yaml", "basler_1");
+        write_yaml(dir, "Basler-2_intrinsics.yml", "cam");
+        write_yaml(dir, "Basler_3.yaml", "Basler_3");
+        write_yaml(dir, "calib.yaml", "Basler_4_intrinsics");
+        write_yaml(dir, "Basler_5_a.yaml", "x");
+        write_yaml(dir, "Basler_5_b.yaml", "y");
+        write_yaml(dir, "other.yaml", "other");
+        std::fs::write(dir.join("Basler_7.yaml"), "not a calibration")?;
+
+        let cams = [
+            ("Basler_1".to_string(), (640, 480)),
+            ("Basler_2".to_string(), (640, 480)),
+            ("Basler_3".to_string(), (1280, 960)),
+            ("Basler_4".to_string(), (640, 480)),
+            ("Basler_5".to_string(), (640, 480)),
+            ("Basler_6".to_string(), (640, 480)),
+            ("Basler_7".to_string(), (640, 480)),
+        ];
+        let report = discover_intrinsics(dir, &cams)?;
+        assert!(!report.all_matched());
+        let found: Vec<&CamIntrinsics> = report.cams.iter().map(|(_, _, found)| found).collect();
+        assert!(matches!(found[0], CamIntrinsics::Matched { .. }));
+        assert!(matches!(found[1], CamIntrinsics::Matched { .. }));
+        assert!(matches!(
+            found[2],
+            CamIntrinsics::Mismatched {
+                yaml_size: (640, 480),
+                ..
+            }
+        ));
+        assert!(matches!(found[3], CamIntrinsics::Matched { .. }));
+        assert!(matches!(found[4], CamIntrinsics::Ambiguous { paths } if paths.len() == 2));
+        assert!(matches!(found[5], CamIntrinsics::Unmatched));
+        assert!(matches!(found[6], CamIntrinsics::Invalid { .. }));
+        assert_eq!(report.unused, vec![dir.join("other.yaml")]);
+        assert!(report.into_intrinsics().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_exact_name_preferred() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let dir = tmpdir.path();
+        write_yaml(dir, "cam1.yaml", "cam1");
+        write_yaml(dir, "cam1_old.yaml", "cam1_old");
+        write_yaml(dir, "cam10.yaml", "cam10");
+
+        let cams = [
+            ("cam1".to_string(), (640, 480)),
+            ("cam10".to_string(), (640, 480)),
+        ];
+        let report = discover_intrinsics(dir, &cams)?;
+        assert!(report.all_matched());
+        assert_eq!(report.unused, vec![dir.join("cam1_old.yaml")]);
+        let intrinsics = report.into_intrinsics().unwrap();
+        assert_eq!(intrinsics[0].camera_name, "cam1");
+        assert_eq!(intrinsics[1].camera_name, "cam10");
+        Ok(())
+    }
+}
@@ -11,6 +11,9 @@ use std::{
 use flydra_mvg::FlydraMultiCameraSystem;
 use mcsc_structs::{DatMat, McscCfg, McscConfigDir, RadFile};
 
+mod calibration_discovery;
+mod wand;
+
 #[derive(Parser, Default)]
 struct Cli {
     /// Input braidz filename.
@@ -20,6 +23,10 @@ struct Cli {
     /// Input directory to be searched for YAML calibration files from
     /// checkerboard calibration. (Typically
     /// "~/.config/strand-cam/camera_info").
+    ///
+    /// Files are matched to cameras by name, ignoring case and allowing
+    /// suffixes such as "_intrinsics". A report of the matched files is
+    /// printed.
     #[arg(long)]
     checkerboard_cal_dir: Option<PathBuf>,
 
@@ -33,6 +40,24 @@ struct Cli {
     /// If set, keep the intermediate MCSC calibration directory.
     #[arg(long)]
     keep: bool,
+
+    /// Input braidz filename with observations of a two-point wand.
+    ///
+    /// If given, the calibration is scaled such that the distance between the
+    /// two wand ends is `--wand-length`. Only frames in which a camera
+    /// detected exactly two points are used.
+    #[arg(long, requires = "wand_length")]
+    wand_input: Option<PathBuf>,
+
+    /// Distance between the two wand ends, in meters.
+    #[arg(long, requires = "wand_input")]
+    wand_length: Option<f64>,
+
+    /// Ignore wand observations with a mean reprojection distance larger than
+    /// this (in pixels). Views of a single camera farther than this from the
+    /// triangulated wand are ignored as outliers.
+    #[arg(long, default_value_t = 2.0)]
+    wand_max_reproj_dist: f64,
 }
 
 fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
@@ -55,11 +80,19 @@ fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()>
 fn main() -> Result<()> {
     env_tracing_logger::init();
     let opt = Cli::parse();
+    let scaled = opt.wand_input.is_some();
     let xml_out_name = braiz_mcsc(opt)?;
-    println!(
-        "Unaligned calibration XML saved to {}",
-        xml_out_name.display()
-    );
+    if scaled {
+        println!(
+            "Wand-scaled (but unaligned) calibration XML saved to {}",
+            xml_out_name.display()
+        );
+    } else {
+        println!(
+            "Unaligned calibration XML saved to {}",
+            xml_out_name.display()
+        );
+    }
     Ok(())
 }
 
@@ -122,31 +155,27 @@ fn braiz_mcsc(opt: Cli) -> Result<PathBuf> {
         if opt.force_allow_no_checkerboard_cal {
             eyre::bail!("--checkerboard-cal-dir was specified but --force-allow-no-checkerboard-cal is set.");
         }
-        let mut radfiles = vec![];
-
-        for cam_id in camid2camn_df["cam_id"].str()?.iter() {
-            let cam_id = cam_id.unwrap();
-            let yaml_intrinsics_fname = checkerboard_cal_dir.join(&format!("{cam_id}.yaml"));
-            let yaml_buf = std::fs::read_to_string(&yaml_intrinsics_fname)
-                .with_context(|| format!("while reading {}", yaml_intrinsics_fname.display()))?;
-
-            let intrinsics: opencv_ros_camera::RosCameraInfo<f64> = serde_yaml::from_str(&yaml_buf)
-                .with_context(|| format!("while parsing {}", yaml_intrinsics_fname.display()))?;
-
-            radfiles.push(RadFile::new(&intrinsics)?);
-
-            let im = &images[cam_id];
-            let w: usize = im.width().try_into().unwrap();
-            let h: usize = im.height().try_into().unwrap();
-            if intrinsics.image_width != w {
-                eyre::bail!("PNG image resolution does not match YAML file.")
-            }
-            if intrinsics.image_height != h {
-                eyre::bail!("PNG image resolution does not match YAML file.")
-            }
+        let cams: Vec<(String, (usize, usize))> = camera_order
+            .iter()
+            .map(|cam_id| {
+                let im = &images[cam_id.as_str()];
+                (cam_id.clone(), (im.width() as usize, im.height() as usize))
+            })
+            .collect();
+        let report = calibration_discovery::discover_intrinsics(checkerboard_cal_dir, &cams)?;
+        print!("{report}");
+        if !report.all_matched() {
+            eyre::bail!(
+                "Intrinsic calibrations in {} do not match all cameras. See report above.",
+                checkerboard_cal_dir.display()
+            );
         }
-
-        radfiles
+        report
+            .into_intrinsics()
+            .unwrap()
+            .iter()
+            .map(RadFile::new)
+            .collect::<Result<Vec<_>>>()?
     } else {
         if opt.force_allow_no_checkerboard_cal {
             vec![]
@@ -188,6 +217,7 @@ fn braiz_mcsc(opt: Cli) -> Result<PathBuf> {
             "cur_val",
             "mean_val",
             "sumsqf_val",
+            "mean_intensity",
         ];
 
         for colname in drop_columns {
@@ -367,6 +397,28 @@ fn braiz_mcsc(opt: Cli) -> Result<PathBuf> {
     let calibration = FlydraMultiCameraSystem::<f64>::from_path(&resultdir)
         .with_context(|| format!("while reading calibration at {}", resultdir.display()))?;
 
+    let calibration = if let (Some(wand_input), Some(wand_length)) =
+        (&opt.wand_input, opt.wand_length)
+    {
+        let observations = wand::load_wand_observations(wand_input, &calibration)?;
+        println!("{} wand observations", observations.len());
+        let (scaled, wand_scale) = calibration.system().scale_to_wand(
+            &observations,
+            wand_length,
+            Some(opt.wand_max_reproj_dist),
+        )?;
+        println!(
+            "Used {} wand observations. Median wand length {:.5} (relative MAD {:.4}), scale factor {:.5}.",
+            wand_scale.n_observations,
+            wand_scale.median_length,
+            wand_scale.relative_mad,
+            wand_scale.scale
+        );
+        FlydraMultiCameraSystem::from_system(scaled, calibration.water())
+    } else {
+        calibration
+    };
+
     let mut out_fd = std::fs::File::create_new(&xml_out_name).with_context(|| {
         format!(
             "While creating XML calibration output file {}",
@@ -0,0 +1,91 @@
+use eyre::{self, Context, Result};
+use polars::prelude::*;
+use std::{collections::BTreeMap, io::Read, path::Path};
+
+use flydra_mvg::FlydraMultiCameraSystem;
+use mvg::wand::{WandObservation, WandView};
+
+/// Read a CSV file from a braidz archive into a dataframe.
+fn read_csv_df<R: Read + std::io::Seek>(
+    archive: &mut zip_or_dir::ZipDirArchive<R>,
+    fname: &str,
+) -> Result<DataFrame> {
+    let cursor = {
+        let data_fname = archive.path_starter().join(fname);
+        let mut rdr = braidz_parser::open_maybe_gzipped(data_fname)?;
+        let mut buf = Vec::new();
+        rdr.read_to_end(&mut buf)?;
+        std::io::Cursor::new(buf)
+    };
+
+    Ok(polars_io::csv::read::CsvReadOptions::default()
+        .with_has_header(true)
+        .into_reader_with_file_handle(cursor)
+        .finish()?)
+}
+
+/// Load observations of a two-point wand from a braidz file.
+///
+/// A camera contributes to a frame only if it detected exactly two points in
+/// that frame. Frames seen in this way by fewer than two cameras are skipped.
+pub(crate) fn load_wand_observations(
+    wand_braidz: &Path,
+    calibration: &FlydraMultiCameraSystem<f64>,
+) -> Result<Vec<WandObservation<f64>>> {
+    let mut archive = zip_or_dir::ZipDirArchive::auto_from_path(wand_braidz)
+        .with_context(|| format!("Parsing file {}", wand_braidz.display()))?;
+
+    let cam_info_df = read_csv_df(&mut archive, flydra_types::CAM_INFO_CSV_FNAME)?;
+    let mut camn2cam_id = BTreeMap::new();
+    for (camn, cam_id) in cam_info_df["camn"]
+        .i64()?
+        .iter()
+        .zip(cam_info_df["cam_id"].str()?.iter())
+    {
+        let (camn, cam_id) = (camn.unwrap(), cam_id.unwrap());
+        if calibration.cam_by_name(cam_id).is_none() {
+            eyre::bail!("Camera \"{cam_id}\" in wand data is not in calibration.");
+        }
+        camn2cam_id.insert(camn, cam_id.to_string());
+    }
+
+    let data2d_df = read_csv_df(&mut archive, flydra_types::DATA2D_DISTORTED_CSV_FNAME)?;
+    let cond = data2d_df["x"].is_not_nan()?;
+    let data2d_df = data2d_df.filter(&cond)?;
+
+    let mut observations = vec![];
+    for gdf in data2d_df.partition_by_stable(["frame"], true)?.iter() {
+        let mut by_camn: BTreeMap<i64, Vec<(f64, f64)>> = BTreeMap::new();
+        for ((camn, x), y) in gdf["camn"]
+            .i64()?
+            .iter()
+            .zip(gdf["x"].f64()?.iter())
+            .zip(gdf["y"].f64()?.iter())
+        {
+            by_camn
+                .entry(camn.unwrap())
+                .or_default()
+                .push((x.unwrap(), y.unwrap()));
+        }
+
+        let views: Vec<WandView<f64>> = by_camn
+            .into_iter()
+            .filter(|(_camn, pts)| pts.len() == 2)
+            .map(|(camn, pts)| {
+                let cam_name = camn2cam_id[&camn].clone();
+                let cam = calibration.cam_by_name(&cam_name).unwrap();
+                let ends = [pts[0], pts[1]].map(|(x, y)| {
+                    cam.undistort(&mvg::DistortedPixel {
+                        coords: nalgebra::Point2::new(x, y),
+                    })
+                });
+                WandView { cam_name, ends }
+            })
+            .collect();
+
+        if views.len() >= 2 {
+            observations.push(WandObservation { views });
+        }
+    }
+    Ok(observations)
+}
@@ -0,0 +1,203 @@
+//! ChArUco-style calibration boards with AprilTag markers.
+//!
+//! A ChArUco board is a chessboard in which each white square contains a
+//! fiducial marker. Because each marker identifies its square, corners adjacent
+//! to any detected marker can be located even when the board is only partially
+//! visible. Here, the markers are AprilTags, so the existing AprilTag detector
+//! can be used to find them.
+//!
+//! The board layout follows the OpenCV convention: the top-left square is
+//! black and the markers are numbered sequentially, in row-major order, in the
+//! white squares starting with `first_marker_id`. Boards generated by OpenCV
+//! using the `DICT_APRILTAG_36h11` dictionary can be used.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{CheckerBoardData, Coords2D};
+
+/// Description of a ChArUco board.
+///
+/// `n_rows` and `n_cols` are the number of *inner corners*, as for plain
+/// chessboards. The board therefore has `n_rows + 1` rows and `n_cols + 1`
+/// columns of squares.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CharucoBoard {
+    pub n_rows: usize,
+    pub n_cols: usize,
+    /// Side length of the marker divided by side length of the square.
+    pub marker_to_square_ratio: f64,
+    /// The id of the marker in the first white square.
+    pub first_marker_id: i32,
+}
+
+/// A single detected marker.
+#[derive(Debug, Clone)]
+pub struct MarkerDetection {
+    pub id: i32,
+    /// Homography (row-major) from ideal marker coordinates, in which the
+    /// marker spans -1 to 1 in both axes, to image pixels.
+    pub h: [f64; 9],
+}
+
+impl CharucoBoard {
+    fn n_squares_x(&self) -> usize {
+        self.n_cols + 1
+    }
+
+    fn n_squares_y(&self) -> usize {
+        self.n_rows + 1
+    }
+
+    /// Return the (column, row) of the square containing marker `id`.
+    pub fn marker_square(&self, id: i32) -> Option<(usize, usize)> {
+        let idx: usize = id.checked_sub(self.first_marker_id)?.try_into().ok()?;
+        let nx = self.n_squares_x();
+        let mut count = 0;
+        for row in 0..self.n_squares_y() {
+            // White squares are those with (row + col) odd.
+            let first_col = if row % 2 == 0 { 1 } else { 0 };
+            let n_in_row = (nx - first_col).div_ceil(2);
+            if idx < count + n_in_row {
+                let col = first_col + 2 * (idx - count);
+                return Some((col, row));
+            }
+            count += n_in_row;
+        }
+        None
+    }
+
+    /// Locate the board corners adjacent to the detected markers.
+    ///
+    /// Returns the corner index (in row-major order) and image position of
+    /// each located corner. When a corner is adjacent to two detected markers,
+    /// the mean of both position estimates is used. Markers with ids not
+    /// belonging to this board are ignored.
+    pub fn find_corners(&self, markers: &[MarkerDetection]) -> Vec<(usize, Coords2D)> {
+        let half_marker = self.marker_to_square_ratio / 2.0;
+        let mut sums = vec![(0.0, 0.0, 0u8); self.n_rows * self.n_cols];
+
+        for marker in markers.iter() {
+            let (sq_col, sq_row) = match self.marker_square(marker.id) {
+                Some(sq) => sq,
+                None => continue,
+            };
+            let center_x = sq_col as f64 + 0.5;
+            let center_y = sq_row as f64 + 0.5;
+
+            // The four corners of this square, in board units where the board
+            // origin is at the top-left corner of the board and each square
+            // has size 1. Inner corner (c, r) is at board position (c+1, r+1).
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let bx = sq_col + dx;
+                let by = sq_row + dy;
+                if bx == 0 || by == 0 || bx > self.n_cols || by > self.n_rows {
+                    // Outer edge of the board, not an inner corner.
+                    continue;
+                }
+                let u = (bx as f64 - center_x) / half_marker;
+                let v = (by as f64 - center_y) / half_marker;
+                let h = &marker.h;
+                let w = h[6] * u + h[7] * v + h[8];
+                let x = (h[0] * u + h[1] * v + h[2]) / w;
+                let y = (h[3] * u + h[4] * v + h[5]) / w;
+
+                let entry = &mut sums[(by - 1) * self.n_cols + (bx - 1)];
+                entry.0 += x;
+                entry.1 += y;
+                entry.2 += 1;
+            }
+        }
+
+        sums.into_iter()
+            .enumerate()
+            .filter(|(_, (_, _, n))| *n > 0)
+            .map(|(idx, (x, y, n))| (idx, (x / n as f64, y / n as f64)))
+            .collect()
+    }
+
+    /// Locate corners and return them as checkerboard data for calibration.
+    ///
+    /// Returns `None` if fewer than `min_corners` corners were found.
+    pub fn to_checkerboard_data(
+        &self,
+        markers: &[MarkerDetection],
+        min_corners: usize,
+    ) -> Option<CheckerBoardData> {
+        let corners = self.find_corners(markers);
+        if corners.len() < min_corners {
+            return None;
+        }
+        Some(CheckerBoardData::new_partial(
+            self.n_rows,
+            self.n_cols,
+            &corners,
+        ))
+    }
+}
+
+#[test]
+fn test_marker_square() {
+    let board = CharucoBoard {
+        n_rows: 3,
+        n_cols: 4,
+        marker_to_square_ratio: 0.75,
+        first_marker_id: 10,
+    };
+    // 5x4 squares. Row 0 has white squares at columns 1 and 3, row 1 at
+    // columns 0, 2, and 4.
+    assert_eq!(board.marker_square(9), None);
+    assert_eq!(board.marker_square(10), Some((1, 0)));
+    assert_eq!(board.marker_square(11), Some((3, 0)));
+    assert_eq!(board.marker_square(12), Some((0, 1)));
+    assert_eq!(board.marker_square(14), Some((4, 1)));
+    assert_eq!(board.marker_square(19), Some((4, 3)));
+    assert_eq!(board.marker_square(20), None);
+}
+
+#[test]
+fn test_find_corners() {
+    let board = CharucoBoard {
+        n_rows: 3,
+        n_cols: 4,
+        marker_to_square_ratio: 0.75,
+        first_marker_id: 0,
+    };
+    // Board imaged with 50 pixels per square at offset (100, 200).
+    let (scale, ox, oy) = (50.0, 100.0, 200.0);
+    let marker = |id: i32| {
+        let (col, row) = board.marker_square(id).unwrap();
+        let s = scale * board.marker_to_square_ratio / 2.0;
+        let h = [
+            s,
+            0.0,
+            ox + scale * (col as f64 + 0.5),
+            0.0,
+            s,
+            oy + scale * (row as f64 + 0.5),
+            0.0,
+            0.0,
+            1.0,
+        ];
+        MarkerDetection { id, h }
+    };
+
+    // All markers visible: all corners found.
+    let all: Vec<_> = (0..10).map(marker).collect();
+    let corners = board.find_corners(&all);
+    assert_eq!(corners.len(), 12);
+    for (idx, (x, y)) in corners.iter() {
+        let c = idx % board.n_cols;
+        let r = idx / board.n_cols;
+        approx::assert_relative_eq!(*x, ox + scale * (c as f64 + 1.0), epsilon = 1e-9);
+        approx::assert_relative_eq!(*y, oy + scale * (r as f64 + 1.0), epsilon = 1e-9);
+    }
+
+    // A single marker in the interior gives its four surrounding corners.
+    let corners = board.find_corners(&[marker(6)]);
+    let ids: Vec<usize> = corners.iter().map(|(idx, _)| *idx).collect();
+    // Marker 6 is in square (col 3, row 2).
+    assert_eq!(ids, vec![6, 7, 10, 11]);
+
+    assert!(board.to_checkerboard_data(&[marker(6)], 6).is_none());
+    assert!(board.to_checkerboard_data(&all, 6).is_some());
+}
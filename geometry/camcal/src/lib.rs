@@ -4,11 +4,17 @@ use serde::{Deserialize, Serialize};
 type Coords3D = (f64, f64, f64);
 type Coords2D = (f64, f64);
 
-#[derive(Serialize, Deserialize)]
+pub mod charuco;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CheckerBoardData {
     n_rows: usize,
     n_cols: usize,
     points: Vec<Coords2D>,
+    /// For partially visible boards, the index (in row-major order) of each
+    /// corner in `points`. If `None`, all corners are present in order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    corner_ids: Option<Vec<usize>>,
 }
 
 impl CheckerBoardData {
@@ -18,8 +24,36 @@ impl CheckerBoardData {
             n_rows,
             n_cols,
             points,
+            corner_ids: None,
+        }
+    }
+
+    /// Create data for a partially visible board.
+    ///
+    /// Each corner is given with its index in row-major order.
+    pub fn new_partial(n_rows: usize, n_cols: usize, corners: &[(usize, Coords2D)]) -> Self {
+        let (corner_ids, points) = corners.iter().copied().unzip();
+        Self {
+            n_rows,
+            n_cols,
+            points,
+            corner_ids: Some(corner_ids),
         }
     }
+
+    /// The image coordinates of the detected corners.
+    pub fn points(&self) -> &[Coords2D] {
+        &self.points
+    }
+
+    /// The number of detected corners.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
 }
 
 fn to_image_points(board: &CheckerBoardData) -> Vec<Coords2D> {
@@ -144,6 +178,81 @@ pub fn compute_intrinsics<R: RealField>(
     Ok(convert_to_cam_geom(&opencv_results))
 }
 
+/// Compute the reprojection distance of each checkerboard corner.
+///
+/// The corners are projected into the image with the calibrated camera, i.e.
+/// the intrinsic parameters and the pose of each board estimated in the
+/// calibration. `data` must be the boards with which `opencv_results` was
+/// computed. The returned vector has one entry per board, each with the
+/// distances (in pixels) of all corners on that board.
+pub fn reprojection_residuals(
+    opencv_results: &opencv_calibrate::CalibrationResult,
+    data: &[CheckerBoardData],
+) -> Vec<Vec<f64>> {
+    assert_eq!(opencv_results.rotation_matrices.len(), data.len());
+    let k = &opencv_results.camera_matrix;
+    let (fx, skew, cx, fy, cy) = (k[0], k[1], k[2], k[4], k[5]);
+    let [k1, k2, p1, p2, k3] = opencv_results.distortion_coeffs;
+
+    let object_points = mk_object_points(data);
+    object_points
+        .iter()
+        .zip(data.iter())
+        .zip(
+            opencv_results
+                .rotation_matrices
+                .iter()
+                .zip(opencv_results.translation_vectors.iter()),
+        )
+        .map(|((obj_pts, board), (rmat, tvec))| {
+            let rot = nalgebra::Matrix3::from_row_slice(rmat);
+            let t = nalgebra::Vector3::from(*tvec);
+            obj_pts
+                .iter()
+                .zip(board.points.iter())
+                .map(|(&(ox, oy, oz), &(im_x, im_y))| {
+                    let pc = rot * nalgebra::Vector3::new(ox, oy, oz) + t;
+                    let x = pc.x / pc.z;
+                    let y = pc.y / pc.z;
+
+                    // Apply the OpenCV distortion model.
+                    let r2 = x * x + y * y;
+                    let radial = 1.0 + k1 * r2 + k2 * r2 * r2 + k3 * r2 * r2 * r2;
+                    let xd = x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x);
+                    let yd = y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y;
+
+                    let u = fx * xd + skew * yd + cx;
+                    let v = fy * yd + cy;
+                    ((u - im_x).powi(2) + (v - im_y).powi(2)).sqrt()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Count the number of points falling into each cell of a grid over the image.
+///
+/// The result is in row-major order with `n_rows * n_cols` entries.
+pub fn coverage_counts<I>(size: &PixelSize, n_cols: usize, n_rows: usize, points: I) -> Vec<u32>
+where
+    I: IntoIterator<Item = Coords2D>,
+{
+    let mut counts = vec![0; n_rows * n_cols];
+    let cell_w = size.width as f64 / n_cols as f64;
+    let cell_h = size.height as f64 / n_rows as f64;
+    for (x, y) in points.into_iter() {
+        if x < 0.0 || y < 0.0 {
+            continue;
+        }
+        let col = (x / cell_w) as usize;
+        let row = (y / cell_h) as usize;
+        if col < n_cols && row < n_rows {
+            counts[row * n_cols + col] += 1;
+        }
+    }
+    counts
+}
+
 fn mk_object_points(data: &[CheckerBoardData]) -> Vec<Vec<Coords3D>> {
     /*
 
@@ -168,8 +277,12 @@ fn mk_object_points(data: &[CheckerBoardData]) -> Vec<Vec<Coords3D>> {
     let mut result = Vec::with_capacity(data.len());
     for b in data.iter() {
         let num_pts = b.n_cols * b.n_rows;
-        let mut opts_loc: Vec<Coords3D> = Vec::with_capacity(num_pts);
-        for j in 0..num_pts {
+        let corner_ids: Vec<usize> = match &b.corner_ids {
+            Some(ids) => ids.clone(),
+            None => (0..num_pts).collect(),
+        };
+        let mut opts_loc: Vec<Coords3D> = Vec::with_capacity(corner_ids.len());
+        for j in corner_ids {
             let x = (j as f64 / b.n_cols as f64).trunc();
             let y = j as f64 % b.n_cols as f64;
             let z = 0.0;
@@ -179,3 +292,39 @@ fn mk_object_points(data: &[CheckerBoardData]) -> Vec<Vec<Coords3D>> {
     }
     result
 }
+
+#[test]
+fn test_reprojection_residuals() {
+    // A camera 10 units in front of a 2x3 board, without distortion. The
+    // corners project to `(100 * row + 320, 100 * col + 240)`.
+    let cal = opencv_calibrate::CalibrationResult {
+        mean_reprojection_distance_pixels: 0.0,
+        camera_matrix: [1000.0, 0.0, 320.0, 0.0, 1000.0, 240.0, 0.0, 0.0, 1.0],
+        distortion_coeffs: [0.0; 5],
+        rotation_matrices: vec![[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]],
+        translation_vectors: vec![[0.0, 0.0, 10.0]],
+        image_width: 640,
+        image_height: 480,
+    };
+    let board = CheckerBoardData::new(
+        2,
+        3,
+        &[
+            (320.0, 240.0),
+            (320.0, 340.0),
+            // 3 pixels right and 4 pixels down
+            (323.0, 444.0),
+            (420.0, 240.0),
+            (420.0, 340.0),
+            // 1 pixel left
+            (419.0, 440.0),
+        ],
+    );
+    let residuals = reprojection_residuals(&cal, &[board]);
+    assert_eq!(residuals.len(), 1);
+    let expected = [0.0, 0.0, 5.0, 0.0, 0.0, 1.0];
+    assert_eq!(residuals[0].len(), expected.len());
+    for (actual, expected) in residuals[0].iter().zip(expected.iter()) {
+        approx::assert_abs_diff_eq!(actual, expected, epsilon = 1e-9);
+    }
+}
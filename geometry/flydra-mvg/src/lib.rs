@@ -1,6 +1,7 @@
 use std::collections::BTreeMap;
 use std::io::{Read, Write};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use serde::de::DeserializeOwned;
 
@@ -17,9 +18,10 @@ use cam_geom::ExtrinsicParameters;
 use opencv_ros_camera::{Distortion, RosOpenCvIntrinsics};
 
 use mvg::{
-    rq_decomposition, vec_sum, Camera, DistortedPixel, MultiCameraSystem, MvgError,
-    PointWorldFrame, PointWorldFrameMaybeWithSumReprojError, PointWorldFrameWithSumReprojError,
-    UndistortedPixel, WorldCoordAndUndistorted2D,
+    rq_decomposition, triangulation_uncertainty::TriangulatedPoint, undistortion::UndistortionMap,
+    vec_sum, Camera, DistortedPixel, MultiCameraSystem, MvgError, PointWorldFrame,
+    PointWorldFrameMaybeWithSumReprojError, PointWorldFrameWithSumReprojError, UndistortedPixel,
+    WorldCoordAndUndistorted2D,
 };
 
 mod fermats_least_time;
@@ -206,6 +208,7 @@ pub struct MultiCamera<R: RealField + Copy + Default + serde::Serialize> {
     water: Option<R>,
     name: String,
     cam: Camera<R>,
+    undistortion: Option<Arc<UndistortionMap<R>>>,
 }
 
 impl<R: RealField + Copy + Default + serde::Serialize> MultiCamera<R> {
@@ -368,6 +371,9 @@ impl<R: RealField + Copy + Default + serde::Serialize> MultiCamera<R> {
     }
 
     pub fn undistort(&self, a: &mvg::DistortedPixel<R>) -> mvg::UndistortedPixel<R> {
+        if let Some(undistortion) = &self.undistortion {
+            return undistortion.undistort(a);
+        }
         let a2: cam_geom::Pixels<R, U1, _> = a.into();
         let b1: opencv_ros_camera::UndistortedPixels<R, U1, _> =
             self.cam.intrinsics().undistort(&a2);
@@ -396,11 +402,28 @@ impl<R: RealField + Copy + Default + serde::Serialize> MultiCamera<R> {
 pub struct FlydraMultiCameraSystem<R: RealField + Copy + serde::Serialize> {
     system: MultiCameraSystem<R>,
     water: Option<R>,
+    /// The undistortion of each camera, created with the system so that
+    /// looking up a camera does not need a lock. Shared by all clones.
+    undistortion: Arc<BTreeMap<String, Arc<UndistortionMap<R>>>>,
 }
 
 impl<R: RealField + Copy + Default + serde::Serialize> FlydraMultiCameraSystem<R> {
     pub fn from_system(system: MultiCameraSystem<R>, water: Option<R>) -> Self {
-        FlydraMultiCameraSystem { system, water }
+        // The lookup table for images is only computed when first needed, so
+        // this is cheap.
+        let undistortion = system
+            .cams()
+            .iter()
+            .filter_map(|(name, cam)| {
+                let map = UndistortionMap::new(cam).ok()?;
+                Some((name.clone(), Arc::new(map)))
+            })
+            .collect();
+        FlydraMultiCameraSystem {
+            system,
+            water,
+            undistortion: Arc::new(undistortion),
+        }
     }
 
     pub fn has_refractive_boundary(&self) -> bool {
@@ -422,7 +445,7 @@ impl<R: RealField + Copy + Default + serde::Serialize> FlydraMultiCameraSystem<R
     pub fn new(cams_by_name: BTreeMap<String, Camera<R>>, water: Option<R>) -> Self {
         let system = MultiCameraSystem::new(cams_by_name);
 
-        FlydraMultiCameraSystem { system, water }
+        Self::from_system(system, water)
     }
 
     pub fn len(&self) -> usize {
@@ -438,6 +461,7 @@ impl<R: RealField + Copy + Default + serde::Serialize> FlydraMultiCameraSystem<R
             water: self.water,
             name: name.to_string(),
             cam: cam.clone(),
+            undistortion: self.undistortion.get(name).cloned(),
         })
     }
 
@@ -547,6 +571,22 @@ impl<R: RealField + Copy + Default + serde::Serialize> FlydraMultiCameraSystem<R
         ))
     }
 
+    /// Find 3D coordinate together with its covariance and measures of its
+    /// conditioning.
+    ///
+    /// `pixel_std` is the standard deviation of the noise of the observations,
+    /// in pixels. This is not implemented with a refractive boundary.
+    pub fn find3d_with_uncertainty(
+        &self,
+        points: &[(String, UndistortedPixel<R>)],
+        pixel_std: R,
+    ) -> Result<TriangulatedPoint<R>> {
+        if self.water.is_some() {
+            return Err(FlydraMvgError::NotImplemented);
+        }
+        Ok(self.system.find3d_with_uncertainty(points, pixel_std)?)
+    }
+
     fn find3d_water(
         &self,
         points: &[(String, UndistortedPixel<R>)],
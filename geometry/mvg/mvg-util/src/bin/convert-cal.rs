@@ -0,0 +1,169 @@
+//! Convert camera calibrations between Braid and the file formats of other
+//! tools.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, ValueEnum};
+use eyre::{self as anyhow, Context};
+
+use flydra_mvg::FlydraMultiCameraSystem;
+use mvg::{calibration_formats as formats, Camera, MultiCameraSystem};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Braid (flydra) XML. When reading, pymvg JSON files and MCSC
+    /// directories are also accepted.
+    Braid,
+    /// pymvg JSON
+    Pymvg,
+    /// ROS camera_info YAML (intrinsics only, one camera per file)
+    RosYaml,
+    /// OpenCV FileStorage XML (one camera per file)
+    OpencvXml,
+    /// OpenCV FileStorage JSON (one camera per file)
+    OpencvJson,
+    /// Agisoft Metashape XML (intrinsics only, one camera per file)
+    AgisoftXml,
+    /// Generic JSON with all cameras
+    GenericJson,
+}
+
+impl Format {
+    /// Whether a file in this format stores only a single camera.
+    fn is_single_camera(&self) -> bool {
+        matches!(
+            self,
+            Format::RosYaml | Format::OpencvXml | Format::OpencvJson | Format::AgisoftXml
+        )
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Braid | Format::OpencvXml | Format::AgisoftXml => "xml",
+            Format::Pymvg => "pymvg",
+            Format::RosYaml => "yaml",
+            Format::OpencvJson | Format::GenericJson => "json",
+        }
+    }
+}
+
+/// Convert camera calibrations between file formats.
+///
+/// Inputs in a single-camera format are combined into one system in which
+/// each camera is named after its file (or, for ROS YAML, the saved camera
+/// name). When writing a single-camera format, the output is a directory with
+/// one file per camera.
+#[derive(Debug, Parser)]
+#[command(name = "convert-cal", version)]
+struct Opt {
+    /// Format of the input files
+    #[arg(long, value_enum)]
+    from: Format,
+    /// Format of the output
+    #[arg(long, value_enum)]
+    to: Format,
+    /// Output file, or directory for single-camera formats
+    #[arg(short = 'o', long)]
+    output: PathBuf,
+    /// Input files
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+}
+
+fn file_stem(path: &Path) -> anyhow::Result<String> {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow::anyhow!("no file name in {}", path.display()))
+}
+
+/// Read all cameras in `path`.
+fn read_cams(
+    format: Format,
+    path: &Path,
+) -> anyhow::Result<(Vec<(String, Camera<f64>)>, Option<f64>)> {
+    let open = || std::fs::File::open(path);
+    let (system, water) = match format {
+        Format::Braid => {
+            let system = FlydraMultiCameraSystem::<f64>::from_path(path)?;
+            let water = system.water();
+            (system.to_system(), water)
+        }
+        Format::Pymvg => (MultiCameraSystem::<f64>::from_pymvg_json(open()?)?, None),
+        Format::GenericJson => (formats::read_generic_json(open()?)?, None),
+        Format::RosYaml => return Ok((vec![formats::read_ros_yaml(open()?)?], None)),
+        Format::OpencvXml => {
+            let cam = formats::read_opencv_xml(open()?)?;
+            return Ok((vec![(file_stem(path)?, cam)], None));
+        }
+        Format::OpencvJson => {
+            let cam = formats::read_opencv_json(open()?)?;
+            return Ok((vec![(file_stem(path)?, cam)], None));
+        }
+        Format::AgisoftXml => {
+            let cam = formats::read_agisoft_xml(open()?)?;
+            return Ok((vec![(file_stem(path)?, cam)], None));
+        }
+    };
+    let cams = system.cams_by_name().clone().into_iter().collect();
+    Ok((cams, water))
+}
+
+fn write_cam(format: Format, name: &str, cam: &Camera<f64>, path: &Path) -> anyhow::Result<()> {
+    let wtr = std::fs::File::create(path)?;
+    match format {
+        Format::RosYaml => formats::write_ros_yaml(name, cam, wtr)?,
+        Format::OpencvXml => formats::write_opencv_xml(cam, wtr)?,
+        Format::OpencvJson => formats::write_opencv_json(cam, wtr)?,
+        Format::AgisoftXml => formats::write_agisoft_xml(cam, wtr)?,
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let opt = Opt::parse();
+
+    let mut cams_by_name = BTreeMap::new();
+    let mut water = None;
+    for input in opt.inputs.iter() {
+        println!("Reading calibration at {}", input.display());
+        let (cams, this_water) = read_cams(opt.from, input)
+            .with_context(|| format!("while reading calibration at {}", input.display()))?;
+        water = water.or(this_water);
+        for (name, cam) in cams {
+            if cams_by_name.insert(name.clone(), cam).is_some() {
+                anyhow::bail!("camera \"{name}\" is in more than one input");
+            }
+        }
+    }
+
+    if water.is_some() && opt.to != Format::Braid {
+        eprintln!("Warning: the refractive boundary (water) cannot be saved in this format.");
+    }
+
+    if opt.to.is_single_camera() {
+        std::fs::create_dir_all(&opt.output)
+            .with_context(|| format!("while creating {}", opt.output.display()))?;
+        for (name, cam) in cams_by_name.iter() {
+            let path = opt.output.join(format!("{name}.{}", opt.to.extension()));
+            println!("Writing camera {name} to {}", path.display());
+            write_cam(opt.to, name, cam, &path)
+                .with_context(|| format!("while writing camera {name}"))?;
+        }
+        return Ok(());
+    }
+
+    println!("Writing calibration to {}", opt.output.display());
+    let system = MultiCameraSystem::new(cams_by_name);
+    let mut wtr = std::fs::File::create(&opt.output)?;
+    match opt.to {
+        Format::Braid => FlydraMultiCameraSystem::from_system(system, water).to_flydra_xml(wtr)?,
+        Format::Pymvg => system.to_pymvg_writer(&mut wtr)?,
+        Format::GenericJson => formats::write_generic_json(&system, wtr)?,
+        _ => unreachable!(),
+    }
+    Ok(())
+}
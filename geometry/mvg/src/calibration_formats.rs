@@ -0,0 +1,661 @@
+//! Conversion of camera calibrations to and from the file formats of other
+//! tools.
+//!
+//! The supported formats are:
+//!
+//! - ROS `camera_info` YAML, as saved by the ROS camera calibrator.
+//! - OpenCV `FileStorage` XML or JSON as saved after `cv::calibrateCamera`,
+//!   with the entries `image_width`, `image_height`, `camera_matrix` and
+//!   `distortion_coefficients`. Optionally, `rvec` (a Rodrigues rotation
+//!   vector) and `tvec` give the pose of the camera such that `x_cam = R x_world
+//!   + t`, as returned by `cv::solvePnP`. Other entries are ignored.
+//! - Agisoft Metashape camera calibration XML.
+//! - A generic JSON file with all cameras of a system, see [GenericCalibration].
+//!
+//! The ROS, OpenCV and Agisoft formats store a single camera. The ROS and
+//! Agisoft formats store only intrinsic parameters, so cameras read from them
+//! have default extrinsic parameters.
+//!
+//! Except for ROS YAML, which stores it, the projection matrix of the
+//! intrinsic parameters is not saved and is taken to be the camera matrix when
+//! reading. This does not change the projection of 3D points to distorted
+//! (raw) image coordinates. A rectification matrix other than the identity
+//! cannot be saved except in ROS YAML.
+
+use std::{collections::BTreeMap, io::Read, io::Write};
+
+use nalgebra::{Matrix3, Point3, Rotation3, UnitQuaternion, Vector3, Vector5};
+use serde::{Deserialize, Serialize};
+
+use opencv_ros_camera::{from_ros_yaml, NamedIntrinsicParameters, RosCameraInfo};
+
+use crate::{
+    extrinsics::make_default_extrinsics, Camera, Distortion, ExtrinsicParameters,
+    MultiCameraSystem, MvgError, Result, RosOpenCvIntrinsics,
+};
+
+fn invalid(msg: impl Into<String>) -> MvgError {
+    MvgError::InvalidCalibration { msg: msg.into() }
+}
+
+/// Intrinsic parameters in the form used by most tools.
+struct SimpleIntrinsics {
+    k: Matrix3<f64>,
+    /// OpenCV distortion coefficients `[k1, k2, p1, p2, k3]`.
+    distortion: [f64; 5],
+}
+
+impl SimpleIntrinsics {
+    fn from_camera(cam: &Camera<f64>) -> Result<Self> {
+        let intrinsics = cam.intrinsics();
+        if intrinsics.rect != Matrix3::identity() {
+            return Err(MvgError::RectificationMatrixNotSupported);
+        }
+        let d = &intrinsics.distortion;
+        Ok(Self {
+            k: intrinsics.k,
+            distortion: [
+                d.radial1(),
+                d.radial2(),
+                d.tangential1(),
+                d.tangential2(),
+                d.radial3(),
+            ],
+        })
+    }
+
+    fn to_intrinsics(&self) -> RosOpenCvIntrinsics<f64> {
+        let k = &self.k;
+        let distortion = Distortion::from_opencv_vec(Vector5::from(self.distortion));
+        RosOpenCvIntrinsics::from_params_with_distortion(
+            k[(0, 0)],
+            k[(0, 1)],
+            k[(1, 1)],
+            k[(0, 2)],
+            k[(1, 2)],
+            distortion,
+        )
+    }
+}
+
+/// Convert distortion coefficients in OpenCV order to `[k1, k2, p1, p2, k3]`.
+///
+/// OpenCV models with more coefficients are accepted only if the additional
+/// coefficients are zero.
+fn opencv_distortion(coeffs: &[f64]) -> Result<[f64; 5]> {
+    if coeffs.len() < 4 {
+        return Err(invalid(format!(
+            "expected at least 4 distortion coefficients, found {}",
+            coeffs.len()
+        )));
+    }
+    if coeffs.iter().skip(5).any(|c| *c != 0.0) {
+        return Err(invalid(
+            "only the distortion coefficients k1, k2, p1, p2 and k3 are supported",
+        ));
+    }
+    let mut result = [0.0; 5];
+    for (dest, src) in result.iter_mut().zip(coeffs.iter()) {
+        *dest = *src;
+    }
+    Ok(result)
+}
+
+/// Extrinsic parameters from a Rodrigues rotation vector and translation such
+/// that `x_cam = R x_world + t`.
+fn extrinsics_from_rvec_tvec(rvec: [f64; 3], tvec: [f64; 3]) -> ExtrinsicParameters<f64> {
+    let rquat = UnitQuaternion::from_scaled_axis(Vector3::from(rvec));
+    let t = Point3::from(tvec);
+    crate::extrinsics::from_rquat_translation(rquat, t)
+}
+
+fn rvec_tvec_from_extrinsics(extrinsics: &ExtrinsicParameters<f64>) -> ([f64; 3], [f64; 3]) {
+    let rvec = extrinsics.rotation().scaled_axis();
+    let tvec = extrinsics.translation();
+    ([rvec.x, rvec.y, rvec.z], [tvec.x, tvec.y, tvec.z])
+}
+
+// ROS ------------------------------------------------------------------------
+
+/// Read a camera from ROS `camera_info` YAML.
+///
+/// Returns the camera name saved in the file and the camera.
+pub fn read_ros_yaml<Rd: Read>(reader: Rd) -> Result<(String, Camera<f64>)> {
+    let named: NamedIntrinsicParameters<f64> = from_ros_yaml(reader)?;
+    let cam = Camera::new(
+        named.width,
+        named.height,
+        make_default_extrinsics(),
+        named.intrinsics,
+    )?;
+    Ok((named.name, cam))
+}
+
+/// Write the intrinsic parameters of a camera as ROS `camera_info` YAML.
+pub fn write_ros_yaml<W: Write>(name: &str, cam: &Camera<f64>, writer: W) -> Result<()> {
+    let ci: RosCameraInfo<f64> = NamedIntrinsicParameters {
+        name: name.to_string(),
+        width: cam.width(),
+        height: cam.height(),
+        intrinsics: cam.intrinsics().clone(),
+    }
+    .into();
+    serde_yaml::to_writer(writer, &ci)?;
+    Ok(())
+}
+
+// OpenCV ---------------------------------------------------------------------
+
+/// A matrix in an OpenCV `FileStorage` file.
+///
+/// In XML files, `data` is text with whitespace-separated numbers. In JSON
+/// files, it is an array of numbers. The number of rows and columns is not
+/// needed as all matrices have a known size.
+#[derive(Debug, Deserialize)]
+struct OpenCvMatrix<D> {
+    data: D,
+}
+
+/// The data of an [OpenCvMatrix].
+trait OpenCvData {
+    fn values(&self, name: &str) -> Result<Vec<f64>>;
+}
+
+impl OpenCvData for String {
+    fn values(&self, name: &str) -> Result<Vec<f64>> {
+        self.split_whitespace()
+            .map(|s| {
+                s.parse()
+                    .map_err(|_| invalid(format!("could not parse \"{s}\" in {name}")))
+            })
+            .collect()
+    }
+}
+
+impl OpenCvData for Vec<f64> {
+    fn values(&self, _name: &str) -> Result<Vec<f64>> {
+        Ok(self.clone())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenCvStorage<D> {
+    image_width: usize,
+    image_height: usize,
+    camera_matrix: OpenCvMatrix<D>,
+    distortion_coefficients: OpenCvMatrix<D>,
+    rvec: Option<OpenCvMatrix<D>>,
+    tvec: Option<OpenCvMatrix<D>>,
+}
+
+fn check_size(values: &[f64], n: usize, name: &str) -> Result<()> {
+    if values.len() != n {
+        return Err(invalid(format!(
+            "expected {n} values in {name}, found {}",
+            values.len()
+        )));
+    }
+    Ok(())
+}
+
+fn vec3(values: Vec<f64>, name: &str) -> Result<[f64; 3]> {
+    check_size(&values, 3, name)?;
+    Ok([values[0], values[1], values[2]])
+}
+
+/// Build a camera from the entries of an OpenCV `FileStorage` file.
+fn camera_from_opencv<D: OpenCvData>(storage: OpenCvStorage<D>) -> Result<Camera<f64>> {
+    let camera_matrix = storage.camera_matrix.data.values("camera_matrix")?;
+    check_size(&camera_matrix, 9, "camera_matrix")?;
+    let distortion = storage
+        .distortion_coefficients
+        .data
+        .values("distortion_coefficients")?;
+    let simple = SimpleIntrinsics {
+        k: Matrix3::from_row_slice(&camera_matrix),
+        distortion: opencv_distortion(&distortion)?,
+    };
+    let extrinsics = match (storage.rvec, storage.tvec) {
+        (Some(rvec), Some(tvec)) => {
+            let rvec = vec3(rvec.data.values("rvec")?, "rvec")?;
+            let tvec = vec3(tvec.data.values("tvec")?, "tvec")?;
+            extrinsics_from_rvec_tvec(rvec, tvec)
+        }
+        (None, None) => make_default_extrinsics(),
+        _ => {
+            return Err(invalid(
+                "either both or none of rvec and tvec must be given",
+            ));
+        }
+    };
+    Camera::new(
+        storage.image_width,
+        storage.image_height,
+        extrinsics,
+        simple.to_intrinsics(),
+    )
+}
+
+/// Read a camera from an OpenCV `FileStorage` XML file.
+pub fn read_opencv_xml<Rd: Read>(reader: Rd) -> Result<Camera<f64>> {
+    let storage: OpenCvStorage<String> = serde_xml_rs::from_reader(reader)?;
+    camera_from_opencv(storage)
+}
+
+/// Read a camera from an OpenCV `FileStorage` JSON file.
+pub fn read_opencv_json<Rd: Read>(reader: Rd) -> Result<Camera<f64>> {
+    let storage: OpenCvStorage<Vec<f64>> = serde_json::from_reader(reader)?;
+    camera_from_opencv(storage)
+}
+
+/// The entries of an OpenCV `FileStorage` file for a camera.
+fn opencv_entries(cam: &Camera<f64>) -> Result<Vec<(&'static str, usize, Vec<f64>)>> {
+    let simple = SimpleIntrinsics::from_camera(cam)?;
+    let (rvec, tvec) = rvec_tvec_from_extrinsics(cam.extrinsics());
+    // Row-major order.
+    let k: Vec<f64> = simple.k.transpose().iter().copied().collect();
+    Ok(vec![
+        ("camera_matrix", 3, k),
+        ("distortion_coefficients", 1, simple.distortion.to_vec()),
+        ("rvec", 1, rvec.to_vec()),
+        ("tvec", 1, tvec.to_vec()),
+    ])
+}
+
+/// Write a camera as an OpenCV `FileStorage` XML file.
+///
+/// This can be read with `cv::FileStorage`.
+pub fn write_opencv_xml<W: Write>(cam: &Camera<f64>, mut writer: W) -> Result<()> {
+    let mut buf = String::from("<?xml version=\"1.0\"?>\n<opencv_storage>\n");
+    buf.push_str(&format!("<image_width>{}</image_width>\n", cam.width()));
+    buf.push_str(&format!("<image_height>{}</image_height>\n", cam.height()));
+    for (name, cols, values) in opencv_entries(cam)? {
+        let data: Vec<String> = values.iter().map(|v| format!("{v:e}")).collect();
+        buf.push_str(&format!(
+            "<{name} type_id=\"opencv-matrix\">\n  <rows>{}</rows>\n  <cols>{cols}</cols>\n  \
+            <dt>d</dt>\n  <data>\n    {}</data></{name}>\n",
+            values.len() / cols,
+            data.join(" "),
+        ));
+    }
+    buf.push_str("</opencv_storage>\n");
+    writer.write_all(buf.as_bytes())?;
+    Ok(())
+}
+
+/// Write a camera as an OpenCV `FileStorage` JSON file.
+///
+/// This can be read with `cv::FileStorage`.
+pub fn write_opencv_json<W: Write>(cam: &Camera<f64>, writer: W) -> Result<()> {
+    let mut storage = serde_json::Map::new();
+    storage.insert("image_width".into(), cam.width().into());
+    storage.insert("image_height".into(), cam.height().into());
+    for (name, cols, values) in opencv_entries(cam)? {
+        let mut mat = serde_json::Map::new();
+        mat.insert("type_id".into(), "opencv-matrix".into());
+        mat.insert("rows".into(), (values.len() / cols).into());
+        mat.insert("cols".into(), cols.into());
+        mat.insert("dt".into(), "d".into());
+        mat.insert("data".into(), values.into());
+        storage.insert(name.into(), mat.into());
+    }
+    serde_json::to_writer_pretty(writer, &storage)?;
+    Ok(())
+}
+
+// Agisoft --------------------------------------------------------------------
+
+/// Camera calibration XML of Agisoft Metashape.
+///
+/// In contrast to OpenCV, `cx` and `cy` are offsets from the image center, the
+/// origin of the image coordinates is the corner (not the center) of the first
+/// pixel and the tangential distortion coefficients `p1` and `p2` are swapped.
+#[derive(Debug, Deserialize)]
+struct AgisoftCalibration {
+    projection: String,
+    width: usize,
+    height: usize,
+    f: f64,
+    #[serde(default)]
+    cx: f64,
+    #[serde(default)]
+    cy: f64,
+    #[serde(default)]
+    b1: f64,
+    #[serde(default)]
+    b2: f64,
+    #[serde(default)]
+    k1: f64,
+    #[serde(default)]
+    k2: f64,
+    #[serde(default)]
+    k3: f64,
+    #[serde(default)]
+    k4: f64,
+    #[serde(default)]
+    p1: f64,
+    #[serde(default)]
+    p2: f64,
+}
+
+/// Read the intrinsic parameters of a camera from Agisoft Metashape
+/// calibration XML.
+pub fn read_agisoft_xml<Rd: Read>(reader: Rd) -> Result<Camera<f64>> {
+    let a: AgisoftCalibration = serde_xml_rs::from_reader(reader)?;
+    if a.projection != "frame" {
+        return Err(invalid(format!(
+            "only \"frame\" projection is supported, not \"{}\"",
+            a.projection
+        )));
+    }
+    if a.k4 != 0.0 {
+        return Err(invalid("distortion coefficient k4 is not supported"));
+    }
+    let cx = a.width as f64 * 0.5 + a.cx - 0.5;
+    let cy = a.height as f64 * 0.5 + a.cy - 0.5;
+    #[rustfmt::skip]
+    let k = Matrix3::new(
+        a.f + a.b1, a.b2, cx,
+        0.0, a.f, cy,
+        0.0, 0.0, 1.0,
+    );
+    let simple = SimpleIntrinsics {
+        k,
+        distortion: [a.k1, a.k2, a.p2, a.p1, a.k3],
+    };
+    Camera::new(
+        a.width,
+        a.height,
+        make_default_extrinsics(),
+        simple.to_intrinsics(),
+    )
+}
+
+/// Write the intrinsic parameters of a camera as Agisoft Metashape
+/// calibration XML.
+pub fn write_agisoft_xml<W: Write>(cam: &Camera<f64>, mut writer: W) -> Result<()> {
+    let simple = SimpleIntrinsics::from_camera(cam)?;
+    let k = &simple.k;
+    let [k1, k2, p1, p2, k3] = simple.distortion;
+    let entries = [
+        ("f", k[(1, 1)]),
+        ("cx", k[(0, 2)] - cam.width() as f64 * 0.5 + 0.5),
+        ("cy", k[(1, 2)] - cam.height() as f64 * 0.5 + 0.5),
+        ("b1", k[(0, 0)] - k[(1, 1)]),
+        ("b2", k[(0, 1)]),
+        ("k1", k1),
+        ("k2", k2),
+        ("k3", k3),
+        ("p1", p2),
+        ("p2", p1),
+    ];
+    let mut buf = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<calibration>\n");
+    buf.push_str("  <projection>frame</projection>\n");
+    buf.push_str(&format!("  <width>{}</width>\n", cam.width()));
+    buf.push_str(&format!("  <height>{}</height>\n", cam.height()));
+    for (name, value) in entries {
+        buf.push_str(&format!("  <{name}>{value}</{name}>\n"));
+    }
+    buf.push_str("</calibration>\n");
+    writer.write_all(buf.as_bytes())?;
+    Ok(())
+}
+
+// Generic JSON ---------------------------------------------------------------
+
+/// All cameras of a system in a simple JSON format.
+///
+/// This is meant for exchanging calibrations with tools for which no specific
+/// converter exists.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenericCalibration {
+    pub cameras: BTreeMap<String, GenericCamera>,
+}
+
+/// A camera in a [GenericCalibration].
+///
+/// Pixel coordinates follow the OpenCV convention, i.e. the center of the
+/// first pixel is at (0, 0).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenericCamera {
+    pub width: usize,
+    pub height: usize,
+    /// Focal length in x, in pixels.
+    pub fx: f64,
+    /// Focal length in y, in pixels.
+    pub fy: f64,
+    /// Principal point x coordinate, in pixels.
+    pub cx: f64,
+    /// Principal point y coordinate, in pixels.
+    pub cy: f64,
+    #[serde(default)]
+    pub skew: f64,
+    /// OpenCV distortion coefficients `[k1, k2, p1, p2, k3]`.
+    #[serde(default)]
+    pub distortion: [f64; 5],
+    /// Rotation matrix, as rows, from world to camera coordinates.
+    pub rotation: [[f64; 3]; 3],
+    /// Position of the camera center in world coordinates.
+    pub camera_center: [f64; 3],
+}
+
+impl GenericCamera {
+    fn from_camera(cam: &Camera<f64>) -> Result<Self> {
+        let simple = SimpleIntrinsics::from_camera(cam)?;
+        let k = &simple.k;
+        let r = cam.extrinsics().rotation().to_rotation_matrix();
+        let r = r.matrix();
+        let cc = cam.extrinsics().camcenter();
+        Ok(Self {
+            width: cam.width(),
+            height: cam.height(),
+            fx: k[(0, 0)],
+            fy: k[(1, 1)],
+            cx: k[(0, 2)],
+            cy: k[(1, 2)],
+            skew: k[(0, 1)],
+            distortion: simple.distortion,
+            rotation: [0, 1, 2].map(|i| [r[(i, 0)], r[(i, 1)], r[(i, 2)]]),
+            camera_center: [cc.x, cc.y, cc.z],
+        })
+    }
+
+    fn to_camera(&self) -> Result<Camera<f64>> {
+        #[rustfmt::skip]
+        let k = Matrix3::new(
+            self.fx, self.skew, self.cx,
+            0.0, self.fy, self.cy,
+            0.0, 0.0, 1.0,
+        );
+        let simple = SimpleIntrinsics {
+            k,
+            distortion: self.distortion,
+        };
+        let rows = self.rotation.concat();
+        let rmat = Rotation3::from_matrix_unchecked(Matrix3::from_row_slice(&rows));
+        let rquat = UnitQuaternion::from_rotation_matrix(&rmat);
+        let max_err = (rquat.to_rotation_matrix().into_inner() - rmat.into_inner())
+            .abs()
+            .max();
+        if max_err > 1e-6 {
+            return Err(MvgError::InvalidRotationMatrix);
+        }
+        let extrinsics = ExtrinsicParameters::from_rotation_and_camcenter(
+            rquat,
+            Point3::from(self.camera_center),
+        );
+        Camera::new(self.width, self.height, extrinsics, simple.to_intrinsics())
+    }
+}
+
+/// Read all cameras of a system from generic JSON.
+pub fn read_generic_json<Rd: Read>(reader: Rd) -> Result<MultiCameraSystem<f64>> {
+    let cal: GenericCalibration = serde_json::from_reader(reader)?;
+    let cams = cal
+        .cameras
+        .iter()
+        .map(|(name, cam)| Ok((name.clone(), cam.to_camera()?)))
+        .collect::<Result<BTreeMap<_, _>>>()?;
+    Ok(MultiCameraSystem::new(cams))
+}
+
+/// Write all cameras of a system as generic JSON.
+pub fn write_generic_json<W: Write>(system: &MultiCameraSystem<f64>, writer: W) -> Result<()> {
+    let cameras = system
+        .cams_by_name()
+        .iter()
+        .map(|(name, cam)| Ok((name.clone(), GenericCamera::from_camera(cam)?)))
+        .collect::<Result<BTreeMap<_, _>>>()?;
+    serde_json::to_writer_pretty(writer, &GenericCalibration { cameras })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PointWorldFrame;
+
+    fn test_camera() -> Camera<f64> {
+        let distortion = Distortion::from_opencv_vec(Vector5::new(-0.1, 0.05, 0.001, -0.002, 0.01));
+        let intrinsics = RosOpenCvIntrinsics::from_params_with_distortion(
+            500.0, 1.5, 510.0, 330.0, 250.0, distortion,
+        );
+        let extrinsics = ExtrinsicParameters::from_view(
+            &Vector3::new(1.0, 2.0, 3.0),
+            &Vector3::new(0.0, 0.0, 0.0),
+            &nalgebra::Unit::new_normalize(Vector3::z()),
+        );
+        Camera::new(640, 480, extrinsics, intrinsics).unwrap()
+    }
+
+    /// Check that both cameras project 3D points to the same raw pixels.
+    fn assert_same_projection(cam1: &Camera<f64>, cam2: &Camera<f64>) {
+        assert_eq!(cam1.width(), cam2.width());
+        assert_eq!(cam1.height(), cam2.height());
+        for pt in [[0.0, 0.0, 0.0], [0.1, -0.2, 0.3], [-0.3, 0.2, -0.1]] {
+            let pt = PointWorldFrame {
+                coords: Point3::from(pt),
+            };
+            let px1 = cam1.project_3d_to_distorted_pixel(&pt);
+            let px2 = cam2.project_3d_to_distorted_pixel(&pt);
+            approx::assert_relative_eq!(px1.coords, px2.coords, epsilon = 1e-6);
+        }
+    }
+
+    /// Check that both cameras have the same intrinsic parameters.
+    fn assert_same_intrinsics(cam1: &Camera<f64>, cam2: &Camera<f64>) {
+        let cam2 = Camera::new(
+            cam2.width(),
+            cam2.height(),
+            cam1.extrinsics().clone(),
+            cam2.intrinsics().clone(),
+        )
+        .unwrap();
+        assert_same_projection(cam1, &cam2);
+    }
+
+    #[test]
+    fn test_ros_yaml_roundtrip() {
+        let cam = test_camera();
+        let mut buf = Vec::new();
+        write_ros_yaml("cam1", &cam, &mut buf).unwrap();
+        let (name, cam2) = read_ros_yaml(buf.as_slice()).unwrap();
+        assert_eq!(name, "cam1");
+        assert_same_intrinsics(&cam, &cam2);
+    }
+
+    #[test]
+    fn test_opencv_roundtrip() {
+        let cam = test_camera();
+
+        let mut buf = Vec::new();
+        write_opencv_xml(&cam, &mut buf).unwrap();
+        let cam2 = read_opencv_xml(buf.as_slice()).unwrap();
+        assert_same_projection(&cam, &cam2);
+
+        let mut buf = Vec::new();
+        write_opencv_json(&cam, &mut buf).unwrap();
+        let cam2 = read_opencv_json(buf.as_slice()).unwrap();
+        assert_same_projection(&cam, &cam2);
+    }
+
+    #[test]
+    fn test_read_opencv_xml() {
+        // As saved by the OpenCV camera calibration sample.
+        let buf = r#"<?xml version="1.0"?>
+<opencv_storage>
+<calibration_time>"Mon Jan  1 12:00:00 2024"</calibration_time>
+<image_width>640</image_width>
+<image_height>480</image_height>
+<camera_matrix type_id="opencv-matrix">
+  <rows>3</rows>
+  <cols>3</cols>
+  <dt>d</dt>
+  <data>
+    5.0e+02 0. 3.2e+02 0. 5.1e+02 2.4e+02 0. 0. 1.</data></camera_matrix>
+<distortion_coefficients type_id="opencv-matrix">
+  <rows>5</rows>
+  <cols>1</cols>
+  <dt>d</dt>
+  <data>
+    -1.e-01 1.e-02 0. 0. 0.</data></distortion_coefficients>
+<avg_reprojection_error>3.0e-01</avg_reprojection_error>
+</opencv_storage>
+"#;
+        let cam = read_opencv_xml(buf.as_bytes()).unwrap();
+        let k = cam.intrinsics().k;
+        assert_eq!(k[(0, 0)], 500.0);
+        assert_eq!(k[(1, 1)], 510.0);
+        assert_eq!(k[(0, 2)], 320.0);
+        assert_eq!(cam.intrinsics().distortion.radial1(), -0.1);
+    }
+
+    #[test]
+    fn test_agisoft_xml() {
+        let buf = r#"<?xml version="1.0" encoding="UTF-8"?>
+<calibration>
+  <projection>frame</projection>
+  <width>640</width>
+  <height>480</height>
+  <f>510</f>
+  <cx>10.5</cx>
+  <cy>-4.5</cy>
+  <b1>-10</b1>
+  <k1>-0.1</k1>
+  <p1>0.001</p1>
+  <p2>0.002</p2>
+  <date>2024-01-01T12:00:00Z</date>
+</calibration>
+"#;
+        let cam = read_agisoft_xml(buf.as_bytes()).unwrap();
+        let k = cam.intrinsics().k;
+        assert_eq!(k[(0, 0)], 500.0);
+        assert_eq!(k[(1, 1)], 510.0);
+        assert_eq!(k[(0, 2)], 330.0);
+        assert_eq!(k[(1, 2)], 235.0);
+        assert_eq!(cam.intrinsics().distortion.tangential1(), 0.002);
+        assert_eq!(cam.intrinsics().distortion.tangential2(), 0.001);
+
+        let cam = test_camera();
+        let mut buf = Vec::new();
+        write_agisoft_xml(&cam, &mut buf).unwrap();
+        let cam2 = read_agisoft_xml(buf.as_slice()).unwrap();
+        assert_same_intrinsics(&cam, &cam2);
+    }
+
+    #[test]
+    fn test_generic_json_roundtrip() {
+        let mut cams = BTreeMap::new();
+        cams.insert("cam1".to_string(), test_camera());
+        let system = MultiCameraSystem::new(cams);
+        let mut buf = Vec::new();
+        write_generic_json(&system, &mut buf).unwrap();
+        let system2 = read_generic_json(buf.as_slice()).unwrap();
+        assert_same_projection(
+            system.cam_by_name("cam1").unwrap(),
+            system2.cam_by_name("cam1").unwrap(),
+        );
+    }
+}
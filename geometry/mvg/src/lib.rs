@@ -54,6 +54,13 @@ pub enum MvgError {
         #[from]
         source: serde_json::Error,
     },
+    #[error("serde_xml_rs error: {source}")]
+    SerdeXml {
+        #[from]
+        source: serde_xml_rs::Error,
+    },
+    #[error("invalid calibration: {msg}")]
+    InvalidCalibration { msg: String },
     #[error("SvgError: {}", error)]
     SvgError { error: &'static str },
     #[error("PinvError: {}", error)]
@@ -91,6 +98,16 @@ pub mod extrinsics;
 
 pub mod align_points;
 
+pub mod wand;
+
+pub mod robust_triangulation;
+
+pub mod triangulation_uncertainty;
+
+pub mod undistortion;
+
+pub mod calibration_formats;
+
 #[cfg(feature = "rerun-io")]
 pub mod rerun_io;
 
@@ -297,6 +314,20 @@ pub fn vec_sum<R: RealField + Copy>(vec: &[R]) -> R {
     vec.iter().fold(na::convert(0.0), |acc, i| acc + *i)
 }
 
+/// The median of the finite values in `vals`, `None` if there are none.
+pub fn median<R: RealField + Copy + num_traits::float::TotalOrder>(vals: &[R]) -> Option<R> {
+    let mut vals: Vec<R> = vals.iter().copied().filter(|v| v.is_finite()).collect();
+    vals.sort_by(|a, b| a.total_cmp(b));
+    let n = vals.len();
+    if n == 0 {
+        None
+    } else if n % 2 == 0 {
+        Some((vals[n / 2 - 1] + vals[n / 2]) * na::convert(0.5))
+    } else {
+        Some(vals[n / 2])
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PointWorldFrameWithSumReprojError<R: RealField + Copy> {
     pub point: PointWorldFrame<R>,
@@ -374,6 +405,15 @@ pub fn make_default_intrinsics<R: RealField + Copy>() -> RosOpenCvIntrinsics<R>
 mod tests {
     use crate::*;
 
+    #[test]
+    fn test_median() {
+        assert_eq!(median::<f64>(&[]), None);
+        assert_eq!(median(&[3.0, 1.0, 2.0]), Some(2.0));
+        assert_eq!(median(&[4.0, 1.0, 2.0, 3.0]), Some(2.5));
+        assert_eq!(median(&[f64::NAN, 3.0, f64::INFINITY, 1.0]), Some(2.0));
+        assert_eq!(median(&[f64::NAN]), None);
+    }
+
     fn get_test_intrinsics() -> Vec<(String, RosOpenCvIntrinsics<f64>)> {
         use na::Vector5;
         let mut result = Vec::new();
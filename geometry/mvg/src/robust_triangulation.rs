@@ -0,0 +1,263 @@
+//! Robust triangulation of points with outlier observations.
+//!
+//! Triangulating with all observations of a point, as [MultiCameraSystem::find3d]
+//! does, gives a bad point if any observation is wrong (e.g. a reflection or a
+//! detection of another object). Such points are a poor starting point for
+//! nonlinear optimization such as bundle adjustment. Here, RANSAC is used over
+//! the cameras observing a point: the point is triangulated from each pair of
+//! cameras, the hypothesis consistent with the most observations is kept, and
+//! the point is then triangulated again from these inlier observations only.
+//! Because the number of cameras is small, all pairs are tried rather than a
+//! random sample of them.
+
+use nalgebra::RealField;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::{MultiCameraSystem, MvgError, PointWorldFrame, Result, UndistortedPixel};
+
+/// Parameters for [MultiCameraSystem::find3d_ransac].
+#[derive(Debug, Clone)]
+pub struct RansacParams<R: RealField + Copy> {
+    /// Observations with a reprojection distance larger than this (in pixels)
+    /// are outliers.
+    pub max_reproj_dist: R,
+    /// Minimum number of inlier observations (at least 2).
+    pub min_inliers: usize,
+}
+
+impl<R: RealField + Copy> Default for RansacParams<R> {
+    fn default() -> Self {
+        Self {
+            max_reproj_dist: nalgebra::convert(2.0),
+            min_inliers: 2,
+        }
+    }
+}
+
+/// A point triangulated from inlier observations only.
+#[derive(Debug, Clone)]
+pub struct RobustPoint<R: RealField + Copy> {
+    pub point: PointWorldFrame<R>,
+    /// Indices of the inlier observations, in increasing order. There is at
+    /// most one inlier per camera.
+    pub inliers: Vec<usize>,
+    /// Mean reprojection distance (in pixels) of the inlier observations.
+    pub mean_reproj_dist: R,
+}
+
+impl<R: RealField + Default + Serialize + Copy> MultiCameraSystem<R> {
+    /// Reprojection distance of `point` to each observation, or `None` if the
+    /// point is behind the camera.
+    fn reproj_dists_in_front(
+        &self,
+        points: &[(String, UndistortedPixel<R>)],
+        point: &PointWorldFrame<R>,
+    ) -> Result<Vec<Option<R>>> {
+        let dists = self.get_reprojection_undistorted_dists(points, point)?;
+        points
+            .iter()
+            .zip(dists)
+            .map(|((cam_name, _), dist)| {
+                let cam = self.cam_by_name(cam_name).ok_or(MvgError::UnknownCamera)?;
+                let depth = (cam.extrinsics().matrix() * point.coords.to_homogeneous())[2];
+                Ok(if depth > R::zero() { Some(dist) } else { None })
+            })
+            .collect()
+    }
+
+    /// The inlier observations of `point`, keeping only the closest
+    /// observation of each camera, and the sum of their reprojection
+    /// distances.
+    fn inliers(
+        &self,
+        points: &[(String, UndistortedPixel<R>)],
+        point: &PointWorldFrame<R>,
+        max_reproj_dist: R,
+    ) -> Result<(Vec<usize>, R)> {
+        let dists = self.reproj_dists_in_front(points, point)?;
+        let mut best_by_cam: std::collections::BTreeMap<&str, (usize, R)> = Default::default();
+        for (i, dist) in dists.into_iter().enumerate() {
+            let Some(dist) = dist else { continue };
+            if dist > max_reproj_dist {
+                continue;
+            }
+            let entry = best_by_cam.entry(points[i].0.as_str()).or_insert((i, dist));
+            if dist < entry.1 {
+                *entry = (i, dist);
+            }
+        }
+        let sum = best_by_cam
+            .values()
+            .fold(R::zero(), |acc, (_, dist)| acc + *dist);
+        let mut inliers: Vec<usize> = best_by_cam.into_values().map(|(i, _)| i).collect();
+        inliers.sort_unstable();
+        Ok((inliers, sum))
+    }
+
+    /// Find 3D coordinate using pixel coordinates from cameras, ignoring
+    /// outlier observations.
+    ///
+    /// Returns [MvgError::NotEnoughPoints] if fewer than
+    /// `params.min_inliers` observations are consistent with any point.
+    pub fn find3d_ransac(
+        &self,
+        points: &[(String, UndistortedPixel<R>)],
+        params: &RansacParams<R>,
+    ) -> Result<RobustPoint<R>> {
+        let min_inliers = params.min_inliers.max(2);
+        if points.len() < min_inliers {
+            return Err(MvgError::NotEnoughPoints);
+        }
+
+        // Hypotheses from each pair of observations of different cameras.
+        let mut best: Option<(Vec<usize>, R)> = None;
+        for i in 0..points.len() {
+            for j in (i + 1)..points.len() {
+                if points[i].0 == points[j].0 {
+                    continue;
+                }
+                let pair = [points[i].clone(), points[j].clone()];
+                let Ok(point) = self.find3d(&pair) else {
+                    continue;
+                };
+                let (inliers, sum) = self.inliers(points, &point, params.max_reproj_dist)?;
+                let is_better = match &best {
+                    None => true,
+                    Some((best_inliers, best_sum)) => {
+                        inliers.len() > best_inliers.len()
+                            || (inliers.len() == best_inliers.len() && sum < *best_sum)
+                    }
+                };
+                if is_better {
+                    best = Some((inliers, sum));
+                }
+            }
+        }
+
+        let Some((mut inliers, _)) = best else {
+            return Err(MvgError::NotEnoughPoints);
+        };
+        if inliers.len() < min_inliers {
+            return Err(MvgError::NotEnoughPoints);
+        }
+
+        // Triangulate again from the inliers until the inliers do not change.
+        const MAX_REFITS: usize = 5;
+        let mut point = None;
+        for _ in 0..MAX_REFITS {
+            let inlier_points: Vec<_> = inliers.iter().map(|i| points[*i].clone()).collect();
+            let refit = self.find3d(&inlier_points)?;
+            let (new_inliers, _) = self.inliers(points, &refit, params.max_reproj_dist)?;
+            point = Some(refit);
+            if new_inliers == inliers || new_inliers.len() < min_inliers {
+                break;
+            }
+            inliers = new_inliers;
+        }
+        let point = point.unwrap();
+
+        let inlier_points: Vec<_> = inliers.iter().map(|i| points[*i].clone()).collect();
+        let dists = self.get_reprojection_undistorted_dists(&inlier_points, &point)?;
+        let n: R = nalgebra::convert(dists.len() as f64);
+        let mean_reproj_dist = dists.into_iter().fold(R::zero(), |acc, d| acc + d) / n;
+
+        Ok(RobustPoint {
+            point,
+            inliers,
+            mean_reproj_dist,
+        })
+    }
+
+    /// Run [Self::find3d_ransac] on many points in parallel.
+    ///
+    /// The results are in the order of `points`.
+    pub fn find3d_ransac_many(
+        &self,
+        points: &[Vec<(String, UndistortedPixel<R>)>],
+        params: &RansacParams<R>,
+    ) -> Vec<Result<RobustPoint<R>>>
+    where
+        R: Send + Sync,
+    {
+        points
+            .par_iter()
+            .map(|obs| self.find3d_ransac(obs, params))
+            .collect()
+    }
+}
+
+#[test]
+fn test_find3d_ransac() {
+    use nalgebra::{Point2, Point3, Unit, Vector3};
+    use std::collections::BTreeMap;
+
+    let mut cams = BTreeMap::new();
+    for (name, x, y) in [
+        ("cam1", -1.0, 0.3),
+        ("cam2", 1.0, 0.3),
+        ("cam3", 0.2, -1.0),
+        ("cam4", 0.0, 1.2),
+    ] {
+        let extrinsics = cam_geom::ExtrinsicParameters::from_view(
+            &Vector3::new(x, y, 5.0),
+            &Vector3::new(0.0, 0.0, 0.0),
+            &Unit::new_normalize(Vector3::new(0.0, 1.0, 0.0)),
+        );
+        let intrinsics = crate::make_default_intrinsics();
+        let cam = crate::Camera::new(640, 480, extrinsics, intrinsics).unwrap();
+        cams.insert(name.to_string(), cam);
+    }
+    let system = MultiCameraSystem::new(cams);
+
+    let truths = [
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(0.1, 0.2, 0.3),
+        Point3::new(-0.2, 0.1, -0.3),
+    ];
+    let all_obs: Vec<Vec<_>> = truths
+        .iter()
+        .map(|truth| {
+            let mut obs: Vec<_> = system
+                .cams()
+                .iter()
+                .map(|(name, cam)| {
+                    let px = cam.project_3d_to_pixel(&PointWorldFrame { coords: *truth });
+                    (name.clone(), px)
+                })
+                .collect();
+            // A wrong detection in cam3 and an additional wrong detection in
+            // cam1.
+            obs[2].1.coords += nalgebra::Vector2::new(50.0, -30.0);
+            obs.push((
+                "cam1".to_string(),
+                UndistortedPixel {
+                    coords: Point2::new(10.0, 10.0),
+                },
+            ));
+            obs
+        })
+        .collect();
+
+    let params = RansacParams::default();
+    let results = system.find3d_ransac_many(&all_obs, &params);
+    for (truth, result) in truths.iter().zip(results) {
+        let result = result.unwrap();
+        assert_eq!(result.inliers, vec![0, 1, 3]);
+        approx::assert_relative_eq!(result.point.coords, *truth, epsilon = 1e-6);
+        assert!(result.mean_reproj_dist < 1e-6);
+    }
+
+    // Naive triangulation with all observations is off.
+    let naive = system.find3d(&all_obs[0]).unwrap();
+    assert!(nalgebra::distance(&naive.coords, &truths[0]) > 1e-3);
+
+    let params = RansacParams {
+        min_inliers: 4,
+        ..Default::default()
+    };
+    assert!(matches!(
+        system.find3d_ransac(&all_obs[0], &params),
+        Err(MvgError::NotEnoughPoints)
+    ));
+}
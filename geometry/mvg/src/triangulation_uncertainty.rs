@@ -0,0 +1,193 @@
+//! Uncertainty and conditioning of triangulated points.
+//!
+//! The covariance of a triangulated point is estimated by first-order
+//! propagation of isotropic noise in the undistorted pixel coordinates of the
+//! observations through the (linear) projection of each camera. Points seen by
+//! cameras with nearly parallel rays are poorly constrained along the rays;
+//! this shows up as a large covariance, a large condition number and a small
+//! angle between the rays.
+
+use nalgebra::{Matrix3, RealField, Vector3};
+use serde::Serialize;
+
+use crate::{MultiCameraSystem, MvgError, PointWorldFrame, Result, UndistortedPixel};
+
+/// A triangulated point with estimates of its uncertainty.
+#[derive(Debug, Clone)]
+pub struct TriangulatedPoint<R: RealField + Copy> {
+    pub point: PointWorldFrame<R>,
+    /// The covariance of the point, in squared world units.
+    pub covariance: Matrix3<R>,
+    /// The condition number of the linearized projection of the point into
+    /// all observations. Large values indicate a poorly constrained point.
+    pub condition_number: R,
+    /// The number of distinct cameras with observations of the point.
+    pub num_cams: usize,
+    /// The largest angle, in radians, between the rays from any two of the
+    /// cameras to the point.
+    pub max_ray_angle: R,
+}
+
+impl<R: RealField + Default + Serialize + Copy> MultiCameraSystem<R> {
+    /// The largest angle, in radians, between the rays from the centers of
+    /// any two of `cam_names` to `point`.
+    ///
+    /// This is zero if fewer than two cameras are given.
+    pub fn max_ray_angle<'a, I>(&self, cam_names: I, point: &PointWorldFrame<R>) -> Result<R>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut dirs: Vec<Vector3<R>> = vec![];
+        for name in cam_names {
+            let cam = self.cam_by_name(name).ok_or(MvgError::UnknownCamera)?;
+            let dir = point.coords - cam.extrinsics().camcenter();
+            if let Some(dir) = dir.try_normalize(R::zero()) {
+                dirs.push(dir);
+            }
+        }
+        let mut max_angle = R::zero();
+        for (i, a) in dirs.iter().enumerate() {
+            for b in dirs[i + 1..].iter() {
+                let cos = a.dot(b).clamp(-R::one(), R::one());
+                max_angle = max_angle.max(cos.acos());
+            }
+        }
+        Ok(max_angle)
+    }
+
+    /// The covariance of `point` given its observations `points`, each with
+    /// independent noise of standard deviation `pixel_std` (in pixels) in
+    /// both coordinates, and the condition number of the linearized
+    /// projection.
+    pub fn point_covariance(
+        &self,
+        points: &[(String, UndistortedPixel<R>)],
+        point: &PointWorldFrame<R>,
+        pixel_std: R,
+    ) -> Result<(Matrix3<R>, R)> {
+        // Normal matrix J^T J of the Jacobian J of the projections with
+        // respect to the point.
+        let mut jtj = Matrix3::<R>::zeros();
+        let pt_h = point.coords.to_homogeneous();
+        for (name, _) in points.iter() {
+            let cam = self.cam_by_name(name).ok_or(MvgError::UnknownCamera)?;
+            let pmat = cam.linear_part_as_pmat();
+            let x = pmat * pt_h;
+            let w = x[2];
+            if w == R::zero() {
+                return Err(MvgError::InvalidShape);
+            }
+            let w2 = w * w;
+            let p0 = pmat.fixed_view::<1, 3>(0, 0);
+            let p1 = pmat.fixed_view::<1, 3>(1, 0);
+            let p2 = pmat.fixed_view::<1, 3>(2, 0);
+            let du = (p0 * w - p2 * x[0]) / w2;
+            let dv = (p1 * w - p2 * x[1]) / w2;
+            jtj += du.transpose() * du + dv.transpose() * dv;
+        }
+
+        let eigenvalues = jtj.symmetric_eigenvalues();
+        let min = eigenvalues.min();
+        let max = eigenvalues.max();
+        if min <= R::zero() {
+            return Err(MvgError::NotEnoughPoints);
+        }
+        let condition_number = (max / min).sqrt();
+
+        let inv = jtj.try_inverse().ok_or(MvgError::NotEnoughPoints)?;
+        Ok((inv * (pixel_std * pixel_std), condition_number))
+    }
+
+    /// Find 3D coordinate using pixel coordinates from cameras, together with
+    /// its covariance and measures of its conditioning.
+    ///
+    /// `pixel_std` is the standard deviation of the noise of the observations,
+    /// in pixels.
+    pub fn find3d_with_uncertainty(
+        &self,
+        points: &[(String, UndistortedPixel<R>)],
+        pixel_std: R,
+    ) -> Result<TriangulatedPoint<R>> {
+        let point = self.find3d(points)?;
+        let (covariance, condition_number) = self.point_covariance(points, &point, pixel_std)?;
+        let cam_names: std::collections::BTreeSet<&str> =
+            points.iter().map(|(name, _)| name.as_str()).collect();
+        let num_cams = cam_names.len();
+        let max_ray_angle = self.max_ray_angle(cam_names, &point)?;
+        Ok(TriangulatedPoint {
+            point,
+            covariance,
+            condition_number,
+            num_cams,
+            max_ray_angle,
+        })
+    }
+}
+
+#[test]
+fn test_find3d_with_uncertainty() {
+    use nalgebra::{Point3, Unit};
+    use std::collections::BTreeMap;
+
+    let make_system = |baseline: f64| {
+        let mut cams = BTreeMap::new();
+        for (name, x) in [("cam1", -baseline / 2.0), ("cam2", baseline / 2.0)] {
+            let extrinsics = cam_geom::ExtrinsicParameters::from_view(
+                &Vector3::new(x, 0.0, 5.0),
+                &Vector3::new(0.0, 0.0, 0.0),
+                &Unit::new_normalize(Vector3::new(0.0, 1.0, 0.0)),
+            );
+            let intrinsics = crate::make_default_intrinsics();
+            let cam = crate::Camera::new(640, 480, extrinsics, intrinsics).unwrap();
+            cams.insert(name.to_string(), cam);
+        }
+        MultiCameraSystem::new(cams)
+    };
+
+    let truth = PointWorldFrame {
+        coords: Point3::new(0.1, 0.2, 0.0),
+    };
+    let observe = |system: &MultiCameraSystem<f64>| -> Vec<_> {
+        system
+            .cams()
+            .iter()
+            .map(|(name, cam)| (name.clone(), cam.project_3d_to_pixel(&truth)))
+            .collect()
+    };
+
+    let wide = make_system(4.0);
+    let narrow = make_system(0.5);
+    let wide_result = wide.find3d_with_uncertainty(&observe(&wide), 1.0).unwrap();
+    let narrow_result = narrow
+        .find3d_with_uncertainty(&observe(&narrow), 1.0)
+        .unwrap();
+
+    for result in [&wide_result, &narrow_result] {
+        approx::assert_relative_eq!(result.point.coords, truth.coords, epsilon = 1e-6);
+        assert_eq!(result.num_cams, 2);
+        let cov = &result.covariance;
+        approx::assert_relative_eq!(*cov, cov.transpose(), epsilon = 1e-12);
+        assert!(cov.symmetric_eigenvalues().min() > 0.0);
+    }
+
+    // The cameras are at (-b/2, 0, 5) and (b/2, 0, 5), so the angle is close
+    // to 2 atan(b/2 / 5).
+    approx::assert_relative_eq!(
+        wide_result.max_ray_angle,
+        2.0 * (2.0f64 / 5.0).atan(),
+        epsilon = 0.05
+    );
+    assert!(narrow_result.max_ray_angle < wide_result.max_ray_angle);
+
+    // A narrow baseline gives a larger uncertainty in depth (z).
+    assert!(narrow_result.covariance[(2, 2)] > wide_result.covariance[(2, 2)]);
+    assert!(narrow_result.condition_number > wide_result.condition_number);
+
+    // The covariance scales with the pixel variance.
+    let scaled = wide.find3d_with_uncertainty(&observe(&wide), 2.0).unwrap();
+    approx::assert_relative_eq!(
+        scaled.covariance,
+        wide_result.covariance * 4.0,
+        epsilon = 1e-12
+    );
+}
@@ -0,0 +1,325 @@
+//! Fast undistortion of points and images.
+//!
+//! [UndistortionMap] precomputes what is needed to undistort the points and
+//! images of a camera. Points are undistorted with Newton's method, which
+//! converges in a few iterations even for strong distortion. Images are
+//! undistorted with a lookup table giving, for each pixel of the undistorted
+//! image, the position in the distorted image. The lookup table is computed
+//! when first needed and kept for further images.
+//!
+//! The undistorted coordinates are those of [UndistortedPixel], i.e. those of
+//! the projection matrix of the intrinsic parameters.
+
+use std::sync::{Arc, Mutex, OnceLock};
+
+use nalgebra::{Matrix2, Matrix3, Point2, RealField, Vector2, Vector3};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::{Camera, DistortedPixel, MvgError, Result, RosOpenCvIntrinsics, UndistortedPixel};
+
+/// Maximum number of Newton iterations when undistorting a point.
+const MAX_ITERATIONS: usize = 20;
+
+/// Undistortion of the points and images of one camera.
+pub struct UndistortionMap<R: RealField + Copy> {
+    intrinsics: RosOpenCvIntrinsics<R>,
+    width: usize,
+    height: usize,
+    /// Inverse of the camera matrix.
+    k_inv: Matrix3<R>,
+    /// Maps normalized undistorted coordinates to undistorted pixels.
+    rect_to_p: Matrix3<R>,
+    /// Inverse of `rect_to_p`.
+    p_to_rect: Matrix3<R>,
+    /// Distortion coefficients `[k1, k2, p1, p2, k3]`.
+    dist: [R; 5],
+    /// For each pixel of the undistorted image (row-major), the position in
+    /// the distorted image.
+    lut: OnceLock<Vec<[f32; 2]>>,
+}
+
+impl<R: RealField + Copy> std::fmt::Debug for UndistortionMap<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UndistortionMap")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("lut_computed", &self.lut.get().is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: RealField + Default + Serialize + Copy> UndistortionMap<R> {
+    /// Create the undistortion of a camera.
+    pub fn new(cam: &Camera<R>) -> Result<Self> {
+        let intrinsics = cam.intrinsics().clone();
+        let k_inv = intrinsics.k.try_inverse().ok_or(MvgError::InvalidShape)?;
+        let p33 = intrinsics.p.fixed_view::<3, 3>(0, 0).into_owned();
+        let rect_to_p = p33 * intrinsics.rect;
+        let p_to_rect = rect_to_p.try_inverse().ok_or(MvgError::InvalidRectMatrix)?;
+        let d = &intrinsics.distortion;
+        let dist = [
+            d.radial1(),
+            d.radial2(),
+            d.tangential1(),
+            d.tangential2(),
+            d.radial3(),
+        ];
+        Ok(Self {
+            width: cam.width(),
+            height: cam.height(),
+            intrinsics,
+            k_inv,
+            rect_to_p,
+            p_to_rect,
+            dist,
+            lut: OnceLock::new(),
+        })
+    }
+}
+
+impl<R: RealField + Copy> UndistortionMap<R> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Apply the distortion model to normalized coordinates, returning the
+    /// distorted normalized coordinates and their Jacobian.
+    fn distort_normalized(&self, x: R, y: R) -> (Vector2<R>, Matrix2<R>) {
+        let [k1, k2, p1, p2, k3] = self.dist;
+        let one = R::one();
+        let two: R = nalgebra::convert(2.0);
+        let three: R = nalgebra::convert(3.0);
+        let six: R = nalgebra::convert(6.0);
+
+        let r2 = x * x + y * y;
+        let radial = one + r2 * (k1 + r2 * (k2 + r2 * k3));
+        // Derivative of `radial` with respect to `r2`.
+        let d_radial = k1 + r2 * (two * k2 + three * k3 * r2);
+
+        let xd = x * radial + two * p1 * x * y + p2 * (r2 + two * x * x);
+        let yd = y * radial + p1 * (r2 + two * y * y) + two * p2 * x * y;
+
+        let dxd_dx = radial + two * x * x * d_radial + two * p1 * y + six * p2 * x;
+        let dxd_dy = two * x * y * d_radial + two * p1 * x + two * p2 * y;
+        let dyd_dx = two * x * y * d_radial + two * p1 * x + two * p2 * y;
+        let dyd_dy = radial + two * y * y * d_radial + six * p1 * y + two * p2 * x;
+
+        (
+            Vector2::new(xd, yd),
+            Matrix2::new(dxd_dx, dxd_dy, dyd_dx, dyd_dy),
+        )
+    }
+
+    /// Undistort a point.
+    ///
+    /// The distortion model is inverted with Newton's method starting at the
+    /// distorted position.
+    pub fn undistort(&self, pt: &DistortedPixel<R>) -> UndistortedPixel<R> {
+        let tol: R = nalgebra::convert(1e-12);
+        let target = self.k_inv * pt.coords.to_homogeneous();
+        let target = Vector2::new(target.x / target.z, target.y / target.z);
+
+        let mut xy = target;
+        for _ in 0..MAX_ITERATIONS {
+            let (distorted, jacobian) = self.distort_normalized(xy.x, xy.y);
+            let residual = distorted - target;
+            if residual.norm_squared() < tol * tol {
+                break;
+            }
+            let Some(step) = jacobian.lu().solve(&residual) else {
+                break;
+            };
+            xy -= step;
+        }
+
+        let undistorted = self.rect_to_p * Vector3::new(xy.x, xy.y, R::one());
+        UndistortedPixel {
+            coords: Point2::new(undistorted.x / undistorted.z, undistorted.y / undistorted.z),
+        }
+    }
+
+    /// Distort a point.
+    pub fn distort(&self, pt: &UndistortedPixel<R>) -> DistortedPixel<R> {
+        let ray = self.p_to_rect * pt.coords.to_homogeneous();
+        let (distorted, _) = self.distort_normalized(ray.x / ray.z, ray.y / ray.z);
+        let px = self.intrinsics.k * Vector3::new(distorted.x, distorted.y, R::one());
+        DistortedPixel {
+            coords: Point2::new(px.x / px.z, px.y / px.z),
+        }
+    }
+}
+
+impl UndistortionMap<f64> {
+    fn lut(&self) -> &[[f32; 2]] {
+        self.lut.get_or_init(|| {
+            (0..self.width * self.height)
+                .into_par_iter()
+                .map(|i| {
+                    let pt = UndistortedPixel {
+                        coords: Point2::new((i % self.width) as f64, (i / self.width) as f64),
+                    };
+                    let d = self.distort(&pt);
+                    [d.coords.x as f32, d.coords.y as f32]
+                })
+                .collect()
+        })
+    }
+
+    /// Undistort an 8-bit image with `channels` interleaved channels (e.g. 1
+    /// for mono8 or 3 for RGB8) using bilinear interpolation.
+    ///
+    /// `src` must be an image of the size of the camera with rows `stride`
+    /// bytes apart. The returned image has rows `width * channels` bytes
+    /// apart. Pixels whose position in the distorted image is outside it are
+    /// black.
+    pub fn remap_u8(&self, src: &[u8], stride: usize, channels: usize) -> Result<Vec<u8>> {
+        let (w, h) = (self.width, self.height);
+        if w == 0 || h == 0 {
+            return Ok(vec![]);
+        }
+        if channels == 0 || stride < w * channels || src.len() < stride * (h - 1) + w * channels {
+            return Err(MvgError::InvalidShape);
+        }
+        let lut = self.lut();
+        let row_len = w * channels;
+        let mut dst = vec![0u8; row_len * h];
+        dst.par_chunks_mut(row_len)
+            .zip(lut.par_chunks(w))
+            .for_each(|(dst_row, lut_row)| {
+                for (dst_px, [x, y]) in dst_row.chunks_exact_mut(channels).zip(lut_row) {
+                    let (x0, y0) = (x.floor(), y.floor());
+                    if x0 < 0.0 || y0 < 0.0 || x0 as usize + 1 >= w || y0 as usize + 1 >= h {
+                        continue;
+                    }
+                    let (fx, fy) = (x - x0, y - y0);
+                    let idx = y0 as usize * stride + x0 as usize * channels;
+                    for (c, dst_val) in dst_px.iter_mut().enumerate() {
+                        let v00 = src[idx + c] as f32;
+                        let v01 = src[idx + channels + c] as f32;
+                        let v10 = src[idx + stride + c] as f32;
+                        let v11 = src[idx + stride + channels + c] as f32;
+                        let top = v00 + (v01 - v00) * fx;
+                        let bottom = v10 + (v11 - v10) * fx;
+                        *dst_val = (top + (bottom - top) * fy).round() as u8;
+                    }
+                }
+            });
+        Ok(dst)
+    }
+}
+
+/// Undistortion maps of several cameras, created when first needed.
+///
+/// This can be shared between threads. If the calibration of a camera
+/// changes, its map is created again.
+#[derive(Debug, Default)]
+pub struct UndistortionMapCache<R: RealField + Copy> {
+    maps: Mutex<std::collections::BTreeMap<String, Arc<UndistortionMap<R>>>>,
+}
+
+impl<R: RealField + Default + Serialize + Copy> UndistortionMapCache<R> {
+    pub fn new() -> Self {
+        Self {
+            maps: Mutex::new(Default::default()),
+        }
+    }
+
+    /// Return the undistortion map of camera `name`.
+    pub fn get(&self, name: &str, cam: &Camera<R>) -> Result<Arc<UndistortionMap<R>>> {
+        let mut maps = self.maps.lock().unwrap();
+        if let Some(map) = maps.get(name) {
+            if &map.intrinsics == cam.intrinsics()
+                && map.width == cam.width()
+                && map.height == cam.height()
+            {
+                return Ok(map.clone());
+            }
+        }
+        let map = Arc::new(UndistortionMap::new(cam)?);
+        maps.insert(name.to_string(), map.clone());
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Distortion, ExtrinsicParameters};
+    use nalgebra::Vector5;
+
+    fn strongly_distorted_camera() -> Camera<f64> {
+        let distortion = Distortion::from_opencv_vec(Vector5::new(-0.4, 0.2, 0.002, -0.001, -0.05));
+        let intrinsics = RosOpenCvIntrinsics::from_params_with_distortion(
+            300.0, 0.0, 310.0, 160.0, 120.0, distortion,
+        );
+        let extrinsics = ExtrinsicParameters::from_view(
+            &Vector3::new(0.0, 0.0, 1.0),
+            &Vector3::new(0.0, 0.0, 0.0),
+            &nalgebra::Unit::new_normalize(Vector3::y()),
+        );
+        Camera::new(320, 240, extrinsics, intrinsics).unwrap()
+    }
+
+    #[test]
+    fn test_undistort_points() {
+        let cam = strongly_distorted_camera();
+        let map = UndistortionMap::new(&cam).unwrap();
+        for (x, y) in [(0.0, 0.0), (160.0, 120.0), (319.0, 5.0), (20.0, 230.0)] {
+            let undistorted = UndistortedPixel {
+                coords: Point2::new(x, y),
+            };
+            // Distortion agrees with the camera.
+            let distorted = map.distort(&undistorted);
+            let ud: opencv_ros_camera::UndistortedPixels<f64, nalgebra::U1, _> =
+                (&undistorted).into();
+            let expected: DistortedPixel<f64> = cam.intrinsics().distort(&ud).into();
+            approx::assert_relative_eq!(distorted.coords, expected.coords, epsilon = 1e-9);
+            // Undistortion inverts distortion.
+            let roundtrip = map.undistort(&distorted);
+            approx::assert_relative_eq!(roundtrip.coords, undistorted.coords, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_remap() {
+        let cam = strongly_distorted_camera();
+        let map = UndistortionMap::new(&cam).unwrap();
+        let (w, h) = (cam.width(), cam.height());
+        let stride = w + 3;
+        // Image with a horizontal gradient.
+        let src: Vec<u8> = (0..stride * h)
+            .map(|i| ((i % stride) % 256) as u8)
+            .collect();
+        let dst = map.remap_u8(&src, stride, 1).unwrap();
+        assert_eq!(dst.len(), w * h);
+        // Sample the undistorted image at the principal point, which is not
+        // moved by the distortion.
+        let (cx, cy) = (160, 120);
+        assert_eq!(dst[cy * w + cx], 160);
+
+        assert!(map.remap_u8(&src[..10], stride, 1).is_err());
+    }
+
+    #[test]
+    fn test_cache() {
+        let cam = strongly_distorted_camera();
+        let cache = UndistortionMapCache::new();
+        let a = cache.get("cam", &cam).unwrap();
+        let b = cache.get("cam", &cam).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        let other = Camera::new(
+            cam.width(),
+            cam.height(),
+            cam.extrinsics().clone(),
+            crate::make_default_intrinsics(),
+        )
+        .unwrap();
+        let c = cache.get("cam", &other).unwrap();
+        assert!(!Arc::ptr_eq(&a, &c));
+    }
+}
@@ -0,0 +1,265 @@
+//! Metric scaling of a calibration from observations of a two-point wand.
+//!
+//! Self-calibration (e.g. with MCSC) determines the camera geometry only up to
+//! an unknown similarity transform. When a wand with two markers separated by
+//! a known distance is waved through the tracking volume, the distance between
+//! the two triangulated wand ends constrains the scale of the reconstruction.
+//! This module triangulates both wand ends in each observation, resolves which
+//! 2D detection corresponds to which wand end in each camera, and computes the
+//! scale factor which makes the wand have its known length. The wand ends are
+//! triangulated robustly, ignoring cameras which detected something else.
+//!
+//! The wand length is not a residual of a bundle adjustment. This workspace
+//! has no bundle adjuster; the calibration comes from MCSC. Scaling does not
+//! change any reprojection error, so the known length cannot improve the
+//! camera geometry, only fix its scale, which scaling the finished
+//! calibration by the median wand length does.
+
+use nalgebra::{Matrix3, RealField, Vector3};
+use serde::Serialize;
+
+use crate::{
+    robust_triangulation::RansacParams, MultiCameraSystem, MvgError, PointWorldFrame, Result,
+    UndistortedPixel,
+};
+
+/// The two wand ends as seen by a single camera in a single frame.
+///
+/// The order of the two points is arbitrary and need not be consistent across
+/// cameras. The correspondence is resolved during triangulation.
+#[derive(Debug, Clone)]
+pub struct WandView<R: RealField + Copy> {
+    pub cam_name: String,
+    pub ends: [UndistortedPixel<R>; 2],
+}
+
+/// All views of the wand at a single instant.
+#[derive(Debug, Clone)]
+pub struct WandObservation<R: RealField + Copy> {
+    pub views: Vec<WandView<R>>,
+}
+
+/// The triangulated wand ends of a single observation.
+#[derive(Debug, Clone)]
+pub struct WandEnds<R: RealField + Copy> {
+    pub ends: [PointWorldFrame<R>; 2],
+    /// Mean reprojection distance (in pixels) over both ends in the inlier
+    /// cameras.
+    pub mean_reproj_dist: R,
+}
+
+impl<R: RealField + Copy> WandEnds<R> {
+    /// The distance between the two wand ends.
+    pub fn length(&self) -> R {
+        nalgebra::distance(&self.ends[0].coords, &self.ends[1].coords)
+    }
+}
+
+/// The result of [estimate_wand_scale].
+#[derive(Debug, Clone)]
+pub struct WandScale<R: RealField + Copy> {
+    /// Multiply the coordinates of the original calibration by this to obtain
+    /// metric coordinates.
+    pub scale: R,
+    /// Median wand length in the units of the original calibration.
+    pub median_length: R,
+    /// Median absolute deviation of the wand lengths relative to the median.
+    ///
+    /// This is a unitless measure of calibration quality. A good calibration
+    /// has a small value here (e.g. below 0.01).
+    pub relative_mad: R,
+    /// Number of observations used.
+    pub n_observations: usize,
+}
+
+impl<R: RealField + Default + Serialize + Copy> MultiCameraSystem<R> {
+    /// Triangulate both ends of the wand in a single observation.
+    ///
+    /// At least two views are required. The first view is used as reference
+    /// and, for each other view, the correspondence of wand ends which results
+    /// in the lower reprojection error when triangulated with the reference
+    /// view is chosen. Each end is then triangulated with
+    /// [MultiCameraSystem::find3d_ransac], ignoring outlier views.
+    pub fn triangulate_wand(
+        &self,
+        obs: &WandObservation<R>,
+        params: &RansacParams<R>,
+    ) -> Result<WandEnds<R>> {
+        if obs.views.len() < 2 {
+            return Err(MvgError::NotEnoughPoints);
+        }
+        let reference = &obs.views[0];
+
+        let mut end0 = vec![(reference.cam_name.clone(), reference.ends[0].clone())];
+        let mut end1 = vec![(reference.cam_name.clone(), reference.ends[1].clone())];
+
+        for view in obs.views[1..].iter() {
+            let straight = self.pair_cost(reference, view, false)?;
+            let swapped = self.pair_cost(reference, view, true)?;
+            let (a, b) = if swapped < straight { (1, 0) } else { (0, 1) };
+            end0.push((view.cam_name.clone(), view.ends[a].clone()));
+            end1.push((view.cam_name.clone(), view.ends[b].clone()));
+        }
+
+        let p0 = self.find3d_ransac(&end0, params)?;
+        let p1 = self.find3d_ransac(&end1, params)?;
+        let two: R = nalgebra::convert(2.0);
+        Ok(WandEnds {
+            mean_reproj_dist: (p0.mean_reproj_dist + p1.mean_reproj_dist) / two,
+            ends: [p0.point, p1.point],
+        })
+    }
+
+    /// Sum of reprojection errors of both wand ends triangulated from two views.
+    fn pair_cost(&self, a: &WandView<R>, b: &WandView<R>, swap: bool) -> Result<R> {
+        let (i0, i1) = if swap { (1, 0) } else { (0, 1) };
+        let e0 = [
+            (a.cam_name.clone(), a.ends[0].clone()),
+            (b.cam_name.clone(), b.ends[i0].clone()),
+        ];
+        let e1 = [
+            (a.cam_name.clone(), a.ends[1].clone()),
+            (b.cam_name.clone(), b.ends[i1].clone()),
+        ];
+        let r0 = self.find3d_and_cum_reproj_dist(&e0)?;
+        let r1 = self.find3d_and_cum_reproj_dist(&e1)?;
+        Ok(r0.cum_reproj_dist + r1.cum_reproj_dist)
+    }
+
+    /// Return a copy of this system scaled such that the wand has the given
+    /// length, together with the estimated scale.
+    pub fn scale_to_wand(
+        &self,
+        observations: &[WandObservation<R>],
+        wand_length: R,
+        max_mean_reproj_dist: Option<R>,
+    ) -> Result<(Self, WandScale<R>)> {
+        let scale = estimate_wand_scale(self, observations, wand_length, max_mean_reproj_dist)?;
+        let scaled = self.align(scale.scale, Matrix3::identity(), Vector3::zeros())?;
+        Ok((scaled, scale))
+    }
+}
+
+/// Estimate the factor which scales `system` to have metric units.
+///
+/// Observations whose mean reprojection distance exceeds
+/// `max_mean_reproj_dist` (if given) are ignored, as are observations which
+/// cannot be triangulated. Within an observation, views farther than
+/// `max_mean_reproj_dist` (or the default of [RansacParams]) from the wand
+/// ends are outliers. The median of the remaining wand lengths is used so that
+/// occasional mis-detections do not bias the result.
+pub fn estimate_wand_scale<R>(
+    system: &MultiCameraSystem<R>,
+    observations: &[WandObservation<R>],
+    wand_length: R,
+    max_mean_reproj_dist: Option<R>,
+) -> Result<WandScale<R>>
+where
+    R: RealField + Default + Serialize + Copy + num_traits::float::TotalOrder,
+{
+    let mut params = RansacParams::default();
+    if let Some(max_dist) = max_mean_reproj_dist {
+        params.max_reproj_dist = max_dist;
+    }
+    let mut lengths = Vec::with_capacity(observations.len());
+    for obs in observations.iter() {
+        let ends = match system.triangulate_wand(obs, &params) {
+            Ok(ends) => ends,
+            Err(MvgError::NotEnoughPoints) => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(max_dist) = max_mean_reproj_dist {
+            if ends.mean_reproj_dist > max_dist {
+                continue;
+            }
+        }
+        let length = ends.length();
+        // A degenerate triangulation gives no usable length.
+        if length.is_finite() {
+            lengths.push(length);
+        }
+    }
+
+    let median_length = crate::median(&lengths).ok_or(MvgError::NotEnoughPoints)?;
+    let abs_devs: Vec<R> = lengths.iter().map(|l| (*l - median_length).abs()).collect();
+    let mad = crate::median(&abs_devs).ok_or(MvgError::NotEnoughPoints)?;
+
+    Ok(WandScale {
+        scale: wand_length / median_length,
+        median_length,
+        relative_mad: mad / median_length,
+        n_observations: lengths.len(),
+    })
+}
+
+#[test]
+fn test_wand_scale() {
+    use nalgebra::{Point3, Unit};
+    use std::collections::BTreeMap;
+
+    let mut cams = BTreeMap::new();
+    for (name, x) in [("cam1", -1.0), ("cam2", 1.0), ("cam3", 0.2)] {
+        let extrinsics = cam_geom::ExtrinsicParameters::from_view(
+            &Vector3::new(x, 0.3, 5.0),
+            &Vector3::new(0.0, 0.0, 0.0),
+            &Unit::new_normalize(Vector3::new(0.0, 1.0, 0.0)),
+        );
+        let intrinsics = crate::make_default_intrinsics();
+        let cam = crate::Camera::new(640, 480, extrinsics, intrinsics).unwrap();
+        cams.insert(name.to_string(), cam);
+    }
+    let system = MultiCameraSystem::new(cams);
+
+    // Wand of length 0.5 in the calibration's units.
+    let true_ends = [
+        (Point3::new(0.0, 0.0, 0.0), Point3::new(0.5, 0.0, 0.0)),
+        (Point3::new(0.1, 0.2, 0.0), Point3::new(0.1, 0.2, 0.5)),
+        (Point3::new(-0.2, 0.1, 0.3), Point3::new(-0.2, -0.4, 0.3)),
+    ];
+
+    let observations: Vec<_> = true_ends
+        .iter()
+        .map(|(a, b)| {
+            let views = system
+                .cams()
+                .iter()
+                .enumerate()
+                .map(|(i, (name, cam))| {
+                    let pa = cam.project_3d_to_pixel(&PointWorldFrame { coords: *a });
+                    let pb = cam.project_3d_to_pixel(&PointWorldFrame { coords: *b });
+                    // Shuffle the order of the ends in some cameras.
+                    let ends = if i % 2 == 0 { [pa, pb] } else { [pb, pa] };
+                    WandView {
+                        cam_name: name.clone(),
+                        ends,
+                    }
+                })
+                .collect();
+            WandObservation { views }
+        })
+        .collect();
+
+    // Real wand is 0.25 m long.
+    let (scaled, scale) = system.scale_to_wand(&observations, 0.25, None).unwrap();
+    approx::assert_relative_eq!(scale.median_length, 0.5, epsilon = 1e-6);
+    approx::assert_relative_eq!(scale.scale, 0.5, epsilon = 1e-6);
+    assert_eq!(scale.n_observations, 3);
+
+    for obs in observations.iter() {
+        let ends = scaled
+            .triangulate_wand(obs, &RansacParams::default())
+            .unwrap();
+        approx::assert_relative_eq!(ends.length(), 0.25, epsilon = 1e-6);
+    }
+
+    // A camera which detected something else is ignored.
+    let mut corrupted = observations[0].clone();
+    for end in corrupted.views[2].ends.iter_mut() {
+        end.coords.y += 40.0;
+    }
+    let ends = system
+        .triangulate_wand(&corrupted, &RansacParams::default())
+        .unwrap();
+    approx::assert_relative_eq!(ends.length(), 0.5, epsilon = 1e-6);
+    approx::assert_relative_eq!(ends.mean_reproj_dist, 0.0, epsilon = 1e-6);
+}
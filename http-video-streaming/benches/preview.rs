@@ -0,0 +1,86 @@
+//! Benchmarks of the conversion and JPEG encoding of preview frames.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use machine_vision_formats::{
+    owned::OImage,
+    pixel_format::{BayerRG8, Mono8, RGB8},
+    PixelFormat,
+};
+
+/// The default JPEG quality of the preview.
+const JPEG_QUALITY: u8 = 80;
+
+/// Sizes of common camera sensors.
+const SENSOR_SIZES: [(u32, u32); 3] = [(640, 480), (1440, 1080), (2048, 2048)];
+
+/// An image with a gradient and some structure, so that JPEG encoding does
+/// not take shortcuts on uniform blocks.
+fn test_image<FMT: PixelFormat>(w: u32, h: u32) -> OImage<FMT> {
+    let fmt = machine_vision_formats::pixel_format::pixfmt::<FMT>().unwrap();
+    let stride = w as usize * fmt.bits_per_pixel() as usize / 8;
+    let mut data = vec![0u8; stride * h as usize];
+    for (row, line) in data.chunks_exact_mut(stride).enumerate() {
+        for (col, px) in line.iter_mut().enumerate() {
+            *px = ((row / 4 + col) % 256) as u8 ^ ((row * col) % 7) as u8;
+        }
+    }
+    OImage::new(w, h, stride, data).unwrap()
+}
+
+fn bench_convert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("convert");
+    for (w, h) in SENSOR_SIZES {
+        group.throughput(Throughput::Elements(w as u64 * h as u64));
+        let size = format!("{w}x{h}");
+
+        let mono = test_image::<Mono8>(w, h);
+        group.bench_function(BenchmarkId::new("mono8_to_rgb8", &size), |b| {
+            b.iter(|| convert_image::convert_ref::<_, RGB8>(black_box(&mono)).unwrap())
+        });
+
+        let bayer = test_image::<BayerRG8>(w, h);
+        group.bench_function(BenchmarkId::new("bayerrg8_to_rgb8", &size), |b| {
+            b.iter(|| convert_image::convert_ref::<_, RGB8>(black_box(&bayer)).unwrap())
+        });
+
+        let rgb = test_image::<RGB8>(w, h);
+        group.bench_function(BenchmarkId::new("rgb8_to_mono8", &size), |b| {
+            b.iter(|| convert_image::convert_ref::<_, Mono8>(black_box(&rgb)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_jpeg(c: &mut Criterion) {
+    let mut group = c.benchmark_group("jpeg");
+    for (w, h) in SENSOR_SIZES {
+        group.throughput(Throughput::Elements(w as u64 * h as u64));
+        let size = format!("{w}x{h}");
+
+        let mono = test_image::<Mono8>(w, h);
+        group.bench_function(BenchmarkId::new("mono8", &size), |b| {
+            b.iter(|| {
+                convert_image::frame_to_encoded_buffer(
+                    black_box(&mono),
+                    convert_image::EncoderOptions::Jpeg(JPEG_QUALITY),
+                )
+                .unwrap()
+            })
+        });
+
+        let rgb = test_image::<RGB8>(w, h);
+        group.bench_function(BenchmarkId::new("rgb8", &size), |b| {
+            b.iter(|| {
+                convert_image::frame_to_encoded_buffer(
+                    black_box(&rgb),
+                    convert_image::EncoderOptions::Jpeg(JPEG_QUALITY),
+                )
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_convert, bench_jpeg);
+criterion_main!(benches);
@@ -28,10 +28,114 @@ pub struct ToClient {
     pub valid_display: Option<Shape>,
     /// Annotations associated with this particular image, e.g. from tracking.
     pub annotations: Vec<DrawableShape>,
+    /// Intensity histogram of the frame.
+    #[serde(default)]
+    pub histogram: Option<Histogram>,
+    /// The region of the camera image in the frame, if it is cropped.
+    ///
+    /// The annotations and `valid_display` are in the pixel coordinates of the
+    /// full camera image.
+    #[serde(default)]
+    pub crop: Option<PreviewCrop>,
     pub ts_rfc3339: String, // timestamp in RFC3339 format
     pub ck: ConnectionKey,
 }
 
+/// A rectangular region of the camera image, in pixels, shown in the live
+/// view.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PreviewCrop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PreviewCrop {
+    /// The part of this region which is within an image of `width` x `height`
+    /// pixels, with its corners moved to even coordinates so that Bayer and
+    /// YUV422 images keep their layout, or `None` if it is empty.
+    pub fn clamp(&self, width: u32, height: u32) -> Option<Self> {
+        let x = self.x.min(width) & !1;
+        let y = self.y.min(height) & !1;
+        let x1 = self.x.saturating_add(self.width).min(width) & !1;
+        let y1 = self.y.saturating_add(self.height).min(height) & !1;
+        (x1 > x && y1 > y).then_some(Self {
+            x,
+            y,
+            width: x1 - x,
+            height: y1 - y,
+        })
+    }
+}
+
+/// Number of bins of [Histogram], evenly dividing the intensities 0-255.
+pub const HISTOGRAM_BINS: usize = 64;
+
+/// Fraction of saturated pixels above which a frame is overexposed.
+const OVEREXPOSED_FRACTION: f64 = 0.01;
+
+/// A frame is underexposed if 99% of its pixels are darker than this.
+const UNDEREXPOSED_LEVEL: u8 = 32;
+
+/// Intensity histogram of a subsample of the pixels of a frame.
+///
+/// For color images, the intensity of a pixel is that of its brightest
+/// channel, so that saturation of any channel is detected.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Histogram {
+    /// The number of pixels in each of [HISTOGRAM_BINS] bins.
+    pub counts: Vec<u32>,
+    /// The number of pixels with intensity 255.
+    pub saturated: u32,
+}
+
+/// A problem with the exposure of a frame, see [Histogram::exposure_warning].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExposureWarning {
+    /// This fraction of the pixels is saturated.
+    Overexposed(f64),
+    Underexposed,
+}
+
+impl Histogram {
+    /// Compute the histogram of `levels`.
+    pub fn from_levels(levels: impl Iterator<Item = u8>) -> Self {
+        let mut counts = vec![0; HISTOGRAM_BINS];
+        let mut saturated = 0;
+        for level in levels {
+            counts[usize::from(level) * HISTOGRAM_BINS / 256] += 1;
+            if level == 255 {
+                saturated += 1;
+            }
+        }
+        Self { counts, saturated }
+    }
+
+    /// The number of pixels.
+    pub fn total(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+
+    /// Whether the frame is overexposed or underexposed.
+    pub fn exposure_warning(&self) -> Option<ExposureWarning> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+        let saturated_fraction = f64::from(self.saturated) / f64::from(total);
+        if saturated_fraction > OVEREXPOSED_FRACTION {
+            return Some(ExposureWarning::Overexposed(saturated_fraction));
+        }
+        let bright_bins = usize::from(UNDEREXPOSED_LEVEL) * HISTOGRAM_BINS / 256;
+        let bright: u32 = self.counts[bright_bins..].iter().sum();
+        if f64::from(bright) < 0.01 * f64::from(total) {
+            return Some(ExposureWarning::Underexposed);
+        }
+        None
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct CircleParams {
     pub center_x: i16,
@@ -163,6 +267,30 @@ mod tests {
 
 pub const VIDEO_STREAM_EVENT_NAME: &str = "http-video-streaming";
 
+#[test]
+fn test_histogram() {
+    let hist = Histogram::from_levels([0, 3, 4, 255, 255].into_iter());
+    assert_eq!(hist.counts.len(), HISTOGRAM_BINS);
+    assert_eq!(hist.counts[0], 2);
+    assert_eq!(hist.counts[1], 1);
+    assert_eq!(hist.counts[HISTOGRAM_BINS - 1], 2);
+    assert_eq!(hist.total(), 5);
+    assert_eq!(
+        hist.exposure_warning(),
+        Some(ExposureWarning::Overexposed(0.4))
+    );
+
+    let dark = Histogram::from_levels(std::iter::repeat(10).take(1000));
+    assert_eq!(dark.exposure_warning(), Some(ExposureWarning::Underexposed));
+
+    let good = Histogram::from_levels((0..=254).cycle().take(1000));
+    assert_eq!(good.exposure_warning(), None);
+    assert_eq!(
+        Histogram::from_levels(std::iter::empty()).exposure_warning(),
+        None
+    );
+}
+
 #[test]
 fn test_polygon_from_yaml() {
     let mystr = "Polygon:
@@ -204,3 +332,24 @@ fn test_multiple_circles_yaml_roundtrip() {
     let circles2: Shape = serde_yaml::from_str(&mystr).unwrap();
     assert_eq!(circles, circles2);
 }
+
+#[test]
+fn test_preview_crop_clamp() {
+    let crop = PreviewCrop {
+        x: 101,
+        y: 50,
+        width: 1000,
+        height: 100,
+    };
+    assert_eq!(
+        crop.clamp(640, 480),
+        Some(PreviewCrop {
+            x: 100,
+            y: 50,
+            width: 540,
+            height: 100,
+        })
+    );
+    // Outside of a smaller image.
+    assert_eq!(crop.clamp(100, 480), None);
+}
@@ -0,0 +1,88 @@
+use basic_frame::DynamicFrame;
+use http_video_streaming_types::PreviewCrop;
+use machine_vision_formats::Stride;
+
+/// Crop `frame` to the part of `crop` within it.
+///
+/// Returns `None` if the frame is not cropped, because the region is empty or
+/// covers the entire frame or because the pixel format does not have a whole
+/// number of bytes per pixel. Otherwise, the cropped frame is returned with the
+/// region actually used.
+pub(crate) fn crop(
+    frame: &DynamicFrame,
+    crop: &PreviewCrop,
+) -> Option<(DynamicFrame, PreviewCrop)> {
+    let (width, height) = (frame.width(), frame.height());
+    let crop = crop.clamp(width, height)?;
+    if crop.width == width && crop.height == height {
+        return None;
+    }
+    let pixfmt = frame.pixel_format();
+    let bits_per_pixel = usize::from(pixfmt.bits_per_pixel());
+    if bits_per_pixel % 8 != 0 {
+        return None;
+    }
+    let bytes_per_pixel = bits_per_pixel / 8;
+
+    let src_stride = frame.stride();
+    let src = frame.image_data_without_format();
+    let stride = crop.width as usize * bytes_per_pixel;
+    let start = crop.x as usize * bytes_per_pixel;
+    let mut image_data = Vec::with_capacity(stride * crop.height as usize);
+    for row in crop.y as usize..(crop.y + crop.height) as usize {
+        let row_start = row * src_stride + start;
+        image_data.extend_from_slice(&src[row_start..row_start + stride]);
+    }
+    let cropped = DynamicFrame::new(
+        crop.width,
+        crop.height,
+        stride.try_into().unwrap(),
+        image_data,
+        pixfmt,
+    );
+    Some((cropped, crop))
+}
+
+#[test]
+fn test_crop() {
+    // 6x4 frame with a stride of 7.
+    #[rustfmt::skip]
+    let image_data = vec![
+        0, 1, 2, 3, 4, 5, 99,
+        10, 11, 12, 13, 14, 15, 99,
+        20, 21, 22, 23, 24, 25, 99,
+        30, 31, 32, 33, 34, 35, 99,
+    ];
+    let frame = DynamicFrame::new(6, 4, 7, image_data, machine_vision_formats::PixFmt::Mono8);
+    let region = PreviewCrop {
+        x: 3,
+        y: 2,
+        width: 10,
+        height: 2,
+    };
+    let (cropped, used) = crop(&frame, &region).unwrap();
+    assert_eq!(
+        used,
+        PreviewCrop {
+            x: 2,
+            y: 2,
+            width: 4,
+            height: 2,
+        }
+    );
+    assert_eq!(cropped.width(), 4);
+    assert_eq!(cropped.height(), 2);
+    assert_eq!(
+        cropped.image_data_without_format(),
+        &[22, 23, 24, 25, 32, 33, 34, 35]
+    );
+
+    // The entire frame is not cropped.
+    let everything = PreviewCrop {
+        x: 0,
+        y: 0,
+        width: 100,
+        height: 100,
+    };
+    assert!(crop(&frame, &everything).is_none());
+}
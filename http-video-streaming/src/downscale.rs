@@ -0,0 +1,82 @@
+use basic_frame::{match_all_dynamic_fmts, BasicFrame, DynamicFrame};
+use machine_vision_formats::{pixel_format::RGB8, ImageData, ImageStride, Stride};
+
+use crate::Result;
+
+/// The integer factor by which a frame `width` pixels wide must be downscaled
+/// to be at most `max_width` pixels wide.
+pub(crate) fn downscale_factor(width: u32, max_width: Option<u32>) -> u32 {
+    match max_width {
+        Some(max_width) if max_width > 0 => width.div_ceil(max_width).max(1),
+        _ => 1,
+    }
+}
+
+/// Downscale `frame` by averaging blocks of `factor` x `factor` pixels.
+///
+/// Mono8 frames remain Mono8, all other formats are converted to RGB8.
+/// Remaining rows and columns which do not fill a block are dropped.
+pub(crate) fn downscale(frame: &DynamicFrame, factor: u32) -> Result<DynamicFrame> {
+    Ok(match frame {
+        DynamicFrame::Mono8(x) => DynamicFrame::Mono8(box_filter(x, 1, factor)),
+        _ => {
+            let rgb = match_all_dynamic_fmts!(frame, x, convert_image::convert_ref::<_, RGB8>(x))?;
+            DynamicFrame::RGB8(box_filter(&rgb, 3, factor))
+        }
+    })
+}
+
+fn box_filter<F>(src: &dyn ImageStride<F>, channels: usize, factor: u32) -> BasicFrame<F> {
+    let width = src.width() / factor;
+    let height = src.height() / factor;
+    let factor = factor as usize;
+    let n = u32::try_from(factor * factor).unwrap();
+    let src_stride = src.stride();
+    let src_data = src.image_data();
+
+    let stride = width as usize * channels;
+    let mut image_data = Vec::with_capacity(stride * height as usize);
+    for row in 0..height as usize {
+        let rows = &src_data[row * factor * src_stride..];
+        for col in 0..width as usize {
+            for channel in 0..channels {
+                let mut sum = 0u32;
+                for dy in 0..factor {
+                    let src_row = &rows[dy * src_stride..];
+                    for dx in 0..factor {
+                        sum += u32::from(src_row[(col * factor + dx) * channels + channel]);
+                    }
+                }
+                image_data.push(((sum + n / 2) / n) as u8);
+            }
+        }
+    }
+    BasicFrame {
+        width,
+        height,
+        stride: stride.try_into().unwrap(),
+        image_data,
+        pixel_format: std::marker::PhantomData,
+    }
+}
+
+#[test]
+fn test_downscale_mono8() {
+    assert_eq!(downscale_factor(1280, Some(640)), 2);
+    assert_eq!(downscale_factor(1281, Some(640)), 3);
+    assert_eq!(downscale_factor(320, Some(640)), 1);
+    assert_eq!(downscale_factor(1280, None), 1);
+
+    // 5x3 frame with a stride of 6.
+    #[rustfmt::skip]
+    let image_data = vec![
+        0, 2, 10, 20, 99, 0,
+        4, 6, 30, 40, 99, 0,
+        99, 99, 99, 99, 99, 0,
+    ];
+    let frame = DynamicFrame::new(5, 3, 6, image_data, machine_vision_formats::PixFmt::Mono8);
+    let small = downscale(&frame, 2).unwrap();
+    assert_eq!(small.width(), 2);
+    assert_eq!(small.height(), 1);
+    assert_eq!(small.image_data_without_format(), &[3, 25]);
+}
@@ -0,0 +1,70 @@
+use basic_frame::{match_all_dynamic_fmts, DynamicFrame};
+use http_video_streaming_types::Histogram;
+use machine_vision_formats::{pixel_format::Mono8, ImageData, ImageStride, Stride};
+
+use crate::Result;
+
+/// Only every this many-th row and column are included in the histogram.
+const SUBSAMPLE: usize = 4;
+
+/// The intensity histogram of `frame`.
+///
+/// Mono8, RGB8 and 8-bit Bayer frames are used directly, all other formats are
+/// converted to Mono8.
+pub(crate) fn histogram(frame: &DynamicFrame) -> Result<Histogram> {
+    Ok(match frame {
+        DynamicFrame::Mono8(x) => subsampled(x, 1),
+        DynamicFrame::RGB8(x) => subsampled(x, 3),
+        DynamicFrame::BayerRG8(x) => subsampled(x, 1),
+        DynamicFrame::BayerGB8(x) => subsampled(x, 1),
+        DynamicFrame::BayerGR8(x) => subsampled(x, 1),
+        DynamicFrame::BayerBG8(x) => subsampled(x, 1),
+        _ => {
+            let mono =
+                match_all_dynamic_fmts!(frame, x, convert_image::convert_ref::<_, Mono8>(x))?;
+            subsampled(&mono, 1)
+        }
+    })
+}
+
+/// The histogram of the brightest of the `channels` of each pixel.
+fn subsampled<F>(image: &dyn ImageStride<F>, channels: usize) -> Histogram {
+    let width = image.width() as usize;
+    let stride = image.stride();
+    let data = image.image_data();
+    let levels = (0..image.height() as usize)
+        .step_by(SUBSAMPLE)
+        .flat_map(move |row| {
+            let row_data = &data[row * stride..row * stride + width * channels];
+            row_data
+                .chunks_exact(channels)
+                .step_by(SUBSAMPLE)
+                .map(|pixel| pixel.iter().copied().max().unwrap())
+        });
+    Histogram::from_levels(levels)
+}
+
+#[test]
+fn test_histogram() {
+    use basic_frame::BasicFrame;
+
+    // An 8x8 RGB8 image with a stride larger than its width, whose left half
+    // is saturated in the red channel only.
+    let stride = 8 * 3 + 4;
+    let mut image_data = vec![10u8; stride * 8];
+    for row in 0..8 {
+        for col in 0..4 {
+            image_data[row * stride + col * 3] = 255;
+        }
+    }
+    let frame = DynamicFrame::RGB8(BasicFrame {
+        width: 8,
+        height: 8,
+        stride: stride as u32,
+        image_data,
+        pixel_format: std::marker::PhantomData,
+    });
+    let hist = histogram(&frame).unwrap();
+    assert_eq!(hist.total(), 4);
+    assert_eq!(hist.saturated, 2);
+}
@@ -1,8 +1,5 @@
-use http_video_streaming_types::StrokeStyle;
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use http_video_streaming_types::{Histogram, StrokeStyle};
+use std::{collections::HashMap, sync::Arc};
 
 use tokio_stream::StreamExt;
 
@@ -10,7 +7,13 @@ use basic_frame::DynamicFrame;
 use bui_backend_session_types::ConnectionKey;
 use event_stream_types::{ConnectionEvent, ConnectionEventType, EventChunkSender};
 
-pub use http_video_streaming_types::{CircleParams, DrawableShape, Point, Shape, ToClient};
+pub use http_video_streaming_types::{
+    CircleParams, DrawableShape, Point, PreviewCrop, Shape, ToClient,
+};
+
+mod crop;
+mod downscale;
+mod histogram;
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -22,11 +25,36 @@ pub enum Error {
     ConvertImageError(#[from] convert_image::Error),
 }
 
+/// Settings for encoding the frames sent to the browser.
+///
+/// These affect only the live view, not the recordings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreviewConfig {
+    /// JPEG quality, from 1 to 100.
+    pub jpeg_quality: u8,
+    /// If set, frames wider than this are downscaled by an integer factor.
+    pub max_width: Option<u32>,
+    /// If set, only this region of the frames is sent. This is done before
+    /// downscaling.
+    pub crop: Option<PreviewCrop>,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            jpeg_quality: 80,
+            max_width: None,
+            crop: None,
+        }
+    }
+}
+
 // future: use MediaSource API? https://w3c.github.io/media-source
 
 #[derive(Debug)]
 pub struct AnnotatedFrame {
-    pub frame: DynamicFrame,
+    /// The frame, shared with the other consumers of the acquired frames.
+    pub frame: Arc<DynamicFrame>,
     pub found_points: Vec<Point>,
     pub valid_display: Option<Shape>,
     pub annotations: Vec<DrawableShape>,
@@ -38,13 +66,84 @@ fn _test_annotated_frame_is_send() {
     implements::<AnnotatedFrame>();
 }
 
+/// A frame encoded for the browser, shared by all senders.
+struct EncodedFrame {
+    /// The frame from which this was encoded.
+    source: Arc<AnnotatedFrame>,
+    /// The settings with which this was encoded.
+    config: PreviewConfig,
+    data_url: String,
+    annotations: Vec<DrawableShape>,
+    histogram: Option<Histogram>,
+    crop: Option<PreviewCrop>,
+}
+
+impl EncodedFrame {
+    fn new(source: Arc<AnnotatedFrame>, config: PreviewConfig) -> Result<Self> {
+        let frame = &*source.frame;
+        let cropped = config.crop.and_then(|c| crop::crop(frame, &c));
+        let (frame, crop) = match &cropped {
+            Some((cropped_frame, crop)) => (cropped_frame, Some(*crop)),
+            None => (frame, None),
+        };
+        // The histogram is optional for the live view, so an error is only
+        // logged.
+        let histogram = histogram::histogram(frame)
+            .map_err(|e| tracing::warn!("computing histogram failed: {e}"))
+            .ok();
+        let factor = downscale::downscale_factor(frame.width(), config.max_width);
+        let small;
+        let frame = if factor > 1 {
+            small = downscale::downscale(frame, factor)?;
+            &small
+        } else {
+            frame
+        };
+        let bytes = basic_frame::match_all_dynamic_fmts!(
+            frame,
+            x,
+            convert_image::frame_to_encoded_buffer(
+                x,
+                convert_image::EncoderOptions::Jpeg(config.jpeg_quality),
+            )
+        )?;
+        let firehose_frame_base64 = base64::encode(&bytes);
+        let data_url = format!("data:image/jpeg;base64,{}", firehose_frame_base64);
+        let mut annotations = source.annotations.clone();
+        // Convert found points into normal annotations. (This should perhaps be done earlier.)
+        let green_stroke = StrokeStyle::from_rgb(0x7F, 0xFF, 0x7F);
+        for found_point in source.found_points.iter() {
+            let line_width = 5.0;
+            let shape = Shape::Circle(CircleParams {
+                center_x: found_point.x.round() as i16,
+                center_y: found_point.y.round() as i16,
+                radius: 10,
+            });
+            let green_shape = http_video_streaming_types::DrawableShape::from_shape(
+                &shape,
+                &green_stroke,
+                line_width,
+            );
+            annotations.push(green_shape);
+        }
+        Ok(Self {
+            source,
+            config,
+            data_url,
+            annotations,
+            histogram,
+            crop,
+        })
+    }
+}
+
 struct PerSender {
     out: EventChunkSender,
-    frame_lifo: Option<Arc<Mutex<AnnotatedFrame>>>,
+    /// Whether a frame arrived since the last one was sent.
+    has_new_frame: bool,
     ready_to_send: bool,
     conn_key: ConnectionKey,
     fno: u64,
-    green_stroke: StrokeStyle,
 }
 
 fn _test_per_sender_is_send() {
@@ -61,101 +160,62 @@ pub enum NameSelector {
 }
 
 impl PerSender {
-    fn new(
-        out: EventChunkSender,
-        conn_key: ConnectionKey,
-        frame: Arc<Mutex<AnnotatedFrame>>,
-    ) -> PerSender {
+    fn new(out: EventChunkSender, conn_key: ConnectionKey) -> PerSender {
         PerSender {
             out,
-            frame_lifo: Some(frame),
+            has_new_frame: true,
             ready_to_send: true,
             conn_key,
             fno: 0,
-            green_stroke: StrokeStyle::from_rgb(0x7F, 0xFF, 0x7F),
         }
     }
-    fn push(&mut self, frame: Arc<Mutex<AnnotatedFrame>>) {
+    fn push(&mut self) {
         self.fno += 1;
-        self.frame_lifo = Some(frame);
+        self.has_new_frame = true;
     }
     fn got_callback(&mut self, _msg: ConnectionKey) {
         self.ready_to_send = true;
     }
-    async fn service(&mut self) -> Result<()> {
-        // check if we should send frame(s) and send if so.
-
-        // should we send it?
-        // TODO cache the converted frame.
+    fn wants_frame(&self) -> bool {
+        self.has_new_frame && self.ready_to_send
+    }
+    async fn service(&mut self, encoded: &EncodedFrame) {
         // TODO allow client to throttle?
         // TODO make algorithm smarter to have more in-flight frames?
         // TODO include sent time in message to clients so we don't maintain that
 
-        if let Some(ref most_recent_frame_data) = self.frame_lifo {
-            if self.ready_to_send {
-                // sent_time computed early so that latency includes duration to encode, etc.
-                let sent_time = chrono::Local::now();
-                let tc = {
-                    let most_recent_frame_data = most_recent_frame_data.lock().unwrap();
-                    let bytes = basic_frame::match_all_dynamic_fmts!(
-                        &most_recent_frame_data.frame,
-                        x,
-                        convert_image::frame_to_encoded_buffer(
-                            x,
-                            convert_image::EncoderOptions::Jpeg(80),
-                        )
-                    )?;
-                    let firehose_frame_base64 = base64::encode(&bytes);
-                    let data_url = format!("data:image/jpeg;base64,{}", firehose_frame_base64);
-                    // most_recent_frame_data.data_url = Some(data_url.clone()); // todo: cache like this
-                    let mut annotations = most_recent_frame_data.annotations.clone();
-                    // Convert found points into normal annotations. (This should perhaps be done earlier.)
-                    for found_point in most_recent_frame_data.found_points.iter() {
-                        let line_width = 5.0;
-                        let shape = Shape::Circle(CircleParams {
-                            center_x: found_point.x.round() as i16,
-                            center_y: found_point.y.round() as i16,
-                            radius: 10,
-                        });
-                        let green_shape = http_video_streaming_types::DrawableShape::from_shape(
-                            &shape,
-                            &self.green_stroke,
-                            line_width,
-                        );
-                        annotations.push(green_shape);
-                    }
-                    ToClient {
-                        firehose_frame_data_url: data_url,
-                        valid_display: most_recent_frame_data.valid_display.clone(),
-                        annotations,
-                        fno: self.fno,
-                        ts_rfc3339: sent_time.to_rfc3339(),
-                        ck: self.conn_key,
-                    }
-                };
-                let buf = serde_json::to_string(&tc).expect("encode");
-                let buf = format!(
-                    "event: {}\ndata: {}\n\n",
-                    http_video_streaming_types::VIDEO_STREAM_EVENT_NAME,
-                    buf
-                );
-                let hc = http_body::Frame::data(bytes::Bytes::from(buf));
+        if self.wants_frame() {
+            let sent_time = chrono::Local::now();
+            let tc = ToClient {
+                firehose_frame_data_url: encoded.data_url.clone(),
+                valid_display: encoded.source.valid_display.clone(),
+                annotations: encoded.annotations.clone(),
+                histogram: encoded.histogram.clone(),
+                crop: encoded.crop,
+                fno: self.fno,
+                ts_rfc3339: sent_time.to_rfc3339(),
+                ck: self.conn_key,
+            };
+            let buf = serde_json::to_string(&tc).expect("encode");
+            let buf = format!(
+                "event: {}\ndata: {}\n\n",
+                http_video_streaming_types::VIDEO_STREAM_EVENT_NAME,
+                buf
+            );
+            let hc = http_body::Frame::data(bytes::Bytes::from(buf));
 
-                match self.out.send(Ok(hc)).await {
-                    Ok(()) => {}
-                    Err(_) => {
-                        tracing::info!("failed to send data to connection. dropping.");
-                        // Failed to send data to event stream key.
-                        // TODO: drop this sender.
-                    }
+            match self.out.send(Ok(hc)).await {
+                Ok(()) => {}
+                Err(_) => {
+                    tracing::info!("failed to send data to connection. dropping.");
+                    // Failed to send data to event stream key.
+                    // TODO: drop this sender.
                 }
-                self.ready_to_send = false;
             }
+            self.ready_to_send = false;
         }
 
-        self.frame_lifo = None;
-
-        Ok(())
+        self.has_new_frame = false;
     }
 }
 
@@ -163,7 +223,11 @@ struct TaskState {
     /// cache of senders
     per_sender_map: HashMap<ConnectionKey, PerSender>,
     /// most recent image frame, with annotations
-    frame: Arc<Mutex<AnnotatedFrame>>,
+    frame: Arc<AnnotatedFrame>,
+    /// the most recent frame encoded for the browser, if it was needed
+    encoded: Option<EncodedFrame>,
+    /// settings for encoding the frames
+    preview_config_rx: tokio::sync::watch::Receiver<PreviewConfig>,
 }
 
 fn _test_task_state_is_send() {
@@ -174,9 +238,34 @@ fn _test_task_state_is_send() {
 
 impl TaskState {
     async fn service(&mut self) -> Result<()> {
+        if !self.per_sender_map.values().any(PerSender::wants_frame) {
+            return Ok(());
+        }
+        // Encode the frame once for all senders.
+        let config = *self.preview_config_rx.borrow();
+        let is_current = self
+            .encoded
+            .as_ref()
+            .map(|e| Arc::ptr_eq(&e.source, &self.frame) && e.config == config)
+            .unwrap_or(false);
+        if !is_current {
+            match EncodedFrame::new(self.frame.clone(), config) {
+                Ok(encoded) => self.encoded = Some(encoded),
+                Err(e) => {
+                    // Skip this frame but keep streaming.
+                    tracing::error!("encoding frame for live view failed: {e}");
+                    self.encoded = None;
+                    for ps in self.per_sender_map.values_mut() {
+                        ps.has_new_frame = false;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        let encoded = self.encoded.as_ref().unwrap();
         // TODO: make sending concurrent on all listeners and set a timeout.
         for ps in self.per_sender_map.values_mut() {
-            ps.service().await?;
+            ps.service(encoded).await;
         }
         Ok(())
     }
@@ -184,7 +273,7 @@ impl TaskState {
         match conn_evt.typ {
             ConnectionEventType::Connect(chunk_sender) => {
                 // sender was added.
-                let ps = PerSender::new(chunk_sender, conn_evt.connection_key, self.frame.clone());
+                let ps = PerSender::new(chunk_sender, conn_evt.connection_key);
                 self.per_sender_map.insert(conn_evt.connection_key, ps);
             }
             ConnectionEventType::Disconnect => {
@@ -194,11 +283,10 @@ impl TaskState {
         Ok(())
     }
     fn handle_frame(&mut self, new_frame: AnnotatedFrame) -> Result<()> {
-        // Move the frame into a reference-counted pointer.
-        self.frame = Arc::new(Mutex::new(new_frame));
+        // The frame is encoded only when a sender is ready for it.
+        self.frame = Arc::new(new_frame);
         for ps in self.per_sender_map.values_mut() {
-            // Clone the pointer and move the pointer into each sender.
-            ps.push(self.frame.clone());
+            ps.push();
         }
         Ok(())
     }
@@ -219,14 +307,17 @@ pub async fn firehose_task(
     connection_callback_rx: tokio::sync::mpsc::Receiver<ConnectionEvent>,
     mut firehose_rx: tokio::sync::mpsc::Receiver<AnnotatedFrame>,
     firehose_callback_rx: tokio::sync::mpsc::Receiver<ConnectionKey>,
+    preview_config_rx: tokio::sync::watch::Receiver<PreviewConfig>,
 ) -> Result<()> {
     // Wait for the first frame so we don't need to deal with an Option<>.
     let first_frame = firehose_rx.recv().await.unwrap();
-    let frame = Arc::new(Mutex::new(first_frame));
+    let frame = Arc::new(first_frame);
 
     let mut task_state = TaskState {
         per_sender_map: HashMap::new(),
         frame,
+        encoded: None,
+        preview_config_rx,
     };
 
     let mut connection_callback_rx =
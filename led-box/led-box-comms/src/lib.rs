@@ -7,16 +7,35 @@ extern crate core as std;
 
 use serde::{Serialize, Deserialize};
 
+mod sequence;
+pub use sequence::{
+    SequenceConfig, SequenceError, SequencePlayer, SequenceStep, SequenceUpdate,
+    MAX_SEQUENCE_STEPS,
+};
+
 pub const MAX_INTENSITY: u16 = 16000;
-pub const COMM_VERSION: u16 = 3;
+pub const COMM_VERSION: u16 = 4;
 pub const BAUD_RATE: u32 = 230_400;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
 #[cfg_attr(feature = "print-defmt", derive(defmt::Format))]
 pub enum ToDevice {
+    /// Set the state of all channels. This stops a running sequence.
     DeviceState(DeviceState),
     EchoRequest8((u8, u8, u8, u8, u8, u8, u8, u8)),
     VersionRequest,
+    /// Store a step of the sequence at the given index.
+    SetSequenceStep((u8, SequenceStep)),
+    /// Set the number of steps and looping of the sequence.
+    SetSequenceConfig(SequenceConfig),
+    /// Start the stored sequence.
+    ///
+    /// The sequence is executed by the device. To start it with minimal
+    /// latency, upload the steps and configuration beforehand so that only
+    /// this short message must be sent as the trigger.
+    StartSequence,
+    /// Stop a running sequence and return to the last [DeviceState].
+    StopSequence,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
@@ -26,6 +45,14 @@ pub enum FromDevice {
     EchoResponse8((u8, u8, u8, u8, u8, u8, u8, u8)),
     VersionResponse(u16),
     StateWasSet,
+    /// A step or the configuration of the sequence was stored.
+    SequenceWasSet,
+    SequenceStarted,
+    SequenceStopped,
+    /// A non-looping sequence ran to its end. The channels returned to the last
+    /// [DeviceState].
+    SequenceFinished,
+    SequenceError(SequenceError),
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
@@ -60,6 +87,16 @@ impl DeviceState {
             ch4: ChannelState::default(4),
         }
     }
+
+    /// The PWM duty cycle of channels 1-4.
+    pub const fn output_intensities(&self) -> [u16; 4] {
+        [
+            self.ch1.output_intensity(),
+            self.ch2.output_intensity(),
+            self.ch3.output_intensity(),
+            self.ch4.output_intensity(),
+        ]
+    }
 }
 
 impl Default for DeviceState {
@@ -84,6 +121,14 @@ impl ChannelState {
             intensity: MAX_INTENSITY,
         }
     }
+
+    /// The PWM duty cycle of this channel.
+    pub const fn output_intensity(&self) -> u16 {
+        match self.on_state {
+            OnState::Off => 0,
+            OnState::ConstantOn => self.intensity,
+        }
+    }
 }
 
 impl Default for ChannelState {
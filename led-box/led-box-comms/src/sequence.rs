@@ -0,0 +1,286 @@
+//! Sequences of channel intensities executed on the device.
+//!
+//! A sequence is a list of steps, each of which changes the intensity of one
+//! channel over a given duration. Steps run one after another. Channels not
+//! changed by a step keep their intensity, so a step with zero duration can be
+//! used to change several channels at the same time.
+
+use serde::{Deserialize, Serialize};
+
+/// The maximum number of steps in a sequence.
+pub const MAX_SEQUENCE_STEPS: usize = 32;
+
+const NUM_CHANNELS: usize = 4;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(feature = "print-defmt", derive(defmt::Format))]
+pub struct SequenceStep {
+    /// Channel number (1-4).
+    pub channel: u8,
+    /// PWM duty cycle of the channel at the end of the step.
+    pub intensity: u16,
+    /// Duration of the step in milliseconds.
+    pub duration_msec: u32,
+    /// If true, the intensity changes linearly during the step, starting from
+    /// the intensity at the start of the step. Otherwise, the intensity is set
+    /// at the start of the step.
+    pub ramp: bool,
+}
+
+impl SequenceStep {
+    const fn empty() -> Self {
+        Self {
+            channel: 1,
+            intensity: 0,
+            duration_msec: 0,
+            ramp: false,
+        }
+    }
+}
+
+impl Default for SequenceStep {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "print-defmt", derive(defmt::Format))]
+pub struct SequenceConfig {
+    /// Number of steps in the sequence.
+    pub num_steps: u8,
+    /// If true, the sequence starts again after the last step until stopped.
+    pub looping: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(feature = "print-defmt", derive(defmt::Format))]
+pub enum SequenceError {
+    /// The step index or number of steps is not less than
+    /// [MAX_SEQUENCE_STEPS].
+    TooManySteps,
+    /// The channel number is not in the range 1-4.
+    InvalidChannel,
+}
+
+/// The result of [SequencePlayer::update].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SequenceUpdate {
+    /// No sequence is running.
+    Idle,
+    /// A sequence is running with these intensities of channels 1-4.
+    Running([u16; NUM_CHANNELS]),
+    /// The sequence ran to its end.
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RunningState {
+    step_idx: usize,
+    step_start_usec: u64,
+    step_start_intensity: u16,
+    intensities: [u16; NUM_CHANNELS],
+}
+
+/// Stores a sequence and computes the channel intensities while it runs.
+///
+/// The player does not read a clock itself. Firmware calls
+/// [SequencePlayer::update] with the current time as often as possible and
+/// sets the PWM outputs to the returned intensities.
+#[derive(Debug, Clone)]
+pub struct SequencePlayer {
+    steps: [SequenceStep; MAX_SEQUENCE_STEPS],
+    config: SequenceConfig,
+    running: Option<RunningState>,
+}
+
+impl SequencePlayer {
+    pub const fn new() -> Self {
+        Self {
+            steps: [SequenceStep::empty(); MAX_SEQUENCE_STEPS],
+            config: SequenceConfig {
+                num_steps: 0,
+                looping: false,
+            },
+            running: None,
+        }
+    }
+
+    pub fn set_step(&mut self, idx: u8, step: SequenceStep) -> Result<(), SequenceError> {
+        if usize::from(idx) >= MAX_SEQUENCE_STEPS {
+            return Err(SequenceError::TooManySteps);
+        }
+        if !(1..=NUM_CHANNELS as u8).contains(&step.channel) {
+            return Err(SequenceError::InvalidChannel);
+        }
+        self.steps[usize::from(idx)] = step;
+        Ok(())
+    }
+
+    pub fn set_config(&mut self, config: SequenceConfig) -> Result<(), SequenceError> {
+        if usize::from(config.num_steps) > MAX_SEQUENCE_STEPS {
+            return Err(SequenceError::TooManySteps);
+        }
+        self.config = config;
+        Ok(())
+    }
+
+    /// Start the sequence at time `now_usec` from the current channel
+    /// intensities.
+    pub fn start(&mut self, now_usec: u64, intensities: [u16; NUM_CHANNELS]) {
+        let mut state = RunningState {
+            step_idx: 0,
+            step_start_usec: now_usec,
+            step_start_intensity: 0,
+            intensities,
+        };
+        if self.config.num_steps > 0 {
+            state.step_start_intensity = intensities[self.steps[0].channel as usize - 1];
+        }
+        self.running = Some(state);
+    }
+
+    pub fn stop(&mut self) {
+        self.running = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Advance the sequence to time `now_usec`.
+    pub fn update(&mut self, now_usec: u64) -> SequenceUpdate {
+        let num_steps = usize::from(self.config.num_steps);
+        // A looping sequence without duration would never return.
+        let looping = self.config.looping
+            && self.steps[..num_steps]
+                .iter()
+                .any(|step| step.duration_msec > 0);
+        let Some(state) = self.running.as_mut() else {
+            return SequenceUpdate::Idle;
+        };
+        loop {
+            if state.step_idx >= num_steps {
+                if looping {
+                    state.step_idx = 0;
+                } else {
+                    self.running = None;
+                    return SequenceUpdate::Finished;
+                }
+            }
+            let step = &self.steps[state.step_idx];
+            let chan_idx = step.channel as usize - 1;
+            let duration_usec = u64::from(step.duration_msec) * 1000;
+            let elapsed_usec = now_usec.saturating_sub(state.step_start_usec);
+            if elapsed_usec < duration_usec {
+                state.intensities[chan_idx] = if step.ramp {
+                    let start = i64::from(state.step_start_intensity);
+                    let delta = i64::from(step.intensity) - start;
+                    (start + delta * elapsed_usec as i64 / duration_usec as i64) as u16
+                } else {
+                    step.intensity
+                };
+                return SequenceUpdate::Running(state.intensities);
+            }
+            // This step is done. Continue with the next one.
+            state.intensities[chan_idx] = step.intensity;
+            state.step_idx += 1;
+            state.step_start_usec += duration_usec;
+            let next_idx = if state.step_idx < num_steps {
+                state.step_idx
+            } else {
+                0
+            };
+            state.step_start_intensity =
+                state.intensities[self.steps[next_idx].channel as usize - 1];
+        }
+    }
+}
+
+impl Default for SequencePlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(channel: u8, intensity: u16, duration_msec: u32, ramp: bool) -> SequenceStep {
+        SequenceStep {
+            channel,
+            intensity,
+            duration_msec,
+            ramp,
+        }
+    }
+
+    fn player(steps: &[SequenceStep], looping: bool) -> SequencePlayer {
+        let mut player = SequencePlayer::new();
+        for (i, s) in steps.iter().enumerate() {
+            player.set_step(i as u8, *s).unwrap();
+        }
+        player
+            .set_config(SequenceConfig {
+                num_steps: steps.len() as u8,
+                looping,
+            })
+            .unwrap();
+        player
+    }
+
+    #[test]
+    fn test_steps_and_ramp() {
+        let mut p = player(&[step(1, 1000, 10, false), step(2, 2000, 20, true)], false);
+        assert_eq!(p.update(0), SequenceUpdate::Idle);
+        p.start(100, [0, 500, 0, 0]);
+        assert_eq!(p.update(100), SequenceUpdate::Running([1000, 500, 0, 0]));
+        assert_eq!(p.update(10_099), SequenceUpdate::Running([1000, 500, 0, 0]));
+        assert_eq!(p.update(10_100), SequenceUpdate::Running([1000, 500, 0, 0]));
+        assert_eq!(
+            p.update(20_100),
+            SequenceUpdate::Running([1000, 1250, 0, 0])
+        );
+        assert_eq!(p.update(30_100), SequenceUpdate::Finished);
+        assert_eq!(p.update(30_200), SequenceUpdate::Idle);
+    }
+
+    #[test]
+    fn test_looping() {
+        let mut p = player(
+            &[
+                step(3, 100, 0, false),
+                step(4, 200, 5, false),
+                step(3, 0, 5, false),
+            ],
+            true,
+        );
+        p.start(0, [0; 4]);
+        assert_eq!(p.update(1_000), SequenceUpdate::Running([0, 0, 100, 200]));
+        assert_eq!(p.update(6_000), SequenceUpdate::Running([0, 0, 0, 200]));
+        assert_eq!(p.update(11_000), SequenceUpdate::Running([0, 0, 100, 200]));
+        p.stop();
+        assert_eq!(p.update(12_000), SequenceUpdate::Idle);
+    }
+
+    #[test]
+    fn test_zero_duration_loop_finishes() {
+        let mut p = player(&[step(1, 100, 0, false)], true);
+        p.start(0, [0; 4]);
+        assert_eq!(p.update(0), SequenceUpdate::Finished);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut p = SequencePlayer::new();
+        assert_eq!(
+            p.set_step(0, step(5, 0, 0, false)),
+            Err(SequenceError::InvalidChannel)
+        );
+        assert_eq!(
+            p.set_step(MAX_SEQUENCE_STEPS as u8, step(1, 0, 0, false)),
+            Err(SequenceError::TooManySteps)
+        );
+    }
+}
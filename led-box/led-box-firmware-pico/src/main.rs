@@ -21,7 +21,9 @@ use defmt_rtt as _;
 use panic_probe as _;
 use rtic::Mutex;
 
-use led_box_comms::{ChannelState, DeviceState, FromDevice, OnState, ToDevice};
+use led_box_comms::{
+    ChannelState, DeviceState, FromDevice, OnState, SequencePlayer, SequenceUpdate, ToDevice,
+};
 
 use json_lines::accumulator::{FeedResult, NewlinesAccumulator};
 
@@ -205,8 +207,29 @@ mod app {
         let mut decoder = NewlinesAccumulator::<512>::new();
         let mut current_device_state = DeviceState::default();
         let mut out_buf = [0u8; 256];
+        let mut sequence = SequencePlayer::new();
+        let mut sequence_intensities = current_device_state.output_intensities();
 
         loop {
+            // The timer runs at 1 MHz.
+            let now_usec = monotonics::now().ticks();
+            match sequence.update(now_usec) {
+                SequenceUpdate::Idle => {}
+                SequenceUpdate::Running(intensities) => {
+                    for (i, intensity) in intensities.iter().enumerate() {
+                        if sequence_intensities[i] != *intensity {
+                            set_duty(i as u8 + 1, *intensity, &mut ctx);
+                        }
+                    }
+                    sequence_intensities = intensities;
+                }
+                SequenceUpdate::Finished => {
+                    restore_device_state(&current_device_state, &mut ctx);
+                    send_response(&FromDevice::SequenceFinished, &mut out_buf, &mut ctx);
+                    defmt::debug!("sequence finished");
+                }
+            }
+
             let frame = match ctx.local.rx_cons.dequeue() {
                 Some(frame) => frame,
                 None => continue,
@@ -230,6 +253,10 @@ mod app {
                 let response;
                 match msg {
                     ToDevice::DeviceState(next_state) => {
+                        if sequence.is_running() {
+                            sequence.stop();
+                            restore_device_state(&current_device_state, &mut ctx);
+                        }
                         update_device_state(&mut current_device_state, &next_state, &mut ctx);
                         response = FromDevice::StateWasSet;
                         defmt::debug!("device state set");
@@ -242,18 +269,50 @@ mod app {
                         response = FromDevice::VersionResponse(led_box_comms::COMM_VERSION);
                         defmt::debug!("version request");
                     }
+                    ToDevice::SetSequenceStep((idx, step)) => {
+                        response = match sequence.set_step(idx, step) {
+                            Ok(()) => FromDevice::SequenceWasSet,
+                            Err(e) => FromDevice::SequenceError(e),
+                        };
+                        defmt::debug!("sequence step {} set", idx);
+                    }
+                    ToDevice::SetSequenceConfig(config) => {
+                        response = match sequence.set_config(config) {
+                            Ok(()) => FromDevice::SequenceWasSet,
+                            Err(e) => FromDevice::SequenceError(e),
+                        };
+                        defmt::debug!("sequence config set");
+                    }
+                    ToDevice::StartSequence => {
+                        sequence_intensities = current_device_state.output_intensities();
+                        sequence.start(monotonics::now().ticks(), sequence_intensities);
+                        response = FromDevice::SequenceStarted;
+                        defmt::debug!("sequence started");
+                    }
+                    ToDevice::StopSequence => {
+                        if sequence.is_running() {
+                            sequence.stop();
+                            restore_device_state(&current_device_state, &mut ctx);
+                        }
+                        response = FromDevice::SequenceStopped;
+                        defmt::debug!("sequence stopped");
+                    }
                 }
 
-                let encoded = json_lines::to_slice_newline(&response, &mut out_buf[..]).unwrap();
-
-                ctx.shared.usb_serial.lock(|usb_serial| {
-                    usb_serial.write(&encoded).unwrap();
-                });
-                defmt::trace!("sent {} bytes", encoded.len());
+                send_response(&response, &mut out_buf, &mut ctx);
             }
         }
     }
 
+    fn send_response(response: &FromDevice, out_buf: &mut [u8], ctx: &mut idle::Context) {
+        let encoded = json_lines::to_slice_newline(response, out_buf).unwrap();
+
+        ctx.shared.usb_serial.lock(|usb_serial| {
+            usb_serial.write(encoded).unwrap();
+        });
+        defmt::trace!("sent {} bytes", encoded.len());
+    }
+
     /// This function is called from the USB interrupt handler function (which
     /// does not have a return value). By here returning Result, we can abort
     /// processing early using idiomatic rust, even in the interrupt handler
@@ -352,17 +411,30 @@ mod app {
                 next_state.num,
                 pwm_period
             );
-            match next_state.num {
-                1 => ctx.local.pwms.pwm3_slice.channel_a.set_duty(pwm_period),
-                2 => ctx.local.pwms.pwm3_slice.channel_b.set_duty(pwm_period),
-                3 => ctx.local.pwms.pwm4_slice.channel_a.set_duty(pwm_period),
-                4 => ctx.local.pwms.pwm4_slice.channel_b.set_duty(pwm_period),
-                _ => panic!("unknown channel"),
-            };
+            set_duty(next_state.num, pwm_period, ctx);
         }
         // rtic::pend(pac::Interrupt::TIM2);
     }
 
+    /// Set the PWM duty cycle of a channel.
+    fn set_duty(num: u8, duty: u16, ctx: &mut idle::Context) {
+        match num {
+            1 => ctx.local.pwms.pwm3_slice.channel_a.set_duty(duty),
+            2 => ctx.local.pwms.pwm3_slice.channel_b.set_duty(duty),
+            3 => ctx.local.pwms.pwm4_slice.channel_a.set_duty(duty),
+            4 => ctx.local.pwms.pwm4_slice.channel_b.set_duty(duty),
+            _ => panic!("unknown channel"),
+        };
+    }
+
+    /// Set all channels to `state`, e.g. after a sequence ended.
+    fn restore_device_state(state: &DeviceState, ctx: &mut idle::Context) {
+        update_led_state(&state.ch1, ctx);
+        update_led_state(&state.ch2, ctx);
+        update_led_state(&state.ch3, ctx);
+        update_led_state(&state.ch4, ctx);
+    }
+
     fn update_device_state(
         current_state: &mut DeviceState,
         next_state: &DeviceState,
@@ -35,7 +35,9 @@ use defmt::{error, info};
 
 use rtic::Mutex;
 
-use led_box_comms::{ChannelState, DeviceState, FromDevice, OnState, ToDevice};
+use led_box_comms::{
+    ChannelState, DeviceState, FromDevice, OnState, SequencePlayer, SequenceUpdate, ToDevice,
+};
 use stm32f3xx_hal::gpio::gpioa::PA5;
 
 use json_lines::accumulator::{FeedResult, NewlinesAccumulator};
@@ -67,6 +69,7 @@ mod app {
     #[local]
     struct Local {
         inner_led_state: InnerLedState,
+        usec_clock: UsecClock,
         pwm3_ch1: stm32f3xx_hal::pwm::PwmChannel<
             stm32f3xx_hal::pwm::Tim3Ch1,
             stm32f3xx_hal::pwm::WithPins,
@@ -107,6 +110,12 @@ mod app {
         let mut gpiob = c.device.GPIOB.split(&mut rcc.ahb);
         let clocks = rcc.cfgr.freeze(&mut flash.acr);
 
+        // The cycle counter is used to time sequences.
+        let mut core = c.core;
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+        let usec_clock = UsecClock::new(clocks.sysclk().0);
+
         // initialize serial
         let tx = gpioa
             .pa2
@@ -205,6 +214,7 @@ mod app {
             Shared { serial, green_led },
             Local {
                 inner_led_state: InnerLedState::default(),
+                usec_clock,
                 pwm3_ch1,
                 pwm3_ch2,
                 pwm3_ch3,
@@ -218,11 +228,13 @@ mod app {
         )
     }
 
-    #[idle(shared = [green_led, serial], local = [inner_led_state, pwm3_ch1, pwm3_ch2, pwm3_ch3, pwm3_ch4, rx_cons, tx_prod])]
+    #[idle(shared = [green_led, serial], local = [inner_led_state, usec_clock, pwm3_ch1, pwm3_ch2, pwm3_ch3, pwm3_ch4, rx_cons, tx_prod])]
     fn idle(mut ctx: idle::Context) -> ! {
         let mut decoder = NewlinesAccumulator::<RX_BUF_SZ>::new();
         let mut current_device_state = DeviceState::default();
         let mut out_buf = [0u8; 256];
+        let mut sequence = SequencePlayer::new();
+        let mut sequence_intensities = current_device_state.output_intensities();
 
         info!("starting idle loop");
 
@@ -231,6 +243,24 @@ mod app {
         //     .lock(|green_led| green_led.set_high().unwrap());
 
         loop {
+            let now_usec = ctx.local.usec_clock.now_usec();
+            match sequence.update(now_usec) {
+                SequenceUpdate::Idle => {}
+                SequenceUpdate::Running(intensities) => {
+                    for (i, intensity) in intensities.iter().enumerate() {
+                        if sequence_intensities[i] != *intensity {
+                            set_duty(i as u8 + 1, *intensity, &mut ctx);
+                        }
+                    }
+                    sequence_intensities = intensities;
+                }
+                SequenceUpdate::Finished => {
+                    restore_device_state(&current_device_state, &mut ctx);
+                    send_response(&FromDevice::SequenceFinished, &mut out_buf, &mut ctx);
+                    defmt::debug!("sequence finished");
+                }
+            }
+
             let ret = if let Some(ch) = ctx.local.rx_cons.dequeue() {
                 let ret = match decoder.feed::<ToDevice>(&[ch]) {
                     FeedResult::Consumed => None,
@@ -253,6 +283,10 @@ mod app {
                 let response;
                 match msg {
                     ToDevice::DeviceState(next_state) => {
+                        if sequence.is_running() {
+                            sequence.stop();
+                            restore_device_state(&current_device_state, &mut ctx);
+                        }
                         update_device_state(&mut current_device_state, &next_state, &mut ctx);
                         response = FromDevice::StateWasSet;
                         defmt::debug!("device state set");
@@ -265,21 +299,53 @@ mod app {
                         response = FromDevice::VersionResponse(led_box_comms::COMM_VERSION);
                         defmt::debug!("version request");
                     }
+                    ToDevice::SetSequenceStep((idx, step)) => {
+                        response = match sequence.set_step(idx, step) {
+                            Ok(()) => FromDevice::SequenceWasSet,
+                            Err(e) => FromDevice::SequenceError(e),
+                        };
+                        defmt::debug!("sequence step {} set", idx);
+                    }
+                    ToDevice::SetSequenceConfig(config) => {
+                        response = match sequence.set_config(config) {
+                            Ok(()) => FromDevice::SequenceWasSet,
+                            Err(e) => FromDevice::SequenceError(e),
+                        };
+                        defmt::debug!("sequence config set");
+                    }
+                    ToDevice::StartSequence => {
+                        sequence_intensities = current_device_state.output_intensities();
+                        sequence.start(ctx.local.usec_clock.now_usec(), sequence_intensities);
+                        response = FromDevice::SequenceStarted;
+                        defmt::debug!("sequence started");
+                    }
+                    ToDevice::StopSequence => {
+                        if sequence.is_running() {
+                            sequence.stop();
+                            restore_device_state(&current_device_state, &mut ctx);
+                        }
+                        response = FromDevice::SequenceStopped;
+                        defmt::debug!("sequence stopped");
+                    }
                 }
 
-                let encoded = json_lines::to_slice_newline(&response, &mut out_buf[..]).unwrap();
-                for ch in encoded.iter() {
-                    ctx.local.tx_prod.enqueue(*ch).unwrap();
-                }
-
-                defmt::trace!("idle pushed {} bytes", encoded.len());
-                ctx.shared.serial.lock(|serial| {
-                    serial.enable_interrupt(Event::TransmitDataRegisterEmtpy);
-                });
+                send_response(&response, &mut out_buf, &mut ctx);
             }
         }
     }
 
+    fn send_response(response: &FromDevice, out_buf: &mut [u8], ctx: &mut idle::Context) {
+        let encoded = json_lines::to_slice_newline(response, out_buf).unwrap();
+        for ch in encoded.iter() {
+            ctx.local.tx_prod.enqueue(*ch).unwrap();
+        }
+
+        defmt::trace!("idle pushed {} bytes", encoded.len());
+        ctx.shared.serial.lock(|serial| {
+            serial.enable_interrupt(Event::TransmitDataRegisterEmtpy);
+        });
+    }
+
     #[task(binds = USART2_EXTI26, shared = [serial, green_led], local = [rx_prod, tx_cons])]
     fn protocol_serial_task(mut cx: protocol_serial_task::Context) {
         defmt::trace!("IRQ start");
@@ -394,17 +460,30 @@ mod app {
                 next_state.num,
                 pwm_period
             );
-            match next_state.num {
-                1 => ctx.local.pwm3_ch1.set_duty(pwm_period),
-                2 => ctx.local.pwm3_ch2.set_duty(pwm_period),
-                3 => ctx.local.pwm3_ch3.set_duty(pwm_period),
-                4 => ctx.local.pwm3_ch4.set_duty(pwm_period),
-                _ => panic!("unknown channel"),
-            };
+            set_duty(next_state.num, pwm_period, ctx);
         }
         // rtic::pend(pac::Interrupt::TIM2);
     }
 
+    /// Set the PWM duty cycle of a channel.
+    fn set_duty(num: u8, duty: u16, ctx: &mut idle::Context) {
+        match num {
+            1 => ctx.local.pwm3_ch1.set_duty(duty),
+            2 => ctx.local.pwm3_ch2.set_duty(duty),
+            3 => ctx.local.pwm3_ch3.set_duty(duty),
+            4 => ctx.local.pwm3_ch4.set_duty(duty),
+            _ => panic!("unknown channel"),
+        };
+    }
+
+    /// Set all channels to `state`, e.g. after a sequence ended.
+    fn restore_device_state(state: &DeviceState, ctx: &mut idle::Context) {
+        update_led_state(&state.ch1, ctx);
+        update_led_state(&state.ch2, ctx);
+        update_led_state(&state.ch3, ctx);
+        update_led_state(&state.ch4, ctx);
+    }
+
     fn update_device_state(
         current_state: &mut DeviceState,
         next_state: &DeviceState,
@@ -471,3 +550,30 @@ impl InnerLedState {
         }
     }
 }
+
+/// Microseconds since start, computed from the DWT cycle counter.
+///
+/// The 32 bit cycle counter wraps around after about 9 minutes at 8 MHz, so
+/// [UsecClock::now_usec] must be called more often than that.
+pub struct UsecClock {
+    cycles_per_usec: u32,
+    last_cycles: u32,
+    total_cycles: u64,
+}
+
+impl UsecClock {
+    fn new(sysclk_hz: u32) -> Self {
+        Self {
+            cycles_per_usec: sysclk_hz / 1_000_000,
+            last_cycles: cortex_m::peripheral::DWT::cycle_count(),
+            total_cycles: 0,
+        }
+    }
+
+    fn now_usec(&mut self) -> u64 {
+        let cycles = cortex_m::peripheral::DWT::cycle_count();
+        self.total_cycles += u64::from(cycles.wrapping_sub(self.last_cycles));
+        self.last_cycles = cycles;
+        self.total_cycles / u64::from(self.cycles_per_usec)
+    }
+}
@@ -187,7 +187,14 @@ pub async fn handle_box(
                     debug!("round trip time: {} msec", now_millis - sent_millis);
                 }
                 Ok(led_box_comms::FromDevice::StateWasSet)
-                | Ok(led_box_comms::FromDevice::DeviceState(_)) => {}
+                | Ok(led_box_comms::FromDevice::DeviceState(_))
+                | Ok(led_box_comms::FromDevice::SequenceWasSet)
+                | Ok(led_box_comms::FromDevice::SequenceStarted)
+                | Ok(led_box_comms::FromDevice::SequenceStopped)
+                | Ok(led_box_comms::FromDevice::SequenceFinished) => {}
+                Ok(led_box_comms::FromDevice::SequenceError(e)) => {
+                    error!("sequence error: {e:?}");
+                }
                 Ok(led_box_comms::FromDevice::VersionResponse(found)) => {
                     info!("Found comm version {found}.");
                     let expected = led_box_comms::COMM_VERSION;
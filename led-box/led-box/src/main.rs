@@ -75,6 +75,12 @@ async fn try_serial(serial_device: &str, next_state: &DeviceState) {
                             info!("state was set");
                         }
                         Some(Ok(led_box_comms::FromDevice::DeviceState(_))) => {}
+                        Some(Ok(led_box_comms::FromDevice::SequenceError(e))) => {
+                            tracing::error!("sequence error: {e:?}");
+                        }
+                        Some(Ok(msg)) => {
+                            info!("received: {msg:?}");
+                        }
                         Some(Err(e)) => {
                             panic!("unexpected error: {}: {:?}", e, e);
                         }
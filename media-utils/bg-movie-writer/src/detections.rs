@@ -0,0 +1,150 @@
+//! Drawing of detected points into frames, for debug recordings.
+//!
+//! Each detection is drawn as a circle and, if its orientation is known, a line
+//! through its center along the orientation.
+
+use machine_vision_formats::{pixel_format, ImageMutStride};
+
+/// Radius of the circle drawn for a detection of unknown area.
+const DEFAULT_RADIUS: f32 = 8.0;
+/// Minimum and maximum radius of the circle drawn for a detection.
+const MIN_RADIUS: f32 = 4.0;
+const MAX_RADIUS: f32 = 50.0;
+
+const MONO8_COLOR: [u8; 1] = [255];
+const RGB8_COLOR: [u8; 3] = [0, 255, 0];
+
+/// A detected point, in pixel coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub x: f32,
+    pub y: f32,
+    /// Orientation in radians.
+    pub theta: Option<f32>,
+    /// Area in pixels, which determines the size of the circle drawn.
+    pub area: Option<f32>,
+}
+
+impl Detection {
+    fn radius(&self) -> f32 {
+        match self.area {
+            Some(area) => (area / std::f32::consts::PI)
+                .sqrt()
+                .clamp(MIN_RADIUS, MAX_RADIUS),
+            None => DEFAULT_RADIUS,
+        }
+    }
+}
+
+pub(crate) fn draw_mono8(
+    image: &mut dyn ImageMutStride<pixel_format::Mono8>,
+    detections: &[Detection],
+) {
+    let (width, height, stride) = (image.width(), image.height(), image.stride());
+    draw(
+        image.buffer_mut_ref().data,
+        width,
+        height,
+        stride,
+        &MONO8_COLOR,
+        detections,
+    );
+}
+
+pub(crate) fn draw_rgb8(
+    image: &mut dyn ImageMutStride<pixel_format::RGB8>,
+    detections: &[Detection],
+) {
+    let (width, height, stride) = (image.width(), image.height(), image.stride());
+    draw(
+        image.buffer_mut_ref().data,
+        width,
+        height,
+        stride,
+        &RGB8_COLOR,
+        detections,
+    );
+}
+
+/// Draw into image data with `color.len()` bytes per pixel.
+fn draw(
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    color: &[u8],
+    detections: &[Detection],
+) {
+    let mut put = |x: f32, y: f32| {
+        let (x, y) = (x.round(), y.round());
+        if x < 0.0 || y < 0.0 || x >= width as f32 || y >= height as f32 {
+            return;
+        }
+        let start = y as usize * stride + x as usize * color.len();
+        data[start..start + color.len()].copy_from_slice(color);
+    };
+    for detection in detections.iter() {
+        let radius = detection.radius();
+        // Steps of about half a pixel leave no gaps.
+        let n_steps = (4.0 * std::f32::consts::PI * radius).ceil() as usize;
+        for i in 0..n_steps {
+            let angle = i as f32 / n_steps as f32 * 2.0 * std::f32::consts::PI;
+            put(
+                detection.x + radius * angle.cos(),
+                detection.y + radius * angle.sin(),
+            );
+        }
+        if let Some(theta) = detection.theta {
+            let (dx, dy) = (theta.cos(), theta.sin());
+            let n_steps = (4.0 * radius).ceil() as i32;
+            for i in -n_steps..=n_steps {
+                let t = i as f32 * 0.5;
+                put(detection.x + t * dx, detection.y + t * dy);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_draw_detections() {
+    let (width, height) = (40, 30);
+    let mut data = vec![0u8; width * height];
+    let detections = [
+        Detection {
+            x: 10.0,
+            y: 10.0,
+            theta: None,
+            area: None,
+        },
+        Detection {
+            x: 30.0,
+            y: 20.0,
+            theta: Some(0.0),
+            area: None,
+        },
+        // Partly outside of the image.
+        Detection {
+            x: 39.0,
+            y: 1.0,
+            theta: Some(1.0),
+            area: Some(1000.0),
+        },
+    ];
+    draw(
+        &mut data,
+        width as u32,
+        height as u32,
+        width,
+        &[255],
+        &detections,
+    );
+    let pixel = |x: usize, y: usize| data[y * width + x];
+    // The circle is drawn, but not its center.
+    assert_eq!(pixel(10 + DEFAULT_RADIUS as usize, 10), 255);
+    assert_eq!(pixel(10, 10 - DEFAULT_RADIUS as usize), 255);
+    assert_eq!(pixel(10, 10), 0);
+    // The orientation is drawn through the center.
+    assert_eq!(pixel(30, 20), 255);
+    assert_eq!(pixel(25, 20), 255);
+    assert_eq!(pixel(30, 15), 0);
+}
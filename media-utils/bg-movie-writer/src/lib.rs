@@ -5,8 +5,11 @@ use std::{
 
 use basic_frame::DynamicFrame;
 
+mod detections;
 mod movie_writer_thread;
 
+pub use detections::Detection;
+
 /// Possible errors
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -31,6 +34,8 @@ pub enum Error {
     FilenameDoesNotEndWithMp4,
     #[error("ffmpeg rewriter error {0}")]
     FfmpegReWriterError(#[from] ffmpeg_rewriter::Error),
+    #[error("image conversion error: {0}")]
+    ConvertImageError(#[from] convert_image::Error),
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -45,6 +50,15 @@ macro_rules! poll_err {
     }};
 }
 
+/// Text drawn into each frame before encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlay {
+    /// The camera name, drawn before the timestamp and frame number.
+    pub cam_name: String,
+    pub corner: ci2_remote_control::OverlayCorner,
+    pub size: ci2_remote_control::OverlaySize,
+}
+
 /// A writer which will save a movie in a background thread.
 ///
 /// [Self::new] will spawn the thread and the methods [Self::write] and
@@ -65,10 +79,12 @@ impl BgMovieWriter {
     ///   frames will be dropped.
     /// - `data_dir`, if specified, will be the directory location of the saved
     ///   file.
+    /// - `overlay`, if specified, is drawn into each frame.
     pub fn new(
         recording_config: ci2_remote_control::RecordingConfig,
         queue_size: usize,
         mp4_path: PathBuf,
+        overlay: Option<Overlay>,
     ) -> Self {
         // Create an Arc<Mutex<Option<Error>>> to hold a potential error from
         // the to-be-spawned writer thread.
@@ -79,7 +95,13 @@ impl BgMovieWriter {
         // Spawn the writer thread
         std::thread::spawn(move || {
             // Runs until the movie is done.
-            movie_writer_thread::writer_thread_loop(recording_config, err_to_launcher, rx, mp4_path)
+            movie_writer_thread::writer_thread_loop(
+                recording_config,
+                err_to_launcher,
+                rx,
+                mp4_path,
+                overlay,
+            )
         });
         Self {
             tx,
@@ -88,11 +110,25 @@ impl BgMovieWriter {
         }
     }
 
-    /// Enqueue the frame and timestamp for writing to the background thread.
+    /// Enqueue the frame, its timestamp and frame number for writing to the
+    /// background thread.
+    ///
+    /// The frame is shared with the writer thread rather than copied. The frame
+    /// number is only used for the overlay. `detections` are drawn into the
+    /// frame, which is useful for debugging tracking.
+    ///
+    /// Returns `false` if the frame was dropped because the background thread
+    /// is behind.
     ///
     /// If the background writer thread has previously encountered an error,
     /// this will return that previously-encountered error.
-    pub fn write<TS>(&mut self, frame: DynamicFrame, timestamp: TS) -> Result<()>
+    pub fn write<TS>(
+        &mut self,
+        frame: Arc<DynamicFrame>,
+        timestamp: TS,
+        frame_number: usize,
+        detections: Vec<Detection>,
+    ) -> Result<bool>
     where
         TS: Into<chrono::DateTime<chrono::Local>>,
     {
@@ -101,18 +137,18 @@ impl BgMovieWriter {
         if self.is_done {
             return Err(Error::AlreadyDone);
         }
-        let msg = Msg::Write((frame, timestamp));
+        let msg = Msg::Write((frame, timestamp, frame_number, detections));
         // This will only succeed if the channel is not full. It will not block.
         match self.tx.try_send(msg) {
-            Ok(()) => {}
+            Ok(()) => Ok(true),
             Err(std::sync::mpsc::TrySendError::Full(_msg)) => {
                 tracing::warn!("Dropping frame to save: channel full");
+                Ok(false)
             }
             Err(std::sync::mpsc::TrySendError::Disconnected(_msg)) => {
-                return Err(Error::WorkerDisconnected);
+                Err(Error::WorkerDisconnected)
             }
         }
-        Ok(())
     }
 
     /// Enqueue a message telling the background thread to finish writing.
@@ -137,6 +173,13 @@ impl BgMovieWriter {
 }
 
 pub(crate) enum Msg {
-    Write((DynamicFrame, chrono::DateTime<chrono::Local>)),
+    Write(
+        (
+            Arc<DynamicFrame>,
+            chrono::DateTime<chrono::Local>,
+            usize,
+            Vec<Detection>,
+        ),
+    ),
     Finish,
 }
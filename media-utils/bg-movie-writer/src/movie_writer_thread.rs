@@ -15,7 +15,7 @@ use ci2_remote_control::FfmpegRecordingConfig;
 use machine_vision_formats::{ImageStride, PixelFormat};
 use mp4_writer::Mp4Writer;
 
-use crate::{Error, Msg, Result};
+use crate::{Detection, Error, Msg, Overlay, Result};
 
 macro_rules! thread_try {
     ($xx: expr, $result: expr) => {{
@@ -159,6 +159,80 @@ fn create_writer<'a>(
     Ok(raw)
 }
 
+/// The overlay with its glyphs rasterized. Runs inside writer thread loop.
+struct OverlayDrawer {
+    text_overlay: font_drawing::TextOverlay,
+    corner: font_drawing::Corner,
+    cam_name: String,
+}
+
+impl OverlayDrawer {
+    fn new(overlay: Overlay) -> Self {
+        let font = rusttype::Font::try_from_bytes(ttf_firacode::REGULAR as &[u8])
+            .expect("Error constructing Font");
+        let text_overlay =
+            font_drawing::TextOverlay::new(&font, f32::from(overlay.size.text_height()));
+        use ci2_remote_control::OverlayCorner;
+        let corner = match overlay.corner {
+            OverlayCorner::TopLeft => font_drawing::Corner::TopLeft,
+            OverlayCorner::TopRight => font_drawing::Corner::TopRight,
+            OverlayCorner::BottomLeft => font_drawing::Corner::BottomLeft,
+            OverlayCorner::BottomRight => font_drawing::Corner::BottomRight,
+        };
+        Self {
+            text_overlay,
+            corner,
+            cam_name: overlay.cam_name,
+        }
+    }
+
+    fn text(&self, stamp: DateTime<Local>, frame_number: usize) -> String {
+        format!(
+            "{} {} #{}",
+            self.cam_name,
+            stamp.format("%Y-%m-%d %H:%M:%S%.6f %:z"),
+            frame_number
+        )
+    }
+}
+
+/// Return a copy of `frame` with the detections and the overlay drawn.
+///
+/// Mono8 and RGB8 frames are drawn into directly, frames of other pixel formats
+/// are converted to RGB8 first. Runs inside writer thread loop.
+fn annotate(
+    frame: &DynamicFrame,
+    overlay: Option<&OverlayDrawer>,
+    stamp: DateTime<Local>,
+    frame_number: usize,
+    detections: &[Detection],
+) -> Result<DynamicFrame> {
+    let text = overlay.map(|overlay| overlay.text(stamp, frame_number));
+    let frame = match frame.clone() {
+        DynamicFrame::Mono8(mut x) => {
+            crate::detections::draw_mono8(&mut x, detections);
+            if let (Some(overlay), Some(text)) = (overlay, &text) {
+                overlay
+                    .text_overlay
+                    .draw_mono8(&mut x, overlay.corner, text);
+            }
+            DynamicFrame::Mono8(x)
+        }
+        other => {
+            let mut x = match other {
+                DynamicFrame::RGB8(x) => x,
+                other => other.into_pixel_format::<machine_vision_formats::pixel_format::RGB8>()?,
+            };
+            crate::detections::draw_rgb8(&mut x, detections);
+            if let (Some(overlay), Some(text)) = (overlay, &text) {
+                overlay.text_overlay.draw_rgb8(&mut x, overlay.corner, text);
+            }
+            DynamicFrame::RGB8(x)
+        }
+    };
+    Ok(frame)
+}
+
 /// Save an image. Runs inside writer thread loop.
 fn save_frame(
     raw: &mut RawWriter<'_, File>,
@@ -197,6 +271,7 @@ pub(crate) fn writer_thread_loop(
     err_tx: Arc<Mutex<Option<Error>>>,
     rx: std::sync::mpsc::Receiver<Msg>,
     mp4_path: PathBuf,
+    overlay: Option<Overlay>,
 ) {
     {
         // Load CUDA and nvidia-encode shared libs, but do not return error
@@ -207,10 +282,12 @@ pub(crate) fn writer_thread_loop(
 
         let mut last_saved_stamp: Option<chrono::DateTime<chrono::Local>> = None;
 
+        let overlay = overlay.map(OverlayDrawer::new);
+
         loop {
             let msg = thread_try!(err_tx, rx.recv());
             match msg {
-                Msg::Write((frame, stamp)) => {
+                Msg::Write((frame, stamp, frame_number, detections)) => {
                     let raw_ref = if let Some(raw_ref) = raw.as_mut() {
                         raw_ref
                     } else {
@@ -230,6 +307,21 @@ pub(crate) fn writer_thread_loop(
                         }
                     };
                     if do_save {
+                        // The shared frame is only copied if something is drawn.
+                        let frame = if overlay.is_some() || !detections.is_empty() {
+                            Arc::new(thread_try!(
+                                err_tx,
+                                annotate(
+                                    &frame,
+                                    overlay.as_ref(),
+                                    stamp,
+                                    frame_number,
+                                    &detections
+                                )
+                            ))
+                        } else {
+                            frame
+                        };
                         thread_try!(
                             err_tx,
                             save_frame(raw_ref, &frame, stamp, &mut last_saved_stamp)
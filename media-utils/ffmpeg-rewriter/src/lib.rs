@@ -81,6 +81,7 @@ impl FfmpegReWriter {
             codec: Mp4Codec::H264RawStream,
             max_framerate: RecordingFrameRate::Unlimited,
             h264_metadata,
+            color_encoding: Default::default(),
         };
 
         let out_fd = std::fs::File::create(&srt_file_path)?;
@@ -169,6 +169,7 @@ impl FfmpegWriter {
             rated,
             aspectn: 1,
             aspectd: 1,
+            color_encoding: Default::default(),
         };
         let (wtr, ffmpeg_child) = {
             let mut args = ffmpeg_codec_args.to_args();
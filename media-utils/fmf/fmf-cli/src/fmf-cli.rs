@@ -90,6 +90,10 @@ enum Opt {
         /// Filename of output .fmf, "-" for stdout
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// losslessly compress each frame with zstd (writes an FMF v4 file)
+        #[arg(long)]
+        zstd: bool,
     },
 
     /// print information about an fmf file
@@ -287,6 +291,7 @@ fn export_fmf(
     new_pixel_format: Option<PixFmt>,
     output: Option<PathBuf>,
     forced_input_pixel_format: Option<PixFmt>,
+    zstd: bool,
 ) -> Result<()> {
     let output_fname = default_filename(&path, output, "fmf");
 
@@ -299,8 +304,14 @@ fn export_fmf(
 
     let output_fname = output_fname.unwrap(); // XXX temp hack FIXME
 
+    let compression = if zstd {
+        fmf::Compression::Zstd { level: 0 }
+    } else {
+        fmf::Compression::None
+    };
+
     let f = std::fs::File::create(&output_fname)?;
-    let mut writer = fmf::FMFWriter::new(f)?;
+    let mut writer = fmf::FMFWriter::with_compression(f, compression)?;
 
     for res_frame in reader {
         let (frame, fts) = res_frame?;
@@ -529,6 +540,7 @@ fn export_mp4(x: ExportMp4) -> Result<()> {
         codec,
         max_framerate: ci2_remote_control::RecordingFrameRate::Unlimited,
         h264_metadata: None,
+        color_encoding: Default::default(),
     };
 
     debug!("opening file {}", output_fname.unwrap().display());
@@ -568,6 +580,7 @@ fn export_y4m(x: ExportY4m) -> Result<()> {
         aspectd: x.aspect_denominator.try_into().unwrap(),
         raten: x.fps_numerator.try_into().unwrap(),
         rated: x.fps_denominator.try_into().unwrap(),
+        color_encoding: Default::default(),
     };
     let mut y4m_writer = y4m_writer::Y4MWriter::from_writer(out_fd, opts);
 
@@ -596,8 +609,15 @@ fn main() -> Result<()> {
             new_pixel_format,
             output,
             forced_input_pixel_format,
+            zstd,
         } => {
-            export_fmf(input, new_pixel_format, output, forced_input_pixel_format)?;
+            export_fmf(
+                input,
+                new_pixel_format,
+                output,
+                forced_input_pixel_format,
+                zstd,
+            )?;
         }
         Opt::Info { input } => {
             info(input)?;
@@ -24,8 +24,14 @@ pub enum FMFError {
     #[error("unimplemented pixel_format {0}")]
     UnimplementedPixelFormat(PixFmt),
 
-    #[error("Unimplemented FMF file version {0}. Only FMF v3 files supported.")]
+    #[error("Unimplemented FMF file version {0}. Only FMF v3 and v4 files supported.")]
     UnimplementedVersion(u32),
+    #[error("unknown FMF compression {0}")]
+    UnknownCompression(u32),
+    #[error("decompressed frame has unexpected size")]
+    DecompressedSize,
+    #[error("compressed frame size {0} exceeds the largest possible size")]
+    CompressedSize(u64),
     #[error("premature file end")]
     PrematureFileEnd,
     #[error("unknown format {0}")]
@@ -62,15 +68,41 @@ impl From<std::cell::BorrowMutError> for FMFError {
 }
 
 pub mod reader;
-pub use crate::reader::FMFReader;
+pub use crate::reader::{FMFReader, SeekableFMFReader};
+
+/// Compression applied to the image data of each frame.
+///
+/// Anything other than [Compression::None] results in an FMF v4 file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// Raw image bytes (FMF v3).
+    #[default]
+    None,
+    /// Lossless zstd compression of each frame (FMF v4).
+    Zstd {
+        /// The zstd compression level. 0 selects the zstd default.
+        level: i32,
+    },
+}
+
+impl Compression {
+    fn id(&self) -> u32 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd { .. } => 1,
+        }
+    }
+}
 
 /// Writes FMF (fly movie format) movie files.
 ///
 /// The FMF format is very simple and writes a fixed sized chunk of bytes to
 /// disk on every frame. This allows random access to individual frames. The
-/// bytes are not compressed but rather store the raw image bytes.
+/// bytes are not compressed but rather store the raw image bytes unless a
+/// [Compression] is selected with [FMFWriter::with_compression].
 pub struct FMFWriter<F: Write + Seek> {
     state: WriterState<F>,
+    compression: Compression,
 }
 
 enum WriterState<F: Write + Seek> {
@@ -84,12 +116,13 @@ struct FMFWriterInner<F: Write + Seek> {
     w: u32,
     h: u32,
     e: PixFmt,
+    compression: Compression,
     row_bytes: usize,
     n_frames_pos_bytes: u64,
     n_frames: u64,
 }
 
-// Things to improve in FMF v 4:
+// Things to improve in a future FMF version:
 //  * Specify a [file signature](https://en.wikipedia.org/wiki/List_of_file_signatures).
 //  * Specify that timestamp is in UTC. (Provide timezone in header?)
 //  * Provide magic number at start of file.
@@ -105,8 +138,14 @@ struct FMFWriterInner<F: Write + Seek> {
 impl<F: Write + Seek> FMFWriter<F> {
     /// Open a new writer.
     pub fn new(f: F) -> FMFResult<Self> {
+        Self::with_compression(f, Compression::None)
+    }
+
+    /// Open a new writer which compresses each frame.
+    pub fn with_compression(f: F, compression: Compression) -> FMFResult<Self> {
         Ok(Self {
             state: WriterState::FileOpened(f),
+            compression,
         })
     }
 
@@ -132,6 +171,7 @@ impl<F: Write + Seek> FMFWriter<F> {
                     frame.width(),
                     frame.height(),
                     machine_vision_formats::pixel_format::pixfmt::<FMT>().unwrap(),
+                    self.compression,
                 )?;
                 WriterState::Writing(inner)
             }
@@ -177,7 +217,13 @@ impl<F: Write + Seek> FMFWriter<F> {
 }
 
 impl<F: Write + Seek> FMFWriterInner<F> {
-    fn new(mut f: F, w: u32, h: u32, pixel_format: PixFmt) -> FMFResult<Self> {
+    fn new(
+        mut f: F,
+        w: u32,
+        h: u32,
+        pixel_format: PixFmt,
+        compression: Compression,
+    ) -> FMFResult<Self> {
         let format = pixel_formats::get_format(pixel_format)?;
 
         let bytes_per_pixel = pixel_format.bits_per_pixel() / 8;
@@ -185,9 +231,14 @@ impl<F: Write + Seek> FMFWriterInner<F> {
         let row_bytes = w as usize * bytes_per_pixel as usize;
         let chunksize = row_bytes * h as usize + 8;
 
+        let version = match compression {
+            Compression::None => 3,
+            _ => 4,
+        };
+
         let mut pos = 0;
-        f.write_u32::<LittleEndian>(3)?;
-        pos += 4; // FMF version = 3
+        f.write_u32::<LittleEndian>(version)?;
+        pos += 4; // FMF version = 3 or 4
         f.write_u32::<LittleEndian>(format.len() as u32)?;
         pos += 4;
         f.write_all(&format)?;
@@ -201,6 +252,9 @@ impl<F: Write + Seek> FMFWriterInner<F> {
         f.write_u64::<LittleEndian>(chunksize as u64)?;
         pos += 8;
         f.write_u64::<LittleEndian>(0)?; // n_frames = 0
+        if version == 4 {
+            f.write_u32::<LittleEndian>(compression.id())?;
+        }
 
         let f = Some(f);
 
@@ -209,6 +263,7 @@ impl<F: Write + Seek> FMFWriterInner<F> {
             w,
             h,
             e: pixel_format,
+            compression,
             row_bytes,
             n_frames_pos_bytes: pos as u64,
             n_frames: 0,
@@ -236,11 +291,26 @@ impl<F: Write + Seek> FMFWriterInner<F> {
         let bpp = e.bits_per_pixel();
         let n_bytes_per_row = self.w as usize * (bpp / 8) as usize;
         let mut ptr = 0;
-        for _ in 0..self.h {
-            let end = ptr + n_bytes_per_row;
-            let row_buf = &image_data[ptr..end];
-            self_f.write_all(row_buf)?;
-            ptr += frame.stride();
+        match self.compression {
+            Compression::None => {
+                for _ in 0..self.h {
+                    let end = ptr + n_bytes_per_row;
+                    let row_buf = &image_data[ptr..end];
+                    self_f.write_all(row_buf)?;
+                    ptr += frame.stride();
+                }
+            }
+            Compression::Zstd { level } => {
+                let mut packed = Vec::with_capacity(n_bytes_per_row * self.h as usize);
+                for _ in 0..self.h {
+                    let end = ptr + n_bytes_per_row;
+                    packed.extend_from_slice(&image_data[ptr..end]);
+                    ptr += frame.stride();
+                }
+                let compressed = zstd::bulk::compress(&packed, level)?;
+                self_f.write_u64::<LittleEndian>(compressed.len() as u64)?;
+                self_f.write_all(&compressed)?;
+            }
         }
 
         self.n_frames += 1;
@@ -283,7 +353,7 @@ impl<F: Write + Seek> Drop for FMFWriterInner<F> {
 
 #[cfg(test)]
 mod tests {
-    use super::FMFWriter;
+    use super::{Compression, FMFError, FMFReader, FMFWriter, SeekableFMFReader};
     use basic_frame::BasicFrame;
 
     use machine_vision_formats::pixel_format::Mono8;
@@ -329,4 +399,118 @@ mod tests {
         let expected = [3, 0, 0, 0, 5, 0, 0, 0, 77, 79]; // TODO improve test
         assert_eq!(&buf[0..10], expected);
     }
+
+    #[test]
+    fn test_zstd_roundtrip() {
+        let (w, h) = (32, 8);
+        let tmpdir = tempfile::tempdir().unwrap();
+
+        let mut frame = zeros(w, h);
+        for (i, px) in frame.image_data.iter_mut().enumerate() {
+            *px = (i % 7) as u8;
+        }
+        let dt = chrono::DateTime::from_timestamp(61, 0).unwrap();
+
+        for compression in [Compression::None, Compression::Zstd { level: 0 }] {
+            let path = tmpdir.path().join("movie.fmf");
+            let f = std::fs::File::create(&path).unwrap();
+            let mut writer = FMFWriter::with_compression(f, compression).unwrap();
+            writer.write(&frame, dt).unwrap();
+            writer.write(&frame, dt).unwrap();
+            writer.close().unwrap();
+
+            let reader = FMFReader::new(&path).unwrap();
+            assert_eq!(reader.is_compressed(), compression != Compression::None);
+            assert_eq!(reader.n_frames(), 2);
+            for result in reader {
+                let (read_frame, read_dt) = result.unwrap();
+                assert_eq!(read_dt, dt);
+                let read_frame = read_frame.as_basic::<Mono8>().unwrap();
+                assert_eq!(read_frame.image_data, frame.image_data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_seekable_reader() {
+        let (w, h) = (16, 4);
+        let tmpdir = tempfile::tempdir().unwrap();
+
+        for compression in [Compression::None, Compression::Zstd { level: 0 }] {
+            let path = tmpdir.path().join("movie.fmf");
+            let f = std::fs::File::create(&path).unwrap();
+            let mut writer = FMFWriter::with_compression(f, compression).unwrap();
+            for i in 0..10 {
+                let mut frame = zeros(w, h);
+                frame.image_data[0] = i;
+                let dt = chrono::DateTime::from_timestamp(100 + i as i64, 0).unwrap();
+                writer.write(&frame, dt).unwrap();
+            }
+            writer.close().unwrap();
+
+            let mut reader = SeekableFMFReader::new(&path).unwrap();
+            assert_eq!(reader.n_frames(), 10);
+            assert_eq!(
+                reader.timestamps()[3],
+                chrono::DateTime::from_timestamp(103, 0).unwrap()
+            );
+            for idx in [7, 2, 9, 0] {
+                let (frame, dt) = reader.read_frame(idx).unwrap();
+                assert_eq!(dt.timestamp(), 100 + idx as i64);
+                let frame = frame.as_basic::<Mono8>().unwrap();
+                assert_eq!(frame.image_data[0], idx as u8);
+            }
+            assert!(reader.read_frame(10).is_err());
+        }
+    }
+
+    #[test]
+    fn test_corrupt_compressed_size() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("movie.fmf");
+        let f = std::fs::File::create(&path).unwrap();
+        let mut writer = FMFWriter::with_compression(f, Compression::Zstd { level: 0 }).unwrap();
+        let dt = chrono::DateTime::from_timestamp(61, 0).unwrap();
+        writer.write(&zeros(16, 4), dt).unwrap();
+        writer.close().unwrap();
+
+        // Overwrite the compressed size of the first frame, which follows the
+        // 45 byte header and the timestamp.
+        let mut buf = std::fs::read(&path).unwrap();
+        buf[53..61].copy_from_slice(&(u64::MAX >> 8).to_le_bytes());
+        std::fs::write(&path, buf).unwrap();
+
+        let mut reader = FMFReader::new(&path).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(FMFError::CompressedSize(_)))
+        ));
+        assert!(matches!(
+            SeekableFMFReader::new(&path),
+            Err(FMFError::CompressedSize(_))
+        ));
+    }
+
+    #[test]
+    fn test_seekable_reader_corrupt_frame_count() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("movie.fmf");
+        let f = std::fs::File::create(&path).unwrap();
+        let mut writer = FMFWriter::new(f).unwrap();
+        let dt = chrono::DateTime::from_timestamp(61, 0).unwrap();
+        for _ in 0..3 {
+            writer.write(&zeros(16, 4), dt).unwrap();
+        }
+        writer.close().unwrap();
+
+        // Overwrite the number of frames in the header, which follows the
+        // version, format "MONO8", bits per pixel, height, width and chunk
+        // size.
+        let mut buf = std::fs::read(&path).unwrap();
+        buf[33..41].copy_from_slice(&(u64::MAX >> 8).to_le_bytes());
+        std::fs::write(&path, buf).unwrap();
+
+        let reader = SeekableFMFReader::new(&path).unwrap();
+        assert_eq!(reader.n_frames(), 3);
+    }
 }
@@ -1,4 +1,8 @@
-use std::{fs::File, io::Read, path::Path};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
@@ -50,43 +54,26 @@ fn open_buffered<P: AsRef<Path>>(p: &P) -> std::io::Result<std::io::BufReader<Fi
     Ok(std::io::BufReader::new(File::open(p.as_ref())?))
 }
 
-pub struct FMFReader {
-    // We cannot Seek because the gzip Decoder does not implement that.
-    f: Box<dyn Read>,
+/// The header of an FMF file.
+struct Header {
     pixel_format: PixFmt,
     height: u32,
     width: u32,
     image_data_size: usize,
-    // In theory, a corrupt file could have more frames than indicated by the
-    // `n_frames` field in the header, but we assume the file is OK.
+    /// Compression id from the FMF v4 header. 0 means uncompressed.
+    compression: u32,
     n_frames: usize,
-    count: usize,
-    file_pos: usize,
-    did_error: bool,
+    /// Size of the header in bytes.
+    size: usize,
 }
 
-impl FMFReader {
-    pub fn new<P: AsRef<Path>>(path: P) -> FMFResult<FMFReader> {
-        let extension = path.as_ref().extension().and_then(|x| x.to_str());
-        let mut f: Box<dyn Read> = if extension == Some("gz") {
-            let gz_fd = open_buffered(&path).map_err(|e| FMFError::IoPath {
-                source: e,
-                path: path.as_ref().display().to_string(),
-            })?;
-            let decoder = libflate::gzip::Decoder::new(gz_fd)?;
-            Box::new(decoder)
-        } else {
-            Box::new(open_buffered(&path).map_err(|e| FMFError::IoPath {
-                source: e,
-                path: path.as_ref().display().to_string(),
-            })?)
-        };
-
+impl Header {
+    fn read<R: Read>(f: &mut R) -> FMFResult<Self> {
         // version
         let mut pos = 0;
         let version = f.read_u32::<LittleEndian>()?;
         pos += 4;
-        if version != 3 {
+        if version != 3 && version != 4 {
             return Err(FMFError::UnimplementedVersion(version));
         }
 
@@ -113,73 +100,163 @@ impl FMFReader {
         pos += 8;
         let n_frames = f.read_u64::<LittleEndian>()?.try_into().unwrap();
         pos += 8;
-        let count = 0;
+        let compression = if version == 4 {
+            pos += 4;
+            f.read_u32::<LittleEndian>()?
+        } else {
+            0
+        };
+        if compression > 1 {
+            return Err(FMFError::UnknownCompression(compression));
+        }
 
         Ok(Self {
-            f,
             pixel_format,
             height,
             width,
             image_data_size,
+            compression,
             n_frames,
-            count,
-            file_pos: pos,
+            size: pos,
+        })
+    }
+
+    fn is_compressed(&self) -> bool {
+        self.compression != 0
+    }
+
+    /// The largest size of a compressed frame.
+    fn max_compressed_size(&self) -> usize {
+        zstd::zstd_safe::compress_bound(self.image_data_size)
+    }
+
+    /// Read the frame chunk at the current position of `f`.
+    ///
+    /// Returns the frame, its timestamp and the number of bytes read.
+    fn read_frame<R: Read>(&self, f: &mut R) -> FMFResult<(DynamicFrame, DateTime<Utc>, usize)> {
+        let mut n_bytes = 0;
+        let mut timestamp_data: Vec<u8> = vec![0; TIMESTAMP_SIZE];
+        f.read_exact(&mut timestamp_data)?;
+        n_bytes += TIMESTAMP_SIZE;
+
+        let image_data = if self.is_compressed() {
+            let compressed_size = f.read_u64::<LittleEndian>()?;
+            n_bytes += 8;
+            // Check the size read from the file before allocating.
+            let compressed_size = usize::try_from(compressed_size)
+                .ok()
+                .filter(|size| *size <= self.max_compressed_size())
+                .ok_or(FMFError::CompressedSize(compressed_size))?;
+            let mut compressed: Vec<u8> = vec![0; compressed_size];
+            f.read_exact(&mut compressed)?;
+            n_bytes += compressed_size;
+            let image_data = zstd::bulk::decompress(&compressed, self.image_data_size)?;
+            if image_data.len() != self.image_data_size {
+                return Err(FMFError::DecompressedSize);
+            }
+            image_data
+        } else {
+            let mut image_data: Vec<u8> = vec![0; self.image_data_size];
+            f.read_exact(&mut image_data)?;
+            n_bytes += self.image_data_size;
+            image_data
+        };
+
+        let timestamp_f64 = timestamp_data.as_slice().read_f64::<LittleEndian>()?;
+        let dt = datetime_conversion::f64_to_datetime(timestamp_f64);
+
+        let width = self.width;
+        let height = self.height;
+        let pixel_format = self.pixel_format;
+        let bpp = self.pixel_format.bits_per_pixel() as u32;
+        let stride = (width * bpp) / 8;
+
+        Ok((
+            to_dynamic!(pixel_format, width, height, stride, image_data),
+            dt,
+            n_bytes,
+        ))
+    }
+}
+
+pub struct FMFReader {
+    // We cannot Seek because the gzip Decoder does not implement that.
+    f: Box<dyn Read>,
+    header: Header,
+    // In theory, a corrupt file could have more frames than indicated by the
+    // `n_frames` field in the header, but we assume the file is OK.
+    count: usize,
+    file_pos: usize,
+    did_error: bool,
+}
+
+impl FMFReader {
+    pub fn new<P: AsRef<Path>>(path: P) -> FMFResult<FMFReader> {
+        let extension = path.as_ref().extension().and_then(|x| x.to_str());
+        let mut f: Box<dyn Read> = if extension == Some("gz") {
+            let gz_fd = open_buffered(&path).map_err(|e| FMFError::IoPath {
+                source: e,
+                path: path.as_ref().display().to_string(),
+            })?;
+            let decoder = libflate::gzip::Decoder::new(gz_fd)?;
+            Box::new(decoder)
+        } else {
+            Box::new(open_buffered(&path).map_err(|e| FMFError::IoPath {
+                source: e,
+                path: path.as_ref().display().to_string(),
+            })?)
+        };
+
+        let header = Header::read(&mut f)?;
+        let file_pos = header.size;
+
+        Ok(Self {
+            f,
+            header,
+            count: 0,
+            file_pos,
             did_error: false,
         })
     }
 
     #[inline]
     pub fn width(&self) -> u32 {
-        self.width
+        self.header.width
     }
 
     #[inline]
     pub fn height(&self) -> u32 {
-        self.height
+        self.header.height
     }
 
     #[inline]
     pub fn format(&self) -> PixFmt {
-        self.pixel_format
+        self.header.pixel_format
     }
 
     pub fn file_pos(&self) -> usize {
         self.file_pos
     }
 
+    /// Return whether the image data of each frame is zstd compressed (FMF v4).
+    pub fn is_compressed(&self) -> bool {
+        self.header.is_compressed()
+    }
+
     /// Return the number of frames indicated in the header.
     pub fn n_frames(&self) -> usize {
-        self.n_frames
+        self.header.n_frames
     }
 
     fn next_frame(&mut self) -> FMFResult<(DynamicFrame, DateTime<Utc>)> {
         // Private function to actually read next frame.
-        if self.count >= self.n_frames {
+        if self.count >= self.header.n_frames {
             return Err(FMFError::ReadingPastEnd);
         }
-
-        let mut timestamp_data: Vec<u8> = vec![0; TIMESTAMP_SIZE];
-        self.f.read_exact(&mut timestamp_data)?;
-        self.file_pos += TIMESTAMP_SIZE;
-
-        let mut image_data: Vec<u8> = vec![0; self.image_data_size];
-        self.f.read_exact(&mut image_data)?;
-        self.file_pos += self.image_data_size;
-
-        let timestamp_f64 = timestamp_data.as_slice().read_f64::<LittleEndian>()?;
-        let dt = datetime_conversion::f64_to_datetime(timestamp_f64);
-
-        let width = self.width;
-        let height = self.height;
-        let pixel_format = self.pixel_format;
-        let bpp = self.pixel_format.bits_per_pixel() as u32;
-        let stride = (width * bpp) / 8;
+        let (frame, dt, n_bytes) = self.header.read_frame(&mut self.f)?;
+        self.file_pos += n_bytes;
         self.count += 1;
-
-        Ok((
-            to_dynamic!(pixel_format, width, height, stride, image_data),
-            dt,
-        ))
+        Ok((frame, dt))
     }
 }
 
@@ -191,7 +268,7 @@ impl Iterator for FMFReader {
             return None;
         }
 
-        if self.count >= self.n_frames {
+        if self.count >= self.header.n_frames {
             // Done reading all frames. Do not read more.
             return None;
         }
@@ -203,3 +280,110 @@ impl Iterator for FMFReader {
         Some(frame)
     }
 }
+
+/// Reads frames of an uncompressed (i.e. not `.fmf.gz`) FMF file in any order.
+///
+/// When opened, the file is scanned once to find the position and timestamp
+/// of every frame. In FMF v3 files, frames have a constant size and this only
+/// reads the timestamps. In FMF v4 files, the size of each compressed frame is
+/// read and the image data is skipped.
+pub struct SeekableFMFReader {
+    f: BufReader<File>,
+    header: Header,
+    /// File position of each frame chunk.
+    positions: Vec<u64>,
+    timestamps: Vec<DateTime<Utc>>,
+}
+
+impl SeekableFMFReader {
+    pub fn new<P: AsRef<Path>>(path: P) -> FMFResult<Self> {
+        let mut f = open_buffered(&path).map_err(|e| FMFError::IoPath {
+            source: e,
+            path: path.as_ref().display().to_string(),
+        })?;
+        let file_len = f.get_ref().metadata()?.len();
+        let header = Header::read(&mut f)?;
+
+        // A value of 0 in the header means the number of frames is unknown.
+        let max_frames = match header.n_frames {
+            0 => usize::MAX,
+            n_frames => n_frames,
+        };
+        // Do not trust the header with the allocation: the file cannot contain
+        // more frames than chunks of the smallest possible size.
+        let min_chunk_size = if header.is_compressed() {
+            TIMESTAMP_SIZE + 8
+        } else {
+            TIMESTAMP_SIZE + header.image_data_size
+        };
+        let max_chunks = file_len.saturating_sub(header.size as u64) / min_chunk_size as u64;
+        let capacity = header
+            .n_frames
+            .min(max_chunks.try_into().unwrap_or(usize::MAX));
+        let mut positions = Vec::with_capacity(capacity);
+        let mut timestamps = Vec::with_capacity(capacity);
+        let mut pos = header.size as u64;
+        while positions.len() < max_frames && pos + TIMESTAMP_SIZE as u64 <= file_len {
+            f.seek(SeekFrom::Start(pos))?;
+            let timestamp_f64 = f.read_f64::<LittleEndian>()?;
+            let chunk_size = if header.is_compressed() {
+                let compressed_size = f.read_u64::<LittleEndian>()?;
+                if compressed_size > header.max_compressed_size() as u64 {
+                    return Err(FMFError::CompressedSize(compressed_size));
+                }
+                TIMESTAMP_SIZE as u64 + 8 + compressed_size
+            } else {
+                (TIMESTAMP_SIZE + header.image_data_size) as u64
+            };
+            if pos + chunk_size > file_len {
+                break;
+            }
+            positions.push(pos);
+            timestamps.push(datetime_conversion::f64_to_datetime(timestamp_f64));
+            pos += chunk_size;
+        }
+        if header.n_frames > 0 && positions.len() < header.n_frames {
+            return Err(FMFError::PrematureFileEnd);
+        }
+
+        Ok(Self {
+            f,
+            header,
+            positions,
+            timestamps,
+        })
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.header.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.header.height
+    }
+
+    #[inline]
+    pub fn format(&self) -> PixFmt {
+        self.header.pixel_format
+    }
+
+    /// Return the number of frames.
+    pub fn n_frames(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Return the timestamps of all frames.
+    pub fn timestamps(&self) -> &[DateTime<Utc>] {
+        &self.timestamps
+    }
+
+    /// Read frame number `idx`.
+    pub fn read_frame(&mut self, idx: usize) -> FMFResult<(DynamicFrame, DateTime<Utc>)> {
+        let pos = *self.positions.get(idx).ok_or(FMFError::ReadingPastEnd)?;
+        self.f.seek(SeekFrom::Start(pos))?;
+        let (frame, dt, _n_bytes) = self.header.read_frame(&mut self.f)?;
+        Ok((frame, dt))
+    }
+}
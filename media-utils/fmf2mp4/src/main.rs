@@ -0,0 +1,240 @@
+//! Batch convert FMF (`.fmf`, `.fmf.gz`) and UFMF (`.ufmf`) files to MP4.
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, ValueEnum};
+use eyre::{self, Result, WrapErr};
+use rayon::prelude::*;
+
+use ci2_remote_control::{H264Metadata, Mp4Codec, Mp4RecordingConfig, OpenH264Options};
+use frame_source::Timestamp;
+
+/// Convert FMF and UFMF files to H264 encoded MP4 files.
+///
+/// The timestamp of each frame is saved in the MP4 file as an H264 precision
+/// timestamp (SEI message), as in MP4 files saved by Strand Camera. The time of
+/// the first frame and, when available, camera name and gamma are saved in the
+/// H264 metadata.
+///
+/// For a UFMF input, full frames are reconstructed from the saved background
+/// model with the saved foreground regions drawn on top.
+///
+/// Several files are converted in parallel.
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Cli {
+    /// Input files.
+    #[arg(required = true, num_args = 1..)]
+    inputs: Vec<PathBuf>,
+
+    /// Output directory. Defaults to the directory of each input file.
+    #[arg(short, long)]
+    output_dir: Option<PathBuf>,
+
+    /// Set the H264 encoder
+    #[arg(long, value_enum, default_value_t)]
+    encoder: Encoder,
+
+    /// Bitrate (in kbps) for the OpenH264 encoder.
+    ///
+    /// If not set, all frames are encoded at the default quality.
+    #[arg(long)]
+    bitrate: Option<u32>,
+
+    /// Number of files to convert in parallel. Defaults to the number of CPUs.
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// Overwrite existing output.
+    #[arg(long)]
+    overwrite: bool,
+}
+
+#[derive(Default, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum Encoder {
+    /// The less-avc uncompressed (lossless) H264 encoder
+    LessAvc,
+    /// The openh264 encoder
+    #[default]
+    OpenH264,
+}
+
+impl Cli {
+    fn codec(&self) -> Mp4Codec {
+        match self.encoder {
+            Encoder::LessAvc => Mp4Codec::H264LessAvc,
+            Encoder::OpenH264 => {
+                let preset = match self.bitrate {
+                    None => ci2_remote_control::OpenH264Preset::AllFrames,
+                    Some(bitrate) => ci2_remote_control::OpenH264Preset::SkipFramesBitrate(bitrate),
+                };
+                Mp4Codec::H264OpenH264(OpenH264Options {
+                    debug: false,
+                    preset,
+                })
+            }
+        }
+    }
+
+    /// Get the output filename for `input`.
+    fn output_path(&self, input: &Path) -> Result<PathBuf> {
+        let fname = input
+            .file_name()
+            .and_then(|x| x.to_str())
+            .ok_or_else(|| eyre::eyre!("invalid input filename {}", input.display()))?;
+        let lower = fname.to_lowercase();
+        let stem = [".fmf.gz", ".fmf", ".ufmf"]
+            .iter()
+            .find(|ext| lower.ends_with(*ext))
+            .map(|ext| &fname[..fname.len() - ext.len()])
+            .ok_or_else(|| eyre::eyre!("input {} is not an FMF or UFMF file", input.display()))?;
+        let dirname = match &self.output_dir {
+            Some(output_dir) => output_dir.as_path(),
+            None => input.parent().unwrap_or_else(|| Path::new("")),
+        };
+        Ok(dirname.join(format!("{stem}.mp4")))
+    }
+}
+
+/// Check that no two inputs, e.g. `a/cam.fmf` and `b/cam.fmf` converted into
+/// the same output directory, would be saved to the same output.
+fn check_unique_outputs(jobs: &[(&Path, PathBuf)]) -> Result<()> {
+    let mut inputs_by_output = std::collections::BTreeMap::new();
+    for (input, output) in jobs.iter() {
+        if let Some(other) = inputs_by_output.insert(output, input) {
+            eyre::bail!(
+                "Inputs {} and {} would both be saved to {}.",
+                other.display(),
+                input.display(),
+                output.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Convert a single file. Returns the number of frames written.
+fn convert(input: &Path, output: &Path, codec: Mp4Codec) -> Result<usize> {
+    let writing_app = format!("{}-{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+    let do_decode_h264 = false;
+    let mut src = frame_source::from_path(input, do_decode_h264)?;
+    let frame0_time = src
+        .frame0_time()
+        .ok_or_else(|| eyre::eyre!("no timestamp found for first frame"))?;
+
+    let mut h264_metadata = H264Metadata::new(&writing_app, frame0_time);
+    h264_metadata.camera_name = src.camera_name().map(Into::into);
+    h264_metadata.gamma = src.gamma();
+
+    let cfg = Mp4RecordingConfig {
+        codec,
+        max_framerate: ci2_remote_control::RecordingFrameRate::Unlimited,
+        h264_metadata: Some(h264_metadata),
+        color_encoding: Default::default(),
+    };
+
+    let out_fd = std::fs::File::create(output)?;
+    let mut my_mp4_writer = mp4_writer::Mp4Writer::new(out_fd, cfg, None)?;
+
+    let mut n_frames = 0;
+    for frame in src.iter() {
+        let frame = frame?;
+        let pts = match frame.timestamp() {
+            Timestamp::Duration(pts) => pts,
+            Timestamp::Fraction(_) => {
+                eyre::bail!("no timestamp for frame {}", frame.idx());
+            }
+        };
+        let timestamp = frame0_time + chrono::Duration::from_std(pts)?;
+        let image = frame
+            .decoded()
+            .ok_or_else(|| eyre::eyre!("frame {} was not decoded", frame.idx()))?;
+        my_mp4_writer.write_dynamic(image, timestamp)?;
+        n_frames += 1;
+    }
+    my_mp4_writer.finish()?;
+    Ok(n_frames)
+}
+
+fn main() -> Result<()> {
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    env_tracing_logger::init();
+    let cli = Cli::parse();
+
+    let jobs = cli
+        .inputs
+        .iter()
+        .map(|input| Ok((input.as_path(), cli.output_path(input)?)))
+        .collect::<Result<Vec<_>>>()?;
+    check_unique_outputs(&jobs)?;
+
+    if !cli.overwrite {
+        for (_, output) in jobs.iter() {
+            if output.exists() {
+                eyre::bail!(
+                    "Output {} exists. Use --overwrite to overwrite.",
+                    output.display()
+                );
+            }
+        }
+    }
+
+    let mut pool = rayon::ThreadPoolBuilder::new();
+    if let Some(n_jobs) = cli.jobs {
+        pool = pool.num_threads(n_jobs);
+    }
+    let pool = pool.build()?;
+
+    let results: Vec<Result<()>> = pool.install(|| {
+        jobs.par_iter()
+            .map(|(input, output)| {
+                let n_frames = convert(input, output, cli.codec())
+                    .with_context(|| format!("while converting {}", input.display()))
+                    .inspect_err(|_| {
+                        // Do not leave a partial output file.
+                        std::fs::remove_file(output).ok();
+                    })?;
+                tracing::info!(
+                    "Saved {n_frames} frames from {} to {}.",
+                    input.display(),
+                    output.display()
+                );
+                Ok(())
+            })
+            .collect()
+    });
+
+    let mut n_failed = 0;
+    for err in results.into_iter().filter_map(Result::err) {
+        tracing::error!("{err:?}");
+        n_failed += 1;
+    }
+    if n_failed > 0 {
+        eyre::bail!("{n_failed} of {} file(s) failed to convert.", jobs.len());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_output_collision() {
+    let cli = Cli::parse_from(["fmf2mp4", "-o", "out", "a/cam.fmf", "b/cam.fmf.gz"]);
+    let jobs: Vec<_> = cli
+        .inputs
+        .iter()
+        .map(|input| (input.as_path(), cli.output_path(input).unwrap()))
+        .collect();
+    assert_eq!(jobs[0].1, Path::new("out/cam.mp4"));
+    assert!(check_unique_outputs(&jobs).is_err());
+
+    // Without an output directory, each output is next to its input.
+    let cli = Cli::parse_from(["fmf2mp4", "a/cam.fmf", "b/cam.fmf.gz"]);
+    let jobs: Vec<_> = cli
+        .inputs
+        .iter()
+        .map(|input| (input.as_path(), cli.output_path(input).unwrap()))
+        .collect();
+    assert_eq!(jobs[1].1, Path::new("b/cam.mp4"));
+    assert!(check_unique_outputs(&jobs).is_ok());
+}
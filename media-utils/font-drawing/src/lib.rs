@@ -2,6 +2,9 @@ use rusttype::{point, Scale};
 use machine_vision_formats::{pixel_format, ImageMutStride};
 use eyre::Result;
 
+mod text_overlay;
+pub use text_overlay::{Corner, TextOverlay};
+
 struct Rgba(pub [u8; 4]);
 
 fn put_pixel(image: &mut dyn ImageMutStride<pixel_format::RGB8>, x: u32, y: u32, incoming: Rgba) {
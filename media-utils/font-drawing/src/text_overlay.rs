@@ -0,0 +1,244 @@
+//! Fast drawing of a line of text into a corner of Mono8 and RGB8 images.
+//!
+//! The glyphs are rasterized once when the [TextOverlay] is created, so that
+//! drawing a frame only copies the precomputed coverage into the image. The
+//! font is assumed to be monospaced.
+
+use machine_vision_formats::{pixel_format, ImageMutStride};
+use rusttype::{point, Scale};
+
+/// The first and last character which can be drawn. Other characters are
+/// drawn as [REPLACEMENT].
+const FIRST_CHAR: char = ' ';
+const LAST_CHAR: char = '~';
+const REPLACEMENT: char = '?';
+
+/// The corner of the image in which the text is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Draws white text on a black box.
+pub struct TextOverlay {
+    /// The coverage (0-255) of each character cell, row-major.
+    glyphs: Vec<Vec<u8>>,
+    cell_width: usize,
+    cell_height: usize,
+    /// Width of the black border around the text.
+    padding: usize,
+}
+
+impl TextOverlay {
+    /// Rasterize the glyphs of `font` with a height of `text_height` pixels.
+    pub fn new(font: &rusttype::Font<'_>, text_height: f32) -> Self {
+        let scale = Scale::uniform(text_height);
+        let v_metrics = font.v_metrics(scale);
+        let cell_height = (v_metrics.ascent - v_metrics.descent).ceil().max(1.0) as usize;
+        let cell_width = font
+            .glyph('0')
+            .scaled(scale)
+            .h_metrics()
+            .advance_width
+            .ceil()
+            .max(1.0) as usize;
+
+        let glyphs = (FIRST_CHAR..=LAST_CHAR)
+            .map(|c| {
+                let mut coverage = vec![0u8; cell_width * cell_height];
+                let glyph = font
+                    .glyph(c)
+                    .scaled(scale)
+                    .positioned(point(0.0, v_metrics.ascent));
+                if let Some(bounding_box) = glyph.pixel_bounding_box() {
+                    glyph.draw(|x, y, v| {
+                        // Parts of a glyph outside its cell are dropped.
+                        let x = bounding_box.min.x + x as i32;
+                        let y = bounding_box.min.y + y as i32;
+                        if (0..cell_width as i32).contains(&x)
+                            && (0..cell_height as i32).contains(&y)
+                        {
+                            coverage[y as usize * cell_width + x as usize] =
+                                (v * 255.0).round() as u8;
+                        }
+                    });
+                }
+                coverage
+            })
+            .collect();
+
+        Self {
+            glyphs,
+            cell_width,
+            cell_height,
+            padding: (cell_height / 4).max(1),
+        }
+    }
+
+    /// The width and height, in pixels, of the box drawn for `text`.
+    pub fn size(&self, text: &str) -> (usize, usize) {
+        (
+            text.chars().count() * self.cell_width + 2 * self.padding,
+            self.cell_height + 2 * self.padding,
+        )
+    }
+
+    /// Draw `text` into `corner` of `image`.
+    ///
+    /// Text which does not fit into the image is cut off.
+    pub fn draw_mono8(
+        &self,
+        image: &mut dyn ImageMutStride<pixel_format::Mono8>,
+        corner: Corner,
+        text: &str,
+    ) {
+        let (width, height, stride) = (
+            image.width() as usize,
+            image.height() as usize,
+            image.stride(),
+        );
+        self.draw(
+            image.buffer_mut_ref().data,
+            width,
+            height,
+            stride,
+            1,
+            corner,
+            text,
+        );
+    }
+
+    /// Draw `text` into `corner` of `image`.
+    ///
+    /// Text which does not fit into the image is cut off.
+    pub fn draw_rgb8(
+        &self,
+        image: &mut dyn ImageMutStride<pixel_format::RGB8>,
+        corner: Corner,
+        text: &str,
+    ) {
+        let (width, height, stride) = (
+            image.width() as usize,
+            image.height() as usize,
+            image.stride(),
+        );
+        self.draw(
+            image.buffer_mut_ref().data,
+            width,
+            height,
+            stride,
+            3,
+            corner,
+            text,
+        );
+    }
+
+    /// Draw into image data with `bytes_per_pixel` identical bytes per pixel.
+    #[allow(clippy::too_many_arguments)]
+    fn draw(
+        &self,
+        data: &mut [u8],
+        width: usize,
+        height: usize,
+        stride: usize,
+        bytes_per_pixel: usize,
+        corner: Corner,
+        text: &str,
+    ) {
+        let (box_width, box_height) = self.size(text);
+        let box_width = box_width.min(width);
+        let box_height = box_height.min(height);
+        let x0 = match corner {
+            Corner::TopLeft | Corner::BottomLeft => 0,
+            Corner::TopRight | Corner::BottomRight => width - box_width,
+        };
+        let y0 = match corner {
+            Corner::TopLeft | Corner::TopRight => 0,
+            Corner::BottomLeft | Corner::BottomRight => height - box_height,
+        };
+        let glyphs: Vec<&[u8]> = text.chars().map(|c| self.glyph(c)).collect();
+
+        for row in 0..box_height {
+            let start = (y0 + row) * stride + x0 * bytes_per_pixel;
+            let line = &mut data[start..start + box_width * bytes_per_pixel];
+            line.fill(0);
+            let glyph_row = match row.checked_sub(self.padding) {
+                Some(glyph_row) if glyph_row < self.cell_height => glyph_row,
+                _ => continue,
+            };
+            for (i, glyph) in glyphs.iter().enumerate() {
+                let col0 = self.padding + i * self.cell_width;
+                if col0 >= box_width {
+                    break;
+                }
+                let coverage =
+                    &glyph[glyph_row * self.cell_width..(glyph_row + 1) * self.cell_width];
+                let n = coverage.len().min(box_width - col0);
+                let pixels = &mut line[col0 * bytes_per_pixel..(col0 + n) * bytes_per_pixel];
+                for (pixel, &value) in pixels.chunks_exact_mut(bytes_per_pixel).zip(coverage) {
+                    pixel.fill(value);
+                }
+            }
+        }
+    }
+
+    fn glyph(&self, c: char) -> &[u8] {
+        let c = if (FIRST_CHAR..=LAST_CHAR).contains(&c) {
+            c
+        } else {
+            REPLACEMENT
+        };
+        &self.glyphs[c as usize - FIRST_CHAR as usize]
+    }
+}
+
+#[test]
+fn test_text_overlay() {
+    let font = rusttype::Font::try_from_bytes(ttf_firacode::REGULAR).unwrap();
+    let overlay = TextOverlay::new(&font, 16.0);
+    let (box_width, box_height) = overlay.size("12");
+    assert!(box_width < 40 && box_height < 40);
+
+    // RGB8 image with a stride larger than its width.
+    let (width, height, stride) = (40, 40, 40 * 3 + 8);
+    let mut data = vec![128u8; stride * height];
+    overlay.draw(
+        &mut data,
+        width,
+        height,
+        stride,
+        3,
+        Corner::BottomRight,
+        "12",
+    );
+    let pixel = |x: usize, y: usize| &data[y * stride + x * 3..y * stride + x * 3 + 3];
+    // Outside the box, nothing is changed.
+    assert_eq!(pixel(0, 0), [128, 128, 128]);
+    assert_eq!(pixel(width - box_width - 1, height - 1), [128, 128, 128]);
+    // The border of the box is black.
+    assert_eq!(pixel(width - 1, height - 1), [0, 0, 0]);
+    // Text is drawn, with equal channels.
+    let box_pixels: Vec<&[u8]> = (height - box_height..height)
+        .flat_map(|y| (width - box_width..width).map(move |x| (x, y)))
+        .map(|(x, y)| pixel(x, y))
+        .collect();
+    assert!(box_pixels.iter().any(|p| p[0] > 200));
+    assert!(box_pixels.iter().all(|p| p[0] == p[1] && p[1] == p[2]));
+
+    // Text longer than a Mono8 image is cut off.
+    let mut data = vec![128u8; 20 * 30];
+    overlay.draw(
+        &mut data,
+        20,
+        30,
+        20,
+        1,
+        Corner::TopLeft,
+        "a long line of text",
+    );
+    assert!(data[..20].iter().all(|&v| v == 0));
+    assert!(data[20 * box_height..].iter().all(|&v| v == 128));
+}
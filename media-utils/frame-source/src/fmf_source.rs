@@ -1,63 +1,25 @@
-use crate::{FrameData, FrameDataSource, ImageData, Result, Timestamp};
-use fmf::reader::FMFReader;
+use crate::{
+    reopen::ReopenState, FrameData, FrameDataSource, ImageData, Result, SeekableFrameDataSource,
+    Timestamp,
+};
+use fmf::{reader::FMFReader, SeekableFMFReader};
 use std::path::Path;
 
-struct FmfSourceIter {
-    rdr: FMFReader,
-    frame0_time_utc: chrono::DateTime<chrono::Utc>,
-    idx: usize,
-}
-impl FmfSourceIter {
-    fn new(parent: &FmfSource) -> Result<Self> {
-        let mut rdr = FMFReader::new(&parent.filename)?;
-        let frame0_time_utc = parent.frame0_time_utc;
-        for _ in 0..parent.skip_frames {
-            rdr.next();
-        }
-        Ok(Self {
-            rdr,
-            frame0_time_utc,
-            idx: 0,
-        })
-    }
-}
-impl Iterator for FmfSourceIter {
-    type Item = Result<FrameData>;
-    fn next(&mut self) -> Option<Self::Item> {
-        let pos_start = self.rdr.file_pos();
-        self.rdr.next().map(|fmf_result| match fmf_result {
-            Ok((frame, frame_time_utc)) => {
-                let pos_end = self.rdr.file_pos();
-                let buf_len = pos_end - pos_start;
-                let timestamp = frame_time_utc - self.frame0_time_utc;
-                let timestamp = Timestamp::Duration(timestamp.to_std()?);
-                let idx = self.idx;
-                self.idx += 1;
-                Ok(FrameData {
-                    image: ImageData::Decoded(frame),
-                    timestamp,
-                    buf_len,
-                    idx,
-                })
-            }
-            Err(e) => Err(e.into()),
-        })
-    }
-}
-
 // Because of the need to create an iterator over the frames an arbitrary number
 // of times but the inability of `FMFReader` to seek (due to underlying
 // potential use of a .gz file reader which does not support seeking), we store
 // the filename and repeatedly reopen the file as necessary. An an optimization,
 // the opened reader and its last read frame could be kept in a cache. This
 // would reduce the number of re-openings.
+//
+// For random access (see [SeekableFrameDataSource]), which is not possible with
+// .gz files, a [SeekableFMFReader] is opened on first use and kept.
 pub struct FmfSource {
     filename: std::path::PathBuf,
     width: u32,
     height: u32,
-    frame0_time_utc: chrono::DateTime<chrono::Utc>,
-    frame0_time: chrono::DateTime<chrono::FixedOffset>,
-    skip_frames: usize,
+    reopen: ReopenState,
+    seekable: Option<SeekableFMFReader>,
 }
 
 impl FrameDataSource for FmfSource {
@@ -68,38 +30,23 @@ impl FrameDataSource for FmfSource {
         self.height
     }
     fn frame0_time(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
-        Some(self.frame0_time)
+        Some(self.reopen.frame0_time)
     }
     fn skip_n_frames(&mut self, n_frames: usize) -> Result<()> {
-        if n_frames == 0 {
-            return Ok(());
-        }
-        let mut rdr = FMFReader::new(&self.filename)?;
-
-        let mut frame_timestamp = None;
-        for _ in 0..n_frames {
-            frame_timestamp = rdr.next()
-        }
-
-        let frame_timestamp = frame_timestamp
-            .map(|f| f.map_err(Into::into))
-            .unwrap_or_else(|| Err(crate::Error::FmfWithNotEnoughData))?;
-
-        let (_frame, frame_time_utc) = frame_timestamp;
-        let duration = frame_time_utc - self.frame0_time_utc;
-        let frame_time = self.frame0_time + duration;
-
-        self.skip_frames = n_frames;
-        self.frame0_time = frame_time;
-        self.frame0_time_utc = frame_time_utc;
-        Ok(())
+        let filename = &self.filename;
+        self.reopen.skip_n_frames(
+            n_frames,
+            || Ok(FMFReader::new(filename)?),
+            crate::Error::FmfWithNotEnoughData,
+        )
     }
     fn estimate_luminance_range(&mut self) -> Result<(u16, u16)> {
         // FMF reader does not support seek because we may read .gz files.
         Err(crate::Error::UnsupportedForEsimatingLuminangeRange)
     }
     fn iter(&mut self) -> Box<dyn Iterator<Item = Result<FrameData>>> {
-        Box::new(FmfSourceIter::new(self).unwrap())
+        let rdr = FMFReader::new(&self.filename).map_err(Into::into);
+        self.reopen.iter(rdr, FMFReader::file_pos)
     }
     fn timestamp_source(&self) -> &str {
         "FMF frame metadata"
@@ -109,7 +56,58 @@ impl FrameDataSource for FmfSource {
     }
 }
 
+impl SeekableFrameDataSource for FmfSource {
+    fn frame_timestamps(&mut self) -> Result<Vec<std::time::Duration>> {
+        let frame0_time_utc = self.reopen.frame0_time_utc;
+        let skip_frames = self.reopen.skip_frames;
+        self.seekable()?.timestamps()[skip_frames..]
+            .iter()
+            .map(|t| Ok((*t - frame0_time_utc).to_std()?))
+            .collect()
+    }
+    fn read_frames(&mut self, range: std::ops::Range<usize>) -> Result<Vec<FrameData>> {
+        let frame0_time_utc = self.reopen.frame0_time_utc;
+        let skip_frames = self.reopen.skip_frames;
+        let rdr = self.seekable()?;
+        if range.end + skip_frames > rdr.n_frames() {
+            return Err(crate::Error::FrameIndexOutOfRange(
+                range.end.saturating_sub(1),
+            ));
+        }
+        range
+            .map(|idx| {
+                let (frame, frame_time_utc) = rdr.read_frame(idx + skip_frames)?;
+                let buf_len = frame.image_data_without_format().len();
+                let timestamp = Timestamp::Duration((frame_time_utc - frame0_time_utc).to_std()?);
+                Ok(FrameData {
+                    image: ImageData::Decoded(frame),
+                    timestamp,
+                    buf_len,
+                    idx,
+                })
+            })
+            .collect()
+    }
+}
+
 impl FmfSource {
+    fn seekable(&mut self) -> Result<&mut SeekableFMFReader> {
+        if self.seekable.is_none() {
+            if self
+                .filename
+                .to_string_lossy()
+                .to_lowercase()
+                .ends_with(".gz")
+            {
+                return Err(crate::Error::NotImplemented(
+                    "random access in .fmf.gz files",
+                ));
+            }
+            self.seekable = Some(SeekableFMFReader::new(&self.filename)?);
+        }
+        Ok(self.seekable.as_mut().unwrap())
+    }
+
     fn new<P: AsRef<std::path::Path>>(filename: P) -> Result<Self> {
         let filename = filename.as_ref().to_path_buf();
         let mut rdr = FMFReader::new(&filename)?;
@@ -127,9 +125,8 @@ impl FmfSource {
             filename,
             width,
             height,
-            frame0_time_utc,
-            frame0_time,
-            skip_frames: 0,
+            reopen: ReopenState::new(frame0_time_utc, frame0_time),
+            seekable: None,
         })
     }
 }
@@ -1,5 +1,6 @@
 // Copyright 2024 Andrew D. Straw.
 use bytes::Buf;
+use h264_nal_parse::trim_trailing_zeros;
 use std::io::Read;
 
 use crate::{h264_source::AnnexBLocation, Result};
@@ -22,13 +23,11 @@ pub(crate) fn find_nals<R: Read>(mut rdr: R) -> Result<Vec<AnnexBLocation>> {
 
         while let Some(idx) = finder.find(&buf) {
             if let Some(prev_nal_start) = cur_nal_start.take() {
-                let prev_nal_end = if idx >= 1 && buf[idx - 1] == 0x00 {
-                    // 4 byte start code
-                    buf_position_in_source + idx - 1
-                } else {
-                    // 3 byte start code
-                    buf_position_in_source + idx
-                };
+                // The buffer starts at the previous NAL unit. Its end excludes
+                // the leading zero of a 4 byte start code and any
+                // trailing_zero_8bits.
+                debug_assert_eq!(prev_nal_start, buf_position_in_source);
+                let prev_nal_end = prev_nal_start + trim_trailing_zeros(&buf[..idx]).len();
                 start_stop.push((prev_nal_start, prev_nal_end));
             }
             cur_nal_start = Some(buf_position_in_source + idx + 3);
@@ -39,11 +38,13 @@ pub(crate) fn find_nals<R: Read>(mut rdr: R) -> Result<Vec<AnnexBLocation>> {
         }
     }
     if let Some(prev_nal_start) = cur_nal_start.take() {
-        let prev_nal_end = prev_nal_start + buf.len();
+        let prev_nal_end = prev_nal_start + trim_trailing_zeros(&buf).len();
         start_stop.push((prev_nal_start, prev_nal_end));
     }
     Ok(start_stop
         .iter()
+        // Skip empty NAL units between consecutive start codes.
+        .filter(|(start, stop)| stop > start)
         .map(|(start, stop)| AnnexBLocation {
             start: *start as u64,
             sz: stop - start,
@@ -124,6 +125,23 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_malformed_synthetic() -> Result<()> {
+        // Leading garbage, an empty NAL unit, trailing zero bytes and a start
+        // code at the end.
+        let buf = &[
+            7, 7, 0, 0, 1, 9, 10, 0, 0, 0, 0, 0, 1, 0, 0, 1, 3, 0, 0, 0, 0, 1,
+        ];
+        for split in 1..buf.len() {
+            let rdr = SplitReader::new(buf, split);
+            let results = find_nals(rdr)?;
+            assert_eq!(results.len(), 2);
+            assert_eq!(results[0], AnnexBLocation { start: 5, sz: 2 });
+            assert_eq!(results[1], AnnexBLocation { start: 16, sz: 1 });
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_real_file() -> Result<()> {
         let buf = include_bytes!("test-data/test_less-avc_mono8_15x14.h264");
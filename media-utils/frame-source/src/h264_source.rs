@@ -4,10 +4,11 @@ use std::{
     path::Path,
 };
 
+use basic_frame::DynamicFrame;
 use chrono::{DateTime, FixedOffset, Utc};
 use h264_reader::{
     nal::{
-        sei::{HeaderType, SeiMessage, SeiReader},
+        sei::{HeaderType, SeiReader},
         Nal, RefNal, UnitType,
     },
     rbsp::BitReaderError,
@@ -15,19 +16,18 @@ use h264_reader::{
 };
 use serde::{Deserialize, Serialize};
 
-use ci2_remote_control::{H264Metadata, H264_METADATA_UUID, H264_METADATA_VERSION};
+use h264_sei::{H264Metadata, UserDataUnregistered, H264_METADATA_UUID, PRECISION_TIME_UUID};
 
 use crate::{
     ntp_timestamp::NtpTimestamp,
     srt_reader::{self, Stanza},
-    EncodedH264, Error, FrameData, FrameDataSource, H264EncodingVariant, ImageData, MyAsStr,
-    Result, Timestamp, TimestampSource,
+    EncodedH264, Error, ExtractedFrame, FrameData, FrameDataSource, H264EncodingVariant, ImageData,
+    MyAsStr, Result, SeekableFrameDataSource, Timestamp, TimestampSource,
 };
 
 struct SrtData {
     stanzas: Vec<Stanza>,
     frame0_time: DateTime<FixedOffset>,
-    idx: usize,
 }
 
 #[derive(serde::Deserialize)]
@@ -40,9 +40,10 @@ impl SrtData {
         let msg: SrtMsg = serde_json::from_str(&stanza.lines).unwrap();
         msg.timestamp
     }
-    fn next_pts(&mut self) -> Result<std::time::Duration> {
-        let stanza = &self.stanzas[self.idx];
-        self.idx += 1;
+    fn pts(&self, frame_idx: usize) -> Result<std::time::Duration> {
+        let stanza = self.stanzas.get(frame_idx).ok_or_else(|| {
+            Error::H264TimestampError(format!("no SRT entry for frame {frame_idx}"))
+        })?;
         let tnow = Self::parse_time(stanza);
         Ok(tnow.signed_duration_since(self.frame0_time).to_std()?)
     }
@@ -120,6 +121,215 @@ impl<H: SeekableH264Source> H264Source<H> {
     pub fn as_seekable_h264_source(&self) -> &H {
         &self.seekable_h264_source
     }
+
+    /// Index of the first NAL unit location of frame `frame_idx`.
+    fn first_nal_location_index(&self, frame_idx: usize) -> usize {
+        if frame_idx == 0 {
+            0
+        } else {
+            self.frame_time_info[frame_idx - 1].nal_location_index + 1
+        }
+    }
+
+    /// Timestamp of frame `frame_idx` relative to frame 0.
+    ///
+    /// Returns `None` if the source has no timestamps.
+    fn frame_timestamp(&self, frame_idx: usize) -> Result<Option<std::time::Duration>> {
+        let missing = || Error::H264TimestampError(format!("no timestamp for frame {frame_idx}"));
+        let nti = &self.frame_time_info[frame_idx];
+        let pts = match self.timestamp_source {
+            Some(TimestampSource::BestGuess) => unreachable!(),
+            Some(TimestampSource::MispMicrosectime) => {
+                let f0 = self.frame0_precision_time.as_ref().unwrap();
+                nti.precise_timestamp
+                    .ok_or_else(missing)?
+                    .signed_duration_since(*f0)
+                    .to_std()?
+            }
+            Some(TimestampSource::FrameInfoRecvTime) => {
+                let t0 = self.frame0_frameinfo_recv_ntp.as_ref().unwrap();
+                let t0: chrono::DateTime<chrono::Utc> = (*t0).into();
+                let this_frame: chrono::DateTime<chrono::Utc> =
+                    nti.frameinfo_recv_ntp.ok_or_else(missing)?.into();
+                this_frame.signed_duration_since(t0).to_std()?
+            }
+            Some(TimestampSource::Mp4Pts) => {
+                // one per mp4 sample
+                let mp4_pts = self.mp4_pts.as_ref().unwrap();
+                mp4_pts[self.first_nal_location_index(frame_idx)]
+            }
+            Some(TimestampSource::SrtFile) => self.srt_data.as_ref().unwrap().pts(frame_idx)?,
+            None => return Ok(None),
+        };
+        Ok(Some(pts))
+    }
+}
+
+impl<H: SeekableH264Source> SeekableFrameDataSource for H264Source<H> {
+    fn frame_timestamps(&mut self) -> Result<Vec<std::time::Duration>> {
+        (0..self.frame_time_info.len())
+            .map(|frame_idx| self.frame_timestamp(frame_idx)?.ok_or(Error::NoTimestamps))
+            .collect()
+    }
+
+    /// Read the frames with indices in `range`.
+    ///
+    /// Decoding starts at the last keyframe (IDR frame) before the first
+    /// requested frame.
+    fn read_frames(&mut self, range: std::ops::Range<usize>) -> Result<Vec<FrameData>> {
+        let indices: Vec<usize> = range.collect();
+        let mut frames = self.decode_frames(&indices)?;
+        for frame in frames.iter_mut() {
+            if let Some(pts) = self.frame_timestamp(frame.idx)? {
+                frame.timestamp = Timestamp::Duration(pts);
+            }
+        }
+        Ok(frames)
+    }
+}
+
+impl<H: SeekableH264Source> H264Source<H> {
+    /// Get the `MISPmicrosectime` timestamp of every frame.
+    ///
+    /// Unlike [SeekableFrameDataSource::frame_timestamps], these are absolute
+    /// times, so frames with timestamps before that of the first frame (e.g.
+    /// after a step of the camera clock) are not an error.
+    pub fn precision_times(&self) -> Vec<Option<DateTime<FixedOffset>>> {
+        let offset = self.precision_time_offset();
+        self.frame_time_info
+            .iter()
+            .map(|fti| fti.precise_timestamp.map(|t| t.with_timezone(&offset)))
+            .collect()
+    }
+
+    /// Decode the frame with the `MISPmicrosectime` timestamp nearest to `t`.
+    ///
+    /// See [FrameDataSource::extract_frames_at].
+    pub fn extract_frame_at<Tz: chrono::TimeZone>(
+        &mut self,
+        t: &DateTime<Tz>,
+    ) -> Result<ExtractedFrame> {
+        let mut frames = self.extract_frames_at(&[t.with_timezone(&Utc)])?;
+        Ok(frames.pop().unwrap())
+    }
+
+    /// The time zone of the H264 metadata, or UTC if there is none.
+    fn precision_time_offset(&self) -> FixedOffset {
+        self.frame0_precision_time
+            .map(|t| *t.offset())
+            .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap())
+    }
+
+    /// Create a decoder ready to decode starting at keyframe `keyframe_idx`.
+    fn decoder_at_keyframe(
+        &mut self,
+        keyframe_idx: usize,
+    ) -> Result<crate::opt_openh264_decoder::DecoderType> {
+        let mut decoder = crate::opt_openh264_decoder::DecoderType::new()?;
+
+        // Send the parameter sets (SPS and PPS) preceding the first frame.
+        let mut parameter_sets: Vec<Vec<u8>> = self
+            .seekable_h264_source
+            .first_sps()
+            .into_iter()
+            .chain(self.seekable_h264_source.first_pps())
+            .collect();
+        if keyframe_idx > 0 {
+            let frame0_nal_location_index = self.frame_time_info[0].nal_location_index;
+            parameter_sets.extend(
+                self.seekable_h264_source
+                    .read_nal_units_at_locations(&self.nal_locations[..frame0_nal_location_index])?
+                    .into_iter()
+                    .filter(|nal_unit| {
+                        matches!(
+                            nal_unit_type(nal_unit),
+                            Ok(UnitType::SeqParameterSet | UnitType::PicParameterSet)
+                        )
+                    }),
+            );
+        }
+        if !parameter_sets.is_empty() {
+            decoder.decode(&copy_nalus_to_annex_b(&parameter_sets))?;
+        }
+        Ok(decoder)
+    }
+
+    /// Decode the frames with the sorted, unique `indices`.
+    ///
+    /// The timestamp of each frame is the fraction of the source before it;
+    /// callers set the timestamp they need. (Relative timestamps of frames
+    /// before the first frame, after a step of the camera clock, would be an
+    /// error.)
+    ///
+    /// A single decoder is used while the frames can be reached by decoding
+    /// forward. Decoding restarts at the last keyframe (IDR frame) before a
+    /// requested frame when that keyframe is ahead of the decoder.
+    fn decode_frames(&mut self, indices: &[usize]) -> Result<Vec<FrameData>> {
+        let Some(&last_idx) = indices.last() else {
+            return Ok(Vec::new());
+        };
+        if last_idx >= self.frame_time_info.len() {
+            return Err(Error::FrameIndexOutOfRange(last_idx));
+        }
+        if !self.do_decode_h264 {
+            return Err(Error::SeekRequiresH264Decoding);
+        }
+
+        let mut decoder = None;
+        // The index of the next frame to send to the decoder.
+        let mut next_frame_idx = 0;
+        let mut result = Vec::with_capacity(indices.len());
+        for &target_idx in indices {
+            let keyframe_idx = (0..=target_idx)
+                .rev()
+                .find(|idx| self.frame_time_info[*idx].is_keyframe)
+                .unwrap_or(0);
+            if decoder.is_none() || keyframe_idx > next_frame_idx {
+                decoder = Some(self.decoder_at_keyframe(keyframe_idx)?);
+                next_frame_idx = keyframe_idx;
+            }
+            let decoder = decoder.as_mut().unwrap();
+
+            for frame_idx in next_frame_idx..=target_idx {
+                let start = self.first_nal_location_index(frame_idx);
+                let stop = self.frame_time_info[frame_idx].nal_location_index;
+                let nal_units = self
+                    .seekable_h264_source
+                    .read_nal_units_at_locations(&self.nal_locations[start..=stop])?;
+                let decoded = decoder.decode(&copy_nalus_to_annex_b(nal_units.as_slice()))?;
+                if frame_idx < target_idx {
+                    continue;
+                }
+                let timestamp = Timestamp::Fraction(start as f32 / self.nal_locations.len() as f32);
+                match decoded {
+                    Some(decoded_yuv) => {
+                        result.push(yuv2rgb(decoded_yuv, frame_idx, nal_units, timestamp)?);
+                    }
+                    None => return Err(crate::Error::DecoderDidNotReturnImageData),
+                }
+            }
+            next_frame_idx = target_idx + 1;
+        }
+        Ok(result)
+    }
+}
+
+/// Find the frame index of the timestamp in `sorted` nearest to `t`.
+///
+/// `sorted` must not be empty.
+fn nearest_precision_time(sorted: &[(DateTime<Utc>, usize)], t: DateTime<Utc>) -> usize {
+    let after = sorted.partition_point(|(x, _)| *x < t);
+    if after == 0 {
+        return sorted[0].1;
+    }
+    if after == sorted.len() {
+        return sorted[after - 1].1;
+    }
+    if t - sorted[after - 1].0 <= sorted[after].0 - t {
+        sorted[after - 1].1
+    } else {
+        sorted[after].1
+    }
 }
 
 /// Timing information for a frame of video.
@@ -132,6 +342,9 @@ pub struct FrameTimeInfo {
     nal_location_index: usize,
     precise_timestamp: Option<DateTime<Utc>>,
     frameinfo_recv_ntp: Option<NtpTimestamp>,
+    /// Whether the frame is an IDR frame, which can be decoded without
+    /// previous frames.
+    is_keyframe: bool,
 }
 
 impl<H: SeekableH264Source> FrameDataSource for H264Source<H> {
@@ -193,6 +406,49 @@ impl<H: SeekableH264Source> FrameDataSource for H264Source<H> {
     fn has_timestamps(&self) -> bool {
         self.has_timestamps
     }
+    fn extract_frames_at(&mut self, times: &[DateTime<Utc>]) -> Result<Vec<ExtractedFrame>> {
+        // Timestamps sorted by time, as the camera clock may step backwards.
+        let mut sorted: Vec<(DateTime<Utc>, usize)> = self
+            .frame_time_info
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, fti)| fti.precise_timestamp.map(|t| (t, idx)))
+            .collect();
+        if sorted.is_empty() {
+            return Err(Error::NoPrecisionTimestamps);
+        }
+        sorted.sort();
+
+        let requested: Vec<usize> = times
+            .iter()
+            .map(|t| nearest_precision_time(&sorted, *t))
+            .collect();
+        let mut indices = requested.clone();
+        indices.sort_unstable();
+        indices.dedup();
+
+        let offset = self.precision_time_offset();
+        let mut decoded = Vec::with_capacity(indices.len());
+        for frame in self.decode_frames(&indices)? {
+            let idx = frame.idx();
+            // Only frames with a timestamp were requested.
+            let precision_time = self.frame_time_info[idx].precise_timestamp.unwrap();
+            decoded.push(ExtractedFrame {
+                idx,
+                precision_time: precision_time.with_timezone(&offset),
+                image: frame
+                    .take_decoded()
+                    .ok_or(Error::DecoderDidNotReturnImageData)?,
+            });
+        }
+        Ok(requested
+            .iter()
+            .map(|idx| {
+                let i = indices.binary_search(idx).unwrap();
+                decoded[i].clone()
+            })
+            .collect())
+    }
 }
 
 pub(crate) struct FromMp4Track {
@@ -301,7 +557,6 @@ where
             let frame0_time = SrtData::parse_time(&stanzas[0]);
             Some(SrtData {
                 stanzas,
-                idx: 0,
                 frame0_time,
             })
         } else {
@@ -313,28 +568,28 @@ where
             tracing::debug!("Using SPS and PPS data from mp4 track.");
             {
                 // SPS
-                let sps_nal = RefNal::new(&dfc.sequence_parameter_set, &[], true);
-                if sps_nal.header().unwrap().nal_unit_type() != UnitType::SeqParameterSet {
+                if nal_unit_type(&dfc.sequence_parameter_set)? != UnitType::SeqParameterSet {
                     return Err(Error::ExpectedSpsNotFound);
                 }
+                let sps_nal = RefNal::new(&dfc.sequence_parameter_set, &[], true);
 
-                let isps =
-                    h264_reader::nal::sps::SeqParameterSet::from_bits(sps_nal.rbsp_bits()).unwrap();
+                let isps = h264_reader::nal::sps::SeqParameterSet::from_bits(sps_nal.rbsp_bits())
+                    .map_err(|e| Error::H264Sps(format!("reading SPS: {e:?}")))?;
                 parsing_ctx.put_seq_param_set(isps);
             }
 
             {
                 // PPS
-                let pps_nal = RefNal::new(&dfc.picture_parameter_set, &[], true);
-                if pps_nal.header().unwrap().nal_unit_type() != UnitType::PicParameterSet {
+                if nal_unit_type(&dfc.picture_parameter_set)? != UnitType::PicParameterSet {
                     return Err(Error::ExpectedPpsNotFound);
                 }
+                let pps_nal = RefNal::new(&dfc.picture_parameter_set, &[], true);
 
                 let ipps = h264_reader::nal::pps::PicParameterSet::from_bits(
                     &parsing_ctx,
                     pps_nal.rbsp_bits(),
                 )
-                .unwrap();
+                .map_err(|e| Error::H264Pps(format!("reading PPS: {e:?}")))?;
                 parsing_ctx.put_pic_param_set(ipps);
             }
         }
@@ -483,8 +738,8 @@ where
             // Note, there are multiple NAL units per `nal_location_index`
             // in MP4 files because in that case, `nal_location_index`
             // refers to the MP4 sample which has multiple NAL units.
+            let nal_unit_type = nal_unit_type(nal_unit)?;
             let nal = RefNal::new(nal_unit.as_slice(), &[], true);
-            let nal_unit_type = nal.header().unwrap().nal_unit_type();
             tracing::trace!("NAL unit location index {nal_location_index}, {nal_unit_type:?}");
             match nal_unit_type {
                 UnitType::SEI => {
@@ -495,16 +750,10 @@ where
                                 tracing::trace!("SEI payload type: {:?}", sei_message.payload_type);
                                 match &sei_message.payload_type {
                                     HeaderType::UserDataUnregistered => {
-                                        let udu = UserDataUnregistered::read(&sei_message)?;
+                                        let udu = UserDataUnregistered::parse(sei_message.payload)?;
                                         match udu.uuid {
                                             &H264_METADATA_UUID => {
-                                                let md: H264Metadata =
-                                                    serde_json::from_slice(udu.payload)?;
-                                                if md.version != H264_METADATA_VERSION {
-                                                    return Err(Error::H264Error(
-                                                        "unexpected version in h264 metadata",
-                                                    ));
-                                                }
+                                                let md = h264_sei::decode_metadata(udu.payload)?;
                                                 if h264_metadata.is_some() {
                                                     return Err(Error::H264Error(
                                                         "multiple SEI messages, but expected exactly one"
@@ -527,9 +776,9 @@ where
                                                 "Ignoring SEI UserDataUnregistered from videotoolbox."
                                             );
                                             }
-                                            b"MISPmicrosectime" => {
+                                            PRECISION_TIME_UUID => {
                                                 let precision_time =
-                                                    parse_precision_time(udu.payload)?;
+                                                    h264_sei::decode_precision_time(udu.payload)?;
                                                 precise_timestamp = Some(precision_time);
                                                 if next_frame_num == 0 {
                                                     frame0_precision_time = Some(precision_time);
@@ -579,8 +828,8 @@ where
                     }
                 }
                 UnitType::SeqParameterSet => {
-                    let isps =
-                        h264_reader::nal::sps::SeqParameterSet::from_bits(nal.rbsp_bits()).unwrap();
+                    let isps = h264_reader::nal::sps::SeqParameterSet::from_bits(nal.rbsp_bits())
+                        .map_err(|e| Error::H264Sps(format!("reading SPS: {e:?}")))?;
                     parsing_ctx.put_seq_param_set(isps);
                 }
                 UnitType::PicParameterSet => {
@@ -610,6 +859,7 @@ where
                         nal_location_index,
                         precise_timestamp,
                         frameinfo_recv_ntp,
+                        is_keyframe: nal_unit_type == UnitType::SliceLayerWithoutPartitioningIdr,
                     });
                     // Reset temporary values.
                     precise_timestamp = None;
@@ -643,43 +893,22 @@ impl<H: SeekableH264Source> Iterator for RawH264Iter<'_, H> {
     type Item = Result<FrameData>;
     fn next(&mut self) -> Option<Self::Item> {
         let frame_number = self.frame_idx;
-        let res = self.parent.frame_time_info.get(self.frame_idx);
+        let res = self
+            .parent
+            .frame_time_info
+            .get(self.frame_idx)
+            .map(|nti| nti.nal_location_index);
         self.frame_idx += 1;
 
-        res.map(|nti| {
+        res.map(|nal_location_index| {
             // create slice of all NAL units up and including NALU for the frame
-            let nal_locations =
-                &self.parent.nal_locations[self.next_nal_idx..=(nti.nal_location_index)];
-            let mp4_pts = self.parent.mp4_pts.as_ref().map(|x| x[self.next_nal_idx]); // one per mp4 sample
+            let nal_locations = &self.parent.nal_locations[self.next_nal_idx..=nal_location_index];
             let fraction_done = self.next_nal_idx as f32 / self.parent.nal_locations.len() as f32;
 
-            self.next_nal_idx = nti.nal_location_index + 1;
-
-            let frame_timestamp = match self.parent.timestamp_source {
-                Some(TimestampSource::BestGuess) => unreachable!(),
-                Some(TimestampSource::MispMicrosectime) => {
-                    let f0 = self.parent.frame0_precision_time.as_ref().unwrap();
-                    Timestamp::Duration(
-                        nti.precise_timestamp
-                            .unwrap()
-                            .signed_duration_since(*f0)
-                            .to_std()
-                            .unwrap(),
-                    )
-                }
-                Some(TimestampSource::FrameInfoRecvTime) => {
-                    let t0 = self.parent.frame0_frameinfo_recv_ntp.as_ref().unwrap();
-                    let t0: chrono::DateTime<chrono::Utc> = (*t0).into();
-                    let this_frame: chrono::DateTime<chrono::Utc> =
-                        nti.frameinfo_recv_ntp.unwrap().into();
-                    Timestamp::Duration(this_frame.signed_duration_since(t0).to_std().unwrap())
-                }
-                Some(TimestampSource::Mp4Pts) => Timestamp::Duration(mp4_pts.unwrap()),
-                Some(TimestampSource::SrtFile) => {
-                    let srt_data = self.parent.srt_data.as_mut().unwrap();
-                    let pts = srt_data.next_pts().unwrap();
-                    Timestamp::Duration(pts)
-                }
+            self.next_nal_idx = nal_location_index + 1;
+
+            let frame_timestamp = match self.parent.frame_timestamp(frame_number)? {
+                Some(pts) => Timestamp::Duration(pts),
                 None => Timestamp::Fraction(fraction_done),
             };
 
@@ -801,69 +1030,10 @@ fn from_annexb_reader_with_timestamp_source(
     )
 }
 
-pub(crate) struct UserDataUnregistered<'a> {
-    pub uuid: &'a [u8; 16],
-    pub payload: &'a [u8],
-}
-
-impl<'a> UserDataUnregistered<'a> {
-    pub fn read(msg: &SeiMessage<'a>) -> Result<UserDataUnregistered<'a>> {
-        if msg.payload_type != HeaderType::UserDataUnregistered {
-            return Err(Error::UduError(format!(
-                "expected UserDataUnregistered message, found {:?}",
-                msg.payload_type
-            )));
-        }
-        if msg.payload.len() < 16 {
-            return Err(Error::UduError(
-                "SEI payload too short to contain UserDataUnregistered message".to_string(),
-            ));
-        }
-        let uuid = (&msg.payload[0..16]).try_into().unwrap();
-
-        let payload = &msg.payload[16..];
-        Ok(UserDataUnregistered { uuid, payload })
-    }
-}
-
-pub(crate) fn parse_precision_time(payload: &[u8]) -> Result<chrono::DateTime<chrono::Utc>> {
-    if payload.len() != 12 {
-        return Err(Error::UnexpectedPayloadLength);
-    }
-
-    // // Time Stamp Status byte from MISB Standard 0603.
-    // // Could parse Locked/Unlocked (bit 7), Normal/Discontinuity (bit 6),
-    // // Forward/Reverse (bit 5).
-
-    // let time_stamp_status = payload[0];
-    // if time_stamp_status & 0x1F != 0x1F {
-    //     anyhow::bail!(
-    //         "unexpected time stamp status byte. Full payload: {{{}}}",
-    //         pretty_hex::simple_hex(&payload),
-    //     );
-    // }
-
-    let mut precision_time_stamp_bytes = [0u8; 8];
-    for i in &[3, 6, 9] {
-        if payload[*i] != 0xFF {
-            return Err(Error::UnexpectedStartCodeByte);
-        }
-    }
-    precision_time_stamp_bytes[0..2].copy_from_slice(&payload[1..3]);
-    precision_time_stamp_bytes[2..4].copy_from_slice(&payload[4..6]);
-    precision_time_stamp_bytes[4..6].copy_from_slice(&payload[7..9]);
-    precision_time_stamp_bytes[6..8].copy_from_slice(&payload[10..12]);
-    let precision_time_stamp: i64 = i64::from_be_bytes(precision_time_stamp_bytes);
-    let dur = chrono::Duration::microseconds(precision_time_stamp);
-
-    let epoch_start = chrono::NaiveDate::from_ymd_opt(1970, 1, 1)
-        .unwrap()
-        .and_hms_micro_opt(0, 0, 0, 0)
-        .unwrap()
-        .and_local_timezone(chrono::Utc)
-        .unwrap();
-
-    Ok(epoch_start + dur)
+/// The type of a NAL unit, or an error if its header is invalid.
+fn nal_unit_type(nal_ebsp_bytes: &[u8]) -> Result<UnitType> {
+    let header = h264_nal_parse::NalHeader::parse(nal_ebsp_bytes)?;
+    UnitType::for_id(header.nal_unit_type).map_err(|_| Error::H264Error("invalid NAL unit type"))
 }
 
 /// Copy raw headerless EBSP NAL units to Annex B
@@ -9,9 +9,12 @@ pub mod fmf_source;
 mod h264_annexb_splitter;
 pub mod h264_source;
 pub mod mp4_source;
+pub mod network_source;
 mod opt_openh264_decoder;
+mod reopen;
 mod srt_reader;
 pub mod strand_cam_mkv_source;
+pub mod ufmf_source;
 
 mod ntp_timestamp;
 #[cfg(test)]
@@ -31,6 +34,8 @@ pub enum Error {
     ExpectedPpsNotFound,
     #[error("fmf file with not enough data")]
     FmfWithNotEnoughData,
+    #[error("ufmf file with no frames")]
+    UfmfWithNotEnoughData,
     #[error("JSON parse error")]
     JsonParseError,
     #[error("expected tiff image")]
@@ -49,6 +54,14 @@ pub enum Error {
     SkippingFramesNotSupported,
     #[error("Not implemented: {0}")]
     NotImplemented(&'static str),
+    #[error("Frame index {0} out of range.")]
+    FrameIndexOutOfRange(usize),
+    #[error("Random access in H264 source requires decoding.")]
+    SeekRequiresH264Decoding,
+    #[error("Source has no timestamps.")]
+    NoTimestamps,
+    #[error("Source has no MISPmicrosectime precision timestamps.")]
+    NoPrecisionTimestamps,
     #[error("Requested SRT file as timestamp source, but no .srt file path given.")]
     NoSrtPathGiven,
     #[error("H264Error: {0}")]
@@ -60,16 +73,18 @@ pub enum Error {
     },
     #[error("PPS error {0}")]
     H264Pps(String),
+    #[error("SPS error {0}")]
+    H264Sps(String),
+    #[error("H264 parse error: {0}")]
+    H264NalParse(#[from] h264_nal_parse::Error),
     #[error("H264 timestamp error {0}")]
     H264TimestampError(String),
-    #[error("H264 UDU error {0}")]
-    UduError(String),
-    #[error("unexpected payload length")]
-    UnexpectedPayloadLength,
-    #[error("unexpected start code emulation prevention byte")]
-    UnexpectedStartCodeByte,
+    #[error("H264 SEI error: {0}")]
+    H264Sei(#[from] h264_sei::Error),
     #[error("MP4 source error: {0}")]
     Mp4SourceError(#[from] mp4_source::Mp4SourceError),
+    #[error("network source error: {0}")]
+    NetworkSourceError(#[from] network_source::NetworkSourceError),
     #[error("strand camera MKV source error: {0}")]
     StrandMkvSourceError(#[from] strand_cam_mkv_source::StrandMkvSourceError),
     #[error("srt file given, but not supported for this file type")]
@@ -81,6 +96,8 @@ pub enum Error {
     #[error("{0}")]
     FmfError(#[from] fmf::FMFError),
     #[error("{0}")]
+    UfmfError(#[from] ufmf::UFMFError),
+    #[error("{0}")]
     PatternError(#[from] glob::PatternError),
     #[error("{0}")]
     GlobError(#[from] glob::GlobError),
@@ -157,6 +174,73 @@ pub trait FrameDataSource {
     fn timestamp_source(&self) -> &str;
     /// Get an iterator over all frames.
     fn iter<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<FrameData>> + 'a>;
+    /// Decode, for each time in `times`, the frame with the `MISPmicrosectime`
+    /// timestamp nearest to it.
+    ///
+    /// The result has one frame per requested time, in the same order. Each
+    /// frame is decoded once, even if requested several times. Frames are
+    /// decoded in file order, restarting at the keyframe before a requested
+    /// frame only when this skips frames. Frames without timestamp are never
+    /// returned. If two frames are equally near, the earlier one is returned.
+    /// The caller can check [ExtractedFrame::precision_time] to reject frames
+    /// too far from the requested time.
+    ///
+    /// Only MP4 and H264 sources support this, if opened with H264 decoding.
+    /// Other sources return [Error::NoPrecisionTimestamps].
+    fn extract_frames_at(
+        &mut self,
+        _times: &[chrono::DateTime<chrono::Utc>],
+    ) -> Result<Vec<ExtractedFrame>> {
+        Err(Error::NoPrecisionTimestamps)
+    }
+}
+
+/// A [FrameDataSource] which supports random access to frames.
+///
+/// Frames are addressed by index, starting with 0 (after any frames skipped
+/// with [FrameDataSource::skip_n_frames]), or by timestamp relative to the
+/// first frame.
+pub trait SeekableFrameDataSource: FrameDataSource {
+    /// Get the timestamps of all frames, relative to the first frame.
+    fn frame_timestamps(&mut self) -> Result<Vec<std::time::Duration>>;
+    /// Read the frames with indices in `range`.
+    fn read_frames(&mut self, range: std::ops::Range<usize>) -> Result<Vec<FrameData>>;
+    /// Read the frame with timestamp nearest to `t`.
+    ///
+    /// Returns `Ok(None)` if the source has no frames.
+    fn frame_nearest(&mut self, t: std::time::Duration) -> Result<Option<FrameData>> {
+        let timestamps = self.frame_timestamps()?;
+        match nearest_index(&timestamps, t) {
+            Some(idx) => Ok(self.read_frames(idx..idx + 1)?.pop()),
+            None => Ok(None),
+        }
+    }
+    /// Read all frames with timestamps within `range`.
+    fn frames_in_time_range(
+        &mut self,
+        range: std::ops::Range<std::time::Duration>,
+    ) -> Result<Vec<FrameData>> {
+        let timestamps = self.frame_timestamps()?;
+        let start = timestamps.partition_point(|t| *t < range.start);
+        let end = timestamps.partition_point(|t| *t < range.end);
+        self.read_frames(start..end.max(start))
+    }
+}
+
+/// Find the index of the value in sorted `timestamps` nearest to `t`.
+fn nearest_index(timestamps: &[std::time::Duration], t: std::time::Duration) -> Option<usize> {
+    let after = timestamps.partition_point(|x| *x < t);
+    if after == 0 {
+        return if timestamps.is_empty() { None } else { Some(0) };
+    }
+    if after == timestamps.len() {
+        return Some(after - 1);
+    }
+    if t - timestamps[after - 1] <= timestamps[after] - t {
+        Some(after - 1)
+    } else {
+        Some(after)
+    }
 }
 
 /// A single frame of data, including `image` and `timestamp` fields.
@@ -234,6 +318,21 @@ impl FrameData {
     }
 }
 
+/// A decoded frame together with its precision timestamp.
+///
+/// Returned by [FrameDataSource::extract_frames_at].
+#[derive(Clone)]
+pub struct ExtractedFrame {
+    /// The index of the frame in the source, starting with 0.
+    pub idx: usize,
+    /// The `MISPmicrosectime` timestamp of the frame.
+    ///
+    /// This is in the time zone of the H264 metadata, if present, else UTC.
+    pub precision_time: chrono::DateTime<chrono::FixedOffset>,
+    /// The decoded image.
+    pub image: DynamicFrame,
+}
+
 /// The image data
 #[derive(Clone, PartialEq)]
 pub enum ImageData {
@@ -315,6 +414,9 @@ impl MyAsStr for Option<TimestampSource> {
 
 /// Create a [FrameDataSource] from a path.
 ///
+/// Network URLs such as `rtsp://camera/stream` are opened with
+/// [network_source::from_url].
+///
 /// The `do_decode_h264` argument specifies that an H264 source will be decoded
 /// (e.g. to extract individual images).
 pub fn from_path<P: AsRef<std::path::Path>>(
@@ -324,6 +426,31 @@ pub fn from_path<P: AsRef<std::path::Path>>(
     from_path_with_srt_timestamp_source(input, do_decode_h264, TimestampSource::BestGuess, None)
 }
 
+/// Create a [SeekableFrameDataSource] from a path.
+///
+/// Random access is supported for MP4 files (which are decoded) and
+/// uncompressed FMF files.
+pub fn seekable_from_path<P: AsRef<std::path::Path>>(
+    input: P,
+    timestamp_source: TimestampSource,
+) -> Result<Box<dyn SeekableFrameDataSource>> {
+    let input_path = PathBuf::from(input.as_ref());
+    let fname_lower = input_path.to_string_lossy().to_lowercase();
+    if fname_lower.ends_with(".mp4") {
+        let mp4_video =
+            mp4_source::from_path_with_timestamp_source(&input, true, timestamp_source, None)?;
+        return Ok(Box::new(mp4_video));
+    }
+    if fname_lower.ends_with(".fmf") {
+        let fmf_video = fmf_source::from_path(&input)?;
+        return Ok(Box::new(fmf_video));
+    }
+    if fname_lower.ends_with(".fmf.gz") {
+        return Err(Error::NotImplemented("random access in .fmf.gz files"));
+    }
+    Err(Error::UnknownExtensionForFile(input_path))
+}
+
 /// Create a [FrameDataSource] from a path with defined timestamp source
 ///
 /// The `do_decode_h264` argument specifies that an H264 source will be decoded
@@ -346,6 +473,14 @@ pub fn from_path_with_srt_timestamp_source<P: AsRef<std::path::Path>>(
     timestamp_source: TimestampSource,
     srt_file_path: Option<PathBuf>,
 ) -> Result<Box<dyn FrameDataSource>> {
+    if let Some(url) = input.as_ref().to_str() {
+        if network_source::is_network_url(url) {
+            if srt_file_path.is_some() {
+                return Err(Error::NoSrtSupportForFileType);
+            }
+            return Ok(Box::new(network_source::from_url(url)?));
+        }
+    }
     let input_path = PathBuf::from(input.as_ref());
     let is_file = std::fs::metadata(input.as_ref())?.is_file();
     if is_file {
@@ -383,6 +518,13 @@ pub fn from_path_with_srt_timestamp_source<P: AsRef<std::path::Path>>(
                     )?;
                     return Ok(Box::new(h264_video));
                 }
+                Some("ufmf") => {
+                    if srt_file_path.is_some() {
+                        return Err(Error::NoSrtSupportForFileType);
+                    }
+                    let ufmf_video = ufmf_source::from_path(&input)?;
+                    return Ok(Box::new(ufmf_video));
+                }
                 _ => {}
             }
         }
@@ -408,3 +550,16 @@ pub fn from_path_with_srt_timestamp_source<P: AsRef<std::path::Path>>(
         Ok(Box::new(stack))
     }
 }
+
+#[test]
+fn test_nearest_index() {
+    use std::time::Duration;
+    let ms = Duration::from_millis;
+    let timestamps = [ms(0), ms(10), ms(20)];
+    assert_eq!(nearest_index(&[], ms(5)), None);
+    assert_eq!(nearest_index(&timestamps, ms(0)), Some(0));
+    assert_eq!(nearest_index(&timestamps, ms(4)), Some(0));
+    assert_eq!(nearest_index(&timestamps, ms(6)), Some(1));
+    assert_eq!(nearest_index(&timestamps, ms(20)), Some(2));
+    assert_eq!(nearest_index(&timestamps, ms(100)), Some(2));
+}
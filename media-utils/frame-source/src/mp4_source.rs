@@ -24,10 +24,6 @@ pub enum Mp4SourceError {
     SingleH264TrackOnly,
     #[error("No H264 video track found in MP4 file.")]
     NoH264Track,
-    #[error("sample buffer is too short for NAL unit header")]
-    SampleBufferTooShort,
-    #[error("AVCC buffer length: {sz}+4 but buffer {cur_len}")]
-    LengthMismatch { sz: usize, cur_len: usize },
 }
 
 pub struct Mp4Source {
@@ -161,35 +157,7 @@ pub fn from_path_with_timestamp_source<P: AsRef<Path>>(
 /// This function is not capable of parsing on non-NALU boundaries and must
 /// contain complete NALUs. For well-formed MP4 files, this should be the case.
 fn avcc_to_nalu_ebsp(mp4_sample_buffer: &[u8]) -> Result<Vec<&[u8]>> {
-    let mut result = vec![];
-    let mut cur_buf = mp4_sample_buffer;
-    let mut total_nal_sizes = 0;
-    while !cur_buf.is_empty() {
-        if cur_buf.len() < 4 {
-            return Err(Mp4SourceError::SampleBufferTooShort.into());
-        }
-        let header = [cur_buf[0], cur_buf[1], cur_buf[2], cur_buf[3]];
-        let sz: usize = u32::from_be_bytes(header).try_into().unwrap();
-        let used = sz + 4;
-        if cur_buf.len() < used {
-            return Err(Mp4SourceError::LengthMismatch {
-                sz,
-                cur_len: cur_buf.len(),
-            }
-            .into());
-        }
-        total_nal_sizes += used;
-        result.push(&cur_buf[4..used]);
-        cur_buf = &cur_buf[used..];
-    }
-    if total_nal_sizes != mp4_sample_buffer.len() {
-        tracing::warn!(
-            "MP4 sample was {} bytes, but H264 NAL units totaled {} bytes.",
-            mp4_sample_buffer.len(),
-            total_nal_sizes
-        );
-    }
-    Ok(result)
+    Ok(h264_nal_parse::iter_avcc(mp4_sample_buffer, 4).collect::<std::result::Result<_, _>>()?)
 }
 
 fn raw2dur(raw: u64, timescale: u32) -> std::time::Duration {
@@ -0,0 +1,408 @@
+// Copyright 2022-2024 Andrew D. Straw.
+
+//! Read frames from a network stream such as an RTSP URL.
+//!
+//! The stream is received and decoded by an `ffmpeg` subprocess which writes
+//! raw frames to its stdout. The presentation timestamp of each frame is taken
+//! from the output of ffmpeg's `showinfo` filter. If the connection is lost,
+//! ffmpeg is restarted up to [NetworkSourceOptions::max_reconnects] times.
+
+use std::{
+    io::{BufRead, BufReader, Read},
+    process::{Child, ChildStdout, Command, Stdio},
+    sync::mpsc::{channel, Receiver},
+    time::Duration,
+};
+
+use basic_frame::DynamicFrame;
+use chrono::{DateTime, FixedOffset, Local};
+use machine_vision_formats::pixel_format::PixFmt;
+
+use crate::{FrameData, FrameDataSource, ImageData, Result, Timestamp};
+
+const FFMPEG: &str = "ffmpeg";
+const FFPROBE: &str = "ffprobe";
+
+/// URL schemes which are opened with [from_url].
+const NETWORK_SCHEMES: &[&str] = &["rtsp://", "rtsps://", "rtp://", "udp://", "srt://"];
+
+#[derive(thiserror::Error, Debug)]
+pub enum NetworkSourceError {
+    #[error("ffprobe failed for {url}: {stderr}")]
+    FfprobeFailed { url: String, stderr: String },
+    #[error("could not parse image size from ffprobe output \"{0}\"")]
+    UnexpectedFfprobeOutput(String),
+    #[error("pixel format {0} not supported, use Mono8 or RGB8")]
+    UnsupportedPixelFormat(PixFmt),
+    #[error("image size changed from {expected:?} to {actual:?} after reconnecting")]
+    SizeChanged {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    #[error("stream ended before the first frame")]
+    NoFrames,
+}
+
+/// Return whether `input` is a URL which should be opened with [from_url].
+pub fn is_network_url(input: &str) -> bool {
+    let lower = input.to_lowercase();
+    NETWORK_SCHEMES
+        .iter()
+        .any(|scheme| lower.starts_with(scheme))
+}
+
+/// Options for opening a network stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkSourceOptions {
+    /// Pixel format of the decoded frames. Either `Mono8` or `RGB8`.
+    pub pixel_format: PixFmt,
+    /// Use TCP (rather than UDP) for the RTP data of RTSP streams.
+    pub rtsp_over_tcp: bool,
+    /// How often to reconnect after the stream was lost.
+    pub max_reconnects: usize,
+    /// Delay before reconnecting.
+    pub reconnect_delay: Duration,
+}
+
+impl Default for NetworkSourceOptions {
+    fn default() -> Self {
+        Self {
+            pixel_format: PixFmt::RGB8,
+            rtsp_over_tcp: true,
+            max_reconnects: 10,
+            reconnect_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl NetworkSourceOptions {
+    fn ffmpeg_pix_fmt(&self) -> Result<&'static str> {
+        match self.pixel_format {
+            PixFmt::Mono8 => Ok("gray"),
+            PixFmt::RGB8 => Ok("rgb24"),
+            f => Err(NetworkSourceError::UnsupportedPixelFormat(f).into()),
+        }
+    }
+
+    fn input_args(&self, url: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.rtsp_over_tcp && url.to_lowercase().starts_with("rtsp") {
+            args.extend(["-rtsp_transport".to_string(), "tcp".to_string()]);
+        }
+        args.extend(["-i".to_string(), url.to_string()]);
+        args
+    }
+}
+
+/// Parse the `pts_time` field of a line printed by ffmpeg's `showinfo` filter.
+fn parse_showinfo_pts_time(line: &str) -> Option<f64> {
+    if !line.contains("Parsed_showinfo") {
+        return None;
+    }
+    let rest = &line[line.find("pts_time:")? + "pts_time:".len()..];
+    rest.split_ascii_whitespace().next()?.parse().ok()
+}
+
+fn probe_size(url: &str, opts: &NetworkSourceOptions) -> Result<(u32, u32)> {
+    let mut args: Vec<String> = ["-v", "error", "-select_streams", "v:0"]
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    args.extend([
+        "-show_entries".to_string(),
+        "stream=width,height".to_string(),
+        "-of".to_string(),
+        "csv=p=0".to_string(),
+    ]);
+    if opts.rtsp_over_tcp && url.to_lowercase().starts_with("rtsp") {
+        args.extend(["-rtsp_transport".to_string(), "tcp".to_string()]);
+    }
+    args.push(url.to_string());
+    let output = Command::new(FFPROBE).args(args).output()?;
+    if !output.status.success() {
+        return Err(NetworkSourceError::FfprobeFailed {
+            url: url.to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+    let stdout = String::from_utf8(output.stdout)?;
+    let line = stdout.lines().next().unwrap_or_default().trim();
+    let mut parts = line.split(',');
+    match (
+        parts.next().and_then(|w| w.parse().ok()),
+        parts.next().and_then(|h| h.parse().ok()),
+    ) {
+        (Some(w), Some(h)) => Ok((w, h)),
+        _ => Err(NetworkSourceError::UnexpectedFfprobeOutput(line.to_string()).into()),
+    }
+}
+
+/// A running ffmpeg process receiving the stream.
+struct Connection {
+    child: Child,
+    stdout: ChildStdout,
+    pts_rx: Receiver<f64>,
+    /// Time since frame 0 of the source when this connection started.
+    offset: Duration,
+    /// PTS of the first frame of this connection.
+    pts0: Option<f64>,
+}
+
+impl Connection {
+    fn open(url: &str, opts: &NetworkSourceOptions, offset: Duration) -> Result<Self> {
+        let mut args: Vec<String> = ["-hide_banner", "-nostdin", "-nostats", "-loglevel", "info"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        args.extend(opts.input_args(url));
+        args.extend(
+            ["-an", "-vf", "showinfo", "-f", "rawvideo", "-pix_fmt"]
+                .iter()
+                .map(|x| x.to_string()),
+        );
+        args.push(opts.ffmpeg_pix_fmt()?.to_string());
+        args.push("-".to_string());
+
+        tracing::debug!("ffmpeg {}", args.join(" "));
+        let mut child = Command::new(FFMPEG)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().expect("failed to get stdout");
+        let stderr = child.stderr.take().expect("failed to get stderr");
+
+        let (pts_tx, pts_rx) = channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stderr).lines() {
+                let Ok(line) = line else { break };
+                match parse_showinfo_pts_time(&line) {
+                    Some(pts_time) => {
+                        if pts_tx.send(pts_time).is_err() {
+                            break;
+                        }
+                    }
+                    None => tracing::trace!("ffmpeg: {line}"),
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdout,
+            pts_rx,
+            offset,
+            pts0: None,
+        })
+    }
+
+    /// Read the next frame and its timestamp relative to frame 0 of the source.
+    ///
+    /// Returns `None` when the stream ended.
+    fn read_frame(&mut self, frame_size: usize) -> Option<(Vec<u8>, Duration)> {
+        let mut buf = vec![0u8; frame_size];
+        self.stdout.read_exact(&mut buf).ok()?;
+        let pts_time = self.pts_rx.recv().ok()?;
+        let pts0 = *self.pts0.get_or_insert(pts_time);
+        let since_connect = Duration::from_secs_f64((pts_time - pts0).max(0.0));
+        Some((buf, self.offset + since_connect))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A live network stream, decoded by ffmpeg.
+///
+/// Unlike file based sources, the stream can only be iterated once.
+pub struct NetworkSource {
+    url: String,
+    opts: NetworkSourceOptions,
+    width: u32,
+    height: u32,
+    frame0_time: DateTime<FixedOffset>,
+    /// Host time at frame 0, used to place frames after a reconnect.
+    frame0_instant: std::time::Instant,
+    conn: Option<Connection>,
+    /// A frame which was already read but not yet returned.
+    pending: Option<(Vec<u8>, Duration)>,
+    /// Duration of the frames dropped with `skip_n_frames`.
+    skipped: Duration,
+    n_reconnects: usize,
+    idx: usize,
+}
+
+impl NetworkSource {
+    fn new(url: &str, opts: NetworkSourceOptions) -> Result<Self> {
+        opts.ffmpeg_pix_fmt()?;
+        let (width, height) = probe_size(url, &opts)?;
+        let mut conn = Connection::open(url, &opts, Duration::ZERO)?;
+        let frame_size = frame_size(&opts, width, height);
+        let first = conn
+            .read_frame(frame_size)
+            .ok_or(NetworkSourceError::NoFrames)?;
+        let frame0_time = Local::now().fixed_offset();
+        Ok(Self {
+            url: url.to_string(),
+            opts,
+            width,
+            height,
+            frame0_time,
+            frame0_instant: std::time::Instant::now(),
+            conn: Some(conn),
+            pending: Some(first),
+            skipped: Duration::ZERO,
+            n_reconnects: 0,
+            idx: 0,
+        })
+    }
+
+    fn reconnect(&mut self) -> Result<bool> {
+        self.conn = None;
+        while self.n_reconnects < self.opts.max_reconnects {
+            self.n_reconnects += 1;
+            tracing::warn!(
+                "Stream {} lost. Reconnecting ({}/{}).",
+                self.url,
+                self.n_reconnects,
+                self.opts.max_reconnects
+            );
+            std::thread::sleep(self.opts.reconnect_delay);
+            let size = match probe_size(&self.url, &self.opts) {
+                Ok(size) => size,
+                Err(e) => {
+                    tracing::warn!("Reconnecting failed: {e}");
+                    continue;
+                }
+            };
+            if size != (self.width, self.height) {
+                return Err(NetworkSourceError::SizeChanged {
+                    expected: (self.width, self.height),
+                    actual: size,
+                }
+                .into());
+            }
+            let offset = self.frame0_instant.elapsed();
+            self.conn = Some(Connection::open(&self.url, &self.opts, offset)?);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Read the next frame and its timestamp since the start of the stream.
+    fn next_raw_frame(&mut self) -> Option<Result<(Vec<u8>, Duration)>> {
+        if let Some(pending) = self.pending.take() {
+            return Some(Ok(pending));
+        }
+        let frame_size = frame_size(&self.opts, self.width, self.height);
+        loop {
+            if let Some(frame) = self.conn.as_mut()?.read_frame(frame_size) {
+                return Some(Ok(frame));
+            }
+            match self.reconnect() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    fn next_frame(&mut self) -> Option<Result<FrameData>> {
+        let (buf, timestamp) = match self.next_raw_frame()? {
+            Ok(frame) => frame,
+            Err(e) => return Some(Err(e)),
+        };
+        let timestamp = timestamp.saturating_sub(self.skipped);
+        let frame_size = buf.len();
+        let stride = frame_size as u32 / self.height;
+        let image = DynamicFrame::new(self.width, self.height, stride, buf, self.opts.pixel_format);
+        let idx = self.idx;
+        self.idx += 1;
+        Some(Ok(FrameData {
+            timestamp: Timestamp::Duration(timestamp),
+            image: ImageData::Decoded(image),
+            buf_len: frame_size,
+            idx,
+        }))
+    }
+}
+
+fn frame_size(opts: &NetworkSourceOptions, width: u32, height: u32) -> usize {
+    let bytes_per_pixel = opts.pixel_format.bits_per_pixel() as usize / 8;
+    width as usize * height as usize * bytes_per_pixel
+}
+
+impl FrameDataSource for NetworkSource {
+    fn width(&self) -> u32 {
+        self.width
+    }
+    fn height(&self) -> u32 {
+        self.height
+    }
+    fn frame0_time(&self) -> Option<DateTime<FixedOffset>> {
+        Some(self.frame0_time)
+    }
+    fn skip_n_frames(&mut self, n_frames: usize) -> Result<()> {
+        if n_frames == 0 {
+            return Ok(());
+        }
+        for _ in 0..n_frames {
+            self.next_raw_frame()
+                .ok_or(NetworkSourceError::NoFrames)??;
+        }
+        // The next frame becomes frame 0.
+        let (buf, timestamp) = self
+            .next_raw_frame()
+            .ok_or(NetworkSourceError::NoFrames)??;
+        // Like in `next_frame`, a timestamp going backward counts as no time.
+        let elapsed = timestamp.saturating_sub(self.skipped);
+        self.frame0_time += chrono::TimeDelta::from_std(elapsed)?;
+        self.skipped += elapsed;
+        self.pending = Some((buf, timestamp));
+        self.idx = 0;
+        Ok(())
+    }
+    fn estimate_luminance_range(&mut self) -> Result<(u16, u16)> {
+        Err(crate::Error::UnsupportedForEsimatingLuminangeRange)
+    }
+    fn has_timestamps(&self) -> bool {
+        true
+    }
+    fn timestamp_source(&self) -> &str {
+        "ffmpeg PTS"
+    }
+    fn iter<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<FrameData>> + 'a> {
+        Box::new(std::iter::from_fn(move || self.next_frame()))
+    }
+}
+
+/// Open the network stream at `url` with default options.
+pub fn from_url(url: &str) -> Result<NetworkSource> {
+    from_url_with_options(url, NetworkSourceOptions::default())
+}
+
+/// Open the network stream at `url`.
+pub fn from_url_with_options(url: &str, opts: NetworkSourceOptions) -> Result<NetworkSource> {
+    NetworkSource::new(url, opts)
+}
+
+#[test]
+fn test_parse_showinfo() {
+    let line = "[Parsed_showinfo_0 @ 0x600001d00000] n:  12 pts: 108000 pts_time:1.2     \
+        duration:   3000 duration_time:0.0333333 fmt:yuv420p sar:1/1 s:640x480 i:P iskey:0";
+    assert_eq!(parse_showinfo_pts_time(line), Some(1.2));
+    assert_eq!(
+        parse_showinfo_pts_time("Input #0, rtsp, from 'rtsp://camera/stream':"),
+        None
+    );
+
+    assert!(is_network_url("rtsp://192.168.1.10:554/stream1"));
+    assert!(is_network_url("RTSP://camera/stream"));
+    assert!(!is_network_url("movie.mp4"));
+}
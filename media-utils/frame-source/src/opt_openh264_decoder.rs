@@ -13,7 +13,7 @@ impl NoH264Decoder {
     pub(crate) fn new() -> Result<Self> {
         Err(Error::H264Error("No H264 decoder support at compile time"))
     }
-    pub(crate) fn decode(&self, _data: &[u8]) -> Result<Option<()>> {
+    pub(crate) fn decode(&mut self, _data: &[u8]) -> Result<Option<()>> {
         Err(Error::H264Error("No H264 decoder support at compile time"))
     }
 }
@@ -0,0 +1,139 @@
+//! Iteration over the frames of a file which is reopened each time.
+//!
+//! The readers of FMF and UFMF files cannot seek, so these sources store the
+//! filename and reopen the file for each iteration, reading and discarding the
+//! frames skipped with [crate::FrameDataSource::skip_n_frames].
+
+use basic_frame::DynamicFrame;
+use chrono::{DateTime, FixedOffset, Utc};
+
+use crate::{Error, FrameData, ImageData, Result, Timestamp};
+
+/// The frames skipped in a reopened file and the time of the first frame
+/// after them.
+pub(crate) struct ReopenState {
+    pub(crate) frame0_time_utc: DateTime<Utc>,
+    pub(crate) frame0_time: DateTime<FixedOffset>,
+    pub(crate) skip_frames: usize,
+}
+
+impl ReopenState {
+    pub(crate) fn new(frame0_time_utc: DateTime<Utc>, frame0_time: DateTime<FixedOffset>) -> Self {
+        Self {
+            frame0_time_utc,
+            frame0_time,
+            skip_frames: 0,
+        }
+    }
+
+    /// Skip the first `n_frames` frames of the reader returned by `open`.
+    ///
+    /// `not_enough_data` is returned if the file has fewer frames.
+    pub(crate) fn skip_n_frames<I, E>(
+        &mut self,
+        n_frames: usize,
+        open: impl FnOnce() -> Result<I>,
+        not_enough_data: Error,
+    ) -> Result<()>
+    where
+        I: Iterator<Item = std::result::Result<(DynamicFrame, DateTime<Utc>), E>>,
+        Error: From<E>,
+    {
+        if n_frames == 0 {
+            return Ok(());
+        }
+        let mut rdr = open()?;
+
+        let mut frame_timestamp = None;
+        for _ in 0..n_frames {
+            frame_timestamp = rdr.next()
+        }
+        let (_frame, frame_time_utc) = frame_timestamp.ok_or(not_enough_data)??;
+
+        let duration = frame_time_utc - self.frame0_time_utc;
+        self.frame0_time += duration;
+        self.frame0_time_utc = frame_time_utc;
+        self.skip_frames = n_frames;
+        Ok(())
+    }
+
+    /// Iterate over the frames of `rdr`, a newly opened reader, after the
+    /// skipped frames.
+    ///
+    /// `file_pos` returns the position of the reader in the file, from which
+    /// the number of bytes of each frame is computed. If `rdr` could not be
+    /// opened, the iterator returns the error once.
+    pub(crate) fn iter<I, E>(
+        &self,
+        rdr: Result<I>,
+        file_pos: fn(&I) -> usize,
+    ) -> Box<dyn Iterator<Item = Result<FrameData>>>
+    where
+        I: Iterator<Item = std::result::Result<(DynamicFrame, DateTime<Utc>), E>> + 'static,
+        Error: From<E>,
+    {
+        let mut rdr = match rdr {
+            Ok(rdr) => rdr,
+            Err(e) => return Box::new(std::iter::once(Err(e))),
+        };
+        for _ in 0..self.skip_frames {
+            rdr.next();
+        }
+        Box::new(ReopenIter {
+            rdr,
+            file_pos,
+            frame0_time_utc: self.frame0_time_utc,
+            idx: 0,
+        })
+    }
+}
+
+struct ReopenIter<I> {
+    rdr: I,
+    file_pos: fn(&I) -> usize,
+    frame0_time_utc: DateTime<Utc>,
+    idx: usize,
+}
+
+impl<I, E> Iterator for ReopenIter<I>
+where
+    I: Iterator<Item = std::result::Result<(DynamicFrame, DateTime<Utc>), E>>,
+    Error: From<E>,
+{
+    type Item = Result<FrameData>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos_start = (self.file_pos)(&self.rdr);
+        self.rdr.next().map(|result| {
+            let (frame, frame_time_utc) = result?;
+            let buf_len = (self.file_pos)(&self.rdr) - pos_start;
+            let timestamp = frame_time_utc - self.frame0_time_utc;
+            let timestamp = Timestamp::Duration(timestamp.to_std()?);
+            let idx = self.idx;
+            self.idx += 1;
+            Ok(FrameData {
+                image: ImageData::Decoded(frame),
+                timestamp,
+                buf_len,
+                idx,
+            })
+        })
+    }
+}
+
+#[test]
+fn test_reopen_errors() {
+    type Frames = std::iter::Empty<Result<(DynamicFrame, DateTime<Utc>)>>;
+    let now = Utc::now();
+    let mut state = ReopenState::new(now, now.fixed_offset());
+
+    // A file which cannot be opened gives a single error.
+    let rdr: Result<Frames> = Err(Error::FmfWithNotEnoughData);
+    let results: Vec<_> = state.iter(rdr, |_| 0).collect();
+    assert_eq!(results.len(), 1);
+    assert!(matches!(results[0], Err(Error::FmfWithNotEnoughData)));
+
+    // Skipping more frames than the file has fails and skips nothing.
+    let result = state.skip_n_frames(2, || Ok(Frames::default()), Error::UfmfWithNotEnoughData);
+    assert!(matches!(result, Err(Error::UfmfWithNotEnoughData)));
+    assert_eq!(state.skip_frames, 0);
+}
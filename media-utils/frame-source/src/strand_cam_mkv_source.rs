@@ -24,12 +24,6 @@ pub enum StrandMkvSourceError {
     UnexpectedImageData,
 }
 
-// NAL unit start for b"MISPmicrosectime":
-const PRECISION_TIME_NALU_START: &[u8] = &[
-    0x00, 0x00, 0x00, 0x01, 0x06, 0x05, 28, b'M', b'I', b'S', b'P', b'm', b'i', b'c', b'r', b'o',
-    b's', b'e', b'c', b't', b'i', b'm', b'e',
-];
-
 /// An MKV file saved by Strand Camera.
 ///
 /// Note that this is not a general purpose MKV file converter but is specific
@@ -244,20 +238,14 @@ impl<R: Read + Seek> StrandCamMkvSource<R> {
                 machine_vision_formats::PixFmt::Mono8,
             )),
             Format::H264 => {
-                // This is a hacky and imperfect way to check if the h264 stream
-                // has a timestamp. It is hacky because:
-                //  1) it assumes the timestamp NAL unit will be the first NAL
-                // unit (or that there will only be one NAL unit). That said, I
-                // think this is actually what the MISB standard specifies.
-                //  2) it does not really parse the NAL unit structure and
-                // assumes, for example, that the start bytes are `[0x00, 0x00,
-                // 0x00, 0x01]` whereas `[0x00, 0x00, 0x01]` would also be
-                // theoretically valid start bytes. Still, we write the full 4
-                // start bytes, so this should be OK.
-                if !image_data.starts_with(&[0, 0, 0, 1]) {
-                    return Err(StrandMkvSourceError::UnexpectedImageData.into());
-                }
-                let has_precision_timestamp = image_data.starts_with(PRECISION_TIME_NALU_START);
+                // Check if the h264 stream has a timestamp. This assumes the
+                // timestamp NAL unit will be the first NAL unit (or that there
+                // will only be one NAL unit). That said, I think this is
+                // actually what the MISB standard specifies.
+                let first_nal = h264_nal_parse::split_annexb(&image_data)
+                    .next()
+                    .ok_or(StrandMkvSourceError::UnexpectedImageData)?;
+                let has_precision_timestamp = h264_sei::precision_time(first_nal)?.is_some();
                 if let Some(decoder) = self.h264_decoder_state.as_mut() {
                     let dynamic_frame = if let Some(decoded_yuv) = decoder.decode(&image_data)? {
                         my_decode(decoded_yuv, width, height)?
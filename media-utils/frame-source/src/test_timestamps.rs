@@ -14,6 +14,7 @@ fn test_h264_precision_timestamps() -> Result<()> {
         codec: ci2_remote_control::Mp4Codec::H264LessAvc,
         max_framerate: Default::default(),
         h264_metadata: None,
+        color_encoding: Default::default(),
     };
 
     const W: u32 = 32;
@@ -72,3 +73,79 @@ fn test_h264_precision_timestamps() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(feature = "openh264")]
+#[test]
+fn test_extract_frames_at() -> Result<()> {
+    let start: DateTime<Utc> = DateTime::from_timestamp(60 * 60, 0).unwrap();
+
+    let cfg = Mp4RecordingConfig {
+        codec: ci2_remote_control::Mp4Codec::H264LessAvc,
+        max_framerate: Default::default(),
+        h264_metadata: None,
+        color_encoding: Default::default(),
+    };
+
+    const W: u32 = 32;
+    const H: u32 = 16;
+    const STRIDE: usize = W as usize * 3;
+
+    // Frames every 10 msec. The intensity of frame N is N * 20.
+    let frame_times: Vec<DateTime<Utc>> = (0..10)
+        .map(|fno| start + Duration::try_milliseconds(fno * 10).unwrap())
+        .collect();
+
+    let mut mp4_buf = Vec::new();
+    {
+        let mut my_mp4_writer =
+            mp4_writer::Mp4Writer::new(std::io::Cursor::new(&mut mp4_buf), cfg, None).unwrap();
+        for (fno, ts) in frame_times.iter().enumerate() {
+            let image_data = vec![(fno * 20) as u8; STRIDE * H as usize];
+            let frame =
+                machine_vision_formats::owned::OImage::<RGB8>::new(W, H, STRIDE, image_data)
+                    .unwrap();
+            my_mp4_writer.write(&frame, *ts).unwrap();
+        }
+        my_mp4_writer.finish().unwrap();
+    }
+
+    let size = mp4_buf.len() as u64;
+    let buf_reader: Box<(dyn SeekRead + Send)> =
+        Box::new(std::io::BufReader::new(std::io::Cursor::new(mp4_buf)));
+    let mp4_reader = mp4::Mp4Reader::read_header(buf_reader, size)?;
+    let mut src = crate::mp4_source::from_reader_with_timestamp_source(
+        mp4_reader,
+        true,
+        crate::TimestampSource::BestGuess,
+        None,
+    )?;
+
+    let precision_times: Vec<_> = src
+        .precision_times()
+        .into_iter()
+        .map(|t| t.unwrap().with_timezone(&Utc))
+        .collect();
+    assert_eq!(precision_times, frame_times);
+
+    let ms = |msec| start + Duration::try_milliseconds(msec).unwrap();
+    // Requests out of order, repeated, equally near two frames and outside
+    // the recording.
+    let requested = [ms(52), ms(13), ms(52), ms(-100), ms(35), ms(1000)];
+    // As for a source opened with `crate::from_path`.
+    let dyn_src: &mut dyn FrameDataSource = &mut src;
+    let frames = dyn_src.extract_frames_at(&requested)?;
+    let indices: Vec<usize> = frames.iter().map(|f| f.idx).collect();
+    assert_eq!(indices, [5, 1, 5, 0, 3, 9]);
+    for frame in frames.iter() {
+        assert_eq!(frame.precision_time, frame_times[frame.idx]);
+        assert_eq!(frame.image.width(), W);
+        let expected = (frame.idx * 20) as i32;
+        for value in frame.image.image_data_without_format() {
+            assert!((*value as i32 - expected).abs() <= 3);
+        }
+    }
+
+    let frame = src.extract_frame_at(&ms(44).fixed_offset())?;
+    assert_eq!(frame.idx, 4);
+    Ok(())
+}
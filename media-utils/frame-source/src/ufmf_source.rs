@@ -0,0 +1,82 @@
+use crate::{reopen::ReopenState, FrameData, FrameDataSource, Result};
+use std::path::Path;
+use ufmf::UFMFReader;
+
+/// Full frames reconstructed from a UFMF file.
+///
+/// Each frame is the background model saved in the file with the saved regions
+/// around detected foreground drawn on top. See [ufmf::Reconstructor].
+///
+/// As with [crate::fmf_source::FmfSource], the file is reopened for each
+/// iteration over the frames.
+pub struct UfmfSource {
+    filename: std::path::PathBuf,
+    width: u32,
+    height: u32,
+    reopen: ReopenState,
+}
+
+impl FrameDataSource for UfmfSource {
+    fn width(&self) -> u32 {
+        self.width
+    }
+    fn height(&self) -> u32 {
+        self.height
+    }
+    fn frame0_time(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        Some(self.reopen.frame0_time)
+    }
+    fn skip_n_frames(&mut self, n_frames: usize) -> Result<()> {
+        let filename = &self.filename;
+        self.reopen.skip_n_frames(
+            n_frames,
+            || Ok(UFMFReader::from_path(filename)?.reconstruct()),
+            crate::Error::UfmfWithNotEnoughData,
+        )
+    }
+    fn estimate_luminance_range(&mut self) -> Result<(u16, u16)> {
+        Err(crate::Error::UnsupportedForEsimatingLuminangeRange)
+    }
+    fn iter(&mut self) -> Box<dyn Iterator<Item = Result<FrameData>>> {
+        let rdr = UFMFReader::from_path(&self.filename)
+            .map(UFMFReader::reconstruct)
+            .map_err(Into::into);
+        // The number of bytes in the file is not tracked since the frames are
+        // reconstructed from several chunks.
+        self.reopen.iter(rdr, |_| 0)
+    }
+    fn timestamp_source(&self) -> &str {
+        "UFMF frame metadata"
+    }
+    fn has_timestamps(&self) -> bool {
+        true
+    }
+}
+
+impl UfmfSource {
+    fn new<P: AsRef<std::path::Path>>(filename: P) -> Result<Self> {
+        let filename = filename.as_ref().to_path_buf();
+        let mut rdr = UFMFReader::from_path(&filename)?.reconstruct();
+        let width = rdr.width();
+        let height = rdr.height();
+        let frame_timestamp0 = rdr
+            .next()
+            .map(|f| f.map_err(crate::Error::from))
+            .unwrap_or_else(|| Err(crate::Error::UfmfWithNotEnoughData))?;
+
+        let (_frame0, frame0_time_utc) = frame_timestamp0;
+        let frame0_time = mkv_strand_reader::infer_timezone(&frame0_time_utc, filename.to_str())?;
+
+        Ok(Self {
+            filename,
+            width,
+            height,
+            reopen: ReopenState::new(frame0_time_utc, frame0_time),
+        })
+    }
+}
+
+pub fn from_path<P: AsRef<Path>>(path: P) -> Result<UfmfSource> {
+    let filename = path.as_ref();
+    UfmfSource::new(filename)
+}
@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let nals: Vec<&[u8]> = h264_nal_parse::split_annexb(data).collect();
+    for nal in nals.iter() {
+        assert!(!nal.is_empty());
+        assert_ne!(nal.last(), Some(&0));
+        let _ = h264_nal_parse::NalHeader::parse(nal);
+    }
+
+    // Joining the NAL units with start codes and splitting again gives the
+    // same NAL units.
+    let mut joined = Vec::new();
+    for nal in nals.iter() {
+        joined.extend_from_slice(&[0, 0, 0, 1]);
+        joined.extend_from_slice(nal);
+    }
+    let resplit: Vec<&[u8]> = h264_nal_parse::split_annexb(&joined).collect();
+    assert_eq!(nals, resplit);
+});
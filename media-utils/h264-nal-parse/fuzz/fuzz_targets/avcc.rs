@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for length_size in [1, 2, 3, 4] {
+        let mut total = 0;
+        for nal in h264_nal_parse::iter_avcc(data, length_size) {
+            match nal {
+                Ok(nal) => {
+                    assert!(!nal.is_empty());
+                    total += length_size + nal.len();
+                }
+                Err(_) => break,
+            }
+        }
+        assert!(total <= data.len());
+    }
+});
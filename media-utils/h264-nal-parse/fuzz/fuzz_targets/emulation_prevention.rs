@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let ebsp = h264_nal_parse::rbsp_to_ebsp(data);
+    // No start code, or zero bytes followed by 00, 01 or 02, can occur in the
+    // escaped data.
+    assert!(ebsp.windows(3).all(|w| w[0] != 0 || w[1] != 0 || w[2] >= 3));
+    assert_eq!(&*h264_nal_parse::ebsp_to_rbsp(&ebsp), data);
+
+    let _ = h264_nal_parse::ebsp_to_rbsp(data);
+});
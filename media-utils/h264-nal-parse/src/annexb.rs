@@ -0,0 +1,106 @@
+/// Find the index of the first three-byte start code (`00 00 01`) in `buf`.
+fn find_start_code(buf: &[u8]) -> Option<usize> {
+    let mut i = 2;
+    while i < buf.len() {
+        match buf[i] {
+            1 if buf[i - 1] == 0 && buf[i - 2] == 0 => return Some(i - 2),
+            // No start code can end at i, i+1 or i+2.
+            b if b > 1 => i += 3,
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Remove trailing zero bytes.
+///
+/// Zero bytes following a NAL unit are either the leading zero of a four-byte
+/// start code or `trailing_zero_8bits` and are never part of the NAL unit,
+/// which ends with the `rbsp_stop_one_bit`.
+pub fn trim_trailing_zeros(buf: &[u8]) -> &[u8] {
+    let len = buf.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    &buf[..len]
+}
+
+/// Iterator over the NAL units of Annex B data, created by [split_annexb].
+#[derive(Debug, Clone)]
+pub struct AnnexBSplit<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for AnnexBSplit<'a> {
+    type Item = &'a [u8];
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.rest.is_empty() {
+            let nal = match find_start_code(self.rest) {
+                Some(idx) => {
+                    let nal = &self.rest[..idx];
+                    self.rest = &self.rest[idx + 3..];
+                    nal
+                }
+                None => std::mem::take(&mut self.rest),
+            };
+            let nal = trim_trailing_zeros(nal);
+            if !nal.is_empty() {
+                return Some(nal);
+            }
+        }
+        None
+    }
+}
+
+/// Split Annex B data into NAL units.
+///
+/// Both three-byte (`00 00 01`) and four-byte (`00 00 00 01`) start codes are
+/// recognized, and trailing zero bytes are removed from the NAL units. Data
+/// before the first start code is ignored, as are empty NAL units between
+/// consecutive start codes. A sequence `00 00 03` is an emulation prevention
+/// sequence within a NAL unit, not a start code.
+pub fn split_annexb(buf: &[u8]) -> AnnexBSplit<'_> {
+    let rest = match find_start_code(buf) {
+        Some(idx) => &buf[idx + 3..],
+        None => &[],
+    };
+    AnnexBSplit { rest }
+}
+
+#[cfg(test)]
+fn split(buf: &[u8]) -> Vec<&[u8]> {
+    split_annexb(buf).collect()
+}
+
+#[test]
+fn test_split() {
+    let expected: [&[u8]; 3] = [&[9, 10, 10], &[3, 20], &[99, 99]];
+    let four_byte = [0, 0, 0, 1, 9, 10, 10, 0, 0, 0, 1, 3, 20, 0, 0, 0, 1, 99, 99];
+    assert_eq!(split(&four_byte), expected);
+    let three_byte = [0, 0, 1, 9, 10, 10, 0, 0, 1, 3, 20, 0, 0, 1, 99, 99];
+    assert_eq!(split(&three_byte), expected);
+    let mixed = [0, 0, 1, 9, 10, 10, 0, 0, 0, 1, 3, 20, 0, 0, 1, 99, 99];
+    assert_eq!(split(&mixed), expected);
+    assert!(split(&[]).is_empty());
+}
+
+#[test]
+fn test_split_malformed() {
+    // No start code.
+    assert!(split(&[9, 10, 0, 0, 2, 0]).is_empty());
+    // Only start codes.
+    assert!(split(&[0, 0, 1, 0, 0, 0, 1, 0, 0, 1]).is_empty());
+    // Truncated start codes.
+    assert!(split(&[0]).is_empty());
+    assert!(split(&[0, 0]).is_empty());
+    // Leading garbage, an empty NAL unit, trailing_zero_8bits and a start code
+    // at the end.
+    let buf = [
+        7, 7, 0, 0, 1, 9, 10, 0, 0, 0, 0, 0, 1, 0, 0, 1, 3, 0, 0, 0, 0, 1,
+    ];
+    assert_eq!(split(&buf), [&[9, 10][..], &[3]]);
+}
+
+#[test]
+fn test_split_emulation_prevention() {
+    // `00 00 03` does not split a NAL unit.
+    let buf = [0, 0, 0, 1, 6, 0, 0, 3, 1, 0, 0, 3, 0, 0x80];
+    assert_eq!(split(&buf), [&buf[4..]]);
+}
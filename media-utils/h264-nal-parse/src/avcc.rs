@@ -0,0 +1,103 @@
+use crate::{Error, Result};
+
+/// Iterator over the NAL units of AVCC data, created by [iter_avcc].
+///
+/// After an error, the iterator returns `None`.
+#[derive(Debug, Clone)]
+pub struct AvccIter<'a> {
+    rest: &'a [u8],
+    length_size: usize,
+}
+
+impl<'a> Iterator for AvccIter<'a> {
+    type Item = Result<&'a [u8]>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let result = self.next_nal();
+        if result.is_err() {
+            self.rest = &[];
+        }
+        Some(result)
+    }
+}
+
+impl<'a> AvccIter<'a> {
+    fn next_nal(&mut self) -> Result<&'a [u8]> {
+        if !matches!(self.length_size, 1 | 2 | 4) {
+            return Err(Error::InvalidLengthSize(self.length_size));
+        }
+        if self.rest.len() < self.length_size {
+            return Err(Error::TruncatedLength {
+                available: self.rest.len(),
+            });
+        }
+        let (len_bytes, rest) = self.rest.split_at(self.length_size);
+        // On 16-bit platforms, a length which does not fit in `usize` cannot
+        // fit in the buffer either.
+        let len = len_bytes
+            .iter()
+            .fold(0u64, |acc, b| (acc << 8) | u64::from(*b));
+        let len = usize::try_from(len).unwrap_or(usize::MAX);
+        if len == 0 {
+            return Err(Error::EmptyNal);
+        }
+        if len > rest.len() {
+            return Err(Error::LengthMismatch {
+                len,
+                available: rest.len(),
+            });
+        }
+        let (nal, rest) = rest.split_at(len);
+        self.rest = rest;
+        Ok(nal)
+    }
+}
+
+/// Iterate over the NAL units of AVCC data, in which each NAL unit is
+/// preceded by its length as a big-endian integer of `length_size` bytes.
+///
+/// In MP4 files, `length_size` is given by the `lengthSizeMinusOne` field of
+/// the `avcC` box and is almost always 4. Invalid values of `length_size`,
+/// lengths of zero, lengths exceeding the remaining data and data ending
+/// within a length field are reported as errors.
+pub fn iter_avcc(buf: &[u8], length_size: usize) -> AvccIter<'_> {
+    AvccIter {
+        rest: buf,
+        length_size,
+    }
+}
+
+#[test]
+fn test_iter_avcc() {
+    let buf = [0, 0, 0, 3, 9, 10, 10, 0, 0, 0, 2, 3, 20];
+    let nals: Vec<_> = iter_avcc(&buf, 4).collect::<Result<_>>().unwrap();
+    assert_eq!(nals, [&[9, 10, 10][..], &[3, 20]]);
+
+    let buf = [0, 3, 9, 10, 10, 0, 2, 3, 20];
+    let nals: Vec<_> = iter_avcc(&buf, 2).collect::<Result<_>>().unwrap();
+    assert_eq!(nals, [&[9, 10, 10][..], &[3, 20]]);
+
+    assert_eq!(iter_avcc(&[], 4).count(), 0);
+}
+
+#[test]
+fn test_iter_avcc_malformed() {
+    fn errors(buf: &[u8], length_size: usize) -> Vec<Result<&[u8]>> {
+        iter_avcc(buf, length_size).collect()
+    }
+    assert_eq!(
+        errors(&[0, 0, 0, 1, 9, 0, 0], 4),
+        [Ok(&[9][..]), Err(Error::TruncatedLength { available: 2 })]
+    );
+    assert_eq!(
+        errors(&[0xFF, 0xFF, 0xFF, 0xFF, 9], 4),
+        [Err(Error::LengthMismatch {
+            len: 0xFFFF_FFFF,
+            available: 1
+        })]
+    );
+    assert_eq!(errors(&[0, 0, 0, 0, 9], 4), [Err(Error::EmptyNal)]);
+    assert_eq!(errors(&[0, 1, 9], 3), [Err(Error::InvalidLengthSize(3))]);
+}
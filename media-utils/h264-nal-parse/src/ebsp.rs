@@ -0,0 +1,68 @@
+use std::borrow::Cow;
+
+/// Remove the emulation prevention bytes from a NAL unit, converting
+/// Encapsulated Byte Sequence Payload (EBSP) to Raw Byte Sequence Payload
+/// (RBSP).
+///
+/// Each `03` byte following two zero bytes is removed. Data without emulation
+/// prevention bytes is returned without copying.
+pub fn ebsp_to_rbsp(ebsp: &[u8]) -> Cow<'_, [u8]> {
+    let mut zeros = 0;
+    let mut result: Option<Vec<u8>> = None;
+    for (i, &b) in ebsp.iter().enumerate() {
+        if zeros >= 2 && b == 3 {
+            result.get_or_insert_with(|| ebsp[..i].to_vec());
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        if let Some(result) = result.as_mut() {
+            result.push(b);
+        }
+    }
+    match result {
+        Some(rbsp) => Cow::Owned(rbsp),
+        None => Cow::Borrowed(ebsp),
+    }
+}
+
+/// Insert emulation prevention bytes into a NAL unit, converting Raw Byte
+/// Sequence Payload (RBSP) to Encapsulated Byte Sequence Payload (EBSP).
+///
+/// A `03` byte is inserted after two zero bytes followed by a byte of `03` or
+/// less, so that the result contains no start code. As the last byte of a NAL
+/// unit is never zero, a `03` byte is also inserted if `rbsp` ends with two
+/// zero bytes.
+pub fn rbsp_to_ebsp(rbsp: &[u8]) -> Vec<u8> {
+    let mut ebsp = Vec::with_capacity(rbsp.len() + rbsp.len() / 64 + 1);
+    let mut zeros = 0;
+    for &b in rbsp.iter() {
+        if zeros >= 2 && b <= 3 {
+            ebsp.push(3);
+            zeros = 0;
+        }
+        ebsp.push(b);
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+    }
+    if zeros >= 2 {
+        ebsp.push(3);
+    }
+    ebsp
+}
+
+#[test]
+fn test_emulation_prevention() {
+    let rbsp = [6, 0, 0, 1, 0, 0, 0, 0, 0, 3, 0x80];
+    let ebsp = rbsp_to_ebsp(&rbsp);
+    assert_eq!(ebsp, [6, 0, 0, 3, 1, 0, 0, 3, 0, 0, 3, 0, 3, 0x80]);
+    assert_eq!(ebsp_to_rbsp(&ebsp), &rbsp[..]);
+
+    // Without emulation prevention bytes, no copy is made.
+    let plain = [6, 5, 0, 4, 0x80];
+    assert!(matches!(ebsp_to_rbsp(&plain), Cow::Borrowed(_)));
+    assert_eq!(rbsp_to_ebsp(&plain), plain);
+
+    // Trailing zero bytes.
+    assert_eq!(rbsp_to_ebsp(&[1, 0, 0]), [1, 0, 0, 3]);
+    assert_eq!(ebsp_to_rbsp(&[1, 0, 0, 3]), &[1, 0, 0][..]);
+}
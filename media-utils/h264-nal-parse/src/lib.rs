@@ -0,0 +1,89 @@
+// Copyright 2024 Andrew D. Straw.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Splitting of H.264 streams into NAL units.
+//!
+//! H.264 data from cameras and encoders arrives either in Annex B format, with
+//! NAL units separated by start codes, or in AVCC format, with each NAL unit
+//! preceded by its big-endian length, as in MP4 files. The functions here
+//! never panic, whatever the input: malformed AVCC data gives an [Error], and
+//! Annex B data, which has no invalid byte sequences, is split as a decoder
+//! would split it.
+//!
+//! The NAL units returned are Encapsulated Byte Sequence Payload (EBSP) data,
+//! i.e. they still contain emulation prevention bytes. [ebsp_to_rbsp] removes
+//! them and [rbsp_to_ebsp] inserts them.
+//!
+//! The `fuzz` directory has `cargo fuzz` targets for all parsers.
+
+mod annexb;
+mod avcc;
+mod ebsp;
+
+pub use annexb::{split_annexb, trim_trailing_zeros, AnnexBSplit};
+pub use avcc::{iter_avcc, AvccIter};
+pub use ebsp::{ebsp_to_rbsp, rbsp_to_ebsp};
+
+/// NAL unit type of a coded slice of a non-IDR picture.
+pub const NAL_UNIT_TYPE_SLICE_NON_IDR: u8 = 1;
+/// NAL unit type of a coded slice of an IDR picture.
+pub const NAL_UNIT_TYPE_SLICE_IDR: u8 = 5;
+/// NAL unit type of supplemental enhancement information.
+pub const NAL_UNIT_TYPE_SEI: u8 = 6;
+/// NAL unit type of a sequence parameter set.
+pub const NAL_UNIT_TYPE_SPS: u8 = 7;
+/// NAL unit type of a picture parameter set.
+pub const NAL_UNIT_TYPE_PPS: u8 = 8;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    #[error("AVCC length field size must be 1, 2 or 4 bytes, not {0}")]
+    InvalidLengthSize(usize),
+    #[error("AVCC data ends within a length field ({available} bytes left)")]
+    TruncatedLength { available: usize },
+    #[error("AVCC NAL unit of {len} bytes, but only {available} bytes left")]
+    LengthMismatch { len: usize, available: usize },
+    #[error("empty NAL unit")]
+    EmptyNal,
+    #[error("NAL unit header with forbidden_zero_bit set")]
+    ForbiddenZeroBit,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The header byte of a NAL unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NalHeader {
+    pub nal_ref_idc: u8,
+    pub nal_unit_type: u8,
+}
+
+impl NalHeader {
+    /// Parse the header of a NAL unit, the first byte of `nal`.
+    pub fn parse(nal: &[u8]) -> Result<Self> {
+        let byte = *nal.first().ok_or(Error::EmptyNal)?;
+        if byte & 0x80 != 0 {
+            return Err(Error::ForbiddenZeroBit);
+        }
+        Ok(Self {
+            nal_ref_idc: (byte >> 5) & 0x03,
+            nal_unit_type: byte & 0x1F,
+        })
+    }
+}
+
+#[test]
+fn test_nal_header() {
+    let sps = NalHeader::parse(&[0x67, 0x42]).unwrap();
+    assert_eq!(sps.nal_unit_type, NAL_UNIT_TYPE_SPS);
+    assert_eq!(sps.nal_ref_idc, 3);
+    let idr = NalHeader::parse(&[0x25]).unwrap();
+    assert_eq!(idr.nal_unit_type, NAL_UNIT_TYPE_SLICE_IDR);
+    assert_eq!(idr.nal_ref_idc, 1);
+    assert_eq!(NalHeader::parse(&[]), Err(Error::EmptyNal));
+    assert_eq!(NalHeader::parse(&[0xE7]), Err(Error::ForbiddenZeroBit));
+}
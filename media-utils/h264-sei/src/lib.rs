@@ -0,0 +1,241 @@
+// Copyright 2024 Andrew D. Straw.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Supplemental enhancement information (SEI) written by Strand Camera.
+//!
+//! Strand Camera stores two kinds of SEI "user data unregistered" messages in
+//! its H.264 streams: a `MISPmicrosectime` precision timestamp, following MISB
+//! Standard 0603, before each frame and an [H264Metadata] message, encoded as
+//! JSON, before the first frame. This crate encodes and decodes both, so that
+//! other tools can write and read timestamps compatible with Strand Camera.
+//!
+//! All NAL units here are Encapsulated Byte Sequence Payload (EBSP) data
+//! without a start code or length prefix, as returned by
+//! [h264_nal_parse::split_annexb] and [h264_nal_parse::iter_avcc].
+
+use chrono::{DateTime, Utc};
+
+pub use ci2_remote_control::{H264Metadata, H264_METADATA_UUID, H264_METADATA_VERSION};
+
+mod sei;
+mod timestamps;
+
+pub use sei::{
+    sei_messages, user_data_unregistered_nal, SeiMessage, SeiMessages, UserDataUnregistered,
+    PAYLOAD_TYPE_USER_DATA_UNREGISTERED,
+};
+pub use timestamps::{timestamped_nals, TimestampedNal, TimestampedNals};
+
+/// The UUID of a precision timestamp message.
+pub const PRECISION_TIME_UUID: &[u8; 16] = b"MISPmicrosectime";
+
+/// The length of the user data of a precision timestamp message.
+const PRECISION_TIME_LEN: usize = 12;
+
+/// The Time Stamp Status byte from MISB Standard 0603 written by Strand
+/// Camera.
+const TIME_STAMP_STATUS: u8 = 0x1F;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("H264 parse error: {0}")]
+    NalParse(#[from] h264_nal_parse::Error),
+    #[error("SEI message truncated")]
+    TruncatedSei,
+    #[error("SEI payload of {0} bytes too short for UserDataUnregistered message")]
+    UduTooShort(usize),
+    #[error("precision timestamp of {0} bytes, expected 12")]
+    UnexpectedPayloadLength(usize),
+    #[error("unexpected start code emulation prevention byte in precision timestamp")]
+    UnexpectedStartCodeByte,
+    #[error("precision timestamp out of range")]
+    TimestampOutOfRange,
+    #[error("unexpected version \"{0}\" in H264 metadata")]
+    UnexpectedMetadataVersion(String),
+    #[error("H264 metadata JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Encode a timestamp as the payload of a precision timestamp SEI message,
+/// including its UUID.
+///
+/// The timestamp is stored with microsecond resolution.
+pub fn encode_precision_time(timestamp: DateTime<Utc>) -> [u8; 28] {
+    let precision_time_stamp_bytes: [u8; 8] = timestamp.timestamp_micros().to_be_bytes();
+
+    let mut payload = [0u8; 28];
+    payload[0..16].copy_from_slice(PRECISION_TIME_UUID); // uuid_iso_iec_11578
+
+    payload[16] = TIME_STAMP_STATUS;
+
+    // The standard has 0xFF present after every two bytes as "Start Code
+    // Emulation Prevention". This means that the raw byte sequence is identical
+    // to the encoded byte sequence as there is nothing to encode.
+    payload[17..19].copy_from_slice(&precision_time_stamp_bytes[0..2]);
+    payload[19] = 0xff;
+    payload[20..22].copy_from_slice(&precision_time_stamp_bytes[2..4]);
+    payload[22] = 0xff;
+    payload[23..25].copy_from_slice(&precision_time_stamp_bytes[4..6]);
+    payload[25] = 0xff;
+    payload[26..28].copy_from_slice(&precision_time_stamp_bytes[6..8]);
+    payload
+}
+
+/// Decode the user data, following the UUID, of a precision timestamp SEI
+/// message.
+pub fn decode_precision_time(user_data: &[u8]) -> Result<DateTime<Utc>> {
+    if user_data.len() != PRECISION_TIME_LEN {
+        return Err(Error::UnexpectedPayloadLength(user_data.len()));
+    }
+
+    // The Time Stamp Status byte, `user_data[0]`, could be parsed for
+    // Locked/Unlocked (bit 7), Normal/Discontinuity (bit 6) and Forward/Reverse
+    // (bit 5), but is not checked.
+
+    for i in [3, 6, 9] {
+        if user_data[i] != 0xFF {
+            return Err(Error::UnexpectedStartCodeByte);
+        }
+    }
+    let mut precision_time_stamp_bytes = [0u8; 8];
+    precision_time_stamp_bytes[0..2].copy_from_slice(&user_data[1..3]);
+    precision_time_stamp_bytes[2..4].copy_from_slice(&user_data[4..6]);
+    precision_time_stamp_bytes[4..6].copy_from_slice(&user_data[7..9]);
+    precision_time_stamp_bytes[6..8].copy_from_slice(&user_data[10..12]);
+    let precision_time_stamp = i64::from_be_bytes(precision_time_stamp_bytes);
+    DateTime::from_timestamp_micros(precision_time_stamp).ok_or(Error::TimestampOutOfRange)
+}
+
+/// Create an SEI NAL unit containing a precision timestamp.
+pub fn precision_time_nal(timestamp: DateTime<Utc>) -> Vec<u8> {
+    let payload = encode_precision_time(timestamp);
+    user_data_unregistered_nal(PRECISION_TIME_UUID, &payload[16..])
+}
+
+/// Create an SEI NAL unit containing H264 metadata.
+pub fn metadata_nal(metadata: &H264Metadata) -> Vec<u8> {
+    // Serialization cannot fail: all map keys are strings.
+    let msg = serde_json::to_vec(metadata).unwrap();
+    user_data_unregistered_nal(&H264_METADATA_UUID, &msg)
+}
+
+/// Decode the user data, following the UUID, of an H264 metadata SEI message.
+pub fn decode_metadata(user_data: &[u8]) -> Result<H264Metadata> {
+    let metadata: H264Metadata = serde_json::from_slice(user_data)?;
+    if metadata.version != H264_METADATA_VERSION {
+        return Err(Error::UnexpectedMetadataVersion(metadata.version));
+    }
+    Ok(metadata)
+}
+
+/// Call `f` with each UserDataUnregistered message of a NAL unit.
+///
+/// NAL units other than SEI are ignored.
+fn for_each_udu<F>(nal: &[u8], mut f: F) -> Result<()>
+where
+    F: FnMut(UserDataUnregistered<'_>) -> Result<()>,
+{
+    let header = h264_nal_parse::NalHeader::parse(nal)?;
+    if header.nal_unit_type != h264_nal_parse::NAL_UNIT_TYPE_SEI {
+        return Ok(());
+    }
+    let rbsp = h264_nal_parse::ebsp_to_rbsp(&nal[1..]);
+    for msg in sei_messages(&rbsp) {
+        let msg = msg?;
+        if msg.payload_type == PAYLOAD_TYPE_USER_DATA_UNREGISTERED {
+            f(UserDataUnregistered::parse(msg.payload)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// The precision timestamp in a NAL unit, if it is an SEI NAL unit with one.
+pub fn precision_time(nal: &[u8]) -> Result<Option<DateTime<Utc>>> {
+    let mut result = None;
+    for_each_udu(nal, |udu| {
+        if udu.uuid == PRECISION_TIME_UUID {
+            result = Some(decode_precision_time(udu.payload)?);
+        }
+        Ok(())
+    })?;
+    Ok(result)
+}
+
+/// The H264 metadata in a NAL unit, if it is an SEI NAL unit with it.
+pub fn metadata(nal: &[u8]) -> Result<Option<H264Metadata>> {
+    let mut result = None;
+    for_each_udu(nal, |udu| {
+        if udu.uuid == &H264_METADATA_UUID {
+            result = Some(decode_metadata(udu.payload)?);
+        }
+        Ok(())
+    })?;
+    Ok(result)
+}
+
+#[test]
+fn test_precision_time_roundtrip() {
+    let timestamp = DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap();
+    let payload = encode_precision_time(timestamp);
+    assert_eq!(&payload[..16], PRECISION_TIME_UUID);
+    assert_eq!(decode_precision_time(&payload[16..]).unwrap(), timestamp);
+
+    // Timestamps before 1970 are negative.
+    let early = DateTime::from_timestamp_micros(-1).unwrap();
+    let payload = encode_precision_time(early);
+    assert_eq!(decode_precision_time(&payload[16..]).unwrap(), early);
+
+    // The NAL unit is the same as that written by earlier versions of Strand
+    // Camera, with no emulation prevention bytes.
+    let nal = precision_time_nal(timestamp);
+    assert_eq!(nal.len(), 32);
+    assert_eq!(&nal[..3], &[0x06, 0x05, 28]);
+    assert_eq!(&nal[3..31], &payload[..]);
+    assert_eq!(nal[31], 0x80);
+    assert_eq!(precision_time(&nal).unwrap(), Some(timestamp));
+    assert_eq!(metadata(&nal).unwrap(), None);
+}
+
+#[test]
+fn test_precision_time_errors() {
+    assert!(matches!(
+        decode_precision_time(&[0; 11]),
+        Err(Error::UnexpectedPayloadLength(11))
+    ));
+    assert!(matches!(
+        decode_precision_time(&[0; 12]),
+        Err(Error::UnexpectedStartCodeByte)
+    ));
+    let mut user_data = [0xFF; 12];
+    user_data[1] = 0x7F;
+    assert!(matches!(
+        decode_precision_time(&user_data),
+        Err(Error::TimestampOutOfRange)
+    ));
+    // A slice NAL unit has no timestamp.
+    assert_eq!(precision_time(&[0x65, 0x88, 0x84]).unwrap(), None);
+}
+
+#[test]
+fn test_metadata_roundtrip() {
+    let creation_time = DateTime::parse_from_rfc3339("2024-03-01T12:00:00+01:00").unwrap();
+    let mut md = H264Metadata::new("h264-sei-test", creation_time);
+    // Long enough that the payload size takes more than one byte.
+    md.camera_name = Some("a".repeat(300));
+    let nal = metadata_nal(&md);
+    assert_eq!(metadata(&nal).unwrap(), Some(md.clone()));
+    assert_eq!(precision_time(&nal).unwrap(), None);
+
+    md.version = "https://example.com/v0/".into();
+    let nal = metadata_nal(&md);
+    assert!(matches!(
+        metadata(&nal),
+        Err(Error::UnexpectedMetadataVersion(_))
+    ));
+}
@@ -0,0 +1,161 @@
+use crate::{Error, Result};
+
+/// SEI payload type of a UserDataUnregistered message.
+pub const PAYLOAD_TYPE_USER_DATA_UNREGISTERED: u32 = 5;
+
+/// A message in an SEI NAL unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeiMessage<'a> {
+    pub payload_type: u32,
+    pub payload: &'a [u8],
+}
+
+/// Iterator over the messages of an SEI NAL unit, returned by [sei_messages].
+///
+/// After returning an error, the iterator ends.
+#[derive(Debug, Clone)]
+pub struct SeiMessages<'a> {
+    rbsp: &'a [u8],
+}
+
+/// Iterate over the messages in the Raw Byte Sequence Payload (RBSP) of an SEI
+/// NAL unit, the data after its header byte with emulation prevention bytes
+/// removed.
+pub fn sei_messages(rbsp: &[u8]) -> SeiMessages<'_> {
+    SeiMessages { rbsp }
+}
+
+impl<'a> SeiMessages<'a> {
+    /// Read a payload type or size, coded as a run of `FF` bytes, each adding
+    /// 255, and a final byte.
+    fn read_value(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        loop {
+            let (&b, rest) = self.rbsp.split_first().ok_or(Error::TruncatedSei)?;
+            self.rbsp = rest;
+            value = value.saturating_add(b.into());
+            if b != 0xFF {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn read_message(&mut self) -> Result<SeiMessage<'a>> {
+        let payload_type = self.read_value()?;
+        let payload_size = self.read_value()? as usize;
+        if payload_size > self.rbsp.len() {
+            return Err(Error::TruncatedSei);
+        }
+        let (payload, rest) = self.rbsp.split_at(payload_size);
+        self.rbsp = rest;
+        Ok(SeiMessage {
+            payload_type,
+            payload,
+        })
+    }
+}
+
+impl<'a> Iterator for SeiMessages<'a> {
+    type Item = Result<SeiMessage<'a>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        // Stop at the RBSP trailing bits, a one bit followed by zero bits.
+        match self.rbsp.split_first() {
+            None => return None,
+            Some((0x80, rest)) if rest.iter().all(|&b| b == 0) => {
+                self.rbsp = &[];
+                return None;
+            }
+            Some(_) => {}
+        }
+        let result = self.read_message();
+        if result.is_err() {
+            self.rbsp = &[];
+        }
+        Some(result)
+    }
+}
+
+/// An SEI UserDataUnregistered message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserDataUnregistered<'a> {
+    pub uuid: &'a [u8; 16],
+    /// The user data following the UUID.
+    pub payload: &'a [u8],
+}
+
+impl<'a> UserDataUnregistered<'a> {
+    /// Parse the payload of an SEI message of type
+    /// [PAYLOAD_TYPE_USER_DATA_UNREGISTERED].
+    pub fn parse(payload: &'a [u8]) -> Result<Self> {
+        if payload.len() < 16 {
+            return Err(Error::UduTooShort(payload.len()));
+        }
+        let (uuid, payload) = payload.split_at(16);
+        Ok(Self {
+            uuid: uuid.try_into().unwrap(),
+            payload,
+        })
+    }
+}
+
+/// Create an SEI NAL unit, with `nal_ref_idc` zero, containing a single
+/// UserDataUnregistered message.
+pub fn user_data_unregistered_nal(uuid: &[u8; 16], payload: &[u8]) -> Vec<u8> {
+    let mut size = uuid.len() + payload.len();
+    let mut rbsp = Vec::with_capacity(size + size / 255 + 3);
+    rbsp.push(PAYLOAD_TYPE_USER_DATA_UNREGISTERED as u8);
+    while size >= 255 {
+        rbsp.push(0xFF);
+        size -= 255;
+    }
+    rbsp.push(size as u8);
+    rbsp.extend_from_slice(uuid);
+    rbsp.extend_from_slice(payload);
+    rbsp.push(0x80); // rbsp_trailing_bits
+
+    let mut nal = vec![h264_nal_parse::NAL_UNIT_TYPE_SEI];
+    nal.extend(h264_nal_parse::rbsp_to_ebsp(&rbsp));
+    nal
+}
+
+#[test]
+fn test_sei_messages() {
+    // Two messages, the second with a payload type and size over 255.
+    let mut rbsp = vec![5, 2, 0xAA, 0xBB, 0xFF, 0x01, 0xFF, 0x00];
+    rbsp.extend(std::iter::repeat(7).take(255));
+    rbsp.push(0x80);
+    let msgs: Vec<_> = sei_messages(&rbsp).collect::<Result<_>>().unwrap();
+    assert_eq!(msgs.len(), 2);
+    assert_eq!(msgs[0].payload_type, 5);
+    assert_eq!(msgs[0].payload, &[0xAA, 0xBB]);
+    assert_eq!(msgs[1].payload_type, 256);
+    assert_eq!(msgs[1].payload.len(), 255);
+
+    // A truncated message gives one error.
+    let mut msgs = sei_messages(&[5, 20, 1, 2, 3]);
+    assert!(matches!(msgs.next(), Some(Err(Error::TruncatedSei))));
+    assert!(msgs.next().is_none());
+    let mut msgs = sei_messages(&[0xFF, 0xFF]);
+    assert!(matches!(msgs.next(), Some(Err(Error::TruncatedSei))));
+
+    assert!(matches!(
+        UserDataUnregistered::parse(&[0; 15]),
+        Err(Error::UduTooShort(15))
+    ));
+}
+
+#[test]
+fn test_user_data_unregistered_nal() {
+    let uuid = [0u8; 16];
+    let payload = [0u8, 0, 1, 2];
+    let nal = user_data_unregistered_nal(&uuid, &payload);
+    // The NAL unit contains emulation prevention bytes.
+    assert!(!nal.windows(3).any(|w| w[0] == 0 && w[1] == 0 && w[2] < 3));
+    let rbsp = h264_nal_parse::ebsp_to_rbsp(&nal[1..]);
+    let msgs: Vec<_> = sei_messages(&rbsp).collect::<Result<_>>().unwrap();
+    assert_eq!(msgs.len(), 1);
+    assert_eq!(msgs[0].payload_type, PAYLOAD_TYPE_USER_DATA_UNREGISTERED);
+    let udu = UserDataUnregistered::parse(msgs[0].payload).unwrap();
+    assert_eq!(udu.uuid, &uuid);
+    assert_eq!(udu.payload, &payload);
+}
@@ -0,0 +1,130 @@
+use chrono::{DateTime, Utc};
+
+use h264_nal_parse::{NalHeader, NAL_UNIT_TYPE_SLICE_IDR, NAL_UNIT_TYPE_SLICE_NON_IDR};
+
+use crate::Result;
+
+/// A NAL unit and, for coded slices, the precision timestamp of its picture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampedNal<'a> {
+    pub header: NalHeader,
+    pub nal: &'a [u8],
+    /// The precision timestamp, if this is a coded slice preceded by a
+    /// precision timestamp SEI message in the same access unit.
+    pub precision_time: Option<DateTime<Utc>>,
+}
+
+/// Iterator over NAL units with precision timestamps, returned by
+/// [timestamped_nals].
+///
+/// After returning an error, the iterator ends.
+#[derive(Debug, Clone)]
+pub struct TimestampedNals<I> {
+    nals: I,
+    precision_time: Option<DateTime<Utc>>,
+    after_slice: bool,
+    done: bool,
+}
+
+/// Iterate over NAL units, attaching precision timestamps to coded slices.
+///
+/// A precision timestamp applies to the slices following it until the next
+/// access unit, which starts with the first non-slice NAL unit after a slice,
+/// as Strand Camera writes them. Slices of a picture without a precision
+/// timestamp of its own, and all other NAL units, have none.
+///
+/// Use with [h264_nal_parse::split_annexb] for Annex B data. For AVCC data,
+/// collect the results of [h264_nal_parse::iter_avcc] first.
+pub fn timestamped_nals<'a, I>(nals: I) -> TimestampedNals<I::IntoIter>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    TimestampedNals {
+        nals: nals.into_iter(),
+        precision_time: None,
+        after_slice: false,
+        done: false,
+    }
+}
+
+impl<I> TimestampedNals<I> {
+    fn timestamp<'a>(&mut self, nal: &'a [u8]) -> Result<TimestampedNal<'a>> {
+        let header = NalHeader::parse(nal)?;
+        let is_slice =
+            (NAL_UNIT_TYPE_SLICE_NON_IDR..=NAL_UNIT_TYPE_SLICE_IDR).contains(&header.nal_unit_type);
+        if is_slice {
+            self.after_slice = true;
+            return Ok(TimestampedNal {
+                header,
+                nal,
+                precision_time: self.precision_time,
+            });
+        }
+        if self.after_slice {
+            self.after_slice = false;
+            self.precision_time = None;
+        }
+        if let Some(t) = crate::precision_time(nal)? {
+            self.precision_time = Some(t);
+        }
+        Ok(TimestampedNal {
+            header,
+            nal,
+            precision_time: None,
+        })
+    }
+}
+
+impl<'a, I> Iterator for TimestampedNals<I>
+where
+    I: Iterator<Item = &'a [u8]>,
+{
+    type Item = Result<TimestampedNal<'a>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let nal = self.nals.next()?;
+        let result = self.timestamp(nal);
+        self.done = result.is_err();
+        Some(result)
+    }
+}
+
+#[test]
+fn test_timestamped_nals() {
+    let t0 = DateTime::from_timestamp_micros(1_000_000).unwrap();
+    let t2 = DateTime::from_timestamp_micros(1_020_000).unwrap();
+    let sps = vec![0x67, 0x42, 0x00, 0x1F];
+    let pps = vec![0x68, 0xCE];
+    let idr = vec![0x65, 0x88];
+    let non_idr = vec![0x41, 0x9A];
+    let mut annexb = Vec::new();
+    for nal in [
+        sps,
+        pps,
+        crate::precision_time_nal(t0),
+        idr.clone(),
+        idr,
+        // A second frame without a timestamp.
+        crate::metadata_nal(&crate::H264Metadata::new("test", t0.fixed_offset())),
+        non_idr.clone(),
+        crate::precision_time_nal(t2),
+        non_idr,
+    ] {
+        annexb.extend([0, 0, 0, 1]);
+        annexb.extend(nal);
+    }
+
+    let times: Vec<_> = timestamped_nals(h264_nal_parse::split_annexb(&annexb))
+        .map(|r| r.unwrap())
+        .filter(|n| n.header.nal_unit_type <= NAL_UNIT_TYPE_SLICE_IDR)
+        .map(|n| n.precision_time)
+        .collect();
+    assert_eq!(times, vec![Some(t0), Some(t0), None, Some(t2)]);
+
+    // An invalid NAL unit ends iteration with an error.
+    let mut iter = timestamped_nals([&[0x80u8][..], &[0x65][..]]);
+    assert!(iter.next().unwrap().is_err());
+    assert!(iter.next().is_none());
+}
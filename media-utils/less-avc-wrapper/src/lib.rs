@@ -15,13 +15,11 @@ pub enum Error {
     LessAvcError {
         #[from]
         source: less_avc::Error,
-
     },
     #[error("convert image error: {source}")]
     ConvertImageError {
         #[from]
         source: convert_image::Error,
-
     },
     #[error("y4m writer error: {0}")]
     Y4mError(#[from] y4m_writer::Error),
@@ -29,14 +27,22 @@ pub enum Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
-fn convert_to_y4m<FRAME, FMT>(frame: &FRAME) -> Result<y4m_writer::Y4MFrame>
+fn convert_to_y4m<FRAME, FMT>(
+    frame: &FRAME,
+    color_encoding: y4m_writer::ColorEncoding,
+) -> Result<y4m_writer::Y4MFrame>
 where
     FRAME: ImageStride<FMT>,
     FMT: PixelFormat,
 {
     let out_colorspace = y4m::Colorspace::C420paldv;
     let forced_block_size = Some(16);
-    let y4m = y4m_writer::encode_y4m_frame(frame, out_colorspace, forced_block_size)?;
+    let y4m = y4m_writer::encode_y4m_frame_with_encoding(
+        frame,
+        out_colorspace,
+        forced_block_size,
+        color_encoding,
+    )?;
     Ok(y4m)
 }
 
@@ -77,15 +83,24 @@ fn gen_y4m_ref(y4m: &y4m_writer::Y4MFrame) -> Result<YCbCrImage<'_>> {
 #[derive(Default)]
 pub struct WrappedLessEncoder {
     inner: Option<less_avc::LessEncoder>,
+    color_encoding: y4m_writer::ColorEncoding,
 }
 
 impl WrappedLessEncoder {
+    /// Create an encoder converting RGB to YUV with `color_encoding`.
+    pub fn with_color_encoding(color_encoding: y4m_writer::ColorEncoding) -> Self {
+        Self {
+            inner: None,
+            color_encoding,
+        }
+    }
+
     pub fn encode_to_nal_units<FRAME, FMT>(&mut self, frame: &FRAME) -> Result<Vec<Vec<u8>>>
     where
         FRAME: ImageStride<FMT>,
         FMT: PixelFormat,
     {
-        let y4m = convert_to_y4m(frame)?;
+        let y4m = convert_to_y4m(frame, self.color_encoding)?;
         let y4m_ref = gen_y4m_ref(&y4m)?;
 
         let (nals, encoder) = match self.inner.take() {
@@ -139,7 +154,7 @@ impl<W: Write> H264WriterWrapper<W> {
         IM: ImageStride<FMT>,
         FMT: PixelFormat,
     {
-        let y4m = convert_to_y4m(frame)?;
+        let y4m = convert_to_y4m(frame, Default::default())?;
         let y4m_ref = gen_y4m_ref(&y4m)?;
         self.inner.write(&y4m_ref)?;
         Ok(())
@@ -0,0 +1,252 @@
+//! Reading and editing the H264 metadata of MP4 files saved by Strand Camera.
+//!
+//! Strand Camera stores [H264Metadata], such as the camera name and
+//! experiment description, in an SEI message in the first frame of the H264
+//! stream. [rewrite_metadata] copies an MP4 file, one sample at a time,
+//! replacing or inserting this message. The video is not re-encoded and the
+//! memory used does not grow with the size of the video data.
+//!
+//! MP4 files saved by Strand Camera do not contain `udta` boxes, so the SEI
+//! message is the only metadata written.
+
+use std::io::{Read, Seek, Write};
+
+use chrono::FixedOffset;
+use mp4::MediaType;
+
+pub use h264_sei::H264Metadata;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("MP4 error: {0}")]
+    Mp4(#[from] mp4::Error),
+    #[error("H264 parse error: {0}")]
+    H264NalParse(#[from] h264_nal_parse::Error),
+    #[error("H264 SEI error: {0}")]
+    H264Sei(#[from] h264_sei::Error),
+    #[error("no H264 track found")]
+    NoH264Track,
+    #[error("only MP4 files with a single track, of H264 video, are supported")]
+    SingleH264TrackOnly,
+    #[error("MP4 file has no samples")]
+    NoSamples,
+    #[error("MP4 sample {0} not found")]
+    MissingSample(u32),
+    #[error("no H264 metadata or precision timestamp in first frame")]
+    NoCreationTime,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Find the ID of the H264 track, the only track of the file.
+fn h264_track_id<R: Read + Seek>(mp4_reader: &mp4::Mp4Reader<R>) -> Result<u32> {
+    let mut tracks = mp4_reader.tracks().iter();
+    let (track_id, track) = tracks.next().ok_or(Error::NoH264Track)?;
+    if tracks.next().is_some() {
+        return Err(Error::SingleH264TrackOnly);
+    }
+    if track.media_type()? != MediaType::H264 {
+        return Err(Error::NoH264Track);
+    }
+    Ok(*track_id)
+}
+
+/// Read the first sample of the H264 track.
+fn read_first_sample<R: Read + Seek>(
+    mp4_reader: &mut mp4::Mp4Reader<R>,
+    track_id: u32,
+) -> Result<mp4::Mp4Sample> {
+    // mp4 uses 1 based indexing
+    mp4_reader.read_sample(track_id, 1)?.ok_or(Error::NoSamples)
+}
+
+/// Find the metadata in the NAL units of an AVCC sample.
+fn find_metadata(sample: &[u8]) -> Result<Option<H264Metadata>> {
+    for nal in h264_nal_parse::iter_avcc(sample, 4) {
+        if let Some(metadata) = h264_sei::metadata(nal?)? {
+            return Ok(Some(metadata));
+        }
+    }
+    Ok(None)
+}
+
+/// Create metadata for a sample without it, with the creation time taken from
+/// the precision timestamp of the frame.
+fn new_metadata(sample: &[u8]) -> Result<H264Metadata> {
+    for nal in h264_nal_parse::iter_avcc(sample, 4) {
+        if let Some(timestamp) = h264_sei::precision_time(nal?)? {
+            let writing_app = format!("{}-{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            return Ok(H264Metadata::new(&writing_app, timestamp.fixed_offset()));
+        }
+    }
+    Err(Error::NoCreationTime)
+}
+
+fn push_avcc(buf: &mut Vec<u8>, nal: &[u8]) {
+    buf.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+    buf.extend_from_slice(nal);
+}
+
+/// Replace the SEI NAL unit containing metadata in an AVCC sample or, if
+/// there is none, insert one at the start of the sample.
+fn replace_metadata(sample: &[u8], metadata: &H264Metadata) -> Result<Vec<u8>> {
+    let nals = h264_nal_parse::iter_avcc(sample, 4).collect::<h264_nal_parse::Result<Vec<_>>>()?;
+    let mut is_metadata = Vec::with_capacity(nals.len());
+    for nal in nals.iter() {
+        is_metadata.push(h264_sei::metadata(nal)?.is_some());
+    }
+
+    let sei_nal = h264_sei::metadata_nal(metadata);
+    let mut result = Vec::with_capacity(sample.len() + sei_nal.len() + 4);
+    let mut replaced = false;
+    if !is_metadata.contains(&true) {
+        push_avcc(&mut result, &sei_nal);
+        replaced = true;
+    }
+    for (nal, is_metadata) in nals.iter().zip(is_metadata) {
+        if !is_metadata {
+            push_avcc(&mut result, nal);
+        } else if !replaced {
+            push_avcc(&mut result, &sei_nal);
+            replaced = true;
+        }
+    }
+    Ok(result)
+}
+
+/// Read the H264 metadata of an MP4 file, if it has any.
+pub fn read_metadata<R: Read + Seek>(reader: R, size: u64) -> Result<Option<H264Metadata>> {
+    let mut mp4_reader = mp4::Mp4Reader::read_header(reader, size)?;
+    let track_id = h264_track_id(&mp4_reader)?;
+    let sample = read_first_sample(&mut mp4_reader, track_id)?;
+    find_metadata(&sample.bytes)
+}
+
+/// Copy an MP4 file of `size` bytes from `reader` to `writer`, with its H264
+/// metadata changed by `edit`.
+///
+/// `edit` is called with the existing metadata or, if there is none, new
+/// metadata with the creation time of the precision timestamp of the first
+/// frame. The samples, their timing and the H264 parameter sets are copied
+/// unchanged, except for the first sample, which gets the new metadata. The
+/// edited metadata is returned with the writer.
+pub fn rewrite_metadata<R, W, F>(
+    reader: R,
+    size: u64,
+    writer: W,
+    edit: F,
+) -> Result<(W, H264Metadata)>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+    F: FnOnce(&mut H264Metadata),
+{
+    let mut mp4_reader = mp4::Mp4Reader::read_header(reader, size)?;
+    let track_id = h264_track_id(&mp4_reader)?;
+
+    let first_sample = read_first_sample(&mut mp4_reader, track_id)?;
+    let mut metadata = match find_metadata(&first_sample.bytes)? {
+        Some(metadata) => metadata,
+        None => new_metadata(&first_sample.bytes)?,
+    };
+    edit(&mut metadata);
+
+    let mp4_config = mp4::Mp4Config {
+        major_brand: *mp4_reader.major_brand(),
+        minor_version: mp4_reader.minor_version(),
+        compatible_brands: mp4_reader.compatible_brands().to_vec(),
+        timescale: mp4_reader.timescale(),
+    };
+    let track = &mp4_reader.tracks()[&track_id];
+    let track_conf = mp4::TrackConfig {
+        track_type: mp4::TrackType::Video,
+        timescale: track.timescale(),
+        language: track.language().to_string(),
+        media_conf: mp4::MediaConfig::AvcConfig(mp4::AvcConfig {
+            width: track.width(),
+            height: track.height(),
+            seq_param_set: track.sequence_parameter_set()?.to_vec(),
+            pic_param_set: track.picture_parameter_set()?.to_vec(),
+        }),
+    };
+
+    let mut mp4_writer = mp4::Mp4Writer::write_start(writer, &mp4_config)?;
+    mp4_writer.add_track(&track_conf)?;
+    // The writer numbers tracks from 1.
+    const OUT_TRACK_ID: u32 = 1;
+
+    let first_sample = mp4::Mp4Sample {
+        bytes: replace_metadata(&first_sample.bytes, &metadata)?.into(),
+        ..first_sample
+    };
+    mp4_writer.write_sample(OUT_TRACK_ID, &first_sample)?;
+
+    let num_samples = mp4_reader.sample_count(track_id)?;
+    for sample_id in 2..=num_samples {
+        let sample = mp4_reader
+            .read_sample(track_id, sample_id)?
+            .ok_or(Error::MissingSample(sample_id))?;
+        mp4_writer.write_sample(OUT_TRACK_ID, &sample)?;
+    }
+    mp4_writer.write_end()?;
+    tracing::debug!("copied {num_samples} samples");
+    Ok((mp4_writer.into_writer(), metadata))
+}
+
+/// Change the time zone of the creation time of `metadata`, keeping the
+/// instant.
+///
+/// The time zone of the creation time is used for the timestamps of all
+/// frames when reading the file.
+pub fn set_timezone(metadata: &mut H264Metadata, offset: FixedOffset) {
+    metadata.creation_time = metadata.creation_time.with_timezone(&offset);
+}
+
+#[test]
+fn test_replace_metadata() {
+    let creation_time = chrono::DateTime::parse_from_rfc3339("2024-03-01T12:00:00+01:00").unwrap();
+    let timestamp = creation_time.with_timezone(&chrono::Utc);
+    let to_avcc = |nals: &[Vec<u8>]| {
+        let mut buf = Vec::new();
+        for nal in nals {
+            push_avcc(&mut buf, nal);
+        }
+        buf
+    };
+    let idr = vec![0x65, 0x88, 0x84];
+    let sample = to_avcc(&[h264_sei::precision_time_nal(timestamp), idr.clone()]);
+
+    // Without metadata, it is created from the precision timestamp and
+    // inserted first.
+    assert_eq!(find_metadata(&sample).unwrap(), None);
+    let mut metadata = new_metadata(&sample).unwrap();
+    assert_eq!(metadata.creation_time, timestamp.fixed_offset());
+    set_timezone(&mut metadata, *creation_time.offset());
+    assert_eq!(metadata.creation_time, creation_time);
+    assert_eq!(
+        metadata.creation_time.to_rfc3339(),
+        "2024-03-01T12:00:00+01:00"
+    );
+    let inserted = replace_metadata(&sample, &metadata).unwrap();
+    let nals: Vec<_> = h264_nal_parse::iter_avcc(&inserted, 4)
+        .map(|nal| nal.unwrap().to_vec())
+        .collect();
+    assert_eq!(nals.len(), 3);
+    assert_eq!(
+        h264_sei::metadata(&nals[0]).unwrap().as_ref(),
+        Some(&metadata)
+    );
+    assert_eq!(&nals[2], &idr);
+
+    // Existing metadata is replaced in place.
+    metadata.camera_name = Some("cam1".into());
+    let replaced = replace_metadata(&inserted, &metadata).unwrap();
+    assert_eq!(find_metadata(&replaced).unwrap(), Some(metadata));
+    assert_eq!(h264_nal_parse::iter_avcc(&replaced, 4).count(), 3);
+
+    // Without precision timestamp, the creation time is unknown.
+    assert!(matches!(
+        new_metadata(&to_avcc(&[idr])),
+        Err(Error::NoCreationTime)
+    ));
+}
@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use chrono::FixedOffset;
+use clap::{Args, Parser, Subcommand};
+use eyre::{self, Result, WrapErr};
+
+use mp4_metadata::H264Metadata;
+
+/// Show or edit the H264 metadata of MP4 files saved by Strand Camera.
+///
+/// Editing copies the file without re-encoding the video.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print the H264 metadata as JSON.
+    Show {
+        /// Input MP4 file.
+        input: PathBuf,
+    },
+    /// Set fields of the H264 metadata, inserting it if there is none.
+    Set(SetArgs),
+}
+
+#[derive(Debug, Args)]
+struct SetArgs {
+    /// Input MP4 file.
+    input: PathBuf,
+
+    /// Output MP4 file.
+    #[arg(short, long, required_unless_present = "in_place")]
+    output: Option<PathBuf>,
+
+    /// Replace the input file.
+    #[arg(long, conflicts_with = "output")]
+    in_place: bool,
+
+    /// Camera name.
+    #[arg(long)]
+    camera_name: Option<String>,
+
+    /// Time zone of the creation time, e.g. "+01:00", which sets the time zone
+    /// of all frame timestamps.
+    #[arg(long)]
+    timezone: Option<FixedOffset>,
+
+    /// Name of the experimenter.
+    #[arg(long)]
+    experimenter: Option<String>,
+
+    /// ID of the animal.
+    #[arg(long)]
+    animal_id: Option<String>,
+
+    /// Experimental condition.
+    #[arg(long)]
+    condition: Option<String>,
+
+    /// Free-text notes.
+    #[arg(long)]
+    notes: Option<String>,
+}
+
+impl SetArgs {
+    fn edit(&self, metadata: &mut H264Metadata) {
+        if let Some(camera_name) = &self.camera_name {
+            metadata.camera_name = Some(camera_name.clone());
+        }
+        if let Some(offset) = self.timezone {
+            mp4_metadata::set_timezone(metadata, offset);
+        }
+        let mut experiment = metadata.experiment.take().unwrap_or_default();
+        for (field, value) in [
+            (&mut experiment.experimenter, &self.experimenter),
+            (&mut experiment.animal_id, &self.animal_id),
+            (&mut experiment.condition, &self.condition),
+            (&mut experiment.notes, &self.notes),
+        ] {
+            if let Some(value) = value {
+                field.clone_from(value);
+            }
+        }
+        if !experiment.is_empty() {
+            metadata.experiment = Some(experiment);
+        }
+    }
+}
+
+fn show(input: &Path) -> Result<()> {
+    let rdr = std::fs::File::open(input)
+        .with_context(|| format!("Opening input file {}", input.display()))?;
+    let size = rdr.metadata()?.len();
+    match mp4_metadata::read_metadata(std::io::BufReader::new(rdr), size)? {
+        Some(metadata) => println!("{}", serde_json::to_string_pretty(&metadata)?),
+        None => println!("{} has no H264 metadata", input.display()),
+    }
+    Ok(())
+}
+
+fn set(args: &SetArgs) -> Result<()> {
+    let input = &args.input;
+    let rdr = std::fs::File::open(input)
+        .with_context(|| format!("Opening input file {}", input.display()))?;
+    let size = rdr.metadata()?.len();
+    let rdr = std::io::BufReader::new(rdr);
+
+    if args.in_place {
+        // Write to a temporary file in the same directory, then replace the
+        // input, so that the input is untouched if an error occurs.
+        let dirname = input.parent().unwrap_or_else(|| Path::new(""));
+        let dirname = if dirname.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dirname
+        };
+        let tmp = tempfile::NamedTempFile::new_in(dirname)?;
+        let wtr = std::io::BufWriter::new(tmp);
+        let (wtr, metadata) = mp4_metadata::rewrite_metadata(rdr, size, wtr, |m| args.edit(m))?;
+        let tmp = wtr.into_inner().map_err(|e| e.into_error())?;
+        tmp.persist(input)
+            .with_context(|| format!("Replacing {}", input.display()))?;
+        tracing::info!("Saved {}: {metadata:?}", input.display());
+    } else {
+        let output = args.output.as_ref().unwrap();
+        if output == input {
+            eyre::bail!("Output is the same as input. Use --in-place to replace the input.");
+        }
+        let out_fd = std::fs::File::create(output)
+            .with_context(|| format!("Creating output file {}", output.display()))?;
+        let wtr = std::io::BufWriter::new(out_fd);
+        let (wtr, metadata) = mp4_metadata::rewrite_metadata(rdr, size, wtr, |m| args.edit(m))?;
+        wtr.into_inner().map_err(|e| e.into_error())?;
+        tracing::info!("Saved {}: {metadata:?}", output.display());
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    env_tracing_logger::init();
+    let cli = Cli::parse();
+
+    match &cli.command {
+        Command::Show { input } => show(input),
+        Command::Set(args) => set(args),
+    }
+}
@@ -0,0 +1,99 @@
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use machine_vision_formats::pixel_format::Mono8;
+
+use ci2_remote_control::Mp4RecordingConfig;
+use frame_source::FrameDataSource;
+
+const NUM_FRAMES: i64 = 10;
+
+/// Save a short MP4 file, without H264 metadata, with Strand Camera
+/// precision timestamps.
+fn save_mp4(start: DateTime<Utc>) -> Vec<u8> {
+    let cfg = Mp4RecordingConfig {
+        codec: ci2_remote_control::Mp4Codec::H264LessAvc,
+        max_framerate: Default::default(),
+        h264_metadata: None,
+        color_encoding: Default::default(),
+    };
+
+    const W: u32 = 32;
+    const H: u32 = 16;
+
+    let mut mp4_buf = Vec::new();
+    {
+        let mut my_mp4_writer =
+            mp4_writer::Mp4Writer::new(std::io::Cursor::new(&mut mp4_buf), cfg, None).unwrap();
+        let image_data = vec![0u8; (W * H) as usize];
+        let frame =
+            machine_vision_formats::owned::OImage::<Mono8>::new(W, H, W as usize, image_data)
+                .unwrap();
+        for fno in 0..NUM_FRAMES {
+            let ts = start + Duration::try_milliseconds(fno * 10).unwrap();
+            my_mp4_writer.write(&frame, ts).unwrap();
+        }
+        my_mp4_writer.finish().unwrap();
+    }
+    mp4_buf
+}
+
+fn rewrite<F: FnOnce(&mut mp4_metadata::H264Metadata)>(input: &[u8], edit: F) -> Vec<u8> {
+    let rdr = std::io::Cursor::new(input);
+    let wtr = std::io::Cursor::new(Vec::new());
+    let (wtr, _metadata) =
+        mp4_metadata::rewrite_metadata(rdr, input.len() as u64, wtr, edit).unwrap();
+    wtr.into_inner()
+}
+
+fn read_metadata(buf: &[u8]) -> Option<mp4_metadata::H264Metadata> {
+    mp4_metadata::read_metadata(std::io::Cursor::new(buf), buf.len() as u64).unwrap()
+}
+
+#[test]
+fn test_insert_then_edit() {
+    let start = DateTime::from_timestamp(60 * 60, 0).unwrap();
+    let orig = save_mp4(start);
+    assert_eq!(read_metadata(&orig), None);
+
+    // Insert metadata.
+    let tz = FixedOffset::east_opt(2 * 60 * 60).unwrap();
+    let inserted = rewrite(&orig, |md| {
+        md.camera_name = Some("cam-a".into());
+        mp4_metadata::set_timezone(md, tz);
+    });
+    let md = read_metadata(&inserted).unwrap();
+    assert_eq!(md.camera_name.as_deref(), Some("cam-a"));
+    assert_eq!(md.creation_time, start);
+    assert_eq!(md.creation_time.offset(), &tz);
+
+    // Edit the inserted metadata, keeping the other fields.
+    let edited = rewrite(&inserted, |md| {
+        md.gamma = Some(1.0);
+    });
+    let md2 = read_metadata(&edited).unwrap();
+    assert_eq!(md2.camera_name.as_deref(), Some("cam-a"));
+    assert_eq!(md2.gamma, Some(1.0));
+    assert_eq!(md2.creation_time.offset(), &tz);
+
+    // The frames and their timestamps are unchanged.
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = tmpdir.path().join("edited.mp4");
+    std::fs::write(&path, &edited).unwrap();
+    let do_decode_h264 = false;
+    let mut src = frame_source::from_path(&path, do_decode_h264).unwrap();
+    assert_eq!(src.camera_name(), Some("cam-a"));
+    assert_eq!(src.frame0_time().unwrap(), start);
+    assert_eq!(src.frame0_time().unwrap().offset(), &tz);
+    let mut n_frames = 0;
+    for (fno, frame) in src.iter().enumerate() {
+        let frame = frame.unwrap();
+        let expected = Duration::try_milliseconds(fno as i64 * 10).unwrap();
+        match frame.timestamp() {
+            frame_source::Timestamp::Duration(pts) => {
+                assert_eq!(pts, expected.to_std().unwrap());
+            }
+            _ => panic!("expected duration"),
+        }
+        n_frames += 1;
+    }
+    assert_eq!(n_frames, NUM_FRAMES);
+}
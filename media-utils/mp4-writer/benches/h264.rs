@@ -0,0 +1,86 @@
+//! Benchmarks of saving already encoded H264 frames, which parses the NAL
+//! units of each frame.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use frame_source::H264EncodingVariant;
+use machine_vision_formats::{owned::OImage, pixel_format::Mono8};
+
+use ci2_remote_control::Mp4RecordingConfig;
+
+const W: u32 = 640;
+const H: u32 = 480;
+const NUM_FRAMES: usize = 30;
+
+/// Encode frames of a moving square.
+fn encoded_frames() -> Vec<Vec<Vec<u8>>> {
+    let mut encoder = less_avc_wrapper::WrappedLessEncoder::default();
+    (0..NUM_FRAMES)
+        .map(|i| {
+            let mut data = vec![0u8; (W * H) as usize];
+            for row in 100..140 {
+                for col in 0..40 {
+                    data[row * W as usize + 10 * i + col] = 255;
+                }
+            }
+            let frame = OImage::<Mono8>::new(W, H, W as usize, data).unwrap();
+            encoder.encode_to_nal_units(&frame).unwrap()
+        })
+        .collect()
+}
+
+fn to_annexb(nals: &[Vec<u8>]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for nal in nals.iter() {
+        buf.extend_from_slice(&[0, 0, 0, 1]);
+        buf.extend_from_slice(nal);
+    }
+    buf
+}
+
+fn write_all(frames: &[H264EncodingVariant]) {
+    let cfg = Mp4RecordingConfig {
+        codec: ci2_remote_control::Mp4Codec::H264LessAvc,
+        max_framerate: Default::default(),
+        h264_metadata: None,
+        color_encoding: Default::default(),
+    };
+    let fd = std::io::Cursor::new(Vec::new());
+    #[cfg(feature = "nv-encode")]
+    let mut writer = mp4_writer::Mp4Writer::new(fd, cfg, None).unwrap();
+    #[cfg(not(feature = "nv-encode"))]
+    let mut writer = mp4_writer::Mp4Writer::new(fd, cfg).unwrap();
+    let frame0_time = chrono::DateTime::from_timestamp(61, 0).unwrap();
+    for (i, data) in frames.iter().enumerate() {
+        let timestamp = frame0_time + chrono::TimeDelta::milliseconds(10 * i as i64);
+        writer
+            .write_h264_buf(data, W, H, timestamp, frame0_time, true)
+            .unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+fn bench_write_h264_buf(c: &mut Criterion) {
+    let encoded = encoded_frames();
+    let mut group = c.benchmark_group("write_h264_buf");
+    group.throughput(Throughput::Elements(NUM_FRAMES as u64));
+
+    let raw_ebsp: Vec<_> = encoded
+        .iter()
+        .map(|nals| H264EncodingVariant::RawEbsp(nals.clone()))
+        .collect();
+    group.bench_function(BenchmarkId::from_parameter("raw_ebsp"), |b| {
+        b.iter(|| write_all(&raw_ebsp))
+    });
+
+    let annexb: Vec<_> = encoded
+        .iter()
+        .map(|nals| H264EncodingVariant::AnnexB(to_annexb(nals)))
+        .collect();
+    group.bench_function(BenchmarkId::from_parameter("annexb"), |b| {
+        b.iter(|| write_all(&annexb))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_write_h264_buf);
+criterion_main!(benches);
@@ -100,6 +100,7 @@ fn main() -> eyre::Result<()> {
                     codec,
                     max_framerate: Default::default(),
                     h264_metadata: None,
+                    color_encoding: Default::default(),
                 };
 
                 #[cfg(feature = "nv-encode")]
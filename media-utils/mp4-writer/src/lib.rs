@@ -16,7 +16,7 @@
 #[cfg(feature = "nv-encode")]
 use std::rc::Rc;
 
-use ci2_remote_control::{H264Metadata, Mp4RecordingConfig, H264_METADATA_UUID};
+use ci2_remote_control::{H264Metadata, Mp4RecordingConfig};
 #[cfg(feature = "nv-encode")]
 use convert_image::convert_into;
 #[cfg(feature = "nv-encode")]
@@ -37,14 +37,31 @@ use nvenc::{InputBuffer, OutputBuffer, RateControlMode};
 
 use thiserror::Error;
 
-mod h264_annexb_split;
-use h264_annexb_split::h264_annexb_split;
+use h264_nal_parse::NalHeader;
+use y4m_writer::{ColorEncoding, ColorMatrix, ColorRange};
+
+mod vui;
 
 // The number of time units that pass in one second.
 // const MOVIE_TIMESCALE: u32 = 1_000_000;
 const MOVIE_TIMESCALE: u32 = 90_000;
 const TRACK_ID: u32 = 1;
 
+/// The color encoding, as used by `y4m_writer`, of `config`.
+fn color_encoding(config: ci2_remote_control::Mp4ColorEncoding) -> ColorEncoding {
+    use ci2_remote_control::{Mp4ColorMatrix, Mp4ColorRange};
+    ColorEncoding {
+        matrix: match config.matrix {
+            Mp4ColorMatrix::Bt601 => ColorMatrix::Bt601,
+            Mp4ColorMatrix::Bt709 => ColorMatrix::Bt709,
+        },
+        range: match config.range {
+            Mp4ColorRange::Full => ColorRange::Full,
+            Mp4ColorRange::Limited => ColorRange::Limited,
+        },
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("{source}")]
@@ -65,6 +82,8 @@ pub enum Error {
     RawH264CopyCannotEncodeFrame {},
     #[error("bad input data")]
     BadInputData {},
+    #[error("H264 parse error: {0}")]
+    H264ParseError(#[from] h264_nal_parse::Error),
     #[error("inconsistent state")]
     InconsistentState {},
     #[error("timestamp too large")]
@@ -224,7 +243,7 @@ where
         let mp4_sample_start_time = dur2raw(&pts.to_std().unwrap());
         let sample = match &data {
             frame_source::H264EncodingVariant::AnnexB(buf) => {
-                let nals = h264_annexb_split(&buf[..]).collect();
+                let nals = h264_annexb_split(&buf[..]);
 
                 EbspNals {
                     pts,
@@ -234,7 +253,7 @@ where
                 }
             }
             frame_source::H264EncodingVariant::Avcc(bufs) => {
-                let nal_iter = iter_avcc_bufs(bufs);
+                let nal_iter = h264_nal_parse::iter_avcc(bufs, 4);
                 let mut nals = Vec::new();
                 for nal_ebsp_bytes in nal_iter {
                     let nal_ebsp_bytes = nal_ebsp_bytes?;
@@ -491,7 +510,9 @@ where
                     },
                     ci2_remote_control::Mp4Codec::H264LessAvc => {
                         MyEncoder::LessH264(LessEncoderWrapper {
-                            encoder: Default::default(),
+                            encoder: less_avc_wrapper::WrappedLessEncoder::with_color_encoding(
+                                color_encoding(cfg.color_encoding),
+                            ),
                             h264_parser,
                             first_timestamp: timestamp,
                         })
@@ -543,6 +564,7 @@ where
                     .unwrap(),
                     trim_width: width,
                     trim_height: height,
+                    color_encoding: color_encoding(cfg.color_encoding),
                 };
 
                 let mut state = RecordingState {
@@ -663,7 +685,7 @@ where
 
 #[cfg(feature = "nv-encode")]
 fn nv_outbuf_to_sample(outbuf: dynlink_nvidia_encode::api::LockedOutputBuffer) -> EbspNals {
-    let nals = h264_annexb_split(outbuf.mem()).collect();
+    let nals = h264_annexb_split(outbuf.mem());
 
     EbspNals {
         pts: chrono::Duration::from_std(*outbuf.pts()).unwrap(),
@@ -712,7 +734,8 @@ where
             return Err(Error::RawH264CopyCannotEncodeFrame {});
         }
         (MyEncoder::LessH264(encoder), Some(state_inner)) => {
-            let nals = encoder.encoder.encode_to_nal_units(raw_frame)?;
+            let mut nals = encoder.encoder.encode_to_nal_units(raw_frame)?;
+            vui::signal_color_encoding(&mut nals, state_inner.color_encoding);
 
             let is_keyframe = true;
 
@@ -737,7 +760,12 @@ where
         (MyEncoder::OpenH264(encoder), Some(state_inner)) => {
             // todo: bitrate, keyframes, timestamp check and duration finding.
 
-            let y4m = y4m_writer::encode_y4m_frame(raw_frame, y4m::Colorspace::C420paldv, None)?;
+            let y4m = y4m_writer::encode_y4m_frame_with_encoding(
+                raw_frame,
+                y4m::Colorspace::C420paldv,
+                None,
+                state_inner.color_encoding,
+            )?;
 
             let encoded = encoder.encoder.encode(&YUVData::from(y4m)).unwrap();
 
@@ -748,7 +776,8 @@ where
             // todo: preallocate and keep buffer available by using write_vec
             let annex_b_data = encoded.to_vec();
 
-            let nals = h264_annexb_split(&annex_b_data).collect();
+            let mut nals = h264_annexb_split(&annex_b_data);
+            vui::signal_color_encoding(&mut nals, state_inner.color_encoding);
 
             let pts = timestamp - encoder.first_timestamp;
             let mp4_sample_start_time = dur2raw(&pts.to_std().unwrap());
@@ -855,6 +884,9 @@ struct RecordingStateInner {
     interval_for_limiting_fps: chrono::Duration,
     trim_width: u32,
     trim_height: u32,
+    /// The color encoding of the frames we encode, which is signaled in the
+    /// SPS.
+    color_encoding: ColorEncoding,
 }
 
 struct LessEncoderWrapper {
@@ -1081,17 +1113,11 @@ impl H264Parser {
         nals: EbspNals,
         mut precision_timestamp: Option<chrono::DateTime<chrono::Local>>,
     ) {
-        // We assume that sample contains one or more compete NAL units and
-        // starts with a NAL unit. Furthermore, we assume the start bytes can
-        // only be [0x00, 0x00, 0x00, 0x01]. This is not a real Annex B parser
-        // because of these assumptions but rather tuned to the output of
-        // less-avc, nvenc and openh264 as we use them.
+        // We assume that sample contains one or more complete NAL units.
 
         let mut all_avcc_nal_units: Vec<u8> = Vec::with_capacity(nals.annex_b_size() + 32);
 
         if !self.first_frame_done {
-            use less_avc::{nal_unit::*, sei::UserDataUnregistered};
-
             if let Some(h264_metadata) = &self.h264_metadata {
                 // Update the `creation_time` field of the metadata with the
                 // timestamp of the first frame.
@@ -1105,26 +1131,8 @@ impl H264Parser {
                     h264_metadata.clone()
                 };
 
-                let msg = serde_json::to_vec(&h264_metadata_updated).unwrap();
-
-                let payload = UserDataUnregistered::new(H264_METADATA_UUID, msg);
-
-                use less_avc::sei::SupplementalEnhancementInformation;
-                let rbsp_data =
-                    SupplementalEnhancementInformation::UserDataUnregistered(payload).to_rbsp();
-                let annex_b_data = NalUnit::new(
-                    less_avc::nal_unit::NalRefIdc::Zero,
-                    less_avc::nal_unit::NalUnitType::SupplementalEnhancementInformation,
-                    rbsp_data,
-                )
-                .to_annex_b_data();
-
-                const ANNEX_B_START: &[u8] = &[0x00, 0x00, 0x00, 0x01];
-                debug_assert_eq!(&annex_b_data[..4], ANNEX_B_START);
-
-                // Don't use the start code from Annex B but do use the raw EBSP
-                // NALU.
-                all_avcc_nal_units.extend(buf_to_avcc(&annex_b_data[4..]));
+                let sei_nal = h264_sei::metadata_nal(&h264_metadata_updated);
+                all_avcc_nal_units.extend(buf_to_avcc(&sei_nal));
             }
 
             self.first_frame_done = true;
@@ -1133,14 +1141,13 @@ impl H264Parser {
         // Split into Encapsulated Byte Sequence Payload (EBSP) message
         for ebsp_msg in nals.nals.iter() {
             let mut is_this_sps_or_pps = false;
-            if !ebsp_msg.is_empty() {
-                let code = ebsp_msg[0];
-                match code {
-                    0x67 => {
+            if let Ok(header) = NalHeader::parse(ebsp_msg) {
+                match header.nal_unit_type {
+                    h264_nal_parse::NAL_UNIT_TYPE_SPS => {
                         self.sps = Some(ebsp_msg[..].to_vec());
                         is_this_sps_or_pps = true;
                     }
-                    0x68 => {
+                    h264_nal_parse::NAL_UNIT_TYPE_PPS => {
                         self.pps = Some(ebsp_msg[..].to_vec());
                         is_this_sps_or_pps = true;
                     }
@@ -1151,21 +1158,8 @@ impl H264Parser {
                     // an SPS or PPS because we do not want to write our
                     // timestamp prior to SPS or PPS.
                     if let Some(ts) = precision_timestamp.take() {
-                        let mut rbsp_msg = [0u8; 32];
-                        rbsp_msg[0] = 0x06; // code 6 - SEI
-                        rbsp_msg[1] = 0x05; // header type: UserDataUnregistered
-                        rbsp_msg[2] = 28; // size
-                        timestamp_to_sei_payload(ts.into(), &mut rbsp_msg[3..31]);
-                        rbsp_msg[31] = 0x80; // rbsp_trailing_bits
-
-                        // Create new NAL unit for precision timestamp. In
-                        // theory we should ensure that this does not have start
-                        // code bytes and thus we should convert from RBSP to
-                        // EBSP. However, the standard ensures that there is no
-                        // need for encoding and thus the RBSP is the EBSP for
-                        // this case.
-                        let ebsp_msg = rbsp_msg;
-                        all_avcc_nal_units.extend(buf_to_avcc(&ebsp_msg[..]));
+                        let sei_nal = h264_sei::precision_time_nal(ts.into());
+                        all_avcc_nal_units.extend(buf_to_avcc(&sei_nal));
                     }
                 }
                 all_avcc_nal_units.extend(buf_to_avcc(ebsp_msg));
@@ -1355,28 +1349,6 @@ fn dur2raw(dur: &std::time::Duration) -> u64 {
     (dur.as_secs_f64() * MOVIE_TIMESCALE as f64).round() as u64
 }
 
-fn timestamp_to_sei_payload(timestamp: chrono::DateTime<chrono::Utc>, payload: &mut [u8]) {
-    assert_eq!(payload.len(), 28);
-    let precision_time_stamp = timestamp.timestamp_micros();
-
-    let precision_time_stamp_bytes: [u8; 8] = precision_time_stamp.to_be_bytes();
-
-    payload[0..16].copy_from_slice(b"MISPmicrosectime"); // uuid_iso_iec_11578
-
-    payload[16] = 0x1F; // Time Stamp Status byte from MISB Standard 0603
-
-    // The standard has 0xFF present after every two bytes as "Start Code
-    // Emulation Prevention". This means that the raw byte sequence is identical
-    // to the encoded byte sequence as there is nothing to encode.
-    payload[17..19].copy_from_slice(&precision_time_stamp_bytes[0..2]);
-    payload[19] = 0xff;
-    payload[20..22].copy_from_slice(&precision_time_stamp_bytes[2..4]);
-    payload[22] = 0xff;
-    payload[23..25].copy_from_slice(&precision_time_stamp_bytes[4..6]);
-    payload[25] = 0xff;
-    payload[26..28].copy_from_slice(&precision_time_stamp_bytes[6..8]);
-}
-
 #[cfg(feature = "openh264")]
 fn convert_openh264_rc_mode(
     orig: ci2_remote_control::OpenH264RateControlMode,
@@ -1392,80 +1364,45 @@ fn convert_openh264_rc_mode(
     }
 }
 
-struct NalAvccBufIter<'a> {
-    cur_buf: &'a [u8],
-}
-
-impl<'a> Iterator for NalAvccBufIter<'a> {
-    type Item = Result<&'a [u8]>;
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.cur_buf.is_empty() {
-            return None;
-        }
-        if self.cur_buf.len() < 4 {
-            return Some(Err(Error::BadInputData {}));
-        }
-        let bytes: [u8; 4] = self.cur_buf[0..4].try_into().unwrap();
-        let nal_unit_payload_len = usize::try_from(u32::from_be_bytes(bytes)).unwrap();
-        let nal_ebsp_bytes = &self.cur_buf[4..4 + nal_unit_payload_len];
-        if nal_ebsp_bytes.len() != nal_unit_payload_len {
-            return Some(Err(Error::BadInputData {}));
-        }
-        self.cur_buf = &self.cur_buf[4 + nal_unit_payload_len..];
-        Some(Ok(nal_ebsp_bytes))
-    }
-}
-
-fn iter_avcc_bufs(buf: &[u8]) -> NalAvccBufIter {
-    NalAvccBufIter { cur_buf: buf }
+/// Split Annex B data into Encapsulated Byte Sequence Payload (EBSP) NAL units.
+fn h264_annexb_split(buf: &[u8]) -> Vec<Vec<u8>> {
+    h264_nal_parse::split_annexb(buf)
+        .map(<[u8]>::to_vec)
+        .collect()
 }
 
 /// parse h264 NAL unit and return if it is an IDR frame
 fn parse_h264_is_idr_frame(data: &frame_source::H264EncodingVariant) -> Result<bool> {
-    use h264_reader::nal::{Nal, RefNal, UnitType};
-    use h264_reader::push::NalInterest;
+    use h264_nal_parse::{NAL_UNIT_TYPE_SLICE_IDR, NAL_UNIT_TYPE_SLICE_NON_IDR};
     let mut calls = Vec::new();
     match data {
         frame_source::H264EncodingVariant::Avcc(buf) => {
-            let nal_iter = iter_avcc_bufs(buf);
-            for nal_ebsp_bytes in nal_iter {
-                let nal_ebsp_bytes = nal_ebsp_bytes?;
-                let nal = RefNal::new(nal_ebsp_bytes, &[], true);
-                let nal_unit_type = nal.header().unwrap().nal_unit_type();
-                calls.push(nal_unit_type);
+            for nal_ebsp_bytes in h264_nal_parse::iter_avcc(buf, 4) {
+                calls.push(NalHeader::parse(nal_ebsp_bytes?)?.nal_unit_type);
             }
         }
         frame_source::H264EncodingVariant::AnnexB(buf) => {
-            use h264_reader::annexb::AnnexBReader;
-            let mut reader = AnnexBReader::accumulate(|nal: RefNal<'_>| {
-                let nal_unit_type = nal.header().unwrap().nal_unit_type();
-                calls.push(nal_unit_type);
-                match nal_unit_type {
-                    UnitType::SeqParameterSet => NalInterest::Buffer,
-                    _ => NalInterest::Ignore,
-                }
-            });
-            reader.push(&buf[..]);
+            for nal_ebsp_bytes in h264_nal_parse::split_annexb(buf) {
+                calls.push(NalHeader::parse(nal_ebsp_bytes)?.nal_unit_type);
+            }
         }
         frame_source::H264EncodingVariant::RawEbsp(nals) => {
             for nal_ebsp_bytes in nals.iter() {
-                let nal = RefNal::new(nal_ebsp_bytes, &[], true);
-                let nal_unit_type = nal.header().unwrap().nal_unit_type();
-                calls.push(nal_unit_type);
+                calls.push(NalHeader::parse(nal_ebsp_bytes)?.nal_unit_type);
             }
         }
     }
     let mut is_keyframe = None;
     for nal_unit_type in calls.into_iter() {
         match nal_unit_type {
-            UnitType::SliceLayerWithoutPartitioningIdr => {
+            NAL_UNIT_TYPE_SLICE_IDR => {
                 if is_keyframe.is_some() {
                     // cannot have multiple frames
                     return Err(Error::BadInputData {});
                 };
                 is_keyframe = Some(true);
             }
-            UnitType::SliceLayerWithoutPartitioningNonIdr => {
+            NAL_UNIT_TYPE_SLICE_NON_IDR => {
                 if is_keyframe.is_some() {
                     // cannot have multiple frames
                     return Err(Error::BadInputData {});
@@ -0,0 +1,179 @@
+//! Signaling of the color encoding in the H.264 sequence parameter set.
+//!
+//! Our encoders convert RGB to YUV with a [ColorEncoding], but the sequence
+//! parameter sets (SPS) they write have no video usability information (VUI).
+//! Without it, decoders guess the color encoding, typically as BT.601 with
+//! limited range, and so show full range video with the wrong contrast. Here,
+//! VUI parameters with the color encoding are added to the SPS.
+
+use h264_nal_parse::{ebsp_to_rbsp, rbsp_to_ebsp, NalHeader, NAL_UNIT_TYPE_SPS};
+use h264_reader::nal::{sps::SeqParameterSet, Nal, RefNal};
+use y4m_writer::{ColorEncoding, ColorMatrix, ColorRange};
+
+/// `video_format` of "unspecified video format" (Table E-2).
+const VIDEO_FORMAT_UNSPECIFIED: u32 = 5;
+
+/// Add VUI parameters signaling `encoding` to each SPS in `nals`.
+///
+/// Other NAL units, and SPS which already have VUI parameters, are unchanged.
+pub(crate) fn signal_color_encoding(nals: &mut [Vec<u8>], encoding: ColorEncoding) {
+    for nal in nals.iter_mut() {
+        let is_sps = NalHeader::parse(nal)
+            .map(|header| header.nal_unit_type == NAL_UNIT_TYPE_SPS)
+            .unwrap_or(false);
+        if !is_sps {
+            continue;
+        }
+        if let Some(sps) = add_vui(nal, encoding) {
+            *nal = sps;
+        }
+    }
+}
+
+/// Return the SPS `sps_ebsp` with VUI parameters, or `None` if it already has
+/// VUI parameters or cannot be parsed.
+fn add_vui(sps_ebsp: &[u8], encoding: ColorEncoding) -> Option<Vec<u8>> {
+    let parsed = SeqParameterSet::from_bits(RefNal::new(sps_ebsp, &[], true).rbsp_bits()).ok()?;
+    if parsed.vui_parameters.is_some() {
+        return None;
+    }
+
+    // Without VUI parameters, the SPS ends with `vui_parameters_present_flag`
+    // (zero) followed by the stop bit and zero bits for byte alignment.
+    let rbsp = ebsp_to_rbsp(sps_ebsp);
+    let last = rbsp.iter().rposition(|byte| *byte != 0)?;
+    let stop_bit = last * 8 + 7 - rbsp[last].trailing_zeros() as usize;
+    let vui_flag = stop_bit.checked_sub(1)?;
+    if get_bit(&rbsp, vui_flag) {
+        return None;
+    }
+
+    let (colour_primaries, transfer_characteristics, matrix_coefficients) = match encoding.matrix {
+        // SMPTE 170M, i.e. BT.601 525 line (Tables E-3, E-4 and E-5)
+        ColorMatrix::Bt601 => (6, 6, 6),
+        ColorMatrix::Bt709 => (1, 1, 1),
+    };
+    let video_full_range_flag = match encoding.range {
+        ColorRange::Full => 1,
+        ColorRange::Limited => 0,
+    };
+
+    let mut bits = BitWriter::default();
+    for i in 0..vui_flag {
+        bits.push(1, u32::from(get_bit(&rbsp, i)));
+    }
+    bits.push(1, 1); // vui_parameters_present_flag
+    bits.push(1, 0); // aspect_ratio_info_present_flag
+    bits.push(1, 0); // overscan_info_present_flag
+    bits.push(1, 1); // video_signal_type_present_flag
+    bits.push(3, VIDEO_FORMAT_UNSPECIFIED);
+    bits.push(1, video_full_range_flag);
+    bits.push(1, 1); // colour_description_present_flag
+    bits.push(8, colour_primaries);
+    bits.push(8, transfer_characteristics);
+    bits.push(8, matrix_coefficients);
+    bits.push(1, 0); // chroma_loc_info_present_flag
+    bits.push(1, 0); // timing_info_present_flag
+    bits.push(1, 0); // nal_hrd_parameters_present_flag
+    bits.push(1, 0); // vcl_hrd_parameters_present_flag
+    bits.push(1, 0); // pic_struct_present_flag
+    bits.push(1, 0); // bitstream_restriction_flag
+    bits.push(1, 1); // rbsp_stop_one_bit
+    Some(rbsp_to_ebsp(&bits.into_bytes()))
+}
+
+fn get_bit(buf: &[u8], i: usize) -> bool {
+    buf[i / 8] & (0x80 >> (i % 8)) != 0
+}
+
+/// Writes bits, most significant first.
+#[derive(Default)]
+struct BitWriter {
+    buf: Vec<u8>,
+    num_bits: usize,
+}
+
+impl BitWriter {
+    /// Append the lowest `n` bits of `value`.
+    fn push(&mut self, n: usize, value: u32) {
+        for i in (0..n).rev() {
+            if self.num_bits % 8 == 0 {
+                self.buf.push(0);
+            }
+            if (value >> i) & 1 != 0 {
+                *self.buf.last_mut().unwrap() |= 0x80 >> (self.num_bits % 8);
+            }
+            self.num_bits += 1;
+        }
+    }
+
+    /// The bits written, padded with zero bits to a whole number of bytes.
+    fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+#[test]
+fn test_signal_color_encoding() {
+    let parse =
+        |nal: &[u8]| SeqParameterSet::from_bits(RefNal::new(nal, &[], true).rbsp_bits()).unwrap();
+
+    let frame = basic_frame::DynamicFrame::new(
+        32,
+        16,
+        32,
+        vec![0; 32 * 16],
+        machine_vision_formats::PixFmt::Mono8,
+    );
+    let mut encoder = less_avc_wrapper::WrappedLessEncoder::default();
+    let mut nals = encoder.encode_dynamic_to_nal_units(&frame).unwrap();
+    let sps_idx = nals
+        .iter()
+        .position(|nal| NalHeader::parse(nal).unwrap().nal_unit_type == NAL_UNIT_TYPE_SPS)
+        .unwrap();
+    let orig = parse(&nals[sps_idx]);
+    assert!(orig.vui_parameters.is_none());
+    let others: Vec<_> = nals
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != sps_idx)
+        .map(|(_, nal)| nal.clone())
+        .collect();
+
+    let encoding = ColorEncoding {
+        matrix: ColorMatrix::Bt709,
+        range: ColorRange::Limited,
+    };
+    signal_color_encoding(&mut nals, encoding);
+    let sps = parse(&nals[sps_idx]);
+    assert_eq!(
+        sps.pixel_dimensions().unwrap(),
+        orig.pixel_dimensions().unwrap()
+    );
+    let video_signal_type = sps.vui_parameters.unwrap().video_signal_type.unwrap();
+    assert!(!video_signal_type.video_full_range_flag);
+    let colour = video_signal_type.colour_description.unwrap();
+    assert_eq!(colour.colour_primaries, 1);
+    assert_eq!(colour.transfer_characteristics, 1);
+    assert_eq!(colour.matrix_coefficients, 1);
+
+    // Only the SPS is changed, and only once.
+    let with_vui = nals.clone();
+    signal_color_encoding(&mut nals, ColorEncoding::default());
+    assert_eq!(nals, with_vui);
+    for (nal, orig) in nals
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != sps_idx)
+        .map(|(_, nal)| nal)
+        .zip(others.iter())
+    {
+        assert_eq!(nal, orig);
+    }
+
+    // Later frames have no SPS.
+    let mut nals = encoder.encode_dynamic_to_nal_units(&frame).unwrap();
+    let orig_nals = nals.clone();
+    signal_color_encoding(&mut nals, ColorEncoding::default());
+    assert_eq!(nals, orig_nals);
+}
@@ -0,0 +1,48 @@
+use eyre::Result;
+use h264_reader::nal::{sps::SeqParameterSet, Nal, RefNal};
+
+use ci2_remote_control::{Mp4ColorEncoding, Mp4ColorMatrix, Mp4ColorRange, Mp4RecordingConfig};
+
+#[test]
+fn test_color_encoding_in_sps() -> Result<()> {
+    let start = chrono::DateTime::from_timestamp(61, 0).unwrap();
+    let frame = basic_frame::DynamicFrame::new(
+        32,
+        16,
+        32 * 3,
+        vec![128; 32 * 16 * 3],
+        machine_vision_formats::PixFmt::RGB8,
+    );
+
+    let cfg = Mp4RecordingConfig {
+        codec: ci2_remote_control::Mp4Codec::H264LessAvc,
+        max_framerate: Default::default(),
+        h264_metadata: None,
+        color_encoding: Mp4ColorEncoding {
+            matrix: Mp4ColorMatrix::Bt709,
+            range: Mp4ColorRange::Limited,
+        },
+    };
+
+    let mut buf = std::io::Cursor::new(Vec::new());
+    {
+        #[cfg(feature = "nv-encode")]
+        let mut my_mp4_writer = mp4_writer::Mp4Writer::new(&mut buf, cfg, None)?;
+        #[cfg(not(feature = "nv-encode"))]
+        let mut my_mp4_writer = mp4_writer::Mp4Writer::new(&mut buf, cfg)?;
+        my_mp4_writer.write_dynamic(&frame, start)?;
+        my_mp4_writer.finish()?;
+    }
+
+    let size = buf.get_ref().len() as u64;
+    buf.set_position(0);
+    let mp4_reader = mp4::Mp4Reader::read_header(buf, size)?;
+    let track = mp4_reader.tracks().values().next().unwrap();
+    let sps = track.sequence_parameter_set()?;
+    let sps = SeqParameterSet::from_bits(RefNal::new(sps, &[], true).rbsp_bits()).unwrap();
+    let video_signal_type = sps.vui_parameters.unwrap().video_signal_type.unwrap();
+    assert!(!video_signal_type.video_full_range_flag);
+    let colour = video_signal_type.colour_description.unwrap();
+    assert_eq!(colour.matrix_coefficients, 1);
+    Ok(())
+}
@@ -100,6 +100,7 @@ fn test_save_then_read_with_ffmpeg() -> Result<()> {
             codec,
             max_framerate: Default::default(),
             h264_metadata: None,
+            color_encoding: Default::default(),
         };
 
         let frame = generate_image(pixfmt_str, *width, *height)?;
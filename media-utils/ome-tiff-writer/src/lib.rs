@@ -0,0 +1,232 @@
+//! Save images as [OME-TIFF](https://docs.openmicroscopy.org/ome-model/latest/ome-tiff/)
+//! files.
+//!
+//! OME-TIFF is a TIFF file with OME-XML metadata in the `ImageDescription`
+//! tag of the first image. This allows microscopy software such as Fiji (with
+//! Bio-Formats) and OMERO to read the acquisition conditions, such as exposure
+//! time and gain, together with the pixel data.
+//!
+//! Mono8 and Bayer images are saved as 8 bit grayscale, RGB8 images as 8 bit
+//! RGB, and Mono32f images (e.g. a background model) as 32 bit floating point
+//! grayscale. Other pixel formats are converted to RGB8 first.
+
+use std::io::{Seek, Write};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use machine_vision_formats::{
+    iter::HasRowChunksExact,
+    pixel_format::{self, PixFmt},
+    ImageData, PixelFormat, Stride,
+};
+use tiff::{
+    encoder::{colortype, TiffEncoder},
+    tags::Tag,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("TIFF error: {0}")]
+    Tiff(#[from] tiff::TiffError),
+    #[error("convert-image error: {0}")]
+    ConvertImage(#[from] convert_image::Error),
+    #[error("unknown pixel format: {0}")]
+    UnknownPixelFormat(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Acquisition conditions saved with an image.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OmeMetadata {
+    /// Name of the camera, saved as the image name and detector model.
+    pub camera_name: Option<String>,
+    /// Time at which the image was acquired.
+    pub acquisition_time: Option<DateTime<Utc>>,
+    /// Exposure time in microseconds.
+    pub exposure_time_usec: Option<f64>,
+    /// Gain in dB.
+    pub gain_db: Option<f64>,
+}
+
+/// Write `frame` as an OME-TIFF file with metadata `meta` into `wtr`.
+pub fn write_ome_tiff<W, FMT>(
+    wtr: W,
+    frame: &dyn HasRowChunksExact<FMT>,
+    meta: &OmeMetadata,
+) -> Result<()>
+where
+    W: Write + Seek,
+    FMT: PixelFormat,
+{
+    let pixfmt = pixel_format::pixfmt::<FMT>()
+        .map_err(|estr| Error::UnknownPixelFormat(estr.to_string()))?;
+    let (w, h) = (frame.width(), frame.height());
+    let mut encoder = TiffEncoder::new(wtr)?;
+    match pixfmt {
+        PixFmt::Mono8
+        | PixFmt::BayerRG8
+        | PixFmt::BayerBG8
+        | PixFmt::BayerGB8
+        | PixFmt::BayerGR8 => {
+            let xml = ome_xml(w, h, 1, "uint8", meta);
+            let mut image = encoder.new_image::<colortype::Gray8>(w, h)?;
+            image
+                .encoder()
+                .write_tag(Tag::ImageDescription, xml.as_str())?;
+            image.write_data(&packed_rows(frame, 1))?;
+        }
+        PixFmt::RGB8 => {
+            let xml = ome_xml(w, h, 3, "uint8", meta);
+            let mut image = encoder.new_image::<colortype::RGB8>(w, h)?;
+            image
+                .encoder()
+                .write_tag(Tag::ImageDescription, xml.as_str())?;
+            image.write_data(&packed_rows(frame, 3))?;
+        }
+        PixFmt::Mono32f => {
+            let xml = ome_xml(w, h, 1, "float", meta);
+            let data: Vec<f32> = packed_rows(frame, 4)
+                .chunks_exact(4)
+                .map(|b| f32::from_ne_bytes(b.try_into().unwrap()))
+                .collect();
+            let mut image = encoder.new_image::<colortype::Gray32Float>(w, h)?;
+            image
+                .encoder()
+                .write_tag(Tag::ImageDescription, xml.as_str())?;
+            image.write_data(&data)?;
+        }
+        _ => {
+            let rgb = convert_image::convert_ref::<_, pixel_format::RGB8>(frame)?;
+            let xml = ome_xml(w, h, 3, "uint8", meta);
+            let mut image = encoder.new_image::<colortype::RGB8>(w, h)?;
+            image
+                .encoder()
+                .write_tag(Tag::ImageDescription, xml.as_str())?;
+            image.write_data(&packed_rows(&rgb, 3))?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy the image data into a new buffer without row padding.
+fn packed_rows<FMT>(frame: &dyn HasRowChunksExact<FMT>, bytes_per_pixel: usize) -> Vec<u8> {
+    let row_len = frame.width() as usize * bytes_per_pixel;
+    let mut data = Vec::with_capacity(row_len * frame.height() as usize);
+    for row in frame
+        .image_data()
+        .chunks(frame.stride())
+        .take(frame.height() as usize)
+    {
+        data.extend_from_slice(&row[..row_len]);
+    }
+    data
+}
+
+/// Build the OME-XML description of a single plane image.
+fn ome_xml(width: u32, height: u32, samples: u32, pixel_type: &str, meta: &OmeMetadata) -> String {
+    let name = meta.camera_name.as_deref().map(escape).unwrap_or_default();
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        r#"<OME xmlns="http://www.openmicroscopy.org/Schemas/OME/2016-06" "#,
+        r#"xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" "#,
+        r#"xsi:schemaLocation="http://www.openmicroscopy.org/Schemas/OME/2016-06 "#,
+        r#"http://www.openmicroscopy.org/Schemas/OME/2016-06/ome.xsd" "#,
+        r#"Creator="strand-braid">"#,
+        r#"<Instrument ID="Instrument:0">"#,
+    ));
+    xml.push_str(&format!(
+        r#"<Detector ID="Detector:0" Model="{name}"/></Instrument>"#
+    ));
+    xml.push_str(&format!(r#"<Image ID="Image:0" Name="{name}">"#));
+    if let Some(t) = &meta.acquisition_time {
+        xml.push_str(&format!(
+            "<AcquisitionDate>{}</AcquisitionDate>",
+            t.to_rfc3339_opts(SecondsFormat::Micros, true)
+        ));
+    }
+    xml.push_str(r#"<InstrumentRef ID="Instrument:0"/>"#);
+    xml.push_str(&format!(
+        r#"<Pixels ID="Pixels:0" DimensionOrder="XYCZT" Type="{pixel_type}" "#
+    ));
+    xml.push_str(&format!(
+        r#"SizeX="{width}" SizeY="{height}" SizeC="{samples}" SizeZ="1" SizeT="1" "#
+    ));
+    xml.push_str(r#"Interleaved="true">"#);
+    xml.push_str(&format!(
+        r#"<Channel ID="Channel:0:0" SamplesPerPixel="{samples}">"#
+    ));
+    xml.push_str(r#"<DetectorSettings ID="Detector:0""#);
+    if let Some(gain) = meta.gain_db {
+        xml.push_str(&format!(r#" Gain="{gain}""#));
+    }
+    xml.push_str("/></Channel>");
+    xml.push_str(r#"<TiffData IFD="0" PlaneCount="1"/>"#);
+    xml.push_str(r#"<Plane TheC="0" TheZ="0" TheT="0""#);
+    if let Some(exposure) = meta.exposure_time_usec {
+        // Seconds is the default unit. This keeps the description ASCII.
+        xml.push_str(&format!(r#" ExposureTime="{}""#, exposure / 1e6));
+    }
+    xml.push_str("/></Pixels></Image></OME>");
+    xml
+}
+
+/// Escape a string for use in an XML attribute.
+///
+/// Non-ASCII characters are written as character references because TIFF
+/// ASCII fields may only contain 7 bit characters.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c if c.is_ascii() && !c.is_ascii_control() => out.push(c),
+            c => out.push_str(&format!("&#x{:X};", c as u32)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use machine_vision_formats::{image_ref::ImageRef, pixel_format::Mono8};
+    use tiff::decoder::{Decoder, DecodingResult};
+
+    #[test]
+    fn roundtrip_mono8_with_metadata() {
+        let (w, h, stride) = (5u32, 3u32, 8usize);
+        let buf: Vec<u8> = (0..stride as u8 * h as u8).collect();
+        let frame = ImageRef::<Mono8>::new(w, h, stride, &buf).unwrap();
+        let meta = OmeMetadata {
+            camera_name: Some("Basler-123 <cam1>".into()),
+            acquisition_time: Some(DateTime::from_timestamp(1_700_000_000, 0).unwrap()),
+            exposure_time_usec: Some(5000.0),
+            gain_db: Some(2.5),
+        };
+        let mut cursor = std::io::Cursor::new(Vec::new());
+        write_ome_tiff(&mut cursor, &frame, &meta).unwrap();
+
+        cursor.set_position(0);
+        let mut decoder = Decoder::new(cursor).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (w, h));
+        let description = decoder.get_tag_ascii_string(Tag::ImageDescription).unwrap();
+        assert!(description.contains(r#"Name="Basler-123 &lt;cam1&gt;""#));
+        assert!(description.contains("<AcquisitionDate>2023-11-14T22:13:20.000000Z"));
+        assert!(description.contains(r#"ExposureTime="0.005""#));
+        assert!(description.contains(r#"Gain="2.5""#));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => {
+                let expected: Vec<u8> = buf
+                    .chunks(stride)
+                    .flat_map(|row| row[..w as usize].to_vec())
+                    .collect();
+                assert_eq!(data, expected);
+            }
+            _ => panic!("unexpected sample type"),
+        }
+    }
+}
@@ -16,8 +16,8 @@ use ci2_remote_control::H264Metadata;
 
 use basic_frame::{match_all_dynamic_fmts, DynamicFrame};
 use frame_source::{
-    fmf_source, mp4_source, pv_tiff_stack, strand_cam_mkv_source, FrameData, FrameDataSource,
-    ImageData,
+    fmf_source, mp4_source, pv_tiff_stack, strand_cam_mkv_source, ufmf_source, FrameData,
+    FrameDataSource, ImageData,
 };
 use tiff_decoder::HdrConfig;
 
@@ -36,6 +36,9 @@ const N_FRAMES_TO_COMPUTE_FPS: usize = 100;
 /// Metadata from Strand Camera is preserved when saving to MP4, but lost when
 /// saving to a PNG sequence.
 ///
+/// For a UFMF (`.ufmf`) input, full frames are reconstructed from the saved
+/// background model with the saved foreground regions drawn on top.
+///
 /// Large deviations of the data from the nominal framerate result in an error.
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
@@ -492,6 +495,12 @@ pub fn run_cli(cli: Cli) -> Result<()> {
                 src = Box::new(fmf_video);
                 default_encoder = Encoder::LessAvc;
             }
+            Some("ufmf") => {
+                let ufmf_video = ufmf_source::from_path(&input_path)?;
+                tracing::debug!("  UFMF video, reconstructing full frames");
+                src = Box::new(ufmf_video);
+                default_encoder = Encoder::LessAvc;
+            }
             _ => {
                 anyhow::bail!(
                     "input {} is a file, but not a supported extension.",
@@ -781,6 +790,7 @@ pub fn run_cli(cli: Cli) -> Result<()> {
             codec,
             max_framerate: Default::default(),
             h264_metadata,
+            color_encoding: Default::default(),
         };
 
         let out_fd = std::fs::File::create(&output_fname)
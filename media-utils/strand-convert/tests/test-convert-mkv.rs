@@ -75,6 +75,7 @@ fn get_metadata<P: AsRef<Path>>(fname: P) -> Result<H264Metadata> {
                 camera_name,
                 gamma,
                 creation_time,
+                experiment: None,
             })
         }
         Some("mp4") => {
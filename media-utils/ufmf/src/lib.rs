@@ -14,6 +14,9 @@ pub type UFMFResult<M> = std::result::Result<M, UFMFError>;
 
 mod save_indices;
 
+pub mod reader;
+pub use crate::reader::{Reconstructor, UFMFReader};
+
 #[derive(Debug, thiserror::Error)]
 pub enum UFMFError {
     #[error("unimplemented pixel_format {0}")]
@@ -25,11 +28,30 @@ pub enum UFMFError {
     #[error("the pixel format changed")]
     FormatChanged,
 
+    #[error("not a UFMF file")]
+    NotUfmf,
+    #[error("Unimplemented UFMF file version {0}. Only UFMF v3 files supported.")]
+    UnimplementedVersion(u32),
+    #[error("unknown format {0}")]
+    UnknownFormat(String),
+    #[error("unknown keyframe dtype {0}")]
+    UnknownDtype(u8),
+    #[error("unknown chunk type {0}")]
+    UnknownChunk(u8),
+    #[error("saved region extends beyond the image")]
+    PatchOutOfBounds,
+
     #[error("{source}")]
     Io {
         #[from]
         source: std::io::Error,
     },
+    #[error("From {path}: {source}")]
+    IoPath {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
     #[error("{0}")]
     Cast(#[from] cast::Error),
 }
@@ -714,4 +736,78 @@ mod tests {
         ];
         assert_eq!(&buf[0..], expected);
     }
+
+    #[test]
+    fn test_reconstruct() {
+        use formats::pixel_format::Mono8;
+
+        let (arr, dt) = arange(0, 123.456);
+        let w = 10;
+        let h = 10;
+        let pixel_format = formats::pixel_format::PixFmt::Mono8;
+        let f = std::io::Cursor::new(Vec::new());
+        let mut writer = UFMFWriter::new(f, w, h, pixel_format, Some((&arr, dt))).unwrap();
+
+        let (arr2, dt2) = arange(100, 42.42);
+        let point_data = vec![RectFromCenter::from_xy_wh(4, 4, 4, 4)];
+        writer.add_frame(&arr2, dt2, &point_data).unwrap();
+
+        let (mean, _) = arange_float(0.1, 123.456);
+        let mean = mean.as_basic::<formats::pixel_format::Mono32f>().unwrap();
+        writer.add_keyframe(b"mean", &mean, dt).unwrap();
+        writer.add_frame(&arr2, dt2, &[]).unwrap();
+
+        let buf = writer.close().unwrap().into_inner();
+
+        let reader = UFMFReader::new(std::io::Cursor::new(buf)).unwrap();
+        assert_eq!((reader.width(), reader.height()), (10, 10));
+        assert_eq!(reader.format(), pixel_format);
+
+        let frames: Vec<_> = reader.reconstruct().map(|r| r.unwrap()).collect();
+        assert_eq!(frames.len(), 2);
+
+        // The first frame is `frame0` with the region (2,2)-(5,5) from `arr2`.
+        let (im, ts) = &frames[0];
+        assert_eq!(*ts, dt2);
+        let im = im.clone().as_basic::<Mono8>().unwrap();
+        for y in 0..10 {
+            for x in 0..10 {
+                let i = y * 10 + x;
+                let inside = (2..6).contains(&x) && (2..6).contains(&y);
+                let expected = if inside { 100 + i } else { i };
+                assert_eq!(im.image_data[i], expected as u8);
+            }
+        }
+
+        // The second frame is the rounded `mean` keyframe.
+        let im = frames[1].0.clone().as_basic::<Mono8>().unwrap();
+        let expected: Vec<u8> = (0..100).map(|i| i as u8).collect();
+        assert_eq!(im.image_data, expected);
+    }
+
+    #[test]
+    fn test_reconstruct_empty_patch() {
+        use formats::pixel_format::Mono8;
+
+        let (arr, dt) = arange(0, 123.456);
+        let pixel_format = formats::pixel_format::PixFmt::Mono8;
+        let f = std::io::Cursor::new(Vec::new());
+        let mut writer = UFMFWriter::new(f, 10, 10, pixel_format, Some((&arr, dt))).unwrap();
+
+        // Regions of zero width or height have no data.
+        let (arr2, dt2) = arange(100, 42.42);
+        let point_data = vec![
+            RectFromCenter::from_xy_wh(4, 4, 0, 4),
+            RectFromCenter::from_xy_wh(4, 4, 4, 0),
+        ];
+        writer.add_frame(&arr2, dt2, &point_data).unwrap();
+        let buf = writer.close().unwrap().into_inner();
+
+        let reader = UFMFReader::new(std::io::Cursor::new(buf)).unwrap();
+        let frames: Vec<_> = reader.reconstruct().map(|r| r.unwrap()).collect();
+        assert_eq!(frames.len(), 1);
+        let im = frames[0].0.clone().as_basic::<Mono8>().unwrap();
+        let expected: Vec<u8> = (0..100).map(|i| i as u8).collect();
+        assert_eq!(im.image_data, expected);
+    }
 }
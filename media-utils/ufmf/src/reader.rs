@@ -0,0 +1,342 @@
+use std::{
+    fs::File,
+    io::{BufReader, ErrorKind, Read},
+    path::Path,
+};
+
+use basic_frame::DynamicFrame;
+use byteorder::{LittleEndian, ReadBytesExt};
+use chrono::{DateTime, Utc};
+use machine_vision_formats::pixel_format::PixFmt;
+
+use crate::{UFMFError, UFMFResult, FRAME_CHUNK, INDEX_DICT_CHUNK, KEYFRAME_CHUNK};
+
+fn get_pixel_format(coding: &[u8]) -> UFMFResult<PixFmt> {
+    use PixFmt::*;
+    let r = match coding {
+        b"MONO8" => Mono8,
+        b"RAW8:RGGB" => BayerRG8,
+        b"RAW8:GBRG" => BayerGB8,
+        b"RAW8:GRBG" => BayerGR8,
+        b"RAW8:BGGR" => BayerBG8,
+        b"YUV422" => YUV422,
+        b"RGB8" => RGB8,
+        _ => {
+            return Err(UFMFError::UnknownFormat(
+                String::from_utf8_lossy(coding).into_owned(),
+            ));
+        }
+    };
+    Ok(r)
+}
+
+/// The image data of a keyframe.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyframeData {
+    /// Data with the pixel format of the file (e.g. the `frame0` keyframe).
+    U8(Vec<u8>),
+    /// Single channel floating point data (e.g. the `mean` keyframe).
+    F32(Vec<f32>),
+}
+
+/// A full image saved in the file, such as the background model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframe {
+    /// The keyframe type, e.g. `frame0`, `mean` or `sumsq`.
+    pub keyframe_type: Vec<u8>,
+    pub timestamp: DateTime<Utc>,
+    pub width: u16,
+    pub height: u16,
+    pub data: KeyframeData,
+}
+
+/// A rectangular region of a frame saved in the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patch {
+    /// x lower left of region
+    pub x0: u16,
+    /// y lower left of region
+    pub y0: u16,
+    /// width of region
+    pub w: u16,
+    /// height of region
+    pub h: u16,
+    /// Image data of the region, packed without padding between rows.
+    pub data: Vec<u8>,
+}
+
+/// A frame, consisting of the regions saved around detected foreground.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FramePatches {
+    pub timestamp: DateTime<Utc>,
+    pub patches: Vec<Patch>,
+}
+
+/// A chunk of data read from a UFMF file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Chunk {
+    Keyframe(Keyframe),
+    Frame(FramePatches),
+}
+
+/// Reads UFMF (micro fly movie format) v3 files.
+///
+/// Chunks are read sequentially, so files which were not closed properly
+/// (and thus lack an index) can still be read.
+pub struct UFMFReader<R: Read> {
+    rdr: R,
+    max_width: u16,
+    max_height: u16,
+    pixel_format: PixFmt,
+    bytes_per_pixel: usize,
+    done: bool,
+}
+
+impl UFMFReader<BufReader<File>> {
+    /// Open the UFMF file at `path`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> UFMFResult<Self> {
+        let f = File::open(path.as_ref()).map_err(|source| UFMFError::IoPath {
+            path: path.as_ref().display().to_string(),
+            source,
+        })?;
+        Self::new(BufReader::new(f))
+    }
+}
+
+impl<R: Read> UFMFReader<R> {
+    /// Read the header of the UFMF file from `rdr`.
+    pub fn new(mut rdr: R) -> UFMFResult<Self> {
+        let mut magic = [0u8; 4];
+        rdr.read_exact(&mut magic)?;
+        if &magic != b"ufmf" {
+            return Err(UFMFError::NotUfmf);
+        }
+        let version = rdr.read_u32::<LittleEndian>()?;
+        if version != 3 {
+            return Err(UFMFError::UnimplementedVersion(version));
+        }
+        let _index_loc = rdr.read_u64::<LittleEndian>()?;
+        let max_width = rdr.read_u16::<LittleEndian>()?;
+        let max_height = rdr.read_u16::<LittleEndian>()?;
+        let coding_len = rdr.read_u8()?;
+        let mut coding = vec![0u8; coding_len as usize];
+        rdr.read_exact(&mut coding)?;
+        let pixel_format = get_pixel_format(&coding)?;
+        let bytes_per_pixel = (pixel_format.bits_per_pixel() / 8) as usize;
+        Ok(Self {
+            rdr,
+            max_width,
+            max_height,
+            pixel_format,
+            bytes_per_pixel,
+            done: false,
+        })
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.max_width.into()
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.max_height.into()
+    }
+
+    #[inline]
+    pub fn format(&self) -> PixFmt {
+        self.pixel_format
+    }
+
+    /// Turn this reader into an iterator over full frames.
+    ///
+    /// See [Reconstructor].
+    pub fn reconstruct(self) -> Reconstructor<R> {
+        Reconstructor::new(self)
+    }
+
+    fn read_timestamp(&mut self) -> UFMFResult<DateTime<Utc>> {
+        let timestamp = self.rdr.read_f64::<LittleEndian>()?;
+        Ok(datetime_conversion::f64_to_datetime(timestamp))
+    }
+
+    fn read_keyframe(&mut self) -> UFMFResult<Keyframe> {
+        let type_len = self.rdr.read_u8()?;
+        let mut keyframe_type = vec![0u8; type_len as usize];
+        self.rdr.read_exact(&mut keyframe_type)?;
+        let dtype = self.rdr.read_u8()?;
+        let width = self.rdr.read_u16::<LittleEndian>()?;
+        let height = self.rdr.read_u16::<LittleEndian>()?;
+        let timestamp = self.read_timestamp()?;
+        let n_pixels = width as usize * height as usize;
+        let data = match dtype {
+            b'B' => {
+                let mut buf = vec![0u8; n_pixels * self.bytes_per_pixel];
+                self.rdr.read_exact(&mut buf)?;
+                KeyframeData::U8(buf)
+            }
+            b'f' => {
+                let mut buf = vec![0f32; n_pixels];
+                self.rdr.read_f32_into::<LittleEndian>(&mut buf)?;
+                KeyframeData::F32(buf)
+            }
+            dtype => {
+                return Err(UFMFError::UnknownDtype(dtype));
+            }
+        };
+        Ok(Keyframe {
+            keyframe_type,
+            timestamp,
+            width,
+            height,
+            data,
+        })
+    }
+
+    fn read_frame(&mut self) -> UFMFResult<FramePatches> {
+        let timestamp = self.read_timestamp()?;
+        let n_pts = self.rdr.read_u16::<LittleEndian>()?;
+        let mut patches = Vec::with_capacity(n_pts as usize);
+        for _ in 0..n_pts {
+            let x0 = self.rdr.read_u16::<LittleEndian>()?;
+            let y0 = self.rdr.read_u16::<LittleEndian>()?;
+            let w = self.rdr.read_u16::<LittleEndian>()?;
+            let h = self.rdr.read_u16::<LittleEndian>()?;
+            let mut data = vec![0u8; w as usize * h as usize * self.bytes_per_pixel];
+            self.rdr.read_exact(&mut data)?;
+            patches.push(Patch { x0, y0, w, h, data });
+        }
+        Ok(FramePatches { timestamp, patches })
+    }
+
+    fn next_chunk(&mut self) -> UFMFResult<Option<Chunk>> {
+        let chunk_id = match self.rdr.read_u8() {
+            Ok(chunk_id) => chunk_id,
+            // A file which was not closed ends without an index.
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match chunk_id {
+            KEYFRAME_CHUNK => Ok(Some(Chunk::Keyframe(self.read_keyframe()?))),
+            FRAME_CHUNK => Ok(Some(Chunk::Frame(self.read_frame()?))),
+            INDEX_DICT_CHUNK => Ok(None),
+            chunk_id => Err(UFMFError::UnknownChunk(chunk_id)),
+        }
+    }
+}
+
+impl<R: Read> Iterator for UFMFReader<R> {
+    type Item = UFMFResult<Chunk>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_chunk().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+/// Iterator over full frames reconstructed from a UFMF file.
+///
+/// Each frame is the background with the saved regions drawn over it. The
+/// background is the most recent `mean` keyframe (rounded to 8 bits) if the
+/// pixel format has one byte per pixel. Otherwise, or before the first `mean`
+/// keyframe, the `frame0` keyframe is used. Frames before any keyframe have a
+/// black background.
+pub struct Reconstructor<R: Read> {
+    reader: UFMFReader<R>,
+    background: Vec<u8>,
+    have_mean: bool,
+}
+
+impl<R: Read> Reconstructor<R> {
+    fn new(reader: UFMFReader<R>) -> Self {
+        let size = reader.max_width as usize * reader.max_height as usize * reader.bytes_per_pixel;
+        Self {
+            reader,
+            background: vec![0; size],
+            have_mean: false,
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.reader.width()
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.reader.height()
+    }
+
+    #[inline]
+    pub fn format(&self) -> PixFmt {
+        self.reader.format()
+    }
+
+    fn update_background(&mut self, keyframe: Keyframe) {
+        if keyframe.width != self.reader.max_width || keyframe.height != self.reader.max_height {
+            return;
+        }
+        match (keyframe.keyframe_type.as_slice(), keyframe.data) {
+            (b"mean", KeyframeData::F32(mean)) if self.reader.bytes_per_pixel == 1 => {
+                self.background = mean
+                    .iter()
+                    .map(|x| x.round().clamp(0.0, 255.0) as u8)
+                    .collect();
+                self.have_mean = true;
+            }
+            (b"frame0", KeyframeData::U8(frame0)) if !self.have_mean => {
+                self.background = frame0;
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&self, frame: &FramePatches) -> UFMFResult<DynamicFrame> {
+        let bpp = self.reader.bytes_per_pixel;
+        let stride = self.reader.max_width as usize * bpp;
+        let mut image_data = self.background.clone();
+        for patch in frame.patches.iter() {
+            if patch.x0 as usize + patch.w as usize > self.reader.max_width as usize
+                || patch.y0 as usize + patch.h as usize > self.reader.max_height as usize
+            {
+                return Err(UFMFError::PatchOutOfBounds);
+            }
+            let row_bytes = patch.w as usize * bpp;
+            if row_bytes == 0 {
+                // Nothing to draw for an empty patch.
+                continue;
+            }
+            for (i, src_row) in patch.data.chunks_exact(row_bytes).enumerate() {
+                let start = (patch.y0 as usize + i) * stride + patch.x0 as usize * bpp;
+                image_data[start..start + row_bytes].copy_from_slice(src_row);
+            }
+        }
+        Ok(DynamicFrame::new(
+            self.width(),
+            self.height(),
+            cast::u32(stride)?,
+            image_data,
+            self.reader.pixel_format,
+        ))
+    }
+}
+
+impl<R: Read> Iterator for Reconstructor<R> {
+    type Item = UFMFResult<(DynamicFrame, DateTime<Utc>)>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.reader.next()? {
+                Ok(Chunk::Keyframe(keyframe)) => self.update_background(keyframe),
+                Ok(Chunk::Frame(frame)) => {
+                    return Some(self.render(&frame).map(|im| (im, frame.timestamp)));
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
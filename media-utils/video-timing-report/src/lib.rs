@@ -0,0 +1,440 @@
+//! Audit of the precision timestamps of videos saved by Strand Camera.
+//!
+//! [read_precision_times] extracts the `MISPmicrosectime` precision timestamp
+//! of each frame of an MP4 file. [analyze] finds the statistics of the
+//! intervals between frames and the gaps (dropped frames), duplicate
+//! timestamps and backward clock steps. [compare] measures the offset and
+//! drift between the timestamps of two cameras recording simultaneously.
+
+use std::io::{Read, Seek};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("MP4 error: {0}")]
+    Mp4(#[from] mp4::Error),
+    #[error("H264 parse error: {0}")]
+    H264NalParse(#[from] h264_nal_parse::Error),
+    #[error("H264 SEI error: {0}")]
+    H264Sei(#[from] h264_sei::Error),
+    #[error("no H264 track found")]
+    NoH264Track,
+    #[error("MP4 sample {0} not found")]
+    MissingSample(u32),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Read the precision timestamp of each frame of the H264 track of an MP4
+/// file of `size` bytes.
+///
+/// Frames without a precision timestamp give `None`.
+pub fn read_precision_times<R: Read + Seek>(
+    reader: R,
+    size: u64,
+) -> Result<Vec<Option<DateTime<Utc>>>> {
+    let mut mp4_reader = mp4::Mp4Reader::read_header(reader, size)?;
+    let mut track_id = None;
+    for (id, track) in mp4_reader.tracks().iter() {
+        if track.media_type()? == mp4::MediaType::H264 {
+            track_id = Some(*id);
+            break;
+        }
+    }
+    let track_id = track_id.ok_or(Error::NoH264Track)?;
+
+    let num_samples = mp4_reader.sample_count(track_id)?;
+    let mut result = Vec::with_capacity(num_samples as usize);
+    // mp4 uses 1 based indexing
+    for sample_id in 1..=num_samples {
+        let sample = mp4_reader
+            .read_sample(track_id, sample_id)?
+            .ok_or(Error::MissingSample(sample_id))?;
+        let mut precision_time = None;
+        for nal in h264_nal_parse::iter_avcc(&sample.bytes, 4) {
+            precision_time = h264_sei::precision_time(nal?)?;
+            if precision_time.is_some() {
+                break;
+            }
+        }
+        result.push(precision_time);
+    }
+    Ok(result)
+}
+
+fn micros_to_ms(micros: i64) -> f64 {
+    micros as f64 / 1000.0
+}
+
+/// Statistics of the intervals between frames, in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IntervalStats {
+    pub count: usize,
+    pub mean_ms: f64,
+    pub std_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+}
+
+impl IntervalStats {
+    fn new(intervals_ms: &[f64]) -> Option<Self> {
+        if intervals_ms.is_empty() {
+            return None;
+        }
+        let n = intervals_ms.len() as f64;
+        let mean_ms = intervals_ms.iter().sum::<f64>() / n;
+        let var = intervals_ms
+            .iter()
+            .map(|x| (x - mean_ms).powi(2))
+            .sum::<f64>()
+            / n;
+        Some(Self {
+            count: intervals_ms.len(),
+            mean_ms,
+            std_ms: var.sqrt(),
+            min_ms: intervals_ms.iter().copied().fold(f64::INFINITY, f64::min),
+            max_ms: intervals_ms
+                .iter()
+                .copied()
+                .fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+}
+
+/// An interval between frames longer than expected, e.g. due to dropped
+/// frames.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Gap {
+    /// The index of the frame after the gap.
+    pub frame_idx: usize,
+    pub interval_ms: f64,
+    /// The estimated number of frames missing in the gap.
+    pub missing_frames: u64,
+}
+
+/// A frame with a timestamp earlier than that of the frame before it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClockStep {
+    pub frame_idx: usize,
+    /// The (negative) difference to the timestamp of the previous frame.
+    pub step_ms: f64,
+}
+
+/// The timing of the frames of a single video.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimingReport {
+    pub num_frames: usize,
+    /// The indices of frames without a precision timestamp.
+    pub missing_timestamps: Vec<usize>,
+    pub first_timestamp: Option<DateTime<Utc>>,
+    pub last_timestamp: Option<DateTime<Utc>>,
+    /// The median interval between frames, taken as the nominal frame
+    /// interval.
+    pub median_interval_ms: Option<f64>,
+    /// The statistics of the intervals between frames, excluding gaps,
+    /// duplicates and clock steps. The standard deviation is the jitter.
+    pub regular_intervals: Option<IntervalStats>,
+    pub gaps: Vec<Gap>,
+    /// The indices of frames with the same timestamp as the frame before.
+    pub duplicates: Vec<usize>,
+    pub backward_steps: Vec<ClockStep>,
+}
+
+impl TimingReport {
+    /// The frame rate corresponding to the median interval.
+    pub fn fps(&self) -> Option<f64> {
+        self.median_interval_ms.map(|dt| 1000.0 / dt)
+    }
+
+    /// Whether no gaps, duplicates, backward steps or missing timestamps were
+    /// found.
+    pub fn is_ok(&self) -> bool {
+        self.missing_timestamps.is_empty()
+            && self.gaps.is_empty()
+            && self.duplicates.is_empty()
+            && self.backward_steps.is_empty()
+    }
+}
+
+/// The median of non-empty values.
+fn median(values: &mut [i64]) -> f64 {
+    values.sort_unstable();
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) as f64 / 2.0
+    } else {
+        values[mid] as f64
+    }
+}
+
+/// Analyze the timestamps of the frames of a video.
+///
+/// Intervals are taken between successive frames with timestamps. An
+/// interval longer than `gap_factor` times the median interval is a gap.
+pub fn analyze(timestamps: &[Option<DateTime<Utc>>], gap_factor: f64) -> TimingReport {
+    let missing_timestamps = timestamps
+        .iter()
+        .enumerate()
+        .filter_map(|(i, t)| t.is_none().then_some(i))
+        .collect();
+    let stamped: Vec<(usize, i64)> = timestamps
+        .iter()
+        .enumerate()
+        .filter_map(|(i, t)| t.map(|t| (i, t.timestamp_micros())))
+        .collect();
+    let intervals: Vec<(usize, i64)> = stamped
+        .windows(2)
+        .map(|w| (w[1].0, w[1].1 - w[0].1))
+        .collect();
+
+    let mut positive: Vec<i64> = intervals
+        .iter()
+        .map(|(_, dt)| *dt)
+        .filter(|dt| *dt > 0)
+        .collect();
+    let median_interval = if positive.is_empty() {
+        None
+    } else {
+        Some(median(&mut positive))
+    };
+
+    let mut regular = Vec::new();
+    let mut gaps = Vec::new();
+    let mut duplicates = Vec::new();
+    let mut backward_steps = Vec::new();
+    for &(frame_idx, dt) in intervals.iter() {
+        if dt < 0 {
+            backward_steps.push(ClockStep {
+                frame_idx,
+                step_ms: micros_to_ms(dt),
+            });
+        } else if dt == 0 {
+            duplicates.push(frame_idx);
+        } else {
+            let nominal = median_interval.unwrap();
+            if dt as f64 > gap_factor * nominal {
+                let missing_frames = ((dt as f64 / nominal).round() as u64).max(2) - 1;
+                gaps.push(Gap {
+                    frame_idx,
+                    interval_ms: micros_to_ms(dt),
+                    missing_frames,
+                });
+            } else {
+                regular.push(micros_to_ms(dt));
+            }
+        }
+    }
+
+    TimingReport {
+        num_frames: timestamps.len(),
+        missing_timestamps,
+        first_timestamp: timestamps.iter().find_map(|t| *t),
+        last_timestamp: timestamps.iter().rev().find_map(|t| *t),
+        median_interval_ms: median_interval.map(|dt| dt / 1000.0),
+        regular_intervals: IntervalStats::new(&regular),
+        gaps,
+        duplicates,
+        backward_steps,
+    }
+}
+
+/// The offset between the timestamps of a video and those of a reference
+/// video of the same scene.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OffsetReport {
+    /// The number of frames of the reference matched to a frame of the other
+    /// video.
+    pub num_matched: usize,
+    /// The offset, other minus reference, of the first matched frame.
+    pub first_offset_ms: f64,
+    /// The offset of the last matched frame.
+    pub last_offset_ms: f64,
+    pub mean_offset_ms: f64,
+    pub max_abs_offset_ms: f64,
+    /// The change of the offset over time from a least squares fit, in parts
+    /// per million (microseconds per second). `None` with fewer than two
+    /// matches.
+    pub drift_ppm: Option<f64>,
+}
+
+/// Compare the timestamps of `other` with those of `reference`.
+///
+/// Each frame of `reference` is matched to the frame of `other` with the
+/// nearest timestamp, if that is within `max_match_ms`. Returns `None` if no
+/// frames match.
+pub fn compare(
+    reference: &[Option<DateTime<Utc>>],
+    other: &[Option<DateTime<Utc>>],
+    max_match_ms: f64,
+) -> Option<OffsetReport> {
+    let mut other: Vec<i64> = other
+        .iter()
+        .flatten()
+        .map(|t| t.timestamp_micros())
+        .collect();
+    other.sort_unstable();
+    if other.is_empty() {
+        return None;
+    }
+    let max_match = max_match_ms * 1000.0;
+
+    // Pairs of reference time and offset, in microseconds.
+    let mut matches = Vec::new();
+    for t in reference.iter().flatten().map(|t| t.timestamp_micros()) {
+        let idx = other.partition_point(|&x| x < t);
+        let nearest = [idx.checked_sub(1), Some(idx)]
+            .into_iter()
+            .flatten()
+            .filter_map(|i| other.get(i))
+            .map(|x| x - t)
+            .min_by_key(|d| d.abs())
+            .unwrap();
+        if (nearest.abs() as f64) <= max_match {
+            matches.push((t, nearest));
+        }
+    }
+    let (&(t0, first_offset), &(_, last_offset)) = (matches.first()?, matches.last()?);
+
+    let n = matches.len() as f64;
+    let xs: Vec<f64> = matches.iter().map(|(t, _)| (t - t0) as f64 / 1e6).collect();
+    let ys: Vec<f64> = matches.iter().map(|(_, d)| *d as f64).collect();
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+    let sxx: f64 = xs.iter().map(|x| (x - mean_x).powi(2)).sum();
+    let sxy: f64 = xs
+        .iter()
+        .zip(ys.iter())
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let drift_ppm = (matches.len() >= 2 && sxx > 0.0).then_some(sxy / sxx);
+
+    Some(OffsetReport {
+        num_matched: matches.len(),
+        first_offset_ms: micros_to_ms(first_offset),
+        last_offset_ms: micros_to_ms(last_offset),
+        mean_offset_ms: mean_y / 1000.0,
+        max_abs_offset_ms: micros_to_ms(matches.iter().map(|(_, d)| d.abs()).max().unwrap()),
+        drift_ppm,
+    })
+}
+
+#[cfg(test)]
+fn stamps(micros: &[Option<i64>]) -> Vec<Option<DateTime<Utc>>> {
+    micros
+        .iter()
+        .map(|t| t.map(|t| DateTime::from_timestamp_micros(1_700_000_000_000_000 + t).unwrap()))
+        .collect()
+}
+
+#[test]
+fn test_analyze() {
+    let timestamps = stamps(&[
+        Some(0),
+        Some(10_000),
+        Some(20_000),
+        // Two dropped frames.
+        Some(50_000),
+        None,
+        Some(70_000),
+        // Duplicate.
+        Some(70_000),
+        Some(80_000),
+        // Clock steps back.
+        Some(75_000),
+        Some(85_100),
+        Some(95_100),
+    ]);
+    let report = analyze(&timestamps, 1.5);
+    assert_eq!(report.num_frames, 11);
+    assert_eq!(report.missing_timestamps, vec![4]);
+    assert_eq!(report.median_interval_ms, Some(10.0));
+    assert_eq!(report.fps(), Some(100.0));
+    assert_eq!(
+        report.gaps,
+        vec![
+            Gap {
+                frame_idx: 3,
+                interval_ms: 30.0,
+                missing_frames: 2,
+            },
+            Gap {
+                frame_idx: 5,
+                interval_ms: 20.0,
+                missing_frames: 1,
+            },
+        ]
+    );
+    assert_eq!(report.duplicates, vec![6]);
+    assert_eq!(
+        report.backward_steps,
+        vec![ClockStep {
+            frame_idx: 8,
+            step_ms: -5.0,
+        }]
+    );
+    let regular = report.regular_intervals.unwrap();
+    assert_eq!(regular.count, 5);
+    assert!((regular.min_ms - 10.0).abs() < 1e-9);
+    assert!((regular.max_ms - 10.1).abs() < 1e-9);
+    assert!(!report.is_ok());
+
+    let report = analyze(&stamps(&[Some(0), Some(10_000), Some(20_000)]), 1.5);
+    assert!(report.is_ok());
+    assert_eq!(report.regular_intervals.unwrap().std_ms, 0.0);
+}
+
+#[test]
+fn test_compare() {
+    // The other camera starts 0.5 ms later and its clock runs 100 ppm fast.
+    let reference: Vec<_> = (0..1000).map(|i| Some(i * 10_000)).collect();
+    let other: Vec<_> = (0..1000).map(|i| Some(500 + i * 10_001)).collect();
+    let report = compare(&stamps(&reference), &stamps(&other), 5.0).unwrap();
+    assert_eq!(report.num_matched, 1000);
+    assert_eq!(report.first_offset_ms, 0.5);
+    assert!((report.last_offset_ms - 1.499).abs() < 1e-9);
+    assert!((report.drift_ppm.unwrap() - 100.0).abs() < 0.1);
+
+    // No frames within the matching distance.
+    let far: Vec<_> = (0..10).map(|i| Some(1_000_000_000 + i * 10_000)).collect();
+    assert_eq!(compare(&stamps(&reference), &stamps(&far), 5.0), None);
+}
+
+#[test]
+fn test_read_precision_times() {
+    use machine_vision_formats::pixel_format::Mono8;
+
+    let start = DateTime::from_timestamp(60 * 60, 0).unwrap();
+    let cfg = ci2_remote_control::Mp4RecordingConfig {
+        codec: ci2_remote_control::Mp4Codec::H264LessAvc,
+        max_framerate: Default::default(),
+        h264_metadata: None,
+        color_encoding: Default::default(),
+    };
+    const W: u32 = 32;
+    const H: u32 = 16;
+    let mut mp4_buf = Vec::new();
+    let mut expected = Vec::new();
+    {
+        let mut my_mp4_writer =
+            mp4_writer::Mp4Writer::new(std::io::Cursor::new(&mut mp4_buf), cfg, None).unwrap();
+        let image_data = vec![0u8; (W * H) as usize];
+        let frame =
+            machine_vision_formats::owned::OImage::<Mono8>::new(W, H, W as usize, image_data)
+                .unwrap();
+        for fno in [0, 1, 2, 4, 5] {
+            let ts = start + chrono::Duration::try_milliseconds(fno * 10).unwrap();
+            expected.push(Some(ts));
+            my_mp4_writer.write(&frame, ts).unwrap();
+        }
+        my_mp4_writer.finish().unwrap();
+    }
+    let size = mp4_buf.len() as u64;
+    let actual = read_precision_times(std::io::Cursor::new(mp4_buf), size).unwrap();
+    assert_eq!(actual, expected);
+
+    let report = analyze(&actual, 1.5);
+    assert_eq!(report.gaps.len(), 1);
+    assert_eq!(report.gaps[0].frame_idx, 3);
+}
@@ -0,0 +1,188 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use eyre::{self, Result, WrapErr};
+use serde::Serialize;
+
+use video_timing_report::{OffsetReport, TimingReport};
+
+/// Report the timing of the frames of MP4 files saved by Strand Camera.
+///
+/// The `MISPmicrosectime` precision timestamp of each frame is read. For each
+/// file, the statistics of the intervals between frames are printed together
+/// with any gaps (e.g. dropped frames), duplicate timestamps and backward
+/// steps of the clock. With several files, which should be recordings of
+/// simultaneously triggered cameras, the offset and drift of the timestamps
+/// of each file relative to the first are printed.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Input MP4 files.
+    #[arg(required = true, num_args = 1..)]
+    inputs: Vec<PathBuf>,
+
+    /// Intervals longer than this factor times the median interval are gaps.
+    #[arg(long, default_value_t = 1.5)]
+    gap_factor: f64,
+
+    /// Maximum difference, in milliseconds, between the timestamps of frames
+    /// of different cameras to be matched. Defaults to half the median frame
+    /// interval of the first input.
+    #[arg(long)]
+    max_match_ms: Option<f64>,
+
+    /// Maximum number of events of each kind (gaps, duplicates, ...) listed
+    /// per file.
+    #[arg(long, default_value_t = 10)]
+    max_events: usize,
+
+    /// Print the full report as JSON.
+    #[arg(long)]
+    json: bool,
+
+    /// Exit with an error if any problem is found.
+    #[arg(long)]
+    strict: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct FileReport {
+    path: PathBuf,
+    timing: TimingReport,
+    /// The offset relative to the first input.
+    offset: Option<OffsetReport>,
+}
+
+/// Print at most `max` items, then the number of remaining items.
+fn print_events<T>(name: &str, items: &[T], max: usize, display: impl Fn(&T) -> String) {
+    println!("  {name}: {}", items.len());
+    for item in items.iter().take(max) {
+        println!("    {}", display(item));
+    }
+    if items.len() > max {
+        println!("    ... and {} more", items.len() - max);
+    }
+}
+
+fn print_summary(report: &FileReport, max_events: usize) {
+    let timing = &report.timing;
+    println!("Path: {}", report.path.display());
+    println!(
+        "  Frames: {}, first: {}, last: {}",
+        timing.num_frames,
+        timing
+            .first_timestamp
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_else(|| "(none)".into()),
+        timing
+            .last_timestamp
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_else(|| "(none)".into()),
+    );
+    if let (Some(median), Some(fps)) = (timing.median_interval_ms, timing.fps()) {
+        println!("  Median frame interval: {median:.3} ms ({fps:.2} fps)");
+    }
+    if let Some(s) = &timing.regular_intervals {
+        println!(
+            "  Regular intervals: {}, mean {:.3} ms, std (jitter) {:.3} ms, min {:.3} ms, \
+            max {:.3} ms",
+            s.count, s.mean_ms, s.std_ms, s.min_ms, s.max_ms
+        );
+    }
+    print_events(
+        "Frames without precision timestamp",
+        &timing.missing_timestamps,
+        max_events,
+        |i| format!("frame {i}"),
+    );
+    print_events("Gaps", &timing.gaps, max_events, |g| {
+        format!(
+            "frame {}: {:.3} ms, ~{} missing frame(s)",
+            g.frame_idx, g.interval_ms, g.missing_frames
+        )
+    });
+    print_events(
+        "Duplicate timestamps",
+        &timing.duplicates,
+        max_events,
+        |i| format!("frame {i}"),
+    );
+    print_events(
+        "Backward clock steps",
+        &timing.backward_steps,
+        max_events,
+        |s| format!("frame {}: {:.3} ms", s.frame_idx, s.step_ms),
+    );
+    if let Some(o) = &report.offset {
+        let drift = o
+            .drift_ppm
+            .map(|d| format!("{d:.1} ppm"))
+            .unwrap_or_else(|| "unknown".into());
+        println!(
+            "  Offset to first input: {} matched frames, first {:.3} ms, last {:.3} ms, \
+            mean {:.3} ms, max |offset| {:.3} ms, drift {drift}",
+            o.num_matched,
+            o.first_offset_ms,
+            o.last_offset_ms,
+            o.mean_offset_ms,
+            o.max_abs_offset_ms,
+        );
+    }
+}
+
+fn main() -> Result<()> {
+    env_tracing_logger::init();
+    let cli = Cli::parse();
+
+    let mut all_timestamps = Vec::new();
+    for input in cli.inputs.iter() {
+        let rdr = std::fs::File::open(input)
+            .with_context(|| format!("Opening input file {}", input.display()))?;
+        let size = rdr.metadata()?.len();
+        let timestamps =
+            video_timing_report::read_precision_times(std::io::BufReader::new(rdr), size)
+                .with_context(|| format!("Reading {}", input.display()))?;
+        all_timestamps.push(timestamps);
+    }
+
+    let mut reports: Vec<FileReport> = Vec::new();
+    for (i, (input, timestamps)) in cli.inputs.iter().zip(all_timestamps.iter()).enumerate() {
+        let timing = video_timing_report::analyze(timestamps, cli.gap_factor);
+        let offset = if i == 0 {
+            None
+        } else {
+            let reference = &reports[0];
+            let max_match_ms = cli
+                .max_match_ms
+                .or_else(|| reference.timing.median_interval_ms.map(|dt| dt / 2.0));
+            max_match_ms.and_then(|max_match_ms| {
+                video_timing_report::compare(&all_timestamps[0], timestamps, max_match_ms)
+            })
+        };
+        reports.push(FileReport {
+            path: input.clone(),
+            timing,
+            offset,
+        });
+    }
+
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for report in reports.iter() {
+            print_summary(report, cli.max_events);
+        }
+    }
+
+    if cli.strict {
+        let bad: Vec<_> = reports
+            .iter()
+            .filter(|r| !r.timing.is_ok())
+            .map(|r| r.path.display().to_string())
+            .collect();
+        if !bad.is_empty() {
+            eyre::bail!("Timing problems found in: {}", bad.join(", "));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,902 @@
+//! Row kernels for conversion between packed RGB8 and full swing BT.601 YUV.
+//!
+//! The public functions in this module dispatch to SSE2 or AVX2 (on x86_64,
+//! detected at runtime) or NEON (on aarch64) implementations and fall back to
+//! the scalar code for any remaining pixels and on other architectures. All
+//! implementations give bit-identical results.
+//!
+//! The RGB to YUV conversion uses coefficients with 8 fractional bits. For
+//! BT.601 full range this is the same integer arithmetic as `convert_image`.
+//! The YUV to RGB conversion uses coefficients with 7 fractional bits so that
+//! all intermediate values fit in 16 bit lanes. Only BT.601 full range is
+//! vectorized for YUV to RGB; other encodings use [scalar::YuvToRgb].
+
+use crate::{ColorEncoding, ColorMatrix, ColorRange};
+
+/// Fixed point coefficients, with 8 fractional bits, for RGB to YUV.
+///
+/// The luma coefficients sum to at most 256 and the positive and negative
+/// chroma coefficients each sum to at most 128 in magnitude, so all
+/// intermediate values fit in 16 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RgbToYuv {
+    pub(crate) y: [i16; 3],
+    pub(crate) u: [i16; 3],
+    pub(crate) v: [i16; 3],
+    /// Added to the scaled luma value.
+    pub(crate) y_offset: i16,
+}
+
+impl RgbToYuv {
+    pub(crate) const BT601_FULL: Self = Self {
+        y: [77, 150, 29],
+        u: [-43, -84, 127],
+        v: [127, -106, -21],
+        y_offset: 0,
+    };
+    pub(crate) const BT601_LIMITED: Self = Self {
+        y: [66, 129, 25],
+        u: [-38, -74, 112],
+        v: [112, -94, -18],
+        y_offset: 16,
+    };
+    pub(crate) const BT709_FULL: Self = Self {
+        y: [54, 183, 19],
+        u: [-29, -98, 127],
+        v: [127, -115, -12],
+        y_offset: 0,
+    };
+    pub(crate) const BT709_LIMITED: Self = Self {
+        y: [47, 157, 16],
+        u: [-26, -86, 112],
+        v: [112, -102, -10],
+        y_offset: 16,
+    };
+}
+
+impl From<ColorEncoding> for RgbToYuv {
+    fn from(encoding: ColorEncoding) -> Self {
+        match (encoding.matrix, encoding.range) {
+            (ColorMatrix::Bt601, ColorRange::Full) => Self::BT601_FULL,
+            (ColorMatrix::Bt601, ColorRange::Limited) => Self::BT601_LIMITED,
+            (ColorMatrix::Bt709, ColorRange::Full) => Self::BT709_FULL,
+            (ColorMatrix::Bt709, ColorRange::Limited) => Self::BT709_LIMITED,
+        }
+    }
+}
+
+/// Convert a row of packed RGB8 pixels into planar Y, U and V rows.
+///
+/// The number of pixels converted is `y.len()`.
+pub(crate) fn rgb8_to_yuv444_row(
+    k: &RgbToYuv,
+    rgb: &[u8],
+    y: &mut [u8],
+    u: &mut [u8],
+    v: &mut [u8],
+) {
+    let n = y.len();
+    let (rgb, u, v) = (&rgb[..n * 3], &mut u[..n], &mut v[..n]);
+    let done = simd_rgb8_to_yuv444_row(k, rgb, y, u, v);
+    scalar::rgb8_to_yuv444_row(
+        k,
+        &rgb[done * 3..],
+        &mut y[done..],
+        &mut u[done..],
+        &mut v[done..],
+    );
+}
+
+/// Convert planar BT.601 full range Y, U and V rows into a row of packed RGB8
+/// pixels.
+///
+/// The number of pixels converted is `y.len()`.
+pub(crate) fn yuv444_to_rgb8_row(y: &[u8], u: &[u8], v: &[u8], rgb: &mut [u8]) {
+    let n = y.len();
+    let (u, v, rgb) = (&u[..n], &v[..n], &mut rgb[..n * 3]);
+    let done = simd_yuv444_to_rgb8_row(y, u, v, rgb);
+    scalar::yuv444_to_rgb8_row(&y[done..], &u[done..], &v[done..], &mut rgb[done * 3..]);
+}
+
+/// Returns the number of pixels converted.
+#[cfg(target_arch = "x86_64")]
+fn simd_rgb8_to_yuv444_row(
+    k: &RgbToYuv,
+    rgb: &[u8],
+    y: &mut [u8],
+    u: &mut [u8],
+    v: &mut [u8],
+) -> usize {
+    if is_x86_feature_detected!("avx2") {
+        // Safety: we checked that AVX2 is available.
+        unsafe { simd_avx2::rgb8_to_yuv444_row(k, rgb, y, u, v) }
+    } else {
+        // Safety: SSE2 is always available on x86_64.
+        unsafe { simd_sse2::rgb8_to_yuv444_row(k, rgb, y, u, v) }
+    }
+}
+
+/// Returns the number of pixels converted.
+#[cfg(target_arch = "aarch64")]
+fn simd_rgb8_to_yuv444_row(
+    k: &RgbToYuv,
+    rgb: &[u8],
+    y: &mut [u8],
+    u: &mut [u8],
+    v: &mut [u8],
+) -> usize {
+    // Safety: NEON is always available on aarch64.
+    unsafe { simd_neon::rgb8_to_yuv444_row(k, rgb, y, u, v) }
+}
+
+/// Returns the number of pixels converted.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn simd_rgb8_to_yuv444_row(
+    _k: &RgbToYuv,
+    _rgb: &[u8],
+    _y: &mut [u8],
+    _u: &mut [u8],
+    _v: &mut [u8],
+) -> usize {
+    0
+}
+
+/// Returns the number of pixels converted.
+#[cfg(target_arch = "x86_64")]
+fn simd_yuv444_to_rgb8_row(y: &[u8], u: &[u8], v: &[u8], rgb: &mut [u8]) -> usize {
+    if is_x86_feature_detected!("avx2") {
+        // Safety: we checked that AVX2 is available.
+        unsafe { simd_avx2::yuv444_to_rgb8_row(y, u, v, rgb) }
+    } else {
+        // Safety: SSE2 is always available on x86_64.
+        unsafe { simd_sse2::yuv444_to_rgb8_row(y, u, v, rgb) }
+    }
+}
+
+/// Returns the number of pixels converted.
+#[cfg(target_arch = "aarch64")]
+fn simd_yuv444_to_rgb8_row(y: &[u8], u: &[u8], v: &[u8], rgb: &mut [u8]) -> usize {
+    // Safety: NEON is always available on aarch64.
+    unsafe { simd_neon::yuv444_to_rgb8_row(y, u, v, rgb) }
+}
+
+/// Returns the number of pixels converted.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn simd_yuv444_to_rgb8_row(_y: &[u8], _u: &[u8], _v: &[u8], _rgb: &mut [u8]) -> usize {
+    0
+}
+
+pub(crate) mod scalar {
+    use super::RgbToYuv;
+    use crate::{ColorEncoding, ColorMatrix, ColorRange};
+
+    /// Convert RGB to YUV.
+    ///
+    /// With [RgbToYuv::BT601_FULL], this matches
+    /// `RGB888toYUV444_bt601_full_swing` in `convert_image`.
+    #[inline]
+    pub(crate) fn rgb_to_yuv(k: &RgbToYuv, r: u8, g: u8, b: u8) -> [u8; 3] {
+        let (r, g, b) = (r as i32, g as i32, b as i32);
+        let dot = |c: [i16; 3]| (c[0] as i32 * r + c[1] as i32 * g + c[2] as i32 * b + 128) >> 8;
+        let y = dot(k.y) + k.y_offset as i32;
+        let u = dot(k.u) + 128;
+        let v = dot(k.v) + 128;
+        [y as u8, u as u8, v as u8]
+    }
+
+    /// Convert full swing BT.601 YUV to RGB.
+    #[inline]
+    pub(crate) fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+        // Coefficients in 7 bit fixed point. See https://en.wikipedia.org/wiki/YCbCr
+        let y = y as i32;
+        let u = u as i32 - 128;
+        let v = v as i32 - 128;
+        let r = y + ((179 * v + 64) >> 7);
+        let g = y - ((44 * u + 91 * v + 64) >> 7);
+        let b = y + ((227 * u + 64) >> 7);
+        [
+            r.clamp(0, 255) as u8,
+            g.clamp(0, 255) as u8,
+            b.clamp(0, 255) as u8,
+        ]
+    }
+
+    pub(crate) fn rgb8_to_yuv444_row(
+        k: &RgbToYuv,
+        rgb: &[u8],
+        y: &mut [u8],
+        u: &mut [u8],
+        v: &mut [u8],
+    ) {
+        for (pix, (y, (u, v))) in rgb
+            .chunks_exact(3)
+            .zip(y.iter_mut().zip(u.iter_mut().zip(v.iter_mut())))
+        {
+            [*y, *u, *v] = rgb_to_yuv(k, pix[0], pix[1], pix[2]);
+        }
+    }
+
+    pub(crate) fn yuv444_to_rgb8_row(y: &[u8], u: &[u8], v: &[u8], rgb: &mut [u8]) {
+        for (pix, (y, (u, v))) in rgb
+            .chunks_exact_mut(3)
+            .zip(y.iter().zip(u.iter().zip(v.iter())))
+        {
+            pix.copy_from_slice(&yuv_to_rgb(*y, *u, *v));
+        }
+    }
+
+    /// Fixed point coefficients, with 16 fractional bits, for YUV to RGB in
+    /// any [ColorEncoding].
+    #[derive(Debug, Clone, Copy)]
+    pub(crate) struct YuvToRgb {
+        y_offset: i32,
+        y_scale: i32,
+        rv: i32,
+        gu: i32,
+        gv: i32,
+        bu: i32,
+    }
+
+    impl From<ColorEncoding> for YuvToRgb {
+        fn from(encoding: ColorEncoding) -> Self {
+            // See https://en.wikipedia.org/wiki/YCbCr
+            let (kr, kb) = match encoding.matrix {
+                ColorMatrix::Bt601 => (0.299, 0.114),
+                ColorMatrix::Bt709 => (0.2126, 0.0722),
+            };
+            let kg = 1.0 - kr - kb;
+            let (y_offset, y_scale, c_scale) = match encoding.range {
+                ColorRange::Full => (0, 1.0, 1.0),
+                ColorRange::Limited => (16, 255.0 / 219.0, 255.0 / 224.0),
+            };
+            let fixed = |x: f64| (x * 65536.0).round() as i32;
+            Self {
+                y_offset,
+                y_scale: fixed(y_scale),
+                rv: fixed(2.0 * (1.0 - kr) * c_scale),
+                gu: fixed(2.0 * kb * (1.0 - kb) / kg * c_scale),
+                gv: fixed(2.0 * kr * (1.0 - kr) / kg * c_scale),
+                bu: fixed(2.0 * (1.0 - kb) * c_scale),
+            }
+        }
+    }
+
+    impl YuvToRgb {
+        #[inline]
+        pub(crate) fn yuv_to_rgb(&self, y: u8, u: u8, v: u8) -> [u8; 3] {
+            let y = (y as i32 - self.y_offset) * self.y_scale;
+            let u = u as i32 - 128;
+            let v = v as i32 - 128;
+            let clamp = |x: i32| ((x + (1 << 15)) >> 16).clamp(0, 255) as u8;
+            [
+                clamp(y + self.rv * v),
+                clamp(y - self.gu * u - self.gv * v),
+                clamp(y + self.bu * u),
+            ]
+        }
+
+        pub(crate) fn yuv444_to_rgb8_row(&self, y: &[u8], u: &[u8], v: &[u8], rgb: &mut [u8]) {
+            for (pix, (y, (u, v))) in rgb
+                .chunks_exact_mut(3)
+                .zip(y.iter().zip(u.iter().zip(v.iter())))
+            {
+                pix.copy_from_slice(&self.yuv_to_rgb(*y, *u, *v));
+            }
+        }
+    }
+}
+
+/// Split `N` packed RGB8 pixels into separate R, G and B arrays.
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn deinterleave<const N: usize>(rgb: &[u8]) -> [[u8; N]; 3] {
+    let mut out = [[0u8; N]; 3];
+    for (i, pix) in rgb[..N * 3].chunks_exact(3).enumerate() {
+        out[0][i] = pix[0];
+        out[1][i] = pix[1];
+        out[2][i] = pix[2];
+    }
+    out
+}
+
+/// Merge separate R, G and B arrays into `N` packed RGB8 pixels.
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn interleave<const N: usize>(planes: &[[u8; N]; 3], rgb: &mut [u8]) {
+    for (i, pix) in rgb[..N * 3].chunks_exact_mut(3).enumerate() {
+        pix[0] = planes[0][i];
+        pix[1] = planes[1][i];
+        pix[2] = planes[2][i];
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod simd_sse2 {
+    use core::arch::x86_64::*;
+
+    use super::RgbToYuv;
+
+    /// Compute `c[0] * r + c[1] * g + c[2] * b + 128` in 16 bit lanes.
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn dot_epi16(r: __m128i, g: __m128i, b: __m128i, c: [i16; 3]) -> __m128i {
+        _mm_add_epi16(
+            _mm_add_epi16(
+                _mm_mullo_epi16(r, _mm_set1_epi16(c[0])),
+                _mm_mullo_epi16(g, _mm_set1_epi16(c[1])),
+            ),
+            _mm_add_epi16(
+                _mm_mullo_epi16(b, _mm_set1_epi16(c[2])),
+                _mm_set1_epi16(128),
+            ),
+        )
+    }
+
+    /// Compute Y, U and V from eight R, G and B values in 16 bit lanes.
+    ///
+    /// The luma sum may exceed `i16::MAX` but always fits in `u16`, so it is
+    /// computed with wrapping arithmetic and a logical shift. The chroma sums
+    /// always fit in `i16`.
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn rgb_to_yuv_epi16(k: &RgbToYuv, r: __m128i, g: __m128i, b: __m128i) -> [__m128i; 3] {
+        let offset = _mm_set1_epi16(128);
+        [
+            _mm_add_epi16(
+                _mm_srli_epi16(dot_epi16(r, g, b, k.y), 8),
+                _mm_set1_epi16(k.y_offset),
+            ),
+            _mm_add_epi16(_mm_srai_epi16(dot_epi16(r, g, b, k.u), 8), offset),
+            _mm_add_epi16(_mm_srai_epi16(dot_epi16(r, g, b, k.v), 8), offset),
+        ]
+    }
+
+    /// Compute R, G and B from eight Y, U and V values in 16 bit lanes.
+    ///
+    /// The results are not clamped to the range 0-255.
+    #[inline]
+    #[target_feature(enable = "sse2")]
+    unsafe fn yuv_to_rgb_epi16(y: __m128i, u: __m128i, v: __m128i) -> [__m128i; 3] {
+        let round = _mm_set1_epi16(64);
+        let u = _mm_sub_epi16(u, _mm_set1_epi16(128));
+        let v = _mm_sub_epi16(v, _mm_set1_epi16(128));
+        let dr = _mm_add_epi16(_mm_mullo_epi16(v, _mm_set1_epi16(179)), round);
+        let dg = _mm_add_epi16(
+            _mm_add_epi16(
+                _mm_mullo_epi16(u, _mm_set1_epi16(44)),
+                _mm_mullo_epi16(v, _mm_set1_epi16(91)),
+            ),
+            round,
+        );
+        let db = _mm_add_epi16(_mm_mullo_epi16(u, _mm_set1_epi16(227)), round);
+        [
+            _mm_add_epi16(y, _mm_srai_epi16(dr, 7)),
+            _mm_sub_epi16(y, _mm_srai_epi16(dg, 7)),
+            _mm_add_epi16(y, _mm_srai_epi16(db, 7)),
+        ]
+    }
+
+    /// Convert packed RGB8 to planar YUV, 16 pixels at a time.
+    ///
+    /// Returns the number of pixels converted.
+    ///
+    /// # Safety
+    ///
+    /// This unconditionally generates code that depends on the SSE2
+    /// instruction set. The caller must ensure that the SSE2 feature is
+    /// available.
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn rgb8_to_yuv444_row(
+        k: &RgbToYuv,
+        rgb: &[u8],
+        y: &mut [u8],
+        u: &mut [u8],
+        v: &mut [u8],
+    ) -> usize {
+        let n = y.len() / 16 * 16;
+        let zero = _mm_setzero_si128();
+        for start in (0..n).step_by(16) {
+            let planes = super::deinterleave::<16>(&rgb[start * 3..]);
+            let [r, g, b] = planes.map(|p| _mm_loadu_si128(p.as_ptr() as *const __m128i));
+            let lo = rgb_to_yuv_epi16(
+                k,
+                _mm_unpacklo_epi8(r, zero),
+                _mm_unpacklo_epi8(g, zero),
+                _mm_unpacklo_epi8(b, zero),
+            );
+            let hi = rgb_to_yuv_epi16(
+                k,
+                _mm_unpackhi_epi8(r, zero),
+                _mm_unpackhi_epi8(g, zero),
+                _mm_unpackhi_epi8(b, zero),
+            );
+            for (i, dest) in [&mut *y, &mut *u, &mut *v].into_iter().enumerate() {
+                _mm_storeu_si128(
+                    dest[start..start + 16].as_mut_ptr() as *mut __m128i,
+                    _mm_packus_epi16(lo[i], hi[i]),
+                );
+            }
+        }
+        n
+    }
+
+    /// Convert planar YUV to packed RGB8, 16 pixels at a time.
+    ///
+    /// Returns the number of pixels converted.
+    ///
+    /// # Safety
+    ///
+    /// This unconditionally generates code that depends on the SSE2
+    /// instruction set. The caller must ensure that the SSE2 feature is
+    /// available.
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn yuv444_to_rgb8_row(y: &[u8], u: &[u8], v: &[u8], rgb: &mut [u8]) -> usize {
+        let n = y.len() / 16 * 16;
+        let zero = _mm_setzero_si128();
+        for start in (0..n).step_by(16) {
+            let [yy, uu, vv] =
+                [y, u, v].map(|p| _mm_loadu_si128(p[start..start + 16].as_ptr() as *const __m128i));
+            let lo = yuv_to_rgb_epi16(
+                _mm_unpacklo_epi8(yy, zero),
+                _mm_unpacklo_epi8(uu, zero),
+                _mm_unpacklo_epi8(vv, zero),
+            );
+            let hi = yuv_to_rgb_epi16(
+                _mm_unpackhi_epi8(yy, zero),
+                _mm_unpackhi_epi8(uu, zero),
+                _mm_unpackhi_epi8(vv, zero),
+            );
+            let mut planes = [[0u8; 16]; 3];
+            for (i, plane) in planes.iter_mut().enumerate() {
+                _mm_storeu_si128(
+                    plane.as_mut_ptr() as *mut __m128i,
+                    _mm_packus_epi16(lo[i], hi[i]),
+                );
+            }
+            super::interleave(&planes, &mut rgb[start * 3..]);
+        }
+        n
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod simd_avx2 {
+    use core::arch::x86_64::*;
+
+    use super::RgbToYuv;
+
+    /// Compute `c[0] * r + c[1] * g + c[2] * b + 128` in 16 bit lanes.
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn dot_epi16(r: __m256i, g: __m256i, b: __m256i, c: [i16; 3]) -> __m256i {
+        _mm256_add_epi16(
+            _mm256_add_epi16(
+                _mm256_mullo_epi16(r, _mm256_set1_epi16(c[0])),
+                _mm256_mullo_epi16(g, _mm256_set1_epi16(c[1])),
+            ),
+            _mm256_add_epi16(
+                _mm256_mullo_epi16(b, _mm256_set1_epi16(c[2])),
+                _mm256_set1_epi16(128),
+            ),
+        )
+    }
+
+    /// Compute Y, U and V from sixteen R, G and B values in 16 bit lanes.
+    ///
+    /// See the SSE2 version for the range of intermediate values.
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn rgb_to_yuv_epi16(k: &RgbToYuv, r: __m256i, g: __m256i, b: __m256i) -> [__m256i; 3] {
+        let offset = _mm256_set1_epi16(128);
+        [
+            _mm256_add_epi16(
+                _mm256_srli_epi16(dot_epi16(r, g, b, k.y), 8),
+                _mm256_set1_epi16(k.y_offset),
+            ),
+            _mm256_add_epi16(_mm256_srai_epi16(dot_epi16(r, g, b, k.u), 8), offset),
+            _mm256_add_epi16(_mm256_srai_epi16(dot_epi16(r, g, b, k.v), 8), offset),
+        ]
+    }
+
+    /// Compute R, G and B from sixteen Y, U and V values in 16 bit lanes.
+    ///
+    /// The results are not clamped to the range 0-255.
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn yuv_to_rgb_epi16(y: __m256i, u: __m256i, v: __m256i) -> [__m256i; 3] {
+        let round = _mm256_set1_epi16(64);
+        let u = _mm256_sub_epi16(u, _mm256_set1_epi16(128));
+        let v = _mm256_sub_epi16(v, _mm256_set1_epi16(128));
+        let dr = _mm256_add_epi16(_mm256_mullo_epi16(v, _mm256_set1_epi16(179)), round);
+        let dg = _mm256_add_epi16(
+            _mm256_add_epi16(
+                _mm256_mullo_epi16(u, _mm256_set1_epi16(44)),
+                _mm256_mullo_epi16(v, _mm256_set1_epi16(91)),
+            ),
+            round,
+        );
+        let db = _mm256_add_epi16(_mm256_mullo_epi16(u, _mm256_set1_epi16(227)), round);
+        [
+            _mm256_add_epi16(y, _mm256_srai_epi16(dr, 7)),
+            _mm256_sub_epi16(y, _mm256_srai_epi16(dg, 7)),
+            _mm256_add_epi16(y, _mm256_srai_epi16(db, 7)),
+        ]
+    }
+
+    /// Load 16 bytes and zero extend them to 16 bit lanes.
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn load_epu8(src: &[u8]) -> __m256i {
+        _mm256_cvtepu8_epi16(_mm_loadu_si128(src[..16].as_ptr() as *const __m128i))
+    }
+
+    /// Saturate two vectors of 16 bit lanes to bytes, preserving order.
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn pack_epu8(lo: __m256i, hi: __m256i) -> __m256i {
+        // `_mm256_packus_epi16` works within 128 bit lanes, so reorder the
+        // 64 bit quarters afterwards.
+        _mm256_permute4x64_epi64(_mm256_packus_epi16(lo, hi), 0b11_01_10_00)
+    }
+
+    /// Convert packed RGB8 to planar YUV, 32 pixels at a time.
+    ///
+    /// Returns the number of pixels converted.
+    ///
+    /// # Safety
+    ///
+    /// This unconditionally generates code that depends on the AVX2
+    /// instruction set. The caller must ensure that the AVX2 feature is
+    /// available.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn rgb8_to_yuv444_row(
+        k: &RgbToYuv,
+        rgb: &[u8],
+        y: &mut [u8],
+        u: &mut [u8],
+        v: &mut [u8],
+    ) -> usize {
+        let n = y.len() / 32 * 32;
+        for start in (0..n).step_by(32) {
+            let [r, g, b] = super::deinterleave::<32>(&rgb[start * 3..]);
+            let lo = rgb_to_yuv_epi16(k, load_epu8(&r), load_epu8(&g), load_epu8(&b));
+            let hi = rgb_to_yuv_epi16(
+                k,
+                load_epu8(&r[16..]),
+                load_epu8(&g[16..]),
+                load_epu8(&b[16..]),
+            );
+            for (i, dest) in [&mut *y, &mut *u, &mut *v].into_iter().enumerate() {
+                _mm256_storeu_si256(
+                    dest[start..start + 32].as_mut_ptr() as *mut __m256i,
+                    pack_epu8(lo[i], hi[i]),
+                );
+            }
+        }
+        n
+    }
+
+    /// Convert planar YUV to packed RGB8, 32 pixels at a time.
+    ///
+    /// Returns the number of pixels converted.
+    ///
+    /// # Safety
+    ///
+    /// This unconditionally generates code that depends on the AVX2
+    /// instruction set. The caller must ensure that the AVX2 feature is
+    /// available.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn yuv444_to_rgb8_row(y: &[u8], u: &[u8], v: &[u8], rgb: &mut [u8]) -> usize {
+        let n = y.len() / 32 * 32;
+        for start in (0..n).step_by(32) {
+            let (y, u, v) = (&y[start..], &u[start..], &v[start..]);
+            let lo = yuv_to_rgb_epi16(load_epu8(y), load_epu8(u), load_epu8(v));
+            let hi = yuv_to_rgb_epi16(
+                load_epu8(&y[16..]),
+                load_epu8(&u[16..]),
+                load_epu8(&v[16..]),
+            );
+            let mut planes = [[0u8; 32]; 3];
+            for (i, plane) in planes.iter_mut().enumerate() {
+                _mm256_storeu_si256(plane.as_mut_ptr() as *mut __m256i, pack_epu8(lo[i], hi[i]));
+            }
+            super::interleave(&planes, &mut rgb[start * 3..]);
+        }
+        n
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod simd_neon {
+    use core::arch::aarch64::*;
+
+    use super::RgbToYuv;
+
+    /// Compute Y, U and V from eight R, G and B values in 16 bit lanes.
+    ///
+    /// The luma sum may exceed `i16::MAX` but always fits in `u16`, so it is
+    /// computed with wrapping arithmetic and a logical shift. The chroma sums
+    /// always fit in `i16`.
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn rgb_to_yuv_s16(
+        k: &RgbToYuv,
+        r: uint16x8_t,
+        g: uint16x8_t,
+        b: uint16x8_t,
+    ) -> [uint8x8_t; 3] {
+        let y = vaddq_u16(
+            vaddq_u16(vmulq_n_u16(r, k.y[0] as u16), vmulq_n_u16(g, k.y[1] as u16)),
+            vaddq_u16(vmulq_n_u16(b, k.y[2] as u16), vdupq_n_u16(128)),
+        );
+        let (r, g, b) = (
+            vreinterpretq_s16_u16(r),
+            vreinterpretq_s16_u16(g),
+            vreinterpretq_s16_u16(b),
+        );
+        let round = vdupq_n_s16(128);
+        let u = vaddq_s16(
+            vaddq_s16(vmulq_n_s16(r, k.u[0]), vmulq_n_s16(g, k.u[1])),
+            vaddq_s16(vmulq_n_s16(b, k.u[2]), round),
+        );
+        let v = vaddq_s16(
+            vaddq_s16(vmulq_n_s16(r, k.v[0]), vmulq_n_s16(g, k.v[1])),
+            vaddq_s16(vmulq_n_s16(b, k.v[2]), round),
+        );
+        [
+            vmovn_u16(vaddq_u16(vshrq_n_u16(y, 8), vdupq_n_u16(k.y_offset as u16))),
+            vqmovun_s16(vaddq_s16(vshrq_n_s16(u, 8), round)),
+            vqmovun_s16(vaddq_s16(vshrq_n_s16(v, 8), round)),
+        ]
+    }
+
+    /// Compute R, G and B from eight Y, U and V values in 16 bit lanes.
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn yuv_to_rgb_s16(y: uint16x8_t, u: uint16x8_t, v: uint16x8_t) -> [uint8x8_t; 3] {
+        let offset = vdupq_n_s16(128);
+        let round = vdupq_n_s16(64);
+        let y = vreinterpretq_s16_u16(y);
+        let u = vsubq_s16(vreinterpretq_s16_u16(u), offset);
+        let v = vsubq_s16(vreinterpretq_s16_u16(v), offset);
+        let dr = vaddq_s16(vmulq_n_s16(v, 179), round);
+        let dg = vaddq_s16(vaddq_s16(vmulq_n_s16(u, 44), vmulq_n_s16(v, 91)), round);
+        let db = vaddq_s16(vmulq_n_s16(u, 227), round);
+        [
+            vqmovun_s16(vaddq_s16(y, vshrq_n_s16(dr, 7))),
+            vqmovun_s16(vsubq_s16(y, vshrq_n_s16(dg, 7))),
+            vqmovun_s16(vaddq_s16(y, vshrq_n_s16(db, 7))),
+        ]
+    }
+
+    /// Convert packed RGB8 to planar YUV, 16 pixels at a time.
+    ///
+    /// Returns the number of pixels converted.
+    ///
+    /// # Safety
+    ///
+    /// This unconditionally generates code that depends on the NEON
+    /// instruction set. The caller must ensure that the NEON feature is
+    /// available.
+    #[target_feature(enable = "neon")]
+    pub unsafe fn rgb8_to_yuv444_row(
+        k: &RgbToYuv,
+        rgb: &[u8],
+        y: &mut [u8],
+        u: &mut [u8],
+        v: &mut [u8],
+    ) -> usize {
+        let n = y.len() / 16 * 16;
+        for start in (0..n).step_by(16) {
+            let px = vld3q_u8(rgb[start * 3..(start + 16) * 3].as_ptr());
+            let lo = rgb_to_yuv_s16(
+                k,
+                vmovl_u8(vget_low_u8(px.0)),
+                vmovl_u8(vget_low_u8(px.1)),
+                vmovl_u8(vget_low_u8(px.2)),
+            );
+            let hi = rgb_to_yuv_s16(
+                k,
+                vmovl_high_u8(px.0),
+                vmovl_high_u8(px.1),
+                vmovl_high_u8(px.2),
+            );
+            for (i, dest) in [&mut *y, &mut *u, &mut *v].into_iter().enumerate() {
+                vst1q_u8(
+                    dest[start..start + 16].as_mut_ptr(),
+                    vcombine_u8(lo[i], hi[i]),
+                );
+            }
+        }
+        n
+    }
+
+    /// Convert planar YUV to packed RGB8, 16 pixels at a time.
+    ///
+    /// Returns the number of pixels converted.
+    ///
+    /// # Safety
+    ///
+    /// This unconditionally generates code that depends on the NEON
+    /// instruction set. The caller must ensure that the NEON feature is
+    /// available.
+    #[target_feature(enable = "neon")]
+    pub unsafe fn yuv444_to_rgb8_row(y: &[u8], u: &[u8], v: &[u8], rgb: &mut [u8]) -> usize {
+        let n = y.len() / 16 * 16;
+        for start in (0..n).step_by(16) {
+            let [yy, uu, vv] = [y, u, v].map(|p| vld1q_u8(p[start..start + 16].as_ptr()));
+            let lo = yuv_to_rgb_s16(
+                vmovl_u8(vget_low_u8(yy)),
+                vmovl_u8(vget_low_u8(uu)),
+                vmovl_u8(vget_low_u8(vv)),
+            );
+            let hi = yuv_to_rgb_s16(vmovl_high_u8(yy), vmovl_high_u8(uu), vmovl_high_u8(vv));
+            vst3q_u8(
+                rgb[start * 3..(start + 16) * 3].as_mut_ptr(),
+                uint8x16x3_t(
+                    vcombine_u8(lo[0], hi[0]),
+                    vcombine_u8(lo[1], hi[1]),
+                    vcombine_u8(lo[2], hi[2]),
+                ),
+            );
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every RGB value, as rows of packed pixels. Row lengths are chosen so
+    /// that the SIMD main loops and the scalar remainder are both exercised.
+    fn all_rgb_rows() -> impl Iterator<Item = Vec<u8>> {
+        (0..=255u8).flat_map(|r| {
+            (0..=255u8).map(move |g| {
+                (0..=255u8)
+                    .flat_map(|b| [r, g, b])
+                    .chain([r, g, 0])
+                    .collect()
+            })
+        })
+    }
+
+    const ALL_RGB_TO_YUV: [RgbToYuv; 4] = [
+        RgbToYuv::BT601_FULL,
+        RgbToYuv::BT601_LIMITED,
+        RgbToYuv::BT709_FULL,
+        RgbToYuv::BT709_LIMITED,
+    ];
+
+    #[test]
+    fn rgb8_to_yuv444_row_matches_scalar() {
+        let n = 257;
+        let (mut y, mut u, mut v) = (vec![0; n], vec![0; n], vec![0; n]);
+        for k in &ALL_RGB_TO_YUV {
+            for rgb in all_rgb_rows() {
+                rgb8_to_yuv444_row(k, &rgb, &mut y, &mut u, &mut v);
+                for (i, pix) in rgb.chunks_exact(3).enumerate() {
+                    assert_eq!(
+                        [y[i], u[i], v[i]],
+                        scalar::rgb_to_yuv(k, pix[0], pix[1], pix[2]),
+                        "{k:?} rgb {pix:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rgb_to_yuv_gray_and_range() {
+        for k in &ALL_RGB_TO_YUV {
+            let (black, white) = if k.y_offset == 0 { (0, 255) } else { (16, 235) };
+            assert_eq!(scalar::rgb_to_yuv(k, 0, 0, 0), [black, 128, 128], "{k:?}");
+            assert_eq!(
+                scalar::rgb_to_yuv(k, 255, 255, 255),
+                [white, 128, 128],
+                "{k:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn yuv_to_rgb_inverts_rgb_to_yuv() {
+        for matrix in [ColorMatrix::Bt601, ColorMatrix::Bt709] {
+            for range in [ColorRange::Full, ColorRange::Limited] {
+                let encoding = ColorEncoding { matrix, range };
+                let fwd = RgbToYuv::from(encoding);
+                let inv = scalar::YuvToRgb::from(encoding);
+                for rgb in [[0, 0, 0], [255, 255, 255], [200, 30, 90], [10, 240, 60]] {
+                    let [y, u, v] = scalar::rgb_to_yuv(&fwd, rgb[0], rgb[1], rgb[2]);
+                    for (a, b) in inv.yuv_to_rgb(y, u, v).iter().zip(rgb) {
+                        assert!((*a as i32 - b as i32).abs() <= 4, "{encoding:?} {rgb:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scalar_rgb_to_yuv_matches_convert_image() {
+        use machine_vision_formats::{
+            image_ref::{ImageRef, ImageRefMut},
+            pixel_format::{RGB8, YUV444},
+            ImageData,
+        };
+        let n = 257;
+        let mut yuv = vec![0u8; n * 3];
+        for rgb in all_rgb_rows() {
+            let src = ImageRef::<RGB8>::new(n as u32, 1, n * 3, &rgb).unwrap();
+            let mut dest = ImageRefMut::<YUV444>::new(n as u32, 1, n * 3, &mut yuv).unwrap();
+            convert_image::convert_into(&src, &mut dest).unwrap();
+            for (expected, pix) in dest.image_data().chunks_exact(3).zip(rgb.chunks_exact(3)) {
+                assert_eq!(
+                    expected,
+                    scalar::rgb_to_yuv(&RgbToYuv::BT601_FULL, pix[0], pix[1], pix[2])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn yuv444_to_rgb8_row_matches_scalar() {
+        let n = 257;
+        let mut rgb = vec![0; n * 3];
+        for y in 0..=255u8 {
+            for u in 0..=255u8 {
+                let yy = vec![y; n];
+                let uu = vec![u; n];
+                let vv: Vec<u8> = (0..=255u8).chain([0]).collect();
+                yuv444_to_rgb8_row(&yy, &uu, &vv, &mut rgb);
+                for (pix, v) in rgb.chunks_exact(3).zip(vv) {
+                    assert_eq!(pix, scalar::yuv_to_rgb(y, u, v), "yuv {y} {u} {v}");
+                }
+            }
+        }
+    }
+
+    // The dispatching functions above use AVX2 when available, so the SSE2
+    // kernels are checked separately.
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn sse2_rgb8_to_yuv444_row_matches_scalar() {
+        let n = 257;
+        let (mut y, mut u, mut v) = (vec![0; n], vec![0; n], vec![0; n]);
+        for k in &ALL_RGB_TO_YUV {
+            for rgb in all_rgb_rows() {
+                // Safety: SSE2 is always available on x86_64.
+                let done =
+                    unsafe { simd_sse2::rgb8_to_yuv444_row(k, &rgb, &mut y, &mut u, &mut v) };
+                assert_eq!(done, 256);
+                for (i, pix) in rgb[..done * 3].chunks_exact(3).enumerate() {
+                    assert_eq!(
+                        [y[i], u[i], v[i]],
+                        scalar::rgb_to_yuv(k, pix[0], pix[1], pix[2]),
+                        "{k:?} rgb {pix:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn sse2_yuv444_to_rgb8_row_matches_scalar() {
+        let n = 257;
+        let mut rgb = vec![0; n * 3];
+        let vv: Vec<u8> = (0..=255u8).chain([0]).collect();
+        for y in 0..=255u8 {
+            for u in 0..=255u8 {
+                let yy = vec![y; n];
+                let uu = vec![u; n];
+                // Safety: SSE2 is always available on x86_64.
+                let done = unsafe { simd_sse2::yuv444_to_rgb8_row(&yy, &uu, &vv, &mut rgb) };
+                assert_eq!(done, 256);
+                for (pix, v) in rgb[..done * 3].chunks_exact(3).zip(&vv) {
+                    assert_eq!(pix, scalar::yuv_to_rgb(y, u, *v), "yuv {y} {u} {v}");
+                }
+            }
+        }
+    }
+}
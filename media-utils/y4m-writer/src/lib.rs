@@ -5,11 +5,13 @@ use machine_vision_formats as formats;
 use formats::{
     iter::HasRowChunksExact,
     owned::OImage,
-    pixel_format::{self, Mono8, PixFmt},
+    pixel_format::{self, Mono8, PixFmt, NV12, RGB8},
     ImageData, PixelFormat, Stride,
 };
 
-use convert_image::{convert_owned, convert_ref};
+use convert_image::convert_ref;
+
+mod kernels;
 
 const EMPTY_BYTE: u8 = 128;
 
@@ -42,6 +44,35 @@ struct YUV444 {
     V: u8,
 }
 
+/// Matrix coefficients used to convert between RGB and YUV.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMatrix {
+    /// ITU-R BT.601, as used for standard definition video.
+    #[default]
+    Bt601,
+    /// ITU-R BT.709, as used for high definition video.
+    Bt709,
+}
+
+/// Range of the encoded Y, U and V values.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorRange {
+    /// All values 0-255 are used ("full swing" or "PC" range).
+    #[default]
+    Full,
+    /// Y uses 16-235 and U and V use 16-240 ("studio swing" or "TV" range).
+    Limited,
+}
+
+/// How RGB values are encoded as YUV.
+///
+/// The default, BT.601 with full range, matches `convert_image`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ColorEncoding {
+    pub matrix: ColorMatrix,
+    pub range: ColorRange,
+}
+
 #[derive(Debug)]
 pub struct Y4MOptions {
     /// Frame rate (numerator)
@@ -52,6 +83,8 @@ pub struct Y4MOptions {
     pub aspectn: usize,
     /// Aspect ratio (denominator)
     pub aspectd: usize,
+    /// Encoding of RGB data as YUV
+    pub color_encoding: ColorEncoding,
 }
 
 enum Writer {
@@ -72,7 +105,8 @@ impl Writer {
 
 /// An opinionated Y4M writer.
 ///
-/// Saves only progressive video with full color range.
+/// Saves only progressive video. The color range is given by
+/// [Y4MOptions::color_encoding] and written to the header.
 pub struct Y4MWriter {
     wtr: Writer,
     opts: Y4MOptions,
@@ -132,7 +166,10 @@ impl Y4MWriter {
                 .with_pixel_aspect(y4m::Ratio::new(self.opts.aspectn, self.opts.aspectd))
                 .with_colorspace(colorspace)
                 .append_vendor_extension(y4m::VendorExtensionString::new(
-                    b"COLORRANGE=FULL".into(),
+                    match self.opts.color_encoding.range {
+                        ColorRange::Full => b"COLORRANGE=FULL".into(),
+                        ColorRange::Limited => b"COLORRANGE=LIMITED".into(),
+                    },
                 )?);
                 let encoder = builder.write_header(wtr)?;
                 self.wtr = Writer::Started(encoder);
@@ -147,7 +184,8 @@ impl Y4MWriter {
 
         let encoder = self.wtr.encoder().unwrap();
 
-        let encoded = encode_y4m_frame(frame, colorspace, None)?;
+        let encoded =
+            encode_y4m_frame_with_encoding(frame, colorspace, None, self.opts.color_encoding)?;
         let frame = (&encoded).into();
         encoder.write_frame(&frame)?;
 
@@ -192,6 +230,232 @@ fn downsample_plane(arr: &[u8], h: usize, w: usize) -> Vec<u8> {
     result
 }
 
+/// Upsample a chroma value at full resolution location (`x`, `y`).
+///
+/// Chroma samples are taken to be centered between the four luminance samples
+/// they were averaged from (see [downsample_plane]), so the result is a
+/// bilinear interpolation of the four nearest chroma samples with weights 9/16,
+/// 3/16, 3/16 and 1/16. `get` returns the chroma sample at the given (column,
+/// row). Edge samples are repeated.
+#[inline]
+fn upsample_chroma(
+    get: impl Fn(usize, usize) -> u8,
+    x: usize,
+    y: usize,
+    last_col: usize,
+    last_row: usize,
+) -> u8 {
+    let (c0, r0) = (x / 2, y / 2);
+    let c1 = if x % 2 == 1 {
+        (c0 + 1).min(last_col)
+    } else {
+        c0.saturating_sub(1)
+    };
+    let r1 = if y % 2 == 1 {
+        (r0 + 1).min(last_row)
+    } else {
+        r0.saturating_sub(1)
+    };
+    let a = get(c0, r0) as u32;
+    let b = get(c1, r0) as u32;
+    let c = get(c0, r1) as u32;
+    let d = get(c1, r1) as u32;
+    ((9 * a + 3 * b + 3 * c + d + 8) / 16) as u8
+}
+
+/// Convert 4:2:0 subsampled YUV data to packed RGB8 with no padding.
+///
+/// `u_plane` and `v_plane` are indexed as `row * chroma_stride + col *
+/// chroma_step`, which allows both planar (step 1) and interleaved (NV12, step
+/// 2) chroma data.
+#[allow(clippy::too_many_arguments)]
+fn yuv420_into_rgb8(
+    encoding: ColorEncoding,
+    width: usize,
+    height: usize,
+    y_plane: &[u8],
+    y_stride: usize,
+    u_plane: &[u8],
+    v_plane: &[u8],
+    chroma_stride: usize,
+    chroma_step: usize,
+) -> Vec<u8> {
+    let last_col = width.saturating_sub(1) / 2;
+    let last_row = height.saturating_sub(1) / 2;
+    let get_u = |col: usize, row: usize| u_plane[row * chroma_stride + col * chroma_step];
+    let get_v = |col: usize, row: usize| v_plane[row * chroma_stride + col * chroma_step];
+
+    let yuv_to_rgb =
+        (encoding != ColorEncoding::default()).then(|| kernels::scalar::YuvToRgb::from(encoding));
+
+    let mut image_data = vec![0u8; width * height * 3];
+    let mut u_row = vec![0u8; width];
+    let mut v_row = vec![0u8; width];
+    for (y, (dest_row, y_row)) in image_data
+        .chunks_exact_mut(width * 3)
+        .zip(y_plane.chunks(y_stride))
+        .enumerate()
+    {
+        for (x, (u, v)) in u_row.iter_mut().zip(v_row.iter_mut()).enumerate() {
+            *u = upsample_chroma(get_u, x, y, last_col, last_row);
+            *v = upsample_chroma(get_v, x, y, last_col, last_row);
+        }
+        if let Some(yuv_to_rgb) = &yuv_to_rgb {
+            yuv_to_rgb.yuv444_to_rgb8_row(&y_row[..width], &u_row, &v_row, dest_row);
+        } else {
+            kernels::yuv444_to_rgb8_row(&y_row[..width], &u_row, &v_row, dest_row);
+        }
+    }
+    image_data
+}
+
+/// Convert a BT.601 full range NV12 image to RGB8.
+///
+/// The chroma planes are upsampled with bilinear interpolation.
+pub fn nv12_into_rgb8(frame: &dyn HasRowChunksExact<NV12>) -> Result<OImage<RGB8>> {
+    let width: usize = frame.width().try_into().unwrap();
+    let height: usize = frame.height().try_into().unwrap();
+    let stride = frame.stride();
+    let luma_size = stride * height;
+    let chroma_rows = height.div_ceil(2);
+    let data = frame.image_data();
+    if data.len() < luma_size + stride * chroma_rows.saturating_sub(1) + 2 * width.div_ceil(2) {
+        return Err(Error::InvalidAllocatedBufferSize);
+    }
+    let (y_plane, uv_plane) = data.split_at(luma_size);
+    let image_data = yuv420_into_rgb8(
+        ColorEncoding::default(),
+        width,
+        height,
+        y_plane,
+        stride,
+        uv_plane,
+        &uv_plane[1..],
+        stride,
+        2,
+    );
+    Ok(OImage::new(frame.width(), frame.height(), width * 3, image_data).unwrap())
+}
+
+/// Convert `frame` into a newly allocated image with pixel format `DEST`.
+fn convert_to_owned<SRC, DEST>(frame: &dyn HasRowChunksExact<SRC>) -> Result<OImage<DEST>>
+where
+    SRC: PixelFormat,
+    DEST: PixelFormat,
+{
+    let dest_fmt = pixel_format::pixfmt::<DEST>().unwrap();
+    let stride = dest_fmt.bits_per_pixel() as usize * frame.width() as usize / 8;
+    let mut dest = OImage::<DEST>::new(
+        frame.width(),
+        frame.height(),
+        stride,
+        vec![0u8; stride * frame.height() as usize],
+    )
+    .unwrap();
+    convert_image::convert_into(frame, &mut dest)?;
+    Ok(dest)
+}
+
+/// Images with at least this many pixels are converted in parallel when the
+/// `rayon` feature is enabled.
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_PIXELS: usize = 1920 * 1080;
+
+/// Number of rows converted by each parallel task.
+#[cfg(feature = "rayon")]
+const PARALLEL_ROWS_PER_TASK: usize = 32;
+
+/// Convert `frame` into a newly allocated YUV444 image.
+///
+/// RGB8 input is converted with the vectorized kernels in [kernels], giving
+/// results identical to `convert_image` for the default [ColorEncoding].
+/// Other input is converted with `convert_image`, going via RGB8 for
+/// encodings other than the default.
+fn convert_to_yuv444<FMT>(
+    frame: &dyn HasRowChunksExact<FMT>,
+    encoding: ColorEncoding,
+) -> Result<OImage<pixel_format::YUV444>>
+where
+    FMT: PixelFormat,
+{
+    let k = kernels::RgbToYuv::from(encoding);
+    if matches!(pixel_format::pixfmt::<FMT>(), Ok(PixFmt::RGB8)) {
+        Ok(rgb8_to_yuv444(
+            &k,
+            frame.width(),
+            frame.height(),
+            frame.image_data(),
+            frame.stride(),
+        ))
+    } else if encoding == ColorEncoding::default() {
+        convert_to_owned(frame)
+    } else {
+        let rgb8 = convert_ref::<_, RGB8>(frame)?;
+        Ok(rgb8_to_yuv444(
+            &k,
+            rgb8.width(),
+            rgb8.height(),
+            rgb8.image_data(),
+            rgb8.stride(),
+        ))
+    }
+}
+
+/// Convert packed RGB8 data into a newly allocated YUV444 image.
+///
+/// With the `rayon` feature, large images are split into bands of rows which
+/// are converted in parallel. The output does not depend on whether the
+/// parallel path is taken.
+fn rgb8_to_yuv444(
+    k: &kernels::RgbToYuv,
+    width: u32,
+    height: u32,
+    src: &[u8],
+    src_stride: usize,
+) -> OImage<pixel_format::YUV444> {
+    let w: usize = width.try_into().unwrap();
+    let h: usize = height.try_into().unwrap();
+    let stride = w * 3;
+    let mut image_data = vec![0u8; stride * h];
+
+    #[cfg(feature = "rayon")]
+    if w * h >= PARALLEL_MIN_PIXELS {
+        use rayon::prelude::*;
+        image_data
+            .par_chunks_mut(stride * PARALLEL_ROWS_PER_TASK)
+            .zip(src.par_chunks(src_stride * PARALLEL_ROWS_PER_TASK))
+            .for_each(|(dest, src)| rgb8_rows_to_yuv444(k, w, src, src_stride, dest));
+        return OImage::new(width, height, stride, image_data).unwrap();
+    }
+
+    rgb8_rows_to_yuv444(k, w, src, src_stride, &mut image_data);
+    OImage::new(width, height, stride, image_data).unwrap()
+}
+
+/// Convert rows of packed RGB8 pixels into packed YUV444 with no padding.
+///
+/// The final source row need not include its padding.
+fn rgb8_rows_to_yuv444(
+    k: &kernels::RgbToYuv,
+    width: usize,
+    src: &[u8],
+    src_stride: usize,
+    dest: &mut [u8],
+) {
+    let mut y_row = vec![0u8; width];
+    let mut u_row = vec![0u8; width];
+    let mut v_row = vec![0u8; width];
+    for (dest_row, src_row) in dest.chunks_exact_mut(width * 3).zip(src.chunks(src_stride)) {
+        kernels::rgb8_to_yuv444_row(k, src_row, &mut y_row, &mut u_row, &mut v_row);
+        for (dest_pix, ((y, u), v)) in dest_row
+            .chunks_exact_mut(3)
+            .zip(y_row.iter().zip(&u_row).zip(&v_row))
+        {
+            dest_pix.copy_from_slice(&[*y, *u, *v]);
+        }
+    }
+}
+
 fn next_multiple(a: u32, b: u32) -> u32 {
     div_ceil(a, b) * b
 }
@@ -229,6 +493,7 @@ pub struct Y4MFrame {
     pub height: i32,
     pub y_stride: i32,
     colorspace: y4m::Colorspace,
+    color_encoding: ColorEncoding,
     chroma_stride: usize,
     alloc_rows: i32,
     alloc_chroma_rows: i32,
@@ -266,6 +531,7 @@ impl Y4MFrame {
         is_known_mono_only: bool,
         forced_block_size: Option<u32>,
         colorspace: y4m::Colorspace,
+        color_encoding: ColorEncoding,
     ) -> Self {
         let width: i32 = width.try_into().unwrap();
         let height: i32 = height.try_into().unwrap();
@@ -282,6 +548,7 @@ impl Y4MFrame {
             height,
             y_stride,
             colorspace,
+            color_encoding,
             chroma_stride,
             alloc_rows,
             alloc_chroma_rows,
@@ -298,82 +565,45 @@ impl Y4MFrame {
 
         match &self.colorspace {
             y4m::Colorspace::C420paldv => {
-                // // Convert from color data RGB8.
-
-                // // TODO: implement shortcut when DEST is Mono8.
-
-                // // Instead of iterating smartly, just fill to 444.
-                // fn expand_plane(small: &[u8], small_stride: usize) -> Vec<u8> {
-                //     let small_rows = small.len() / small_stride;
-                //     let full_rows = small_rows * 2;
-                //     let full_stride = small_stride * 2;
-                //     let full_len = full_rows * full_stride;
-                //     let mut result = vec![0u8; full_len];
-
-                //     for (small_row_num, small_row) in small.chunks_exact(small_stride).enumerate() {
-                //         let big_row_num = small_row_num * 2;
-
-                //         for (small_col, val) in small_row.iter().enumerate() {
-                //             let big_col = small_col * 2;
-                //             result[big_row_num * full_stride + big_col] = *val;
-                //             result[big_row_num * full_stride + big_col + 1] = *val;
-
-                //             result[(big_row_num + 1) * full_stride + big_col] = *val;
-                //             result[(big_row_num + 1) * full_stride + big_col + 1] = *val;
-                //         }
-                //     }
-
-                //     result
-                // }
-                // let ufull_data = expand_plane(self.u_plane_data(), self.u_stride());
-                // let vfull_data = expand_plane(self.v_plane_data(), self.v_stride());
-
-                // let mut image_data = vec![0u8; vfull_data.len() * 3];
-                // let y_stride = self.y_stride();
-
-                // for (dest_row, (y_row, (u_row, v_row))) in
-                //     image_data.chunks_exact_mut(y_stride * 3).zip(
-                //         y_data.chunks_exact(y_stride).zip(
-                //             ufull_data
-                //                 .chunks_exact(y_stride)
-                //                 .zip(vfull_data.chunks_exact(y_stride)),
-                //         ),
-                //     )
-                // {
-                //     for (col, (y, (u, v))) in
-                //         y_row.iter().zip(u_row.iter().zip(v_row.iter())).enumerate()
-                //     {
-                //         let rgb = YUV444_bt601_toRGB(*y, *u, *v);
-                //         dest_row[col * 3] = rgb.R;
-                //         dest_row[col * 3 + 1] = rgb.G;
-                //         dest_row[col * 3 + 2] = rgb.B;
-                //     }
-                // }
-
-                // let rgb8 = Image::<RGB8>::new(
-                //     self.width.try_into().unwrap(),
-                //     self.height.try_into().unwrap(),
-                //     (self.width * 3).try_into().unwrap(),
-                //     image_data,
-                // )
-                // .unwrap();
-
-                // // Then convert to final target output
-                // let out = convert_owned::<_, RGB8, DEST>(rgb8)?;
-                // Ok(out)
-                todo!();
+                // Convert from color data RGB8.
+                let width = self.width();
+                let height = self.height();
+                let image_data = yuv420_into_rgb8(
+                    self.color_encoding,
+                    width.try_into().unwrap(),
+                    height.try_into().unwrap(),
+                    y_data,
+                    self.y_stride(),
+                    self.u_plane_data(),
+                    self.v_plane_data(),
+                    self.u_stride(),
+                    1,
+                );
+                let rgb8 =
+                    OImage::<RGB8>::new(width, height, width as usize * 3, image_data).unwrap();
+
+                // Then convert to final target output
+                let out = convert_to_owned::<RGB8, DEST>(&rgb8)?;
+                Ok(out)
             }
             y4m::Colorspace::Cmono => {
+                let mut image_data = y_data.to_vec();
+                if self.color_encoding.range == ColorRange::Limited {
+                    let yuv_to_rgb = kernels::scalar::YuvToRgb::from(self.color_encoding);
+                    for y in image_data.iter_mut() {
+                        *y = yuv_to_rgb.yuv_to_rgb(*y, 128, 128)[0];
+                    }
+                }
                 let mono8 = OImage::<Mono8>::new(
                     self.width.try_into().unwrap(),
                     self.height.try_into().unwrap(),
                     self.width.try_into().unwrap(),
-                    y_data.to_vec(),
+                    image_data,
                 )
                 .unwrap();
 
                 // Then convert to final target output
-                let out = convert_owned::<_, Mono8, DEST>(mono8)?;
+                let out = convert_to_owned::<Mono8, DEST>(&mono8)?;
                 Ok(out)
             }
             cs => Err(Error::UnsupportedColorspace(*cs)),
@@ -416,6 +646,7 @@ impl Y4MFrame {
             height,
             y_stride,
             colorspace: y4m::Colorspace::Cmono,
+            color_encoding: ColorEncoding::default(),
             chroma_stride,
             alloc_rows: height,
             alloc_chroma_rows,
@@ -463,11 +694,15 @@ impl Y4MFrame {
     pub fn colorspace(&self) -> y4m::Colorspace {
         self.colorspace
     }
+    pub fn color_encoding(&self) -> ColorEncoding {
+        self.color_encoding
+    }
 }
 
 fn generic_to_c420paldv_macroblocks<FMT>(
     frame: &dyn HasRowChunksExact<FMT>,
     block_size: u32,
+    color_encoding: ColorEncoding,
 ) -> Result<Y4MFrame>
 where
     FMT: PixelFormat,
@@ -476,7 +711,7 @@ where
 
     // TODO: convert directly to YUV420 instead of YUV444 for efficiency.
     // Currently we convert to YUV444 first and then downsample later.
-    let frame_yuv444 = convert_ref::<_, pixel_format::YUV444>(frame)?;
+    let frame_yuv444 = convert_to_yuv444(frame, color_encoding)?;
 
     let width: usize = frame.width().try_into().unwrap();
 
@@ -589,6 +824,7 @@ where
         false,
         Some(block_size),
         y4m::Colorspace::C420paldv,
+        color_encoding,
     );
 
     debug_assert_eq!(result.y_stride(), fullstride);
@@ -607,7 +843,10 @@ where
     Ok(result)
 }
 
-fn generic_to_c420paldv<FMT>(frame: &dyn HasRowChunksExact<FMT>) -> Result<Y4MFrame>
+fn generic_to_c420paldv<FMT>(
+    frame: &dyn HasRowChunksExact<FMT>,
+    color_encoding: ColorEncoding,
+) -> Result<Y4MFrame>
 where
     FMT: PixelFormat,
 {
@@ -616,7 +855,7 @@ where
     // planar.
 
     // TODO: convert to YUV422 instead of YUV444 for efficiency.
-    let frame = convert_ref::<_, pixel_format::YUV444>(frame)?;
+    let frame = convert_to_yuv444(frame, color_encoding)?;
 
     // Convert to planar data.
 
@@ -669,11 +908,15 @@ where
         false,
         None,
         y4m::Colorspace::C420paldv,
+        color_encoding,
     ))
 }
 
 /// Converts input, a reference to a trait object implementing
 /// [`HasRowChunksExact<FMT>`], into a [Y4MFrame].
+///
+/// Color data is encoded with the default [ColorEncoding], BT.601 with full
+/// range.
 pub fn encode_y4m_frame<FMT>(
     frame: &dyn HasRowChunksExact<FMT>,
     out_colorspace: y4m::Colorspace,
@@ -682,7 +925,27 @@ pub fn encode_y4m_frame<FMT>(
 where
     FMT: PixelFormat,
 {
-    match out_colorspace {
+    encode_y4m_frame_with_encoding(
+        frame,
+        out_colorspace,
+        forced_block_size,
+        ColorEncoding::default(),
+    )
+}
+
+/// Converts input into a [Y4MFrame] with the given [ColorEncoding].
+///
+/// See [encode_y4m_frame].
+pub fn encode_y4m_frame_with_encoding<FMT>(
+    frame: &dyn HasRowChunksExact<FMT>,
+    out_colorspace: y4m::Colorspace,
+    forced_block_size: Option<u32>,
+    color_encoding: ColorEncoding,
+) -> Result<Y4MFrame>
+where
+    FMT: PixelFormat,
+{
+    let mut result = match out_colorspace {
         y4m::Colorspace::Cmono => {
             if let Some(block_size) = forced_block_size {
                 if !((frame.width() % block_size == 0) && (frame.height() % block_size == 0)) {
@@ -717,15 +980,29 @@ where
                 }
                 _ => {
                     if let Some(block_size) = forced_block_size {
-                        generic_to_c420paldv_macroblocks(frame, block_size)
+                        generic_to_c420paldv_macroblocks(frame, block_size, color_encoding)
                     } else {
-                        generic_to_c420paldv(frame)
+                        generic_to_c420paldv(frame, color_encoding)
                     }
                 }
             }
         }
         cs => Err(Error::UnsupportedColorspace(cs)),
+    }?;
+    if result.is_known_mono_only && color_encoding != result.color_encoding {
+        // Luma of gray pixels depends only on the range, and the chroma
+        // planes are neutral, so only the luma plane needs rescaling.
+        let k = kernels::RgbToYuv::from(color_encoding);
+        let lut: Vec<u8> = (0..=255u8)
+            .map(|m| kernels::scalar::rgb_to_yuv(&k, m, m, m)[0])
+            .collect();
+        let y_size = result.y_size();
+        for y in result.data[..y_size].iter_mut() {
+            *y = lut[*y as usize];
+        }
+        result.color_encoding = color_encoding;
     }
+    Ok(result)
 }
 
 fn mono8_into_yuv420_planar<FMT>(
@@ -795,5 +1072,106 @@ where
         true,
         forced_block_size,
         y4m::Colorspace::C420paldv,
+        ColorEncoding::default(),
     )
 }
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_parallel_yuv444_matches_serial() {
+    // Use a height that is not a multiple of the rows per task and a padded
+    // source stride.
+    let (w, h) = (1931u32, 1091u32);
+    let stride = w as usize * 3 + 5;
+    let image_data: Vec<u8> = (0..stride * h as usize)
+        .map(|i| (i * 7 + i / stride) as u8)
+        .collect();
+    let orig = OImage::<RGB8>::new(w, h, stride, image_data).unwrap();
+    let parallel = convert_to_yuv444(&orig, ColorEncoding::default()).unwrap();
+
+    let k = kernels::RgbToYuv::BT601_FULL;
+    let mut serial = vec![0u8; w as usize * 3 * h as usize];
+    rgb8_rows_to_yuv444(&k, w as usize, orig.image_data(), stride, &mut serial);
+    assert_eq!(parallel.image_data(), &serial[..]);
+}
+
+#[test]
+fn test_yuv420_to_rgb8_roundtrip() {
+    // A smooth color gradient survives chroma subsampling nearly unchanged. The
+    // largest errors are at the corners, where chroma is extrapolated.
+    let (w, h) = (16u32, 12u32);
+    let mut image_data = Vec::new();
+    for y in 0..h {
+        for x in 0..w {
+            image_data.extend([(x * 4 + 30) as u8, (y * 4 + 40) as u8, 120u8]);
+        }
+    }
+    let orig = OImage::<RGB8>::new(w, h, w as usize * 3, image_data).unwrap();
+
+    let check = |rgb: &[u8]| {
+        for (actual, expected) in rgb.iter().zip(orig.image_data()) {
+            assert!(
+                (*actual as i32 - *expected as i32).abs() <= 4,
+                "{actual} != {expected}"
+            );
+        }
+    };
+
+    for forced_block_size in [None, Some(16)] {
+        let y4m = encode_y4m_frame(&orig, y4m::Colorspace::C420paldv, forced_block_size).unwrap();
+        let rgb8 = y4m.convert::<RGB8>().unwrap();
+        assert_eq!(rgb8.stride(), w as usize * 3);
+        check(rgb8.image_data());
+    }
+
+    let mut nv12_buf = vec![0u8; (w * h * 3 / 2) as usize];
+    let mut nv12 =
+        formats::image_ref::ImageRefMut::<NV12>::new(w, h, w as usize, &mut nv12_buf).unwrap();
+    convert_image::convert_into(&orig, &mut nv12).unwrap();
+    let nv12 = formats::image_ref::ImageRef::<NV12>::new(w, h, w as usize, &nv12_buf).unwrap();
+    check(nv12_into_rgb8(&nv12).unwrap().image_data());
+}
+
+#[test]
+fn test_color_encoding_roundtrip() {
+    let (w, h) = (16u32, 12u32);
+    let mut image_data = Vec::new();
+    for y in 0..h {
+        for x in 0..w {
+            image_data.extend([(x * 4 + 30) as u8, (y * 4 + 40) as u8, 120u8]);
+        }
+    }
+    let orig = OImage::<RGB8>::new(w, h, w as usize * 3, image_data).unwrap();
+    let mono = OImage::<Mono8>::new(w, h, w as usize, vec![255u8; (w * h) as usize]).unwrap();
+
+    for matrix in [ColorMatrix::Bt601, ColorMatrix::Bt709] {
+        for range in [ColorRange::Full, ColorRange::Limited] {
+            let color_encoding = ColorEncoding { matrix, range };
+            let y4m = encode_y4m_frame_with_encoding(
+                &orig,
+                y4m::Colorspace::C420paldv,
+                None,
+                color_encoding,
+            )
+            .unwrap();
+            assert_eq!(y4m.color_encoding(), color_encoding);
+            let rgb8 = y4m.convert::<RGB8>().unwrap();
+            for (actual, expected) in rgb8.image_data().iter().zip(orig.image_data()) {
+                assert!(
+                    (*actual as i32 - *expected as i32).abs() <= 4,
+                    "{color_encoding:?}: {actual} != {expected}"
+                );
+            }
+
+            let white = if range == ColorRange::Full { 255 } else { 235 };
+            for out_colorspace in [y4m::Colorspace::Cmono, y4m::Colorspace::C420paldv] {
+                let y4m =
+                    encode_y4m_frame_with_encoding(&mono, out_colorspace, None, color_encoding)
+                        .unwrap();
+                assert!(y4m.y_plane_data().iter().all(|y| *y == white));
+                let mono8 = y4m.convert::<Mono8>().unwrap();
+                assert!(mono8.image_data().iter().all(|m| *m == 255));
+            }
+        }
+    }
+}
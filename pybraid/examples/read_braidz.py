@@ -0,0 +1,26 @@
+import pybraid # install with "maturin develop"
+import pandas as pd
+import sys
+
+# Get the filename of the braidz file from the command line.
+braidz_fname = sys.argv[1]
+
+braidz = pybraid.read_braidz(braidz_fname)
+print(braidz["metadata"])
+
+# Create pandas DataFrames of the tables.
+data2d = pd.DataFrame(data=braidz["data2d_distorted"])
+print(data2d)
+kest = pd.DataFrame(data=braidz["kalman_estimates"] or {})
+print(kest)
+
+cal = braidz["calibration"]
+if cal is not None and len(kest) > 0:
+    # Project the first 3D estimate into each camera and triangulate it again.
+    xyz = kest[["x", "y", "z"]].to_numpy()[:1]
+    pixels = {}
+    for cam_name in cal.cam_names():
+        px = cal.project(cam_name, xyz)
+        print(cam_name, px, cal.undistort(cam_name, px))
+        pixels[cam_name] = tuple(px[0])
+    print("triangulated", cal.triangulate(pixels), "original", xyz[0])
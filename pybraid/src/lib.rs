@@ -0,0 +1,307 @@
+// Copyright 2024 Andrew D. Straw.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::BTreeMap;
+
+use nalgebra::{Point2, Point3};
+use numpy::{ndarray::Array2, IntoPyArray, PyArray1, PyArray2, PyReadonlyArray2};
+use pyo3::{
+    exceptions::{PyKeyError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+
+use flydra_mvg::FlydraMultiCameraSystem;
+use frame_source::{FrameDataSource, Timestamp, TimestampSource};
+use mvg::{DistortedPixel, PointWorldFrame};
+
+fn value_error<E: std::fmt::Display>(msg: &str) -> impl FnOnce(E) -> PyErr + '_ {
+    move |e| PyErr::new::<PyValueError, _>(format!("{msg}: '{e}'"))
+}
+
+/// Convert a value which can be serialized to JSON into a Python object.
+fn to_python<'py, T: serde::Serialize>(py: Python<'py>, value: &T) -> PyResult<Bound<'py, PyAny>> {
+    let buf = serde_json::to_string(value).map_err(value_error("Could not serialize"))?;
+    py.import("json")?.call_method1("loads", (buf,))
+}
+
+fn timestamp_or_nan<S>(ts: &Option<flydra_types::FlydraFloatTimestampLocal<S>>) -> f64 {
+    ts.as_ref().map(|t| t.as_f64()).unwrap_or(f64::NAN)
+}
+
+/// A multi-camera calibration.
+///
+/// Pixel coordinates are distorted, i.e. as seen in the camera images. Refraction
+/// at a water surface at z=0 is taken into account if the calibration has one.
+#[pyclass(unsendable)]
+struct Calibration {
+    system: FlydraMultiCameraSystem<f64>,
+}
+
+impl Calibration {
+    fn cam(&self, cam_name: &str) -> PyResult<flydra_mvg::MultiCamera<f64>> {
+        self.system
+            .cam_by_name(cam_name)
+            .ok_or_else(|| PyErr::new::<PyKeyError, _>(format!("no camera '{cam_name}'")))
+    }
+}
+
+#[pymethods]
+impl Calibration {
+    /// Read a calibration from a Braid XML, pymvg JSON or MCSC directory path.
+    #[staticmethod]
+    fn from_path(path: &str) -> PyResult<Self> {
+        let system = FlydraMultiCameraSystem::from_path(path)
+            .map_err(value_error(&format!("Could not read calibration {path}")))?;
+        Ok(Self { system })
+    }
+
+    /// The names of the cameras.
+    fn cam_names(&self) -> Vec<String> {
+        self.system.cam_names().map(String::from).collect()
+    }
+
+    /// The refractive index of the water at z<0, or `None`.
+    fn water(&self) -> Option<f64> {
+        self.system.water()
+    }
+
+    /// Project 3D points, an Nx3 array, to Nx2 pixel coordinates of a camera.
+    fn project<'py>(
+        &self,
+        py: Python<'py>,
+        cam_name: &str,
+        points: PyReadonlyArray2<'py, f64>,
+    ) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        let cam = self.cam(cam_name)?;
+        let points = points.as_array();
+        if points.ncols() != 3 {
+            return Err(PyErr::new::<PyValueError, _>("points must be Nx3"));
+        }
+        let mut result = Array2::zeros((points.nrows(), 2));
+        for (pt, mut out) in points.rows().into_iter().zip(result.rows_mut()) {
+            let pt3d = PointWorldFrame {
+                coords: Point3::new(pt[0], pt[1], pt[2]),
+            };
+            let px = cam.project_3d_to_distorted_pixel(&pt3d);
+            out[0] = px.coords.x;
+            out[1] = px.coords.y;
+        }
+        Ok(result.into_pyarray(py))
+    }
+
+    /// Undistort Nx2 pixel coordinates of a camera.
+    fn undistort<'py>(
+        &self,
+        py: Python<'py>,
+        cam_name: &str,
+        pixels: PyReadonlyArray2<'py, f64>,
+    ) -> PyResult<Bound<'py, PyArray2<f64>>> {
+        let cam = self.cam(cam_name)?;
+        let pixels = pixels.as_array();
+        if pixels.ncols() != 2 {
+            return Err(PyErr::new::<PyValueError, _>("pixels must be Nx2"));
+        }
+        let mut result = Array2::zeros((pixels.nrows(), 2));
+        for (px, mut out) in pixels.rows().into_iter().zip(result.rows_mut()) {
+            let distorted = DistortedPixel {
+                coords: Point2::new(px[0], px[1]),
+            };
+            let undistorted = cam.undistort(&distorted);
+            out[0] = undistorted.coords.x;
+            out[1] = undistorted.coords.y;
+        }
+        Ok(result.into_pyarray(py))
+    }
+
+    /// Triangulate a 3D point from a dict of camera name to (x, y) pixel.
+    ///
+    /// At least two cameras are required. Returns (x, y, z).
+    fn triangulate(&self, pixels: BTreeMap<String, (f64, f64)>) -> PyResult<(f64, f64, f64)> {
+        let points: Vec<_> = pixels
+            .into_iter()
+            .map(|(name, (x, y))| {
+                let px = DistortedPixel {
+                    coords: Point2::new(x, y),
+                };
+                (name, px)
+            })
+            .collect();
+        let pt = self
+            .system
+            .find3d_distorted(&points)
+            .map_err(value_error("Could not triangulate"))?
+            .point();
+        Ok((pt.coords.x, pt.coords.y, pt.coords.z))
+    }
+}
+
+/// Read a `.braidz` file (or `.braid` directory).
+///
+/// Returns a dict with the keys:
+///
+/// - `metadata`: dict with the metadata of the file.
+/// - `cam_info`: dict of camera number to camera name.
+/// - `calibration`: the `Calibration`, or `None`.
+/// - `kalman_estimates`: dict of column name to numpy array, or `None` if the
+///   file has no 3D tracking data. Missing timestamps are NaN.
+/// - `data2d_distorted`: dict of column name to numpy array. Missing
+///   timestamps are NaN.
+/// - `object_quality`: dict of column name to numpy array, with the quality
+///   and confidence of the tracking of each object. This is empty for files
+///   saved before it was introduced.
+///
+/// The dicts of arrays can be passed directly to `pandas.DataFrame`.
+#[pyfunction]
+fn read_braidz<'py>(py: Python<'py>, path: &str) -> PyResult<Bound<'py, PyDict>> {
+    let mut archive = braidz_parser::braidz_parse_path(path)
+        .map_err(value_error(&format!("Could not open file {path}")))?;
+
+    let result = PyDict::new(py);
+    result.set_item("metadata", to_python(py, &archive.metadata)?)?;
+    let cam_info: BTreeMap<u8, String> = archive
+        .cam_info
+        .camn2camid
+        .iter()
+        .map(|(camn, cam_id)| (camn.0, cam_id.clone()))
+        .collect();
+    result.set_item("cam_info", cam_info)?;
+    let calibration = archive.calibration_info.as_ref().map(|info| Calibration {
+        system: FlydraMultiCameraSystem::from_system(info.cameras.clone(), info.water),
+    });
+    result.set_item("calibration", calibration)?;
+
+    let kalman_estimates = match archive.kalman_estimates_table.as_ref() {
+        Some(rows) => {
+            let data = PyDict::new(py);
+            macro_rules! column {
+                ($name:expr, $f:expr) => {
+                    let col: Vec<_> = rows.iter().map($f).collect();
+                    data.set_item($name, col.into_pyarray(py))?;
+                };
+            }
+            column!("obj_id", |r| r.obj_id);
+            column!("frame", |r| r.frame.0);
+            column!("timestamp", |r| timestamp_or_nan(&r.timestamp));
+            column!("x", |r| r.x);
+            column!("y", |r| r.y);
+            column!("z", |r| r.z);
+            column!("xvel", |r| r.xvel);
+            column!("yvel", |r| r.yvel);
+            column!("zvel", |r| r.zvel);
+            column!("P00", |r| r.P00);
+            column!("P01", |r| r.P01);
+            column!("P02", |r| r.P02);
+            column!("P11", |r| r.P11);
+            column!("P12", |r| r.P12);
+            column!("P22", |r| r.P22);
+            column!("P33", |r| r.P33);
+            column!("P44", |r| r.P44);
+            column!("P55", |r| r.P55);
+            column!("num_cams", |r| r.num_cams);
+            column!("max_ray_angle", |r| r.max_ray_angle);
+            column!("innovation_pixels", |r| r.innovation_pixels);
+            column!("pos_cov_trace", |r| r.pos_cov_trace);
+            Some(data)
+        }
+        None => None,
+    };
+    result.set_item("kalman_estimates", kalman_estimates)?;
+
+    let rows = archive
+        .iter_data2d_distorted()
+        .map_err(value_error("Could not open data2d_distorted"))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(value_error("Error reading row"))?;
+    let data = PyDict::new(py);
+    macro_rules! column {
+        ($name:expr, $f:expr) => {
+            let col: Vec<_> = rows.iter().map($f).collect();
+            data.set_item($name, col.into_pyarray(py))?;
+        };
+    }
+    column!("camn", |r| r.camn.0);
+    column!("frame", |r| r.frame);
+    column!("timestamp", |r| timestamp_or_nan(&r.timestamp));
+    column!("cam_received_timestamp", |r| r
+        .cam_received_timestamp
+        .as_f64());
+    column!("x", |r| r.x);
+    column!("y", |r| r.y);
+    column!("area", |r| r.area);
+    column!("slope", |r| r.slope);
+    column!("eccentricity", |r| r.eccentricity);
+    column!("frame_pt_idx", |r| r.frame_pt_idx);
+    column!("cur_val", |r| r.cur_val);
+    column!("mean_val", |r| r.mean_val);
+    column!("sumsqf_val", |r| r.sumsqf_val);
+    column!("mean_intensity", |r| r.mean_intensity);
+    result.set_item("data2d_distorted", data)?;
+
+    let rows = archive
+        .object_quality()
+        .map_err(value_error("Could not read object_quality"))?;
+    let data = PyDict::new(py);
+    macro_rules! column {
+        ($name:expr, $f:expr) => {
+            let col: Vec<_> = rows.iter().map($f).collect();
+            data.set_item($name, col.into_pyarray(py))?;
+        };
+    }
+    column!("obj_id", |r| r.obj_id);
+    column!("num_frames", |r| r.num_frames);
+    column!("num_frames_observed", |r| r.num_frames_observed);
+    column!("num_frames_multi_cam", |r| r.num_frames_multi_cam);
+    column!("mean_num_cams", |r| r.mean_num_cams);
+    column!("mean_innovation_pixels", |r| r.mean_innovation_pixels);
+    column!("max_pos_cov_trace", |r| r.max_pos_cov_trace);
+    column!("confidence", |r| r.confidence);
+    result.set_item("object_quality", data)?;
+
+    Ok(result)
+}
+
+/// Read the precision timestamps of the frames of a Strand Camera MP4 file.
+///
+/// Returns an array of the timestamps, in seconds since the Unix epoch. The
+/// frames are not decoded.
+#[pyfunction]
+fn read_mp4_timestamps<'py>(py: Python<'py>, path: &str) -> PyResult<Bound<'py, PyArray1<f64>>> {
+    let mut src = frame_source::from_path_with_timestamp_source(
+        path,
+        false,
+        TimestampSource::MispMicrosectime,
+    )
+    .map_err(value_error(&format!("Could not open file {path}")))?;
+    let frame0_time = src.frame0_time().ok_or_else(|| {
+        PyErr::new::<PyValueError, _>(format!("no precision timestamps in {path}"))
+    })?;
+    let t0 = frame0_time.timestamp_micros() as f64 * 1e-6;
+    let mut timestamps = Vec::new();
+    for frame in src.iter() {
+        let frame = frame.map_err(value_error("Error reading frame"))?;
+        match frame.timestamp() {
+            Timestamp::Duration(dt) => timestamps.push(t0 + dt.as_secs_f64()),
+            Timestamp::Fraction(_) => {
+                return Err(PyErr::new::<PyValueError, _>(format!(
+                    "no precision timestamps in {path}"
+                )));
+            }
+        }
+    }
+    Ok(timestamps.into_pyarray(py))
+}
+
+/// Reading of `.braidz` files, Braid camera calibrations and Strand Camera MP4
+/// timestamps.
+#[pymodule]
+fn pybraid(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Calibration>()?;
+    m.add_function(wrap_pyfunction!(read_braidz, m)?)?;
+    m.add_function(wrap_pyfunction!(read_mp4_timestamps, m)?)?;
+    Ok(())
+}
@@ -41,3 +41,187 @@ pub struct ClockModel {
     pub residuals: f64,
     pub n_measurements: u64,
 }
+
+/// Space on the volume to which recordings are saved.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DiskSpace {
+    /// The monitored directory.
+    pub path: String,
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+    /// Available space is below the warning threshold.
+    pub is_low: bool,
+}
+
+/// Description of an experiment, entered by the user, which is stored with the
+/// recorded data.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExperimentMetadata {
+    pub experimenter: String,
+    pub animal_id: String,
+    pub condition: String,
+    /// Free-text notes.
+    pub notes: String,
+}
+
+impl ExperimentMetadata {
+    /// Whether no field has been filled in.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Transformation applied to camera images, e.g. for cameras mounted upside
+/// down or rotated.
+///
+/// The image is first mirrored left-right (if the name contains "mirror") and
+/// then rotated clockwise.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageTransform {
+    #[default]
+    None,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Mirror,
+    MirrorRotate90,
+    /// Equivalent to flipping the image upside down.
+    MirrorRotate180,
+    MirrorRotate270,
+}
+
+impl ImageTransform {
+    /// Whether the image is unchanged.
+    pub fn is_identity(&self) -> bool {
+        self == &Self::None
+    }
+
+    /// Whether the image is mirrored left-right before rotation.
+    pub fn is_mirrored(&self) -> bool {
+        use ImageTransform::*;
+        matches!(
+            self,
+            Mirror | MirrorRotate90 | MirrorRotate180 | MirrorRotate270
+        )
+    }
+
+    /// The clockwise rotation in degrees: 0, 90, 180 or 270.
+    pub fn rotation_degrees(&self) -> u16 {
+        use ImageTransform::*;
+        match self {
+            None | Mirror => 0,
+            Rotate90 | MirrorRotate90 => 90,
+            Rotate180 | MirrorRotate180 => 180,
+            Rotate270 | MirrorRotate270 => 270,
+        }
+    }
+
+    /// Whether width and height are exchanged.
+    pub fn swaps_axes(&self) -> bool {
+        self.rotation_degrees() % 180 != 0
+    }
+
+    /// The width and height of the transformed image.
+    pub fn transformed_size(&self, width: u32, height: u32) -> (u32, u32) {
+        if self.swaps_axes() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Transform pixel coordinates in the original image of size `width` x
+    /// `height` to coordinates in the transformed image.
+    ///
+    /// Coordinates are those of pixel centers, so that the first pixel is at
+    /// (0, 0) and the last at (width - 1, height - 1).
+    pub fn apply_to_point(&self, x: f64, y: f64, width: u32, height: u32) -> (f64, f64) {
+        let (xmax, ymax) = (f64::from(width) - 1.0, f64::from(height) - 1.0);
+        let x = if self.is_mirrored() { xmax - x } else { x };
+        match self.rotation_degrees() {
+            0 => (x, y),
+            90 => (ymax - y, x),
+            180 => (xmax - x, ymax - y),
+            _ => (y, xmax - x),
+        }
+    }
+
+    /// Transform pixel coordinates in the transformed image back to
+    /// coordinates in the original image of size `width` x `height`.
+    pub fn invert_point(&self, x: f64, y: f64, width: u32, height: u32) -> (f64, f64) {
+        let (xmax, ymax) = (f64::from(width) - 1.0, f64::from(height) - 1.0);
+        let (x, y) = match self.rotation_degrees() {
+            0 => (x, y),
+            90 => (y, ymax - x),
+            180 => (xmax - x, ymax - y),
+            _ => (xmax - y, x),
+        };
+        let x = if self.is_mirrored() { xmax - x } else { x };
+        (x, y)
+    }
+
+    /// The name used in configuration files and on the command line, e.g.
+    /// `mirror-rotate90`.
+    pub fn name(&self) -> &'static str {
+        use ImageTransform::*;
+        match self {
+            None => "none",
+            Rotate90 => "rotate90",
+            Rotate180 => "rotate180",
+            Rotate270 => "rotate270",
+            Mirror => "mirror",
+            MirrorRotate90 => "mirror-rotate90",
+            MirrorRotate180 => "mirror-rotate180",
+            MirrorRotate270 => "mirror-rotate270",
+        }
+    }
+}
+
+impl std::fmt::Display for ImageTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let rotation = self.rotation_degrees();
+        match (self.is_mirrored(), rotation) {
+            (false, 0) => write!(f, "None"),
+            (false, _) => write!(f, "Rotate {rotation}°"),
+            (true, 0) => write!(f, "Mirror"),
+            (true, _) => write!(f, "Mirror, rotate {rotation}°"),
+        }
+    }
+}
+
+impl std::str::FromStr for ImageTransform {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as enum_iter::EnumIter>::variants()
+            .into_iter()
+            .find(|t| t.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = <Self as enum_iter::EnumIter>::variants()
+                    .iter()
+                    .map(|t| t.name())
+                    .collect();
+                format!(
+                    "unknown image transform \"{s}\", expected one of: {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl enum_iter::EnumIter for ImageTransform {
+    fn variants() -> Vec<Self> {
+        use ImageTransform::*;
+        vec![
+            None,
+            Rotate90,
+            Rotate180,
+            Rotate270,
+            Mirror,
+            MirrorRotate90,
+            MirrorRotate180,
+            MirrorRotate270,
+        ]
+    }
+}
@@ -0,0 +1,227 @@
+use na::{DMatrix, Matrix3, Matrix3x4, Point2, Vector3};
+
+use flydra_types::RawCamName;
+
+use crate::MyFloat;
+
+/// Create a camera calibration from a homography between the image and the
+/// z=0 plane, e.g. the floor of an arena.
+///
+/// A homography determines the camera only up to its projection of points on
+/// the plane, which is all that is needed for tracking on the plane. The
+/// remaining degrees of freedom are chosen such that the camera looks onto the
+/// plane from above.
+pub struct HomographyCalibrationData {
+    pub cam_name: RawCamName,
+    pub width: u32,
+    pub height: u32,
+    /// Homography from distorted pixel coordinates to coordinates on the plane.
+    pub image_to_plane: Matrix3<MyFloat>,
+}
+
+impl HomographyCalibrationData {
+    pub fn to_cam(&self) -> Result<mvg::Camera<MyFloat>, mvg::MvgError> {
+        let plane_to_image =
+            self.image_to_plane
+                .try_inverse()
+                .ok_or_else(|| mvg::MvgError::InvalidCalibration {
+                    msg: "homography is singular".into(),
+                })?;
+
+        // Points on the plane must be in front of the camera, i.e. have a
+        // positive homogeneous coordinate in the image. Check with the point
+        // seen at the center of the image.
+        let center = self.image_to_plane
+            * Vector3::new(self.width as f64 * 0.5, self.height as f64 * 0.5, 1.0);
+        let center = center / center[2];
+        let plane_to_image = if (plane_to_image * center)[2] < 0.0 {
+            -plane_to_image
+        } else {
+            plane_to_image
+        };
+
+        // The camera matrix of a point (x, y, 0) on the plane is given by the
+        // homography. The column for z is chosen perpendicular to those for x
+        // and y with a similar norm, so that the camera is well conditioned and
+        // the plane is in front of it.
+        let h1 = plane_to_image.column(0).into_owned();
+        let h2 = plane_to_image.column(1).into_owned();
+        let h3 = plane_to_image.column(2).into_owned();
+        let n = h1.cross(&h2);
+        let n = n * ((h1.norm() * h2.norm()).sqrt() / n.norm());
+
+        let mut pmat = Matrix3x4::zeros();
+        pmat.set_column(0, &h1);
+        pmat.set_column(1, &h2);
+        pmat.set_column(2, &n);
+        pmat.set_column(3, &h3);
+
+        mvg::Camera::from_pmat(self.width as usize, self.height as usize, &pmat)
+    }
+
+    pub fn to_camera_system(
+        &self,
+    ) -> Result<flydra_mvg::FlydraMultiCameraSystem<MyFloat>, mvg::MvgError> {
+        let cam = self.to_cam()?;
+        let mut cams_by_name = std::collections::BTreeMap::new();
+        cams_by_name.insert(self.cam_name.as_str().to_string(), cam);
+
+        let data = serde_json::json!({
+            "homography_camera_calibration": 1,
+        });
+
+        let comment = serde_json::to_string(&data).unwrap();
+        let plain_vanilla = mvg::MultiCameraSystem::new_with_comment(cams_by_name, comment);
+        Ok(flydra_mvg::FlydraMultiCameraSystem::from_system(
+            plain_vanilla,
+            None,
+        ))
+    }
+}
+
+/// Apply the homography `h` to `pt`.
+pub fn apply_homography(h: &Matrix3<MyFloat>, pt: &Point2<MyFloat>) -> Point2<MyFloat> {
+    let v = h * pt.to_homogeneous();
+    Point2::new(v[0] / v[2], v[1] / v[2])
+}
+
+/// Similarity transform moving the centroid of `pts` to the origin and
+/// scaling their mean distance from it to sqrt(2).
+fn normalizing_transform(pts: &[Point2<MyFloat>]) -> Matrix3<MyFloat> {
+    let n = pts.len() as f64;
+    let cx = pts.iter().map(|p| p.x).sum::<f64>() / n;
+    let cy = pts.iter().map(|p| p.y).sum::<f64>() / n;
+    let mean_dist = pts
+        .iter()
+        .map(|p| ((p.x - cx).powi(2) + (p.y - cy).powi(2)).sqrt())
+        .sum::<f64>()
+        / n;
+    let s = if mean_dist > 0.0 {
+        std::f64::consts::SQRT_2 / mean_dist
+    } else {
+        1.0
+    };
+    Matrix3::new(s, 0.0, -s * cx, 0.0, s, -s * cy, 0.0, 0.0, 1.0)
+}
+
+/// Estimate the homography mapping each point in `src` to the corresponding
+/// point in `dst`.
+///
+/// This uses the normalized direct linear transform and requires at least
+/// four points, no three of which are collinear.
+pub fn estimate_homography(
+    src: &[Point2<MyFloat>],
+    dst: &[Point2<MyFloat>],
+) -> Result<Matrix3<MyFloat>, mvg::MvgError> {
+    if src.len() != dst.len() {
+        return Err(mvg::MvgError::InvalidShape);
+    }
+    if src.len() < 4 {
+        return Err(mvg::MvgError::NotEnoughPoints);
+    }
+    let t_src = normalizing_transform(src);
+    let t_dst = normalizing_transform(dst);
+
+    let mut a = DMatrix::<MyFloat>::zeros(2 * src.len(), 9);
+    for (i, (s, d)) in src.iter().zip(dst.iter()).enumerate() {
+        let s = apply_homography(&t_src, s);
+        let d = apply_homography(&t_dst, d);
+        let (x, y, u, v) = (s.x, s.y, d.x, d.y);
+        let rows = [
+            [-x, -y, -1.0, 0.0, 0.0, 0.0, u * x, u * y, u],
+            [0.0, 0.0, 0.0, -x, -y, -1.0, v * x, v * y, v],
+        ];
+        for (j, row) in rows.iter().enumerate() {
+            for (k, val) in row.iter().enumerate() {
+                a[(2 * i + j, k)] = *val;
+            }
+        }
+    }
+
+    let degenerate = || mvg::MvgError::InvalidCalibration {
+        msg: "points are degenerate, e.g. collinear".into(),
+    };
+
+    // The solution is the eigenvector of AᵀA with the smallest eigenvalue.
+    let eig = na::SymmetricEigen::new(a.transpose() * &a);
+    if !eig.eigenvalues.iter().all(|v| v.is_finite()) {
+        return Err(mvg::MvgError::SvdFailed);
+    }
+    let mut order: Vec<usize> = (0..eig.eigenvalues.len()).collect();
+    order.sort_by(|i, j| eig.eigenvalues[*i].total_cmp(&eig.eigenvalues[*j]));
+    // The solution is unique only if a single eigenvalue is zero.
+    let largest = eig.eigenvalues[order[order.len() - 1]];
+    if eig.eigenvalues[order[1]] <= 1e-12 * largest {
+        return Err(degenerate());
+    }
+    let h = eig.eigenvectors.column(order[0]);
+    let hn = Matrix3::new(h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7], h[8]);
+
+    let t_dst_inv = t_dst.try_inverse().ok_or(mvg::MvgError::SvdFailed)?;
+    let h = t_dst_inv * hn * t_src;
+    if h.determinant().abs() < 1e-12 * h.norm().powi(3) {
+        return Err(degenerate());
+    }
+    Ok(h / h[(2, 2)])
+}
+
+#[test]
+fn test_homography() {
+    // A camera looking obliquely onto the floor, with coordinates in meters.
+    let plane_to_image = Matrix3::new(
+        2000.0, 300.0, 640.0, //
+        -50.0, -1800.0, 512.0, //
+        0.1, 0.4, 1.0,
+    );
+    let image_to_plane = plane_to_image.try_inverse().unwrap();
+
+    let plane_pts: Vec<Point2<f64>> = [
+        (0.0, 0.0),
+        (0.1, 0.0),
+        (0.1, 0.1),
+        (0.0, 0.1),
+        (0.05, 0.02),
+        (-0.1, 0.05),
+    ]
+    .iter()
+    .map(|(x, y)| Point2::new(*x, *y))
+    .collect();
+    let image_pts: Vec<Point2<f64>> = plane_pts
+        .iter()
+        .map(|p| apply_homography(&plane_to_image, p))
+        .collect();
+
+    let estimated = estimate_homography(&image_pts, &plane_pts).unwrap();
+    approx::assert_relative_eq!(
+        estimated,
+        image_to_plane / image_to_plane[(2, 2)],
+        epsilon = 1e-9,
+        max_relative = 1e-6
+    );
+
+    assert!(matches!(
+        estimate_homography(&image_pts[..3], &plane_pts[..3]),
+        Err(mvg::MvgError::NotEnoughPoints)
+    ));
+    let collinear: Vec<Point2<f64>> = (0..5).map(|i| Point2::new(i as f64, 0.0)).collect();
+    assert!(matches!(
+        estimate_homography(&collinear, &plane_pts[..5]),
+        Err(mvg::MvgError::InvalidCalibration { .. })
+    ));
+
+    // The camera projects points on the plane like the homography.
+    let cal = HomographyCalibrationData {
+        cam_name: RawCamName::new("cam".to_string()),
+        width: 1280,
+        height: 1024,
+        image_to_plane,
+    };
+    let cam = cal.to_cam().unwrap();
+    for (plane_pt, image_pt) in plane_pts.iter().zip(image_pts.iter()) {
+        let world = mvg::PointWorldFrame {
+            coords: na::Point3::new(plane_pt.x, plane_pt.y, 0.0),
+        };
+        let projected = cam.project_3d_to_distorted_pixel(&world);
+        approx::assert_relative_eq!(projected.coords, *image_pt, max_relative = 1e-6);
+    }
+}
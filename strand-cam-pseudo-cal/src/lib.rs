@@ -5,6 +5,9 @@ pub type MyFloat = f64;
 
 use http_video_streaming_types::CircleParams;
 
+mod homography;
+pub use homography::{apply_homography, estimate_homography, HomographyCalibrationData};
+
 // TODO check KalmanTrackingConfig
 // see:
 // flydratrax_handle_msg::flydratrax_handle_msg
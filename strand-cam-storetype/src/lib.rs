@@ -1,11 +1,14 @@
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
 
-use rust_cam_bui_types::RecordingPath;
+use rust_cam_bui_types::{DiskSpace, ExperimentMetadata, ImageTransform, RecordingPath};
 use serde::{Deserialize, Serialize};
 
-use http_video_streaming_types::{CircleParams, Shape};
+use http_video_streaming_types::{CircleParams, PreviewCrop, Shape};
 
-use ci2_remote_control::{BitrateSelection, CodecSelection, RecordingFrameRate, TagFamily};
+use ci2_remote_control::{
+    BitrateSelection, CheckerboardPattern, CodecSelection, ImOpsFormat, Mp4OverlayConfig,
+    PreviewJpegQuality, PreviewMaxWidth, RecordingFrameRate, TagFamily,
+};
 use flydra_feature_detector_types::ImPtDetectCfg;
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -27,6 +30,10 @@ pub use led_box_comms::ToDevice as ToLedBoxDevice;
 // should work at `http://braid/cam-proxy/cam-name/strand-cam-events` as well as
 // `http://strand-cam/strand-cam-events`.
 pub const STRAND_CAM_EVENTS_URL_PATH: &str = "strand-cam-events";
+/// WebSocket carrying the same events as [STRAND_CAM_EVENTS_URL_PATH] and, in
+/// the other direction, the callbacks. (Also without a leading slash.) This is
+/// not proxied by Braid.
+pub const STRAND_CAM_WS_URL_PATH: &str = "strand-cam-ws";
 pub const STRAND_CAM_EVENT_NAME: &str = "strand-cam";
 pub const CONN_KEY_EVENT_NAME: &str = "connection-key";
 
@@ -45,8 +52,12 @@ pub struct StoreType {
     pub is_recording_mp4: Option<RecordingPath>,
     /// is saving FMF file
     pub is_recording_fmf: Option<RecordingPath>,
+    /// Whether FMF files are saved with lossless zstd compression (FMF v4).
+    pub fmf_zstd_compression: bool,
     /// is saving UFMF file
     pub is_recording_ufmf: Option<RecordingPath>,
+    /// Whether the ongoing MP4, FMF and CSV recordings are paused
+    pub is_recording_paused: bool,
     pub format_str_mp4: String,
     pub format_str: String,
     pub format_str_ufmf: String,
@@ -59,10 +70,31 @@ pub struct StoreType {
     pub mp4_codec: CodecSelection,
     /// CUDA device number (only used if using nvidia encoder)
     pub mp4_cuda_device: String,
+    /// Maximum duration of a single MP4 file before continuing in a new file.
+    pub mp4_rollover_minutes: Option<f64>,
+    /// Maximum size of a single MP4 file before continuing in a new file.
+    pub mp4_rollover_gb: Option<f64>,
+    /// Text burned into the frames of new MP4 recordings.
+    pub mp4_overlay: Mp4OverlayConfig,
+    /// Whether the detected points are drawn into the frames of MP4
+    /// recordings (debug recording).
+    pub mp4_draw_detections: bool,
+    /// Maximum width of the frames sent to the live view.
+    pub preview_max_width: PreviewMaxWidth,
+    /// JPEG quality of the frames sent to the live view.
+    pub preview_jpeg_quality: PreviewJpegQuality,
+    /// The region of the camera image shown in the live view, saved per
+    /// camera. The recorded and tracked images are not cropped.
+    pub preview_crop: Option<PreviewCrop>,
     pub gain_auto: Option<ci2_types::AutoMode>,
     pub gain: RangedValue,
     pub exposure_auto: Option<ci2_types::AutoMode>,
     pub exposure_time: RangedValue,
+    /// Auto-exposure computed by Strand Camera from a region of the image.
+    pub soft_auto_exposure_config: SoftAutoExposureConfig,
+    /// The intensity at the percentile of [SoftAutoExposureConfig], as most
+    /// recently measured. This is None when software auto-exposure is off.
+    pub soft_auto_exposure_level: Option<u8>,
     pub frame_rate_limit_enabled: bool,
     /// None when frame_rate_limit is not supported
     pub frame_rate_limit: Option<RangedValue>,
@@ -70,6 +102,12 @@ pub struct StoreType {
     pub trigger_selector: ci2_types::TriggerSelector,
     pub image_width: u32,
     pub image_height: u32,
+    /// Rotation and mirroring applied to the camera images. The image width
+    /// and height are those of the transformed images.
+    pub image_transform: ImageTransform,
+    /// The image transform saved to be applied after Strand Camera is
+    /// restarted, if it differs from the current one.
+    pub image_transform_after_restart: Option<ImageTransform>,
     /// Whether object detection with image-tracker crate is compiled.
     // We could have made this a cargo feature, but this
     // adds complication to the builds. Here, the cost
@@ -105,6 +143,202 @@ pub struct StoreType {
     pub had_frame_processing_error: bool,
     /// The camera calibration (does not contain potential information about water)
     pub camera_calibration: Option<mvg::Camera<f64>>,
+    /// Status of the camera PTP clock. This is None if PTP is not used.
+    pub ptp_status: Option<PtpStatus>,
+    /// Statistics of the pool of acquired frame buffers. This is None if the
+    /// camera backend does not use a frame pool.
+    pub frame_pool_stats: Option<ci2_types::FramePoolStats>,
+    /// Latency of frame processing and the resulting degradation.
+    pub processing_watchdog: ProcessingWatchdogStatus,
+    /// Space on the volume of the data directory. This is None until checked.
+    pub disk_space: Option<DiskSpace>,
+    /// Names of the saved settings profiles.
+    pub settings_profiles: Vec<String>,
+    /// Name of the settings profile most recently saved or loaded.
+    pub current_settings_profile: Option<String>,
+    /// Description of the experiment, stored in recorded MP4 files.
+    pub experiment_metadata: ExperimentMetadata,
+    /// Which events are logged, in the syntax of the `RUST_LOG` environment
+    /// variable.
+    pub log_filter: String,
+    /// The pixel most recently inspected in the live view.
+    pub inspected_pixel: Option<InspectedPixel>,
+    /// The running or most recent latency test.
+    pub latency_test: LatencyTestState,
+}
+
+/// Configuration of a latency test.
+///
+/// An LED box channel is repeatedly switched on and the time until the light
+/// is detected by the ImOps detector and its UDP message is sent is measured.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LatencyTestConfig {
+    /// The LED box channel (1-4) to switch.
+    pub led_channel: u8,
+    /// The number of times the LED is switched on.
+    pub n_trials: u32,
+    /// The minimum duration the LED is off before it is switched on again.
+    pub off_msec: u32,
+    /// The LED is switched off again if it is not detected within this
+    /// duration, and the trial is counted as missed.
+    pub timeout_msec: u32,
+}
+
+impl Default for LatencyTestConfig {
+    fn default() -> Self {
+        Self {
+            led_channel: 1,
+            n_trials: 100,
+            off_msec: 200,
+            timeout_msec: 1000,
+        }
+    }
+}
+
+/// The state of the latency test.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LatencyTestState {
+    /// Whether a test is running.
+    pub running: bool,
+    pub config: LatencyTestConfig,
+    /// The result so far of the running test, or of the most recent test.
+    pub result: Option<LatencyTestResult>,
+}
+
+/// The latencies measured by a latency test.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LatencyTestResult {
+    /// The latency of each detected trial, from the LED switching command to
+    /// sending the UDP message, in milliseconds.
+    pub latencies_msec: Vec<f64>,
+    /// The number of trials in which the LED was not detected.
+    pub n_missed: u32,
+}
+
+impl LatencyTestResult {
+    /// The number of trials done.
+    pub fn n_trials(&self) -> u32 {
+        self.latencies_msec.len() as u32 + self.n_missed
+    }
+
+    /// The latency at quantile `q` (0-1), or `None` if no latency was measured.
+    ///
+    /// The nearest sample at or above the quantile is returned.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let mut sorted = self.latencies_msec.clone();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len();
+        if n == 0 {
+            return None;
+        }
+        let rank = (q.clamp(0.0, 1.0) * n as f64).ceil().max(1.0) as usize;
+        Some(sorted[rank - 1])
+    }
+
+    /// The mean latency, or `None` if no latency was measured.
+    pub fn mean(&self) -> Option<f64> {
+        let n = self.latencies_msec.len();
+        if n == 0 {
+            return None;
+        }
+        Some(self.latencies_msec.iter().sum::<f64>() / n as f64)
+    }
+}
+
+impl std::fmt::Display for LatencyTestResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} trials, {} missed", self.n_trials(), self.n_missed)?;
+        if let (Some(mean), Some(min), Some(median), Some(p95), Some(p99), Some(max)) = (
+            self.mean(),
+            self.quantile(0.0),
+            self.quantile(0.5),
+            self.quantile(0.95),
+            self.quantile(0.99),
+            self.quantile(1.0),
+        ) {
+            write!(
+                f,
+                ", latency (msec): mean {mean:.2}, min {min:.2}, median {median:.2}, \
+                95% {p95:.2}, 99% {p99:.2}, max {max:.2}"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The value of a pixel of the most recent frame, as requested with
+/// [CallbackType::InspectPixel].
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InspectedPixel {
+    pub x: u32,
+    pub y: u32,
+    /// The number of the frame which was inspected.
+    pub fno: u64,
+    /// The value of each channel, e.g. one for Mono8 and three for RGB8.
+    pub values: Vec<f64>,
+}
+
+/// Status of the PTP (IEEE 1588) clock of the camera.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PtpStatus {
+    /// The PTP state of the camera (e.g. "Slave" or "Master").
+    pub status: String,
+    /// The state of the clock servo, if reported by the camera.
+    pub servo_status: Option<String>,
+    /// Estimated offset of the camera clock from the master clock.
+    pub offset_from_master_nsec: i64,
+    /// Whether the offset is within the configured lock threshold.
+    pub is_locked: bool,
+}
+
+/// How much frame processing is reduced to keep up with the camera.
+///
+/// Frames are never skipped for recording.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize, Default)]
+pub enum DegradationLevel {
+    /// All frames are fully processed.
+    #[default]
+    Normal,
+    /// Only some frames are sent to the live view.
+    SkipPreview,
+    /// Additionally, feature detection runs on only some frames.
+    ReduceDetection,
+}
+
+impl std::fmt::Display for DegradationLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Normal => "normal",
+            Self::SkipPreview => "skipping live view frames",
+            Self::ReduceDetection => "skipping live view frames and feature detection",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Latency of frame processing, averaged over a recent interval.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessingWatchdogStatus {
+    pub level: DegradationLevel,
+    /// Interval between frames from the camera. This is None until the frame
+    /// rate is measured.
+    pub frame_interval_msec: Option<f64>,
+    /// Mean time from acquisition until processing of a frame starts.
+    pub queue_msec: f64,
+    /// Mean time for feature detection and other image analysis of a frame.
+    pub detect_msec: f64,
+    /// Mean time for writing a frame to the recordings.
+    pub record_msec: f64,
+    /// Mean time for sending a frame to the live view.
+    pub preview_msec: f64,
+    /// Mean total processing time of a frame.
+    pub total_msec: f64,
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
@@ -125,6 +359,16 @@ pub struct ImOpsState {
     pub center_x: u32,
     pub center_y: u32,
     pub threshold: u8,
+    /// The maximum number of points sent. Ignored by
+    /// [ImOpsFormat::CborCentroid], which always sends a single point.
+    #[serde(default = "default_im_ops_max_points")]
+    pub max_points: u8,
+    #[serde(default)]
+    pub format: ImOpsFormat,
+}
+
+fn default_im_ops_max_points() -> u8 {
+    1
 }
 
 impl Default for ImOpsState {
@@ -136,6 +380,8 @@ impl Default for ImOpsState {
             center_x: 0,
             center_y: 0,
             threshold: 0,
+            max_points: default_im_ops_max_points(),
+            format: ImOpsFormat::default(),
         }
     }
 }
@@ -167,6 +413,59 @@ impl std::default::Default for KalmanTrackingConfig {
     }
 }
 
+/// Auto-exposure computed by Strand Camera from the image.
+///
+/// Unlike the auto-exposure of the camera, which considers the whole frame,
+/// only the pixels within `region` (and optionally near the detected points)
+/// are considered. The exposure time, and optionally the gain, are adjusted so
+/// that the pixel intensity at `percentile` is held at `target`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoftAutoExposureConfig {
+    pub enabled: bool,
+    /// The pixels considered, in pixel coordinates of the full frame.
+    pub region: Shape,
+    /// If set, only the pixels of `region` within this distance (in pixels)
+    /// of the points detected in the frame are considered. If no point is
+    /// detected, all pixels of `region` are considered.
+    pub detection_radius: Option<u16>,
+    /// The percentile (0-100) of the pixel intensities held at `target`.
+    pub percentile: f64,
+    /// The target intensity (0-255).
+    pub target: f64,
+    /// No adjustment is made while the intensity differs from `target` by at
+    /// most this much.
+    pub tolerance: f64,
+    /// Minimum interval between adjustments, in milliseconds.
+    pub interval_msec: u64,
+    /// Maximum relative change of the brightness per adjustment, e.g. `0.2`
+    /// for 20%.
+    pub max_step: f64,
+    /// Upper limit of the exposure time, e.g. to limit motion blur. If not
+    /// set, the limit of the camera applies.
+    pub max_exposure_time_usec: Option<f64>,
+    /// Whether the gain is adjusted once the exposure time is at its limit.
+    /// The gain is assumed to be in dB.
+    pub adjust_gain: bool,
+}
+
+impl std::default::Default for SoftAutoExposureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            region: Shape::Everything,
+            detection_radius: None,
+            percentile: 95.0,
+            target: 200.0,
+            tolerance: 10.0,
+            interval_msec: 500,
+            max_step: 0.2,
+            max_exposure_time_usec: None,
+            adjust_gain: false,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct LedProgramConfig {
@@ -200,6 +499,14 @@ pub struct CheckerboardCalState {
     pub num_checkerboards_collected: u32,
     pub width: u32,
     pub height: u32,
+    pub pattern: CheckerboardPattern,
+    /// For ChArUco boards, the side length of the marker divided by the side
+    /// length of the square.
+    pub charuco_marker_ratio: f64,
+    /// Number of collected corners in each region of the image.
+    pub coverage: CheckerboardCoverage,
+    /// Quality of the most recent calibration.
+    pub last_calibration: Option<CheckerboardCalQuality>,
 }
 
 impl Default for CheckerboardCalState {
@@ -209,6 +516,108 @@ impl Default for CheckerboardCalState {
             num_checkerboards_collected: 0,
             width: 8,
             height: 6,
+            pattern: CheckerboardPattern::default(),
+            charuco_marker_ratio: 0.75,
+            coverage: CheckerboardCoverage::default(),
+            last_calibration: None,
+        }
+    }
+}
+
+/// Number of checkerboard corners detected in each cell of a coarse grid
+/// covering the image sensor.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckerboardCoverage {
+    pub n_cols: u32,
+    pub n_rows: u32,
+    /// Row-major counts with `n_rows * n_cols` entries.
+    pub counts: Vec<u32>,
+}
+
+impl Default for CheckerboardCoverage {
+    fn default() -> Self {
+        let n_cols = 8;
+        let n_rows = 6;
+        Self {
+            n_cols,
+            n_rows,
+            counts: vec![0; (n_cols * n_rows) as usize],
+        }
+    }
+}
+
+impl CheckerboardCoverage {
+    /// Fraction of grid cells containing at least one corner.
+    pub fn fraction_covered(&self) -> f64 {
+        if self.counts.is_empty() {
+            return 0.0;
+        }
+        let n_covered = self.counts.iter().filter(|c| **c > 0).count();
+        n_covered as f64 / self.counts.len() as f64
+    }
+}
+
+/// Reprojection statistics of a checkerboard calibration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckerboardCalQuality {
+    pub num_checkerboards: u32,
+    pub num_corners: u32,
+    /// Root mean square reprojection distance, in pixels.
+    pub rms_pixels: f64,
+    pub median_pixels: f64,
+    pub p95_pixels: f64,
+    pub max_pixels: f64,
+    /// Histogram of per-corner reprojection distances.
+    ///
+    /// Bin `i` counts distances in `[i*bin_width, (i+1)*bin_width)`. The last
+    /// bin also includes all larger distances.
+    pub residual_histogram: Vec<u32>,
+    pub histogram_bin_width_pixels: f64,
+    /// Sensor coverage of the corners used for calibration.
+    pub coverage: CheckerboardCoverage,
+}
+
+impl CheckerboardCalQuality {
+    /// Compute statistics from per-corner reprojection distances.
+    pub fn new(residuals: &[Vec<f64>], coverage: CheckerboardCoverage) -> Self {
+        const N_BINS: usize = 10;
+        const BIN_WIDTH: f64 = 0.25;
+
+        let mut all: Vec<f64> = residuals.iter().flatten().copied().collect();
+        all.sort_by(|a, b| a.total_cmp(b));
+        let n = all.len();
+
+        let mut residual_histogram = vec![0; N_BINS];
+        for r in all.iter() {
+            let bin = ((r / BIN_WIDTH) as usize).min(N_BINS - 1);
+            residual_histogram[bin] += 1;
+        }
+
+        let percentile = |p: f64| -> f64 {
+            if n == 0 {
+                return 0.0;
+            }
+            all[((n - 1) as f64 * p).round() as usize]
+        };
+
+        let rms_pixels = if n == 0 {
+            0.0
+        } else {
+            (all.iter().map(|r| r * r).sum::<f64>() / n as f64).sqrt()
+        };
+
+        Self {
+            num_checkerboards: residuals.len().try_into().unwrap(),
+            num_corners: n.try_into().unwrap(),
+            rms_pixels,
+            median_pixels: percentile(0.5),
+            p95_pixels: percentile(0.95),
+            max_pixels: all.last().copied().unwrap_or(0.0),
+            residual_histogram,
+            histogram_bin_width_pixels: BIN_WIDTH,
+            coverage,
         }
     }
 }
@@ -222,5 +631,193 @@ pub enum CallbackType {
     TakeCurrentImageAsBackground,
     // used only with image-tracker crate
     ClearBackground(f32),
+    // used only with image-tracker crate
+    SaveBackgroundModel,
+    /// Save the next frame as an OME-TIFF file.
+    ExportFrameOmeTiff,
+    /// Read the value of the pixel at the given x and y coordinates of the
+    /// most recent frame into [StoreType::inspected_pixel].
+    InspectPixel(u32, u32),
+    /// Set the ImOps threshold from the intensities of the most recent frame
+    /// around the ImOps center.
+    ImOpsAutoThreshold,
+    /// Start a latency test of the ImOps detector, see [LatencyTestConfig].
+    StartLatencyTest(LatencyTestConfig),
+    StopLatencyTest,
+    // used only with image-tracker crate
+    ExportBackgroundOmeTiff,
     ToLedBox(ToLedBoxDevice),
 }
+
+impl CallbackType {
+    /// Whether this callback is allowed with read-only access.
+    ///
+    /// This is the case for callbacks which only concern the connection of the
+    /// browser making them.
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, CallbackType::FirehoseNotify(_))
+    }
+}
+
+/// Runtime settings of Strand Camera which can be saved to, and loaded from, a
+/// named profile.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SettingsProfile {
+    pub exposure_auto: Option<ci2_types::AutoMode>,
+    pub exposure_time: f64,
+    pub gain_auto: Option<ci2_types::AutoMode>,
+    pub gain: f64,
+    #[serde(default)]
+    pub soft_auto_exposure_config: SoftAutoExposureConfig,
+    pub frame_rate_limit_enabled: bool,
+    pub frame_rate_limit: Option<f64>,
+    pub mp4_max_framerate: RecordingFrameRate,
+    pub mp4_bitrate: BitrateSelection,
+    pub mp4_codec: CodecSelection,
+    pub mp4_cuda_device: String,
+    pub mp4_rollover_minutes: Option<f64>,
+    pub mp4_rollover_gb: Option<f64>,
+    #[serde(default)]
+    pub mp4_overlay: Mp4OverlayConfig,
+    #[serde(default)]
+    pub mp4_draw_detections: bool,
+    #[serde(default)]
+    pub preview_max_width: PreviewMaxWidth,
+    #[serde(default)]
+    pub preview_jpeg_quality: PreviewJpegQuality,
+    pub post_trigger_buffer_size: usize,
+    pub im_pt_detect_cfg: ImPtDetectCfg,
+    pub kalman_tracking_config: KalmanTrackingConfig,
+    pub led_program_config: LedProgramConfig,
+    pub checkerboard_width: u32,
+    pub checkerboard_height: u32,
+    pub checkerboard_pattern: CheckerboardPattern,
+    pub charuco_marker_ratio: f64,
+    /// This is None if no apriltag support is compiled in.
+    pub april_family: Option<TagFamily>,
+    pub im_ops_state: ImOpsState,
+}
+
+impl SettingsProfile {
+    /// Take the current settings from the store.
+    pub fn from_store(shared: &StoreType) -> Self {
+        Self {
+            exposure_auto: shared.exposure_auto,
+            exposure_time: shared.exposure_time.current,
+            gain_auto: shared.gain_auto,
+            gain: shared.gain.current,
+            soft_auto_exposure_config: shared.soft_auto_exposure_config.clone(),
+            frame_rate_limit_enabled: shared.frame_rate_limit_enabled,
+            frame_rate_limit: shared.frame_rate_limit.as_ref().map(|x| x.current),
+            mp4_max_framerate: shared.mp4_max_framerate.clone(),
+            mp4_bitrate: shared.mp4_bitrate.clone(),
+            mp4_codec: shared.mp4_codec.clone(),
+            mp4_cuda_device: shared.mp4_cuda_device.clone(),
+            mp4_rollover_minutes: shared.mp4_rollover_minutes,
+            mp4_rollover_gb: shared.mp4_rollover_gb,
+            mp4_overlay: shared.mp4_overlay.clone(),
+            mp4_draw_detections: shared.mp4_draw_detections,
+            preview_max_width: shared.preview_max_width.clone(),
+            preview_jpeg_quality: shared.preview_jpeg_quality.clone(),
+            post_trigger_buffer_size: shared.post_trigger_buffer_size,
+            im_pt_detect_cfg: shared.im_pt_detect_cfg.clone(),
+            kalman_tracking_config: shared.kalman_tracking_config.clone(),
+            led_program_config: shared.led_program_config.clone(),
+            checkerboard_width: shared.checkerboard_data.width,
+            checkerboard_height: shared.checkerboard_data.height,
+            checkerboard_pattern: shared.checkerboard_data.pattern.clone(),
+            charuco_marker_ratio: shared.checkerboard_data.charuco_marker_ratio,
+            april_family: shared
+                .apriltag_state
+                .as_ref()
+                .map(|ts| ts.april_family.clone()),
+            im_ops_state: shared.im_ops_state.clone(),
+        }
+    }
+
+    /// The camera commands which apply these settings.
+    ///
+    /// Manual exposure and gain values are only applied when the
+    /// corresponding automatic mode is off.
+    pub fn cam_args(&self) -> Vec<ci2_remote_control::CamArg> {
+        use ci2_remote_control::CamArg;
+        use ci2_types::AutoMode;
+
+        let mut result = Vec::new();
+        if let Some(v) = self.exposure_auto {
+            result.push(CamArg::SetExposureAuto(v));
+        }
+        if self.exposure_auto.unwrap_or(AutoMode::Off) == AutoMode::Off {
+            result.push(CamArg::SetExposureTime(self.exposure_time));
+        }
+        if let Some(v) = self.gain_auto {
+            result.push(CamArg::SetGainAuto(v));
+        }
+        if self.gain_auto.unwrap_or(AutoMode::Off) == AutoMode::Off {
+            result.push(CamArg::SetGain(self.gain));
+        }
+        result.push(CamArg::SetSoftAutoExposureConfig(
+            serde_yaml::to_string(&self.soft_auto_exposure_config).unwrap(),
+        ));
+        result.push(CamArg::SetFrameRateLimitEnabled(
+            self.frame_rate_limit_enabled,
+        ));
+        if let Some(v) = self.frame_rate_limit {
+            result.push(CamArg::SetFrameRateLimit(v));
+        }
+        result.push(CamArg::SetMp4MaxFramerate(self.mp4_max_framerate.clone()));
+        result.push(CamArg::SetMp4Bitrate(self.mp4_bitrate.clone()));
+        result.push(CamArg::SetMp4Codec(self.mp4_codec.clone()));
+        result.push(CamArg::SetMp4CudaDevice(self.mp4_cuda_device.clone()));
+        result.push(CamArg::SetMp4RolloverMinutes(self.mp4_rollover_minutes));
+        result.push(CamArg::SetMp4RolloverGb(self.mp4_rollover_gb));
+        result.push(CamArg::SetMp4Overlay(self.mp4_overlay.clone()));
+        result.push(CamArg::SetMp4DrawDetections(self.mp4_draw_detections));
+        result.push(CamArg::SetPreviewMaxWidth(self.preview_max_width.clone()));
+        result.push(CamArg::SetPreviewJpegQuality(
+            self.preview_jpeg_quality.clone(),
+        ));
+        result.push(CamArg::SetPostTriggerBufferSize(
+            self.post_trigger_buffer_size,
+        ));
+        result.push(CamArg::SetObjDetectionConfig(
+            serde_yaml::to_string(&self.im_pt_detect_cfg).unwrap(),
+        ));
+        result.push(CamArg::CamArgSetKalmanTrackingConfig(
+            serde_yaml::to_string(&self.kalman_tracking_config).unwrap(),
+        ));
+        result.push(CamArg::CamArgSetLedProgramConfig(
+            serde_yaml::to_string(&self.led_program_config).unwrap(),
+        ));
+        result.push(CamArg::SetCheckerboardWidth(self.checkerboard_width));
+        result.push(CamArg::SetCheckerboardHeight(self.checkerboard_height));
+        result.push(CamArg::SetCheckerboardPattern(
+            self.checkerboard_pattern.clone(),
+        ));
+        result.push(CamArg::SetCharucoMarkerRatio(self.charuco_marker_ratio));
+        if let Some(family) = &self.april_family {
+            result.push(CamArg::ToggleAprilTagFamily(family.clone()));
+        }
+        result.push(CamArg::ToggleImOpsDetection(self.im_ops_state.do_detection));
+        result.push(CamArg::SetImOpsDestination(self.im_ops_state.destination));
+        result.push(CamArg::SetImOpsSource(self.im_ops_state.source));
+        result.push(CamArg::SetImOpsCenterX(self.im_ops_state.center_x));
+        result.push(CamArg::SetImOpsCenterY(self.im_ops_state.center_y));
+        result.push(CamArg::SetImOpsThreshold(self.im_ops_state.threshold));
+        result.push(CamArg::SetImOpsMaxPoints(self.im_ops_state.max_points));
+        result.push(CamArg::SetImOpsFormat(self.im_ops_state.format));
+        result
+    }
+}
+
+/// Whether `name` may be used as the name of a settings profile.
+///
+/// Names are used as file names, so only ASCII letters, digits, `-` and `_`
+/// are allowed.
+pub fn is_valid_settings_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
@@ -2,7 +2,7 @@ use std::path::PathBuf;
 
 use clap::{arg, FromArgMatches};
 
-use clap::{Arg, ArgAction, Args};
+use clap::{Arg, ArgAction, Args, Subcommand};
 
 use crate::{run_strand_cam_app, BraidArgs, StandaloneArgs, StandaloneOrBraid, StrandCamArgs};
 
@@ -59,6 +59,50 @@ struct DerivedArgs {
     #[arg(long)]
     data_dir: Option<PathBuf>,
 
+    /// Warn when less than this many megabytes are free in the data directory.
+    #[arg(long, default_value_t = disk_space_monitor::DEFAULT_WARNING_THRESHOLD_MB)]
+    disk_space_warning_mb: u64,
+
+    /// Stop recordings when less than this many megabytes are free in the data
+    /// directory.
+    #[arg(long, default_value_t = disk_space_monitor::DEFAULT_STOP_THRESHOLD_MB)]
+    disk_space_stop_mb: u64,
+
+    /// TOML file configuring notifications about runtime problems, e.g. by
+    /// Slack or email. Within Braid, notifications are configured in the Braid
+    /// configuration file instead.
+    #[arg(long)]
+    notification_config: Option<PathBuf>,
+
+    /// Write the log files as JSON.
+    #[arg(long)]
+    log_json: bool,
+
+    /// Token granting read-only access to the web UI, with which the state and
+    /// the live view can be seen but nothing can be changed. Can be given more
+    /// than once. Only used if the web UI requires a token, i.e. if it is not
+    /// only listening on the loopback interface.
+    #[arg(long = "read-only-token")]
+    read_only_tokens: Vec<String>,
+
+    /// Serve the web UI over HTTPS. Without `--tls-cert-file` and
+    /// `--tls-key-file`, a self-signed certificate is generated.
+    #[arg(long)]
+    tls: bool,
+
+    /// PEM file with the certificate chain for HTTPS. Implies `--tls`.
+    #[arg(long, requires = "tls_key_file")]
+    tls_cert_file: Option<PathBuf>,
+
+    /// PEM file with the private key for HTTPS. Implies `--tls`.
+    #[arg(long, requires = "tls_cert_file")]
+    tls_key_file: Option<PathBuf>,
+
+    /// Do not advertise the web UI on the local network by mDNS (zeroconf).
+    /// It is advertised only if not listening only on the loopback interface.
+    #[arg(long)]
+    no_mdns: bool,
+
     #[cfg(feature = "eframe-gui")]
     /// windowed means "not fullscreen"
     ///
@@ -68,6 +112,42 @@ struct DerivedArgs {
     pub windowed: Option<bool>,
 }
 
+#[derive(Subcommand, Debug)]
+enum DerivedSubcommand {
+    /// Measure the latency of the ImOps detector, log the result and quit.
+    ///
+    /// An LED box channel is repeatedly switched on and the time until the
+    /// light is detected and the ImOps UDP message is sent is measured. The
+    /// LED must be visible to the camera and bright enough to exceed the ImOps
+    /// threshold, and the LED box must be given with `--led-box`.
+    LatencyTest(LatencyTestArgs),
+}
+
+#[derive(Args, Debug)]
+struct LatencyTestArgs {
+    /// The LED box channel (1-4) to switch.
+    #[arg(long, default_value_t = 1)]
+    led_channel: u8,
+
+    /// The number of times the LED is switched on.
+    #[arg(long, default_value_t = 100)]
+    n_trials: u32,
+
+    /// The minimum duration, in milliseconds, the LED is off between trials.
+    #[arg(long, default_value_t = 200)]
+    off_msec: u32,
+
+    /// The duration, in milliseconds, after which an undetected trial is
+    /// counted as missed.
+    #[arg(long, default_value_t = 1000)]
+    timeout_msec: u32,
+
+    /// The ImOps threshold. If not given, the threshold of the loaded settings
+    /// is used.
+    #[arg(long)]
+    threshold: Option<u8>,
+}
+
 fn parse_args(app_name: &str) -> Result<StrandCamArgs> {
     let cli_args: Vec<String> = std::env::args().collect();
 
@@ -142,6 +222,12 @@ fn parse_args(app_name: &str) -> Result<StrandCamArgs> {
                         .help("The desired pixel format. (incompatible with braid).")
                         ,
                 )
+                .arg(
+                    Arg::new("image_transform")
+                        .long("image-transform")
+                        .value_parser(clap::value_parser!(rust_cam_bui_types::ImageTransform))
+                        .help("Rotation and mirroring of the camera images, e.g. \"rotate180\" for an upside down camera. Defaults to the value last set in the web UI. (incompatible with braid)."),
+                )
                 .arg(
                     clap::Arg::new("strand_cam_cookie_secret")
                         .help("The secret (base64 encoded) for signing HTTP cookies.")
@@ -193,9 +279,17 @@ fn parse_args(app_name: &str) -> Result<StrandCamArgs> {
                         .help("The address of the model server.")
                         .default_value(flydra_types::DEFAULT_MODEL_SERVER_ADDR),
                 )
+                .arg(
+                    Arg::new("rerun")
+                        .long("rerun")
+                        .env("RERUN_VIEWER_ADDR")
+                        .value_parser(clap::value_parser!(flydra2::RerunTarget))
+                        .help("Address of a rerun viewer or name of an .rrd file to stream tracking to."),
+                )
         };
 
         let parser = DerivedArgs::augment_args(parser);
+        let parser = DerivedSubcommand::augment_subcommands(parser);
 
         parser.get_matches_from(cli_args)
     };
@@ -274,6 +368,9 @@ fn parse_args(app_name: &str) -> Result<StrandCamArgs> {
         .parse()
         .unwrap();
 
+    #[cfg(feature = "flydratrax")]
+    let rerun = matches.get_one::<flydra2::RerunTarget>("rerun").cloned();
+
     let led_box_device_path = parse_led_box_device(&matches);
 
     let braid_url: Option<String> = matches.get_one::<String>("braid_url").map(Into::into);
@@ -281,6 +378,7 @@ fn parse_args(app_name: &str) -> Result<StrandCamArgs> {
     let standalone_or_braid = if let Some(braid_url) = braid_url {
         for argname in &[
             "pixel_format",
+            "image_transform",
             "JWT_SECRET",
             "camera_settings_filename",
             "http_server_addr",
@@ -313,6 +411,9 @@ fn parse_args(app_name: &str) -> Result<StrandCamArgs> {
     } else {
         // not braid
         let pixel_format = matches.get_one::<String>("pixel_format").map(Into::into);
+        let image_transform = matches
+            .get_one::<rust_cam_bui_types::ImageTransform>("image_transform")
+            .copied();
         let force_camera_sync_mode = !matches!(matches.get_count("force_camera_sync_mode"), 0);
         let software_limit_framerate = flydra_types::StartSoftwareFrameRateLimit::NoChange;
 
@@ -327,6 +428,7 @@ fn parse_args(app_name: &str) -> Result<StrandCamArgs> {
         StandaloneOrBraid::Standalone(StandaloneArgs {
             camera_name,
             pixel_format,
+            image_transform,
             force_camera_sync_mode,
             software_limit_framerate,
             acquisition_duration_allowed_imprecision_msec,
@@ -360,6 +462,58 @@ fn parse_args(app_name: &str) -> Result<StrandCamArgs> {
         .map_err(|err| err.exit())
         .unwrap();
 
+    let latency_test = match matches.subcommand_name() {
+        Some(_) => {
+            let DerivedSubcommand::LatencyTest(test_args) =
+                DerivedSubcommand::from_arg_matches(&matches)
+                    .map_err(|err| err.exit())
+                    .unwrap();
+            Some(crate::StartupLatencyTest {
+                config: strand_cam_storetype::LatencyTestConfig {
+                    led_channel: test_args.led_channel,
+                    n_trials: test_args.n_trials,
+                    off_msec: test_args.off_msec,
+                    timeout_msec: test_args.timeout_msec,
+                },
+                im_ops_threshold: test_args.threshold,
+            })
+        }
+        None => None,
+    };
+
+    let notifications = match &derived_matches.notification_config {
+        Some(path) => {
+            if let StandaloneOrBraid::Braid(_) = &standalone_or_braid {
+                eyre::bail!(
+                    "'notification_config' cannot be set from the command line when calling \
+                    strand-cam from braid.",
+                );
+            }
+            let buf = std::fs::read_to_string(path)
+                .with_context(|| format!("reading \"{}\"", path.display()))?;
+            let cfg: alert_notifier::NotificationConfig =
+                toml::from_str(&buf).with_context(|| format!("parsing \"{}\"", path.display()))?;
+            Some(cfg)
+        }
+        None => None,
+    };
+
+    let tls = if derived_matches.tls || derived_matches.tls_cert_file.is_some() {
+        if let StandaloneOrBraid::Braid(_) = &standalone_or_braid {
+            // Braid connects to Strand Camera by HTTP.
+            eyre::bail!(
+                "'tls' cannot be set from the command line when calling strand-cam from \
+                braid. Configure HTTPS in the Braid configuration file instead.",
+            );
+        }
+        Some(https_server::TlsConfig {
+            cert_file: derived_matches.tls_cert_file,
+            key_file: derived_matches.tls_key_file,
+        })
+    } else {
+        None
+    };
+
     // There are some fields set by `Default::default()` but only when various
     // cargo features are used. So turn off this clippy warning.
     #[allow(clippy::needless_update)]
@@ -379,13 +533,23 @@ fn parse_args(app_name: &str) -> Result<StrandCamArgs> {
         save_empty_data2d,
         #[cfg(feature = "flydratrax")]
         model_server_addr,
+        #[cfg(feature = "flydratrax")]
+        rerun,
         #[cfg(feature = "fiducial")]
         apriltag_csv_filename_template,
         #[cfg(target_os = "linux")]
         v4l2loopback: derived_matches.v4l2loopback,
         data_dir: derived_matches.data_dir,
+        disk_space_warning_mb: derived_matches.disk_space_warning_mb,
+        disk_space_stop_mb: derived_matches.disk_space_stop_mb,
+        notifications,
+        log_json: derived_matches.log_json,
+        read_only_tokens: derived_matches.read_only_tokens,
+        tls,
+        no_mdns: derived_matches.no_mdns,
         #[cfg(feature = "eframe-gui")]
         windowed: derived_matches.windowed,
+        latency_test,
         ..Default::default()
     })
 }
@@ -2,6 +2,8 @@ use eyre::Result;
 #[cfg(feature = "fiducial")]
 use libflate::{finish::AutoFinishUnchecked, gzip::Encoder};
 
+#[cfg(feature = "checkercal")]
+use ci2_remote_control::CheckerboardPattern;
 #[cfg(feature = "checkercal")]
 use machine_vision_formats as formats;
 #[cfg(feature = "fiducial")]
@@ -23,16 +25,19 @@ use flydra_feature_detector_types::ImPtDetectCfg;
 use flydra_types::{FlydraFloatTimestampLocal, PtpStamp, RawCamName, TriggerType};
 use fmf::FMFWriter;
 use http_video_streaming::AnnotatedFrame;
+use machine_vision_formats::{ImageData, Stride};
 use rust_cam_bui_types::RecordingPath;
 
-use strand_cam_storetype::StoreType;
+use strand_cam_storetype::{InspectedPixel, StoreType};
 
 #[cfg(feature = "fiducial")]
 use ads_apriltag as apriltag;
 
+use crate::soft_auto_exposure::SoftAutoExposure;
+use crate::watchdog::{Stage, Watchdog};
 use crate::{
-    convert_stream, open_braid_destination_addr, post_trigger_buffer, video_streaming,
-    CentroidToDevice, FinalMp4RecordingConfig, FmfWriteInfo, FpsCalc, MomentCentroid, Msg,
+    convert_stream, open_braid_destination_addr, post_trigger_buffer, synchronized_start_due,
+    video_streaming, CentroidToDevice, FmfWriteInfo, FpsCalc, MomentCentroid, Mp4Segment, Msg,
     TimestampSource, LED_BOX_HEARTBEAT_INTERVAL_MSEC, MOMENT_CENTROID_SCHEMA_VERSION,
 };
 
@@ -51,11 +56,12 @@ pub(crate) async fn frame_process_task<'a>(
     #[cfg(feature = "flydra_feat_detect")] im_pt_detect_cfg: ImPtDetectCfg,
     #[cfg(feature = "flydra_feat_detect")] csv_save_pathbuf: std::path::PathBuf,
     firehose_tx: tokio::sync::mpsc::Sender<AnnotatedFrame>,
-    #[cfg(feature = "flydratrax")] led_box_tx_std: tokio::sync::mpsc::Sender<crate::ToLedBoxDevice>,
+    led_box_tx_std: tokio::sync::mpsc::Sender<crate::ToLedBoxDevice>,
     #[cfg(feature = "flydratrax")] http_camserver_info: flydra_types::BuiServerAddrInfo,
     transmit_msg_tx: Option<tokio::sync::mpsc::Sender<flydra_types::BraidHttpApiCallback>>,
     camdata_udp_addr: Option<SocketAddr>,
     led_box_heartbeat_update_arc: Arc<RwLock<Option<std::time::Instant>>>,
+    cam_args_tx: tokio::sync::mpsc::Sender<ci2_remote_control::CamArg>,
     #[cfg(feature = "checkercal")] collected_corners_arc: crate::CollectedCornersArc,
     #[cfg(feature = "flydratrax")] args: &crate::StrandCamArgs,
     #[cfg(feature = "flydra_feat_detect")] acquisition_duration_allowed_imprecision_msec: Option<
@@ -87,6 +93,7 @@ pub(crate) async fn frame_process_task<'a>(
     #[cfg_attr(not(feature = "flydra_feat_detect"), allow(dead_code))]
     struct CsvSavingState {
         fd: File,
+        path: PathBuf,
         min_interval: chrono::Duration,
         last_save: chrono::DateTime<chrono::Utc>,
         t0: chrono::DateTime<chrono::Utc>,
@@ -102,14 +109,27 @@ pub(crate) async fn frame_process_task<'a>(
 
     #[cfg(feature = "fiducial")]
     let mut apriltag_writer: Option<_> = None;
-    let mut my_mp4_writer: Option<bg_movie_writer::BgMovieWriter> = None;
+    let mut my_mp4_writer: Option<Mp4Segment> = None;
+    // Synchronized MP4 recording waiting for its start time.
+    let mut pending_mp4_start: Option<ci2_remote_control::SynchronizedMp4Start> = None;
+    // Prefix of the MP4 filename, kept for files started by rollover.
+    let mut mp4_filename_prefix: Option<String> = None;
     let mut fmf_writer: Option<FmfWriteInfo<_>> = None;
+    // Frames are not written to the MP4, FMF and CSV recordings while paused.
+    let mut is_recording_paused = false;
+    // Set when the next frame should be saved as an OME-TIFF file.
+    let mut export_frame_ome_tiff = false;
+    // The most recent frame and its frame number, for pixel inspection.
+    let mut latest_image: Option<(Arc<DynamicFrame>, usize)> = None;
     #[cfg(feature = "flydra_feat_detect")]
     let mut ufmf_state = Some(flydra_feature_detector::UfmfState::Stopped);
     #[cfg(feature = "flydra_feat_detect")]
     #[allow(unused_assignments)]
     let mut is_doing_object_detection = is_braid;
 
+    // Used to inform Braid about MP4 files being recorded.
+    let mp4_recording_tx = transmit_msg_tx.clone();
+
     let transmit_feature_detect_settings_tx = if is_braid {
         let (transmit_feature_detect_settings_tx, transmit_feature_detect_settings_rx) =
             tokio::sync::mpsc::channel::<ImPtDetectCfg>(10);
@@ -153,9 +173,25 @@ pub(crate) async fn frame_process_task<'a>(
         acquisition_duration_allowed_imprecision_msec,
     )?;
     #[cfg(feature = "flydra_feat_detect")]
+    let background_model_path = crate::background_model_path(&raw_cam_name);
+    #[cfg(feature = "flydra_feat_detect")]
+    if let Some(path) = background_model_path.as_ref().filter(|p| p.exists()) {
+        // Skip the warm-up of the background model if it was saved previously.
+        match im_tracker.load_background_model(path) {
+            Ok(true) => info!("Loaded background model from \"{}\".", path.display()),
+            Ok(false) => {}
+            Err(e) => tracing::warn!(
+                "Failed loading background model from \"{}\": {e}",
+                path.display()
+            ),
+        }
+    }
+    #[cfg(feature = "flydra_feat_detect")]
     let mut csv_save_state = SavingState::NotSaving;
     let mut shared_store_arc: Option<Arc<RwLock<ChangeTracker<StoreType>>>> = None;
     let mut fps_calc = FpsCalc::new(100); // average 100 frames to get mean fps
+    let mut watchdog = Watchdog::new();
+    let mut soft_auto_exposure = SoftAutoExposure::new();
     #[cfg(feature = "flydratrax")]
     let mut kalman_tracking_config = strand_cam_storetype::KalmanTrackingConfig::default(); // this is replaced below
     #[cfg(feature = "flydratrax")]
@@ -172,6 +208,8 @@ pub(crate) async fn frame_process_task<'a>(
     #[cfg(feature = "flydratrax")]
     let red_style = http_video_streaming_types::StrokeStyle::from_rgb(255, 100, 100);
 
+    let occlusion_mask_style = http_video_streaming_types::StrokeStyle::from_rgb(255, 165, 0);
+
     let expected_framerate_arc = Arc::new(RwLock::new(None));
 
     let mut post_trig_buffer = post_trigger_buffer::PostTriggerBuffer::new();
@@ -186,6 +224,13 @@ pub(crate) async fn frame_process_task<'a>(
     #[cfg(feature = "fiducial")]
     april_td.add_family(april_tf);
 
+    #[cfg(all(feature = "checkercal", feature = "fiducial"))]
+    let charuco_td = {
+        let mut td = apriltag::Detector::new();
+        td.add_family(apriltag::Family::new_tag_36h11());
+        td
+    };
+
     #[cfg(feature = "checkercal")]
     let mut last_checkerboard_detection = std::time::Instant::now();
 
@@ -205,11 +250,13 @@ pub(crate) async fn frame_process_task<'a>(
     // let current_image_timer_arc = Arc::new(RwLock::new(std::time::Instant::now()));
 
     let mut im_ops_socket: Option<std::net::UdpSocket> = None;
+    let mut bright_points_buffer = crate::im_ops::BrightPointsBuffer::default();
+    let mut latency_test: Option<crate::latency_test::LatencyTest> = None;
 
     let mut triggerbox_clock_model = None;
     let mut opt_frame_offset = None;
 
-    let mut block_id_offset = None;
+    let mut block_id_gaps = flydra_types::FrameGapTracker::default();
 
     loop {
         #[cfg(feature = "flydra_feat_detect")]
@@ -454,6 +501,22 @@ pub(crate) async fn frame_process_task<'a>(
             }
         }
 
+        {
+            // A pause ends once all paused recordings have been stopped.
+            let is_recording = my_mp4_writer.is_some() || fmf_writer.is_some();
+            #[cfg(feature = "flydra_feat_detect")]
+            let is_recording = is_recording || !matches!(csv_save_state, SavingState::NotSaving);
+            if is_recording_paused && !is_recording {
+                is_recording_paused = false;
+                if let Some(ref mut store) = shared_store_arc {
+                    let mut tracker = store.write().unwrap();
+                    tracker.modify(|tracker| {
+                        tracker.is_recording_paused = false;
+                    });
+                }
+            }
+        }
+
         match msg {
             Msg::Store(stor) => {
                 // We get the shared store once at startup.
@@ -470,10 +533,14 @@ pub(crate) async fn frame_process_task<'a>(
                 }
                 shared_store_arc = Some(stor);
             }
-            Msg::StartFMF((dest, recording_framerate)) => {
+            Msg::StartFMF((dest, recording_framerate, compression)) => {
                 let path = Path::new(&dest);
                 let f = std::fs::File::create(path)?;
-                fmf_writer = Some(FmfWriteInfo::new(FMFWriter::new(f)?, recording_framerate));
+                fmf_writer = Some(FmfWriteInfo::new(
+                    FMFWriter::with_compression(f, compression)?,
+                    path.to_path_buf(),
+                    recording_framerate,
+                ));
             }
             #[cfg(feature = "flydra_feat_detect")]
             Msg::StartUFMF(dest) => {
@@ -496,37 +563,39 @@ pub(crate) async fn frame_process_task<'a>(
                     local
                 };
 
-                let (format_str_mp4, mp4_recording_config) = {
+                let mut raw = {
                     // scope for reading cache
                     let tracker = shared_store_arc.as_ref().unwrap().read().unwrap();
                     let shared: &StoreType = tracker.as_ref();
-
-                    let mp4_recording_config = FinalMp4RecordingConfig::new(shared, creation_time);
-
-                    (shared.format_str_mp4.clone(), mp4_recording_config)
-                };
-
-                let filename = creation_time.format(format_str_mp4.as_str()).to_string();
-                let is_recording_mp4 = Some(RecordingPath::new(filename.clone()));
-
-                let mp4_path = {
-                    let local = chrono::Local::now();
-                    let formatted_filename = local.format(&format_str_mp4).to_string();
-                    data_dir.join(formatted_filename)
+                    Mp4Segment::new(
+                        shared,
+                        &data_dir,
+                        creation_time,
+                        frames.len() + 100,
+                        None,
+                        0,
+                    )
                 };
+                mp4_filename_prefix = None;
+                pending_mp4_start = None;
+                let is_recording_mp4 = Some(RecordingPath::new(raw.filename.clone()));
 
-                let mut raw = bg_movie_writer::BgMovieWriter::new(
-                    mp4_recording_config.final_cfg,
-                    frames.len() + 100,
-                    mp4_path,
-                );
-                for mut frame in frames.into_iter() {
+                for frame in frames.into_iter() {
                     // Force frame width to be power of 2.
                     let val = 2;
                     let clipped_width = (frame.width() / val as u32) * val as u32;
-                    match_all_dynamic_fmts!(&mut frame.image, x, { x.width = clipped_width });
+                    let mut image = frame.image;
+                    if image.width() != clipped_width {
+                        // The frame data is shared, so this copies it.
+                        match_all_dynamic_fmts!(Arc::make_mut(&mut image), x, {
+                            x.width = clipped_width
+                        });
+                    }
                     let ts = frame.host_timing.datetime;
-                    raw.write(frame.image, ts)?;
+                    raw.write(image, ts, frame.host_timing.fno, Vec::new())?;
+                }
+                if raw.start.is_some() {
+                    send_mp4_recording(&mp4_recording_tx, &raw_cam_name, Some(&raw));
                 }
                 my_mp4_writer = Some(raw);
 
@@ -537,6 +606,13 @@ pub(crate) async fn frame_process_task<'a>(
                     });
                 }
             }
+            Msg::StartSynchronizedMp4(start) => {
+                if my_mp4_writer.is_some() {
+                    tracing::warn!("Already recording MP4, ignoring synchronized start.");
+                } else {
+                    pending_mp4_start = Some(start);
+                }
+            }
             Msg::StartAprilTagRec(format_str_apriltags_csv) => {
                 #[cfg(feature = "fiducial")]
                 {
@@ -558,6 +634,43 @@ pub(crate) async fn frame_process_task<'a>(
                     apriltag_writer = None;
                 }
             }
+            Msg::SetRecordingPaused(paused) => {
+                if paused != is_recording_paused {
+                    let mut recordings = Vec::new();
+                    if let Some(ref inner) = my_mp4_writer {
+                        recordings.push(inner.path.clone());
+                    }
+                    if let Some(ref inner) = fmf_writer {
+                        recordings.push(inner.path.clone());
+                    }
+                    #[cfg(feature = "flydra_feat_detect")]
+                    if let SavingState::Saving(ref inner) = csv_save_state {
+                        recordings.push(inner.path.clone());
+                    }
+                    if paused && recordings.is_empty() {
+                        info!("Not recording, ignoring command to pause recording.");
+                    } else {
+                        is_recording_paused = paused;
+                        let event = if paused { "pause" } else { "resume" };
+                        let now = chrono::Utc::now();
+                        for path in recordings.iter() {
+                            // Failing to log must not stop the recording.
+                            if let Err(e) = crate::log_pause_event(path, event, now) {
+                                error!(
+                                    "Failed logging {event} of recording \"{}\": {e}",
+                                    path.display()
+                                );
+                            }
+                        }
+                        if let Some(ref mut store) = shared_store_arc {
+                            let mut tracker = store.write().unwrap();
+                            tracker.modify(|tracker| {
+                                tracker.is_recording_paused = paused;
+                            });
+                        }
+                    }
+                }
+            }
             Msg::SetPostTriggerBufferSize(size) => {
                 post_trig_buffer.set_size(size);
                 if let Some(ref mut store) = shared_store_arc {
@@ -568,31 +681,42 @@ pub(crate) async fn frame_process_task<'a>(
                 }
             }
             Msg::Mframe(frame) => {
+                let processing_start = std::time::Instant::now();
+                let queue_latency = (chrono::Utc::now() - frame.host_timing.datetime)
+                    .to_std()
+                    .unwrap_or_default();
+                watchdog.record(Stage::Queue, queue_latency);
+                #[cfg(feature = "flydra_feat_detect")]
+                let run_detection = watchdog.run_detection(frame.host_timing.fno);
+                let send_preview = watchdog.send_preview(frame.host_timing.fno);
+
                 let (device_timestamp, block_id) = extract_backend_data(&frame);
 
                 // Check if frames were skipped
-                if let Some(block_id) = block_id {
-                    let this_offset = block_id as i128 - frame.host_timing.fno as i128;
-                    if let Some(prev_offset) = block_id_offset {
-                        let n_skipped = this_offset - prev_offset;
-                        if n_skipped != 0 {
-                            tracing::error!("{n_skipped} frame(s) skipped. block_id: {block_id}");
-                            block_id_offset = Some(this_offset);
-                        }
-                    } else {
-                        block_id_offset = Some(this_offset);
+                let n_frames_skipped = if let Some(block_id) = block_id {
+                    let n_skipped = block_id_gaps.update(block_id);
+                    if n_skipped != 0 {
+                        tracing::error!(
+                            frame = frame.host_timing.fno,
+                            "{n_skipped} frame(s) skipped. block_id: {block_id}, \
+                            total skipped: {}",
+                            block_id_gaps.total_dropped()
+                        );
                     }
-                }
+                    u32::try_from(n_skipped).unwrap_or(u32::MAX)
+                } else {
+                    0
+                };
 
                 // Compute, as cleverly as possible, a timestamp.
                 let braid_ts = match &trigger_type {
-                    Some(TriggerType::TriggerboxV1(_)) | Some(TriggerType::FakeSync(_)) => {
-                        flydra_types::triggerbox_time(
-                            triggerbox_clock_model.as_ref(),
-                            opt_frame_offset,
-                            frame.host_timing.fno,
-                        )
-                    }
+                    Some(TriggerType::TriggerboxV1(_))
+                    | Some(TriggerType::FakeSync(_))
+                    | Some(TriggerType::SoftwareSync(_)) => flydra_types::triggerbox_time(
+                        triggerbox_clock_model.as_ref(),
+                        opt_frame_offset,
+                        frame.host_timing.fno,
+                    ),
                     Some(TriggerType::PtpSync(ptpcfg)) => {
                         let ptp_stamp = PtpStamp::new(device_timestamp.unwrap());
                         if tracing::Level::TRACE <= tracing::level_filters::STATIC_MAX_LEVEL {
@@ -656,7 +780,25 @@ pub(crate) async fn frame_process_task<'a>(
                     }
                 }
 
-                post_trig_buffer.push(&frame); // If buffer size larger than 0, copies data.
+                post_trig_buffer.push(&frame); // If buffer size larger than 0, shares data.
+                latest_image = Some((frame.image.clone(), frame.host_timing.fno));
+
+                if export_frame_ome_tiff {
+                    export_frame_ome_tiff = false;
+                    let result = match_all_dynamic_fmts!(&*frame.image, x, {
+                        export_ome_tiff(
+                            &data_dir,
+                            "frame",
+                            &raw_cam_name,
+                            x,
+                            save_mp4_fmf_stamp,
+                            store_cache.as_ref(),
+                        )
+                    });
+                    if let Err(e) = result {
+                        error!("Failed saving frame as OME-TIFF: {e}");
+                    }
+                }
 
                 #[cfg(target_os = "linux")]
                 if let Some(v4l_out_stream) = v4l_out_stream.as_mut() {
@@ -686,6 +828,7 @@ pub(crate) async fn frame_process_task<'a>(
                 #[cfg(not(feature = "checkercal"))]
                 let checkercal_tmp: Option<()> = None;
 
+                let detect_start = std::time::Instant::now();
                 #[allow(unused_mut)]
                 let (mut found_points, valid_display) = if let Some(inner) = checkercal_tmp {
                     #[allow(unused_mut)]
@@ -707,7 +850,7 @@ pub(crate) async fn frame_process_task<'a>(
                                     checkerboard_data.width, checkerboard_data.height
                                 );
                                 let stamped = debug_image_stamp.format(&format_str).to_string();
-                                let png_buf = match_all_dynamic_fmts!(&frame.image, x, {
+                                let png_buf = match_all_dynamic_fmts!(&*frame.image, x, {
                                     convert_image::frame_to_encoded_buffer(
                                         x,
                                         convert_image::EncoderOptions::Png,
@@ -723,26 +866,68 @@ pub(crate) async fn frame_process_task<'a>(
 
                             let start_time = std::time::Instant::now();
 
-                            info!(
-                                "Attempting to find {}x{} chessboard.",
-                                checkerboard_data.width, checkerboard_data.height
-                            );
+                            let n_rows = checkerboard_data.height as usize;
+                            let n_cols = checkerboard_data.width as usize;
+                            let board = match checkerboard_data.pattern {
+                                CheckerboardPattern::Chessboard => {
+                                    info!(
+                                        "Attempting to find {}x{} chessboard.",
+                                        checkerboard_data.width, checkerboard_data.height
+                                    );
 
-                            let corners = basic_frame::match_all_dynamic_fmts!(&frame.image, x, {
-                                let rgb: Box<
-                                    dyn formats::ImageStride<formats::pixel_format::RGB8>,
-                                > = Box::new(convert_image::convert_ref::<
-                                    _,
-                                    formats::pixel_format::RGB8,
-                                >(x)?);
-                                let corners = opencv_calibrate::find_chessboard_corners(
-                                    rgb.image_data(),
-                                    rgb.width(),
-                                    rgb.height(),
-                                    checkerboard_data.width as usize,
-                                    checkerboard_data.height as usize,
-                                )?;
-                                corners
+                                    let corners =
+                                        basic_frame::match_all_dynamic_fmts!(&*frame.image, x, {
+                                            let rgb: Box<
+                                                dyn formats::ImageStride<
+                                                    formats::pixel_format::RGB8,
+                                                >,
+                                            > = Box::new(convert_image::convert_ref::<
+                                                _,
+                                                formats::pixel_format::RGB8,
+                                            >(
+                                                x
+                                            )?);
+                                            opencv_calibrate::find_chessboard_corners(
+                                                rgb.image_data(),
+                                                rgb.width(),
+                                                rgb.height(),
+                                                n_cols,
+                                                n_rows,
+                                            )?
+                                        });
+                                    corners.map(|corners| {
+                                        let points: Vec<(f64, f64)> = corners
+                                            .iter()
+                                            .map(|(x, y)| (*x as f64, *y as f64))
+                                            .collect();
+                                        camcal::CheckerBoardData::new(n_rows, n_cols, &points)
+                                    })
+                                }
+                                CheckerboardPattern::Charuco36h11 => {
+                                    info!(
+                                        "Attempting to find {}x{} ChArUco board.",
+                                        checkerboard_data.width, checkerboard_data.height
+                                    );
+                                    let charuco = camcal::charuco::CharucoBoard {
+                                        n_rows,
+                                        n_cols,
+                                        marker_to_square_ratio: checkerboard_data
+                                            .charuco_marker_ratio,
+                                        first_marker_id: 0,
+                                    };
+                                    find_charuco_board(
+                                        &frame.image,
+                                        &charuco,
+                                        #[cfg(feature = "fiducial")]
+                                        &charuco_td,
+                                    )
+                                }
+                            };
+                            let corners: Option<Vec<(f32, f32)>> = board.as_ref().map(|b| {
+                                b.points()
+                                    .iter()
+                                    .map(|(x, y)| (*x as f32, *y as f32))
+                                    .collect()
                             });
 
                             let work_duration = start_time.elapsed();
@@ -779,7 +964,7 @@ pub(crate) async fn frame_process_task<'a>(
 
                             if let Some(corners) = corners {
                                 info!(
-                                    "Found {} chessboard corners in {} msec.",
+                                    "Found {} checkerboard corners in {} msec.",
                                     corners.len(),
                                     work_duration.as_millis()
                                 );
@@ -793,11 +978,16 @@ pub(crate) async fn frame_process_task<'a>(
                                     })
                                     .collect();
 
-                                let num_checkerboards_collected = {
+                                let (num_checkerboards_collected, coverage) = {
                                     let mut collected_corners =
                                         collected_corners_arc.write().unwrap();
-                                    collected_corners.push(corners);
-                                    collected_corners.len().try_into().unwrap()
+                                    collected_corners.push(board.unwrap());
+                                    let coverage = crate::checkerboard_coverage(
+                                        &collected_corners,
+                                        frame.image.width(),
+                                        frame.image.height(),
+                                    );
+                                    (collected_corners.len().try_into().unwrap(), coverage)
                                 };
 
                                 if let Some(ref ssa) = shared_store_arc {
@@ -806,11 +996,12 @@ pub(crate) async fn frame_process_task<'a>(
                                     tracker.modify(|shared| {
                                         shared.checkerboard_data.num_checkerboards_collected =
                                             num_checkerboards_collected;
+                                        shared.checkerboard_data.coverage = coverage;
                                     });
                                 }
                             } else {
                                 info!(
-                                    "Found no chessboard corners in {} msec.",
+                                    "Found no checkerboard corners in {} msec.",
                                     work_duration.as_millis()
                                 );
                             }
@@ -826,48 +1017,85 @@ pub(crate) async fn frame_process_task<'a>(
                             if let (true, Some(framenumber)) =
                                 (store_cache_ref.im_ops_state.do_detection, block_id)
                             {
-                                let thresholded = if let DynamicFrame::Mono8(mono8) = &frame.image {
-                                    imops::threshold(
+                                let im_ops_state = &store_cache_ref.im_ops_state;
+                                let mono8 = if let DynamicFrame::Mono8(mono8) = &*frame.image {
+                                    mono8
+                                } else {
+                                    panic!("imops only implemented for Mono8 pixel format");
+                                };
+                                let buf = if im_ops_state.format
+                                    == ci2_remote_control::ImOpsFormat::CborCentroid
+                                {
+                                    let thresholded = imops::threshold(
                                         mono8.clone(),
                                         imops::CmpOp::LessThan,
-                                        store_cache_ref.im_ops_state.threshold,
+                                        im_ops_state.threshold,
                                         0,
                                         255,
-                                    )
-                                } else {
-                                    panic!("imops only implemented for Mono8 pixel format");
-                                };
-                                let mu00 = imops::spatial_moment_00(&thresholded);
-                                let mu01 = imops::spatial_moment_01(&thresholded);
-                                let mu10 = imops::spatial_moment_10(&thresholded);
-                                let mc = if mu00 != 0.0 {
-                                    let x = mu10 / mu00;
-                                    let y = mu01 / mu00;
-
-                                    // If mu00 is 0.0, these will be NaN. CBOR explicitly can represent NaNs.
-
-                                    let mc = CentroidToDevice::Centroid(MomentCentroid {
-                                        schema_version: MOMENT_CENTROID_SCHEMA_VERSION,
-                                        framenumber,
-                                        timestamp: save_mp4_fmf_stamp,
-                                        timestamp_source,
-                                        mu00,
-                                        mu01,
-                                        mu10,
-                                        center_x: store_cache_ref.im_ops_state.center_x,
-                                        center_y: store_cache_ref.im_ops_state.center_y,
-                                        cam_name: cam_name.as_str().to_string(),
-                                    });
-                                    all_points.push(video_streaming::Point {
-                                        x,
-                                        y,
-                                        area: None,
-                                        theta: None,
-                                    });
+                                    );
+                                    let mu00 = imops::spatial_moment_00(&thresholded);
+                                    let mu01 = imops::spatial_moment_01(&thresholded);
+                                    let mu10 = imops::spatial_moment_10(&thresholded);
+                                    if mu00 != 0.0 {
+                                        let x = mu10 / mu00;
+                                        let y = mu01 / mu00;
+
+                                        // If mu00 is 0.0, these will be NaN. CBOR explicitly can represent NaNs.
+
+                                        let mc = CentroidToDevice::Centroid(MomentCentroid {
+                                            schema_version: MOMENT_CENTROID_SCHEMA_VERSION,
+                                            framenumber,
+                                            timestamp: save_mp4_fmf_stamp,
+                                            timestamp_source,
+                                            mu00,
+                                            mu01,
+                                            mu10,
+                                            center_x: im_ops_state.center_x,
+                                            center_y: im_ops_state.center_y,
+                                            cam_name: cam_name.as_str().to_string(),
+                                        });
+                                        all_points.push(video_streaming::Point {
+                                            x,
+                                            y,
+                                            area: None,
+                                            theta: None,
+                                        });
 
-                                    Some(mc)
+                                        Some(serde_cbor::to_vec(&mc).unwrap())
+                                    } else {
+                                        None
+                                    }
                                 } else {
-                                    None
+                                    let points = crate::im_ops::bright_points(
+                                        &mut bright_points_buffer,
+                                        mono8.image_data(),
+                                        mono8.width() as usize,
+                                        mono8.height() as usize,
+                                        mono8.stride(),
+                                        im_ops_state.threshold,
+                                        im_ops_state.max_points.into(),
+                                    );
+                                    all_points.extend(points.iter().map(|pt| {
+                                        video_streaming::Point {
+                                            x: pt.x,
+                                            y: pt.y,
+                                            area: Some(pt.area),
+                                            theta: None,
+                                        }
+                                    }));
+                                    crate::im_ops::encode(
+                                        im_ops_state.format,
+                                        &crate::im_ops::ImOpsPoints {
+                                            protocol_version: crate::im_ops::PROTOCOL_VERSION,
+                                            cam_name: cam_name.as_str(),
+                                            framenumber,
+                                            timestamp: save_mp4_fmf_stamp,
+                                            timestamp_source,
+                                            center_x: im_ops_state.center_x,
+                                            center_y: im_ops_state.center_y,
+                                            points: &points,
+                                        },
+                                    )
                                 };
 
                                 let need_new_socket = if let Some(socket) = &im_ops_socket {
@@ -893,8 +1121,7 @@ pub(crate) async fn frame_process_task<'a>(
                                 }
 
                                 if let Some(socket) = &mut im_ops_socket {
-                                    if let Some(mc) = mc {
-                                        let buf = serde_cbor::to_vec(&mc).unwrap();
+                                    if let Some(buf) = buf {
                                         match socket
                                             .send_to(&buf, store_cache_ref.im_ops_state.destination)
                                         {
@@ -905,6 +1132,31 @@ pub(crate) async fn frame_process_task<'a>(
                                         }
                                     }
                                 }
+
+                                if let Some(test) = latency_test.as_mut() {
+                                    // So far, only the ImOps detector added points.
+                                    let detected = !all_points.is_empty();
+                                    if let Some(state) =
+                                        test.update(detected, std::time::Instant::now())
+                                    {
+                                        set_led_box_state(&led_box_tx_std, state);
+                                        if let Some(ref mut store) = shared_store_arc {
+                                            let result = test.result().clone();
+                                            let mut tracker = store.write().unwrap();
+                                            tracker.modify(|tracker| {
+                                                tracker.latency_test.result = Some(result);
+                                            });
+                                        }
+                                    }
+                                    if test.is_done() {
+                                        finish_latency_test(
+                                            latency_test.take().unwrap(),
+                                            &led_box_tx_std,
+                                            &shared_store_arc,
+                                            &cam_args_tx,
+                                        );
+                                    }
+                                }
                             }
                         }
                     }
@@ -962,7 +1214,7 @@ pub(crate) async fn frame_process_task<'a>(
                             device_timestamp,
                             block_id,
                             framenumber: frame.host_timing.fno as i32,
-                            n_frames_skipped: 0, // FIXME TODO XXX FIX THIS, should be n_frames_skipped
+                            n_frames_skipped,
                             done_camnode_processing: 0.0,
                             preprocess_stamp,
                             image_processing_steps: ImageProcessingSteps::empty(),
@@ -983,7 +1235,7 @@ pub(crate) async fn frame_process_task<'a>(
 
                     #[cfg(feature = "flydra_feat_detect")]
                     {
-                        if is_doing_object_detection {
+                        if is_doing_object_detection && run_detection {
                             let inner_ufmf_state = ufmf_state.take().unwrap();
                             // Detect features in the image and send them to the
                             // mainbrain for 3D processing.
@@ -995,6 +1247,7 @@ pub(crate) async fn frame_process_task<'a>(
                                     inner_ufmf_state,
                                     device_timestamp,
                                     block_id,
+                                    n_frames_skipped,
                                     braid_ts,
                                 )?;
                             if let Some(ref coord_socket) = coord_socket {
@@ -1099,7 +1352,7 @@ pub(crate) async fn frame_process_task<'a>(
                                         image_path.push(base.clone());
                                         image_path.set_extension("jpg");
 
-                                        let bytes = match_all_dynamic_fmts!(&frame.image, x, {
+                                        let bytes = match_all_dynamic_fmts!(&*frame.image, x, {
                                             convert_image::frame_to_encoded_buffer(
                                                 x,
                                                 convert_image::EncoderOptions::Jpeg(99),
@@ -1123,7 +1376,7 @@ pub(crate) async fn frame_process_task<'a>(
                                         });
                                     }
 
-                                    let mut fd = File::create(csv_path)?;
+                                    let mut fd = File::create(&csv_path)?;
 
                                     // save configuration as commented yaml
                                     {
@@ -1166,6 +1419,7 @@ pub(crate) async fn frame_process_task<'a>(
 
                                     let inner = CsvSavingState {
                                         fd,
+                                        path: csv_path,
                                         min_interval,
                                         last_save: now
                                             .checked_sub_signed(
@@ -1183,7 +1437,10 @@ pub(crate) async fn frame_process_task<'a>(
                                         .datetime
                                         .signed_duration_since(inner.last_save);
                                     // save found points
-                                    if interval >= inner.min_interval && !points.is_empty() {
+                                    if interval >= inner.min_interval
+                                        && !points.is_empty()
+                                        && !is_recording_paused
+                                    {
                                         let time_microseconds = frame
                                             .host_timing
                                             .datetime
@@ -1252,9 +1509,7 @@ pub(crate) async fn frame_process_task<'a>(
                                 .map(|pt| video_streaming::Point {
                                     x: pt.x0_abs as f32,
                                     y: pt.y0_abs as f32,
-                                    theta: pt
-                                        .maybe_slope_eccentricty
-                                        .map(|(slope, _ecc)| f32::atan(slope as f32)),
+                                    theta: pt.orientation().map(|theta| theta as f32),
                                     area: Some(pt.area as f32),
                                 })
                                 .collect();
@@ -1265,13 +1520,153 @@ pub(crate) async fn frame_process_task<'a>(
                     }
                     (all_points, blkajdsfads)
                 };
+                watchdog.record(Stage::Detect, detect_start.elapsed());
 
-                if let Some(ref mut inner) = my_mp4_writer {
-                    let data = frame.image.clone(); // copy entire frame data
-                    inner.write(data, save_mp4_fmf_stamp)?;
+                if let Some(store_cache_ref) = store_cache
+                    .as_ref()
+                    .filter(|s| s.soft_auto_exposure_config.enabled)
+                {
+                    let update = soft_auto_exposure
+                        .update(
+                            &store_cache_ref.soft_auto_exposure_config,
+                            &frame.image,
+                            &found_points,
+                            &store_cache_ref.exposure_time,
+                            &store_cache_ref.gain,
+                        )
+                        .unwrap_or_else(|e| {
+                            // This happens at most once per adjustment
+                            // interval.
+                            error!("Soft auto exposure failed: {e}");
+                            None
+                        });
+                    if let Some(update) = update {
+                        for cam_arg in update.cam_args {
+                            // Skip the adjustment if camera commands are
+                            // backed up. The next one is computed anyway.
+                            let _ = cam_args_tx.try_send(cam_arg);
+                        }
+                        if store_cache_ref.soft_auto_exposure_level != Some(update.level) {
+                            if let Some(ref mut store) = shared_store_arc {
+                                let mut tracker = store.write().unwrap();
+                                tracker.modify(|tracker| {
+                                    tracker.soft_auto_exposure_level = Some(update.level)
+                                });
+                            }
+                        }
+                    }
+                }
+
+                // Frames are never skipped for recording, regardless of the
+                // degradation by the watchdog.
+                let record_start = std::time::Instant::now();
+                let synced_frame = opt_frame_offset
+                    .map(|fo| (frame.host_timing.fno as u64).wrapping_sub(fo) as i64);
+                if pending_mp4_start.as_ref().is_some_and(|start| {
+                    synchronized_start_due(start, synced_frame, save_mp4_fmf_stamp)
+                }) {
+                    let start = pending_mp4_start.take().unwrap();
+                    // Name the file by the requested start time so that the
+                    // files of all cameras share the same name apart from
+                    // the camera name.
+                    let raw = {
+                        let tracker = shared_store_arc.as_ref().unwrap().read().unwrap();
+                        let shared: &StoreType = tracker.as_ref();
+                        Mp4Segment::new(
+                            shared,
+                            &data_dir,
+                            start.start_time.into(),
+                            100,
+                            start.filename_prefix.as_deref(),
+                            0,
+                        )
+                    };
+                    info!(
+                        "Synchronized MP4 recording started in \"{}\".",
+                        raw.path.display()
+                    );
+                    if let Some(ref mut store) = shared_store_arc {
+                        let is_recording_mp4 = Some(RecordingPath::new(raw.filename.clone()));
+                        let mut tracker = store.write().unwrap();
+                        tracker.modify(|tracker| {
+                            tracker.is_recording_mp4 = is_recording_mp4;
+                        });
+                    }
+                    my_mp4_writer = Some(raw);
+                    mp4_filename_prefix = start.filename_prefix;
+                }
+
+                if let Some(inner) = my_mp4_writer.as_mut().filter(|_| !is_recording_paused) {
+                    let (rollover_minutes, rollover_gb, draw_detections) = store_cache
+                        .as_ref()
+                        .map(|s| {
+                            (
+                                s.mp4_rollover_minutes,
+                                s.mp4_rollover_gb,
+                                s.mp4_draw_detections,
+                            )
+                        })
+                        .unwrap_or_default();
+                    if inner.needs_rollover(save_mp4_fmf_stamp, rollover_minutes, rollover_gb) {
+                        // Finish the current file and continue, starting with
+                        // this frame, in a new file.
+                        let next = {
+                            let tracker = shared_store_arc.as_ref().unwrap().read().unwrap();
+                            let shared: &StoreType = tracker.as_ref();
+                            Mp4Segment::new(
+                                shared,
+                                &data_dir,
+                                save_mp4_fmf_stamp.into(),
+                                100,
+                                mp4_filename_prefix.as_deref(),
+                                inner.segment + 1,
+                            )
+                        };
+                        let prev = std::mem::replace(inner, next);
+                        info!(
+                            "MP4 file \"{}\" complete, continuing in \"{}\".",
+                            prev.path.display(),
+                            inner.path.display()
+                        );
+                        // Do not delay the processing of frames.
+                        prev.finish_in_background();
+                        if let Some(ref mut store) = shared_store_arc {
+                            let is_recording_mp4 = Some(RecordingPath::new(inner.filename.clone()));
+                            let mut tracker = store.write().unwrap();
+                            tracker.modify(|tracker| {
+                                tracker.is_recording_mp4 = is_recording_mp4;
+                            });
+                        }
+                    }
+                    let is_first_frame = inner.start.is_none();
+                    // The frame data is shared with the writer rather than copied.
+                    let detections = if draw_detections {
+                        found_points
+                            .iter()
+                            .map(|pt| bg_movie_writer::Detection {
+                                x: pt.x,
+                                y: pt.y,
+                                theta: pt.theta,
+                                area: pt.area,
+                            })
+                            .collect()
+                    } else {
+                        Vec::new()
+                    };
+                    let accepted = inner.write(
+                        frame.image.clone(),
+                        save_mp4_fmf_stamp,
+                        frame.host_timing.fno,
+                        detections,
+                    )?;
+                    // Braid saves the start of the file for the alignment of
+                    // the file with the tracking data.
+                    if is_first_frame && accepted {
+                        send_mp4_recording(&mp4_recording_tx, &raw_cam_name, Some(&*inner));
+                    }
                 }
 
-                if let Some(ref mut inner) = fmf_writer {
+                if let Some(inner) = fmf_writer.as_mut().filter(|_| !is_recording_paused) {
                     // Based on our recording framerate, do we need to save this frame?
                     let do_save = match inner.last_saved_stamp {
                         None => true,
@@ -1282,13 +1677,15 @@ pub(crate) async fn frame_process_task<'a>(
                         }
                     };
                     if do_save {
-                        match_all_dynamic_fmts!(&frame.image, x, {
+                        match_all_dynamic_fmts!(&*frame.image, x, {
                             inner.writer.write(x, save_mp4_fmf_stamp)?
                         });
                         inner.last_saved_stamp = Some(save_mp4_fmf_stamp);
                     }
                 }
+                watchdog.record(Stage::Record, record_start.elapsed());
 
+                let preview_start = std::time::Instant::now();
                 let found_points = found_points
                     .iter()
                     .map(
@@ -1318,7 +1715,7 @@ pub(crate) async fn frame_process_task<'a>(
                 }
 
                 #[cfg(feature = "flydratrax")]
-                let annotations = if let Some(ref clpcs) = current_led_program_config_state {
+                let mut annotations = if let Some(ref clpcs) = current_led_program_config_state {
                     vec![http_video_streaming_types::DrawableShape::from_shape(
                         &clpcs.led_on_shape_pixels,
                         &red_style,
@@ -1329,9 +1726,29 @@ pub(crate) async fn frame_process_task<'a>(
                 };
 
                 #[cfg(not(feature = "flydratrax"))]
-                let annotations = vec![];
+                let mut annotations = vec![];
+
+                if let Some(ref store_cache_ref) = store_cache {
+                    if store_cache_ref.is_doing_object_detection {
+                        annotations.extend(
+                            store_cache_ref
+                                .im_pt_detect_cfg
+                                .occlusion_masks
+                                .iter()
+                                .map(|shape| {
+                                    http_video_streaming_types::DrawableShape::from_shape(
+                                        shape,
+                                        &occlusion_mask_style,
+                                        1.0,
+                                    )
+                                }),
+                        );
+                    }
+                }
 
-                if firehose_tx.capacity() == 0 {
+                if !send_preview {
+                    trace!("skipping frame for viewing");
+                } else if firehose_tx.capacity() == 0 {
                     trace!("cannot transmit frame for viewing: channel full");
                 } else {
                     let result = firehose_tx
@@ -1351,6 +1768,21 @@ pub(crate) async fn frame_process_task<'a>(
                         }
                     }
                 }
+                watchdog.record(Stage::Preview, preview_start.elapsed());
+
+                let frame_interval = expected_framerate_arc
+                    .read()
+                    .unwrap()
+                    .filter(|fps| *fps > 0.0)
+                    .map(|fps| std::time::Duration::from_secs_f64(1.0 / f64::from(fps)));
+                if let Some(status) =
+                    watchdog.frame_done(processing_start.elapsed(), frame_interval)
+                {
+                    if let Some(ref mut store) = shared_store_arc {
+                        let mut tracker = store.write().unwrap();
+                        tracker.modify(|tracker| tracker.processing_watchdog = status);
+                    }
+                }
             }
             #[cfg(feature = "flydra_feat_detect")]
             Msg::SetIsSavingObjDetectionCsv(new_value) => {
@@ -1454,6 +1886,136 @@ pub(crate) async fn frame_process_task<'a>(
             Msg::ClearBackground(value) => {
                 im_tracker.do_clear_background(value)?;
             }
+            #[cfg(feature = "flydra_feat_detect")]
+            Msg::SaveBackgroundModel => {
+                if let Some(path) = background_model_path.as_ref() {
+                    if let Err(e) = save_background_model(&im_tracker, path) {
+                        error!("Failed saving background model: {e}");
+                    }
+                }
+            }
+            #[cfg(feature = "flydra_feat_detect")]
+            Msg::ExportBackgroundOmeTiff => match im_tracker.mean_background() {
+                Ok(Some(mean)) => {
+                    if let Err(e) = export_ome_tiff(
+                        &data_dir,
+                        "background",
+                        &raw_cam_name,
+                        &mean,
+                        chrono::Utc::now(),
+                        store_cache.as_ref(),
+                    ) {
+                        error!("Failed saving background as OME-TIFF: {e}");
+                    }
+                }
+                Ok(None) => {
+                    info!("No background model to export.");
+                }
+                Err(e) => {
+                    error!("Failed exporting background: {e}");
+                }
+            },
+            Msg::ExportFrameOmeTiff => {
+                export_frame_ome_tiff = true;
+            }
+            Msg::InspectPixel(x, y) => match latest_image.as_ref() {
+                Some((image, fno)) => match crate::pixel_inspection::pixel_values(image, x, y) {
+                    Ok(Some(values)) => {
+                        if let Some(ref mut store) = shared_store_arc {
+                            let mut tracker = store.write().unwrap();
+                            tracker.modify(|tracker| {
+                                tracker.inspected_pixel = Some(InspectedPixel {
+                                    x,
+                                    y,
+                                    fno: *fno as u64,
+                                    values,
+                                });
+                            });
+                        }
+                    }
+                    Ok(None) => {
+                        debug!("Pixel {x}, {y} to inspect is outside the frame.");
+                    }
+                    Err(e) => {
+                        error!("Failed inspecting pixel: {e}");
+                    }
+                },
+                None => {
+                    info!("No frame to inspect.");
+                }
+            },
+            Msg::ImOpsAutoThreshold => match (latest_image.as_ref(), store_cache.as_ref()) {
+                (Some((image, _fno)), Some(store_cache_ref)) => {
+                    let im_ops_state = &store_cache_ref.im_ops_state;
+                    match crate::pixel_inspection::im_ops_auto_threshold(
+                        image,
+                        im_ops_state.center_x,
+                        im_ops_state.center_y,
+                    ) {
+                        Ok(Some(threshold)) => {
+                            if let Some(ref mut store) = shared_store_arc {
+                                let mut tracker = store.write().unwrap();
+                                tracker.modify(|tracker| {
+                                    tracker.im_ops_state.threshold = threshold;
+                                });
+                            }
+                        }
+                        Ok(None) => {
+                            info!("No bright target near the ImOps center, threshold unchanged.");
+                        }
+                        Err(e) => {
+                            error!("Failed computing ImOps threshold: {e}");
+                        }
+                    }
+                }
+                _ => {
+                    info!("No frame to compute the ImOps threshold from.");
+                }
+            },
+            Msg::StartLatencyTest(config, quit_when_done) => {
+                let led_box_state = store_cache
+                    .as_ref()
+                    .and_then(|store| store.led_box_device_state);
+                let started = match (led_box_state, latency_test.as_ref()) {
+                    (_, Some(_)) => Err(eyre::eyre!("A latency test is already running.")),
+                    (None, None) => Err(eyre::eyre!("No LED box is connected.")),
+                    (Some(led_box_state), None) => crate::latency_test::LatencyTest::start(
+                        config.clone(),
+                        led_box_state,
+                        quit_when_done,
+                        std::time::Instant::now(),
+                    ),
+                };
+                match started {
+                    Ok((test, state)) => {
+                        info!("Starting latency test: {config:?}");
+                        set_led_box_state(&led_box_tx_std, state);
+                        latency_test = Some(test);
+                        if let Some(ref mut store) = shared_store_arc {
+                            let mut tracker = store.write().unwrap();
+                            tracker.modify(|tracker| {
+                                // The test measures the ImOps detector.
+                                tracker.im_ops_state.do_detection = true;
+                                tracker.latency_test.running = true;
+                                tracker.latency_test.config = config;
+                                tracker.latency_test.result = None;
+                            });
+                        }
+                    }
+                    Err(e) => {
+                        error!("Cannot start latency test: {e}");
+                        if quit_when_done {
+                            let _ = cam_args_tx.try_send(ci2_remote_control::CamArg::DoQuit);
+                        }
+                    }
+                }
+            }
+            Msg::StopLatencyTest => {
+                if let Some(test) = latency_test.take() {
+                    info!("Latency test stopped.");
+                    finish_latency_test(test, &led_box_tx_std, &shared_store_arc, &cam_args_tx);
+                }
+            }
             Msg::SetFrameOffset(fo) => {
                 opt_frame_offset = Some(fo);
             }
@@ -1461,8 +2023,10 @@ pub(crate) async fn frame_process_task<'a>(
                 triggerbox_clock_model = cm;
             }
             Msg::StopMp4 => {
+                pending_mp4_start = None;
                 if let Some(mut inner) = my_mp4_writer.take() {
-                    inner.finish()?;
+                    inner.writer.finish()?;
+                    send_mp4_recording(&mp4_recording_tx, &raw_cam_name, None);
                 }
                 if let Some(ref mut store) = shared_store_arc {
                     let mut tracker = store.write().unwrap();
@@ -1484,6 +2048,12 @@ pub(crate) async fn frame_process_task<'a>(
             }
         };
     }
+    #[cfg(feature = "flydra_feat_detect")]
+    if let Some(path) = background_model_path.as_ref() {
+        if let Err(e) = save_background_model(&im_tracker, path) {
+            info!("Background model not saved: {e}");
+        }
+    }
     info!(
         "frame process thread done for camera '{}'",
         cam_name.as_str()
@@ -1491,6 +2061,112 @@ pub(crate) async fn frame_process_task<'a>(
     Ok(())
 }
 
+/// Send `state` to the LED box without waiting.
+fn set_led_box_state(
+    led_box_tx_std: &tokio::sync::mpsc::Sender<crate::ToLedBoxDevice>,
+    state: led_box_comms::DeviceState,
+) {
+    if let Err(e) = led_box_tx_std.try_send(crate::ToLedBoxDevice::DeviceState(state)) {
+        error!("Failed setting LED box state: {e}");
+    }
+}
+
+/// End the latency test, restore the LED box and report the result.
+fn finish_latency_test(
+    test: crate::latency_test::LatencyTest,
+    led_box_tx_std: &tokio::sync::mpsc::Sender<crate::ToLedBoxDevice>,
+    shared_store_arc: &Option<Arc<RwLock<ChangeTracker<StoreType>>>>,
+    cam_args_tx: &tokio::sync::mpsc::Sender<ci2_remote_control::CamArg>,
+) {
+    let quit_when_done = test.quit_when_done;
+    let (result, initial_state) = test.finish();
+    set_led_box_state(led_box_tx_std, initial_state);
+    info!("Latency test done: {result}");
+    if let Some(store) = shared_store_arc {
+        let mut tracker = store.write().unwrap();
+        tracker.modify(|tracker| {
+            tracker.latency_test.running = false;
+            tracker.latency_test.result = Some(result.clone());
+        });
+    }
+    if quit_when_done {
+        let _ = cam_args_tx.try_send(ci2_remote_control::CamArg::DoQuit);
+    }
+}
+
+/// Inform Braid, if used, that `segment` has started or, if `None`, that MP4
+/// recording stopped.
+fn send_mp4_recording(
+    mp4_recording_tx: &Option<tokio::sync::mpsc::Sender<flydra_types::BraidHttpApiCallback>>,
+    raw_cam_name: &RawCamName,
+    segment: Option<&Mp4Segment>,
+) {
+    let Some(tx) = mp4_recording_tx else {
+        return;
+    };
+    let inner = segment.and_then(|segment| {
+        Some(flydra_types::Mp4RecordingInfo {
+            filename: segment.filename.clone(),
+            start: segment.start?,
+        })
+    });
+    let msg = flydra_types::BraidHttpApiCallback::UpdateMp4Recording(flydra_types::PerCam {
+        raw_cam_name: raw_cam_name.clone(),
+        inner,
+    });
+    // Do not wait here, as this would delay the processing of frames.
+    if let Err(e) = tx.try_send(msg) {
+        error!("Could not inform Braid about MP4 recording: {e}");
+    }
+}
+
+/// Save the background model so that it can be loaded after a restart.
+#[cfg(feature = "flydra_feat_detect")]
+fn save_background_model(
+    im_tracker: &flydra_feature_detector::FlydraFeatureDetector,
+    path: &Path,
+) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    im_tracker.save_background_model(path)?;
+    info!("Saved background model to \"{}\".", path.display());
+    Ok(())
+}
+
+/// Save `frame` as an OME-TIFF file in `data_dir`.
+///
+/// The acquisition settings are taken from `store`, if available.
+fn export_ome_tiff<FMT>(
+    data_dir: &Path,
+    prefix: &str,
+    raw_cam_name: &RawCamName,
+    frame: &dyn machine_vision_formats::iter::HasRowChunksExact<FMT>,
+    timestamp: chrono::DateTime<chrono::Utc>,
+    store: Option<&StoreType>,
+) -> Result<()>
+where
+    FMT: machine_vision_formats::PixelFormat,
+{
+    let local: chrono::DateTime<chrono::Local> = timestamp.into();
+    let filename = format!(
+        "{prefix}{}_{}.ome.tif",
+        local.format("%Y%m%d_%H%M%S.%f"),
+        raw_cam_name.as_str()
+    );
+    let path = data_dir.join(filename);
+    let meta = ome_tiff_writer::OmeMetadata {
+        camera_name: Some(raw_cam_name.as_str().to_string()),
+        acquisition_time: Some(timestamp),
+        exposure_time_usec: store.map(|s| s.exposure_time.current),
+        gain_db: store.map(|s| s.gain.current),
+    };
+    let fd = std::io::BufWriter::new(File::create(&path)?);
+    ome_tiff_writer::write_ome_tiff(fd, frame, &meta)?;
+    info!("Saved OME-TIFF to \"{}\".", path.display());
+    Ok(())
+}
+
 #[cfg(feature = "fiducial")]
 fn make_family(family: &ci2_remote_control::TagFamily) -> apriltag::Family {
     use ci2_remote_control::TagFamily::*;
@@ -1506,6 +2182,43 @@ fn make_family(family: &ci2_remote_control::TagFamily) -> apriltag::Family {
     }
 }
 
+/// Detect the AprilTag markers of a ChArUco board and locate its corners.
+#[cfg(all(feature = "checkercal", feature = "fiducial"))]
+fn find_charuco_board(
+    image: &DynamicFrame,
+    board: &camcal::charuco::CharucoBoard,
+    td: &apriltag::Detector,
+) -> Option<camcal::CheckerBoardData> {
+    /// Minimum number of corners for a view to be used for calibration.
+    const MIN_CHARUCO_CORNERS: usize = 6;
+    use apriltag::ImageU8;
+
+    let Some(mut im) = frame2april(image) else {
+        error!("ChArUco detection requires mono8 images");
+        return None;
+    };
+    let detections = td.detect(im.inner_mut());
+    let markers: Vec<camcal::charuco::MarkerDetection> = detections
+        .as_slice()
+        .iter()
+        .map(|det| camcal::charuco::MarkerDetection {
+            id: det.id(),
+            h: det.h().try_into().unwrap(),
+        })
+        .collect();
+    debug!("found {} ChArUco markers", markers.len());
+    board.to_checkerboard_data(&markers, MIN_CHARUCO_CORNERS)
+}
+
+#[cfg(all(feature = "checkercal", not(feature = "fiducial")))]
+fn find_charuco_board(
+    _image: &DynamicFrame,
+    _board: &camcal::charuco::CharucoBoard,
+) -> Option<camcal::CheckerBoardData> {
+    error!("ChArUco detection requires the `fiducial` feature");
+    None
+}
+
 #[cfg(feature = "fiducial")]
 struct AprilTagWriter {
     wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
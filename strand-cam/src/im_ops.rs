@@ -0,0 +1,271 @@
+//! Detection of the brightest points for the ImOps detector and encoding of
+//! the points in the wire formats other than the original CBOR centroid.
+//!
+//! Pixels at or above the threshold are grouped into 8-connected blobs. The
+//! blobs with the highest peak intensity (ties broken by area) are sent,
+//! brightest first, as the unweighted centroids of their pixels.
+//!
+//! All formats carry [PROTOCOL_VERSION], which is increased whenever a format
+//! changes. A message is sent for every frame, also if no point is found.
+//!
+//! The compact binary format is little-endian:
+//!
+//! | type | field |
+//! |---|---|
+//! | u8 | protocol version |
+//! | u8 | timestamp source (0: Braid trigger, 1: host acquired) |
+//! | u16 | number of points, N |
+//! | u64 | frame number |
+//! | f64 | timestamp, seconds since the Unix epoch |
+//! | u32 | center x |
+//! | u32 | center y |
+//! | u8 | length of camera name, L |
+//! | L bytes | camera name, UTF-8 |
+//! | N times f32, f32, f32 | x, y and area in pixels of each point |
+//!
+//! The OSC message has the address [OSC_ADDRESS] and the arguments `i`
+//! protocol version, `s` camera name, `h` frame number, `d` timestamp, `i`
+//! timestamp source, `i` center x, `i` center y, followed by `f` x, `f` y and
+//! `f` area of each point.
+
+use serde::Serialize;
+
+use ci2_remote_control::ImOpsFormat;
+
+use crate::TimestampSource;
+
+/// The version of the formats of [ImOpsPoints].
+pub(crate) const PROTOCOL_VERSION: u8 = 1;
+
+/// The address of OSC messages.
+pub(crate) const OSC_ADDRESS: &str = "/strand-cam/imops/points";
+
+/// A blob of pixels at or above the threshold.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct BrightPoint {
+    pub(crate) x: f32,
+    pub(crate) y: f32,
+    /// Number of pixels.
+    pub(crate) area: f32,
+    #[serde(skip)]
+    peak: u8,
+}
+
+/// The message sent for each frame.
+#[derive(Debug, Serialize)]
+pub(crate) struct ImOpsPoints<'a> {
+    pub(crate) protocol_version: u8,
+    pub(crate) cam_name: &'a str,
+    pub(crate) framenumber: u64,
+    pub(crate) timestamp: chrono::DateTime<chrono::Utc>,
+    pub(crate) timestamp_source: TimestampSource,
+    pub(crate) center_x: u32,
+    pub(crate) center_y: u32,
+    pub(crate) points: &'a [BrightPoint],
+}
+
+/// Scratch memory of [bright_points], kept from frame to frame to avoid
+/// allocating it for every frame.
+#[derive(Default)]
+pub(crate) struct BrightPointsBuffer {
+    visited: Vec<bool>,
+    stack: Vec<(usize, usize)>,
+}
+
+/// The `max_points` brightest blobs of pixels at or above `threshold`.
+pub(crate) fn bright_points(
+    buf: &mut BrightPointsBuffer,
+    data: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    threshold: u8,
+    max_points: usize,
+) -> Vec<BrightPoint> {
+    let BrightPointsBuffer { visited, stack } = buf;
+    visited.clear();
+    visited.resize(width * height, false);
+    stack.clear();
+    let mut points = Vec::new();
+    for row in 0..height {
+        for col in 0..width {
+            if visited[row * width + col] || data[row * stride + col] < threshold {
+                continue;
+            }
+            // Flood fill the blob containing this pixel.
+            visited[row * width + col] = true;
+            stack.push((col, row));
+            let (mut n, mut sum_x, mut sum_y, mut peak) = (0u64, 0u64, 0u64, 0u8);
+            while let Some((x, y)) = stack.pop() {
+                n += 1;
+                sum_x += x as u64;
+                sum_y += y as u64;
+                peak = peak.max(data[y * stride + x]);
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        if !visited[ny * width + nx] && data[ny * stride + nx] >= threshold {
+                            visited[ny * width + nx] = true;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+            }
+            points.push(BrightPoint {
+                x: (sum_x as f64 / n as f64) as f32,
+                y: (sum_y as f64 / n as f64) as f32,
+                area: n as f32,
+                peak,
+            });
+        }
+    }
+    points.sort_by(|a, b| b.peak.cmp(&a.peak).then(b.area.total_cmp(&a.area)));
+    points.truncate(max_points);
+    points
+}
+
+/// Encode `msg` in `format`.
+///
+/// Returns `None` for [ImOpsFormat::CborCentroid], which does not carry
+/// [ImOpsPoints].
+pub(crate) fn encode(format: ImOpsFormat, msg: &ImOpsPoints) -> Option<Vec<u8>> {
+    match format {
+        ImOpsFormat::CborCentroid => None,
+        ImOpsFormat::CompactBinary => Some(encode_binary(msg)),
+        ImOpsFormat::Json => Some(serde_json::to_vec(msg).unwrap()),
+        ImOpsFormat::Osc => Some(encode_osc(msg)),
+    }
+}
+
+fn timestamp_secs(msg: &ImOpsPoints) -> f64 {
+    msg.timestamp.timestamp_micros() as f64 * 1e-6
+}
+
+fn timestamp_source_code(source: &TimestampSource) -> u8 {
+    match source {
+        TimestampSource::BraidTrigger => 0,
+        TimestampSource::HostAcquiredTimestamp => 1,
+    }
+}
+
+fn encode_binary(msg: &ImOpsPoints) -> Vec<u8> {
+    // Longer names are cut off, which may split a multi-byte character.
+    let cam_name = &msg.cam_name.as_bytes()[..msg.cam_name.len().min(u8::MAX.into())];
+    let n_points = msg.points.len().min(u16::MAX.into());
+    let mut buf = Vec::with_capacity(29 + cam_name.len() + 12 * n_points);
+    buf.push(msg.protocol_version);
+    buf.push(timestamp_source_code(&msg.timestamp_source));
+    buf.extend((n_points as u16).to_le_bytes());
+    buf.extend(msg.framenumber.to_le_bytes());
+    buf.extend(timestamp_secs(msg).to_le_bytes());
+    buf.extend(msg.center_x.to_le_bytes());
+    buf.extend(msg.center_y.to_le_bytes());
+    buf.push(cam_name.len() as u8);
+    buf.extend(cam_name);
+    for pt in msg.points[..n_points].iter() {
+        buf.extend(pt.x.to_le_bytes());
+        buf.extend(pt.y.to_le_bytes());
+        buf.extend(pt.area.to_le_bytes());
+    }
+    buf
+}
+
+/// Append an OSC string, which is null terminated and padded to four bytes.
+fn push_osc_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend(s.as_bytes());
+    buf.push(0);
+    while buf.len() % 4 != 0 {
+        buf.push(0);
+    }
+}
+
+fn encode_osc(msg: &ImOpsPoints) -> Vec<u8> {
+    let mut type_tags = String::from(",ishdiii");
+    for _ in msg.points.iter() {
+        type_tags.push_str("fff");
+    }
+    let mut buf = Vec::new();
+    push_osc_string(&mut buf, OSC_ADDRESS);
+    push_osc_string(&mut buf, &type_tags);
+    buf.extend(i32::from(msg.protocol_version).to_be_bytes());
+    push_osc_string(&mut buf, msg.cam_name);
+    buf.extend((msg.framenumber as i64).to_be_bytes());
+    buf.extend(timestamp_secs(msg).to_be_bytes());
+    buf.extend(i32::from(timestamp_source_code(&msg.timestamp_source)).to_be_bytes());
+    buf.extend((msg.center_x as i32).to_be_bytes());
+    buf.extend((msg.center_y as i32).to_be_bytes());
+    for pt in msg.points.iter() {
+        buf.extend(pt.x.to_be_bytes());
+        buf.extend(pt.y.to_be_bytes());
+        buf.extend(pt.area.to_be_bytes());
+    }
+    buf
+}
+
+#[test]
+fn test_im_ops_points() {
+    // A 10x6 image, with a stride larger than its width, with three blobs: a
+    // dim large one, a bright single pixel and a bright diagonal pair.
+    let (width, height, stride) = (10, 6, 12);
+    let mut data = vec![0u8; stride * height];
+    for (x, y, value) in [
+        (0, 0, 100),
+        (1, 0, 100),
+        (0, 1, 100),
+        (1, 1, 100),
+        (5, 2, 200),
+        (8, 4, 200),
+        (9, 5, 150),
+    ] {
+        data[y * stride + x] = value;
+    }
+
+    let mut buf = BrightPointsBuffer::default();
+    let points = bright_points(&mut buf, &data, width, height, stride, 50, 10);
+    assert_eq!(points.len(), 3);
+    assert_eq!((points[0].x, points[0].y, points[0].area), (8.5, 4.5, 2.0));
+    assert_eq!((points[1].x, points[1].y, points[1].area), (5.0, 2.0, 1.0));
+    assert_eq!((points[2].x, points[2].y, points[2].area), (0.5, 0.5, 4.0));
+    // The buffer is reused.
+    assert_eq!(
+        bright_points(&mut buf, &data, width, height, stride, 50, 1).len(),
+        1
+    );
+    assert_eq!(
+        bright_points(&mut buf, &data, width, height, stride, 250, 10),
+        vec![]
+    );
+    assert_eq!(
+        bright_points(&mut buf, &data, width, height, stride, 50, 10),
+        points
+    );
+
+    let msg = ImOpsPoints {
+        protocol_version: PROTOCOL_VERSION,
+        cam_name: "cam",
+        framenumber: 42,
+        timestamp: chrono::DateTime::from_timestamp(1, 500_000_000).unwrap(),
+        timestamp_source: TimestampSource::HostAcquiredTimestamp,
+        center_x: 3,
+        center_y: 4,
+        points: &points[..2],
+    };
+
+    let buf = encode(ImOpsFormat::CompactBinary, &msg).unwrap();
+    assert_eq!(buf.len(), 28 + 1 + 3 + 2 * 12);
+    assert_eq!(&buf[..4], &[PROTOCOL_VERSION, 1, 2, 0]);
+    assert_eq!(u64::from_le_bytes(buf[4..12].try_into().unwrap()), 42);
+    assert_eq!(f64::from_le_bytes(buf[12..20].try_into().unwrap()), 1.5);
+    assert_eq!(&buf[28..32], &[3, b'c', b'a', b'm']);
+    assert_eq!(f32::from_le_bytes(buf[32..36].try_into().unwrap()), 8.5);
+
+    let buf = encode(ImOpsFormat::Json, &msg).unwrap();
+    let value: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    assert_eq!(value["protocol_version"], PROTOCOL_VERSION);
+    assert_eq!(value["points"][1]["x"], 5.0);
+
+    let buf = encode(ImOpsFormat::Osc, &msg).unwrap();
+    assert_eq!(buf.len() % 4, 0);
+    assert!(buf.starts_with(b"/strand-cam/imops/points\0\0\0\0,ishdiiiffffff\0\0"));
+
+    assert_eq!(encode(ImOpsFormat::CborCentroid, &msg), None);
+}
@@ -0,0 +1,171 @@
+//! Rotation and mirroring of camera images, for cameras which are not mounted
+//! upright.
+//!
+//! The transform is applied to every frame as it arrives from the camera, so
+//! the live view, detections, recordings and calibration all use the
+//! transformed image and its pixel coordinates.
+
+use basic_frame::DynamicFrame;
+use machine_vision_formats::{pixel_format::PixFmt, Stride};
+use rust_cam_bui_types::ImageTransform;
+
+/// The number of bytes per pixel of pixel formats which can be transformed.
+///
+/// Formats with pixels sharing chroma data (e.g. YUV422 and NV12) cannot.
+fn bytes_per_pixel(pixfmt: PixFmt) -> Option<usize> {
+    use PixFmt::*;
+    match pixfmt {
+        Mono8 | BayerRG8 | BayerGB8 | BayerGR8 | BayerBG8 => Some(1),
+        RGB8 | YUV444 => Some(3),
+        Mono32f | BayerRG32f | BayerGB32f | BayerGR32f | BayerBG32f => Some(4),
+        _ => None,
+    }
+}
+
+/// Coordinates in the original image of size `width` x `height` of the pixel
+/// at `xd`, `yd` in the transformed image.
+///
+/// This is linear in `xd`, also for values outside the image.
+fn source_xy(t: ImageTransform, xd: i64, yd: i64, width: u32, height: u32) -> (i64, i64) {
+    let (xmax, ymax) = (i64::from(width) - 1, i64::from(height) - 1);
+    let (x, y) = match t.rotation_degrees() {
+        0 => (xd, yd),
+        90 => (yd, ymax - xd),
+        180 => (xmax - xd, ymax - yd),
+        _ => (xmax - yd, xd),
+    };
+    let x = if t.is_mirrored() { xmax - x } else { x };
+    (x, y)
+}
+
+/// The pixel format of a transformed Bayer image, whose color filter pattern
+/// starts with a different color.
+fn transformed_pixfmt(t: ImageTransform, pixfmt: PixFmt, width: u32, height: u32) -> PixFmt {
+    use PixFmt::*;
+    let (is_float, pattern) = match pixfmt {
+        BayerRG8 => (false, *b"RGGB"),
+        BayerGB8 => (false, *b"GBRG"),
+        BayerGR8 => (false, *b"GRBG"),
+        BayerBG8 => (false, *b"BGGR"),
+        BayerRG32f => (true, *b"RGGB"),
+        BayerGB32f => (true, *b"GBRG"),
+        BayerGR32f => (true, *b"GRBG"),
+        BayerBG32f => (true, *b"BGGR"),
+        _ => return pixfmt,
+    };
+    // The colors of the first 2x2 pixels, row by row, of the transformed image.
+    let new_pattern: Vec<u8> = [(0, 0), (1, 0), (0, 1), (1, 1)]
+        .iter()
+        .map(|(xd, yd)| {
+            let (x, y) = source_xy(t, *xd, *yd, width, height);
+            pattern[(y.rem_euclid(2) * 2 + x.rem_euclid(2)) as usize]
+        })
+        .collect();
+    match (new_pattern.as_slice(), is_float) {
+        (b"RGGB", false) => BayerRG8,
+        (b"GBRG", false) => BayerGB8,
+        (b"GRBG", false) => BayerGR8,
+        (b"BGGR", false) => BayerBG8,
+        (b"RGGB", true) => BayerRG32f,
+        (b"GBRG", true) => BayerGB32f,
+        (b"GRBG", true) => BayerGR32f,
+        _ => BayerBG32f,
+    }
+}
+
+/// Rotate and mirror a frame.
+///
+/// This copies the image data, also for [ImageTransform::None].
+pub(crate) fn transform_frame(
+    frame: &DynamicFrame,
+    t: ImageTransform,
+) -> eyre::Result<DynamicFrame> {
+    let pixfmt = frame.pixel_format();
+    let Some(bpp) = bytes_per_pixel(pixfmt) else {
+        eyre::bail!("Image transform not supported for pixel format {pixfmt}");
+    };
+    let (width, height) = (frame.width(), frame.height());
+    let src_stride = frame.stride() as i64;
+    let src = frame.image_data_without_format();
+
+    let (dst_width, dst_height) = t.transformed_size(width, height);
+    let dst_stride = dst_width as usize * bpp;
+    let mut dst = vec![0u8; dst_stride * dst_height as usize];
+
+    let offset = |(x, y): (i64, i64)| y * src_stride + x * bpp as i64;
+    for (yd, dst_row) in dst.chunks_exact_mut(dst_stride).enumerate() {
+        // Along a row of the transformed image, the source moves by a constant
+        // step.
+        let start = offset(source_xy(t, 0, yd as i64, width, height));
+        let step = offset(source_xy(t, 1, yd as i64, width, height)) - start;
+        for (xd, dst_px) in dst_row.chunks_exact_mut(bpp).enumerate() {
+            let i = (start + xd as i64 * step) as usize;
+            dst_px.copy_from_slice(&src[i..i + bpp]);
+        }
+    }
+
+    let dst_pixfmt = transformed_pixfmt(t, pixfmt, width, height);
+    Ok(DynamicFrame::new(
+        dst_width,
+        dst_height,
+        dst_stride.try_into()?,
+        dst,
+        dst_pixfmt,
+    ))
+}
+
+#[cfg(test)]
+fn mono8(width: u32, height: u32, stride: u32, image_data: Vec<u8>) -> DynamicFrame {
+    DynamicFrame::new(width, height, stride, image_data, PixFmt::Mono8)
+}
+
+#[test]
+fn test_transform_frame() {
+    // A 3x2 image with a stride larger than its width:
+    //   1 2 3
+    //   4 5 6
+    let image = mono8(3, 2, 4, vec![1, 2, 3, 0, 4, 5, 6, 0]);
+    for (t, expected_width, expected) in [
+        (ImageTransform::None, 3, vec![1, 2, 3, 4, 5, 6]),
+        (ImageTransform::Rotate90, 2, vec![4, 1, 5, 2, 6, 3]),
+        (ImageTransform::Rotate180, 3, vec![6, 5, 4, 3, 2, 1]),
+        (ImageTransform::Rotate270, 2, vec![3, 6, 2, 5, 1, 4]),
+        (ImageTransform::Mirror, 3, vec![3, 2, 1, 6, 5, 4]),
+        (ImageTransform::MirrorRotate90, 2, vec![6, 3, 5, 2, 4, 1]),
+        (ImageTransform::MirrorRotate180, 3, vec![4, 5, 6, 1, 2, 3]),
+        (ImageTransform::MirrorRotate270, 2, vec![1, 4, 2, 5, 3, 6]),
+    ] {
+        let transformed = transform_frame(&image, t).unwrap();
+        assert_eq!(transformed.width(), expected_width, "{t:?}");
+        assert_eq!(transformed.image_data_without_format(), &expected, "{t:?}");
+
+        // Point coordinates are transformed like the pixels.
+        let (x, y) = t.apply_to_point(2.0, 0.0, 3, 2);
+        let idx = y as usize * expected_width as usize + x as usize;
+        assert_eq!(expected[idx], 3, "{t:?}");
+        assert_eq!(t.invert_point(x, y, 3, 2), (2.0, 0.0), "{t:?}");
+    }
+}
+
+#[test]
+fn test_transform_bayer() {
+    // Every pixel holds the index of its color in the pattern.
+    let image = DynamicFrame::new(4, 2, 4, vec![0, 1, 0, 1, 2, 3, 2, 3], PixFmt::BayerRG8);
+    let t = ImageTransform::Rotate180;
+    let transformed = transform_frame(&image, t).unwrap();
+    assert_eq!(transformed.pixel_format(), PixFmt::BayerBG8);
+    assert_eq!(
+        transformed.image_data_without_format(),
+        &[3, 2, 3, 2, 1, 0, 1, 0]
+    );
+    assert_eq!(
+        transformed_pixfmt(ImageTransform::Mirror, PixFmt::BayerRG8, 4, 2),
+        PixFmt::BayerGR8
+    );
+    assert_eq!(
+        transformed_pixfmt(ImageTransform::Rotate90, PixFmt::BayerRG8, 4, 2),
+        PixFmt::BayerGR8
+    );
+    let yuv422 = DynamicFrame::new(2, 1, 4, vec![0; 4], PixFmt::YUV422);
+    assert!(transform_frame(&yuv422, t).is_err());
+}
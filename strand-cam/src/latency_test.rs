@@ -0,0 +1,164 @@
+//! Measurement of the latency of the ImOps closed loop.
+//!
+//! A channel of the LED box is switched on and the time until a frame in which
+//! the ImOps detector finds a point has been sent over UDP is measured. The
+//! LED is then switched off and, once it is no longer detected and has been
+//! off for the configured duration, the next trial starts. As the time is
+//! measured from sending the command to the LED box, the latency of the LED
+//! box connection is included.
+
+use std::time::{Duration, Instant};
+
+use led_box_comms::{ChannelState, DeviceState, OnState};
+use strand_cam_storetype::{LatencyTestConfig, LatencyTestResult};
+
+enum Phase {
+    /// The LED was switched off at the given time.
+    Off(Instant),
+    /// The LED was switched on at the given time.
+    On(Instant),
+}
+
+pub(crate) struct LatencyTest {
+    config: LatencyTestConfig,
+    /// The state of the LED box before the test, which is restored after it.
+    initial_state: DeviceState,
+    phase: Phase,
+    result: LatencyTestResult,
+    /// Whether to quit Strand Camera when the test is done.
+    pub(crate) quit_when_done: bool,
+}
+
+impl LatencyTest {
+    /// Start a test with the LED box in state `initial_state`.
+    ///
+    /// Returns the test and the LED box state to set, with the LED off.
+    pub(crate) fn start(
+        config: LatencyTestConfig,
+        initial_state: DeviceState,
+        quit_when_done: bool,
+        now: Instant,
+    ) -> eyre::Result<(Self, DeviceState)> {
+        if !(1..=4).contains(&config.led_channel) {
+            eyre::bail!("LED channel {} does not exist.", config.led_channel);
+        }
+        let test = Self {
+            config,
+            initial_state,
+            phase: Phase::Off(now),
+            result: LatencyTestResult::default(),
+            quit_when_done,
+        };
+        let off = test.led_state(OnState::Off);
+        Ok((test, off))
+    }
+
+    /// Advance the test after a frame was processed and its ImOps message, if
+    /// any, was sent at `now`.
+    ///
+    /// `detected` is whether the ImOps detector found a point in the frame.
+    /// Returns the LED box state to set, if it changes.
+    pub(crate) fn update(&mut self, detected: bool, now: Instant) -> Option<DeviceState> {
+        match self.phase {
+            Phase::Off(since) => {
+                let off = Duration::from_millis(self.config.off_msec.into());
+                if !detected && now.duration_since(since) >= off && !self.is_done() {
+                    self.phase = Phase::On(now);
+                    return Some(self.led_state(OnState::ConstantOn));
+                }
+            }
+            Phase::On(since) => {
+                let elapsed = now.duration_since(since);
+                if detected {
+                    self.result
+                        .latencies_msec
+                        .push(elapsed.as_micros() as f64 / 1000.0);
+                } else if elapsed >= Duration::from_millis(self.config.timeout_msec.into()) {
+                    self.result.n_missed += 1;
+                } else {
+                    return None;
+                }
+                self.phase = Phase::Off(now);
+                return Some(self.led_state(OnState::Off));
+            }
+        }
+        None
+    }
+
+    /// Whether all trials are done.
+    pub(crate) fn is_done(&self) -> bool {
+        matches!(self.phase, Phase::Off(_)) && self.result.n_trials() >= self.config.n_trials
+    }
+
+    pub(crate) fn result(&self) -> &LatencyTestResult {
+        &self.result
+    }
+
+    /// End the test, returning its result and the LED box state to restore.
+    pub(crate) fn finish(self) -> (LatencyTestResult, DeviceState) {
+        (self.result, self.initial_state)
+    }
+
+    fn led_state(&self, on_state: OnState) -> DeviceState {
+        let mut state = self.initial_state;
+        let ch: &mut ChannelState = match self.config.led_channel {
+            1 => &mut state.ch1,
+            2 => &mut state.ch2,
+            3 => &mut state.ch3,
+            _ => &mut state.ch4,
+        };
+        ch.on_state = on_state;
+        state
+    }
+}
+
+#[test]
+fn test_latency_test() {
+    let config = LatencyTestConfig {
+        led_channel: 2,
+        n_trials: 2,
+        off_msec: 100,
+        timeout_msec: 500,
+    };
+    let t0 = Instant::now();
+    let ms = |msec| t0 + Duration::from_millis(msec);
+    let mut initial_state = DeviceState::default();
+    initial_state.ch1.on_state = OnState::ConstantOn;
+    let (mut test, off) = LatencyTest::start(config, initial_state, false, t0).unwrap();
+    assert_eq!(off.ch1.on_state, OnState::ConstantOn);
+    assert_eq!(off.ch2.on_state, OnState::Off);
+
+    // Not yet off for long enough.
+    assert_eq!(test.update(false, ms(50)), None);
+    // Switched on.
+    let on = test.update(false, ms(100)).unwrap();
+    assert_eq!(on.ch2.on_state, OnState::ConstantOn);
+    assert_eq!(test.update(false, ms(110)), None);
+    // Detected after 20 msec and switched off.
+    let off = test.update(true, ms(120)).unwrap();
+    assert_eq!(off.ch2.on_state, OnState::Off);
+    assert_eq!(test.result().latencies_msec, vec![20.0]);
+    // Still detected, so not switched on.
+    assert_eq!(test.update(true, ms(300)), None);
+    // Switched on, but not detected before the timeout.
+    assert!(test.update(false, ms(310)).is_some());
+    assert!(!test.is_done());
+    assert!(test.update(false, ms(810)).is_some());
+    assert!(test.is_done());
+    assert_eq!(test.update(false, ms(2000)), None);
+
+    let (result, restore) = test.finish();
+    assert_eq!(result.n_missed, 1);
+    assert_eq!(result.n_trials(), 2);
+    assert_eq!(restore, initial_state);
+
+    let result = LatencyTestResult {
+        latencies_msec: vec![4.0, 1.0, 3.0, 2.0],
+        n_missed: 0,
+    };
+    assert_eq!(result.quantile(0.0), Some(1.0));
+    assert_eq!(result.quantile(0.5), Some(2.0));
+    assert_eq!(result.quantile(1.0), Some(4.0));
+    assert_eq!(result.mean(), Some(2.5));
+    assert_eq!(LatencyTestResult::default().quantile(0.5), None);
+}
@@ -0,0 +1,278 @@
+use std::{path::PathBuf, result::Result as StdResult, sync::Arc};
+
+use basic_frame::DynamicFrame;
+use strand_cam_storetype::StoreType;
+
+use crate::FinalMp4RecordingConfig;
+
+/// Interval at which the size of an MP4 file being recorded is checked.
+const MP4_SIZE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// An MP4 file being recorded.
+///
+/// If rollover is enabled, a long recording consists of several such files
+/// which are recorded one after the other.
+pub(crate) struct Mp4Segment {
+    pub(crate) writer: bg_movie_writer::BgMovieWriter,
+    /// The filename template after formatting.
+    pub(crate) filename: String,
+    pub(crate) path: PathBuf,
+    /// Timestamp of the first frame.
+    pub(crate) start: Option<chrono::DateTime<chrono::Utc>>,
+    /// Sequence number of this file within the recording, starting at zero.
+    pub(crate) segment: u32,
+    size_bytes: u64,
+    last_size_check: std::time::Instant,
+}
+
+impl Mp4Segment {
+    /// Start recording a new file as configured in `shared`.
+    ///
+    /// The filename is determined by formatting the template with
+    /// `creation_time` and prepending `filename_prefix`, if given. Files after
+    /// the first of a recording also get their `segment` number appended, as
+    /// they could otherwise have the same name if they start within the
+    /// resolution of the template.
+    pub(crate) fn new(
+        shared: &StoreType,
+        data_dir: &std::path::Path,
+        creation_time: chrono::DateTime<chrono::Local>,
+        queue_size: usize,
+        filename_prefix: Option<&str>,
+        segment: u32,
+    ) -> Self {
+        let mp4_recording_config = FinalMp4RecordingConfig::new(shared, creation_time);
+        let filename = creation_time.format(&shared.format_str_mp4).to_string();
+        let filename = match filename_prefix {
+            Some(prefix) => format!("{prefix}_{filename}"),
+            None => filename,
+        };
+        let filename = segment_filename(filename, segment);
+        let path = data_dir.join(&filename);
+        let overlay = Some(&shared.mp4_overlay)
+            .filter(|overlay| overlay.enabled)
+            .map(|overlay| bg_movie_writer::Overlay {
+                cam_name: shared.camera_name.clone(),
+                corner: overlay.corner,
+                size: overlay.size,
+            });
+        let writer = bg_movie_writer::BgMovieWriter::new(
+            mp4_recording_config.final_cfg,
+            queue_size,
+            path.clone(),
+            overlay,
+        );
+        Self {
+            writer,
+            filename,
+            path,
+            start: None,
+            segment,
+            size_bytes: 0,
+            last_size_check: std::time::Instant::now(),
+        }
+    }
+
+    /// Enqueue a frame for writing.
+    ///
+    /// Returns `false` if the writer dropped the frame. The first frame which
+    /// is not dropped is the start of the file.
+    pub(crate) fn write(
+        &mut self,
+        frame: Arc<DynamicFrame>,
+        timestamp: chrono::DateTime<chrono::Utc>,
+        frame_number: usize,
+        detections: Vec<bg_movie_writer::Detection>,
+    ) -> StdResult<bool, bg_movie_writer::Error> {
+        let accepted = self
+            .writer
+            .write(frame, timestamp, frame_number, detections)?;
+        if accepted {
+            self.start.get_or_insert(timestamp);
+        }
+        Ok(accepted)
+    }
+
+    /// Finish this file without waiting for it to be written.
+    ///
+    /// The writer thread writes the remaining frames and closes the file.
+    /// Errors are logged, as the frame loop continues with the next file.
+    pub(crate) fn finish_in_background(mut self) {
+        let path = self.path.clone();
+        let result = std::thread::Builder::new()
+            .name("mp4-segment-finish".to_string())
+            .spawn(move || {
+                if let Err(e) = self.writer.finish() {
+                    tracing::error!("Error finishing MP4 file \"{}\": {e}", self.path.display());
+                }
+            });
+        if let Err(e) = result {
+            tracing::error!("Cannot finish MP4 file \"{}\": {e}", path.display());
+        }
+    }
+
+    /// Determine if a frame with `timestamp` should go into a new file.
+    pub(crate) fn needs_rollover(
+        &mut self,
+        timestamp: chrono::DateTime<chrono::Utc>,
+        rollover_minutes: Option<f64>,
+        rollover_gb: Option<f64>,
+    ) -> bool {
+        if rollover_gb.is_some() {
+            // The file is written in a background thread. Check its size
+            // occasionally rather than with every frame.
+            if self.last_size_check.elapsed() >= MP4_SIZE_CHECK_INTERVAL {
+                self.last_size_check = std::time::Instant::now();
+                // The file may not exist yet.
+                self.size_bytes = std::fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+            }
+        }
+        rollover_due(
+            self.start,
+            timestamp,
+            self.size_bytes,
+            rollover_minutes,
+            rollover_gb,
+        )
+    }
+}
+
+/// Append the sequence number `segment`, if not zero, to the filename stem.
+fn segment_filename(filename: String, segment: u32) -> String {
+    if segment == 0 {
+        return filename;
+    }
+    let path = std::path::Path::new(&filename);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => path
+            .with_file_name(format!(
+                "{}_{segment:03}.{}",
+                stem.to_string_lossy(),
+                ext.to_string_lossy()
+            ))
+            .to_string_lossy()
+            .into_owned(),
+        _ => format!("{filename}_{segment:03}"),
+    }
+}
+
+/// Determine if a file started at `start` and with `size_bytes` is complete
+/// when the frame at `timestamp` arrives.
+fn rollover_due(
+    start: Option<chrono::DateTime<chrono::Utc>>,
+    timestamp: chrono::DateTime<chrono::Utc>,
+    size_bytes: u64,
+    rollover_minutes: Option<f64>,
+    rollover_gb: Option<f64>,
+) -> bool {
+    if let (Some(start), Some(minutes)) = (start, rollover_minutes) {
+        let elapsed_sec = (timestamp - start).num_milliseconds() as f64 / 1000.0;
+        if elapsed_sec >= minutes * 60.0 {
+            return true;
+        }
+    }
+    if let Some(gb) = rollover_gb {
+        if size_bytes as f64 >= gb * 1e9 {
+            return true;
+        }
+    }
+    false
+}
+
+/// Determine if a synchronized recording starts with the frame at `timestamp`.
+///
+/// `synced_frame` is the synchronized frame number of the frame, if the frame
+/// offset is known. It may be negative for a camera which restarted.
+pub(crate) fn synchronized_start_due(
+    start: &ci2_remote_control::SynchronizedMp4Start,
+    synced_frame: Option<i64>,
+    timestamp: chrono::DateTime<chrono::Utc>,
+) -> bool {
+    match (start.start_synced_frame, synced_frame) {
+        (Some(start_frame), Some(synced_frame)) => synced_frame >= start_frame as i64,
+        _ => timestamp >= start.start_time,
+    }
+}
+
+#[test]
+fn test_segment_filename() {
+    let name = "movie20240102_030405_cam1.mp4".to_string();
+    assert_eq!(segment_filename(name.clone(), 0), name);
+    assert_eq!(
+        segment_filename(name.clone(), 1),
+        "movie20240102_030405_cam1_001.mp4"
+    );
+    assert_eq!(
+        segment_filename(name, 1234),
+        "movie20240102_030405_cam1_1234.mp4"
+    );
+    assert_eq!(
+        segment_filename("exp1/movie.mp4".to_string(), 2),
+        "exp1/movie_002.mp4"
+    );
+    assert_eq!(segment_filename("movie".to_string(), 2), "movie_002");
+}
+
+#[test]
+fn test_rollover_due() {
+    let start = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let later = |sec: i64| start + chrono::TimeDelta::try_seconds(sec).unwrap();
+
+    // Without rollover configured, a file is never complete.
+    assert!(!rollover_due(
+        Some(start),
+        later(1_000_000),
+        u64::MAX,
+        None,
+        None
+    ));
+
+    // By duration
+    let minutes = Some(2.0);
+    assert!(!rollover_due(None, later(600), 0, minutes, None));
+    assert!(!rollover_due(Some(start), later(119), 0, minutes, None));
+    assert!(rollover_due(Some(start), later(120), 0, minutes, None));
+
+    // By size
+    let gb = Some(0.5);
+    assert!(!rollover_due(Some(start), later(1), 499_999_999, None, gb));
+    assert!(rollover_due(Some(start), later(1), 500_000_000, None, gb));
+
+    // Whichever comes first
+    assert!(rollover_due(
+        Some(start),
+        later(1),
+        500_000_000,
+        minutes,
+        gb
+    ));
+    assert!(rollover_due(Some(start), later(120), 0, minutes, gb));
+    assert!(!rollover_due(Some(start), later(60), 1000, minutes, gb));
+}
+
+#[test]
+fn test_synchronized_start_due() {
+    let start_time = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let later = |sec: i64| start_time + chrono::TimeDelta::try_seconds(sec).unwrap();
+    let mut start = ci2_remote_control::SynchronizedMp4Start {
+        filename_prefix: None,
+        start_time,
+        start_synced_frame: None,
+    };
+
+    // Without a synchronized frame, the timestamp decides.
+    assert!(!synchronized_start_due(&start, Some(1000), later(-1)));
+    assert!(synchronized_start_due(&start, Some(1000), start_time));
+
+    // With one, the timestamp is ignored so that all cameras start with the
+    // same frame even if their clocks differ.
+    start.start_synced_frame = Some(1000);
+    assert!(!synchronized_start_due(&start, Some(999), later(10)));
+    assert!(synchronized_start_due(&start, Some(1000), later(-10)));
+    assert!(synchronized_start_due(&start, Some(1001), later(-10)));
+    assert!(!synchronized_start_due(&start, Some(-5), later(10)));
+
+    // A camera without frame offset falls back to the timestamp.
+    assert!(!synchronized_start_due(&start, None, later(-1)));
+    assert!(synchronized_start_due(&start, None, later(1)));
+}
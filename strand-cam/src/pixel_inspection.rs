@@ -0,0 +1,183 @@
+//! Reading pixel values, as requested from the live view.
+
+use basic_frame::DynamicFrame;
+use machine_vision_formats::{pixel_format::Mono8, ImageData, ImageStride, Stride};
+
+/// Half the width of the window around the ImOps center used by
+/// [im_ops_auto_threshold].
+const AUTO_THRESHOLD_HALF_WINDOW: u32 = 32;
+
+/// Minimum difference between the brightest pixel and the background for
+/// [im_ops_auto_threshold] to find a target.
+const AUTO_THRESHOLD_MIN_CONTRAST: u8 = 10;
+
+/// The value of each channel of the pixel at `x`, `y` of `image`.
+///
+/// Mono8, RGB8 and Bayer frames are read directly, so that the raw sensor
+/// values are reported. Frames of other pixel formats are converted to Mono8.
+/// Returns `None` if the pixel is outside the image.
+pub(crate) fn pixel_values(image: &DynamicFrame, x: u32, y: u32) -> eyre::Result<Option<Vec<f64>>> {
+    if x >= image.width() || y >= image.height() {
+        return Ok(None);
+    }
+    let (x, y) = (x as usize, y as usize);
+    let read_u8 = |data: &[u8], stride: usize, channels: usize| {
+        let start = y * stride + x * channels;
+        data[start..start + channels]
+            .iter()
+            .map(|v| f64::from(*v))
+            .collect::<Vec<_>>()
+    };
+    let read_f32 = |data: &[u8], stride: usize| {
+        let start = y * stride + x * 4;
+        let bytes: [u8; 4] = data[start..start + 4].try_into().unwrap();
+        vec![f64::from(f32::from_le_bytes(bytes))]
+    };
+    let values = match image {
+        DynamicFrame::Mono8(im) => read_u8(im.image_data(), im.stride(), 1),
+        DynamicFrame::RGB8(im) => read_u8(im.image_data(), im.stride(), 3),
+        DynamicFrame::BayerRG8(im) => read_u8(im.image_data(), im.stride(), 1),
+        DynamicFrame::BayerGB8(im) => read_u8(im.image_data(), im.stride(), 1),
+        DynamicFrame::BayerGR8(im) => read_u8(im.image_data(), im.stride(), 1),
+        DynamicFrame::BayerBG8(im) => read_u8(im.image_data(), im.stride(), 1),
+        DynamicFrame::Mono32f(im) => read_f32(im.image_data(), im.stride()),
+        DynamicFrame::BayerRG32f(im) => read_f32(im.image_data(), im.stride()),
+        DynamicFrame::BayerGB32f(im) => read_f32(im.image_data(), im.stride()),
+        DynamicFrame::BayerGR32f(im) => read_f32(im.image_data(), im.stride()),
+        DynamicFrame::BayerBG32f(im) => read_f32(im.image_data(), im.stride()),
+        _ => {
+            let mono8 = basic_frame::match_all_dynamic_fmts!(image, im, {
+                convert_image::convert_ref::<_, Mono8>(im)?
+            });
+            read_u8(mono8.image_data(), mono8.stride(), 1)
+        }
+    };
+    Ok(Some(values))
+}
+
+/// A threshold for ImOps detection of the bright target near `center_x`,
+/// `center_y`.
+///
+/// Within a window around the center, the median intensity is taken as the
+/// background and the maximum as the target. The threshold is halfway between
+/// both. Returns `None` if the center is outside the image or the window
+/// contains no target brighter than the background.
+pub(crate) fn im_ops_auto_threshold(
+    image: &DynamicFrame,
+    center_x: u32,
+    center_y: u32,
+) -> eyre::Result<Option<u8>> {
+    let threshold = |im: &dyn ImageStride<Mono8>| {
+        auto_threshold(
+            im.image_data(),
+            im.width(),
+            im.height(),
+            im.stride(),
+            center_x,
+            center_y,
+        )
+    };
+    match image {
+        DynamicFrame::Mono8(im) => Ok(threshold(im)),
+        _ => {
+            let mono8 = basic_frame::match_all_dynamic_fmts!(image, im, {
+                convert_image::convert_ref::<_, Mono8>(im)?
+            });
+            Ok(threshold(&mono8))
+        }
+    }
+}
+
+fn auto_threshold(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    center_x: u32,
+    center_y: u32,
+) -> Option<u8> {
+    // The center is set from the browser and may be anywhere.
+    if center_x >= width || center_y >= height {
+        return None;
+    }
+    let x0 = center_x.saturating_sub(AUTO_THRESHOLD_HALF_WINDOW);
+    let x1 = center_x
+        .saturating_add(AUTO_THRESHOLD_HALF_WINDOW + 1)
+        .min(width);
+    let y0 = center_y.saturating_sub(AUTO_THRESHOLD_HALF_WINDOW);
+    let y1 = center_y
+        .saturating_add(AUTO_THRESHOLD_HALF_WINDOW + 1)
+        .min(height);
+    let mut values: Vec<u8> = (y0..y1)
+        .flat_map(|y| {
+            let row = &data[y as usize * stride..];
+            row[x0 as usize..x1 as usize].iter().copied()
+        })
+        .collect();
+    values.sort_unstable();
+    let background = values[values.len() / 2];
+    let peak = values[values.len() - 1];
+    if peak - background < AUTO_THRESHOLD_MIN_CONTRAST {
+        return None;
+    }
+    Some(background + (peak - background).div_ceil(2))
+}
+
+#[test]
+fn test_pixel_values() {
+    use basic_frame::BasicFrame;
+
+    // A 2x2 RGB8 image with a stride larger than its width.
+    let image_data = vec![
+        1, 2, 3, 4, 5, 6, 0, 0, // row 0
+        7, 8, 9, 10, 11, 12, 0, 0, // row 1
+    ];
+    let image = DynamicFrame::RGB8(BasicFrame {
+        width: 2,
+        height: 2,
+        stride: 8,
+        image_data,
+        pixel_format: std::marker::PhantomData,
+    });
+    assert_eq!(
+        pixel_values(&image, 1, 1).unwrap(),
+        Some(vec![10.0, 11.0, 12.0])
+    );
+    assert_eq!(
+        pixel_values(&image, 0, 1).unwrap(),
+        Some(vec![7.0, 8.0, 9.0])
+    );
+    assert_eq!(pixel_values(&image, 2, 0).unwrap(), None);
+
+    let image = DynamicFrame::Mono32f(BasicFrame {
+        width: 1,
+        height: 1,
+        stride: 4,
+        image_data: 0.5f32.to_le_bytes().to_vec(),
+        pixel_format: std::marker::PhantomData,
+    });
+    assert_eq!(pixel_values(&image, 0, 0).unwrap(), Some(vec![0.5]));
+}
+
+#[test]
+fn test_auto_threshold() {
+    // A dark 100x100 image with a bright 3x3 target at 50, 50.
+    let mut data = vec![20u8; 100 * 100];
+    for y in 49..52 {
+        for x in 49..52 {
+            data[y * 100 + x] = 220;
+        }
+    }
+    assert_eq!(auto_threshold(&data, 100, 100, 100, 52, 48), Some(120));
+    // The target is outside the window.
+    assert_eq!(auto_threshold(&data, 100, 100, 100, 0, 0), None);
+    // The center is outside the image.
+    assert_eq!(auto_threshold(&data, 100, 100, 100, 200, 200), None);
+    assert_eq!(auto_threshold(&data, 100, 100, 100, 50, 200), None);
+    assert_eq!(
+        auto_threshold(&data, 100, 100, 100, u32::MAX, u32::MAX),
+        None
+    );
+    // The center is at the last pixel.
+    assert_eq!(auto_threshold(&data, 100, 100, 100, 99, 99), None);
+}
@@ -0,0 +1,293 @@
+//! Auto-exposure computed from a region of the image.
+//!
+//! The auto-exposure of the camera considers the whole frame, so bright arena
+//! walls darken the tracked object. Here, a histogram of the pixels within a
+//! configured region, or near the detected points, is computed and the
+//! exposure time (and optionally the gain) is adjusted so that a percentile of
+//! the intensities is held at a target. Adjustments are made at most once per
+//! interval and change the brightness by at most a fraction, so that the
+//! camera has applied the previous change before the next one is computed and
+//! the exposure does not oscillate.
+
+use std::time::{Duration, Instant};
+
+use basic_frame::DynamicFrame;
+use ci2_remote_control::CamArg;
+use http_video_streaming_types::{Point, Shape};
+use machine_vision_formats::{self as formats, pixel_format::Mono8, ImageData, Stride};
+use strand_cam_storetype::{RangedValue, SoftAutoExposureConfig};
+
+/// Only every this many-th row and column are included in the histogram.
+const SUBSAMPLE: usize = 2;
+
+/// Changes of the exposure time (in microseconds) or gain smaller than this
+/// are not made.
+const MIN_CHANGE: f64 = 1e-3;
+
+pub(crate) struct SoftAutoExposure {
+    last_update: Option<Instant>,
+}
+
+/// The result of an update of [SoftAutoExposure].
+pub(crate) struct Update {
+    /// The intensity at the percentile.
+    pub(crate) level: u8,
+    /// The commands adjusting the camera.
+    pub(crate) cam_args: Vec<CamArg>,
+}
+
+impl SoftAutoExposure {
+    pub(crate) fn new() -> Self {
+        Self { last_update: None }
+    }
+
+    /// Measure `image` and compute the adjustment of the camera.
+    ///
+    /// Returns `None` if it is not yet time for the next adjustment.
+    pub(crate) fn update(
+        &mut self,
+        cfg: &SoftAutoExposureConfig,
+        image: &DynamicFrame,
+        points: &[Point],
+        exposure_time: &RangedValue,
+        gain: &RangedValue,
+    ) -> eyre::Result<Option<Update>> {
+        let now = Instant::now();
+        let interval = Duration::from_millis(cfg.interval_msec);
+        if self
+            .last_update
+            .is_some_and(|last| now.duration_since(last) < interval)
+        {
+            return Ok(None);
+        }
+        self.last_update = Some(now);
+
+        let mono8: Box<dyn formats::ImageStride<Mono8> + '_> =
+            basic_frame::match_all_dynamic_fmts!(image, x, {
+                Box::new(convert_image::convert_ref::<_, Mono8>(x)?)
+            });
+
+        let radius2 = cfg.detection_radius.map(|r| f64::from(r).powi(2));
+        let near_points = |x: f64, y: f64| match radius2 {
+            Some(radius2) if !points.is_empty() => points
+                .iter()
+                .any(|pt| (f64::from(pt.x) - x).powi(2) + (f64::from(pt.y) - y).powi(2) <= radius2),
+            _ => true,
+        };
+        let hist = histogram(
+            mono8.image_data(),
+            mono8.width() as usize,
+            mono8.height() as usize,
+            mono8.stride(),
+            |x, y| shape_contains(&cfg.region, x, y) && near_points(x, y),
+        );
+        let Some(level) = percentile(&hist, cfg.percentile) else {
+            // No pixel in the region.
+            return Ok(None);
+        };
+
+        let mut cam_args = Vec::new();
+        if let Some((new_exposure_time, new_gain)) = next_settings(cfg, level, exposure_time, gain)
+        {
+            if let Some(v) = new_exposure_time {
+                cam_args.push(CamArg::SetExposureTime(v));
+            }
+            if let Some(v) = new_gain {
+                cam_args.push(CamArg::SetGain(v));
+            }
+        }
+        Ok(Some(Update { level, cam_args }))
+    }
+}
+
+/// The histogram of the pixels for which `include(x, y)` is true.
+fn histogram(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    include: impl Fn(f64, f64) -> bool,
+) -> [u64; 256] {
+    let mut hist = [0u64; 256];
+    for row in (0..height).step_by(SUBSAMPLE) {
+        let row_data = &data[row * stride..row * stride + width];
+        for col in (0..width).step_by(SUBSAMPLE) {
+            if include(col as f64, row as f64) {
+                hist[usize::from(row_data[col])] += 1;
+            }
+        }
+    }
+    hist
+}
+
+/// The intensity at percentile `p` (0-100) of `hist`, or `None` if `hist` is
+/// empty.
+fn percentile(hist: &[u64; 256], p: f64) -> Option<u8> {
+    let total: u64 = hist.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let rank = ((p.clamp(0.0, 100.0) / 100.0) * total as f64)
+        .ceil()
+        .max(1.0) as u64;
+    let mut count = 0;
+    for (level, n) in hist.iter().enumerate() {
+        count += n;
+        if count >= rank {
+            return Some(level as u8);
+        }
+    }
+    Some(255)
+}
+
+/// Whether the pixel at `x`, `y` is within `shape`.
+fn shape_contains(shape: &Shape, x: f64, y: f64) -> bool {
+    let in_circle = |c: &http_video_streaming_types::CircleParams| {
+        (x - f64::from(c.center_x)).powi(2) + (y - f64::from(c.center_y)).powi(2)
+            < f64::from(c.radius).powi(2)
+    };
+    match shape {
+        Shape::Everything => true,
+        Shape::Circle(c) => in_circle(c),
+        Shape::MultipleCircles(circles) => circles.iter().any(in_circle),
+        Shape::Polygon(polygon) => {
+            // Even-odd rule.
+            let points = &polygon.points;
+            let mut inside = false;
+            for (i, &(x1, y1)) in points.iter().enumerate() {
+                let (x2, y2) = points[(i + 1) % points.len()];
+                if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+                    inside = !inside;
+                }
+            }
+            inside
+        }
+    }
+}
+
+/// The exposure time and gain bringing `level` towards the target.
+///
+/// Returns `None` if `level` is within the tolerance of the target. Otherwise
+/// returns the new exposure time and gain, each `None` if unchanged. The
+/// brightness is assumed to be proportional to the exposure time and, with the
+/// gain in dB, to `10^(gain/20)`. When brightening, the exposure time is
+/// increased first, when darkening, the gain is decreased first, so that the
+/// gain is only as high as needed.
+fn next_settings(
+    cfg: &SoftAutoExposureConfig,
+    level: u8,
+    exposure_time: &RangedValue,
+    gain: &RangedValue,
+) -> Option<(Option<f64>, Option<f64>)> {
+    let level = f64::from(level);
+    if (level - cfg.target).abs() <= cfg.tolerance {
+        return None;
+    }
+    let max_factor = 1.0 + cfg.max_step.max(0.0);
+    let factor = (cfg.target / level.max(1.0)).clamp(1.0 / max_factor, max_factor);
+
+    let max_exposure_time = cfg
+        .max_exposure_time_usec
+        .map_or(exposure_time.max, |max| max.min(exposure_time.max));
+    let current_exposure_time = exposure_time.current;
+    let current_gain = gain.current;
+
+    let (new_exposure_time, new_gain) = if factor > 1.0 {
+        let new_exposure_time = (current_exposure_time * factor)
+            .min(max_exposure_time)
+            .max(current_exposure_time.min(max_exposure_time));
+        let remaining = factor * current_exposure_time / new_exposure_time;
+        let new_gain = if cfg.adjust_gain && remaining > 1.0 {
+            (current_gain + 20.0 * remaining.log10()).min(gain.max)
+        } else {
+            current_gain
+        };
+        (new_exposure_time, new_gain)
+    } else {
+        let new_gain = if cfg.adjust_gain {
+            (current_gain + 20.0 * factor.log10()).max(gain.min.min(current_gain))
+        } else {
+            current_gain
+        };
+        let remaining = factor / 10f64.powf((new_gain - current_gain) / 20.0);
+        let new_exposure_time = (current_exposure_time * remaining)
+            .max(exposure_time.min)
+            .min(max_exposure_time);
+        (new_exposure_time, new_gain)
+    };
+
+    let changed =
+        |new: f64, current: f64| Some(new).filter(|new| (new - current).abs() > MIN_CHANGE);
+    Some((
+        changed(new_exposure_time, current_exposure_time),
+        changed(new_gain, current_gain),
+    ))
+}
+
+#[test]
+fn test_soft_auto_exposure() {
+    // A 4x4 image of which the left half is dark and the right half bright.
+    let data = [10, 10, 250, 250].repeat(4);
+    let hist = histogram(&data, 4, 4, 4, |_, _| true);
+    assert_eq!(hist.iter().sum::<u64>(), 4);
+    assert_eq!(percentile(&hist, 50.0), Some(10));
+    assert_eq!(percentile(&hist, 95.0), Some(250));
+    let hist = histogram(&data, 4, 4, 4, |x, _| x < 2.0);
+    assert_eq!(percentile(&hist, 95.0), Some(10));
+    assert_eq!(percentile(&[0; 256], 50.0), None);
+
+    let square = Shape::Polygon(http_video_streaming_types::PolygonParams {
+        points: vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)],
+    });
+    assert!(shape_contains(&square, 5.0, 5.0));
+    assert!(!shape_contains(&square, 15.0, 5.0));
+
+    let ranged = |current, min, max| RangedValue {
+        name: String::new(),
+        unit: String::new(),
+        current,
+        min,
+        max,
+    };
+    let cfg = SoftAutoExposureConfig {
+        enabled: true,
+        target: 100.0,
+        tolerance: 5.0,
+        max_step: 0.5,
+        max_exposure_time_usec: Some(2000.0),
+        adjust_gain: true,
+        ..Default::default()
+    };
+    let gain = ranged(0.0, 0.0, 12.0);
+
+    // Within tolerance.
+    assert_eq!(
+        next_settings(&cfg, 103, &ranged(1000.0, 10.0, 10000.0), &gain),
+        None
+    );
+    // Too dark: the change is limited to `max_step`.
+    assert_eq!(
+        next_settings(&cfg, 10, &ranged(1000.0, 10.0, 10000.0), &gain),
+        Some((Some(1500.0), None))
+    );
+    // Too bright.
+    assert_eq!(
+        next_settings(&cfg, 125, &ranged(1000.0, 10.0, 10000.0), &gain),
+        Some((Some(800.0), None))
+    );
+    // At the exposure time limit, the gain is raised.
+    let (exposure_time, gain) =
+        next_settings(&cfg, 50, &ranged(2000.0, 10.0, 10000.0), &gain).unwrap();
+    assert_eq!(exposure_time, None);
+    assert!((gain.unwrap() - 20.0 * 1.5f64.log10()).abs() < 1e-9);
+    // When darkening, the gain is lowered first.
+    let (exposure_time, gain) = next_settings(
+        &cfg,
+        125,
+        &ranged(2000.0, 10.0, 10000.0),
+        &ranged(3.0, 0.0, 12.0),
+    )
+    .unwrap();
+    assert_eq!(exposure_time, None);
+    assert!((gain.unwrap() - (3.0 + 20.0 * 0.8f64.log10())).abs() < 1e-9);
+}
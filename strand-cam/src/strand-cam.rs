@@ -7,8 +7,8 @@
 
 use async_change_tracker::ChangeTracker;
 use event_stream_types::{
-    AcceptsEventStream, ConnectionEvent, ConnectionEventType, ConnectionSessionKey,
-    EventBroadcaster, TolerantJson,
+    AcceptsEventStream, AccessRole, ConnectionEvent, ConnectionEventType, ConnectionSessionKey,
+    EventBroadcaster, EventsBody, TolerantJson,
 };
 use futures::{sink::SinkExt, stream::StreamExt};
 use http::StatusCode;
@@ -23,7 +23,7 @@ use preferences_serde1::{AppInfo, Preferences};
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::error::SendError;
 use tower_http::trace::TraceLayer;
-use tracing::{debug, error, info, trace, warn};
+use tracing::{debug, error, info, trace, warn, Instrument};
 
 use basic_frame::{match_all_dynamic_fmts, DynamicFrame};
 use bui_backend_session_types::{AccessToken, ConnectionKey, SessionKey};
@@ -56,10 +56,13 @@ use strand_cam_csv_config_types::CameraCfgFview2_0_26;
 #[cfg(feature = "fiducial")]
 use strand_cam_storetype::ApriltagState;
 use strand_cam_storetype::{
-    CallbackType, ImOpsState, RangedValue, StoreType, ToLedBoxDevice, STRAND_CAM_EVENT_NAME,
+    CallbackType, ImOpsState, RangedValue, SettingsProfile, StoreType, ToLedBoxDevice,
+    STRAND_CAM_EVENT_NAME,
 };
 
-use rust_cam_bui_types::RecordingPath;
+use disk_space_monitor::DiskSpaceLevel;
+use env_tracing_logger::{component_log_path, LogComponent, LogConfig, LogHandle};
+use rust_cam_bui_types::{ImageTransform, RecordingPath};
 use strand_cam_storetype::{KalmanTrackingConfig, LedProgramConfig};
 
 use std::{
@@ -102,7 +105,15 @@ mod flydratrax_handle_msg;
 
 mod clock_model;
 mod datagram_socket;
+mod im_ops;
+mod image_transform;
+mod latency_test;
+mod mp4_segment;
+use mp4_segment::{synchronized_start_due, Mp4Segment};
+mod pixel_inspection;
 mod post_trigger_buffer;
+mod soft_auto_exposure;
+mod watchdog;
 
 #[cfg(feature = "eframe-gui")]
 mod gui_app;
@@ -132,7 +143,8 @@ use eyre::{eyre, Result, WrapErr};
 pub(crate) enum Msg {
     StartMp4,
     StopMp4,
-    StartFMF((String, RecordingFrameRate)),
+    StartSynchronizedMp4(ci2_remote_control::SynchronizedMp4Start),
+    StartFMF((String, RecordingFrameRate, fmf::Compression)),
     StopFMF,
     #[cfg(feature = "flydra_feat_detect")]
     StartUFMF(String),
@@ -142,6 +154,7 @@ pub(crate) enum Msg {
     SetTracking(bool),
     PostTriggerStartMp4,
     SetPostTriggerBufferSize(usize),
+    SetRecordingPaused(bool),
     Mframe(DynamicFrameWithInfo),
     #[cfg(feature = "flydra_feat_detect")]
     SetIsSavingObjDetectionCsv(CsvSaveConfig),
@@ -152,6 +165,16 @@ pub(crate) enum Msg {
     TakeCurrentImageAsBackground,
     #[cfg(feature = "flydra_feat_detect")]
     ClearBackground(f32),
+    #[cfg(feature = "flydra_feat_detect")]
+    SaveBackgroundModel,
+    ExportFrameOmeTiff,
+    #[cfg(feature = "flydra_feat_detect")]
+    ExportBackgroundOmeTiff,
+    InspectPixel(u32, u32),
+    ImOpsAutoThreshold,
+    /// Start a latency test and whether to quit when it is done.
+    StartLatencyTest(strand_cam_storetype::LatencyTestConfig, bool),
+    StopLatencyTest,
     SetFrameOffset(u64),
     SetTriggerboxClockModel(Option<rust_cam_bui_types::ClockModel>),
     StartAprilTagRec(String),
@@ -172,6 +195,60 @@ pub enum FrameProcessingErrorState {
     IgnoreAll,
 }
 
+/// Minimum interval between alerts that frame processing is falling behind.
+const FRAME_PROCESSING_ALERT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Parts of Strand Camera with their own log file in addition to the main log
+/// file.
+const LOG_COMPONENTS: &[LogComponent] = &[
+    LogComponent {
+        name: "camera",
+        targets: &["ci2"],
+    },
+    LogComponent {
+        name: "detection",
+        targets: &["flydra_feature_detector", "strand_cam::frame_process_task"],
+    },
+    LogComponent {
+        name: "recording",
+        targets: &["mp4_writer", "bg_movie_writer", "fmf"],
+    },
+];
+
+/// Where alerts about runtime problems are sent.
+#[derive(Clone)]
+enum AlertSink {
+    /// Forward alerts to Braid, which shows them and sends notifications.
+    Braid(tokio::sync::mpsc::Sender<flydra_types::BraidHttpApiCallback>),
+    /// Send notifications as configured for standalone Strand Camera.
+    Notifier(alert_notifier::Notifier),
+    /// Only log alerts.
+    LogOnly,
+}
+
+impl AlertSink {
+    fn raise(&self, raw_cam_name: &RawCamName, kind: &str, message: String) {
+        error!("{message}");
+        match self {
+            AlertSink::Braid(transmit_msg_tx) => {
+                let msg = flydra_types::BraidHttpApiCallback::ReportAlert(flydra_types::Alert {
+                    time: chrono::Utc::now(),
+                    source: raw_cam_name.as_str().to_string(),
+                    kind: kind.to_string(),
+                    message,
+                });
+                if let Err(e) = transmit_msg_tx.try_send(msg) {
+                    warn!("could not send alert to Braid: {e}");
+                }
+            }
+            AlertSink::Notifier(notifier) => {
+                notifier.notify(raw_cam_name.as_str(), kind, &message);
+            }
+            AlertSink::LogOnly => {}
+        }
+    }
+}
+
 #[cfg(feature = "flydra_feat_detect")]
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Tracker {
@@ -222,6 +299,7 @@ where
     T: std::io::Write + std::io::Seek,
 {
     writer: FMFWriter<T>,
+    path: PathBuf,
     recording_framerate: RecordingFrameRate,
     last_saved_stamp: Option<chrono::DateTime<chrono::Utc>>,
 }
@@ -230,17 +308,142 @@ impl<T> FmfWriteInfo<T>
 where
     T: std::io::Write + std::io::Seek,
 {
-    fn new(writer: FMFWriter<T>, recording_framerate: RecordingFrameRate) -> Self {
+    fn new(writer: FMFWriter<T>, path: PathBuf, recording_framerate: RecordingFrameRate) -> Self {
         Self {
             writer,
+            path,
             recording_framerate,
             last_saved_stamp: None,
         }
     }
 }
 
+/// Save the time at which a recording was paused or resumed.
+///
+/// While paused, no frames are written, so the recorded timestamps jump.
+/// To allow these discontinuities to be identified during analysis, the
+/// events are appended to a CSV file next to the recording, named like the
+/// recording with `.pauses.csv` appended.
+fn log_pause_event(
+    recording: &std::path::Path,
+    event: &str,
+    timestamp: chrono::DateTime<chrono::Utc>,
+) -> std::io::Result<()> {
+    let mut path = recording.as_os_str().to_owned();
+    path.push(".pauses.csv");
+    let path = PathBuf::from(path);
+    let is_new = !path.exists();
+    let mut fd = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    if is_new {
+        writeln!(fd, "event,timestamp")?;
+    }
+    writeln!(fd, "{event},{}", timestamp.to_rfc3339())?;
+    Ok(())
+}
+
+/// Location of the saved background model of a camera.
+#[cfg(feature = "flydra_feat_detect")]
+fn background_model_path(raw_cam_name: &RawCamName) -> Option<PathBuf> {
+    directories::BaseDirs::new().map(|bd| {
+        bd.config_dir()
+            .join(APP_INFO.name)
+            .join("background")
+            .join(format!("{}.bgmodel", raw_cam_name.as_str()))
+    })
+}
+
+/// Directory in which the named settings profiles are saved.
+fn settings_profile_dir() -> Result<PathBuf> {
+    directories::BaseDirs::new()
+        .map(|bd| bd.config_dir().join(APP_INFO.name).join("profiles"))
+        .ok_or_else(|| eyre!("could not determine configuration directory"))
+}
+
+fn settings_profile_path(name: &str) -> Result<PathBuf> {
+    if !strand_cam_storetype::is_valid_settings_profile_name(name) {
+        eyre::bail!("invalid settings profile name \"{name}\"");
+    }
+    Ok(settings_profile_dir()?.join(format!("{name}.yaml")))
+}
+
+/// Names of the saved settings profiles, sorted alphabetically.
+fn list_settings_profiles() -> Vec<String> {
+    let Ok(entries) = settings_profile_dir().and_then(|dir| Ok(std::fs::read_dir(dir)?)) else {
+        return vec![];
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "yaml" {
+                return None;
+            }
+            Some(path.file_stem()?.to_str()?.to_string())
+        })
+        .filter(|name| strand_cam_storetype::is_valid_settings_profile_name(name))
+        .collect();
+    names.sort();
+    names
+}
+
+fn save_settings_profile(name: &str, profile: &SettingsProfile) -> Result<()> {
+    let path = settings_profile_path(name)?;
+    std::fs::create_dir_all(settings_profile_dir()?)?;
+    let f = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+    serde_yaml::to_writer(f, profile)?;
+    Ok(())
+}
+
+fn load_settings_profile(name: &str) -> Result<SettingsProfile> {
+    let path = settings_profile_path(name)?;
+    let f = File::open(&path).with_context(|| format!("opening {}", path.display()))?;
+    Ok(serde_yaml::from_reader(f)?)
+}
+
+/// Key of the image transform of a camera in the preferences.
+fn image_transform_prefs_key(raw_cam_name: &RawCamName) -> String {
+    format!(
+        "image-transform/{}",
+        flydra_types::braid_http::encode_cam_name(raw_cam_name)
+    )
+}
+
+/// Key of the live view crop of a camera in the preferences.
+fn preview_crop_prefs_key(raw_cam_name: &RawCamName) -> String {
+    format!(
+        "preview-crop/{}",
+        flydra_types::braid_http::encode_cam_name(raw_cam_name)
+    )
+}
+
 #[cfg(feature = "checkercal")]
-type CollectedCornersArc = Arc<RwLock<Vec<Vec<(f32, f32)>>>>;
+type CollectedCornersArc = Arc<RwLock<Vec<camcal::CheckerBoardData>>>;
+
+/// Compute which regions of the image contain collected checkerboard corners.
+#[cfg(feature = "checkercal")]
+fn checkerboard_coverage(
+    collected_corners: &[camcal::CheckerBoardData],
+    image_width: u32,
+    image_height: u32,
+) -> strand_cam_storetype::CheckerboardCoverage {
+    let strand_cam_storetype::CheckerboardCoverage { n_cols, n_rows, .. } = Default::default();
+    let size = camcal::PixelSize::new(image_width as usize, image_height as usize);
+    let counts = camcal::coverage_counts(
+        &size,
+        n_cols as usize,
+        n_rows as usize,
+        collected_corners
+            .iter()
+            .flat_map(|board| board.points().iter().copied()),
+    );
+    strand_cam_storetype::CheckerboardCoverage {
+        n_cols,
+        n_rows,
+        counts,
+    }
+}
 
 async fn convert_stream(
     raw_cam_name: RawCamName,
@@ -464,6 +667,10 @@ pub enum TimestampSource {
 
 const MOMENT_CENTROID_SCHEMA_VERSION: u8 = 2;
 
+/// How long to wait for the LED box before a latency test requested on the
+/// command line is given up.
+const LATENCY_TEST_LED_BOX_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct MomentCentroid {
     pub schema_version: u8,
@@ -502,6 +709,9 @@ pub struct StandaloneArgs {
     /// The HTTP socket address for the Strand Cam BUI.
     pub http_server_addr: Option<String>,
     pub pixel_format: Option<String>,
+    /// Rotation and mirroring of the camera images. If not set, the transform
+    /// last saved from the web UI is used.
+    pub image_transform: Option<ImageTransform>,
     /// If set, camera acquisition will external trigger.
     pub force_camera_sync_mode: bool,
     /// If enabled, limit framerate (FPS) at startup.
@@ -552,6 +762,9 @@ pub struct StrandCamArgs {
     pub save_empty_data2d: SaveEmptyData2dType,
     #[cfg(feature = "flydratrax")]
     pub model_server_addr: std::net::SocketAddr,
+    /// Stream the tracking to rerun.
+    #[cfg(feature = "flydratrax")]
+    pub rerun: Option<flydra2::RerunTarget>,
     #[cfg(feature = "flydratrax")]
     pub flydratrax_calibration_source: CalSource,
     #[cfg(feature = "fiducial")]
@@ -561,8 +774,35 @@ pub struct StrandCamArgs {
     #[cfg(target_os = "linux")]
     v4l2loopback: Option<PathBuf>,
     data_dir: Option<PathBuf>,
+    /// Warn when less than this many megabytes are free in the data directory.
+    disk_space_warning_mb: u64,
+    /// Stop recordings when less than this many megabytes are free in the data
+    /// directory.
+    disk_space_stop_mb: u64,
+    /// Where to send alerts about runtime problems when not running inside
+    /// Braid.
+    notifications: Option<alert_notifier::NotificationConfig>,
+    /// Write the log files as JSON.
+    log_json: bool,
+    /// Tokens granting read-only access to the web UI.
+    pub read_only_tokens: Vec<String>,
+    /// Serve the web UI over HTTPS instead of HTTP.
+    pub tls: Option<https_server::TlsConfig>,
+    /// Do not advertise the web UI on the local network by mDNS.
+    pub no_mdns: bool,
     #[cfg(feature = "eframe-gui")]
     windowed: Option<bool>,
+    /// If set, do this latency test after startup and then quit.
+    pub latency_test: Option<StartupLatencyTest>,
+}
+
+/// A latency test requested on the command line.
+#[derive(Debug, Clone)]
+pub struct StartupLatencyTest {
+    pub config: strand_cam_storetype::LatencyTestConfig,
+    /// The ImOps threshold to set before the test. If not set, the current
+    /// threshold is used.
+    pub im_ops_threshold: Option<u8>,
 }
 
 pub type SaveEmptyData2dType = bool;
@@ -599,24 +839,35 @@ impl Default for StrandCamArgs {
             #[cfg(feature = "flydratrax")]
             model_server_addr: flydra_types::DEFAULT_MODEL_SERVER_ADDR.parse().unwrap(),
             #[cfg(feature = "flydratrax")]
+            rerun: None,
+            #[cfg(feature = "flydratrax")]
             write_buffer_size_num_messages:
                 braid_config_data::default_write_buffer_size_num_messages(),
             #[cfg(target_os = "linux")]
             v4l2loopback: None,
             data_dir: Default::default(),
+            disk_space_warning_mb: disk_space_monitor::DEFAULT_WARNING_THRESHOLD_MB,
+            disk_space_stop_mb: disk_space_monitor::DEFAULT_STOP_THRESHOLD_MB,
+            notifications: None,
+            log_json: false,
+            read_only_tokens: Vec::new(),
+            tls: None,
+            no_mdns: false,
             #[cfg(feature = "eframe-gui")]
             windowed: Default::default(),
+            latency_test: None,
         }
     }
 }
 
-fn test_nvenc_save(frame: DynamicFrame) -> Result<bool> {
+fn test_nvenc_save(frame: &DynamicFrame) -> Result<bool> {
     let cfg = Mp4RecordingConfig {
         codec: Mp4Codec::H264NvEnc(NvidiaH264Options {
             bitrate: 1000,
             cuda_device: 0,
         }),
         h264_metadata: None,
+        color_encoding: Default::default(),
         max_framerate: RecordingFrameRate::Fps30,
     };
     let mut nv_cfg_test = cfg.clone();
@@ -649,7 +900,7 @@ fn test_nvenc_save(frame: DynamicFrame) -> Result<bool> {
     };
 
     let mut mp4_writer = mp4_writer::Mp4Writer::new(&mut buf, nv_cfg_test, Some(nv_enc))?;
-    match mp4_writer.write_dynamic(&frame, chrono::Local::now()) {
+    match mp4_writer.write_dynamic(frame, chrono::Local::now()) {
         Ok(()) => {}
         Err(e) => {
             debug!("nvidia NvEnc could not be initialized: {:?}", e);
@@ -680,7 +931,62 @@ async fn events_handler(
     session_key.is_present();
     tracing::trace!("events");
     // Connection wants to subscribe to event stream.
+    let path = req.uri().path().to_string();
+    new_event_connection(&app_state, SessionKey(session_key.0), addr, path).await
+}
 
+/// Serve the events and callbacks over a WebSocket.
+///
+/// This is an alternative to the event stream at
+/// [strand_cam_storetype::STRAND_CAM_EVENTS_URL_PATH] together with POST
+/// requests to `/callback`. Braid does not proxy WebSockets, so the browser
+/// falls back to the event stream when Strand Camera is viewed via Braid.
+async fn websocket_handler(
+    axum::extract::State(app_state): axum::extract::State<StrandCamAppState>,
+    session_key: axum_token_auth::SessionKey,
+    axum::extract::ConnectInfo(addr): axum::extract::ConnectInfo<SocketAddr>,
+    axum::Extension(role): axum::Extension<AccessRole>,
+    uri: axum::http::Uri,
+    ws: axum::extract::ws::WebSocketUpgrade,
+) -> impl axum::response::IntoResponse {
+    session_key.is_present();
+    tracing::trace!("websocket");
+    let path = uri.path().to_string();
+    let session_key = SessionKey(session_key.0);
+    let body = new_event_connection(&app_state, session_key, addr, path.clone()).await?;
+    Ok::<_, (StatusCode, &'static str)>(ws.on_upgrade(move |socket| async move {
+        event_stream_types::serve_websocket(socket, body, |payload: CallbackType| {
+            let app_state = &app_state;
+            async move {
+                if role == AccessRole::Control || payload.is_read_only() {
+                    handle_callback(app_state, payload).await;
+                } else {
+                    tracing::warn!("Ignoring callback from {addr} with read-only access.");
+                }
+            }
+        })
+        .await;
+        // Unlike an event stream, a WebSocket tells us when it is closed.
+        app_state
+            .tx_new_connection
+            .send(ConnectionEvent {
+                typ: ConnectionEventType::Disconnect,
+                session_key,
+                connection_key: ConnectionKey { addr },
+                path,
+            })
+            .await
+            .ignore_send_error();
+    }))
+}
+
+/// Register a new connection and send it the initial events.
+async fn new_event_connection(
+    app_state: &StrandCamAppState,
+    session_key: SessionKey,
+    addr: SocketAddr,
+    path: String,
+) -> std::result::Result<EventsBody, (StatusCode, &'static str)> {
     let key = ConnectionSessionKey::new(session_key.0, addr);
     let (tx, body) = app_state.event_broadcaster.new_connection(key);
 
@@ -721,9 +1027,7 @@ async fn events_handler(
     // the new connection. The sender receives changes from a global change
     // receiver.
     let typ = ConnectionEventType::Connect(tx);
-    let path = req.uri().path().to_string();
     let connection_key = ConnectionKey { addr };
-    let session_key = SessionKey(session_key.0);
 
     match app_state
         .tx_new_connection
@@ -754,10 +1058,20 @@ async fn cam_name_handler(
 async fn callback_handler(
     axum::extract::State(app_state): axum::extract::State<StrandCamAppState>,
     session_key: axum_token_auth::SessionKey,
+    axum::Extension(role): axum::Extension<AccessRole>,
     TolerantJson(payload): TolerantJson<CallbackType>,
 ) -> impl axum::response::IntoResponse {
     session_key.is_present();
     tracing::trace!("callback");
+    if !payload.is_read_only() {
+        role.require_control()?;
+    }
+    handle_callback(&app_state, payload).await;
+    Ok::<_, (StatusCode, &'static str)>(axum::Json(()))
+}
+
+/// Handle a callback from the browser, received by POST or WebSocket.
+async fn handle_callback(app_state: &StrandCamAppState, payload: CallbackType) {
     match payload {
         CallbackType::ToCamera(cam_arg) => {
             debug!("in cb: {:?}", cam_arg);
@@ -796,6 +1110,64 @@ async fn callback_handler(
             #[cfg(not(feature = "flydra_feat_detect"))]
             let _ = value;
         }
+        CallbackType::SaveBackgroundModel => {
+            #[cfg(feature = "flydra_feat_detect")]
+            app_state
+                .callback_senders
+                .tx_frame
+                .send(Msg::SaveBackgroundModel)
+                .await
+                .ignore_send_error();
+        }
+        CallbackType::ExportFrameOmeTiff => {
+            app_state
+                .callback_senders
+                .tx_frame
+                .send(Msg::ExportFrameOmeTiff)
+                .await
+                .ignore_send_error();
+        }
+        CallbackType::ExportBackgroundOmeTiff => {
+            #[cfg(feature = "flydra_feat_detect")]
+            app_state
+                .callback_senders
+                .tx_frame
+                .send(Msg::ExportBackgroundOmeTiff)
+                .await
+                .ignore_send_error();
+        }
+        CallbackType::InspectPixel(x, y) => {
+            app_state
+                .callback_senders
+                .tx_frame
+                .send(Msg::InspectPixel(x, y))
+                .await
+                .ignore_send_error();
+        }
+        CallbackType::ImOpsAutoThreshold => {
+            app_state
+                .callback_senders
+                .tx_frame
+                .send(Msg::ImOpsAutoThreshold)
+                .await
+                .ignore_send_error();
+        }
+        CallbackType::StartLatencyTest(config) => {
+            app_state
+                .callback_senders
+                .tx_frame
+                .send(Msg::StartLatencyTest(config, false))
+                .await
+                .ignore_send_error();
+        }
+        CallbackType::StopLatencyTest => {
+            app_state
+                .callback_senders
+                .tx_frame
+                .send(Msg::StopLatencyTest)
+                .await
+                .ignore_send_error();
+        }
         CallbackType::ToLedBox(led_box_arg) => futures::executor::block_on(async {
             info!("in led_box callback: {:?}", led_box_arg);
             app_state
@@ -806,7 +1178,6 @@ async fn callback_handler(
                 .ignore_send_error();
         }),
     }
-    Ok::<_, axum::extract::rejection::JsonRejection>(axum::Json(()))
 }
 
 async fn handle_auth_error(err: tower::BoxError) -> (StatusCode, &'static str) {
@@ -910,11 +1281,13 @@ where
     #[cfg(not(feature = "eframe-gui"))]
     let disable_console = args.disable_console;
 
-    let initial_log_file_name2 = initial_log_file_name.clone();
-
-    let _guard =
-        env_tracing_logger::initiate_logging(Some(&initial_log_file_name2), disable_console)
-            .map_err(|e| eyre!("error initiating logging: {e}"))?;
+    let log_handle = env_tracing_logger::initiate_logging_with_config(LogConfig {
+        path: Some(initial_log_file_name.clone()),
+        disable_console,
+        json: args.log_json,
+        components: LOG_COMPONENTS,
+    })
+    .map_err(|e| eyre!("error initiating logging: {e}"))?;
 
     // create tokio runtime
     let runtime = tokio::runtime::Builder::new_multi_thread()
@@ -929,6 +1302,7 @@ where
         log_dir,
         data_dir,
         log_file_time,
+        log_handle,
     };
 
     #[cfg(feature = "eframe-gui")]
@@ -1075,6 +1449,7 @@ struct LogFileInfo {
     /// where movies are saved
     data_dir: PathBuf,
     log_file_time: chrono::DateTime<chrono::Local>,
+    log_handle: LogHandle,
 }
 
 /// First, connect to Braid if requested, then run.
@@ -1179,6 +1554,17 @@ where
             )
         },
     )?;
+    for component in LOG_COMPONENTS.iter() {
+        let from = component_log_path(&log_file_info.initial_log_file_name, component.name);
+        let to = component_log_path(&new_log_file_name, component.name);
+        std::fs::rename(&from, &to).with_context(|| {
+            format!(
+                "Renaming log file \"{}\" -> \"{}\"",
+                from.display(),
+                to.display()
+            )
+        })?;
+    }
 
     run(
         mymod,
@@ -1190,6 +1576,7 @@ where
         gui_app_stuff,
         gui_singleton,
         log_file_info.data_dir,
+        log_file_info.log_handle,
     )
     .await
 }
@@ -1209,7 +1596,8 @@ where
     strand_cam_bui_http_address_string,
     gui_app_stuff,
     gui_singleton,
-    data_dir
+    data_dir,
+    log_handle
 ))]
 async fn run<M, C, G>(
     mut mymod: ci2_async::ThreadedAsyncCameraModule<M, C, G>,
@@ -1221,6 +1609,7 @@ async fn run<M, C, G>(
     gui_app_stuff: Option<GuiAppStuff>,
     gui_singleton: ArcMutGuiSingleton,
     data_dir: PathBuf,
+    log_handle: LogHandle,
 ) -> Result<ci2_async::ThreadedAsyncCameraModule<M, C, G>>
 where
     M: ci2::CameraModule<CameraType = C, Guard = G>,
@@ -1292,6 +1681,20 @@ where
         Err(a) => a.pixel_format.clone(),
     };
 
+    let image_transform_key = image_transform_prefs_key(&raw_cam_name);
+    let image_transform = match &res_braid {
+        Ok(bi) => bi.config_from_braid.config.image_transform,
+        Err(a) => a.image_transform.unwrap_or_else(|| {
+            Preferences::load(&APP_INFO, &image_transform_key).unwrap_or_default()
+        }),
+    };
+
+    // The live view crop only affects the browser, so it is saved also when
+    // running with Braid.
+    let preview_crop_key = preview_crop_prefs_key(&raw_cam_name);
+    let preview_crop: Option<video_streaming::PreviewCrop> =
+        Preferences::load(&APP_INFO, &preview_crop_key).unwrap_or_default();
+
     let send_image_to_braid_interval = res_braid.as_ref().ok().map(|bi| {
         std::time::Duration::from_millis(
             bi.config_from_braid.config.send_current_image_interval_msec,
@@ -1393,16 +1796,27 @@ where
 
     // Get initial frame to determine width, height and pixel_format.
     debug!("  started acquisition, waiting for first frame");
-    let frame = cam.next_frame()?;
+    let mut frame = cam.next_frame()?;
     info!(
         "  acquired first frame: {}x{}",
         frame.width(),
         frame.height()
     );
+    if !image_transform.is_identity() {
+        frame.image = Arc::new(image_transform::transform_frame(
+            &frame.image,
+            image_transform,
+        )?);
+        info!(
+            "  transformed images ({image_transform}): {}x{}",
+            frame.width(),
+            frame.height()
+        );
+    }
 
     #[cfg(target_os = "linux")]
     let v4l_out_stream = {
-        let frame = &frame.image;
+        let frame: &DynamicFrame = &frame.image;
         use machine_vision_formats::Stride;
         if let Some(v4l_device) = &args.v4l2loopback {
             if frame.pixel_format() != PixFmt::Mono8 {
@@ -1464,7 +1878,7 @@ where
     let image_width = frame.width();
     let image_height = frame.height();
 
-    let current_image_png = match_all_dynamic_fmts!(&frame.image, x, {
+    let current_image_png = match_all_dynamic_fmts!(&*frame.image, x, {
         convert_image::frame_to_encoded_buffer(x, convert_image::EncoderOptions::Png)?
     });
 
@@ -1552,6 +1966,7 @@ where
     let mut local_time0 = None;
     let mut cam_time0 = None;
     let mut device_clock_model = None;
+    let mut ptp_status = None;
 
     match &trigger_type {
         Some(TriggerType::PtpSync(ptpcfg)) => {
@@ -1560,26 +1975,29 @@ where
                 tracing::debug!("Set camera parameter {PERIOD_NAME} to {period} microseconds");
             }
             cam.feature_bool_set("PtpEnable", true)?;
-            // Wait until we are within 1 msec from master.
-            const THRESHOLD: i64 = 1_000_000; // Should make this a runtime parameter.
+            // Wait until we are within the lock threshold from master.
+            let threshold = ptpcfg.lock_threshold_nsec();
             loop {
-                cam.command_execute("PtpDataSetLatch", true)?;
-                let ptp_offset_from_master = cam.feature_int("PtpOffsetFromMaster")?;
-                // Basler docs: "PtpOffsetFromMaster: Indicates the estimated
-                // temporal offset between the master clock and the clock of the
-                // current PTP device in ticks (1 tick = 1 nanosecond)."
-                tracing::debug!("PTP clock offset {ptp_offset_from_master} nanoseconds.");
-                if ptp_offset_from_master.abs() < THRESHOLD {
-                    // if within 1 millisecond from master, call it good enough.
+                let Some(status) = read_ptp_status(&cam, threshold)? else {
+                    eyre::bail!("PTP synchronization configured, but camera does not support PTP.");
+                };
+                tracing::debug!(
+                    "PTP status {}, clock offset {} nanoseconds.",
+                    status.status,
+                    status.offset_from_master_nsec
+                );
+                if status.is_locked {
+                    ptp_status = Some(status);
                     break;
                 }
                 tracing::warn!(
-                    "PTP clock offset {ptp_offset_from_master} nanoseconds (threshold \
-                        {THRESHOLD}), waiting 1 second for convergence."
+                    "PTP clock offset {} nanoseconds (threshold {threshold}), waiting 1 \
+                        second for convergence.",
+                    status.offset_from_master_nsec
                 );
                 tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
             }
-            tracing::info!("PTP clock within threshold {THRESHOLD} nanoseconds from master.");
+            tracing::info!("PTP clock within threshold {threshold} nanoseconds from master.");
 
             if cam.feature_enum("TriggerMode")? != "On" {
                 cam.feature_enum_set("TriggerMode", "On")?;
@@ -1715,6 +2133,12 @@ where
         tracing::info!("Registered camera with Braid.");
     }
 
+    let alert_sink = match (&transmit_msg_tx, args.notifications.clone()) {
+        (Some(transmit_msg_tx), _) => AlertSink::Braid(transmit_msg_tx.clone()),
+        (None, Some(cfg)) => AlertSink::Notifier(alert_notifier::Notifier::new(cfg)?),
+        (None, None) => AlertSink::LogOnly,
+    };
+
     if force_camera_sync_mode {
         cam.start_default_external_triggering().unwrap();
         if let Some(transmit_msg_tx) = &transmit_msg_tx {
@@ -1751,8 +2175,9 @@ where
         vendor: cam.vendor().into(),
         model: cam.model().into(),
         serial: cam.serial().into(),
-        width: cam.width()?,
-        height: cam.height()?,
+        // The detected points are in the coordinates of the transformed image.
+        width: image_width,
+        height: image_height,
     };
 
     #[cfg(feature = "flydratrax")]
@@ -1865,7 +2290,7 @@ where
         }
     };
 
-    let is_nvenc_functioning = test_nvenc_save(frame.image)?;
+    let is_nvenc_functioning = test_nvenc_save(&frame.image)?;
 
     let mp4_codec = match is_nvenc_functioning {
         true => CodecSelection::H264Nvenc,
@@ -1911,7 +2336,9 @@ where
         is_videotoolbox_functioning,
         is_recording_mp4: None,
         is_recording_fmf: None,
+        fmf_zstd_compression: false,
         is_recording_ufmf: None,
+        is_recording_paused: false,
         format_str_apriltag_csv,
         format_str_mp4: mp4_filename_template,
         format_str: fmf_filename_template,
@@ -1923,16 +2350,27 @@ where
         mp4_codec,
         mp4_max_framerate: Default::default(),
         mp4_cuda_device,
+        mp4_rollover_minutes: None,
+        mp4_rollover_gb: None,
+        mp4_overlay: Default::default(),
+        mp4_draw_detections: false,
+        preview_max_width: Default::default(),
+        preview_jpeg_quality: Default::default(),
+        preview_crop,
         gain: gain_ranged,
         gain_auto,
         exposure_time: exposure_ranged,
         exposure_auto,
+        soft_auto_exposure_config: Default::default(),
+        soft_auto_exposure_level: None,
         frame_rate_limit_enabled,
         frame_rate_limit,
         trigger_mode,
         trigger_selector,
         image_width,
         image_height,
+        image_transform,
+        image_transform_after_restart: None,
         is_doing_object_detection: false,
         measured_fps: 0.0,
         is_saving_im_pt_detect_csv: None,
@@ -1956,9 +2394,21 @@ where
         im_ops_state,
         had_frame_processing_error: false,
         camera_calibration: None,
+        ptp_status,
+        frame_pool_stats: cam.frame_pool_stats(),
+        processing_watchdog: Default::default(),
+        disk_space: None,
+        settings_profiles: list_settings_profiles(),
+        current_settings_profile: None,
+        experiment_metadata: Default::default(),
+        log_filter: log_handle.filter(),
+        inspected_pixel: None,
+        latency_test: Default::default(),
     });
 
     let frame_processing_error_state = Arc::new(RwLock::new(FrameProcessingErrorState::default()));
+    // Set when quitting, so that the end of the frame stream is not an error.
+    let is_quitting = Arc::new(std::sync::atomic::AtomicBool::new(false));
 
     // let mut config = get_default_config();
     // config.cookie_name = "strand-camclient".to_string();
@@ -1968,6 +2418,78 @@ where
     // A channel for the data sent from the client browser.
     let (firehose_callback_tx, firehose_callback_rx) = tokio::sync::mpsc::channel(10);
 
+    // Settings for encoding the frames sent to the client browser.
+    let (preview_config_tx, preview_config_rx) =
+        tokio::sync::watch::channel(video_streaming::PreviewConfig {
+            crop: preview_crop,
+            ..Default::default()
+        });
+
+    let mut ptp_lock_threshold_nsec = match &trigger_type {
+        Some(TriggerType::PtpSync(ptpcfg)) => Some(ptpcfg.lock_threshold_nsec()),
+        _ => None,
+    };
+
+    if ptp_lock_threshold_nsec.is_some() {
+        // Periodically refresh the PTP status.
+        let cam_args_tx = cam_args_tx.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PTP_STATUS_INTERVAL);
+            loop {
+                interval.tick().await;
+                if cam_args_tx.send(CamArg::UpdatePtpStatus).await.is_err() {
+                    // Receiver is gone, we are quitting.
+                    break;
+                }
+            }
+        });
+    }
+
+    {
+        // Periodically refresh the frame pool statistics.
+        let cam_args_tx = cam_args_tx.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FRAME_POOL_STATS_INTERVAL);
+            loop {
+                interval.tick().await;
+                if cam_args_tx
+                    .send(CamArg::UpdateFramePoolStats)
+                    .await
+                    .is_err()
+                {
+                    // Receiver is gone, we are quitting.
+                    break;
+                }
+            }
+        });
+    }
+
+    {
+        // Quit nicely upon Ctrl-C or SIGTERM so that recordings are finalized.
+        // Ctrl-C in the terminal of Braid also reaches the cameras it launched.
+        // Braid first finalizes its own data and then sends DoQuit, so the
+        // first Ctrl-C is ignored within Braid.
+        let cam_args_tx = cam_args_tx.clone();
+        tokio::spawn(async move {
+            let mut ignore_sigint = is_braid;
+            loop {
+                let is_sigint = flydra_types::quit_signal().await;
+                if is_sigint && ignore_sigint {
+                    info!("Got SIGINT, waiting for Braid to quit. Repeat to quit now.");
+                    ignore_sigint = false;
+                    continue;
+                }
+                info!("Got signal, quitting. Repeat to quit immediately.");
+                // The receiver is gone if we are already quitting.
+                let _ = cam_args_tx.send(CamArg::DoQuit).await;
+                break;
+            }
+            flydra_types::quit_signal().await;
+            warn!("Got signal again, quitting immediately.");
+            std::process::exit(1);
+        });
+    }
+
     let callback_senders = StrandCamCallbackSenders {
         cam_args_tx: cam_args_tx.clone(),
         firehose_callback_tx,
@@ -1991,6 +2513,65 @@ where
 
     let shared_store_arc = shared_state.clone();
 
+    {
+        // Periodically check the free space in the data directory and stop
+        // recordings before the disk is full.
+        let disk_space_monitor = disk_space_monitor::DiskSpaceMonitor::new(
+            &data_dir,
+            args.disk_space_warning_mb,
+            args.disk_space_stop_mb,
+        );
+        let cam_args_tx = cam_args_tx.clone();
+        let shared_store_arc = shared_store_arc.clone();
+        let alert_sink = alert_sink.clone();
+        let raw_cam_name = raw_cam_name.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(disk_space_monitor::CHECK_INTERVAL);
+            let mut prev_level = DiskSpaceLevel::Ok;
+            loop {
+                interval.tick().await;
+                let (space, level) = match disk_space_monitor.check() {
+                    Ok(x) => x,
+                    Err(e) => {
+                        error!(
+                            "checking disk space of \"{}\": {e}",
+                            disk_space_monitor.path().display()
+                        );
+                        continue;
+                    }
+                };
+                if level != prev_level && level != DiskSpaceLevel::Ok {
+                    alert_sink.raise(
+                        &raw_cam_name,
+                        "low-disk-space",
+                        format!(
+                            "Low disk space: {} MB available at \"{}\".",
+                            space.available_bytes / 1_000_000,
+                            space.path
+                        ),
+                    );
+                }
+                prev_level = level;
+                let to_stop = {
+                    let mut tracker = shared_store_arc.write().unwrap();
+                    tracker.modify(|shared| shared.disk_space = Some(space));
+                    if level == DiskSpaceLevel::Full {
+                        recordings_to_stop(tracker.as_ref())
+                    } else {
+                        vec![]
+                    }
+                };
+                for cam_arg in to_stop {
+                    error!("Disk almost full. Stopping recording: {cam_arg:?}");
+                    if cam_args_tx.send(cam_arg).await.is_err() {
+                        // Receiver is gone, we are quitting.
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
     // This future will send state updates to all connected event listeners.
     let event_broadcaster = app_state.event_broadcaster.clone();
     let send_updates_future = async move {
@@ -2036,6 +2617,12 @@ where
         }),
         AccessToken::NoToken => None,
     };
+    let access_control = event_stream_types::AccessControl::new(
+        http_camserver_info.token(),
+        args.read_only_tokens.clone(),
+        persistent_secret.clone(),
+        "strand-cam-role",
+    );
     let cfg = axum_token_auth::AuthConfig {
         token_config,
         persistent_secret,
@@ -2047,12 +2634,19 @@ where
     // Create axum router.
     let router = axum::Router::new()
         .route("/strand-cam-events", axum::routing::get(events_handler))
+        .route("/strand-cam-ws", axum::routing::get(websocket_handler))
         .route("/cam-name", axum::routing::get(cam_name_handler))
         .route("/callback", axum::routing::post(callback_handler))
         .fallback_service(serve_dir)
         .layer(
             tower::ServiceBuilder::new()
                 .layer(TraceLayer::new_for_http())
+                // Determine read-only or control access before the auth layer
+                // checks the token.
+                .layer(axum::middleware::from_fn_with_state(
+                    access_control,
+                    event_stream_types::access_control,
+                ))
                 // Auth layer will produce an error if the request cannot be
                 // authorized so we must handle that.
                 .layer(axum::error_handling::HandleErrorLayer::new(
@@ -2062,18 +2656,27 @@ where
         )
         .with_state(app_state);
 
+    let mut urls = http_camserver_info.build_urls()?;
+
     // create future for our app
-    let http_serve_future = {
-        use std::future::IntoFuture;
-        axum::serve(
-            listener,
-            router.into_make_service_with_connect_info::<SocketAddr>(),
-        )
-        .into_future()
+    let http_serve_future = match &args.tls {
+        Some(tls) => {
+            urls = urls.iter().map(https_server::https_uri).collect();
+            let tls = tls.server_config(https_server::hostnames(&urls))?;
+            futures::future::Either::Left(https_server::serve(listener, router, tls))
+        }
+        None => {
+            use std::future::IntoFuture;
+            futures::future::Either::Right(
+                axum::serve(
+                    listener,
+                    router.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .into_future(),
+            )
+        }
     };
 
-    let urls = http_camserver_info.build_urls()?;
-
     #[cfg(feature = "eframe-gui")]
     {
         // Loop until GUI from other thread is available.
@@ -2117,6 +2720,25 @@ where
         }
     }
 
+    // Advertise the web UI on the local network, unless it is reachable only
+    // from this computer.
+    let _mdns_advertisement = if args.no_mdns || listen_addr.ip().is_loopback() {
+        None
+    } else {
+        match mdns_discovery::Advertisement::new(
+            raw_cam_name.as_str(),
+            listen_addr,
+            args.tls.is_some(),
+            is_braid,
+        ) {
+            Ok(advertisement) => Some(advertisement),
+            Err(e) => {
+                warn!("Cannot advertise by mDNS: {e}");
+                None
+            }
+        }
+    };
+
     #[cfg(feature = "checkercal")]
     let collected_corners_arc: CollectedCornersArc = Arc::new(RwLock::new(Vec::new()));
 
@@ -2127,7 +2749,6 @@ where
         #[cfg(feature = "flydratrax")]
         let model_server_addr = args.model_server_addr.clone();
 
-        #[cfg(feature = "flydratrax")]
         let led_box_tx_std = led_box_tx_std.clone();
         #[cfg(feature = "flydratrax")]
         let http_camserver_info2 = http_camserver_info.clone();
@@ -2138,7 +2759,26 @@ where
             let (model_server_data_tx, data_rx) = tokio::sync::mpsc::channel(50);
             let model_server_future = flydra2::new_model_server(data_rx, model_server_addr);
             tokio::spawn(async { model_server_future.await });
-            model_server_data_tx
+            match &args.rerun {
+                Some(rerun) => {
+                    // Log each message to rerun before passing it on to the
+                    // model server.
+                    let rerun_logger = flydra2::RerunLogger::new(rerun)?;
+                    let (tx, mut rx) = tokio::sync::mpsc::channel(50);
+                    tokio::spawn(async move {
+                        while let Some(msg) = rx.recv().await {
+                            if let Err(e) = rerun_logger.log_send_msg(&msg) {
+                                warn!("Failed logging to rerun: {e}");
+                            }
+                            if model_server_data_tx.send(msg).await.is_err() {
+                                break;
+                            }
+                        }
+                    });
+                    tx
+                }
+                None => model_server_data_tx,
+            }
         };
 
         let cam_name2 = raw_cam_name.clone();
@@ -2158,13 +2798,13 @@ where
             #[cfg(feature = "flydra_feat_detect")]
             std::path::Path::new(&csv_save_dir).to_path_buf(),
             firehose_tx,
-            #[cfg(feature = "flydratrax")]
             led_box_tx_std,
             #[cfg(feature = "flydratrax")]
             http_camserver_info2,
             transmit_msg_tx.clone(),
             camdata_udp_addr,
             led_box_heartbeat_update_arc2,
+            cam_args_tx.clone(),
             #[cfg(feature = "checkercal")]
             collected_corners_arc.clone(),
             #[cfg(feature = "flydratrax")]
@@ -2188,6 +2828,41 @@ where
         .await
         .unwrap();
 
+    if let Some(latency_test) = args.latency_test.clone() {
+        // Start the test once the LED box has reported its state.
+        let tx_frame = tx_frame.clone();
+        let cam_args_tx = cam_args_tx.clone();
+        let shared_store_arc = shared_store_arc.clone();
+        tokio::spawn(async move {
+            let start = std::time::Instant::now();
+            loop {
+                let has_led_box = {
+                    let tracker = shared_store_arc.read().unwrap();
+                    tracker.as_ref().led_box_device_state.is_some()
+                };
+                if has_led_box {
+                    break;
+                }
+                if start.elapsed() > LATENCY_TEST_LED_BOX_TIMEOUT {
+                    error!("No LED box connected, cannot do latency test. (Use --led-box.)");
+                    let _ = cam_args_tx.send(CamArg::DoQuit).await;
+                    return;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+            if let Some(threshold) = latency_test.im_ops_threshold {
+                cam_args_tx
+                    .send(CamArg::SetImOpsThreshold(threshold))
+                    .await
+                    .ignore_send_error();
+            }
+            tx_frame
+                .send(Msg::StartLatencyTest(latency_test.config, true))
+                .await
+                .ignore_send_error();
+        });
+    }
+
     debug!("installing frame stream handler");
 
     // install frame handling
@@ -2198,14 +2873,40 @@ where
         let frame_processing_error_state = frame_processing_error_state.clone();
         let mut transmit_msg_tx = transmit_msg_tx.clone();
         let raw_cam_name = raw_cam_name.clone();
+        let alert_sink = alert_sink.clone();
+        let is_quitting = is_quitting.clone();
         async move {
+            let mut last_behind_alert: Option<std::time::Instant> = None;
             let mut send_image_to_braid_timer = std::time::Instant::now();
             let mut send_image_to_braid_duration = std::time::Duration::from_millis(0);
-            while let Some(frame_msg) = frame_stream.next().await {
+            let mut transform_error_logged = false;
+            while let Some(mut frame_msg) = frame_stream.next().await {
+                if let ci2_async::FrameResult::Frame(fframe) = &mut frame_msg {
+                    if !image_transform.is_identity() {
+                        match image_transform::transform_frame(&fframe.image, image_transform) {
+                            Ok(image) => {
+                                fframe.image = Arc::new(image);
+                            }
+                            Err(e) => {
+                                // Pass the frame through untransformed. Log
+                                // only once, as this likely affects all
+                                // frames.
+                                if !transform_error_logged {
+                                    error!(
+                                        "Cannot transform image ({image_transform}), \
+                                        using untransformed image: {e}"
+                                    );
+                                    transform_error_logged = true;
+                                }
+                            }
+                        }
+                    }
+                }
                 match &frame_msg {
                     ci2_async::FrameResult::Frame(fframe) => {
                         let frame: &DynamicFrame = &fframe.image;
                         trace!(
+                            frame = fframe.host_timing.fno,
                             "  got frame {}: {}x{}",
                             fframe.host_timing.fno,
                             frame.width(),
@@ -2256,7 +2957,22 @@ where
                                     }
                                 }
                             });
-                            error!("Channel full sending frame to process thread. Dropping frame data.");
+                            error!(
+                                frame = fframe.host_timing.fno,
+                                "Channel full sending frame to process thread. Dropping frame data."
+                            );
+                            if last_behind_alert
+                                .map(|t| t.elapsed() >= FRAME_PROCESSING_ALERT_INTERVAL)
+                                .unwrap_or(true)
+                            {
+                                alert_sink.raise(
+                                    &raw_cam_name,
+                                    "frame-processing-behind",
+                                    "Frame processing is falling behind. Frames are being dropped."
+                                        .to_string(),
+                                );
+                                last_behind_alert = Some(std::time::Instant::now());
+                            }
                         } else {
                             tx_frame
                                 .send(Msg::Mframe(fframe.clone()))
@@ -2274,7 +2990,7 @@ where
                         // Check if we need to send this frame to braid because our timer elapsed.
                         if send_image_to_braid_timer.elapsed() >= send_image_to_braid_duration {
                             // If yes, encode frame to png buffer.
-                            let current_image_png = match_all_dynamic_fmts!(&frame.image, x, {
+                            let current_image_png = match_all_dynamic_fmts!(&*frame.image, x, {
                                 convert_image::frame_to_encoded_buffer(
                                     x,
                                     convert_image::EncoderOptions::Png,
@@ -2302,6 +3018,13 @@ where
                     }
                 }
             }
+            if !is_quitting.load(std::sync::atomic::Ordering::SeqCst) {
+                alert_sink.raise(
+                    &raw_cam_name,
+                    "camera-disconnected",
+                    "Camera stopped delivering frames.".to_string(),
+                );
+            }
             debug!("cam_stream_future future done {}:{}", file!(), line!());
             Ok::<_, eyre::Report>(())
         }
@@ -2361,11 +3084,12 @@ where
         debug!("version check future spawned {}:{}", file!(), line!());
     }
 
-    tokio::spawn(Box::pin(cam_stream_future));
+    tokio::spawn(Box::pin(cam_stream_future).in_current_span());
     debug!("cam_stream_future future spawned {}:{}", file!(), line!());
 
     let cam_arg_future = {
         let shared_store_arc = shared_store_arc.clone();
+        let cam_args_tx = cam_args_tx.clone();
 
         #[cfg(feature = "checkercal")]
         let cam_name2 = raw_cam_name.clone();
@@ -2471,6 +3195,36 @@ where
                             error!("setting gain_auto: {:?}", e);
                         }
                     },
+                    CamArg::SetSoftAutoExposureConfig(yaml_buf) => {
+                        match serde_yaml::from_str::<strand_cam_storetype::SoftAutoExposureConfig>(
+                            &yaml_buf,
+                        ) {
+                            Err(e) => {
+                                error!("ignoring SoftAutoExposureConfig with parse error: {e}");
+                            }
+                            Ok(cfg) => {
+                                if cfg.enabled {
+                                    // The auto-exposure of the camera would
+                                    // fight with the software auto-exposure.
+                                    let _ = cam_args_tx.try_send(CamArg::SetExposureAuto(
+                                        ci2_types::AutoMode::Off,
+                                    ));
+                                    if cfg.adjust_gain {
+                                        let _ = cam_args_tx.try_send(CamArg::SetGainAuto(
+                                            ci2_types::AutoMode::Off,
+                                        ));
+                                    }
+                                }
+                                let mut tracker = shared_store_arc.write().unwrap();
+                                tracker.modify(|shared| {
+                                    if !cfg.enabled {
+                                        shared.soft_auto_exposure_level = None;
+                                    }
+                                    shared.soft_auto_exposure_config = cfg;
+                                });
+                            }
+                        }
+                    }
                     CamArg::SetRecordingFps(v) => {
                         let mut tracker = shared_store_arc.write().unwrap();
                         tracker.modify(|tracker| tracker.mp4_max_framerate = v);
@@ -2483,6 +3237,26 @@ where
                         let mut tracker = shared_store_arc.write().unwrap();
                         tracker.modify(|tracker| tracker.mp4_max_framerate = v);
                     }
+                    CamArg::SetMp4RolloverMinutes(v) => {
+                        let mut tracker = shared_store_arc.write().unwrap();
+                        tracker.modify(|tracker| tracker.mp4_rollover_minutes = v);
+                    }
+                    CamArg::SetMp4RolloverGb(v) => {
+                        let mut tracker = shared_store_arc.write().unwrap();
+                        tracker.modify(|tracker| tracker.mp4_rollover_gb = v);
+                    }
+                    CamArg::SetMp4Overlay(v) => {
+                        let mut tracker = shared_store_arc.write().unwrap();
+                        tracker.modify(|tracker| tracker.mp4_overlay = v);
+                    }
+                    CamArg::SetMp4DrawDetections(v) => {
+                        let mut tracker = shared_store_arc.write().unwrap();
+                        tracker.modify(|tracker| tracker.mp4_draw_detections = v);
+                    }
+                    CamArg::SetFmfZstdCompression(v) => {
+                        let mut tracker = shared_store_arc.write().unwrap();
+                        tracker.modify(|tracker| tracker.fmf_zstd_compression = v);
+                    }
                     CamArg::SetMp4Bitrate(v) => {
                         let mut tracker = shared_store_arc.write().unwrap();
                         tracker.modify(|tracker| tracker.mp4_bitrate = v);
@@ -2491,6 +3265,43 @@ where
                         let mut tracker = shared_store_arc.write().unwrap();
                         tracker.modify(|tracker| tracker.mp4_codec = v);
                     }
+                    CamArg::SetPreviewMaxWidth(v) => {
+                        preview_config_tx.send_modify(|cfg| cfg.max_width = v.max_width());
+                        let mut tracker = shared_store_arc.write().unwrap();
+                        tracker.modify(|tracker| tracker.preview_max_width = v);
+                    }
+                    CamArg::SetPreviewJpegQuality(v) => {
+                        preview_config_tx.send_modify(|cfg| cfg.jpeg_quality = v.quality());
+                        let mut tracker = shared_store_arc.write().unwrap();
+                        tracker.modify(|tracker| tracker.preview_jpeg_quality = v);
+                    }
+                    CamArg::SetPreviewCrop(v) => {
+                        preview_config_tx.send_modify(|cfg| cfg.crop = v);
+                        if let Err(e) = v.save(&APP_INFO, &preview_crop_key) {
+                            error!("saving live view crop failed: {e} {e:?}");
+                        }
+                        let mut tracker = shared_store_arc.write().unwrap();
+                        tracker.modify(|tracker| tracker.preview_crop = v);
+                    }
+                    CamArg::SetImageTransform(v) => {
+                        if is_braid {
+                            error!("Ignoring image transform: set it in the Braid configuration.");
+                        } else {
+                            match v.save(&APP_INFO, &image_transform_key) {
+                                Ok(()) => {
+                                    info!("saved image transform {v}, applied after restart");
+                                    let mut tracker = shared_store_arc.write().unwrap();
+                                    tracker.modify(|tracker| {
+                                        tracker.image_transform_after_restart =
+                                            (v != tracker.image_transform).then_some(v);
+                                    });
+                                }
+                                Err(e) => {
+                                    error!("saving image transform failed: {e} {e:?}");
+                                }
+                            }
+                        }
+                    }
                     CamArg::SetExposureAuto(v) => match cam.set_exposure_auto(v) {
                         Ok(()) => {
                             if let Some(transmit_msg_tx) = &transmit_msg_tx {
@@ -2593,6 +3404,62 @@ where
                             .await
                             .map_err(to_eyre)?;
                     }
+                    CamArg::UpdatePtpStatus => {
+                        if let Some(threshold) = ptp_lock_threshold_nsec {
+                            match read_ptp_status(&cam, threshold) {
+                                Ok(None) => {
+                                    tracing::warn!(
+                                        "Camera does not support PTP, not reading PTP status."
+                                    );
+                                    // Stop polling the status.
+                                    ptp_lock_threshold_nsec = None;
+                                }
+                                Ok(Some(status)) => {
+                                    if !status.is_locked {
+                                        tracing::warn!(
+                                            "PTP clock offset {} nanoseconds exceeds threshold.",
+                                            status.offset_from_master_nsec
+                                        );
+                                    }
+                                    let mut tracker = shared_store_arc.write().unwrap();
+                                    tracker.modify(|tracker| tracker.ptp_status = Some(status));
+                                }
+                                Err(e) => {
+                                    error!("reading PTP status: {e}");
+                                }
+                            }
+                        }
+                    }
+                    CamArg::UpdateFramePoolStats => {
+                        if let Some(stats) = cam.frame_pool_stats() {
+                            let mut tracker = shared_store_arc.write().unwrap();
+                            let prev = tracker.as_ref().frame_pool_stats;
+                            let prev_exhausted = prev.map(|p| p.num_exhausted).unwrap_or(0);
+                            if stats.num_exhausted > prev_exhausted {
+                                tracing::warn!(
+                                    "Frame pool exhausted: {} frame(s) allocated outside the \
+                                    pool since the last check. {} frame buffer(s) in use.",
+                                    stats.num_exhausted - prev_exhausted,
+                                    stats.num_in_use,
+                                );
+                            }
+                            debug!("frame pool: {stats:?}");
+                            tracker.modify(|tracker| tracker.frame_pool_stats = Some(stats));
+                        }
+                    }
+                    CamArg::SetExperimentMetadata(v) => {
+                        let mut tracker = shared_store_arc.write().unwrap();
+                        tracker.modify(|tracker| tracker.experiment_metadata = v);
+                    }
+                    CamArg::SetLogFilter(v) => match log_handle.set_filter(&v) {
+                        Ok(()) => {
+                            let mut tracker = shared_store_arc.write().unwrap();
+                            tracker.modify(|tracker| tracker.log_filter = log_handle.filter());
+                        }
+                        Err(e) => {
+                            error!("invalid log filter \"{v}\": {e}");
+                        }
+                    },
                     CamArg::SetFormatStr(v) => {
                         let mut tracker = shared_store_arc.write().unwrap();
                         tracker.modify(|tracker| tracker.format_str = v);
@@ -2605,7 +3472,9 @@ where
                             shared.is_recording_mp4.is_some()
                         };
 
-                        if is_recording_mp4 != do_recording {
+                        // Stopping is always sent so that a pending
+                        // synchronized start is cancelled, too.
+                        if !do_recording || !is_recording_mp4 {
                             let msg = if do_recording {
                                 Msg::StartMp4
                             } else {
@@ -2616,6 +3485,12 @@ where
                             tx_frame2.send(msg).await.map_err(to_eyre)?;
                         }
                     }
+                    CamArg::StartSynchronizedMp4(start) => {
+                        tx_frame2
+                            .send(Msg::StartSynchronizedMp4(start))
+                            .await
+                            .map_err(to_eyre)?;
+                    }
                     CamArg::ToggleAprilTagFamily(family) => {
                         let mut tracker = shared_store_arc.write().unwrap();
                         tracker.modify(|shared| {
@@ -2676,6 +3551,18 @@ where
                             shared.im_ops_state.threshold = v;
                         });
                     }
+                    CamArg::SetImOpsMaxPoints(v) => {
+                        let mut tracker = shared_store_arc.write().unwrap();
+                        tracker.modify(|shared| {
+                            shared.im_ops_state.max_points = v;
+                        });
+                    }
+                    CamArg::SetImOpsFormat(v) => {
+                        let mut tracker = shared_store_arc.write().unwrap();
+                        tracker.modify(|shared| {
+                            shared.im_ops_state.format = v;
+                        });
+                    }
 
                     CamArg::SetIsRecordingAprilTagCsv(do_recording) => {
                         let new_val = {
@@ -2721,6 +3608,50 @@ where
                             });
                         }
                     }
+                    CamArg::SaveProfile(name) => {
+                        let profile = {
+                            let tracker = shared_store_arc.read().unwrap();
+                            SettingsProfile::from_store(tracker.as_ref())
+                        };
+                        match save_settings_profile(&name, &profile) {
+                            Ok(()) => {
+                                info!("Saved settings profile \"{name}\".");
+                                let mut tracker = shared_store_arc.write().unwrap();
+                                tracker.modify(|shared| {
+                                    shared.settings_profiles = list_settings_profiles();
+                                    shared.current_settings_profile = Some(name);
+                                });
+                            }
+                            Err(e) => {
+                                error!("saving settings profile \"{name}\": {e:?}");
+                            }
+                        }
+                    }
+                    CamArg::LoadProfile(name) => match load_settings_profile(&name) {
+                        Ok(profile) => {
+                            info!("Loading settings profile \"{name}\".");
+                            {
+                                let mut tracker = shared_store_arc.write().unwrap();
+                                tracker.modify(|shared| {
+                                    shared.current_settings_profile = Some(name);
+                                });
+                            }
+                            // Apply the settings from a separate task because
+                            // this loop is the receiver of the commands.
+                            let cam_args_tx = cam_args_tx.clone();
+                            tokio::spawn(async move {
+                                for cam_arg in profile.cam_args() {
+                                    if cam_args_tx.send(cam_arg).await.is_err() {
+                                        // Receiver is gone, we are quitting.
+                                        return;
+                                    }
+                                }
+                            });
+                        }
+                        Err(e) => {
+                            error!("loading settings profile \"{name}\": {e:?}");
+                        }
+                    },
                     CamArg::PostTrigger => {
                         info!("Start MP4 recording via post trigger.");
                         tx_frame2
@@ -2737,13 +3668,19 @@ where
                     }
                     CamArg::SetIsRecordingFmf(do_recording) => {
                         // Copy values from cache and release the lock immediately.
-                        let (is_recording_fmf, format_str, recording_framerate) = {
+                        let (is_recording_fmf, format_str, recording_framerate, compression) = {
                             let tracker = shared_store_arc.read().unwrap();
                             let shared: &StoreType = tracker.as_ref();
+                            let compression = if shared.fmf_zstd_compression {
+                                fmf::Compression::Zstd { level: 0 }
+                            } else {
+                                fmf::Compression::None
+                            };
                             (
                                 shared.is_recording_fmf.clone(),
                                 shared.format_str.clone(),
                                 shared.mp4_max_framerate.clone(),
+                                compression,
                             )
                         };
 
@@ -2756,7 +3693,11 @@ where
                                 let local: chrono::DateTime<chrono::Local> = chrono::Local::now();
                                 let filename = local.format(format_str.as_str()).to_string();
                                 (
-                                    Msg::StartFMF((filename.clone(), recording_framerate)),
+                                    Msg::StartFMF((
+                                        filename.clone(),
+                                        recording_framerate,
+                                        compression,
+                                    )),
                                     Some(RecordingPath::new(filename)),
                                 )
                             } else {
@@ -2773,6 +3714,20 @@ where
                             });
                         }
                     }
+                    CamArg::PauseRecording => {
+                        info!("Pausing recording.");
+                        tx_frame2
+                            .send(Msg::SetRecordingPaused(true))
+                            .await
+                            .map_err(to_eyre)?;
+                    }
+                    CamArg::ResumeRecording => {
+                        info!("Resuming recording.");
+                        tx_frame2
+                            .send(Msg::SetRecordingPaused(false))
+                            .await
+                            .map_err(to_eyre)?;
+                    }
                     CamArg::SetIsRecordingUfmf(do_recording) => {
                         #[cfg(feature = "flydra_feat_detect")]
                         {
@@ -2835,6 +3790,7 @@ where
                         }
                     }
                     CamArg::DoQuit => {
+                        is_quitting.store(true, std::sync::atomic::Ordering::SeqCst);
                         break;
                     }
                     CamArg::SetIsSavingObjDetectionCsv(value) => {
@@ -3031,6 +3987,24 @@ where
                             });
                         }
                     }
+                    CamArg::SetCheckerboardPattern(val) => {
+                        #[cfg(feature = "checkercal")]
+                        {
+                            let mut tracker = shared_store_arc.write().unwrap();
+                            tracker.modify(|shared| {
+                                shared.checkerboard_data.pattern = val;
+                            });
+                        }
+                    }
+                    CamArg::SetCharucoMarkerRatio(val) => {
+                        #[cfg(feature = "checkercal")]
+                        {
+                            let mut tracker = shared_store_arc.write().unwrap();
+                            tracker.modify(|shared| {
+                                shared.checkerboard_data.charuco_marker_ratio = val;
+                            });
+                        }
+                    }
                     CamArg::ClearCheckerboards => {
                         #[cfg(feature = "checkercal")]
                         {
@@ -3043,6 +4017,7 @@ where
                                 let mut tracker = shared_store_arc.write().unwrap();
                                 tracker.modify(|shared| {
                                     shared.checkerboard_data.num_checkerboards_collected = 0;
+                                    shared.checkerboard_data.coverage = Default::default();
                                 });
                             }
                         }
@@ -3052,32 +4027,20 @@ where
                         #[cfg(feature = "checkercal")]
                         {
                             info!("computing calibration");
-                            let (n_rows, n_cols, checkerboard_save_debug) = {
+                            let checkerboard_save_debug = {
                                 let tracker = shared_store_arc.read().unwrap();
                                 let shared = (*tracker).as_ref();
-                                let n_rows = shared.checkerboard_data.height;
-                                let n_cols = shared.checkerboard_data.width;
-                                let checkerboard_save_debug =
-                                    shared.checkerboard_save_debug.clone();
-                                (n_rows, n_cols, checkerboard_save_debug)
+                                shared.checkerboard_save_debug.clone()
                             };
 
-                            let goodcorners: Vec<camcal::CheckerBoardData> = {
+                            let (goodcorners, coverage) = {
                                 let collected_corners = collected_corners_arc.read().unwrap();
-                                collected_corners
-                                    .iter()
-                                    .map(|corners| {
-                                        let x: Vec<(f64, f64)> = corners
-                                            .iter()
-                                            .map(|x| (x.0 as f64, x.1 as f64))
-                                            .collect();
-                                        camcal::CheckerBoardData::new(
-                                            n_rows as usize,
-                                            n_cols as usize,
-                                            &x,
-                                        )
-                                    })
-                                    .collect()
+                                let coverage = checkerboard_coverage(
+                                    &collected_corners,
+                                    image_width,
+                                    image_height,
+                                );
+                                (collected_corners.clone(), coverage)
                             };
 
                             let local: chrono::DateTime<chrono::Local> = chrono::Local::now();
@@ -3117,6 +4080,28 @@ where
                                 &goodcorners,
                             ) {
                                 Ok(raw_opencv_cal) => {
+                                    let residuals = camcal::reprojection_residuals(
+                                        &raw_opencv_cal,
+                                        &goodcorners,
+                                    );
+                                    let quality = strand_cam_storetype::CheckerboardCalQuality::new(
+                                        &residuals, coverage,
+                                    );
+                                    info!(
+                                        "Calibration reprojection error: RMS {:.3} pixels, \
+                                        95th percentile {:.3} pixels, {:.0}% of sensor covered.",
+                                        quality.rms_pixels,
+                                        quality.p95_pixels,
+                                        quality.coverage.fraction_covered() * 100.0,
+                                    );
+                                    {
+                                        let mut tracker = shared_store_arc.write().unwrap();
+                                        tracker.modify(|shared| {
+                                            shared.checkerboard_data.last_calibration =
+                                                Some(quality);
+                                        });
+                                    }
+
                                     let cal_dir = directories::BaseDirs::new()
                                         .as_ref()
                                         .map(|bd| {
@@ -3225,9 +4210,14 @@ where
     let firehose_task_join_handle = tokio::spawn(async {
         // The first thing this task does is pop a frame from firehose_rx, so we
         // should ensure there is one present.
-        video_streaming::firehose_task(connection_callback_rx, firehose_rx, firehose_callback_rx)
-            .await
-            .unwrap();
+        video_streaming::firehose_task(
+            connection_callback_rx,
+            firehose_rx,
+            firehose_callback_rx,
+            preview_config_rx,
+        )
+        .await
+        .unwrap();
     });
 
     debug!("  running forever");
@@ -3343,7 +4333,18 @@ where
                                 led_box_heartbeat_update_arc.write().unwrap();
                             *led_box_heartbeat_update = Some(std::time::Instant::now());
                         }
-                        Ok(led_box_comms::FromDevice::StateWasSet) => {}
+                        Ok(led_box_comms::FromDevice::StateWasSet)
+                        | Ok(led_box_comms::FromDevice::SequenceWasSet) => {}
+                        Ok(led_box_comms::FromDevice::SequenceStarted) => {
+                            debug!("LED box sequence started");
+                        }
+                        Ok(led_box_comms::FromDevice::SequenceStopped)
+                        | Ok(led_box_comms::FromDevice::SequenceFinished) => {
+                            debug!("LED box sequence ended");
+                        }
+                        Ok(led_box_comms::FromDevice::SequenceError(e)) => {
+                            error!("LED box sequence error: {e:?}");
+                        }
                         Ok(msg) => {
                             todo!("Did not handle {:?}", msg);
                             // error!("unknown message received: {:?}", msg);
@@ -3439,6 +4440,73 @@ where
     Ok((remote_in_local, remote))
 }
 
+/// The commands to stop all ongoing recordings.
+fn recordings_to_stop(shared: &StoreType) -> Vec<CamArg> {
+    let mut result = Vec::new();
+    if shared.is_recording_mp4.is_some() {
+        result.push(CamArg::SetIsRecordingMp4(false));
+    }
+    if shared.is_recording_fmf.is_some() {
+        result.push(CamArg::SetIsRecordingFmf(false));
+    }
+    if shared.is_recording_ufmf.is_some() {
+        result.push(CamArg::SetIsRecordingUfmf(false));
+    }
+    if shared.is_saving_im_pt_detect_csv.is_some() {
+        result.push(CamArg::SetIsSavingObjDetectionCsv(CsvSaveConfig::NotSaving));
+    }
+    if let Some(ts) = &shared.apriltag_state {
+        if ts.is_recording_csv.is_some() {
+            result.push(CamArg::SetIsRecordingAprilTagCsv(false));
+        }
+    }
+    result
+}
+
+/// Interval at which the PTP status is re-read from the camera.
+const PTP_STATUS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Interval at which the frame pool statistics are re-read from the camera.
+const FRAME_POOL_STATS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Read the PTP status of the camera.
+///
+/// Returns `None` if the camera does not support PTP, i.e. it lacks the PTP
+/// features.
+fn read_ptp_status<C>(
+    cam: &C,
+    threshold_nsec: u64,
+) -> Result<Option<strand_cam_storetype::PtpStatus>>
+where
+    C: ci2::Camera,
+{
+    // The camera backends do not distinguish missing features from other
+    // errors, so any error here is taken to mean that PTP is not supported.
+    if let Err(e) = cam.command_execute("PtpDataSetLatch", true) {
+        tracing::debug!("Cannot latch PTP data, assuming PTP is not supported: {e}");
+        return Ok(None);
+    }
+    let status = match cam.feature_enum("PtpStatus") {
+        Ok(status) => status,
+        Err(e) => {
+            tracing::debug!("Cannot read PTP status, assuming PTP is not supported: {e}");
+            return Ok(None);
+        }
+    };
+    // Not all cameras report the servo status.
+    let servo_status = cam.feature_enum("PtpServoStatus").ok();
+    // Basler docs: "PtpOffsetFromMaster: Indicates the estimated temporal
+    // offset between the master clock and the clock of the current PTP device
+    // in ticks (1 tick = 1 nanosecond)."
+    let offset_from_master_nsec = cam.feature_int("PtpOffsetFromMaster")?;
+    Ok(Some(strand_cam_storetype::PtpStatus {
+        status,
+        servo_status,
+        offset_from_master_nsec,
+        is_locked: offset_from_master_nsec.unsigned_abs() < threshold_nsec,
+    }))
+}
+
 fn open_browser(url: String) -> Result<()> {
     // Spawn a new thread because xdg-open blocks forever
     // if it must open a new browser.
@@ -3529,10 +4597,14 @@ impl FinalMp4RecordingConfig {
                 ci2_remote_control::H264Metadata::new("strand-cam", creation_time.into());
             h264_metadata.camera_name = Some(shared.camera_name.clone());
             h264_metadata.gamma = shared.camera_gamma;
+            if !shared.experiment_metadata.is_empty() {
+                h264_metadata.experiment = Some(shared.experiment_metadata.clone());
+            }
             let final_cfg = Mp4RecordingConfig {
                 codec,
                 max_framerate: shared.mp4_max_framerate.clone(),
                 h264_metadata: Some(h264_metadata),
+                color_encoding: Default::default(),
             };
             ci2_remote_control::RecordingConfig::Mp4(final_cfg)
         } else {
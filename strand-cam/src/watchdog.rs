@@ -0,0 +1,237 @@
+//! Watchdog for the latency of frame processing.
+//!
+//! If processing a frame takes longer than the interval between frames, frames
+//! queue up and are eventually dropped, including frames which should be
+//! recorded. The watchdog measures the latency of the stages of frame
+//! processing and degrades gracefully when processing falls behind: first, only
+//! some frames are sent to the live view, then feature detection runs on only
+//! some frames. Frames are never skipped for recording. Once processing has
+//! kept up for a while, the degradation is undone one level at a time.
+
+use std::time::{Duration, Instant};
+
+use strand_cam_storetype::{DegradationLevel, ProcessingWatchdogStatus};
+use tracing::{info, warn};
+
+/// Interval over which latencies are averaged before the degradation level is
+/// updated.
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Degrade if the mean processing time exceeds this fraction of the frame
+/// interval.
+const DEGRADE_FRACTION: f64 = 0.9;
+
+/// Undo a degradation only if the mean processing time is below this fraction
+/// of the frame interval...
+const RECOVER_FRACTION: f64 = 0.5;
+
+/// ...for this many consecutive windows.
+const RECOVER_WINDOWS: u32 = 5;
+
+/// Degrade if a frame waited longer than this many frame intervals before
+/// processing started.
+const MAX_QUEUED_FRAMES: f64 = 10.0;
+
+/// When skipping live view frames, only every this many-th frame is sent.
+const PREVIEW_DECIMATION: usize = 10;
+
+/// When reducing detection, feature detection runs only on every this many-th
+/// frame.
+const DETECT_DECIMATION: usize = 2;
+
+/// A stage of frame processing.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Stage {
+    /// From acquisition until processing starts.
+    Queue,
+    /// Feature detection and other image analysis.
+    Detect,
+    /// Writing to the recordings.
+    Record,
+    /// Sending to the live view.
+    Preview,
+}
+
+#[derive(Default)]
+struct Sums {
+    n_frames: u32,
+    queue: Duration,
+    max_queue: Duration,
+    detect: Duration,
+    record: Duration,
+    preview: Duration,
+    total: Duration,
+}
+
+pub(crate) struct Watchdog {
+    level: DegradationLevel,
+    window_start: Instant,
+    sums: Sums,
+    n_good_windows: u32,
+}
+
+impl Watchdog {
+    pub(crate) fn new() -> Self {
+        Self {
+            level: DegradationLevel::Normal,
+            window_start: Instant::now(),
+            sums: Sums::default(),
+            n_good_windows: 0,
+        }
+    }
+
+    /// Whether the frame `fno` should be sent to the live view.
+    pub(crate) fn send_preview(&self, fno: usize) -> bool {
+        self.level < DegradationLevel::SkipPreview || fno % PREVIEW_DECIMATION == 0
+    }
+
+    /// Whether feature detection should run on the frame `fno`.
+    pub(crate) fn run_detection(&self, fno: usize) -> bool {
+        self.level < DegradationLevel::ReduceDetection || fno % DETECT_DECIMATION == 0
+    }
+
+    /// Record that `stage` took `duration` for the current frame.
+    pub(crate) fn record(&mut self, stage: Stage, duration: Duration) {
+        let sums = &mut self.sums;
+        match stage {
+            Stage::Queue => {
+                sums.queue += duration;
+                sums.max_queue = sums.max_queue.max(duration);
+            }
+            Stage::Detect => sums.detect += duration,
+            Stage::Record => sums.record += duration,
+            Stage::Preview => sums.preview += duration,
+        }
+    }
+
+    /// Finish the current frame, which took `total` to process.
+    ///
+    /// At the end of each averaging window, the degradation level is updated
+    /// and the status is returned.
+    pub(crate) fn frame_done(
+        &mut self,
+        total: Duration,
+        frame_interval: Option<Duration>,
+    ) -> Option<ProcessingWatchdogStatus> {
+        self.frame_done_at(total, frame_interval, Instant::now())
+    }
+
+    fn frame_done_at(
+        &mut self,
+        total: Duration,
+        frame_interval: Option<Duration>,
+        now: Instant,
+    ) -> Option<ProcessingWatchdogStatus> {
+        self.sums.total += total;
+        self.sums.n_frames += 1;
+        if now.duration_since(self.window_start) < WINDOW {
+            return None;
+        }
+        let sums = std::mem::take(&mut self.sums);
+        self.window_start = now;
+
+        let mean_msec = |d: Duration| d.as_secs_f64() * 1000.0 / f64::from(sums.n_frames);
+        let total_msec = mean_msec(sums.total);
+        let max_queue_msec = sums.max_queue.as_secs_f64() * 1000.0;
+        let frame_interval_msec = frame_interval.map(|d| d.as_secs_f64() * 1000.0);
+
+        if let Some(interval_msec) = frame_interval_msec {
+            let max_queue_limit = interval_msec * MAX_QUEUED_FRAMES;
+            if total_msec > interval_msec * DEGRADE_FRACTION || max_queue_msec > max_queue_limit {
+                self.n_good_windows = 0;
+                let next = match self.level {
+                    DegradationLevel::Normal => DegradationLevel::SkipPreview,
+                    _ => DegradationLevel::ReduceDetection,
+                };
+                if next != self.level {
+                    warn!(
+                        "Frame processing is falling behind (mean {total_msec:.1} msec per \
+                        frame, {interval_msec:.1} msec between frames). Now {next}."
+                    );
+                    self.level = next;
+                }
+            } else if total_msec < interval_msec * RECOVER_FRACTION
+                && max_queue_msec < max_queue_limit / 2.0
+            {
+                self.n_good_windows += 1;
+                if self.n_good_windows >= RECOVER_WINDOWS && self.level > DegradationLevel::Normal {
+                    self.n_good_windows = 0;
+                    self.level = match self.level {
+                        DegradationLevel::ReduceDetection => DegradationLevel::SkipPreview,
+                        _ => DegradationLevel::Normal,
+                    };
+                    info!("Frame processing has caught up. Now {}.", self.level);
+                }
+            } else {
+                self.n_good_windows = 0;
+            }
+        }
+
+        Some(ProcessingWatchdogStatus {
+            level: self.level,
+            frame_interval_msec,
+            queue_msec: mean_msec(sums.queue),
+            detect_msec: mean_msec(sums.detect),
+            record_msec: mean_msec(sums.record),
+            preview_msec: mean_msec(sums.preview),
+            total_msec,
+        })
+    }
+}
+
+#[test]
+fn test_watchdog_degrades_and_recovers() {
+    let interval = Duration::from_millis(10);
+    let mut watchdog = Watchdog::new();
+    let mut now = watchdog.window_start;
+
+    // Run one window of frames, each taking `total` to process.
+    let mut run_window = |watchdog: &mut Watchdog, total: Duration| {
+        let mut status = None;
+        for _ in 0..100 {
+            now += interval;
+            watchdog.record(Stage::Detect, total);
+            status = watchdog.frame_done_at(total, Some(interval), now);
+        }
+        status.unwrap()
+    };
+
+    let status = run_window(&mut watchdog, Duration::from_millis(5));
+    assert_eq!(status.level, DegradationLevel::Normal);
+    assert!((status.detect_msec - 5.0).abs() < 1e-6);
+    assert!(watchdog.send_preview(1));
+    assert!(watchdog.run_detection(1));
+
+    // Too slow: first skip live view frames, then feature detection.
+    let slow = Duration::from_millis(12);
+    assert_eq!(
+        run_window(&mut watchdog, slow).level,
+        DegradationLevel::SkipPreview
+    );
+    assert!(!watchdog.send_preview(1));
+    assert!(watchdog.send_preview(PREVIEW_DECIMATION));
+    assert!(watchdog.run_detection(1));
+    assert_eq!(
+        run_window(&mut watchdog, slow).level,
+        DegradationLevel::ReduceDetection
+    );
+    assert!(!watchdog.run_detection(1));
+    assert!(watchdog.run_detection(DETECT_DECIMATION));
+
+    // Recover one level at a time after enough fast windows.
+    let fast = Duration::from_millis(2);
+    for _ in 1..RECOVER_WINDOWS {
+        assert_eq!(
+            run_window(&mut watchdog, fast).level,
+            DegradationLevel::ReduceDetection
+        );
+    }
+    assert_eq!(
+        run_window(&mut watchdog, fast).level,
+        DegradationLevel::SkipPreview
+    );
+    for _ in 0..RECOVER_WINDOWS {
+        run_window(&mut watchdog, fast);
+    }
+    assert_eq!(watchdog.level, DegradationLevel::Normal);
+}
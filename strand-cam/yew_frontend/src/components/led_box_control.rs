@@ -1,13 +1,34 @@
-use led_box_comms::{ChannelState, DeviceState, ToDevice};
+use ads_webasm::components::Toggle;
+use led_box_comms::{
+    ChannelState, DeviceState, SequenceConfig, SequenceStep, ToDevice, MAX_INTENSITY,
+    MAX_SEQUENCE_STEPS,
+};
 use yew::prelude::*;
-use yew_tincture::components::CheckboxLabel;
+use yew_tincture::components::{Button, CheckboxLabel, TypedInput, TypedInputStorage};
 
 use super::led_control::{ChangeLedState, ChangeLedStateValue, LedControl};
 
-pub struct LedBoxControl {}
+pub struct LedBoxControl {
+    /// Steps of the sequence being edited.
+    steps: Vec<SequenceStep>,
+    looping: bool,
+    new_channel: TypedInputStorage<u8>,
+    new_intensity_percent: TypedInputStorage<f32>,
+    new_duration_msec: TypedInputStorage<u32>,
+    new_ramp: bool,
+}
 
 pub enum Msg {
     LedStateChange(ChangeLedState),
+    SetNewRamp(bool),
+    AddStep,
+    RemoveStep(usize),
+    ClearSteps,
+    SetLooping(bool),
+    /// Send the sequence to the device, which stores it until started.
+    UploadSequence,
+    StartSequence,
+    StopSequence,
 }
 
 #[derive(PartialEq, Properties)]
@@ -21,7 +42,14 @@ impl Component for LedBoxControl {
     type Properties = Props;
 
     fn create(_ctx: &Context<Self>) -> Self {
-        Self {}
+        Self {
+            steps: Vec::new(),
+            looping: false,
+            new_channel: TypedInputStorage::empty(),
+            new_intensity_percent: TypedInputStorage::empty(),
+            new_duration_msec: TypedInputStorage::empty(),
+            new_ramp: false,
+        }
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
@@ -49,9 +77,68 @@ impl Component for LedBoxControl {
                     let to_device = ToDevice::DeviceState(next_state);
                     callback.emit(to_device);
                 }
+                return false;
+            }
+            Msg::SetNewRamp(ramp) => {
+                self.new_ramp = ramp;
+            }
+            Msg::AddStep => {
+                if self.steps.len() >= MAX_SEQUENCE_STEPS {
+                    return false;
+                }
+                let (Ok(channel), Ok(percent), Ok(duration_msec)) = (
+                    self.new_channel.parsed(),
+                    self.new_intensity_percent.parsed(),
+                    self.new_duration_msec.parsed(),
+                ) else {
+                    return false;
+                };
+                if !(1..=4).contains(&channel) {
+                    return false;
+                }
+                let percent = percent.clamp(0.0, 100.0);
+                self.steps.push(SequenceStep {
+                    channel,
+                    intensity: (MAX_INTENSITY as f32 * percent / 100.0) as u16,
+                    duration_msec,
+                    ramp: self.new_ramp,
+                });
+            }
+            Msg::RemoveStep(idx) => {
+                self.steps.remove(idx);
+            }
+            Msg::ClearSteps => {
+                self.steps.clear();
+            }
+            Msg::SetLooping(looping) => {
+                self.looping = looping;
+            }
+            Msg::UploadSequence => {
+                if let Some(ref callback) = ctx.props().onsignal {
+                    for (idx, step) in self.steps.iter().enumerate() {
+                        callback.emit(ToDevice::SetSequenceStep((idx as u8, *step)));
+                    }
+                    callback.emit(ToDevice::SetSequenceConfig(SequenceConfig {
+                        num_steps: self.steps.len() as u8,
+                        looping: self.looping,
+                    }));
+                }
+                return false;
+            }
+            Msg::StartSequence => {
+                if let Some(ref callback) = ctx.props().onsignal {
+                    callback.emit(ToDevice::StartSequence);
+                }
+                return false;
+            }
+            Msg::StopSequence => {
+                if let Some(ref callback) = ctx.props().onsignal {
+                    callback.emit(ToDevice::StopSequence);
+                }
+                return false;
             }
         }
-        false
+        true
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
@@ -73,6 +160,84 @@ impl Component for LedBoxControl {
                             onsignal={ctx.link().callback(Msg::LedStateChange)}
                         />
                     </div>
+                    {self.view_sequence(ctx)}
+                </div>
+            </div>
+        }
+    }
+}
+
+impl LedBoxControl {
+    fn view_sequence(&self, ctx: &Context<Self>) -> Html {
+        let rows = self.steps.iter().enumerate().map(|(idx, step)| {
+            let percent = step.intensity as f32 / MAX_INTENSITY as f32 * 100.0;
+            html! {
+                <tr>
+                    <td>{idx + 1}</td>
+                    <td>{step.channel}</td>
+                    <td>{format!("{percent:.1}")}</td>
+                    <td>{step.duration_msec}</td>
+                    <td>{if step.ramp { "ramp" } else { "step" }}</td>
+                    <td>
+                        <Button
+                            title={"Remove"}
+                            onsignal={ctx.link().callback(move |_| Msg::RemoveStep(idx))}
+                            />
+                    </td>
+                </tr>
+            }
+        });
+        html! {
+            <div class="led-sequence">
+                <h3>{"Sequence"}</h3>
+                <p>
+                    {"Steps run one after another on the LED box. Upload the \
+                    sequence first, then start it with minimal latency."}
+                </p>
+                <table>
+                    <tr>
+                        <th>{"Step"}</th>
+                        <th>{"LED"}</th>
+                        <th>{"Intensity (percent)"}</th>
+                        <th>{"Duration (msec)"}</th>
+                        <th>{"Change"}</th>
+                        <th></th>
+                    </tr>
+                    {for rows}
+                </table>
+                <div>
+                    <label>{"LED "}
+                        <TypedInput<u8>
+                            storage={self.new_channel.clone()}
+                            />
+                    </label>
+                    <label>{"Intensity (percent) "}
+                        <TypedInput<f32>
+                            storage={self.new_intensity_percent.clone()}
+                            />
+                    </label>
+                    <label>{"Duration (msec) "}
+                        <TypedInput<u32>
+                            storage={self.new_duration_msec.clone()}
+                            />
+                    </label>
+                    <Toggle
+                        label={"Ramp"}
+                        value={self.new_ramp}
+                        ontoggle={ctx.link().callback(Msg::SetNewRamp)}
+                        />
+                    <Button title={"Add Step"} onsignal={ctx.link().callback(|_| Msg::AddStep)}/>
+                    <Button title={"Clear"} onsignal={ctx.link().callback(|_| Msg::ClearSteps)}/>
+                </div>
+                <div>
+                    <Toggle
+                        label={"Loop"}
+                        value={self.looping}
+                        ontoggle={ctx.link().callback(Msg::SetLooping)}
+                        />
+                    <Button title={"Upload Sequence"} onsignal={ctx.link().callback(|_| Msg::UploadSequence)}/>
+                    <Button title={"Start Sequence"} onsignal={ctx.link().callback(|_| Msg::StartSequence)}/>
+                    <Button title={"Stop Sequence"} onsignal={ctx.link().callback(|_| Msg::StopSequence)}/>
                 </div>
             </div>
         }
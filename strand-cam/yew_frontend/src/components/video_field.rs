@@ -9,10 +9,16 @@ use yew::{classes, html, Callback, Component, Context, Html, MouseEvent, Propert
 
 use yew_tincture::components::{Button, CheckboxLabel};
 
-use http_video_streaming_types::{CanvasDrawableShape, CircleParams, StrokeStyle};
+use strand_cam_storetype::InspectedPixel;
+
+use http_video_streaming_types::{
+    CanvasDrawableShape, CircleParams, ExposureWarning, Histogram, PreviewCrop, StrokeStyle,
+};
 
 const PLAYING_FPS: f64 = 10.0;
 const PAUSED_FPS: f64 = 0.1;
+/// Smaller selections are ignored, e.g. from a click without dragging.
+const MIN_CROP_SIZE: u32 = 16;
 
 #[derive(Debug)]
 struct MouseCoords {
@@ -23,6 +29,8 @@ struct MouseCoords {
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ImData2 {
     pub draw_shapes: Vec<CanvasDrawableShape>,
+    pub histogram: Option<Histogram>,
+    pub crop: Option<PreviewCrop>,
     pub fno: u64,
     pub ts_rfc3339: String, // timestamp in RFC3339 format
 }
@@ -37,9 +45,17 @@ pub struct VideoField {
     green_stroke: StrokeStyle,
     green: &'static str,
     rendered_frame_number: Option<u64>,
+    /// Histogram of the most recently rendered frame.
+    histogram: Option<Histogram>,
     timeout: Option<Timeout>,
     zoom_mode: ZoomMode,
     rotate_quarter_turns: i8,
+    /// The region of the camera image in the most recent frame.
+    crop: Option<PreviewCrop>,
+    /// Whether dragging the mouse selects a new crop region.
+    selecting_crop: bool,
+    /// The image coordinates where dragging started.
+    drag_start: Option<MouseCoords>,
     ck: ConnectionKey,
     last_recv: f64,
     _clock_handle: Interval,
@@ -49,12 +65,17 @@ pub enum Msg {
     FrameLoaded(ImData2),
     NotifySender,
     MouseMove(MouseEvent),
+    MouseDown(MouseEvent),
+    MouseUp(MouseEvent),
+    Click(MouseEvent),
     ToggleCollapsed(bool),
     ViewFitWidth,
     ViewScale(u8),
     ViewRotateCW,
     ViewRotateCCW,
     ViewFullWindow(bool),
+    ViewSelectCrop,
+    ViewFullImage,
     CheckForUpdate,
 }
 
@@ -75,6 +96,16 @@ pub struct Props {
     pub on_rendered: Option<Callback<ConnectionKey>>,
     pub on_full_window: Option<Callback<bool>>,
     pub full_window: bool,
+    /// Called with the image coordinates of a click to read the pixel value.
+    #[prop_or_default]
+    pub on_inspect_pixel: Option<Callback<(u32, u32)>>,
+    /// The most recently inspected pixel.
+    #[prop_or_default]
+    pub inspected_pixel: Option<InspectedPixel>,
+    /// Called with the region of the camera image to show, or `None` for the
+    /// entire image.
+    #[prop_or_default]
+    pub on_set_crop: Option<Callback<Option<PreviewCrop>>>,
 }
 
 impl Component for VideoField {
@@ -97,9 +128,13 @@ impl Component for VideoField {
             green_stroke: StrokeStyle::from_rgb(0x7F, 0xFF, 0x7F),
             green: "7fff7f",
             rendered_frame_number: None,
+            histogram: None,
             timeout: None,
             zoom_mode: ZoomMode::FitWidth,
             rotate_quarter_turns: 0,
+            crop: None,
+            selecting_crop: false,
+            drag_start: None,
             ck,
             last_recv: 0.0,
             _clock_handle,
@@ -119,28 +154,43 @@ impl Component for VideoField {
                 }
             }
             Msg::MouseMove(mminfo) => {
-                let client_x = mminfo.client_x() as f64;
-                let client_y = mminfo.client_y() as f64;
-                let window = web_sys::window().unwrap();
-                let document = window.document().unwrap();
-                let canvas = document
-                    .get_element_by_id(&self.canvas_css_id)
-                    .unwrap_throw();
-                let canvas: web_sys::HtmlCanvasElement = canvas
-                    .dyn_into::<web_sys::HtmlCanvasElement>()
-                    .map_err(|_| ())
-                    .unwrap_throw();
-                let rect = canvas.get_bounding_client_rect(); // abs. size of element
-                let scale_x = canvas.width() as f64 / rect.width(); // relationship bitmap vs. element for X
-                let scale_y = canvas.height() as f64 / rect.height(); // relationship bitmap vs. element for Y
-                let is_rotate_180 = canvas.class_list().contains("rotate-180");
-                let mut x = (client_x - rect.left()) * scale_x; // scale mouse coordinates after they have
-                let mut y = (client_y - rect.top()) * scale_y; // been adjusted to be relative to element
-                if is_rotate_180 {
-                    x = canvas.width() as f64 - x;
-                    y = canvas.height() as f64 - y;
+                self.mouse_xy = Some(self.canvas_coords(&mminfo));
+            }
+            Msg::MouseDown(mminfo) => {
+                if self.selecting_crop && self.rotate_quarter_turns == 0 {
+                    self.drag_start = Some(self.canvas_coords(&mminfo));
+                }
+            }
+            Msg::MouseUp(mminfo) => {
+                if let Some(start) = self.drag_start.take() {
+                    let end = self.canvas_coords(&mminfo);
+                    let crop = PreviewCrop {
+                        x: start.x.min(end.x).max(0.0) as u32,
+                        y: start.y.min(end.y).max(0.0) as u32,
+                        width: (start.x - end.x).abs() as u32,
+                        height: (start.y - end.y).abs() as u32,
+                    };
+                    if crop.width >= MIN_CROP_SIZE && crop.height >= MIN_CROP_SIZE {
+                        self.selecting_crop = false;
+                        if let Some(callback) = ctx.props().on_set_crop.as_ref() {
+                            callback.emit(Some(crop));
+                        }
+                    }
+                }
+            }
+            Msg::Click(mminfo) => {
+                if self.selecting_crop {
+                    return true;
+                }
+                let coords = self.canvas_coords(&mminfo);
+                if let (Some(callback), 0) = (
+                    ctx.props().on_inspect_pixel.as_ref(),
+                    self.rotate_quarter_turns,
+                ) {
+                    if coords.x >= 0.0 && coords.y >= 0.0 {
+                        callback.emit((coords.x as u32, coords.y as u32));
+                    }
                 }
-                self.mouse_xy = Some(MouseCoords { x, y });
             }
             Msg::ToggleCollapsed(checked) => {
                 self.show_div = checked;
@@ -175,6 +225,7 @@ impl Component for VideoField {
                 }
 
                 self.rendered_frame_number = Some(fno);
+                self.histogram = im_data.histogram;
             }
             Msg::NotifySender => {
                 self.timeout = None;
@@ -194,6 +245,17 @@ impl Component for VideoField {
             Msg::ViewRotateCCW => {
                 self.rotate_quarter_turns = (self.rotate_quarter_turns - 1) % 4;
             }
+            Msg::ViewSelectCrop => {
+                self.selecting_crop = !self.selecting_crop;
+                self.drag_start = None;
+            }
+            Msg::ViewFullImage => {
+                self.selecting_crop = false;
+                self.drag_start = None;
+                if let Some(callback) = ctx.props().on_set_crop.as_ref() {
+                    callback.emit(None);
+                }
+            }
             Msg::ViewFullWindow(val) => {
                 // Initially try fullscreen mode...
                 let window = web_sys::window().unwrap();
@@ -230,10 +292,15 @@ impl Component for VideoField {
             }
 
             let draw_shapes = draw_shapes.into_iter().map(|s| s.into()).collect();
+            // Resize the canvas to the region of the camera image now, before
+            // the frame is drawn.
+            self.crop = in_msg.crop;
             let in_msg2 = ImData2 {
                 fno: in_msg.fno,
                 ts_rfc3339: in_msg.ts_rfc3339,
                 draw_shapes,
+                histogram: in_msg.histogram,
+                crop: in_msg.crop,
             };
 
             // It seems that in some circumstances with yew 0.21.0, this
@@ -265,6 +332,36 @@ impl Component for VideoField {
 }
 
 impl VideoField {
+    /// The image coordinates of the mouse.
+    fn canvas_coords(&self, mminfo: &MouseEvent) -> MouseCoords {
+        let client_x = mminfo.client_x() as f64;
+        let client_y = mminfo.client_y() as f64;
+        let window = web_sys::window().unwrap();
+        let document = window.document().unwrap();
+        let canvas = document
+            .get_element_by_id(&self.canvas_css_id)
+            .unwrap_throw();
+        let canvas: web_sys::HtmlCanvasElement = canvas
+            .dyn_into::<web_sys::HtmlCanvasElement>()
+            .map_err(|_| ())
+            .unwrap_throw();
+        let rect = canvas.get_bounding_client_rect(); // abs. size of element
+        let scale_x = canvas.width() as f64 / rect.width(); // relationship bitmap vs. element for X
+        let scale_y = canvas.height() as f64 / rect.height(); // relationship bitmap vs. element for Y
+        let is_rotate_180 = canvas.class_list().contains("rotate-180");
+        let mut x = (client_x - rect.left()) * scale_x; // scale mouse coordinates after they have
+        let mut y = (client_y - rect.top()) * scale_y; // been adjusted to be relative to element
+        if is_rotate_180 {
+            x = canvas.width() as f64 - x;
+            y = canvas.height() as f64 - y;
+        }
+        if let Some(crop) = self.crop.as_ref() {
+            x += f64::from(crop.x);
+            y += f64::from(crop.y);
+        }
+        MouseCoords { x, y }
+    }
+
     fn fps(&self) -> f64 {
         match self.show_div {
             true => PLAYING_FPS,
@@ -314,15 +411,40 @@ impl VideoField {
                         title={"Fullscreen"}
                         onsignal={ctx.link().callback(|_| Msg::ViewFullWindow(true))}
                         />
+                    { self.view_crop_buttons(ctx) }
                 </div>
                 { self.view_video_div(ctx) }
                 { self.view_text(ctx) }
+                { self.view_histogram() }
               </div>
             </div>
         }
     }
+    fn view_crop_buttons(&self, ctx: &Context<Self>) -> Html {
+        if ctx.props().on_set_crop.is_none() {
+            return html! {};
+        }
+        html! {
+            <>
+                <Button
+                    title={"Select Crop"}
+                    onsignal={ctx.link().callback(|_| Msg::ViewSelectCrop)}
+                    is_active={self.selecting_crop}
+                    />
+                <Button
+                    title={"Full Image"}
+                    onsignal={ctx.link().callback(|_| Msg::ViewFullImage)}
+                    is_active={self.crop.is_none()}
+                    />
+            </>
+        }
+    }
     fn view_video_div(&self, ctx: &Context<Self>) -> Html {
-        let cprops = self.cprops(ctx.props().image_width, ctx.props().image_height);
+        let (width, height) = match self.crop.as_ref() {
+            Some(crop) => (crop.width, crop.height),
+            None => (ctx.props().image_width, ctx.props().image_height),
+        };
+        let cprops = self.cprops(width, height);
         let full_window_skin = if ctx.props().full_window {
             html! {
                 <Button
@@ -344,6 +466,9 @@ impl VideoField {
                         class={classes!("video-field-canvas")}
                         style={cprops.canv_style}
                         onmousemove={ctx.link().callback(Msg::MouseMove)}
+                        onmousedown={ctx.link().callback(Msg::MouseDown)}
+                        onmouseup={ctx.link().callback(Msg::MouseUp)}
+                        onclick={ctx.link().callback(Msg::Click)}
                         />
                 </div>
             </div>
@@ -391,10 +516,30 @@ impl VideoField {
                 "(Rotation disabled mouse position.)".to_string()
             };
         let fno_str = format!("{}", self.rendered_frame_number.unwrap_or(0));
+        let pixel_str = match (
+            ctx.props().on_inspect_pixel.as_ref(),
+            ctx.props().inspected_pixel.as_ref(),
+        ) {
+            (None, _) => String::new(),
+            (Some(_), None) => "(Click to read a pixel value.)".to_string(),
+            (Some(_), Some(pixel)) => {
+                let values = pixel
+                    .values
+                    .iter()
+                    .map(|v| format!("{v}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "pixel {}, {} (frame {}): {values}",
+                    pixel.x, pixel.y, pixel.fno
+                )
+            }
+        };
         html! {
             <div class="video-field-text">
                 <div class="video-field-fno">{"frame: "}{ &fno_str }</div>
                 <div class="video-field-mousepos">{ &mouse_str }</div>
+                <div class="video-field-pixel">{ &pixel_str }</div>
                 <div class="video-field-fps">
                     {"frames per second: "}{ format!("{:.1}", ctx.props().measured_fps) }
                 </div>
@@ -402,6 +547,35 @@ impl VideoField {
         }
     }
 
+    fn view_histogram(&self) -> Html {
+        let Some(histogram) = self.histogram.as_ref() else {
+            return html! {};
+        };
+        let max_count = histogram.counts.iter().copied().max().unwrap_or(0).max(1);
+        let bars = histogram.counts.iter().map(|count| {
+            let height = 100.0 * f64::from(*count) / f64::from(max_count);
+            let style = format!("height: {height:.1}%;");
+            html! {
+                <span class="video-field-histogram-bar" style={style}></span>
+            }
+        });
+        let warning = match histogram.exposure_warning() {
+            Some(ExposureWarning::Overexposed(fraction)) => {
+                format!("Overexposed: {:.1}% of pixels saturated.", fraction * 100.0)
+            }
+            Some(ExposureWarning::Underexposed) => "Underexposed.".to_string(),
+            None => String::new(),
+        };
+        html! {
+            <div class="video-field-histogram-wrap">
+                <div class="video-field-histogram" title="Intensity histogram">
+                    {for bars}
+                </div>
+                <div class="video-field-exposure-warning">{warning}</div>
+            </div>
+        }
+    }
+
     fn draw_frame_canvas(&self, in_msg: &ImData2) {
         let window = web_sys::window().unwrap();
         let document = window.document().unwrap();
@@ -416,12 +590,34 @@ impl VideoField {
             canvas.get_context("2d").unwrap_throw().unwrap_throw(),
         ));
 
-        ctx.draw_image_with_html_image_element(&self.image, 0.0, 0.0)
-            .unwrap_throw();
+        // The image may be downscaled relative to the camera resolution, so
+        // stretch it to fill the canvas. The annotations are in camera pixel
+        // coordinates.
+        ctx.draw_image_with_html_image_element_and_dw_and_dh(
+            &self.image,
+            0.0,
+            0.0,
+            canvas.width() as f64,
+            canvas.height() as f64,
+        )
+        .unwrap_throw();
+
+        // Draw the annotations, which are in camera pixel coordinates, relative
+        // to the cropped region.
+        ctx.save();
+        if let Some(crop) = in_msg.crop.as_ref() {
+            ctx.translate(-f64::from(crop.x), -f64::from(crop.y))
+                .unwrap_throw();
+        }
 
         ctx.set_stroke_style_str(self.green);
         ctx.set_line_width(1.0);
 
+        if let (Some(start), Some(end)) = (self.drag_start.as_ref(), self.mouse_xy.as_ref()) {
+            // The crop region being selected.
+            ctx.stroke_rect(start.x, start.y, end.x - start.x, end.y - start.y);
+        }
+
         for drawable_shape in in_msg.draw_shapes.iter() {
             ctx.set_stroke_style_str(&drawable_shape.stroke_style);
             ctx.set_line_width(drawable_shape.line_width as f64);
@@ -452,6 +648,7 @@ impl VideoField {
                 }
             }
         }
+        ctx.restore();
     }
 }
 
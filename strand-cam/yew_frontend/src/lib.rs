@@ -11,26 +11,31 @@ use ci2_remote_control::CamArg;
 use enum_iter::EnumIter;
 use led_box_comms::ToDevice as ToLedBoxDevice;
 
-use gloo_events::EventListener;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{Event, EventSource, MessageEvent};
+use web_sys::Event;
 
 use yew::prelude::*;
 
 use ads_webasm::components::{EnumToggle, VecToggle};
 
-use http_video_streaming_types::ToClient as FirehoseImageData;
+use http_video_streaming_types::{CircleParams, PreviewCrop, Shape, ToClient as FirehoseImageData};
 
-use ci2_remote_control::{BitrateSelection, CodecSelection};
+use ci2_remote_control::{
+    BitrateSelection, CodecSelection, ImOpsFormat, Mp4OverlayConfig, OverlayCorner, OverlaySize,
+    PreviewJpegQuality, PreviewMaxWidth,
+};
+use rust_cam_bui_types::ImageTransform;
 use strand_cam_storetype::{
-    CallbackType, KalmanTrackingConfig, LedProgramConfig, StoreType as ServerState,
+    is_valid_settings_profile_name, CallbackType, CheckerboardCalQuality, CheckerboardCoverage,
+    KalmanTrackingConfig, LatencyTestConfig, LedProgramConfig, SoftAutoExposureConfig,
+    StoreType as ServerState,
 };
 
 use yew_tincture::components::CheckboxLabel;
 
-use ci2_remote_control::{RecordingFrameRate, TagFamily};
+use ci2_remote_control::{CheckerboardPattern, RecordingFrameRate, TagFamily};
 use ci2_types::AutoMode;
 
 use flydra_feature_detector_types::ImPtDetectCfg;
@@ -47,6 +52,12 @@ use components::{LedBoxControl, VideoField};
 mod video_data;
 use video_data::VideoData;
 
+mod theme;
+use theme::Theme;
+
+mod transport;
+use transport::{EventCallbacks, Transport};
+
 const LAST_DETECTED_VALUE_LABEL: &str = "Last detected value: ";
 
 enum Msg {
@@ -78,6 +89,14 @@ enum Msg {
     ToggleObjDetectionSaveCsv(bool),
     // only used when image-tracker crate used
     ToggleCsvRecordingRate(RecordingFrameRate),
+    // only used when image-tracker crate used
+    SetOcclusionMasks(String),
+    // only used when image-tracker crate used
+    SetOcclusionMaskRadius(u16),
+    // only used when image-tracker crate used
+    PickOcclusionMaskCenter,
+    // only used when image-tracker crate used
+    ClearOcclusionMasks,
 
     ToggleTagFamily(TagFamily),
     ToggleAprilTagDetection(bool),
@@ -89,26 +108,49 @@ enum Msg {
     SetImOpsCenterX(u32),
     SetImOpsCenterY(u32),
     SetImOpsTheshold(u8),
+    SetImOpsMaxPoints(u8),
+    SetImOpsFormat(ImOpsFormat),
+    PickImOpsCenter,
+    ImOpsAutoThreshold,
+    SetLatencyTestLedChannel(u8),
+    SetLatencyTestNumTrials(u32),
+    StartLatencyTest,
+    StopLatencyTest,
 
     CamArgSetKalmanTrackingConfig(String),
     CamArgSetLedProgramConfig(String),
+    SetSoftAutoExposureConfig(String),
 
     ToggleFmfSave(bool),
+    ToggleFmfZstdCompression(bool),
     ToggleFmfRecordingFrameRate(RecordingFrameRate),
 
     // only used when image-tracker crate used
     ToggleUfmfSave(bool),
 
     ToggleMp4Save(bool),
+    PauseRecording,
+    ResumeRecording,
     ToggleMp4RecordingFrameRate(RecordingFrameRate),
     ToggleMp4Bitrate(BitrateSelection),
     ToggleMp4Codec(String),
     ToggleCudaDevice(String),
 
+    TogglePreviewMaxWidth(PreviewMaxWidth),
+    TogglePreviewJpegQuality(PreviewJpegQuality),
+    ToggleImageTransform(ImageTransform),
+    SetPreviewCrop(Option<PreviewCrop>),
+
     // only used when image-tracker crate used
     TakeCurrentImageAsBackground,
     // only used when image-tracker crate used
     ClearBackground(f32),
+    // only used when image-tracker crate used
+    SaveBackgroundModel,
+    ExportFrameOmeTiff,
+    // only used when image-tracker crate used
+    ExportBackgroundOmeTiff,
+    InspectPixel(u32, u32),
 
     LedBoxControlEvent(ToLedBoxDevice),
 
@@ -116,15 +158,29 @@ enum Msg {
     ToggleCheckerboardDebug(bool),
     SetCheckerboardWidth(u32),
     SetCheckerboardHeight(u32),
+    SetCheckerboardPattern(CheckerboardPattern),
+    SetCharucoMarkerRatio(f64),
     PerformCheckerboardCalibration,
     ClearCheckerboards,
 
     SetPostTriggerBufferSize(usize),
+    SetMp4RolloverMinutes(f64),
+    SetMp4RolloverGb(f64),
+    SetMp4Overlay(Mp4OverlayConfig),
+    ToggleMp4DrawDetections(bool),
     PostTriggerMp4Recording,
 
     SendMessageFetchState(FetchState),
+    WebSocketFailed,
     RenderView,
     SetVideoFieldFullWindow(bool),
+    SetTheme(Theme),
+
+    SetSettingsProfileName(String),
+    SaveSettingsProfile,
+    LoadSettingsProfile(String),
+
+    SetLogFilter(String),
 }
 
 // -----------------------------------------------------------------------------
@@ -159,6 +215,7 @@ impl From<JsValue> for FetchError {
 
 struct Model {
     video_field_full_window: bool,
+    theme: Theme,
     conn_key: String,
 
     video_data: Rc<RefCell<VideoData>>,
@@ -166,19 +223,38 @@ struct Model {
     server_state: Option<Box<ServerState>>,
     json_decode_err: Option<String>,
     html_page_title: Option<String>,
-    es: EventSource,
-    _listeners: Vec<EventListener>,
+    transport: Transport,
 
     csv_recording_rate: RecordingFrameRate,
     checkerboard_width: TypedInputStorage<u32>,
     checkerboard_height: TypedInputStorage<u32>,
+    charuco_marker_ratio: TypedInputStorage<f64>,
     post_trigger_buffer_size_local: TypedInputStorage<usize>,
+    mp4_rollover_minutes_local: TypedInputStorage<f64>,
+    mp4_rollover_gb_local: TypedInputStorage<f64>,
 
     im_ops_destination_local: TypedInputStorage<SocketAddr>,
     im_ops_source_local: TypedInputStorage<IpAddr>,
     im_ops_center_x: TypedInputStorage<u32>,
     im_ops_center_y: TypedInputStorage<u32>,
     im_ops_threshold: TypedInputStorage<u8>,
+    im_ops_max_points: TypedInputStorage<u8>,
+    /// The next click into the live view sets the ImOps center.
+    im_ops_pick_center: bool,
+
+    occlusion_mask_radius_local: TypedInputStorage<u16>,
+    occlusion_mask_radius: u16,
+    /// The next click into the live view adds a circular occlusion mask.
+    occlusion_mask_pick_center: bool,
+
+    latency_test_config: LatencyTestConfig,
+    latency_test_led_channel: TypedInputStorage<u8>,
+    latency_test_n_trials: TypedInputStorage<u32>,
+
+    settings_profile_name_local: TypedInputStorage<String>,
+    settings_profile_name: String,
+
+    log_filter_local: TypedInputStorage<String>,
 
     ignore_all_future_frame_processing_errors: bool,
 }
@@ -196,89 +272,48 @@ impl Component for Model {
     type Properties = ();
 
     fn create(ctx: &Context<Self>) -> Self {
-        let es = EventSource::new(strand_cam_storetype::STRAND_CAM_EVENTS_URL_PATH)
-            .map_err(|js_value: JsValue| {
-                let err: js_sys::Error = js_value.dyn_into().unwrap_throw();
-                err
-            })
-            .unwrap_throw();
-        let key_callback = ctx.link().callback(|ck: String| Msg::NewConnKey(ck));
-        let data_callback =
-            ctx.link()
-                .callback(|bufstr: String| match serde_json::from_str(&bufstr) {
-                    Ok(msg) => Msg::NewServerState(msg),
-                    Err(e) => {
-                        log_error(&format!("in data callback: {}", e));
-                        Msg::FailedCallbackJsonDecode(format!("{}", e))
-                    }
-                });
-        let stream_callback = ctx.link().callback(|bufstr: String| {
-            match serde_json::from_str::<FirehoseImageData>(&bufstr) {
-                Ok(image_result) => Msg::NewImageFrame(image_result),
-                Err(e) => {
-                    log_error(&format!("in stream callback: {}", e));
-                    Msg::FailedCallbackJsonDecode(format!("{}", e))
-                }
-            }
-        });
+        let transport = connect_websocket(ctx);
 
-        let mut _listeners = Vec::new();
-        _listeners.push(EventListener::new(
-            &es,
-            strand_cam_storetype::CONN_KEY_EVENT_NAME,
-            move |event: &Event| {
-                let event = event.dyn_ref::<MessageEvent>().unwrap_throw();
-                let text = event.data().as_string().unwrap_throw();
-                key_callback.emit(text);
-            },
-        ));
-
-        _listeners.push(EventListener::new(
-            &es,
-            strand_cam_storetype::STRAND_CAM_EVENT_NAME,
-            move |event: &Event| {
-                let event = event.dyn_ref::<MessageEvent>().unwrap_throw();
-                let text = event.data().as_string().unwrap_throw();
-                data_callback.emit(text);
-            },
-        ));
-
-        _listeners.push(EventListener::new(
-            &es,
-            http_video_streaming_types::VIDEO_STREAM_EVENT_NAME,
-            move |event: &Event| {
-                let event = event.dyn_ref::<MessageEvent>().unwrap_throw();
-                let text = event.data().as_string().unwrap_throw();
-                stream_callback.emit(text);
-            },
-        ));
-
-        let link = ctx.link().clone();
-        _listeners.push(EventListener::new(&es, "error", move |_event: &Event| {
-            // Trigger a UI redraw on error, because we won't get any state
-            // updates from the server which would otherwise cause a redraw.
-            link.send_message(Msg::RenderView);
-        }));
+        let theme = Theme::load();
+        theme.apply();
 
         Self {
             video_field_full_window: false,
+            theme,
             conn_key: "".to_string(), // placeholder
             video_data: Rc::new(RefCell::new(VideoData::new(None))),
             server_state: None,
             json_decode_err: None,
             html_page_title: None,
-            es,
-            _listeners,
+            transport,
             csv_recording_rate: RecordingFrameRate::Unlimited,
             checkerboard_width: TypedInputStorage::empty(),
             checkerboard_height: TypedInputStorage::empty(),
+            charuco_marker_ratio: TypedInputStorage::empty(),
             post_trigger_buffer_size_local: TypedInputStorage::empty(),
+            mp4_rollover_minutes_local: TypedInputStorage::empty(),
+            mp4_rollover_gb_local: TypedInputStorage::empty(),
 
             im_ops_destination_local: TypedInputStorage::empty(),
             im_ops_source_local: TypedInputStorage::empty(),
             im_ops_center_x: TypedInputStorage::empty(),
             im_ops_center_y: TypedInputStorage::empty(),
             im_ops_threshold: TypedInputStorage::empty(),
+            im_ops_max_points: TypedInputStorage::empty(),
+            im_ops_pick_center: false,
+
+            occlusion_mask_radius_local: TypedInputStorage::empty(),
+            occlusion_mask_radius: 20,
+            occlusion_mask_pick_center: false,
+
+            latency_test_config: LatencyTestConfig::default(),
+            latency_test_led_channel: TypedInputStorage::empty(),
+            latency_test_n_trials: TypedInputStorage::empty(),
+
+            settings_profile_name_local: TypedInputStorage::empty(),
+            settings_profile_name: String::new(),
+
+            log_filter_local: TypedInputStorage::empty(),
 
             ignore_all_future_frame_processing_errors: false,
         }
@@ -286,10 +321,33 @@ impl Component for Model {
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
+            Msg::WebSocketFailed => {
+                log_warn("Could not open WebSocket. Using event stream.");
+                self.transport = Transport::event_source(event_callbacks(ctx));
+            }
             Msg::RenderView => {}
             Msg::SetVideoFieldFullWindow(val) => {
                 self.video_field_full_window = val;
             }
+            Msg::SetTheme(theme) => {
+                theme.save();
+                theme.apply();
+                self.theme = theme;
+            }
+            Msg::SetSettingsProfileName(name) => {
+                self.settings_profile_name = name.trim().to_string();
+            }
+            Msg::SaveSettingsProfile => {
+                if is_valid_settings_profile_name(&self.settings_profile_name) {
+                    let name = self.settings_profile_name.clone();
+                    self.send_cam_message(CamArg::SaveProfile(name), ctx);
+                }
+                return false; // don't update DOM, do that on return
+            }
+            Msg::LoadSettingsProfile(name) => {
+                self.send_cam_message(CamArg::LoadProfile(name), ctx);
+                return false; // don't update DOM, do that on return
+            }
             Msg::SendMessageFetchState(_fetch_state) => {
                 return false;
             }
@@ -321,9 +379,18 @@ impl Component for Model {
                     .set_if_not_focused(response.checkerboard_data.width);
                 self.checkerboard_height
                     .set_if_not_focused(response.checkerboard_data.height);
+                self.charuco_marker_ratio
+                    .set_if_not_focused(response.checkerboard_data.charuco_marker_ratio);
 
                 self.post_trigger_buffer_size_local
                     .set_if_not_focused(response.post_trigger_buffer_size);
+                self.log_filter_local
+                    .set_if_not_focused(response.log_filter.clone());
+                // Zero means no rollover.
+                self.mp4_rollover_minutes_local
+                    .set_if_not_focused(response.mp4_rollover_minutes.unwrap_or(0.0));
+                self.mp4_rollover_gb_local
+                    .set_if_not_focused(response.mp4_rollover_gb.unwrap_or(0.0));
 
                 self.im_ops_destination_local
                     .set_if_not_focused(response.im_ops_state.destination);
@@ -340,6 +407,23 @@ impl Component for Model {
                 self.im_ops_threshold
                     .set_if_not_focused(response.im_ops_state.threshold);
 
+                self.im_ops_max_points
+                    .set_if_not_focused(response.im_ops_state.max_points);
+
+                self.latency_test_led_channel
+                    .set_if_not_focused(self.latency_test_config.led_channel);
+                self.latency_test_n_trials
+                    .set_if_not_focused(self.latency_test_config.n_trials);
+
+                let had_error = self
+                    .server_state
+                    .as_ref()
+                    .map(|s| s.had_frame_processing_error)
+                    .unwrap_or(false);
+                if response.had_frame_processing_error && !had_error {
+                    ads_webasm::audio_alert::beep();
+                }
+
                 // Update our cache of the server state
                 self.server_state = Some(response);
             }
@@ -393,6 +477,24 @@ impl Component for Model {
                 self.send_cam_message(CamArg::SetObjDetectionConfig(v), ctx);
                 return false; // don't update DOM, do that on return
             }
+            Msg::SetOcclusionMasks(v) => {
+                match serde_yaml::from_str::<Vec<Shape>>(&v) {
+                    Ok(masks) => self.send_occlusion_masks(|m| *m = masks, ctx),
+                    Err(e) => log_error(&format!("invalid occlusion masks: {e}")),
+                }
+                return false; // don't update DOM, do that on return
+            }
+            Msg::SetOcclusionMaskRadius(v) => {
+                self.occlusion_mask_radius = v;
+                return false; // don't update DOM, do that on return
+            }
+            Msg::PickOcclusionMaskCenter => {
+                self.occlusion_mask_pick_center = true;
+            }
+            Msg::ClearOcclusionMasks => {
+                self.send_occlusion_masks(|m| m.clear(), ctx);
+                return false; // don't update DOM, do that on return
+            }
             Msg::CamArgSetKalmanTrackingConfig(v) => {
                 self.send_cam_message(CamArg::CamArgSetKalmanTrackingConfig(v), ctx);
                 return false; // don't update DOM, do that on return
@@ -401,6 +503,10 @@ impl Component for Model {
                 self.send_cam_message(CamArg::CamArgSetLedProgramConfig(v), ctx);
                 return false; // don't update DOM, do that on return
             }
+            Msg::SetSoftAutoExposureConfig(v) => {
+                self.send_cam_message(CamArg::SetSoftAutoExposureConfig(v), ctx);
+                return false; // don't update DOM, do that on return
+            }
             // only used when image-tracker crate used
             Msg::ToggleObjDetection(v) => {
                 self.send_cam_message(CamArg::SetIsDoingObjDetection(v), ctx);
@@ -457,6 +563,40 @@ impl Component for Model {
                 self.send_cam_message(CamArg::SetImOpsThreshold(v), ctx);
                 return false; // don't update DOM, do that on return
             }
+            Msg::SetImOpsMaxPoints(v) => {
+                self.send_cam_message(CamArg::SetImOpsMaxPoints(v), ctx);
+                return false; // don't update DOM, do that on return
+            }
+            Msg::SetImOpsFormat(v) => {
+                self.send_cam_message(CamArg::SetImOpsFormat(v), ctx);
+                return false; // don't update DOM, do that on return
+            }
+            Msg::SetLatencyTestLedChannel(v) => {
+                self.latency_test_config.led_channel = v;
+                return false; // don't update DOM, do that on return
+            }
+            Msg::SetLatencyTestNumTrials(v) => {
+                self.latency_test_config.n_trials = v;
+                return false; // don't update DOM, do that on return
+            }
+            Msg::StartLatencyTest => {
+                self.send_message(
+                    CallbackType::StartLatencyTest(self.latency_test_config.clone()),
+                    ctx,
+                );
+                return false; // don't update DOM, do that on return
+            }
+            Msg::StopLatencyTest => {
+                self.send_message(CallbackType::StopLatencyTest, ctx);
+                return false; // don't update DOM, do that on return
+            }
+            Msg::PickImOpsCenter => {
+                self.im_ops_pick_center = true;
+            }
+            Msg::ImOpsAutoThreshold => {
+                self.send_message(CallbackType::ImOpsAutoThreshold, ctx);
+                return false; // don't update DOM, do that on return
+            }
             Msg::ToggleFmfRecordingFrameRate(v) => {
                 self.send_cam_message(CamArg::SetRecordingFps(v), ctx);
                 return false; // don't update DOM, do that on return
@@ -484,15 +624,43 @@ impl Component for Model {
                 self.send_cam_message(CamArg::SetMp4CudaDevice(v), ctx);
                 return false; // don't update DOM, do that on return
             }
+            Msg::TogglePreviewMaxWidth(v) => {
+                self.send_cam_message(CamArg::SetPreviewMaxWidth(v), ctx);
+                return false; // don't update DOM, do that on return
+            }
+            Msg::TogglePreviewJpegQuality(v) => {
+                self.send_cam_message(CamArg::SetPreviewJpegQuality(v), ctx);
+                return false; // don't update DOM, do that on return
+            }
+            Msg::ToggleImageTransform(v) => {
+                self.send_cam_message(CamArg::SetImageTransform(v), ctx);
+                return false; // don't update DOM, do that on return
+            }
+            Msg::SetPreviewCrop(v) => {
+                self.send_cam_message(CamArg::SetPreviewCrop(v), ctx);
+                return false; // don't update DOM, do that on return
+            }
             Msg::ToggleFmfSave(v) => {
                 self.send_cam_message(CamArg::SetIsRecordingFmf(v), ctx);
                 return false; // don't update DOM, do that on return
             }
+            Msg::ToggleFmfZstdCompression(v) => {
+                self.send_cam_message(CamArg::SetFmfZstdCompression(v), ctx);
+                return false; // don't update DOM, do that on return
+            }
             // only used when image-tracker crate used
             Msg::ToggleUfmfSave(v) => {
                 self.send_cam_message(CamArg::SetIsRecordingUfmf(v), ctx);
                 return false; // don't update DOM, do that on return
             }
+            Msg::PauseRecording => {
+                self.send_cam_message(CamArg::PauseRecording, ctx);
+                return false; // don't update DOM, do that on return
+            }
+            Msg::ResumeRecording => {
+                self.send_cam_message(CamArg::ResumeRecording, ctx);
+                return false; // don't update DOM, do that on return
+            }
             Msg::ToggleMp4Save(v) => {
                 self.send_cam_message(CamArg::SetIsRecordingMp4(v), ctx);
                 return false; // don't update DOM, do that on return
@@ -507,6 +675,39 @@ impl Component for Model {
                 self.send_message(CallbackType::ClearBackground(value), ctx);
                 return false; // don't update DOM, do that on return
             }
+            // only used when image-tracker crate used
+            Msg::SaveBackgroundModel => {
+                self.send_message(CallbackType::SaveBackgroundModel, ctx);
+                return false; // don't update DOM, do that on return
+            }
+            Msg::ExportFrameOmeTiff => {
+                self.send_message(CallbackType::ExportFrameOmeTiff, ctx);
+                return false; // don't update DOM, do that on return
+            }
+            // only used when image-tracker crate used
+            Msg::ExportBackgroundOmeTiff => {
+                self.send_message(CallbackType::ExportBackgroundOmeTiff, ctx);
+                return false; // don't update DOM, do that on return
+            }
+            Msg::InspectPixel(x, y) => {
+                self.send_message(CallbackType::InspectPixel(x, y), ctx);
+                if self.occlusion_mask_pick_center {
+                    self.occlusion_mask_pick_center = false;
+                    let circle = CircleParams {
+                        center_x: x.try_into().unwrap_or(i16::MAX),
+                        center_y: y.try_into().unwrap_or(i16::MAX),
+                        radius: self.occlusion_mask_radius,
+                    };
+                    self.send_occlusion_masks(|m| m.push(Shape::Circle(circle)), ctx);
+                    return true;
+                }
+                if !self.im_ops_pick_center {
+                    return false; // don't update DOM, do that on return
+                }
+                self.im_ops_pick_center = false;
+                self.send_cam_message(CamArg::SetImOpsCenterX(x), ctx);
+                self.send_cam_message(CamArg::SetImOpsCenterY(y), ctx);
+            }
             Msg::LedBoxControlEvent(command) => {
                 self.send_message(CallbackType::ToLedBox(command), ctx);
                 return false; // don't update DOM, do that on return
@@ -527,6 +728,32 @@ impl Component for Model {
                 self.send_cam_message(CamArg::SetCheckerboardHeight(val), ctx);
                 return false;
             }
+            Msg::SetCheckerboardPattern(val) => {
+                self.send_cam_message(CamArg::SetCheckerboardPattern(val), ctx);
+                return false;
+            }
+            Msg::SetCharucoMarkerRatio(val) => {
+                self.send_cam_message(CamArg::SetCharucoMarkerRatio(val), ctx);
+                return false;
+            }
+            Msg::SetMp4RolloverMinutes(val) => {
+                let val = if val > 0.0 { Some(val) } else { None };
+                self.send_cam_message(CamArg::SetMp4RolloverMinutes(val), ctx);
+                return false;
+            }
+            Msg::SetMp4RolloverGb(val) => {
+                let val = if val > 0.0 { Some(val) } else { None };
+                self.send_cam_message(CamArg::SetMp4RolloverGb(val), ctx);
+                return false;
+            }
+            Msg::SetMp4Overlay(val) => {
+                self.send_cam_message(CamArg::SetMp4Overlay(val), ctx);
+                return false;
+            }
+            Msg::ToggleMp4DrawDetections(val) => {
+                self.send_cam_message(CamArg::SetMp4DrawDetections(val), ctx);
+                return false;
+            }
             Msg::PerformCheckerboardCalibration => {
                 self.send_cam_message(CamArg::PerformCheckerboardCalibration, ctx);
                 return false;
@@ -545,6 +772,11 @@ impl Component for Model {
                 self.send_cam_message(CamArg::PostTrigger, ctx);
                 return false; // don't update DOM, do that on return
             }
+
+            Msg::SetLogFilter(val) => {
+                self.send_cam_message(CamArg::SetLogFilter(val), ctx);
+                return false;
+            }
         }
         true
     }
@@ -562,31 +794,63 @@ impl Component for Model {
                 { self.disconnected_dialog() }
                 { self.frame_processing_error_dialog(ctx) }
                 { self.led_box_failed() }
-                <div class="wrapper">
-                    { self.view_video(ctx) }
-                    { self.view_decode_error(ctx) }
-                    { self.view_led_box(ctx) }
-                    { self.view_led_triggering(ctx) }
-                    { self.view_mp4_recording_options(ctx) }
-                    { self.view_post_trigger_options(ctx) }
-                    { self.point_detection_ui(ctx) }
-                    { self.apriltag_detection_ui(ctx) }
-                    { self.im_ops_ui(ctx) }
-                    { self.checkerboard_calibration_ui(ctx) }
-
-                    <div class="wrap-collapsible">
-                        <CheckboxLabel label="Camera Settings" initially_checked=true />
+                <div class="wrapper theme-select">
+                    {"Theme: "}
+                    <EnumToggle<Theme>
+                        value={self.theme}
+                        onsignal={ctx.link().callback(Msg::SetTheme)}
+                    />
+                </div>
+                <div class="wrapper main-columns">
+                    <div class="main-column wrap-collapsible">
+                        <CheckboxLabel label="Live view" initially_checked=true />
                         <div>
-                            <p>{"Set values on the camera itself."}</p>
+                            { self.view_video(ctx) }
+                            { self.view_preview_settings(ctx) }
+                            { self.view_image_transform(ctx) }
+                            { self.view_decode_error(ctx) }
+                            { self.view_led_box(ctx) }
+                            { self.view_led_triggering(ctx) }
+                            { self.view_settings_profiles(ctx) }
                         </div>
+                    </div>
+                    <div class="main-column wrap-collapsible">
+                        <CheckboxLabel label="Recording" initially_checked=true />
                         <div>
-                            { self.view_gain(ctx) }
-                            { self.view_exposure(ctx) }
-                            { self.view_frame_rate_limit(ctx) }
+                            { self.view_mp4_recording_options(ctx) }
+                            { self.view_post_trigger_options(ctx) }
+                            { self.view_fmf_recording_options(ctx) }
+                            { self.view_ome_tiff_export(ctx) }
+                        </div>
+                    </div>
+                    <div class="main-column wrap-collapsible">
+                        <CheckboxLabel label="Detection and camera" initially_checked=true />
+                        <div>
+                            { self.point_detection_ui(ctx) }
+                            { self.apriltag_detection_ui(ctx) }
+                            { self.im_ops_ui(ctx) }
+                            { self.latency_test_ui(ctx) }
+                            { self.checkerboard_calibration_ui(ctx) }
+
+                            <div class="wrap-collapsible">
+                                <CheckboxLabel label="Camera Settings" initially_checked=true />
+                                <div>
+                                    <p>{"Set values on the camera itself."}</p>
+                                </div>
+                                <div>
+                                    { self.view_gain(ctx) }
+                                    { self.view_exposure(ctx) }
+                                    { self.view_soft_auto_exposure(ctx) }
+                                    { self.view_frame_rate_limit(ctx) }
+                                    { self.view_ptp_status() }
+                                    { self.view_frame_pool_stats() }
+                                    { self.view_processing_watchdog() }
+                                </div>
+                            </div>
+                            { self.view_kalman_tracking(ctx) }
+                            { self.view_log_filter(ctx) }
                         </div>
                     </div>
-                    { self.view_fmf_recording_options(ctx) }
-                    { self.view_kalman_tracking(ctx) }
                 </div>
                 <footer id="footer">
                 {format!(
@@ -603,6 +867,10 @@ impl Component for Model {
 
 impl Model {
     fn send_message(&self, val: CallbackType, ctx: &Context<Self>) {
+        let buf = serde_json::to_string(&val).unwrap_throw();
+        if self.transport.send(&buf) {
+            return;
+        }
         ctx.link().send_future(async move {
             match post_message(&val).await {
                 Ok(()) => Msg::SendMessageFetchState(FetchState::Success),
@@ -615,6 +883,20 @@ impl Model {
         self.send_message(CallbackType::ToCamera(args), ctx);
     }
 
+    /// Send the object detection configuration with the occlusion masks
+    /// changed by `modify`.
+    fn send_occlusion_masks<F>(&self, modify: F, ctx: &Context<Self>)
+    where
+        F: FnOnce(&mut Vec<Shape>),
+    {
+        if let Some(ref shared) = self.server_state {
+            let mut cfg = shared.im_pt_detect_cfg.clone();
+            modify(&mut cfg.occlusion_masks);
+            let cfg_str = serde_yaml::to_string(&cfg).unwrap();
+            self.send_cam_message(CamArg::SetObjDetectionConfig(cfg_str), ctx);
+        }
+    }
+
     fn view_decode_error(&self, ctx: &Context<Self>) -> Html {
         if let Some(ref json_decode_err) = self.json_decode_err {
             html! {
@@ -661,6 +943,9 @@ impl Model {
                     on_full_window={ctx.link().callback(|val| {
                         Msg::SetVideoFieldFullWindow(val)
                     })}
+                    on_inspect_pixel={ctx.link().callback(|(x, y)| Msg::InspectPixel(x, y))}
+                    inspected_pixel={shared.inspected_pixel.clone()}
+                    on_set_crop={ctx.link().callback(Msg::SetPreviewCrop)}
                 />
             }
         } else {
@@ -672,9 +957,81 @@ impl Model {
         }
     }
 
+    fn view_preview_settings(&self, ctx: &Context<Self>) -> Html {
+        if let Some(ref shared) = self.server_state {
+            html! {
+                <div class="wrap-collapsible">
+                    <CheckboxLabel label="Live view settings" initially_checked=false />
+                    <div>
+                        <p>{"These settings affect only the live view, not recordings."}</p>
+                        <div>
+                            <h5>{"Maximum width"}</h5>
+                            <EnumToggle<PreviewMaxWidth>
+                                value={shared.preview_max_width.clone()}
+                                onsignal={ctx.link().callback(Msg::TogglePreviewMaxWidth)}
+                            />
+                        </div>
+                        <div>
+                            <h5>{"JPEG quality"}</h5>
+                            <EnumToggle<PreviewJpegQuality>
+                                value={shared.preview_jpeg_quality.clone()}
+                                onsignal={ctx.link().callback(Msg::TogglePreviewJpegQuality)}
+                            />
+                        </div>
+                    </div>
+                </div>
+            }
+        } else {
+            html! {}
+        }
+    }
+
+    fn view_image_transform(&self, ctx: &Context<Self>) -> Html {
+        if let Some(ref shared) = self.server_state {
+            let current = format!(
+                "Current: {} ({}x{} pixels)",
+                shared.image_transform, shared.image_width, shared.image_height
+            );
+            let setting = if shared.is_braid {
+                html! {
+                    <p>{"Set "}<code>{"image_transform"}</code>{" in the Braid configuration file."}</p>
+                }
+            } else {
+                let after_restart = match shared.image_transform_after_restart {
+                    Some(t) => html! {
+                        <p>{format!("Restart Strand Camera to apply: {t}")}</p>
+                    },
+                    None => html! {},
+                };
+                html! {
+                    <div>
+                        <EnumToggle<ImageTransform>
+                            value={shared.image_transform_after_restart.unwrap_or(shared.image_transform)}
+                            onsignal={ctx.link().callback(Msg::ToggleImageTransform)}
+                        />
+                        {after_restart}
+                    </div>
+                }
+            };
+            html! {
+                <div class="wrap-collapsible">
+                    <CheckboxLabel label="Image rotation" initially_checked=false />
+                    <div>
+                        <p>{"Rotation and mirroring of the camera images, for cameras not mounted \
+                            upright. This applies to the live view, detections and recordings."}</p>
+                        <p>{current}</p>
+                        {setting}
+                    </div>
+                </div>
+            }
+        } else {
+            html! {}
+        }
+    }
+
     fn disconnected_dialog(&self) -> Html {
         // 0: connecting, 1: open, 2: closed
-        if self.es.ready_state() == 1 {
+        if self.transport.ready_state() == 1 {
             html! {
                <div>
                  { "" }
@@ -684,7 +1041,7 @@ impl Model {
             html! {
                 <div class="modal-container">
                     <h1> { "Web browser not connected to Strand Camera" } </h1>
-                    <p>{ format!("Connection State: {:?}", self.es.ready_state()) }</p>
+                    <p>{ format!("Connection State: {:?}", self.transport.ready_state()) }</p>
                     <p>{ "Please restart Strand Camera and " }<ReloadButton label="reload"/></p>
                 </div>
             }
@@ -697,8 +1054,8 @@ impl Model {
                 return {
                     html! {
                     <div class="modal-container">
-                        <h1> { "Error: frame processing too slow" } </h1>
-                        <p>{"Processing of image frames is taking too long. Reduce the computational cost of image processing."}</p>
+                        <h1> { "Error: frames dropped" } </h1>
+                        <p>{"Processing of image frames fell behind the camera and frames were dropped, even after reducing the live view and feature detection. Reduce the computational cost of image processing."}</p>
                         <p><Toggle
                                 label={"Ignore all future errors"}
                                 value={self.ignore_all_future_frame_processing_errors}
@@ -804,6 +1161,7 @@ impl Model {
                     <CheckboxLabel label="MP4 Recording Options" initially_checked=true />
                     <div>
                         <p>{"Record video files."}</p>
+                        { self.view_disk_space() }
                     </div>
                     <div>
 
@@ -814,6 +1172,7 @@ impl Model {
                                 ontoggle={ctx.link().callback(|checked| {Msg::ToggleMp4Save(checked)})}
                                 />
                         </div>
+                        { self.view_pause_recording(ctx) }
                         <div>
                             <h5>{"MP4 Max Framerate"}</h5>
                             <EnumToggle<RecordingFrameRate>
@@ -835,6 +1194,34 @@ impl Model {
 
                         { cuda_select_div }
 
+                        <div>
+                            <h5>{"MP4 Rollover"}</h5>
+                            <p>{"Continue long recordings in a new file after the given duration or size. Zero means unlimited."}</p>
+                            <label>{"minutes per file "}
+                                <TypedInput<f64>
+                                    storage={self.mp4_rollover_minutes_local.clone()}
+                                    on_send_valid={ctx.link().callback(Msg::SetMp4RolloverMinutes)}
+                                    />
+                            </label>
+                            <label>{"GB per file "}
+                                <TypedInput<f64>
+                                    storage={self.mp4_rollover_gb_local.clone()}
+                                    on_send_valid={ctx.link().callback(Msg::SetMp4RolloverGb)}
+                                    />
+                            </label>
+                        </div>
+
+                        { self.view_mp4_overlay(ctx, &shared.mp4_overlay) }
+
+                        <div>
+                            <h5>{"MP4 Debug Recording"}</h5>
+                            <Toggle
+                                label={"Draw detections into MP4 file"}
+                                value={shared.mp4_draw_detections}
+                                ontoggle={ctx.link().callback(Msg::ToggleMp4DrawDetections)}
+                                />
+                        </div>
+
                     </div>
                 </div>
             }
@@ -845,6 +1232,55 @@ impl Model {
         }
     }
 
+    fn view_mp4_overlay(&self, ctx: &Context<Self>, overlay: &Mp4OverlayConfig) -> Html {
+        let enabled = {
+            let overlay = overlay.clone();
+            ctx.link().callback(move |enabled| {
+                Msg::SetMp4Overlay(Mp4OverlayConfig {
+                    enabled,
+                    ..overlay.clone()
+                })
+            })
+        };
+        let corner = {
+            let overlay = overlay.clone();
+            ctx.link().callback(move |corner| {
+                Msg::SetMp4Overlay(Mp4OverlayConfig {
+                    corner,
+                    ..overlay.clone()
+                })
+            })
+        };
+        let size = {
+            let overlay = overlay.clone();
+            ctx.link().callback(move |size| {
+                Msg::SetMp4Overlay(Mp4OverlayConfig {
+                    size,
+                    ..overlay.clone()
+                })
+            })
+        };
+        html! {
+            <div>
+                <h5>{"MP4 Timestamp Overlay"}</h5>
+                <p>{"Burn the camera name, timestamp and frame number into new recordings."}</p>
+                <Toggle
+                    label={"Draw overlay"}
+                    value={overlay.enabled}
+                    ontoggle={enabled}
+                    />
+                <EnumToggle<OverlayCorner>
+                    value={overlay.corner}
+                    onsignal={corner}
+                />
+                <EnumToggle<OverlaySize>
+                    value={overlay.size}
+                    onsignal={size}
+                />
+            </div>
+        }
+    }
+
     fn view_post_trigger_options(&self, ctx: &Context<Self>) -> Html {
         html! {
             <div class="wrap-collapsible">
@@ -869,6 +1305,38 @@ impl Model {
         }
     }
 
+    fn view_log_filter(&self, ctx: &Context<Self>) -> Html {
+        html! {
+            <div class="wrap-collapsible">
+                <CheckboxLabel label="Logging" initially_checked=false />
+                <div>
+                    <p>{"Which messages are logged, e.g. "}<code>{"info,strand_cam=debug"}</code>
+                    {". The syntax is that of the RUST_LOG environment variable."}</p>
+                </div>
+                <div>
+                    <label>{"log filter "}
+                        <TypedInput<String>
+                            storage={self.log_filter_local.clone()}
+                            on_send_valid={ctx.link().callback(Msg::SetLogFilter)}
+                            />
+                    </label>
+                </div>
+            </div>
+        }
+    }
+
+    fn view_ome_tiff_export(&self, ctx: &Context<Self>) -> Html {
+        html! {
+            <div class="wrap-collapsible">
+                <CheckboxLabel label="OME-TIFF Snapshot" initially_checked=true />
+                <div>
+                    <Button title={"Save Current Frame as OME-TIFF"} onsignal={ctx.link().callback(|_| Msg::ExportFrameOmeTiff)}/>
+                    {"(Saves the next frame, with camera name, exposure time, gain and timestamp, to the recording directory.)"}
+                </div>
+            </div>
+        }
+    }
+
     fn view_fmf_recording_options(&self, ctx: &Context<Self>) -> Html {
         if let Some(ref shared) = self.server_state {
             let ufmf_div = if shared.has_image_tracker_compiled {
@@ -900,6 +1368,14 @@ impl Model {
                                 ontoggle={ctx.link().callback(|checked| {Msg::ToggleFmfSave(checked)})}
                                 />
                         </div>
+                        <div>
+                            <Toggle
+                                label={"Compress FMF file (lossless, zstd)"}
+                                value={shared.fmf_zstd_compression}
+                                ontoggle={ctx.link().callback(|checked| {Msg::ToggleFmfZstdCompression(checked)})}
+                                />
+                        </div>
+                        { self.view_pause_recording(ctx) }
                         <div>
                             <h5>{"Record FMF Framerate"}</h5>
                             <EnumToggle<RecordingFrameRate>
@@ -970,6 +1446,15 @@ impl Model {
             </div>
         };
         if let Some(ref shared) = self.server_state {
+            let pick_center = if self.im_ops_pick_center {
+                html! {
+                    <p>{"Click the bright target in the live view to set the center."}</p>
+                }
+            } else {
+                html! {
+                    <Button title={"Pick Center in Live View"} onsignal={ctx.link().callback(|_| Msg::PickImOpsCenter)}/>
+                }
+            };
             html! {
                 <div class="wrap-collapsible">
                     <CheckboxLabel label="ImOps Detection" initially_checked=false />
@@ -1025,6 +1510,10 @@ impl Model {
                             </label>
                         </div>
 
+                        <div>
+                            {pick_center}
+                        </div>
+
                         <div>
                             <label>{"Threshold"}
                                 <TypedInput<u8>
@@ -1034,6 +1523,32 @@ impl Model {
                             </label>
                         </div>
 
+                        <div>
+                            <Button title={"Auto Threshold"} onsignal={ctx.link().callback(|_| Msg::ImOpsAutoThreshold)}/>
+                            <p>{"Sets the threshold halfway between the background and the brightest
+                            pixel near the center in the current frame."}</p>
+                        </div>
+
+                        <div>
+                            <label>{"Output format"}
+                                <EnumToggle<ImOpsFormat>
+                                    value={shared.im_ops_state.format}
+                                    onsignal={ctx.link().callback(Msg::SetImOpsFormat)}
+                                    />
+                            </label>
+                            <p>{"\"CBOR centroid\" sends the centroid of all pixels above the threshold.
+                            The other formats send the brightest separate points, with a protocol version."}</p>
+                        </div>
+
+                        <div>
+                            <label>{"Maximum number of points"}
+                                <TypedInput<u8>
+                                    storage={self.im_ops_max_points.clone()}
+                                    on_send_valid={ctx.link().callback(Msg::SetImOpsMaxPoints)}
+                                    />
+                            </label>
+                        </div>
+
                     </div>
                 </div>
             }
@@ -1042,10 +1557,99 @@ impl Model {
         }
     }
 
+    fn latency_test_ui(&self, ctx: &Context<Self>) -> Html {
+        let Some(ref shared) = self.server_state else {
+            return html! {};
+        };
+        let state = &shared.latency_test;
+        let button = if state.running {
+            html! {
+                <Button title={"Stop Latency Test"} onsignal={ctx.link().callback(|_| Msg::StopLatencyTest)}/>
+            }
+        } else {
+            html! {
+                <Button title={"Start Latency Test"} onsignal={ctx.link().callback(|_| Msg::StartLatencyTest)}/>
+            }
+        };
+        let result = match &state.result {
+            Some(result) => {
+                let row = |label: &str, q: f64| {
+                    let value = result
+                        .quantile(q)
+                        .map(|v| format!("{v:.2}"))
+                        .unwrap_or_default();
+                    html! {
+                        <tr><td>{label}</td><td>{value}</td></tr>
+                    }
+                };
+                let mean = result.mean().map(|v| format!("{v:.2}")).unwrap_or_default();
+                html! {
+                    <div>
+                        <p>{format!(
+                            "Trials: {} of {}, missed: {}",
+                            result.n_trials(),
+                            state.config.n_trials,
+                            result.n_missed
+                        )}</p>
+                        <table>
+                            <tr><th>{"Latency"}</th><th>{"msec"}</th></tr>
+                            { row("Minimum", 0.0) }
+                            { row("Median", 0.5) }
+                            { row("90%", 0.9) }
+                            { row("95%", 0.95) }
+                            { row("99%", 0.99) }
+                            { row("Maximum", 1.0) }
+                            <tr><td>{"Mean"}</td><td>{mean}</td></tr>
+                        </table>
+                    </div>
+                }
+            }
+            None => html! {},
+        };
+        html! {
+            <div class="wrap-collapsible">
+                <CheckboxLabel label="ImOps Latency Test" initially_checked=false />
+                <div>
+                    <p>{"Repeatedly switches an LED box channel on and measures the time until the
+                    ImOps detector has sent the detected light over UDP. The LED must be visible
+                    to the camera and exceed the ImOps threshold. The time includes the LED box
+                    connection."}</p>
+                    <div>
+                        <label>{"LED channel"}
+                            <TypedInput<u8>
+                                storage={self.latency_test_led_channel.clone()}
+                                on_send_valid={ctx.link().callback(Msg::SetLatencyTestLedChannel)}
+                                />
+                        </label>
+                    </div>
+                    <div>
+                        <label>{"Number of trials"}
+                            <TypedInput<u32>
+                                storage={self.latency_test_n_trials.clone()}
+                                on_send_valid={ctx.link().callback(Msg::SetLatencyTestNumTrials)}
+                                />
+                        </label>
+                    </div>
+                    { button }
+                    { result }
+                </div>
+            </div>
+        }
+    }
+
     fn point_detection_ui(&self, ctx: &Context<Self>) -> Html {
         if let Some(ref shared) = self.server_state {
             if shared.has_image_tracker_compiled {
                 let cfg_clone = shared.im_pt_detect_cfg.clone();
+                let pick_mask_center = if self.occlusion_mask_pick_center {
+                    html! {
+                        <p>{"Click the center of the region to mask in the live view."}</p>
+                    }
+                } else {
+                    html! {
+                        <Button title={"Add Circle in Live View"} onsignal={ctx.link().callback(|_| Msg::PickOcclusionMaskCenter)}/>
+                    }
+                };
                 return html! {
                     <div class="wrap-collapsible">
                         <CheckboxLabel label="Object Detection" initially_checked=true />
@@ -1087,6 +1691,25 @@ impl Model {
                                     })}
                                     />
                             </div>
+                            <div>
+                                <h5>{"Occlusion masks"}</h5>
+                                <p>{"No objects are detected inside these regions, which are outlined in orange in the live view."}</p>
+                                <div>
+                                    <label>{"Circle radius (pixels)"}
+                                        <TypedInput<u16>
+                                            storage={self.occlusion_mask_radius_local.clone()}
+                                            on_send_valid={ctx.link().callback(Msg::SetOcclusionMaskRadius)}
+                                            />
+                                    </label>
+                                    {pick_mask_center}
+                                    <Button title={"Clear Occlusion Masks"} onsignal={ctx.link().callback(|_| Msg::ClearOcclusionMasks)}/>
+                                </div>
+                                <ConfigField<Vec<Shape>>
+                                    server_version={Some(shared.im_pt_detect_cfg.occlusion_masks.clone())}
+                                    rows={6}
+                                    onsignal={ctx.link().callback(Msg::SetOcclusionMasks)}
+                                    />
+                            </div>
                             <div>
                                 <h5>{"Detailed configuration"}</h5>
                                 <ConfigField<ImPtDetectCfg>
@@ -1097,6 +1720,8 @@ impl Model {
                                 <div class="reset-background-btn">
                                     <Button title={"Take Current Image As Background"} onsignal={ctx.link().callback(|_| Msg::TakeCurrentImageAsBackground)}/>
                                     <Button title={"Set background to mid-gray"} onsignal={ctx.link().callback(|_| Msg::ClearBackground(127.0))}/>
+                                    <Button title={"Save Background Model"} onsignal={ctx.link().callback(|_| Msg::SaveBackgroundModel)}/>
+                                    <Button title={"Export Background as OME-TIFF"} onsignal={ctx.link().callback(|_| Msg::ExportBackgroundOmeTiff)}/>
                                 </div>
                             </div>
                         </div>
@@ -1168,6 +1793,19 @@ impl Model {
                                     />
                             </label>
 
+                            <h2>{"Input: Board Type"}</h2>
+                            <p>{"A ChArUco board has an AprilTag (36h11 family) in each white square, numbered from 0, so that partially visible boards can be used. ChArUco detection requires mono8 images."}</p>
+                            <EnumToggle<CheckerboardPattern>
+                                value={shared.checkerboard_data.pattern.clone()}
+                                onsignal={ctx.link().callback(Msg::SetCheckerboardPattern)}
+                                />
+                            <label>{"marker size / square size (ChArUco only)"}
+                                <TypedInput<f64>
+                                    storage={self.charuco_marker_ratio.clone()}
+                                    on_send_valid={ctx.link().callback(Msg::SetCharucoMarkerRatio)}
+                                    />
+                            </label>
+
                             <h2>{"Action: Perform Calibration"}</h2>
 
                             <div>
@@ -1186,6 +1824,11 @@ impl Model {
                                 onsignal={ctx.link().callback(move |_| Msg::PerformCheckerboardCalibration)}
                                 />
 
+                            <h3>{"Sensor coverage of collected checkerboards"}</h3>
+                            {view_checkerboard_coverage(&shared.checkerboard_data.coverage)}
+
+                            {view_checkerboard_quality(shared.checkerboard_data.last_calibration.as_ref())}
+
                         </div>
                     </div>
                 };
@@ -1299,6 +1942,205 @@ impl Model {
         }
     }
 
+    fn view_soft_auto_exposure(&self, ctx: &Context<Self>) -> Html {
+        if let Some(ref shared) = self.server_state {
+            let level = match shared.soft_auto_exposure_level {
+                Some(level) => format!(
+                    "Current intensity at percentile: {level} (target {}).",
+                    shared.soft_auto_exposure_config.target
+                ),
+                None => "".to_string(),
+            };
+            return html! {
+                <div class="wrap-collapsible">
+                    <CheckboxLabel label="Software Auto Exposure" initially_checked=false />
+                    <div>
+                        <p>{"Adjust the exposure time (and optionally the gain) so that a
+                        percentile of the pixel intensities within a region, or near the
+                        detected points, is held at a target. Unlike the auto exposure of the
+                        camera, bright areas outside the region are ignored."}</p>
+                        <ConfigField<SoftAutoExposureConfig>
+                            server_version={Some(shared.soft_auto_exposure_config.clone())}
+                            rows=12
+                            onsignal={ctx.link().callback(Msg::SetSoftAutoExposureConfig)}
+                            />
+                        <div>{level}</div>
+                    </div>
+                </div>
+            };
+        }
+        html! {}
+    }
+
+    fn view_ptp_status(&self) -> Html {
+        if let Some(ref shared) = self.server_state {
+            if let Some(ref ptp) = shared.ptp_status {
+                let servo = ptp
+                    .servo_status
+                    .as_ref()
+                    .map(|s| format!(", servo {s}"))
+                    .unwrap_or_default();
+                let lock = if ptp.is_locked {
+                    "locked"
+                } else {
+                    "NOT locked"
+                };
+                return html! {
+                    <div>
+                        {format!(
+                            "PTP clock: {}{}, offset from master {} nsec ({}).",
+                            ptp.status, servo, ptp.offset_from_master_nsec, lock
+                        )}
+                    </div>
+                };
+            }
+        }
+        html! {}
+    }
+
+    fn view_frame_pool_stats(&self) -> Html {
+        if let Some(ref shared) = self.server_state {
+            if let Some(ref stats) = shared.frame_pool_stats {
+                return html! {
+                    <div>
+                        {format!(
+                            "Frame buffers: {} in use, {} allocated, {} frames allocated \
+                            outside the pool.",
+                            stats.num_in_use, stats.num_allocated, stats.num_exhausted
+                        )}
+                    </div>
+                };
+            }
+        }
+        html! {}
+    }
+
+    fn view_processing_watchdog(&self) -> Html {
+        if let Some(ref shared) = self.server_state {
+            let status = &shared.processing_watchdog;
+            let interval = status
+                .frame_interval_msec
+                .map(|msec| format!(" of {msec:.1} msec between frames"))
+                .unwrap_or_default();
+            return html! {
+                <div>
+                    {format!(
+                        "Frame processing: {}. Mean {:.1} msec per frame{} (queue {:.1}, \
+                        detection {:.1}, recording {:.1}, live view {:.1} msec).",
+                        status.level,
+                        status.total_msec,
+                        interval,
+                        status.queue_msec,
+                        status.detect_msec,
+                        status.record_msec,
+                        status.preview_msec,
+                    )}
+                </div>
+            };
+        }
+        html! {}
+    }
+
+    fn view_settings_profiles(&self, ctx: &Context<Self>) -> Html {
+        if let Some(ref shared) = self.server_state {
+            let current = shared.current_settings_profile.as_ref();
+            let onchange = ctx.link().callback(|e: Event| {
+                let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                Msg::LoadSettingsProfile(select.value())
+            });
+            let name = &self.settings_profile_name;
+            let name_hint = if !name.is_empty() && !is_valid_settings_profile_name(name) {
+                html! {<span>{" Use only letters, digits, \"-\" and \"_\"."}</span>}
+            } else {
+                html! {}
+            };
+            html! {
+                <div class="wrap-collapsible">
+                    <CheckboxLabel label="Settings Profiles" initially_checked=true />
+                    <div>
+                        <p>{"Save the current camera, recording and detection settings \
+                            to a named profile, or load a saved profile."}</p>
+                    </div>
+                    <div>
+                        <label>{"Load profile "}
+                            <select {onchange}>
+                                <option value="" disabled=true selected={current.is_none()}>
+                                    {"(select a profile)"}
+                                </option>
+                                { for shared.settings_profiles.iter().map(|name| html! {
+                                    <option value={name.clone()} selected={Some(name) == current}>
+                                        {name}
+                                    </option>
+                                })}
+                            </select>
+                        </label>
+                    </div>
+                    <div>
+                        <label>{"Save current settings as "}
+                            <TypedInput<String>
+                                storage={self.settings_profile_name_local.clone()}
+                                on_send_valid={ctx.link().callback(Msg::SetSettingsProfileName)}
+                                />
+                        </label>
+                        <Button title={"Save"} onsignal={ctx.link().callback(|_| Msg::SaveSettingsProfile)} />
+                        {name_hint}
+                    </div>
+                </div>
+            }
+        } else {
+            html! {}
+        }
+    }
+
+    fn view_pause_recording(&self, ctx: &Context<Self>) -> Html {
+        if let Some(ref shared) = self.server_state {
+            let is_recording = shared.is_recording_mp4.is_some()
+                || shared.is_recording_fmf.is_some()
+                || shared.is_saving_im_pt_detect_csv.is_some();
+            if is_recording {
+                if shared.is_recording_paused {
+                    return html! {
+                        <div>
+                            <Button title={"Resume Recording"} onsignal={ctx.link().callback(|_| Msg::ResumeRecording)}/>
+                            {"(Recording is paused. Frames are not saved to the MP4, FMF and CSV files.)"}
+                        </div>
+                    };
+                }
+                return html! {
+                    <div>
+                        <Button title={"Pause Recording"} onsignal={ctx.link().callback(|_| Msg::PauseRecording)}/>
+                        {"(Pausing keeps the MP4, FMF and CSV files open. Pauses are logged in a \".pauses.csv\" file next to each recording.)"}
+                    </div>
+                };
+            }
+        }
+        html! {}
+    }
+
+    fn view_disk_space(&self) -> Html {
+        if let Some(ref shared) = self.server_state {
+            if let Some(ref space) = shared.disk_space {
+                let msg = format!(
+                    "Free disk space: {:.1} of {:.1} GB at \"{}\".",
+                    space.available_bytes as f64 / 1e9,
+                    space.total_bytes as f64 / 1e9,
+                    space.path
+                );
+                if space.is_low {
+                    return html! {
+                        <div class="disk-space-low">
+                            {msg}{" Recordings will be stopped before the disk is full."}
+                        </div>
+                    };
+                }
+                return html! {
+                    <div>{msg}</div>
+                };
+            }
+        }
+        html! {}
+    }
+
     fn view_frame_rate_limit(&self, ctx: &Context<Self>) -> Html {
         if let Some(ref shared) = self.server_state {
             if let Some(ref frl) = shared.frame_rate_limit {
@@ -1342,6 +2184,81 @@ impl Model {
     }
 }
 
+fn view_checkerboard_coverage(coverage: &CheckerboardCoverage) -> Html {
+    let max_count = coverage.counts.iter().copied().max().unwrap_or(0).max(1);
+    let grid_style = format!("grid-template-columns: repeat({}, 1fr);", coverage.n_cols);
+    let cells = coverage.counts.iter().map(|count| {
+        let title = format!("{count} corners");
+        if *count == 0 {
+            html! {<div class="checkerboard-coverage-cell checkerboard-coverage-empty" title={title}></div>}
+        } else {
+            let alpha = 0.2 + 0.8 * (*count as f64 / max_count as f64);
+            let style = format!("background-color: rgba(0, 170, 0, {alpha:.2});");
+            html! {<div class="checkerboard-coverage-cell" style={style} title={title}></div>}
+        }
+    });
+    let pct = coverage.fraction_covered() * 100.0;
+    html! {
+        <div>
+            <div class="checkerboard-coverage" style={grid_style}>
+                {for cells}
+            </div>
+            <div>{format!("{pct:.0}% of image regions contain corners. Collect more checkerboards in empty (red) regions.")}</div>
+        </div>
+    }
+}
+
+fn view_checkerboard_quality(quality: Option<&CheckerboardCalQuality>) -> Html {
+    let quality = if let Some(quality) = quality {
+        quality
+    } else {
+        return html! {};
+    };
+    let max_count = quality
+        .residual_histogram
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let n_bins = quality.residual_histogram.len();
+    let bars = quality
+        .residual_histogram
+        .iter()
+        .enumerate()
+        .map(|(i, count)| {
+            let lo = i as f64 * quality.histogram_bin_width_pixels;
+            let label = if i + 1 == n_bins {
+                format!(">= {lo:.2}")
+            } else {
+                format!("{lo:.2}-{:.2}", lo + quality.histogram_bin_width_pixels)
+            };
+            let width = 100.0 * *count as f64 / max_count as f64;
+            let style = format!("width: {width:.1}%;");
+            html! {
+                <div class="residual-histogram-row">
+                    <span class="residual-histogram-label">{label}</span>
+                    <span class="residual-histogram-bar" style={style}></span>
+                    <span>{format!("{count}")}</span>
+                </div>
+            }
+        });
+    html! {
+        <div>
+            <h3>{"Last calibration"}</h3>
+            <div>{format!("{} corners from {} checkerboards", quality.num_corners, quality.num_checkerboards)}</div>
+            <div>{format!("Reprojection error: RMS {:.3} pixels, median {:.3}, 95th percentile {:.3}, maximum {:.3}",
+                quality.rms_pixels, quality.median_pixels, quality.p95_pixels, quality.max_pixels)}</div>
+            <div>{"Distribution of per-corner reprojection error (pixels):"}</div>
+            <div class="residual-histogram">
+                {for bars}
+            </div>
+            <div>{"Coverage of checkerboards used:"}</div>
+            {view_checkerboard_coverage(&quality.coverage)}
+        </div>
+    }
+}
+
 fn to_rate(rate_enum: &RecordingFrameRate) -> Option<f32> {
     match rate_enum {
         RecordingFrameRate::Fps1 => Some(1.0),
@@ -1361,6 +2278,51 @@ fn to_rate(rate_enum: &RecordingFrameRate) -> Option<f32> {
 
 // -----------------------------------------------------------------------------
 
+fn event_callbacks(ctx: &Context<Model>) -> EventCallbacks {
+    let conn_key = ctx.link().callback(Msg::NewConnKey);
+    let server_state = ctx
+        .link()
+        .callback(|bufstr: String| match serde_json::from_str(&bufstr) {
+            Ok(msg) => Msg::NewServerState(msg),
+            Err(e) => {
+                log_error(&format!("in data callback: {}", e));
+                Msg::FailedCallbackJsonDecode(format!("{}", e))
+            }
+        });
+    let video = ctx.link().callback(|bufstr: String| {
+        match serde_json::from_str::<FirehoseImageData>(&bufstr) {
+            Ok(image_result) => Msg::NewImageFrame(image_result),
+            Err(e) => {
+                log_error(&format!("in stream callback: {}", e));
+                Msg::FailedCallbackJsonDecode(format!("{}", e))
+            }
+        }
+    });
+    // Trigger a UI redraw on error, because we won't get any state updates
+    // from the server which would otherwise cause a redraw.
+    let error = ctx.link().callback(|()| Msg::RenderView);
+    EventCallbacks {
+        conn_key,
+        server_state,
+        video,
+        error,
+    }
+}
+
+/// Connect with a WebSocket, falling back to an event stream.
+fn connect_websocket(ctx: &Context<Model>) -> Transport {
+    let on_failed = ctx.link().callback(|()| Msg::WebSocketFailed);
+    match Transport::websocket(event_callbacks(ctx), on_failed) {
+        Ok(transport) => transport,
+        Err(e) => {
+            log_warn(&format!(
+                "Could not create WebSocket: {e:?}. Using event stream."
+            ));
+            Transport::event_source(event_callbacks(ctx))
+        }
+    }
+}
+
 async fn post_message(msg: &CallbackType) -> Result<(), FetchError> {
     use web_sys::{Request, RequestInit, Response};
     let opts = RequestInit::new();
@@ -0,0 +1,74 @@
+use std::fmt;
+
+use enum_iter::EnumIter;
+use serde::{Deserialize, Serialize};
+
+/// Key under which the selected theme is saved in the browser's local storage.
+///
+/// This must match the key read by the script in `static/index.html`.
+const THEME_STORAGE_KEY: &str = "strand-cam-theme";
+
+/// Color theme of the user interface.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum Theme {
+    /// Follow the preference of the operating system.
+    #[default]
+    Auto,
+    Light,
+    Dark,
+}
+
+impl EnumIter for Theme {
+    fn variants() -> Vec<Self> {
+        use Theme::*;
+        vec![Auto, Light, Dark]
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Theme::*;
+        let s = match self {
+            Auto => "auto",
+            Light => "light",
+            Dark => "dark",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl Theme {
+    /// Load the theme saved in local storage, if any.
+    pub(crate) fn load() -> Self {
+        let saved = local_storage().and_then(|storage| storage.get_item(THEME_STORAGE_KEY).ok()?);
+        match saved.as_deref() {
+            Some("light") => Theme::Light,
+            Some("dark") => Theme::Dark,
+            _ => Theme::Auto,
+        }
+    }
+
+    /// Save the theme to local storage so that it persists across page loads.
+    pub(crate) fn save(&self) {
+        if let Some(storage) = local_storage() {
+            // Failure here (e.g. storage disabled) just means the theme is not
+            // remembered.
+            let _ = storage.set_item(THEME_STORAGE_KEY, &self.to_string());
+        }
+    }
+
+    /// Apply the theme to the page by setting the `data-theme` attribute of the
+    /// root element, which the stylesheet uses to select colors.
+    pub(crate) fn apply(&self) {
+        if let Some(root) = gloo_utils::document().document_element() {
+            let _ = match self {
+                Theme::Auto => root.remove_attribute("data-theme"),
+                Theme::Light | Theme::Dark => root.set_attribute("data-theme", &self.to_string()),
+            };
+        }
+    }
+}
+
+fn local_storage() -> Option<web_sys::Storage> {
+    gloo_utils::window().local_storage().ok()?
+}
@@ -0,0 +1,168 @@
+//! Connection to the Strand Camera server.
+//!
+//! A WebSocket carries both the events from the server and the callbacks to
+//! the server. If the WebSocket cannot be opened (e.g. when Strand Camera is
+//! proxied by Braid), an event stream (server-sent events) is used instead and
+//! callbacks are sent by POST requests.
+
+use std::{cell::Cell, rc::Rc};
+
+use gloo_events::EventListener;
+use wasm_bindgen::{JsCast, JsValue, UnwrapThrowExt};
+use web_sys::{Event, EventSource, MessageEvent, WebSocket};
+use yew::Callback;
+
+/// Receivers of the events from the server, by event name.
+#[derive(Clone)]
+pub(crate) struct EventCallbacks {
+    pub(crate) conn_key: Callback<String>,
+    pub(crate) server_state: Callback<String>,
+    pub(crate) video: Callback<String>,
+    /// Called when the connection fails or closes.
+    pub(crate) error: Callback<()>,
+}
+
+impl EventCallbacks {
+    fn names(&self) -> [(&'static str, &Callback<String>); 3] {
+        [
+            (strand_cam_storetype::CONN_KEY_EVENT_NAME, &self.conn_key),
+            (
+                strand_cam_storetype::STRAND_CAM_EVENT_NAME,
+                &self.server_state,
+            ),
+            (
+                http_video_streaming_types::VIDEO_STREAM_EVENT_NAME,
+                &self.video,
+            ),
+        ]
+    }
+
+    /// Dispatch a WebSocket message, which has the same format as an event in
+    /// an event stream.
+    fn dispatch(&self, text: &str) {
+        let Some((name, data)) = parse_event(text) else {
+            crate::log_error(&format!("could not parse message: {text}"));
+            return;
+        };
+        if let Some((_, callback)) = self.names().into_iter().find(|(n, _)| *n == name) {
+            callback.emit(data.to_string());
+        }
+    }
+}
+
+/// Parse `event: <name>\ndata: <data>\n\n` into name and data.
+fn parse_event(text: &str) -> Option<(&str, &str)> {
+    let (name, data) = text.strip_prefix("event: ")?.split_once('\n')?;
+    let data = data.strip_prefix("data: ")?.trim_end_matches('\n');
+    Some((name, data))
+}
+
+pub(crate) enum Transport {
+    WebSocket {
+        ws: WebSocket,
+        _listeners: Vec<EventListener>,
+    },
+    EventSource {
+        es: EventSource,
+        _listeners: Vec<EventListener>,
+    },
+}
+
+impl Transport {
+    /// Connect with a WebSocket.
+    ///
+    /// If the WebSocket closes without ever having opened, `on_failed` is
+    /// called so that [Transport::event_source] can be used instead.
+    pub(crate) fn websocket(
+        callbacks: EventCallbacks,
+        on_failed: Callback<()>,
+    ) -> Result<Self, JsValue> {
+        let ws = WebSocket::new(&websocket_url()?)?;
+        let was_opened = Rc::new(Cell::new(false));
+
+        let mut _listeners = Vec::new();
+        {
+            let was_opened = was_opened.clone();
+            _listeners.push(EventListener::new(&ws, "open", move |_event: &Event| {
+                was_opened.set(true);
+            }));
+        }
+        {
+            let callbacks = callbacks.clone();
+            _listeners.push(EventListener::new(&ws, "message", move |event: &Event| {
+                let event = event.dyn_ref::<MessageEvent>().unwrap_throw();
+                if let Some(text) = event.data().as_string() {
+                    callbacks.dispatch(&text);
+                }
+            }));
+        }
+        _listeners.push(EventListener::new(&ws, "close", move |_event: &Event| {
+            if was_opened.get() {
+                callbacks.error.emit(());
+            } else {
+                on_failed.emit(());
+            }
+        }));
+        Ok(Transport::WebSocket { ws, _listeners })
+    }
+
+    /// Connect with an event stream.
+    pub(crate) fn event_source(callbacks: EventCallbacks) -> Self {
+        let es = EventSource::new(strand_cam_storetype::STRAND_CAM_EVENTS_URL_PATH)
+            .map_err(|js_value: JsValue| {
+                let err: js_sys::Error = js_value.dyn_into().unwrap_throw();
+                err
+            })
+            .unwrap_throw();
+
+        let mut _listeners = Vec::new();
+        for (name, callback) in callbacks.names() {
+            let callback = callback.clone();
+            _listeners.push(EventListener::new(&es, name, move |event: &Event| {
+                let event = event.dyn_ref::<MessageEvent>().unwrap_throw();
+                let text = event.data().as_string().unwrap_throw();
+                callback.emit(text);
+            }));
+        }
+        _listeners.push(EventListener::new(&es, "error", move |_event: &Event| {
+            callbacks.error.emit(());
+        }));
+        Transport::EventSource { es, _listeners }
+    }
+
+    /// The `readyState` of the connection. 1 is open.
+    pub(crate) fn ready_state(&self) -> u16 {
+        match self {
+            Transport::WebSocket { ws, .. } => ws.ready_state(),
+            Transport::EventSource { es, .. } => es.ready_state(),
+        }
+    }
+
+    /// Send a callback over the WebSocket.
+    ///
+    /// Returns `false` if it was not sent and must be sent by POST request.
+    pub(crate) fn send(&self, buf: &str) -> bool {
+        match self {
+            Transport::WebSocket { ws, .. } if ws.ready_state() == WebSocket::OPEN => {
+                ws.send_with_str(buf).is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The absolute URL of the WebSocket, relative to the current page.
+fn websocket_url() -> Result<String, JsValue> {
+    let location = gloo_utils::window().location();
+    let url = web_sys::Url::new_with_base(
+        strand_cam_storetype::STRAND_CAM_WS_URL_PATH,
+        &location.href()?,
+    )?;
+    let protocol = if url.protocol() == "https:" {
+        "wss:"
+    } else {
+        "ws:"
+    };
+    url.set_protocol(protocol);
+    Ok(url.href())
+}
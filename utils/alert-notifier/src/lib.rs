@@ -0,0 +1,339 @@
+//! Notifications of runtime errors to people who are not watching the screen.
+//!
+//! Experiments often run unattended, e.g. overnight. When a problem occurs,
+//! such as a camera which stops sending frames, a [Notifier] sends an alert to
+//! the webhooks, Slack channels and email addresses given in a
+//! [NotificationConfig].
+//!
+//! Alerts of the same kind from the same source are sent at most once per
+//! [NotificationConfig::min_interval_sec] so that a persisting problem does not
+//! flood the recipients.
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use http_body_util::Full;
+use hyper_rustls::HttpsConnector;
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+/// Environment variable with the SMTP password if [EmailConfig::password] is
+/// not set.
+pub const SMTP_PASSWORD_ENV_VAR: &str = "ALERT_SMTP_PASSWORD";
+
+/// Number of alerts which can wait to be sent.
+const QUEUE_SIZE: usize = 100;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid email address \"{addr}\": {source}")]
+    InvalidEmailAddress {
+        addr: String,
+        source: lettre::address::AddressError,
+    },
+    #[error("SMTP error: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("email error: {0}")]
+    Email(#[from] lettre::error::Error),
+    #[error("invalid URL \"{url}\": {source}")]
+    InvalidUrl {
+        url: String,
+        source: hyper::http::uri::InvalidUri,
+    },
+    #[error("HTTP error: {0}")]
+    Http(#[from] hyper_util::client::legacy::Error),
+    #[error("HTTP status {status} from \"{url}\"")]
+    HttpStatus {
+        url: String,
+        status: hyper::StatusCode,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn default_min_interval_sec() -> u64 {
+    300
+}
+
+/// Where to send notifications.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// URLs to which alerts are sent by HTTP POST as a JSON object with the
+    /// fields `time`, `source`, `kind` and `message`.
+    #[serde(default)]
+    pub webhook_urls: Vec<String>,
+    /// Slack incoming webhook URLs, e.g.
+    /// `https://hooks.slack.com/services/...`.
+    #[serde(default)]
+    pub slack_webhook_urls: Vec<String>,
+    /// Send alerts by email.
+    pub email: Option<EmailConfig>,
+    /// Minimum interval, in seconds, between alerts of the same kind from the
+    /// same source.
+    #[serde(default = "default_min_interval_sec")]
+    pub min_interval_sec: u64,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            webhook_urls: Vec::new(),
+            slack_webhook_urls: Vec::new(),
+            email: None,
+            min_interval_sec: default_min_interval_sec(),
+        }
+    }
+}
+
+/// Sending alerts by email using an SMTP server with STARTTLS.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    /// Host name of the SMTP server.
+    pub smtp_server: String,
+    /// User name to log in to the SMTP server. If not set, no login is done.
+    pub username: Option<String>,
+    /// Password to log in to the SMTP server. If not set, the password is
+    /// read from the environment variable `ALERT_SMTP_PASSWORD`.
+    pub password: Option<String>,
+    /// Sender address, e.g. `Braid <braid@example.com>`.
+    pub from: String,
+    /// Recipient addresses.
+    pub to: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Alert {
+    time: chrono::DateTime<chrono::Utc>,
+    source: String,
+    kind: String,
+    message: String,
+}
+
+/// Limits how often alerts with the same key are sent.
+#[derive(Debug)]
+struct RateLimiter {
+    min_interval: Duration,
+    last_sent: BTreeMap<(String, String), Instant>,
+}
+
+impl RateLimiter {
+    fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last_sent: BTreeMap::new(),
+        }
+    }
+
+    /// Return whether an alert of `kind` from `source` should be sent at time
+    /// `now`. If so, the time is recorded.
+    fn check(&mut self, source: &str, kind: &str, now: Instant) -> bool {
+        let key = (source.to_string(), kind.to_string());
+        match self.last_sent.get(&key) {
+            Some(last) if now.saturating_duration_since(*last) < self.min_interval => false,
+            _ => {
+                self.last_sent.insert(key, now);
+                true
+            }
+        }
+    }
+}
+
+/// Sends alerts in the background.
+///
+/// Cloning a `Notifier` is cheap and all clones share the rate limit.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    tx: tokio::sync::mpsc::Sender<Alert>,
+}
+
+impl Notifier {
+    /// Start the background task which sends the alerts.
+    ///
+    /// This must be called from within a tokio runtime.
+    pub fn new(cfg: NotificationConfig) -> Result<Self> {
+        let email = cfg.email.as_ref().map(EmailSender::new).transpose()?;
+        for url in cfg.webhook_urls.iter().chain(cfg.slack_webhook_urls.iter()) {
+            parse_uri(url)?;
+        }
+        let https = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+        let client = Client::builder(TokioExecutor::new()).build(https);
+        let mut rate_limiter = RateLimiter::new(Duration::from_secs(cfg.min_interval_sec));
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<Alert>(QUEUE_SIZE);
+        tokio::spawn(async move {
+            while let Some(alert) = rx.recv().await {
+                if !rate_limiter.check(&alert.source, &alert.kind, Instant::now()) {
+                    debug!("not sending alert (rate limit): {alert:?}");
+                    continue;
+                }
+                let webhook_body = serde_json::to_vec(&alert).unwrap();
+                for url in cfg.webhook_urls.iter() {
+                    if let Err(e) = post(&client, url, webhook_body.clone()).await {
+                        error!("sending alert to webhook: {e}");
+                    }
+                }
+                let slack_body = serde_json::to_vec(&serde_json::json!({
+                    "text": format!("{}: {}", alert.source, alert.message),
+                }))
+                .unwrap();
+                for url in cfg.slack_webhook_urls.iter() {
+                    if let Err(e) = post(&client, url, slack_body.clone()).await {
+                        error!("sending alert to Slack: {e}");
+                    }
+                }
+                if let Some(email) = email.as_ref() {
+                    if let Err(e) = email.send(&alert).await {
+                        error!("sending alert by email: {e}");
+                    }
+                }
+            }
+        });
+        Ok(Self { tx })
+    }
+
+    /// Send an alert.
+    ///
+    /// `source` is the program or camera with the problem, `kind` identifies
+    /// the type of problem (e.g. `"camera-stalled"`) and `message` describes it
+    /// for humans. This does not block. If too many alerts are waiting to be
+    /// sent, the alert is dropped.
+    pub fn notify(&self, source: &str, kind: &str, message: &str) {
+        let alert = Alert {
+            time: chrono::Utc::now(),
+            source: source.to_string(),
+            kind: kind.to_string(),
+            message: message.to_string(),
+        };
+        if let Err(e) = self.tx.try_send(alert) {
+            error!("could not queue alert: {e}");
+        }
+    }
+}
+
+fn parse_uri(url: &str) -> Result<hyper::Uri> {
+    url.parse().map_err(|source| Error::InvalidUrl {
+        url: url.to_string(),
+        source,
+    })
+}
+
+async fn post(
+    client: &Client<HttpsConnector<HttpConnector>, Full<bytes::Bytes>>,
+    url: &str,
+    body: Vec<u8>,
+) -> Result<()> {
+    let req = hyper::Request::post(parse_uri(url)?)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Full::new(bytes::Bytes::from(body)))
+        .unwrap();
+    let res = client.request(req).await?;
+    if !res.status().is_success() {
+        return Err(Error::HttpStatus {
+            url: url.to_string(),
+            status: res.status(),
+        });
+    }
+    Ok(())
+}
+
+fn parse_mailbox(addr: &str) -> Result<Mailbox> {
+    addr.parse().map_err(|source| Error::InvalidEmailAddress {
+        addr: addr.to_string(),
+        source,
+    })
+}
+
+struct EmailSender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl EmailSender {
+    fn new(cfg: &EmailConfig) -> Result<Self> {
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&cfg.smtp_server)?;
+        if let Some(username) = &cfg.username {
+            let password = match &cfg.password {
+                Some(password) => password.clone(),
+                None => std::env::var(SMTP_PASSWORD_ENV_VAR).unwrap_or_default(),
+            };
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+        Ok(Self {
+            transport: builder.build(),
+            from: parse_mailbox(&cfg.from)?,
+            to: cfg
+                .to
+                .iter()
+                .map(|addr| parse_mailbox(addr))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    async fn send(&self, alert: &Alert) -> Result<()> {
+        let mut builder = lettre::Message::builder()
+            .from(self.from.clone())
+            .subject(format!("{}: {}", alert.source, alert.kind));
+        for to in self.to.iter() {
+            builder = builder.to(to.clone());
+        }
+        let body = format!(
+            "{}\n\nSource: {}\nTime: {}\n",
+            alert.message,
+            alert.source,
+            alert.time.to_rfc3339()
+        );
+        self.transport.send(builder.body(body)?).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(Duration::from_secs(10));
+        let t0 = Instant::now();
+        assert!(limiter.check("cam1", "stalled", t0));
+        assert!(!limiter.check("cam1", "stalled", t0 + Duration::from_secs(5)));
+        assert!(limiter.check("cam2", "stalled", t0 + Duration::from_secs(5)));
+        assert!(limiter.check("cam1", "behind", t0 + Duration::from_secs(5)));
+        assert!(limiter.check("cam1", "stalled", t0 + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let cfg: NotificationConfig = toml::from_str(
+            r#"
+            slack_webhook_urls = ["https://hooks.slack.com/services/T0/B0/X"]
+            [email]
+            smtp_server = "smtp.example.com"
+            from = "Braid <braid@example.com>"
+            to = ["user@example.com"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.min_interval_sec, default_min_interval_sec());
+        assert!(cfg.webhook_urls.is_empty());
+        let email = cfg.email.unwrap();
+        assert!(EmailSender::new(&email).is_ok());
+    }
+}
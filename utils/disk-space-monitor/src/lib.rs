@@ -0,0 +1,94 @@
+//! Monitoring of the free space on the volume to which recordings are saved.
+//!
+//! Rather than failing in the middle of writing a file when the disk becomes
+//! full, recording programs periodically check the available space with a
+//! [DiskSpaceMonitor]. Below a warning threshold, the user is warned. Below a
+//! (lower) stop threshold, recordings should be stopped so that the files can
+//! be finalized while space for doing so remains.
+
+use std::path::{Path, PathBuf};
+
+pub use rust_cam_bui_types::DiskSpace;
+
+/// Default threshold below which a warning is emitted, in megabytes.
+pub const DEFAULT_WARNING_THRESHOLD_MB: u64 = 10_000;
+
+/// Default threshold below which recordings are stopped, in megabytes.
+pub const DEFAULT_STOP_THRESHOLD_MB: u64 = 1_000;
+
+/// Interval at which the free space should be checked.
+pub const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+const BYTES_PER_MB: u64 = 1_000_000;
+
+/// Amount of available space relative to the thresholds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskSpaceLevel {
+    /// More space than the warning threshold is available.
+    Ok,
+    /// Less space than the warning threshold is available.
+    Low,
+    /// Less space than the stop threshold is available.
+    Full,
+}
+
+/// Checks the free space of the volume containing a directory.
+#[derive(Debug, Clone)]
+pub struct DiskSpaceMonitor {
+    path: PathBuf,
+    warning_threshold_bytes: u64,
+    stop_threshold_bytes: u64,
+}
+
+impl DiskSpaceMonitor {
+    /// Create a new monitor for the volume containing `path`.
+    ///
+    /// The thresholds are given in megabytes.
+    pub fn new<P: AsRef<Path>>(path: P, warning_threshold_mb: u64, stop_threshold_mb: u64) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            warning_threshold_bytes: warning_threshold_mb.saturating_mul(BYTES_PER_MB),
+            stop_threshold_bytes: stop_threshold_mb.saturating_mul(BYTES_PER_MB),
+        }
+    }
+
+    /// The monitored directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Query the volume for its current space.
+    pub fn check(&self) -> std::io::Result<(DiskSpace, DiskSpaceLevel)> {
+        let available_bytes = fs4::available_space(&self.path)?;
+        let total_bytes = fs4::total_space(&self.path)?;
+        let level = self.level(available_bytes);
+        let space = DiskSpace {
+            path: self.path.display().to_string(),
+            available_bytes,
+            total_bytes,
+            is_low: level != DiskSpaceLevel::Ok,
+        };
+        Ok((space, level))
+    }
+
+    fn level(&self, available_bytes: u64) -> DiskSpaceLevel {
+        if available_bytes < self.stop_threshold_bytes {
+            DiskSpaceLevel::Full
+        } else if available_bytes < self.warning_threshold_bytes {
+            DiskSpaceLevel::Low
+        } else {
+            DiskSpaceLevel::Ok
+        }
+    }
+}
+
+#[test]
+fn test_levels() {
+    let monitor = DiskSpaceMonitor::new(".", 100, 10);
+    assert_eq!(monitor.level(200 * BYTES_PER_MB), DiskSpaceLevel::Ok);
+    assert_eq!(monitor.level(50 * BYTES_PER_MB), DiskSpaceLevel::Low);
+    assert_eq!(monitor.level(5 * BYTES_PER_MB), DiskSpaceLevel::Full);
+
+    let (space, _level) = monitor.check().unwrap();
+    assert!(space.available_bytes <= space.total_bytes);
+}
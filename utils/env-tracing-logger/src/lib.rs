@@ -1,12 +1,100 @@
+//! Logging to the console and to files using [tracing].
+//!
+//! Which events are logged is initially set by the `RUST_LOG` environment
+//! variable and can be changed while the program runs with
+//! [LogHandle::set_filter].
+
+use std::path::{Path, PathBuf};
+
 use time::{format_description::well_known::Iso8601, UtcOffset};
 use tracing_subscriber::{
+    filter::{filter_fn, EnvFilter},
     fmt::{self, time::OffsetTime},
-    layer::SubscriberExt,
+    layer::{Layered, SubscriberExt},
+    reload, Layer, Registry,
 };
 
-struct Guard {}
+type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+type FilteredRegistry = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+type BoxedLayer = Box<dyn Layer<FilteredRegistry> + Send + Sync>;
+
+/// A part of a program whose events are additionally written to a separate
+/// log file.
+#[derive(Debug, Clone, Copy)]
+pub struct LogComponent {
+    /// Name of the component, appended to the name of the main log file.
+    pub name: &'static str,
+    /// Targets (module path prefixes, e.g. `flydra2::tracking_core`) of the
+    /// events of this component.
+    pub targets: &'static [&'static str],
+}
+
+/// Where and how to log.
+#[derive(Debug, Clone, Default)]
+pub struct LogConfig {
+    /// Log file with the events of all modules.
+    pub path: Option<PathBuf>,
+    pub disable_console: bool,
+    /// Write the log files as JSON with one event per line. The fields of the
+    /// spans in which an event occurs (e.g. the camera name) are included.
+    pub json: bool,
+    /// Components with their own log file next to `path`. See
+    /// [component_log_path].
+    pub components: &'static [LogComponent],
+}
+
+/// Return the path of the log file of component `name` given the path of the
+/// main log file.
+///
+/// For example, the file for component `tracking` of `braid-1.log` is
+/// `braid-1-tracking.log`.
+pub fn component_log_path(path: &Path, name: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut file_name = format!("{stem}-{name}");
+    if let Some(ext) = path.extension() {
+        file_name.push('.');
+        file_name.push_str(&ext.to_string_lossy());
+    }
+    path.with_file_name(file_name)
+}
+
+/// Changes which events are logged while the program runs.
+///
+/// Cloning a `LogHandle` is cheap and all clones control the same logger.
+#[derive(Clone)]
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>,
+}
+
+impl LogHandle {
+    /// Set which events are logged using the syntax of the `RUST_LOG`
+    /// environment variable, e.g. `info,flydra2=debug`.
+    pub fn set_filter(&self, directives: &str) -> Result<(), Error> {
+        let filter = EnvFilter::try_new(directives)?;
+        self.filter.reload(filter)?;
+        tracing::info!("Log filter set to \"{directives}\".");
+        Ok(())
+    }
+
+    /// The current filter in the syntax of the `RUST_LOG` environment
+    /// variable.
+    pub fn filter(&self) -> String {
+        self.filter
+            .with_current(|filter| filter.to_string())
+            .unwrap_or_default()
+    }
+}
 
-impl Drop for Guard {
+impl std::fmt::Debug for LogHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogHandle")
+            .field("filter", &self.filter())
+            .finish()
+    }
+}
+
+impl Drop for LogHandle {
     fn drop(&mut self) {}
 }
 
@@ -15,10 +103,34 @@ pub fn init() -> impl Drop {
 }
 
 /// Start logging to file and console, both optional.
-pub fn initiate_logging<P: AsRef<std::path::Path>>(
+pub fn initiate_logging<P: AsRef<Path>>(
     path: Option<P>,
     disable_console: bool,
-) -> Result<impl Drop, Box<dyn std::error::Error + Send + Sync + 'static>> {
+) -> Result<LogHandle, Error> {
+    initiate_logging_with_config(LogConfig {
+        path: path.map(|p| p.as_ref().to_path_buf()),
+        disable_console,
+        ..Default::default()
+    })
+}
+
+fn file_layer(path: &Path, json: bool, timer: OffsetTime<Iso8601>) -> Result<BoxedLayer, Error> {
+    let file_writer = std::sync::Mutex::new(std::fs::File::create(path)?);
+    let layer = fmt::layer()
+        .with_timer(timer)
+        .with_writer(file_writer)
+        .with_ansi(false)
+        .with_file(true)
+        .with_line_number(true);
+    if json {
+        Ok(Box::new(layer.json()))
+    } else {
+        Ok(Box::new(layer))
+    }
+}
+
+/// Start logging as given in `cfg`.
+pub fn initiate_logging_with_config(cfg: LogConfig) -> Result<LogHandle, Error> {
     // Create a fixed offset time formatter based on the timezone at the
     // time this line of code runs.
     let timer = OffsetTime::new(
@@ -26,24 +138,27 @@ pub fn initiate_logging<P: AsRef<std::path::Path>>(
         Iso8601::DEFAULT,
     );
 
-    let file_layer = if let Some(path) = &path {
-        let file = std::fs::File::create(path)?;
-        let file_writer = std::sync::Mutex::new(file);
-        Some(
-            fmt::layer()
-                .with_timer(timer.clone())
-                .with_writer(file_writer)
-                .with_ansi(false)
-                .with_file(true)
-                .with_line_number(true),
-        )
-    } else {
-        None
-    };
+    let mut layers: Vec<BoxedLayer> = Vec::new();
 
-    let console_layer = if disable_console {
-        None
-    } else {
+    if let Some(path) = &cfg.path {
+        layers.push(file_layer(path, cfg.json, timer.clone())?);
+        for component in cfg.components.iter() {
+            // Spans are not filtered so that their fields are included.
+            let targets = component.targets;
+            let filter = filter_fn(move |metadata| {
+                metadata.is_span()
+                    || targets
+                        .iter()
+                        .any(|target| metadata.target().starts_with(target))
+            });
+            let path = component_log_path(path, component.name);
+            layers.push(Box::new(
+                file_layer(&path, cfg.json, timer.clone())?.with_filter(filter),
+            ));
+        }
+    }
+
+    if !cfg.disable_console {
         #[cfg(target_os = "windows")]
         let with_ansi = match ansi_term::enable_ansi_support() {
             Ok(_) => true,
@@ -55,19 +170,17 @@ pub fn initiate_logging<P: AsRef<std::path::Path>>(
         #[cfg(not(target_os = "windows"))]
         let with_ansi = true;
 
-        Some(
+        layers.push(Box::new(
             fmt::layer()
                 .with_timer(timer)
                 .with_ansi(with_ansi)
                 .with_file(true)
                 .with_line_number(true),
-        )
-    };
+        ));
+    }
 
-    let collector = tracing_subscriber::registry()
-        .with(file_layer)
-        .with(console_layer)
-        .with(tracing_subscriber::filter::EnvFilter::from_default_env());
+    let (filter, filter_handle) = reload::Layer::new(EnvFilter::from_default_env());
+    let collector = tracing_subscriber::registry().with(filter).with(layers);
     tracing::subscriber::set_global_default(collector)?;
 
     let log_var = if let Ok(var) = std::env::var("RUST_LOG") {
@@ -76,16 +189,32 @@ pub fn initiate_logging<P: AsRef<std::path::Path>>(
         ".".to_string()
     };
 
-    if let Some(path) = &path {
-        tracing::debug!(
-            "Logging initiated to file \"{}\"{log_var}",
-            path.as_ref().display(),
-        );
+    if let Some(path) = &cfg.path {
+        tracing::debug!("Logging initiated to file \"{}\"{log_var}", path.display(),);
     }
 
-    if !disable_console {
+    if !cfg.disable_console {
         tracing::debug!("Logging initiated to console{log_var}",);
     }
 
-    Ok(Guard {})
+    Ok(LogHandle {
+        filter: filter_handle,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_component_log_path() {
+        assert_eq!(
+            component_log_path(Path::new("/tmp/.braid-20240101_120000.123.log"), "tracking"),
+            PathBuf::from("/tmp/.braid-20240101_120000.123-tracking.log")
+        );
+        assert_eq!(
+            component_log_path(Path::new("log"), "camera"),
+            PathBuf::from("log-camera")
+        );
+    }
 }
@@ -0,0 +1,207 @@
+//! Serve an axum router over HTTPS.
+//!
+//! The embedded web servers of Braid and Strand Camera serve plain HTTP by
+//! default. With a [TlsConfig], they serve HTTPS instead, so that camera
+//! control and the live view are not sent in plain text on shared networks and
+//! browsers allow the APIs which require a secure context. The certificate is
+//! either provided by the user or a self-signed certificate is generated at
+//! startup.
+
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tower::ServiceExt;
+use tracing::{debug, info, warn};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("IO error reading \"{path}\": {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("no certificate found in \"{0}\"")]
+    NoCertificate(PathBuf),
+    #[error("no private key found in \"{0}\"")]
+    NoPrivateKey(PathBuf),
+    #[error("both or neither of the certificate and key files must be given")]
+    IncompleteConfig,
+    #[error("generating self-signed certificate failed: {0}")]
+    Rcgen(#[from] rcgen::Error),
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// TLS configuration of an HTTP server.
+///
+/// If neither `cert_file` nor `key_file` is given, a self-signed certificate
+/// is generated at each startup. Browsers will warn about such a certificate
+/// and ask to accept it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain.
+    pub cert_file: Option<PathBuf>,
+    /// PEM file with the private key.
+    pub key_file: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// Build the rustls configuration, loading or generating the certificate.
+    ///
+    /// `hostnames` are the names and IP addresses for which a self-signed
+    /// certificate is generated.
+    pub fn server_config(&self, hostnames: Vec<String>) -> Result<rustls::ServerConfig> {
+        let (certs, key) = match (&self.cert_file, &self.key_file) {
+            (Some(cert_file), Some(key_file)) => (load_certs(cert_file)?, load_key(key_file)?),
+            (None, None) => self_signed(hostnames)?,
+            _ => return Err(Error::IncompleteConfig),
+        };
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut cfg = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+        cfg.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(cfg)
+    }
+}
+
+fn read(path: &std::path::Path) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|source| Error::Io {
+        path: path.into(),
+        source,
+    })
+}
+
+fn load_certs(path: &std::path::Path) -> Result<Vec<CertificateDer<'static>>> {
+    let buf = read(path)?;
+    let certs = rustls_pemfile::certs(&mut buf.as_slice())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|source| Error::Io {
+            path: path.into(),
+            source,
+        })?;
+    if certs.is_empty() {
+        return Err(Error::NoCertificate(path.into()));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &std::path::Path) -> Result<PrivateKeyDer<'static>> {
+    let buf = read(path)?;
+    rustls_pemfile::private_key(&mut buf.as_slice())
+        .map_err(|source| Error::Io {
+            path: path.into(),
+            source,
+        })?
+        .ok_or_else(|| Error::NoPrivateKey(path.into()))
+}
+
+fn self_signed(
+    hostnames: Vec<String>,
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    warn!(
+        "Using a self-signed TLS certificate. Browsers will warn about it. Provide a \
+        certificate to avoid this."
+    );
+    let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(hostnames)?;
+    let key = PrivatePkcs8KeyDer::from(key_pair.serialize_der());
+    Ok((vec![cert.der().clone()], key.into()))
+}
+
+/// The hosts of `urls`, plus `localhost`, for a self-signed certificate.
+pub fn hostnames(urls: &[http::Uri]) -> Vec<String> {
+    let mut names = vec!["localhost".to_string()];
+    for host in urls.iter().filter_map(|url| url.host()) {
+        // IPv6 addresses are in brackets in URLs.
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if !names.iter().any(|name| name == host) {
+            names.push(host.to_string());
+        }
+    }
+    names
+}
+
+/// Change the scheme of `uri` to `https`.
+pub fn https_uri(uri: &http::Uri) -> http::Uri {
+    let mut parts = uri.clone().into_parts();
+    parts.scheme = Some(http::uri::Scheme::HTTPS);
+    http::Uri::from_parts(parts).expect("valid uri")
+}
+
+/// Serve `router` over HTTPS on `listener`.
+///
+/// Like `axum::serve` with `into_make_service_with_connect_info`, the address
+/// of the client is available with the `ConnectInfo<SocketAddr>` extractor.
+/// Connections can be upgraded, e.g. to WebSockets.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    router: axum::Router,
+    tls: rustls::ServerConfig,
+) -> std::io::Result<()> {
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(tls));
+    info!("Serving HTTPS on {}", listener.local_addr()?);
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(x) => x,
+            Err(e) => {
+                // E.g. too many open files. Keep accepting other connections.
+                warn!("error accepting connection: {e}");
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let router = router.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    // E.g. the browser does not (yet) accept the certificate.
+                    debug!("TLS handshake with {addr} failed: {e}");
+                    return;
+                }
+            };
+            let service = hyper::service::service_fn(move |mut req: http::Request<_>| {
+                req.extensions_mut()
+                    .insert(axum::extract::ConnectInfo::<SocketAddr>(addr));
+                router.clone().oneshot(req.map(axum::body::Body::new))
+            });
+            if let Err(e) = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("connection with {addr} ended: {e}");
+            }
+        });
+    }
+}
+
+#[test]
+fn test_tls_config() {
+    let cfg = TlsConfig {
+        cert_file: Some("cert.pem".into()),
+        key_file: None,
+    };
+    assert!(matches!(
+        cfg.server_config(vec![]),
+        Err(Error::IncompleteConfig)
+    ));
+    let cfg = TlsConfig::default()
+        .server_config(vec!["localhost".into()])
+        .unwrap();
+    assert_eq!(cfg.alpn_protocols.len(), 2);
+
+    let uri: http::Uri = "http://127.0.0.1:1234/?token=abc".parse().unwrap();
+    let ipv6: http::Uri = "http://[::1]:1234/".parse().unwrap();
+    assert_eq!(
+        hostnames(&[uri.clone(), ipv6]),
+        ["localhost", "127.0.0.1", "::1"]
+    );
+    assert_eq!(
+        https_uri(&uri).to_string(),
+        "https://127.0.0.1:1234/?token=abc"
+    );
+}
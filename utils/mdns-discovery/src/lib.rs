@@ -0,0 +1,189 @@
+//! Advertise and discover Strand Camera instances on the local network by mDNS
+//! (zeroconf).
+//!
+//! Strand Camera advertises its web UI as a service of type [SERVICE_TYPE]
+//! with the camera name as instance name. Braid browses for these services to
+//! list the cameras available on the network.
+
+use std::net::{IpAddr, SocketAddr};
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+
+/// The mDNS service type of the Strand Camera web UI.
+pub const SERVICE_TYPE: &str = "_strand-cam._tcp.local.";
+
+const CAM_NAME_KEY: &str = "cam_name";
+const SCHEME_KEY: &str = "scheme";
+const IN_BRAID_KEY: &str = "braid";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("mDNS error: {0}")]
+    Mdns(#[from] mdns_sd::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A Strand Camera instance found on the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Camera {
+    /// The camera name.
+    pub name: String,
+    /// The URLs of the web UI, without token.
+    pub urls: Vec<String>,
+    /// Whether the camera is running as part of Braid.
+    pub in_braid: bool,
+}
+
+/// A change of the Strand Camera instances found on the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A camera was found or its advertisement changed.
+    ///
+    /// `id` identifies the advertisement.
+    Found { id: String, camera: Camera },
+    /// The camera advertised as `id` is gone.
+    Lost { id: String },
+}
+
+/// An advertisement of the web UI of a camera.
+///
+/// The service is unregistered on drop.
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl Advertisement {
+    /// Advertise the web UI of the camera `cam_name` listening at `addr`.
+    ///
+    /// If the IP address of `addr` is unspecified, the addresses of all
+    /// network interfaces are advertised.
+    pub fn new(cam_name: &str, addr: SocketAddr, https: bool, in_braid: bool) -> Result<Self> {
+        let daemon = ServiceDaemon::new()?;
+        let host_name = format!("strand-cam-{}.local.", sanitize_host_label(cam_name));
+        let scheme = if https { "https" } else { "http" };
+        let in_braid = if in_braid { "1" } else { "0" };
+        let properties = [
+            (CAM_NAME_KEY, cam_name),
+            (SCHEME_KEY, scheme),
+            (IN_BRAID_KEY, in_braid),
+        ];
+        let info = if addr.ip().is_unspecified() {
+            ServiceInfo::new(
+                SERVICE_TYPE,
+                cam_name,
+                &host_name,
+                (),
+                addr.port(),
+                &properties[..],
+            )?
+            .enable_addr_auto()
+        } else {
+            ServiceInfo::new(
+                SERVICE_TYPE,
+                cam_name,
+                &host_name,
+                addr.ip(),
+                addr.port(),
+                &properties[..],
+            )?
+        };
+        let fullname = info.get_fullname().to_string();
+        daemon.register(info)?;
+        tracing::debug!("Advertising \"{fullname}\" by mDNS.");
+        Ok(Self { daemon, fullname })
+    }
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        let _ = self.daemon.unregister(&self.fullname);
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// Browses for Strand Camera instances on the network.
+pub struct Browser {
+    daemon: ServiceDaemon,
+    receiver: mdns_sd::Receiver<ServiceEvent>,
+}
+
+impl Browser {
+    /// Start browsing.
+    pub fn new() -> Result<Self> {
+        let daemon = ServiceDaemon::new()?;
+        let receiver = daemon.browse(SERVICE_TYPE)?;
+        Ok(Self { daemon, receiver })
+    }
+
+    /// Wait for the next change. Returns `None` if browsing has stopped.
+    pub async fn next(&self) -> Option<Event> {
+        loop {
+            match self.receiver.recv_async().await.ok()? {
+                ServiceEvent::ServiceResolved(info) => {
+                    let camera = camera_from_info(&info);
+                    return Some(Event::Found {
+                        id: info.get_fullname().to_string(),
+                        camera,
+                    });
+                }
+                ServiceEvent::ServiceRemoved(_service_type, fullname) => {
+                    return Some(Event::Lost { id: fullname });
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for Browser {
+    fn drop(&mut self) {
+        let _ = self.daemon.shutdown();
+    }
+}
+
+fn camera_from_info(info: &ServiceInfo) -> Camera {
+    let name = match info.get_property_val_str(CAM_NAME_KEY) {
+        Some(name) => name.to_string(),
+        None => instance_name(info.get_fullname()).to_string(),
+    };
+    let scheme = info.get_property_val_str(SCHEME_KEY).unwrap_or("http");
+    let mut addrs: Vec<IpAddr> = info.get_addresses().iter().copied().collect();
+    addrs.sort();
+    let urls = addrs
+        .into_iter()
+        .map(|ip| format!("{scheme}://{}/", SocketAddr::new(ip, info.get_port())))
+        .collect();
+    Camera {
+        name,
+        urls,
+        in_braid: info.get_property_val_str(IN_BRAID_KEY) == Some("1"),
+    }
+}
+
+fn instance_name(fullname: &str) -> &str {
+    fullname
+        .strip_suffix(SERVICE_TYPE)
+        .and_then(|name| name.strip_suffix('.'))
+        .unwrap_or(fullname)
+}
+
+/// Make `name` usable as a DNS label.
+fn sanitize_host_label(name: &str) -> String {
+    let label: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(50)
+        .collect();
+    label.to_ascii_lowercase()
+}
+
+#[test]
+fn test_names() {
+    assert_eq!(
+        instance_name("Basler-22005677._strand-cam._tcp.local."),
+        "Basler-22005677"
+    );
+    assert_eq!(sanitize_host_label("Basler 22005677"), "basler-22005677");
+}